@@ -0,0 +1,407 @@
+use crate::{
+    error::{CliError, EXIT_LINT_FAILED, EXIT_NO_ROUTE, EXIT_SELFTEST_FAILED, EXIT_UNHEALTHY},
+    output::{describe_path, print, print_json, table, to_dot},
+};
+use polypath_dal::{DalContext, GraphUpdater, Severity, RefreshReport, RouteExecutor, adapters::CircuitState, present::{self, PresentOptions, PresentedRoute}};
+use polypath_graph::{ExplainedPath, ExportFormat, Graph, NodeType, RankedPath, RouteIntent, RouteOptions, Router, export};
+use polypathroute_core::{CoreContext, LoggingManager, RegistryError};
+use serde::Serialize;
+use std::{collections::BTreeSet, path::Path, process::ExitCode, sync::Arc};
+
+pub const GRAPH_SHARDS: usize = 16;
+// Quote requests in flight while recording fixtures
+const FIXTURE_CONCURRENCY: usize = 8;
+
+// The CLI owns stdout, so the core is built without installing the configured log subscriber
+// `simulation` is the seed to simulate every bridge with, if any
+pub fn load_context(config: &str, simulation: Option<u64>) -> Result<DalContext, CliError> {
+    let core = CoreContext::builder().config_path(config).logging(LoggingManager).build()?;
+    let dal = DalContext::from_core(core);
+    Ok(match simulation {
+        Some(seed) => dal.with_simulation(seed),
+        None => dal,
+    })
+}
+
+// The graph from the snapshot saved under `snapshot`, or else from one refresh of every
+// configured bridge. Quotes that failed are mentioned on stderr.
+pub async fn load_graph(dal: DalContext, snapshot: Option<&str>) -> Result<(Arc<Graph>, Option<RefreshReport>), CliError> {
+    if let Some(name) = snapshot {
+        return Ok((Arc::new(dal.load_graph_snapshot(name, GRAPH_SHARDS)?), None));
+    }
+    let updater = Arc::new(GraphUpdater::new(Arc::new(Graph::new(GRAPH_SHARDS)), dal));
+    let report = updater.refresh_once().await;
+    if report.failed > 0 {
+        eprintln!("warning: {} quote(s) failed during the refresh", report.failed);
+    }
+    Ok((Arc::clone(updater.graph()), Some(report)))
+}
+
+#[derive(Serialize)]
+struct RouteRow<'a> {
+    description: String,
+    presented: PresentedRoute,
+    #[serde(flatten)]
+    route: &'a ExplainedPath,
+}
+
+// `canonical` is the `requested` intent as DalContext::canonical_intent resolves it; messages
+// repeat what was asked for. The search runs on `executor` like the server's do, and routes are
+// presented over `graph` with `present`.
+pub async fn route(executor: &RouteExecutor, present: &PresentOptions<'_>, graph: Arc<Graph>, requested: &RouteIntent, canonical: &RouteIntent, opts: &RouteOptions, json: bool) -> Result<ExitCode, CliError> {
+    let router = Arc::new(Router::new(graph));
+    let outcome = executor.run(Arc::clone(&router), canonical.clone(), opts.clone()).await?;
+    let present = present.clone().with_graph(router.graph());
+    let rows: Vec<RouteRow> = outcome
+        .ranked
+        .iter()
+        .map(|route| RouteRow {
+            description: describe_path(router.graph(), &route.ranked.path),
+            presented: present::humanize(&route.ranked, &present),
+            route,
+        })
+        .collect();
+
+    if json {
+        print_json(&serde_json::json!({ "routes": rows, "diagnostics": outcome.diagnostics }))?;
+    } else if !rows.is_empty() {
+        let cells: Vec<Vec<String>> = rows
+            .iter()
+            .map(|row| {
+                let path = &row.route.ranked.path;
+                vec![
+                    row.route.ranked.rank.to_string(),
+                    format!("{:.4}", path.aggregate_score),
+                    row.presented.fee.clone(),
+                    row.presented.duration.clone(),
+                    row.presented.output.clone().unwrap_or_default(),
+                    format!("{:.3}", path.total_risk),
+                    row.description.clone(),
+                ]
+            })
+            .collect();
+        print(&table(&["RANK", "SCORE", "FEE", "TIME", "OUTPUT", "RISK", "ROUTE"], &cells))?;
+    }
+
+    if rows.is_empty() {
+        eprintln!(
+            "no route from {} {} to {} {}",
+            requested.from_chain, requested.from_token, requested.to_chain, requested.to_token
+        );
+        let dropped: Vec<String> = outcome.diagnostics.dropped.iter().map(|(reason, count)| format!("{} {}", count, reason.as_str())).collect();
+        if !dropped.is_empty() {
+            eprintln!("{} candidate(s) found, dropped: {}", outcome.diagnostics.candidates, dropped.join(", "));
+        }
+        return Ok(ExitCode::from(EXIT_NO_ROUTE));
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+// Each intent comes with what DalContext::canonical_intent made of it. One that couldn't be
+// resolved or searched is mentioned on stderr and written as an intent without routes, so one
+// bad line doesn't cost the whole batch. The searches run one after the other, so a long batch
+// can't fill the executor's queue.
+pub async fn route_batch(
+    executor: &RouteExecutor,
+    graph: Arc<Graph>,
+    intents: Vec<(RouteIntent, Result<RouteIntent, RegistryError>)>,
+    opts: &RouteOptions,
+    output: &Path,
+    format: ExportFormat,
+    json: bool,
+) -> Result<ExitCode, CliError> {
+    let router = Arc::new(Router::new(graph));
+    let mut rows: Vec<(RouteIntent, Vec<RankedPath>)> = Vec::with_capacity(intents.len());
+    let mut failed = 0;
+    for (requested, canonical) in intents {
+        let ranked = match canonical {
+            Ok(canonical) => executor.run(Arc::clone(&router), canonical, opts.clone()).await.map_err(CliError::from),
+            Err(err) => Err(err.into()),
+        };
+        let ranked = match ranked {
+            Ok(outcome) => outcome.ranked.into_iter().map(|route| route.ranked).collect(),
+            Err(err) => {
+                eprintln!(
+                    "warning: {} {} to {} {}: {}",
+                    requested.from_chain, requested.from_token, requested.to_chain, requested.to_token, err
+                );
+                failed += 1;
+                Vec::new()
+            }
+        };
+        rows.push((requested, ranked));
+    }
+
+    let write_error = |source| CliError::Write { path: output.to_path_buf(), source };
+    let file = std::fs::File::create(output).map_err(write_error)?;
+    let written = export::write_ranked_paths(std::io::BufWriter::new(file), format, &rows)?;
+    let routed = rows.iter().filter(|(_, ranked)| !ranked.is_empty()).count();
+    if json {
+        print_json(&serde_json::json!({
+            "path": output,
+            "intents": rows.len(),
+            "routed": routed,
+            "failed": failed,
+            "rows": written,
+        }))?;
+    } else {
+        print(&format!(
+            "wrote {} rows for {} intents ({} with routes, {} failed) to {}",
+            written, rows.len(), routed, failed, output.display(),
+        ))?;
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+#[derive(Debug, Serialize)]
+struct GraphStats {
+    version: u64,
+    nodes: usize,
+    assets: usize,
+    exchanges: usize,
+    edges: usize,
+    active_edges: usize,
+    bridges: BTreeSet<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refresh: Option<RefreshReport>,
+}
+
+pub fn graph_stats(graph: &Graph, refresh: Option<RefreshReport>, json: bool) -> Result<ExitCode, CliError> {
+    let snapshot = graph.snapshot();
+    let assets = snapshot.nodes.iter().filter(|node| matches!(node.node_type, NodeType::Asset { .. })).count();
+    let stats = GraphStats {
+        version: snapshot.version,
+        nodes: snapshot.nodes.len(),
+        assets,
+        exchanges: snapshot.nodes.len() - assets,
+        edges: snapshot.edges.len(),
+        active_edges: snapshot.edges.iter().filter(|edge| edge.is_active).count(),
+        bridges: snapshot.edges.iter().map(|edge| edge.bridge_name.clone()).collect(),
+        refresh,
+    };
+
+    if json {
+        print_json(&stats)?;
+    } else {
+        let bridges: Vec<&str> = stats.bridges.iter().map(String::as_str).collect();
+        let mut rows = vec![
+            vec!["version".to_string(), stats.version.to_string()],
+            vec!["nodes".to_string(), format!("{} ({} assets, {} exchanges)", stats.nodes, stats.assets, stats.exchanges)],
+            vec!["edges".to_string(), format!("{} ({} active)", stats.edges, stats.active_edges)],
+            vec!["bridges".to_string(), bridges.join(", ")],
+        ];
+        if let Some(report) = stats.refresh {
+            rows.push(vec![
+                "refresh".to_string(),
+                format!("{} added, {} updated, {} failed, {} deactivated", report.added, report.updated, report.failed, report.deactivated),
+            ]);
+        }
+        print(&table(&["STAT", "VALUE"], &rows))?;
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+// The node directory lets whoever keeps routes printed from the graph read their node ids later
+pub fn graph_export(graph: &Graph, dot: &Path, directory: &Path, json: bool) -> Result<ExitCode, CliError> {
+    let snapshot = graph.snapshot();
+    std::fs::write(dot, to_dot(&snapshot)).map_err(|source| CliError::Write { path: dot.to_path_buf(), source })?;
+    let entries = serde_json::to_string_pretty(&graph.export_node_directory()).map_err(CliError::Json)?;
+    std::fs::write(directory, entries).map_err(|source| CliError::Write { path: directory.to_path_buf(), source })?;
+    if json {
+        print_json(&serde_json::json!({
+            "path": dot,
+            "directory": directory,
+            "nodes": snapshot.nodes.len(),
+            "edges": snapshot.edges.len(),
+        }))?;
+    } else {
+        print(&format!(
+            "wrote {} nodes and {} edges to {}, the node directory to {}",
+            snapshot.nodes.len(), snapshot.edges.len(), dot.display(), directory.display(),
+        ))?;
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+#[derive(Debug, Serialize)]
+struct HealthRow {
+    bridge: String,
+    healthy: bool,
+    latency_ms: Option<u128>,
+    rate_limit_remaining: Option<u32>,
+    circuit: Option<&'static str>,
+    details: String,
+}
+
+// Exits with EXIT_UNHEALTHY when any configured bridge is unreachable or failed its check
+pub async fn adapters_health(dal: &DalContext, json: bool) -> Result<ExitCode, CliError> {
+    let mut rows: Vec<HealthRow> = dal
+        .health_check_all()
+        .await
+        .into_iter()
+        .map(|(bridge, health)| match health {
+            Ok(health) => HealthRow {
+                bridge,
+                healthy: health.reachable,
+                latency_ms: Some(health.latency.as_millis()),
+                rate_limit_remaining: health.rate_limit_remaining,
+                circuit: health.circuit.map(|state| match state {
+                    CircuitState::Closed => "closed",
+                    CircuitState::Open { .. } => "open",
+                    CircuitState::HalfOpen => "half-open",
+                }),
+                details: health.details,
+            },
+            Err(err) => HealthRow {
+                bridge,
+                healthy: false,
+                latency_ms: None,
+                rate_limit_remaining: None,
+                circuit: None,
+                details: err.to_string(),
+            },
+        })
+        .collect();
+    rows.sort_by(|a, b| a.bridge.cmp(&b.bridge));
+
+    if json {
+        print_json(&rows)?;
+    } else {
+        let cells: Vec<Vec<String>> = rows
+            .iter()
+            .map(|row| {
+                vec![
+                    row.bridge.clone(),
+                    if row.healthy { "ok" } else { "down" }.to_string(),
+                    row.latency_ms.map(|ms| format!("{}ms", ms)).unwrap_or_else(|| "-".to_string()),
+                    row.circuit.unwrap_or("-").to_string(),
+                    row.details.clone(),
+                ]
+            })
+            .collect();
+        print(&table(&["BRIDGE", "STATUS", "LATENCY", "CIRCUIT", "DETAILS"], &cells))?;
+    }
+
+    if rows.iter().all(|row| row.healthy) {
+        Ok(ExitCode::SUCCESS)
+    } else {
+        Ok(ExitCode::from(EXIT_UNHEALTHY))
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct FixtureRow {
+    bridge: String,
+    quoted: usize,
+    failed: usize,
+    // The first failure, when any
+    error: Option<String>,
+}
+
+// Quotes each bridge's pairs once with recording on, so every successful API response lands in
+// `directory`. Exits with EXIT_UNHEALTHY when any quote failed, as its fixture is then missing.
+pub async fn fixtures_record(dal: &DalContext, config_pairs: bool, directory: &Path, json: bool) -> Result<ExitCode, CliError> {
+    let mut rows = Vec::new();
+    let mut jobs = Vec::new();
+    for bridge in dal.adapter_names() {
+        let adapter = match dal.adapter(&bridge) {
+            Ok(adapter) => adapter,
+            Err(err) => {
+                rows.push(FixtureRow { bridge, quoted: 0, failed: 0, error: Some(err.to_string()) });
+                continue;
+            }
+        };
+        let pairs = if config_pairs { dal.supported_pairs_for(&bridge) } else { adapter.supported_pairs() };
+        jobs.extend(pairs.into_iter().map(|pair| (Arc::clone(&adapter), pair)));
+        rows.push(FixtureRow { bridge, quoted: 0, failed: 0, error: None });
+    }
+    for outcome in polypath_dal::fetch_all(jobs, FIXTURE_CONCURRENCY).await {
+        let Some(row) = rows.iter_mut().find(|row| row.bridge == outcome.adapter) else { continue };
+        match outcome.result {
+            Ok(_) => row.quoted += 1,
+            Err(err) => {
+                row.failed += 1;
+                row.error.get_or_insert_with(|| err.to_string());
+            }
+        }
+    }
+
+    if json {
+        print_json(&rows)?;
+    } else {
+        let cells: Vec<Vec<String>> = rows
+            .iter()
+            .map(|row| vec![row.bridge.clone(), row.quoted.to_string(), row.failed.to_string(), row.error.clone().unwrap_or_default()])
+            .collect();
+        print(&table(&["BRIDGE", "QUOTED", "FAILED", "ERROR"], &cells))?;
+        print(&format!("fixtures in {}", directory.display()))?;
+    }
+
+    if rows.iter().all(|row| row.error.is_none()) {
+        Ok(ExitCode::SUCCESS)
+    } else {
+        Ok(ExitCode::from(EXIT_UNHEALTHY))
+    }
+}
+
+// Exits with EXIT_SELFTEST_FAILED when any step failed
+pub async fn selftest(config_path: &str, json: bool) -> Result<ExitCode, CliError> {
+    let report = polypath_dal::selftest(config_path).await;
+    if json {
+        print_json(&report)?;
+    } else {
+        let cells: Vec<Vec<String>> = report
+            .steps
+            .iter()
+            .map(|step| vec![step.name.clone(), step.status.as_str().to_string(), step.details.clone()])
+            .collect();
+        print(&table(&["STEP", "STATUS", "DETAILS"], &cells))?;
+    }
+    match report.passed() {
+        true => Ok(ExitCode::SUCCESS),
+        false => Ok(ExitCode::from(EXIT_SELFTEST_FAILED)),
+    }
+}
+
+// A config that loads is valid; warnings about chains the bridges don't list don't fail it
+pub async fn config_validate(dal: &DalContext, json: bool) -> Result<ExitCode, CliError> {
+    let warnings = dal.validate_config().await;
+    let bridges = dal.config().bridges.len();
+    if json {
+        print_json(&serde_json::json!({ "valid": true, "bridges": bridges, "warnings": warnings }))?;
+    } else {
+        for warning in &warnings {
+            eprintln!("warning: {}", warning);
+        }
+        print(&format!("config is valid: {} bridge(s), {} warning(s)", bridges, warnings.len()))?;
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+// Only errors fail a lint; warnings and infos are reported alongside them
+pub async fn config_lint(dal: &DalContext, json: bool) -> Result<ExitCode, CliError> {
+    let report = dal.lint_config().await;
+    if json {
+        print_json(&report)?;
+    } else {
+        let mut lines: Vec<String> = report
+            .findings
+            .iter()
+            .map(|finding| match &finding.suggestion {
+                Some(suggestion) => format!("{}: {}: {} (did you mean {}?)", finding.severity.as_str(), finding.key, finding.message, suggestion),
+                None => format!("{}: {}: {}", finding.severity.as_str(), finding.key, finding.message),
+            })
+            .collect();
+        lines.push(format!(
+            "{} error(s), {} warning(s), {} info",
+            report.count(Severity::Error),
+            report.count(Severity::Warning),
+            report.count(Severity::Info),
+        ));
+        print(&lines.join("\n"))?;
+    }
+    match report.has_errors() {
+        true => Ok(ExitCode::from(EXIT_LINT_FAILED)),
+        false => Ok(ExitCode::SUCCESS),
+    }
+}
@@ -0,0 +1,54 @@
+use polypath_dal::{DalError, ExecutorError};
+use polypath_graph::{ExportError, RouteError};
+use polypathroute_core::{CoreError, RegistryError};
+use std::{io, path::PathBuf};
+use thiserror::Error;
+
+// Exit codes. clap exits with 2 on usage errors, so ours start above it.
+pub const EXIT_ERROR: u8 = 1;
+pub const EXIT_NO_ROUTE: u8 = 3;
+pub const EXIT_UNHEALTHY: u8 = 4;
+pub const EXIT_SELFTEST_FAILED: u8 = 5;
+pub const EXIT_LINT_FAILED: u8 = 6;
+
+#[derive(Debug, Error)]
+pub enum CliError {
+    #[error(transparent)]
+    Core(#[from] CoreError),
+
+    #[error(transparent)]
+    Dal(#[from] DalError),
+
+    #[error(transparent)]
+    Route(#[from] RouteError),
+
+    #[error(transparent)]
+    Executor(#[from] ExecutorError),
+
+    #[error(transparent)]
+    Registry(#[from] RegistryError),
+
+    #[error("cannot read `{}`: {source}", path.display())]
+    Read { path: PathBuf, source: io::Error },
+
+    #[error("cannot write `{}`: {source}", path.display())]
+    Write { path: PathBuf, source: io::Error },
+
+    #[error("cannot tell the format of `{}` from its extension, use .csv or .jsonl", .0.display())]
+    ExportFormat(PathBuf),
+
+    #[error(transparent)]
+    Export(#[from] ExportError),
+
+    #[error("replays need the metrics history, which [history] disables")]
+    HistoryDisabled,
+
+    #[error("cannot start the async runtime: {0}")]
+    Runtime(io::Error),
+
+    #[error(transparent)]
+    Output(#[from] io::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
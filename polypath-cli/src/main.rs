@@ -0,0 +1,373 @@
+mod commands;
+mod error;
+mod output;
+
+use crate::error::{CliError, EXIT_ERROR};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use polypath_dal::{DEFAULT_GRAPH, adapters::FixtureMode, present::PresentOptions};
+use polypath_graph::{ExportFormat, RouteConstraints, RouteIntent, RouteOptions, RoutePriority};
+use std::{path::PathBuf, process::ExitCode, sync::Arc};
+
+// Exit codes: 0 success, 1 error, 2 bad usage, 3 no route, 4 unhealthy bridges, 5 failed self-test,
+// 6 config lint errors
+#[derive(Debug, Parser)]
+#[command(name = "polypath", version, about = "Query cross-chain routes and inspect the PolyPath graph")]
+struct Cli {
+    /// Path to the TOML, YAML or JSON config
+    #[arg(long, global = true, default_value = "config.toml")]
+    config: String,
+
+    /// Machine-readable JSON on stdout
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Quote every configured bridge with the offline simulator instead of its API
+    #[arg(long, global = true)]
+    simulate: bool,
+
+    /// Seed of the simulator's random walks
+    #[arg(long, global = true, default_value_t = 0, requires = "simulate")]
+    seed: u64,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Rank routes between two assets
+    Route(RouteArgs),
+    /// Rank routes for a JSON array of intents and write them all as CSV or JSON lines
+    RouteBatch(RouteBatchArgs),
+    /// Rank routes for an intent on the graph as it was at a past time, from its snapshots and metrics history
+    Replay(ReplayArgs),
+    /// Inspect the graph built from the configured bridges
+    Graph {
+        #[command(subcommand)]
+        command: GraphCommand,
+    },
+    /// Check the configured bridges
+    Adapters {
+        #[command(subcommand)]
+        command: AdaptersCommand,
+    },
+    /// Check the config file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Record bridge API responses for offline replay
+    Fixtures {
+        #[command(subcommand)]
+        command: FixturesCommand,
+    },
+    /// Run the whole pipeline offline against bundled API responses
+    Selftest,
+}
+
+#[derive(Debug, Args)]
+struct GraphSource {
+    /// Load the graph snapshot saved under this name instead of quoting every bridge
+    #[arg(long)]
+    snapshot: Option<String>,
+}
+
+#[derive(Debug, Args)]
+struct RouteArgs {
+    #[arg(long)]
+    from_chain: String,
+    /// Token address or symbol
+    #[arg(long)]
+    from_token: String,
+    #[arg(long)]
+    to_chain: String,
+    /// Token address or symbol
+    #[arg(long)]
+    to_token: String,
+    #[arg(long)]
+    amount: f64,
+    /// cheapest, fastest, balanced, safest, max-liquidity or max-output
+    #[arg(long)]
+    preference: Option<String>,
+    #[arg(long, default_value_t = 3)]
+    max_results: usize,
+    #[arg(long, default_value_t = 4)]
+    max_hops: usize,
+    /// Most same-chain swaps a route may take, within --max-hops
+    #[arg(long)]
+    max_swaps: Option<usize>,
+    /// Bridges to leave out, repeatable
+    #[arg(long = "exclude")]
+    excluded_bridges: Vec<String>,
+    /// Total cost limit
+    #[arg(long)]
+    max_cost: Option<f64>,
+    /// Total time limit, in seconds
+    #[arg(long)]
+    max_time: Option<f64>,
+    #[command(flatten)]
+    source: GraphSource,
+}
+
+#[derive(Debug, Args)]
+struct RouteBatchArgs {
+    /// JSON array of intents, each like the route command's arguments
+    #[arg(long)]
+    input: PathBuf,
+    /// Where the routes go, as CSV or JSON lines by its .csv or .jsonl extension
+    #[arg(long)]
+    output: PathBuf,
+    #[arg(long, default_value_t = 3)]
+    max_results: usize,
+    #[arg(long, default_value_t = 4)]
+    max_hops: usize,
+    /// Bridges to leave out, repeatable
+    #[arg(long = "exclude")]
+    excluded_bridges: Vec<String>,
+    #[command(flatten)]
+    source: GraphSource,
+}
+
+#[derive(Debug, Args)]
+struct ReplayArgs {
+    /// UTC time to replay, like 2024-06-01T14:32Z or 2024-06-01T14:32:05Z, or unix seconds
+    #[arg(long, value_parser = parse_time)]
+    at: u64,
+    /// JSON file with one intent, like the route command's arguments
+    #[arg(long)]
+    intent: PathBuf,
+    /// Graph whose snapshots are replayed
+    #[arg(long, default_value = DEFAULT_GRAPH)]
+    graph: String,
+    #[arg(long, default_value_t = 3)]
+    max_results: usize,
+    #[arg(long, default_value_t = 4)]
+    max_hops: usize,
+    /// Bridges to leave out, repeatable
+    #[arg(long = "exclude")]
+    excluded_bridges: Vec<String>,
+}
+
+#[derive(Debug, Subcommand)]
+enum GraphCommand {
+    /// Node, edge and bridge counts
+    Stats(GraphSource),
+    /// Write the graph as Graphviz DOT, and its node directory as JSON
+    Export {
+        #[arg(long)]
+        dot: PathBuf,
+        /// Where the node directory goes [default: the DOT path with a .nodes.json extension]
+        #[arg(long)]
+        directory: Option<PathBuf>,
+        #[command(flatten)]
+        source: GraphSource,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum AdaptersCommand {
+    /// Probe every configured bridge
+    Health,
+}
+
+#[derive(Debug, Subcommand)]
+enum ConfigCommand {
+    /// Load the config and check its pairs against what the bridges support
+    Validate,
+    /// Look for likely mistakes in the configured pairs without contacting any bridge
+    Lint {
+        /// Also check pairs against the chains the bridges list in these recorded responses
+        #[arg(long)]
+        fixtures: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum FixturesCommand {
+    /// Quote every pair once and write each bridge's API responses to the fixture directory
+    Record {
+        #[arg(long, default_value = "fixtures/recorded")]
+        directory: PathBuf,
+        /// Which pairs to quote: those in the config, or all the adapters report
+        #[arg(long, value_enum, default_value_t = FixturePairs::Config)]
+        pairs: FixturePairs,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum FixturePairs {
+    Config,
+    Adapters,
+}
+
+async fn run(cli: Cli) -> Result<ExitCode, CliError> {
+    // Reports a broken config as a failed step rather than an error
+    if let Command::Selftest = cli.command {
+        return commands::selftest(&cli.config, cli.json).await;
+    }
+    let dal = commands::load_context(&cli.config, cli.simulate.then_some(cli.seed))?;
+    match cli.command {
+        Command::Route(args) => {
+            let intent = RouteIntent {
+                from_chain: args.from_chain,
+                from_token: args.from_token,
+                to_chain: args.to_chain,
+                to_token: args.to_token,
+                amount: args.amount,
+                preference: args.preference,
+                src_address: None,
+            };
+            let opts = RouteOptions {
+                max_results: args.max_results,
+                max_hops: args.max_hops,
+                max_swaps: args.max_swaps,
+                constraints: RouteConstraints { max_cost: args.max_cost, max_time: args.max_time, ..RouteConstraints::default() },
+                excluded_bridges: args.excluded_bridges,
+                // Someone is waiting on the answer
+                priority: RoutePriority::Interactive,
+                ..RouteOptions::default()
+            };
+            let canonical = dal.canonical_intent(&intent)?;
+            let executor = dal.route_executor();
+            // The context goes to the graph's updater, so routes are presented with a copy of its registry
+            let registry = dal.registry().clone();
+            let fx = &dal.config().fx;
+            let present = match fx.enabled() {
+                true => PresentOptions::default().with_quote_currency(&fx.quote_currency),
+                false => PresentOptions::default().in_source_tokens(),
+            };
+            let present = present.with_registry(&registry);
+            let (graph, _) = commands::load_graph(dal, args.source.snapshot.as_deref()).await?;
+            commands::route(&executor, &present, graph, &intent, &canonical, &opts, cli.json).await
+        }
+        Command::RouteBatch(args) => {
+            let format = ExportFormat::from_path(&args.output).ok_or_else(|| CliError::ExportFormat(args.output.clone()))?;
+            let input = std::fs::read_to_string(&args.input).map_err(|source| CliError::Read { path: args.input.clone(), source })?;
+            let intents: Vec<RouteIntent> = serde_json::from_str(&input)?;
+            let opts = RouteOptions {
+                max_results: args.max_results,
+                max_hops: args.max_hops,
+                excluded_bridges: args.excluded_bridges,
+                // Nobody is waiting on any one of them
+                priority: RoutePriority::Batch,
+                ..RouteOptions::default()
+            };
+            let canonical: Vec<_> = intents.iter().map(|intent| dal.canonical_intent(intent)).collect();
+            let executor = dal.route_executor();
+            let (graph, _) = commands::load_graph(dal, args.source.snapshot.as_deref()).await?;
+            commands::route_batch(&executor, graph, intents.into_iter().zip(canonical).collect(), &opts, &args.output, format, cli.json).await
+        }
+        Command::Replay(args) => {
+            let input = std::fs::read_to_string(&args.intent).map_err(|source| CliError::Read { path: args.intent.clone(), source })?;
+            let intent: RouteIntent = serde_json::from_str(&input)?;
+            let opts = RouteOptions {
+                max_results: args.max_results,
+                max_hops: args.max_hops,
+                excluded_bridges: args.excluded_bridges,
+                priority: RoutePriority::Interactive,
+                ..RouteOptions::default()
+            };
+            let canonical = dal.canonical_intent(&intent)?;
+            let replay = dal.replay(&args.graph, commands::GRAPH_SHARDS).ok_or(CliError::HistoryDisabled)?;
+            let graph = Arc::new(replay.graph_at(args.at)?);
+            let present = dal.present_options(&graph);
+            commands::route(&dal.route_executor(), &present, Arc::clone(&graph), &intent, &canonical, &opts, cli.json).await
+        }
+        Command::Graph { command: GraphCommand::Stats(source) } => {
+            let (graph, refresh) = commands::load_graph(dal, source.snapshot.as_deref()).await?;
+            commands::graph_stats(&graph, refresh, cli.json)
+        }
+        Command::Graph { command: GraphCommand::Export { dot, directory, source } } => {
+            let (graph, _) = commands::load_graph(dal, source.snapshot.as_deref()).await?;
+            let directory = directory.unwrap_or_else(|| dot.with_extension("nodes.json"));
+            commands::graph_export(&graph, &dot, &directory, cli.json)
+        }
+        Command::Adapters { command: AdaptersCommand::Health } => commands::adapters_health(&dal, cli.json).await,
+        Command::Config { command: ConfigCommand::Validate } => commands::config_validate(&dal, cli.json).await,
+        Command::Config { command: ConfigCommand::Lint { fixtures } } => {
+            let dal = match fixtures {
+                Some(directory) => dal.with_fixtures(FixtureMode::Replay, directory),
+                None => dal,
+            };
+            commands::config_lint(&dal, cli.json).await
+        }
+        Command::Fixtures { command: FixturesCommand::Record { directory, pairs } } => {
+            let dal = dal.with_fixtures(FixtureMode::Record, &directory);
+            commands::fixtures_record(&dal, pairs == FixturePairs::Config, &directory, cli.json).await
+        }
+        Command::Selftest => unreachable!("handled before the context is loaded"),
+    }
+}
+
+// Unix seconds from "YYYY-MM-DDTHH:MM[:SS]Z", in UTC, or from a plain number
+fn parse_time(text: &str) -> Result<u64, String> {
+    if let Ok(secs) = text.parse() {
+        return Ok(secs);
+    }
+    let invalid = || format!("`{}` is not a time like 2024-06-01T14:32Z", text);
+    let (date, time) = text.strip_suffix(['Z', 'z']).and_then(|text| text.split_once(['T', 't'])).ok_or_else(invalid)?;
+    let numbers = |part: &str, count: std::ops::RangeInclusive<usize>| -> Result<Vec<u64>, String> {
+        let numbers: Vec<u64> = part.split(['-', ':']).map(|n| n.parse().map_err(|_| invalid())).collect::<Result<_, _>>()?;
+        if count.contains(&numbers.len()) { Ok(numbers) } else { Err(invalid()) }
+    };
+    let (date, time) = (numbers(date, 3..=3)?, numbers(time, 2..=3)?);
+    let (year, month, day) = (date[0] as i64, date[1], date[2]);
+    let (hour, minute, second) = (time[0], time[1], time.get(2).copied().unwrap_or(0));
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    if !(1970..=9999).contains(&year) || !(1..=12).contains(&month) || !(1..=days_in_month).contains(&day) || hour > 23 || minute > 59 || second > 59 {
+        return Err(invalid());
+    }
+    // Days since 1970-01-01 of the civil date, counting years from March so leap days come last
+    let (year, month) = if month <= 2 { (year - 1, month + 9) } else { (year, month - 3) };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year as i64;
+    let days = era * 146_097 + day_of_era - 719_468;
+    Ok(days as u64 * 86_400 + hour * 3_600 + minute * 60 + second)
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = tokio::runtime::Runtime::new()
+        .map_err(CliError::Runtime)
+        .and_then(|runtime| runtime.block_on(run(cli)));
+    match result {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times_parse_as_utc() {
+        assert_eq!(parse_time("2024-06-01T14:32Z"), Ok(1_717_252_320));
+        assert_eq!(parse_time("2024-06-01T14:32:05Z"), Ok(1_717_252_325));
+        assert_eq!(parse_time("1970-01-01T00:00Z"), Ok(0));
+        assert_eq!(parse_time("2000-02-29T00:00Z"), Ok(951_782_400));
+        assert_eq!(parse_time("1717252320"), Ok(1_717_252_320));
+        for invalid in ["2024-06-01", "2024-06-01T14:32", "2024-13-01T00:00Z", "2024-06-01T24:00Z", "yesterday"] {
+            assert!(parse_time(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn impossible_dates_are_rejected() {
+        assert_eq!(parse_time("2024-02-29T00:00Z"), Ok(1_709_164_800));
+        for invalid in ["2024-02-31T00:00Z", "2023-02-29T00:00Z", "2100-02-29T00:00Z", "2024-04-31T00:00Z", "2024-06-00T00:00Z"] {
+            assert!(parse_time(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
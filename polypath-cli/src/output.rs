@@ -0,0 +1,122 @@
+use crate::error::CliError;
+use polypath_graph::{Graph, GraphSnapshot, Node, NodeId, NodeType, Path};
+use serde::Serialize;
+use std::{
+    fmt::Write as _,
+    io::{self, Write},
+};
+
+// Writes `text` and a newline to stdout. A closed pipe, as with `polypath ... | head`, is not
+// an error.
+pub fn print(text: &str) -> Result<(), CliError> {
+    let mut stdout = io::stdout().lock();
+    match writeln!(stdout, "{}", text).and_then(|_| stdout.flush()) {
+        Err(err) if err.kind() != io::ErrorKind::BrokenPipe => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+pub fn print_json(value: &impl Serialize) -> Result<(), CliError> {
+    print(&serde_json::to_string_pretty(value)?)
+}
+
+// Left-aligned columns, each as wide as its widest cell
+pub fn table(header: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = header.iter().map(|title| title.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut out = String::new();
+    let header: Vec<String> = header.iter().map(|title| title.to_string()).collect();
+    for row in std::iter::once(&header).chain(rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        let _ = writeln!(out, "{}", line.join("  ").trim_end());
+    }
+    out.truncate(out.trim_end().len());
+    out
+}
+
+// "chain/SYMBOL" for assets, "exchange@chain" for exchanges
+pub fn node_label(node: &Node) -> String {
+    match &node.node_type {
+        NodeType::Asset { chain, token_address, token_symbol } if token_symbol.is_empty() => format!("{}/{}", chain, token_address),
+        NodeType::Asset { chain, token_symbol, .. } => format!("{}/{}", chain, token_symbol),
+        NodeType::Exchange { name, chain } => format!("{}@{}", name, chain),
+    }
+}
+
+fn label_of(graph: &Graph, id: NodeId) -> String {
+    graph.get_node(id).map(|node| node_label(&node)).unwrap_or_else(|| format!("{:x}", id.0))
+}
+
+// e.g. "base/USDC -[stargate]-> arbitrum/USDC"
+pub fn describe_path(graph: &Graph, path: &Path) -> String {
+    let Some(first) = path.hops.first() else {
+        return String::new();
+    };
+    let mut out = label_of(graph, first.from);
+    for hop in &path.hops {
+        let _ = write!(out, " -[{}]-> {}", hop.bridge_name, label_of(graph, hop.to));
+    }
+    out
+}
+
+fn quoted(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Graphviz source for the snapshot: assets as ellipses, exchanges as boxes, inactive edges
+// dashed
+pub fn to_dot(snapshot: &GraphSnapshot) -> String {
+    let mut nodes = snapshot.nodes.clone();
+    nodes.sort_by_key(|node| node.id);
+    let mut out = String::from("digraph polypath {\n    rankdir=LR;\n");
+    for node in &nodes {
+        let shape = match node.node_type {
+            NodeType::Asset { .. } => "ellipse",
+            NodeType::Exchange { .. } => "box",
+        };
+        let _ = writeln!(out, "    n{:x} [label={}, shape={}];", node.id.0, quoted(&node_label(node)), shape);
+    }
+    for edge in &snapshot.edges {
+        let label = format!("{}\\ncost {:.4}, {:.0}s", edge.bridge_name, edge.metrics.cost, edge.metrics.speed);
+        let style = if edge.is_active { "" } else { ", style=dashed" };
+        let _ = writeln!(out, "    n{:x} -> n{:x} [label=\"{}\"{}];", edge.from.0, edge.to.0, label.replace('"', "\\\""), style);
+    }
+    out.push_str("}\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polypath_graph::EdgeMetrics;
+
+    #[test]
+    fn tables_and_dot_render_the_graph() {
+        assert_eq!(
+            table(&["BRIDGE", "OK"], &[vec!["stargate".to_string(), "yes".to_string()], vec!["hop".to_string(), "no".to_string()]]),
+            "BRIDGE    OK\nstargate  yes\nhop       no"
+        );
+
+        let graph = Graph::new(4);
+        let base = graph.get_or_create_asset_node("base", "0x8335", "USDC");
+        let arb = graph.get_or_create_asset_node("arbitrum", "0xaf88", "");
+        let metrics = EdgeMetrics { cost: 1.5, speed: 90.0, liquidity: 1_000.0, risk: 0.1 };
+        graph.add_edge(base, arb, "stargate", metrics, None, None).unwrap();
+        graph.set_edge_active(base, arb, "stargate", false);
+
+        let dot = to_dot(&graph.snapshot());
+        assert!(dot.starts_with("digraph polypath {"));
+        assert!(dot.contains(&format!("n{:x} [label=\"base/USDC\", shape=ellipse];", base.0)));
+        assert!(dot.contains(&format!("n{:x} [label=\"arbitrum/0xaf88\", shape=ellipse];", arb.0)));
+        assert!(dot.contains(&format!("n{:x} -> n{:x} [label=\"stargate\\ncost 1.5000, 90s\", style=dashed];", base.0, arb.0)));
+    }
+}
@@ -0,0 +1,348 @@
+use assert_cmd::Command;
+use predicates::prelude::*;
+use std::path::PathBuf;
+
+const USDC_BASE: &str = "0x833589fcd6edb6e08f4c7c32d4f71b54bda02913";
+const USDC_ARBITRUM: &str = "0xaf88d065e77c8cc2239327c5edb3a432268e5831";
+const USDC_POLYGON: &str = "0x3c499c542cef5e3811e1192ce70d8cc03d5c3359";
+
+fn pair(src_chain: &str, src_token: &str, dst_chain: &str, dst_token: &str) -> String {
+    format!(r#"
+        [[bridges.mock.pairs]]
+        source_chain = "{}"
+        source_address = "{}"
+        source_token_name = "USDC"
+        destination_chain = "{}"
+        destination_address = "{}"
+        destination_token_name = "USDC"
+    "#, src_chain, src_token, dst_chain, dst_token)
+}
+
+// The mock bridge quoting base -> arbitrum -> polygon USDC
+fn config(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("polypath-cli-{}-{}.toml", name, std::process::id()));
+    std::fs::write(&path, format!(
+        "[global]\nupdate_interval = 60\ncache_ttl = 60\nlog_level = \"info\"\n[bridges.mock]\nbase_url = \"http://mock.test\"\nchains = [\"base\", \"arbitrum\", \"polygon\"]\n[bridges.mock.extra.quote]\ncost = 0.5\n{}{}",
+        pair("base", USDC_BASE, "arbitrum", USDC_ARBITRUM),
+        pair("arbitrum", USDC_ARBITRUM, "polygon", USDC_POLYGON),
+    )).unwrap();
+    path
+}
+
+fn polypath(config: &PathBuf) -> Command {
+    let mut cmd = Command::cargo_bin("polypath").unwrap();
+    cmd.arg("--config").arg(config);
+    cmd
+}
+
+fn route(config: &PathBuf, from_chain: &str, to_chain: &str, amount: &str, preference: &str) -> Command {
+    let mut cmd = polypath(config);
+    cmd.args(["route", "--from-chain", from_chain, "--from-token", "USDC", "--to-chain", to_chain, "--to-token", "USDC"]);
+    cmd.arg(format!("--amount={}", amount)).args(["--preference", preference]);
+    cmd
+}
+
+#[test]
+fn route_prints_ranked_routes() {
+    let config = config("route");
+
+    route(&config, "base", "polygon", "1000", "cheapest")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("RANK"))
+        .stdout(predicate::str::contains("base/USDC -[mock]-> arbitrum/USDC -[mock]-> polygon/USDC"));
+
+    let output = route(&config, "base", "polygon", "1000", "cheapest").arg("--json").output().unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let best = &json["routes"][0];
+    assert_eq!(best["ranked"]["rank"], 1);
+    assert_eq!(best["ranked"]["path"]["total_cost"], 1.0);
+    assert_eq!(best["ranked"]["path"]["hops"].as_array().unwrap().len(), 2);
+
+    // The only route costs 1.0
+    route(&config, "base", "polygon", "1000", "cheapest").args(["--max-cost", "0.9"]).assert().code(3);
+    std::fs::remove_file(&config).unwrap();
+}
+
+#[test]
+fn missing_routes_and_errors_have_their_own_exit_codes() {
+    let config = config("codes");
+
+    route(&config, "polygon", "base", "1000", "cheapest")
+        .assert()
+        .code(3)
+        .stdout("")
+        .stderr(predicate::str::contains("no route from polygon USDC to base USDC"));
+    route(&config, "polygon", "base", "1000", "cheapest").arg("--json").assert().code(3).stdout(predicate::str::contains("\"routes\": []"));
+
+    polypath(&config)
+        .args(["route", "--from-chain", "base", "--from-token", "DAI", "--to-chain", "polygon", "--to-token", "USDC", "--amount", "5"])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("error: ").and(predicate::str::contains("DAI")));
+    route(&config, "base", "polygon", "1000", "scenic").assert().code(1);
+    route(&config, "base", "polygon", "-5", "cheapest").assert().code(1).stderr(predicate::str::contains("-5"));
+    route(&config, "base", "polygon", "1000", "cheapest").arg("--snapshot=nightly").assert().code(1).stderr(predicate::str::starts_with("error: "));
+
+    // Usage errors are clap's
+    route(&config, "base", "polygon", "lots", "cheapest").assert().code(2);
+    Command::cargo_bin("polypath").unwrap().args(["--config", "/nonexistent/polypath.toml", "config", "validate"]).assert().code(1);
+    std::fs::remove_file(&config).unwrap();
+}
+
+#[test]
+fn route_batch_writes_every_intent() {
+    let config = config("batch");
+    let temp = |name: &str| std::env::temp_dir().join(format!("polypath-cli-batch-{}-{}", std::process::id(), name));
+    let input = temp("intents.json");
+    let intent = |from_chain: &str, from_token: &str, to_chain: &str| {
+        serde_json::json!({ "from_chain": from_chain, "from_token": from_token, "to_chain": to_chain, "to_token": "USDC", "amount": 1000.0, "preference": "cheapest" })
+    };
+    std::fs::write(&input, serde_json::json!([intent("base", "USDC", "polygon"), intent("polygon", "USDC", "base"), intent("base", "DAI", "polygon")]).to_string()).unwrap();
+
+    let csv = temp("routes.csv");
+    polypath(&config)
+        .args(["route-batch", "--input"])
+        .arg(&input)
+        .arg("--output")
+        .arg(&csv)
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("wrote 3 rows for 3 intents (1 with routes, 1 failed)"))
+        .stderr(predicate::str::contains("warning: base DAI to polygon USDC"));
+    let written = std::fs::read_to_string(&csv).unwrap();
+    let lines: Vec<&str> = written.lines().collect();
+    assert!(lines[0].starts_with("from_chain,from_token,to_chain,to_token,amount,preference,rank,hops,bridges,total_cost"));
+    assert!(lines[1].starts_with("base,USDC,polygon,USDC,1000.0,cheapest,1,2,mock|mock,1.0,"));
+    assert!(lines[2].starts_with("polygon,USDC,base,USDC,1000.0,cheapest,,0,,"));
+    std::fs::remove_file(&csv).unwrap();
+
+    let jsonl = temp("routes.jsonl");
+    let output = polypath(&config).args(["--json", "route-batch", "--input"]).arg(&input).arg("--output").arg(&jsonl).output().unwrap();
+    assert!(output.status.success());
+    let summary: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(summary["rows"], 3);
+    let lines: Vec<serde_json::Value> = std::fs::read_to_string(&jsonl).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(lines[0]["route"]["path"]["hops"].as_array().unwrap().len(), 2);
+    assert_eq!(lines[0]["intent"]["from_chain"], "base");
+    assert!(lines[1]["route"].is_null() && lines[2]["route"].is_null());
+    std::fs::remove_file(&jsonl).unwrap();
+
+    polypath(&config)
+        .args(["route-batch", "--input"])
+        .arg(&input)
+        .arg("--output")
+        .arg(temp("routes.xlsx"))
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("use .csv or .jsonl"));
+    std::fs::remove_file(&input).unwrap();
+    std::fs::remove_file(&config).unwrap();
+}
+
+#[test]
+fn replay_routes_on_a_saved_snapshot() {
+    let temp = |name: &str| std::env::temp_dir().join(format!("polypath-cli-replay-{}-{}", std::process::id(), name));
+    let store = temp("store");
+    let _ = std::fs::remove_dir_all(&store);
+    let config = config("replay");
+    let toml = std::fs::read_to_string(&config).unwrap().replacen("[global]\n", &format!("[global]\npersistence_path = {:?}\n", store), 1);
+    std::fs::write(&config, toml).unwrap();
+
+    // A snapshot saved now, base -> polygon in one hop
+    let graph = polypath_graph::Graph::new(8);
+    let base = graph.get_or_create_asset_node("base", USDC_BASE, "USDC");
+    let polygon = graph.get_or_create_asset_node("polygon", USDC_POLYGON, "USDC");
+    let metrics = polypath_graph::EdgeMetrics { cost: 0.25, speed: 60.0, liquidity: 1_000_000.0, risk: 0.1 };
+    graph.add_edge(base, polygon, "stargate", metrics, None, None).unwrap();
+    let persistence = polypathroute_core::PersistenceManager::open(&store).unwrap();
+    let saved_at = polypath_dal::save_graph_snapshot(&persistence, &graph, "default").unwrap().saved_at;
+    drop(persistence);
+
+    let intent = temp("intent.json");
+    std::fs::write(&intent, r#"{"from_chain": "base", "from_token": "USDC", "to_chain": "polygon", "to_token": "USDC", "amount": 1000.0}"#).unwrap();
+    let replay = |at: String| {
+        let mut cmd = polypath(&config);
+        cmd.args(["replay", "--at", &at, "--intent"]).arg(&intent);
+        cmd
+    };
+    replay((saved_at + 60).to_string())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("base/USDC -[stargate]-> polygon/USDC"));
+    replay("2000-01-01T00:00Z".to_string())
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("no snapshot of graph `default` was saved at or before 946684800"));
+    replay("yesterday".to_string()).assert().code(2);
+
+    std::fs::remove_dir_all(&store).unwrap();
+    std::fs::remove_file(&intent).unwrap();
+    std::fs::remove_file(&config).unwrap();
+}
+
+#[test]
+fn graph_stats_and_export() {
+    let config = config("graph");
+
+    let output = polypath(&config).args(["graph", "stats", "--json"]).output().unwrap();
+    assert!(output.status.success());
+    let stats: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(stats["assets"], 3);
+    assert_eq!(stats["active_edges"], 2);
+    assert_eq!(stats["bridges"], serde_json::json!(["mock"]));
+    assert_eq!(stats["refresh"]["added"], 2);
+
+    let dot = std::env::temp_dir().join(format!("polypath-cli-graph-{}.dot", std::process::id()));
+    polypath(&config)
+        .args(["graph", "export", "--dot"])
+        .arg(&dot)
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("wrote 6 nodes and 2 edges"));
+    let written = std::fs::read_to_string(&dot).unwrap();
+    assert!(written.starts_with("digraph polypath {"));
+    assert_eq!(written.matches(" -> ").count(), 2);
+    std::fs::remove_file(&dot).unwrap();
+    // Next to the DOT file unless placed elsewhere
+    let directory = dot.with_extension("nodes.json");
+    let entries: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&directory).unwrap()).unwrap();
+    assert_eq!(entries.as_array().unwrap().len(), 6);
+    assert!(entries.as_array().unwrap().iter().any(|entry| entry["node_type"] == "asset" && entry["chain"] == "polygon"));
+    std::fs::remove_file(&directory).unwrap();
+
+    polypath(&config).args(["graph", "export", "--dot", "/nonexistent/dir/out.dot"]).assert().code(1);
+    std::fs::remove_file(&config).unwrap();
+}
+
+#[test]
+fn adapters_health_and_config_validate() {
+    let config = config("checks");
+
+    let output = polypath(&config).args(["adapters", "health", "--json"]).output().unwrap();
+    assert!(output.status.success());
+    let health: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(health[0]["bridge"], "mock");
+    assert_eq!(health[0]["healthy"], true);
+
+    polypath(&config)
+        .args(["config", "validate"])
+        .assert()
+        .success()
+        .stdout("config is valid: 1 bridge(s), 0 warning(s)\n");
+    polypath(&config)
+        .args(["--json", "config", "validate"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"valid\": true"));
+    std::fs::remove_file(&config).unwrap();
+}
+
+#[test]
+fn config_lint_fails_on_errors_only() {
+    let config = config("lint");
+    polypath(&config)
+        .args(["config", "lint"])
+        .assert()
+        .success()
+        .stdout(predicate::str::ends_with("0 error(s), 0 warning(s), 0 info\n"));
+    std::fs::remove_file(&config).unwrap();
+
+    let path = std::env::temp_dir().join(format!("polypath-cli-lint-typo-{}.toml", std::process::id()));
+    std::fs::write(&path, format!(
+        "[global]\nupdate_interval = 60\ncache_ttl = 60\nlog_level = \"info\"\n[bridges.mock]\nbase_url = \"http://mock.test\"\nchains = [\"base\", \"polgon\"]\n{}",
+        pair("base", USDC_BASE, "polgon", USDC_POLYGON),
+    )).unwrap();
+    polypath(&path)
+        .args(["config", "lint"])
+        .assert()
+        .code(6)
+        .stdout(predicate::str::contains("(did you mean polygon?)"));
+    let output = polypath(&path).args(["--json", "config", "lint"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(6));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["findings"][0]["kind"], "unknown_chain");
+    assert_eq!(report["findings"][0]["suggestion"], "polygon");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn simulate_quotes_bridges_offline() {
+    // Unreachable APIs, so routes can only come from the simulator
+    let path = std::env::temp_dir().join(format!("polypath-cli-simulate-{}.toml", std::process::id()));
+    std::fs::write(&path, format!(
+        "[global]\nupdate_interval = 60\ncache_ttl = 60\nlog_level = \"info\"\n[bridges.wormhole]\nbase_url = \"http://127.0.0.1:9\"\nchains = [\"base\", \"arbitrum\"]\n[bridges.wormhole.extra.simulation]\nbase_fee = 0.5\nvolatility = 0\n{}",
+        pair("base", USDC_BASE, "arbitrum", USDC_ARBITRUM).replace("bridges.mock", "bridges.wormhole"),
+    )).unwrap();
+
+    let output = route(&path, "base", "arbitrum", "1000", "cheapest").args(["--simulate", "--seed", "3", "--json"]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let best = &json["routes"][0]["ranked"]["path"];
+    assert_eq!(best["total_cost"], 0.5);
+    assert_eq!(best["hops"][0]["bridge_name"], "wormhole");
+
+    // Without the simulator the bridge can't be reached
+    route(&path, "base", "arbitrum", "1000", "cheapest")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("1 quote(s) failed"));
+    polypath(&path).args(["graph", "stats", "--seed", "3"]).assert().code(2);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn fixtures_record_quotes_each_configured_pair() {
+    let config = config("fixtures");
+    let directory = std::env::temp_dir().join(format!("polypath-cli-fixtures-{}", std::process::id()));
+
+    let output = polypath(&config).args(["--json", "fixtures", "record", "--directory"]).arg(&directory).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let rows: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(rows[0]["bridge"], "mock");
+    assert_eq!(rows[0]["quoted"], 2);
+    assert_eq!(rows[0]["failed"], 0);
+    std::fs::remove_file(&config).unwrap();
+
+    // A bridge that can't be reached leaves its fixtures unrecorded
+    let path = std::env::temp_dir().join(format!("polypath-cli-fixtures-down-{}.toml", std::process::id()));
+    std::fs::write(&path, format!(
+        "[global]\nupdate_interval = 60\ncache_ttl = 60\nlog_level = \"info\"\n[bridges.wormhole]\nbase_url = \"http://127.0.0.1:9\"\nchains = [\"base\", \"arbitrum\"]\n[bridges.wormhole.extra.retry]\nmax_attempts = 1\n{}",
+        pair("base", USDC_BASE, "arbitrum", USDC_ARBITRUM).replace("bridges.mock", "bridges.wormhole"),
+    )).unwrap();
+    polypath(&path)
+        .args(["fixtures", "record", "--pairs", "config", "--directory"])
+        .arg(&directory)
+        .assert()
+        .code(4)
+        .stdout(predicate::str::contains("wormhole"));
+    std::fs::remove_file(&path).unwrap();
+    let _ = std::fs::remove_dir_all(&directory);
+}
+
+#[test]
+fn selftest_reports_each_step() {
+    let sample = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../polypath-dal/src/config/config.toml"));
+
+    polypath(&sample)
+        .arg("selftest")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("STEP"))
+        .stdout(predicate::str::contains("fixtures"));
+    let output = polypath(&sample).args(["selftest", "--json"]).output().unwrap();
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(report["steps"].as_array().unwrap().iter().all(|step| step["status"] == "pass"), "{}", report);
+
+    // The mock bridge bundles no responses, and a missing config is a failed step rather than an error
+    let config = config("selftest");
+    polypath(&config).arg("selftest").assert().code(5).stdout(predicate::str::contains("no adapter bundles a response"));
+    std::fs::remove_file(&config).unwrap();
+    polypath(&config)
+        .args(["--json", "selftest"])
+        .assert()
+        .code(5)
+        .stdout(predicate::str::contains("\"status\": \"skip\""));
+}
@@ -0,0 +1,371 @@
+use super::{
+    AdapterContext,
+    fixture_request,
+    FixtureQuote,
+    BridgeAdapter,
+    BridgeEdge,
+    QuoteRequest,
+    SupportedPair,
+    RiskModel,
+    unix_now,
+    FeeComponent,
+    chains::evm_chain_id,
+    settings::AdapterSettings,
+    AdapterError,
+    AdapterHealth,
+    health::probe,
+    MetricsRecorder,
+    RateLimiter,
+    RetryPolicy,
+    TokenDecimals
+};
+use crate::fx::{CurrencyId, Money};
+
+use std::time::Duration;
+use std::sync::{Arc, RwLock};
+use async_trait::async_trait;
+use polypathroute_core::{BridgeConfig, Redacted};
+use reqwest::Client;
+use serde_json::Value;
+use anyhow::Result;
+
+pub struct AcrossAdapter {
+    pub name: String,
+    pub base_url: String,
+    api_key: Option<Redacted<String>>,
+    retry: RetryPolicy,
+    rate_limiter: Option<RateLimiter>,
+    client: Client,
+    pairs: RwLock<Vec<SupportedPair>>,
+    decimals: TokenDecimals,
+    risk_model: Arc<dyn RiskModel>,
+    telemetry: Arc<MetricsRecorder>,
+    // How long quotes stay usable when the API reports no expiry
+    quote_validity: Duration
+}
+
+// Deposit bounds reported by the spoke pool for a quoted route
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Limits {
+    min_deposit: f64,
+    max_deposit: f64,
+}
+
+impl AcrossAdapter {
+    pub fn from_config(config: &BridgeConfig) -> Result<Self> {
+        Self::new(AdapterContext::from_config("across", config)?)
+    }
+
+    // Pairs on chains Across has no chain id for are dropped
+    pub fn new(context: AdapterContext) -> Result<Self> {
+        let settings = AdapterSettings::from_config("across", &context.config)?;
+        let client = context.client;
+        let pairs = settings.pairs
+            .into_iter()
+            .filter(|pair| evm_chain_id(&pair.src_chain).is_some() && evm_chain_id(&pair.dst_chain).is_some())
+            .collect();
+
+        Ok(Self {
+            name: "across".to_string(),
+            base_url: settings.base_url,
+            api_key: settings.api_key,
+            retry: settings.retry,
+            rate_limiter: settings.rate_limiter,
+            client,
+            pairs: RwLock::new(pairs),
+            decimals: settings.decimals,
+            risk_model: settings.risk_model,
+            telemetry: settings.telemetry,
+            quote_validity: settings.quote_validity
+        })
+    }
+
+    // Replaces the risk model built from config
+    pub fn with_risk_model(mut self, risk_model: Arc<dyn RiskModel>) -> Self {
+        self.risk_model = risk_model;
+        self
+    }
+
+    pub fn suggested_fees_url(&self) -> String {
+        format!("{}/suggested-fees", self.base_url)
+    }
+
+    pub fn available_routes_url(&self) -> String {
+        format!("{}/available-routes", self.base_url)
+    }
+
+    // Maps a /suggested-fees body onto a BridgeEdge.
+    // cost = total relay fee + lp fee, speed = estimatedFillTimeSec, liquidity = limits.maxDeposit.
+    // Fees and limits are in input token units.
+    // Across reports rejections as {"code", "message", "param"} bodies.
+    fn parse_fees(&self, request: &QuoteRequest, response: &Value) -> Result<(BridgeEdge, Limits), AdapterError> {
+        let total = |field: &str| -> Option<f64> {
+            response.get(field)
+                .and_then(|fee| fee.get("total"))
+                .and_then(|v| v.as_str())
+                .and_then(|s| s.parse::<f64>().ok())
+        };
+        let human = |raw: f64| self.decimals.to_human(&request.src_chain, &request.src_token, raw);
+        let limit = |field: &str| -> Result<Option<f64>, AdapterError> {
+            response.get("limits")
+                .and_then(|limits| limits.get(field))
+                .and_then(|v| v.as_str())
+                .and_then(|s| s.parse::<f64>().ok())
+                .map(human)
+                .transpose()
+        };
+
+        if let Some(code) = response.get("code").and_then(|v| v.as_str()) {
+            let message = response.get("message").and_then(|v| v.as_str()).unwrap_or("");
+            let param = response.get("param").and_then(|v| v.as_str()).unwrap_or("");
+            return Err(match code {
+                "AMOUNT_TOO_LOW" => {
+                    let (min, max) = self.known_limits(request);
+                    AdapterError::AmountOutOfRange { min, max }
+                }
+                "INVALID_PARAM" if param == "inputToken" || param == "outputToken" => {
+                    AdapterError::unsupported_pair(&self.name, request)
+                }
+                _ => {
+                    let status = response.get("status").and_then(|v| v.as_u64()).unwrap_or(400);
+                    AdapterError::upstream(status as u16, &format!("{}: {}", code, message))
+                }
+            });
+        }
+
+        if response.get("isAmountTooLow").and_then(|v| v.as_bool()).unwrap_or(false) {
+            return Err(AdapterError::AmountOutOfRange { min: limit("minDeposit")?, max: limit("maxDeposit")? });
+        }
+
+        let relay_fee = total("totalRelayFee").ok_or_else(|| AdapterError::missing("totalRelayFee"))?;
+        let lp_fee = total("lpFee").unwrap_or(0.0);
+        let speed = response.get("estimatedFillTimeSec")
+                        .and_then(|v| v.as_f64())
+                        .ok_or_else(|| AdapterError::missing("estimatedFillTimeSec"))?;
+        let limits = Limits {
+            min_deposit: limit("minDeposit")?.unwrap_or(0.0),
+            max_deposit: limit("maxDeposit")?.ok_or_else(|| AdapterError::missing("limits.maxDeposit"))?,
+        };
+
+        let source = CurrencyId::token(&request.src_chain, &request.src_token);
+        let fee_components = vec![
+            FeeComponent { name: "relay".to_string(), amount: Money::new(human(relay_fee)?, source.clone()) },
+            FeeComponent { name: "lp".to_string(), amount: Money::new(human(lp_fee)?, source) },
+        ];
+        let cost = human(relay_fee + lp_fee)?;
+        // Across fills the same token on the destination, minus fees
+        let input = request.src_amount.parse::<f64>().unwrap_or(0.0);
+
+        let edge = self.risk_model.score(&self.name, request, BridgeEdge {
+            from: request.src_chain.clone(),
+            to: request.dst_chain.clone(),
+            cost,
+            speed,
+            liquidity: limits.max_deposit,
+            via: None,
+            bridge: self.name.clone(),
+            estimated_output: (input - cost).max(0.0),
+            fee_components,
+            quoted_at: unix_now(),
+            min_amount: Some(limits.min_deposit),
+            max_amount: Some(limits.max_deposit),
+            ..BridgeEdge::default()
+        }).with_default_validity(self.quote_validity);
+        Ok((edge, limits))
+    }
+
+    // Limits recorded for the pair by an earlier quote, if any
+    fn known_limits(&self, request: &QuoteRequest) -> (Option<f64>, Option<f64>) {
+        self.pairs.read().unwrap()
+            .iter()
+            .find(|pair| pair.matches(&request.src_chain, &request.dst_chain, &request.src_token, &request.dst_token))
+            .map(|pair| (pair.min_amount, pair.max_amount))
+            .unwrap_or((None, None))
+    }
+
+    // Keeps the pair's min/max amounts in line with the latest spoke pool limits
+    fn record_limits(&self, request: &QuoteRequest, limits: Limits) {
+        let mut pairs = self.pairs.write().unwrap();
+        if let Some(pair) = pairs.iter_mut().find(|pair| {
+            pair.matches(&request.src_chain, &request.dst_chain, &request.src_token, &request.dst_token)
+        }) {
+            pair.min_amount = Some(limits.min_deposit);
+            pair.max_amount = Some(limits.max_deposit);
+        }
+    }
+}
+
+#[async_trait]
+impl BridgeAdapter for AcrossAdapter {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn supported_pairs(&self) -> Vec<SupportedPair> {
+        self.pairs.read().unwrap().clone()
+    }
+
+    fn rate_limiter(&self) -> Option<&RateLimiter> {
+        self.rate_limiter.as_ref()
+    }
+
+    fn telemetry(&self) -> Option<&MetricsRecorder> {
+        Some(&self.telemetry)
+    }
+
+    async fn health_check(&self) -> Result<AdapterHealth, AdapterError> {
+        let request = self.client.get(self.available_routes_url());
+        let request = match &self.api_key {
+            Some(key) => request.header("x-api-key", key.expose()),
+            None => request
+        };
+        probe(self.rate_limiter.as_ref(), request).await
+    }
+
+    fn replay_fixture(&self) -> Option<FixtureQuote> {
+        const FEES: &str = include_str!("../../fixtures/across/suggested_fees.json");
+        let request = fixture_request("arbitrum", "0xaf88d065e77c8cc2239327c5edb3a432268e5831", "base", "0x833589fcd6edb6e08f4c7c32d4f71b54bda02913", "1");
+        let result = serde_json::from_str(FEES).map_err(AdapterError::from).and_then(|response| self.parse_fees(&request, &response).map(|(edge, _)| edge));
+        Some(FixtureQuote { request, result })
+    }
+
+    async fn fetch_metrics(&self, request: &QuoteRequest) -> Result<BridgeEdge, AdapterError> {
+        let (origin, destination) = match (evm_chain_id(&request.src_chain), evm_chain_id(&request.dst_chain)) {
+            (Some(origin), Some(destination)) => (origin.to_string(), destination.to_string()),
+            _ => return Err(AdapterError::unsupported_pair(&self.name, request)),
+        };
+
+        let amount = request.src_amount_in(&self.decimals)?.to_raw_string();
+        let params = [
+            ("inputToken", request.src_token.as_str()),
+            ("outputToken", request.dst_token.as_str()),
+            ("originChainId", origin.as_str()),
+            ("destinationChainId", destination.as_str()),
+            ("amount", amount.as_str()),
+        ];
+
+        let response: Value = self.retry
+            .send_with_client_errors(self.rate_limiter.as_ref(), || {
+                let request = self.client.get(self.suggested_fees_url()).query(&params);
+                match &self.api_key {
+                    Some(key) => request.header("x-api-key", key.expose()),
+                    None => request
+                }
+            })
+            .await?
+            .json()
+            .await?;
+
+        let (edge, limits) = self.parse_fees(request, &response)?;
+        self.record_limits(request, limits);
+        Ok(edge)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::{DefaultRiskModel, RiskContext};
+    use wiremock::{Mock, MockServer, ResponseTemplate, matchers::{method, path, query_param}};
+
+    const FEES: &str = include_str!("../../fixtures/across/suggested_fees.json");
+    const AMOUNT_TOO_LOW: &str = include_str!("../../fixtures/across/amount_too_low.json");
+    const UNSUPPORTED_TOKEN: &str = include_str!("../../fixtures/across/unsupported_token.json");
+
+    fn adapter(base_url: &str) -> AcrossAdapter {
+        let config = format!(r#"
+            base_url = "{}"
+            chains = ["arbitrum", "base"]
+
+            [[pairs]]
+            source_chain = "arbitrum"
+            source_token_name = "USDC"
+            destination_chain = "base"
+            destination_token_name = "USDC"
+            source_address = "0xaf88d065e77c8cC2239327C5EDb3A432268e5831"
+            destination_address = "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913"
+        "#, base_url);
+        AcrossAdapter::from_config(&toml::from_str(&config).unwrap()).unwrap()
+    }
+
+    fn request(amount: &str) -> QuoteRequest {
+        QuoteRequest::builder()
+            .src_chain("arbitrum")
+            .dst_chain("base")
+            .src_token("0xaf88d065e77c8cC2239327C5EDb3A432268e5831")
+            .dst_token("0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913")
+            .src_amount(amount)
+            .wallet("0xca699201b15ccef3b8c4012e28570cc5500d9f9a")
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn parses_suggested_fees() {
+        let adapter = adapter("https://across.test/api");
+        let (edge, limits) = adapter.parse_fees(&request("1"), &serde_json::from_str(FEES).unwrap()).unwrap();
+
+        assert_eq!(adapter.suggested_fees_url(), "https://across.test/api/suggested-fees");
+        assert_eq!(edge.from, "arbitrum");
+        assert_eq!(edge.to, "base");
+        assert_eq!(edge.cost, 0.000334);
+        assert_eq!(edge.speed, 12.0);
+        assert_eq!(edge.liquidity, 1816953.927947);
+        assert_eq!(edge.risk, DefaultRiskModel::default().assess("across", &edge, &RiskContext { amount: Some(1.0), ..RiskContext::default() }));
+        assert_eq!(limits, Limits { min_deposit: 0.034713, max_deposit: 1816953.927947 });
+        assert_eq!(edge.min_amount, Some(0.034713));
+        assert_eq!(edge.estimated_output, 1.0 - edge.cost);
+    }
+
+    #[test]
+    fn reports_rejections() {
+        let adapter = adapter("https://across.test/api");
+        let err = adapter.parse_fees(&request("0.00001"), &serde_json::from_str(AMOUNT_TOO_LOW).unwrap()).unwrap_err();
+        assert_eq!(err, AdapterError::AmountOutOfRange { min: None, max: None });
+
+        let err = adapter.parse_fees(&request("1"), &serde_json::from_str(UNSUPPORTED_TOKEN).unwrap()).unwrap_err();
+        assert_eq!(err, AdapterError::unsupported_pair("across", &request("1")));
+    }
+
+    #[tokio::test]
+    async fn fetch_records_limits_on_the_pair() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/suggested-fees"))
+            .and(query_param("originChainId", "42161"))
+            .and(query_param("destinationChainId", "8453"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(FEES))
+            .mount(&server)
+            .await;
+
+        let adapter = adapter(&server.uri());
+        let edge = adapter.fetch_metrics(&request("1")).await.unwrap();
+
+        assert_eq!(edge.cost, 0.000334);
+        let pair = &adapter.supported_pairs()[0];
+        assert_eq!(pair.min_amount, Some(0.034713));
+        assert_eq!(pair.max_amount, Some(1816953.927947));
+    }
+
+    #[tokio::test]
+    async fn amount_too_low_reports_recorded_limits() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(query_param("amount", "1000000"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(FEES))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(query_param("amount", "10"))
+            .respond_with(ResponseTemplate::new(400).set_body_string(AMOUNT_TOO_LOW))
+            .mount(&server)
+            .await;
+
+        let adapter = adapter(&server.uri());
+        adapter.fetch_metrics(&request("1")).await.unwrap();
+        let err = adapter.fetch_metrics(&request("0.00001")).await.unwrap_err();
+
+        assert_eq!(err, AdapterError::AmountOutOfRange { min: Some(0.034713), max: Some(1816953.927947) });
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+    }
+}
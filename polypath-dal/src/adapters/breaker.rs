@@ -0,0 +1,569 @@
+use super::{
+    AdapterError,
+    AdapterHealth,
+    BridgeAdapter,
+    BridgeEdge,
+    ChainInfo,
+    DynBridgeAdapter,
+    Disposition,
+    FixtureQuote,
+    MetricsRecorder,
+    QuoteRequest,
+    RateLimiter,
+    SupportedPair,
+    TransferReference,
+    TransferStatus
+};
+
+use std::{fmt, sync::{Arc, Mutex}, time::{Duration, Instant}};
+use async_trait::async_trait;
+use polypathroute_core::BridgeConfig;
+use anyhow::{Result, anyhow};
+
+// Time source for the breaker, swapped for a ManualClock in tests
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+#[derive(Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+// Clock that only moves when told to. Clones share the same time.
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    start: Instant,
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+            elapsed: Arc::new(Mutex::new(Duration::ZERO)),
+        }
+    }
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.elapsed.lock().unwrap() += by;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + *self.elapsed.lock().unwrap()
+    }
+}
+
+// Read from `breaker_failure_threshold` / `breaker_cool_down_ms` in a bridge's `extra` table
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BreakerSettings {
+    // Consecutive failures that open the breaker
+    pub failure_threshold: u32,
+    // How long an open breaker fails fast before letting a probe through
+    pub cool_down: Duration,
+}
+
+impl Default for BreakerSettings {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            cool_down: Duration::from_secs(30),
+        }
+    }
+}
+
+impl BreakerSettings {
+    pub fn from_config(bridge: &str, config: &BridgeConfig) -> Result<Self> {
+        let mut settings = Self::default();
+        let Some(extra) = config.extra.as_ref() else {
+            return Ok(settings);
+        };
+
+        let positive = |key: &str| -> Result<Option<u64>> {
+            match extra.get(key) {
+                Some(value) => value
+                    .as_integer()
+                    .filter(|v| *v > 0)
+                    .map(|v| Some(v as u64))
+                    .ok_or_else(|| anyhow!("bridges.{}.extra.{} must be a positive integer", bridge, key)),
+                None => Ok(None),
+            }
+        };
+
+        if let Some(threshold) = positive("breaker_failure_threshold")? {
+            settings.failure_threshold = threshold.min(u32::MAX as u64) as u32;
+        }
+        if let Some(cool_down) = positive("breaker_cool_down_ms")? {
+            settings.cool_down = Duration::from_millis(cool_down);
+        }
+        Ok(settings)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    Closed,
+    // Failing fast until `retry_at`
+    Open { retry_at: Instant },
+    // Cool-down is over, one probe request decides whether to close again
+    HalfOpen,
+}
+
+#[derive(Debug)]
+struct Circuit {
+    state: CircuitState,
+    consecutive_failures: u32,
+    // The probe let through while half-open, numbered so a late permit can't release another's
+    probe_in_flight: Option<u64>,
+    probes: u64,
+}
+
+// Closed -> Open after `failure_threshold` consecutive failures, Open -> HalfOpen once the
+// cool-down has passed, HalfOpen -> Closed on a successful probe or back to Open on a failed one.
+// Only errors a retry could fix (5xx, timeouts, network) count as failures.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    settings: BreakerSettings,
+    clock: Arc<dyn Clock>,
+    circuit: Mutex<Circuit>,
+}
+
+impl CircuitBreaker {
+    pub fn new(settings: BreakerSettings) -> Self {
+        Self::with_clock(settings, Arc::new(SystemClock))
+    }
+
+    pub fn with_clock(settings: BreakerSettings, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            settings,
+            clock,
+            circuit: Mutex::new(Circuit {
+                state: CircuitState::Closed,
+                consecutive_failures: 0,
+                probe_in_flight: None,
+                probes: 0,
+            }),
+        }
+    }
+
+    pub fn settings(&self) -> BreakerSettings {
+        self.settings
+    }
+
+    pub fn state(&self) -> CircuitState {
+        let mut circuit = self.circuit.lock().unwrap();
+        self.expire_cool_down(&mut circuit);
+        circuit.state
+    }
+
+    fn expire_cool_down(&self, circuit: &mut Circuit) {
+        if let CircuitState::Open { retry_at } = circuit.state
+            && self.clock.now() >= retry_at
+        {
+            circuit.state = CircuitState::HalfOpen;
+        }
+    }
+
+    // Admits a request or fails fast. In half-open state only one probe is let through at a time;
+    // if its permit is dropped before an outcome is recorded, e.g. because the request was
+    // cancelled, the next request probes instead.
+    pub fn acquire(&self) -> Result<Permit<'_>, AdapterError> {
+        let mut circuit = self.circuit.lock().unwrap();
+        self.expire_cool_down(&mut circuit);
+        match circuit.state {
+            CircuitState::Closed => Ok(Permit { breaker: self, probe: None }),
+            CircuitState::Open { retry_at } => Err(AdapterError::CircuitOpen { retry_at }),
+            CircuitState::HalfOpen if circuit.probe_in_flight.is_some() => {
+                Err(AdapterError::CircuitOpen { retry_at: self.clock.now() })
+            }
+            CircuitState::HalfOpen => {
+                circuit.probes += 1;
+                circuit.probe_in_flight = Some(circuit.probes);
+                Ok(Permit { breaker: self, probe: Some(circuit.probes) })
+            }
+        }
+    }
+
+    // Outcome of a request admitted with `probe`. Once the circuit has opened, only the half-open
+    // probe's outcome moves it; late results of requests admitted before that are ignored.
+    fn record<T>(&self, probe: Option<u64>, result: &Result<T, AdapterError>) -> bool {
+        let failed = matches!(result, Err(err) if err.disposition() == Disposition::Retry);
+        let mut circuit = self.circuit.lock().unwrap();
+        let probing = probe.is_some() && circuit.probe_in_flight == probe;
+        if probing {
+            circuit.probe_in_flight = None;
+        } else if circuit.state != CircuitState::Closed {
+            return false;
+        }
+
+        if !failed {
+            circuit.consecutive_failures = 0;
+            circuit.state = CircuitState::Closed;
+            return false;
+        }
+        circuit.consecutive_failures = circuit.consecutive_failures.saturating_add(1);
+        if probing || circuit.consecutive_failures >= self.settings.failure_threshold {
+            circuit.state = CircuitState::Open { retry_at: self.clock.now() + self.settings.cool_down };
+            return true;
+        }
+        false
+    }
+}
+
+// A request let through by `CircuitBreaker::acquire`, to be held until its outcome is recorded
+#[derive(Debug)]
+pub struct Permit<'a> {
+    breaker: &'a CircuitBreaker,
+    probe: Option<u64>,
+}
+
+impl Permit<'_> {
+    // Records the request's outcome; returns true when it opened the circuit
+    pub fn record<T>(&self, result: &Result<T, AdapterError>) -> bool {
+        self.breaker.record(self.probe, result)
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        let mut circuit = self.breaker.circuit.lock().unwrap();
+        if self.probe.is_some() && circuit.probe_in_flight == self.probe {
+            circuit.probe_in_flight = None;
+        }
+    }
+}
+
+// Wraps an adapter so every quote goes through its circuit breaker.
+// The breaker lives with the instance, so concurrent callers share its state.
+// Quotes, their latency and breaker events are recorded into the inner adapter's telemetry.
+pub struct BreakerAdapter {
+    inner: DynBridgeAdapter,
+    breaker: CircuitBreaker,
+}
+
+impl BreakerAdapter {
+    pub fn new(inner: DynBridgeAdapter, breaker: CircuitBreaker) -> Self {
+        Self { inner, breaker }
+    }
+
+    pub fn breaker(&self) -> &CircuitBreaker {
+        &self.breaker
+    }
+
+    fn admit(&self) -> Result<Permit<'_>, AdapterError> {
+        let admitted = self.breaker.acquire();
+        if admitted.is_err()
+            && let Some(telemetry) = self.inner.telemetry()
+        {
+            telemetry.record_circuit_rejection();
+        }
+        admitted
+    }
+
+    fn observe<T>(&self, permit: &Permit<'_>, started: Instant, result: &Result<T, AdapterError>) {
+        let opened = permit.record(result);
+        if let Some(telemetry) = self.inner.telemetry() {
+            telemetry.record(started.elapsed(), result);
+            if opened {
+                telemetry.record_circuit_open();
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl BridgeAdapter for BreakerAdapter {
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    fn supported_pairs(&self) -> Vec<SupportedPair> {
+        self.inner.supported_pairs()
+    }
+
+    fn rate_limiter(&self) -> Option<&RateLimiter> {
+        self.inner.rate_limiter()
+    }
+
+    fn circuit_state(&self) -> Option<CircuitState> {
+        Some(self.breaker.state())
+    }
+
+    fn telemetry(&self) -> Option<&MetricsRecorder> {
+        self.inner.telemetry()
+    }
+
+    fn is_supported_pair(&self, src_chain: &str, dst_chain: &str, src_token: &str, dst_token: &str) -> bool {
+        self.inner.is_supported_pair(src_chain, dst_chain, src_token, dst_token)
+    }
+
+    async fn fetch_metrics(&self, request: &QuoteRequest) -> Result<BridgeEdge, AdapterError> {
+        let permit = self.admit()?;
+        let started = Instant::now();
+        let result = self.inner.fetch_metrics(request).await;
+        self.observe(&permit, started, &result);
+        result
+    }
+
+    fn max_batch_size(&self) -> usize {
+        self.inner.max_batch_size()
+    }
+
+    // One call through the breaker, which then counts each answer as a request of its own
+    async fn fetch_metrics_batch(&self, requests: &[QuoteRequest]) -> Vec<Result<BridgeEdge, AdapterError>> {
+        let permit = match self.admit() {
+            Ok(permit) => permit,
+            Err(err) => return requests.iter().map(|_| Err(err.clone())).collect(),
+        };
+        let started = Instant::now();
+        let results = self.inner.fetch_metrics_batch(requests).await;
+        for result in &results {
+            self.observe(&permit, started, result);
+        }
+        results
+    }
+
+    async fn fetch_depth(&self, request: &QuoteRequest, amounts: &[f64]) -> Result<Vec<(f64, BridgeEdge)>, AdapterError> {
+        let permit = self.admit()?;
+        let started = Instant::now();
+        let result = self.inner.fetch_depth(request, amounts).await;
+        self.observe(&permit, started, &result);
+        result
+    }
+
+    // Not gated by the breaker, so an open circuit can still be inspected
+    async fn health_check(&self) -> Result<AdapterHealth, AdapterError> {
+        let mut health = self.inner.health_check().await?;
+        health.circuit = Some(self.breaker.state());
+        Ok(health)
+    }
+
+    // Not gated either: listings are fetched once and cached by the adapter
+    async fn supported_chains(&self) -> Result<Vec<ChainInfo>, AdapterError> {
+        self.inner.supported_chains().await
+    }
+
+    // Parsing only, so not gated either
+    fn replay_fixture(&self) -> Option<FixtureQuote> {
+        self.inner.replay_fixture()
+    }
+
+    // Not gated and not counted: transfer lookups say nothing of whether quotes can be had
+    async fn track_transfer(&self, reference: &TransferReference) -> Result<TransferStatus, AdapterError> {
+        self.inner.track_transfer(reference).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::mock::MockAdapter;
+
+    fn edge(from: &str, to: &str) -> BridgeEdge {
+        BridgeEdge {
+            from: from.to_string(),
+            to: to.to_string(),
+            cost: 1.0,
+            ..BridgeEdge::default()
+        }
+    }
+
+    fn request(src_chain: &str, dst_chain: &str) -> QuoteRequest {
+        QuoteRequest::builder()
+            .src_chain(src_chain)
+            .dst_chain(dst_chain)
+            .src_token("usdc")
+            .dst_token("usdc")
+            .src_amount("1")
+            .wallet("0xca699201b15ccef3b8c4012e28570cc5500d9f9a")
+            .build()
+            .unwrap()
+    }
+
+    fn guarded(mock: &Arc<MockAdapter>, clock: &ManualClock) -> BreakerAdapter {
+        let settings = BreakerSettings { failure_threshold: 3, cool_down: Duration::from_secs(60) };
+        BreakerAdapter::new(
+            Box::new(Arc::clone(mock)),
+            CircuitBreaker::with_clock(settings, Arc::new(clock.clone())),
+        )
+    }
+
+    #[tokio::test]
+    async fn opens_fails_fast_and_closes_after_a_good_probe() {
+        let mock = Arc::new(MockAdapter::new()
+            .with_quote("ethereum", "polygon", edge("ethereum", "polygon"))
+            .with_failure("base", "polygon", AdapterError::upstream(503, "down")));
+        let clock = ManualClock::new();
+        let adapter = guarded(&mock, &clock);
+        let failing = request("base", "polygon");
+        let healthy = request("ethereum", "polygon");
+
+        for _ in 0..3 {
+            assert_eq!(adapter.fetch_metrics(&failing).await.unwrap_err(), AdapterError::upstream(503, "down"));
+        }
+        let retry_at = clock.now() + Duration::from_secs(60);
+        assert_eq!(adapter.circuit_state(), Some(CircuitState::Open { retry_at }));
+
+        // Open: nothing reaches the upstream, even for a pair that would succeed
+        assert_eq!(adapter.fetch_metrics(&healthy).await.unwrap_err(), AdapterError::CircuitOpen { retry_at });
+        assert_eq!(mock.call_count(), 3);
+
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(adapter.circuit_state(), Some(CircuitState::HalfOpen));
+
+        adapter.fetch_metrics(&healthy).await.unwrap();
+        assert_eq!(adapter.circuit_state(), Some(CircuitState::Closed));
+        assert_eq!(mock.call_count(), 4);
+    }
+
+    #[tokio::test]
+    async fn failed_probe_reopens_and_other_errors_do_not_trip() {
+        let mock = Arc::new(MockAdapter::new()
+            .with_failure("base", "polygon", AdapterError::Timeout { attempts: 3 }));
+        let clock = ManualClock::new();
+        let adapter = guarded(&mock, &clock);
+
+        // Unsupported pairs are the caller's problem, not the upstream's
+        for _ in 0..5 {
+            assert!(matches!(
+                adapter.fetch_metrics(&request("ethereum", "polygon")).await,
+                Err(AdapterError::UnsupportedPair { .. })
+            ));
+        }
+        assert_eq!(adapter.circuit_state(), Some(CircuitState::Closed));
+
+        for _ in 0..3 {
+            adapter.fetch_metrics(&request("base", "polygon")).await.unwrap_err();
+        }
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(adapter.circuit_state(), Some(CircuitState::HalfOpen));
+
+        adapter.fetch_metrics(&request("base", "polygon")).await.unwrap_err();
+        let retry_at = clock.now() + Duration::from_secs(60);
+        assert_eq!(adapter.circuit_state(), Some(CircuitState::Open { retry_at }));
+    }
+
+    #[tokio::test]
+    async fn quotes_and_breaker_events_feed_telemetry() {
+        let mock = Arc::new(MockAdapter::new()
+            .with_quote("ethereum", "polygon", edge("ethereum", "polygon"))
+            .with_failure("base", "polygon", AdapterError::upstream(503, "down"))
+            .with_latency(Duration::from_millis(50)));
+        let clock = ManualClock::new();
+        let adapter = guarded(&mock, &clock);
+
+        for _ in 0..4 {
+            adapter.fetch_metrics(&request("ethereum", "polygon")).await.unwrap();
+        }
+        for _ in 0..3 {
+            adapter.fetch_metrics(&request("base", "polygon")).await.unwrap_err();
+        }
+        // Rejected by the open circuit: counted, but not as a request
+        adapter.fetch_metrics(&request("ethereum", "polygon")).await.unwrap_err();
+
+        let metrics = adapter.metrics();
+        assert_eq!(metrics, mock.metrics());
+        assert_eq!(metrics.requests, 7);
+        assert_eq!(metrics.errors, 3);
+        assert_eq!(metrics.errors_by_kind["upstream"], 3);
+        assert_eq!(metrics.last_error.unwrap().message, "upstream returned 503: down");
+        assert_eq!(metrics.circuit_opens, 1);
+        assert_eq!(metrics.circuit_rejections, 1);
+        for latency in [metrics.latency_p50_ms.unwrap(), metrics.latency_p95_ms.unwrap()] {
+            assert!((50.0..150.0).contains(&latency), "latency {}ms", latency);
+        }
+    }
+
+    #[test]
+    fn one_probe_at_a_time_when_half_open() {
+        let clock = ManualClock::new();
+        let settings = BreakerSettings { failure_threshold: 1, cool_down: Duration::from_secs(10) };
+        let breaker = CircuitBreaker::with_clock(settings, Arc::new(clock.clone()));
+
+        breaker.acquire().unwrap().record::<()>(&Err(AdapterError::Network("reset".to_string())));
+        clock.advance(Duration::from_secs(10));
+
+        let probe = breaker.acquire().unwrap();
+        assert!(matches!(breaker.acquire(), Err(AdapterError::CircuitOpen { .. })));
+        probe.record(&Ok(()));
+        drop(probe);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        breaker.acquire().unwrap();
+    }
+
+    #[test]
+    fn late_results_do_not_settle_the_probe() {
+        let clock = ManualClock::new();
+        let settings = BreakerSettings { failure_threshold: 1, cool_down: Duration::from_secs(10) };
+        let breaker = CircuitBreaker::with_clock(settings, Arc::new(clock.clone()));
+
+        let slow = breaker.acquire().unwrap();
+        breaker.acquire().unwrap().record::<()>(&Err(AdapterError::Network("reset".to_string())));
+        clock.advance(Duration::from_secs(10));
+        let probe = breaker.acquire().unwrap();
+
+        // Admitted before the circuit opened, so neither outcome speaks for the probe
+        assert!(!slow.record(&Ok(())));
+        assert!(!slow.record::<()>(&Err(AdapterError::Network("reset".to_string()))));
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert!(matches!(breaker.acquire(), Err(AdapterError::CircuitOpen { .. })));
+
+        assert!(probe.record::<()>(&Err(AdapterError::Network("reset".to_string()))));
+        assert_eq!(breaker.state(), CircuitState::Open { retry_at: clock.now() + Duration::from_secs(10) });
+    }
+
+    #[tokio::test]
+    async fn a_cancelled_probe_lets_the_next_request_probe() {
+        let mock = Arc::new(MockAdapter::new()
+            .with_quote("ethereum", "polygon", edge("ethereum", "polygon"))
+            .with_failure("base", "polygon", AdapterError::upstream(503, "down"))
+            .with_latency(Duration::from_millis(200)));
+        let clock = ManualClock::new();
+        let adapter = guarded(&mock, &clock);
+        for _ in 0..3 {
+            adapter.fetch_metrics(&request("base", "polygon")).await.unwrap_err();
+        }
+        clock.advance(Duration::from_secs(60));
+
+        let probe = tokio::time::timeout(Duration::from_millis(10), adapter.fetch_metrics(&request("ethereum", "polygon"))).await;
+        assert!(probe.is_err());
+        assert_eq!(adapter.circuit_state(), Some(CircuitState::HalfOpen));
+        adapter.fetch_metrics(&request("ethereum", "polygon")).await.unwrap();
+        assert_eq!(adapter.circuit_state(), Some(CircuitState::Closed));
+    }
+
+    #[test]
+    fn settings_come_from_extra() {
+        let config: BridgeConfig = toml::from_str(r#"
+            base_url = "https://bridge.test"
+            chains = ["ethereum"]
+
+            [extra]
+            breaker_failure_threshold = 10
+            breaker_cool_down_ms = 1500
+        "#).unwrap();
+        let settings = BreakerSettings::from_config("test", &config).unwrap();
+        assert_eq!(settings, BreakerSettings { failure_threshold: 10, cool_down: Duration::from_millis(1500) });
+
+        let config: BridgeConfig = toml::from_str(r#"
+            base_url = "https://bridge.test"
+            chains = ["ethereum"]
+
+            [extra]
+            breaker_failure_threshold = 0
+        "#).unwrap();
+        assert!(BreakerSettings::from_config("test", &config).is_err());
+    }
+}
@@ -0,0 +1,395 @@
+use super::{
+    AdapterContext,
+    fixture_request,
+    FixtureQuote,
+    AdapterError,
+    AdapterHealth,
+    health::probe,
+    BridgeAdapter,
+    BridgeEdge,
+    QuoteRequest,
+    SupportedPair,
+    RiskModel,
+    unix_now,
+    FeeComponent,
+    chains::evm_chain_id,
+    settings::AdapterSettings,
+    MetricsRecorder,
+    RateLimiter,
+    RetryPolicy,
+    TokenDecimals
+};
+use crate::fx::{CurrencyId, Money};
+
+use std::collections::HashMap;
+use std::time::Duration;
+use std::sync::Arc;
+use async_trait::async_trait;
+use polypathroute_core::BridgeConfig;
+use reqwest::Client;
+use serde_json::Value;
+use anyhow::{Result, anyhow};
+
+// cBridge doesn't return a latency estimate. These are the documented typical
+// transfer times by source chain, in seconds; `extra.latency_secs."src:dst"` overrides them.
+const DEFAULT_LATENCY_SECS: &[(&str, f64)] = &[
+    ("ethereum", 1200.0),
+    ("polygon", 900.0),
+    ("arbitrum", 600.0),
+    ("optimism", 600.0),
+    ("base", 600.0),
+    ("bsc", 300.0),
+    ("avalanche", 300.0),
+];
+const FALLBACK_LATENCY_SECS: f64 = 1200.0;
+
+// slippage_tolerance is in millionths, 3000 = 0.3%
+const DEFAULT_SLIPPAGE_TOLERANCE: i64 = 3000;
+
+pub struct CelerAdapter {
+    pub name: String,
+    pub base_url: String,
+    retry: RetryPolicy,
+    rate_limiter: Option<RateLimiter>,
+    client: Client,
+    slippage_tolerance: i64,
+    latency_overrides: HashMap<String, f64>,
+    pairs: Vec<SupportedPair>,
+    decimals: TokenDecimals,
+    risk_model: Arc<dyn RiskModel>,
+    telemetry: Arc<MetricsRecorder>,
+    // How long quotes stay usable when the API reports no expiry
+    quote_validity: Duration
+}
+
+impl CelerAdapter {
+    pub fn from_config(config: &BridgeConfig) -> Result<Self> {
+        Self::new(AdapterContext::from_config("celer", config)?)
+    }
+
+    // Reads optional `slippage_tolerance`, `[extra.latency_secs]` ("src:dst" = seconds) and
+    // `[extra.send_limits.<SYMBOL>]` (min / max, in token units) from the bridge's extra table.
+    pub fn new(context: AdapterContext) -> Result<Self> {
+        let settings = AdapterSettings::from_config("celer", &context.config)?;
+        let client = context.client;
+        let extra = context.config.extra.clone().unwrap_or_default();
+
+        let slippage_tolerance = match extra.get("slippage_tolerance") {
+            Some(value) => value
+                .as_integer()
+                .filter(|v| *v > 0)
+                .ok_or_else(|| anyhow!("bridges.celer.extra.slippage_tolerance must be a positive integer"))?,
+            None => DEFAULT_SLIPPAGE_TOLERANCE,
+        };
+
+        let latency_overrides = match extra.get("latency_secs").and_then(|v| v.as_table()) {
+            Some(table) => table
+                .iter()
+                .map(|(route, secs)| {
+                    secs.as_float()
+                        .or_else(|| secs.as_integer().map(|v| v as f64))
+                        .map(|secs| (route.to_lowercase(), secs))
+                        .ok_or_else(|| anyhow!("bridges.celer.extra.latency_secs.{} must be a number", route))
+                })
+                .collect::<Result<_>>()?,
+            None => HashMap::new(),
+        };
+
+        let send_limits = extra.get("send_limits").and_then(|v| v.as_table());
+        let limit = |symbol: &str, key: &str| -> Option<f64> {
+            let value = send_limits?.get(symbol)?.get(key)?;
+            value.as_float().or_else(|| value.as_integer().map(|v| v as f64))
+        };
+
+        let pairs = settings.pairs
+            .into_iter()
+            .filter(|pair| evm_chain_id(&pair.src_chain).is_some() && evm_chain_id(&pair.dst_chain).is_some())
+            .map(|mut pair| {
+                if let Some(symbol) = pair.token_symbol.clone() {
+                    pair.min_amount = limit(&symbol, "min");
+                    pair.max_amount = limit(&symbol, "max");
+                }
+                pair
+            })
+            .collect();
+
+        Ok(Self {
+            name: "celer".to_string(),
+            base_url: settings.base_url,
+            retry: settings.retry,
+            rate_limiter: settings.rate_limiter,
+            client,
+            slippage_tolerance,
+            latency_overrides,
+            pairs,
+            decimals: settings.decimals,
+            risk_model: settings.risk_model,
+            telemetry: settings.telemetry,
+            quote_validity: settings.quote_validity
+        })
+    }
+
+    // Replaces the risk model built from config
+    pub fn with_risk_model(mut self, risk_model: Arc<dyn RiskModel>) -> Self {
+        self.risk_model = risk_model;
+        self
+    }
+
+    pub fn estimate_url(&self) -> String {
+        format!("{}/v2/estimateAmt", self.base_url)
+    }
+
+    pub fn transfer_configs_url(&self) -> String {
+        format!("{}/v2/getTransferConfigsForAll", self.base_url)
+    }
+
+    pub fn latency(&self, src_chain: &str, dst_chain: &str) -> f64 {
+        let route = format!("{}:{}", src_chain.to_lowercase(), dst_chain.to_lowercase());
+        if let Some(secs) = self.latency_overrides.get(&route) {
+            return *secs;
+        }
+        DEFAULT_LATENCY_SECS
+            .iter()
+            .find(|(chain, _)| chain.eq_ignore_ascii_case(src_chain))
+            .map(|(_, secs)| *secs)
+            .unwrap_or(FALLBACK_LATENCY_SECS)
+    }
+
+    // cBridge quotes by symbol, which only configured pairs carry
+    fn configured_pair(&self, request: &QuoteRequest) -> Option<SupportedPair> {
+        self.pairs
+            .iter()
+            .find(|pair| pair.matches(&request.src_chain, &request.dst_chain, &request.src_token, &request.dst_token))
+            .cloned()
+    }
+
+    // cost = base_fee + perc_fee, liquidity = estimated_receive_amt, both in destination token
+    // units, speed from the latency table.
+    // A zero receive amount means the pool can't fill the transfer and is reported as NoLiquidity.
+    // Errors come back in a 200 body; "bad amount" ones carry the configured send limits.
+    fn parse_estimate(&self, request: &QuoteRequest, response: &Value) -> Result<BridgeEdge, AdapterError> {
+        if let Some(err) = response.get("err").filter(|err| !err.is_null()) {
+            let msg = err.get("msg").and_then(|v| v.as_str()).unwrap_or("unknown error");
+            if msg.starts_with("bad amount") {
+                let pair = self.configured_pair(request);
+                return Err(AdapterError::AmountOutOfRange {
+                    min: pair.as_ref().and_then(|pair| pair.min_amount),
+                    max: pair.as_ref().and_then(|pair| pair.max_amount),
+                });
+            }
+            return Err(AdapterError::upstream(200, msg));
+        }
+
+        let amount = |field: &str| -> Option<f64> {
+            response.get(field)
+                .and_then(|v| v.as_str())
+                .and_then(|s| s.parse::<f64>().ok())
+        };
+
+        let base_fee = amount("base_fee").ok_or_else(|| AdapterError::missing("base_fee"))?;
+        let perc_fee = amount("perc_fee").ok_or_else(|| AdapterError::missing("perc_fee"))?;
+        let received = amount("estimated_receive_amt")
+                        .ok_or_else(|| AdapterError::missing("estimated_receive_amt"))?;
+
+        if received <= 0.0 {
+            return Err(AdapterError::NoLiquidity {
+                bridge: self.name.clone(),
+                src_chain: request.src_chain.clone(),
+                dst_chain: request.dst_chain.clone(),
+            });
+        }
+
+        let human = |raw: f64| self.decimals.to_human(&request.dst_chain, &request.dst_token, raw);
+        let destination = CurrencyId::token(&request.dst_chain, &request.dst_token);
+        let fee_components = vec![
+            FeeComponent { name: "base".to_string(), amount: Money::new(human(base_fee)?, destination.clone()) },
+            FeeComponent { name: "percentage".to_string(), amount: Money::new(human(perc_fee)?, destination) },
+        ];
+        let received = human(received)?;
+        let pair = self.configured_pair(request);
+
+        let speed = self.latency(&request.src_chain, &request.dst_chain);
+        Ok(self.risk_model.score(&self.name, request, BridgeEdge {
+            from: request.src_chain.clone(),
+            to: request.dst_chain.clone(),
+            cost: human(base_fee + perc_fee)?,
+            speed,
+            // estimateAmt has no pool depth
+            liquidity: received,
+            liquidity_unknown: true,
+            via: None,
+            bridge: self.name.clone(),
+            estimated_output: received,
+            fee_components,
+            quoted_at: unix_now(),
+            min_amount: pair.as_ref().and_then(|pair| pair.min_amount),
+            max_amount: pair.as_ref().and_then(|pair| pair.max_amount),
+            ..BridgeEdge::default()
+        }).with_default_validity(self.quote_validity))
+    }
+}
+
+#[async_trait]
+impl BridgeAdapter for CelerAdapter {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn supported_pairs(&self) -> Vec<SupportedPair> {
+        self.pairs.clone()
+    }
+
+    fn rate_limiter(&self) -> Option<&RateLimiter> {
+        self.rate_limiter.as_ref()
+    }
+
+    fn telemetry(&self) -> Option<&MetricsRecorder> {
+        Some(&self.telemetry)
+    }
+
+    async fn health_check(&self) -> Result<AdapterHealth, AdapterError> {
+        probe(self.rate_limiter.as_ref(), self.client.get(self.transfer_configs_url())).await
+    }
+
+    fn replay_fixture(&self) -> Option<FixtureQuote> {
+        const ESTIMATE: &str = include_str!("../../fixtures/celer/estimate_amt.json");
+        let request = fixture_request("ethereum", "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", "bsc", "0x8ac76a51cc950d9822d68b83fe1ad97b32cd580d", "1000");
+        let result = serde_json::from_str(ESTIMATE).map_err(AdapterError::from).and_then(|response| self.parse_estimate(&request, &response));
+        Some(FixtureQuote { request, result })
+    }
+
+    async fn fetch_metrics(&self, request: &QuoteRequest) -> Result<BridgeEdge, AdapterError> {
+        let pair = self.configured_pair(request)
+            .ok_or_else(|| AdapterError::unsupported_pair(&self.name, request))?;
+        let symbol = pair.token_symbol
+            .ok_or_else(|| AdapterError::unsupported_pair(&self.name, request))?;
+        let src_chain_id = evm_chain_id(&request.src_chain)
+            .ok_or_else(|| AdapterError::unsupported_pair(&self.name, request))?
+            .to_string();
+        let dst_chain_id = evm_chain_id(&request.dst_chain)
+            .ok_or_else(|| AdapterError::unsupported_pair(&self.name, request))?
+            .to_string();
+        let slippage_tolerance = self.slippage_tolerance.to_string();
+        let amount = request.src_amount_in(&self.decimals)?.to_raw_string();
+
+        let params = [
+            ("src_chain_id", src_chain_id.as_str()),
+            ("dst_chain_id", dst_chain_id.as_str()),
+            ("token_symbol", symbol.as_str()),
+            ("amt", amount.as_str()),
+            ("usr_addr", request.src_address.as_str()),
+            ("slippage_tolerance", slippage_tolerance.as_str()),
+        ];
+
+        let response: Value = self.retry
+            .send(self.rate_limiter.as_ref(), || self.client.get(self.estimate_url()).query(&params))
+            .await?
+            .json()
+            .await?;
+
+        self.parse_estimate(request, &response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{Mock, MockServer, ResponseTemplate, matchers::{method, path, query_param}};
+
+    const ESTIMATE: &str = include_str!("../../fixtures/celer/estimate_amt.json");
+    const NO_LIQUIDITY: &str = include_str!("../../fixtures/celer/estimate_amt_no_liquidity.json");
+    const REJECTED: &str = include_str!("../../fixtures/celer/estimate_amt_error.json");
+
+    fn adapter(base_url: &str) -> CelerAdapter {
+        let config = format!(r#"
+            base_url = "{}"
+            chains = ["ethereum", "bsc"]
+
+            [[pairs]]
+            source_chain = "ethereum"
+            source_token_name = "USDC"
+            destination_chain = "bsc"
+            destination_token_name = "USDC"
+            source_address = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"
+            destination_address = "0x8ac76a51cc950d9822d68b83fe1ad97b32cd580d"
+
+            [extra.latency_secs]
+            "bsc:ethereum" = 420
+
+            [extra.send_limits.USDC]
+            min = 20
+            max = 500000
+        "#, base_url);
+        CelerAdapter::from_config(&toml::from_str(&config).unwrap()).unwrap()
+    }
+
+    fn request(dst_token: &str) -> QuoteRequest {
+        QuoteRequest::builder()
+            .src_chain("ethereum")
+            .dst_chain("bsc")
+            .src_token("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48")
+            .dst_token(dst_token)
+            .src_amount("1000")
+            .wallet("0xca699201b15ccef3b8c4012e28570cc5500d9f9a")
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn parses_estimate_and_limits() {
+        let adapter = adapter("https://celer.test");
+        let edge = adapter
+            .parse_estimate(&request("0x8ac76a51cc950d9822d68b83fe1ad97b32cd580d"), &serde_json::from_str(ESTIMATE).unwrap())
+            .unwrap();
+
+        assert_eq!(edge.cost, 1.699856);
+        assert_eq!(edge.liquidity, 998.012144);
+        assert!(edge.liquidity_unknown);
+        assert_eq!(edge.speed, 1200.0);
+        assert_eq!(adapter.latency("bsc", "ethereum"), 420.0);
+        assert_eq!(adapter.latency("fantom", "ethereum"), FALLBACK_LATENCY_SECS);
+
+        let pair = &adapter.supported_pairs()[0];
+        assert_eq!(pair.min_amount, Some(20.0));
+        assert_eq!(pair.max_amount, Some(500000.0));
+    }
+
+    #[test]
+    fn zero_receive_amount_is_no_liquidity() {
+        let err = adapter("https://celer.test")
+            .parse_estimate(&request("0x8ac76a51cc950d9822d68b83fe1ad97b32cd580d"), &serde_json::from_str(NO_LIQUIDITY).unwrap())
+            .unwrap_err();
+
+        assert!(matches!(err, AdapterError::NoLiquidity { .. }));
+    }
+
+    #[test]
+    fn amount_rejection_carries_send_limits() {
+        let err = adapter("https://celer.test")
+            .parse_estimate(&request("0x8ac76a51cc950d9822d68b83fe1ad97b32cd580d"), &serde_json::from_str(REJECTED).unwrap())
+            .unwrap_err();
+
+        assert_eq!(err, AdapterError::AmountOutOfRange { min: Some(20.0), max: Some(500000.0) });
+    }
+
+    #[tokio::test]
+    async fn quotes_configured_pairs_by_symbol() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v2/estimateAmt"))
+            .and(query_param("src_chain_id", "1"))
+            .and(query_param("dst_chain_id", "56"))
+            .and(query_param("token_symbol", "USDC"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(ESTIMATE))
+            .mount(&server)
+            .await;
+
+        let adapter = adapter(&server.uri());
+        let edge = adapter.fetch_metrics(&request("0x8ac76a51cc950d9822d68b83fe1ad97b32cd580d")).await.unwrap();
+        assert_eq!(edge.liquidity, 998.012144);
+
+        let err = adapter.fetch_metrics(&request("0xdead")).await.unwrap_err();
+        assert!(matches!(err, AdapterError::UnsupportedPair { .. }));
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,34 @@
+use polypathroute_core::Registry;
+use serde::Serialize;
+use std::sync::LazyLock;
+
+// Chain ids for chain keys, from the built-in registry so aliases ("arb", "matic") work too.
+// Used by adapters whose APIs take numeric chain ids (Across, Hop, Synapse, ...).
+static BUILTIN: LazyLock<Registry> = LazyLock::new(Registry::builtin);
+
+// The registry's key for a chain key or alias; unknown chains are just lowercased
+pub fn canonical_chain_key(chain: &str) -> String {
+    match BUILTIN.chains.resolve(chain) {
+        Ok(found) => found.key.clone(),
+        Err(_) => chain.trim().to_lowercase(),
+    }
+}
+
+pub fn evm_chain_id(chain_key: &str) -> Option<u64> {
+    BUILTIN.chains.resolve(chain_key).ok()?.chain_id
+}
+
+pub fn evm_chain_key(chain_id: u64) -> Option<&'static str> {
+    BUILTIN.chains.by_chain_id(chain_id).map(|chain| chain.key.as_str())
+}
+
+// A chain as a bridge reports it. `key` is the chain key used in config.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChainInfo {
+    pub key: String,
+    // The bridge's own id for the chain: the EVM chain id for most, Wormhole's chain id for Wormhole
+    pub chain_id: Option<u64>,
+    pub name: String,
+    // Symbol of the gas token
+    pub native_token: Option<String>,
+}
@@ -0,0 +1,216 @@
+use super::AdapterError;
+use std::collections::HashMap;
+use polypathroute_core::{Amount, BridgeConfig};
+use anyhow::{Result, anyhow};
+
+// Decimals for common tokens by symbol, used to seed configured pairs
+const SYMBOL_DECIMALS: &[(&str, u8)] = &[
+    ("USDC", 6),
+    ("USDC.E", 6),
+    ("USDT", 6),
+    ("DAI", 18),
+    ("FRAX", 18),
+    ("ETH", 18),
+    ("WETH", 18),
+    ("WBTC", 8),
+];
+
+// Majors by chain and address. These win over symbol seeding, e.g. USDC on bsc has 18 decimals.
+const TOKEN_DECIMALS: &[(&str, &str, u8)] = &[
+    ("ethereum", "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", 6),
+    ("ethereum", "0xdac17f958d2ee523a2206206994597c13d831ec7", 6),
+    ("ethereum", "0x6b175474e89094c44da98b954eedeac495271d0f", 18),
+    ("ethereum", "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2", 18),
+    ("ethereum", "0x2260fac5e5542a773aa44fbcfedf7c193bc2c599", 8),
+    ("polygon", "0x3c499c542cef5e3811e1192ce70d8cc03d5c3359", 6),
+    ("polygon", "0x2791bca1f2de4661ed88a30c99a7a9449aa84174", 6),
+    ("polygon", "0xc2132d05d31c914a87c6611c10748aeb04b58e8f", 6),
+    ("polygon", "0x8f3cf7ad23cd3cadbd9735aff958023239c6a063", 18),
+    ("polygon", "0x7ceb23fd6bc0add59e62ac25578270cff1b9f619", 18),
+    ("arbitrum", "0xaf88d065e77c8cc2239327c5edb3a432268e5831", 6),
+    ("arbitrum", "0xff970a61a04b1ca14834a43f5de4533ebddb5cc8", 6),
+    ("arbitrum", "0xfd086bc7cd5c481dcc9c85ebe478a1c0b69fcbb9", 6),
+    ("arbitrum", "0xda10009cbd5d07dd0cecc66161fc93d7c9000da1", 18),
+    ("arbitrum", "0x82af49447d8a07e3bd95bd0d56f35241523fbab1", 18),
+    ("optimism", "0x0b2c639c533813f4aa9d7837caf62653d097ff85", 6),
+    ("optimism", "0x94b008aa00579c1307b0ef2c499ad98a8ce58e58", 6),
+    ("optimism", "0xda10009cbd5d07dd0cecc66161fc93d7c9000da1", 18),
+    ("optimism", "0x4200000000000000000000000000000000000006", 18),
+    ("base", "0x833589fcd6edb6e08f4c7c32d4f71b54bda02913", 6),
+    ("base", "0x50c5725949a6f0c72e6c4a641f24049a917db0cb", 18),
+    ("base", "0x4200000000000000000000000000000000000006", 18),
+    ("bsc", "0x8ac76a51cc950d9822d68b83fe1ad97b32cd580d", 18),
+    ("bsc", "0x55d398326f99059ff775485246999027b3197955", 18),
+    ("avalanche", "0xb97ef9ef8734c71904d8002f8b6bc66dd9c48a6e", 6),
+    ("avalanche", "0x9702230a8ea53601f5cd2dc00fdbc13d4df4a8c7", 6),
+];
+
+// Placeholder addresses APIs use for the native gas token, 18 decimals on every EVM chain
+pub(crate) const NATIVE_ADDRESSES: &[&str] = &[
+    "0x0000000000000000000000000000000000000000",
+    "0xeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeee",
+];
+
+// Token decimals by (chain, address). Quote requests carry human amounts ("1.5" USDC) and
+// APIs work in raw integer units, adapters convert both ways through this registry.
+#[derive(Debug, Clone, Default)]
+pub struct TokenDecimals {
+    tokens: HashMap<(String, String), u8>,
+}
+
+impl TokenDecimals {
+    // Configured pairs seeded by symbol, then the built-in table, then
+    // `extra.decimals."<chain>:<address>"` overrides.
+    pub fn from_config(bridge: &str, config: &BridgeConfig) -> Result<Self> {
+        let mut decimals = Self::default();
+        for pair in config.pairs.iter().flatten() {
+            let sides = [
+                (&pair.source_chain, &pair.source_address, &pair.source_token_name),
+                (&pair.destination_chain, &pair.destination_address, &pair.destination_token_name),
+            ];
+            for (chain, token, symbol) in sides {
+                if let Some(value) = symbol_decimals(symbol) {
+                    decimals.insert(chain, token, value);
+                }
+            }
+        }
+        for (chain, token, value) in TOKEN_DECIMALS {
+            decimals.insert(chain, token, *value);
+        }
+
+        let overrides = config.extra.as_ref().and_then(|extra| extra.get("decimals"));
+        if let Some(overrides) = overrides {
+            let table = overrides
+                .as_table()
+                .ok_or_else(|| anyhow!("bridges.{}.extra.decimals must be a table", bridge))?;
+            for (key, value) in table {
+                let (chain, token) = key
+                    .split_once(':')
+                    .ok_or_else(|| anyhow!("bridges.{}.extra.decimals keys must look like `chain:address`, got `{}`", bridge, key))?;
+                let value = value
+                    .as_integer()
+                    .filter(|v| (0..=36).contains(v))
+                    .ok_or_else(|| anyhow!("bridges.{}.extra.decimals.\"{}\" must be an integer between 0 and 36", bridge, key))?;
+                decimals.insert(chain, token, value as u8);
+            }
+        }
+        Ok(decimals)
+    }
+
+    // Decimals of a well-known symbol, for adapters that list tokens by symbol
+    pub fn for_symbol(symbol: &str) -> Option<u8> {
+        symbol_decimals(symbol)
+    }
+
+    // Whether `token` is a placeholder for the chain's gas token
+    pub fn is_native(token: &str) -> bool {
+        NATIVE_ADDRESSES.iter().any(|native| native.eq_ignore_ascii_case(token))
+    }
+
+    pub fn insert(&mut self, chain: &str, token: &str, decimals: u8) {
+        self.tokens.insert((chain.to_lowercase(), token.to_lowercase()), decimals);
+    }
+
+    pub fn get(&self, chain: &str, token: &str) -> Option<u8> {
+        if Self::is_native(token) {
+            return Some(18);
+        }
+        self.tokens.get(&(chain.to_lowercase(), token.to_lowercase())).copied()
+    }
+
+    fn require(&self, chain: &str, token: &str) -> Result<u8, AdapterError> {
+        self.get(chain, token).ok_or_else(|| AdapterError::UnknownToken {
+            chain: chain.to_string(),
+            token: token.to_string(),
+        })
+    }
+
+    // Human amount ("1.5") of `token` as an exact Amount. Digits past its decimals are dropped.
+    pub fn amount(&self, chain: &str, token: &str, human: &str) -> Result<Amount, AdapterError> {
+        let decimals = self.require(chain, token)?;
+        Amount::from_human(human, decimals).map_err(|err| AdapterError::Config(err.to_string()))
+    }
+
+    // Raw integer amount as an API response gives it
+    pub fn amount_from_raw(&self, chain: &str, token: &str, raw: &str) -> Result<Amount, AdapterError> {
+        let decimals = self.require(chain, token)?;
+        Amount::from_raw(raw, decimals).map_err(|err| AdapterError::Config(err.to_string()))
+    }
+
+    // Human amount -> raw integer string for an API call, through Amount so no digit is lost
+    pub fn to_raw(&self, chain: &str, token: &str, amount: &str) -> Result<String, AdapterError> {
+        Ok(self.amount(chain, token, amount)?.to_raw_string())
+    }
+
+    // Raw integer amount from an API response -> human units
+    pub fn to_human(&self, chain: &str, token: &str, raw: f64) -> Result<f64, AdapterError> {
+        Ok(raw / 10f64.powi(self.require(chain, token)? as i32))
+    }
+}
+
+fn symbol_decimals(symbol: &str) -> Option<u8> {
+    let symbol = symbol.to_uppercase();
+    SYMBOL_DECIMALS.iter()
+        .find(|(name, _)| *name == symbol)
+        .map(|(_, value)| *value)
+}
+
+pub(crate) fn is_decimal(amount: &str) -> bool {
+    let (int, frac) = amount.split_once('.').unwrap_or((amount, ""));
+    !(int.is_empty() && frac.is_empty())
+        && int.chars().all(|c| c.is_ascii_digit())
+        && frac.chars().all(|c| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scales_human_amounts_to_raw_units() {
+        let mut decimals = TokenDecimals::default();
+        decimals.insert("ethereum", "0xusdc", 6);
+        decimals.insert("ethereum", "0xdai", 18);
+        let raw = |token: &str, amount: &str| decimals.to_raw("ethereum", token, amount);
+        assert_eq!(raw("0xusdc", "1.5").unwrap(), "1500000");
+        assert_eq!(raw("0xdai", "1000").unwrap(), "1000000000000000000000");
+        assert_eq!(raw("0xusdc", "0.0000001").unwrap(), "0");
+        assert_eq!(raw("0xusdc", ".25").unwrap(), "250000");
+        assert!(raw("0xusdc", "1e6").is_err());
+        assert!(raw("0xusdc", "-1").is_err());
+        // Every digit of an 18-decimal amount past what f64 holds
+        assert_eq!(raw("0xdai", "123456789.123456789123456789").unwrap(), "123456789123456789123456789");
+        let amount = decimals.amount_from_raw("ethereum", "0xdai", "123456789123456789123456789").unwrap();
+        assert_eq!(amount.to_string(), "123456789.123456789123456789");
+    }
+
+    #[test]
+    fn config_pairs_builtins_and_overrides() {
+        let config: BridgeConfig = toml::from_str(r#"
+            base_url = "https://bridge.test"
+            chains = ["bsc", "polygon", "linea"]
+
+            [[pairs]]
+            source_chain = "bsc"
+            source_token_name = "USDC"
+            destination_chain = "linea"
+            destination_token_name = "USDC"
+            source_address = "0x8AC76a51cc950d9822D68b83fE1Ad97B32Cd580d"
+            destination_address = "0x176211869ca2b568f2a7d4ee941e073a821ee1ff"
+
+            [extra.decimals]
+            "polygon:0xabc" = 9
+        "#).unwrap();
+
+        let decimals = TokenDecimals::from_config("test", &config).unwrap();
+        // the symbol says 6, the built-in table knows bsc USDC has 18
+        assert_eq!(decimals.get("bsc", "0x8ac76a51cc950d9822d68b83fe1ad97b32cd580d"), Some(18));
+        assert_eq!(decimals.get("linea", "0x176211869ca2b568f2a7d4ee941e073a821ee1ff"), Some(6));
+        assert_eq!(decimals.get("Polygon", "0xABC"), Some(9));
+        assert_eq!(decimals.get("ethereum", "0x0000000000000000000000000000000000000000"), Some(18));
+        assert_eq!(
+            decimals.to_human("polygon", "0xdead", 1.0),
+            Err(AdapterError::UnknownToken { chain: "polygon".to_string(), token: "0xdead".to_string() })
+        );
+    }
+}
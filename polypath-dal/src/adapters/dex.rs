@@ -0,0 +1,53 @@
+// Same-chain swaps quoted by a DEX or DEX aggregator, which GraphUpdater::with_dex turns into
+// swap edges next to the bridges' edges
+
+use std::fmt;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::AdapterError;
+
+// A swap a venue offers, from one token to another on the same chain
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SwapPair {
+    pub chain: String,
+    pub token_in: String,
+    pub token_out: String,
+    // Used for the tokens' nodes when the registry doesn't know them
+    pub symbol_in: String,
+    pub symbol_out: String,
+}
+
+impl SwapPair {
+    pub fn new(chain: &str, (token_in, symbol_in): (&str, &str), (token_out, symbol_out): (&str, &str)) -> Self {
+        Self {
+            chain: chain.to_string(),
+            token_in: token_in.to_string(),
+            token_out: token_out.to_string(),
+            symbol_in: symbol_in.to_string(),
+            symbol_out: symbol_out.to_string(),
+        }
+    }
+}
+
+// What swapping an amount in would give, `fee` being out of `amount_out` already. `fee` and
+// `gas` are in the units of BridgeEdge::cost; `liquidity` is what the venue can take before its
+// price moves much.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SwapQuote {
+    pub amount_out: f64,
+    pub fee: f64,
+    pub gas: f64,
+    pub liquidity: f64,
+}
+
+#[async_trait]
+pub trait DexAdapter: Send + Sync + fmt::Debug {
+    // Swap edges are labelled with it
+    fn name(&self) -> String;
+
+    fn swap_pairs(&self) -> Vec<SwapPair>;
+
+    // Quotes swapping `amount_in` human units of the pair's token_in
+    async fn quote_in(&self, pair: &SwapPair, amount_in: f64) -> Result<SwapQuote, AdapterError>;
+}
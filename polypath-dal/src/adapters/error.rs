@@ -0,0 +1,190 @@
+use std::{path::PathBuf, time::{Duration, Instant}};
+use thiserror::Error;
+
+use super::QuoteRequest;
+
+// How much of an upstream error body is kept for diagnostics
+const BODY_SNIPPET_LEN: usize = 200;
+
+// Everything an adapter can fail with. Callers decide what to do from the variant,
+// see `disposition`.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum AdapterError {
+    #[error("{bridge} does not serve {src_chain}:{src_token} -> {dst_chain}:{dst_token}")]
+    UnsupportedPair {
+        bridge: String,
+        src_chain: String,
+        dst_chain: String,
+        src_token: String,
+        dst_token: String,
+    },
+
+    #[error("rate limited by upstream{}", retry_after.map(|d| format!(", retry after {:?}", d)).unwrap_or_default())]
+    RateLimited { retry_after: Option<Duration> },
+
+    #[error("request timed out after {attempts} attempt(s)")]
+    Timeout { attempts: u32 },
+
+    #[error("upstream returned {status}: {body_snippet}")]
+    Upstream { status: u16, body_snippet: String },
+
+    #[error("network error: {0}")]
+    Network(String),
+
+    #[error("malformed response: missing {missing}")]
+    MalformedResponse { missing: String },
+
+    #[error("amount outside the accepted range (min {min:?}, max {max:?})")]
+    AmountOutOfRange { min: Option<f64>, max: Option<f64> },
+
+    #[error("{bridge} reports no liquidity for {src_chain} -> {dst_chain}")]
+    NoLiquidity {
+        bridge: String,
+        src_chain: String,
+        dst_chain: String,
+    },
+
+    #[error("no decimals known for token {token} on {chain}")]
+    UnknownToken { chain: String, token: String },
+
+    #[error("adapter configuration error: {0}")]
+    Config(String),
+
+    // Replaying fixtures and none was recorded for the request, see FixtureStore
+    #[error("no fixture for request {request} ({adapter}, expected at {})", path.display())]
+    MissingFixture { adapter: String, request: String, path: PathBuf },
+
+    // An operation the adapter doesn't offer, like tracking transfers over a bridge whose API
+    // can't tell where they are
+    #[error("{bridge} does not support {operation}")]
+    Unsupported { bridge: String, operation: &'static str },
+
+    // The bridge has no transfer by that reference, or hasn't seen it yet
+    #[error("{bridge} knows no transfer with {reference}")]
+    UnknownTransfer { bridge: String, reference: String },
+
+    // The adapter's circuit breaker is open; no request was sent
+    #[error("circuit breaker open, retry in {:?}", retry_at.saturating_duration_since(Instant::now()))]
+    CircuitOpen { retry_at: Instant },
+}
+
+// What a scheduler should do with a pair whose quote failed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Disposition {
+    // Try again on the next cycle
+    Retry,
+    // Skip the pair for a while
+    Defer(Option<Duration>),
+    // Stop scheduling the pair
+    Drop,
+    // Needs operator attention, retrying won't help
+    Fail,
+}
+
+impl AdapterError {
+    pub fn unsupported_pair(bridge: &str, request: &QuoteRequest) -> Self {
+        AdapterError::UnsupportedPair {
+            bridge: bridge.to_string(),
+            src_chain: request.src_chain.clone(),
+            dst_chain: request.dst_chain.clone(),
+            src_token: request.src_token.clone(),
+            dst_token: request.dst_token.clone(),
+        }
+    }
+
+    pub fn missing(field: impl Into<String>) -> Self {
+        AdapterError::MalformedResponse { missing: field.into() }
+    }
+
+    pub fn upstream(status: u16, body: &str) -> Self {
+        AdapterError::Upstream {
+            status,
+            body_snippet: body.chars().take(BODY_SNIPPET_LEN).collect(),
+        }
+    }
+
+    // Stable snake_case name of the variant, for metrics
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AdapterError::UnsupportedPair { .. } => "unsupported_pair",
+            AdapterError::RateLimited { .. } => "rate_limited",
+            AdapterError::Timeout { .. } => "timeout",
+            AdapterError::Upstream { .. } => "upstream",
+            AdapterError::Network(_) => "network",
+            AdapterError::MalformedResponse { .. } => "malformed_response",
+            AdapterError::AmountOutOfRange { .. } => "amount_out_of_range",
+            AdapterError::NoLiquidity { .. } => "no_liquidity",
+            AdapterError::UnknownToken { .. } => "unknown_token",
+            AdapterError::Config(_) => "config",
+            AdapterError::MissingFixture { .. } => "missing_fixture",
+            AdapterError::Unsupported { .. } => "unsupported",
+            AdapterError::UnknownTransfer { .. } => "unknown_transfer",
+            AdapterError::CircuitOpen { .. } => "circuit_open",
+        }
+    }
+
+    pub fn disposition(&self) -> Disposition {
+        match self {
+            AdapterError::RateLimited { retry_after } => Disposition::Defer(*retry_after),
+            AdapterError::UnsupportedPair { .. } | AdapterError::Unsupported { .. } => Disposition::Drop,
+            AdapterError::Upstream { status, .. } if *status >= 500 => Disposition::Retry,
+            AdapterError::Timeout { .. } | AdapterError::Network(_) | AdapterError::UnknownTransfer { .. } => Disposition::Retry,
+            AdapterError::NoLiquidity { .. } | AdapterError::AmountOutOfRange { .. } => Disposition::Defer(None),
+            AdapterError::CircuitOpen { retry_at } => Disposition::Defer(Some(retry_at.saturating_duration_since(Instant::now()))),
+            AdapterError::Upstream { .. }
+            | AdapterError::MalformedResponse { .. }
+            | AdapterError::UnknownToken { .. }
+            | AdapterError::Config(_)
+            | AdapterError::MissingFixture { .. } => Disposition::Fail,
+        }
+    }
+}
+
+impl From<reqwest::Error> for AdapterError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_decode() {
+            AdapterError::missing(format!("a valid JSON body ({})", err))
+        } else if err.is_timeout() {
+            AdapterError::Timeout { attempts: 1 }
+        } else {
+            AdapterError::Network(err.to_string())
+        }
+    }
+}
+
+// Typed bodies fail with serde's "missing field `x`", keep just the field name
+impl From<serde_json::Error> for AdapterError {
+    fn from(err: serde_json::Error) -> Self {
+        let message = err.to_string();
+        let field = message
+            .strip_prefix("missing field `")
+            .and_then(|rest| rest.split('`').next());
+        match field {
+            Some(field) => AdapterError::missing(field),
+            None => AdapterError::missing(format!("a well-formed body ({})", message)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dispositions_follow_the_variant() {
+        assert_eq!(AdapterError::RateLimited { retry_after: Some(Duration::from_secs(3)) }.disposition(),
+            Disposition::Defer(Some(Duration::from_secs(3))));
+        assert_eq!(AdapterError::upstream(503, "busy").disposition(), Disposition::Retry);
+        assert_eq!(AdapterError::upstream(400, "bad").disposition(), Disposition::Fail);
+        assert_eq!(AdapterError::missing("quotes").disposition(), Disposition::Fail);
+    }
+
+    #[test]
+    fn upstream_bodies_are_truncated() {
+        let body = "x".repeat(1000);
+        match AdapterError::upstream(500, &body) {
+            AdapterError::Upstream { body_snippet, .. } => assert_eq!(body_snippet.len(), BODY_SNIPPET_LEN),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
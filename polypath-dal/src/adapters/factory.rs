@@ -0,0 +1,27 @@
+use super::{AdapterContext, DynBridgeAdapter};
+use std::{collections::HashMap, sync::{Arc, LazyLock, RwLock}};
+use anyhow::Result;
+
+// Builds an adapter from its bridge's context. create_adapter wraps the result in a circuit breaker.
+pub type AdapterFactory = Arc<dyn Fn(AdapterContext) -> Result<DynBridgeAdapter> + Send + Sync>;
+
+// Keyed by lowercased bridge name
+static FACTORIES: LazyLock<RwLock<HashMap<String, AdapterFactory>>> = LazyLock::new(Default::default);
+
+// Makes `name` buildable by create_adapter, for adapters that live outside this crate.
+// Registered factories are tried before the built-in adapters, so they can replace one.
+// Registering a name again replaces the earlier factory.
+pub fn register<F>(name: &str, factory: F)
+where
+    F: Fn(AdapterContext) -> Result<DynBridgeAdapter> + Send + Sync + 'static,
+{
+    FACTORIES.write().unwrap().insert(name.to_lowercase(), Arc::new(factory));
+}
+
+pub(crate) fn registered(name: &str) -> Option<AdapterFactory> {
+    FACTORIES.read().unwrap().get(&name.to_lowercase()).cloned()
+}
+
+pub(crate) fn registered_names() -> Vec<String> {
+    FACTORIES.read().unwrap().keys().cloned().collect()
+}
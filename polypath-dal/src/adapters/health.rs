@@ -0,0 +1,69 @@
+use super::{AdapterError, CircuitState, RateLimiter};
+use std::time::{Duration, Instant};
+use reqwest::{RequestBuilder, Response};
+
+// Headers upstreams use to report how many requests are left in the current window
+const RATE_LIMIT_HEADERS: &[&str] = &["x-ratelimit-remaining", "ratelimit-remaining"];
+
+// Result of probing an adapter's upstream
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdapterHealth {
+    pub reachable: bool,
+    pub latency: Duration,
+    pub rate_limit_remaining: Option<u32>,
+    pub details: String,
+    // Filled in when the adapter sits behind a circuit breaker
+    pub circuit: Option<CircuitState>,
+}
+
+impl AdapterHealth {
+    pub fn healthy(details: impl Into<String>) -> Self {
+        Self {
+            reachable: true,
+            latency: Duration::ZERO,
+            rate_limit_remaining: None,
+            details: details.into(),
+            circuit: None,
+        }
+    }
+}
+
+// Sends one request, without retries, and reports how the upstream answered.
+// Any HTTP answer is a health report; only transport failures are errors.
+pub(crate) async fn probe(limiter: Option<&RateLimiter>, request: RequestBuilder) -> Result<AdapterHealth, AdapterError> {
+    if let Some(limiter) = limiter {
+        limiter.acquire().await;
+    }
+
+    let started = Instant::now();
+    let response = request.send().await?;
+    let latency = started.elapsed();
+
+    let status = response.status();
+    let rate_limit_remaining = rate_limit_remaining(&response);
+    let details = if status.is_success() {
+        status.to_string()
+    } else {
+        let body = response.text().await.unwrap_or_default();
+        match AdapterError::upstream(status.as_u16(), body.trim()) {
+            AdapterError::Upstream { body_snippet, .. } if !body_snippet.is_empty() => format!("{}: {}", status, body_snippet),
+            _ => status.to_string(),
+        }
+    };
+
+    Ok(AdapterHealth {
+        reachable: status.is_success(),
+        latency,
+        rate_limit_remaining,
+        details,
+        circuit: None,
+    })
+}
+
+fn rate_limit_remaining(response: &Response) -> Option<u32> {
+    RATE_LIMIT_HEADERS
+        .iter()
+        .find_map(|name| response.headers().get(*name))
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+}
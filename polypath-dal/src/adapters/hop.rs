@@ -0,0 +1,446 @@
+use super::{
+    AdapterContext,
+    fixture_request,
+    FixtureQuote,
+    AdapterError,
+    AdapterHealth,
+    health::probe,
+    BridgeAdapter,
+    BridgeEdge,
+    QuoteRequest,
+    SupportedPair,
+    RiskModel,
+    unix_now,
+    FeeComponent,
+    pairs::merge_pair,
+    settings::AdapterSettings,
+    MetricsRecorder,
+    RateLimiter,
+    RetryPolicy,
+    TokenDecimals
+};
+use crate::fx::{CurrencyId, Money};
+
+use std::time::Duration;
+use std::sync::Arc;
+use async_trait::async_trait;
+use polypathroute_core::{BridgeConfig, RefreshPriority, Redacted};
+use reqwest::{Client, RequestBuilder};
+use serde_json::Value;
+use anyhow::Result;
+
+const HOP_CHAINS: &[&str] = &["ethereum", "polygon", "arbitrum", "optimism", "base"];
+
+// Canonical token addresses Hop bridges, per chain (from Hop's published addresses).
+// Hop quotes by symbol, so this also translates addresses back to symbols.
+const HOP_TOKENS: &[(&str, &[(&str, &str)])] = &[
+    ("USDC", &[
+        ("ethereum", "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"),
+        ("polygon", "0x2791bca1f2de4661ed88a30c99a7a9449aa84174"),
+        ("arbitrum", "0xff970a61a04b1ca14834a43f5de4533ebddb5cc8"),
+        ("optimism", "0x7f5c764cbc14f9669b88837ca1490cca17c31607"),
+        ("base", "0xd9aaec86b65d86f6a7b5b1b0c42ffa531710b6ca"),
+    ]),
+    ("USDT", &[
+        ("ethereum", "0xdac17f958d2ee523a2206206994597c13d831ec7"),
+        ("polygon", "0xc2132d05d31c914a87c6611c10748aeb04b58e8f"),
+        ("arbitrum", "0xfd086bc7cd5c481dcc9c85ebe478a1c0b69fcbb9"),
+        ("optimism", "0x94b008aa00579c1307b0ef2c499ad98a8ce58e58"),
+    ]),
+    ("DAI", &[
+        ("ethereum", "0x6b175474e89094c44da98b954eedeac495271d0f"),
+        ("polygon", "0x8f3cf7ad23cd3cadbd9735aff958023239c6a063"),
+        ("arbitrum", "0xda10009cbd5d07dd0cecc66161fc93d7c9000da1"),
+        ("optimism", "0xda10009cbd5d07dd0cecc66161fc93d7c9000da1"),
+    ]),
+    ("ETH", &[
+        ("ethereum", "0x0000000000000000000000000000000000000000"),
+        ("polygon", "0x7ceb23fd6bc0add59e62ac25578270cff1b9f619"),
+        ("arbitrum", "0x0000000000000000000000000000000000000000"),
+        ("optimism", "0x0000000000000000000000000000000000000000"),
+        ("base", "0x0000000000000000000000000000000000000000"),
+    ]),
+];
+
+fn hop_symbol(chain: &str, address: &str) -> Option<&'static str> {
+    HOP_TOKENS
+        .iter()
+        .find(|(_, deployments)| {
+            deployments.iter().any(|(c, a)| c.eq_ignore_ascii_case(chain) && a.eq_ignore_ascii_case(address))
+        })
+        .map(|(symbol, _)| *symbol)
+}
+
+// Every route between two Hop chains where the token is deployed on both ends,
+// restricted to `chains` when the config lists any
+fn hop_pairs(chains: &[String]) -> Vec<SupportedPair> {
+    let allowed = |chain: &str| chains.is_empty() || chains.iter().any(|c| c.eq_ignore_ascii_case(chain));
+    let mut pairs = Vec::new();
+
+    for (symbol, deployments) in HOP_TOKENS {
+        for (src_chain, src_token) in deployments.iter() {
+            for (dst_chain, dst_token) in deployments.iter() {
+                if src_chain == dst_chain || !allowed(src_chain) || !allowed(dst_chain) {
+                    continue;
+                }
+                merge_pair(&mut pairs, SupportedPair {
+                    src_chain: src_chain.to_string(),
+                    dst_chain: dst_chain.to_string(),
+                    src_token: src_token.to_string(),
+                    dst_token: dst_token.to_string(),
+                    min_amount: None,
+                    max_amount: None,
+                    token_symbol: Some(symbol.to_string()),
+                    priority: RefreshPriority::default(),
+                    refresh_interval: None,
+                });
+            }
+        }
+    }
+    pairs
+}
+
+pub struct HopAdapter {
+    pub name: String,
+    pub base_url: String,
+    api_key: Option<Redacted<String>>,
+    retry: RetryPolicy,
+    rate_limiter: Option<RateLimiter>,
+    client: Client,
+    pairs: Vec<SupportedPair>,
+    decimals: TokenDecimals,
+    risk_model: Arc<dyn RiskModel>,
+    telemetry: Arc<MetricsRecorder>,
+    // How long quotes stay usable when the API reports no expiry
+    quote_validity: Duration
+}
+
+impl HopAdapter {
+    pub fn from_config(config: &BridgeConfig) -> Result<Self> {
+        Self::new(AdapterContext::from_config("hop", config)?)
+    }
+
+    // Configured pairs are kept only when Hop actually serves them
+    pub fn new(context: AdapterContext) -> Result<Self> {
+        let settings = AdapterSettings::from_config("hop", &context.config)?;
+        let client = context.client;
+
+        let mut pairs = hop_pairs(&context.config.chains);
+        for pair in settings.pairs {
+            if Self::route_symbol(&pair.src_chain, &pair.dst_chain, &pair.src_token, &pair.dst_token).is_some() {
+                merge_pair(&mut pairs, pair);
+            }
+        }
+
+        let mut decimals = settings.decimals;
+        for (symbol, deployments) in HOP_TOKENS {
+            for (chain, token) in deployments.iter() {
+                if let (None, Some(value)) = (decimals.get(chain, token), TokenDecimals::for_symbol(symbol)) {
+                    decimals.insert(chain, token, value);
+                }
+            }
+        }
+
+        Ok(Self {
+            name: "hop".to_string(),
+            base_url: settings.base_url,
+            api_key: settings.api_key,
+            retry: settings.retry,
+            rate_limiter: settings.rate_limiter,
+            client,
+            pairs,
+            decimals,
+            risk_model: settings.risk_model,
+            telemetry: settings.telemetry,
+            quote_validity: settings.quote_validity
+        })
+    }
+
+    // Replaces the risk model built from config
+    pub fn with_risk_model(mut self, risk_model: Arc<dyn RiskModel>) -> Self {
+        self.risk_model = risk_model;
+        self
+    }
+
+    pub fn quote_url(&self) -> String {
+        format!("{}/quote", self.base_url)
+    }
+
+    pub fn available_liquidity_url(&self) -> String {
+        format!("{}/available-liquidity", self.base_url)
+    }
+
+    pub fn available_routes_url(&self) -> String {
+        format!("{}/available-routes", self.base_url)
+    }
+
+    // Symbol Hop would quote the route under, if it serves it
+    fn route_symbol(src_chain: &str, dst_chain: &str, src_token: &str, dst_token: &str) -> Option<&'static str> {
+        let is_hop_chain = |chain: &str| HOP_CHAINS.iter().any(|c| c.eq_ignore_ascii_case(chain));
+        if !is_hop_chain(src_chain) || !is_hop_chain(dst_chain) || src_chain.eq_ignore_ascii_case(dst_chain) {
+            return None;
+        }
+        let symbol = hop_symbol(src_chain, src_token)?;
+        (hop_symbol(dst_chain, dst_token) == Some(symbol)).then_some(symbol)
+    }
+
+    fn get(&self, url: String) -> RequestBuilder {
+        let request = self.client.get(url);
+        match &self.api_key {
+            Some(key) => request.header("x-api-key", key.expose()),
+            None => request
+        }
+    }
+
+    // cost = bonderFee + destinationTxFee, speed = estimatedTime (seconds),
+    // liquidity = bonder's availableLiquidity for the route. All amounts are in the bridged token.
+    // The quote's deadline is when it stops being valid.
+    fn parse_quote(&self, request: &QuoteRequest, quote: &Value, liquidity: &Value) -> Result<BridgeEdge, AdapterError> {
+        let amount = |body: &Value, field: &str| -> Option<f64> {
+            body.get(field)
+                .and_then(|v| v.as_str())
+                .and_then(|s| s.parse::<f64>().ok())
+        };
+
+        let bonder_fee = amount(quote, "bonderFee").ok_or_else(|| AdapterError::missing("bonderFee"))?;
+        let destination_tx_fee = amount(quote, "destinationTxFee").unwrap_or(0.0);
+        let speed = quote.get("estimatedTime").and_then(|v| v.as_f64()).unwrap_or(0.0);
+        let liquidity = amount(liquidity, "availableLiquidity")
+                            .ok_or_else(|| AdapterError::missing("availableLiquidity"))?;
+
+        let src_human = |raw: f64| self.decimals.to_human(&request.src_chain, &request.src_token, raw);
+        let dst_human = |raw: f64| self.decimals.to_human(&request.dst_chain, &request.dst_token, raw);
+        let source = CurrencyId::token(&request.src_chain, &request.src_token);
+        let fee_components = vec![
+            FeeComponent { name: "bonder".to_string(), amount: Money::new(src_human(bonder_fee)?, source.clone()) },
+            FeeComponent { name: "destination_tx".to_string(), amount: Money::new(src_human(destination_tx_fee)?, source) },
+        ];
+
+        Ok(self.risk_model.score(&self.name, request, BridgeEdge {
+            from: request.src_chain.clone(),
+            to: request.dst_chain.clone(),
+            cost: src_human(bonder_fee + destination_tx_fee)?,
+            speed,
+            liquidity: dst_human(liquidity)?,
+            via: None,
+            bridge: self.name.clone(),
+            estimated_output: amount(quote, "estimatedRecieved").map(dst_human).transpose()?.unwrap_or(0.0),
+            fee_components,
+            quoted_at: unix_now(),
+            valid_until: quote.get("deadline").and_then(|v| v.as_u64()),
+            ..BridgeEdge::default()
+        }).with_default_validity(self.quote_validity))
+    }
+}
+
+#[async_trait]
+impl BridgeAdapter for HopAdapter {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn supported_pairs(&self) -> Vec<SupportedPair> {
+        self.pairs.clone()
+    }
+
+    fn rate_limiter(&self) -> Option<&RateLimiter> {
+        self.rate_limiter.as_ref()
+    }
+
+    fn telemetry(&self) -> Option<&MetricsRecorder> {
+        Some(&self.telemetry)
+    }
+
+    async fn health_check(&self) -> Result<AdapterHealth, AdapterError> {
+        probe(self.rate_limiter.as_ref(), self.get(self.available_routes_url())).await
+    }
+
+    fn replay_fixture(&self) -> Option<FixtureQuote> {
+        const QUOTE: &str = include_str!("../../fixtures/hop/quote.json");
+        const LIQUIDITY: &str = include_str!("../../fixtures/hop/available_liquidity.json");
+        let request = fixture_request("polygon", "0x2791bca1f2de4661ed88a30c99a7a9449aa84174", "arbitrum", "0xff970a61a04b1ca14834a43f5de4533ebddb5cc8", "1000");
+        let result = match (serde_json::from_str(QUOTE), serde_json::from_str(LIQUIDITY)) {
+            (Ok(quote), Ok(liquidity)) => self.parse_quote(&request, &quote, &liquidity),
+            (Err(err), _) | (_, Err(err)) => Err(err.into()),
+        };
+        Some(FixtureQuote { request, result })
+    }
+
+    async fn fetch_metrics(&self, request: &QuoteRequest) -> Result<BridgeEdge, AdapterError> {
+        let symbol = Self::route_symbol(&request.src_chain, &request.dst_chain, &request.src_token, &request.dst_token)
+            .ok_or_else(|| AdapterError::unsupported_pair(&self.name, request))?;
+        let src_chain = request.src_chain.to_lowercase();
+        let dst_chain = request.dst_chain.to_lowercase();
+
+        let amount = self.decimals.to_raw(&request.src_chain, &request.src_token, &request.src_amount)?;
+        let quote_params = [
+            ("amount", amount.as_str()),
+            ("token", symbol),
+            ("fromChain", src_chain.as_str()),
+            ("toChain", dst_chain.as_str()),
+            ("slippage", "0.5"),
+        ];
+        let liquidity_params = [
+            ("token", symbol),
+            ("fromChain", src_chain.as_str()),
+            ("toChain", dst_chain.as_str()),
+        ];
+
+        let quote: Value = self.retry
+            .send(self.rate_limiter.as_ref(), || self.get(self.quote_url()).query(&quote_params))
+            .await?
+            .json()
+            .await?;
+        let liquidity: Value = self.retry
+            .send(self.rate_limiter.as_ref(), || self.get(self.available_liquidity_url()).query(&liquidity_params))
+            .await?
+            .json()
+            .await?;
+
+        self.parse_quote(request, &quote, &liquidity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{Mock, MockServer, ResponseTemplate, matchers::{method, path, query_param}};
+
+    const QUOTE: &str = include_str!("../../fixtures/hop/quote.json");
+    const LIQUIDITY: &str = include_str!("../../fixtures/hop/available_liquidity.json");
+    const QUOTE_DAI: &str = include_str!("../../fixtures/hop/quote_dai.json");
+    const LIQUIDITY_DAI: &str = include_str!("../../fixtures/hop/available_liquidity_dai.json");
+
+    fn adapter(base_url: &str, chains: &str) -> HopAdapter {
+        let config = format!("base_url = \"{}\"\nchains = {}\n", base_url, chains);
+        HopAdapter::from_config(&toml::from_str(&config).unwrap()).unwrap()
+    }
+
+    fn request(dst_chain: &str, dst_token: &str) -> QuoteRequest {
+        request_for("0x2791Bca1f2de4661ED88A30C99A7a9449Aa84174", dst_chain, dst_token)
+    }
+
+    fn request_for(src_token: &str, dst_chain: &str, dst_token: &str) -> QuoteRequest {
+        QuoteRequest::builder()
+            .src_chain("polygon")
+            .dst_chain(dst_chain)
+            .src_token(src_token)
+            .dst_token(dst_token)
+            .src_amount("1000")
+            .wallet("0xca699201b15ccef3b8c4012e28570cc5500d9f9a")
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn supported_pairs_come_from_hop_addresses() {
+        let all = adapter("https://hop.test/v1", "[]");
+        assert!(all.is_supported_pair("polygon", "optimism",
+            "0x2791bca1f2de4661ed88a30c99a7a9449aa84174", "0x7f5c764cbc14f9669b88837ca1490cca17c31607"));
+        assert!(!all.is_supported_pair("polygon", "base",
+            "0xc2132d05d31c914a87c6611c10748aeb04b58e8f", "0xd9aaec86b65d86f6a7b5b1b0c42ffa531710b6ca"));
+
+        let restricted = adapter("https://hop.test/v1", "[\"ethereum\", \"arbitrum\"]");
+        assert_eq!(restricted.supported_pairs().len(), 8);
+    }
+
+    #[tokio::test]
+    async fn quotes_a_served_route() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/quote"))
+            .and(query_param("token", "USDC"))
+            .and(query_param("fromChain", "polygon"))
+            .and(query_param("toChain", "arbitrum"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(QUOTE))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/available-liquidity"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(LIQUIDITY))
+            .mount(&server)
+            .await;
+
+        let edge = adapter(&server.uri(), "[]")
+            .fetch_metrics(&request("arbitrum", "0xff970a61a04b1ca14834a43f5de4533ebddb5cc8"))
+            .await
+            .unwrap();
+
+        assert_eq!(edge.from, "polygon");
+        assert_eq!(edge.to, "arbitrum");
+        assert_eq!(edge.cost, 1.59);
+        assert_eq!(edge.speed, 300.0);
+        assert_eq!(edge.liquidity, 2458712.3);
+        assert_eq!(edge.estimated_output, 997.31);
+        assert_eq!(edge.valid_until, Some(1717528835));
+    }
+
+    // The same 1000-unit transfer in a 6 and an 18 decimal token lands on the same scale
+    #[tokio::test]
+    async fn usdc_and_dai_costs_are_comparable() {
+        let server = MockServer::start().await;
+        for (token, amount, quote, liquidity) in [
+            ("USDC", "1000000000", QUOTE, LIQUIDITY),
+            ("DAI", "1000000000000000000000", QUOTE_DAI, LIQUIDITY_DAI),
+        ] {
+            Mock::given(method("GET"))
+                .and(path("/quote"))
+                .and(query_param("token", token))
+                .and(query_param("amount", amount))
+                .respond_with(ResponseTemplate::new(200).set_body_string(quote))
+                .mount(&server)
+                .await;
+            Mock::given(method("GET"))
+                .and(path("/available-liquidity"))
+                .and(query_param("token", token))
+                .respond_with(ResponseTemplate::new(200).set_body_string(liquidity))
+                .mount(&server)
+                .await;
+        }
+        let adapter = adapter(&server.uri(), "[]");
+
+        let usdc = adapter
+            .fetch_metrics(&request("arbitrum", "0xff970a61a04b1ca14834a43f5de4533ebddb5cc8"))
+            .await
+            .unwrap();
+        let dai = adapter
+            .fetch_metrics(&request_for("0x8f3cf7ad23cd3cadbd9735aff958023239c6a063",
+                "arbitrum", "0xda10009cbd5d07dd0cecc66161fc93d7c9000da1"))
+            .await
+            .unwrap();
+
+        assert_eq!(usdc.cost, 1.59);
+        assert_eq!(dai.cost, 1.59);
+        assert_eq!(dai.liquidity, 1250000.0);
+    }
+
+    #[tokio::test]
+    async fn unknown_token_decimals_are_a_typed_error() {
+        let mut adapter = adapter("https://hop.test/v1", "[]");
+        adapter.decimals = TokenDecimals::default();
+
+        let err = adapter
+            .fetch_metrics(&request("arbitrum", "0xff970a61a04b1ca14834a43f5de4533ebddb5cc8"))
+            .await
+            .unwrap_err();
+        assert_eq!(err, AdapterError::UnknownToken {
+            chain: "polygon".to_string(),
+            token: "0x2791Bca1f2de4661ED88A30C99A7a9449Aa84174".to_string(),
+        });
+    }
+
+    #[tokio::test]
+    async fn unserved_pair_is_a_typed_error() {
+        let server = MockServer::start().await;
+        let adapter = adapter(&server.uri(), "[]");
+
+        // USDC on polygon to USDT on arbitrum: Hop only bridges a token to itself
+        let err = adapter
+            .fetch_metrics(&request("arbitrum", "0xfd086bc7cd5c481dcc9c85ebe478a1c0b69fcbb9"))
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, AdapterError::UnsupportedPair { .. }));
+        assert!(server.received_requests().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,337 @@
+use super::{
+    AdapterContext,
+    fixture_request,
+    FixtureQuote,
+    BridgeAdapter,
+    BridgeEdge,
+    QuoteRequest,
+    SupportedPair,
+    RiskModel,
+    unix_now,
+    FeeComponent,
+    chains::evm_chain_id,
+    settings::AdapterSettings,
+    AdapterError,
+    AdapterHealth,
+    health::probe,
+    MetricsRecorder,
+    RateLimiter,
+    RetryPolicy,
+    TokenDecimals
+};
+use crate::fx::{CurrencyId, Money};
+
+use std::time::Duration;
+use std::sync::Arc;
+use async_trait::async_trait;
+use polypathroute_core::{BridgeConfig, Redacted};
+use reqwest::Client;
+use serde_json::Value;
+use anyhow::Result;
+
+// LiFi tool keys that correspond to bridges we also quote directly
+const TOOL_ALIASES: &[(&str, &str)] = &[
+    ("stargatev2", "stargate"),
+    ("stargatev2bus", "stargate"),
+    ("stargate", "stargate"),
+    ("across", "across"),
+    ("acrossv3", "across"),
+    ("hop", "hop"),
+    ("synapse", "synapse"),
+    ("cbridge", "celer"),
+    ("wormhole", "wormhole"),
+];
+
+fn underlying_bridge(tool: &str) -> String {
+    let key = tool.to_lowercase();
+    TOOL_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == key)
+        .map(|(_, bridge)| bridge.to_string())
+        .unwrap_or(key)
+}
+
+pub struct LiFiAdapter {
+    pub name: String,
+    pub base_url: String,
+    api_key: Option<Redacted<String>>,
+    retry: RetryPolicy,
+    rate_limiter: Option<RateLimiter>,
+    client: Client,
+    pairs: Vec<SupportedPair>,
+    decimals: TokenDecimals,
+    risk_model: Arc<dyn RiskModel>,
+    telemetry: Arc<MetricsRecorder>,
+    // How long quotes stay usable when the API reports no expiry
+    quote_validity: Duration
+}
+
+impl LiFiAdapter {
+    pub fn from_config(config: &BridgeConfig) -> Result<Self> {
+        Self::new(AdapterContext::from_config("lifi", config)?)
+    }
+
+    pub fn new(context: AdapterContext) -> Result<Self> {
+        let settings = AdapterSettings::from_config("lifi", &context.config)?;
+        let client = context.client;
+        let pairs = settings.pairs
+            .into_iter()
+            .filter(|pair| evm_chain_id(&pair.src_chain).is_some() && evm_chain_id(&pair.dst_chain).is_some())
+            .collect();
+
+        Ok(Self {
+            name: "lifi".to_string(),
+            base_url: settings.base_url,
+            api_key: settings.api_key,
+            retry: settings.retry,
+            rate_limiter: settings.rate_limiter,
+            client,
+            pairs,
+            decimals: settings.decimals,
+            risk_model: settings.risk_model,
+            telemetry: settings.telemetry,
+            quote_validity: settings.quote_validity
+        })
+    }
+
+    // Replaces the risk model built from config
+    pub fn with_risk_model(mut self, risk_model: Arc<dyn RiskModel>) -> Self {
+        self.risk_model = risk_model;
+        self
+    }
+
+    pub fn quote_url(&self) -> String {
+        format!("{}/quote", self.base_url)
+    }
+
+    pub fn chains_url(&self) -> String {
+        format!("{}/chains", self.base_url)
+    }
+
+    // cost = USD value of feeCosts + gasCosts (fees and gas are in different tokens, so USD is
+    // the only common unit), speed = estimate.executionDuration, liquidity = estimate.toAmount.
+    // `via` is the underlying tool LiFi routed through; the gas estimate is gasCosts in USD.
+    fn parse_quote(&self, request: &QuoteRequest, response: &Value) -> Result<BridgeEdge, AdapterError> {
+        let estimate = response
+                        .get("estimate")
+                        .ok_or_else(|| AdapterError::missing("estimate"))?;
+
+        let usd_total = |field: &str| -> f64 {
+            estimate.get(field)
+                .and_then(|costs| costs.as_array())
+                .map(|costs| {
+                    costs.iter()
+                        .filter_map(|cost| {
+                            cost.get("amountUSD")
+                                .and_then(|v| v.as_str())
+                                .and_then(|s| s.parse::<f64>().ok())
+                        })
+                        .sum::<f64>()
+                })
+                .unwrap_or(0.0)
+        };
+
+        let speed = estimate.get("executionDuration")
+                        .and_then(|v| v.as_f64())
+                        .ok_or_else(|| AdapterError::missing("estimate.executionDuration"))?;
+        let liquidity = estimate.get("toAmount")
+                            .and_then(|v| v.as_str())
+                            .and_then(|s| s.parse::<f64>().ok())
+                            .ok_or_else(|| AdapterError::missing("estimate.toAmount"))?;
+        let liquidity = self.decimals.to_human(&request.dst_chain, &request.dst_token, liquidity)?;
+        let tool = response.get("tool")
+                        .or_else(|| estimate.get("tool"))
+                        .and_then(|v| v.as_str())
+                        .ok_or_else(|| AdapterError::missing("tool"))?;
+
+        let mut fee_components = estimate.get("feeCosts")
+            .and_then(|costs| costs.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|cost| {
+                Some(FeeComponent {
+                    name: cost.get("name").and_then(|v| v.as_str()).unwrap_or("fee").to_string(),
+                    amount: Money::new(cost.get("amountUSD")?.as_str()?.parse::<f64>().ok()?, CurrencyId::fiat("USD")),
+                })
+            })
+            .collect::<Vec<_>>();
+        let gas = usd_total("gasCosts");
+        // Gas is part of the cost, so it's listed with the fees
+        if gas > 0.0 {
+            fee_components.push(FeeComponent { name: "gas".to_string(), amount: Money::new(gas, CurrencyId::fiat("USD")) });
+        }
+
+        Ok(self.risk_model.score(&self.name, request, BridgeEdge {
+            from: request.src_chain.clone(),
+            to: request.dst_chain.clone(),
+            cost: usd_total("feeCosts") + gas,
+            speed,
+            // A quote has no pool depth
+            liquidity,
+            liquidity_unknown: true,
+            via: Some(underlying_bridge(tool)),
+            bridge: self.name.clone(),
+            estimated_output: liquidity,
+            gas_estimate: Some(Money::new(gas, CurrencyId::fiat("USD"))),
+            fee_components,
+            quoted_at: unix_now(),
+            ..BridgeEdge::default()
+        }).with_default_validity(self.quote_validity))
+    }
+}
+
+#[async_trait]
+impl BridgeAdapter for LiFiAdapter {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn supported_pairs(&self) -> Vec<SupportedPair> {
+        self.pairs.clone()
+    }
+
+    fn rate_limiter(&self) -> Option<&RateLimiter> {
+        self.rate_limiter.as_ref()
+    }
+
+    fn telemetry(&self) -> Option<&MetricsRecorder> {
+        Some(&self.telemetry)
+    }
+
+    async fn health_check(&self) -> Result<AdapterHealth, AdapterError> {
+        let request = self.client.get(self.chains_url());
+        let request = match &self.api_key {
+            Some(key) => request.header("x-lifi-api-key", key.expose()),
+            None => request
+        };
+        probe(self.rate_limiter.as_ref(), request).await
+    }
+
+    fn replay_fixture(&self) -> Option<FixtureQuote> {
+        const QUOTE: &str = include_str!("../../fixtures/lifi/quote.json");
+        let request = fixture_request("ethereum", "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", "polygon", "0x3c499c542cef5e3811e1192ce70d8cc03d5c3359", "1000");
+        let result = serde_json::from_str(QUOTE).map_err(AdapterError::from).and_then(|response| self.parse_quote(&request, &response));
+        Some(FixtureQuote { request, result })
+    }
+
+    async fn fetch_metrics(&self, request: &QuoteRequest) -> Result<BridgeEdge, AdapterError> {
+        let (from_chain, to_chain) = match (evm_chain_id(&request.src_chain), evm_chain_id(&request.dst_chain)) {
+            (Some(from_chain), Some(to_chain)) => (from_chain.to_string(), to_chain.to_string()),
+            _ => return Err(AdapterError::unsupported_pair(&self.name, request)),
+        };
+
+        let amount = request.src_amount_in(&self.decimals)?.to_raw_string();
+        let params = [
+            ("fromChain", from_chain.as_str()),
+            ("toChain", to_chain.as_str()),
+            ("fromToken", request.src_token.as_str()),
+            ("toToken", request.dst_token.as_str()),
+            ("fromAmount", amount.as_str()),
+            ("fromAddress", request.src_address.as_str()),
+            ("toAddress", request.dst_address.as_str()),
+        ];
+
+        let response: Value = self.retry
+            .send(self.rate_limiter.as_ref(), || {
+                let request = self.client.get(self.quote_url()).query(&params);
+                match &self.api_key {
+                    Some(key) => request.header("x-lifi-api-key", key.expose()),
+                    None => request
+                }
+            })
+            .await?
+            .json()
+            .await?;
+
+        self.parse_quote(request, &response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::dedup_by_underlying;
+    use wiremock::{Mock, MockServer, ResponseTemplate, matchers::{header, method, path}};
+
+    const QUOTE: &str = include_str!("../../fixtures/lifi/quote.json");
+    const QUOTE_NO_FEES: &str = include_str!("../../fixtures/lifi/quote_no_fees.json");
+
+    fn adapter(base_url: &str, extra: &str) -> LiFiAdapter {
+        let config = format!("base_url = \"{}\"\nchains = [\"ethereum\", \"polygon\"]\n{}", base_url, extra);
+        LiFiAdapter::from_config(&toml::from_str(&config).unwrap()).unwrap()
+    }
+
+    fn request() -> QuoteRequest {
+        QuoteRequest::builder()
+            .src_chain("ethereum")
+            .dst_chain("polygon")
+            .src_token("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48")
+            .dst_token("0x3c499c542cef5e3811e1192ce70d8cc03d5c3359")
+            .src_amount("1000")
+            .wallet("0xca699201b15ccef3b8c4012e28570cc5500d9f9a")
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn attributes_the_underlying_tool() {
+        let edge = adapter("https://lifi.test/v1", "")
+            .parse_quote(&request(), &serde_json::from_str(QUOTE).unwrap())
+            .unwrap();
+
+        assert_eq!(edge.via.as_deref(), Some("stargate"));
+        assert_eq!(edge.label("lifi"), "lifi:stargate");
+        assert!((edge.cost - 9.5).abs() < 1e-9);
+        assert_eq!(edge.speed, 180.5);
+        assert_eq!(edge.liquidity, 999.007);
+        assert!(edge.liquidity_unknown);
+        assert_eq!(edge.gas_estimate, Some(Money::new(9.1, CurrencyId::fiat("USD"))));
+        assert_eq!(edge.fee_components.len(), 3);
+        assert_eq!(edge.fee_components.iter().map(|fee| fee.amount.amount).sum::<f64>(), edge.cost);
+    }
+
+    #[test]
+    fn quote_without_fee_entries_costs_only_gas() {
+        let edge = adapter("https://lifi.test/v1", "")
+            .parse_quote(&request(), &serde_json::from_str(QUOTE_NO_FEES).unwrap())
+            .unwrap();
+
+        assert_eq!(edge.via.as_deref(), Some("across"));
+        assert!((edge.cost - 1.4).abs() < 1e-9);
+    }
+
+    #[test]
+    fn direct_quotes_win_over_aggregated_duplicates() {
+        let lifi = adapter("https://lifi.test/v1", "")
+            .parse_quote(&request(), &serde_json::from_str(QUOTE).unwrap())
+            .unwrap();
+        let direct = BridgeEdge { via: None, cost: 12.0, ..lifi.clone() };
+
+        let kept = dedup_by_underlying(vec![
+            ("lifi".to_string(), lifi.clone()),
+            ("stargate".to_string(), direct.clone()),
+            ("wormhole".to_string(), direct.clone()),
+        ]);
+
+        assert_eq!(kept.len(), 2);
+        assert_eq!(kept[0], ("stargate".to_string(), direct.clone()));
+        assert_eq!(kept[1].0, "wormhole");
+    }
+
+    #[tokio::test]
+    async fn sends_the_configured_api_key() {
+        // SAFETY: the variable name is unique to this test
+        unsafe { std::env::set_var("POLYPATH_TEST_LIFI_API_KEY", "lifi-key") };
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/quote"))
+            .and(header("x-lifi-api-key", "lifi-key"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(QUOTE))
+            .mount(&server)
+            .await;
+
+        let adapter = adapter(&server.uri(), "[extra]\napi_key = \"${POLYPATH_TEST_LIFI_API_KEY}\"\n");
+        let edge = adapter.fetch_metrics(&request()).await.unwrap();
+        assert_eq!(edge.via.as_deref(), Some("stargate"));
+    }
+}
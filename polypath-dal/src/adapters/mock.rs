@@ -0,0 +1,498 @@
+use super::{
+    AdapterContext,
+    AdapterError,
+    AdapterHealth,
+    MetricsRecorder,
+    RateLimiter,
+    pairs_from_config,
+    BridgeAdapter,
+    BridgeEdge,
+    DexAdapter,
+    QuoteRequest,
+    SupportedPair,
+    SwapPair,
+    SwapQuote,
+    TransferReference,
+    TransferStatus
+};
+
+use std::{collections::HashMap, ops::Range, sync::{Mutex, atomic::{AtomicUsize, Ordering}}, time::Duration};
+use async_trait::async_trait;
+use polypathroute_core::RefreshPriority;
+use anyhow::{Result, anyhow};
+
+// Deterministic adapter for tests. Quotes and failures are keyed by (src_chain, dst_chain);
+// anything not programmed is reported as an unsupported pair.
+#[derive(Debug, Default)]
+pub struct MockAdapter {
+    name: String,
+    quotes: HashMap<(String, String), BridgeEdge>,
+    // Quotes for a route's second call on, the last one repeating
+    later_quotes: HashMap<(String, String), Vec<BridgeEdge>>,
+    failures: HashMap<(String, String), AdapterError>,
+    // Failures of some of a route's calls only, by call index
+    failing_calls: HashMap<(String, String), Vec<FailingCalls>>,
+    latency: Duration,
+    health: Option<Result<AdapterHealth, AdapterError>>,
+    rate_limiter: Option<RateLimiter>,
+    telemetry: MetricsRecorder,
+    // What supported_pairs reports instead of the programmed routes, see `advertise`
+    advertised: Mutex<Option<Vec<SupportedPair>>>,
+    requests: Mutex<Vec<QuoteRequest>>,
+    in_flight: AtomicUsize,
+    peak_in_flight: AtomicUsize,
+    // Most requests per fetch_metrics_batch call, see `with_batch_size`
+    batch_size: usize,
+    // Requests taken by each fetch_metrics_batch call
+    batches: Mutex<Vec<usize>>,
+    // What track_transfer answers; references not programmed are unknown transfers
+    transfers: HashMap<TransferReference, Result<TransferStatus, AdapterError>>,
+}
+
+type FailingCalls = (Range<usize>, AdapterError);
+
+fn route(src_chain: &str, dst_chain: &str) -> (String, String) {
+    (src_chain.to_lowercase(), dst_chain.to_lowercase())
+}
+
+impl MockAdapter {
+    pub fn new() -> Self {
+        Self::named("mock")
+    }
+
+    pub fn named(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Self::default()
+        }
+    }
+
+    // The "mock" bridge built from config: every configured pair is quoted with the metrics in
+    // the bridge's `[extra.quote]` table (cost, speed, liquidity, risk), each optional. Transfers
+    // in `[extra.transfers]`, by source tx hash, are tracked with the status given there, like
+    // `"0xabc" = { status = "in_flight", eta = 1718000600 }`.
+    pub fn from_context(name: &str, context: &AdapterContext) -> Result<Self> {
+        let quote = context.config.extra.as_ref().and_then(|extra| extra.get("quote"));
+        let metric = |key: &str, default: f64| -> Result<f64> {
+            match quote.and_then(|quote| quote.get(key)) {
+                Some(value) => value
+                    .as_float()
+                    .or_else(|| value.as_integer().map(|v| v as f64))
+                    .filter(|v| v.is_finite() && *v >= 0.0)
+                    .ok_or_else(|| anyhow!("bridges.{}.extra.quote.{} must be a non-negative number", name, key)),
+                None => Ok(default),
+            }
+        };
+        let template = BridgeEdge {
+            cost: metric("cost", 1.0)?,
+            speed: metric("speed", 60.0)?,
+            liquidity: metric("liquidity", 1_000_000.0)?,
+            risk: metric("risk", 0.1)?,
+            ..BridgeEdge::default()
+        };
+
+        let mut adapter = Self::named(name);
+        for pair in pairs_from_config(&context.config) {
+            let edge = BridgeEdge {
+                from: pair.src_chain.clone(),
+                to: pair.dst_chain.clone(),
+                ..template.clone()
+            };
+            adapter = adapter.with_quote(&pair.src_chain, &pair.dst_chain, edge);
+        }
+        let transfers = context.config.extra.as_ref().and_then(|extra| extra.get("transfers"));
+        for (hash, status) in transfers.and_then(|transfers| transfers.as_table()).into_iter().flatten() {
+            let status: TransferStatus = status
+                .clone()
+                .try_into()
+                .map_err(|err| anyhow!("bridges.{}.extra.transfers.{}: {}", name, hash, err))?;
+            adapter = adapter.with_transfer(TransferReference::SourceTx(hash.clone()), Ok(status));
+        }
+        Ok(adapter)
+    }
+
+    pub fn with_quote(mut self, src_chain: &str, dst_chain: &str, edge: BridgeEdge) -> Self {
+        self.quotes.insert(route(src_chain, dst_chain), edge);
+        self
+    }
+
+    // Quoted in turn from the route's second call on, the last one repeating
+    pub fn then_quote(mut self, src_chain: &str, dst_chain: &str, edge: BridgeEdge) -> Self {
+        self.later_quotes.entry(route(src_chain, dst_chain)).or_default().push(edge);
+        self
+    }
+
+    // Failures take precedence over quotes for the same route
+    pub fn with_failure(mut self, src_chain: &str, dst_chain: &str, error: AdapterError) -> Self {
+        self.failures.insert(route(src_chain, dst_chain), error);
+        self
+    }
+
+    // Fails the route's calls in `calls`, counted from 0, with `error`; the others are quoted
+    // as programmed
+    pub fn with_failing_calls(mut self, src_chain: &str, dst_chain: &str, calls: Range<usize>, error: AdapterError) -> Self {
+        self.failing_calls.entry(route(src_chain, dst_chain)).or_default().push((calls, error));
+        self
+    }
+
+    // What track_transfer answers for `reference`
+    pub fn with_transfer(mut self, reference: TransferReference, status: Result<TransferStatus, AdapterError>) -> Self {
+        self.transfers.insert(reference, status);
+        self
+    }
+
+    // Delay applied to every fetch, for timeout and concurrency tests
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    // Paces fetches the way a real adapter's limiter does
+    pub fn with_rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    // What health_check reports; healthy when not programmed
+    pub fn with_health(mut self, health: Result<AdapterHealth, AdapterError>) -> Self {
+        self.health = Some(health);
+        self
+    }
+
+    // Pairs supported_pairs reports, whether or not they're quoted
+    pub fn with_advertised_pairs(self, pairs: Vec<SupportedPair>) -> Self {
+        self.advertise(pairs);
+        self
+    }
+
+    // Changes what supported_pairs reports from now on
+    pub fn advertise(&self, pairs: Vec<SupportedPair>) {
+        *self.advertised.lock().unwrap() = Some(pairs);
+    }
+
+    // Quotes up to `size` requests per fetch_metrics_batch call, as one request that takes
+    // `latency` once
+    pub fn with_batch_size(mut self, size: usize) -> Self {
+        self.batch_size = size.max(1);
+        self
+    }
+
+    // How many requests each fetch_metrics_batch call took, in call order
+    pub fn batches(&self) -> Vec<usize> {
+        self.batches.lock().unwrap().clone()
+    }
+
+    // Every request received, in arrival order
+    pub fn requests(&self) -> Vec<QuoteRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    pub fn call_count(&self) -> usize {
+        self.requests.lock().unwrap().len()
+    }
+
+    // Highest number of fetches that were running at the same time
+    pub fn peak_in_flight(&self) -> usize {
+        self.peak_in_flight.load(Ordering::SeqCst)
+    }
+
+    // Takes `requests` as one call: waits for the limiter and the latency once, and returns
+    // how many earlier requests each one's route had
+    async fn receive(&self, requests: &[QuoteRequest]) -> Vec<usize> {
+        if let Some(limiter) = &self.rate_limiter
+            && !limiter.acquire().await.is_zero()
+        {
+            self.telemetry.record_rate_limit_wait();
+        }
+        let earlier_calls = {
+            let mut received = self.requests.lock().unwrap();
+            requests
+                .iter()
+                .map(|request| {
+                    let key = route(&request.src_chain, &request.dst_chain);
+                    let earlier = received.iter().filter(|earlier| route(&earlier.src_chain, &earlier.dst_chain) == key).count();
+                    received.push(request.clone());
+                    earlier
+                })
+                .collect()
+        };
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+        if !self.latency.is_zero() {
+            tokio::time::sleep(self.latency).await;
+        }
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        earlier_calls
+    }
+
+    // The programmed answer to `request`, the route's call number `earlier_calls`
+    fn answer(&self, request: &QuoteRequest, earlier_calls: usize) -> Result<BridgeEdge, AdapterError> {
+        let key = route(&request.src_chain, &request.dst_chain);
+        if let Some(error) = self.failures.get(&key) {
+            return Err(error.clone());
+        }
+        let mut failing = self.failing_calls.get(&key).into_iter().flatten();
+        if let Some((_, error)) = failing.find(|(calls, _)| calls.contains(&earlier_calls)) {
+            return Err(error.clone());
+        }
+        let later = earlier_calls
+            .checked_sub(1)
+            .and_then(|call| self.later_quotes.get(&key).and_then(|quotes| quotes.get(call.min(quotes.len() - 1))));
+        later
+            .or_else(|| self.quotes.get(&key))
+            .cloned()
+            .map(|mut edge| {
+                if edge.bridge.is_empty() {
+                    edge.bridge = self.name.clone();
+                }
+                edge
+            })
+            .ok_or_else(|| AdapterError::unsupported_pair(&self.name, request))
+    }
+}
+
+#[async_trait]
+impl BridgeAdapter for MockAdapter {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    // Routes are programmed per chain, so tokens are left empty unless advertised
+    fn supported_pairs(&self) -> Vec<SupportedPair> {
+        if let Some(advertised) = self.advertised.lock().unwrap().as_ref() {
+            return advertised.clone();
+        }
+        self.quotes
+            .keys()
+            .map(|(src_chain, dst_chain)| SupportedPair {
+                src_chain: src_chain.clone(),
+                dst_chain: dst_chain.clone(),
+                src_token: String::new(),
+                dst_token: String::new(),
+                min_amount: None,
+                max_amount: None,
+                token_symbol: None,
+                priority: RefreshPriority::default(),
+                refresh_interval: None,
+            })
+            .collect()
+    }
+
+    fn rate_limiter(&self) -> Option<&RateLimiter> {
+        self.rate_limiter.as_ref()
+    }
+
+    fn telemetry(&self) -> Option<&MetricsRecorder> {
+        Some(&self.telemetry)
+    }
+
+    fn is_supported_pair(&self, src_chain: &str, dst_chain: &str, _src_token: &str, _dst_token: &str) -> bool {
+        self.quotes.contains_key(&route(src_chain, dst_chain))
+    }
+
+    async fn fetch_metrics(&self, request: &QuoteRequest) -> Result<BridgeEdge, AdapterError> {
+        let earlier_calls = self.receive(std::slice::from_ref(request)).await;
+        self.answer(request, earlier_calls[0])
+    }
+
+    fn max_batch_size(&self) -> usize {
+        self.batch_size.max(1)
+    }
+
+    async fn fetch_metrics_batch(&self, requests: &[QuoteRequest]) -> Vec<Result<BridgeEdge, AdapterError>> {
+        self.batches.lock().unwrap().push(requests.len());
+        let earlier_calls = self.receive(requests).await;
+        requests.iter().zip(earlier_calls).map(|(request, earlier)| self.answer(request, earlier)).collect()
+    }
+
+    async fn health_check(&self) -> Result<AdapterHealth, AdapterError> {
+        self.health
+            .clone()
+            .unwrap_or_else(|| Ok(AdapterHealth::healthy(self.name.clone())))
+    }
+
+    async fn track_transfer(&self, reference: &TransferReference) -> Result<TransferStatus, AdapterError> {
+        self.transfers.get(reference).cloned().unwrap_or_else(|| {
+            Err(AdapterError::UnknownTransfer { bridge: self.name.clone(), reference: reference.to_string() })
+        })
+    }
+}
+
+// Deterministic DEX for tests: each programmed pair swaps at a fixed rate, less a fixed fee
+#[derive(Debug, Default)]
+pub struct MockDex {
+    name: String,
+    // Rate, fee and gas per pair
+    swaps: Vec<(SwapPair, f64, f64, f64)>,
+}
+
+impl MockDex {
+    pub fn named(name: &str) -> Self {
+        Self { name: name.to_string(), swaps: Vec::new() }
+    }
+
+    pub fn with_swap(mut self, pair: SwapPair, rate: f64, fee: f64, gas: f64) -> Self {
+        self.swaps.push((pair, rate, fee, gas));
+        self
+    }
+}
+
+#[async_trait]
+impl DexAdapter for MockDex {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn swap_pairs(&self) -> Vec<SwapPair> {
+        self.swaps.iter().map(|(pair, ..)| pair.clone()).collect()
+    }
+
+    async fn quote_in(&self, pair: &SwapPair, amount_in: f64) -> Result<SwapQuote, AdapterError> {
+        let (_, rate, fee, gas) = self.swaps.iter().find(|(swap, ..)| swap == pair).ok_or_else(|| AdapterError::UnsupportedPair {
+            bridge: self.name.clone(),
+            src_chain: pair.chain.clone(),
+            dst_chain: pair.chain.clone(),
+            src_token: pair.token_in.clone(),
+            dst_token: pair.token_out.clone(),
+        })?;
+        Ok(SwapQuote { amount_out: (amount_in * rate - fee).max(0.0), fee: *fee, gas: *gas, liquidity: 1_000_000.0 })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edge(from: &str, to: &str, cost: f64) -> BridgeEdge {
+        BridgeEdge {
+            from: from.to_string(),
+            to: to.to_string(),
+            cost,
+            speed: 60.0,
+            liquidity: 1_000_000.0,
+            risk: 600.0,
+            via: None,
+            ..BridgeEdge::default()
+        }
+    }
+
+    fn request(src_chain: &str, dst_chain: &str) -> QuoteRequest {
+        QuoteRequest::builder()
+            .src_chain(src_chain)
+            .dst_chain(dst_chain)
+            .src_token("0xa0b8")
+            .dst_token("0x3c49")
+            .src_amount("1000000")
+            .wallet("0xca69")
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn serves_programmed_quotes_and_failures() {
+        let adapter = MockAdapter::new()
+            .with_quote("ethereum", "polygon", edge("ethereum", "polygon", 3.0))
+            .with_failure("ethereum", "base", AdapterError::RateLimited { retry_after: None });
+
+        assert_eq!(adapter.fetch_metrics(&request("Ethereum", "polygon")).await.unwrap().cost, 3.0);
+
+        let err = adapter.fetch_metrics(&request("ethereum", "base")).await.unwrap_err();
+        assert_eq!(err, AdapterError::RateLimited { retry_after: None });
+
+        let err = adapter.fetch_metrics(&request("ethereum", "arbitrum")).await.unwrap_err();
+        assert!(matches!(err, AdapterError::UnsupportedPair { .. }));
+
+        assert_eq!(adapter.call_count(), 3);
+        assert_eq!(adapter.requests()[1].dst_chain, "base");
+        assert!(adapter.is_supported_pair("ethereum", "polygon", "", ""));
+        assert!(!adapter.is_supported_pair("ethereum", "base", "", ""));
+    }
+
+    #[tokio::test]
+    async fn later_calls_take_the_next_quotes() {
+        let adapter = MockAdapter::new()
+            .with_quote("ethereum", "polygon", edge("ethereum", "polygon", 1.0))
+            .then_quote("ethereum", "polygon", edge("ethereum", "polygon", 2.0))
+            .then_quote("ethereum", "polygon", edge("ethereum", "polygon", 3.0))
+            .with_quote("ethereum", "base", edge("ethereum", "base", 5.0));
+
+        let mut costs = Vec::new();
+        for _ in 0..4 {
+            costs.push(adapter.fetch_metrics(&request("ethereum", "polygon")).await.unwrap().cost);
+        }
+        assert_eq!(costs, [1.0, 2.0, 3.0, 3.0]);
+        assert_eq!(adapter.fetch_metrics(&request("ethereum", "base")).await.unwrap().cost, 5.0);
+    }
+
+    #[tokio::test]
+    async fn quotes_configured_pairs_from_context() {
+        let config: polypathroute_core::BridgeConfig = toml::from_str(r#"
+            base_url = "http://mock.test"
+            chains = ["ethereum", "polygon"]
+
+            [[pairs]]
+            source_chain = "ethereum"
+            source_token_name = "USDC"
+            destination_chain = "polygon"
+            destination_token_name = "USDC"
+            source_address = "0xa0b8"
+            destination_address = "0x3c49"
+
+            [extra.quote]
+            cost = 2
+            risk = 0.5
+        "#).unwrap();
+        let context = AdapterContext::with_client(reqwest::Client::new(), config.clone());
+        let adapter = MockAdapter::from_context("mock", &context).unwrap();
+
+        let edge = adapter.fetch_metrics(&request("ethereum", "polygon")).await.unwrap();
+        assert_eq!((edge.cost, edge.speed, edge.risk), (2.0, 60.0, 0.5));
+        assert_eq!(edge.bridge, "mock");
+        assert!(!adapter.is_supported_pair("polygon", "ethereum", "", ""));
+
+        let mut bad = config;
+        bad.extra.as_mut().unwrap().insert("quote".to_string(), toml::from_str("cost = -1").unwrap());
+        let context = AdapterContext::with_client(reqwest::Client::new(), bad);
+        assert!(MockAdapter::from_context("mock", &context).is_err());
+    }
+
+    #[tokio::test]
+    async fn tracks_programmed_and_configured_transfers() {
+        let stuck = TransferStatus::Stuck { since: 1_718_000_000, reason: "paused".to_string() };
+        let adapter = MockAdapter::new()
+            .with_transfer(TransferReference::MessageId("0x5c1e".to_string()), Ok(stuck.clone()))
+            .with_transfer(TransferReference::SourceTx("0x8f2c".to_string()), Err(AdapterError::Timeout { attempts: 1 }));
+
+        assert_eq!(adapter.track_transfer(&TransferReference::MessageId("0x5c1e".to_string())).await, Ok(stuck));
+        assert_eq!(adapter.track_transfer(&TransferReference::SourceTx("0x8f2c".to_string())).await, Err(AdapterError::Timeout { attempts: 1 }));
+        // Same id, other kind of reference
+        assert_eq!(
+            adapter.track_transfer(&TransferReference::SourceTx("0x5c1e".to_string())).await,
+            Err(AdapterError::UnknownTransfer { bridge: "mock".to_string(), reference: "source_tx 0x5c1e".to_string() })
+        );
+
+        let config: polypathroute_core::BridgeConfig = toml::from_str(r#"
+            base_url = "http://mock.test"
+            chains = ["ethereum", "polygon"]
+
+            [extra.transfers]
+            "0x8f2c" = { status = "in_flight", eta = 1718000600 }
+            "0x1d3f" = { status = "delivered", dst_tx = "0x9a0c" }
+        "#).unwrap();
+        let context = AdapterContext::with_client(reqwest::Client::new(), config.clone());
+        let adapter = MockAdapter::from_context("mock", &context).unwrap();
+        assert_eq!(
+            adapter.track_transfer(&TransferReference::SourceTx("0x8f2c".to_string())).await,
+            Ok(TransferStatus::InFlight { eta: Some(1_718_000_600) })
+        );
+        assert_eq!(
+            adapter.track_transfer(&TransferReference::SourceTx("0x1d3f".to_string())).await,
+            Ok(TransferStatus::Delivered { dst_tx: "0x9a0c".to_string() })
+        );
+
+        let mut bad = config;
+        bad.extra.as_mut().unwrap().insert("transfers".to_string(), toml::from_str(r#""0x8f2c" = { status = "lost" }"#).unwrap());
+        let context = AdapterContext::with_client(reqwest::Client::new(), bad);
+        assert!(MockAdapter::from_context("mock", &context).is_err());
+    }
+}
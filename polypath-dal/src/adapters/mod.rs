@@ -1,12 +1,70 @@
 pub mod stargate;
 pub mod wormhole;
+pub mod across;
+pub mod hop;
+pub mod synapse;
+pub mod lifi;
+pub mod celer;
+pub mod simulated;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+mod quote;
+mod pairs;
+mod settings;
+mod retry;
+mod error;
+mod rate_limit;
+mod chains;
+mod decimals;
+mod health;
+mod breaker;
+mod risk;
+mod telemetry;
+mod factory;
+mod dex;
+mod paging;
+mod recording;
+mod transfer;
 
-use std::collections::HashMap;
-use serde::Serialize;
-use serde_json::Value;
-use anyhow::Result;
+pub use quote::{QuoteRequest, QuoteRequestBuilder};
+pub use pairs::{SupportedPair, pairs_from_config};
+pub(crate) use pairs::merge_pair;
+pub use settings::expand_env;
+pub(crate) use settings::DEFAULT_QUOTE_VALIDITY;
+pub use retry::{RetryPolicy, StatusClass};
+pub use recording::{FixtureMode, FixtureStore, RECORD_FIXTURES_ENV, REPLAY_FIXTURES_ENV, RecordedResponse};
+pub use transfer::{TransferReference, TransferStatus};
+pub use error::{AdapterError, Disposition};
+pub use settings::{AdapterContext, HttpSettings, Timeouts};
+pub use rate_limit::RateLimiter;
+pub use chains::{ChainInfo, canonical_chain_key, evm_chain_id, evm_chain_key};
+pub use decimals::TokenDecimals;
+pub(crate) use decimals::NATIVE_ADDRESSES;
+pub use health::AdapterHealth;
+pub use dex::{DexAdapter, SwapPair, SwapQuote};
+pub use paging::{MAX_LISTING_PAGES, Page, paginate};
+pub use factory::{AdapterFactory, register};
+pub use telemetry::{AdapterMetrics, LastError, MetricsRecorder, MetricsReport};
+pub use risk::{BridgeStatus, DefaultRiskModel, RiskContext, RiskModel};
+pub use breaker::{BreakerAdapter, BreakerSettings, CircuitBreaker, CircuitState, Clock, ManualClock, Permit, SystemClock};
 
-#[derive(Serialize, Debug, Clone)]
+use std::{sync::Arc, time::Duration};
+use async_trait::async_trait;
+use polypathroute_core::BridgeConfig;
+use crate::fx::{FxConverter, Money};
+use serde::{Deserialize, Serialize};
+use anyhow::{Result, anyhow};
+
+// One fee line of a quote, in whatever currency the API charges it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FeeComponent {
+    pub name: String,
+    pub amount: Money,
+}
+
+// A quoted hop. Amounts are in human token units, times in unix seconds. `cost` adds up the fees
+// as quoted, whatever their currencies; GraphUpdater rebuilds it in the [fx] quote currency.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct BridgeEdge {
     pub from: String,
     pub to: String,
@@ -14,29 +72,447 @@ pub struct BridgeEdge {
     pub speed: f64,
     pub liquidity: f64,
     pub risk: f64,
+    // Underlying bridge when quoted through an aggregator, e.g. "stargate" for a LiFi route
+    #[serde(default)]
+    pub via: Option<String>,
+    // Adapter that produced the quote
+    #[serde(default)]
+    pub bridge: String,
+    // Destination amount the quoted transfer should deliver
+    #[serde(default)]
+    pub estimated_output: f64,
+    #[serde(default)]
+    pub gas_estimate: Option<Money>,
+    #[serde(default)]
+    pub fee_components: Vec<FeeComponent>,
+    #[serde(default)]
+    pub quoted_at: u64,
+    #[serde(default)]
+    pub valid_until: Option<u64>,
+    // Source amount bounds the route accepts, when the API reports them
+    #[serde(default)]
+    pub min_amount: Option<f64>,
+    #[serde(default)]
+    pub max_amount: Option<f64>,
+    // Set when the API said nothing of the route's depth, so `liquidity` is only what the
+    // quoted amount would deliver; GraphUpdater learns the depth instead
+    #[serde(default)]
+    pub liquidity_unknown: bool,
+    // `cost` in the source token, set by GraphUpdater once it has put `cost` in the [fx] quote
+    // currency, for taking the fees out of an amount sent
+    #[serde(default)]
+    pub cost_in_source: Option<f64>,
+}
+
+impl BridgeEdge {
+    // Sets valid_until to quoted_at + `validity` when the upstream reported no expiry
+    pub fn with_default_validity(mut self, validity: Duration) -> Self {
+        if self.valid_until.is_none() {
+            self.valid_until = Some(self.quoted_at + validity.as_secs());
+        }
+        self
+    }
+
+    // Whether the quote can still be acted on at unix time `now`. Quotes without an expiry can.
+    pub fn is_valid_at(&self, now: u64) -> bool {
+        self.valid_until.is_none_or(|until| now < until)
+    }
+
+    // Time left before the quote expires, zero once it has; None without an expiry.
+    // What a graph edge built from this quote should live for.
+    pub fn remaining_validity(&self) -> Option<Duration> {
+        self.valid_until.map(|until| Duration::from_secs(until.saturating_sub(unix_now())))
+    }
+
+    // Graph label for an edge quoted by `bridge`: "lifi:stargate" for aggregated routes, else the bridge itself
+    pub fn label(&self, bridge: &str) -> String {
+        match &self.via {
+            Some(via) => format!("{}:{}", bridge, via),
+            None => bridge.to_string(),
+        }
+    }
+
+    // The bridge that actually moves the funds
+    pub fn underlying_bridge<'a>(&'a self, bridge: &'a str) -> &'a str {
+        self.via.as_deref().unwrap_or(bridge)
+    }
+}
+
+// Drops aggregator edges that duplicate a direct quote for the same underlying bridge and hop.
+// Input is (adapter name, edge); direct quotes always win, otherwise the first seen is kept.
+pub fn dedup_by_underlying(edges: Vec<(String, BridgeEdge)>) -> Vec<(String, BridgeEdge)> {
+    let same_hop = |a: &(String, BridgeEdge), b: &(String, BridgeEdge)| {
+        a.1.underlying_bridge(&a.0) == b.1.underlying_bridge(&b.0)
+            && a.1.from.eq_ignore_ascii_case(&b.1.from)
+            && a.1.to.eq_ignore_ascii_case(&b.1.to)
+    };
+
+    let mut kept: Vec<(String, BridgeEdge)> = Vec::with_capacity(edges.len());
+    for edge in edges {
+        match kept.iter().position(|existing| same_hop(existing, &edge)) {
+            Some(index) => {
+                if kept[index].1.via.is_some() && edge.1.via.is_none() {
+                    kept[index] = edge;
+                }
+            }
+            None => kept.push(edge),
+        }
+    }
+    kept
 }
 
+// A bundled API response run through an adapter's parser, and the request it answers
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixtureQuote {
+    pub request: QuoteRequest,
+    pub result: Result<BridgeEdge, AdapterError>,
+}
 
+// The request a bundled response answers, from the wallet the fixtures were recorded with
+pub(crate) fn fixture_request(src_chain: &str, src_token: &str, dst_chain: &str, dst_token: &str, amount: &str) -> QuoteRequest {
+    QuoteRequest::builder()
+        .src_chain(src_chain)
+        .dst_chain(dst_chain)
+        .src_token(src_token)
+        .dst_token(dst_token)
+        .src_amount(amount)
+        .wallet("0xca699201b15ccef3b8c4012e28570cc5500d9f9a")
+        .build()
+        .expect("fixture requests are complete")
+}
+
+
+#[async_trait]
 pub trait BridgeAdapter {
     fn name(&self) -> String;
-    fn supported_pairs(&self) -> HashMap<String, String>;
-    fn is_supported_pair(&self) -> bool;
-    fn fetch_metrics(&self, src_chain: &str, dst_chain: &str, src_token: &str, dst_token: &str,
-        src_amount: &str, dst_amount_min: &str, src_address: &str, dst_address: &str) -> Result<Value>;
+    fn supported_pairs(&self) -> Vec<SupportedPair>;
+
+    // Limiter applied to this adapter's requests, if one is configured
+    fn rate_limiter(&self) -> Option<&RateLimiter> {
+        None
+    }
+
+    // State of the circuit breaker guarding this adapter, if it has one
+    fn circuit_state(&self) -> Option<CircuitState> {
+        None
+    }
+
+    // Recorder for this adapter's telemetry, if it keeps any
+    fn telemetry(&self) -> Option<&MetricsRecorder> {
+        None
+    }
+
+    // Zeros for adapters that don't track
+    fn metrics(&self) -> AdapterMetrics {
+        self.telemetry().map(MetricsRecorder::snapshot).unwrap_or_default()
+    }
+
+    fn is_supported_pair(&self, src_chain: &str, dst_chain: &str, src_token: &str, dst_token: &str) -> bool {
+        self.supported_pairs()
+            .iter()
+            .any(|pair| pair.matches(src_chain, dst_chain, src_token, dst_token))
+    }
+
+    async fn fetch_metrics(&self, request: &QuoteRequest) -> Result<BridgeEdge, AdapterError>;
+
+    // Most requests fetch_metrics_batch takes in one call; 1 for adapters that quote one pair
+    // per request
+    fn max_batch_size(&self) -> usize {
+        1
+    }
+
+    // Quotes up to max_batch_size requests, answering each in request order. Adapters whose API
+    // takes several pairs per call send them together; by default each is quoted in turn.
+    async fn fetch_metrics_batch(&self, requests: &[QuoteRequest]) -> Vec<Result<BridgeEdge, AdapterError>> {
+        let mut results = Vec::with_capacity(requests.len());
+        for request in requests {
+            results.push(self.fetch_metrics(request).await);
+        }
+        results
+    }
+
+    // Quotes the same route at each of `amounts` (human units), in order and one at a time so
+    // the adapter's rate limiter paces the ladder. The first failing amount fails the probe.
+    async fn fetch_depth(&self, request: &QuoteRequest, amounts: &[f64]) -> Result<Vec<(f64, BridgeEdge)>, AdapterError> {
+        let mut depth = Vec::with_capacity(amounts.len());
+        for &amount in amounts {
+            let edge = self.fetch_metrics(&request.with_src_amount(amount)?).await?;
+            depth.push((amount, edge));
+        }
+        Ok(depth)
+    }
+
+    // Cheap probe of the upstream API. HTTP failures such as a 401 are reported as
+    // unreachable with details, transport failures are errors.
+    async fn health_check(&self) -> Result<AdapterHealth, AdapterError>;
+
+    // Chains the bridge itself says it serves. Empty for adapters that can't tell.
+    async fn supported_chains(&self) -> Result<Vec<ChainInfo>, AdapterError> {
+        Ok(Vec::new())
+    }
+
+    // A bundled response parsed as if the API had sent it, without touching the network.
+    // None for adapters that bundle none.
+    fn replay_fixture(&self) -> Option<FixtureQuote> {
+        None
+    }
+
+    // Where a transfer sent over the bridge stands. Unsupported for adapters whose API can't
+    // tell.
+    async fn track_transfer(&self, reference: &TransferReference) -> Result<TransferStatus, AdapterError> {
+        let _ = reference;
+        Err(AdapterError::Unsupported { bridge: self.name(), operation: "transfer tracking" })
+    }
+
+    // For callers outside an async runtime. Must not be called from within one.
+    fn fetch_metrics_blocking(&self, request: &QuoteRequest) -> Result<BridgeEdge, AdapterError> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|err| AdapterError::Config(format!("failed to start runtime: {}", err)))?;
+        runtime.block_on(self.fetch_metrics(request))
+    }
+}
+
+// Lets one adapter instance be shared, e.g. between a registry and a batch fetch
+#[async_trait]
+impl<T: BridgeAdapter + Send + Sync + ?Sized> BridgeAdapter for std::sync::Arc<T> {
+    fn name(&self) -> String {
+        (**self).name()
+    }
+
+    fn supported_pairs(&self) -> Vec<SupportedPair> {
+        (**self).supported_pairs()
+    }
+
+    fn rate_limiter(&self) -> Option<&RateLimiter> {
+        (**self).rate_limiter()
+    }
+
+    fn circuit_state(&self) -> Option<CircuitState> {
+        (**self).circuit_state()
+    }
+
+    fn telemetry(&self) -> Option<&MetricsRecorder> {
+        (**self).telemetry()
+    }
+
+    fn is_supported_pair(&self, src_chain: &str, dst_chain: &str, src_token: &str, dst_token: &str) -> bool {
+        (**self).is_supported_pair(src_chain, dst_chain, src_token, dst_token)
+    }
+
+    async fn fetch_metrics(&self, request: &QuoteRequest) -> Result<BridgeEdge, AdapterError> {
+        (**self).fetch_metrics(request).await
+    }
+
+    fn max_batch_size(&self) -> usize {
+        (**self).max_batch_size()
+    }
+
+    async fn fetch_metrics_batch(&self, requests: &[QuoteRequest]) -> Vec<Result<BridgeEdge, AdapterError>> {
+        (**self).fetch_metrics_batch(requests).await
+    }
+
+    async fn fetch_depth(&self, request: &QuoteRequest, amounts: &[f64]) -> Result<Vec<(f64, BridgeEdge)>, AdapterError> {
+        (**self).fetch_depth(request, amounts).await
+    }
+
+    async fn health_check(&self) -> Result<AdapterHealth, AdapterError> {
+        (**self).health_check().await
+    }
+
+    async fn supported_chains(&self) -> Result<Vec<ChainInfo>, AdapterError> {
+        (**self).supported_chains().await
+    }
+
+    fn replay_fixture(&self) -> Option<FixtureQuote> {
+        (**self).replay_fixture()
+    }
+
+    async fn track_transfer(&self, reference: &TransferReference) -> Result<TransferStatus, AdapterError> {
+        (**self).track_transfer(reference).await
+    }
+}
+
+pub(crate) fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
 }
 
 pub type DynBridgeAdapter = Box<dyn BridgeAdapter + Send + Sync>;
 
-pub fn create_adapter(name: &str) -> Option<DynBridgeAdapter> {
+// Bridges that have an adapter implementation, built in or registered
+pub fn available_adapters() -> Vec<String> {
+    #[allow(unused_mut)]
+    let mut names = vec!["stargate", "wormhole", "across", "hop", "synapse", "lifi", "celer", "simulated"];
+    #[cfg(any(test, feature = "mock"))]
+    names.push("mock");
+
+    let mut names: Vec<String> = names.into_iter().map(String::from).collect();
+    for name in factory::registered_names() {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+// Builds the named adapter from its bridge config, behind a circuit breaker. Fails on
+// unknown bridges and on missing or unresolvable settings, both as AdapterError::Config.
+pub fn create_adapter(name: &str, config: &BridgeConfig) -> Result<DynBridgeAdapter, AdapterError> {
+    create_adapter_with(name, config, None)
+}
+
+// create_adapter with amounts priced by `fx`, see AdapterContext::with_fx_converter
+pub(crate) fn create_adapter_with(name: &str, config: &BridgeConfig, fx: Option<Arc<dyn FxConverter>>) -> Result<DynBridgeAdapter, AdapterError> {
+    let build = || -> Result<DynBridgeAdapter> {
+        let breaker = CircuitBreaker::new(BreakerSettings::from_config(name, config)?);
+        let context = AdapterContext::from_config(name, config)?;
+        let context = match fx.clone() {
+            Some(fx) => context.with_fx_converter(fx),
+            None => context,
+        };
+        Ok(Box::new(BreakerAdapter::new(build_adapter(name, context)?, breaker)))
+    };
+    build().map_err(|err| AdapterError::Config(format!("{:#}", err)))
+}
+
+// A SimulatedAdapter for the named bridge's pairs and `[extra.simulation]` table, seeded with
+// `seed` instead of the table's own. Nothing is sent over the network.
+pub fn create_simulated_adapter(name: &str, config: &BridgeConfig, seed: u64) -> Result<DynBridgeAdapter, AdapterError> {
+    simulated::SimulatedAdapter::from_config(name, config)
+        .map(|adapter| Box::new(adapter.with_seed(seed)) as DynBridgeAdapter)
+        .map_err(|err| AdapterError::Config(format!("{:#}", err)))
+}
+
+fn build_adapter(name: &str, context: AdapterContext) -> Result<DynBridgeAdapter> {
+    if let Some(factory) = factory::registered(name) {
+        return factory(context);
+    }
     match name.to_lowercase().as_str() {
         "stargate" => {
-            return Some(Box::new(stargate::StargateAdapter::new()));
+            Ok(Box::new(stargate::StargateAdapter::new(context)?))
         }
         "wormhole" => {
-            return Some(Box::new(wormhole::WormholeAdapter::new()));
+            Ok(Box::new(wormhole::WormholeAdapter::new(context)?))
+        }
+        "across" => {
+            Ok(Box::new(across::AcrossAdapter::new(context)?))
+        }
+        "hop" => {
+            Ok(Box::new(hop::HopAdapter::new(context)?))
+        }
+        "synapse" => {
+            Ok(Box::new(synapse::SynapseAdapter::new(context)?))
+        }
+        "lifi" => {
+            Ok(Box::new(lifi::LiFiAdapter::new(context)?))
+        }
+        "celer" => {
+            Ok(Box::new(celer::CelerAdapter::new(context)?))
+        }
+        "simulated" => {
+            Ok(Box::new(simulated::SimulatedAdapter::from_config(name, &context.config)?))
+        }
+        #[cfg(any(test, feature = "mock"))]
+        "mock" => {
+            Ok(Box::new(mock::MockAdapter::from_context(name, &context)?))
         }
         _ => {
-            return None;
+            Err(anyhow!("no adapter available for bridge `{}`", name))
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fx::CurrencyId;
+
+    #[test]
+    fn bridge_edge_round_trips_through_json() {
+        let full = BridgeEdge {
+            from: "ethereum".to_string(),
+            to: "polygon".to_string(),
+            cost: 0.6,
+            speed: 180.0,
+            liquidity: 1000.0,
+            risk: 1.0,
+            via: Some("stargate".to_string()),
+            bridge: "lifi".to_string(),
+            estimated_output: 999.4,
+            gas_estimate: Some(Money::new(9.1, CurrencyId::fiat("USD"))),
+            fee_components: vec![FeeComponent {
+                name: "relayer".to_string(),
+                amount: Money::new(0.6, CurrencyId::token("ethereum", "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48")),
+            }],
+            quoted_at: 1717442435,
+            valid_until: Some(1717442495),
+            min_amount: Some(1.0),
+            max_amount: Some(75000.0),
+            liquidity_unknown: true,
+            cost_in_source: Some(0.6),
+        };
+        let json = serde_json::to_string(&full).unwrap();
+        assert_eq!(serde_json::from_str::<BridgeEdge>(&json).unwrap(), full);
+
+        let minimal = BridgeEdge {
+            from: "ethereum".to_string(),
+            to: "polygon".to_string(),
+            cost: 0.6,
+            speed: 180.0,
+            liquidity: 1000.0,
+            risk: 1.0,
+            ..BridgeEdge::default()
+        };
+        let json = serde_json::to_string(&minimal).unwrap();
+        assert_eq!(serde_json::from_str::<BridgeEdge>(&json).unwrap(), minimal);
+
+        // Edges cached before the richer fields existed still load
+        let legacy = r#"{"from":"ethereum","to":"polygon","cost":0.6,"speed":180.0,"liquidity":1000.0,"risk":1.0}"#;
+        assert_eq!(serde_json::from_str::<BridgeEdge>(legacy).unwrap(), minimal);
+    }
+
+    #[tokio::test]
+    async fn adapters_send_configured_headers_through_their_client() {
+        use wiremock::{Mock, MockServer, ResponseTemplate, matchers::{header, method, path}};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/chains"))
+            .and(header("x-api-key", "infra-key"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"chains":[]}"#))
+            .mount(&server)
+            .await;
+
+        let config: BridgeConfig = toml::from_str(&format!(r#"
+            base_url = "{}"
+            chains = ["ethereum"]
+
+            [extra.headers]
+            x-api-key = "infra-key"
+        "#, server.uri())).unwrap();
+
+        let adapter = create_adapter("stargate", &config).unwrap();
+        assert!(adapter.health_check().await.unwrap().reachable);
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn invalid_proxy_fails_construction() {
+        let config: BridgeConfig = toml::from_str(r#"
+            base_url = "https://example.test"
+            chains = ["ethereum"]
+
+            [extra]
+            proxy = "not a proxy url"
+        "#).unwrap();
+
+        let Err(AdapterError::Config(message)) = create_adapter("stargate", &config) else {
+            panic!("expected a config error");
+        };
+        assert!(message.starts_with("bridges.stargate.extra.proxy is not a valid proxy URL"), "{}", message);
+    }
+}
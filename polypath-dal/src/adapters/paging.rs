@@ -0,0 +1,86 @@
+// Listings some APIs return a page at a time, each page naming the cursor of the next
+
+use std::future::Future;
+
+use super::AdapterError;
+
+// Most pages read from one listing; a cursor still left after that many is dropped
+pub const MAX_LISTING_PAGES: usize = 20;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    // None, or an empty cursor, on the last page
+    pub next: Option<String>,
+}
+
+// Reads pages from `fetch_page`, first with no cursor then with each page's next cursor, until a
+// page has none or `max_pages` have been read. A failed page fails the whole listing.
+pub async fn paginate<T, F, Fut>(max_pages: usize, mut fetch_page: F) -> Result<Vec<T>, AdapterError>
+where
+    F: FnMut(Option<String>) -> Fut,
+    Fut: Future<Output = Result<Page<T>, AdapterError>>,
+{
+    let mut items = Vec::new();
+    let mut cursor = None;
+    for _ in 0..max_pages.max(1) {
+        let page = fetch_page(cursor.take()).await?;
+        items.extend(page.items);
+        match page.next.filter(|next| !next.is_empty()) {
+            Some(next) => cursor = Some(next),
+            None => break,
+        }
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    // Pages of two items, `pages` of them, with cursors "page-1", "page-2", ...
+    fn listing(pages: usize, cursors: &Mutex<Vec<Option<String>>>, cursor: Option<String>) -> Result<Page<usize>, AdapterError> {
+        cursors.lock().unwrap().push(cursor.clone());
+        let page = cursor.map_or(0, |cursor| cursor.trim_start_matches("page-").parse().unwrap());
+        Ok(Page {
+            items: vec![page * 2, page * 2 + 1],
+            next: (page + 1 < pages).then(|| format!("page-{}", page + 1)),
+        })
+    }
+
+    #[tokio::test]
+    async fn follows_cursors_until_the_last_page() {
+        let cursors = Mutex::new(Vec::new());
+        let items = paginate(MAX_LISTING_PAGES, |cursor| async { listing(3, &cursors, cursor) }).await.unwrap();
+        assert_eq!(items, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(cursors.into_inner().unwrap(), vec![None, Some("page-1".to_string()), Some("page-2".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn stops_at_the_page_cap() {
+        let cursors = Mutex::new(Vec::new());
+        let items = paginate(4, |cursor| async { listing(usize::MAX, &cursors, cursor) }).await.unwrap();
+        assert_eq!(items, (0..8).collect::<Vec<_>>());
+        assert_eq!(cursors.into_inner().unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn an_empty_cursor_ends_the_listing_and_errors_fail_it() {
+        let items = paginate(MAX_LISTING_PAGES, |_| async { Ok(Page { items: vec![1], next: Some(String::new()) }) }).await.unwrap();
+        assert_eq!(items, vec![1]);
+
+        let calls = Mutex::new(0);
+        let result: Result<Vec<u8>, _> = paginate(MAX_LISTING_PAGES, |_| async {
+            let mut calls = calls.lock().unwrap();
+            *calls += 1;
+            if *calls == 2 {
+                Err(AdapterError::missing("chains"))
+            } else {
+                Ok(Page { items: vec![1], next: Some("more".to_string()) })
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap_err(), AdapterError::missing("chains"));
+    }
+}
@@ -0,0 +1,116 @@
+use std::time::Duration;
+use polypathroute_core::{BridgeConfig, Pair, RefreshPriority};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// A route an adapter can quote. Tokens are addresses on their respective chains.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SupportedPair {
+    pub src_chain: String,
+    pub dst_chain: String,
+    pub src_token: String,
+    pub dst_token: String,
+    pub min_amount: Option<f64>,
+    pub max_amount: Option<f64>,
+    // Source token symbol, for APIs that quote by symbol rather than address
+    #[serde(default)]
+    pub token_symbol: Option<String>,
+    // How often refreshes quote the pair, see RefreshScheduler
+    #[serde(default)]
+    pub priority: RefreshPriority,
+    // Overrides the priority's cadence
+    #[serde(default)]
+    pub refresh_interval: Option<Duration>,
+}
+
+impl SupportedPair {
+    // Chain keys and addresses are compared case-insensitively, checksummed and
+    // lowercase EVM addresses are both common in configs.
+    pub fn matches(&self, src_chain: &str, dst_chain: &str, src_token: &str, dst_token: &str) -> bool {
+        self.src_chain.eq_ignore_ascii_case(src_chain)
+            && self.dst_chain.eq_ignore_ascii_case(dst_chain)
+            && self.src_token.eq_ignore_ascii_case(src_token)
+            && self.dst_token.eq_ignore_ascii_case(dst_token)
+    }
+
+    pub(crate) fn same_route(&self, other: &SupportedPair) -> bool {
+        self.matches(&other.src_chain, &other.dst_chain, &other.src_token, &other.dst_token)
+    }
+}
+
+impl From<&Pair> for SupportedPair {
+    fn from(pair: &Pair) -> Self {
+        Self {
+            src_chain: pair.source_chain.clone(),
+            dst_chain: pair.destination_chain.clone(),
+            src_token: pair.source_address.clone(),
+            dst_token: pair.destination_address.clone(),
+            min_amount: None,
+            max_amount: None,
+            token_symbol: Some(pair.source_token_name.clone()),
+            priority: pair.priority,
+            refresh_interval: pair.refresh_interval,
+        }
+    }
+}
+
+// Configured pairs for a bridge, with duplicate entries dropped.
+pub fn pairs_from_config(config: &BridgeConfig) -> Vec<SupportedPair> {
+    let mut pairs = Vec::new();
+    for pair in config.pairs.iter().flatten() {
+        merge_pair(&mut pairs, SupportedPair::from(pair));
+    }
+    pairs
+}
+
+// Adds the pair unless the same route is already listed. Returns true when added.
+pub(crate) fn merge_pair(pairs: &mut Vec<SupportedPair>, pair: SupportedPair) -> bool {
+    if pairs.iter().any(|existing| existing.same_route(&pair)) {
+        return false;
+    }
+    pairs.push(pair);
+    true
+}
+
+// Builds pairs from a token listing of the shape
+// {"tokens":[{"chainKey", "address", "symbol", "isBridgeable"}]}:
+// every bridgeable token is paired with same-symbol tokens on the other allowed chains.
+pub(crate) fn pairs_from_token_listing(listing: &Value, chains: &[String]) -> Vec<SupportedPair> {
+    let tokens: Vec<(&str, &str, &str)> = listing
+        .get("tokens")
+        .and_then(|tokens| tokens.as_array())
+        .map(|tokens| {
+            tokens.iter()
+                .filter(|token| token.get("isBridgeable").and_then(|v| v.as_bool()).unwrap_or(false))
+                .filter_map(|token| {
+                    let chain = token.get("chainKey")?.as_str()?;
+                    let address = token.get("address")?.as_str()?;
+                    let symbol = token.get("symbol")?.as_str()?;
+                    Some((chain, address, symbol))
+                })
+                .filter(|(chain, _, _)| chains.is_empty() || chains.iter().any(|c| c.eq_ignore_ascii_case(chain)))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut pairs = Vec::new();
+    for (src_chain, src_token, src_symbol) in &tokens {
+        for (dst_chain, dst_token, dst_symbol) in &tokens {
+            if src_chain == dst_chain || src_symbol != dst_symbol {
+                continue;
+            }
+            merge_pair(&mut pairs, SupportedPair {
+                src_chain: src_chain.to_string(),
+                dst_chain: dst_chain.to_string(),
+                src_token: src_token.to_string(),
+                dst_token: dst_token.to_string(),
+                min_amount: None,
+                max_amount: None,
+                token_symbol: Some(src_symbol.to_string()),
+                priority: RefreshPriority::default(),
+                refresh_interval: None,
+            });
+        }
+    }
+    pairs
+}
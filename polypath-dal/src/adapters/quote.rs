@@ -0,0 +1,244 @@
+use serde::{Deserialize, Serialize};
+use anyhow::{Result, anyhow};
+use polypathroute_core::Amount;
+use super::{AdapterError, TokenDecimals, decimals::is_decimal};
+
+// Everything an adapter needs to price a single transfer.
+// Built through QuoteRequest::builder() so fields are always set by name.
+// Amounts are in human units ("1.5" USDC); adapters convert to raw units for the API.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct QuoteRequest {
+    pub src_chain: String,
+    pub dst_chain: String,
+    pub src_token: String,
+    pub dst_token: String,
+    pub src_amount: String,
+    pub dst_amount_min: String,
+    pub src_address: String,
+    pub dst_address: String,
+}
+
+impl QuoteRequest {
+    pub fn builder() -> QuoteRequestBuilder {
+        QuoteRequestBuilder::default()
+    }
+
+    // Same route at another source amount, for depth probes. The slippage bound is dropped
+    // since it was set for the original amount.
+    pub fn with_src_amount(&self, amount: f64) -> Result<QuoteRequest, AdapterError> {
+        let formatted = amount.to_string();
+        if !amount.is_finite() || !is_decimal(&formatted) {
+            return Err(AdapterError::Config(format!("`{}` is not a valid source amount", amount)));
+        }
+        Ok(QuoteRequest {
+            src_amount: formatted,
+            dst_amount_min: "0".to_string(),
+            ..self.clone()
+        })
+    }
+
+    // Same route for exactly `amount`, as with_src_amount
+    pub fn with_amount(&self, amount: Amount) -> QuoteRequest {
+        QuoteRequest {
+            src_amount: amount.to_string(),
+            dst_amount_min: "0".to_string(),
+            ..self.clone()
+        }
+    }
+
+    // The source amount in base units of the source token
+    pub fn src_amount_in(&self, decimals: &TokenDecimals) -> Result<Amount, AdapterError> {
+        decimals.amount(&self.src_chain, &self.src_token, &self.src_amount)
+    }
+
+    // Identifies requests that should get the same quote. Chain keys and tokens are
+    // case-folded and amounts stripped of leading and trailing zeros; wallet addresses are
+    // left out since they don't change the price.
+    pub fn cache_key(&self) -> String {
+        fn amount(value: &str) -> String {
+            let value = value.trim();
+            let (int, frac) = value.split_once('.').unwrap_or((value, ""));
+            let int = match int.trim_start_matches('0') {
+                "" => "0",
+                int => int,
+            };
+            match frac.trim_end_matches('0') {
+                "" => int.to_string(),
+                frac => format!("{}.{}", int, frac),
+            }
+        }
+
+        format!(
+            "{}:{}:{}:{}:{}:{}",
+            self.src_chain.trim().to_lowercase(),
+            self.dst_chain.trim().to_lowercase(),
+            self.src_token.trim().to_lowercase(),
+            self.dst_token.trim().to_lowercase(),
+            amount(&self.src_amount),
+            amount(&self.dst_amount_min),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct QuoteRequestBuilder {
+    src_chain: Option<String>,
+    dst_chain: Option<String>,
+    src_token: Option<String>,
+    dst_token: Option<String>,
+    src_amount: Option<String>,
+    dst_amount_min: Option<String>,
+    src_address: Option<String>,
+    dst_address: Option<String>,
+}
+
+impl QuoteRequestBuilder {
+    pub fn src_chain(mut self, chain: impl Into<String>) -> Self {
+        self.src_chain = Some(chain.into());
+        self
+    }
+
+    pub fn dst_chain(mut self, chain: impl Into<String>) -> Self {
+        self.dst_chain = Some(chain.into());
+        self
+    }
+
+    pub fn src_token(mut self, token: impl Into<String>) -> Self {
+        self.src_token = Some(token.into());
+        self
+    }
+
+    pub fn dst_token(mut self, token: impl Into<String>) -> Self {
+        self.dst_token = Some(token.into());
+        self
+    }
+
+    pub fn src_amount(mut self, amount: impl Into<String>) -> Self {
+        self.src_amount = Some(amount.into());
+        self
+    }
+
+    pub fn dst_amount_min(mut self, amount: impl Into<String>) -> Self {
+        self.dst_amount_min = Some(amount.into());
+        self
+    }
+
+    pub fn src_address(mut self, address: impl Into<String>) -> Self {
+        self.src_address = Some(address.into());
+        self
+    }
+
+    pub fn dst_address(mut self, address: impl Into<String>) -> Self {
+        self.dst_address = Some(address.into());
+        self
+    }
+
+    // Sets both addresses, the common case of bridging to your own wallet
+    pub fn wallet(self, address: impl Into<String>) -> Self {
+        let address = address.into();
+        self.src_address(address.clone()).dst_address(address)
+    }
+
+    // dst_amount_min defaults to "0" (no slippage bound) and dst_address to src_address.
+    pub fn build(self) -> Result<QuoteRequest> {
+        fn required(value: Option<String>, field: &str) -> Result<String> {
+            value
+                .filter(|v| !v.is_empty())
+                .ok_or_else(|| anyhow!("QuoteRequest is missing `{}`", field))
+        }
+
+        fn amount(value: String, field: &str) -> Result<String> {
+            if is_decimal(value.trim()) {
+                Ok(value.trim().to_string())
+            } else {
+                Err(anyhow!("QuoteRequest `{}` must be a decimal amount, got `{}`", field, value))
+            }
+        }
+
+        let src_address = required(self.src_address, "src_address")?;
+        Ok(QuoteRequest {
+            src_chain: required(self.src_chain, "src_chain")?,
+            dst_chain: required(self.dst_chain, "dst_chain")?,
+            src_token: required(self.src_token, "src_token")?,
+            dst_token: required(self.dst_token, "dst_token")?,
+            src_amount: amount(required(self.src_amount, "src_amount")?, "src_amount")?,
+            dst_amount_min: amount(self.dst_amount_min.unwrap_or_else(|| "0".to_string()), "dst_amount_min")?,
+            dst_address: self.dst_address.unwrap_or_else(|| src_address.clone()),
+            src_address,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder_fills_defaults() {
+        let request = QuoteRequest::builder()
+            .src_chain("ethereum")
+            .dst_chain("polygon")
+            .src_token("0xa0b8")
+            .dst_token("0x3c49")
+            .src_amount("1000000")
+            .src_address("0xca69")
+            .build()
+            .unwrap();
+
+        assert_eq!(request.dst_amount_min, "0");
+        assert_eq!(request.dst_address, "0xca69");
+    }
+
+    #[test]
+    fn builder_rejects_missing_fields() {
+        let err = QuoteRequest::builder()
+            .src_chain("ethereum")
+            .dst_chain("polygon")
+            .src_token("0xa0b8")
+            .src_amount("1000000")
+            .wallet("0xca69")
+            .build()
+            .unwrap_err();
+
+        assert!(err.to_string().contains("dst_token"));
+
+        let err = QuoteRequest::builder()
+            .src_chain("ethereum")
+            .dst_chain("polygon")
+            .src_token("0xa0b8")
+            .dst_token("0x3c49")
+            .src_amount("1e6")
+            .wallet("0xca69")
+            .build()
+            .unwrap_err();
+        assert!(err.to_string().contains("decimal amount"));
+    }
+
+    #[test]
+    fn cache_key_ignores_case_padding_and_wallets() {
+        let request = |src_chain: &str, src_token: &str, amount: &str, wallet: &str| {
+            QuoteRequest::builder()
+                .src_chain(src_chain)
+                .dst_chain("polygon")
+                .src_token(src_token)
+                .dst_token("0x3c49")
+                .src_amount(amount)
+                .wallet(wallet)
+                .build()
+                .unwrap()
+        };
+
+        assert_eq!(
+            request("Ethereum", "0xA0B8", "001000", "0xaaaa").cache_key(),
+            request("ethereum", "0xa0b8", "1000", "0xbbbb").cache_key()
+        );
+        assert_eq!(
+            request("ethereum", "0xa0b8", "1.50", "0xaaaa").cache_key(),
+            request("ethereum", "0xa0b8", "01.5", "0xaaaa").cache_key()
+        );
+        assert_ne!(
+            request("ethereum", "0xa0b8", "1000", "0xaaaa").cache_key(),
+            request("ethereum", "0xa0b8", "2000", "0xaaaa").cache_key()
+        );
+    }
+}
@@ -0,0 +1,175 @@
+use std::{sync::Mutex, time::{Duration, Instant}};
+use anyhow::{Result, anyhow};
+use polypathroute_core::BridgeConfig;
+
+// Token bucket shared by every request an adapter makes.
+// Callers over the limit wait for a token instead of failing.
+#[derive(Debug)]
+pub struct RateLimiter {
+    requests_per_second: f64,
+    burst: f64,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    // May go negative: each waiter reserves its token up front so concurrent callers queue fairly
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_second: f64, burst: u32) -> Self {
+        let burst = burst.max(1) as f64;
+        Self {
+            requests_per_second,
+            burst,
+            bucket: Mutex::new(Bucket { tokens: burst, refilled_at: Instant::now() }),
+        }
+    }
+
+    // Reads `requests_per_second` and optional `burst` (default 1) from the bridge's `extra` table.
+    // No limiter is configured when requests_per_second is absent.
+    pub fn from_config(bridge: &str, config: &BridgeConfig) -> Result<Option<Self>> {
+        let Some(extra) = config.extra.as_ref() else {
+            return Ok(None);
+        };
+        let Some(rps) = extra.get("requests_per_second") else {
+            return Ok(None);
+        };
+
+        let rps = rps
+            .as_float()
+            .or_else(|| rps.as_integer().map(|v| v as f64))
+            .filter(|v| v.is_finite() && *v > 0.0)
+            .ok_or_else(|| anyhow!("bridges.{}.extra.requests_per_second must be a positive number", bridge))?;
+        let burst = match extra.get("burst") {
+            Some(value) => value
+                .as_integer()
+                .filter(|v| *v >= 1 && *v <= u32::MAX as i64)
+                .ok_or_else(|| anyhow!("bridges.{}.extra.burst must be a positive integer", bridge))? as u32,
+            None => 1,
+        };
+
+        Ok(Some(Self::new(rps, burst)))
+    }
+
+    pub fn requests_per_second(&self) -> f64 {
+        self.requests_per_second
+    }
+
+    pub fn burst(&self) -> u32 {
+        self.burst as u32
+    }
+
+    // Tokens available right now. Negative while callers are queued.
+    pub fn available_tokens(&self) -> f64 {
+        let mut bucket = self.bucket.lock().unwrap();
+        self.refill(&mut bucket);
+        bucket.tokens
+    }
+
+    // Waits for a token and returns how long that took. A caller dropped while waiting hands its
+    // reserved token back.
+    pub async fn acquire(&self) -> Duration {
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            self.refill(&mut bucket);
+            bucket.tokens -= 1.0;
+            if bucket.tokens >= 0.0 {
+                Duration::ZERO
+            } else {
+                Duration::from_secs_f64(-bucket.tokens / self.requests_per_second)
+            }
+        };
+
+        if !wait.is_zero() {
+            let reservation = Reservation { limiter: self };
+            tokio::time::sleep(wait).await;
+            std::mem::forget(reservation);
+        }
+        wait
+    }
+
+    fn refill(&self, bucket: &mut Bucket) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.requests_per_second).min(self.burst);
+        bucket.refilled_at = now;
+    }
+}
+
+// A token reserved by a waiting `acquire`, returned to the bucket if the wait is cancelled
+struct Reservation<'a> {
+    limiter: &'a RateLimiter,
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        let mut bucket = self.limiter.bucket.lock().unwrap();
+        self.limiter.refill(&mut bucket);
+        bucket.tokens = (bucket.tokens + 1.0).min(self.limiter.burst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn burst_is_available_immediately() {
+        let limiter = RateLimiter::new(1.0, 3);
+        let started = Instant::now();
+        for _ in 0..3 {
+            limiter.acquire().await;
+        }
+
+        assert!(started.elapsed() < Duration::from_millis(100));
+        assert!(limiter.available_tokens() < 1.0);
+    }
+
+    #[tokio::test]
+    async fn waits_for_a_token_once_drained() {
+        let limiter = RateLimiter::new(10.0, 1);
+        let started = Instant::now();
+        for _ in 0..4 {
+            limiter.acquire().await;
+        }
+
+        // 3 tokens refilled at 10/s
+        assert!(started.elapsed() >= Duration::from_millis(280));
+    }
+
+    #[tokio::test]
+    async fn cancelled_waits_return_their_token() {
+        let limiter = RateLimiter::new(1.0, 1);
+        limiter.acquire().await;
+        assert!(tokio::time::timeout(Duration::from_millis(50), limiter.acquire()).await.is_err());
+
+        // Only the first caller's token is spent, so the next one waits under a second
+        assert!(limiter.available_tokens() > -0.5);
+        let waited = limiter.acquire().await;
+        assert!(waited < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn unconfigured_bridges_are_unlimited() {
+        let config: BridgeConfig = toml::from_str(r#"
+            base_url = "https://example.test"
+            chains = ["ethereum"]
+        "#).unwrap();
+        assert!(RateLimiter::from_config("stargate", &config).unwrap().is_none());
+
+        let config: BridgeConfig = toml::from_str(r#"
+            base_url = "https://example.test"
+            chains = ["ethereum"]
+
+            [extra]
+            requests_per_second = 2.5
+            burst = 4
+        "#).unwrap();
+        let limiter = RateLimiter::from_config("stargate", &config).unwrap().unwrap();
+        assert_eq!(limiter.requests_per_second(), 2.5);
+        assert_eq!(limiter.burst(), 4);
+    }
+}
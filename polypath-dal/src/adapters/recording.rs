@@ -0,0 +1,299 @@
+// Recorded upstream responses, so adapter tests can replay what a real API answered instead of
+// hand-copied bodies. A RetryPolicy with a FixtureStore either records every response it hands
+// to the adapter under `<directory>/<adapter>/<key>.json`, or replays those files and never
+// touches the network. Requests sent outside RetryPolicy, e.g. health probes, aren't covered.
+
+use std::{collections::HashMap, path::{Path, PathBuf}};
+use anyhow::{Result, anyhow};
+use polypathroute_core::LoggingManager;
+use reqwest::{Request, Response, header::CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+
+use super::AdapterError;
+
+// Directory to record every adapter's responses into
+pub const RECORD_FIXTURES_ENV: &str = "POLYPATH_RECORD_FIXTURES";
+// Directory to replay every adapter's responses from
+pub const REPLAY_FIXTURES_ENV: &str = "POLYPATH_REPLAY_FIXTURES";
+
+// Query parameters bridge APIs take credentials in, left out of fixtures. Compared
+// case-insensitively and by whole name, so `token_symbol` or `monkey` still keys a fixture.
+const CREDENTIAL_PARAMS: &[&str] =
+    &["apikey", "api_key", "api-key", "key", "access_token", "token", "auth", "secret", "signature"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FixtureMode {
+    Record,
+    Replay,
+}
+
+impl FixtureMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FixtureMode::Record => "record",
+            FixtureMode::Replay => "replay",
+        }
+    }
+}
+
+// One recorded response, with the request it answered for whoever reads the file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub request: String,
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    pub body: String,
+}
+
+// One adapter's recorded responses
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixtureStore {
+    mode: FixtureMode,
+    directory: PathBuf,
+    adapter: String,
+}
+
+impl FixtureStore {
+    pub fn new(mode: FixtureMode, directory: impl Into<PathBuf>, adapter: &str) -> Self {
+        Self { mode, directory: directory.into(), adapter: adapter.to_string() }
+    }
+
+    // Reads an optional `[bridges.<name>.extra.fixtures]` table, `mode = "record"` or
+    // "replay" and `directory`, else POLYPATH_RECORD_FIXTURES or POLYPATH_REPLAY_FIXTURES.
+    // None when neither asks for fixtures.
+    pub fn from_extra(bridge: &str, extra: Option<&HashMap<String, toml::Value>>) -> Result<Option<Self>> {
+        Self::from_sources(bridge, extra, |name| std::env::var(name).ok())
+    }
+
+    fn from_sources(bridge: &str, extra: Option<&HashMap<String, toml::Value>>, env: impl Fn(&str) -> Option<String>) -> Result<Option<Self>> {
+        if let Some(table) = extra.and_then(|extra| extra.get("fixtures")) {
+            let table = table.as_table().ok_or_else(|| anyhow!("extra.fixtures must be a table"))?;
+            let mode = match table.get("mode").and_then(|mode| mode.as_str()) {
+                Some("record") => FixtureMode::Record,
+                Some("replay") => FixtureMode::Replay,
+                _ => return Err(anyhow!("extra.fixtures.mode must be \"record\" or \"replay\"")),
+            };
+            let directory = table
+                .get("directory")
+                .and_then(|directory| directory.as_str())
+                .filter(|directory| !directory.is_empty())
+                .ok_or_else(|| anyhow!("extra.fixtures.directory must be set"))?;
+            return Ok(Some(Self::new(mode, directory, bridge)));
+        }
+
+        let record = env(RECORD_FIXTURES_ENV).filter(|directory| !directory.is_empty());
+        let replay = env(REPLAY_FIXTURES_ENV).filter(|directory| !directory.is_empty());
+        match (record, replay) {
+            (Some(_), Some(_)) => Err(anyhow!("set only one of {} and {}", RECORD_FIXTURES_ENV, REPLAY_FIXTURES_ENV)),
+            (Some(directory), None) => Ok(Some(Self::new(FixtureMode::Record, directory, bridge))),
+            (None, Some(directory)) => Ok(Some(Self::new(FixtureMode::Replay, directory, bridge))),
+            (None, None) => Ok(None),
+        }
+    }
+
+    pub fn mode(&self) -> FixtureMode {
+        self.mode
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    // The request as fixtures know it: method, path, query parameters sorted and without
+    // secrets, and the body with JSON keys sorted. The host is left out, so fixtures recorded
+    // against one base URL replay against another.
+    pub fn describe(request: &Request) -> String {
+        let url = request.url();
+        let mut query: Vec<(String, String)> = url
+            .query_pairs()
+            .filter(|(name, _)| !CREDENTIAL_PARAMS.iter().any(|param| name.eq_ignore_ascii_case(param)))
+            .map(|(name, value)| (name.into_owned(), value.into_owned()))
+            .collect();
+        query.sort();
+        let mut described = format!("{} {}", request.method(), url.path());
+        if !query.is_empty() {
+            let query: Vec<String> = query.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+            described = format!("{}?{}", described, query.join("&"));
+        }
+        if let Some(body) = request.body().and_then(|body| body.as_bytes()).filter(|body| !body.is_empty()) {
+            let body = match serde_json::from_slice::<serde_json::Value>(body) {
+                Ok(json) => json.to_string(),
+                Err(_) => String::from_utf8_lossy(body).into_owned(),
+            };
+            described = format!("{} {}", described, body);
+        }
+        described
+    }
+
+    // FNV-1a of the description, as hex
+    pub fn key(description: &str) -> String {
+        let hash = description.bytes().fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3));
+        format!("{:016x}", hash)
+    }
+
+    pub fn path_for(&self, description: &str) -> PathBuf {
+        self.directory.join(&self.adapter).join(format!("{}.json", Self::key(description)))
+    }
+
+    // The recorded answer to `request`, as if the upstream had sent it
+    pub(crate) fn replay(&self, request: &Request) -> Result<Response, AdapterError> {
+        let description = Self::describe(request);
+        let path = self.path_for(&description);
+        let missing = || AdapterError::MissingFixture { adapter: self.adapter.clone(), request: description.clone(), path: path.clone() };
+        let text = std::fs::read_to_string(&path).map_err(|_| missing())?;
+        let recorded: RecordedResponse = serde_json::from_str(&text)
+            .map_err(|err| AdapterError::Config(format!("fixture {} is unreadable: {}", path.display(), err)))?;
+        Ok(rebuild(&recorded))
+    }
+
+    // Writes `response` out as the answer to the request `description` describes and hands
+    // back an equivalent one. A fixture that can't be written is logged; the adapter still
+    // gets its response.
+    pub(crate) async fn record(&self, description: String, response: Response) -> Result<Response, AdapterError> {
+        let status = response.status().as_u16();
+        let content_type = response.headers().get(CONTENT_TYPE).and_then(|value| value.to_str().ok()).map(str::to_string);
+        let body = String::from_utf8_lossy(&response.bytes().await?).into_owned();
+        let path = self.path_for(&description);
+        let recorded = RecordedResponse { request: description, status, content_type, body };
+        let written = match tokio::fs::create_dir_all(path.parent().unwrap_or(&self.directory)).await {
+            Ok(()) => tokio::fs::write(&path, serde_json::to_string_pretty(&recorded).unwrap_or_default()).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = written {
+            LoggingManager.warn_with("fixture not recorded", &[("adapter", &self.adapter), ("path", &path.display()), ("error", &err)]);
+        }
+        Ok(rebuild(&recorded))
+    }
+}
+
+fn rebuild(recorded: &RecordedResponse) -> Response {
+    let mut response = http::Response::builder().status(recorded.status);
+    if let Some(content_type) = &recorded.content_type {
+        response = response.header(CONTENT_TYPE, content_type);
+    }
+    // Both parts were read from a valid response or fixture, so only a hand-edited status fails
+    let response = response
+        .body(recorded.body.clone())
+        .unwrap_or_else(|_| http::Response::new(recorded.body.clone()));
+    Response::from(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::{BridgeAdapter, BridgeEdge, QuoteRequest, across::AcrossAdapter};
+    use wiremock::{Mock, MockServer, ResponseTemplate, matchers::{method, path}};
+
+    const FEES: &str = include_str!("../../fixtures/across/suggested_fees.json");
+
+    fn scratch(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("polypath-dal-fixtures-{}-{}", name, std::process::id()))
+    }
+
+    fn across(base_url: &str, mode: &str, directory: &Path) -> AcrossAdapter {
+        let config = format!(r#"
+            base_url = "{}"
+            chains = ["arbitrum", "base"]
+
+            [[pairs]]
+            source_chain = "arbitrum"
+            source_token_name = "USDC"
+            destination_chain = "base"
+            destination_token_name = "USDC"
+            source_address = "0xaf88d065e77c8cC2239327C5EDb3A432268e5831"
+            destination_address = "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913"
+
+            [extra]
+            api_key = "not-recorded"
+            fixtures = {{ mode = "{}", directory = "{}" }}
+        "#, base_url, mode, directory.display());
+        AcrossAdapter::from_config(&toml::from_str(&config).unwrap()).unwrap()
+    }
+
+    fn request(amount: &str) -> QuoteRequest {
+        QuoteRequest::builder()
+            .src_chain("arbitrum")
+            .dst_chain("base")
+            .src_token("0xaf88d065e77c8cC2239327C5EDb3A432268e5831")
+            .dst_token("0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913")
+            .src_amount(amount)
+            .wallet("0xca699201b15ccef3b8c4012e28570cc5500d9f9a")
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn recorded_responses_replay_without_the_network() {
+        let directory = scratch("round-trip");
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/suggested-fees"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(FEES))
+            .mount(&server)
+            .await;
+
+        let live = across(&server.uri(), "record", &directory).fetch_metrics(&request("1")).await.unwrap();
+        let files: Vec<PathBuf> = std::fs::read_dir(directory.join("across")).unwrap().map(|entry| entry.unwrap().path()).collect();
+        assert_eq!(files.len(), 1);
+        let recorded: RecordedResponse = serde_json::from_str(&std::fs::read_to_string(&files[0]).unwrap()).unwrap();
+        assert_eq!(recorded.status, 200);
+        assert!(recorded.request.starts_with("GET /suggested-fees?amount=1000000&destinationChainId=8453"), "{}", recorded.request);
+
+        // Nothing listens at this address any more
+        drop(server);
+        let replayed = across("http://127.0.0.1:9", "replay", &directory).fetch_metrics(&request("1")).await.unwrap();
+        // Quotes are stamped when they're parsed, which may be a second later
+        assert_eq!(BridgeEdge { quoted_at: live.quoted_at, valid_until: live.valid_until, ..replayed }, live);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn unrecorded_requests_say_what_is_missing() {
+        let directory = scratch("miss");
+        let err = across("http://127.0.0.1:9", "replay", &directory).fetch_metrics(&request("2")).await.unwrap_err();
+        let AdapterError::MissingFixture { adapter, request, path } = &err else {
+            panic!("{:?}", err);
+        };
+        assert_eq!(adapter, "across");
+        assert!(request.starts_with("GET /suggested-fees?amount=2000000&"), "{}", request);
+        assert!(path.starts_with(directory.join("across")));
+        assert!(err.to_string().starts_with("no fixture for request GET /suggested-fees?amount=2000000&"), "{}", err);
+    }
+
+    #[test]
+    fn requests_are_described_without_secrets_or_key_order() {
+        let client = reqwest::Client::new();
+        let described = |builder: reqwest::RequestBuilder| FixtureStore::describe(&builder.build().unwrap());
+        let first = described(client.post("https://a.test/quote?b=2&apiKey=s3cret&a=1").body(r#"{"to":"base","from":"arbitrum"}"#));
+        let second = described(client.post("http://b.test/quote?a=1&b=2").body(r#"{"from":"arbitrum","to":"base"}"#));
+        assert_eq!(first, r#"POST /quote?a=1&b=2 {"from":"arbitrum","to":"base"}"#);
+        assert_eq!(first, second);
+        assert_eq!(FixtureStore::key(&first), FixtureStore::key(&second));
+    }
+
+    #[test]
+    fn fixtures_come_from_the_bridge_config_or_the_environment() {
+        let env = |record: Option<&str>, replay: Option<&str>| {
+            let (record, replay) = (record.map(str::to_string), replay.map(str::to_string));
+            move |name: &str| match name {
+                RECORD_FIXTURES_ENV => record.clone(),
+                REPLAY_FIXTURES_ENV => replay.clone(),
+                _ => None,
+            }
+        };
+        assert_eq!(FixtureStore::from_sources("across", None, env(None, None)).unwrap(), None);
+        assert_eq!(
+            FixtureStore::from_sources("across", None, env(Some("/tmp/recorded"), None)).unwrap(),
+            Some(FixtureStore::new(FixtureMode::Record, "/tmp/recorded", "across"))
+        );
+        assert!(FixtureStore::from_sources("across", None, env(Some("/a"), Some("/b"))).is_err());
+
+        let extra: HashMap<String, toml::Value> = toml::from_str("fixtures = { mode = \"replay\", directory = \"fixtures/recorded\" }").unwrap();
+        let store = FixtureStore::from_sources("across", Some(&extra), env(Some("/tmp/recorded"), None)).unwrap().unwrap();
+        assert_eq!((store.mode(), store.directory()), (FixtureMode::Replay, Path::new("fixtures/recorded")));
+
+        let extra: HashMap<String, toml::Value> = toml::from_str("fixtures = { mode = \"rewind\", directory = \"x\" }").unwrap();
+        assert!(FixtureStore::from_sources("across", Some(&extra), env(None, None)).unwrap_err().to_string().contains("extra.fixtures.mode"));
+    }
+}
@@ -0,0 +1,348 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use reqwest::{RequestBuilder, Response, StatusCode, header::RETRY_AFTER};
+use anyhow::{Result, anyhow};
+use super::{AdapterError, FixtureMode, FixtureStore, MetricsRecorder, RateLimiter};
+
+// Failure classes a RetryPolicy can retry. 4xx other than 429 is never retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusClass {
+    Connect,
+    Timeout,
+    TooManyRequests,
+    ServerError,
+}
+
+impl StatusClass {
+    fn parse(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "connect" => Ok(StatusClass::Connect),
+            "timeout" => Ok(StatusClass::Timeout),
+            "429" | "too-many-requests" => Ok(StatusClass::TooManyRequests),
+            "5xx" | "server-error" => Ok(StatusClass::ServerError),
+            other => Err(anyhow!("unknown retry class `{}`", other)),
+        }
+    }
+
+    fn of_status(status: StatusCode) -> Option<Self> {
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            Some(StatusClass::TooManyRequests)
+        } else if status.is_server_error() {
+            Some(StatusClass::ServerError)
+        } else {
+            None
+        }
+    }
+
+    fn of_error(err: &reqwest::Error) -> Option<Self> {
+        if err.is_timeout() {
+            Some(StatusClass::Timeout)
+        } else if err.is_connect() {
+            Some(StatusClass::Connect)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub retry_on: Vec<StatusClass>,
+    // Where retries and rate-limit waits are reported, see with_telemetry
+    telemetry: Option<Arc<MetricsRecorder>>,
+    // Where responses are recorded to or replayed from, see with_fixtures
+    fixtures: Option<Arc<FixtureStore>>,
+}
+
+// Two policies are equal when they retry the same way, whoever they report to
+impl PartialEq for RetryPolicy {
+    fn eq(&self, other: &Self) -> bool {
+        self.max_attempts == other.max_attempts
+            && self.base_delay == other.base_delay
+            && self.max_delay == other.max_delay
+            && self.retry_on == other.retry_on
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(5),
+            retry_on: vec![
+                StatusClass::Connect,
+                StatusClass::Timeout,
+                StatusClass::TooManyRequests,
+                StatusClass::ServerError,
+            ],
+            telemetry: None,
+            fixtures: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    // A single attempt, for callers that handle failures themselves
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    // Reads an optional `[bridges.<name>.extra.retry]` table:
+    // max_attempts, base_delay_ms, max_delay_ms and retry_on = ["connect", "timeout", "429", "5xx"].
+    // Missing keys keep their defaults.
+    pub fn from_extra(extra: Option<&HashMap<String, toml::Value>>) -> Result<Self> {
+        let mut policy = Self::default();
+        let Some(table) = extra.and_then(|extra| extra.get("retry")) else {
+            return Ok(policy);
+        };
+        let table = table
+            .as_table()
+            .ok_or_else(|| anyhow!("extra.retry must be a table"))?;
+
+        let millis = |key: &str| -> Result<Option<Duration>> {
+            match table.get(key) {
+                Some(value) => value
+                    .as_integer()
+                    .filter(|v| *v >= 0)
+                    .map(|v| Some(Duration::from_millis(v as u64)))
+                    .ok_or_else(|| anyhow!("extra.retry.{} must be a non-negative integer", key)),
+                None => Ok(None),
+            }
+        };
+
+        if let Some(value) = table.get("max_attempts") {
+            policy.max_attempts = value
+                .as_integer()
+                .filter(|v| *v >= 1)
+                .ok_or_else(|| anyhow!("extra.retry.max_attempts must be at least 1"))? as u32;
+        }
+        if let Some(delay) = millis("base_delay_ms")? {
+            policy.base_delay = delay;
+        }
+        if let Some(delay) = millis("max_delay_ms")? {
+            policy.max_delay = delay;
+        }
+        if let Some(value) = table.get("retry_on") {
+            policy.retry_on = value
+                .as_array()
+                .ok_or_else(|| anyhow!("extra.retry.retry_on must be an array"))?
+                .iter()
+                .map(|class| {
+                    class
+                        .as_str()
+                        .ok_or_else(|| anyhow!("extra.retry.retry_on entries must be strings"))
+                        .and_then(StatusClass::parse)
+                })
+                .collect::<Result<_>>()?;
+        }
+
+        Ok(policy)
+    }
+
+    pub fn with_telemetry(mut self, telemetry: Arc<MetricsRecorder>) -> Self {
+        self.telemetry = Some(telemetry);
+        self
+    }
+
+    // Records every response handed back to the adapter, or answers from the recordings
+    // without sending anything
+    pub fn with_fixtures(mut self, fixtures: FixtureStore) -> Self {
+        self.fixtures = Some(Arc::new(fixtures));
+        self
+    }
+
+    // Exponential backoff with jitter: a random delay in [d/2, d] where d = base * 2^(attempt - 1), capped at max_delay.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self.base_delay.saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+        let capped = exp.min(self.max_delay);
+        let half = capped / 2;
+        half + half.mul_f64(fastrand::f64())
+    }
+
+    fn should_retry(&self, class: Option<StatusClass>, attempt: u32) -> bool {
+        attempt < self.max_attempts && class.is_some_and(|class| self.retry_on.contains(&class))
+    }
+
+    // Sends the request built by `build` until it succeeds or the policy gives up.
+    // Non-success responses that are not retried surface as AdapterError::Upstream,
+    // or AdapterError::RateLimited for 429. Timeouts that exhaust the policy surface
+    // as AdapterError::Timeout. Every attempt, retries included, first takes a token from `limiter`.
+    pub async fn send<F>(&self, limiter: Option<&RateLimiter>, build: F) -> Result<Response, AdapterError>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        self.execute(limiter, build, false).await
+    }
+
+    // Like send, but 4xx responses other than 429 are handed back so APIs that
+    // explain rejections in the body can be parsed by the adapter.
+    pub async fn send_with_client_errors<F>(&self, limiter: Option<&RateLimiter>, build: F) -> Result<Response, AdapterError>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        self.execute(limiter, build, true).await
+    }
+
+    async fn execute<F>(&self, limiter: Option<&RateLimiter>, build: F, accept_client_errors: bool) -> Result<Response, AdapterError>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        if let Some(fixtures) = self.fixtures.as_deref().filter(|fixtures| fixtures.mode() == FixtureMode::Replay) {
+            let request = build().build().map_err(|err| AdapterError::Config(format!("invalid request: {}", err)))?;
+            return fixtures.replay(&request);
+        }
+        let recording = self.fixtures.as_deref().filter(|fixtures| fixtures.mode() == FixtureMode::Record);
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            if let Some(limiter) = limiter
+                && !limiter.acquire().await.is_zero()
+                && let Some(telemetry) = &self.telemetry
+            {
+                telemetry.record_rate_limit_wait();
+            }
+            if attempt > 1
+                && let Some(telemetry) = &self.telemetry
+            {
+                telemetry.record_retry();
+            }
+            let request = build();
+            let description = recording.and_then(|_| request.try_clone()?.build().ok()).map(|request| FixtureStore::describe(&request));
+            let handed_back = |response: Response| async move {
+                match (recording, description) {
+                    (Some(fixtures), Some(description)) => fixtures.record(description, response).await,
+                    _ => Ok(response),
+                }
+            };
+            match request.send().await {
+                Ok(response) if response.status().is_success() => return handed_back(response).await,
+                Ok(response) if accept_client_errors
+                    && response.status().is_client_error()
+                    && response.status() != StatusCode::TOO_MANY_REQUESTS => return handed_back(response).await,
+                Ok(response) => {
+                    let status = response.status();
+                    let delay = retry_after(&response);
+                    if !self.should_retry(StatusClass::of_status(status), attempt) {
+                        if status == StatusCode::TOO_MANY_REQUESTS {
+                            return Err(AdapterError::RateLimited { retry_after: delay });
+                        }
+                        let body = response.text().await.unwrap_or_default();
+                        return Err(AdapterError::upstream(status.as_u16(), &body));
+                    }
+                    tokio::time::sleep(delay.unwrap_or_else(|| self.backoff(attempt))).await;
+                }
+                Err(err) => {
+                    let class = StatusClass::of_error(&err);
+                    if !self.should_retry(class, attempt) {
+                        if class == Some(StatusClass::Timeout) {
+                            return Err(AdapterError::Timeout { attempts: attempt });
+                        }
+                        return Err(AdapterError::Network(format!("{} (after {} attempt(s))", err, attempt)));
+                    }
+                    tokio::time::sleep(self.backoff(attempt)).await;
+                }
+            }
+        }
+    }
+}
+
+fn retry_after(response: &Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{Mock, MockServer, ResponseTemplate, matchers::method};
+
+    fn fast_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+            ..RetryPolicy::default()
+        }
+    }
+
+    #[test]
+    fn backoff_is_capped_and_jittered() {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(300),
+            ..RetryPolicy::default()
+        };
+        for _ in 0..20 {
+            let first = policy.backoff(1);
+            assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+            let late = policy.backoff(10);
+            assert!(late >= Duration::from_millis(150) && late <= Duration::from_millis(300));
+        }
+    }
+
+    #[test]
+    fn reads_policy_from_extra() {
+        let extra: HashMap<String, toml::Value> = toml::from_str(r#"
+            [retry]
+            max_attempts = 5
+            base_delay_ms = 50
+            retry_on = ["429"]
+        "#).unwrap();
+
+        let policy = RetryPolicy::from_extra(Some(&extra)).unwrap();
+        assert_eq!(policy.max_attempts, 5);
+        assert_eq!(policy.base_delay, Duration::from_millis(50));
+        assert_eq!(policy.max_delay, RetryPolicy::default().max_delay);
+        assert_eq!(policy.retry_on, vec![StatusClass::TooManyRequests]);
+        assert_eq!(RetryPolicy::from_extra(None).unwrap(), RetryPolicy::default());
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_client_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(400))
+            .mount(&server)
+            .await;
+
+        let client = reqwest::Client::new();
+        let err = fast_policy(3).send(None, || client.get(server.uri())).await.unwrap_err();
+
+        assert_eq!(err, AdapterError::Upstream { status: 400, body_snippet: String::new() });
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "0"))
+            .mount(&server)
+            .await;
+
+        let client = reqwest::Client::new();
+        let telemetry = Arc::new(MetricsRecorder::new());
+        let err = fast_policy(4)
+            .with_telemetry(Arc::clone(&telemetry))
+            .send(None, || client.get(server.uri()))
+            .await
+            .unwrap_err();
+
+        assert_eq!(err, AdapterError::RateLimited { retry_after: Some(Duration::ZERO) });
+        assert_eq!(server.received_requests().await.unwrap().len(), 4);
+        assert_eq!(telemetry.snapshot().retries, 3);
+    }
+}
@@ -0,0 +1,219 @@
+use super::{BridgeEdge, QuoteRequest};
+use std::{collections::HashMap, fmt, time::Duration};
+use polypathroute_core::BridgeConfig;
+use anyhow::{Result, anyhow};
+
+// Base risk of each bridge's security model, 0 (safest) to 1. Bridges not listed get DEFAULT_BASE_RISK.
+const BASE_RISK: &[(&str, f64)] = &[
+    ("stargate", 0.2),
+    ("across", 0.2),
+    ("hop", 0.3),
+    ("celer", 0.35),
+    ("synapse", 0.35),
+    ("wormhole", 0.5),
+];
+const DEFAULT_BASE_RISK: f64 = 0.5;
+
+// How a bridge's own network is doing, for bridges that report it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BridgeStatus {
+    pub degraded: bool,
+    // How long a transfer sent now waits behind the ones already in flight
+    pub backlog_estimate: Duration,
+    pub notes: Vec<String>,
+}
+
+// What the model knows about the transfer besides the quote
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RiskContext {
+    // Source amount being bridged, in human units
+    pub amount: Option<f64>,
+    // Left unset by bridges that don't report their status
+    pub bridge_status: Option<BridgeStatus>,
+}
+
+impl RiskContext {
+    pub fn for_request(request: &QuoteRequest) -> Self {
+        Self {
+            amount: request.src_amount.parse().ok(),
+            bridge_status: None,
+        }
+    }
+
+    pub fn with_bridge_status(mut self, bridge_status: Option<BridgeStatus>) -> Self {
+        self.bridge_status = bridge_status;
+        self
+    }
+
+    fn is_degraded(&self) -> bool {
+        self.bridge_status.as_ref().is_some_and(|status| status.degraded)
+    }
+}
+
+// Scores how risky an edge is. Every adapter goes through one so risk is comparable across bridges.
+pub trait RiskModel: fmt::Debug + Send + Sync {
+    fn assess(&self, bridge: &str, edge: &BridgeEdge, ctx: &RiskContext) -> f64;
+
+    // Fills in the risk of an edge quoted for `request`
+    fn score(&self, bridge: &str, request: &QuoteRequest, edge: BridgeEdge) -> BridgeEdge {
+        self.score_with(bridge, &RiskContext::for_request(request), edge)
+    }
+
+    fn score_with(&self, bridge: &str, ctx: &RiskContext, mut edge: BridgeEdge) -> BridgeEdge {
+        edge.risk = self.assess(bridge, &edge, ctx);
+        edge
+    }
+}
+
+// risk = base risk of the bridge moving the funds
+//      + liquidity_weight * share of the available liquidity the transfer takes (capped at 1)
+//      + duration_weight * duration / duration_cap (capped at 1)
+//      + degraded_risk while the bridge reports its network degraded
+#[derive(Debug, Clone, PartialEq)]
+pub struct DefaultRiskModel {
+    pub base_risk: HashMap<String, f64>,
+    pub liquidity_weight: f64,
+    pub duration_weight: f64,
+    // Seconds after which a slower route is not considered any riskier
+    pub duration_cap: f64,
+    pub degraded_risk: f64,
+}
+
+impl Default for DefaultRiskModel {
+    fn default() -> Self {
+        Self {
+            base_risk: BASE_RISK.iter().map(|(bridge, risk)| (bridge.to_string(), *risk)).collect(),
+            liquidity_weight: 0.3,
+            duration_weight: 0.2,
+            duration_cap: 3600.0,
+            degraded_risk: 0.25,
+        }
+    }
+}
+
+impl DefaultRiskModel {
+    // Reads `base_risk`, `risk_liquidity_weight`, `risk_duration_weight`,
+    // `risk_duration_cap_secs` and `risk_degraded` from the bridge's `extra` table
+    pub fn from_config(bridge: &str, config: &BridgeConfig) -> Result<Self> {
+        let mut model = Self::default();
+        let Some(extra) = config.extra.as_ref() else {
+            return Ok(model);
+        };
+
+        let number = |key: &str| -> Result<Option<f64>> {
+            match extra.get(key) {
+                Some(value) => value
+                    .as_float()
+                    .or_else(|| value.as_integer().map(|v| v as f64))
+                    .filter(|v| v.is_finite() && *v >= 0.0)
+                    .map(Some)
+                    .ok_or_else(|| anyhow!("bridges.{}.extra.{} must be a non-negative number", bridge, key)),
+                None => Ok(None),
+            }
+        };
+
+        if let Some(base_risk) = number("base_risk")? {
+            model.base_risk.insert(bridge.to_lowercase(), base_risk);
+        }
+        if let Some(weight) = number("risk_liquidity_weight")? {
+            model.liquidity_weight = weight;
+        }
+        if let Some(weight) = number("risk_duration_weight")? {
+            model.duration_weight = weight;
+        }
+        if let Some(cap) = number("risk_duration_cap_secs")? {
+            if cap == 0.0 {
+                return Err(anyhow!("bridges.{}.extra.risk_duration_cap_secs must be positive", bridge));
+            }
+            model.duration_cap = cap;
+        }
+        if let Some(risk) = number("risk_degraded")? {
+            model.degraded_risk = risk;
+        }
+        Ok(model)
+    }
+
+    pub fn base_risk(&self, bridge: &str) -> f64 {
+        self.base_risk
+            .get(&bridge.to_lowercase())
+            .copied()
+            .unwrap_or(DEFAULT_BASE_RISK)
+    }
+}
+
+impl RiskModel for DefaultRiskModel {
+    fn assess(&self, bridge: &str, edge: &BridgeEdge, ctx: &RiskContext) -> f64 {
+        // Aggregated routes carry the risk of the bridge that actually moves the funds
+        let base = self.base_risk(edge.underlying_bridge(bridge));
+
+        let thinness = match ctx.amount {
+            _ if edge.liquidity <= 0.0 => 1.0,
+            Some(amount) => (amount / edge.liquidity).clamp(0.0, 1.0),
+            None => 0.0,
+        };
+        let duration = (edge.speed.max(0.0) / self.duration_cap).min(1.0);
+
+        let degraded = if ctx.is_degraded() { self.degraded_risk } else { 0.0 };
+
+        base + self.liquidity_weight * thinness + self.duration_weight * duration + degraded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edge(speed: f64, liquidity: f64) -> BridgeEdge {
+        BridgeEdge { speed, liquidity, ..BridgeEdge::default() }
+    }
+
+    fn config(extra: &str) -> BridgeConfig {
+        toml::from_str(&format!("base_url = \"https://bridge.test\"\nchains = [\"ethereum\"]\n{}", extra)).unwrap()
+    }
+
+    #[test]
+    fn same_speed_different_bridges_score_differently() {
+        let model = DefaultRiskModel::default();
+        let ctx = RiskContext { amount: Some(100.0), ..RiskContext::default() };
+        let quote = edge(600.0, 1_000_000.0);
+
+        let stargate = model.assess("stargate", &quote, &ctx);
+        let wormhole = model.assess("wormhole", &quote, &ctx);
+        assert!(stargate < wormhole);
+        assert!((wormhole - stargate - 0.3).abs() < 1e-9);
+
+        // LiFi routed through stargate is scored as stargate
+        let via = BridgeEdge { via: Some("stargate".to_string()), ..quote.clone() };
+        assert_eq!(model.assess("lifi", &via, &ctx), stargate);
+    }
+
+    #[test]
+    fn thin_liquidity_and_slow_routes_add_risk_up_to_a_cap() {
+        let model = DefaultRiskModel::default();
+        let ctx = RiskContext { amount: Some(500.0), ..RiskContext::default() };
+
+        assert_eq!(model.assess("across", &edge(0.0, 1000.0), &ctx), 0.2 + 0.3 * 0.5);
+        assert_eq!(model.assess("across", &edge(0.0, 0.0), &ctx), 0.2 + 0.3);
+        assert_eq!(model.assess("across", &edge(7200.0, 1e9), &RiskContext::default()), 0.2 + 0.2);
+
+        // A degraded network adds its own penalty, however short the backlog
+        let status = BridgeStatus { degraded: true, ..BridgeStatus::default() };
+        let degraded = RiskContext::default().with_bridge_status(Some(status));
+        assert_eq!(model.assess("across", &edge(0.0, 1e9), &degraded), 0.2 + 0.25);
+    }
+
+    #[test]
+    fn config_overrides_take_effect() {
+        let model = DefaultRiskModel::from_config("stargate", &config(
+            "[extra]\nbase_risk = 0.9\nrisk_duration_weight = 0\nrisk_liquidity_weight = 1\n"
+        )).unwrap();
+        let ctx = RiskContext { amount: Some(250.0), ..RiskContext::default() };
+
+        assert_eq!(model.assess("stargate", &edge(3600.0, 1000.0), &ctx), 0.9 + 0.25);
+        // Other bridges keep their built-in base risk
+        assert_eq!(model.base_risk("wormhole"), 0.5);
+
+        assert!(DefaultRiskModel::from_config("stargate", &config("[extra]\nbase_risk = -1\n")).is_err());
+        assert!(DefaultRiskModel::from_config("stargate", &config("[extra]\nrisk_duration_cap_secs = 0\n")).is_err());
+    }
+}
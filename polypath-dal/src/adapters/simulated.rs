@@ -0,0 +1,563 @@
+use super::{
+    AdapterError,
+    AdapterHealth,
+    MetricsRecorder,
+    pairs_from_config,
+    unix_now,
+    BridgeAdapter,
+    BridgeEdge,
+    Clock,
+    QuoteRequest,
+    SupportedPair,
+    SystemClock,
+    TransferReference,
+    TransferStatus,
+    DEFAULT_QUOTE_VALIDITY
+};
+
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::{Duration, Instant}};
+use async_trait::async_trait;
+use anyhow::{Result, anyhow};
+use polypathroute_core::{BridgeConfig, RefreshPriority, Registry};
+use serde::Deserialize;
+
+// The bridge's `[extra.simulation]` table. Cost, speed and liquidity each follow a random walk
+// around their base value in log space, one step per quote of a route.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimulationSettings {
+    pub seed: u64,
+    pub base_fee: f64,
+    // Base fees of single routes keyed "source_chain->destination_chain", replacing base_fee
+    pub fees: HashMap<String, f64>,
+    pub speed: f64,
+    pub liquidity: f64,
+    pub risk: f64,
+    // Standard deviation of each step of a walk
+    pub volatility: f64,
+    // Share of its distance from the base value a walk moves back each step
+    pub reversion: f64,
+    // Symbols quoted between every two of the bridge's chains when it lists no pairs
+    pub tokens: Vec<String>,
+    pub events: Vec<SimulatedEvent>,
+    // Share of tracked transfers that stall once their source transaction is confirmed
+    pub stuck_rate: f64,
+}
+
+impl Default for SimulationSettings {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            base_fee: 1.0,
+            fees: HashMap::new(),
+            speed: 60.0,
+            liquidity: 1_000_000.0,
+            risk: 0.1,
+            volatility: 0.05,
+            reversion: 0.1,
+            tokens: vec!["USDC".to_string()],
+            events: Vec::new(),
+            stuck_rate: 0.0,
+        }
+    }
+}
+
+// Scales the metrics of matching routes for a stretch of ticks, on top of their walks
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SimulatedEvent {
+    // First tick it applies to; a route's first quote is tick 0
+    pub tick: u64,
+    // Ticks it lasts, the rest of the run when unset
+    #[serde(default)]
+    pub ticks: Option<u64>,
+    #[serde(default = "unscaled")]
+    pub cost_multiplier: f64,
+    #[serde(default = "unscaled")]
+    pub liquidity_multiplier: f64,
+    // Routes it applies to, all of them when unset
+    #[serde(default)]
+    pub source_chain: Option<String>,
+    #[serde(default)]
+    pub destination_chain: Option<String>,
+}
+
+fn unscaled() -> f64 {
+    1.0
+}
+
+impl SimulatedEvent {
+    fn applies(&self, tick: u64, src_chain: &str, dst_chain: &str) -> bool {
+        let chain_matches = |wanted: &Option<String>, chain: &str| wanted.as_ref().is_none_or(|wanted| wanted.eq_ignore_ascii_case(chain));
+        tick >= self.tick
+            && self.ticks.is_none_or(|ticks| tick < self.tick + ticks)
+            && chain_matches(&self.source_chain, src_chain)
+            && chain_matches(&self.destination_chain, dst_chain)
+    }
+}
+
+// Where one route's walks are: offsets from the base values, in log space
+#[derive(Debug)]
+struct Walk {
+    tick: u64,
+    rng: fastrand::Rng,
+    cost: f64,
+    speed: f64,
+    liquidity: f64,
+}
+
+// Keyed by lowercased (src_chain, dst_chain, src_token, dst_token)
+type RouteKey = (String, String, String, String);
+
+// Offline adapter quoting plausible, evolving metrics. Each route has its own walks and tick
+// counter, seeded from the seed, the bridge name and the route, so the n-th quote of a route
+// is the same on every run with the same seed however the quotes are interleaved.
+#[derive(Debug)]
+pub struct SimulatedAdapter {
+    name: String,
+    settings: SimulationSettings,
+    pairs: Vec<SupportedPair>,
+    quote_validity: Duration,
+    walks: Mutex<HashMap<RouteKey, Walk>>,
+    telemetry: MetricsRecorder,
+    clock: Arc<dyn Clock>,
+    // When each tracked transfer was first asked about, as an instant and in unix seconds
+    transfers: Mutex<HashMap<TransferReference, (Instant, u64)>>,
+}
+
+impl SimulatedAdapter {
+    // Quotes the configured pairs, or when there are none every `tokens` symbol the registry
+    // knows on both ends between every two of the bridge's chains
+    pub fn from_config(name: &str, config: &BridgeConfig) -> Result<Self> {
+        let settings: SimulationSettings = match config.extra.as_ref().and_then(|extra| extra.get("simulation")) {
+            Some(table) => table
+                .clone()
+                .try_into()
+                .map_err(|err| anyhow!("bridges.{}.extra.simulation: {}", name, err))?,
+            None => SimulationSettings::default(),
+        };
+        validate(name, &settings)?;
+
+        let quote_validity = match config.extra.as_ref().and_then(|extra| extra.get("quote_validity_secs")) {
+            Some(value) => value
+                .as_integer()
+                .filter(|v| *v > 0)
+                .map(|v| Duration::from_secs(v as u64))
+                .ok_or_else(|| anyhow!("bridges.{}.extra.quote_validity_secs must be a positive integer", name))?,
+            None => DEFAULT_QUOTE_VALIDITY,
+        };
+
+        let mut pairs = pairs_from_config(config);
+        if pairs.is_empty() {
+            pairs = token_pairs(&config.chains, &settings.tokens);
+        }
+        Ok(Self {
+            name: name.to_string(),
+            settings,
+            pairs,
+            quote_validity,
+            walks: Mutex::default(),
+            telemetry: MetricsRecorder::new(),
+            clock: Arc::new(SystemClock),
+            transfers: Mutex::default(),
+        })
+    }
+
+    // Time source for tracked transfers, a ManualClock in tests
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    // Replaces the configured seed, starting every walk over
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.settings.seed = seed;
+        self.walks = Mutex::default();
+        self
+    }
+
+    pub fn settings(&self) -> &SimulationSettings {
+        &self.settings
+    }
+
+    fn base_fee(&self, src_chain: &str, dst_chain: &str) -> f64 {
+        let route = format!("{}->{}", src_chain, dst_chain);
+        self.settings.fees
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(&route))
+            .map_or(self.settings.base_fee, |(_, fee)| *fee)
+    }
+
+    // The route's quote at its current tick, then one step of its walks
+    fn next_quote(&self, pair: &SupportedPair) -> BridgeEdge {
+        let key = (
+            pair.src_chain.to_lowercase(),
+            pair.dst_chain.to_lowercase(),
+            pair.src_token.to_lowercase(),
+            pair.dst_token.to_lowercase(),
+        );
+        let mut walks = self.walks.lock().unwrap();
+        let walk = walks.entry(key).or_insert_with_key(|key| Walk {
+            tick: 0,
+            rng: fastrand::Rng::with_seed(stable_hash(&[&self.settings.seed.to_string(), &self.name, &key.0, &key.1, &key.2, &key.3])),
+            cost: 0.0,
+            speed: 0.0,
+            liquidity: 0.0,
+        });
+
+        let (mut cost_multiplier, mut liquidity_multiplier) = (1.0, 1.0);
+        for event in self.settings.events.iter().filter(|event| event.applies(walk.tick, &pair.src_chain, &pair.dst_chain)) {
+            cost_multiplier *= event.cost_multiplier;
+            liquidity_multiplier *= event.liquidity_multiplier;
+        }
+        let quoted_at = unix_now();
+        let edge = BridgeEdge {
+            from: pair.src_chain.clone(),
+            to: pair.dst_chain.clone(),
+            cost: self.base_fee(&pair.src_chain, &pair.dst_chain) * walk.cost.exp() * cost_multiplier,
+            speed: self.settings.speed * walk.speed.exp(),
+            liquidity: self.settings.liquidity * walk.liquidity.exp() * liquidity_multiplier,
+            risk: self.settings.risk,
+            bridge: self.name.clone(),
+            quoted_at,
+            ..BridgeEdge::default()
+        }
+        .with_default_validity(self.quote_validity);
+
+        let (volatility, keep) = (self.settings.volatility, 1.0 - self.settings.reversion);
+        walk.cost = walk.cost * keep + volatility * standard_normal(&mut walk.rng);
+        walk.speed = walk.speed * keep + volatility * standard_normal(&mut walk.rng);
+        walk.liquidity = walk.liquidity * keep + volatility * standard_normal(&mut walk.rng);
+        walk.tick += 1;
+        edge
+    }
+
+    // Transfers are taken to have been sent when first asked about. The source transaction is
+    // confirmed for the first tenth of `speed`, then the transfer is in flight until `speed` has
+    // passed and delivered after, unless its draw against stuck_rate stalls it for good.
+    fn transfer_status(&self, reference: &TransferReference) -> TransferStatus {
+        let now = self.clock.now();
+        let (sent, sent_unix) = *self.transfers.lock().unwrap().entry(reference.clone()).or_insert((now, unix_now()));
+        let elapsed = now.duration_since(sent).as_secs_f64();
+        let confirming = self.settings.speed / 10.0;
+        if elapsed < confirming {
+            return TransferStatus::SourceConfirmed;
+        }
+        let seed = self.settings.seed.to_string();
+        if fastrand::Rng::with_seed(stable_hash(&[&seed, &self.name, reference.id()])).f64() < self.settings.stuck_rate {
+            return TransferStatus::Stuck { since: sent_unix + confirming as u64, reason: "simulated stall".to_string() };
+        }
+        if elapsed < self.settings.speed {
+            return TransferStatus::InFlight { eta: Some(sent_unix + self.settings.speed as u64) };
+        }
+        let dst_tx = format!("0x{:016x}", stable_hash(&[&seed, &self.name, reference.id(), "destination"]));
+        TransferStatus::Delivered { dst_tx }
+    }
+}
+
+fn validate(name: &str, settings: &SimulationSettings) -> Result<()> {
+    let check = |key: &str, value: f64, valid: bool| match valid && value.is_finite() {
+        true => Ok(()),
+        false => Err(anyhow!("bridges.{}.extra.simulation.{} is out of range: {}", name, key, value)),
+    };
+    check("base_fee", settings.base_fee, settings.base_fee >= 0.0)?;
+    for (route, fee) in &settings.fees {
+        check(&format!("fees.\"{}\"", route), *fee, *fee >= 0.0)?;
+    }
+    check("speed", settings.speed, settings.speed >= 0.0)?;
+    check("liquidity", settings.liquidity, settings.liquidity >= 0.0)?;
+    check("risk", settings.risk, settings.risk >= 0.0)?;
+    check("volatility", settings.volatility, settings.volatility >= 0.0)?;
+    check("reversion", settings.reversion, (0.0..=1.0).contains(&settings.reversion))?;
+    check("stuck_rate", settings.stuck_rate, (0.0..=1.0).contains(&settings.stuck_rate))?;
+    for (index, event) in settings.events.iter().enumerate() {
+        check(&format!("events[{}].cost_multiplier", index), event.cost_multiplier, event.cost_multiplier > 0.0)?;
+        check(&format!("events[{}].liquidity_multiplier", index), event.liquidity_multiplier, event.liquidity_multiplier > 0.0)?;
+        if event.ticks == Some(0) {
+            return Err(anyhow!("bridges.{}.extra.simulation.events[{}].ticks must be positive", name, index));
+        }
+    }
+    Ok(())
+}
+
+// Same-symbol pairs between every two distinct chains, addressed by the built-in registry
+fn token_pairs(chains: &[String], symbols: &[String]) -> Vec<SupportedPair> {
+    let registry = Registry::builtin();
+    let mut pairs = Vec::new();
+    for symbol in symbols {
+        for src_chain in chains {
+            for dst_chain in chains.iter().filter(|chain| !chain.eq_ignore_ascii_case(src_chain)) {
+                let (Ok(src), Ok(dst)) = (registry.resolve_token(src_chain, symbol), registry.resolve_token(dst_chain, symbol)) else {
+                    continue;
+                };
+                pairs.push(SupportedPair {
+                    src_chain: src_chain.clone(),
+                    dst_chain: dst_chain.clone(),
+                    src_token: src.address,
+                    dst_token: dst.address,
+                    min_amount: None,
+                    max_amount: None,
+                    token_symbol: Some(src.symbol),
+                    priority: RefreshPriority::default(),
+                    refresh_interval: None,
+                });
+            }
+        }
+    }
+    pairs
+}
+
+// FNV-1a, which unlike std's hashers is the same on every build
+fn stable_hash(parts: &[&str]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for part in parts {
+        for byte in part.bytes().chain(std::iter::once(0)) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+// Box-Muller; 1 - f64() is in (0, 1] so its log is finite
+fn standard_normal(rng: &mut fastrand::Rng) -> f64 {
+    let (u, v) = (1.0 - rng.f64(), rng.f64());
+    (-2.0 * u.ln()).sqrt() * (std::f64::consts::TAU * v).cos()
+}
+
+#[async_trait]
+impl BridgeAdapter for SimulatedAdapter {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn supported_pairs(&self) -> Vec<SupportedPair> {
+        self.pairs.clone()
+    }
+
+    fn telemetry(&self) -> Option<&MetricsRecorder> {
+        Some(&self.telemetry)
+    }
+
+    async fn fetch_metrics(&self, request: &QuoteRequest) -> Result<BridgeEdge, AdapterError> {
+        let pair = self.pairs
+            .iter()
+            .find(|pair| pair.matches(&request.src_chain, &request.dst_chain, &request.src_token, &request.dst_token))
+            .ok_or_else(|| AdapterError::unsupported_pair(&self.name, request))?;
+        Ok(self.next_quote(pair))
+    }
+
+    async fn health_check(&self) -> Result<AdapterHealth, AdapterError> {
+        Ok(AdapterHealth::healthy(self.name.clone()))
+    }
+
+    async fn track_transfer(&self, reference: &TransferReference) -> Result<TransferStatus, AdapterError> {
+        Ok(self.transfer_status(reference))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::ManualClock;
+    use crate::{DalContext, GraphUpdater, RefreshScheduler};
+    use polypath_graph::{Graph, RoutingEngine, RoutingParams};
+    use tokio_util::sync::CancellationToken;
+
+    const USDC_ETHEREUM: &str = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+    const USDC_POLYGON: &str = "0x3c499c542cef5e3811e1192ce70d8cc03d5c3359";
+
+    fn bridge(simulation: &str) -> BridgeConfig {
+        toml::from_str(&format!(r#"
+            base_url = "https://simulated.test"
+            chains = ["ethereum", "polygon"]
+
+            [[pairs]]
+            source_chain = "ethereum"
+            source_token_name = "USDC"
+            destination_chain = "polygon"
+            destination_token_name = "USDC"
+            source_address = "{}"
+            destination_address = "{}"
+
+            [extra.simulation]
+            {}
+        "#, USDC_ETHEREUM, USDC_POLYGON, simulation)).unwrap()
+    }
+
+    fn request() -> QuoteRequest {
+        QuoteRequest::builder()
+            .src_chain("ethereum")
+            .dst_chain("polygon")
+            .src_token(USDC_ETHEREUM.to_lowercase())
+            .dst_token(USDC_POLYGON)
+            .src_amount("1")
+            .wallet("0xca69")
+            .build()
+            .unwrap()
+    }
+
+    async fn quotes(adapter: &SimulatedAdapter, count: usize) -> Vec<(f64, f64, f64)> {
+        let mut quotes = Vec::new();
+        for _ in 0..count {
+            let edge = adapter.fetch_metrics(&request()).await.unwrap();
+            quotes.push((edge.cost, edge.speed, edge.liquidity));
+        }
+        quotes
+    }
+
+    #[tokio::test]
+    async fn walks_repeat_for_the_same_seed() {
+        let config = bridge("seed = 7\nvolatility = 0.1");
+        let first = quotes(&SimulatedAdapter::from_config("sim", &config).unwrap(), 20).await;
+        let second = quotes(&SimulatedAdapter::from_config("sim", &config).unwrap(), 20).await;
+        assert_eq!(first, second);
+        assert!(first.windows(2).all(|pair| pair[0] != pair[1]), "metrics should move every tick: {:?}", first);
+        assert!(first.iter().all(|(cost, speed, liquidity)| *cost > 0.0 && *speed > 0.0 && *liquidity > 0.0));
+        assert_eq!(first[0], (1.0, 60.0, 1_000_000.0));
+
+        let reseeded = quotes(&SimulatedAdapter::from_config("sim", &config).unwrap().with_seed(8), 20).await;
+        assert_ne!(first, reseeded);
+        // Another bridge walks on its own
+        let renamed = quotes(&SimulatedAdapter::from_config("other", &config).unwrap(), 20).await;
+        assert_ne!(first, renamed);
+
+        let adapter = SimulatedAdapter::from_config("sim", &config).unwrap();
+        let edge = adapter.fetch_metrics(&request()).await.unwrap();
+        assert_eq!(edge.bridge, "sim");
+        assert_eq!(edge.valid_until, Some(edge.quoted_at + 60));
+        let mut reverse = request();
+        std::mem::swap(&mut reverse.src_chain, &mut reverse.dst_chain);
+        assert!(matches!(adapter.fetch_metrics(&reverse).await, Err(AdapterError::UnsupportedPair { .. })));
+    }
+
+    #[tokio::test]
+    async fn events_scale_the_metrics_of_their_ticks() {
+        let adapter = SimulatedAdapter::from_config("sim", &bridge(r#"
+            volatility = 0
+            fees = { "Ethereum->polygon" = 2.0 }
+
+            [[extra.simulation.events]]
+            tick = 2
+            ticks = 2
+            cost_multiplier = 5
+            liquidity_multiplier = 0.5
+
+            [[extra.simulation.events]]
+            tick = 3
+            source_chain = "arbitrum"
+            cost_multiplier = 100
+        "#)).unwrap();
+
+        let costs: Vec<(f64, f64)> = quotes(&adapter, 6).await.into_iter().map(|(cost, _, liquidity)| (cost, liquidity)).collect();
+        assert_eq!(costs, [
+            (2.0, 1_000_000.0),
+            (2.0, 1_000_000.0),
+            (10.0, 500_000.0),
+            (10.0, 500_000.0),
+            (2.0, 1_000_000.0),
+            (2.0, 1_000_000.0),
+        ]);
+    }
+
+    #[test]
+    fn settings_are_checked_and_pairs_default_to_registry_tokens() {
+        let mut config = bridge("");
+        config.pairs = None;
+        config.chains = vec!["ethereum".to_string(), "polygon".to_string(), "solana".to_string()];
+        let pairs = SimulatedAdapter::from_config("sim", &config).unwrap().supported_pairs();
+        let routes: Vec<(&str, &str)> = pairs.iter().map(|pair| (pair.src_chain.as_str(), pair.dst_chain.as_str())).collect();
+        // The built-in registry has no USDC on solana
+        assert_eq!(routes, [("ethereum", "polygon"), ("polygon", "ethereum")]);
+        assert_eq!(pairs[0].src_token, USDC_ETHEREUM);
+        assert_eq!(pairs[0].token_symbol.as_deref(), Some("USDC"));
+
+        for (settings, key) in [
+            ("reversion = 1.5", "reversion"),
+            ("base_fee = -1", "base_fee"),
+            ("stuck_rate = 2", "stuck_rate"),
+            ("[[extra.simulation.events]]\ntick = 1\ncost_multiplier = 0", "events[0].cost_multiplier"),
+        ] {
+            let err = SimulatedAdapter::from_config("sim", &bridge(settings)).unwrap_err();
+            assert!(err.to_string().contains(&format!("bridges.sim.extra.simulation.{}", key)), "{}", err);
+        }
+        let err = SimulatedAdapter::from_config("sim", &bridge("volatilty = 0.1")).unwrap_err();
+        assert!(err.to_string().contains("volatilty"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn tracked_transfers_move_through_to_delivery_unless_they_stall() {
+        let clock = ManualClock::new();
+        let adapter = SimulatedAdapter::from_config("sim", &bridge("speed = 100")).unwrap().with_clock(Arc::new(clock.clone()));
+        let reference = TransferReference::SourceTx("0x8f2c".to_string());
+
+        assert_eq!(adapter.track_transfer(&reference).await, Ok(TransferStatus::SourceConfirmed));
+        clock.advance(Duration::from_secs(10));
+        let Ok(TransferStatus::InFlight { eta: Some(eta) }) = adapter.track_transfer(&reference).await else {
+            panic!("expected the transfer in flight");
+        };
+        assert!(eta.abs_diff(unix_now() + 100) <= 1, "{}", eta);
+        clock.advance(Duration::from_secs(90));
+        let delivered = adapter.track_transfer(&reference).await.unwrap();
+        assert!(matches!(&delivered, TransferStatus::Delivered { dst_tx } if dst_tx.len() == 18), "{:?}", delivered);
+        // Another transfer starts over
+        assert_eq!(adapter.track_transfer(&TransferReference::SourceTx("0x1d3f".to_string())).await, Ok(TransferStatus::SourceConfirmed));
+
+        let clock = ManualClock::new();
+        let adapter = SimulatedAdapter::from_config("sim", &bridge("speed = 100\nstuck_rate = 1")).unwrap().with_clock(Arc::new(clock.clone()));
+        assert_eq!(adapter.track_transfer(&reference).await, Ok(TransferStatus::SourceConfirmed));
+        clock.advance(Duration::from_secs(1_000));
+        let stuck = adapter.track_transfer(&reference).await.unwrap();
+        assert!(matches!(&stuck, TransferStatus::Stuck { reason, .. } if reason == "simulated stall"), "{:?}", stuck);
+    }
+
+    // Wormhole is the cheaper bridge until its fee spikes at tick 10, the eleventh refresh. Hot
+    // pairs are quoted on every refresh.
+    #[tokio::test(start_paused = true)]
+    async fn refreshes_flip_the_route_when_the_wormhole_fee_spikes() {
+        let route = |bridge: &str| format!(r#"
+            [bridges.{0}]
+            base_url = "https://{0}.test"
+            chains = ["ethereum", "polygon"]
+            [[bridges.{0}.pairs]]
+            source_chain = "ethereum"
+            source_address = "{1}"
+            source_token_name = "USDC"
+            destination_chain = "polygon"
+            destination_address = "{2}"
+            destination_token_name = "USDC"
+            priority = "hot"
+        "#, bridge, USDC_ETHEREUM, USDC_POLYGON);
+        let config_path = std::env::temp_dir().join(format!("polypath-dal-simulated-{}.toml", std::process::id()));
+        std::fs::write(&config_path, format!(
+            "[global]\nupdate_interval = 60\ncache_ttl = 1\nlog_level = \"info\"\n[history]\nenabled = false\n{}\n[bridges.stargate.extra.simulation]\nbase_fee = 2.0\n{}\n[bridges.wormhole.extra.simulation]\nbase_fee = 1.0\n[[bridges.wormhole.extra.simulation.events]]\ntick = 10\ncost_multiplier = 10\n",
+            route("stargate"),
+            route("wormhole"),
+        )).unwrap();
+        let dal = DalContext::new(config_path.to_str().unwrap()).unwrap().with_simulation(42);
+        std::fs::remove_file(&config_path).unwrap();
+        assert_eq!(dal.simulation_seed(), Some(42));
+
+        let updater = Arc::new(GraphUpdater::new(Arc::new(Graph::new(16)), dal));
+        let (eth, polygon) = (updater.asset_node_id("ethereum", USDC_ETHEREUM), updater.asset_node_id("polygon", USDC_POLYGON));
+        let scheduler = RefreshScheduler::new(Arc::clone(&updater)).with_max_jitter(Duration::ZERO);
+        let mut reports = scheduler.subscribe();
+        let shutdown = CancellationToken::new();
+        let handle = scheduler.spawn(shutdown.clone());
+
+        let mut chosen = Vec::new();
+        for _ in 0..12 {
+            let report = reports.recv().await.unwrap();
+            assert_eq!(report.failed, 0);
+            let engine = RoutingEngine::new(Arc::clone(updater.graph()), 4);
+            let path = engine.find_path(eth, polygon, &RoutingParams::cheapest()).unwrap();
+            chosen.push(path.hops[0].bridge_name.to_string());
+        }
+        shutdown.cancel();
+        handle.await.unwrap();
+
+        let mut expected = vec!["wormhole"; 10];
+        expected.extend(["stargate", "stargate"]);
+        assert_eq!(chosen, expected);
+    }
+}
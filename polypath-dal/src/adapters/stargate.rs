@@ -1,124 +1,980 @@
 use super::{
+    AdapterContext,
+    fixture_request,
+    FixtureQuote,
     BridgeAdapter,
-    BridgeEdge
+    BridgeEdge,
+    ChainInfo,
+    QuoteRequest,
+    SupportedPair,
+    RiskModel,
+    unix_now,
+    FeeComponent,
+    pairs::{merge_pair, pairs_from_token_listing},
+    settings::AdapterSettings,
+    AdapterError,
+    AdapterHealth,
+    health::probe,
+    MetricsRecorder,
+    Page,
+    paginate,
+    MAX_LISTING_PAGES,
+    RateLimiter,
+    RetryPolicy,
+    TokenDecimals,
+    TransferReference,
+    TransferStatus
 };
+use crate::fx::{CurrencyId, Money};
 
-use std::collections::HashMap;
-use reqwest::blocking::Client;
+use std::time::Duration;
+use std::sync::{Arc, RwLock};
+use async_trait::async_trait;
+use futures::future::join_all;
+use tokio::sync::OnceCell;
+use polypathroute_core::{BridgeConfig, Redacted};
+use reqwest::{Client, RequestBuilder};
+use serde::Deserialize;
 use serde_json::Value;
-use anyhow::{Result, anyhow};
+use anyhow::Result;
 
 pub struct StargateAdapter {
     pub name: String,
-    private_key: String,
-    pub base_url: String
+    pub base_url: String,
+    api_key: Option<Redacted<String>>,
+    retry: RetryPolicy,
+    rate_limiter: Option<RateLimiter>,
+    // Shared so requests reuse pooled connections
+    client: Client,
+    pairs: RwLock<Vec<SupportedPair>>,
+    decimals: RwLock<TokenDecimals>,
+    risk_model: Arc<dyn RiskModel>,
+    telemetry: Arc<MetricsRecorder>,
+    // How long quotes stay usable when the API reports no expiry
+    quote_validity: Duration,
+    // Chain listing, fetched on first use and kept for the adapter's lifetime
+    chains: OnceCell<Vec<ChainInfo>>
 }
 
 impl StargateAdapter {
-    pub fn new() -> Self {
-        Self {
+    pub fn from_config(config: &BridgeConfig) -> Result<Self> {
+        Self::new(AdapterContext::from_config("stargate", config)?)
+    }
+
+    pub fn new(context: AdapterContext) -> Result<Self> {
+        let settings = AdapterSettings::from_config("stargate", &context.config)?;
+        let client = context.client;
+        Ok(Self {
             name: "stargate".to_string(),
-            private_key: "".to_string(),
-            base_url: "".to_string()
+            base_url: settings.base_url,
+            api_key: settings.api_key,
+            retry: settings.retry,
+            rate_limiter: settings.rate_limiter,
+            client,
+            pairs: RwLock::new(settings.pairs),
+            decimals: RwLock::new(settings.decimals),
+            risk_model: settings.risk_model,
+            telemetry: settings.telemetry,
+            quote_validity: settings.quote_validity,
+            chains: OnceCell::new()
+        })
+    }
+
+    // Replaces the risk model built from config
+    pub fn with_risk_model(mut self, risk_model: Arc<dyn RiskModel>) -> Self {
+        self.risk_model = risk_model;
+        self
+    }
+
+    pub fn quotes_url(&self) -> String {
+        format!("{}/quotes", self.base_url)
+    }
+
+    pub fn tokens_url(&self) -> String {
+        format!("{}/tokens", self.base_url)
+    }
+
+    pub fn chains_url(&self) -> String {
+        format!("{}/chains", self.base_url)
+    }
+
+    pub fn transfer_url(&self, reference: &TransferReference) -> String {
+        match reference {
+            TransferReference::SourceTx(hash) => format!("{}/transfers/tx/{}", self.base_url, hash),
+            TransferReference::MessageId(guid) => format!("{}/transfers/guid/{}", self.base_url, guid),
+        }
+    }
+
+    fn get(&self, url: String) -> RequestBuilder {
+        let request = self.client.get(url);
+        match &self.api_key {
+            Some(key) => request.header("x-api-key", key.expose()),
+            None => request
+        }
+    }
+
+    // Extends the configured pairs with routes from Stargate's token listing, and learns the
+    // decimals of every listed token. Only chains already covered by a configured pair are
+    // considered for routes. Returns the number of new pairs.
+    pub async fn refresh_pairs(&self) -> Result<usize> {
+        let listing: Value = self.retry
+            .send(self.rate_limiter.as_ref(), || self.get(self.tokens_url()))
+            .await?
+            .json()
+            .await?;
+
+        Ok(self.merge_token_listing(&listing))
+    }
+
+    fn merge_token_listing(&self, listing: &Value) -> usize {
+        let tokens = listing.get("tokens").and_then(|tokens| tokens.as_array());
+        let mut decimals = self.decimals.write().unwrap();
+        for token in tokens.into_iter().flatten() {
+            let chain = token.get("chainKey").and_then(|v| v.as_str());
+            let address = token.get("address").and_then(|v| v.as_str());
+            let value = token.get("decimals").and_then(|v| v.as_u64());
+            if let (Some(chain), Some(address), Some(value)) = (chain, address, value) {
+                decimals.insert(chain, address, value as u8);
+            }
+        }
+        drop(decimals);
+
+        let mut pairs = self.pairs.write().unwrap();
+        let mut chains: Vec<String> = Vec::new();
+        for pair in pairs.iter() {
+            for chain in [&pair.src_chain, &pair.dst_chain] {
+                if !chains.contains(chain) {
+                    chains.push(chain.clone());
+                }
+            }
         }
+
+        pairs_from_token_listing(listing, &chains)
+            .into_iter()
+            .filter(|pair| merge_pair(&mut pairs, pair.clone()))
+            .count()
     }
 }
 
+#[async_trait]
 impl BridgeAdapter for StargateAdapter {
     fn name(&self) -> String {
         self.name.clone()
     }
 
-    fn supported_pairs(&self) -> HashMap<String, String> {
-        HashMap::new()
+    fn supported_pairs(&self) -> Vec<SupportedPair> {
+        self.pairs.read().unwrap().clone()
+    }
+
+    fn rate_limiter(&self) -> Option<&RateLimiter> {
+        self.rate_limiter.as_ref()
+    }
+
+    fn telemetry(&self) -> Option<&MetricsRecorder> {
+        Some(&self.telemetry)
     }
 
-    fn is_supported_pair(&self) -> bool {
-        true
+    async fn health_check(&self) -> Result<AdapterHealth, AdapterError> {
+        probe(self.rate_limiter.as_ref(), self.get(self.chains_url())).await
     }
 
-    fn fetch_metrics(&self, src_chain: &str, dst_chain: &str, src_token: &str, dst_token: &str,
-        src_amount: &str, dst_amount: &str, src_address: &str, dst_address: &str) -> Result<Value> {    
-        let client = Client::new();
+    // Failed fetches aren't cached, the next call tries again
+    async fn supported_chains(&self) -> Result<Vec<ChainInfo>, AdapterError> {
+        self.chains
+            .get_or_try_init(|| async {
+                let chains = paginate(MAX_LISTING_PAGES, |cursor| async move {
+                    let listing: StargateChainsResponse = self.retry
+                        .send(self.rate_limiter.as_ref(), || {
+                            let request = self.get(self.chains_url());
+                            match &cursor {
+                                Some(cursor) => request.query(&[("cursor", cursor)]),
+                                None => request,
+                            }
+                        })
+                        .await?
+                        .json()
+                        .await?;
+                    Ok(Page { items: listing.chains, next: listing.next_cursor })
+                })
+                .await?;
+                Ok(chains.into_iter().map(StargateChain::into_chain_info).collect())
+            })
+            .await
+            .cloned()
+    }
+
+    fn replay_fixture(&self) -> Option<FixtureQuote> {
+        const QUOTE: &str = include_str!("../../fixtures/stargate/quote.json");
+        let request = fixture_request("ethereum", "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", "polygon", "0x3c499c542cef5e3811e1192ce70d8cc03d5c3359", "1");
+        let result = serde_json::from_str(QUOTE).map_err(AdapterError::from).and_then(|response| self.parse_quote(&request, response));
+        Some(FixtureQuote { request, result })
+    }
+
+    async fn fetch_metrics(&self, request: &QuoteRequest) -> Result<BridgeEdge, AdapterError> {
+        let (src_amount, dst_amount_min) = {
+            let decimals = self.decimals.read().unwrap();
+            (
+                request.src_amount_in(&decimals)?.to_raw_string(),
+                decimals.to_raw(&request.dst_chain, &request.dst_token, &request.dst_amount_min)?,
+            )
+        };
         let params = [
-            ("srcChainKey", src_chain),
-            ("dstChainKey", dst_chain),
-            ("srcToken", src_token),
-            ("dstToken", dst_token),
-            ("srcAmount", src_amount),
-            ("dstAmountMin", dst_amount),
-            ("srcAddress", src_address),
-            ("dstAddress", dst_address),
+            ("srcChainKey", request.src_chain.as_str()),
+            ("dstChainKey", request.dst_chain.as_str()),
+            ("srcToken", request.src_token.as_str()),
+            ("dstToken", request.dst_token.as_str()),
+            ("srcAmount", src_amount.as_str()),
+            ("dstAmountMin", dst_amount_min.as_str()),
+            ("srcAddress", request.src_address.as_str()),
+            ("dstAddress", request.dst_address.as_str()),
         ];
 
-        let response: Value = client
-            .get("https://stargate.finance/api/v1/quotes")
-            .query(&params)
-            .send()?
-            .json()?;
-
-        let quote = response
-                    .get("quotes")
-                    .and_then(|quotes| quotes.as_array())
-                    .and_then(|quotes| quotes.first())
-                    .ok_or_else(|| anyhow::anyhow!("No quotes found in the response!"));
-        
-        let quote = quote.unwrap();
-
-        let src_chain_key = quote
-                                    .get("srcChainKey")
-                                    .and_then(|v| v.as_str())
-                                    .ok_or_else(|| anyhow::anyhow!("srcChainKey not present!"))?;
-        
-        let dst_chain_key = quote
-                                    .get("dstChainKey")
-                                    .and_then(|v| v.as_str())
-                                    .ok_or_else(|| anyhow::anyhow!("dstChainKey not present!"))?;
-
-        let cost = quote
-                            .get("fees")
-                            .and_then(|fees| fees.as_array())
-                            .map(|fees| {
-                                fees.iter()
-                                    .filter_map(|fee| {
-                                        fee.get("amount")
-                                            .and_then(|v| v.as_str())
-                                            .and_then(|s| s.parse::<f64>().ok())
-                                    })
-                                    .sum::<f64>()
-                            })
-                            .unwrap_or(0.0);
-        
-        let speed = quote
-                            .get("duration")
-                            .and_then(|d| d.get("estimated"))
-                            .and_then(|v| v.as_f64())
-                            .unwrap_or(0.0);
-        
-        let liquidity = quote.get("dstAmount")
-                                    .and_then(|v| v.as_str())
-                                    .and_then(|s| s.parse::<f64>().ok())
-                                    .or_else(|| {
-                                        quote.get("srcAmount")
-                                                .and_then(|v| v.as_str())
-                                                .and_then(|s| s.parse::<f64>().ok())
-                                    });
-        
-        let risk = if speed > 0.0 {
-            (speed * 10.0).min(1000.0)
-        } else {
-            500.0
+        let response: Value = self.retry
+            .send(self.rate_limiter.as_ref(), || self.get(self.quotes_url()).query(&params))
+            .await?
+            .json()
+            .await?;
+
+        self.parse_quote(request, response)
+    }
+
+    // A 404 is how Stargate says it hasn't indexed the transfer (yet)
+    async fn track_transfer(&self, reference: &TransferReference) -> Result<TransferStatus, AdapterError> {
+        let unknown = || AdapterError::UnknownTransfer { bridge: self.name.clone(), reference: reference.to_string() };
+        let response: StargateTransfersResponse = match self.retry.send(self.rate_limiter.as_ref(), || self.get(self.transfer_url(reference))).await {
+            Ok(response) => response.json().await?,
+            Err(AdapterError::Upstream { status: 404, .. }) => return Err(unknown()),
+            Err(err) => return Err(err),
         };
+        response.data.into_iter().next().ok_or_else(unknown)?.into_status()
+    }
 
-        let bridge_edge = BridgeEdge {
-            from: src_chain_key.to_string(),
-            to: dst_chain_key.to_string(),
-            cost: cost,
-            speed: speed,
-            liquidity: liquidity.unwrap(),
-            risk: risk
+    // All amounts are quoted at once over the pooled client; the rate limiter still paces
+    // the requests. Results keep the order of `amounts`.
+    async fn fetch_depth(&self, request: &QuoteRequest, amounts: &[f64]) -> Result<Vec<(f64, BridgeEdge)>, AdapterError> {
+        let requests = amounts
+            .iter()
+            .map(|&amount| request.with_src_amount(amount))
+            .collect::<Result<Vec<_>, _>>()?;
+        let edges = join_all(requests.iter().map(|request| self.fetch_metrics(request))).await;
+        amounts.iter().copied().zip(edges).map(|(amount, edge)| Ok((amount, edge?))).collect()
+    }
+}
+
+impl StargateAdapter {
+    // cost = sum of fees, each in human units of the token it is charged in,
+    // speed = duration.estimated, liquidity = dstAmount in destination token units.
+    // Fees charged in the gas token (the messaging fee) double as the gas estimate.
+    // The first route without an error is used.
+    fn parse_quote(&self, request: &QuoteRequest, response: Value) -> Result<BridgeEdge, AdapterError> {
+        if let Some(error) = response.get("error").filter(|error| !error.is_null()) {
+            let error: StargateError = serde_json::from_value(error.clone())?;
+            return Err(AdapterError::upstream(200, error.message()));
+        }
+        let response: StargateQuoteResponse = serde_json::from_value(response)?;
+
+        let quote = match response.quotes.iter().find(|quote| quote.error.is_none()) {
+            Some(quote) => quote,
+            None => {
+                return Err(match response.quotes.first().and_then(|quote| quote.error.as_ref()) {
+                    Some(error) => AdapterError::upstream(200, error.message()),
+                    None => AdapterError::missing("quotes[0]"),
+                });
+            }
         };
+        if quote.fees.is_empty() {
+            return Err(AdapterError::missing("quotes[0].fees"));
+        }
+        if quote.duration.estimated.is_nan() || quote.duration.estimated <= 0.0 {
+            return Err(AdapterError::missing("a positive quotes[0].duration.estimated"));
+        }
+
+        let decimals = self.decimals.read().unwrap();
+        let mut fee_components = Vec::with_capacity(quote.fees.len());
+        let mut gas_estimate = None;
+        for fee in &quote.fees {
+            let human = decimals.to_human(&fee.chain_key, &fee.token, raw_amount("fees[].amount", &fee.amount)?)?;
+            let currency = CurrencyId::token(&fee.chain_key, &fee.token);
+            // The gas estimate is the native fees of the first chain charging any
+            if currency.is_native() && gas_estimate.as_ref().is_none_or(|gas: &Money| gas.currency == currency) {
+                let paid = gas_estimate.map_or(0.0, |gas| gas.amount);
+                gas_estimate = Some(Money::new(paid + human, currency.clone()));
+            }
+            fee_components.push(FeeComponent {
+                name: fee.kind.clone(),
+                amount: Money::new(human, currency),
+            });
+        }
+        let cost = fee_components.iter().map(|fee| fee.amount.amount).sum();
+        let speed = quote.duration.estimated;
+
+        let liquidity = decimals.to_human(&request.dst_chain, &request.dst_token, raw_amount("dstAmount", &quote.dst_amount)?)?;
+        let max_amount = quote.src_amount_max
+            .as_deref()
+            .map(|raw| decimals.to_human(&request.src_chain, &request.src_token, raw_amount("srcAmountMax", raw)?))
+            .transpose()?;
+
+        Ok(self.risk_model.score(&self.name, request, BridgeEdge {
+            from: quote.src_chain_key.clone(),
+            to: quote.dst_chain_key.clone(),
+            cost,
+            speed,
+            liquidity,
+            via: None,
+            bridge: self.name.clone(),
+            estimated_output: liquidity,
+            gas_estimate,
+            fee_components,
+            quoted_at: unix_now(),
+            // The route is only as valid as its earliest-expiring part
+            valid_until: quote.steps.iter().filter_map(|step| step.expires_at).chain(quote.expires_at).min(),
+            min_amount: None,
+            max_amount,
+            ..BridgeEdge::default()
+        }).with_default_validity(self.quote_validity))
+    }
+}
+
+fn raw_amount(field: &str, value: &str) -> Result<f64, AdapterError> {
+    value
+        .parse()
+        .map_err(|_| AdapterError::missing(format!("a numeric {} (got `{}`)", field, value)))
+}
+
+// GET /quotes. Unknown fields are ignored so additions on Stargate's side don't break parsing.
+#[derive(Deserialize, Debug, Clone)]
+struct StargateQuoteResponse {
+    quotes: Vec<StargateQuote>,
+}
 
-        Ok(serde_json::to_value(&bridge_edge)?)
+// One route (taxi, bus, ...). Amounts are raw integer strings.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct StargateQuote {
+    // Set instead of a price when the route can't serve the request
+    #[serde(default)]
+    error: Option<StargateError>,
+    dst_amount: String,
+    // Absent for routes without a transfer cap
+    #[serde(default)]
+    src_amount_max: Option<String>,
+    src_chain_key: String,
+    dst_chain_key: String,
+    duration: StargateDuration,
+    fees: Vec<StargateFee>,
+    // Unix seconds after which the route must be re-quoted
+    #[serde(default)]
+    expires_at: Option<u64>,
+    #[serde(default)]
+    steps: Vec<StargateStep>,
+}
+
+// A transaction the route needs; each can carry its own, earlier expiry
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct StargateStep {
+    #[serde(default)]
+    expires_at: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct StargateDuration {
+    // Seconds
+    estimated: f64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct StargateFee {
+    token: String,
+    chain_key: String,
+    amount: String,
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+// Either `"error": "message"` or `"error": {"message": "..."}`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+enum StargateError {
+    Message(String),
+    Detailed { message: String },
+}
+
+impl StargateError {
+    fn message(&self) -> &str {
+        match self {
+            StargateError::Message(message) | StargateError::Detailed { message } => message,
+        }
     }
-}
\ No newline at end of file
+}
+
+// GET /chains, a page at a time when the listing is long
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct StargateChainsResponse {
+    chains: Vec<StargateChain>,
+    #[serde(default)]
+    next_cursor: Option<String>,
+}
+
+// Non-EVM chains come without a chain id or native currency
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct StargateChain {
+    chain_key: String,
+    #[serde(default)]
+    chain_id: Option<u64>,
+    name: String,
+    #[serde(default)]
+    native_currency: Option<StargateCurrency>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct StargateCurrency {
+    symbol: String,
+}
+
+// GET /transfers/{tx,guid}/<id>: the messages a transaction or GUID carried, one per transfer
+#[derive(Deserialize, Debug, Clone)]
+struct StargateTransfersResponse {
+    #[serde(default)]
+    data: Vec<StargateTransfer>,
+}
+
+// Times in unix seconds
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct StargateTransfer {
+    status: StargateTransferStatus,
+    #[serde(default)]
+    destination: Option<StargateTransferSide>,
+    updated: u64,
+    #[serde(default)]
+    estimated_arrival: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct StargateTransferStatus {
+    name: String,
+    #[serde(default)]
+    message: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct StargateTransferSide {
+    #[serde(default)]
+    tx: Option<StargateTransferTx>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct StargateTransferTx {
+    tx_hash: String,
+}
+
+impl StargateTransfer {
+    fn into_status(self) -> Result<TransferStatus, AdapterError> {
+        match self.status.name.as_str() {
+            "CONFIRMING" => Ok(TransferStatus::SourceConfirmed),
+            "INFLIGHT" => Ok(TransferStatus::InFlight { eta: self.estimated_arrival }),
+            "DELIVERED" => {
+                let dst_tx = self.destination
+                    .and_then(|destination| destination.tx)
+                    .ok_or_else(|| AdapterError::missing("data[0].destination.tx of a delivered transfer"))?;
+                Ok(TransferStatus::Delivered { dst_tx: dst_tx.tx_hash })
+            }
+            "BLOCKED" | "FAILED" | "PAYLOAD_STORED" => Ok(TransferStatus::Stuck {
+                since: self.updated,
+                reason: self.status.message.unwrap_or(self.status.name),
+            }),
+            other => Err(AdapterError::missing(format!("a known data[0].status.name (got `{}`)", other))),
+        }
+    }
+}
+
+impl StargateChain {
+    fn into_chain_info(self) -> ChainInfo {
+        ChainInfo {
+            key: self.chain_key,
+            chain_id: self.chain_id,
+            name: self.name,
+            native_token: self.native_currency.map(|currency| currency.symbol),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::AdapterError;
+    use std::time::{Duration, Instant};
+    use std::sync::{Arc, Mutex};
+    use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate, matchers::{method, path, query_param, query_param_is_missing}};
+
+    const TOKENS: &str = include_str!("../../fixtures/stargate/tokens.json");
+    const CHAINS: &str = include_str!("../../fixtures/stargate/chains.json");
+    const QUOTE: &str = include_str!("../../fixtures/stargate/quote.json");
+    const QUOTE_EMPTY: &str = include_str!("../../fixtures/stargate/quote_empty.json");
+    const QUOTE_ERROR: &str = include_str!("../../fixtures/stargate/quote_error.json");
+    const QUOTE_NO_FEES: &str = include_str!("../../fixtures/stargate/quote_no_fees.json");
+    const QUOTE_UNKNOWN_FIELDS: &str = include_str!("../../fixtures/stargate/quote_unknown_fields.json");
+    const QUOTE_EXPIRING: &str = include_str!("../../fixtures/stargate/quote_expiring.json");
+    const TRANSFER_CONFIRMING: &str = include_str!("../../fixtures/stargate/transfer_confirming.json");
+    const TRANSFER_INFLIGHT: &str = include_str!("../../fixtures/stargate/transfer_inflight.json");
+    const TRANSFER_DELIVERED: &str = include_str!("../../fixtures/stargate/transfer_delivered.json");
+    const TRANSFER_BLOCKED: &str = include_str!("../../fixtures/stargate/transfer_blocked.json");
+    const TRANSFER_UNKNOWN: &str = include_str!("../../fixtures/stargate/transfer_unknown.json");
+
+    const CONFIG: &str = r#"
+        base_url = "http://localhost:8080/api/v1/"
+        chains = ["ethereum", "polygon"]
+
+        [[pairs]]
+        source_chain = "ethereum"
+        source_token_name = "USDC"
+        destination_chain = "polygon"
+        destination_token_name = "USDC"
+        source_address = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"
+        destination_address = "0x3c499c542cEF5E3811e1192ce70d8cC03d5c3359"
+    "#;
+
+    fn configured() -> StargateAdapter {
+        StargateAdapter::from_config(&toml::from_str(CONFIG).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn request_urls_come_from_config() {
+        let adapter = configured();
+        assert_eq!(adapter.quotes_url(), "http://localhost:8080/api/v1/quotes");
+        assert_eq!(adapter.tokens_url(), "http://localhost:8080/api/v1/tokens");
+        assert!(adapter.api_key.is_none());
+    }
+
+    #[test]
+    fn api_key_is_read_from_extra() {
+        // SAFETY: the variable name is unique to this test
+        unsafe { std::env::set_var("POLYPATH_TEST_STARGATE_API_KEY", "sg-key") };
+        let config = format!("{}\n[extra]\napi_key = \"${{POLYPATH_TEST_STARGATE_API_KEY}}\"\n", CONFIG);
+        let adapter = StargateAdapter::from_config(&toml::from_str(&config).unwrap()).unwrap();
+        assert_eq!(adapter.api_key.as_ref().map(|key| key.expose().as_str()), Some("sg-key"));
+
+        let config = format!("{}\n[extra]\napi_key = \"${{POLYPATH_TEST_UNSET_API_KEY}}\"\n", CONFIG);
+        let err = StargateAdapter::from_config(&toml::from_str(&config).unwrap()).err().unwrap();
+        assert!(err.to_string().contains("POLYPATH_TEST_UNSET_API_KEY"));
+    }
+
+    #[test]
+    fn reports_configured_pairs_only() {
+        let adapter = configured();
+        assert!(adapter.is_supported_pair("ethereum", "polygon",
+            "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", "0x3c499c542cef5e3811e1192ce70d8cc03d5c3359"));
+        assert!(!adapter.is_supported_pair("polygon", "ethereum",
+            "0x3c499c542cef5e3811e1192ce70d8cc03d5c3359", "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"));
+        assert!(!adapter.is_supported_pair("ethereum", "polygon", "0xdead", "0xbeef"));
+    }
+
+    #[test]
+    fn token_listing_adds_routes_between_configured_chains() {
+        let adapter = configured();
+        let added = adapter.merge_token_listing(&serde_json::from_str(TOKENS).unwrap());
+
+        // Only the reverse polygon -> ethereum USDC route is new, arbitrum/bsc are not configured chains
+        assert_eq!(added, 1);
+        assert_eq!(adapter.supported_pairs().len(), 2);
+        assert!(adapter.is_supported_pair("polygon", "ethereum",
+            "0x3c499c542cef5e3811e1192ce70d8cc03d5c3359", "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"));
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors_until_a_quote_arrives() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/quotes"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/quotes"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(QUOTE))
+            .mount(&server)
+            .await;
+
+        let config = CONFIG.replace("http://localhost:8080/api/v1/", &server.uri())
+            + "\n[extra.retry]\nbase_delay_ms = 1\nmax_delay_ms = 5\n";
+        let adapter = StargateAdapter::from_config(&toml::from_str(&config).unwrap()).unwrap();
+        let request = QuoteRequest::builder()
+            .src_chain("ethereum")
+            .dst_chain("polygon")
+            .src_token("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48")
+            .dst_token("0x3c499c542cef5e3811e1192ce70d8cc03d5c3359")
+            .src_amount("1")
+            .wallet("0xca699201b15ccef3b8c4012e28570cc5500d9f9a")
+            .build()
+            .unwrap();
+
+        let edge = adapter.fetch_metrics(&request).await.unwrap();
+
+        assert_eq!(edge.from, "ethereum");
+        assert_eq!(edge.to, "polygon");
+        assert_eq!(edge.liquidity, 0.9994);
+        assert_eq!(server.received_requests().await.unwrap().len(), 3);
+    }
+
+    fn usdc_request() -> QuoteRequest {
+        QuoteRequest::builder()
+            .src_chain("ethereum")
+            .dst_chain("polygon")
+            .src_token("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48")
+            .dst_token("0x3c499c542cef5e3811e1192ce70d8cc03d5c3359")
+            .src_amount("1")
+            .wallet("0xca699201b15ccef3b8c4012e28570cc5500d9f9a")
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn quote_fills_the_rich_edge_fields() {
+        let edge = configured().parse_quote(&usdc_request(), serde_json::from_str(QUOTE).unwrap()).unwrap();
+
+        let message_fee = 41522281335914.0 / 1e18;
+        assert_eq!(edge.bridge, "stargate");
+        assert_eq!(edge.estimated_output, 0.9994);
+        assert_eq!(edge.cost, message_fee);
+        assert_eq!(edge.gas_estimate.as_ref().map(|gas| (gas.amount, gas.currency.is_native())), Some((message_fee, true)));
+        assert_eq!(edge.fee_components.len(), 1);
+        assert_eq!(edge.fee_components[0].name, "message");
+        assert_eq!(edge.max_amount, Some(74999.999999));
+        assert_eq!(edge.min_amount, None);
+        assert!(edge.quoted_at > 0);
+    }
+
+    #[test]
+    fn quote_responses_are_validated() {
+        let adapter = configured();
+        let parse = |body: &str| adapter.parse_quote(&usdc_request(), serde_json::from_str(body).unwrap());
+
+        // Fields Stargate adds later are ignored
+        assert_eq!(parse(QUOTE_UNKNOWN_FIELDS).unwrap().cost, parse(QUOTE).unwrap().cost);
+
+        assert_eq!(
+            parse(QUOTE_ERROR).unwrap_err(),
+            AdapterError::upstream(200, "Invalid srcToken: 0xdead is not supported on ethereum")
+        );
+        // A quote without fees would look free, so it is rejected rather than priced at 0
+        assert_eq!(parse(QUOTE_NO_FEES).unwrap_err(), AdapterError::missing("quotes[0].fees"));
+
+        let no_duration = QUOTE.replace("\"estimated\": 180.6", "\"estimated\": 0");
+        assert!(matches!(parse(&no_duration), Err(AdapterError::MalformedResponse { .. })));
+        let no_dst_amount = QUOTE.replace("\"dstAmount\"", "\"dstAmountRenamed\"");
+        assert!(matches!(parse(&no_dst_amount), Err(AdapterError::MalformedResponse { missing }) if missing.contains("dstAmount")));
+    }
+
+    #[test]
+    fn quotes_expire_with_their_earliest_step_or_the_configured_default() {
+        let edge = configured().parse_quote(&usdc_request(), serde_json::from_str(QUOTE_EXPIRING).unwrap()).unwrap();
+        assert_eq!(edge.valid_until, Some(1717442480));
+
+        let edge = configured().parse_quote(&usdc_request(), serde_json::from_str(QUOTE).unwrap()).unwrap();
+        assert_eq!(edge.valid_until, Some(edge.quoted_at + 60));
+
+        let config = format!("{}\n[extra]\nquote_validity_secs = 15\n", CONFIG);
+        let adapter = StargateAdapter::from_config(&toml::from_str(&config).unwrap()).unwrap();
+        let edge = adapter.parse_quote(&usdc_request(), serde_json::from_str(QUOTE).unwrap()).unwrap();
+        assert_eq!(edge.valid_until, Some(edge.quoted_at + 15));
+    }
+
+    #[tokio::test]
+    async fn depth_amounts_are_quoted_in_order() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/quotes"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(QUOTE))
+            .mount(&server)
+            .await;
+        let config = CONFIG.replace("http://localhost:8080/api/v1/", &server.uri());
+        let adapter = StargateAdapter::from_config(&toml::from_str(&config).unwrap()).unwrap();
+
+        let depth = adapter.fetch_depth(&usdc_request(), &[1.0, 5.0, 25.0]).await.unwrap();
+        assert_eq!(depth.iter().map(|(amount, _)| *amount).collect::<Vec<_>>(), [1.0, 5.0, 25.0]);
+
+        let mut sent: Vec<String> = server.received_requests().await.unwrap()
+            .iter()
+            .filter_map(|request| request.url.query_pairs().find(|(key, _)| key == "srcAmount").map(|(_, value)| value.into_owned()))
+            .collect();
+        sent.sort_by_key(|amount| amount.len());
+        assert_eq!(sent, ["1000000", "5000000", "25000000"]);
+    }
+
+    #[tokio::test]
+    async fn eighteen_decimal_amounts_reach_the_api_unrounded() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/quotes"))
+            .and(query_param("srcAmount", "9007199254740993123"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(QUOTE))
+            .mount(&server)
+            .await;
+        let config = CONFIG.replace("http://localhost:8080/api/v1/", &server.uri());
+        let adapter = StargateAdapter::from_config(&toml::from_str(&config).unwrap()).unwrap();
+        // WETH, whose 18 decimals put this amount past what f64 holds
+        let weth = QuoteRequest { src_token: "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2".to_string(), ..usdc_request() };
+        let amount = polypathroute_core::Amount::from_human("9.007199254740993123", 18).unwrap();
+
+        adapter.fetch_metrics(&weth.with_amount(amount)).await.unwrap();
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn hung_upstream_times_out() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/quotes"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_string(QUOTE)
+                .set_delay(Duration::from_secs(5)))
+            .mount(&server)
+            .await;
+
+        let config = CONFIG.replace("http://localhost:8080/api/v1/", &server.uri())
+            + "\n[extra]\nrequest_timeout_ms = 200\n\n[extra.retry]\nmax_attempts = 1\n";
+        let adapter = StargateAdapter::from_config(&toml::from_str(&config).unwrap()).unwrap();
+        let request = QuoteRequest::builder()
+            .src_chain("ethereum")
+            .dst_chain("polygon")
+            .src_token("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48")
+            .dst_token("0x3c499c542cef5e3811e1192ce70d8cc03d5c3359")
+            .src_amount("1")
+            .wallet("0xca699201b15ccef3b8c4012e28570cc5500d9f9a")
+            .build()
+            .unwrap();
+
+        let started = Instant::now();
+        let err = adapter.fetch_metrics(&request).await.unwrap_err();
+
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(err, AdapterError::Timeout { attempts: 1 });
+    }
+
+    #[tokio::test]
+    async fn upstream_failures_are_typed() {
+        let server = MockServer::start().await;
+        let config = CONFIG.replace("http://localhost:8080/api/v1/", &server.uri())
+            + "\n[extra.retry]\nmax_attempts = 1\n";
+        let adapter = StargateAdapter::from_config(&toml::from_str(&config).unwrap()).unwrap();
+        let request = QuoteRequest::builder()
+            .src_chain("ethereum")
+            .dst_chain("polygon")
+            .src_token("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48")
+            .dst_token("0x3c499c542cef5e3811e1192ce70d8cc03d5c3359")
+            .src_amount("1")
+            .wallet("0xca699201b15ccef3b8c4012e28570cc5500d9f9a")
+            .build()
+            .unwrap();
+
+        let cases = [
+            (ResponseTemplate::new(429).insert_header("Retry-After", "30"),
+                AdapterError::RateLimited { retry_after: Some(Duration::from_secs(30)) }),
+            (ResponseTemplate::new(503).set_body_string("maintenance"),
+                AdapterError::Upstream { status: 503, body_snippet: "maintenance".to_string() }),
+            (ResponseTemplate::new(200).set_body_string(QUOTE_EMPTY),
+                AdapterError::missing("quotes[0]")),
+        ];
+        for (response, expected) in cases {
+            server.reset().await;
+            Mock::given(method("GET"))
+                .and(path("/quotes"))
+                .respond_with(response)
+                .mount(&server)
+                .await;
+
+            assert_eq!(adapter.fetch_metrics(&request).await.unwrap_err(), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn transfers_are_tracked_to_delivery() {
+        const TX: &str = "0x8f2c6e4a1b3d5f7e9c0a2b4d6f8e1c3a5b7d9f0e2c4a6b8d1f3e5c7a9b0d2f4e";
+        let server = MockServer::start().await;
+        let config = CONFIG.replace("http://localhost:8080/api/v1/", &server.uri())
+            + "\n[extra.retry]\nmax_attempts = 1\n";
+        let adapter = StargateAdapter::from_config(&toml::from_str(&config).unwrap()).unwrap();
+        let reference = TransferReference::SourceTx(TX.to_string());
+        let unknown = AdapterError::UnknownTransfer { bridge: "stargate".to_string(), reference: reference.to_string() };
+
+        let cases = [
+            (ResponseTemplate::new(200).set_body_string(TRANSFER_CONFIRMING), Ok(TransferStatus::SourceConfirmed)),
+            (ResponseTemplate::new(200).set_body_string(TRANSFER_INFLIGHT), Ok(TransferStatus::InFlight { eta: Some(1_718_000_600) })),
+            (ResponseTemplate::new(200).set_body_string(TRANSFER_DELIVERED), Ok(TransferStatus::Delivered {
+                dst_tx: "0x1d3f5a7c9e0b2d4f6a8c1e3b5d7f9a0c2e4b6d8f1a3c5e7b9d0f2a4c6e8b1d3f".to_string(),
+            })),
+            (ResponseTemplate::new(200).set_body_string(TRANSFER_BLOCKED), Ok(TransferStatus::Stuck {
+                since: 1_718_000_420,
+                reason: "Nonce gap: an earlier message on this pathway is undelivered".to_string(),
+            })),
+            (ResponseTemplate::new(200).set_body_string(TRANSFER_UNKNOWN), Err(unknown.clone())),
+            (ResponseTemplate::new(404).set_body_string("not found"), Err(unknown)),
+            (ResponseTemplate::new(200).set_body_string(TRANSFER_INFLIGHT.replace("INFLIGHT", "REWOUND")),
+                Err(AdapterError::missing("a known data[0].status.name (got `REWOUND`)"))),
+        ];
+        for (response, expected) in cases {
+            server.reset().await;
+            Mock::given(method("GET"))
+                .and(path(format!("/transfers/tx/{}", TX)))
+                .respond_with(response)
+                .mount(&server)
+                .await;
+
+            assert_eq!(adapter.track_transfer(&reference).await, expected);
+        }
+
+        // Message ids are looked up by GUID
+        server.reset().await;
+        Mock::given(method("GET"))
+            .and(path("/transfers/guid/0x5c1e"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(TRANSFER_CONFIRMING))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let status = adapter.track_transfer(&TransferReference::MessageId("0x5c1e".to_string())).await;
+        assert_eq!(status, Ok(TransferStatus::SourceConfirmed));
+    }
+
+    #[tokio::test]
+    async fn health_check_reports_latency_and_auth_failures() {
+        let server = MockServer::start().await;
+        let config = CONFIG.replace("http://localhost:8080/api/v1/", &server.uri());
+        let adapter = StargateAdapter::from_config(&toml::from_str(&config).unwrap()).unwrap();
+
+        Mock::given(method("GET"))
+            .and(path("/chains"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_string(r#"{"chains":[]}"#)
+                .insert_header("x-ratelimit-remaining", "42"))
+            .mount(&server)
+            .await;
+        let health = adapter.health_check().await.unwrap();
+        assert!(health.reachable);
+        assert_eq!(health.rate_limit_remaining, Some(42));
+
+        server.reset().await;
+        Mock::given(method("GET"))
+            .and(path("/chains"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_string(r#"{"chains":[]}"#)
+                .set_delay(Duration::from_millis(200)))
+            .mount(&server)
+            .await;
+        let health = adapter.health_check().await.unwrap();
+        assert!(health.reachable);
+        assert!(health.latency >= Duration::from_millis(200));
+        assert_eq!(health.rate_limit_remaining, None);
+
+        server.reset().await;
+        Mock::given(method("GET"))
+            .and(path("/chains"))
+            .respond_with(ResponseTemplate::new(401).set_body_string("invalid api key"))
+            .mount(&server)
+            .await;
+        let health = adapter.health_check().await.unwrap();
+        assert!(!health.reachable);
+        assert_eq!(health.details, "401 Unauthorized: invalid api key");
+    }
+
+    #[tokio::test]
+    async fn chain_listing_is_fetched_once() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/chains"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(CHAINS))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let config = CONFIG.replace("http://localhost:8080/api/v1/", &server.uri());
+        let adapter = StargateAdapter::from_config(&toml::from_str(&config).unwrap()).unwrap();
+
+        let chains = adapter.supported_chains().await.unwrap();
+        assert_eq!(chains.len(), 5);
+        assert_eq!(chains[2], ChainInfo {
+            key: "arbitrum".to_string(),
+            chain_id: Some(42161),
+            name: "Arbitrum One".to_string(),
+            native_token: Some("ETH".to_string()),
+        });
+        assert_eq!(chains[4].chain_id, None);
+        assert_eq!(chains[4].native_token, None);
+
+        assert_eq!(adapter.supported_chains().await.unwrap(), chains);
+    }
+
+    #[tokio::test]
+    async fn chain_listing_follows_next_cursors() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/chains"))
+            .and(query_param("cursor", "page-2"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_string(r#"{"chains":[{"chainKey":"base","chainId":8453,"name":"Base"}]}"#))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/chains"))
+            .and(query_param_is_missing("cursor"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_string(r#"{"chains":[{"chainKey":"ethereum","chainId":1,"name":"Ethereum"}],"nextCursor":"page-2"}"#))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let config = CONFIG.replace("http://localhost:8080/api/v1/", &server.uri());
+        let adapter = StargateAdapter::from_config(&toml::from_str(&config).unwrap()).unwrap();
+
+        let chains = adapter.supported_chains().await.unwrap();
+        assert_eq!(chains.iter().map(|chain| chain.key.as_str()).collect::<Vec<_>>(), ["ethereum", "base"]);
+    }
+
+    // Records when each request reached the server
+    struct ArrivalRecorder(Arc<Mutex<Vec<Instant>>>);
+
+    impl Respond for ArrivalRecorder {
+        fn respond(&self, _request: &Request) -> ResponseTemplate {
+            self.0.lock().unwrap().push(Instant::now());
+            ResponseTemplate::new(200).set_body_string(QUOTE)
+        }
+    }
+
+    #[tokio::test]
+    async fn concurrent_fetches_respect_the_rate_limit() {
+        let server = MockServer::start().await;
+        let arrivals = Arc::new(Mutex::new(Vec::new()));
+        Mock::given(method("GET"))
+            .and(path("/quotes"))
+            .respond_with(ArrivalRecorder(Arc::clone(&arrivals)))
+            .mount(&server)
+            .await;
+
+        let config = CONFIG.replace("http://localhost:8080/api/v1/", &server.uri())
+            + "\n[extra]\nrequests_per_second = 5\n";
+        let adapter = StargateAdapter::from_config(&toml::from_str(&config).unwrap()).unwrap();
+        let request = QuoteRequest::builder()
+            .src_chain("ethereum")
+            .dst_chain("polygon")
+            .src_token("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48")
+            .dst_token("0x3c499c542cef5e3811e1192ce70d8cc03d5c3359")
+            .src_amount("1")
+            .wallet("0xca699201b15ccef3b8c4012e28570cc5500d9f9a")
+            .build()
+            .unwrap();
+
+        let results = futures::future::join_all((0..20).map(|_| adapter.fetch_metrics(&request))).await;
+        assert!(results.iter().all(|result| result.is_ok()));
+
+        let arrivals = arrivals.lock().unwrap();
+        let first = arrivals.iter().min().unwrap();
+        let last = arrivals.iter().max().unwrap();
+        assert_eq!(arrivals.len(), 20);
+        assert!(last.duration_since(*first) >= Duration::from_millis(3000));
+        assert_eq!(adapter.rate_limiter().unwrap().requests_per_second(), 5.0);
+    }
+}
@@ -0,0 +1,287 @@
+use super::{
+    AdapterContext,
+    fixture_request,
+    FixtureQuote,
+    BridgeAdapter,
+    BridgeEdge,
+    QuoteRequest,
+    SupportedPair,
+    RiskModel,
+    unix_now,
+    FeeComponent,
+    chains::evm_chain_id,
+    settings::AdapterSettings,
+    AdapterError,
+    AdapterHealth,
+    health::probe,
+    MetricsRecorder,
+    RateLimiter,
+    RetryPolicy,
+    TokenDecimals
+};
+use crate::fx::{CurrencyId, Money};
+
+use std::time::Duration;
+use std::sync::Arc;
+use async_trait::async_trait;
+use polypathroute_core::{BridgeConfig, Redacted};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::Value;
+use anyhow::Result;
+
+// ethers-style `{"type": "BigNumber", "hex": "0x..."}`
+#[derive(Deserialize, Debug, Clone)]
+struct BigNumber {
+    hex: String,
+}
+
+impl BigNumber {
+    fn to_f64(&self, field: &str) -> Result<f64, AdapterError> {
+        let digits = self.hex.trim_start_matches("0x");
+        u128::from_str_radix(digits, 16)
+            .map(|v| v as f64)
+            .map_err(|_| AdapterError::missing(format!("a hex value in {} (got `{}`)", field, self.hex)))
+    }
+}
+
+// One entry of the /bridge response. Synapse returns a quote per bridge module
+// (SynapseRFQ, SynapseCCTP, ...); only the fields we price on are read.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct SynapseQuote {
+    fee_amount: BigNumber,
+    max_amount_out: BigNumber,
+    estimated_time: f64,
+}
+
+pub struct SynapseAdapter {
+    pub name: String,
+    pub base_url: String,
+    api_key: Option<Redacted<String>>,
+    retry: RetryPolicy,
+    rate_limiter: Option<RateLimiter>,
+    client: Client,
+    pairs: Vec<SupportedPair>,
+    decimals: TokenDecimals,
+    risk_model: Arc<dyn RiskModel>,
+    telemetry: Arc<MetricsRecorder>,
+    // How long quotes stay usable when the API reports no expiry
+    quote_validity: Duration
+}
+
+impl SynapseAdapter {
+    pub fn from_config(config: &BridgeConfig) -> Result<Self> {
+        Self::new(AdapterContext::from_config("synapse", config)?)
+    }
+
+    pub fn new(context: AdapterContext) -> Result<Self> {
+        let settings = AdapterSettings::from_config("synapse", &context.config)?;
+        let client = context.client;
+        let pairs = settings.pairs
+            .into_iter()
+            .filter(|pair| evm_chain_id(&pair.src_chain).is_some() && evm_chain_id(&pair.dst_chain).is_some())
+            .collect();
+
+        Ok(Self {
+            name: "synapse".to_string(),
+            base_url: settings.base_url,
+            api_key: settings.api_key,
+            retry: settings.retry,
+            rate_limiter: settings.rate_limiter,
+            client,
+            pairs,
+            decimals: settings.decimals,
+            risk_model: settings.risk_model,
+            telemetry: settings.telemetry,
+            quote_validity: settings.quote_validity
+        })
+    }
+
+    // Replaces the risk model built from config
+    pub fn with_risk_model(mut self, risk_model: Arc<dyn RiskModel>) -> Self {
+        self.risk_model = risk_model;
+        self
+    }
+
+    pub fn bridge_url(&self) -> String {
+        format!("{}/bridge", self.base_url)
+    }
+
+    pub fn token_list_url(&self) -> String {
+        format!("{}/tokenList", self.base_url)
+    }
+
+    // Picks the module delivering the most (ties go to the lower fee).
+    // cost = feeAmount in origin token units, speed = estimatedTime (seconds),
+    // liquidity = maxAmountOut in destination token units.
+    // An empty list means no module bridges the route.
+    fn parse_quotes(&self, request: &QuoteRequest, response: Value) -> Result<BridgeEdge, AdapterError> {
+        let quotes: Vec<SynapseQuote> = serde_json::from_value(response)?;
+
+        let mut best: Option<(SynapseQuote, f64, f64)> = None;
+        for quote in quotes {
+            let out = quote.max_amount_out.to_f64("maxAmountOut")?;
+            let fee = quote.fee_amount.to_f64("feeAmount")?;
+            let better = match &best {
+                Some((_, best_out, best_fee)) => out > *best_out || (out == *best_out && fee < *best_fee),
+                None => true,
+            };
+            if better {
+                best = Some((quote, out, fee));
+            }
+        }
+
+        let (quote, out, fee) = best.ok_or_else(|| AdapterError::unsupported_pair(&self.name, request))?;
+
+        let cost = self.decimals.to_human(&request.src_chain, &request.src_token, fee)?;
+        let out = self.decimals.to_human(&request.dst_chain, &request.dst_token, out)?;
+
+        Ok(self.risk_model.score(&self.name, request, BridgeEdge {
+            from: request.src_chain.clone(),
+            to: request.dst_chain.clone(),
+            cost,
+            speed: quote.estimated_time,
+            liquidity: out,
+            via: None,
+            bridge: self.name.clone(),
+            estimated_output: out,
+            fee_components: vec![FeeComponent { name: "bridge".to_string(), amount: Money::new(cost, CurrencyId::token(&request.src_chain, &request.src_token)) }],
+            quoted_at: unix_now(),
+            ..BridgeEdge::default()
+        }).with_default_validity(self.quote_validity))
+    }
+}
+
+#[async_trait]
+impl BridgeAdapter for SynapseAdapter {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn supported_pairs(&self) -> Vec<SupportedPair> {
+        self.pairs.clone()
+    }
+
+    fn rate_limiter(&self) -> Option<&RateLimiter> {
+        self.rate_limiter.as_ref()
+    }
+
+    fn telemetry(&self) -> Option<&MetricsRecorder> {
+        Some(&self.telemetry)
+    }
+
+    async fn health_check(&self) -> Result<AdapterHealth, AdapterError> {
+        let request = self.client.get(self.token_list_url());
+        let request = match &self.api_key {
+            Some(key) => request.header("x-api-key", key.expose()),
+            None => request
+        };
+        probe(self.rate_limiter.as_ref(), request).await
+    }
+
+    fn replay_fixture(&self) -> Option<FixtureQuote> {
+        const QUOTES: &str = include_str!("../../fixtures/synapse/quotes.json");
+        let request = fixture_request("ethereum", "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", "arbitrum", "0xaf88d065e77c8cc2239327c5edb3a432268e5831", "1000");
+        let result = serde_json::from_str(QUOTES).map_err(AdapterError::from).and_then(|response| self.parse_quotes(&request, response));
+        Some(FixtureQuote { request, result })
+    }
+
+    async fn fetch_metrics(&self, request: &QuoteRequest) -> Result<BridgeEdge, AdapterError> {
+        let (from_chain, to_chain) = match (evm_chain_id(&request.src_chain), evm_chain_id(&request.dst_chain)) {
+            (Some(from_chain), Some(to_chain)) => (from_chain.to_string(), to_chain.to_string()),
+            _ => return Err(AdapterError::unsupported_pair(&self.name, request)),
+        };
+
+        let amount = request.src_amount_in(&self.decimals)?.to_raw_string();
+        let params = [
+            ("fromChain", from_chain.as_str()),
+            ("toChain", to_chain.as_str()),
+            ("fromToken", request.src_token.as_str()),
+            ("toToken", request.dst_token.as_str()),
+            ("amount", amount.as_str()),
+        ];
+
+        let response: Value = self.retry
+            .send(self.rate_limiter.as_ref(), || {
+                let request = self.client.get(self.bridge_url()).query(&params);
+                match &self.api_key {
+                    Some(key) => request.header("x-api-key", key.expose()),
+                    None => request
+                }
+            })
+            .await?
+            .json()
+            .await?;
+
+        self.parse_quotes(request, response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{Mock, MockServer, ResponseTemplate, matchers::{method, path, query_param}};
+
+    const QUOTES: &str = include_str!("../../fixtures/synapse/quotes.json");
+    const EMPTY: &str = include_str!("../../fixtures/synapse/empty.json");
+    const MISSING_FEE: &str = include_str!("../../fixtures/synapse/quotes_missing_fee.json");
+
+    fn adapter(base_url: &str) -> SynapseAdapter {
+        let config = format!("base_url = \"{}\"\nchains = [\"ethereum\", \"arbitrum\"]\n", base_url);
+        SynapseAdapter::from_config(&toml::from_str(&config).unwrap()).unwrap()
+    }
+
+    fn request() -> QuoteRequest {
+        QuoteRequest::builder()
+            .src_chain("ethereum")
+            .dst_chain("arbitrum")
+            .src_token("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48")
+            .dst_token("0xaf88d065e77c8cC2239327C5EDb3A432268e5831")
+            .src_amount("1000")
+            .wallet("0xca699201b15ccef3b8c4012e28570cc5500d9f9a")
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn picks_the_quote_delivering_most() {
+        let edge = adapter("https://synapse.test")
+            .parse_quotes(&request(), serde_json::from_str(QUOTES).unwrap())
+            .unwrap();
+
+        // SynapseRFQ: 999.045808 out for a 0.4 fee
+        assert_eq!(edge.cost, 0.4);
+        assert_eq!(edge.speed, 60.0);
+        assert_eq!(edge.liquidity, 999.045808);
+    }
+
+    #[test]
+    fn empty_and_malformed_responses_are_errors() {
+        let adapter = adapter("https://synapse.test");
+
+        let err = adapter.parse_quotes(&request(), serde_json::from_str(EMPTY).unwrap()).unwrap_err();
+        assert_eq!(err, AdapterError::unsupported_pair("synapse", &request()));
+
+        let err = adapter.parse_quotes(&request(), serde_json::from_str(MISSING_FEE).unwrap()).unwrap_err();
+        assert_eq!(err, AdapterError::missing("feeAmount"));
+    }
+
+    #[tokio::test]
+    async fn requests_use_chain_ids_and_config_base_url() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/bridge"))
+            .and(query_param("fromChain", "1"))
+            .and(query_param("toChain", "42161"))
+            .and(query_param("amount", "1000000000"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(QUOTES))
+            .mount(&server)
+            .await;
+
+        let edge = adapter(&server.uri()).fetch_metrics(&request()).await.unwrap();
+        assert_eq!(edge.from, "ethereum");
+        assert_eq!(edge.to, "arbitrum");
+        assert_eq!(edge.liquidity, 999.045808);
+    }
+}
@@ -0,0 +1,200 @@
+use super::{AdapterError, Disposition, unix_now};
+use std::{collections::{BTreeMap, VecDeque}, sync::Mutex, time::Duration};
+use serde::Serialize;
+
+// Latency samples kept for percentiles; older samples are dropped first
+const LATENCY_SAMPLES: usize = 1024;
+// Requests recent_error_rate is taken over
+const RECENT_REQUESTS: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LastError {
+    // Unix seconds
+    pub at: u64,
+    pub message: String,
+}
+
+// Snapshot of what an adapter has been doing since it was built
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct AdapterMetrics {
+    pub requests: u64,
+    pub errors: u64,
+    // Keyed by AdapterError::kind
+    pub errors_by_kind: BTreeMap<String, u64>,
+    pub latency_p50_ms: Option<f64>,
+    pub latency_p95_ms: Option<f64>,
+    pub last_error: Option<LastError>,
+    pub retries: u64,
+    pub rate_limit_waits: u64,
+    // Requests refused by an open circuit breaker, never sent upstream
+    pub circuit_rejections: u64,
+    pub circuit_opens: u64,
+    // Share of the last RECENT_REQUESTS requests that failed, leaving out those for pairs the
+    // adapter doesn't serve; None before the first
+    pub recent_error_rate: Option<f64>,
+}
+
+impl AdapterMetrics {
+    // Share of requests that failed, None before the first
+    pub fn error_rate(&self) -> Option<f64> {
+        (self.requests > 0).then(|| self.errors as f64 / self.requests as f64)
+    }
+}
+
+// Metrics of every adapter in use, with totals across them
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MetricsReport {
+    pub adapters: BTreeMap<String, AdapterMetrics>,
+    pub requests: u64,
+    pub errors: u64,
+}
+
+impl MetricsReport {
+    pub fn new(adapters: BTreeMap<String, AdapterMetrics>) -> Self {
+        Self {
+            requests: adapters.values().map(|metrics| metrics.requests).sum(),
+            errors: adapters.values().map(|metrics| metrics.errors).sum(),
+            adapters,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Recorded {
+    metrics: AdapterMetrics,
+    latencies_ms: VecDeque<f64>,
+    // Whether each of the last RECENT_REQUESTS requests failed, see recent_error_rate
+    recent_failures: VecDeque<bool>,
+}
+
+// Collects an adapter's telemetry. Shared with its retry policy, which reports retries and
+// rate-limit waits; quotes, errors and latency are recorded by the circuit breaker layer.
+#[derive(Debug, Default)]
+pub struct MetricsRecorder {
+    recorded: Mutex<Recorded>,
+}
+
+impl MetricsRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record<T>(&self, latency: Duration, result: &Result<T, AdapterError>) {
+        let mut recorded = self.recorded.lock().unwrap();
+        recorded.metrics.requests += 1;
+        if recorded.latencies_ms.len() == LATENCY_SAMPLES {
+            recorded.latencies_ms.pop_front();
+        }
+        recorded.latencies_ms.push_back(latency.as_micros() as f64 / 1000.0);
+
+        if !matches!(result, Err(err) if err.disposition() == Disposition::Drop) {
+            if recorded.recent_failures.len() == RECENT_REQUESTS {
+                recorded.recent_failures.pop_front();
+            }
+            recorded.recent_failures.push_back(result.is_err());
+        }
+
+        if let Err(err) = result {
+            let metrics = &mut recorded.metrics;
+            metrics.errors += 1;
+            *metrics.errors_by_kind.entry(err.kind().to_string()).or_default() += 1;
+            metrics.last_error = Some(LastError { at: unix_now(), message: err.to_string() });
+        }
+    }
+
+    pub fn record_retry(&self) {
+        self.recorded.lock().unwrap().metrics.retries += 1;
+    }
+
+    pub fn record_rate_limit_wait(&self) {
+        self.recorded.lock().unwrap().metrics.rate_limit_waits += 1;
+    }
+
+    pub fn record_circuit_rejection(&self) {
+        self.recorded.lock().unwrap().metrics.circuit_rejections += 1;
+    }
+
+    pub fn record_circuit_open(&self) {
+        self.recorded.lock().unwrap().metrics.circuit_opens += 1;
+    }
+
+    pub fn snapshot(&self) -> AdapterMetrics {
+        let recorded = self.recorded.lock().unwrap();
+        let mut latencies: Vec<f64> = recorded.latencies_ms.iter().copied().collect();
+        latencies.sort_by(f64::total_cmp);
+
+        AdapterMetrics {
+            latency_p50_ms: percentile(&latencies, 0.50),
+            latency_p95_ms: percentile(&latencies, 0.95),
+            recent_error_rate: (!recorded.recent_failures.is_empty())
+                .then(|| recorded.recent_failures.iter().filter(|failed| **failed).count() as f64 / recorded.recent_failures.len() as f64),
+            ..recorded.metrics.clone()
+        }
+    }
+}
+
+// Nearest-rank percentile of sorted samples
+fn percentile(sorted: &[f64], p: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_use_nearest_rank() {
+        let samples: Vec<f64> = (1..=100).map(f64::from).collect();
+        assert_eq!(percentile(&samples, 0.50), Some(50.0));
+        assert_eq!(percentile(&samples, 0.95), Some(95.0));
+        assert_eq!(percentile(&[7.0], 0.95), Some(7.0));
+        assert_eq!(percentile(&[], 0.5), None);
+    }
+
+    #[test]
+    fn errors_are_counted_by_kind() {
+        let recorder = MetricsRecorder::new();
+        recorder.record::<()>(Duration::from_millis(10), &Ok(()));
+        recorder.record::<()>(Duration::from_millis(20), &Err(AdapterError::upstream(503, "down")));
+        recorder.record::<()>(Duration::from_millis(30), &Err(AdapterError::Timeout { attempts: 3 }));
+        recorder.record::<()>(Duration::from_millis(40), &Err(AdapterError::upstream(502, "bad gateway")));
+
+        let metrics = recorder.snapshot();
+        assert_eq!(metrics.requests, 4);
+        assert_eq!(metrics.errors, 3);
+        assert_eq!(metrics.errors_by_kind["upstream"], 2);
+        assert_eq!(metrics.errors_by_kind["timeout"], 1);
+        assert_eq!(metrics.last_error.unwrap().message, "upstream returned 502: bad gateway");
+        assert_eq!(metrics.latency_p50_ms, Some(20.0));
+    }
+
+    #[test]
+    fn the_recent_error_rate_forgets_old_failures_and_unserved_pairs() {
+        let recorder = MetricsRecorder::new();
+        for _ in 0..RECENT_REQUESTS {
+            recorder.record::<()>(Duration::ZERO, &Err(AdapterError::Timeout { attempts: 3 }));
+        }
+        assert_eq!(recorder.snapshot().recent_error_rate, Some(1.0));
+
+        for _ in 0..RECENT_REQUESTS - 10 {
+            recorder.record::<()>(Duration::ZERO, &Ok(()));
+        }
+        let unsupported = AdapterError::UnsupportedPair {
+            bridge: "relay".to_string(),
+            src_chain: "ethereum".to_string(),
+            dst_chain: "base".to_string(),
+            src_token: "usdc".to_string(),
+            dst_token: "usdc".to_string(),
+        };
+        for _ in 0..50 {
+            recorder.record::<()>(Duration::ZERO, &Err(unsupported.clone()));
+        }
+        let metrics = recorder.snapshot();
+        assert_eq!(metrics.recent_error_rate, Some(0.1));
+        assert_eq!(metrics.error_rate(), Some(150.0 / 240.0));
+    }
+}
@@ -0,0 +1,65 @@
+// Where a transfer stands once its source transaction is sent, as the bridge carrying it tells.
+// Read only: nothing here signs or submits anything. See BridgeAdapter::track_transfer.
+
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+// What a bridge looks a transfer up by
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "id", rename_all = "snake_case")]
+pub enum TransferReference {
+    // Hash of the transaction sent on the source chain
+    SourceTx(String),
+    // The bridge's own id for the message carrying the transfer, e.g. a LayerZero GUID
+    MessageId(String),
+}
+
+impl TransferReference {
+    pub fn id(&self) -> &str {
+        match self {
+            TransferReference::SourceTx(id) | TransferReference::MessageId(id) => id,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TransferReference::SourceTx(_) => "source_tx",
+            TransferReference::MessageId(_) => "message_id",
+        }
+    }
+}
+
+impl fmt::Display for TransferReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.kind(), self.id())
+    }
+}
+
+// Times in unix seconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum TransferStatus {
+    // The source transaction landed and the bridge is waiting for it to be final
+    SourceConfirmed,
+    // On its way to the destination chain, due by `eta` when the bridge says
+    InFlight { eta: Option<u64> },
+    Delivered { dst_tx: String },
+    // Not moved on since `since`, for the bridge's `reason`
+    Stuck { since: u64, reason: String },
+}
+
+impl TransferStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransferStatus::SourceConfirmed => "source_confirmed",
+            TransferStatus::InFlight { .. } => "in_flight",
+            TransferStatus::Delivered { .. } => "delivered",
+            TransferStatus::Stuck { .. } => "stuck",
+        }
+    }
+
+    // Whether the status can't change any more. Stuck transfers may still be delivered.
+    pub fn is_final(&self) -> bool {
+        matches!(self, TransferStatus::Delivered { .. })
+    }
+}
@@ -0,0 +1,625 @@
+use super::{
+    AdapterContext,
+    fixture_request,
+    FixtureQuote,
+    BridgeAdapter,
+    BridgeEdge,
+    ChainInfo,
+    QuoteRequest,
+    SupportedPair,
+    RiskContext,
+    RiskModel,
+    BridgeStatus,
+    unix_now,
+    FeeComponent,
+    settings::AdapterSettings,
+    AdapterError,
+    AdapterHealth,
+    health::probe,
+    MetricsRecorder,
+    RateLimiter,
+    RetryPolicy,
+    TokenDecimals,
+};
+use crate::fx::{CurrencyId, FxConverter, Money};
+
+use std::time::{Duration, Instant};
+use std::sync::{Arc, Mutex, RwLock};
+use async_trait::async_trait;
+use polypathroute_core::{BridgeConfig, Redacted};
+use reqwest::Client;
+use serde_json::Value;
+use anyhow::{Result, anyhow};
+
+mod status;
+pub mod translate;
+
+use status::GuardianStatus;
+use translate::{CHAINS, WrappedAssets, from_wormhole_chain_id, is_evm_chain, to_universal_address, to_wormhole_chain_id, universal_hex};
+
+pub struct WormholeAdapter {
+    pub name: String,
+    pub base_url: String,
+    api_key: Option<Redacted<String>>,
+    retry: RetryPolicy,
+    rate_limiter: Option<RateLimiter>,
+    client: Client,
+    pairs: RwLock<Vec<SupportedPair>>,
+    // Origins of wrapped tokens, seeded from `[[extra.wrapped_assets]]`
+    wrapped: RwLock<WrappedAssets>,
+    decimals: TokenDecimals,
+    risk_model: Arc<dyn RiskModel>,
+    telemetry: Arc<MetricsRecorder>,
+    // How long quotes stay usable when the API reports no expiry
+    quote_validity: Duration,
+    // Guardian heartbeat and governor limit endpoints, from `extra`; neither is polled unless set
+    heartbeats_url: Option<String>,
+    governor_url: Option<String>,
+    // Last guardian status fetched and when; None when it couldn't be fetched
+    status: Mutex<Option<(Instant, Option<GuardianStatus>)>>,
+    // Prices amounts in USD against the governor's limits
+    fx: Option<Arc<dyn FxConverter>>,
+}
+
+// How long a fetched guardian status is reused
+const STATUS_TTL: Duration = Duration::from_secs(60);
+
+impl WormholeAdapter {
+    pub fn from_config(config: &BridgeConfig) -> Result<Self> {
+        Self::new(AdapterContext::from_config("wormhole", config)?)
+    }
+
+    // Pairs on chains without a Wormhole chain id can never be quoted and are dropped
+    pub fn new(context: AdapterContext) -> Result<Self> {
+        let settings = AdapterSettings::from_config("wormhole", &context.config)?;
+        let client = context.client;
+        let pairs = settings.pairs
+            .into_iter()
+            .filter(|pair| to_wormhole_chain_id(&pair.src_chain).is_ok() && to_wormhole_chain_id(&pair.dst_chain).is_ok())
+            .collect();
+        let wrapped = WrappedAssets::from_config("wormhole", &context.config)?;
+        let url = |key: &str| -> Result<Option<String>> {
+            match context.config.extra.as_ref().and_then(|extra| extra.get(key)) {
+                Some(value) => value
+                    .as_str()
+                    .map(|url| Some(url.trim_end_matches('/').to_string()))
+                    .ok_or_else(|| anyhow!("bridges.wormhole.extra.{} must be a string", key)),
+                None => Ok(None),
+            }
+        };
+        let (heartbeats_url, governor_url) = (url("heartbeats_url")?, url("governor_url")?);
+        let fx = context.fx.filter(|fx| fx.quote_currency().eq_ignore_ascii_case("USD"));
+        if governor_url.is_some() && fx.is_none() {
+            return Err(anyhow!("bridges.wormhole.extra.governor_url: governor limits are in USD, which needs [fx] with quote_currency = \"USD\""));
+        }
+
+        Ok(Self {
+            name: "wormhole".to_string(),
+            base_url: settings.base_url,
+            api_key: settings.api_key,
+            retry: settings.retry,
+            rate_limiter: settings.rate_limiter,
+            client,
+            pairs: RwLock::new(pairs),
+            wrapped: RwLock::new(wrapped),
+            decimals: settings.decimals,
+            risk_model: settings.risk_model,
+            telemetry: settings.telemetry,
+            quote_validity: settings.quote_validity,
+            heartbeats_url,
+            governor_url,
+            status: Mutex::new(None),
+            fx,
+        })
+    }
+
+    // Replaces the risk model built from config
+    pub fn with_risk_model(mut self, risk_model: Arc<dyn RiskModel>) -> Self {
+        self.risk_model = risk_model;
+        self
+    }
+
+    pub fn wrapped_assets(&self) -> WrappedAssets {
+        self.wrapped.read().unwrap().clone()
+    }
+
+    // Swaps in a table refreshed from elsewhere, e.g. Wormhole's token list
+    pub fn set_wrapped_assets(&self, wrapped: WrappedAssets) {
+        *self.wrapped.write().unwrap() = wrapped;
+    }
+
+    // What one unit of the request's source token is worth in USD, priced by the symbol its
+    // pair was configured with
+    async fn usd_price(&self, request: &QuoteRequest) -> Result<f64, AdapterError> {
+        let Some(fx) = &self.fx else {
+            return Err(AdapterError::Config("governor limits need [fx] to price transfers in USD".to_string()));
+        };
+        let symbol = self.pairs
+            .read()
+            .unwrap()
+            .iter()
+            .find(|pair| pair.src_chain.eq_ignore_ascii_case(&request.src_chain) && pair.src_token.eq_ignore_ascii_case(&request.src_token))
+            .and_then(|pair| pair.token_symbol.clone());
+        let Some(symbol) = symbol else {
+            return Err(AdapterError::Config(format!("no symbol configured for {}:{} to price against the governor limit", request.src_chain, request.src_token)));
+        };
+        fx.rate(&symbol).await.map_err(|err| AdapterError::Config(format!("cannot price {} against the governor limit: {}", symbol, err)))
+    }
+
+    pub fn quote_url(&self) -> String {
+        format!("{}/portal/quote", self.base_url)
+    }
+
+    pub fn health_url(&self) -> String {
+        format!("{}/health", self.base_url)
+    }
+
+    // The guardians' status, fetched at most every STATUS_TTL. None when no status endpoint is
+    // configured or none answered; quotes then go by the Portal's own estimates.
+    async fn guardian_status(&self) -> Option<GuardianStatus> {
+        if self.heartbeats_url.is_none() && self.governor_url.is_none() {
+            return None;
+        }
+        if let Some((fetched_at, status)) = self.status.lock().unwrap().as_ref()
+            && fetched_at.elapsed() < STATUS_TTL
+        {
+            return status.clone();
+        }
+
+        let mut status = None;
+        if let Some(url) = &self.heartbeats_url
+            && let Ok(with_heartbeats) = self.fetch_status(url).await.and_then(|body| GuardianStatus::default().with_heartbeats(&body))
+        {
+            status = Some(with_heartbeats);
+        }
+        if let Some(url) = &self.governor_url
+            && let Ok(with_governor) = self.fetch_status(url).await.and_then(|body| status.clone().unwrap_or_default().with_governor(&body))
+        {
+            status = Some(with_governor);
+        }
+        *self.status.lock().unwrap() = Some((Instant::now(), status.clone()));
+        status
+    }
+
+    async fn fetch_status(&self, url: &str) -> Result<String, AdapterError> {
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.acquire().await;
+        }
+        let request = self.client.get(url);
+        let request = match &self.api_key {
+            Some(key) => request.header("x-api-key", key.expose()),
+            None => request
+        };
+        let response = request.send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+        match status.is_success() {
+            true => Ok(body),
+            false => Err(AdapterError::upstream(status.as_u16(), &body)),
+        }
+    }
+
+    // Maps a Portal quote body onto a BridgeEdge.
+    // cost = relayer + protocol fee in source token units, speed = source finality + guardian
+    // signing time (seconds), liquidity in destination token units. While the guardians are
+    // degraded on the source chain their backlog adds to the speed and the risk model hears of it.
+    fn parse_quote(&self, request: &QuoteRequest, response: &Value, status: Option<BridgeStatus>) -> Result<BridgeEdge, AdapterError> {
+        let quote = response
+                    .get("quote")
+                    .ok_or_else(|| AdapterError::missing("quote"))?;
+
+        let amount = |field: &str| -> Option<f64> {
+            quote.get(field)
+                .and_then(|v| v.as_str())
+                .and_then(|s| s.parse::<f64>().ok())
+        };
+
+        let relayer_fee = amount("relayerFee").ok_or_else(|| AdapterError::missing("relayerFee"))?;
+        let protocol_fee = amount("protocolFee").unwrap_or(0.0);
+        let fee = |name: &str, raw: f64| -> Result<FeeComponent, AdapterError> {
+            Ok(FeeComponent {
+                name: name.to_string(),
+                amount: Money::new(self.decimals.to_human(&request.src_chain, &request.src_token, raw)?, CurrencyId::token(&request.src_chain, &request.src_token)),
+            })
+        };
+        let fee_components = vec![fee("relayer", relayer_fee)?, fee("protocol", protocol_fee)?];
+        let cost = self.decimals.to_human(&request.src_chain, &request.src_token, relayer_fee + protocol_fee)?;
+
+        let eta = quote.get("eta").ok_or_else(|| AdapterError::missing("eta"))?;
+        let finality = eta.get("finalitySeconds").and_then(|v| v.as_f64()).unwrap_or(0.0);
+        let guardian = eta.get("guardianSeconds").and_then(|v| v.as_f64()).unwrap_or(0.0);
+        let backlog = status.as_ref().filter(|status| status.degraded).map_or(0.0, |status| status.backlog_estimate.as_secs_f64());
+        let speed = finality + guardian + backlog;
+
+        let reported = amount("destinationLiquidity");
+        let liquidity = reported
+                            .or_else(|| amount("amountOut"))
+                            .ok_or_else(|| AdapterError::missing("amountOut"))?;
+        let liquidity = self.decimals.to_human(&request.dst_chain, &request.dst_token, liquidity)?;
+        let estimated_output = amount("amountOut")
+                                .map(|raw| self.decimals.to_human(&request.dst_chain, &request.dst_token, raw))
+                                .transpose()?
+                                .unwrap_or(liquidity);
+
+        // Prefer the chain keys echoed back by the API, fall back to the requested ones
+        let from = quote.get("sourceChain")
+                        .and_then(|v| v.as_u64())
+                        .and_then(|id| from_wormhole_chain_id(id as u16).ok())
+                        .unwrap_or(&request.src_chain);
+        let to = quote.get("targetChain")
+                        .and_then(|v| v.as_u64())
+                        .and_then(|id| from_wormhole_chain_id(id as u16).ok())
+                        .unwrap_or(&request.dst_chain);
+
+        let context = RiskContext::for_request(request).with_bridge_status(status);
+        Ok(self.risk_model.score_with(&self.name, &context, BridgeEdge {
+            from: from.to_string(),
+            to: to.to_string(),
+            cost,
+            speed,
+            liquidity,
+            liquidity_unknown: reported.is_none(),
+            via: None,
+            bridge: self.name.clone(),
+            estimated_output,
+            fee_components,
+            quoted_at: unix_now(),
+            ..BridgeEdge::default()
+        }).with_default_validity(self.quote_validity))
+    }
+}
+
+#[async_trait]
+impl BridgeAdapter for WormholeAdapter {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn supported_pairs(&self) -> Vec<SupportedPair> {
+        self.pairs.read().unwrap().clone()
+    }
+
+    fn rate_limiter(&self) -> Option<&RateLimiter> {
+        self.rate_limiter.as_ref()
+    }
+
+    fn telemetry(&self) -> Option<&MetricsRecorder> {
+        Some(&self.telemetry)
+    }
+
+    // Wormhole has no chain listing endpoint; the table the adapter quotes with is the listing
+    async fn supported_chains(&self) -> Result<Vec<ChainInfo>, AdapterError> {
+        Ok(CHAINS
+            .iter()
+            .map(|(key, id, name, native_token, _)| ChainInfo {
+                key: key.to_string(),
+                chain_id: Some(u64::from(*id)),
+                name: name.to_string(),
+                native_token: Some(native_token.to_string()),
+            })
+            .collect())
+    }
+
+    async fn health_check(&self) -> Result<AdapterHealth, AdapterError> {
+        let request = self.client.get(self.health_url());
+        let request = match &self.api_key {
+            Some(key) => request.header("x-api-key", key.expose()),
+            None => request
+        };
+        let mut health = probe(self.rate_limiter.as_ref(), request).await?;
+        if let Some(status) = self.guardian_status().await {
+            for (chain, status) in status.degraded_chains() {
+                health.details.push_str(&format!("; degraded on {}: {}", chain, status.notes.join(", ")));
+            }
+        }
+        Ok(health)
+    }
+
+    // Chains without a Wormhole chain id and EVM token addresses that can't be made universal
+    // fail as unsupported pairs before anything is sent
+    fn replay_fixture(&self) -> Option<FixtureQuote> {
+        const QUOTE: &str = include_str!("../../../fixtures/wormhole/quote.json");
+        let request = fixture_request("ethereum", "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", "polygon", "0x3c499c542cef5e3811e1192ce70d8cc03d5c3359", "1");
+        let result = serde_json::from_str(QUOTE).map_err(AdapterError::from).and_then(|response| self.parse_quote(&request, &response, None));
+        Some(FixtureQuote { request, result })
+    }
+
+    async fn fetch_metrics(&self, request: &QuoteRequest) -> Result<BridgeEdge, AdapterError> {
+        let unsupported = |_| AdapterError::unsupported_pair(&self.name, request);
+        let source_id = to_wormhole_chain_id(&request.src_chain).map_err(unsupported)?;
+        let source_chain = source_id.to_string();
+        let target_chain = to_wormhole_chain_id(&request.dst_chain).map_err(unsupported)?.to_string();
+        let token = |chain: &str, address: &str| -> Result<String, AdapterError> {
+            match is_evm_chain(chain) {
+                true => to_universal_address(address).map(|universal| universal_hex(&universal)).map_err(unsupported),
+                false => Ok(address.to_string()),
+            }
+        };
+        let source_token = token(&request.src_chain, &request.src_token)?;
+        let target_token = token(&request.dst_chain, &request.dst_token)?;
+        // Wrapped tokens are quoted as the asset they wrap
+        let (origin_key, origin_address) = self.wrapped.read().unwrap().origin(&request.src_chain, &request.src_token);
+        let origin_chain = to_wormhole_chain_id(&origin_key).map_err(unsupported)?.to_string();
+        let origin_token = token(&origin_key, &origin_address)?;
+
+        // The governor would hold the transfer back, maybe for a day. Its limits are in USD,
+        // so the amount is priced at the [fx] rate of the pair's token.
+        let status = self.guardian_status().await;
+        if let Some(limit) = status.as_ref().and_then(|status| status.governor_limit(source_id))
+            && let Ok(amount) = request.src_amount.parse::<f64>()
+        {
+            let price = self.usd_price(request).await?;
+            if amount * price > limit {
+                return Err(AdapterError::AmountOutOfRange { min: None, max: Some(limit / price) });
+            }
+        }
+
+        let amount = request.src_amount_in(&self.decimals)?.to_raw_string();
+        let params = [
+            ("sourceChain", source_chain.as_str()),
+            ("targetChain", target_chain.as_str()),
+            ("sourceToken", source_token.as_str()),
+            ("targetToken", target_token.as_str()),
+            ("originChain", origin_chain.as_str()),
+            ("originToken", origin_token.as_str()),
+            ("amount", amount.as_str()),
+        ];
+
+        let response: Value = self.retry
+            .send(self.rate_limiter.as_ref(), || {
+                let request = self.client.get(self.quote_url()).query(&params);
+                match &self.api_key {
+                    Some(key) => request.header("x-api-key", key.expose()),
+                    None => request
+                }
+            })
+            .await?
+            .json()
+            .await?;
+
+        self.parse_quote(request, &response, status.and_then(|status| status.bridge_status(source_id)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::{DefaultRiskModel, RiskContext};
+
+    const QUOTE: &str = include_str!("../../../fixtures/wormhole/quote.json");
+    const QUOTE_MISSING_FEE: &str = include_str!("../../../fixtures/wormhole/quote_missing_fee.json");
+    const HEARTBEATS: &str = include_str!("../../../fixtures/wormhole/heartbeats.json");
+    const HEARTBEATS_DEGRADED: &str = include_str!("../../../fixtures/wormhole/heartbeats_degraded.json");
+    const GOVERNOR: &str = include_str!("../../../fixtures/wormhole/governor_limits.json");
+
+    fn adapter_with(snippet: &str) -> WormholeAdapter {
+        let config = format!("base_url = \"https://wormhole.test/api/v1\"\nchains = [\"ethereum\", \"polygon\"]\n{}", snippet);
+        WormholeAdapter::from_config(&toml::from_str(&config).unwrap()).unwrap()
+    }
+
+    fn usdc_request() -> QuoteRequest {
+        QuoteRequest::builder()
+            .src_chain("ethereum")
+            .dst_chain("polygon")
+            .src_token("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48")
+            .dst_token("0x3c499c542cef5e3811e1192ce70d8cc03d5c3359")
+            .src_amount("1")
+            .wallet("0xca69")
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn parses_normal_quote() {
+        let adapter = adapter_with("");
+        let edge = adapter.parse_quote(&usdc_request(), &serde_json::from_str(QUOTE).unwrap(), None).unwrap();
+
+        assert_eq!(adapter.name(), "wormhole");
+        assert_eq!(edge.from, "ethereum");
+        assert_eq!(edge.to, "polygon");
+        assert_eq!(edge.cost, 0.0015);
+        assert_eq!(edge.speed, 985.0);
+        assert_eq!(edge.liquidity, 250000.0);
+        assert!(!edge.liquidity_unknown);
+        assert_eq!(edge.estimated_output, 0.9985);
+        assert_eq!(edge.fee_components.len(), 2);
+        assert_eq!(edge.risk, DefaultRiskModel::default().assess("wormhole", &edge, &RiskContext { amount: Some(1.0), ..RiskContext::default() }));
+    }
+
+    #[test]
+    fn rejects_quote_without_relayer_fee() {
+        let adapter = adapter_with("");
+        let err = adapter.parse_quote(&usdc_request(), &serde_json::from_str(QUOTE_MISSING_FEE).unwrap(), None).unwrap_err();
+        assert_eq!(err, AdapterError::missing("relayerFee"));
+    }
+
+    #[tokio::test]
+    async fn unsupported_pair_fails_before_any_request() {
+        let adapter = adapter_with("");
+        let request = QuoteRequest::builder()
+            .src_chain("ethereum")
+            .dst_chain("zksync")
+            .src_token("0xa0b8")
+            .dst_token("0x3c49")
+            .src_amount("1")
+            .wallet("0xca69")
+            .build()
+            .unwrap();
+        let err = adapter.fetch_metrics(&request)
+            .await
+            .unwrap_err();
+        assert_eq!(err, AdapterError::unsupported_pair("wormhole", &request));
+    }
+
+    #[tokio::test]
+    async fn quotes_send_universal_addresses_and_wrapped_origins() {
+        use wiremock::{Mock, MockServer, ResponseTemplate, matchers::{method, path, query_param}};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/portal/quote"))
+            .and(query_param("sourceChain", "2"))
+            .and(query_param("targetChain", "5"))
+            .and(query_param("sourceToken", "0x000000000000000000000000a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"))
+            .and(query_param("targetToken", "0x0000000000000000000000003c499c542cef5e3811e1192ce70d8cc03d5c3359"))
+            .and(query_param("originChain", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(QUOTE))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let config = format!("base_url = \"{}\"\nchains = [\"ethereum\", \"polygon\"]", server.uri());
+        let adapter = WormholeAdapter::from_config(&toml::from_str(&config).unwrap()).unwrap();
+        assert_eq!(adapter.fetch_metrics(&usdc_request()).await.unwrap().cost, 0.0015);
+
+        // Once the table says the source token wraps a Solana mint, the mint is quoted as the origin
+        let mint = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+        let mut wrapped = WrappedAssets::default();
+        wrapped.extend([translate::WrappedAsset {
+            origin_chain: "solana".to_string(),
+            origin_address: mint.to_string(),
+            chain: "ethereum".to_string(),
+            address: "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48".to_string(),
+        }]).unwrap();
+        adapter.set_wrapped_assets(wrapped);
+        Mock::given(method("GET"))
+            .and(query_param("originChain", "1"))
+            .and(query_param("originToken", mint.to_lowercase()))
+            .respond_with(ResponseTemplate::new(200).set_body_string(QUOTE))
+            .expect(1)
+            .mount(&server)
+            .await;
+        adapter.fetch_metrics(&usdc_request()).await.unwrap();
+
+        let mut malformed = usdc_request();
+        malformed.dst_token = "0x3c49".to_string();
+        assert_eq!(adapter.fetch_metrics(&malformed).await.unwrap_err(), AdapterError::unsupported_pair("wormhole", &malformed));
+    }
+
+    // A configured USDC pair, which the governor check prices by its symbol
+    fn usdc_pair(src_chain: &str, dst_chain: &str) -> String {
+        let address = |chain: &str| match chain {
+            "ethereum" => "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+            _ => "0x3c499c542cef5e3811e1192ce70d8cc03d5c3359",
+        };
+        format!(
+            "[[pairs]]\nsource_chain = \"{}\"\nsource_token_name = \"USDC\"\nsource_address = \"{}\"\ndestination_chain = \"{}\"\ndestination_token_name = \"USDC\"\ndestination_address = \"{}\"\n",
+            src_chain, address(src_chain), dst_chain, address(dst_chain)
+        )
+    }
+
+    // [fx] pricing USDC at a quarter of a dollar, to tell USD limits from token amounts
+    fn quarter_dollar_usdc() -> Arc<dyn FxConverter> {
+        let config = polypathroute_core::FxConfig { rates: [("USDC".to_string(), 0.25)].into(), ..polypathroute_core::FxConfig::default() };
+        Arc::new(crate::StaticFxTable::from_config(&config))
+    }
+
+    // A Portal and guardian status API serving `heartbeats`, each endpoint at most `calls` times
+    async fn status_server(heartbeats: &str, calls: u64) -> (wiremock::MockServer, WormholeAdapter) {
+        use wiremock::{Mock, MockServer, ResponseTemplate, matchers::{method, path}};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET")).and(path("/portal/quote")).respond_with(ResponseTemplate::new(200).set_body_string(QUOTE)).mount(&server).await;
+        Mock::given(method("GET"))
+            .and(path("/heartbeats"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(heartbeats))
+            .expect(calls)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/governor/limit"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(GOVERNOR))
+            .expect(calls)
+            .mount(&server)
+            .await;
+        let config = format!(
+            "base_url = \"{0}\"\nchains = [\"ethereum\", \"polygon\"]\n[extra]\nheartbeats_url = \"{0}/heartbeats\"\ngovernor_url = \"{0}/governor/limit/\"\n{1}",
+            server.uri(),
+            usdc_pair("polygon", "ethereum") + &usdc_pair("ethereum", "polygon"),
+        );
+        let context = AdapterContext::from_config("wormhole", &toml::from_str(&config).unwrap()).unwrap();
+        let adapter = WormholeAdapter::new(context.with_fx_converter(quarter_dollar_usdc())).unwrap();
+        (server, adapter)
+    }
+
+    #[tokio::test]
+    async fn healthy_guardians_leave_quotes_as_they_are() {
+        // Two quotes and a health check share one status fetch
+        let (_server, adapter) = status_server(HEARTBEATS, 1).await;
+        let edge = adapter.fetch_metrics(&usdc_request()).await.unwrap();
+        assert_eq!(edge.speed, 985.0);
+        assert_eq!(edge.risk, DefaultRiskModel::default().assess("wormhole", &edge, &RiskContext { amount: Some(1.0), ..RiskContext::default() }));
+        assert_eq!(adapter.fetch_metrics(&usdc_request()).await.unwrap(), BridgeEdge { quoted_at: edge.quoted_at, valid_until: edge.valid_until, ..edge.clone() });
+        assert_eq!(adapter.health_check().await.unwrap().details, "404 Not Found");
+    }
+
+    #[tokio::test]
+    async fn degraded_guardians_slow_and_raise_the_risk_of_quotes() {
+        let (_server, adapter) = status_server(HEARTBEATS_DEGRADED, 1).await;
+        let edge = adapter.fetch_metrics(&usdc_request()).await.unwrap();
+
+        // Ten minutes behind the quorum on ethereum
+        assert_eq!(edge.speed, 985.0 + 600.0);
+        let status = BridgeStatus {
+            degraded: true,
+            backlog_estimate: Duration::from_secs(600),
+            notes: vec!["2 of 4 guardians behind on ethereum".to_string()],
+        };
+        let model = DefaultRiskModel::default();
+        let healthy = RiskContext { amount: Some(1.0), ..RiskContext::default() };
+        assert_eq!(edge.risk, model.assess("wormhole", &edge, &healthy.clone().with_bridge_status(Some(status))));
+        assert_eq!(edge.risk, model.assess("wormhole", &edge, &healthy) + model.degraded_risk);
+
+        let details = adapter.health_check().await.unwrap().details;
+        assert!(details.contains("; degraded on ethereum: 2 of 4 guardians behind on ethereum; degraded on polygon: only 2 of 4 guardians watch polygon"), "{}", details);
+    }
+
+    #[tokio::test]
+    async fn transfers_over_the_governor_limit_are_out_of_range() {
+        let (server, adapter) = status_server(HEARTBEATS, 1).await;
+        let mut from_polygon = usdc_request();
+        (from_polygon.src_chain, from_polygon.dst_chain) = ("polygon".to_string(), "ethereum".to_string());
+        (from_polygon.src_token, from_polygon.dst_token) = (from_polygon.dst_token.clone(), from_polygon.src_token.clone());
+
+        // $0.50 left on polygon is two USDC at a quarter each
+        from_polygon.src_amount = "3".to_string();
+        let err = adapter.fetch_metrics(&from_polygon).await.unwrap_err();
+        assert_eq!(err, AdapterError::AmountOutOfRange { min: None, max: Some(2.0) });
+        from_polygon.src_amount = "2".to_string();
+        adapter.fetch_metrics(&from_polygon).await.unwrap();
+        let quotes = server.received_requests().await.unwrap().iter().filter(|request| request.url.path() == "/portal/quote").count();
+        assert_eq!(quotes, 1);
+    }
+
+    #[test]
+    fn governor_limits_need_usd_prices() {
+        let config: BridgeConfig = toml::from_str("base_url = \"https://wormhole.test/api/v1\"\nchains = [\"ethereum\"]\n[extra]\ngovernor_url = \"https://wormhole.test/governor\"\n").unwrap();
+        let err = WormholeAdapter::from_config(&config).err().unwrap();
+        assert!(err.to_string().contains("governor limits are in USD"), "{}", err);
+        let in_euros = polypathroute_core::FxConfig { quote_currency: "EUR".to_string(), ..polypathroute_core::FxConfig::default() };
+        let context = AdapterContext::from_config("wormhole", &config).unwrap().with_fx_converter(Arc::new(crate::StaticFxTable::from_config(&in_euros)));
+        assert!(WormholeAdapter::new(context).is_err());
+        WormholeAdapter::new(AdapterContext::from_config("wormhole", &config).unwrap().with_fx_converter(quarter_dollar_usdc())).unwrap();
+    }
+
+    #[test]
+    fn quote_url_comes_from_config() {
+        assert_eq!(adapter_with("").quote_url(), "https://wormhole.test/api/v1/portal/quote");
+    }
+
+    #[test]
+    fn drops_pairs_without_chain_ids() {
+        let pair = |dst_chain: &str| format!(r#"
+            [[pairs]]
+            source_chain = "ethereum"
+            source_token_name = "USDC"
+            destination_chain = "{}"
+            destination_token_name = "USDC"
+            source_address = "0xa0b8"
+            destination_address = "0x3c49"
+        "#, dst_chain);
+        let adapter = adapter_with(&(pair("polygon") + &pair("zksync")));
+
+        assert!(adapter.is_supported_pair("ethereum", "polygon", "0xa0b8", "0x3c49"));
+        assert!(!adapter.is_supported_pair("ethereum", "zksync", "0xa0b8", "0x3c49"));
+    }
+}
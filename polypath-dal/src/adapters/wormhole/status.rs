@@ -0,0 +1,196 @@
+// Guardian network health from Wormholescan's heartbeat and governor endpoints. A transfer is
+// only signed once a quorum of guardians has seen it, so the quorum's slowest member on the
+// source chain sets how far behind transfers run; the governor holds back transfers over a
+// chain's remaining notional allowance.
+
+use std::{collections::HashMap, time::Duration};
+use serde::Deserialize;
+
+use super::translate::{CHAINS, from_wormhole_chain_id};
+use crate::adapters::{AdapterError, BridgeStatus};
+
+// Backlogs up to this long are normal signing delay rather than a degraded network
+pub(super) const DEGRADED_BACKLOG: Duration = Duration::from_secs(60);
+
+// Average block time per chain key, to turn a guardian's lag in blocks into time. Chains not
+// listed count as Ethereum's.
+const BLOCK_SECS: &[(&str, f64)] = &[
+    ("solana", 0.4),
+    ("ethereum", 12.0),
+    ("bsc", 3.0),
+    ("polygon", 2.0),
+    ("avalanche", 2.0),
+    ("fantom", 1.0),
+    ("celo", 5.0),
+    ("moonbeam", 12.0),
+    ("arbitrum", 0.25),
+    ("optimism", 2.0),
+    ("base", 2.0),
+];
+
+#[derive(Debug, Deserialize)]
+struct Heartbeats {
+    heartbeats: Vec<Heartbeat>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Heartbeat {
+    raw_heartbeat: RawHeartbeat,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawHeartbeat {
+    node_name: String,
+    #[serde(default)]
+    networks: Vec<Network>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Network {
+    id: u16,
+    // Wormholescan sends heights as strings
+    #[serde(deserialize_with = "height")]
+    height: u64,
+}
+
+fn height<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Height {
+        Number(u64),
+        Text(String),
+    }
+    match Height::deserialize(deserializer)? {
+        Height::Number(height) => Ok(height),
+        Height::Text(text) => text.parse().map_err(serde::de::Error::custom),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GovernorLimits {
+    data: Vec<GovernorLimit>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GovernorLimit {
+    chain_id: u16,
+    available_notional: f64,
+    max_transaction_size: f64,
+}
+
+// What the guardians last reported. Either half is left empty when its endpoint isn't configured.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GuardianStatus {
+    // Node name and the height it has seen, per Wormhole chain id
+    heights: Vec<(String, HashMap<u16, u64>)>,
+    // The largest transfer the governor lets through at once, in USD, per Wormhole chain id
+    governor: HashMap<u16, f64>,
+}
+
+impl GuardianStatus {
+    pub(super) fn with_heartbeats(mut self, body: &str) -> Result<Self, AdapterError> {
+        let parsed: Heartbeats = serde_json::from_str(body)?;
+        self.heights = parsed
+            .heartbeats
+            .into_iter()
+            .map(|beat| (beat.raw_heartbeat.node_name, beat.raw_heartbeat.networks.into_iter().map(|network| (network.id, network.height)).collect()))
+            .collect();
+        Ok(self)
+    }
+
+    pub(super) fn with_governor(mut self, body: &str) -> Result<Self, AdapterError> {
+        let parsed: GovernorLimits = serde_json::from_str(body)?;
+        self.governor = parsed
+            .data
+            .into_iter()
+            .map(|limit| (limit.chain_id, limit.available_notional.min(limit.max_transaction_size).max(0.0)))
+            .collect();
+        Ok(self)
+    }
+
+    // How signing runs for transfers from `chain_id`, None when no heartbeat mentions the chain.
+    // Degraded when fewer than a quorum of guardians watch the chain, or the quorum runs
+    // DEGRADED_BACKLOG behind the guardian furthest ahead.
+    pub(super) fn bridge_status(&self, chain_id: u16) -> Option<BridgeStatus> {
+        let chain = from_wormhole_chain_id(chain_id).unwrap_or("unknown");
+        let guardians = self.heights.len();
+        let quorum = guardians * 2 / 3 + 1;
+        let mut heights: Vec<u64> = self.heights.iter().filter_map(|(_, heights)| heights.get(&chain_id).copied()).collect();
+        heights.sort_unstable_by(|a, b| b.cmp(a));
+        if heights.is_empty() {
+            return None;
+        }
+        if heights.len() < quorum {
+            return Some(BridgeStatus {
+                degraded: true,
+                backlog_estimate: Duration::ZERO,
+                notes: vec![format!("only {} of {} guardians watch {}", heights.len(), guardians, chain)],
+            });
+        }
+
+        let block_secs = BLOCK_SECS.iter().find(|(key, _)| *key == chain).map_or(12.0, |(_, secs)| *secs);
+        let backlog_estimate = Duration::from_secs_f64((heights[0] - heights[quorum - 1]) as f64 * block_secs);
+        let behind = heights.iter().filter(|height| (heights[0] - **height) as f64 * block_secs > DEGRADED_BACKLOG.as_secs_f64()).count();
+        let mut notes = Vec::new();
+        if behind > 0 {
+            notes.push(format!("{} of {} guardians behind on {}", behind, guardians, chain));
+        }
+        Some(BridgeStatus { degraded: backlog_estimate > DEGRADED_BACKLOG, backlog_estimate, notes })
+    }
+
+    // The largest transfer out of `chain_id` the governor lets through unheld, in USD
+    pub(super) fn governor_limit(&self, chain_id: u16) -> Option<f64> {
+        self.governor.get(&chain_id).copied()
+    }
+
+    // Known chains the guardians are degraded on, with why
+    pub(super) fn degraded_chains(&self) -> Vec<(&'static str, BridgeStatus)> {
+        CHAINS
+            .iter()
+            .filter_map(|(key, id, ..)| self.bridge_status(*id).filter(|status| status.degraded).map(|status| (*key, status)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEALTHY: &str = include_str!("../../../fixtures/wormhole/heartbeats.json");
+    const DEGRADED: &str = include_str!("../../../fixtures/wormhole/heartbeats_degraded.json");
+    const GOVERNOR: &str = include_str!("../../../fixtures/wormhole/governor_limits.json");
+
+    #[test]
+    fn quorum_lag_sets_the_backlog() {
+        let healthy = GuardianStatus::default().with_heartbeats(HEALTHY).unwrap();
+        let status = healthy.bridge_status(2).unwrap();
+        assert!(!status.degraded && status.notes.is_empty());
+        assert_eq!(status.backlog_estimate, Duration::from_secs(12));
+
+        // Two of four ethereum guardians are 50 blocks behind, one of them in the quorum of three
+        let degraded = GuardianStatus::default().with_heartbeats(DEGRADED).unwrap();
+        let status = degraded.bridge_status(2).unwrap();
+        assert_eq!(status, BridgeStatus {
+            degraded: true,
+            backlog_estimate: Duration::from_secs(600),
+            notes: vec!["2 of 4 guardians behind on ethereum".to_string()],
+        });
+        // Only two guardians report polygon
+        assert_eq!(degraded.bridge_status(5).unwrap().notes, ["only 2 of 4 guardians watch polygon"]);
+        assert_eq!(degraded.degraded_chains().iter().map(|(chain, _)| *chain).collect::<Vec<_>>(), ["ethereum", "polygon"]);
+        assert_eq!(GuardianStatus::default().bridge_status(2), None);
+        assert_eq!(healthy.bridge_status(1), None);
+    }
+
+    #[test]
+    fn governor_limit_is_the_smaller_of_allowance_and_transaction_size() {
+        let status = GuardianStatus::default().with_governor(GOVERNOR).unwrap();
+        assert_eq!(status.governor_limit(2), Some(5_000_000.0));
+        assert_eq!(status.governor_limit(5), Some(0.5));
+        assert_eq!(status.governor_limit(23), None);
+        assert!(GuardianStatus::default().with_governor("{}").is_err());
+    }
+}
@@ -0,0 +1,269 @@
+// Wormhole names chains by its own u16 ids and tokens by 32-byte universal addresses; these
+// translate to and from the chain keys and addresses the rest of PolyPath uses
+
+use std::collections::HashMap;
+use anyhow::anyhow;
+use polypathroute_core::BridgeConfig;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::adapters::canonical_chain_key;
+
+// (chain key, Wormhole chain id, display name, native token, EVM addresses)
+pub(super) const CHAINS: &[(&str, u16, &str, &str, bool)] = &[
+    ("solana", 1, "Solana", "SOL", false),
+    ("ethereum", 2, "Ethereum", "ETH", true),
+    ("bsc", 4, "BNB Smart Chain", "BNB", true),
+    ("polygon", 5, "Polygon", "POL", true),
+    ("avalanche", 6, "Avalanche", "AVAX", true),
+    ("fantom", 10, "Fantom", "FTM", true),
+    ("celo", 14, "Celo", "CELO", true),
+    ("moonbeam", 16, "Moonbeam", "GLMR", true),
+    ("arbitrum", 23, "Arbitrum", "ETH", true),
+    ("optimism", 24, "Optimism", "ETH", true),
+    ("base", 30, "Base", "ETH", true),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TranslateError {
+    #[error("wormhole has no chain id for `{0}`")]
+    UnknownChain(String),
+
+    #[error("no chain has wormhole chain id {0}")]
+    UnknownChainId(u16),
+
+    #[error("`{address}` is not an EVM address: {reason}")]
+    InvalidAddress { address: String, reason: String },
+}
+
+// By chain key or any alias the registry knows, e.g. "arb"
+pub fn to_wormhole_chain_id(chain_key: &str) -> Result<u16, TranslateError> {
+    let key = canonical_chain_key(chain_key);
+    CHAINS.iter()
+        .find(|(name, ..)| *name == key)
+        .map(|(_, id, ..)| *id)
+        .ok_or_else(|| TranslateError::UnknownChain(chain_key.trim().to_string()))
+}
+
+pub fn from_wormhole_chain_id(chain_id: u16) -> Result<&'static str, TranslateError> {
+    CHAINS.iter()
+        .find(|(_, id, ..)| *id == chain_id)
+        .map(|(name, ..)| *name)
+        .ok_or(TranslateError::UnknownChainId(chain_id))
+}
+
+// Whether tokens on the chain have 20-byte EVM addresses. False for unknown chains.
+pub fn is_evm_chain(chain_key: &str) -> bool {
+    let key = canonical_chain_key(chain_key);
+    CHAINS.iter().any(|(name, .., evm)| *name == key && *evm)
+}
+
+// The 20-byte address left-padded with zeros to 32 bytes. Checksums aren't verified.
+pub fn to_universal_address(evm_address: &str) -> Result<[u8; 32], TranslateError> {
+    let invalid = |reason: &str| TranslateError::InvalidAddress { address: evm_address.to_string(), reason: reason.to_string() };
+    let trimmed = evm_address.trim();
+    let hex = trimmed
+        .strip_prefix("0x")
+        .or_else(|| trimmed.strip_prefix("0X"))
+        .ok_or_else(|| invalid("missing the 0x prefix"))?;
+    if hex.len() != 40 {
+        return Err(invalid(&format!("expected 40 hex digits, found {}", hex.len())));
+    }
+    if !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err(invalid("not hex"));
+    }
+
+    let mut universal = [0u8; 32];
+    for (index, digits) in hex.as_bytes().chunks(2).enumerate() {
+        let digit = |byte: u8| (byte as char).to_digit(16).unwrap_or_default() as u8;
+        universal[12 + index] = digit(digits[0]) << 4 | digit(digits[1]);
+    }
+    Ok(universal)
+}
+
+// The lowercase 0x-prefixed EVM address a universal address pads. Universal addresses whose
+// first 12 bytes aren't zero belong to non-EVM chains and are rejected.
+pub fn from_universal_address(universal: &[u8; 32]) -> Result<String, TranslateError> {
+    if universal[..12].iter().any(|byte| *byte != 0) {
+        return Err(TranslateError::InvalidAddress {
+            address: format!("0x{}", hex(universal)),
+            reason: "the first 12 bytes are not zero".to_string(),
+        });
+    }
+    Ok(format!("0x{}", hex(&universal[12..])))
+}
+
+// 0x-prefixed hex of the universal address, the form Wormhole's API takes
+pub fn universal_hex(universal: &[u8; 32]) -> String {
+    format!("0x{}", hex(universal))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// One `[[extra.wrapped_assets]]` entry: the token `address` on `chain` is Wormhole's wrapped
+// form of `origin_address` on `origin_chain`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WrappedAsset {
+    pub origin_chain: String,
+    pub origin_address: String,
+    pub chain: String,
+    pub address: String,
+}
+
+// Which tokens are wrapped forms of which, keyed by canonical chain key and lowercased address.
+// Seeded from config; `extend` lets a token list fetched later add to or correct it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WrappedAssets {
+    // (chain, address) of a wrapped token -> (origin chain, origin address)
+    origins: HashMap<(String, String), (String, String)>,
+}
+
+fn asset_key(chain: &str, address: &str) -> (String, String) {
+    (canonical_chain_key(chain), address.trim().to_lowercase())
+}
+
+impl WrappedAssets {
+    pub fn from_config(bridge: &str, config: &BridgeConfig) -> anyhow::Result<Self> {
+        let Some(entries) = config.extra.as_ref().and_then(|extra| extra.get("wrapped_assets")) else {
+            return Ok(Self::default());
+        };
+        let entries: Vec<WrappedAsset> = entries
+            .clone()
+            .try_into()
+            .map_err(|err| anyhow!("bridges.{}.extra.wrapped_assets: {}", bridge, err))?;
+        let mut table = Self::default();
+        table.extend(entries).map_err(|err| anyhow!("bridges.{}.extra.wrapped_assets: {}", bridge, err))?;
+        Ok(table)
+    }
+
+    // Adds the entries, replacing what was known about the same wrapped tokens. Every chain
+    // needs a Wormhole chain id and addresses on EVM chains must be valid; on error nothing is added.
+    pub fn extend(&mut self, entries: impl IntoIterator<Item = WrappedAsset>) -> Result<(), TranslateError> {
+        let mut added = Vec::new();
+        for entry in entries {
+            for (chain, address) in [(&entry.origin_chain, &entry.origin_address), (&entry.chain, &entry.address)] {
+                to_wormhole_chain_id(chain)?;
+                if is_evm_chain(chain) {
+                    to_universal_address(address)?;
+                }
+            }
+            added.push((asset_key(&entry.chain, &entry.address), asset_key(&entry.origin_chain, &entry.origin_address)));
+        }
+        self.origins.extend(added);
+        Ok(())
+    }
+
+    // The (origin chain, origin address) of the token, itself when it isn't a known wrapped token
+    pub fn origin(&self, chain: &str, address: &str) -> (String, String) {
+        let key = asset_key(chain, address);
+        self.origins.get(&key).cloned().unwrap_or(key)
+    }
+
+    // The wrapped form on `chain` of the token native to `origin_chain`, if known
+    pub fn wrapped_on(&self, origin_chain: &str, origin_address: &str, chain: &str) -> Option<String> {
+        let origin = asset_key(origin_chain, origin_address);
+        let chain = canonical_chain_key(chain);
+        self.origins
+            .iter()
+            .find(|((wrapped_chain, _), source)| *wrapped_chain == chain && **source == origin)
+            .map(|((_, address), _)| address.clone())
+    }
+
+    pub fn len(&self) -> usize {
+        self.origins.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.origins.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USDC_ETHEREUM: &str = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+
+    #[test]
+    fn chain_keys_and_aliases_map_to_wormhole_ids() {
+        assert_eq!(to_wormhole_chain_id("ethereum"), Ok(2));
+        assert_eq!(to_wormhole_chain_id("Arbitrum"), Ok(23));
+        assert_eq!(to_wormhole_chain_id("arb"), Ok(23));
+        assert_eq!(to_wormhole_chain_id("zksync"), Err(TranslateError::UnknownChain("zksync".to_string())));
+        assert_eq!(from_wormhole_chain_id(30), Ok("base"));
+        assert_eq!(from_wormhole_chain_id(9999), Err(TranslateError::UnknownChainId(9999)));
+        assert!(is_evm_chain("polygon") && !is_evm_chain("solana") && !is_evm_chain("zksync"));
+    }
+
+    #[test]
+    fn evm_addresses_round_trip_through_universal_form() {
+        let universal = to_universal_address(USDC_ETHEREUM).unwrap();
+        // Left-padded: 12 zero bytes, then the 20 address bytes
+        assert_eq!(universal[..12], [0u8; 12]);
+        assert_eq!(universal[12..14], [0xa0, 0xb8]);
+        assert_eq!(universal[31], 0x48);
+        assert_eq!(from_universal_address(&universal).unwrap(), USDC_ETHEREUM.to_lowercase());
+        assert_eq!(universal_hex(&universal), format!("0x000000000000000000000000{}", &USDC_ETHEREUM[2..].to_lowercase()));
+        assert_eq!(to_universal_address(&USDC_ETHEREUM.to_uppercase().replacen("0X", "0x", 1)).unwrap(), universal);
+
+        let zero = to_universal_address("0x0000000000000000000000000000000000000000").unwrap();
+        assert_eq!(zero, [0u8; 32]);
+
+        let mut solana = [7u8; 32];
+        assert!(matches!(from_universal_address(&solana), Err(TranslateError::InvalidAddress { .. })));
+        solana[..12].fill(0);
+        assert_eq!(from_universal_address(&solana).unwrap(), format!("0x{}", "07".repeat(20)));
+    }
+
+    #[test]
+    fn malformed_addresses_are_rejected() {
+        for (address, reason) in [
+            ("a0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", "0x prefix"),
+            ("0xa0b8", "found 4"),
+            ("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb4800", "found 42"),
+            ("0xg0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", "not hex"),
+            ("0x+0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", "not hex"),
+            ("0xéb86991c6218b36c1d19d4a2e9eb0ce3606eb48", "not hex"),
+        ] {
+            let err = to_universal_address(address).unwrap_err();
+            assert!(err.to_string().contains(reason), "{}: {}", address, err);
+        }
+    }
+
+    #[test]
+    fn wrapped_assets_come_from_config_and_can_be_extended() {
+        let config: BridgeConfig = toml::from_str(r#"
+            base_url = "https://wormhole.test"
+            chains = ["ethereum", "polygon"]
+
+            [[extra.wrapped_assets]]
+            origin_chain = "ethereum"
+            origin_address = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"
+            chain = "Polygon"
+            address = "0x4318CB63A2b8edf2De971E2F17F77097e499459D"
+        "#).unwrap();
+        let mut table = WrappedAssets::from_config("wormhole", &config).unwrap();
+
+        let wrapped = "0x4318cb63a2b8edf2de971e2f17f77097e499459d";
+        assert_eq!(table.origin("polygon", wrapped), ("ethereum".to_string(), USDC_ETHEREUM.to_lowercase()));
+        assert_eq!(table.origin("ethereum", USDC_ETHEREUM), ("ethereum".to_string(), USDC_ETHEREUM.to_lowercase()));
+        assert_eq!(table.wrapped_on("eth", USDC_ETHEREUM, "polygon").as_deref(), Some(wrapped));
+        assert_eq!(table.wrapped_on("ethereum", USDC_ETHEREUM, "base"), None);
+
+        let bad = WrappedAsset {
+            origin_chain: "ethereum".to_string(),
+            origin_address: USDC_ETHEREUM.to_string(),
+            chain: "zksync".to_string(),
+            address: "0x1d17CBcF0D6D143135aE902365D2E5e2A16538D4".to_string(),
+        };
+        assert_eq!(table.extend([bad]), Err(TranslateError::UnknownChain("zksync".to_string())));
+        assert_eq!(table.len(), 1);
+
+        let mut invalid = config.clone();
+        invalid.extra.as_mut().unwrap().insert("wrapped_assets".to_string(), toml::from_str::<toml::Value>("entries = 1").unwrap());
+        assert!(WrappedAssets::from_config("wormhole", &invalid).unwrap_err().to_string().contains("bridges.wormhole.extra.wrapped_assets"));
+    }
+}
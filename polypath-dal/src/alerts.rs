@@ -0,0 +1,449 @@
+// Alerts on edges getting worse: the [alerts] rules are checked against every edge update the
+// GraphUpdater makes, repeats within the cooldown are dropped, and what's left goes out
+// through the engine's Notifiers
+
+use std::{collections::{HashMap, VecDeque}, fmt, sync::{Arc, Mutex}, time::Duration};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use polypath_graph::EdgeMetrics;
+use polypathroute_core::{AlertCondition, AlertRule, AlertsConfig, LoggingManager};
+use reqwest::Client;
+use serde::Serialize;
+
+use crate::digest::RefreshDigest;
+
+// How long a webhook gets to accept an alert
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Which edge an update or alert is about, by the chain keys and token addresses the graph uses
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EdgeIdentity {
+    // See history::edge_id
+    pub edge_id: String,
+    // The edge's label, e.g. "lifi:stargate"
+    pub bridge: String,
+    pub src_chain: String,
+    pub src_token: String,
+    pub dst_chain: String,
+    pub dst_token: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EdgeEvent {
+    // The edge was added or given fresh metrics at unix time `at`
+    Metrics { edge: EdgeIdentity, metrics: EdgeMetrics, at: u64 },
+    Deactivated { edge: EdgeIdentity, at: u64 },
+}
+
+impl EdgeEvent {
+    fn edge(&self) -> &EdgeIdentity {
+        match self {
+            EdgeEvent::Metrics { edge, .. } | EdgeEvent::Deactivated { edge, .. } => edge,
+        }
+    }
+
+    fn at(&self) -> u64 {
+        match self {
+            EdgeEvent::Metrics { at, .. } | EdgeEvent::Deactivated { at, .. } => *at,
+        }
+    }
+}
+
+// One rule firing for one edge; the body of webhook posts
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Alert {
+    pub rule: String,
+    // The rule's kind as configured, e.g. "cost_increase"
+    pub kind: &'static str,
+    pub edge: EdgeIdentity,
+    // "cost", "liquidity", or "active" with 1 for on and 0 for off
+    pub metric: &'static str,
+    // For cost increases, the lowest cost within the rule's window
+    pub old_value: f64,
+    pub new_value: f64,
+    // Unix seconds
+    pub fired_at: u64,
+}
+
+#[async_trait]
+pub trait Notifier: Send + Sync + fmt::Debug {
+    fn name(&self) -> &str;
+
+    async fn notify(&self, alert: &Alert) -> Result<()>;
+
+    // Refresh digests, see digest::DigestNotifier; ignored unless a notifier takes them
+    async fn notify_digest(&self, _digest: &RefreshDigest) -> Result<()> {
+        Ok(())
+    }
+}
+
+// Logs alerts as warnings
+#[derive(Debug)]
+pub struct LogNotifier {
+    logger: LoggingManager,
+}
+
+impl LogNotifier {
+    pub fn new(logger: LoggingManager) -> Self {
+        Self { logger }
+    }
+}
+
+#[async_trait]
+impl Notifier for LogNotifier {
+    fn name(&self) -> &str {
+        "log"
+    }
+
+    async fn notify(&self, alert: &Alert) -> Result<()> {
+        self.logger.warn_with("edge alert", &[
+            ("rule", &alert.rule),
+            ("kind", &alert.kind),
+            ("edge", &alert.edge.edge_id),
+            ("metric", &alert.metric),
+            ("old_value", &alert.old_value),
+            ("new_value", &alert.new_value),
+        ]);
+        Ok(())
+    }
+
+    async fn notify_digest(&self, digest: &RefreshDigest) -> Result<()> {
+        let slowest = digest.slowest_adapter.as_ref().map_or("-", |slowest| slowest.adapter.as_str());
+        let coverage = digest.coverage_pct.map_or("-".to_string(), |pct| format!("{:.1}%", pct));
+        self.logger.info_with("refresh digest", &[
+            ("cycle", &digest.cycle),
+            ("pairs_refreshed", &digest.pairs_refreshed),
+            ("failed", &digest.failed),
+            ("edges_deactivated", &digest.edges_deactivated),
+            ("coverage", &coverage),
+            ("slowest_adapter", &slowest),
+        ]);
+        Ok(())
+    }
+}
+
+// POSTs each alert or digest as JSON to `url`; a non-2xx response is an error
+#[derive(Debug)]
+pub struct WebhookNotifier {
+    client: Client,
+    url: String,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: impl Into<String>) -> Result<Self> {
+        let client = Client::builder().timeout(WEBHOOK_TIMEOUT).build()?;
+        Ok(Self { client, url: url.into() })
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    async fn notify(&self, alert: &Alert) -> Result<()> {
+        self.client.post(&self.url).json(alert).send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    async fn notify_digest(&self, digest: &RefreshDigest) -> Result<()> {
+        self.client.post(&self.url).json(digest).send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+// What the rules need to remember about an edge between updates
+#[derive(Debug, Default)]
+struct EdgeState {
+    // (unix time, cost) within the longest cost_increase window, oldest first
+    costs: VecDeque<(u64, f64)>,
+    liquidity: Option<f64>,
+}
+
+#[derive(Debug)]
+pub struct AlertEngine {
+    rules: Vec<AlertRule>,
+    cooldown: Duration,
+    notifiers: Vec<Arc<dyn Notifier>>,
+    // Longest window of the cost_increase rules
+    cost_window: u64,
+    // Keyed by edge id
+    edges: Mutex<HashMap<String, EdgeState>>,
+    // Unix time each rule last fired for each edge, keyed by (rule, edge id)
+    last_fired: Mutex<HashMap<(String, String), u64>>,
+}
+
+impl AlertEngine {
+    // The config's rules without notifiers; see `with_notifier`
+    pub fn new(config: &AlertsConfig) -> Self {
+        let cost_window = config.rules
+            .iter()
+            .filter_map(|rule| match rule.condition {
+                AlertCondition::CostIncrease { within, .. } => Some(within.as_secs()),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+        Self {
+            rules: config.rules.clone(),
+            cooldown: config.cooldown,
+            notifiers: Vec::new(),
+            cost_window,
+            edges: Mutex::default(),
+            last_fired: Mutex::default(),
+        }
+    }
+
+    // Logs every alert, and posts it to alerts.webhook_url when one is set
+    pub fn from_config(config: &AlertsConfig, logger: LoggingManager) -> Result<Self> {
+        let mut engine = Self::new(config).with_notifier(Arc::new(LogNotifier::new(logger)));
+        if let Some(url) = &config.webhook_url {
+            engine = engine.with_notifier(Arc::new(WebhookNotifier::new(url)?));
+        }
+        Ok(engine)
+    }
+
+    pub fn with_notifier(mut self, notifier: Arc<dyn Notifier>) -> Self {
+        self.notifiers.push(notifier);
+        self
+    }
+
+    pub fn rules(&self) -> &[AlertRule] {
+        &self.rules
+    }
+
+    // The alerts `event` fires, minus rules that fired for its edge within the cooldown.
+    // Nothing is sent; see `dispatch`.
+    pub fn evaluate(&self, event: &EdgeEvent) -> Vec<Alert> {
+        let edge = event.edge();
+        let at = event.at();
+        let mut edges = self.edges.lock().unwrap();
+        let state = edges.entry(edge.edge_id.clone()).or_default();
+
+        let mut fired = Vec::new();
+        for rule in self.rules.iter().filter(|rule| matches(rule, edge)) {
+            let alert = |kind, metric, old_value, new_value| Alert {
+                rule: rule.name.clone(),
+                kind,
+                edge: edge.clone(),
+                metric,
+                old_value,
+                new_value,
+                fired_at: at,
+            };
+            match (&rule.condition, event) {
+                (AlertCondition::CostIncrease { pct, within }, EdgeEvent::Metrics { metrics, .. }) => {
+                    let since = at.saturating_sub(within.as_secs());
+                    let lowest = state.costs
+                        .iter()
+                        .filter(|(seen, _)| *seen >= since)
+                        .map(|(_, cost)| *cost)
+                        .fold(f64::INFINITY, f64::min);
+                    if lowest > 0.0 && lowest.is_finite() && (metrics.cost - lowest) / lowest * 100.0 > *pct {
+                        fired.push(alert("cost_increase", "cost", lowest, metrics.cost));
+                    }
+                }
+                (AlertCondition::LiquidityBelow { threshold }, EdgeEvent::Metrics { metrics, .. }) if metrics.liquidity < *threshold => {
+                    let previous = state.liquidity.unwrap_or(metrics.liquidity);
+                    fired.push(alert("liquidity_below", "liquidity", previous, metrics.liquidity));
+                }
+                (AlertCondition::EdgeDeactivated, EdgeEvent::Deactivated { .. }) => {
+                    fired.push(alert("edge_deactivated", "active", 1.0, 0.0));
+                }
+                _ => {}
+            }
+        }
+
+        if let EdgeEvent::Metrics { metrics, .. } = event {
+            state.costs.push_back((at, metrics.cost));
+            while state.costs.front().is_some_and(|(seen, _)| *seen < at.saturating_sub(self.cost_window)) {
+                state.costs.pop_front();
+            }
+            state.liquidity = Some(metrics.liquidity);
+        }
+        drop(edges);
+
+        let mut last_fired = self.last_fired.lock().unwrap();
+        fired.retain(|alert| {
+            let key = (alert.rule.clone(), alert.edge.edge_id.clone());
+            match last_fired.get(&key) {
+                Some(last) if at < last + self.cooldown.as_secs() => false,
+                _ => {
+                    last_fired.insert(key, at);
+                    true
+                }
+            }
+        });
+        fired
+    }
+
+    // Sends every alert through every notifier and returns what failed. A failing notifier
+    // doesn't stop the others.
+    pub async fn dispatch(&self, alerts: &[Alert]) -> Vec<anyhow::Error> {
+        let mut failures = Vec::new();
+        for alert in alerts {
+            for notifier in &self.notifiers {
+                if let Err(err) = notifier.notify(alert).await.with_context(|| format!("notifier `{}` failed on rule `{}`", notifier.name(), alert.rule)) {
+                    failures.push(err);
+                }
+            }
+        }
+        failures
+    }
+}
+
+// Bridge rules match either part of an aggregated label, so "stargate" and "lifi" both match
+// "lifi:stargate"
+fn matches(rule: &AlertRule, edge: &EdgeIdentity) -> bool {
+    rule.bridge.as_ref().is_none_or(|bridge| edge.bridge.split(':').any(|part| part == bridge))
+        && rule.source_chain.as_ref().is_none_or(|chain| *chain == edge.src_chain)
+        && rule.destination_chain.as_ref().is_none_or(|chain| *chain == edge.dst_chain)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use polypathroute_core::{ConfigFormat, ConfigManager};
+    use wiremock::{Mock, MockServer, ResponseTemplate, matchers::{method, path}};
+
+    // Keeps what it's sent
+    #[derive(Debug, Default)]
+    pub(crate) struct RecordingNotifier {
+        pub(crate) alerts: Mutex<Vec<Alert>>,
+        pub(crate) digests: Mutex<Vec<RefreshDigest>>,
+    }
+
+    #[async_trait]
+    impl Notifier for RecordingNotifier {
+        fn name(&self) -> &str {
+            "recording"
+        }
+
+        async fn notify(&self, alert: &Alert) -> Result<()> {
+            self.alerts.lock().unwrap().push(alert.clone());
+            Ok(())
+        }
+
+        async fn notify_digest(&self, digest: &RefreshDigest) -> Result<()> {
+            self.digests.lock().unwrap().push(digest.clone());
+            Ok(())
+        }
+    }
+
+    fn config(webhook_url: Option<&str>) -> AlertsConfig {
+        let webhook = webhook_url.map(|url| format!("webhook_url = {:?}\n", url)).unwrap_or_default();
+        ConfigManager::from_str(&format!(r#"
+            [alerts]
+            cooldown = "10m"
+            {}
+            [[alerts.rules]]
+            name = "stargate-cost"
+            bridge = "stargate"
+            kind = "cost_increase"
+            pct = 100
+            within = "30m"
+
+            [[alerts.rules]]
+            name = "thin"
+            source_chain = "ethereum"
+            kind = "liquidity_below"
+            threshold = 50000
+
+            [[alerts.rules]]
+            name = "gone"
+            kind = "edge_deactivated"
+
+            [bridges]
+        "#, webhook), ConfigFormat::Toml).unwrap().alerts
+    }
+
+    fn edge(bridge: &str, src_chain: &str) -> EdgeIdentity {
+        EdgeIdentity {
+            edge_id: format!("{}:{}:0xa0b8->polygon:0x3c49", bridge, src_chain),
+            bridge: bridge.to_string(),
+            src_chain: src_chain.to_string(),
+            src_token: "0xa0b8".to_string(),
+            dst_chain: "polygon".to_string(),
+            dst_token: "0x3c49".to_string(),
+        }
+    }
+
+    fn update(edge: &EdgeIdentity, cost: f64, liquidity: f64, at: u64) -> EdgeEvent {
+        EdgeEvent::Metrics { edge: edge.clone(), metrics: EdgeMetrics { cost, speed: 60.0, liquidity, risk: 0.1 }, at }
+    }
+
+    #[tokio::test]
+    async fn rules_fire_once_per_cooldown() {
+        let notifier = Arc::new(RecordingNotifier::default());
+        let engine = AlertEngine::new(&config(None)).with_notifier(notifier.clone());
+        let stargate = edge("lifi:stargate", "ethereum");
+        let across = edge("across", "arbitrum");
+
+        // Cost creeps up, then doubles within the window but not over the lowest cost of it
+        for (minute, cost) in [(0, 1.0), (10, 1.5), (20, 1.9)] {
+            assert!(engine.evaluate(&update(&stargate, cost, 1_000_000.0, minute * 60)).is_empty());
+        }
+        let fired = engine.evaluate(&update(&stargate, 2.5, 1_000_000.0, 25 * 60));
+        assert_eq!(fired.len(), 1);
+        assert_eq!((fired[0].rule.as_str(), fired[0].metric, fired[0].old_value, fired[0].new_value), ("stargate-cost", "cost", 1.0, 2.5));
+        assert_eq!(engine.dispatch(&fired).await.len(), 0);
+        assert_eq!(notifier.alerts.lock().unwrap().as_slice(), fired.as_slice());
+
+        // Still doubled, but within the cooldown
+        assert!(engine.evaluate(&update(&stargate, 2.6, 1_000_000.0, 30 * 60)).is_empty());
+        // Once the cheaper quotes are out of the window, 2.6 over 1.9 isn't a doubling any more
+        assert!(engine.evaluate(&update(&stargate, 2.6, 1_000_000.0, 41 * 60)).is_empty());
+        // A fresh spike after the cooldown fires again
+        let fired = engine.evaluate(&update(&stargate, 6.0, 1_000_000.0, 45 * 60));
+        assert_eq!((fired.len(), fired[0].old_value), (1, 1.9));
+
+        // Other bridges aren't watched for cost, and the liquidity rule only watches ethereum
+        assert!(engine.evaluate(&update(&across, 1.0, 10.0, 0)).is_empty());
+        assert!(engine.evaluate(&update(&across, 9.0, 10.0, 60)).is_empty());
+
+        let fired = engine.evaluate(&update(&stargate, 6.0, 20_000.0, 46 * 60));
+        assert_eq!((fired[0].rule.as_str(), fired[0].old_value, fired[0].new_value), ("thin", 1_000_000.0, 20_000.0));
+
+        let fired = engine.evaluate(&EdgeEvent::Deactivated { edge: across.clone(), at: 120 });
+        assert_eq!((fired[0].rule.as_str(), fired[0].kind, fired[0].old_value, fired[0].new_value), ("gone", "edge_deactivated", 1.0, 0.0));
+        assert!(engine.evaluate(&EdgeEvent::Deactivated { edge: across, at: 180 }).is_empty());
+    }
+
+    #[tokio::test]
+    async fn webhook_receives_the_alert_as_json() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST")).and(path("/hooks/polypath")).respond_with(ResponseTemplate::new(204)).expect(1).mount(&server).await;
+        let url = format!("{}/hooks/polypath", server.uri());
+        let engine = AlertEngine::from_config(&config(Some(&url)), LoggingManager).unwrap();
+
+        let fired = engine.evaluate(&EdgeEvent::Deactivated { edge: edge("stargate", "ethereum"), at: 1_700_000_000 });
+        assert!(engine.dispatch(&fired).await.is_empty());
+
+        let received = server.received_requests().await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&received[0].body).unwrap();
+        assert_eq!(body, serde_json::json!({
+            "rule": "gone",
+            "kind": "edge_deactivated",
+            "edge": {
+                "edge_id": "stargate:ethereum:0xa0b8->polygon:0x3c49",
+                "bridge": "stargate",
+                "src_chain": "ethereum",
+                "src_token": "0xa0b8",
+                "dst_chain": "polygon",
+                "dst_token": "0x3c49",
+            },
+            "metric": "active",
+            "old_value": 1.0,
+            "new_value": 0.0,
+            "fired_at": 1_700_000_000u64,
+        }));
+
+        // A failing webhook is reported without holding back the log notifier
+        let failing = AlertEngine::from_config(&config(Some(&format!("{}/missing", server.uri()))), LoggingManager).unwrap();
+        let fired = failing.evaluate(&EdgeEvent::Deactivated { edge: edge("stargate", "ethereum"), at: 0 });
+        let failures = failing.dispatch(&fired).await;
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].to_string().contains("notifier `webhook` failed on rule `gone`"), "{}", failures[0]);
+    }
+}
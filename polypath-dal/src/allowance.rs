@@ -0,0 +1,298 @@
+// ERC-20 allowances bridges' contracts already have, so plans only ask for the approvals missing
+
+use std::{collections::HashMap, fmt, sync::Arc, time::Duration};
+use async_trait::async_trait;
+use futures::future::join_all;
+use polypath_graph::{BridgeStep, ExecutionPlan, ExecutionStep};
+use polypathroute_core::{LoggingManager, Registry};
+use reqwest::Client;
+use serde_json::{Value, json};
+use thiserror::Error;
+
+use crate::adapters::TokenDecimals;
+
+// How long an RPC gets to answer
+const ALLOWANCE_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+// Selector of allowance(address,address)
+const ALLOWANCE_SELECTOR: &str = "dd62ed3e";
+
+#[derive(Debug, Error)]
+pub enum AllowanceError {
+    #[error("no RPC is configured for chain `{0}`")]
+    NoRpc(String),
+
+    #[error("`{0}` is not an EVM address")]
+    InvalidAddress(String),
+
+    #[error("cannot read the allowance on `{chain}`: {reason}")]
+    Fetch { chain: String, reason: String },
+}
+
+#[async_trait]
+pub trait AllowanceChecker: Send + Sync + fmt::Debug {
+    // Raw units of `token` on `chain` that `spender` may move for `owner`; allowances past
+    // u128::MAX, e.g. unlimited approvals, are u128::MAX
+    async fn allowance(&self, chain: &str, token: &str, owner: &str, spender: &str) -> Result<u128, AllowanceError>;
+}
+
+// Allowances read with an eth_call to the token's allowance(owner, spender) through each
+// chain's RPC, the [gas.chains.<chain>] rpc_url
+#[derive(Debug)]
+pub struct RpcAllowanceChecker {
+    client: Client,
+    rpc_urls: HashMap<String, String>,
+}
+
+impl RpcAllowanceChecker {
+    // `rpc_urls`' chains are matched against the chains `allowance` is asked about as given
+    pub fn new(rpc_urls: HashMap<String, String>) -> anyhow::Result<Self> {
+        let client = Client::builder().timeout(ALLOWANCE_REQUEST_TIMEOUT).build()?;
+        Ok(Self { client, rpc_urls })
+    }
+}
+
+#[async_trait]
+impl AllowanceChecker for RpcAllowanceChecker {
+    async fn allowance(&self, chain: &str, token: &str, owner: &str, spender: &str) -> Result<u128, AllowanceError> {
+        let url = self.rpc_urls.get(chain).ok_or_else(|| AllowanceError::NoRpc(chain.to_string()))?;
+        let data = format!("0x{}{}{}", ALLOWANCE_SELECTOR, abi_address(owner)?, abi_address(spender)?);
+        let fetch_error = |reason: String| AllowanceError::Fetch { chain: chain.to_string(), reason };
+        let body: Value = self
+            .client
+            .post(url)
+            .json(&json!({ "jsonrpc": "2.0", "id": 1, "method": "eth_call", "params": [{ "to": token, "data": data }, "latest"] }))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| fetch_error(err.to_string()))?
+            .json()
+            .await
+            .map_err(|err| fetch_error(err.to_string()))?;
+
+        let digits = body["result"].as_str().and_then(|hex| hex.strip_prefix("0x")).map(|hex| hex.trim_start_matches('0'));
+        match digits {
+            Some("") => Ok(0),
+            Some(digits) if digits.len() > 32 && digits.chars().all(|c| c.is_ascii_hexdigit()) => Ok(u128::MAX),
+            Some(digits) => u128::from_str_radix(digits, 16).map_err(|_| fetch_error(format!("unexpected response {}", body))),
+            None => Err(fetch_error(format!("unexpected response {}", body))),
+        }
+    }
+}
+
+// A 20-byte address left-padded to an ABI word, without 0x
+fn abi_address(address: &str) -> Result<String, AllowanceError> {
+    let hex = address.trim().strip_prefix("0x").filter(|hex| hex.len() == 40 && hex.chars().all(|c| c.is_ascii_hexdigit()));
+    hex.map(|hex| format!("{:0>64}", hex.to_lowercase())).ok_or_else(|| AllowanceError::InvalidAddress(address.to_string()))
+}
+
+// Fixed allowances for tests, keyed by (chain, token, spender); anything else has none
+#[cfg(any(test, feature = "mock"))]
+#[derive(Debug, Default)]
+pub struct MockAllowanceChecker {
+    allowances: HashMap<(String, String, String), u128>,
+}
+
+#[cfg(any(test, feature = "mock"))]
+impl MockAllowanceChecker {
+    pub fn with_allowance(mut self, chain: &str, token: &str, spender: &str, raw: u128) -> Self {
+        self.allowances.insert((chain.to_lowercase(), token.to_lowercase(), spender.to_lowercase()), raw);
+        self
+    }
+}
+
+#[cfg(any(test, feature = "mock"))]
+#[async_trait]
+impl AllowanceChecker for MockAllowanceChecker {
+    async fn allowance(&self, chain: &str, token: &str, _owner: &str, spender: &str) -> Result<u128, AllowanceError> {
+        let key = (chain.to_lowercase(), token.to_lowercase(), spender.to_lowercase());
+        Ok(self.allowances.get(&key).copied().unwrap_or(0))
+    }
+}
+
+// Adds the approvals an ExecutionPlan's bridge steps need. Each bridge's spender per chain comes
+// from its [extra.spenders] table, e.g. `spenders = { ethereum = "0x..." }`; steps of bridges
+// or chains without one, and of native tokens, need no approval. An allowance that can't be
+// read, for lack of an RPC or otherwise, is taken to fall short: the approval is added with
+// assumed_required set.
+#[derive(Debug)]
+pub struct ApprovalPlanner {
+    checker: Arc<dyn AllowanceChecker>,
+    // (bridge, chain) -> spender
+    spenders: HashMap<(String, String), String>,
+    // For token decimals
+    registry: Registry,
+    logger: LoggingManager,
+}
+
+impl ApprovalPlanner {
+    pub fn new(checker: Arc<dyn AllowanceChecker>, spenders: HashMap<(String, String), String>, registry: Registry, logger: LoggingManager) -> Self {
+        Self { checker, spenders, registry, logger }
+    }
+
+    pub fn spender(&self, bridge: &str, chain: &str) -> Option<&str> {
+        self.spenders.get(&(bridge.to_string(), chain.to_string())).map(String::as_str)
+    }
+
+    // Inserts an ExecutionStep::Approve before every bridge step whose allowance for `owner`
+    // is short of its amount_in
+    pub async fn add_approvals(&self, plan: &mut ExecutionPlan, owner: &str) {
+        let checks = plan.bridge_steps().map(|step| async move { (step.step_index, self.approval(step, owner).await) });
+        let approvals = join_all(checks)
+            .await
+            .into_iter()
+            .filter_map(|(index, approval)| Some((index, approval?)))
+            .collect();
+        plan.insert_approvals(approvals);
+    }
+
+    async fn approval(&self, step: &BridgeStep, owner: &str) -> Option<ExecutionStep> {
+        let (chain, token) = (&step.src_chain, &step.src_token_address);
+        let spender = self.spender(&step.bridge, chain)?;
+        if TokenDecimals::is_native(token) {
+            return None;
+        }
+        let approve = |assumed_required: bool| ExecutionStep::Approve {
+            chain: chain.clone(),
+            token: token.clone(),
+            spender: spender.to_string(),
+            amount: step.amount_in,
+            assumed_required,
+        };
+
+        let allowance = match self.checker.allowance(chain, token, owner, spender).await {
+            Ok(allowance) => allowance,
+            Err(AllowanceError::NoRpc(_)) => return Some(approve(true)),
+            Err(err) => {
+                self.logger.warn_with("allowance unavailable, assuming an approval is needed", &[("chain", chain), ("token", token), ("error", &err.to_string())]);
+                return Some(approve(true));
+            }
+        };
+        let Ok(decimals) = self.registry.resolve_token(chain, token).map(|found| found.decimals) else {
+            self.logger.warn_with("token decimals unknown, assuming an approval is needed", &[("chain", chain), ("token", token)]);
+            return Some(approve(true));
+        };
+        let needed = (step.amount_in * 10f64.powi(decimals as i32)).ceil() as u128;
+        (allowance < needed).then(|| approve(false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polypath_graph::{EdgeMetrics, Graph, PlanOptions, RouteIntent, RouteOptions, Router, SlippageModel};
+    use wiremock::{Mock, MockServer, ResponseTemplate, matchers::{body_partial_json, method}};
+
+    const USDC_ETHEREUM: &str = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+    const USDC_ARBITRUM: &str = "0xaf88d065e77c8cC2239327C5EDb3A432268e5831";
+    const USDC_BASE: &str = "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913";
+    const OWNER: &str = "0x1111111111111111111111111111111111111111";
+    const SPENDER: &str = "0x2222222222222222222222222222222222222222";
+
+    // 100 USDC from ethereum to base over arbitrum, for a fee of 1 per hop and no slippage
+    fn plan() -> ExecutionPlan {
+        let graph = Arc::new(Graph::new(4));
+        let nodes: Vec<_> = [("ethereum", USDC_ETHEREUM), ("arbitrum", USDC_ARBITRUM), ("base", USDC_BASE)]
+            .iter()
+            .map(|(chain, address)| graph.get_or_create_asset_node(chain, address, "USDC"))
+            .collect();
+        for pair in nodes.windows(2) {
+            let metrics = EdgeMetrics { cost: 1.0, speed: 60.0, liquidity: 1_000_000.0, risk: 0.1 };
+            graph.add_edge(pair[0], pair[1], "stargate", metrics, None, None).unwrap();
+        }
+        let intent = RouteIntent {
+            from_chain: "ethereum".to_string(),
+            from_token: "USDC".to_string(),
+            to_chain: "base".to_string(),
+            to_token: "USDC".to_string(),
+            amount: 100.0,
+            preference: Some("cheapest".to_string()),
+            src_address: None,
+        };
+        let router = Router::new(Arc::clone(&graph)).with_slippage(SlippageModel::Linear { impact_per_utilization: 0.0 }, 1.0);
+        let routes = router.best_routes(&intent, &RouteOptions::default()).unwrap();
+        ExecutionPlan::from_path_with(&routes[0].ranked, &graph, &intent, &PlanOptions::default(), 0).unwrap()
+    }
+
+    // Stargate spends on both chains the plan leaves from
+    fn planner(checker: impl AllowanceChecker + 'static) -> ApprovalPlanner {
+        let spenders = ["ethereum", "arbitrum"].iter().map(|chain| (("stargate".to_string(), chain.to_string()), SPENDER.to_string())).collect();
+        ApprovalPlanner::new(Arc::new(checker), spenders, Registry::builtin(), LoggingManager)
+    }
+
+    fn approvals(plan: &ExecutionPlan) -> Vec<(&str, f64, bool)> {
+        plan.steps
+            .iter()
+            .filter_map(|step| match step {
+                ExecutionStep::Approve { chain, amount, assumed_required, .. } => Some((chain.as_str(), *amount, *assumed_required)),
+                ExecutionStep::Bridge(_) => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn sufficient_allowances_need_no_approval() {
+        let checker = MockAllowanceChecker::default()
+            .with_allowance("ethereum", USDC_ETHEREUM, SPENDER, 100_000_000)
+            .with_allowance("arbitrum", USDC_ARBITRUM, SPENDER, u128::MAX);
+        let mut plan = plan();
+        let unchanged = plan.clone();
+        planner(checker).add_approvals(&mut plan, OWNER).await;
+        assert_eq!(plan, unchanged);
+
+        // A raw unit short on ethereum
+        let checker = MockAllowanceChecker::default()
+            .with_allowance("ethereum", USDC_ETHEREUM, SPENDER, 99_999_999)
+            .with_allowance("arbitrum", USDC_ARBITRUM, SPENDER, u128::MAX);
+        planner(checker).add_approvals(&mut plan, OWNER).await;
+        assert_eq!(approvals(&plan), [("ethereum", 100.0, false)]);
+    }
+
+    #[tokio::test]
+    async fn insufficient_allowances_are_approved_first() {
+        let rpc = MockServer::start().await;
+        let call = |token: &str| {
+            body_partial_json(json!({
+                "method": "eth_call",
+                "params": [{ "to": token, "data": format!("0x{}{:0>64}{:0>64}", ALLOWANCE_SELECTOR, &OWNER[2..], &SPENDER[2..]) }, "latest"]
+            }))
+        };
+        // 50 USDC approved on ethereum, unlimited on arbitrum
+        Mock::given(method("POST"))
+            .and(call(USDC_ETHEREUM))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "jsonrpc": "2.0", "id": 1, "result": format!("0x{:064x}", 50_000_000) })))
+            .mount(&rpc)
+            .await;
+        Mock::given(method("POST"))
+            .and(call(USDC_ARBITRUM))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "jsonrpc": "2.0", "id": 1, "result": format!("0x{}", "f".repeat(64)) })))
+            .mount(&rpc)
+            .await;
+        let rpc_urls = ["ethereum", "arbitrum"].iter().map(|chain| (chain.to_string(), rpc.uri())).collect();
+        let mut plan = plan();
+        planner(RpcAllowanceChecker::new(rpc_urls).unwrap()).add_approvals(&mut plan, OWNER).await;
+
+        assert_eq!(plan.steps[0], ExecutionStep::Approve {
+            chain: "ethereum".to_string(),
+            token: USDC_ETHEREUM.to_string(),
+            spender: SPENDER.to_string(),
+            amount: 100.0,
+            assumed_required: false,
+        });
+        assert_eq!(approvals(&plan).len(), 1);
+        let bridges: Vec<(usize, Vec<usize>)> = plan.bridge_steps().map(|step| (step.step_index, step.depends_on.clone())).collect();
+        assert_eq!(bridges, [(1, vec![0]), (2, vec![1])]);
+    }
+
+    #[tokio::test]
+    async fn chains_without_an_rpc_are_assumed_to_need_approval() {
+        let mut plan = plan();
+        planner(RpcAllowanceChecker::new(HashMap::new()).unwrap()).add_approvals(&mut plan, OWNER).await;
+        assert_eq!(approvals(&plan), [("ethereum", 100.0, true), ("arbitrum", 99.0, true)]);
+
+        // Unreachable RPCs and bad owners are treated alike
+        let rpc_urls = HashMap::from([("ethereum".to_string(), "http://127.0.0.1:9".to_string())]);
+        let mut plan = self::plan();
+        planner(RpcAllowanceChecker::new(rpc_urls).unwrap()).add_approvals(&mut plan, "alice").await;
+        assert_eq!(approvals(&plan), [("ethereum", 100.0, true), ("arbitrum", 99.0, true)]);
+    }
+}
@@ -0,0 +1,134 @@
+// API keys an operator stores rather than configures, served alongside the [api_keys] ones.
+// Only a salted hash of each key is stored.
+
+use polypathroute_core::{ApiKeyConfig, CoreError, HashedSecret, PersistenceError, PersistenceManager, Versioned};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::DalError;
+
+const API_KEY_PREFIX: &str = "api_keys/";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredApiKey {
+    pub tenant: String,
+    pub key_hash: HashedSecret,
+    // Without its key
+    #[serde(flatten)]
+    pub policy: ApiKeyConfig,
+}
+
+// Version 1 stored the key itself
+impl Versioned for StoredApiKey {
+    const SCHEMA: &'static str = "api_key";
+    const VERSION: u32 = 2;
+}
+
+impl StoredApiKey {
+    // Checks the policy while it still has its key, then keeps only the key's hash
+    pub fn new(tenant: &str, mut policy: ApiKeyConfig) -> Result<Self, DalError> {
+        policy.validate(tenant).map_err(|(field, reason)| DalError::InvalidApiKey {
+            tenant: tenant.to_string(),
+            reason: format!("{} {}", field, reason),
+        })?;
+        let key_hash = HashedSecret::new(&std::mem::take(&mut policy.key));
+        Ok(Self { tenant: tenant.to_string(), key_hash, policy })
+    }
+
+    pub fn verify(&self, key: &str) -> bool {
+        self.key_hash.verify(key)
+    }
+}
+
+// A version 1 payload with its key swapped for the key's hash
+fn hash_stored_key(mut payload: Value) -> Result<Value, String> {
+    let object = payload.as_object_mut().ok_or("not an object")?;
+    let key = object.remove("key").and_then(|key| key.as_str().map(str::to_string)).ok_or("no key")?;
+    let key_hash = serde_json::to_value(HashedSecret::new(&key)).map_err(|err| err.to_string())?;
+    object.insert("key_hash".to_string(), key_hash);
+    Ok(payload)
+}
+
+fn api_key_key(tenant: &str) -> String {
+    format!("{}{}", API_KEY_PREFIX, tenant)
+}
+
+fn api_key_error(tenant: &str, err: PersistenceError) -> DalError {
+    match err {
+        PersistenceError::Encode { source, .. } | PersistenceError::Decode { source, .. } => {
+            DalError::ApiKey { tenant: tenant.to_string(), source }
+        }
+        err => CoreError::from(err).into(),
+    }
+}
+
+// Replaces the tenant's stored key, if it had one
+pub fn save_api_key(persistence: &PersistenceManager, api_key: &StoredApiKey) -> Result<(), DalError> {
+    persistence.put_typed(&api_key_key(&api_key.tenant), api_key).map_err(|err| api_key_error(&api_key.tenant, err))
+}
+
+// Sorted by tenant. Keys still stored as they were sent are rewritten as hashes on the way.
+pub fn list_api_keys(persistence: &PersistenceManager) -> Result<Vec<StoredApiKey>, DalError> {
+    persistence.migrator().register(StoredApiKey::SCHEMA, 1, hash_stored_key);
+    let mut api_keys = Vec::new();
+    for (key, encoded) in persistence.scan_prefix(API_KEY_PREFIX).map_err(CoreError::from)? {
+        let tenant = key.trim_start_matches(API_KEY_PREFIX);
+        let api_key = persistence.decode_typed::<StoredApiKey>(&key, &encoded).map_err(|err| api_key_error(tenant, err))?;
+        let version = serde_json::from_str::<Value>(&encoded).ok().and_then(|stored| stored["version"].as_u64());
+        if version != Some(StoredApiKey::VERSION as u64) {
+            save_api_key(persistence, &api_key)?;
+        }
+        api_keys.push(api_key);
+    }
+    api_keys.sort_by(|a, b| a.tenant.cmp(&b.tenant));
+    Ok(api_keys)
+}
+
+// Whether there was a key to delete
+pub fn delete_api_key(persistence: &PersistenceManager, tenant: &str) -> Result<bool, DalError> {
+    Ok(persistence.delete(api_key_key(tenant)).map_err(CoreError::from)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polypathroute_core::ApiFeature;
+
+    #[test]
+    fn api_keys_round_trip_through_persistence() {
+        let persistence = PersistenceManager::new();
+        let partner = StoredApiKey::new("partner-b", ApiKeyConfig { features: vec![ApiFeature::Watch], ..ApiKeyConfig::new("pb-0123456789abcdef") }).unwrap();
+        save_api_key(&persistence, &partner).unwrap();
+        save_api_key(&persistence, &StoredApiKey::new("partner-a", ApiKeyConfig::new("pa-0123456789abcdef")).unwrap()).unwrap();
+
+        let tenants: Vec<String> = list_api_keys(&persistence).unwrap().into_iter().map(|api_key| api_key.tenant).collect();
+        assert_eq!(tenants, ["partner-a", "partner-b"]);
+        assert_eq!(list_api_keys(&persistence).unwrap()[1], partner);
+        assert!(partner.verify("pb-0123456789abcdef") && !partner.verify("pa-0123456789abcdef"));
+
+        assert!(delete_api_key(&persistence, "partner-a").unwrap());
+        assert!(!delete_api_key(&persistence, "partner-a").unwrap());
+        assert_eq!(list_api_keys(&persistence).unwrap(), [partner]);
+
+        let short = StoredApiKey::new("partner-c", ApiKeyConfig::new("short")).unwrap_err();
+        assert!(matches!(short, DalError::InvalidApiKey { .. }), "{}", short);
+        assert!(StoredApiKey::new("../partner", ApiKeyConfig::new("pc-0123456789abcdef")).is_err());
+    }
+
+    #[test]
+    fn keys_are_never_stored_as_sent() {
+        let persistence = PersistenceManager::new();
+        save_api_key(&persistence, &StoredApiKey::new("partner-a", ApiKeyConfig::new("pa-0123456789abcdef")).unwrap()).unwrap();
+        // As version 1 stored them
+        let legacy = r#"{"schema":"api_key","version":1,"payload":{"tenant":"partner-b","key":"pb-0123456789abcdef","max_results":2}}"#;
+        persistence.store(api_key_key("partner-b"), legacy.to_string()).unwrap();
+
+        let api_keys = list_api_keys(&persistence).unwrap();
+        assert!(api_keys[1].verify("pb-0123456789abcdef"));
+        assert_eq!(api_keys[1].policy.max_results, Some(2));
+        for (_, encoded) in persistence.scan_prefix(API_KEY_PREFIX).unwrap() {
+            assert!(!encoded.contains("0123456789abcdef"), "{}", encoded);
+        }
+        assert_eq!(list_api_keys(&persistence).unwrap(), api_keys);
+    }
+}
@@ -0,0 +1,454 @@
+// A record of the route queries answered, for compliance: when, what was asked with which
+// options and profile, against which graph version, and the routes given back in summary.
+// Entries are JSON lines appended to size-capped segments in the persistence store by a
+// background thread, so queries never wait on storage.
+
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+    },
+};
+use polypath_graph::{ExplainedPath, RouteIntent, RouteObserver, RouteOptions};
+use polypathroute_core::{AuditConfig, CoreError, LoggingManager, PersistenceError, PersistenceManager, Versioned, WriteOp};
+use serde::{Deserialize, Serialize};
+
+use crate::{adapters::unix_now, error::DalError};
+
+// Prefix of every audit segment in the persistence store
+const AUDIT_PREFIX: &str = "audit/";
+
+// A route as the audit keeps it: enough to tell which route was offered, not its hop data
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditedRoute {
+    pub rank: usize,
+    pub bridges: Vec<String>,
+    pub score: f64,
+    pub total_cost: f64,
+    pub total_time: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimated_output: Option<f64>,
+    pub summary: String,
+}
+
+impl From<&ExplainedPath> for AuditedRoute {
+    fn from(route: &ExplainedPath) -> Self {
+        let path = &route.ranked.path;
+        Self {
+            rank: route.ranked.rank,
+            bridges: path.hops.iter().map(|hop| hop.bridge_name.to_string()).collect(),
+            score: route.ranked.score_breakdown.final_score,
+            total_cost: path.total_cost,
+            total_time: path.total_time,
+            estimated_output: path.estimated_output,
+            summary: route.summary.clone(),
+        }
+    }
+}
+
+// One answered query
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    // Unix seconds
+    pub timestamp: u64,
+    // See `intent_hash`
+    pub intent_hash: String,
+    pub intent: RouteIntent,
+    pub options: RouteOptions,
+    // The preference profile the options were layered from, if any
+    #[serde(default)]
+    pub profile: Option<String>,
+    // The API tenant the query was answered for, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    pub graph_version: u64,
+    // Best first; empty when no route satisfied the options
+    pub routes: Vec<AuditedRoute>,
+}
+
+impl Versioned for AuditEntry {
+    const SCHEMA: &'static str = "audit_entry";
+    const VERSION: u32 = 1;
+}
+
+impl AuditEntry {
+    pub fn new(timestamp: u64, intent: &RouteIntent, options: &RouteOptions, routes: &[ExplainedPath], graph_version: u64) -> Self {
+        Self {
+            timestamp,
+            intent_hash: intent_hash(intent),
+            intent: intent.clone(),
+            options: options.clone(),
+            profile: options.profile.clone(),
+            tenant: options.tenant.clone(),
+            graph_version,
+            routes: routes.iter().map(AuditedRoute::from).collect(),
+        }
+    }
+}
+
+// See RouteIntent::intent_hash
+pub fn intent_hash(intent: &RouteIntent) -> String {
+    intent.intent_hash()
+}
+
+// What the writer got done so far
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct AuditStats {
+    pub written: u64,
+    // Entries dropped because the queue was full
+    pub dropped: u64,
+    // Entries that couldn't be encoded or whose write failed
+    pub failed: u64,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    written: AtomicU64,
+    dropped: AtomicU64,
+    failed: AtomicU64,
+}
+
+enum Message {
+    Entry(Box<AuditEntry>),
+    // Answered once everything queued before it is written
+    Flush(SyncSender<()>),
+}
+
+// Writes the entries it's given from a background thread, through a queue of
+// audit.queue_capacity entries. Recording never blocks: entries that don't fit in the queue are
+// dropped and counted. Dropping the log lets the thread write what's queued and exit.
+#[derive(Debug)]
+pub struct AuditLog {
+    store: PersistenceManager,
+    queue: SyncSender<Message>,
+    counters: Arc<Counters>,
+}
+
+impl AuditLog {
+    // Appends to the last segment already in `store`, if any
+    pub fn new(store: PersistenceManager, config: &AuditConfig) -> Self {
+        let (queue, received) = mpsc::sync_channel(config.queue_capacity);
+        let counters = Arc::new(Counters::default());
+        let writer = Writer::open(store.clone(), config.segment_bytes, Arc::clone(&counters));
+        let _ = std::thread::Builder::new().name("polypath-audit".to_string()).spawn(move || writer.run(received));
+        Self { store, queue, counters }
+    }
+
+    // Queues `entry`, or drops it when the queue is full
+    pub fn record(&self, entry: AuditEntry) {
+        match self.queue.try_send(Message::Entry(Box::new(entry))) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_) | TrySendError::Disconnected(_)) => {
+                self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    // Waits until everything recorded so far is written
+    pub fn flush(&self) {
+        let (done, written) = mpsc::sync_channel(1);
+        if self.queue.send(Message::Flush(done)).is_ok() {
+            let _ = written.recv();
+        }
+    }
+
+    pub fn stats(&self) -> AuditStats {
+        AuditStats {
+            written: self.counters.written.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+            failed: self.counters.failed.load(Ordering::Relaxed),
+        }
+    }
+
+    // Written entries from `from` up to and including `to`, in unix seconds, oldest first
+    pub fn by_time_range(&self, from: u64, to: u64) -> Result<Vec<AuditEntry>, DalError> {
+        self.entries(|entry| (from..=to).contains(&entry.timestamp))
+    }
+
+    // Written entries for intents with this `intent_hash`, oldest first
+    pub fn by_intent_hash(&self, hash: &str) -> Result<Vec<AuditEntry>, DalError> {
+        self.entries(|entry| entry.intent_hash == hash)
+    }
+
+    fn entries(&self, wanted: impl Fn(&AuditEntry) -> bool) -> Result<Vec<AuditEntry>, DalError> {
+        let mut entries = Vec::new();
+        for (key, segment) in self.store.scan_prefix(AUDIT_PREFIX).map_err(CoreError::from)? {
+            for line in segment.lines() {
+                let entry: AuditEntry = self.store.decode_typed(&key, line).map_err(|err| match err {
+                    PersistenceError::Decode { source, .. } => DalError::Audit { segment: key.clone(), source },
+                    err => CoreError::from(err).into(),
+                })?;
+                if wanted(&entry) {
+                    entries.push(entry);
+                }
+            }
+        }
+        entries.sort_by_key(|entry| entry.timestamp);
+        Ok(entries)
+    }
+}
+
+impl RouteObserver for AuditLog {
+    fn routes_served(&self, intent: &RouteIntent, opts: &RouteOptions, routes: &[ExplainedPath], graph_version: u64) {
+        self.record(AuditEntry::new(unix_now(), intent, opts, routes, graph_version));
+    }
+}
+
+// Zero-padded so segments sort in the order they were written
+fn segment_key(index: u64) -> String {
+    format!("{}{:010}", AUDIT_PREFIX, index)
+}
+
+// Owns the last segment: entries are only ever appended to it, and once the next one would grow
+// it past segment_bytes a new segment is started
+struct Writer {
+    store: PersistenceManager,
+    segment_bytes: usize,
+    counters: Arc<Counters>,
+    index: u64,
+    segment: String,
+    // Entries in `segment` not written yet
+    pending: u64,
+}
+
+impl Writer {
+    fn open(store: PersistenceManager, segment_bytes: usize, counters: Arc<Counters>) -> Self {
+        let last = store.keys_with_prefix(AUDIT_PREFIX).ok().and_then(|keys| keys.last().cloned());
+        let index = last.as_deref().and_then(|key| key.strip_prefix(AUDIT_PREFIX)?.parse().ok());
+        let (index, segment) = match index {
+            Some(index) => match store.get(segment_key(index)) {
+                Ok(segment) => (index, segment.unwrap_or_default()),
+                // Never write over a segment that couldn't be read
+                Err(_) => (index + 1, String::new()),
+            },
+            None => (0, String::new()),
+        };
+        Self { store, segment_bytes, counters, index, segment, pending: 0 }
+    }
+
+    fn run(mut self, received: Receiver<Message>) {
+        while let Ok(first) = received.recv() {
+            let mut flushes = Vec::new();
+            let mut batch = BTreeMap::new();
+            for message in std::iter::once(first).chain(received.try_iter()) {
+                match message {
+                    Message::Entry(entry) => self.append(&entry, &mut batch),
+                    Message::Flush(done) => flushes.push(done),
+                }
+            }
+            batch.insert(self.index, self.segment.clone());
+            self.write(batch);
+            for done in flushes {
+                let _ = done.send(());
+            }
+        }
+    }
+
+    // Segments finished along the way go into `batch`
+    fn append(&mut self, entry: &AuditEntry, batch: &mut BTreeMap<u64, String>) {
+        let Ok(line) = self.store.encode_typed(&segment_key(self.index), entry) else {
+            self.counters.failed.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+        if !self.segment.is_empty() && self.segment.len() + line.len() + 1 > self.segment_bytes {
+            batch.insert(self.index, std::mem::take(&mut self.segment));
+            self.index += 1;
+        }
+        self.segment.push_str(&line);
+        self.segment.push('\n');
+        self.pending += 1;
+    }
+
+    fn write(&mut self, batch: BTreeMap<u64, String>) {
+        if self.pending == 0 {
+            return;
+        }
+        let ops: Vec<WriteOp> = batch.into_iter().map(|(index, value)| WriteOp::Put { key: segment_key(index), value }).collect();
+        match self.store.storage().apply(&ops) {
+            Ok(()) => self.counters.written.fetch_add(self.pending, Ordering::Relaxed),
+            Err(err) => {
+                LoggingManager.warn_with("audit entries not written", &[("entries", &self.pending), ("error", &err.to_string())]);
+                self.counters.failed.fetch_add(self.pending, Ordering::Relaxed)
+            }
+        };
+        self.pending = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use crate::{DalContext, graphs::GraphRegistry};
+    use polypath_graph::{EdgeMetrics, Graph};
+    use polypathroute_core::{MemoryStorage, PersistenceError, Storage};
+
+    fn intent(amount: f64) -> RouteIntent {
+        RouteIntent {
+            from_chain: "ethereum".to_string(),
+            from_token: "USDC".to_string(),
+            to_chain: "polygon".to_string(),
+            to_token: "USDC".to_string(),
+            amount,
+            preference: None,
+            src_address: None,
+        }
+    }
+
+    fn entry(timestamp: u64, amount: f64) -> AuditEntry {
+        AuditEntry::new(timestamp, &intent(amount), &RouteOptions::default(), &[], 1)
+    }
+
+    fn config(segment_bytes: usize, queue_capacity: usize) -> AuditConfig {
+        AuditConfig { enabled: true, segment_bytes, queue_capacity }
+    }
+
+    // A default graph with ethereum -> polygon USDC over stargate
+    fn registry(name: &str, audit: &str) -> GraphRegistry {
+        let config_path = std::env::temp_dir().join(format!("polypath-dal-audit-{}-{}.toml", name, std::process::id()));
+        std::fs::write(&config_path, format!("{}[bridges]\n", audit)).unwrap();
+        let dal = DalContext::new(config_path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&config_path).unwrap();
+
+        let graph = Graph::new(4);
+        let eth = graph.get_or_create_asset_node("ethereum", "0xa0b8", "USDC");
+        let pol = graph.get_or_create_asset_node("polygon", "0x3c49", "USDC");
+        graph.add_edge(eth, pol, "stargate", EdgeMetrics { cost: 1.0, speed: 60.0, liquidity: 1_000_000.0, risk: 0.1 }, None, None).unwrap();
+        GraphRegistry::with_default_graph(dal, graph, 4)
+    }
+
+    fn audit_keys(graphs: &GraphRegistry) -> Vec<String> {
+        graphs.dal().core.persisence_manager.keys_with_prefix(AUDIT_PREFIX).unwrap()
+    }
+
+    #[test]
+    fn served_routes_are_audited() {
+        let graphs = registry("enabled", "[audit]\nenabled = true\n");
+        let entry = graphs.default_graph();
+        let router = entry.router();
+        let profiled = RouteOptions { profile: Some("safest".to_string()), ..entry.route_options() };
+        let routes = router.best_routes(&intent(100.0), &profiled).unwrap();
+        router.best_routes(&intent(5_000.0), &entry.route_options()).unwrap();
+        // Not answered, so not audited
+        router.best_routes(&RouteIntent { to_token: "DAI".to_string(), ..intent(100.0) }, &profiled).unwrap_err();
+
+        let audit = graphs.audit().unwrap();
+        audit.flush();
+        assert_eq!(audit.stats(), AuditStats { written: 2, dropped: 0, failed: 0 });
+        let found = audit.by_intent_hash(&intent_hash(&RouteIntent { from_token: "usdc".to_string(), ..intent(100.0) })).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].profile.as_deref(), Some("safest"));
+        assert_eq!(found[0].graph_version, entry.graph().version());
+        assert_eq!(found[0].routes, [AuditedRoute::from(&routes[0])]);
+        assert_eq!(found[0].routes[0].bridges, ["stargate"]);
+        assert_eq!(audit.by_time_range(0, u64::MAX).unwrap().len(), 2);
+        assert_eq!(audit_keys(&graphs).len(), 1);
+    }
+
+    #[test]
+    fn disabled_audits_write_nothing() {
+        let graphs = registry("disabled", "");
+        assert!(graphs.audit().is_none());
+        let entry = graphs.default_graph();
+        assert_eq!(entry.router().best_routes(&intent(100.0), &entry.route_options()).unwrap().len(), 1);
+        assert!(audit_keys(&graphs).is_empty());
+    }
+
+    #[test]
+    fn entries_are_found_by_time_range() {
+        let audit = AuditLog::new(PersistenceManager::new(), &AuditConfig::default());
+        for (timestamp, amount) in [(300, 3.0), (100, 1.0), (200, 2.0), (400, 4.0)] {
+            audit.record(entry(timestamp, amount));
+        }
+        audit.flush();
+
+        let amounts = |entries: Vec<AuditEntry>| entries.iter().map(|entry| entry.intent.amount).collect::<Vec<_>>();
+        assert_eq!(amounts(audit.by_time_range(150, 300).unwrap()), [2.0, 3.0]);
+        assert_eq!(amounts(audit.by_time_range(100, 100).unwrap()), [1.0]);
+        assert!(audit.by_time_range(401, 500).unwrap().is_empty());
+        assert_eq!(amounts(audit.by_intent_hash(&intent_hash(&intent(4.0))).unwrap()), [4.0]);
+    }
+
+    #[test]
+    fn segments_rotate_at_the_size_cap() {
+        let store = PersistenceManager::new();
+        let line = store.encode_typed("audit", &entry(0, 0.0)).unwrap().len() + 1;
+        // Three entries to a segment
+        let config = config(line * 3 + line / 2, 64);
+        let audit = AuditLog::new(store.clone(), &config);
+        for index in 0..7 {
+            audit.record(entry(index, 0.0));
+        }
+        audit.flush();
+        assert_eq!(store.keys_with_prefix(AUDIT_PREFIX).unwrap(), [segment_key(0), segment_key(1), segment_key(2)]);
+        let sizes: Vec<usize> = store.scan_prefix(AUDIT_PREFIX).unwrap().iter().map(|(_, segment)| segment.lines().count()).collect();
+        assert_eq!(sizes, [3, 3, 1]);
+
+        // A new log appends to the last segment rather than writing over it
+        drop(audit);
+        let audit = AuditLog::new(store.clone(), &config);
+        for index in 7..10 {
+            audit.record(entry(index, 0.0));
+        }
+        audit.flush();
+        let sizes: Vec<usize> = store.scan_prefix(AUDIT_PREFIX).unwrap().iter().map(|(_, segment)| segment.lines().count()).collect();
+        assert_eq!(sizes, [3, 3, 3, 1]);
+        let timestamps: Vec<u64> = audit.by_time_range(0, u64::MAX).unwrap().iter().map(|entry| entry.timestamp).collect();
+        assert_eq!(timestamps, (0..10).collect::<Vec<_>>());
+    }
+
+    // Writes signal `entered`, then wait for the gate to be opened
+    #[derive(Debug)]
+    struct Gated {
+        inner: MemoryStorage,
+        entered: SyncSender<()>,
+        gate: Arc<Mutex<()>>,
+    }
+
+    impl Storage for Gated {
+        fn get(&self, key: &str) -> Result<Option<String>, PersistenceError> {
+            self.inner.get(key)
+        }
+
+        fn put(&self, key: &str, value: &str) -> Result<(), PersistenceError> {
+            self.inner.put(key, value)
+        }
+
+        fn delete(&self, key: &str) -> Result<bool, PersistenceError> {
+            self.inner.delete(key)
+        }
+
+        fn scan_prefix(&self, prefix: &str) -> Result<Vec<(String, String)>, PersistenceError> {
+            self.inner.scan_prefix(prefix)
+        }
+
+        fn apply(&self, batch: &[WriteOp]) -> Result<(), PersistenceError> {
+            let _ = self.entered.try_send(());
+            let _open = self.gate.lock().unwrap();
+            self.inner.apply(batch)
+        }
+    }
+
+    #[test]
+    fn a_full_queue_drops_entries_instead_of_blocking() {
+        let (entered, writing) = mpsc::sync_channel(1);
+        let gate = Arc::new(Mutex::new(()));
+        let store = PersistenceManager::from_storage(Gated { inner: MemoryStorage::new(), entered, gate: Arc::clone(&gate) });
+        let audit = AuditLog::new(store, &config(1024 * 1024, 1));
+
+        let closed = gate.lock().unwrap();
+        audit.record(entry(0, 0.0));
+        writing.recv().unwrap();
+        // The writer is stuck on the first entry, so one more fits in the queue
+        for index in 1..21 {
+            audit.record(entry(index, 0.0));
+        }
+        assert_eq!(audit.stats(), AuditStats { written: 0, dropped: 19, failed: 0 });
+        drop(closed);
+        audit.flush();
+        assert_eq!(audit.stats(), AuditStats { written: 2, dropped: 19, failed: 0 });
+        assert_eq!(audit.by_time_range(0, u64::MAX).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,158 @@
+// Native balances held on a route's source chain, against the gas its first transfer needs
+
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::{Duration, Instant}};
+use async_trait::async_trait;
+use polypath_graph::{Affordability, BalanceChecker};
+use polypathroute_core::LoggingManager;
+use reqwest::Client;
+use serde_json::{Value, json};
+
+use crate::gas::{GasAction, GasEstimator};
+
+// How long an RPC gets to answer
+const BALANCE_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+const WEI_PER_NATIVE: f64 = 1e18;
+
+// Balances read with eth_getBalance through each chain's RPC, the [gas.chains.<chain>] rpc_url,
+// once per (chain, address) for `ttl`. The gas is the estimator's for a transfer over the
+// bridge. Either one failing leaves the affordability Unknown.
+#[derive(Debug)]
+pub struct RpcBalanceChecker {
+    client: Client,
+    rpc_urls: HashMap<String, String>,
+    gas: Arc<dyn GasEstimator>,
+    ttl: Duration,
+    // Last balance fetched per (chain, address) and when
+    balances: Mutex<HashMap<(String, String), (f64, Instant)>>,
+    logger: LoggingManager,
+}
+
+impl RpcBalanceChecker {
+    // `rpc_urls`' chains are matched against the chains `affordability` is asked about as given
+    pub fn new(rpc_urls: HashMap<String, String>, gas: Arc<dyn GasEstimator>, ttl: Duration, logger: LoggingManager) -> anyhow::Result<Self> {
+        let client = Client::builder().timeout(BALANCE_REQUEST_TIMEOUT).build()?;
+        Ok(Self { client, rpc_urls, gas, ttl, balances: Mutex::default(), logger })
+    }
+
+    // In human units of the chain's native token
+    async fn balance(&self, chain: &str, address: &str) -> Result<f64, String> {
+        let key = (chain.to_string(), address.to_lowercase());
+        if let Some((balance, fetched_at)) = self.balances.lock().unwrap().get(&key)
+            && fetched_at.elapsed() < self.ttl
+        {
+            return Ok(*balance);
+        }
+        let url = self.rpc_urls.get(chain).ok_or_else(|| format!("no RPC is configured for chain `{}`", chain))?;
+        let body: Value = self
+            .client
+            .post(url)
+            .json(&json!({ "jsonrpc": "2.0", "id": 1, "method": "eth_getBalance", "params": [address, "latest"] }))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| err.to_string())?
+            .json()
+            .await
+            .map_err(|err| err.to_string())?;
+        let wei = body["result"]
+            .as_str()
+            .and_then(|hex| hex.strip_prefix("0x"))
+            .and_then(|hex| u128::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| format!("unexpected response {}", body))?;
+        let balance = wei as f64 / WEI_PER_NATIVE;
+        self.balances.lock().unwrap().insert(key, (balance, Instant::now()));
+        Ok(balance)
+    }
+}
+
+#[async_trait]
+impl BalanceChecker for RpcBalanceChecker {
+    async fn affordability(&self, chain: &str, bridge: &str, address: &str) -> Affordability {
+        let available = match self.balance(chain, address).await {
+            Ok(available) => available,
+            Err(reason) => {
+                self.logger.warn_with("cannot read the native balance", &[("chain", &chain), ("reason", &reason)]);
+                return Affordability::Unknown;
+            }
+        };
+        let required = match self.gas.estimate(chain, &GasAction::Bridge { bridge: bridge.to_string() }).await {
+            Ok(estimate) => estimate.native_cost(),
+            Err(err) => {
+                self.logger.warn_with("cannot estimate the gas of a route's first step", &[("chain", &chain), ("error", &err.to_string())]);
+                return Affordability::Unknown;
+            }
+        };
+        match available >= required {
+            true => Affordability::Affordable,
+            false => Affordability::InsufficientGas { required, available },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gas::OracleGasEstimator;
+    use polypathroute_core::{ConfigFormat, ConfigManager};
+    use wiremock::{Mock, MockServer, ResponseTemplate, matchers::{body_partial_json, method}};
+
+    const ALICE: &str = "0x00000000000000000000000000000000000a11ce";
+    const BOB: &str = "0x0000000000000000000000000000000000000b0b";
+
+    // Ethereum's gas priced through `rpc`, 150k gas units for a stargate transfer
+    fn checker(rpc: &MockServer) -> RpcBalanceChecker {
+        let config = ConfigManager::from_str(
+            &format!(
+                "[gas.chains.ethereum]\nrpc_url = \"{}\"\nnative_usd = 2000.0\n[bridges.stargate]\nbase_url = \"https://stargate.example\"\nchains = [\"ethereum\"]\nextra = {{ gas_units = {{ bridge = 150000 }} }}\n",
+                rpc.uri()
+            ),
+            ConfigFormat::Toml,
+        )
+        .unwrap();
+        let gas = Arc::new(OracleGasEstimator::from_config(&config.gas, &config.bridges, LoggingManager).unwrap());
+        let rpc_urls = HashMap::from([("ethereum".to_string(), rpc.uri())]);
+        RpcBalanceChecker::new(rpc_urls, gas, Duration::from_secs(30), LoggingManager).unwrap()
+    }
+
+    async fn respond(rpc: &MockServer, body: Value, result: &str) {
+        Mock::given(method("POST"))
+            .and(body_partial_json(body))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "jsonrpc": "2.0", "id": 1, "result": result })))
+            .expect(1)
+            .mount(rpc)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn balances_are_read_once_and_weighed_against_the_gas() {
+        let rpc = MockServer::start().await;
+        // 20 gwei, so 150k gas costs 0.003 ETH
+        respond(&rpc, json!({ "method": "eth_gasPrice" }), "0x4a817c800").await;
+        // 0.01 ETH for alice, 0.001 for bob
+        respond(&rpc, json!({ "method": "eth_getBalance", "params": [ALICE, "latest"] }), "0x2386f26fc10000").await;
+        respond(&rpc, json!({ "method": "eth_getBalance", "params": [BOB, "latest"] }), "0x38d7ea4c68000").await;
+        let checker = checker(&rpc);
+
+        assert_eq!(checker.affordability("ethereum", "stargate", ALICE).await, Affordability::Affordable);
+        // Cached, so the RPC isn't asked again
+        assert_eq!(checker.affordability("ethereum", "stargate", ALICE).await, Affordability::Affordable);
+        match checker.affordability("ethereum", "stargate", BOB).await {
+            Affordability::InsufficientGas { required, available } => {
+                assert!((required - 0.003).abs() < 1e-12, "{}", required);
+                assert!((available - 0.001).abs() < 1e-12, "{}", available);
+            }
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(checker.affordability("ethereum", "stargate", BOB).await, checker.affordability("ethereum", "stargate", BOB).await);
+
+        // No RPC for the chain
+        assert_eq!(checker.affordability("polygon", "stargate", ALICE).await, Affordability::Unknown);
+    }
+
+    #[tokio::test]
+    async fn unreadable_balances_are_unknown() {
+        let rpc = MockServer::start().await;
+        Mock::given(method("POST")).respond_with(ResponseTemplate::new(503)).mount(&rpc).await;
+        assert_eq!(checker(&rpc).affordability("ethereum", "stargate", ALICE).await, Affordability::Unknown);
+    }
+}
@@ -0,0 +1,429 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::{Duration, Instant}};
+use futures::{Stream, StreamExt, stream::{self, FuturesUnordered}};
+use tokio::sync::{Semaphore, watch};
+use tracing::{Instrument, Span};
+use anyhow::Result;
+use polypathroute_core::{LoggingManager, SlowOpsConfig};
+
+use crate::adapters::{AdapterError, BridgeEdge, DynBridgeAdapter, QuoteRequest, SupportedPair};
+
+// Amount quoted for each pair during a batch refresh: 1 unit of the source token
+pub const PROBE_AMOUNT: &str = "1";
+
+// PROBE_AMOUNT as a number
+pub(crate) fn probe_amount() -> f64 {
+    PROBE_AMOUNT.parse().expect("PROBE_AMOUNT is a number")
+}
+// Quotes are price discovery only, nothing is ever sent from or to this address
+pub const PROBE_ADDRESS: &str = "0x0000000000000000000000000000000000000001";
+
+// Name adapter calls are timed under, see SlowOpsConfig
+pub(crate) const FETCH_OPERATION: &str = "fetch_metrics";
+
+// Result of quoting one pair on one adapter. AdapterError::disposition tells the
+// caller whether to retry, defer or drop a failed pair.
+#[derive(Debug, Clone)]
+pub struct FetchOutcome {
+    pub adapter: String,
+    pub pair: SupportedPair,
+    // Adapter the quote was asked of when it isn't `adapter`, see GraphUpdater's source policies
+    pub source: Option<String>,
+    pub result: Result<BridgeEdge, AdapterError>,
+    // Time spent on the upstream request; None when no request was sent
+    pub latency: Option<Duration>,
+}
+
+impl FetchOutcome {
+    pub fn is_ok(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+pub(crate) fn probe_request(pair: &SupportedPair) -> Result<QuoteRequest> {
+    QuoteRequest::builder()
+        .src_chain(pair.src_chain.clone())
+        .dst_chain(pair.dst_chain.clone())
+        .src_token(pair.src_token.clone())
+        .dst_token(pair.dst_token.clone())
+        .src_amount(PROBE_AMOUNT)
+        .wallet(PROBE_ADDRESS)
+        .build()
+}
+
+// Quotes every (adapter, pair) job with at most `concurrency` requests in flight.
+// Adapters still apply their own rate limiters; a failing job only affects its own outcome.
+// Outcomes are returned in job order.
+pub async fn fetch_all(jobs: Vec<(Arc<DynBridgeAdapter>, SupportedPair)>, concurrency: usize) -> Vec<FetchOutcome> {
+    let threshold = SlowOpsConfig::default().threshold(FETCH_OPERATION);
+    fetch_all_in(jobs.into_iter().map(|(adapter, pair)| (adapter, pair, Span::none())).collect(), concurrency, threshold).await
+}
+
+// fetch_all with each job run in its own span, which tags whatever the adapter logs while quoting.
+// Jobs of an adapter with a max_batch_size above 1 are quoted in chunks of that size, one
+// fetch_metrics_batch call and one permit per chunk, run in the span of the chunk's first job.
+// Calls taking longer than `slow_after` are logged as slow.
+pub(crate) async fn fetch_all_in(
+    jobs: Vec<(Arc<DynBridgeAdapter>, SupportedPair, Span)>,
+    concurrency: usize,
+    slow_after: Duration,
+) -> Vec<FetchOutcome> {
+    let mut outcomes: Vec<Option<FetchOutcome>> = jobs.iter().map(|_| None).collect();
+    let mut fetched = std::pin::pin!(fetch_stream_in(jobs, concurrency, slow_after));
+    while let Some((index, outcome)) = fetched.next().await {
+        outcomes[index] = Some(outcome);
+    }
+    outcomes.into_iter().map(|outcome| outcome.expect("every job is fetched")).collect()
+}
+
+// As fetch_all_in, with each outcome given as soon as its chunk is quoted, with the index of
+// its job. Chunks only make progress while the stream is polled.
+pub(crate) fn fetch_stream_in(
+    jobs: Vec<(Arc<DynBridgeAdapter>, SupportedPair, Span)>,
+    concurrency: usize,
+    slow_after: Duration,
+) -> impl Stream<Item = (usize, FetchOutcome)> {
+    let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+    // Jobs that fail before any request is sent
+    let mut unsent: Vec<(usize, FetchOutcome)> = Vec::new();
+    let mut chunks: Vec<Chunk> = Vec::new();
+    // Chunk still filling up for each batching adapter, by its index in `chunks`
+    let mut filling: Vec<(Arc<DynBridgeAdapter>, usize)> = Vec::new();
+
+    for (index, (adapter, pair, span)) in jobs.into_iter().enumerate() {
+        let request = match probe_request(&pair) {
+            Ok(request) => request,
+            Err(err) => {
+                unsent.push((index, FetchOutcome {
+                    adapter: adapter.name(),
+                    pair,
+                    source: None,
+                    result: Err(AdapterError::Config(err.to_string())),
+                    latency: None,
+                }));
+                continue;
+            }
+        };
+        let batch_size = adapter.max_batch_size();
+        let open = filling
+            .iter()
+            .find(|(filling, _)| Arc::ptr_eq(filling, &adapter))
+            .map(|(_, chunk)| *chunk)
+            .filter(|chunk| batch_size > 1 && chunks[*chunk].jobs.len() < batch_size);
+        match open {
+            Some(chunk) => chunks[chunk].jobs.push((index, pair, request)),
+            None => {
+                if batch_size > 1 {
+                    filling.retain(|(filling, _)| !Arc::ptr_eq(filling, &adapter));
+                    filling.push((Arc::clone(&adapter), chunks.len()));
+                }
+                chunks.push(Chunk { adapter, span, jobs: vec![(index, pair, request)] });
+            }
+        }
+    }
+
+    let fetched: FuturesUnordered<_> = chunks.into_iter().map(|chunk| {
+        let semaphore = Arc::clone(&semaphore);
+        let span = chunk.span.clone();
+        async move {
+            let _permit = semaphore.acquire().await.expect("semaphore is never closed");
+            LoggingManager.debug("fetching quote");
+            let name = chunk.adapter.name();
+            let first = &chunk.jobs[0].1;
+            let slow = LoggingManager.time_scope_with(
+                FETCH_OPERATION,
+                slow_after,
+                &[("adapter", &name), ("pair", &format!("{}->{}", first.src_chain, first.dst_chain)), ("pairs", &chunk.jobs.len())],
+            );
+            let started = Instant::now();
+            let mut results = if chunk.adapter.max_batch_size() == 1 {
+                vec![chunk.adapter.fetch_metrics(&chunk.jobs[0].2).await]
+            } else {
+                let requests: Vec<QuoteRequest> = chunk.jobs.iter().map(|(_, _, request)| request.clone()).collect();
+                chunk.adapter.fetch_metrics_batch(&requests).await
+            }
+            .into_iter();
+            let latency = started.elapsed();
+            drop(slow);
+            chunk
+                .jobs
+                .into_iter()
+                .map(|(index, pair, _)| {
+                    let result = results.next().unwrap_or_else(|| Err(AdapterError::missing("batch result")));
+                    (index, FetchOutcome { adapter: name.clone(), pair, source: None, result, latency: Some(latency) })
+                })
+                .collect::<Vec<_>>()
+        }
+        .instrument(span)
+    }).collect();
+    stream::iter(unsent).chain(fetched.flat_map(stream::iter))
+}
+
+// Adapter and lowercased chains and tokens of a quote being fetched
+type FetchKey = (String, String, String, String, String);
+
+fn fetch_key(adapter: &str, pair: &SupportedPair) -> FetchKey {
+    let lower = |value: &str| value.to_lowercase();
+    (adapter.to_string(), lower(&pair.src_chain), lower(&pair.src_token), lower(&pair.dst_chain), lower(&pair.dst_token))
+}
+
+// Quotes being fetched, so that refreshes of several graphs, or a bridge's fallback source that
+// is a bridge of its own, ask an adapter for a pair once at a time, see fetch_stream_shared
+#[derive(Debug, Default)]
+pub(crate) struct InFlight(Mutex<HashMap<FetchKey, watch::Receiver<Option<FetchOutcome>>>>);
+
+// Held by the job fetching a key; hands its outcome to whoever joined it and gives the key up,
+// however the fetch ends
+struct Leader {
+    key: FetchKey,
+    outcome: watch::Sender<Option<FetchOutcome>>,
+    in_flight: Arc<InFlight>,
+}
+
+impl Leader {
+    fn publish(self, outcome: &FetchOutcome) {
+        self.outcome.send_replace(Some(outcome.clone()));
+    }
+}
+
+impl Drop for Leader {
+    fn drop(&mut self) {
+        let mut in_flight = self.in_flight.0.lock().unwrap();
+        if in_flight.get(&self.key).is_some_and(|fetching| fetching.same_channel(&self.outcome.subscribe())) {
+            in_flight.remove(&self.key);
+        }
+    }
+}
+
+// As fetch_stream_in, except that a job whose adapter and pair are already being fetched,
+// by another stream or earlier in this one, waits for that fetch and takes its outcome with
+// no latency, as it sent no request. One whose fetch is dropped unfinished fetches on its own.
+pub(crate) fn fetch_stream_shared(
+    in_flight: &Arc<InFlight>,
+    jobs: Vec<(Arc<DynBridgeAdapter>, SupportedPair, Span)>,
+    concurrency: usize,
+    slow_after: Duration,
+) -> impl Stream<Item = (usize, FetchOutcome)> + use<> {
+    let mut led = Vec::new();
+    let mut leaders = Vec::new();
+    let mut joined = Vec::new();
+    {
+        let mut fetching = in_flight.0.lock().unwrap();
+        for (index, (adapter, pair, span)) in jobs.into_iter().enumerate() {
+            let key = fetch_key(&adapter.name(), &pair);
+            match fetching.get(&key) {
+                Some(outcome) => joined.push((index, (adapter, pair, span), outcome.clone())),
+                None => {
+                    let (outcome, receiver) = watch::channel(None);
+                    fetching.insert(key.clone(), receiver);
+                    leaders.push((index, Some(Leader { key, outcome, in_flight: Arc::clone(in_flight) })));
+                    led.push((adapter, pair, span));
+                }
+            }
+        }
+    }
+
+    let led = fetch_stream_in(led, concurrency, slow_after).map(move |(position, outcome)| {
+        let (index, leader) = &mut leaders[position];
+        if let Some(leader) = leader.take() {
+            leader.publish(&outcome);
+        }
+        (*index, outcome)
+    });
+    let joined: FuturesUnordered<_> = joined
+        .into_iter()
+        .map(|(index, (adapter, pair, span), mut outcome)| async move {
+            let shared = outcome.wait_for(Option::is_some).await.ok().and_then(|outcome| outcome.clone());
+            match shared {
+                Some(shared) => (index, FetchOutcome { pair, latency: None, ..shared }),
+                None => {
+                    let mut alone = std::pin::pin!(fetch_stream_in(vec![(adapter, pair, span)], 1, slow_after));
+                    let (_, outcome) = alone.next().await.expect("every job is fetched");
+                    (index, outcome)
+                }
+            }
+        })
+        .collect();
+    stream::select(led, joined)
+}
+
+// Jobs quoted by one call: a single job, or up to max_batch_size jobs of a batching adapter
+struct Chunk {
+    adapter: Arc<DynBridgeAdapter>,
+    span: Span,
+    jobs: Vec<(usize, SupportedPair, QuoteRequest)>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::{AdapterError, mock::MockAdapter};
+    use polypathroute_core::RefreshPriority;
+
+    fn pair(src_chain: &str) -> SupportedPair {
+        SupportedPair {
+            src_chain: src_chain.to_string(),
+            dst_chain: "polygon".to_string(),
+            src_token: "usdc".to_string(),
+            dst_token: "usdc".to_string(),
+            min_amount: None,
+            max_amount: None,
+            token_symbol: None,
+            priority: RefreshPriority::default(),
+            refresh_interval: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn bounded_concurrency_and_isolated_failures() {
+        let chains: Vec<String> = (0..100).map(|i| format!("chain-{}", i)).collect();
+        let mut mock = MockAdapter::new().with_latency(Duration::from_millis(10));
+        for chain in &chains {
+            mock = mock.with_quote(chain, "polygon", BridgeEdge {
+                from: chain.clone(),
+                to: "polygon".to_string(),
+                cost: 1.0,
+                speed: 60.0,
+                liquidity: 1_000_000.0,
+                risk: 600.0,
+                via: None,
+                ..BridgeEdge::default()
+            });
+        }
+        let mock = mock.with_failure("chain-42", "polygon", AdapterError::RateLimited { retry_after: None });
+        let mock = Arc::new(mock);
+
+        let adapter: Arc<DynBridgeAdapter> = Arc::new(Box::new(Arc::clone(&mock)));
+        let jobs = chains.iter().map(|chain| (Arc::clone(&adapter), pair(chain))).collect();
+
+        let outcomes = fetch_all(jobs, 8).await;
+
+        assert_eq!(outcomes.len(), 100);
+        assert_eq!(outcomes.iter().filter(|outcome| outcome.is_ok()).count(), 99);
+        assert_eq!(
+            outcomes[42].result.as_ref().unwrap_err().disposition(),
+            crate::adapters::Disposition::Defer(None)
+        );
+        assert_eq!(outcomes[42].pair.src_chain, "chain-42");
+        assert!(outcomes.iter().all(|outcome| outcome.adapter == "mock"));
+        assert!(outcomes.iter().all(|outcome| outcome.latency.is_some_and(|latency| latency >= Duration::from_millis(10))));
+        assert!(mock.peak_in_flight() <= 8);
+        assert!(mock.peak_in_flight() > 1);
+    }
+
+    fn quote(chain: &str) -> BridgeEdge {
+        BridgeEdge { from: chain.to_string(), to: "polygon".to_string(), cost: 1.0, speed: 60.0, liquidity: 1_000_000.0, ..BridgeEdge::default() }
+    }
+
+    #[tokio::test]
+    async fn batching_adapters_are_quoted_in_chunks_of_their_batch_size() {
+        let chains: Vec<String> = (0..7).map(|i| format!("chain-{}", i)).collect();
+        let batching = chains.iter().fold(MockAdapter::named("batching").with_batch_size(3), |mock, chain| mock.with_quote(chain, "polygon", quote(chain)));
+        let batching = Arc::new(batching.with_failure("chain-4", "polygon", AdapterError::RateLimited { retry_after: None }));
+        let single = Arc::new(MockAdapter::named("single").with_quote("chain-0", "polygon", quote("chain-0")));
+        let batching_adapter: Arc<DynBridgeAdapter> = Arc::new(Box::new(Arc::clone(&batching)));
+        let single_adapter: Arc<DynBridgeAdapter> = Arc::new(Box::new(Arc::clone(&single)));
+
+        // The single-pair adapter's jobs sit between the batching adapter's
+        let mut jobs: Vec<_> = chains.iter().map(|chain| (Arc::clone(&batching_adapter), pair(chain))).collect();
+        jobs.insert(2, (Arc::clone(&single_adapter), pair("chain-0")));
+        jobs.insert(5, (Arc::clone(&single_adapter), pair("chain-9")));
+
+        let outcomes = fetch_all(jobs, 2).await;
+
+        assert_eq!(batching.batches(), vec![3, 3, 1]);
+        assert_eq!(batching.requests().len(), 7);
+        assert_eq!(single.requests().len(), 2);
+        let labels: Vec<(String, String)> = outcomes.iter().map(|outcome| (outcome.adapter.clone(), outcome.pair.src_chain.clone())).collect();
+        assert_eq!(labels[2], ("single".to_string(), "chain-0".to_string()));
+        assert_eq!(labels[5], ("single".to_string(), "chain-9".to_string()));
+        assert_eq!(labels.iter().filter(|(adapter, _)| adapter == "batching").map(|(_, chain)| chain.clone()).collect::<Vec<_>>(), chains);
+
+        // Each result belongs to its own pair, failures included
+        for outcome in &outcomes {
+            match (outcome.adapter.as_str(), outcome.pair.src_chain.as_str()) {
+                ("batching", "chain-4") => assert_eq!(outcome.result.as_ref().unwrap_err(), &AdapterError::RateLimited { retry_after: None }),
+                ("single", "chain-9") => assert!(matches!(outcome.result, Err(AdapterError::UnsupportedPair { .. }))),
+                (_, chain) => assert_eq!(outcome.result.as_ref().unwrap().from, chain),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn a_pair_already_being_quoted_waits_for_that_quote() {
+        let mock = Arc::new(MockAdapter::named("shared").with_latency(Duration::from_millis(20)).with_quote("base", "polygon", quote("base")).with_quote("optimism", "polygon", quote("optimism")));
+        let adapter: Arc<DynBridgeAdapter> = Arc::new(Box::new(Arc::clone(&mock)));
+        let in_flight = Arc::new(InFlight::default());
+        let jobs = |chains: &[&str]| chains.iter().map(|chain| (Arc::clone(&adapter), pair(chain), Span::none())).collect::<Vec<_>>();
+        let collect = |stream| async move {
+            let mut outcomes: Vec<(usize, FetchOutcome)> = StreamExt::collect(stream).await;
+            outcomes.sort_by_key(|(index, _)| *index);
+            outcomes.into_iter().map(|(_, outcome)| outcome).collect::<Vec<_>>()
+        };
+
+        // Two refreshes at once, the second also asking for base twice, one with a
+        // differently cased token
+        let mut upper = pair("base");
+        upper.src_token = "USDC".to_string();
+        let mut second = jobs(&["base", "optimism"]);
+        second.push((Arc::clone(&adapter), upper, Span::none()));
+        let first = fetch_stream_shared(&in_flight, jobs(&["base"]), 4, Duration::from_secs(10));
+        let second = fetch_stream_shared(&in_flight, second, 4, Duration::from_secs(10));
+        let (first, second) = tokio::join!(collect(first), collect(second));
+
+        assert_eq!(mock.requests().len(), 2);
+        assert!(first.iter().chain(&second).all(FetchOutcome::is_ok));
+        assert!(first[0].latency.is_some() && second[0].latency.is_none() && second[1].latency.is_some());
+        // Each takes the quote under its own pair
+        assert_eq!((second[0].pair.src_chain.as_str(), second[2].pair.src_token.as_str()), ("base", "USDC"));
+        assert!(in_flight.0.lock().unwrap().is_empty());
+
+        // A fetch given up on leaves those waiting on it to fetch on their own
+        let mut abandoned = Box::pin(fetch_stream_shared(&in_flight, jobs(&["base"]), 4, Duration::from_secs(10)));
+        let waiting = fetch_stream_shared(&in_flight, jobs(&["base"]), 4, Duration::from_secs(10));
+        assert!(futures::poll!(abandoned.next()).is_pending());
+        drop(abandoned);
+        let waited = collect(waiting).await;
+        assert!(waited[0].is_ok() && waited[0].latency.is_some());
+        assert_eq!(mock.requests().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn slow_fetches_are_logged_with_their_adapter_and_pair() {
+        #[derive(Clone, Default)]
+        struct Capture(Arc<std::sync::Mutex<Vec<u8>>>);
+        impl std::io::Write for Capture {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mock = MockAdapter::named("slowpoke").with_latency(Duration::from_millis(30)).with_quote("base", "polygon", BridgeEdge::default());
+        let adapter: Arc<DynBridgeAdapter> = Arc::new(Box::new(mock));
+        let fetch = |slow_after: Duration| fetch_all_in(vec![(Arc::clone(&adapter), pair("base"), Span::none())], 1, slow_after);
+
+        let capture = Capture::default();
+        let writer = capture.clone();
+        let subscriber = tracing_subscriber::fmt().json().with_max_level(tracing::Level::INFO).with_writer(move || writer.clone()).finish();
+        {
+            let _default = tracing::subscriber::set_default(subscriber);
+            fetch(Duration::from_millis(5)).await;
+            fetch(Duration::from_secs(10)).await;
+        }
+
+        let lines: Vec<serde_json::Value> = String::from_utf8(capture.0.lock().unwrap().clone())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["level"], "WARN");
+        assert_eq!(lines[0]["fields"]["operation"], FETCH_OPERATION);
+        assert_eq!(lines[0]["fields"]["fields"], "adapter=slowpoke pair=base->polygon pairs=1");
+        assert!(lines[0]["fields"]["elapsed_ms"].as_u64().unwrap() >= 30);
+    }
+}
@@ -0,0 +1,182 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use polypathroute_core::{CacheManager, CacheNamespace, RefreshClaim, sha256_hex};
+use serde::{Deserialize, Serialize};
+use anyhow::Result;
+
+use crate::adapters::{BridgeEdge, QuoteRequest};
+
+// A quote and whether it was served from the cache
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedQuote {
+    pub edge: BridgeEdge,
+    pub from_cache: bool,
+}
+
+// What gets serialized into CacheManager. CacheManager expires entries by the second,
+// the millisecond expiry and quote validity are tracked here.
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    expires_at_ms: u128,
+    edge: BridgeEdge,
+}
+
+fn now_ms() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0)
+}
+
+// Short-lived cache of adapter quotes keyed by bridge and normalized request
+#[derive(Debug, Clone)]
+pub struct QuoteCache {
+    cache: CacheNamespace,
+    ttl: Duration,
+}
+
+impl QuoteCache {
+    // Stores into the "quotes" namespace of `cache`, typically the CoreContext's, so quotes
+    // are visible to its other users
+    pub fn new(cache: CacheManager, ttl: Duration) -> Self {
+        Self {
+            cache: cache.namespace("quotes"),
+            ttl,
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    // The request is hashed since its addresses make the key too long to persist as a file name
+    fn key(bridge: &str, request: &QuoteRequest) -> String {
+        format!("{}:{}", bridge.to_lowercase(), sha256_hex(request.cache_key().as_bytes()))
+    }
+
+    fn ttl_secs(&self) -> u64 {
+        self.ttl.as_millis().div_ceil(1000) as u64
+    }
+
+    pub fn get(&self, bridge: &str, request: &QuoteRequest) -> Option<BridgeEdge> {
+        let key = Self::key(bridge, request);
+        let entry: Entry = self.cache.get_json(&key).ok().flatten()?;
+        self.unexpired(&key, entry)
+    }
+
+    // As `get`, with the claim to quote the request again ahead of time when the entry is within
+    // global.cache_soft_ttl of expiring and nobody else is, see CacheManager::get_for_refresh
+    pub fn get_for_refresh(&self, bridge: &str, request: &QuoteRequest) -> Option<(BridgeEdge, Option<RefreshClaim>)> {
+        let key = Self::key(bridge, request);
+        let (entry, claim) = self.cache.get_json_for_refresh::<Entry>(&key, Some(self.ttl_secs())).ok().flatten()?;
+        self.unexpired(&key, entry).map(|edge| (edge, claim))
+    }
+
+    fn unexpired(&self, key: &str, entry: Entry) -> Option<BridgeEdge> {
+        if entry.expires_at_ms <= now_ms() {
+            let _ = self.cache.remove(key);
+            return None;
+        }
+        Some(entry.edge)
+    }
+
+    // Entries live for the cache ttl, or until the quote itself expires if that comes first.
+    // Quotes that have already expired are not cached.
+    pub fn insert(&self, bridge: &str, request: &QuoteRequest, edge: &BridgeEdge) -> Result<()> {
+        let mut expires_at_ms = now_ms() + self.ttl.as_millis();
+        if let Some(valid_until) = edge.valid_until {
+            expires_at_ms = expires_at_ms.min(u128::from(valid_until) * 1000);
+        }
+        if expires_at_ms <= now_ms() {
+            return Ok(());
+        }
+
+        let entry = Entry {
+            expires_at_ms,
+            edge: edge.clone(),
+        };
+        self.cache.set_json(&Self::key(bridge, request), &entry, Some(self.ttl_secs()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polypath_graph::{EdgeKind, EdgeMetrics, Hop, NodeId, Path, RankedPath, ScoreBreakDown};
+    use polypathroute_core::PersistenceManager;
+
+    #[test]
+    fn edges_and_ranked_paths_round_trip_through_the_typed_api() {
+        let cache = CacheManager::new().namespace("dal_test");
+
+        let edge = BridgeEdge {
+            from: "ethereum".to_string(),
+            to: "polygon".to_string(),
+            cost: 0.6,
+            speed: 180.0,
+            liquidity: 1000.0,
+            risk: 0.25,
+            via: Some("stargate".to_string()),
+            bridge: "lifi".to_string(),
+            quoted_at: 1717442435,
+            valid_until: Some(1717442495),
+            ..BridgeEdge::default()
+        };
+        cache.set_json("edge", &edge, None).unwrap();
+        assert_eq!(cache.get_json::<BridgeEdge>("edge").unwrap(), Some(edge));
+
+        let metrics = EdgeMetrics { cost: 0.6, speed: 180.0, liquidity: 1000.0, risk: 0.25 };
+        let ranked = vec![RankedPath {
+            path: Path {
+                hops: vec![Hop { from: NodeId(1), to: NodeId(2), bridge_name: "stargate".into(), kind: EdgeKind::Bridge, metrics, quote: None, slippage_pct: None, alternatives: None, confidence: 1.0 }],
+                total_cost: 0.6,
+                total_time: 180.0,
+                total_risk: 0.25,
+                min_liquidity: 1000.0,
+                aggregate_score: 0.4,
+                estimated_output: Some(999.4),
+                graph_version: 0,
+                confidence: 1.0,
+            },
+            rank: 1,
+            score_breakdown: ScoreBreakDown {
+                cost_score: 0.6,
+                speed_score: 180.0,
+                liquidity_score: 1000.0,
+                risk_score: 0.25,
+                final_score: 0.4,
+                estimated_output: Some(999.4),
+            },
+            affordability: None,
+        }];
+        cache.set_json("paths", &ranked, None).unwrap();
+        assert_eq!(cache.get_json::<Vec<RankedPath>>("paths").unwrap(), Some(ranked));
+    }
+
+    #[test]
+    fn quotes_persist_through_file_storage() {
+        let dir = std::env::temp_dir().join(format!("polypath-quote-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let request = QuoteRequest {
+            src_chain: "ethereum".to_string(),
+            dst_chain: "arbitrum".to_string(),
+            src_token: "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48".to_string(),
+            dst_token: "0xaf88d065e77c8cc2239327c5edb3a432268e5831".to_string(),
+            src_amount: "1000".to_string(),
+            dst_amount_min: "990".to_string(),
+            src_address: "0x0000000000000000000000000000000000000001".to_string(),
+            dst_address: "0x0000000000000000000000000000000000000001".to_string(),
+        };
+        let edge = BridgeEdge { from: "ethereum".to_string(), to: "arbitrum".to_string(), cost: 0.4, bridge: "stargate".to_string(), ..BridgeEdge::default() };
+
+        let store = PersistenceManager::open(&dir).unwrap();
+        let cache = CacheManager::new().with_write_through(store.clone(), Duration::from_secs(60));
+        QuoteCache::new(cache.clone(), Duration::from_secs(60)).insert("Stargate", &request, &edge).unwrap();
+        cache.flush().unwrap();
+
+        let restarted = CacheManager::new();
+        assert_eq!(restarted.restore_from(&PersistenceManager::open(&dir).unwrap()).unwrap(), 1);
+        assert_eq!(QuoteCache::new(restarted, Duration::from_secs(60)).get("stargate", &request), Some(edge));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,88 @@
+use crate::adapters::BridgeEdge;
+
+// Geometric ladder of source amounts for depth probes: base, base * factor, base * factor^2, ...
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DepthLadder {
+    pub factor: f64,
+    pub steps: usize,
+    // Largest drop in output per unit, relative to the smallest rung, still considered fillable.
+    // 0.005 = 0.5%.
+    pub max_slippage: f64,
+}
+
+impl Default for DepthLadder {
+    fn default() -> Self {
+        Self {
+            factor: 5.0,
+            steps: 3,
+            max_slippage: 0.005,
+        }
+    }
+}
+
+impl DepthLadder {
+    pub fn amounts(&self, base: f64) -> Vec<f64> {
+        (0..self.steps)
+            .map(|step| base * self.factor.powi(step as i32))
+            .collect()
+    }
+}
+
+// Quotes of one route at increasing amounts, with the largest amount that stays within the
+// ladder's slippage threshold. That amount is what a graph edge should carry as max_amount.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DepthProfile {
+    pub points: Vec<(f64, BridgeEdge)>,
+    pub max_amount: Option<f64>,
+}
+
+// Compares each rung's output per unit against the smallest rung's and returns the largest
+// amount before the degradation first exceeds `max_slippage`. None without a usable baseline.
+pub fn max_amount_within_slippage(points: &[(f64, BridgeEdge)], max_slippage: f64) -> Option<f64> {
+    let mut points: Vec<(f64, f64)> = points
+        .iter()
+        .filter(|(amount, _)| *amount > 0.0)
+        .map(|(amount, edge)| (*amount, edge.estimated_output / amount))
+        .collect();
+    points.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let (first_amount, baseline) = *points.first()?;
+    if baseline <= 0.0 {
+        return None;
+    }
+
+    let mut max_amount = first_amount;
+    for (amount, rate) in points.into_iter().skip(1) {
+        if 1.0 - rate / baseline > max_slippage {
+            break;
+        }
+        max_amount = amount;
+    }
+    Some(max_amount)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(amount: f64, output: f64) -> (f64, BridgeEdge) {
+        (amount, BridgeEdge { estimated_output: output, ..BridgeEdge::default() })
+    }
+
+    #[test]
+    fn ladder_is_geometric() {
+        assert_eq!(DepthLadder::default().amounts(2.0), vec![2.0, 10.0, 50.0]);
+    }
+
+    #[test]
+    fn max_amount_stops_at_the_first_rung_over_the_threshold() {
+        // 0%, 0.2% and 1% worse per unit than the 1-unit quote
+        let points = [point(25.0, 24.75), point(1.0, 1.0), point(5.0, 4.99)];
+        assert_eq!(max_amount_within_slippage(&points, 0.005), Some(5.0));
+        assert_eq!(max_amount_within_slippage(&points, 0.02), Some(25.0));
+        assert_eq!(max_amount_within_slippage(&points, 0.0), Some(1.0));
+
+        assert_eq!(max_amount_within_slippage(&[], 0.005), None);
+        assert_eq!(max_amount_within_slippage(&[point(1.0, 0.0)], 0.005), None);
+    }
+}
@@ -0,0 +1,225 @@
+// Summaries of refreshes for ops: RefreshScheduler composes a digest after each refresh and
+// queues it for a background task, which sends it through the Notifiers with retries. A slow
+// or hanging webhook only ever holds up that task, never the next refresh.
+
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use anyhow::Result;
+use polypath_graph::Coverage;
+use polypathroute_core::{DigestConfig, LoggingManager};
+use serde::Serialize;
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use crate::{
+    alerts::{LogNotifier, Notifier, WebhookNotifier},
+    updater::RefreshReport,
+};
+
+// Tries after the first before a notifier is given up on for a digest
+pub const DIGEST_RETRIES: u32 = 3;
+// Wait before the first retry, doubled before each further one
+const DIGEST_BACKOFF: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AdapterLatency {
+    pub adapter: String,
+    pub latency_ms: u64,
+}
+
+// What one refresh did; the body of webhook posts
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RefreshDigest {
+    // 1 for the scheduler's first refresh
+    pub cycle: u64,
+    // Unix seconds
+    pub at: u64,
+    // Edges added or given fresh metrics
+    pub pairs_refreshed: usize,
+    pub failed: usize,
+    pub failures_by_adapter: BTreeMap<String, usize>,
+    // Switched off because their bridge stopped serving the pair or their quote expired
+    pub edges_deactivated: usize,
+    // Of the configured pairs, as in Coverage::fraction; None before coverage was measured
+    pub coverage_pct: Option<f64>,
+    // The bridge with the slowest quote request
+    pub slowest_adapter: Option<AdapterLatency>,
+}
+
+impl RefreshDigest {
+    pub fn new(cycle: u64, report: &RefreshReport, coverage: Option<&Coverage>, at: u64) -> Self {
+        let slowest_adapter = report
+            .slowest_quote_ms
+            .iter()
+            .max_by_key(|(_, latency_ms)| **latency_ms)
+            .map(|(adapter, latency_ms)| AdapterLatency { adapter: adapter.clone(), latency_ms: *latency_ms });
+        Self {
+            cycle,
+            at,
+            pairs_refreshed: report.added + report.updated,
+            failed: report.failed,
+            failures_by_adapter: report.failed_by_adapter.clone(),
+            edges_deactivated: report.deactivated + report.expired,
+            coverage_pct: coverage.map(|coverage| coverage.fraction * 100.0),
+            slowest_adapter,
+        }
+    }
+}
+
+// Which refreshes get a digest
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigestCadence {
+    // The first refresh and every this many after it
+    Every(u32),
+    // Refreshes with more failed quotes than this
+    FailuresAbove(usize),
+}
+
+impl DigestCadence {
+    pub fn is_due(&self, digest: &RefreshDigest) -> bool {
+        match *self {
+            DigestCadence::Every(every) => digest.cycle.saturating_sub(1).is_multiple_of(u64::from(every.max(1))),
+            DigestCadence::FailuresAbove(threshold) => digest.failed > threshold,
+        }
+    }
+}
+
+// Where and how often digests go, see RefreshScheduler::with_digests
+#[derive(Debug, Clone)]
+pub struct DigestNotifier {
+    cadence: DigestCadence,
+    notifiers: Vec<Arc<dyn Notifier>>,
+    queue_capacity: usize,
+    backoff: Duration,
+}
+
+impl DigestNotifier {
+    // Without notifiers; see `with_notifier`
+    pub fn new(cadence: DigestCadence) -> Self {
+        Self { cadence, notifiers: Vec::new(), queue_capacity: 16, backoff: DIGEST_BACKOFF }
+    }
+
+    // Logs every digest, and posts it to digest.webhook_url when one is set
+    pub fn from_config(config: &DigestConfig, logger: LoggingManager) -> Result<Self> {
+        let cadence = match config.failures_above {
+            Some(threshold) => DigestCadence::FailuresAbove(threshold),
+            None => DigestCadence::Every(config.every),
+        };
+        let mut notifier = Self::new(cadence)
+            .with_queue_capacity(config.queue_capacity)
+            .with_notifier(Arc::new(LogNotifier::new(logger)));
+        if let Some(url) = &config.webhook_url {
+            notifier = notifier.with_notifier(Arc::new(WebhookNotifier::new(url)?));
+        }
+        Ok(notifier)
+    }
+
+    pub fn with_notifier(mut self, notifier: Arc<dyn Notifier>) -> Self {
+        self.notifiers.push(notifier);
+        self
+    }
+
+    // Digests waiting for delivery past this are dropped
+    pub fn with_queue_capacity(mut self, capacity: usize) -> Self {
+        self.queue_capacity = capacity.max(1);
+        self
+    }
+
+    pub fn with_backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn cadence(&self) -> DigestCadence {
+        self.cadence
+    }
+
+    // Spawns the task that delivers queued digests; it ends once the queue is dropped
+    pub(crate) fn start(self, logger: LoggingManager) -> DigestQueue {
+        let (sender, mut queued) = mpsc::channel::<RefreshDigest>(self.queue_capacity);
+        let (notifiers, backoff, task_logger) = (self.notifiers, self.backoff, logger.clone());
+        tokio::spawn(async move {
+            while let Some(digest) = queued.recv().await {
+                for notifier in &notifiers {
+                    deliver(notifier.as_ref(), &digest, backoff, &task_logger).await;
+                }
+            }
+        });
+        DigestQueue { sender, cadence: self.cadence, logger }
+    }
+}
+
+async fn deliver(notifier: &dyn Notifier, digest: &RefreshDigest, backoff: Duration, logger: &LoggingManager) {
+    let mut wait = backoff;
+    for attempt in 0..=DIGEST_RETRIES {
+        match notifier.notify_digest(digest).await {
+            Ok(()) => return,
+            Err(err) if attempt == DIGEST_RETRIES => {
+                logger.warn_with("refresh digest not delivered", &[("notifier", &notifier.name()), ("cycle", &digest.cycle), ("error", &err)]);
+            }
+            Err(_) => {
+                tokio::time::sleep(wait).await;
+                wait *= 2;
+            }
+        }
+    }
+}
+
+// The scheduler's end of a started DigestNotifier
+#[derive(Debug)]
+pub(crate) struct DigestQueue {
+    sender: mpsc::Sender<RefreshDigest>,
+    cadence: DigestCadence,
+    logger: LoggingManager,
+}
+
+impl DigestQueue {
+    // Queues `digest` when its refresh is due one, without waiting for room in the queue
+    pub(crate) fn offer(&self, digest: RefreshDigest) {
+        if !self.cadence.is_due(&digest) {
+            return;
+        }
+        if let Err(TrySendError::Full(digest)) = self.sender.try_send(digest) {
+            self.logger.warn_with("refresh digest queue is full, dropping a digest", &[("cycle", &digest.cycle)]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digest(cycle: u64, failed: usize) -> RefreshDigest {
+        let report = RefreshReport { failed, ..RefreshReport::default() };
+        RefreshDigest::new(cycle, &report, None, 0)
+    }
+
+    #[test]
+    fn cadences_pick_their_refreshes() {
+        let every_third: Vec<u64> = (1..=7).filter(|cycle| DigestCadence::Every(3).is_due(&digest(*cycle, 0))).collect();
+        assert_eq!(every_third, [1, 4, 7]);
+        assert!((1..=3).all(|cycle| DigestCadence::Every(1).is_due(&digest(cycle, 0))));
+
+        let on_failures = DigestCadence::FailuresAbove(2);
+        assert!(!on_failures.is_due(&digest(1, 2)));
+        assert!(on_failures.is_due(&digest(2, 3)));
+    }
+
+    #[test]
+    fn digests_sum_up_the_report() {
+        let report = RefreshReport {
+            added: 2,
+            updated: 5,
+            failed: 3,
+            deactivated: 1,
+            expired: 2,
+            failed_by_adapter: BTreeMap::from([("across".to_string(), 1), ("stargate".to_string(), 2)]),
+            slowest_quote_ms: BTreeMap::from([("across".to_string(), 900), ("stargate".to_string(), 2_500)]),
+            ..RefreshReport::default()
+        };
+        let coverage = Coverage { configured: 4, fresh: 3, fraction: 0.75, ..Coverage::default() };
+        let digest = RefreshDigest::new(7, &report, Some(&coverage), 1_700_000_000);
+        assert_eq!((digest.pairs_refreshed, digest.failed, digest.edges_deactivated), (7, 3, 3));
+        assert_eq!(digest.coverage_pct, Some(75.0));
+        assert_eq!(digest.slowest_adapter, Some(AdapterLatency { adapter: "stargate".to_string(), latency_ms: 2_500 }));
+        assert_eq!(serde_json::to_value(&digest).unwrap()["failures_by_adapter"], serde_json::json!({ "across": 1, "stargate": 2 }));
+    }
+}
@@ -0,0 +1,319 @@
+// Re-quotes a ranked route hop by hop before it's executed, to see how far live quotes have
+// moved from the graph metrics it was ranked on
+
+use std::time::Instant;
+use polypath_graph::{Graph, NodeId, NodeType, PlanError, RankedPath, RouteIntent};
+use polypathroute_core::RefreshPriority;
+use serde::Serialize;
+
+use crate::{
+    adapters::{AdapterError, QuoteRequest, SupportedPair},
+    batch::{FetchOutcome, PROBE_ADDRESS},
+    error::DalError,
+    updater::{GraphUpdater, RefreshReport},
+};
+
+// How far the route's output may fall short of what it was ranked for, in percent
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct DryRunThresholds {
+    // Beyond this the route is Degraded
+    pub degraded_pct: f64,
+    // Beyond this it's aborted
+    pub abort_pct: f64,
+}
+
+impl Default for DryRunThresholds {
+    fn default() -> Self {
+        Self { degraded_pct: 0.5, abort_pct: 5.0 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "verdict", rename_all = "snake_case")]
+pub enum DryRunVerdict {
+    Proceed,
+    // The output falls `pct` percent short
+    Degraded { pct: f64 },
+    Abort { reason: String },
+}
+
+// One hop re-quoted, in human units of its tokens
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HopDrift {
+    pub hop_index: usize,
+    pub bridge: String,
+    pub src_chain: String,
+    pub dst_chain: String,
+    // What the fresh quotes of the hops before deliver, or what they were expected to past a
+    // hop that failed
+    pub amount_in: f64,
+    pub ranked_cost: f64,
+    pub fresh_cost: Option<f64>,
+    pub cost_delta: Option<f64>,
+    // Output the ranking's metrics promised, and the fresh quote's
+    pub expected_output: f64,
+    pub fresh_output: Option<f64>,
+    pub output_delta: Option<f64>,
+    // The bridge no longer serves the pair
+    pub unsupported: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DryRunReport {
+    pub hops: Vec<HopDrift>,
+    pub expected_output: f64,
+    // None when a hop couldn't be quoted
+    pub fresh_output: Option<f64>,
+    // How far fresh_output falls short of expected_output, in percent; negative when it's more
+    pub shortfall_pct: Option<f64>,
+    pub verdict: DryRunVerdict,
+}
+
+impl GraphUpdater {
+    // See dry_run_with; uses the default thresholds
+    pub async fn dry_run(&self, ranked: &RankedPath, intent: &RouteIntent) -> Result<DryRunReport, DalError> {
+        self.dry_run_with(ranked, intent, &DryRunThresholds::default()).await
+    }
+
+    // Quotes each hop of the route live, bypassing the quote cache, for what the fresh quotes
+    // of the hops before it deliver, starting from intent.amount. The quotes go into the graph
+    // as a refresh's would, so the next search sees them. Fails only for a route without hops
+    // or assets, or an amount that isn't positive.
+    pub async fn dry_run_with(&self, ranked: &RankedPath, intent: &RouteIntent, thresholds: &DryRunThresholds) -> Result<DryRunReport, DalError> {
+        let hops = &ranked.path.hops;
+        if hops.is_empty() {
+            return Err(PlanError::EmptyPath.into());
+        }
+        if !intent.amount.is_finite() || intent.amount <= 0.0 {
+            return Err(PlanError::InvalidAmount(intent.amount).into());
+        }
+
+        let mut drifts = Vec::with_capacity(hops.len());
+        let (mut expected, mut fresh) = (intent.amount, Some(intent.amount));
+        for (hop_index, hop) in hops.iter().enumerate() {
+            let (src_chain, src_token, symbol) = asset(self.graph(), hop.from)?;
+            let (dst_chain, dst_token, _) = asset(self.graph(), hop.to)?;
+            let amount_in = fresh.unwrap_or(expected);
+            let pair = SupportedPair {
+                src_chain: src_chain.clone(),
+                dst_chain: dst_chain.clone(),
+                src_token,
+                dst_token,
+                min_amount: None,
+                max_amount: None,
+                token_symbol: Some(symbol),
+                priority: RefreshPriority::default(),
+                refresh_interval: None,
+            };
+            let quote = self.requote(&hop.bridge_name, pair, amount_in).await;
+
+            expected -= hop.fees_in_source();
+            let fresh_cost = quote.as_ref().ok().copied();
+            let fresh_output = fresh_cost.map(|cost| amount_in - cost);
+            fresh = fresh.zip(fresh_output).map(|(_, output)| output);
+            drifts.push(HopDrift {
+                hop_index,
+                bridge: hop.bridge_name.to_string(),
+                src_chain,
+                dst_chain,
+                amount_in,
+                ranked_cost: hop.metrics.cost,
+                fresh_cost,
+                cost_delta: fresh_cost.map(|cost| cost - hop.metrics.cost),
+                expected_output: expected,
+                fresh_output,
+                output_delta: fresh_output.map(|output| output - expected),
+                unsupported: matches!(quote, Err(AdapterError::UnsupportedPair { .. })),
+                error: quote.err().map(|err| err.to_string()),
+            });
+        }
+
+        let shortfall_pct = fresh.map(|fresh| match expected > 0.0 {
+            true => (expected - fresh) / expected * 100.0,
+            false => 0.0,
+        });
+        let verdict = match (drifts.iter().find(|drift| drift.error.is_some()), fresh, shortfall_pct) {
+            (Some(failed), _, _) => DryRunVerdict::Abort {
+                reason: format!("hop {} over {} has no quote: {}", failed.hop_index, failed.bridge, failed.error.as_deref().unwrap_or_default()),
+            },
+            (None, Some(fresh), _) if fresh <= 0.0 => DryRunVerdict::Abort { reason: "fees use up the whole amount".to_string() },
+            (None, _, Some(pct)) if pct > thresholds.abort_pct => DryRunVerdict::Abort {
+                reason: format!("output falls {:.2}% short of the ranked route's", pct),
+            },
+            (None, _, Some(pct)) if pct > thresholds.degraded_pct => DryRunVerdict::Degraded { pct },
+            _ => DryRunVerdict::Proceed,
+        };
+        self.dal().logger().info_with("route dry run", &[
+            ("hops", &hops.len()),
+            ("expected_output", &expected),
+            ("fresh_output", &fresh.map_or("none".to_string(), |fresh| fresh.to_string())),
+            ("verdict", &format!("{:?}", verdict)),
+        ]);
+        Ok(DryRunReport { hops: drifts, expected_output: expected, fresh_output: fresh, shortfall_pct, verdict })
+    }
+
+    // The hop's cost quoted live for `amount`, gas included, after applying the quote to the
+    // graph. `label` is the edge's: aggregated routes are quoted through their aggregator.
+    async fn requote(&self, label: &str, pair: SupportedPair, amount: f64) -> Result<f64, AdapterError> {
+        let adapter_name = label.split_once(':').map_or(label, |(aggregator, _)| aggregator);
+        let adapter = self.dal().adapter(adapter_name).map_err(|err| AdapterError::Config(err.to_string()))?;
+        let request = QuoteRequest::builder()
+            .src_chain(pair.src_chain.clone())
+            .dst_chain(pair.dst_chain.clone())
+            .src_token(pair.src_token.clone())
+            .dst_token(pair.dst_token.clone())
+            .src_amount(amount.to_string())
+            .wallet(PROBE_ADDRESS)
+            .build()
+            .map_err(|err| AdapterError::Config(err.to_string()))?;
+
+        let started = Instant::now();
+        let result = adapter.fetch_metrics(&request).await;
+        let latency = started.elapsed();
+        self.dal().metrics().record_adapter_request(adapter_name, result.is_ok(), latency);
+        let mut outcome = FetchOutcome { adapter: adapter.name(), pair, source: None, result, latency: Some(latency) };
+        self.price(&mut outcome).await.map_err(|err| AdapterError::Config(err.to_string()))?;
+        let cost = outcome.result.as_ref().map(|quote| quote.cost).map_err(Clone::clone);
+        self.apply(outcome, &mut RefreshReport::default());
+        cost
+    }
+}
+
+// (chain, token address, symbol) of an asset node
+fn asset(graph: &Graph, id: NodeId) -> Result<(String, String, String), PlanError> {
+    match graph.get_node(id).as_deref().map(|node| &node.node_type) {
+        Some(NodeType::Asset { chain, token_address, token_symbol }) => Ok((chain.clone(), token_address.clone(), token_symbol.clone())),
+        _ => Err(PlanError::NotAnAsset(id)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Arc, time::Duration};
+    use polypath_graph::{EdgeMetrics, RouteOptions, Router};
+    use crate::{
+        adapters::{self, BridgeEdge, mock::MockAdapter, unix_now},
+        updater::tests::{configured_updater, updater},
+    };
+
+    fn quote(from: &str, to: &str, cost: f64) -> BridgeEdge {
+        BridgeEdge {
+            from: from.to_string(),
+            to: to.to_string(),
+            cost,
+            speed: 60.0,
+            liquidity: 1_000_000.0,
+            risk: 0.1,
+            valid_until: Some(unix_now() + 600),
+            ..BridgeEdge::default()
+        }
+    }
+
+    fn intent(to_chain: &str) -> RouteIntent {
+        RouteIntent {
+            from_chain: "ethereum".to_string(),
+            from_token: "USDC".to_string(),
+            to_chain: to_chain.to_string(),
+            to_token: "USDC".to_string(),
+            amount: 100.0,
+            preference: Some("cheapest".to_string()),
+            src_address: None,
+        }
+    }
+
+    fn best(updater: &GraphUpdater, intent: &RouteIntent) -> RankedPath {
+        let intent = updater.dal().canonical_intent(intent).unwrap();
+        let routes = Router::new(Arc::clone(updater.graph())).best_routes(&intent, &RouteOptions::default()).unwrap();
+        routes.into_iter().next().unwrap().ranked
+    }
+
+    // ethereum -> polygon -> arbitrum for 1 a hop, refreshed into the graph; ethereum ->
+    // polygon costs `later_cost` once it's quoted again
+    async fn drifting(bridge: &'static str, later_cost: f64) -> Arc<GraphUpdater> {
+        adapters::register(bridge, move |_| {
+            Ok(Box::new(
+                MockAdapter::named(bridge)
+                    .with_quote("ethereum", "polygon", quote("ethereum", "polygon", 1.0))
+                    .then_quote("ethereum", "polygon", quote("ethereum", "polygon", later_cost))
+                    .with_quote("polygon", "arbitrum", quote("polygon", "arbitrum", 1.0)),
+            ))
+        });
+        let updater = Arc::new(configured_updater(bridge));
+        updater.refresh_once().await;
+        updater
+    }
+
+    #[tokio::test]
+    async fn drift_is_classified_against_the_thresholds() {
+        let cases = [
+            ("steady", 1.0, DryRunVerdict::Proceed),
+            // 97.6 where 98 was expected, 0.41% short
+            ("slipping", 1.4, DryRunVerdict::Proceed),
+            ("worse", 2.0, DryRunVerdict::Degraded { pct: 100.0 / 98.0 }),
+            ("gouging", 8.0, DryRunVerdict::Abort { reason: "output falls 7.14% short of the ranked route's".to_string() }),
+        ];
+        for (bridge, later_cost, verdict) in cases {
+            let updater = drifting(bridge, later_cost).await;
+            let intent = intent("arbitrum");
+            let report = updater.dry_run(&best(&updater, &intent), &intent).await.unwrap();
+            match (&report.verdict, &verdict) {
+                (DryRunVerdict::Degraded { pct }, DryRunVerdict::Degraded { pct: want }) => assert!((pct - want).abs() < 1e-9),
+                (got, want) => assert_eq!(got, want, "{}", bridge),
+            }
+            assert_eq!(report.expected_output, 98.0);
+            assert_eq!(report.fresh_output, Some(99.0 - later_cost));
+        }
+
+        // Stricter thresholds degrade the route that slipped
+        let updater = drifting("slipping-strict", 1.4).await;
+        let intent = intent("arbitrum");
+        let strict = DryRunThresholds { degraded_pct: 0.1, ..DryRunThresholds::default() };
+        let report = updater.dry_run_with(&best(&updater, &intent), &intent, &strict).await.unwrap();
+        assert!(matches!(report.verdict, DryRunVerdict::Degraded { pct } if (pct - 0.4 / 0.98).abs() < 1e-9));
+    }
+
+    #[tokio::test]
+    async fn hops_are_requoted_at_the_propagated_amount_and_fed_back() {
+        let updater = drifting("drifting", 2.0).await;
+        let intent = intent("arbitrum");
+        let ranked = best(&updater, &intent);
+        let report = updater.dry_run(&ranked, &intent).await.unwrap();
+
+        let amounts: Vec<(f64, f64)> = report.hops.iter().map(|hop| (hop.amount_in, hop.expected_output)).collect();
+        assert_eq!(amounts, [(100.0, 99.0), (98.0, 98.0)]);
+        let deltas: Vec<(Option<f64>, Option<f64>)> = report.hops.iter().map(|hop| (hop.cost_delta, hop.output_delta)).collect();
+        assert_eq!(deltas, [(Some(1.0), Some(-1.0)), (Some(0.0), Some(-1.0))]);
+        assert_eq!(report.shortfall_pct, Some(100.0 / 98.0));
+
+        // The fresh quote is in the graph: the same route now ranks at the new cost
+        let again = best(&updater, &intent);
+        assert_eq!(again.path.hops[0].metrics.cost, 2.0);
+        assert_eq!(updater.dry_run(&again, &intent).await.unwrap().verdict, DryRunVerdict::Proceed);
+    }
+
+    #[tokio::test]
+    async fn unsupported_hops_abort_and_go_dark() {
+        let updater = Arc::new(updater("vanishing", Duration::ZERO));
+        updater.refresh_once().await;
+        // Left over from when the bridge still served base
+        let graph = updater.graph();
+        let eth = updater.asset_node_id("ethereum", "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48");
+        let base = updater.asset_node_id("base", "0x833589fcd6edb6e08f4c7c32d4f71b54bda02913");
+        graph.get_or_create_asset_node("base", "0x833589fcd6edb6e08f4c7c32d4f71b54bda02913", "USDC");
+        let metrics = EdgeMetrics { cost: 1.0, speed: 60.0, liquidity: 1_000_000.0, risk: 0.1 };
+        graph.add_edge(eth, base, "vanishing", metrics, None, None).unwrap();
+
+        let intent = intent("base");
+        let report = updater.dry_run(&best(&updater, &intent), &intent).await.unwrap();
+        assert!(report.hops[0].unsupported);
+        assert_eq!((report.fresh_output, report.shortfall_pct), (None, None));
+        assert!(matches!(&report.verdict, DryRunVerdict::Abort { reason } if reason.starts_with("hop 0 over vanishing has no quote")));
+        assert!(graph.get_outgoing_edges(eth).iter().all(|edge| edge.to != base));
+
+        let mut empty = best(&updater, &self::intent("arbitrum"));
+        empty.path.hops.clear();
+        assert!(matches!(updater.dry_run(&empty, &intent).await, Err(DalError::Plan(PlanError::EmptyPath))));
+    }
+}
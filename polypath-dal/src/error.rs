@@ -0,0 +1,51 @@
+use crate::adapters::AdapterError;
+use polypath_graph::{GraphError, PlanError, RouteError};
+use polypathroute_core::CoreError;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum DalError {
+    // Config, cache and persistence failures from the core crate
+    #[error(transparent)]
+    Core(#[from] CoreError),
+
+    #[error(transparent)]
+    Graph(#[from] GraphError),
+
+    // A route that can't be planned or dry run
+    #[error(transparent)]
+    Plan(#[from] PlanError),
+
+    #[error("graph snapshot `{name}` is not readable: {source}")]
+    Snapshot { name: String, source: serde_json::Error },
+
+    #[error("no snapshot of graph `{name}` was saved at or before {at}")]
+    NoSnapshot { name: String, at: u64 },
+
+    #[error(transparent)]
+    Route(#[from] RouteError),
+
+    #[error("invalid preference profile `{name}`: {reason}")]
+    InvalidProfile { name: String, reason: String },
+
+    #[error("preference profile `{name}` is not readable: {source}")]
+    Profile { name: String, source: serde_json::Error },
+
+    #[error("invalid API key of tenant `{tenant}`: {reason}")]
+    InvalidApiKey { tenant: String, reason: String },
+
+    #[error("API key of tenant `{tenant}` is not readable: {source}")]
+    ApiKey { tenant: String, source: serde_json::Error },
+
+    #[error("audit log segment `{segment}` is not readable: {source}")]
+    Audit { segment: String, source: serde_json::Error },
+
+    #[error("unknown adapter `{name}`, known adapters: {}", known.join(", "))]
+    UnknownAdapter { name: String, known: Vec<String> },
+
+    #[error(transparent)]
+    Adapter(#[from] AdapterError),
+
+    #[error("unknown graph `{name}`, known graphs: {}", known.join(", "))]
+    UnknownGraph { name: String, known: Vec<String> },
+}
@@ -0,0 +1,264 @@
+// Route searches run on a bounded pool, the queued ones taken by priority
+
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+use polypath_graph::{ExplainedPath, RankingOutcome, RouteError, RouteIntent, RouteOptions, RoutePriority, Router};
+use polypathroute_core::{ExecutorConfig, MetricsManager};
+use thiserror::Error;
+use tokio::{runtime::Handle, sync::oneshot};
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum ExecutorError {
+    // The queue is full and the job isn't interactive
+    #[error("{queued} route searches are already waiting, {} ones are turned away", priority.as_str())]
+    Overloaded { priority: RoutePriority, queued: usize },
+
+    // The job was dropped without an answer, e.g. its search panicked
+    #[error("the route search ended without an answer")]
+    Abandoned,
+
+    #[error(transparent)]
+    Route(#[from] RouteError),
+}
+
+struct Job {
+    priority: RoutePriority,
+    // Submission order, so jobs of one priority run first come first served
+    seq: u64,
+    enqueued_at: Instant,
+    run: Box<dyn FnOnce() + Send>,
+}
+
+impl PartialEq for Job {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Job {}
+
+impl PartialOrd for Job {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// The greatest job is the next to run
+impl Ord for Job {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority.cmp(&other.priority).then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    jobs: BinaryHeap<Job>,
+    running: usize,
+    next_seq: u64,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    workers: usize,
+    queue_depth: usize,
+    metrics: MetricsManager,
+}
+
+// Runs at most `workers` searches at once on tokio's blocking threads. The others wait in a
+// queue, interactive ones ahead of normal ones ahead of batch ones; once `queue_depth` are
+// waiting, only interactive ones are still taken. Clones share one pool.
+#[derive(Clone)]
+pub struct RouteExecutor {
+    shared: Arc<Shared>,
+}
+
+impl std::fmt::Debug for RouteExecutor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let queue = self.shared.queue.lock().unwrap();
+        f.debug_struct("RouteExecutor")
+            .field("workers", &self.shared.workers)
+            .field("queue_depth", &self.shared.queue_depth)
+            .field("running", &queue.running)
+            .field("queued", &queue.jobs.len())
+            .finish()
+    }
+}
+
+// A submitted search's answer, once a worker got to it
+#[derive(Debug)]
+pub struct RouteHandle {
+    answer: oneshot::Receiver<Result<RankingOutcome<ExplainedPath>, RouteError>>,
+}
+
+impl RouteHandle {
+    pub async fn wait(self) -> Result<RankingOutcome<ExplainedPath>, ExecutorError> {
+        match self.answer.await {
+            Ok(answer) => answer.map_err(ExecutorError::from),
+            Err(_) => Err(ExecutorError::Abandoned),
+        }
+    }
+}
+
+impl RouteExecutor {
+    // Queue and execution times, and rejections, are recorded into `metrics` per priority
+    pub fn new(config: &ExecutorConfig, metrics: MetricsManager) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                queue: Mutex::default(),
+                workers: config.workers.max(1),
+                queue_depth: config.queue_depth,
+                metrics,
+            }),
+        }
+    }
+
+    // Queues Router::rank_routes for `intent` at `opts`' priority. Must be called from within a
+    // tokio runtime, whose blocking threads run the search.
+    pub fn submit(&self, router: Arc<Router>, intent: RouteIntent, opts: RouteOptions) -> Result<RouteHandle, ExecutorError> {
+        let (reply, answer) = oneshot::channel();
+        let metrics = self.shared.metrics.clone();
+        self.enqueue(opts.priority, move || {
+            let answer = metrics.time_route_search(|| router.rank_routes(&intent, &opts));
+            let _ = reply.send(answer);
+        })?;
+        Ok(RouteHandle { answer })
+    }
+
+    // Submits and waits for the answer
+    pub async fn run(&self, router: Arc<Router>, intent: RouteIntent, opts: RouteOptions) -> Result<RankingOutcome<ExplainedPath>, ExecutorError> {
+        self.submit(router, intent, opts)?.wait().await
+    }
+
+    // Jobs run in the tracing span they were queued from
+    fn enqueue(&self, priority: RoutePriority, run: impl FnOnce() + Send + 'static) -> Result<(), ExecutorError> {
+        let span = tracing::Span::current();
+        {
+            let mut queue = self.shared.queue.lock().unwrap();
+            let queued = queue.jobs.len();
+            if queued >= self.shared.queue_depth && priority != RoutePriority::Interactive {
+                self.shared.metrics.record_route_job_rejected(priority.as_str());
+                return Err(ExecutorError::Overloaded { priority, queued });
+            }
+            let seq = queue.next_seq;
+            queue.next_seq += 1;
+            queue.jobs.push(Job {
+                priority,
+                seq,
+                enqueued_at: Instant::now(),
+                run: Box::new(move || span.in_scope(run)),
+            });
+        }
+        Self::dispatch(&self.shared, &Handle::current());
+        Ok(())
+    }
+
+    // Hands queued jobs to idle workers; each worker looks for the next job when it's done
+    fn dispatch(shared: &Arc<Shared>, runtime: &Handle) {
+        let mut queue = shared.queue.lock().unwrap();
+        while queue.running < shared.workers {
+            let Some(job) = queue.jobs.pop() else {
+                break;
+            };
+            queue.running += 1;
+            let (shared, next) = (Arc::clone(shared), runtime.clone());
+            runtime.spawn_blocking(move || {
+                let started = Instant::now();
+                // Frees the worker even if the job panics
+                let outcome = std::panic::catch_unwind(std::panic::AssertUnwindSafe(job.run));
+                shared.metrics.record_route_job(job.priority.as_str(), started - job.enqueued_at, started.elapsed());
+                shared.queue.lock().unwrap().running -= 1;
+                Self::dispatch(&shared, &next);
+                if let Err(panic) = outcome {
+                    std::panic::resume_unwind(panic);
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::atomic::{AtomicUsize, Ordering as AtomicOrdering}, time::Duration};
+
+    fn executor(workers: usize, queue_depth: usize) -> RouteExecutor {
+        RouteExecutor::new(&ExecutorConfig { workers, queue_depth }, MetricsManager::new())
+    }
+
+    // Queues `run`; the receiver gets what it returns
+    fn job<T: Send + 'static>(executor: &RouteExecutor, priority: RoutePriority, run: impl FnOnce() -> T + Send + 'static) -> Result<oneshot::Receiver<T>, ExecutorError> {
+        let (reply, answer) = oneshot::channel();
+        executor.enqueue(priority, move || {
+            let _ = reply.send(run());
+        })?;
+        Ok(answer)
+    }
+
+    // Jobs answer before their worker records them
+    async fn idle(executor: &RouteExecutor) {
+        while executor.shared.queue.lock().unwrap().running > 0 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn no_more_than_the_workers_run_at_once() {
+        let executor = executor(3, 64);
+        let running = Arc::new(AtomicUsize::new(0));
+        let most = Arc::new(AtomicUsize::new(0));
+        let jobs: Vec<_> = (0..24)
+            .map(|_| {
+                let (running, most) = (Arc::clone(&running), Arc::clone(&most));
+                job(&executor, RoutePriority::Normal, move || {
+                    let now = running.fetch_add(1, AtomicOrdering::SeqCst) + 1;
+                    most.fetch_max(now, AtomicOrdering::SeqCst);
+                    std::thread::sleep(Duration::from_millis(10));
+                    running.fetch_sub(1, AtomicOrdering::SeqCst);
+                })
+                .unwrap()
+            })
+            .collect();
+        for job in jobs {
+            job.await.unwrap();
+        }
+        assert_eq!(most.load(AtomicOrdering::SeqCst), 3);
+        idle(&executor).await;
+        assert!(executor.shared.metrics.encode_prometheus().contains("polypath_route_job_duration_seconds_count{priority=\"normal\"} 24"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn interactive_jobs_overtake_queued_batch_ones_and_are_never_turned_away() {
+        let executor = executor(1, 2);
+        let order = Arc::new(Mutex::new(Vec::new()));
+        // Holds the only worker until released
+        let (release, held) = std::sync::mpsc::channel::<()>();
+        let blocker = job(&executor, RoutePriority::Normal, move || held.recv().unwrap()).unwrap();
+
+        let queue = |priority: RoutePriority, name: &'static str| {
+            let order = Arc::clone(&order);
+            job(&executor, priority, move || order.lock().unwrap().push(name))
+        };
+        let batch = [queue(RoutePriority::Batch, "batch 1").unwrap(), queue(RoutePriority::Batch, "batch 2").unwrap()];
+        assert_eq!(
+            queue(RoutePriority::Batch, "batch 3").unwrap_err(),
+            ExecutorError::Overloaded { priority: RoutePriority::Batch, queued: 2 }
+        );
+        assert!(queue(RoutePriority::Normal, "normal").is_err());
+        let interactive = [queue(RoutePriority::Interactive, "interactive 1").unwrap(), queue(RoutePriority::Interactive, "interactive 2").unwrap()];
+
+        release.send(()).unwrap();
+        blocker.await.unwrap();
+        for job in batch.into_iter().chain(interactive) {
+            job.await.unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), ["interactive 1", "interactive 2", "batch 1", "batch 2"]);
+        idle(&executor).await;
+        let metrics = executor.shared.metrics.encode_prometheus();
+        assert!(metrics.contains("polypath_route_jobs_rejected_total{priority=\"batch\"} 1"), "{}", metrics);
+        assert!(metrics.contains("polypath_route_job_queued_seconds_count{priority=\"interactive\"} 2"), "{}", metrics);
+    }
+}
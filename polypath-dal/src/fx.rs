@@ -0,0 +1,281 @@
+// Amounts tagged with the currency they're in, and the prices that bring them into one quote
+// currency so edge costs can be compared
+
+use std::{collections::HashMap, fmt, sync::Mutex, time::{Duration, Instant}};
+use async_trait::async_trait;
+use polypathroute_core::{FxConfig, FxSource, LoggingManager};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::adapters::{NATIVE_ADDRESSES, TokenDecimals};
+
+// How long the price endpoint gets to answer
+const FX_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Wrapped tokens and the token each wraps, which they're priced as when not priced themselves.
+// Listed rather than guessed from a leading "W", which would turn WLD into LD.
+const WRAPPED_TOKENS: &[(&str, &str)] = &[
+    ("WETH", "ETH"),
+    ("WBTC", "BTC"),
+    ("WPOL", "POL"),
+    ("WMATIC", "MATIC"),
+    ("WAVAX", "AVAX"),
+    ("WBNB", "BNB"),
+    ("WFTM", "FTM"),
+    ("WSOL", "SOL"),
+    ("WXDAI", "XDAI"),
+];
+
+// The token `symbol` wraps, matched case-insensitively; None for tokens that wrap nothing
+pub(crate) fn unwrapped(symbol: &str) -> Option<&'static str> {
+    WRAPPED_TOKENS.iter().find(|(wrapped, _)| wrapped.eq_ignore_ascii_case(symbol)).map(|(_, token)| *token)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum CurrencyId {
+    // An off-chain unit, e.g. "USD" for fees an API only gives a USD value for
+    Fiat(String),
+    // A token by chain and address, the chain's native token by one of TokenDecimals' placeholders
+    Token { chain: String, address: String },
+}
+
+impl CurrencyId {
+    pub fn fiat(code: &str) -> Self {
+        CurrencyId::Fiat(code.trim().to_uppercase())
+    }
+
+    pub fn token(chain: &str, address: &str) -> Self {
+        CurrencyId::Token { chain: chain.to_string(), address: address.to_string() }
+    }
+
+    pub fn native(chain: &str) -> Self {
+        Self::token(chain, NATIVE_ADDRESSES[0])
+    }
+
+    pub fn is_native(&self) -> bool {
+        matches!(self, CurrencyId::Token { address, .. } if TokenDecimals::is_native(address))
+    }
+}
+
+// Fiat codes and token addresses as they are
+impl fmt::Display for CurrencyId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CurrencyId::Fiat(code) => f.write_str(code),
+            CurrencyId::Token { address, .. } => f.write_str(address),
+        }
+    }
+}
+
+// An amount in human units of its currency
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Money {
+    pub amount: f64,
+    pub currency: CurrencyId,
+}
+
+impl Money {
+    pub fn new(amount: f64, currency: CurrencyId) -> Self {
+        Self { amount, currency }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum FxError {
+    // A token neither the registry nor the pair gives a symbol for
+    #[error("no symbol is known for {currency} on `{chain}`")]
+    UnknownCurrency { chain: String, currency: String },
+
+    #[error("no {quote} rate is known for {symbol}")]
+    NoRate { symbol: String, quote: String },
+
+    #[error("cannot fetch the {quote} price of {symbol}: {reason}")]
+    Fetch { symbol: String, quote: String, reason: String },
+}
+
+// Prices currencies, by symbol, in one quote currency
+#[async_trait]
+pub trait FxConverter: Send + Sync + fmt::Debug {
+    fn quote_currency(&self) -> &str;
+
+    // What one unit of `symbol` is worth in the quote currency
+    async fn rate(&self, symbol: &str) -> Result<f64, FxError>;
+
+    async fn convert(&self, amount: f64, symbol: &str) -> Result<f64, FxError> {
+        Ok(amount * self.rate(symbol).await?)
+    }
+}
+
+// The [fx] converter: its static rates or its price endpoint
+pub(crate) fn fx_converter(config: &FxConfig, logger: LoggingManager) -> anyhow::Result<Box<dyn FxConverter>> {
+    Ok(match config.source {
+        FxSource::Static => Box::new(StaticFxTable::from_config(config)),
+        FxSource::Http => Box::new(HttpFxConverter::from_config(config, logger)?),
+    })
+}
+
+// The [fx.rates] table. Symbols are matched case-insensitively and a wrapped token ("WETH") not
+// listed is priced as the token it wraps, see WRAPPED_TOKENS.
+#[derive(Debug, Clone)]
+pub struct StaticFxTable {
+    quote: String,
+    rates: HashMap<String, f64>,
+}
+
+impl StaticFxTable {
+    pub fn from_config(config: &FxConfig) -> Self {
+        Self {
+            quote: config.quote_currency.trim().to_uppercase(),
+            rates: config.rates.iter().map(|(symbol, rate)| (symbol.to_uppercase(), *rate)).collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl FxConverter for StaticFxTable {
+    fn quote_currency(&self) -> &str {
+        &self.quote
+    }
+
+    async fn rate(&self, symbol: &str) -> Result<f64, FxError> {
+        let symbol = symbol.trim().to_uppercase();
+        if symbol == self.quote {
+            return Ok(1.0);
+        }
+        self.rates
+            .get(&symbol)
+            .or_else(|| unwrapped(&symbol).and_then(|token| self.rates.get(token)))
+            .copied()
+            .ok_or_else(|| FxError::NoRate { symbol, quote: self.quote.clone() })
+    }
+}
+
+// Prices from the [fx] price_url, reused for its cache_ttl. A symbol whose price can't be
+// fetched keeps its last price however old, with a warning.
+#[derive(Debug)]
+pub struct HttpFxConverter {
+    client: Client,
+    url: String,
+    quote: String,
+    ttl: Duration,
+    // Last price fetched per symbol and when
+    prices: Mutex<HashMap<String, (f64, Instant)>>,
+    logger: LoggingManager,
+}
+
+impl HttpFxConverter {
+    pub fn from_config(config: &FxConfig, logger: LoggingManager) -> anyhow::Result<Self> {
+        let url = config.price_url.clone().ok_or_else(|| anyhow::anyhow!("fx.price_url is not set"))?;
+        let client = Client::builder().timeout(FX_REQUEST_TIMEOUT).build()?;
+        Ok(Self {
+            client,
+            url,
+            quote: config.quote_currency.trim().to_uppercase(),
+            ttl: config.cache_ttl,
+            prices: Mutex::default(),
+            logger,
+        })
+    }
+
+    async fn fetch(&self, symbol: &str) -> Result<f64, String> {
+        let body: Value = self
+            .client
+            .get(&self.url)
+            .query(&[("symbol", symbol), ("quote", &self.quote)])
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| err.to_string())?
+            .json()
+            .await
+            .map_err(|err| err.to_string())?;
+        body["price"]
+            .as_f64()
+            .filter(|price| price.is_finite() && *price > 0.0)
+            .ok_or_else(|| format!("unexpected response {}", body))
+    }
+}
+
+#[async_trait]
+impl FxConverter for HttpFxConverter {
+    fn quote_currency(&self) -> &str {
+        &self.quote
+    }
+
+    async fn rate(&self, symbol: &str) -> Result<f64, FxError> {
+        let symbol = symbol.trim().to_uppercase();
+        if symbol == self.quote {
+            return Ok(1.0);
+        }
+        let cached = self.prices.lock().unwrap().get(&symbol).copied();
+        if let Some((price, fetched_at)) = cached
+            && fetched_at.elapsed() < self.ttl
+        {
+            return Ok(price);
+        }
+        match (self.fetch(&symbol).await, cached) {
+            (Ok(price), _) => {
+                self.prices.lock().unwrap().insert(symbol, (price, Instant::now()));
+                Ok(price)
+            }
+            (Err(reason), Some((price, _))) => {
+                self.logger.warn_with("price unavailable, using the last one fetched", &[("symbol", &symbol), ("error", &reason)]);
+                Ok(price)
+            }
+            (Err(reason), None) => Err(FxError::Fetch { symbol, quote: self.quote.clone(), reason }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polypathroute_core::{ConfigFormat, ConfigManager};
+    use serde_json::json;
+    use wiremock::{Mock, MockServer, ResponseTemplate, matchers::{method, query_param}};
+
+    #[tokio::test]
+    async fn static_rates_come_from_the_fx_section() {
+        let config = ConfigManager::from_str("[fx]\nquote_currency = \"usd\"\n[fx.rates]\neth = 2500.0\nUSDC = 1.0\n[bridges]\n", ConfigFormat::Toml).unwrap();
+        let fx = fx_converter(&config.fx, LoggingManager).unwrap();
+
+        assert_eq!(fx.quote_currency(), "USD");
+        assert_eq!(fx.rate("USD").await, Ok(1.0));
+        assert_eq!(fx.rate("ETH").await, Ok(2500.0));
+        // Wrapped ether at ether's rate
+        assert_eq!(fx.convert(2.0, "weth").await, Ok(5000.0));
+        // A token whose name merely starts with a W is not ether's or anyone's wrapper
+        let wld = ConfigManager::from_str("[fx.rates]\nLD = 3.0\n[bridges]\n", ConfigFormat::Toml).unwrap();
+        assert!(matches!(fx_converter(&wld.fx, LoggingManager).unwrap().rate("WLD").await, Err(FxError::NoRate { .. })));
+        assert_eq!(fx.rate("POL").await, Err(FxError::NoRate { symbol: "POL".to_string(), quote: "USD".to_string() }));
+    }
+
+    #[tokio::test]
+    async fn fetched_prices_are_cached_and_outlive_the_endpoint() {
+        let prices = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(query_param("symbol", "ETH"))
+            .and(query_param("quote", "EUR"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "price": 2300.0 })))
+            .up_to_n_times(1)
+            .mount(&prices)
+            .await;
+        Mock::given(method("GET")).respond_with(ResponseTemplate::new(503)).mount(&prices).await;
+        let config = FxConfig {
+            quote_currency: "EUR".to_string(),
+            source: FxSource::Http,
+            price_url: Some(prices.uri()),
+            cache_ttl: Duration::ZERO,
+            ..FxConfig::default()
+        };
+        let fx = fx_converter(&config, LoggingManager).unwrap();
+
+        assert_eq!(fx.rate("eth").await, Ok(2300.0));
+        // The endpoint now fails; the last price is kept however stale
+        assert_eq!(fx.rate("ETH").await, Ok(2300.0));
+        assert!(matches!(fx.rate("POL").await, Err(FxError::Fetch { symbol, .. }) if symbol == "POL"));
+    }
+}
@@ -0,0 +1,270 @@
+// Gas a bridge transfer costs on its source chain, priced so it can be folded into edge costs
+
+use std::{collections::HashMap, fmt, sync::Mutex, time::{Duration, Instant}};
+use async_trait::async_trait;
+use polypathroute_core::{BridgeConfig, GasChainConfig, GasConfig, LoggingManager};
+use reqwest::Client;
+use serde_json::{Value, json};
+use thiserror::Error;
+
+// How long an RPC or oracle gets to answer
+const GAS_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+// Gas units used unless the bridge's [extra.gas_units] table says otherwise
+pub const DEFAULT_APPROVE_GAS_UNITS: u64 = 46_000;
+pub const DEFAULT_BRIDGE_GAS_UNITS: u64 = 200_000;
+
+const WEI_PER_GWEI: f64 = 1e9;
+const WEI_PER_NATIVE: f64 = 1e18;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GasAction {
+    // Letting the bridge's contract spend the token, once per token and bridge
+    Approve { bridge: String },
+    // The bridge transfer itself
+    Bridge { bridge: String },
+}
+
+impl GasAction {
+    pub fn bridge(&self) -> &str {
+        match self {
+            GasAction::Approve { bridge } | GasAction::Bridge { bridge } => bridge,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GasAction::Approve { .. } => "approve",
+            GasAction::Bridge { .. } => "bridge",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GasEstimate {
+    pub gas_units: u64,
+    pub gas_price_wei: u128,
+    // USD price of the chain's native token
+    pub native_usd: f64,
+}
+
+impl GasEstimate {
+    // In human units of the chain's native token
+    pub fn native_cost(&self) -> f64 {
+        self.gas_units as f64 * self.gas_price_wei as f64 / WEI_PER_NATIVE
+    }
+
+    pub fn usd_cost(&self) -> f64 {
+        self.native_cost() * self.native_usd
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum GasError {
+    #[error("no gas source is configured for chain `{0}`")]
+    UnknownChain(String),
+
+    #[error("cannot fetch the gas price on `{chain}`: {reason}")]
+    Fetch { chain: String, reason: String },
+}
+
+#[async_trait]
+pub trait GasEstimator: Send + Sync + fmt::Debug {
+    async fn estimate(&self, chain: &str, action: &GasAction) -> Result<GasEstimate, GasError>;
+}
+
+// Gas prices from each chain's RPC (eth_gasPrice) or oracle, reused for the [gas] cache_ttl.
+// A chain whose source fails falls back to its last fetched price however old, then to its
+// default_gas_price_gwei, with a warning either way. Gas units come from each bridge's
+// [extra.gas_units] table, e.g. `gas_units = { approve = 50000, bridge = 180000 }`.
+#[derive(Debug)]
+pub struct OracleGasEstimator {
+    client: Client,
+    chains: HashMap<String, GasChainConfig>,
+    ttl: Duration,
+    // Per bridge, the (approve, bridge) gas units
+    units: HashMap<String, (u64, u64)>,
+    // Last price fetched per chain and when
+    prices: Mutex<HashMap<String, (u128, Instant)>>,
+    logger: LoggingManager,
+}
+
+impl OracleGasEstimator {
+    // `config`'s chains are matched against the chains `estimate` is asked about as given
+    pub fn from_config(config: &GasConfig, bridges: &HashMap<String, BridgeConfig>, logger: LoggingManager) -> anyhow::Result<Self> {
+        let client = Client::builder().timeout(GAS_REQUEST_TIMEOUT).build()?;
+        let units = bridges.iter().map(|(name, bridge)| (name.clone(), gas_units(bridge))).collect();
+        Ok(Self {
+            client,
+            chains: config.chains.clone(),
+            ttl: config.cache_ttl,
+            units,
+            prices: Mutex::default(),
+            logger,
+        })
+    }
+
+    fn units_for(&self, action: &GasAction) -> u64 {
+        let (approve, bridge) = self.units.get(action.bridge()).copied().unwrap_or((DEFAULT_APPROVE_GAS_UNITS, DEFAULT_BRIDGE_GAS_UNITS));
+        match action {
+            GasAction::Approve { .. } => approve,
+            GasAction::Bridge { .. } => bridge,
+        }
+    }
+
+    async fn gas_price(&self, chain: &str, config: &GasChainConfig) -> Result<u128, GasError> {
+        let cached = self.prices.lock().unwrap().get(chain).copied();
+        if let Some((price, fetched)) = cached
+            && fetched.elapsed() < self.ttl
+        {
+            return Ok(price);
+        }
+
+        let err = match self.fetch_price(chain, config).await {
+            Ok(Some(price)) => {
+                self.prices.lock().unwrap().insert(chain.to_string(), (price, Instant::now()));
+                return Ok(price);
+            }
+            Ok(None) => None,
+            Err(err) => Some(err),
+        };
+        let reason = err.as_ref().map(ToString::to_string).unwrap_or_default();
+        if let Some((price, _)) = cached {
+            if err.is_some() {
+                self.logger.warn_with("gas price unavailable, using the last one fetched", &[("chain", &chain), ("error", &reason)]);
+            }
+            return Ok(price);
+        }
+        match config.default_gas_price_gwei {
+            Some(gwei) => {
+                if err.is_some() {
+                    self.logger.warn_with("gas price unavailable, using the configured default", &[("chain", &chain), ("error", &reason)]);
+                }
+                Ok((gwei * WEI_PER_GWEI).round() as u128)
+            }
+            None => Err(err.unwrap_or_else(|| GasError::UnknownChain(chain.to_string()))),
+        }
+    }
+
+    // None for a chain with neither an RPC nor an oracle
+    async fn fetch_price(&self, chain: &str, config: &GasChainConfig) -> Result<Option<u128>, GasError> {
+        let fetch_error = |reason: String| GasError::Fetch { chain: chain.to_string(), reason };
+        let request = match (&config.rpc_url, &config.oracle_url) {
+            (Some(url), _) => self.client.post(url).json(&json!({ "jsonrpc": "2.0", "id": 1, "method": "eth_gasPrice", "params": [] })),
+            (None, Some(url)) => self.client.get(url),
+            (None, None) => return Ok(None),
+        };
+        let body: Value = request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| fetch_error(err.to_string()))?
+            .json()
+            .await
+            .map_err(|err| fetch_error(err.to_string()))?;
+
+        let price = match config.rpc_url {
+            Some(_) => body["result"].as_str().and_then(|hex| u128::from_str_radix(hex.trim_start_matches("0x"), 16).ok()),
+            None => match &body["gas_price_wei"] {
+                Value::Number(number) => number.as_u64().map(u128::from),
+                Value::String(digits) => digits.parse().ok(),
+                _ => None,
+            },
+        };
+        price.map(Some).ok_or_else(|| fetch_error(format!("unexpected response {}", body)))
+    }
+}
+
+#[async_trait]
+impl GasEstimator for OracleGasEstimator {
+    async fn estimate(&self, chain: &str, action: &GasAction) -> Result<GasEstimate, GasError> {
+        let config = self.chains.get(chain).ok_or_else(|| GasError::UnknownChain(chain.to_string()))?;
+        let gas_price_wei = self.gas_price(chain, config).await?;
+        Ok(GasEstimate { gas_units: self.units_for(action), gas_price_wei, native_usd: config.native_usd })
+    }
+}
+
+// The bridge's (approve, bridge) gas units; entries missing or not whole numbers use the defaults
+fn gas_units(bridge: &BridgeConfig) -> (u64, u64) {
+    let table = bridge.extra.as_ref().and_then(|extra| extra.get("gas_units")).and_then(|units| units.as_table());
+    let units = |key: &str, default: u64| {
+        table
+            .and_then(|table| table.get(key))
+            .and_then(|value| value.as_integer())
+            .and_then(|units| u64::try_from(units).ok())
+            .unwrap_or(default)
+    };
+    (units("approve", DEFAULT_APPROVE_GAS_UNITS), units("bridge", DEFAULT_BRIDGE_GAS_UNITS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polypathroute_core::{ConfigFormat, ConfigManager};
+    use wiremock::{Mock, MockServer, ResponseTemplate, matchers::{body_partial_json, method}};
+
+    fn config(gas: &str) -> ConfigManager {
+        let source = format!(
+            "{}\n[bridges.stargate]\nbase_url = \"https://stargate.example\"\nchains = [\"ethereum\"]\nextra = {{ gas_units = {{ bridge = 150000 }} }}\n",
+            gas
+        );
+        ConfigManager::from_str(&source, ConfigFormat::Toml).unwrap()
+    }
+
+    fn estimator(config: &ConfigManager) -> OracleGasEstimator {
+        OracleGasEstimator::from_config(&config.gas, &config.bridges, LoggingManager).unwrap()
+    }
+
+    #[tokio::test]
+    async fn rpc_prices_are_cached_for_the_ttl() {
+        let rpc = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_partial_json(json!({ "method": "eth_gasPrice" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "jsonrpc": "2.0", "id": 1, "result": "0x4a817c800" })))
+            .expect(1)
+            .mount(&rpc)
+            .await;
+        let config = config(&format!("[gas.chains.ethereum]\nrpc_url = \"{}\"\nnative_usd = 2000.0", rpc.uri()));
+        let estimator = estimator(&config);
+
+        let bridge = estimator.estimate("ethereum", &GasAction::Bridge { bridge: "stargate".to_string() }).await.unwrap();
+        assert_eq!(bridge, GasEstimate { gas_units: 150_000, gas_price_wei: 20_000_000_000, native_usd: 2000.0 });
+        assert!((bridge.native_cost() - 0.003).abs() < 1e-12);
+        assert!((bridge.usd_cost() - 6.0).abs() < 1e-9);
+
+        // Cached: the RPC is only asked once
+        let approve = estimator.estimate("ethereum", &GasAction::Approve { bridge: "across".to_string() }).await.unwrap();
+        assert_eq!(approve.gas_units, DEFAULT_APPROVE_GAS_UNITS);
+        assert_eq!(approve.gas_price_wei, 20_000_000_000);
+
+        assert!(matches!(
+            estimator.estimate("polygon", &GasAction::Bridge { bridge: "stargate".to_string() }).await,
+            Err(GasError::UnknownChain(chain)) if chain == "polygon"
+        ));
+    }
+
+    #[tokio::test]
+    async fn unreachable_oracles_fall_back_to_the_last_price_then_the_default() {
+        let oracle = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "gas_price_wei": "30000000000" })))
+            .up_to_n_times(1)
+            .mount(&oracle)
+            .await;
+        Mock::given(method("GET")).respond_with(ResponseTemplate::new(503)).mount(&oracle).await;
+        let config = config(&format!(
+            "[gas]\ncache_ttl = \"0s\"\n[gas.chains.ethereum]\noracle_url = \"{}\"\nnative_usd = 2000.0\n[gas.chains.arbitrum]\noracle_url = \"http://127.0.0.1:9\"\nnative_usd = 2000.0\ndefault_gas_price_gwei = 0.1",
+            oracle.uri()
+        ));
+        let estimator = estimator(&config);
+        let action = GasAction::Bridge { bridge: "stargate".to_string() };
+
+        assert_eq!(estimator.estimate("ethereum", &action).await.unwrap().gas_price_wei, 30_000_000_000);
+        // The oracle now fails; the last price is kept however stale
+        assert_eq!(estimator.estimate("ethereum", &action).await.unwrap().gas_price_wei, 30_000_000_000);
+        // Never reached: the configured default
+        assert_eq!(estimator.estimate("arbitrum", &action).await.unwrap().gas_price_wei, 100_000_000);
+
+        let config = self::config("[gas.chains.ethereum]\noracle_url = \"http://127.0.0.1:9\"\nnative_usd = 2000.0");
+        assert!(matches!(self::estimator(&config).estimate("ethereum", &action).await, Err(GasError::Fetch { .. })));
+    }
+}
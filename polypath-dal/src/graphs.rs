@@ -0,0 +1,361 @@
+// Named graphs served side by side, e.g. a stables-only one next to one over every asset
+
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use polypath_graph::{ConfidenceCombinator, ConfidenceModel, Graph, RouteIntent, RouteOptions, Router, SlippageModel, StageTimer};
+use polypathroute_core::{ConfidenceCombinatorKind, GraphConfig, LoggingManager, SlippageKind, SlowOpsConfig};
+
+use crate::{
+    DalContext,
+    audit::AuditLog,
+    balance::RpcBalanceChecker,
+    error::DalError,
+    scheduler::RefreshScheduler,
+    updater::GraphUpdater,
+};
+
+// The graph queries use unless they name another
+pub const DEFAULT_GRAPH: &str = "default";
+
+// One named graph: its updater, which owns the graph, and the [graphs.<name>] section it was
+// built from
+#[derive(Debug)]
+pub struct GraphEntry {
+    name: String,
+    updater: Arc<GraphUpdater>,
+    config: GraphConfig,
+    audit: Option<Arc<AuditLog>>,
+    balance: Option<Arc<RpcBalanceChecker>>,
+}
+
+impl GraphEntry {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn updater(&self) -> &Arc<GraphUpdater> {
+        &self.updater
+    }
+
+    pub fn graph(&self) -> &Arc<Graph> {
+        self.updater.graph()
+    }
+
+    pub fn config(&self) -> &GraphConfig {
+        &self.config
+    }
+
+    // The section's update_interval, else global.update_interval
+    pub fn update_interval(&self) -> Duration {
+        self.config.update_interval.unwrap_or(self.updater.dal().config().global.update_interval)
+    }
+
+    // Options for queries that don't give any: RouteOptions' defaults under the section's
+    pub fn route_options(&self) -> RouteOptions {
+        let defaults = RouteOptions::default();
+        RouteOptions {
+            max_hops: self.config.max_hops.unwrap_or(defaults.max_hops),
+            max_results: self.config.max_results.unwrap_or(defaults.max_results),
+            ..defaults
+        }
+    }
+
+    // Applies the config's [slippage] section and global.min_coverage, records the queries it
+    // answers in the registry's audit log when [audit] is enabled, checks affordability
+    // against the [gas] chains' RPCs when there are any and keeps repeat transfers on their
+    // confirmed routes with global.route_stickiness. Searches and scoring slower than
+    // logging.slow_ops allows are logged as warnings.
+    pub fn router(&self) -> Router {
+        let config = self.updater.dal().config();
+        let slippage = &config.slippage;
+        let model = match slippage.model {
+            SlippageKind::Linear => SlippageModel::Linear { impact_per_utilization: slippage.impact_per_utilization },
+            SlippageKind::Sqrt => SlippageModel::Sqrt,
+        };
+        let confidence = &config.confidence;
+        let combinator = match confidence.combinator {
+            ConfidenceCombinatorKind::Min => ConfidenceCombinator::Min,
+            ConfidenceCombinatorKind::Product => ConfidenceCombinator::Product,
+            ConfidenceCombinatorKind::Mean => ConfidenceCombinator::Mean,
+        };
+        // Quotes that don't say how long they hold are good until they're quoted again: the
+        // longest cadence of their bridge's pairs, unless configured
+        let mut validities = match confidence.validity {
+            Some(_) => BTreeMap::new(),
+            None => self.updater.bridge_cadences(self.update_interval()),
+        };
+        validities.extend(confidence.bridges.iter().map(|(bridge, validity)| (bridge.clone(), *validity)));
+        let confidence = validities.iter().fold(
+            ConfidenceModel::new()
+                .with_validity(confidence.validity.unwrap_or_else(|| self.update_interval()))
+                .with_combinator(combinator)
+                .with_source_quality(Arc::clone(&self.updater) as _),
+            |model, (bridge, validity)| model.with_bridge_validity(bridge, *validity),
+        );
+        let router = Router::new(Arc::clone(self.graph()))
+            .with_slippage(model, slippage.max_utilization)
+            .with_confidence(confidence)
+            .with_min_coverage(config.global.min_coverage)
+            .with_stage_timer(Arc::new(SlowStages {
+                logger: self.updater.dal().logger().clone(),
+                slow_ops: config.logging.slow_ops.clone(),
+                graph: self.name.clone(),
+            }));
+        let router = match config.global.route_stickiness {
+            Some(bonus) => router.with_stickiness(Arc::new(self.updater.dal().pinned_routes(&self.name)), bonus),
+            None => router,
+        };
+        let router = match &self.balance {
+            Some(balance) => router.with_balance_checker(Arc::clone(balance) as _),
+            None => router,
+        };
+        match &self.audit {
+            Some(audit) => router.with_observer(Arc::clone(audit) as _),
+            None => router,
+        }
+    }
+
+    // Refreshes this graph on its own update_interval
+    pub fn scheduler(&self) -> RefreshScheduler {
+        let interval = self.update_interval();
+        RefreshScheduler::new(Arc::clone(&self.updater)).with_interval(interval).with_max_jitter(interval / 10)
+    }
+}
+
+// Logs how long a router's queries spend searching and scoring
+struct SlowStages {
+    logger: LoggingManager,
+    slow_ops: SlowOpsConfig,
+    graph: String,
+}
+
+impl StageTimer for SlowStages {
+    fn stage_timed(&self, stage: &'static str, elapsed: Duration, intent: &RouteIntent) {
+        let pair = format!("{}->{}", intent.from_chain, intent.to_chain);
+        self.logger.record_slow_op(&self.slow_ops, stage, elapsed, &[("graph", &self.graph), ("pair", &pair)]);
+    }
+}
+
+// One graph per [graphs.<name>] section, all quoted through the same DalContext, so they share
+// its adapters, rate limits and quote cache. There's always a DEFAULT_GRAPH: without a
+// [graphs.default] section it takes every bridge and pair.
+#[derive(Debug)]
+pub struct GraphRegistry {
+    dal: Arc<DalContext>,
+    graphs: BTreeMap<String, GraphEntry>,
+    // Shared by every graph's routers, see DalContext::audit_log
+    audit: Option<Arc<AuditLog>>,
+}
+
+impl GraphRegistry {
+    // Every graph starts empty, each split into `shards`, and serves no routes until its first
+    // refreshes reach global.min_coverage
+    pub fn new(dal: DalContext, shards: usize) -> Self {
+        Self::build(dal, shards, None)
+    }
+
+    // Warm start of the default graph from an already populated one, e.g. a loaded snapshot
+    pub fn with_default_graph(dal: DalContext, graph: Graph, shards: usize) -> Self {
+        Self::build(dal, shards, Some(graph))
+    }
+
+    fn build(dal: DalContext, shards: usize, mut default: Option<Graph>) -> Self {
+        let dal = Arc::new(dal);
+        let audit = dal.audit_log().map(Arc::new);
+        let balance = dal.balance_checker().map(Arc::new);
+        let mut configs = dal.config().graphs.clone();
+        configs.entry(DEFAULT_GRAPH.to_string()).or_default();
+        let graphs = configs
+            .into_iter()
+            .map(|(name, config)| {
+                let graph = match name == DEFAULT_GRAPH {
+                    true => default.take(),
+                    false => None,
+                };
+                let graph = Arc::new(graph.unwrap_or_else(|| Graph::new(shards)));
+                let updater = Arc::new(GraphUpdater::shared(graph, Arc::clone(&dal)).with_scope(config.clone()));
+                // Routes are held back until enough of the pairs are quoted, counting restored edges
+                updater.measure_coverage();
+                (name.clone(), GraphEntry { name, updater, config, audit: audit.clone(), balance: balance.clone() })
+            })
+            .collect();
+        Self { dal, graphs, audit }
+    }
+
+    pub fn dal(&self) -> &Arc<DalContext> {
+        &self.dal
+    }
+
+    // The graph called `name`, or the default one for None
+    pub fn get(&self, name: Option<&str>) -> Result<&GraphEntry, DalError> {
+        let name = name.unwrap_or(DEFAULT_GRAPH);
+        self.graphs.get(name).ok_or_else(|| DalError::UnknownGraph {
+            name: name.to_string(),
+            known: self.graphs.keys().cloned().collect(),
+        })
+    }
+
+    // None unless [audit] is enabled
+    pub fn audit(&self) -> Option<&Arc<AuditLog>> {
+        self.audit.as_ref()
+    }
+
+    pub fn default_graph(&self) -> &GraphEntry {
+        &self.graphs[DEFAULT_GRAPH]
+    }
+
+    // Sorted by name
+    pub fn iter(&self) -> impl Iterator<Item = &GraphEntry> {
+        self.graphs.values()
+    }
+
+    // One scheduler per graph, see GraphEntry::scheduler
+    pub fn schedulers(&self) -> Vec<RefreshScheduler> {
+        self.iter().map(GraphEntry::scheduler).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::{self, AdapterError, BridgeEdge, mock::MockAdapter, unix_now};
+    use polypath_graph::{RouteError, RouteIntent};
+
+    const USDC_ETHEREUM: &str = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+    // Destinations of the pairs in `warming_up`, the first quoted on the first refresh, the
+    // second from the second one on, and so on
+    const DESTINATIONS: [(&str, &str); 4] = [
+        ("polygon", "0x3c499c542cef5e3811e1192ce70d8cc03d5c3359"),
+        ("arbitrum", "0xaf88d065e77c8cc2239327c5edb3a432268e5831"),
+        ("base", "0x833589fcd6edb6e08f4c7c32d4f71b54bda02913"),
+        ("optimism", "0x0b2c639c533813f4aa9d7837caf62653d097ff85"),
+    ];
+
+    fn registry(name: &str, graphs: &str) -> GraphRegistry {
+        let config_path = std::env::temp_dir().join(format!("polypath-dal-graphs-{}-{}.toml", name, std::process::id()));
+        std::fs::write(&config_path, format!(
+            "[global]\nupdate_interval = 60\n[bridges.stargate]\nbase_url = \"https://stargate.test\"\nchains = [\"ethereum\", \"polygon\"]\n{}",
+            graphs
+        )).unwrap();
+        let dal = DalContext::new(config_path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&config_path).unwrap();
+        GraphRegistry::new(dal, 4)
+    }
+
+    #[test]
+    fn every_section_gets_a_graph_next_to_the_default_one() {
+        let graphs = registry("sections", "[graphs.stables]\nbridges = [\"stargate\"]\nupdate_interval = \"5m\"\nmax_hops = 2\n");
+        assert_eq!(graphs.iter().map(GraphEntry::name).collect::<Vec<_>>(), ["default", "stables"]);
+        assert_eq!(graphs.get(None).unwrap().name(), DEFAULT_GRAPH);
+
+        let stables = graphs.get(Some("stables")).unwrap();
+        assert_eq!(stables.update_interval(), Duration::from_secs(300));
+        assert_eq!((stables.route_options().max_hops, stables.route_options().max_results), (2, 3));
+        assert_eq!(graphs.default_graph().update_interval(), Duration::from_secs(60));
+        assert_eq!(graphs.default_graph().route_options(), RouteOptions::default());
+        assert!(!Arc::ptr_eq(stables.graph(), graphs.default_graph().graph()));
+        assert_eq!(graphs.schedulers().len(), 2);
+
+        let err = graphs.get(Some("volatile")).unwrap_err();
+        assert_eq!(err.to_string(), "unknown graph `volatile`, known graphs: default, stables");
+    }
+
+    // Graphs over the "trickle" bridge's four pairs out of ethereum, which only serve routes once
+    // three of them are quoted
+    fn warming_up(graph: Option<Graph>) -> GraphRegistry {
+        adapters::register("trickle", |_| {
+            let mut mock = MockAdapter::named("trickle");
+            for (failures, (chain, _)) in DESTINATIONS.iter().enumerate() {
+                let quote = BridgeEdge { cost: 1.0, speed: 60.0, liquidity: 1_000_000.0, risk: 0.1, valid_until: Some(unix_now() + 600), ..BridgeEdge::default() };
+                mock = mock
+                    .with_quote("ethereum", chain, quote)
+                    .with_failing_calls("ethereum", chain, 0..failures, AdapterError::Network("reset".to_string()));
+            }
+            Ok(Box::new(mock))
+        });
+        let pairs: String = DESTINATIONS
+            .iter()
+            .map(|(chain, token)| format!(
+                "[[bridges.trickle.pairs]]\nsource_chain = \"ethereum\"\nsource_address = \"{}\"\nsource_token_name = \"USDC\"\ndestination_chain = \"{}\"\ndestination_address = \"{}\"\ndestination_token_name = \"USDC\"\n",
+                USDC_ETHEREUM, chain, token,
+            ))
+            .collect();
+        let config_path = std::env::temp_dir().join(format!("polypath-dal-graphs-warmup-{}-{}.toml", graph.is_some(), std::process::id()));
+        std::fs::write(&config_path, format!(
+            "[global]\nupdate_interval = 60\nmin_coverage = 0.75\n[bridges.trickle]\nbase_url = \"https://trickle.test\"\nchains = [\"ethereum\", \"polygon\", \"arbitrum\", \"base\", \"optimism\"]\n{}",
+            pairs,
+        )).unwrap();
+        let dal = DalContext::new(config_path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&config_path).unwrap();
+        match graph {
+            Some(graph) => GraphRegistry::with_default_graph(dal, graph, 4),
+            None => GraphRegistry::new(dal, 4),
+        }
+    }
+
+    // The fraction of an ethereum -> polygon query refused for warming up, None when answered
+    fn refused_at(entry: &GraphEntry, options: &RouteOptions) -> Option<f64> {
+        let intent = RouteIntent {
+            from_chain: "ethereum".to_string(),
+            from_token: "USDC".to_string(),
+            to_chain: "polygon".to_string(),
+            to_token: "USDC".to_string(),
+            amount: 1_000.0,
+            preference: None,
+            src_address: None,
+        };
+        match entry.router().best_routes(&intent, options) {
+            Ok(routes) => {
+                assert_eq!(routes.len(), 1);
+                None
+            }
+            Err(RouteError::Warmup { coverage, .. }) => Some(coverage),
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    #[tokio::test]
+    async fn routes_are_held_back_until_enough_pairs_are_quoted() {
+        let graphs = warming_up(None);
+        let entry = graphs.default_graph();
+        let coverage = entry.graph().coverage().unwrap();
+        assert_eq!((coverage.fraction, coverage.configured, coverage.missing_sample.len()), (0.0, 4, 4));
+        assert!(coverage.missing_sample[0].starts_with("trickle:ethereum:"), "{:?}", coverage.missing_sample);
+
+        let partial = RouteOptions { allow_partial: true, ..RouteOptions::default() };
+        for fraction in [0.25, 0.5] {
+            entry.updater().refresh_once().await;
+            assert_eq!(refused_at(entry, &RouteOptions::default()), Some(fraction));
+            assert_eq!(refused_at(entry, &partial), None);
+        }
+        // Open from exactly min_coverage
+        entry.updater().refresh_once().await;
+        assert_eq!(refused_at(entry, &RouteOptions::default()), None);
+        let coverage = entry.graph().coverage().unwrap();
+        assert_eq!((coverage.fresh, coverage.stale, coverage.missing_sample.len()), (3, 0, 1));
+        let snapshot = entry.graph().snapshot();
+
+        // Once open, losing pairs again doesn't close it
+        let from = entry.updater().asset_node_id("ethereum", USDC_ETHEREUM);
+        let polygon = entry.updater().asset_node_id(DESTINATIONS[0].0, DESTINATIONS[0].1);
+        for edge in entry.graph().get_outgoing_edges(from).iter().filter(|edge| edge.to != polygon) {
+            entry.graph().set_edge_active(from, edge.to, &edge.bridge_name, false);
+        }
+        let coverage = entry.updater().measure_coverage();
+        assert!(coverage.fraction < 0.75 && coverage.warmed_up);
+        assert_eq!(refused_at(entry, &RouteOptions::default()), None);
+
+        // The three edges restored from a snapshot count half until they're quoted again
+        let restored = Graph::from_snapshot(snapshot, 4).unwrap();
+        let graphs = warming_up(Some(restored));
+        let entry = graphs.default_graph();
+        assert_eq!(refused_at(entry, &RouteOptions::default()), Some(0.375));
+        for fraction in [0.5, 0.625] {
+            entry.updater().refresh_once().await;
+            assert_eq!(refused_at(entry, &RouteOptions::default()), Some(fraction));
+        }
+        entry.updater().refresh_once().await;
+        assert_eq!(refused_at(entry, &RouteOptions::default()), None);
+        let coverage = entry.graph().coverage().unwrap();
+        assert_eq!((coverage.fresh, coverage.stale), (3, 0));
+    }
+}
@@ -0,0 +1,444 @@
+// Edge metrics over time in the persistence store, e.g. to see how a bridge's fee moved over
+// the last day. Samples are kept raw in one bucket per edge and hour; once a bucket is older
+// than history.downsample_after it's averaged into one sample in the edge's bucket for that
+// day, and buckets older than history.retention are dropped.
+
+use std::{collections::BTreeMap, time::Duration};
+use polypathroute_core::{CoreError, HistoryConfig, PersistenceError, PersistenceManager, Versioned, sha256_hex};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::error::DalError;
+
+// Prefix of every history entry in the persistence store
+const HISTORY_PREFIX: &str = "edge_history/";
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+
+// Metrics of an edge at one time, or averaged over several samples from `timestamp` on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricsSample {
+    // Unix seconds
+    pub timestamp: u64,
+    pub cost: f64,
+    pub speed: f64,
+    pub liquidity: f64,
+    pub risk: f64,
+    // Raw samples behind this one, 1 for a raw sample
+    #[serde(default = "one")]
+    pub samples: u32,
+}
+
+fn one() -> u32 {
+    1
+}
+
+impl MetricsSample {
+    // Average of `samples` weighted by how many raw samples each stands for, at `timestamp`
+    fn average(timestamp: u64, samples: &[MetricsSample]) -> MetricsSample {
+        let count: u32 = samples.iter().map(|sample| sample.samples).sum();
+        let mean = |field: fn(&MetricsSample) -> f64| {
+            samples.iter().map(|sample| field(sample) * sample.samples as f64).sum::<f64>() / count as f64
+        };
+        MetricsSample {
+            timestamp,
+            cost: mean(|sample| sample.cost),
+            speed: mean(|sample| sample.speed),
+            liquidity: mean(|sample| sample.liquidity),
+            risk: mean(|sample| sample.risk),
+            samples: count,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Resolution {
+    // Samples as stored: raw ones where they haven't been downsampled yet, hourly ones before
+    Raw,
+    // One average per minute that has samples
+    Minute,
+    // One average per hour that has samples
+    Hour,
+}
+
+impl Resolution {
+    fn seconds(self) -> Option<u64> {
+        match self {
+            Resolution::Raw => None,
+            Resolution::Minute => Some(60),
+            Resolution::Hour => Some(HOUR),
+        }
+    }
+}
+
+// What one History::compact did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CompactionReport {
+    // Raw hour buckets averaged into their day's bucket
+    pub downsampled: usize,
+    // Buckets dropped for being older than the retention
+    pub pruned: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum BucketKind {
+    // One hour of raw samples
+    Raw,
+    // One day of hourly averages
+    Hourly,
+}
+
+impl BucketKind {
+    fn name(self) -> &'static str {
+        match self {
+            BucketKind::Raw => "raw",
+            BucketKind::Hourly => "hourly",
+        }
+    }
+
+    fn span(self) -> u64 {
+        match self {
+            BucketKind::Raw => HOUR,
+            BucketKind::Hourly => DAY,
+        }
+    }
+}
+
+// A bucket key split into its parts
+struct Bucket {
+    // See edge_key
+    edge_key: String,
+    kind: BucketKind,
+    // Unix seconds, a multiple of the kind's span
+    start: u64,
+}
+
+impl Bucket {
+    // Zero-padded so keys sort by time
+    fn key(edge_key: &str, kind: BucketKind, start: u64) -> String {
+        format!("{}{}/{}/{:012}", HISTORY_PREFIX, edge_key, kind.name(), start)
+    }
+
+    fn parse(key: &str) -> Option<Bucket> {
+        let mut parts = key.strip_prefix(HISTORY_PREFIX)?.rsplitn(3, '/');
+        let start = parts.next()?.parse().ok()?;
+        let kind = match parts.next()? {
+            "raw" => BucketKind::Raw,
+            "hourly" => BucketKind::Hourly,
+            _ => return None,
+        };
+        Some(Bucket { kind, start, edge_key: parts.next()?.to_string() })
+    }
+
+    fn end(&self) -> u64 {
+        self.start + self.kind.span()
+    }
+}
+
+// Names an edge in the history by its bridge label and the asset nodes it joins, e.g.
+// "stargate:ethereum:0xa0b8…->polygon:0x3c49…". Callers pass the chain keys and token
+// addresses the graph uses, see GraphUpdater::edge_id.
+pub fn edge_id(bridge: &str, src: (&str, &str), dst: (&str, &str)) -> String {
+    format!("{}:{}:{}->{}:{}", bridge, src.0, src.1, dst.0, dst.1)
+}
+
+// Stands in for an edge id in bucket keys, which would otherwise run past what file names can
+// hold. The full id is stored in the bucket.
+fn edge_key(edge_id: &str) -> String {
+    sha256_hex(edge_id.as_bytes())[..16].to_string()
+}
+
+// Clones share one store
+#[derive(Debug, Clone)]
+pub struct History {
+    store: PersistenceManager,
+    retention: Duration,
+    downsample_after: Duration,
+}
+
+impl History {
+    pub fn new(store: PersistenceManager, config: &HistoryConfig) -> Self {
+        // Buckets written before keys were hashed sit under their full edge id, which queries no
+        // longer read; compact still ages them out
+        store.migrator().register(StoredBucket::SCHEMA, 1, |samples| Ok(json!({ "edge_id": "", "samples": samples })));
+        Self { store, retention: config.retention, downsample_after: config.downsample_after }
+    }
+
+    // Adds `sample` to its edge's raw bucket for the hour
+    pub fn record(&self, edge_id: &str, sample: &MetricsSample) -> Result<(), DalError> {
+        let key = Bucket::key(&edge_key(edge_id), BucketKind::Raw, sample.timestamp - sample.timestamp % HOUR);
+        self.store
+            .transaction(|tx| {
+                let mut samples = self.decode(&key, tx.get(&key)?)?.samples;
+                let at = samples.partition_point(|stored| stored.timestamp <= sample.timestamp);
+                samples.insert(at, sample.clone());
+                tx.put(key.clone(), self.encode(&key, edge_id, samples));
+                Ok(())
+            })
+            .map_err(CoreError::from)?;
+        Ok(())
+    }
+
+    // The edge's samples with `from <= timestamp < to`, oldest first. Coarser resolutions
+    // average the samples of each minute or hour, stamped with its start; hours already
+    // downsampled stay one sample at any resolution.
+    pub fn query(&self, edge_id: &str, from: u64, to: u64, resolution: Resolution) -> Result<Vec<MetricsSample>, DalError> {
+        let mut samples = Vec::new();
+        for key in self.store.keys_with_prefix(&edge_prefix(edge_id)).map_err(CoreError::from)? {
+            let Some(bucket) = Bucket::parse(&key) else {
+                continue;
+            };
+            if bucket.end() <= from || bucket.start >= to {
+                continue;
+            }
+            let stored = self.store.get(key.clone()).map_err(CoreError::from)?;
+            let stored = self.decode(&key, stored).map_err(CoreError::from)?;
+            if stored.edge_id != edge_id {
+                continue;
+            }
+            samples.extend(stored.samples.into_iter().filter(|sample| (from..to).contains(&sample.timestamp)));
+        }
+        samples.sort_by_key(|sample| sample.timestamp);
+
+        let Some(width) = resolution.seconds() else {
+            return Ok(samples);
+        };
+        let mut grouped: BTreeMap<u64, Vec<MetricsSample>> = BTreeMap::new();
+        for sample in samples {
+            grouped.entry(sample.timestamp - sample.timestamp % width).or_default().push(sample);
+        }
+        Ok(grouped.into_iter().map(|(start, samples)| MetricsSample::average(start, &samples)).collect())
+    }
+
+    // The edge's most recent sample, None when it has no history
+    pub fn latest(&self, edge_id: &str) -> Result<Option<MetricsSample>, DalError> {
+        let mut latest: Option<MetricsSample> = None;
+        for kind in [BucketKind::Raw, BucketKind::Hourly] {
+            let prefix = format!("{}{}/", edge_prefix(edge_id), kind.name());
+            let Some(key) = self.store.keys_with_prefix(&prefix).map_err(CoreError::from)?.pop() else {
+                continue;
+            };
+            let stored = self.store.get(key.clone()).map_err(CoreError::from)?;
+            let stored = self.decode(&key, stored).map_err(CoreError::from)?;
+            if stored.edge_id == edge_id
+                && let Some(last) = stored.samples.last().cloned()
+                && latest.as_ref().is_none_or(|latest| last.timestamp > latest.timestamp)
+            {
+                latest = Some(last);
+            }
+        }
+        Ok(latest)
+    }
+
+    // Drops buckets that ended more than history.retention before unix time `now`, then
+    // averages raw buckets that ended more than history.downsample_after before it into
+    // hourly ones. Applied as one batch.
+    pub fn compact(&self, now: u64) -> Result<CompactionReport, DalError> {
+        let prune_before = now.saturating_sub(self.retention.as_secs());
+        let downsample_before = now.saturating_sub(self.downsample_after.as_secs());
+        let keys = self.store.keys_with_prefix(HISTORY_PREFIX).map_err(CoreError::from)?;
+        self.store
+            .transaction(|tx| {
+                let mut report = CompactionReport::default();
+                for key in keys {
+                    let Some(bucket) = Bucket::parse(&key) else {
+                        continue;
+                    };
+                    if bucket.end() <= prune_before {
+                        tx.delete(key);
+                        report.pruned += 1;
+                        continue;
+                    }
+                    if bucket.kind != BucketKind::Raw || bucket.end() > downsample_before {
+                        continue;
+                    }
+
+                    let raw = self.decode(&key, tx.get(&key)?)?;
+                    tx.delete(key);
+                    report.downsampled += 1;
+                    if raw.samples.is_empty() {
+                        continue;
+                    }
+                    let hourly_key = Bucket::key(&bucket.edge_key, BucketKind::Hourly, bucket.start - bucket.start % DAY);
+                    let mut hourly = self.decode(&hourly_key, tx.get(&hourly_key)?)?.samples;
+                    // A raw bucket written again after it was downsampled merges into the same hour
+                    let mut merged: Vec<MetricsSample> = raw.samples;
+                    merged.extend(hourly.iter().filter(|sample| sample.timestamp == bucket.start).cloned());
+                    hourly.retain(|sample| sample.timestamp != bucket.start);
+                    let at = hourly.partition_point(|sample| sample.timestamp < bucket.start);
+                    hourly.insert(at, MetricsSample::average(bucket.start, &merged));
+                    tx.put(hourly_key.clone(), self.encode(&hourly_key, &raw.edge_id, hourly));
+                }
+                Ok(report)
+            })
+            .map_err(|err| CoreError::from(err).into())
+    }
+}
+
+fn edge_prefix(edge_id: &str) -> String {
+    format!("{}{}/", HISTORY_PREFIX, edge_key(edge_id))
+}
+
+// A bucket as stored. Version 1 was the bare list of samples.
+#[derive(Default, Serialize, Deserialize)]
+struct StoredBucket {
+    edge_id: String,
+    samples: Vec<MetricsSample>,
+}
+
+impl Versioned for StoredBucket {
+    const SCHEMA: &'static str = "metrics_samples";
+    const VERSION: u32 = 2;
+}
+
+impl History {
+    fn encode(&self, key: &str, edge_id: &str, samples: Vec<MetricsSample>) -> String {
+        let bucket = StoredBucket { edge_id: edge_id.to_string(), samples };
+        self.store.encode_typed(key, &bucket).expect("metrics samples always encode")
+    }
+
+    // An unreadable bucket is reported rather than silently dropped
+    fn decode(&self, key: &str, value: Option<String>) -> Result<StoredBucket, PersistenceError> {
+        match value {
+            Some(value) => self.store.decode_typed::<StoredBucket>(key, &value),
+            None => Ok(StoredBucket::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EDGE: &str = "stargate:ethereum:0xa0b8->polygon:0x3c49";
+    // A day boundary, so tests cross hour and day buckets
+    const START: u64 = 1_700_000_000 - 1_700_000_000 % DAY;
+
+    fn history() -> History {
+        History::new(PersistenceManager::new(), &HistoryConfig::default())
+    }
+
+    fn history_dir() -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("polypath-history-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn sample(timestamp: u64, cost: f64) -> MetricsSample {
+        MetricsSample { timestamp, cost, speed: 60.0, liquidity: 1_000_000.0, risk: 0.1, samples: 1 }
+    }
+
+    // One sample every 30 seconds for `hours` hours from `from`, costing its index
+    fn record_every_30s(history: &History, edge_id: &str, from: u64, hours: u64) {
+        for i in 0..hours * 120 {
+            history.record(edge_id, &sample(from + i * 30, i as f64)).unwrap();
+        }
+    }
+
+    #[test]
+    fn queries_cross_bucket_boundaries() {
+        let history = history();
+        record_every_30s(&history, EDGE, START, 3);
+        record_every_30s(&history, "across:ethereum:0xa0b8->polygon:0x3c49", START, 1);
+
+        // 30 minutes either side of the first hour boundary
+        let raw = history.query(EDGE, START + 1800, START + 5400, Resolution::Raw).unwrap();
+        assert_eq!(raw.len(), 120);
+        assert_eq!((raw[0].timestamp, raw[119].timestamp), (START + 1800, START + 5370));
+        assert!(raw.windows(2).all(|pair| pair[0].timestamp < pair[1].timestamp));
+
+        let minutes = history.query(EDGE, START + 1800, START + 5400, Resolution::Minute).unwrap();
+        assert_eq!(minutes.len(), 60);
+        assert_eq!(minutes[0], MetricsSample { cost: 60.5, samples: 2, ..sample(START + 1800, 0.0) });
+
+        let hours = history.query(EDGE, START, START + 3 * HOUR, Resolution::Hour).unwrap();
+        assert_eq!(hours.iter().map(|hour| (hour.timestamp, hour.samples)).collect::<Vec<_>>(), [
+            (START, 120),
+            (START + HOUR, 120),
+            (START + 2 * HOUR, 120),
+        ]);
+        assert_eq!(hours[1].cost, 179.5);
+
+        assert!(history.query(EDGE, START + 3 * HOUR, START + 4 * HOUR, Resolution::Raw).unwrap().is_empty());
+        assert_eq!(history.latest(EDGE).unwrap(), Some(sample(START + 3 * HOUR - 30, 359.0)));
+        assert_eq!(history.latest("missing").unwrap(), None);
+    }
+
+    #[test]
+    fn out_of_order_samples_are_kept_sorted() {
+        let history = history();
+        for timestamp in [START + 90, START + 30, START + 60] {
+            history.record(EDGE, &sample(timestamp, 1.0)).unwrap();
+        }
+        let raw = history.query(EDGE, START, START + HOUR, Resolution::Raw).unwrap();
+        assert_eq!(raw.iter().map(|sample| sample.timestamp).collect::<Vec<_>>(), [START + 30, START + 60, START + 90]);
+    }
+
+    #[test]
+    fn compaction_downsamples_then_prunes() {
+        let history = history();
+        // Eight days of samples, two an hour
+        for hour in 0..8 * 24 {
+            for (offset, cost) in [(0, 1.0), (1800, 3.0)] {
+                history.record(EDGE, &sample(START + hour * HOUR + offset, cost)).unwrap();
+            }
+        }
+        let now = START + 8 * DAY;
+
+        let report = history.compact(now).unwrap();
+        // Seven days of raw buckets are older than a day; one of those days is past retention
+        assert_eq!(report, CompactionReport { downsampled: 6 * 24, pruned: 24 });
+        assert_eq!(history.compact(now).unwrap(), CompactionReport::default());
+
+        assert!(history.query(EDGE, 0, START + DAY, Resolution::Raw).unwrap().is_empty());
+        let downsampled = history.query(EDGE, START + DAY, START + 7 * DAY, Resolution::Raw).unwrap();
+        assert_eq!(downsampled.len(), 6 * 24);
+        assert!(downsampled.iter().all(|hour| hour.cost == 2.0 && hour.samples == 2 && hour.timestamp % HOUR == 0));
+        assert_eq!(history.query(EDGE, START + 7 * DAY, now, Resolution::Raw).unwrap().len(), 48);
+        // Minute resolution can't split an hour that's been downsampled
+        assert_eq!(history.query(EDGE, START + DAY, START + DAY + HOUR, Resolution::Minute).unwrap().len(), 1);
+
+        // A late sample for a downsampled hour merges into its average
+        history.record(EDGE, &sample(START + DAY + 60, 8.0)).unwrap();
+        assert_eq!(history.compact(now).unwrap(), CompactionReport { downsampled: 1, pruned: 0 });
+        let hour = history.query(EDGE, START + DAY, START + DAY + HOUR, Resolution::Hour).unwrap();
+        assert_eq!((hour.len(), hour[0].timestamp, hour[0].cost, hour[0].samples), (1, START + DAY, 4.0, 3));
+
+        // A week on, every bucket is past retention and dropped without being downsampled
+        let report = history.compact(now + 7 * DAY).unwrap();
+        assert_eq!(report, CompactionReport { downsampled: 0, pruned: 6 + 24 });
+        assert!(history.query(EDGE, 0, u64::MAX, Resolution::Raw).unwrap().is_empty());
+    }
+
+    #[test]
+    fn history_survives_a_restart_on_files() {
+        let dir = history_dir();
+        // Long enough that the edge id alone would overflow a file name
+        let edge = edge_id(
+            "stargate",
+            ("ethereum", "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"),
+            ("arbitrum", "0xaf88d065e77c8cc2239327c5edb3a432268e5831"),
+        );
+        let history = History::new(PersistenceManager::open(&dir).unwrap(), &HistoryConfig::default());
+        record_every_30s(&history, &edge, START, 2);
+        assert_eq!(history.compact(START + 3 * DAY).unwrap().downsampled, 2);
+
+        let reopened = History::new(PersistenceManager::open(&dir).unwrap(), &HistoryConfig::default());
+        let hours = reopened.query(&edge, START, START + DAY, Resolution::Hour).unwrap();
+        assert_eq!(hours.iter().map(|hour| (hour.timestamp, hour.samples)).collect::<Vec<_>>(), [(START, 120), (START + HOUR, 120)]);
+        assert_eq!(reopened.latest(&edge).unwrap().map(|sample| sample.timestamp), Some(START + HOUR));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn buckets_from_before_hashed_keys_still_compact() {
+        let history = history();
+        let legacy = format!("{}{}/raw/{:012}", HISTORY_PREFIX, EDGE, START);
+        history.store.store(legacy.clone(), serde_json::to_string(&[sample(START, 1.0)]).unwrap()).unwrap();
+
+        assert_eq!(history.compact(START + 2 * DAY).unwrap(), CompactionReport { downsampled: 1, pruned: 0 });
+        assert!(history.query(EDGE, 0, u64::MAX, Resolution::Raw).unwrap().is_empty());
+        assert_eq!(history.compact(START + 9 * DAY).unwrap().pruned, 1);
+    }
+}
@@ -1,46 +1,1194 @@
-mod adapters;
+pub mod adapters;
+mod alerts;
+mod api_keys;
+mod allowance;
+mod audit;
+mod balance;
+mod cache;
+mod batch;
+mod error;
+mod depth;
+mod digest;
+mod dry_run;
+mod executor;
+mod fx;
+mod gas;
+mod graphs;
+mod history;
+mod lint;
+mod pins;
+pub mod present;
+mod profiles;
+mod quarantine;
+mod registry;
+mod replay;
+mod runtime;
+mod sanity;
+mod scheduler;
+mod selftest;
+mod snapshot;
+mod updater;
+mod updates;
 
-use polypathroute_core::{CoreContext, LoggingManager};
+pub use crate::alerts::{Alert, AlertEngine, EdgeEvent, EdgeIdentity, LogNotifier, Notifier, WebhookNotifier};
+pub use crate::api_keys::StoredApiKey;
+#[cfg(any(test, feature = "mock"))]
+pub use crate::allowance::MockAllowanceChecker;
+pub use crate::allowance::{AllowanceChecker, AllowanceError, ApprovalPlanner, RpcAllowanceChecker};
+pub use crate::audit::{AuditEntry, AuditLog, AuditStats, AuditedRoute, intent_hash};
+pub use crate::balance::RpcBalanceChecker;
+pub use crate::cache::{CachedQuote, QuoteCache};
+pub use crate::error::DalError;
+pub use crate::dry_run::{DryRunReport, DryRunThresholds, DryRunVerdict, HopDrift};
+pub use crate::executor::{ExecutorError, RouteExecutor, RouteHandle};
+pub use crate::digest::{AdapterLatency, DIGEST_RETRIES, DigestCadence, DigestNotifier, RefreshDigest};
+pub use crate::depth::{DepthLadder, DepthProfile, max_amount_within_slippage};
+pub use crate::fx::{CurrencyId, FxConverter, FxError, HttpFxConverter, Money, StaticFxTable};
+pub use crate::gas::{DEFAULT_APPROVE_GAS_UNITS, DEFAULT_BRIDGE_GAS_UNITS, GasAction, GasError, GasEstimate, GasEstimator, OracleGasEstimator};
+pub use crate::graphs::{DEFAULT_GRAPH, GraphEntry, GraphRegistry};
+pub use crate::lint::{LintFinding, LintKind, LintReport, Severity};
+pub use crate::history::{CompactionReport, History, MetricsSample, Resolution, edge_id};
+pub use crate::pins::PersistedPins;
+pub use crate::profiles::{PreferenceProfile, layered_options};
+pub use crate::quarantine::{QUARANTINE_BACKOFF, QuarantinedPair};
+pub use crate::replay::Replay;
+pub use crate::selftest::{SelfTestReport, SelfTestStep, StepStatus, selftest};
+pub use crate::batch::{FetchOutcome, PROBE_ADDRESS, PROBE_AMOUNT, fetch_all};
+pub use crate::runtime::{Runtime, ShutdownReport};
+pub use crate::sanity::{QuoteSanityChecker, RejectedQuote, SanityViolation};
+pub use crate::scheduler::{PairsChange, RefreshScheduler, SchedulerStats};
+pub use crate::snapshot::{DEFAULT_SNAPSHOT_MAX_AGE, SNAPSHOT_ARCHIVE_LIMIT, SnapshotMetadata, load_graph_snapshot, load_graph_snapshot_at, load_node_directory, save_graph_snapshot};
+pub use crate::updater::{DEFAULT_REFRESH_CONCURRENCY, FetchCounts, GraphUpdater, RefreshReport};
+
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::{Duration, Instant}};
+use futures::future::join_all;
+use tracing::Instrument;
+use polypath_graph::{Graph, NodeDirectory, NodeId, Path, ROUTE_SEARCH_STAGE, RouteIntent, RoutingEngine, RoutingParams};
+use polypathroute_core::{BridgeConfig, CacheManager, ConfigManager, CoreContext, Fields, FinalityModel, LoggingManager, MetricsManager, Registry, RegistryError, RequestContext, SlowOpGuard};
+use anyhow::Result;
+
+use crate::{batch::fetch_all_in, registry::AdapterRegistry};
 
 #[derive(Debug)]
 pub struct DalContext {
-    core: CoreContext
+    core: CoreContext,
+    quote_cache: QuoteCache,
+    adapters: AdapterRegistry,
+    // Settlement time added to every edge's speed, see GraphUpdater
+    finality: FinalityModel,
+    // Seed every bridge is simulated with, see `with_simulation`
+    simulation: Option<u64>,
+    // Where every bridge's responses are recorded to or replayed from, see `with_fixtures`
+    fixtures: Option<(adapters::FixtureMode, PathBuf)>,
+    // Quotes being fetched by any refresh, see batch::fetch_stream_shared
+    in_flight: Arc<batch::InFlight>,
+}
+
+// Quotes `request` on `adapter` and caches the quote
+async fn fetch_into(
+    adapter: &(dyn adapters::BridgeAdapter + Send + Sync),
+    request: &adapters::QuoteRequest,
+    quotes: &QuoteCache,
+    metrics: &MetricsManager,
+) -> Result<adapters::BridgeEdge> {
+    let bridge = adapter.name();
+    let started = Instant::now();
+    let fetched = adapter.fetch_metrics(request).await;
+    metrics.record_adapter_request(&bridge, fetched.is_ok(), started.elapsed());
+    let edge = fetched?;
+    quotes.insert(&bridge, request, &edge)?;
+    Ok(edge)
 }
 
 impl DalContext {
-    fn new(path: &str) -> DalContext {
+    pub fn new(path: &str) -> Result<DalContext, DalError> {
+        Ok(Self::from_core(CoreContext::new(path)?))
+    }
+
+    // For a core built with CoreContext::builder, e.g. one whose logging the caller set up
+    pub fn from_core(core: CoreContext) -> DalContext {
+        let ttl = core.config_manager.global.cache_ttl;
         DalContext {
-            core: CoreContext::new(path)    
+            quote_cache: QuoteCache::new(core.cache_manager.clone(), ttl),
+            adapters: AdapterRegistry::default(),
+            finality: FinalityModel::from_config(&core.config_manager.finality, &core.registry),
+            simulation: None,
+            fixtures: None,
+            in_flight: Arc::default(),
+            core
+        }
+    }
+
+    // Quotes every configured bridge with a SimulatedAdapter seeded with `seed` rather than its
+    // API, reading the simulation's parameters from the bridge's `[extra.simulation]` table.
+    // Call before any adapter is built.
+    pub fn with_simulation(mut self, seed: u64) -> Self {
+        self.simulation = Some(seed);
+        self
+    }
+
+    pub fn simulation_seed(&self) -> Option<u64> {
+        self.simulation
+    }
+
+    // Records every bridge's API responses into `directory`, or replays them from there, as if
+    // each bridge's section had an `[extra.fixtures]` table. Simulated bridges send nothing to
+    // record. Call before any adapter is built.
+    pub fn with_fixtures(mut self, mode: adapters::FixtureMode, directory: impl Into<PathBuf>) -> Self {
+        self.fixtures = Some((mode, directory.into()));
+        self
+    }
+
+    // Quotes through the cache: identical requests within global.cache_ttl reuse the stored edge.
+    // Within global.cache_soft_ttl of its expiry, the first request to find it also quotes it
+    // again in the background, so a popular quote is replaced before every reader misses it.
+    pub async fn fetch_quote(
+        &self,
+        adapter: &Arc<dyn adapters::BridgeAdapter + Send + Sync>,
+        request: &adapters::QuoteRequest
+    ) -> Result<CachedQuote> {
+        let bridge = adapter.name();
+        let pair = format!("{}->{}", request.src_chain, request.dst_chain);
+        let span = self.logger().span_with("fetch_quote", &[("adapter", &bridge), ("pair", &pair)]).exit();
+        async {
+            if let Some((edge, claim)) = self.quote_cache.get_for_refresh(&bridge, request) {
+                if let Some(claim) = claim {
+                    let (adapter, request) = (Arc::clone(adapter), request.clone());
+                    let (quotes, metrics, logger) = (self.quote_cache.clone(), self.metrics().clone(), self.logger().clone());
+                    tokio::spawn(
+                        async move {
+                            if let Err(err) = fetch_into(adapter.as_ref(), &request, &quotes, &metrics).await {
+                                logger.debug_with("early quote refresh failed", &[("error", &err)]);
+                            }
+                            drop(claim);
+                        }
+                        .in_current_span(),
+                    );
+                }
+                return Ok(CachedQuote { edge, from_cache: true });
+            }
+
+            let edge = fetch_into(adapter.as_ref(), request, &self.quote_cache, self.metrics()).await?;
+            self.logger().debug_with("quote fetched", &[("estimated_output", &edge.estimated_output)]);
+            Ok(CachedQuote { edge, from_cache: false })
+        }
+        .instrument(span)
+        .await
+    }
+
+    // Whether a quote can still be acted on. Expired quotes are never served from the cache,
+    // but callers holding on to an edge should check before using it.
+    pub fn is_quote_fresh(&self, edge: &adapters::BridgeEdge) -> bool {
+        edge.is_valid_at(adapters::unix_now())
+    }
+
+    // Configured bridges that have an adapter implementation, sorted. Every bridge has one
+    // when simulated.
+    pub fn adapter_names(&self) -> Vec<String> {
+        let available = adapters::available_adapters();
+        let mut names: Vec<String> = self.core.config_manager.bridges
+            .keys()
+            .filter(|name| self.simulation.is_some() || available.contains(&name.to_lowercase()))
+            .cloned()
+            .collect();
+        names.sort();
+        names
+    }
+
+    // Quotes `request`'s route along `ladder`, starting from the request's own amount
+    pub async fn probe_depth(
+        &self,
+        adapter: &(dyn adapters::BridgeAdapter + Send + Sync),
+        request: &adapters::QuoteRequest,
+        ladder: &DepthLadder
+    ) -> Result<DepthProfile, adapters::AdapterError> {
+        let base: f64 = request.src_amount.parse().map_err(|_| {
+            adapters::AdapterError::Config(format!("`{}` is not a valid source amount", request.src_amount))
+        })?;
+        let points = adapter.fetch_depth(request, &ladder.amounts(base)).await?;
+        let max_amount = max_amount_within_slippage(&points, ladder.max_slippage);
+        Ok(DepthProfile { points, max_amount })
+    }
+
+    // The context's shared instance of an adapter, built on first use. Unlike create_adapter,
+    // every caller sees the same circuit breaker and telemetry.
+    pub fn adapter(&self, adapter_name: &str) -> Result<Arc<adapters::DynBridgeAdapter>, DalError> {
+        self.adapters.get_or_try_insert(adapter_name, || self.create_adapter(adapter_name))
+    }
+
+    // Where a transfer sent over `adapter_name`'s bridge stands. Only reads from the bridge.
+    pub async fn track(&self, adapter_name: &str, reference: &adapters::TransferReference) -> Result<adapters::TransferStatus, DalError> {
+        Ok(self.adapter(adapter_name)?.track_transfer(reference).await?)
+    }
+
+    // Telemetry of every adapter instance this context has handed out
+    pub fn metrics_report(&self) -> adapters::MetricsReport {
+        adapters::MetricsReport::new(
+            self.adapters
+                .all()
+                .into_iter()
+                .map(|(name, adapter)| (name, adapter.metrics()))
+                .collect()
+        )
+    }
+
+    // An adapter's metrics, None until it's first used
+    pub fn adapter_metrics(&self, name: &str) -> Option<adapters::AdapterMetrics> {
+        self.adapters.get(name).map(|adapter| adapter.metrics())
+    }
+
+    pub fn create_adapter(&self, adapter_name: &str) -> Result<adapters::DynBridgeAdapter, DalError> {
+        let known = self.adapter_names();
+        if !known.iter().any(|name| name == adapter_name) {
+            return Err(DalError::UnknownAdapter { name: adapter_name.to_string(), known });
         }
+        Ok(self.build_adapter(adapter_name, &self.core.config_manager.bridges[adapter_name])?)
+    }
+
+    fn build_adapter(&self, bridge: &str, config: &BridgeConfig) -> Result<adapters::DynBridgeAdapter, adapters::AdapterError> {
+        let fx = self.fx_converter();
+        match (self.simulation, &self.fixtures) {
+            (Some(seed), _) => adapters::create_simulated_adapter(bridge, config, seed),
+            (None, Some((mode, directory))) => {
+                let mut fixtures = toml::Table::new();
+                fixtures.insert("mode".to_string(), toml::Value::String(mode.as_str().to_string()));
+                fixtures.insert("directory".to_string(), toml::Value::String(directory.display().to_string()));
+                let mut config = config.clone();
+                config.extra.get_or_insert_with(HashMap::new).insert("fixtures".to_string(), toml::Value::Table(fixtures));
+                adapters::create_adapter_with(bridge, &config, fx)
+            }
+            (None, None) => adapters::create_adapter_with(bridge, config, fx),
+        }
+    }
+
+    // Fresh instances of every configured bridge's adapter, keyed by bridge name. Sections
+    // without an implementation, or whose adapter fails to build, are skipped with a warning.
+    pub fn create_all_adapters(&self) -> HashMap<String, adapters::DynBridgeAdapter> {
+        let available = adapters::available_adapters();
+        let mut created = HashMap::new();
+        for (bridge, config) in &self.core.config_manager.bridges {
+            if self.simulation.is_none() && !available.contains(&bridge.to_lowercase()) {
+                self.logger().warn_with("no adapter implementation registered, skipping", &[("bridge", bridge)]);
+                continue;
+            }
+            match self.build_adapter(bridge, config) {
+                Ok(adapter) => {
+                    created.insert(bridge.clone(), adapter);
+                }
+                Err(err) => {
+                    self.logger().warn_with("skipping bridge", &[("bridge", bridge), ("error", &err)]);
+                }
+            }
+        }
+        created
+    }
+
+    // Quotes every supported pair of every configured bridge with at most `concurrency`
+    // requests in flight, batching pairs for bridges whose adapter takes several per call.
+    // Bridges without an adapter implementation are skipped.
+    pub async fn fetch_all_metrics(&self, concurrency: usize) -> Vec<FetchOutcome> {
+        let mut jobs = Vec::new();
+        for bridge in self.adapter_names() {
+            let adapter = match self.adapter(&bridge) {
+                Ok(adapter) => adapter,
+                Err(err) => {
+                    self.logger().warn_with("skipping bridge", &[("bridge", &bridge), ("error", &err)]);
+                    continue;
+                }
+            };
+            for pair in adapter.supported_pairs() {
+                let context = self.quote_request(&bridge, &pair);
+                jobs.push((Arc::clone(&adapter), pair, context));
+            }
+        }
+
+        self.fetch_jobs(jobs, concurrency).await
+    }
+
+    // Context for quoting `pair` on `adapter`: its events carry a fresh trace id and the
+    // adapter and chains
+    pub fn quote_request(&self, adapter: &str, pair: &adapters::SupportedPair) -> RequestContext {
+        self.logger().request("quote_pair", &[("adapter", &adapter), ("src_chain", &pair.src_chain), ("dst_chain", &pair.dst_chain)])
+    }
+
+    // fetch_all with each job inside its request's span, recording each request in the
+    // context's metrics and warning about calls past logging.slow_ops
+    pub(crate) async fn fetch_jobs(
+        &self,
+        jobs: Vec<(Arc<adapters::DynBridgeAdapter>, adapters::SupportedPair, RequestContext)>,
+        concurrency: usize
+    ) -> Vec<FetchOutcome> {
+        let jobs = jobs.into_iter().map(|(adapter, pair, context)| (adapter, pair, context.span)).collect();
+        let slow_after = self.config().logging.slow_ops.threshold(batch::FETCH_OPERATION);
+        let outcomes = fetch_all_in(jobs, concurrency, slow_after).await;
+        for outcome in &outcomes {
+            if let Some(latency) = outcome.latency {
+                self.metrics().record_adapter_request(&outcome.adapter, outcome.is_ok(), latency);
+            }
+        }
+        outcomes
+    }
+
+    // As fetch_jobs, with each outcome as soon as it's quoted, with the index of its job. A pair
+    // another refresh is already quoting on the same adapter waits for that quote.
+    pub(crate) fn fetch_jobs_streamed(
+        &self,
+        jobs: Vec<(Arc<adapters::DynBridgeAdapter>, adapters::SupportedPair, RequestContext)>,
+        concurrency: usize
+    ) -> impl futures::Stream<Item = (usize, FetchOutcome)> + use<> {
+        let jobs = jobs.into_iter().map(|(adapter, pair, context)| (adapter, pair, context.span)).collect();
+        let slow_after = self.config().logging.slow_ops.threshold(batch::FETCH_OPERATION);
+        let metrics = self.metrics().clone();
+        futures::StreamExt::inspect(batch::fetch_stream_shared(&self.in_flight, jobs, concurrency, slow_after), move |(_, outcome)| {
+            if let Some(latency) = outcome.latency {
+                metrics.record_adapter_request(&outcome.adapter, outcome.is_ok(), latency);
+            }
+        })
+    }
+
+    // Probes every configured bridge concurrently, keyed by bridge name. Bridges whose
+    // adapter cannot be built are reported with the construction error.
+    pub async fn health_check_all(&self) -> HashMap<String, Result<adapters::AdapterHealth, adapters::AdapterError>> {
+        let checks = self.core.config_manager.bridges.keys().map(|bridge| async move {
+            let health = match self.adapter(bridge) {
+                Ok(adapter) => adapter.health_check().await,
+                Err(DalError::Adapter(err)) => Err(err),
+                Err(err) => Err(adapters::AdapterError::Config(err.to_string())),
+            };
+            (bridge.clone(), health)
+        });
+        join_all(checks).await.into_iter().collect()
+    }
+
+    // Checks the chains of every configured pair against what the bridge itself lists and
+    // returns a warning, also logged, per chain the bridge doesn't know. Bridges that can't
+    // list their chains are skipped; a failed listing is a warning of its own.
+    pub async fn validate_config(&self) -> Vec<String> {
+        let checks = self.adapter_names().into_iter().map(|bridge| async move {
+            let adapter = match self.adapter(&bridge) {
+                Ok(adapter) => adapter,
+                Err(err) => return vec![format!("bridges.{}: {}", bridge, err)],
+            };
+            let listed = match adapter.supported_chains().await {
+                Ok(listed) if listed.is_empty() => return Vec::new(),
+                Ok(listed) => listed,
+                Err(err) => return vec![format!("bridges.{}: cannot list supported chains: {}", bridge, err)],
+            };
+
+            let mut unknown: Vec<String> = Vec::new();
+            for pair in self.supported_pairs_for(&bridge) {
+                for chain in [pair.src_chain, pair.dst_chain] {
+                    let known = listed.iter().any(|info| info.key.eq_ignore_ascii_case(&chain));
+                    if !known && !unknown.contains(&chain) {
+                        unknown.push(chain);
+                    }
+                }
+            }
+            unknown
+                .into_iter()
+                .map(|chain| format!("bridges.{}: chain `{}` in configured pairs is not supported by {}", bridge, chain, bridge))
+                .collect()
+        });
+
+        let warnings: Vec<String> = join_all(checks).await.into_iter().flatten().collect();
+        for warning in &warnings {
+            self.logger().warn(warning);
+        }
+        warnings
+    }
+
+    // Configured pairs for a bridge, used to seed graph edges. Empty for unknown bridges.
+    pub fn supported_pairs_for(&self, adapter_name: &str) -> Vec<adapters::SupportedPair> {
+        self.core.config_manager.bridges
+            .get(adapter_name)
+            .map(adapters::pairs_from_config)
+            .unwrap_or_default()
     }
 
-    pub fn create_adapter(&self, adapter_name: &str) -> adapters::DynBridgeAdapter {
-        adapters::create_adapter(adapter_name).unwrap()
+    pub fn config(&self) -> &ConfigManager {
+        &self.core.config_manager
     }
 
     pub fn logger(&self) -> &LoggingManager {
         &self.core.logging_manager
     }
+
+    pub fn metrics(&self) -> &MetricsManager {
+        &self.core.metrics_manager
+    }
+
+    // LoggingManager::time_scope_with under the threshold logging.slow_ops sets for `operation`
+    pub fn time_scope(&self, operation: &str, fields: Fields) -> SlowOpGuard {
+        self.logger().time_scope_with(operation, self.config().logging.slow_ops.threshold(operation), fields)
+    }
+
+    // A pool for route searches sized by [executor], recording into the core's metrics. Each
+    // call makes a new pool, so callers share the one they make.
+    pub fn route_executor(&self) -> RouteExecutor {
+        RouteExecutor::new(&self.core.config_manager.executor, self.core.metrics_manager.clone())
+    }
+
+    // The core's cache, persisted along with the quotes when global.cache_write_through is set
+    pub fn cache(&self) -> &CacheManager {
+        &self.core.cache_manager
+    }
+
+    pub fn registry(&self) -> &Registry {
+        &self.core.registry
+    }
+
+    pub fn finality(&self) -> &FinalityModel {
+        &self.finality
+    }
+
+    // Edge metrics history in the configured persistence store, None when [history] disables it
+    pub fn history(&self) -> Option<History> {
+        let config = &self.core.config_manager.history;
+        config.enabled.then(|| History::new(self.core.persisence_manager.clone(), config))
+    }
+
+    // Audit log of the route queries answered, in the configured persistence store. None when
+    // [audit] isn't enabled. Each call starts its own writer, so callers keep the one they get.
+    pub fn audit_log(&self) -> Option<AuditLog> {
+        let config = &self.core.config_manager.audit;
+        config.enabled.then(|| AuditLog::new(self.core.persisence_manager.clone(), config))
+    }
+
+    // Routes pinned for `graph`'s routers, in the configured persistence store, see
+    // global.route_stickiness
+    pub fn pinned_routes(&self, graph: &str) -> PersistedPins {
+        PersistedPins::new(self.core.persisence_manager.clone(), graph, self.logger().clone())
+    }
+
+    // Engine for the [alerts] rules, with their chains as registry keys. None without rules;
+    // a webhook that can't be set up is left out with a warning.
+    pub fn alert_engine(&self) -> Option<AlertEngine> {
+        let mut config = self.core.config_manager.alerts.clone();
+        if config.rules.is_empty() {
+            return None;
+        }
+        for rule in &mut config.rules {
+            for chain in [&mut rule.source_chain, &mut rule.destination_chain].into_iter().flatten() {
+                *chain = self.chain_key(chain);
+            }
+        }
+        match AlertEngine::from_config(&config, self.logger().clone()) {
+            Ok(engine) => Some(engine),
+            Err(err) => {
+                self.logger().warn_with("alert webhook unavailable, alerts are only logged", &[("error", &err)]);
+                config.webhook_url = None;
+                AlertEngine::from_config(&config, self.logger().clone()).ok()
+            }
+        }
+    }
+
+    // Digests of scheduled refreshes for the [digest] settings. None unless enabled; a webhook
+    // that can't be set up is left out with a warning.
+    pub fn digest_notifier(&self) -> Option<DigestNotifier> {
+        let mut config = self.core.config_manager.digest.clone();
+        if !config.enabled {
+            return None;
+        }
+        match DigestNotifier::from_config(&config, self.logger().clone()) {
+            Ok(notifier) => Some(notifier),
+            Err(err) => {
+                self.logger().warn_with("digest webhook unavailable, digests are only logged", &[("error", &err)]);
+                config.webhook_url = None;
+                DigestNotifier::from_config(&config, self.logger().clone()).ok()
+            }
+        }
+    }
+
+    // Checks quotes against the config's [sanity] bounds, None when it sets none
+    pub fn sanity_checker(&self) -> Option<QuoteSanityChecker> {
+        let config = &self.core.config_manager.sanity;
+        (!config.is_empty()).then(|| QuoteSanityChecker::new(config.clone()))
+    }
+
+    // Estimator for the [gas] chains, keyed by registry chain key. None without any; an
+    // estimator that can't be set up is left out with a warning.
+    pub fn gas_estimator(&self) -> Option<Arc<dyn GasEstimator>> {
+        let mut config = self.core.config_manager.gas.clone();
+        if config.chains.is_empty() {
+            return None;
+        }
+        config.chains = config.chains.into_iter().map(|(chain, gas)| (self.chain_key(&chain), gas)).collect();
+        match OracleGasEstimator::from_config(&config, &self.core.config_manager.bridges, self.logger().clone()) {
+            Ok(estimator) => Some(Arc::new(estimator)),
+            Err(err) => {
+                self.logger().warn_with("gas estimation unavailable, edge costs leave gas out", &[("error", &format!("{:#}", err))]);
+                None
+            }
+        }
+    }
+
+    // Converter into the [fx] quote currency, None when [fx] has neither rates nor a price_url;
+    // one that can't be set up is left out with a warning
+    pub fn fx_converter(&self) -> Option<Arc<dyn FxConverter>> {
+        let config = &self.core.config_manager.fx;
+        if !config.enabled() {
+            return None;
+        }
+        match fx::fx_converter(config, self.logger().clone()) {
+            Ok(converter) => Some(Arc::from(converter)),
+            Err(err) => {
+                self.logger().warn_with("fx conversion unavailable, edge costs stay in their quoted units", &[("error", &format!("{:#}", err))]);
+                None
+            }
+        }
+    }
+
+    // Approvals for plans, checked through the [gas.chains.<chain>] rpc_urls against each
+    // bridge's [extra.spenders]
+    pub fn approval_planner(&self) -> Result<ApprovalPlanner> {
+        let config = &self.core.config_manager;
+        let rpc_urls = config
+            .gas
+            .chains
+            .iter()
+            .filter_map(|(chain, gas)| Some((self.chain_key(chain), gas.rpc_url.clone()?)))
+            .collect();
+        let mut spenders = HashMap::new();
+        for (bridge, bridge_config) in &config.bridges {
+            let table = bridge_config.extra.as_ref().and_then(|extra| extra.get("spenders")).and_then(|spenders| spenders.as_table());
+            for (chain, spender) in table.into_iter().flatten() {
+                if let Some(spender) = spender.as_str() {
+                    spenders.insert((bridge.clone(), self.chain_key(chain)), spender.to_string());
+                }
+            }
+        }
+        let checker = Arc::new(RpcAllowanceChecker::new(rpc_urls)?);
+        Ok(ApprovalPlanner::new(checker, spenders, self.registry().clone(), self.logger().clone()))
+    }
+
+    // Affordability checks for routers, reading balances through the [gas.chains.<chain>]
+    // rpc_urls for the [gas] cache_ttl. None without a gas estimator or an RPC to read from.
+    pub fn balance_checker(&self) -> Option<RpcBalanceChecker> {
+        let gas = &self.core.config_manager.gas;
+        let rpc_urls: HashMap<String, String> =
+            gas.chains.iter().filter_map(|(chain, gas)| Some((self.chain_key(chain), gas.rpc_url.clone()?))).collect();
+        if rpc_urls.is_empty() {
+            return None;
+        }
+        match RpcBalanceChecker::new(rpc_urls, self.gas_estimator()?, gas.cache_ttl, self.logger().clone()) {
+            Ok(checker) => Some(checker),
+            Err(err) => {
+                self.logger().warn_with("balance checks unavailable, routes aren't checked for affordability", &[("error", &format!("{:#}", err))]);
+                None
+            }
+        }
+    }
+
+    // Registry key for a chain name or alias; chains the registry doesn't know are lowercased
+    pub fn chain_key(&self, chain: &str) -> String {
+        match self.registry().resolve_chain(chain) {
+            Ok(found) => found.key,
+            Err(_) => chain.trim().to_lowercase(),
+        }
+    }
+
+    // The intent with its chains as registry keys and its tokens as registry addresses, so it
+    // finds the GraphUpdater's nodes whatever aliases or casing it was given in. Tokens the
+    // registry doesn't know are left for the graph to resolve; symbols it finds on several
+    // tokens are an error.
+    pub fn canonical_intent(&self, intent: &RouteIntent) -> Result<RouteIntent, RegistryError> {
+        let side = |chain: &str, token: &str| {
+            let chain = self.chain_key(chain);
+            match self.registry().resolve_token(&chain, token) {
+                Ok(found) => Ok((chain, found.address)),
+                Err(RegistryError::UnknownChain(_) | RegistryError::UnknownToken { .. }) => Ok((chain, token.to_string())),
+                Err(err) => Err(err),
+            }
+        };
+        let (from_chain, from_token) = side(&intent.from_chain, &intent.from_token)?;
+        let (to_chain, to_token) = side(&intent.to_chain, &intent.to_token)?;
+        Ok(RouteIntent { from_chain, from_token, to_chain, to_token, ..intent.clone() })
+    }
+
+    // RoutingEngine::find_path, recording the search and the graph's size in the context's metrics
+    // and warning when it's slower than logging.slow_ops allows
+    pub fn find_path(&self, engine: &RoutingEngine, start: NodeId, end: NodeId, params: &RoutingParams) -> Option<Path> {
+        let graph = engine.graph();
+        self.metrics().set_graph_size(graph.node_count(), graph.active_edge_count());
+        let _slow = self.time_scope(ROUTE_SEARCH_STAGE, &[("from", &start.0), ("to", &end.0)]);
+        self.metrics().time_route_search(|| engine.find_path(start, end, params))
+    }
+
+    // Persists `graph` under `name` in the configured persistence store
+    pub fn save_graph_snapshot(&self, graph: &Graph, name: &str) -> Result<SnapshotMetadata, DalError> {
+        save_graph_snapshot(&self.core.persisence_manager, graph, name)
+    }
+
+    // Warm start from the snapshot saved under `name`, honouring global.snapshot_max_age_secs
+    pub fn load_graph_snapshot(&self, name: &str, shard_count: usize) -> Result<Graph, DalError> {
+        let max_age = self.core.config_manager.global.snapshot_max_age_secs
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_SNAPSHOT_MAX_AGE);
+        load_graph_snapshot(&self.core.persisence_manager, name, shard_count, max_age)
+    }
+
+    // Replays of the graph saved under `name` from the configured store, with edges older than
+    // global.snapshot_max_age_secs at the replayed time switched off. None when [history]
+    // disables the metrics history.
+    pub fn replay(&self, name: &str, shard_count: usize) -> Option<Replay> {
+        let max_age = self.core.config_manager.global.snapshot_max_age_secs
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_SNAPSHOT_MAX_AGE);
+        self.history().map(|history| Replay::new(self.core.persisence_manager.clone(), history, name, shard_count, max_age))
+    }
+
+    // The node directory saved with the snapshot under `name`, see snapshot::load_node_directory
+    pub fn load_node_directory(&self, name: &str) -> Result<Option<NodeDirectory>, DalError> {
+        load_node_directory(&self.core.persisence_manager, name)
+    }
+
+    // Profiles are the tenant's own; without a tenant they're the open API's
+    pub fn save_profile(&self, tenant: Option<&str>, profile: &PreferenceProfile) -> Result<(), DalError> {
+        profiles::save_profile(&self.core.persisence_manager, tenant, profile)
+    }
+
+    pub fn load_profile(&self, tenant: Option<&str>, name: &str) -> Result<Option<PreferenceProfile>, DalError> {
+        profiles::load_profile(&self.core.persisence_manager, tenant, name)
+    }
+
+    // Sorted by name
+    pub fn list_profiles(&self, tenant: Option<&str>) -> Result<Vec<PreferenceProfile>, DalError> {
+        profiles::list_profiles(&self.core.persisence_manager, tenant)
+    }
+
+    pub fn delete_profile(&self, tenant: Option<&str>, name: &str) -> Result<bool, DalError> {
+        profiles::delete_profile(&self.core.persisence_manager, tenant, name)
+    }
+
+    // API keys kept in the persistence store, on top of the config's [api_keys]
+    pub fn save_api_key(&self, api_key: &StoredApiKey) -> Result<(), DalError> {
+        api_keys::save_api_key(&self.core.persisence_manager, api_key)
+    }
+
+    // Sorted by tenant
+    pub fn list_api_keys(&self) -> Result<Vec<StoredApiKey>, DalError> {
+        api_keys::list_api_keys(&self.core.persisence_manager)
+    }
+
+    pub fn delete_api_key(&self, tenant: &str) -> Result<bool, DalError> {
+        api_keys::delete_api_key(&self.core.persisence_manager, tenant)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use adapters::BridgeAdapter;
+    use polypathroute_core::{ConfigError, CoreError};
 
     #[test]
     fn it_works() {
-        let dal_context = DalContext::new("./src/config/config.toml");
+        let dal_context = DalContext::new("./src/config/config.toml").unwrap();
 
         println!("{:?}", dal_context.core.config_manager.bridges.get("stargate").unwrap().pairs);
 
-        let stargate_adapter = dal_context.create_adapter("stargate");
-        dal_context.logger().info("Created Stargate Adapter!").unwrap();
-        // println!("fetch_metrics: {:?}", stargate_adapter.fetch_metrics("ethereum", "polygon", "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", "0x3c499c542cef5e3811e1192ce70d8cc03d5c3359", "1000000", "990000", "0xca699201b15ccef3b8c4012e28570cc5500d9f9a", "0xca699201b15ccef3b8c4012e28570cc5500d9f9a").unwrap());
-        // println!("fetch_metrics: {:?}", stargate_adapter.fetch_metrics("base", "arbitrum", "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913", "0xaf88d065e77c8cC2239327C5EDb3A432268e5831", "1000000", "990000", "0xca699201b15ccef3b8c4012e28570cc5500d9f9a", "0xca699201b15ccef3b8c4012e28570cc5500d9f9a").unwrap());
-        // println!("fetch_metrics: {:?}", stargate_adapter.fetch_metrics("base", "polygon", "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913", "0x3c499c542cEF5E3811e1192ce70d8cC03d5c3359", "1000000", "990000", "0xca699201b15ccef3b8c4012e28570cc5500d9f9a", "0xca699201b15ccef3b8c4012e28570cc5500d9f9a").unwrap());
-        
-        
+        let _stargate_adapter = dal_context.create_adapter("stargate").unwrap();
+        assert!(dal_context.create_adapter("routerprotocol").is_err());
+        dal_context.logger().info("Created Stargate Adapter!");
     }
-}
 
+    #[test]
+    fn missing_or_malformed_config_is_an_error() {
+        let err = DalContext::new("./src/config/does-not-exist.toml").unwrap_err();
+        assert!(matches!(&err, DalError::Core(CoreError::Config(ConfigError::Read { path, .. })) if path.ends_with("does-not-exist.toml")));
+
+        let config_path = std::env::temp_dir().join(format!("polypath-dal-malformed-{}.toml", std::process::id()));
+        std::fs::write(&config_path, "[global]\nupdate_interval = \"often\"\n").unwrap();
+        let result = DalContext::new(config_path.to_str().unwrap());
+        std::fs::remove_file(&config_path).unwrap();
+        assert!(matches!(result, Err(DalError::Core(CoreError::Config(ConfigError::Parse { key, .. }))) if key == "global.update_interval"));
+    }
+
+    #[test]
+    fn adapter_names_follow_config() {
+        let dal_context = DalContext::new("./src/config/config.toml").unwrap();
+        // routerprotocol and symbiosis are configured but have no adapter yet
+        assert_eq!(
+            dal_context.adapter_names(),
+            ["across", "celer", "hop", "lifi", "stargate", "synapse", "wormhole"]
+        );
+    }
+
+    #[test]
+    fn unknown_adapter_lists_known_ones() {
+        let dal_context = DalContext::new("./src/config/config.toml").unwrap();
+        match dal_context.create_adapter("routerprotocol") {
+            Err(DalError::UnknownAdapter { name, known }) => {
+                assert_eq!(name, "routerprotocol");
+                assert_eq!(known, dal_context.adapter_names());
+            }
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => panic!("routerprotocol has no adapter"),
+        }
+        assert!(matches!(dal_context.create_adapter("nope"), Err(DalError::UnknownAdapter { .. })));
+    }
+
+    #[test]
+    fn registered_adapters_are_built_for_configured_bridges_only() {
+        adapters::register("acme", |context| {
+            assert_eq!(context.config.chains, ["ethereum", "polygon"]);
+            Ok(Box::new(adapters::mock::MockAdapter::named("acme")))
+        });
+
+        let config_path = std::env::temp_dir().join(format!("polypath-dal-registry-{}.toml", std::process::id()));
+        std::fs::write(&config_path, r#"
+            [global]
+            update_interval = 60
+            cache_ttl = 1
+            log_level = "info"
+
+            [bridges.acme]
+            base_url = "https://acme.test"
+            chains = ["ethereum", "polygon"]
+
+            [bridges.stargate]
+            base_url = "https://stargate.test"
+            chains = ["ethereum", "polygon"]
+
+            [bridges.unregistered]
+            base_url = "https://unregistered.test"
+            chains = ["ethereum", "polygon"]
+        "#).unwrap();
+        let dal_context = DalContext::new(config_path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&config_path).unwrap();
+
+        assert_eq!(dal_context.create_adapter("acme").unwrap().name(), "acme");
+
+        let all = dal_context.create_all_adapters();
+        let mut names: Vec<&String> = all.keys().collect();
+        names.sort();
+        assert_eq!(names, ["acme", "stargate"]);
+        assert_eq!(all["stargate"].name(), "stargate");
+    }
+
+    #[test]
+    fn adapters_are_shared_and_reported() {
+        let dal_context = DalContext::new("./src/config/config.toml").unwrap();
+        assert_eq!(dal_context.metrics_report(), adapters::MetricsReport::default());
+
+        let stargate = dal_context.adapter("stargate").unwrap();
+        assert!(Arc::ptr_eq(&stargate, &dal_context.adapter("stargate").unwrap()));
+        assert!(matches!(dal_context.adapter("routerprotocol"), Err(DalError::UnknownAdapter { .. })));
+
+        stargate.telemetry().unwrap().record::<()>(Duration::from_millis(5), &Err(adapters::AdapterError::Network("reset".to_string())));
+        let report = dal_context.metrics_report();
+        assert_eq!(report.adapters.keys().collect::<Vec<_>>(), ["stargate"]);
+        assert_eq!((report.requests, report.errors), (1, 1));
+        assert_eq!(report.adapters["stargate"].errors_by_kind["network"], 1);
+    }
+
+    #[tokio::test]
+    async fn transfers_are_tracked_through_the_shared_adapter() {
+        let dal_context = DalContext::new("./src/config/config.toml").unwrap();
+        let reference = adapters::TransferReference::SourceTx("0x8f2c".to_string());
+
+        // Across quotes but can't say where a transfer is
+        let err = dal_context.track("across", &reference).await.unwrap_err();
+        assert!(matches!(err, DalError::Adapter(adapters::AdapterError::Unsupported { ref bridge, .. }) if bridge == "across"), "{}", err);
+        assert!(matches!(dal_context.track("routerprotocol", &reference).await, Err(DalError::UnknownAdapter { .. })));
+
+        let simulated = DalContext::new("./src/config/config.toml").unwrap().with_simulation(1);
+        assert_eq!(simulated.track("across", &reference).await.unwrap(), adapters::TransferStatus::SourceConfirmed);
+    }
+
+    #[test]
+    fn spenders_come_from_bridge_extras() {
+        let config_path = std::env::temp_dir().join(format!("polypath-dal-spenders-{}.toml", std::process::id()));
+        std::fs::write(&config_path, r#"
+            [global]
+            update_interval = 60
+
+            [bridges.stargate]
+            base_url = "https://stargate.test"
+            chains = ["ethereum", "polygon"]
+            extra = { spenders = { Ethereum = "0x8731d54E9D02c286767d56ac03e8037C07e01e98", matic = 7 } }
+        "#).unwrap();
+        let dal_context = DalContext::new(config_path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&config_path).unwrap();
+
+        let planner = dal_context.approval_planner().unwrap();
+        assert_eq!(planner.spender("stargate", "ethereum"), Some("0x8731d54E9D02c286767d56ac03e8037C07e01e98"));
+        assert_eq!(planner.spender("stargate", "polygon"), None);
+        assert_eq!(planner.spender("across", "ethereum"), None);
+    }
+
+    #[test]
+    fn supported_pairs_come_from_config() {
+        let dal_context = DalContext::new("./src/config/config.toml").unwrap();
+
+        // config.toml lists ethereum -> polygon USDC twice, it is reported once
+        let pairs = dal_context.supported_pairs_for("stargate");
+        assert_eq!(pairs.len(), 3);
+        assert!(dal_context.supported_pairs_for("unknown").is_empty());
+
+        let stargate_adapter: Arc<dyn adapters::BridgeAdapter + Send + Sync> = dal_context.create_adapter("stargate").unwrap().into();
+        assert!(stargate_adapter.is_supported_pair("base", "arbitrum",
+            "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913", "0xaf88d065e77c8cC2239327C5EDb3A432268e5831"));
+        assert!(!stargate_adapter.is_supported_pair("base", "ethereum",
+            "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913", "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"));
+    }
+
+    #[tokio::test]
+    async fn repeated_quotes_are_served_from_cache_until_ttl() {
+        use wiremock::{Mock, MockServer, ResponseTemplate, matchers::{method, path}};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/quotes"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_string(include_str!("../fixtures/stargate/quote.json")))
+            .mount(&server)
+            .await;
+
+        let config_path = std::env::temp_dir().join(format!("polypath-dal-cache-{}.toml", std::process::id()));
+        std::fs::write(&config_path, format!(r#"
+            [global]
+            update_interval = 60
+            cache_ttl = 1
+            log_level = "info"
 
+            [bridges.stargate]
+            base_url = "{}"
+            chains = ["ethereum", "polygon"]
+        "#, server.uri())).unwrap();
+
+        let dal_context = DalContext::new(config_path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&config_path).unwrap();
+        let stargate_adapter: Arc<dyn adapters::BridgeAdapter + Send + Sync> = dal_context.create_adapter("stargate").unwrap().into();
+        let request = adapters::QuoteRequest::builder()
+            .src_chain("ethereum")
+            .dst_chain("polygon")
+            .src_token("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48")
+            .dst_token("0x3c499c542cef5e3811e1192ce70d8cc03d5c3359")
+            .src_amount("1")
+            .wallet("0xca699201b15ccef3b8c4012e28570cc5500d9f9a")
+            .build()
+            .unwrap();
+
+        let first = dal_context.fetch_quote(&stargate_adapter, &request).await.unwrap();
+        let second = dal_context.fetch_quote(&stargate_adapter, &request).await.unwrap();
+        assert!(!first.from_cache);
+        assert!(second.from_cache);
+        assert_eq!(first.edge, second.edge);
+        assert_eq!(second.edge.bridge, "stargate");
+        assert_eq!(second.edge.estimated_output, 0.9994);
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        let third = dal_context.fetch_quote(&stargate_adapter, &request).await.unwrap();
+        assert!(!third.from_cache);
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn expired_quotes_are_refetched_within_the_cache_ttl() {
+        use wiremock::{Mock, MockServer, ResponseTemplate, matchers::{method, path}};
+
+        // Valid for one to two seconds from now, well inside the cache ttl
+        let expires_at = adapters::unix_now() + 2;
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/quotes"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(include_str!("../fixtures/stargate/quote.json")
+                .replace("\"steps\": []", &format!("\"expiresAt\": {}, \"steps\": []", expires_at))))
+            .mount(&server)
+            .await;
+
+        let config_path = std::env::temp_dir().join(format!("polypath-dal-validity-{}.toml", std::process::id()));
+        std::fs::write(&config_path, format!(r#"
+            [global]
+            update_interval = 60
+            cache_ttl = 60
+            log_level = "info"
+
+            [bridges.stargate]
+            base_url = "{}"
+            chains = ["ethereum", "polygon"]
+        "#, server.uri())).unwrap();
+        let dal_context = DalContext::new(config_path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&config_path).unwrap();
+        let stargate_adapter: Arc<dyn adapters::BridgeAdapter + Send + Sync> = dal_context.create_adapter("stargate").unwrap().into();
+        let request = adapters::QuoteRequest::builder()
+            .src_chain("ethereum")
+            .dst_chain("polygon")
+            .src_token("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48")
+            .dst_token("0x3c499c542cef5e3811e1192ce70d8cc03d5c3359")
+            .src_amount("1")
+            .wallet("0xca699201b15ccef3b8c4012e28570cc5500d9f9a")
+            .build()
+            .unwrap();
+
+        let first = dal_context.fetch_quote(&stargate_adapter, &request).await.unwrap();
+        assert_eq!(first.edge.valid_until, Some(expires_at));
+        assert!(dal_context.is_quote_fresh(&first.edge));
+        assert!(dal_context.fetch_quote(&stargate_adapter, &request).await.unwrap().from_cache);
+
+        tokio::time::sleep(Duration::from_millis(2100)).await;
+        assert!(!dal_context.is_quote_fresh(&first.edge));
+        let refetched = dal_context.fetch_quote(&stargate_adapter, &request).await.unwrap();
+        assert!(!refetched.from_cache);
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn health_check_all_covers_every_configured_bridge() {
+        use wiremock::{Mock, MockServer, ResponseTemplate, matchers::{method, path}};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/chains"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"chains":[]}"#))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/health"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+
+        let config_path = std::env::temp_dir().join(format!("polypath-dal-health-{}.toml", std::process::id()));
+        std::fs::write(&config_path, format!(r#"
+            [global]
+            update_interval = 60
+            cache_ttl = 1
+            log_level = "info"
+
+            [bridges.stargate]
+            base_url = "{0}"
+            chains = ["ethereum", "polygon"]
+
+            [bridges.wormhole]
+            base_url = "{0}"
+            chains = ["ethereum", "polygon"]
+
+            [bridges.routerprotocol]
+            base_url = "{0}"
+            chains = ["ethereum", "polygon"]
+        "#, server.uri())).unwrap();
+
+        let dal_context = DalContext::new(config_path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&config_path).unwrap();
+        let health = dal_context.health_check_all().await;
+
+        assert_eq!(health.len(), 3);
+        assert!(health["stargate"].as_ref().unwrap().reachable);
+        assert!(!health["wormhole"].as_ref().unwrap().reachable);
+        assert!(matches!(health["routerprotocol"], Err(adapters::AdapterError::Config(_))));
+    }
+
+    #[tokio::test]
+    async fn validate_config_flags_chains_the_bridge_does_not_list() {
+        use wiremock::{Mock, MockServer, ResponseTemplate, matchers::{method, path}};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/chains"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(include_str!("../fixtures/stargate/chains.json")))
+            .mount(&server)
+            .await;
+
+        let pair = |src: &str, dst: &str| format!(r#"
+            [[bridges.{{bridge}}.pairs]]
+            source_chain = "{}"
+            destination_chain = "{}"
+            source_token_name = "USDC"
+            source_address = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"
+            destination_address = "0x3c499c542cef5e3811e1192ce70d8cc03d5c3359"
+            destination_token_name = "USDC"
+        "#, src, dst);
+        let bridge = |name: &str| format!(
+            "[bridges.{0}]\nbase_url = \"{1}\"\nchains = [\"ethereum\", \"polygon\", \"made-up-chain\"]\n{2}{3}",
+            name,
+            server.uri(),
+            pair("ethereum", "polygon").replace("{bridge}", name),
+            pair("ethereum", "made-up-chain").replace("{bridge}", name),
+        );
+
+        let config_path = std::env::temp_dir().join(format!("polypath-dal-chains-{}.toml", std::process::id()));
+        std::fs::write(&config_path, format!(
+            "[global]\nupdate_interval = 60\ncache_ttl = 1\nlog_level = \"info\"\n{}{}",
+            bridge("stargate"),
+            bridge("across"),
+        )).unwrap();
+        let dal_context = DalContext::new(config_path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&config_path).unwrap();
+
+        // Across can't list its chains, so only Stargate is checked
+        assert_eq!(dal_context.validate_config().await, [
+            "bridges.stargate: chain `made-up-chain` in configured pairs is not supported by stargate",
+        ]);
+    }
+
+    fn usdc_edge(from: &str, to: &str, cost: f64) -> adapters::BridgeEdge {
+        adapters::BridgeEdge {
+            from: from.to_string(),
+            to: to.to_string(),
+            cost,
+            speed: 120.0,
+            liquidity: 1_000_000.0,
+            risk: 1.0,
+            via: None,
+            ..adapters::BridgeEdge::default()
+        }
+    }
+
+    fn usdc_quote(src_chain: &str, dst_chain: &str) -> adapters::QuoteRequest {
+        adapters::QuoteRequest::builder()
+            .src_chain(src_chain)
+            .dst_chain(dst_chain)
+            .src_token(format!("usdc-{}", src_chain))
+            .dst_token(format!("usdc-{}", dst_chain))
+            .src_amount("1")
+            .dst_amount_min("0.99")
+            .wallet("0xca699201b15ccef3b8c4012e28570cc5500d9f9a")
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn fetch_pairs_concurrently() {
+        let mock = adapters::mock::MockAdapter::new()
+            .with_quote("ethereum", "polygon", usdc_edge("ethereum", "polygon", 3.0))
+            .with_quote("base", "arbitrum", usdc_edge("base", "arbitrum", 1.0))
+            .with_failure("base", "polygon", adapters::AdapterError::RateLimited { retry_after: None })
+            .with_latency(Duration::from_millis(50));
+        let requests = [
+            usdc_quote("ethereum", "polygon"),
+            usdc_quote("base", "arbitrum"),
+            usdc_quote("base", "polygon"),
+        ];
+
+        let results = futures::future::join_all(
+            requests.iter().map(|request| mock.fetch_metrics(request))
+        ).await;
+
+        assert_eq!(results[0].as_ref().unwrap().cost, 3.0);
+        assert_eq!(results[1].as_ref().unwrap().cost, 1.0);
+        assert_eq!(results[1].as_ref().unwrap().bridge, "mock");
+        assert!(results[2].is_err());
+        assert_eq!(mock.call_count(), 3);
+        assert!(mock.requests().iter().all(|request| request.src_amount == "1"));
+    }
+
+    #[tokio::test]
+    async fn depth_probe_walks_the_ladder_at_the_adapter_rate() {
+        let mock = adapters::mock::MockAdapter::new()
+            .with_quote("ethereum", "polygon", adapters::BridgeEdge {
+                estimated_output: 0.999,
+                ..usdc_edge("ethereum", "polygon", 0.001)
+            })
+            .with_rate_limiter(adapters::RateLimiter::new(10.0, 1));
+        let dal_context = DalContext::new("./src/config/config.toml").unwrap();
+
+        let started = std::time::Instant::now();
+        let profile = dal_context
+            .probe_depth(&mock, &usdc_quote("ethereum", "polygon"), &DepthLadder::default())
+            .await
+            .unwrap();
+
+        let amounts: Vec<String> = mock.requests().into_iter().map(|request| request.src_amount).collect();
+        assert_eq!(amounts, ["1", "5", "25"]);
+        // burst 1 at 10 rps: the second and third rung each wait ~100ms
+        assert!(started.elapsed() >= Duration::from_millis(190));
+        // The mock quotes the same output whatever the amount, so only the first rung holds its rate
+        assert_eq!(profile.points.len(), 3);
+        assert_eq!(profile.max_amount, Some(1.0));
+    }
+
+    #[tokio::test]
+    async fn popular_quotes_are_refetched_before_they_expire() {
+        let config_path = std::env::temp_dir().join(format!("polypath-dal-soft-ttl-{}.toml", std::process::id()));
+        std::fs::write(&config_path, "[global]\ncache_ttl = 2\ncache_soft_ttl = \"1s\"\n[bridges]\n").unwrap();
+        let dal_context = DalContext::new(config_path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&config_path).unwrap();
+        let mock = Arc::new(adapters::mock::MockAdapter::new().with_quote("ethereum", "polygon", usdc_edge("ethereum", "polygon", 3.0)));
+        let adapter: Arc<dyn adapters::BridgeAdapter + Send + Sync> = mock.clone();
+        let request = usdc_quote("ethereum", "polygon");
+
+        assert!(!dal_context.fetch_quote(&adapter, &request).await.unwrap().from_cache);
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        // Still served from the cache while it is quoted again
+        assert!(dal_context.fetch_quote(&adapter, &request).await.unwrap().from_cache);
+        assert!(dal_context.fetch_quote(&adapter, &request).await.unwrap().from_cache);
+        for _ in 0..100 {
+            if mock.call_count() == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(mock.call_count(), 2);
+
+        // The refetched quote outlives the one it replaced
+        tokio::time::sleep(Duration::from_millis(1000)).await;
+        assert!(dal_context.fetch_quote(&adapter, &request).await.unwrap().from_cache);
+        assert_eq!(mock.call_count(), 2);
+    }
+
+    #[tokio::test]
+    async fn cached_fetches_and_routes_are_counted() {
+        use polypath_graph::EdgeMetrics;
+
+        let mock: Arc<dyn adapters::BridgeAdapter + Send + Sync> = Arc::new(adapters::mock::MockAdapter::new().with_quote("ethereum", "polygon", usdc_edge("ethereum", "polygon", 3.0)));
+        let dal_context = DalContext::new("./src/config/config.toml").unwrap();
+        let request = usdc_quote("ethereum", "polygon");
+        let first = dal_context.fetch_quote(&mock, &request).await.unwrap();
+        assert!(dal_context.fetch_quote(&mock, &request).await.unwrap().from_cache);
+
+        let graph = Graph::new(16);
+        let from = graph.get_or_create_asset_node("ethereum", "usdc-ethereum", "USDC");
+        let to = graph.get_or_create_asset_node("polygon", "usdc-polygon", "USDC");
+        let metrics = EdgeMetrics { cost: first.edge.cost, speed: first.edge.speed, liquidity: first.edge.liquidity, risk: first.edge.risk };
+        graph.add_edge(from, to, "mock", metrics, None, None).unwrap();
+        let engine = RoutingEngine::new(Arc::new(graph), 4);
+        assert!(dal_context.find_path(&engine, from, to, &RoutingParams::cheapest()).is_some());
+
+        let encoded = dal_context.metrics().encode_prometheus();
+        for line in [
+            "polypath_cache_hits_total 1",
+            "polypath_cache_misses_total 1",
+            "polypath_adapter_requests_total{adapter=\"mock\",outcome=\"ok\"} 1",
+            "polypath_routes_computed_total 1",
+            "polypath_graph_nodes 2",
+            "polypath_graph_edges_active 1",
+        ] {
+            assert!(encoded.lines().any(|encoded| encoded == line), "missing `{}` in\n{}", line, encoded);
+        }
+    }
+
+    // Quotes from the mock adapter become graph edges and the router picks the cheaper two-hop route
+    #[tokio::test]
+    async fn mock_quotes_drive_routing() {
+        use polypath_graph::{Graph, RoutingEngine, RoutingParams, EdgeMetrics};
+        use std::sync::Arc;
+
+        let mock = adapters::mock::MockAdapter::new()
+            .with_quote("ethereum", "polygon", usdc_edge("ethereum", "polygon", 10.0))
+            .with_quote("ethereum", "arbitrum", usdc_edge("ethereum", "arbitrum", 2.0))
+            .with_quote("arbitrum", "polygon", usdc_edge("arbitrum", "polygon", 2.0));
+
+        let graph = Graph::new(16);
+        for (src_chain, dst_chain) in [("ethereum", "polygon"), ("ethereum", "arbitrum"), ("arbitrum", "polygon")] {
+            let edge = mock.fetch_metrics(&usdc_quote(src_chain, dst_chain)).await.unwrap();
+            let from = graph.get_or_create_asset_node(&edge.from, &format!("usdc-{}", edge.from), "USDC");
+            let to = graph.get_or_create_asset_node(&edge.to, &format!("usdc-{}", edge.to), "USDC");
+            let metrics = EdgeMetrics { cost: edge.cost, speed: edge.speed, liquidity: edge.liquidity, risk: edge.risk };
+            graph.add_edge(from, to, &edge.label(&mock.name()), metrics, None, None).unwrap();
+        }
+
+        let start = graph.get_or_create_asset_node("ethereum", "usdc-ethereum", "USDC");
+        let end = graph.get_or_create_asset_node("polygon", "usdc-polygon", "USDC");
+        let path = RoutingEngine::new(Arc::new(graph), 4)
+            .find_path(start, end, &RoutingParams::cheapest())
+            .unwrap();
+
+        assert_eq!(path.hops.len(), 2);
+        assert_eq!(path.total_cost, 4.0);
+        assert!(path.hops.iter().all(|hop| &*hop.bridge_name == "mock"));
+        assert_eq!(mock.call_count(), 3);
+    }
+}
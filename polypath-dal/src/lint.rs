@@ -0,0 +1,415 @@
+// Catches the config mistakes that make quoting fail quietly, which loading the config doesn't:
+// pair addresses missing from the token registry, pairs on chains their bridge doesn't serve,
+// duplicate and self-referential pairs, and bridges without pairs or pairs without an adapter.
+// Nothing leaves the machine: what the bridges themselves serve is only looked at when they're
+// simulated or replayed from fixtures.
+
+use polypathroute_core::{BridgeConfig, Pair, Registry, checksum_address};
+use serde::Serialize;
+
+use crate::{DalContext, adapters::{FixtureMode, SupportedPair}};
+
+// Most edits between an unknown address and a registered one for it to be taken for a typo
+const ADDRESS_TYPO_DISTANCE: usize = 3;
+// The same between unknown and known chain names or token symbols
+const NAME_TYPO_DISTANCE: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LintKind {
+    // Not in the registry; an error when close to a chain that is
+    UnknownChain,
+    // Not in the registry; an error when close to a token that is
+    UnknownToken,
+    InvalidAddress,
+    // The address is registered under another symbol
+    SymbolMismatch,
+    // Not among the chains the bridge lists
+    UnsupportedChain,
+    // Not among the pairs the bridge's adapter knows
+    UnsupportedPair,
+    DuplicatePair,
+    SelfPair,
+    NoPairs,
+    // Pairs of a bridge without an adapter, which are never quoted
+    NoAdapter,
+    AdapterFailed,
+    ListingFailed,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LintFinding {
+    pub severity: Severity,
+    pub kind: LintKind,
+    // Config key the finding is about, e.g. bridges.stargate.pairs[2].source_address
+    pub key: String,
+    pub message: String,
+    // What was probably meant
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suggestion: Option<String>,
+}
+
+// Findings by bridge, in name order, then by pair
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LintReport {
+    pub findings: Vec<LintFinding>,
+}
+
+impl LintReport {
+    pub fn count(&self, severity: Severity) -> usize {
+        self.findings.iter().filter(|finding| finding.severity == severity).count()
+    }
+
+    // Warnings and infos don't fail a lint
+    pub fn has_errors(&self) -> bool {
+        self.count(Severity::Error) > 0
+    }
+
+    fn push(&mut self, severity: Severity, kind: LintKind, key: String, message: String, suggestion: Option<String>) {
+        self.findings.push(LintFinding { severity, kind, key, message, suggestion });
+    }
+}
+
+impl DalContext {
+    pub async fn lint_config(&self) -> LintReport {
+        let mut report = LintReport::default();
+        let config = self.config();
+        let with_adapter = self.adapter_names();
+        let mut bridges: Vec<(&String, &BridgeConfig)> = config.bridges.iter().collect();
+        bridges.sort_by_key(|(name, _)| *name);
+        for (bridge, bridge_config) in bridges {
+            let pairs = bridge_config.pairs.as_deref().unwrap_or_default();
+            let key = format!("bridges.{}", bridge);
+            match (pairs.is_empty(), bridge_config.auto_discover) {
+                (true, true) => report.push(Severity::Info, LintKind::NoPairs, key.clone(), "no pairs configured, only discovered ones are quoted".to_string(), None),
+                (true, false) => report.push(Severity::Warning, LintKind::NoPairs, key.clone(), "no pairs configured and auto_discover is off, so nothing is quoted".to_string(), None),
+                (false, _) if !with_adapter.contains(bridge) => {
+                    report.push(Severity::Warning, LintKind::NoAdapter, key.clone(), format!("there is no adapter for `{}`, so its pairs are never quoted", bridge), None);
+                }
+                (false, _) => {}
+            }
+
+            for (index, pair) in pairs.iter().enumerate() {
+                let key = format!("{}.pairs[{}]", key, index);
+                lint_pair(&mut report, self.registry(), &key, pair);
+                let route = SupportedPair::from(pair);
+                if let Some(first) = pairs[..index].iter().position(|other| SupportedPair::from(other).same_route(&route)) {
+                    report.push(Severity::Warning, LintKind::DuplicatePair, key, format!("same route as pairs[{}]", first), None);
+                }
+            }
+            if !pairs.is_empty() && with_adapter.contains(bridge) {
+                self.lint_capabilities(&mut report, bridge, pairs).await;
+            }
+        }
+        report
+    }
+
+    // Pairs against what the bridge's adapter knows it serves
+    async fn lint_capabilities(&self, report: &mut LintReport, bridge: &str, pairs: &[Pair]) {
+        let key = format!("bridges.{}", bridge);
+        let adapter = match self.adapter(bridge) {
+            Ok(adapter) => adapter,
+            Err(err) => return report.push(Severity::Warning, LintKind::AdapterFailed, key, err.to_string(), None),
+        };
+        let known = adapter.supported_pairs();
+        if !known.is_empty() {
+            for (index, pair) in pairs.iter().enumerate() {
+                let route = SupportedPair::from(pair);
+                if !known.iter().any(|other| serves(other, &route)) {
+                    let message = format!("{} does not list this pair among the {} it supports", bridge, known.len());
+                    report.push(Severity::Warning, LintKind::UnsupportedPair, format!("{}.pairs[{}]", key, index), message, None);
+                }
+            }
+        }
+
+        let offline = self.simulation.is_some() || matches!(self.fixtures, Some((FixtureMode::Replay, _)));
+        if !offline {
+            return;
+        }
+        let listed = match adapter.supported_chains().await {
+            Ok(listed) if listed.is_empty() => return,
+            Ok(listed) => listed,
+            Err(err) => return report.push(Severity::Info, LintKind::ListingFailed, key, format!("cannot list supported chains offline: {}", err), None),
+        };
+        for (index, pair) in pairs.iter().enumerate() {
+            for (field, chain) in [("source_chain", &pair.source_chain), ("destination_chain", &pair.destination_chain)] {
+                if !listed.iter().any(|info| info.key.eq_ignore_ascii_case(chain)) {
+                    let suggestion = closest(chain, listed.iter().map(|info| info.key.clone()), NAME_TYPO_DISTANCE);
+                    let message = format!("{} does not serve chain `{}`", bridge, chain);
+                    report.push(Severity::Error, LintKind::UnsupportedChain, format!("{}.pairs[{}].{}", key, index, field), message, suggestion);
+                }
+            }
+        }
+    }
+}
+
+// Whether `known` covers `route`; a known pair without tokens stands for every token between
+// its chains
+fn serves(known: &SupportedPair, route: &SupportedPair) -> bool {
+    let src_token = if known.src_token.is_empty() { "" } else { &route.src_token };
+    let dst_token = if known.dst_token.is_empty() { "" } else { &route.dst_token };
+    known.matches(&route.src_chain, &route.dst_chain, src_token, dst_token)
+}
+
+fn lint_pair(report: &mut LintReport, registry: &Registry, key: &str, pair: &Pair) {
+    let sides = [
+        ("source", &pair.source_chain, &pair.source_address, &pair.source_token_name),
+        ("destination", &pair.destination_chain, &pair.destination_address, &pair.destination_token_name),
+    ];
+    for (side, chain, address, symbol) in sides {
+        let chain_key = format!("{}.{}_chain", key, side);
+        let Ok(resolved) = registry.resolve_chain(chain) else {
+            let names = registry.chains.iter().flat_map(|known| std::iter::once(known.key.clone()).chain(known.aliases.iter().cloned()));
+            let suggestion = closest(chain, names, NAME_TYPO_DISTANCE);
+            let severity = if suggestion.is_some() { Severity::Error } else { Severity::Warning };
+            report.push(severity, LintKind::UnknownChain, chain_key, format!("chain `{}` is not in the registry", chain), suggestion);
+            continue;
+        };
+        lint_token(report, registry, &resolved.key, &format!("{}.{}_address", key, side), address, symbol);
+    }
+
+    let same_chain = match (registry.resolve_chain(&pair.source_chain), registry.resolve_chain(&pair.destination_chain)) {
+        (Ok(source), Ok(destination)) => source.key == destination.key,
+        _ => pair.source_chain.eq_ignore_ascii_case(&pair.destination_chain),
+    };
+    if same_chain && pair.source_address.trim().eq_ignore_ascii_case(pair.destination_address.trim()) {
+        report.push(Severity::Error, LintKind::SelfPair, key.to_string(), "source and destination are the same token on the same chain".to_string(), None);
+    }
+}
+
+// `address` on the chain with canonical key `chain`, configured as `symbol`
+fn lint_token(report: &mut LintReport, registry: &Registry, chain: &str, key: &str, address: &str, symbol: &str) {
+    let on_chain: Vec<_> = registry.tokens.iter().filter(|token| token.chain == chain).collect();
+    let near_address = || {
+        on_chain
+            .iter()
+            .map(|token| (edit_distance(&address.trim().to_lowercase(), &token.address.to_lowercase()), *token))
+            .filter(|(distance, _)| *distance <= ADDRESS_TYPO_DISTANCE)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, token)| format!("{} ({})", token.address, token.symbol))
+    };
+
+    let evm = address.trim().starts_with("0x") || address.trim().starts_with("0X");
+    if evm && let Err(err) = checksum_address(address.trim()) {
+        return report.push(Severity::Error, LintKind::InvalidAddress, key.to_string(), err.to_string(), near_address());
+    }
+    match registry.tokens.find(chain, address).as_slice() {
+        [] => {
+            // A registered address a few characters off, else the registered token with the
+            // configured symbol
+            let suggestion = near_address().or_else(|| {
+                let symbols = on_chain.iter().map(|token| token.symbol.clone());
+                let symbol = closest(symbol, symbols, NAME_TYPO_DISTANCE)?;
+                match on_chain.iter().filter(|token| token.symbol == symbol).collect::<Vec<_>>().as_slice() {
+                    [token] => Some(format!("{} ({})", token.address, token.symbol)),
+                    _ => None,
+                }
+            });
+            let severity = if suggestion.is_some() { Severity::Error } else { Severity::Warning };
+            report.push(severity, LintKind::UnknownToken, key.to_string(), format!("no token at `{}` on {} in the registry", address, chain), suggestion);
+        }
+        [token, ..] if !token.symbol.eq_ignore_ascii_case(symbol.trim()) => {
+            let message = format!("configured as `{}` but registered as `{}`", symbol, token.symbol);
+            report.push(Severity::Warning, LintKind::SymbolMismatch, key.to_string(), message, Some(token.symbol.clone()));
+        }
+        _ => {}
+    }
+}
+
+// The candidate fewest edits from `name`, case aside, when within `max` of it and not half of it
+// rewritten
+fn closest(name: &str, candidates: impl IntoIterator<Item = String>, max: usize) -> Option<String> {
+    let name = name.trim().to_lowercase();
+    let limit = max.min(name.chars().count() / 2);
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(&name, &candidate.to_lowercase()), candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+// Levenshtein distance over chars
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::{self, mock::MockAdapter};
+    use polypathroute_core::RefreshPriority;
+
+    const USDC_ETHEREUM: &str = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+    const USDC_POLYGON: &str = "0x3c499c542cef5e3811e1192ce70d8cc03d5c3359";
+    const USDCE_POLYGON: &str = "0x2791bca1f2de4661ed88a30c99a7a9449aa84174";
+    const USDT_POLYGON: &str = "0xc2132d05d31c914a87c6611c10748aeb04b58e8f";
+    const USDC_SCROLL: &str = "0x06efdbff2a14a7c8e15944d1f4a48f9f95f663a4";
+
+    fn pair(bridge: &str, (src_chain, src, src_name): (&str, &str, &str), (dst_chain, dst, dst_name): (&str, &str, &str)) -> String {
+        format!(
+            "[[bridges.{}.pairs]]\nsource_chain = \"{}\"\nsource_address = \"{}\"\nsource_token_name = \"{}\"\ndestination_chain = \"{}\"\ndestination_address = \"{}\"\ndestination_token_name = \"{}\"\n",
+            bridge, src_chain, src, src_name, dst_chain, dst, dst_name,
+        )
+    }
+
+    fn context(name: &str, config: &str) -> DalContext {
+        let config_path = std::env::temp_dir().join(format!("polypath-dal-lint-{}-{}.toml", name, std::process::id()));
+        std::fs::write(&config_path, format!("[global]\nupdate_interval = 60\ncache_ttl = 1\nlog_level = \"info\"\n{}", config)).unwrap();
+        let dal = DalContext::new(config_path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&config_path).unwrap();
+        dal
+    }
+
+    fn findings(report: &LintReport) -> Vec<(Severity, LintKind, &str)> {
+        report.findings.iter().map(|finding| (finding.severity, finding.kind, finding.key.as_str())).collect()
+    }
+
+    #[tokio::test]
+    async fn pairs_are_checked_against_the_registry_and_each_other() {
+        adapters::register("linted", |_| Ok(Box::new(MockAdapter::named("linted"))));
+        let usdc_ethereum = ("ethereum", USDC_ETHEREUM, "USDC");
+        let usdc_polygon = ("polygon", USDC_POLYGON, "USDC");
+        // The bridge's chains are checked on load, so its misspelt chain is among them
+        let mut config = "[bridges.linted]\nbase_url = \"https://linted.test\"\nchains = [\"ethereum\", \"polygon\", \"etherum\"]\n".to_string();
+        for (source, destination) in [
+            (usdc_ethereum, usdc_polygon),
+            (("ethereum", &USDC_ETHEREUM.to_uppercase().replace("0X", "0x"), "USDC"), usdc_polygon),
+            // The last two characters swapped
+            (("ethereum", "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb84", "USDC"), usdc_polygon),
+            (usdc_polygon, usdc_polygon),
+            (("etherum", USDC_ETHEREUM, "USDC"), usdc_polygon),
+            // Mixed case with a lowercase b where the checksum has a capital
+            (("ethereum", "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eb48", "USDC"), ("polygon", USDT_POLYGON, "USDT")),
+            (usdc_ethereum, ("polygon", USDCE_POLYGON, "USDC")),
+            (("ethereum", "0x1111111111111111111111111111111111111111", "FOO"), usdc_polygon),
+            (("ethereum", "0x2222222222222222222222222222222222222222", "USDT"), usdc_polygon),
+        ] {
+            config.push_str(&pair("linted", source, destination));
+        }
+        config.push_str("[bridges.idle]\nbase_url = \"https://idle.test\"\nchains = [\"ethereum\"]\n");
+        config.push_str("[bridges.discovered]\nbase_url = \"https://discovered.test\"\nchains = [\"ethereum\"]\nauto_discover = true\n");
+        config.push_str("[bridges.routerprotocol]\nbase_url = \"https://router.test\"\nchains = [\"ethereum\", \"polygon\"]\n");
+        config.push_str(&pair("routerprotocol", usdc_ethereum, usdc_polygon));
+
+        let report = context("registry", &config).lint_config().await;
+        assert_eq!(findings(&report), [
+            (Severity::Info, LintKind::NoPairs, "bridges.discovered"),
+            (Severity::Warning, LintKind::NoPairs, "bridges.idle"),
+            (Severity::Warning, LintKind::DuplicatePair, "bridges.linted.pairs[1]"),
+            (Severity::Error, LintKind::UnknownToken, "bridges.linted.pairs[2].source_address"),
+            (Severity::Error, LintKind::SelfPair, "bridges.linted.pairs[3]"),
+            (Severity::Error, LintKind::UnknownChain, "bridges.linted.pairs[4].source_chain"),
+            (Severity::Error, LintKind::InvalidAddress, "bridges.linted.pairs[5].source_address"),
+            (Severity::Warning, LintKind::SymbolMismatch, "bridges.linted.pairs[6].destination_address"),
+            (Severity::Warning, LintKind::UnknownToken, "bridges.linted.pairs[7].source_address"),
+            (Severity::Error, LintKind::UnknownToken, "bridges.linted.pairs[8].source_address"),
+            (Severity::Warning, LintKind::NoAdapter, "bridges.routerprotocol"),
+        ]);
+        let suggestions: Vec<Option<&str>> = report.findings.iter().map(|finding| finding.suggestion.as_deref()).collect();
+        assert_eq!(suggestions[3], Some("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48 (USDC)"));
+        assert_eq!(suggestions[5], Some("ethereum"));
+        assert_eq!(suggestions[6], Some("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48 (USDC)"));
+        assert_eq!(suggestions[7], Some("USDC.e"));
+        assert_eq!(suggestions[8], None);
+        assert_eq!(suggestions[9], Some("0xdAC17F958D2ee523a2206206994597C13D831ec7 (USDT)"));
+        assert!(report.has_errors());
+        assert_eq!((report.count(Severity::Error), report.count(Severity::Warning), report.count(Severity::Info)), (5, 5, 1));
+
+        assert_eq!(serde_json::to_value(&report.findings[5]).unwrap(), serde_json::json!({
+            "severity": "error",
+            "kind": "unknown_chain",
+            "key": "bridges.linted.pairs[4].source_chain",
+            "message": "chain `etherum` is not in the registry",
+            "suggestion": "ethereum",
+        }));
+    }
+
+    #[tokio::test]
+    async fn pairs_are_checked_against_what_the_bridge_serves_offline() {
+        let advertised = SupportedPair {
+            src_chain: "ethereum".to_string(),
+            dst_chain: "polygon".to_string(),
+            src_token: USDC_ETHEREUM.to_string(),
+            dst_token: USDC_POLYGON.to_string(),
+            min_amount: None,
+            max_amount: None,
+            token_symbol: None,
+            priority: RefreshPriority::default(),
+            refresh_interval: None,
+        };
+        adapters::register("advertised", move |_| Ok(Box::new(MockAdapter::named("advertised").with_advertised_pairs(vec![advertised.clone()]))));
+        let usdc_ethereum = ("ethereum", USDC_ETHEREUM, "USDC");
+        let config = [
+            "[[registry.tokens]]\nchain = \"scroll\"\naddress = \"0x06efdbff2a14a7c8e15944d1f4a48f9f95f663a4\"\nsymbol = \"USDC\"\ndecimals = 6\n".to_string(),
+            "[bridges.advertised]\nbase_url = \"https://advertised.test\"\nchains = [\"ethereum\", \"polygon\"]\n".to_string(),
+            pair("advertised", usdc_ethereum, ("polygon", USDC_POLYGON, "USDC")),
+            pair("advertised", usdc_ethereum, ("polygon", USDCE_POLYGON, "USDC.e")),
+            "[bridges.wormhole]\nbase_url = \"https://wormhole.test\"\nchains = [\"ethereum\", \"polygon\", \"scroll\"]\n".to_string(),
+            pair("wormhole", usdc_ethereum, ("polygon", USDC_POLYGON, "USDC")),
+            pair("wormhole", usdc_ethereum, ("scroll", USDC_SCROLL, "USDC")),
+        ]
+        .concat();
+
+        // Listing Wormhole's chains could mean asking its API, so only the pairs adapters know
+        // of are checked. Wormhole only keeps configured pairs it can translate.
+        let online = context("online", &config).lint_config().await;
+        assert_eq!(findings(&online), [
+            (Severity::Warning, LintKind::UnsupportedPair, "bridges.advertised.pairs[1]"),
+            (Severity::Warning, LintKind::UnsupportedPair, "bridges.wormhole.pairs[1]"),
+        ]);
+        assert!(!online.has_errors());
+
+        let fixtures = std::env::temp_dir().join(format!("polypath-dal-lint-fixtures-{}", std::process::id()));
+        let offline = context("offline", &config).with_fixtures(FixtureMode::Replay, &fixtures).lint_config().await;
+        assert_eq!(findings(&offline), [
+            (Severity::Warning, LintKind::UnsupportedPair, "bridges.advertised.pairs[1]"),
+            (Severity::Warning, LintKind::UnsupportedPair, "bridges.wormhole.pairs[1]"),
+            (Severity::Error, LintKind::UnsupportedChain, "bridges.wormhole.pairs[1].destination_chain"),
+        ]);
+        assert_eq!(offline.findings[2].message, "wormhole does not serve chain `scroll`");
+    }
+
+    #[test]
+    fn typos_are_told_from_other_names() {
+        assert_eq!(edit_distance("ethereum", "etherum"), 1);
+        assert_eq!(edit_distance("polygon", "polgyon"), 2);
+        assert_eq!(edit_distance("", "base"), 4);
+        let chains = || ["ethereum", "polygon", "base"].map(String::from);
+        assert_eq!(closest("Polgyon", chains(), NAME_TYPO_DISTANCE).as_deref(), Some("polygon"));
+        // Swapped letters are two edits
+        assert_eq!(closest("bsae", chains(), NAME_TYPO_DISTANCE).as_deref(), Some("base"));
+        // A prefix is too far off
+        assert_eq!(closest("pol", chains(), NAME_TYPO_DISTANCE), None);
+        assert_eq!(closest("basee", chains(), NAME_TYPO_DISTANCE).as_deref(), Some("base"));
+        assert_eq!(closest("fantom", chains(), NAME_TYPO_DISTANCE), None);
+    }
+}
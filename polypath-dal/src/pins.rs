@@ -0,0 +1,103 @@
+// Routes pinned for route stickiness (see Router::with_stickiness), kept in the persistence
+// store so a repeat transfer keeps to its route across restarts
+
+use polypath_graph::{PinStore, PinnedRoute};
+use polypathroute_core::{LoggingManager, PersistenceManager, Versioned};
+use serde::{Deserialize, Serialize};
+
+const PIN_PREFIX: &str = "pins/";
+
+#[derive(Serialize, Deserialize)]
+#[serde(transparent)]
+struct StoredPin(PinnedRoute);
+
+impl Versioned for StoredPin {
+    const SCHEMA: &'static str = "pinned_route";
+    const VERSION: u32 = 1;
+}
+
+// One graph's pins, by intent hash. Node ids are the graph's, so each graph keeps its own.
+#[derive(Debug, Clone)]
+pub struct PersistedPins {
+    persistence: PersistenceManager,
+    graph: String,
+    logger: LoggingManager,
+}
+
+impl PersistedPins {
+    pub fn new(persistence: PersistenceManager, graph: &str, logger: LoggingManager) -> Self {
+        Self { persistence, graph: graph.to_string(), logger }
+    }
+
+    fn key(&self, intent_hash: &str) -> String {
+        format!("{}{}/{}", PIN_PREFIX, self.graph, intent_hash)
+    }
+}
+
+// A pin that can't be read or written is logged and the query goes on without it
+impl PinStore for PersistedPins {
+    fn load(&self, intent_hash: &str) -> Option<PinnedRoute> {
+        match self.persistence.get_typed::<StoredPin>(&self.key(intent_hash)) {
+            Ok(pin) => pin.map(|StoredPin(route)| route),
+            Err(err) => {
+                self.logger.warn_with("pinned route unreadable, ignoring it", &[("graph", &self.graph), ("intent_hash", &intent_hash), ("error", &err)]);
+                None
+            }
+        }
+    }
+
+    fn save(&self, intent_hash: &str, route: &PinnedRoute) {
+        if let Err(err) = self.persistence.put_typed(&self.key(intent_hash), &StoredPin(route.clone())) {
+            self.logger.warn_with("pinned route not saved", &[("graph", &self.graph), ("intent_hash", &intent_hash), ("error", &err)]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polypath_graph::{EdgeMetrics, Graph, RouteIntent, RouteOptions, Router, RoutingParams};
+    use std::sync::Arc;
+
+    #[test]
+    fn pins_outlive_the_router_that_confirmed_them() {
+        let graph = Graph::new(4);
+        let eth = graph.get_or_create_asset_node("ethereum", "0xa0b8", "USDC");
+        let pol = graph.get_or_create_asset_node("polygon", "0x3c49", "USDC");
+        let metrics = |cost: f64| EdgeMetrics { cost, speed: 60.0, liquidity: 1_000_000.0, risk: 0.1 };
+        graph.add_edge(eth, pol, "stargate", metrics(2.0), None, None).unwrap();
+        graph.add_edge(eth, pol, "across", metrics(3.0), None, None).unwrap();
+        let graph = Arc::new(graph);
+        let intent = RouteIntent {
+            from_chain: "ethereum".to_string(),
+            from_token: "USDC".to_string(),
+            to_chain: "polygon".to_string(),
+            to_token: "USDC".to_string(),
+            amount: 100.0,
+            preference: None,
+            src_address: None,
+        };
+        let opts = RouteOptions { routing_params: Some(RoutingParams::cheapest()), ..RouteOptions::default() };
+        let persistence = PersistenceManager::new();
+        let router = |graph_name: &str| {
+            let pins = PersistedPins::new(persistence.clone(), graph_name, LoggingManager);
+            Router::new(Arc::clone(&graph)).with_stickiness(Arc::new(pins), 2.0)
+        };
+
+        let confirmed = router("default").best_routes(&intent, &opts).unwrap().remove(0);
+        router("default").confirm_selection(&intent.intent_hash(), &confirmed.ranked);
+        let key = format!("pins/default/{}", intent.intent_hash());
+        assert!(persistence.get(key.clone()).unwrap().unwrap().contains("\"schema\":\"pinned_route\""));
+
+        graph.update_edge_metrics(eth, pol, "across", metrics(1.0)).unwrap();
+        let pinned = router("default").best_routes(&intent, &opts).unwrap();
+        assert_eq!(&*pinned[0].ranked.path.hops[0].bridge_name, "stargate");
+        assert!(pinned[0].pinned.is_some());
+        // Another graph's pins are its own
+        assert_eq!(&*router("fast").best_routes(&intent, &opts).unwrap()[0].ranked.path.hops[0].bridge_name, "across");
+
+        // An unreadable pin is skipped
+        persistence.store(key, "{\"schema\":\"pinned_route\",\"version\":9,\"payload\":{}}".to_string()).unwrap();
+        assert_eq!(&*router("default").best_routes(&intent, &opts).unwrap()[0].ranked.path.hops[0].bridge_name, "across");
+    }
+}
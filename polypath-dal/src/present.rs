@@ -0,0 +1,429 @@
+// Display-ready strings for ranked routes, for the CLI's tables and API clients that don't want
+// to format raw metrics themselves. Amounts are in human token units, and so are costs unless
+// the options name the quote currency the GraphUpdater put them in, which it only does when
+// [fx] is configured.
+
+use polypath_graph::{Graph, Hop, NodeId, NodeType, Path, RankedPath};
+use polypathroute_core::Registry;
+use serde::Serialize;
+
+use crate::DalContext;
+
+#[derive(Debug, Clone)]
+pub struct PresentOptions<'a> {
+    // What route costs are in, e.g. "USD"; None when each hop's cost is in its source token
+    pub quote_currency: Option<String>,
+    pub fee_decimals: usize,
+    // Most decimals an amount is shown with; fewer when its token has fewer
+    pub amount_decimals: usize,
+    // Between groups of three digits; none when None
+    pub thousands_separator: Option<char>,
+    pub decimal_separator: char,
+    // Where hops' chains and tokens are looked up; hops show node ids without it
+    graph: Option<&'a Graph>,
+    // Token symbols and decimals by chain and address
+    registry: Option<&'a Registry>,
+}
+
+impl Default for PresentOptions<'_> {
+    fn default() -> Self {
+        Self {
+            quote_currency: Some("USD".to_string()),
+            fee_decimals: 2,
+            amount_decimals: 4,
+            thousands_separator: Some(','),
+            decimal_separator: '.',
+            graph: None,
+            registry: None,
+        }
+    }
+}
+
+impl<'a> PresentOptions<'a> {
+    pub fn with_graph(mut self, graph: &'a Graph) -> Self {
+        self.graph = Some(graph);
+        self
+    }
+
+    pub fn with_registry(mut self, registry: &'a Registry) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    pub fn with_quote_currency(mut self, currency: &str) -> Self {
+        self.quote_currency = Some(currency.to_string());
+        self
+    }
+
+    // Costs shown in the token each hop is sent in, as they are without [fx]
+    pub fn in_source_tokens(mut self) -> Self {
+        self.quote_currency = None;
+        self
+    }
+
+    pub fn with_fee_decimals(mut self, decimals: usize) -> Self {
+        self.fee_decimals = decimals;
+        self
+    }
+
+    pub fn with_amount_decimals(mut self, decimals: usize) -> Self {
+        self.amount_decimals = decimals;
+        self
+    }
+
+    // e.g. (Some('.'), ',') for "1.234,56"
+    pub fn with_separators(mut self, thousands: Option<char>, decimal: char) -> Self {
+        self.thousands_separator = thousands;
+        self.decimal_separator = decimal;
+        self
+    }
+
+    // `value` with `decimals` places and the separators
+    fn number(&self, value: f64, decimals: usize) -> String {
+        let fixed = format!("{:.*}", decimals, value.abs());
+        let (whole, fraction) = fixed.split_once('.').unwrap_or((&fixed, ""));
+        let mut grouped = String::new();
+        for (index, digit) in whole.chars().enumerate() {
+            if index > 0 && (whole.len() - index) % 3 == 0 {
+                grouped.extend(self.thousands_separator);
+            }
+            grouped.push(digit);
+        }
+        let sign = if value < 0.0 && fixed.chars().any(|digit| digit.is_ascii_digit() && digit != '0') { "-" } else { "" };
+        match fraction {
+            "" => format!("{}{}", sign, grouped),
+            fraction => format!("{}{}{}{}", sign, grouped, self.decimal_separator, fraction),
+        }
+    }
+
+    // "$1.84", "1.84 CHF"; "<$0.01" for fees too small to show and "$0.00" for none. Without a
+    // quote currency, the amount in the token at `node`.
+    fn money(&self, amount: f64, node: NodeId) -> String {
+        let Some(currency) = &self.quote_currency else {
+            return self.amount(amount.max(0.0), node);
+        };
+        let smallest = 10f64.powi(-(self.fee_decimals as i32));
+        let (prefix, amount) = match amount > 0.0 && amount < smallest {
+            true => ("<", smallest),
+            false => ("", amount.max(0.0)),
+        };
+        match currency_symbol(currency) {
+            Some(symbol) => format!("{}{}{}", prefix, symbol, self.number(amount, self.fee_decimals)),
+            None => format!("{}{} {}", prefix, self.number(amount, self.fee_decimals), currency),
+        }
+    }
+
+    // The path's total cost; without a quote currency, summed per token and shown as
+    // "0.31 USDC + 1.53 USDT" when its hops are sent in several
+    fn total_cost(&self, path: &Path) -> String {
+        let Some(first) = path.hops.first() else {
+            return self.money(path.total_cost, NodeId(0));
+        };
+        if self.quote_currency.is_some() {
+            return self.money(path.total_cost, first.from);
+        }
+        let mut by_token: Vec<(Option<String>, NodeId, f64)> = Vec::new();
+        for hop in &path.hops {
+            let symbol = self.asset(hop.from).symbol;
+            match by_token.iter_mut().find(|(seen, _, _)| *seen == symbol) {
+                Some((_, _, cost)) => *cost += hop.metrics.cost,
+                None => by_token.push((symbol, hop.from, hop.metrics.cost)),
+            }
+        }
+        let costs: Vec<String> = by_token
+            .iter()
+            .filter(|(_, _, cost)| *cost > 0.0)
+            .map(|(_, node, cost)| self.money(*cost, *node))
+            .collect();
+        match costs.is_empty() {
+            true => self.money(0.0, first.from),
+            false => costs.join(" + "),
+        }
+    }
+
+    // Human units of the token at `node`, with no more decimals than it has and no trailing zeros
+    fn amount(&self, amount: f64, node: NodeId) -> String {
+        let asset = self.asset(node);
+        let decimals = asset.decimals.map_or(self.amount_decimals, |decimals| self.amount_decimals.min(decimals as usize));
+        let mut shown = self.number(amount, decimals);
+        if shown.contains(self.decimal_separator) {
+            shown.truncate(shown.trim_end_matches('0').trim_end_matches(self.decimal_separator).len());
+        }
+        match asset.symbol {
+            Some(symbol) => format!("{} {}", shown, symbol),
+            None => shown,
+        }
+    }
+
+    fn asset(&self, node: NodeId) -> Asset {
+        let found = self.graph.and_then(|graph| graph.get_node(node));
+        match found.as_ref().map(|node| &node.node_type) {
+            Some(NodeType::Asset { chain, token_address, token_symbol }) => {
+                let token = self.registry.and_then(|registry| registry.resolve_token(chain, token_address).ok());
+                let symbol = match token_symbol.is_empty() {
+                    true => token.as_ref().map(|token| token.symbol.clone()),
+                    false => Some(token_symbol.clone()),
+                };
+                Asset { chain: title(chain), symbol, decimals: token.map(|token| token.decimals) }
+            }
+            Some(NodeType::Exchange { name, chain }) => Asset { chain: format!("{} on {}", title(name), title(chain)), symbol: None, decimals: None },
+            None => Asset { chain: format!("{:x}", node.0), symbol: None, decimals: None },
+        }
+    }
+}
+
+// What a hop's end is shown as
+struct Asset {
+    chain: String,
+    symbol: Option<String>,
+    decimals: Option<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PresentedRoute {
+    pub rank: usize,
+    // e.g. "Base USDC → Arbitrum USDC"
+    pub summary: String,
+    pub duration: String,
+    pub fee: String,
+    // What arrives at the destination, when the route was searched for an amount
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    pub hops: Vec<PresentedHop>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PresentedHop {
+    pub bridge: String,
+    pub from: String,
+    pub to: String,
+    pub duration: String,
+    pub fee: String,
+    // e.g. "Stargate: Base → Arbitrum, ~45s, $0.31 fee"
+    pub line: String,
+}
+
+pub fn humanize(ranked: &RankedPath, opts: &PresentOptions) -> PresentedRoute {
+    let path = &ranked.path;
+    let hops: Vec<PresentedHop> = path.hops.iter().map(|hop| present_hop(hop, opts)).collect();
+    let summary = match (path.hops.first(), path.hops.last()) {
+        (Some(first), Some(last)) => format!("{} → {}", end(&opts.asset(first.from)), end(&opts.asset(last.to))),
+        _ => String::new(),
+    };
+    PresentedRoute {
+        rank: ranked.rank,
+        summary,
+        duration: duration(path.total_time),
+        fee: opts.total_cost(path),
+        output: path.estimated_output.zip(path.hops.last()).map(|(output, last)| opts.amount(output, last.to)),
+        hops,
+    }
+}
+
+fn present_hop(hop: &Hop, opts: &PresentOptions) -> PresentedHop {
+    let bridge = bridge_name(&hop.bridge_name);
+    let (from, to) = (opts.asset(hop.from), opts.asset(hop.to));
+    // Swaps stay on one chain, so they're told apart by their tokens
+    let (from, to) = match from.chain == to.chain {
+        true => (end(&from), to.symbol.clone().unwrap_or(to.chain)),
+        false => (from.chain, to.chain),
+    };
+    let duration = duration(hop.metrics.speed);
+    let fee = opts.money(hop.metrics.cost, hop.from);
+    let charge = match hop.metrics.cost > 0.0 {
+        true => format!("{} fee", fee),
+        false => "no fee".to_string(),
+    };
+    PresentedHop { line: format!("{}: {} → {}, {}, {}", bridge, from, to, duration, charge), bridge, from, to, duration, fee }
+}
+
+// "Base USDC", or the chain alone without a symbol
+fn end(asset: &Asset) -> String {
+    match &asset.symbol {
+        Some(symbol) => format!("{} {}", asset.chain, symbol),
+        None => asset.chain.clone(),
+    }
+}
+
+// "~12 min", "~45s", "<1s", "~2 h 5 min"
+pub fn duration(secs: f64) -> String {
+    if !secs.is_finite() || secs < 0.0 {
+        return "unknown".to_string();
+    }
+    if secs < 1.0 {
+        return "<1s".to_string();
+    }
+    let secs = secs.round() as u64;
+    if secs < 60 {
+        return format!("~{}s", secs);
+    }
+    let mins = (secs + 30) / 60;
+    match (mins / 60, mins % 60) {
+        (0, mins) => format!("~{} min", mins),
+        (hours, 0) => format!("~{} h", hours),
+        (hours, mins) => format!("~{} h {} min", hours, mins),
+    }
+}
+
+// "Stargate", or "Stargate via Lifi" for an edge an aggregator quoted, labelled "lifi:stargate"
+fn bridge_name(label: &str) -> String {
+    match label.split_once(':') {
+        Some((aggregator, via)) => format!("{} via {}", title(via), title(aggregator)),
+        None => title(label),
+    }
+}
+
+fn title(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn currency_symbol(code: &str) -> Option<&'static str> {
+    match code.to_uppercase().as_str() {
+        "USD" => Some("$"),
+        "EUR" => Some("€"),
+        "GBP" => Some("£"),
+        "JPY" => Some("¥"),
+        _ => None,
+    }
+}
+
+impl DalContext {
+    // Options presenting routes over `graph` with the registry's tokens, costs in the [fx] quote
+    // currency when costs are normalized into one and in the hops' tokens when they aren't
+    pub fn present_options<'a>(&'a self, graph: &'a Graph) -> PresentOptions<'a> {
+        let fx = &self.config().fx;
+        let opts = PresentOptions::default().with_graph(graph).with_registry(self.registry());
+        match fx.enabled() {
+            true => opts.with_quote_currency(&fx.quote_currency),
+            false => opts.in_source_tokens(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polypath_graph::{EdgeKind, EdgeMetrics, ScoreBreakDown};
+
+    const USDC_BASE: &str = "0x833589fcd6edb6e08f4c7c32d4f71b54bda02913";
+    const USDC_ARBITRUM: &str = "0xaf88d065e77c8cc2239327c5edb3a432268e5831";
+    const USDT_ARBITRUM: &str = "0xfd086bc7cd5c481dcc9c85ebe478a1c0b69fcbb9";
+
+    fn hop(from: NodeId, to: NodeId, bridge: &str, cost: f64, speed: f64) -> Hop {
+        Hop {
+            from,
+            to,
+            bridge_name: bridge.into(),
+            kind: EdgeKind::Bridge,
+            metrics: EdgeMetrics { cost, speed, liquidity: 1_000_000.0, risk: 0.1 },
+            quote: None,
+            slippage_pct: None,
+            alternatives: None,
+            confidence: 1.0,
+        }
+    }
+
+    fn ranked(hops: Vec<Hop>, estimated_output: Option<f64>) -> RankedPath {
+        RankedPath {
+            path: Path {
+                total_cost: hops.iter().map(|hop| hop.metrics.cost).sum(),
+                total_time: hops.iter().map(|hop| hop.metrics.speed).sum(),
+                total_risk: 0.2,
+                min_liquidity: 1_000_000.0,
+                aggregate_score: 0.0,
+                estimated_output,
+                graph_version: 0,
+                confidence: 1.0,
+                hops,
+            },
+            rank: 1,
+            score_breakdown: ScoreBreakDown { cost_score: 0.0, speed_score: 0.0, liquidity_score: 0.0, risk_score: 0.0, final_score: 0.9, estimated_output },
+            affordability: None,
+        }
+    }
+
+    #[test]
+    fn routes_are_presented_in_words() {
+        let graph = Graph::new(4);
+        let base = graph.get_or_create_asset_node("base", USDC_BASE, "USDC");
+        let arb = graph.get_or_create_asset_node("arbitrum", USDC_ARBITRUM, "");
+        let usdt = graph.get_or_create_asset_node("arbitrum", USDT_ARBITRUM, "USDT");
+        let registry = Registry::builtin();
+        let opts = PresentOptions::default().with_graph(&graph).with_registry(&registry);
+        let route = ranked(
+            vec![hop(base, arb, "stargate", 0.3125, 45.2), hop(arb, usdt, "uniswap", 0.0, 0.4), hop(usdt, arb, "lifi:across", 1.53, 680.0)],
+            Some(1_234_567.891_234),
+        );
+
+        assert_eq!(
+            serde_json::to_value(humanize(&route, &opts)).unwrap(),
+            serde_json::json!({
+                "rank": 1,
+                "summary": "Base USDC → Arbitrum USDC",
+                "duration": "~12 min",
+                "fee": "$1.84",
+                "output": "1,234,567.8912 USDC",
+                "hops": [
+                    { "bridge": "Stargate", "from": "Base", "to": "Arbitrum", "duration": "~45s", "fee": "$0.31", "line": "Stargate: Base → Arbitrum, ~45s, $0.31 fee" },
+                    { "bridge": "Uniswap", "from": "Arbitrum USDC", "to": "USDT", "duration": "<1s", "fee": "$0.00", "line": "Uniswap: Arbitrum USDC → USDT, <1s, no fee" },
+                    { "bridge": "Across via Lifi", "from": "Arbitrum USDT", "to": "USDC", "duration": "~11 min", "fee": "$1.53", "line": "Across via Lifi: Arbitrum USDT → USDC, ~11 min, $1.53 fee" },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn edge_cases_are_formatted_consistently() {
+        let graph = Graph::new(4);
+        let base = graph.get_or_create_asset_node("base", USDC_BASE, "USDC");
+        let arb = graph.get_or_create_asset_node("arbitrum", USDC_ARBITRUM, "USDC");
+        let registry = Registry::builtin();
+        let european = PresentOptions::default()
+            .with_graph(&graph)
+            .with_registry(&registry)
+            .with_quote_currency("EUR")
+            .with_separators(Some('.'), ',')
+            .with_amount_decimals(10);
+
+        // Amounts have no more decimals than their token, and no trailing zeros
+        let huge = humanize(&ranked(vec![hop(base, arb, "across", 12_345.678, 7_260.0)], Some(98_765_432_109.125)), &european);
+        assert_eq!((huge.fee.as_str(), huge.duration.as_str()), ("€12.345,68", "~2 h 1 min"));
+        assert_eq!(huge.output.as_deref(), Some("98.765.432.109,125 USDC"));
+        let small = humanize(&ranked(vec![hop(base, arb, "across", 0.5, 60.0)], Some(0.123_456_789)), &european);
+        assert_eq!(small.output.as_deref(), Some("0,123457 USDC"));
+
+        let tiny = humanize(&ranked(vec![hop(base, arb, "across", 0.004, 0.2)], Some(1.0)), &PresentOptions::default().with_graph(&graph).with_quote_currency("CHF"));
+        assert_eq!((tiny.fee.as_str(), tiny.duration.as_str(), tiny.output.as_deref()), ("<0.01 CHF", "<1s", Some("1 USDC")));
+        assert_eq!(tiny.hops[0].line, "Across: Base → Arbitrum, <1s, <0.01 CHF fee");
+
+        // Without a graph hops are told by node id, and routes searched without an amount have no output
+        let bare = humanize(&ranked(vec![hop(NodeId(0xab), NodeId(0xcd), "hop", 0.0, 3_600.0)], None), &PresentOptions::default());
+        assert_eq!((bare.summary.as_str(), bare.fee.as_str(), bare.duration.as_str(), bare.output), ("ab → cd", "$0.00", "~1 h", None));
+        assert_eq!(bare.hops[0].line, "Hop: ab → cd, ~1 h, no fee");
+
+        assert_eq!([duration(59.6), duration(89.0), duration(f64::NAN)], ["~1 min", "~1 min", "unknown"]);
+    }
+
+    #[test]
+    fn costs_without_a_quote_currency_are_shown_in_the_tokens_sent() {
+        let graph = Graph::new(4);
+        let base = graph.get_or_create_asset_node("base", USDC_BASE, "USDC");
+        let arb = graph.get_or_create_asset_node("arbitrum", USDC_ARBITRUM, "USDC");
+        let usdt = graph.get_or_create_asset_node("arbitrum", USDT_ARBITRUM, "USDT");
+        let registry = Registry::builtin();
+        let opts = PresentOptions::default().with_graph(&graph).with_registry(&registry).in_source_tokens();
+
+        let route = humanize(&ranked(vec![hop(base, arb, "stargate", 0.3125, 45.0), hop(arb, usdt, "uniswap", 0.0, 0.4)], Some(999.0)), &opts);
+        assert_eq!(route.fee, "0.3125 USDC");
+        assert_eq!(route.hops[0].line, "Stargate: Base → Arbitrum, ~45s, 0.3125 USDC fee");
+        assert_eq!(route.hops[1].line, "Uniswap: Arbitrum USDC → USDT, <1s, no fee");
+
+        // Fees in several tokens aren't added up
+        let mixed = humanize(&ranked(vec![hop(base, arb, "stargate", 0.3125, 45.0), hop(usdt, arb, "across", 1.53, 60.0)], None), &opts);
+        assert_eq!(mixed.fee, "0.3125 USDC + 1.53 USDT");
+    }
+}
@@ -0,0 +1,246 @@
+// Routing preferences an API consumer stores once and names in its queries, e.g. a "safest"
+// profile that never takes a given bridge. Each tenant has profiles of its own; those stored
+// without one are the open API's.
+
+use polypath_graph::{RouteConstraints, RouteOptions, RoutingParams};
+use polypathroute_core::{CoreError, PersistenceError, PersistenceManager, Versioned};
+use serde::{Deserialize, Serialize};
+
+use crate::error::DalError;
+
+const PROFILE_PREFIX: &str = "profiles/";
+
+// What a profile sets on the options of the queries naming it; unset fields keep the graph's
+// defaults
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PreferenceProfile {
+    pub name: String,
+    pub routing_params: Option<RoutingParams>,
+    pub constraints: RouteConstraints,
+    pub excluded_bridges: Vec<String>,
+    pub max_hops: Option<usize>,
+}
+
+impl Versioned for PreferenceProfile {
+    const SCHEMA: &'static str = "preference_profile";
+    const VERSION: u32 = 1;
+}
+
+impl PreferenceProfile {
+    pub fn new(name: &str) -> Self {
+        Self { name: name.to_string(), ..Self::default() }
+    }
+
+    pub fn with_routing_params(mut self, params: RoutingParams) -> Self {
+        self.routing_params = Some(params);
+        self
+    }
+
+    pub fn with_constraints(mut self, constraints: RouteConstraints) -> Self {
+        self.constraints = constraints;
+        self
+    }
+
+    pub fn with_excluded_bridges(mut self, bridges: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.excluded_bridges = bridges.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_max_hops(mut self, max_hops: usize) -> Self {
+        self.max_hops = Some(max_hops);
+        self
+    }
+
+    // Names end up in persistence keys, so they're kept to letters, digits, `-` and `_`
+    pub fn validate(&self) -> Result<(), DalError> {
+        let invalid = |reason: String| DalError::InvalidProfile { name: self.name.clone(), reason };
+        if self.name.is_empty() || self.name.len() > 64 {
+            return Err(invalid("names are 1 to 64 characters".to_string()));
+        }
+        if !self.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(invalid("names are letters, digits, `-` and `_`".to_string()));
+        }
+        if let Some(params) = &self.routing_params {
+            params.validate().map_err(|err| invalid(err.to_string()))?;
+        }
+        if self.max_hops == Some(0) {
+            return Err(invalid("max_hops must be at least 1".to_string()));
+        }
+        Ok(())
+    }
+
+    // `options` with what the profile sets put over it
+    pub fn apply(&self, mut options: RouteOptions) -> RouteOptions {
+        if let Some(params) = &self.routing_params {
+            options.routing_params = Some(params.clone());
+        }
+        let constraints = &mut options.constraints;
+        constraints.max_cost = self.constraints.max_cost.or(constraints.max_cost);
+        constraints.max_time = self.constraints.max_time.or(constraints.max_time);
+        constraints.max_risk = self.constraints.max_risk.or(constraints.max_risk);
+        constraints.min_liquidity = self.constraints.min_liquidity.or(constraints.min_liquidity);
+        if !self.excluded_bridges.is_empty() {
+            options.excluded_bridges = self.excluded_bridges.clone();
+        }
+        options.max_hops = self.max_hops.unwrap_or(options.max_hops);
+        options.profile = Some(self.name.clone());
+        options
+    }
+}
+
+// Options for a query naming `profile`: `defaults`, the profile over them, then whatever
+// `explicit`, the RouteOptions object the query sent, sets itself. Fields it leaves out or sets
+// to null keep the value under them.
+pub fn layered_options(defaults: &RouteOptions, profile: &PreferenceProfile, explicit: &serde_json::Value) -> Result<RouteOptions, serde_json::Error> {
+    let mut options = serde_json::to_value(profile.apply(defaults.clone()))?;
+    overlay(&mut options, explicit);
+    serde_json::from_value(options)
+}
+
+fn overlay(base: &mut serde_json::Value, top: &serde_json::Value) {
+    match (base, top) {
+        (_, serde_json::Value::Null) => {}
+        (serde_json::Value::Object(base), serde_json::Value::Object(top)) => {
+            for (key, value) in top {
+                overlay(base.entry(key.clone()).or_insert(serde_json::Value::Null), value);
+            }
+        }
+        (base, top) => *base = top.clone(),
+    }
+}
+
+// The tenant's own, else the open API's
+fn profile_prefix(tenant: Option<&str>) -> String {
+    match tenant {
+        Some(tenant) => format!("{}{}/", PROFILE_PREFIX, tenant),
+        None => PROFILE_PREFIX.to_string(),
+    }
+}
+
+fn profile_key(tenant: Option<&str>, name: &str) -> String {
+    format!("{}{}", profile_prefix(tenant), name)
+}
+
+fn profile_error(name: &str, err: PersistenceError) -> DalError {
+    match err {
+        PersistenceError::Encode { source, .. } | PersistenceError::Decode { source, .. } => {
+            DalError::Profile { name: name.to_string(), source }
+        }
+        err => CoreError::from(err).into(),
+    }
+}
+
+pub fn save_profile(persistence: &PersistenceManager, tenant: Option<&str>, profile: &PreferenceProfile) -> Result<(), DalError> {
+    profile.validate()?;
+    persistence.put_typed(&profile_key(tenant, &profile.name), profile).map_err(|err| profile_error(&profile.name, err))
+}
+
+pub fn load_profile(persistence: &PersistenceManager, tenant: Option<&str>, name: &str) -> Result<Option<PreferenceProfile>, DalError> {
+    PreferenceProfile::new(name).validate()?;
+    persistence.get_typed(&profile_key(tenant, name)).map_err(|err| profile_error(name, err))
+}
+
+// Sorted by name
+pub fn list_profiles(persistence: &PersistenceManager, tenant: Option<&str>) -> Result<Vec<PreferenceProfile>, DalError> {
+    let prefix = profile_prefix(tenant);
+    let mut profiles = persistence
+        .scan_prefix(&prefix)
+        .map_err(CoreError::from)?
+        .into_iter()
+        // Under the open API's prefix are the tenants' too
+        .filter(|(key, _)| !key[prefix.len()..].contains('/'))
+        .map(|(key, encoded)| {
+            persistence
+                .decode_typed::<PreferenceProfile>(&key, &encoded)
+                .map_err(|err| profile_error(&key[prefix.len()..], err))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    profiles.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(profiles)
+}
+
+// Whether there was a profile to delete
+pub fn delete_profile(persistence: &PersistenceManager, tenant: Option<&str>, name: &str) -> Result<bool, DalError> {
+    PreferenceProfile::new(name).validate()?;
+    Ok(persistence.delete(profile_key(tenant, name)).map_err(CoreError::from)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn safest() -> PreferenceProfile {
+        PreferenceProfile::new("safest")
+            .with_routing_params(RoutingParams::safest())
+            .with_constraints(RouteConstraints { max_risk: Some(0.5), ..RouteConstraints::default() })
+            .with_excluded_bridges(["wormhole"])
+            .with_max_hops(2)
+    }
+
+    #[test]
+    fn profiles_round_trip_through_persistence() {
+        let persistence = PersistenceManager::new();
+        save_profile(&persistence, None, &safest()).unwrap();
+        save_profile(&persistence, None, &PreferenceProfile::new("cheap").with_routing_params(RoutingParams::cheapest())).unwrap();
+
+        assert_eq!(load_profile(&persistence, None, "safest").unwrap(), Some(safest()));
+        assert_eq!(load_profile(&persistence, None, "fast").unwrap(), None);
+        let names: Vec<String> = list_profiles(&persistence, None).unwrap().into_iter().map(|profile| profile.name).collect();
+        assert_eq!(names, ["cheap", "safest"]);
+
+        assert!(delete_profile(&persistence, None, "cheap").unwrap());
+        assert!(!delete_profile(&persistence, None, "cheap").unwrap());
+        assert_eq!(list_profiles(&persistence, None).unwrap(), [safest()]);
+
+        let bad_name = save_profile(&persistence, None, &PreferenceProfile::new("../safest")).unwrap_err();
+        assert!(matches!(bad_name, DalError::InvalidProfile { .. }), "{}", bad_name);
+        let bad_weights = PreferenceProfile::new("none").with_routing_params(RoutingParams { alpha: 0.0, beta: 0.0, gamma: 0.0, delta: 0.0, omega: 0.0, epsilon: 0.0, ..RoutingParams::default() });
+        assert!(matches!(save_profile(&persistence, None, &bad_weights), Err(DalError::InvalidProfile { .. })));
+        assert!(load_profile(&persistence, None, "").is_err());
+    }
+
+    #[test]
+    fn tenants_only_see_their_own_profiles() {
+        let persistence = PersistenceManager::new();
+        save_profile(&persistence, Some("partner-a"), &safest()).unwrap();
+        save_profile(&persistence, None, &PreferenceProfile::new("cheap")).unwrap();
+
+        assert_eq!(load_profile(&persistence, Some("partner-a"), "safest").unwrap(), Some(safest()));
+        assert_eq!(load_profile(&persistence, Some("partner-b"), "safest").unwrap(), None);
+        assert_eq!(load_profile(&persistence, None, "safest").unwrap(), None);
+        assert_eq!(list_profiles(&persistence, Some("partner-a")).unwrap(), [safest()]);
+        assert!(list_profiles(&persistence, Some("partner-b")).unwrap().is_empty());
+        assert_eq!(list_profiles(&persistence, None).unwrap(), [PreferenceProfile::new("cheap")]);
+
+        assert!(!delete_profile(&persistence, Some("partner-b"), "safest").unwrap());
+        assert!(delete_profile(&persistence, Some("partner-a"), "safest").unwrap());
+    }
+
+    #[test]
+    fn explicit_options_override_the_profile() {
+        let defaults = RouteOptions { max_hops: 3, ..RouteOptions::default() };
+
+        let profiled = layered_options(&defaults, &safest(), &serde_json::json!({ "profile": "safest" })).unwrap();
+        assert_eq!(profiled.excluded_bridges, ["wormhole"]);
+        assert_eq!((profiled.max_hops, profiled.max_results), (2, 3));
+        assert_eq!(profiled.constraints.max_risk, Some(0.5));
+        assert_eq!(profiled.routing_params, Some(RoutingParams::safest()));
+
+        let explicit = serde_json::json!({
+            "profile": "safest",
+            "max_hops": 4,
+            "excluded_bridges": [],
+            "constraints": { "max_cost": 10.0, "max_risk": null },
+        });
+        let overridden = layered_options(&defaults, &safest(), &explicit).unwrap();
+        assert!(overridden.excluded_bridges.is_empty());
+        assert_eq!(overridden.max_hops, 4);
+        // Constraints merge field by field, and a null keeps the profile's value
+        assert_eq!(overridden.constraints.max_cost, Some(10.0));
+        assert_eq!(overridden.constraints.max_risk, Some(0.5));
+        assert_eq!(overridden.routing_params, Some(RoutingParams::safest()));
+
+        assert!(layered_options(&defaults, &safest(), &serde_json::json!({ "max_hops": "many" })).is_err());
+    }
+}
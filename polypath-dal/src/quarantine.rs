@@ -0,0 +1,187 @@
+// Pairs that fail every refresh, quoted only on a backoff schedule until a probe succeeds
+
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+use serde::Serialize;
+use tokio::time::Instant;
+
+use crate::adapters::{SupportedPair, unix_now};
+
+// Waits between probes of a quarantined pair; the last one repeats
+pub const QUARANTINE_BACKOFF: [Duration; 3] = [
+    Duration::from_secs(10 * 60),
+    Duration::from_secs(30 * 60),
+    Duration::from_secs(2 * 60 * 60),
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QuarantinedPair {
+    pub bridge: String,
+    pub pair: SupportedPair,
+    // Consecutive failures, failed probes included
+    pub failures: u32,
+    pub last_error: String,
+    // Unix seconds
+    pub since: u64,
+    pub next_probe: u64,
+}
+
+#[derive(Debug)]
+struct Entry {
+    bridge: String,
+    pair: SupportedPair,
+    failures: u32,
+    last_error: String,
+    // Unix time it was quarantined at, probes failed since, and when the next one is due
+    quarantined: Option<(u64, usize, Instant)>,
+}
+
+impl Entry {
+    fn listed(&self) -> Option<QuarantinedPair> {
+        let (since, _, next) = self.quarantined?;
+        Some(QuarantinedPair {
+            bridge: self.bridge.clone(),
+            pair: self.pair.clone(),
+            failures: self.failures,
+            last_error: self.last_error.clone(),
+            since,
+            next_probe: unix_now() + next.saturating_duration_since(Instant::now()).as_secs(),
+        })
+    }
+}
+
+// Consecutive failures per pair, keyed by whatever identifies the pair to the caller. A pair is
+// quarantined after `after` of them in a row.
+#[derive(Debug)]
+pub(crate) struct Quarantine {
+    after: u32,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl Quarantine {
+    pub(crate) fn new(after: u32) -> Self {
+        Self { after: after.max(1), entries: Mutex::default() }
+    }
+
+    pub(crate) fn set_after(&mut self, after: u32) {
+        self.after = after.max(1);
+    }
+
+    // Whether to quote the pair now: it isn't quarantined or its next probe is due
+    pub(crate) fn is_due(&self, key: &str) -> bool {
+        match self.entries.lock().unwrap().get(key).and_then(|entry| entry.quarantined) {
+            Some((_, _, next)) => Instant::now() >= next,
+            None => true,
+        }
+    }
+
+    // Clears the pair's failures; whether it was quarantined until now
+    pub(crate) fn succeeded(&self, key: &str) -> bool {
+        self.entries.lock().unwrap().remove(key).is_some_and(|entry| entry.quarantined.is_some())
+    }
+
+    // Counts a failure. Returns the pair when this one quarantined it, so it's warned about once.
+    pub(crate) fn failed(&self, key: &str, bridge: &str, pair: &SupportedPair, error: String) -> Option<QuarantinedPair> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.entry(key.to_string()).or_insert_with(|| Entry {
+            bridge: bridge.to_string(),
+            pair: pair.clone(),
+            failures: 0,
+            last_error: String::new(),
+            quarantined: None,
+        });
+        entry.failures += 1;
+        entry.last_error = error;
+        match &mut entry.quarantined {
+            Some((_, probes, next)) => {
+                *probes += 1;
+                *next = Instant::now() + QUARANTINE_BACKOFF[(*probes).min(QUARANTINE_BACKOFF.len() - 1)];
+                None
+            }
+            None if entry.failures >= self.after => {
+                entry.quarantined = Some((unix_now(), 0, Instant::now() + QUARANTINE_BACKOFF[0]));
+                entry.listed()
+            }
+            None => None,
+        }
+    }
+
+    // Drops the bookkeeping of `bridge`'s pairs, e.g. once its pairs were reconfigured
+    pub(crate) fn forget_bridge(&self, bridge: &str) {
+        self.entries.lock().unwrap().retain(|_, entry| entry.bridge != bridge);
+    }
+
+    // Sorted by bridge, then by when they were quarantined
+    pub(crate) fn list(&self) -> Vec<QuarantinedPair> {
+        let mut listed: Vec<QuarantinedPair> = self.entries.lock().unwrap().values().filter_map(Entry::listed).collect();
+        listed.sort_by(|a, b| (&a.bridge, a.since).cmp(&(&b.bridge, b.since)));
+        listed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polypathroute_core::RefreshPriority;
+
+    fn pair(dst_chain: &str) -> SupportedPair {
+        SupportedPair {
+            src_chain: "ethereum".to_string(),
+            dst_chain: dst_chain.to_string(),
+            src_token: "usdc".to_string(),
+            dst_token: "usdc".to_string(),
+            min_amount: None,
+            max_amount: None,
+            token_symbol: None,
+            priority: RefreshPriority::default(),
+            refresh_interval: None,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn probes_back_off_until_one_succeeds() {
+        let quarantine = Quarantine::new(3);
+        let fail = |error: &str| quarantine.failed("base", "relay", &pair("base"), error.to_string());
+
+        assert!(fail("unknown token").is_none());
+        assert!(fail("unknown token").is_none());
+        let quarantined = fail("delisted").unwrap();
+        assert_eq!((quarantined.failures, quarantined.last_error.as_str()), (3, "delisted"));
+        assert_eq!(quarantined.next_probe, quarantined.since + 600);
+        assert_eq!(quarantine.list(), [quarantined]);
+
+        // Waits of 10m, 30m, then 2h from every failed probe on
+        for wait in [600, 1800, 7200, 7200] {
+            tokio::time::advance(Duration::from_secs(wait - 1)).await;
+            assert!(!quarantine.is_due("base"));
+            tokio::time::advance(Duration::from_secs(1)).await;
+            assert!(quarantine.is_due("base"));
+            // Only the failure that quarantined the pair is reported
+            assert!(fail("delisted").is_none());
+        }
+        assert_eq!(quarantine.list()[0].failures, 7);
+        assert!(quarantine.is_due("polygon"));
+
+        assert!(quarantine.succeeded("base"));
+        assert!(quarantine.list().is_empty());
+        assert!(quarantine.is_due("base"));
+        assert!(!quarantine.succeeded("base"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn successes_reset_the_count_and_reconfigured_bridges_start_over() {
+        let quarantine = Quarantine::new(2);
+        let fail = |bridge: &str, key: &str| quarantine.failed(key, bridge, &pair(key), "down".to_string());
+
+        assert!(fail("relay", "base").is_none());
+        assert!(!quarantine.succeeded("base"));
+        assert!(fail("relay", "base").is_none());
+        assert!(fail("relay", "base").is_some());
+        assert!(fail("hop", "polygon").is_none());
+        assert!(fail("hop", "polygon").is_some());
+        assert_eq!(quarantine.list().iter().map(|listed| listed.bridge.as_str()).collect::<Vec<_>>(), ["hop", "relay"]);
+
+        quarantine.forget_bridge("relay");
+        assert!(quarantine.is_due("base"));
+        assert_eq!(quarantine.list().len(), 1);
+    }
+}
@@ -0,0 +1,50 @@
+use crate::adapters::DynBridgeAdapter;
+use std::{collections::BTreeMap, fmt, sync::{Arc, RwLock}};
+
+// Adapter instances shared by everything a DalContext does, so circuit breaker state and
+// telemetry accumulate per bridge rather than per call
+#[derive(Default)]
+pub(crate) struct AdapterRegistry {
+    adapters: RwLock<BTreeMap<String, Arc<DynBridgeAdapter>>>,
+}
+
+impl AdapterRegistry {
+    pub fn get_or_try_insert<E>(
+        &self,
+        name: &str,
+        build: impl FnOnce() -> Result<DynBridgeAdapter, E>
+    ) -> Result<Arc<DynBridgeAdapter>, E> {
+        if let Some(adapter) = self.adapters.read().unwrap().get(name) {
+            return Ok(Arc::clone(adapter));
+        }
+        let mut adapters = self.adapters.write().unwrap();
+        if let Some(adapter) = adapters.get(name) {
+            return Ok(Arc::clone(adapter));
+        }
+        let adapter = Arc::new(build()?);
+        adapters.insert(name.to_string(), Arc::clone(&adapter));
+        Ok(adapter)
+    }
+
+    // None when no instance was built under `name`
+    pub fn get(&self, name: &str) -> Option<Arc<DynBridgeAdapter>> {
+        self.adapters.read().unwrap().get(name).cloned()
+    }
+
+    // Instances built so far, by name
+    pub fn all(&self) -> Vec<(String, Arc<DynBridgeAdapter>)> {
+        self.adapters
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, adapter)| (name.clone(), Arc::clone(adapter)))
+            .collect()
+    }
+}
+
+impl fmt::Debug for AdapterRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<String> = self.adapters.read().unwrap().keys().cloned().collect();
+        f.debug_struct("AdapterRegistry").field("adapters", &names).finish()
+    }
+}
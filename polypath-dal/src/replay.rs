@@ -0,0 +1,178 @@
+// Rebuilds a graph as it was at some past time, from the snapshot saved before it and the edge
+// metrics history since, so post-mortems can ask what routes were served then
+
+use std::{sync::Arc, time::Duration};
+use polypath_graph::{EdgeMetrics, Graph, NodeId, NodeType, RankedPath, RouteIntent, RouteOptions, Router};
+use polypathroute_core::PersistenceManager;
+
+use crate::{
+    error::DalError,
+    history::{History, MetricsSample, Resolution, edge_id},
+    snapshot::load_graph_snapshot_at,
+};
+
+// Clones share one store
+#[derive(Debug, Clone)]
+pub struct Replay {
+    persistence: PersistenceManager,
+    history: History,
+    graph_name: String,
+    shard_count: usize,
+    // Edges without metrics this much before the replayed time are switched off
+    max_age: Duration,
+}
+
+impl Replay {
+    pub fn new(persistence: PersistenceManager, history: History, graph_name: &str, shard_count: usize, max_age: Duration) -> Self {
+        Self { persistence, history, graph_name: graph_name.to_string(), shard_count, max_age }
+    }
+
+    // The graph as of unix time `at`: the newest snapshot saved by then, each edge with the last
+    // metrics recorded for it by then, stamped with when they were. Edges whose metrics were more
+    // than max_age old at `at` are switched off, as a refresh would have by then; the snapshot's
+    // own metrics count as of when it was saved. Its clock is pinned to `at`, so quotes are aged
+    // as they were then rather than by the time of the replay.
+    pub fn graph_at(&self, at: u64) -> Result<Graph, DalError> {
+        let Some((metadata, graph)) = load_graph_snapshot_at(&self.persistence, &self.graph_name, self.shard_count, at)? else {
+            return Err(DalError::NoSnapshot { name: self.graph_name.clone(), at });
+        };
+        let oldest = at.saturating_sub(self.max_age.as_secs());
+        for edge in graph.snapshot().edges {
+            let recorded = match edge_id_of(&graph, edge.from, edge.to, &edge.bridge_name) {
+                Some(id) => self.history.query(&id, metadata.saved_at, at + 1, Resolution::Raw)?.pop(),
+                None => None,
+            };
+            let updated_at = match recorded {
+                Some(sample) => {
+                    graph.update_edge_metrics_at(edge.from, edge.to, &edge.bridge_name, metrics(&sample), sample.timestamp)?;
+                    sample.timestamp
+                }
+                None => {
+                    // Stamped with when the snapshot was saved rather than restored; still stale
+                    if let Some(restored) = graph.get_outgoing_edges(edge.from).into_iter().find(|restored| restored.to == edge.to && *restored.bridge_name == edge.bridge_name) {
+                        restored.metrics.update_at(edge.metrics.clone(), metadata.saved_at);
+                    }
+                    metadata.saved_at
+                }
+            };
+            if updated_at < oldest {
+                graph.set_edge_active(edge.from, edge.to, &edge.bridge_name, false);
+            }
+        }
+        graph.pin_clock(at);
+        Ok(graph)
+    }
+
+    // The routes the router would have ranked for `intent` at unix time `at`, on graph_at(at).
+    // The intent is taken as is, so it should already be canonical.
+    pub fn route_at(&self, at: u64, intent: &RouteIntent, opts: &RouteOptions) -> Result<Vec<RankedPath>, DalError> {
+        let router = Router::new(Arc::new(self.graph_at(at)?));
+        Ok(router.best_routes(intent, opts)?.into_iter().map(|explained| explained.ranked).collect())
+    }
+}
+
+// The id the history records the edge under; None unless it joins two assets
+fn edge_id_of(graph: &Graph, from: NodeId, to: NodeId, bridge: &str) -> Option<String> {
+    let asset = |id| match graph.get_node(id).map(|node| node.node_type.clone()) {
+        Some(NodeType::Asset { chain, token_address, .. }) => Some((chain, token_address)),
+        _ => None,
+    };
+    let ((src_chain, src_token), (dst_chain, dst_token)) = (asset(from)?, asset(to)?);
+    Some(edge_id(bridge, (&src_chain, &src_token), (&dst_chain, &dst_token)))
+}
+
+fn metrics(sample: &MetricsSample) -> EdgeMetrics {
+    EdgeMetrics { cost: sample.cost, speed: sample.speed, liquidity: sample.liquidity, risk: sample.risk }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polypath_graph::RoutingParams;
+    use polypathroute_core::HistoryConfig;
+    use crate::snapshot::save_graph_snapshot_at;
+
+    const SAVED_AT: u64 = 1_717_200_000;
+
+    fn edge_metrics(cost: f64) -> EdgeMetrics {
+        EdgeMetrics { cost, speed: 60.0, liquidity: 1_000_000.0, risk: 0.1 }
+    }
+
+    fn sample(timestamp: u64, cost: f64) -> MetricsSample {
+        MetricsSample { timestamp, cost, speed: 60.0, liquidity: 1_000_000.0, risk: 0.1, samples: 1 }
+    }
+
+    fn intent() -> RouteIntent {
+        RouteIntent {
+            from_chain: "ethereum".to_string(),
+            from_token: "usdc".to_string(),
+            to_chain: "polygon".to_string(),
+            to_token: "usdc".to_string(),
+            amount: 100.0,
+            preference: Some("cheapest".to_string()),
+            src_address: None,
+        }
+    }
+
+    // Stargate and across between the same assets; stargate's fee spikes ten minutes in, for ten
+    // minutes
+    fn replay() -> Replay {
+        let persistence = PersistenceManager::new();
+        let history = History::new(persistence.clone(), &HistoryConfig::default());
+        let graph = Graph::new(8);
+        let eth = graph.get_or_create_asset_node("ethereum", "usdc", "USDC");
+        let polygon = graph.get_or_create_asset_node("polygon", "usdc", "USDC");
+        graph.add_edge(eth, polygon, "stargate", edge_metrics(5.0), None, None).unwrap();
+        graph.add_edge(eth, polygon, "across", edge_metrics(6.0), None, None).unwrap();
+        save_graph_snapshot_at(&persistence, &graph, "default", SAVED_AT).unwrap();
+
+        let id = |bridge| edge_id(bridge, ("ethereum", "usdc"), ("polygon", "usdc"));
+        for minute in [1, 5, 10, 15, 20, 25] {
+            let stargate = if (10..20).contains(&minute) { 50.0 } else { 5.0 };
+            history.record(&id("stargate"), &sample(SAVED_AT + minute * 60, stargate)).unwrap();
+            history.record(&id("across"), &sample(SAVED_AT + minute * 60, 6.0)).unwrap();
+        }
+        Replay::new(persistence, history, "default", 8, Duration::from_secs(60 * 60))
+    }
+
+    fn best_bridge(replay: &Replay, at: u64) -> String {
+        let opts = RouteOptions { routing_params: Some(RoutingParams::cheapest()), ..RouteOptions::default() };
+        replay.route_at(at, &intent(), &opts).unwrap()[0].path.hops[0].bridge_name.to_string()
+    }
+
+    #[test]
+    fn routes_follow_the_recorded_fee_spike() {
+        let replay = replay();
+        assert_eq!(best_bridge(&replay, SAVED_AT + 7 * 60), "stargate");
+        assert_eq!(best_bridge(&replay, SAVED_AT + 12 * 60), "across");
+        assert_eq!(best_bridge(&replay, SAVED_AT + 22 * 60), "stargate");
+
+        // Metrics are stamped with when they were recorded, not when they were replayed
+        let graph = replay.graph_at(SAVED_AT + 12 * 60).unwrap();
+        let eth = graph.get_or_create_asset_node("ethereum", "usdc", "USDC");
+        let stargate = graph.get_outgoing_edges(eth).into_iter().find(|edge| &*edge.bridge_name == "stargate").unwrap();
+        assert_eq!(stargate.get_metrics().cost, 50.0);
+        assert_eq!(stargate.metrics.last_updated(), SAVED_AT + 10 * 60);
+        assert!(!stargate.is_stale());
+        // Quotes are aged to the replayed time
+        assert_eq!(graph.clock(), SAVED_AT + 12 * 60);
+        let graph = replay.graph_at(SAVED_AT + 30).unwrap();
+        assert_eq!(graph.clock(), SAVED_AT + 30);
+        assert!(graph.get_outgoing_edges(eth).iter().all(|edge| edge.metrics.last_updated() == SAVED_AT));
+    }
+
+    #[test]
+    fn edges_too_old_at_the_replayed_time_are_switched_off() {
+        let replay = replay();
+        // The last samples are from 25 minutes in
+        let graph = replay.graph_at(SAVED_AT + 25 * 60 + 60 * 60 + 1).unwrap();
+        assert_eq!(graph.active_edge_count(), 0);
+        assert!(matches!(replay.route_at(SAVED_AT + 2 * 60 * 60, &intent(), &RouteOptions::default()), Ok(routes) if routes.is_empty()));
+
+        // Before the first sample the snapshot's metrics stand
+        let graph = replay.graph_at(SAVED_AT + 30).unwrap();
+        assert_eq!(graph.active_edge_count(), 2);
+
+        assert!(matches!(replay.graph_at(SAVED_AT - 1), Err(DalError::NoSnapshot { at, .. }) if at == SAVED_AT - 1));
+    }
+}
@@ -0,0 +1,219 @@
+// The background refreshes of a running service, and stopping them without losing state
+
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use tokio::{task::JoinHandle, time::Instant};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    graphs::GraphRegistry,
+    scheduler::{RefreshScheduler, SchedulerStats},
+    snapshot::SnapshotMetadata,
+};
+
+// What Runtime::shutdown got done
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ShutdownReport {
+    // Summed over the graphs
+    pub refreshes: SchedulerStats,
+    // A refresh was still running at the timeout and was dropped
+    pub timed_out: bool,
+    // Per graph name, the snapshot saved under it
+    pub snapshots: BTreeMap<String, SnapshotMetadata>,
+    pub cache_flushed: bool,
+    // Whether there was a log file to flush
+    pub logs_flushed: bool,
+    // Steps that failed, which don't stop the ones after them
+    pub errors: Vec<String>,
+    pub elapsed: Duration,
+}
+
+// Every graph refreshing on its scheduler until `shutdown`
+#[derive(Debug)]
+pub struct Runtime {
+    graphs: Arc<GraphRegistry>,
+    stop: CancellationToken,
+    abort: CancellationToken,
+    refreshes: Vec<JoinHandle<SchedulerStats>>,
+}
+
+impl Runtime {
+    // Spawns `schedulers`, normally GraphRegistry::schedulers of `graphs`
+    pub fn start(graphs: Arc<GraphRegistry>, schedulers: Vec<RefreshScheduler>) -> Self {
+        let (stop, abort) = (CancellationToken::new(), CancellationToken::new());
+        let refreshes = schedulers.into_iter().map(|scheduler| scheduler.spawn_draining(stop.clone(), abort.clone())).collect();
+        Self { graphs, stop, abort, refreshes }
+    }
+
+    pub fn graphs(&self) -> &Arc<GraphRegistry> {
+        &self.graphs
+    }
+
+    // Stops the schedulers, lets refreshes in flight finish for up to `timeout` and drops them
+    // after that, so the graphs keep what the last completed refresh left. Then writes the audit
+    // entries still queued, saves a snapshot of every populated graph under its name, flushes the
+    // cache's pending writes to the persistence store and flushes the log file.
+    pub async fn shutdown(self, timeout: Duration) -> ShutdownReport {
+        let started = Instant::now();
+        let dal = self.graphs.dal();
+        let mut report = ShutdownReport::default();
+
+        self.stop.cancel();
+        for mut refresh in self.refreshes {
+            let stats = match tokio::time::timeout_at(started + timeout, &mut refresh).await {
+                Ok(stats) => stats.unwrap_or_default(),
+                Err(_) => {
+                    report.timed_out = true;
+                    self.abort.cancel();
+                    refresh.await.unwrap_or_default()
+                }
+            };
+            report.refreshes.ticks += stats.ticks;
+            report.refreshes.refreshes += stats.refreshes;
+            report.refreshes.skipped += stats.skipped;
+            report.refreshes.interrupted += stats.interrupted;
+        }
+        if report.timed_out {
+            dal.logger().warn_with("shutdown timed out, dropped the refreshes still running", &[
+                ("timeout_secs", &timeout.as_secs_f64()),
+                ("interrupted", &report.refreshes.interrupted),
+            ]);
+        }
+
+        if let Some(audit) = self.graphs.audit() {
+            audit.flush();
+        }
+
+        // A cold graph would replace the snapshot it could have been warm started from
+        for entry in self.graphs.iter().filter(|entry| entry.graph().edge_count() > 0) {
+            match dal.save_graph_snapshot(entry.graph(), entry.name()) {
+                Ok(metadata) => {
+                    report.snapshots.insert(entry.name().to_string(), metadata);
+                }
+                Err(err) => report.errors.push(format!("snapshot of graph `{}`: {}", entry.name(), err)),
+            }
+        }
+        match dal.core.cache_manager.flush() {
+            Ok(()) => report.cache_flushed = true,
+            Err(err) => report.errors.push(format!("cache flush: {}", err)),
+        }
+
+        report.elapsed = started.elapsed();
+        dal.logger().info_with("shut down", &[
+            ("refreshes", &report.refreshes.refreshes),
+            ("interrupted", &report.refreshes.interrupted),
+            ("snapshots", &report.snapshots.len()),
+            ("errors", &report.errors.len()),
+            ("elapsed_ms", &report.elapsed.as_millis()),
+        ]);
+        report.logs_flushed = dal.core.flush_logs();
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        DalContext,
+        adapters::{self, BridgeEdge, mock::MockAdapter, unix_now},
+        snapshot::DEFAULT_SNAPSHOT_MAX_AGE,
+    };
+    use polypathroute_core::PersistenceManager;
+
+    const USDC_ETHEREUM: &str = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+    const USDC_POLYGON: &str = "0x3c499c542cef5e3811e1192ce70d8cc03d5c3359";
+
+    fn quote(cost: f64) -> BridgeEdge {
+        BridgeEdge {
+            from: "ethereum".to_string(),
+            to: "polygon".to_string(),
+            cost,
+            speed: 60.0,
+            liquidity: 1_000_000.0,
+            risk: 0.1,
+            // Expired as it's quoted, so the quote cache never answers for the mock
+            valid_until: Some(unix_now()),
+            ..BridgeEdge::default()
+        }
+    }
+
+    // Graphs persisted under `dir`, over a bridge whose quotes take 150s and cost 1.0 the first
+    // time and 2.0 after that, refreshed every 60s from the start
+    fn runtime(bridge: &'static str, dir: &std::path::Path) -> Runtime {
+        adapters::register(bridge, move |_| {
+            Ok(Box::new(
+                MockAdapter::named(bridge)
+                    .with_latency(Duration::from_secs(150))
+                    .with_quote("ethereum", "polygon", quote(1.0))
+                    .then_quote("ethereum", "polygon", quote(2.0)),
+            ))
+        });
+        let config_path = std::env::temp_dir().join(format!("polypath-dal-runtime-{}-{}.toml", bridge, std::process::id()));
+        std::fs::write(&config_path, format!(
+            "[global]\nupdate_interval = 60\npersistence_path = {:?}\n[bridges.{1}]\nbase_url = \"https://{1}.test\"\nchains = [\"ethereum\", \"polygon\"]\n[[bridges.{1}.pairs]]\nsource_chain = \"ethereum\"\nsource_address = \"{2}\"\nsource_token_name = \"USDC\"\ndestination_chain = \"polygon\"\ndestination_address = \"{3}\"\ndestination_token_name = \"USDC\"\n",
+            dir, bridge, USDC_ETHEREUM, USDC_POLYGON,
+        )).unwrap();
+        let dal = DalContext::new(config_path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&config_path).unwrap();
+
+        let graphs = Arc::new(GraphRegistry::new(dal, 4));
+        let schedulers = graphs.iter().map(|entry| entry.scheduler().with_max_jitter(Duration::ZERO)).collect();
+        Runtime::start(graphs, schedulers)
+    }
+
+    fn saved_cost(dir: &std::path::Path) -> f64 {
+        let graph = crate::load_graph_snapshot(&PersistenceManager::open(dir).unwrap(), "default", 4, DEFAULT_SNAPSHOT_MAX_AGE).unwrap();
+        assert_eq!(graph.edge_count(), 1);
+        graph.snapshot().edges[0].metrics.cost
+    }
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("polypath-runtime-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_refresh_past_the_timeout_is_dropped_and_the_last_completed_one_saved() {
+        let dir = temp_dir("timeout");
+        let runtime = runtime("halting", &dir);
+
+        // The first refresh completes at 150s, the next runs from 180s to 330s
+        tokio::time::sleep(Duration::from_secs(200)).await;
+        let report = runtime.shutdown(Duration::from_secs(30)).await;
+        assert_eq!(report.elapsed, Duration::from_secs(30));
+        assert!(report.timed_out);
+        assert_eq!((report.refreshes.refreshes, report.refreshes.interrupted), (2, 1));
+        assert_eq!(report.snapshots["default"].edge_count, 1);
+        assert!(report.cache_flushed && report.errors.is_empty());
+        assert_eq!(saved_cost(&dir), 1.0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn refreshes_in_flight_finish_within_the_timeout() {
+        let dir = temp_dir("drain");
+        let runtime = runtime("draining", &dir);
+
+        tokio::time::sleep(Duration::from_secs(200)).await;
+        let report = runtime.shutdown(Duration::from_secs(600)).await;
+        assert_eq!(report.elapsed, Duration::from_secs(130));
+        assert!(!report.timed_out);
+        assert_eq!((report.refreshes.refreshes, report.refreshes.interrupted), (2, 0));
+        assert_eq!(saved_cost(&dir), 2.0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cold_graphs_keep_their_saved_snapshot() {
+        let dir = temp_dir("cold");
+        let runtime = runtime("frozen", &dir);
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let report = runtime.shutdown(Duration::from_secs(5)).await;
+        assert!(report.timed_out);
+        assert!(report.snapshots.is_empty());
+        assert!(PersistenceManager::open(&dir).unwrap().keys_with_prefix("graph_snapshot:").unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
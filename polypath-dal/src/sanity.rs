@@ -0,0 +1,174 @@
+// Bounds on what a quote can plausibly say, from the config's [sanity] section. A quote outside
+// them is an API hiccup or a unit mixup rather than a price, and GraphUpdater keeps it out of
+// the graph; the checker can also be run over quotes on its own.
+
+use polypathroute_core::{SanityBounds, SanityConfig};
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::adapters::BridgeEdge;
+
+// What max_fee_fraction is judged at without a fee_reference_amount
+const DEFAULT_FEE_REFERENCE_AMOUNT: f64 = 1_000.0;
+
+#[derive(Debug, Clone, PartialEq, Error, Serialize)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+pub enum SanityViolation {
+    #[error("fees are {fraction:.4} of the amount, above the {max} allowed")]
+    FeeTooHigh { fraction: f64, max: f64 },
+
+    #[error("transfer takes {secs}s, under the {min}s allowed")]
+    TooFast { secs: f64, min: f64 },
+
+    #[error("transfer takes {secs}s, over the {max}s allowed")]
+    TooSlow { secs: f64, max: f64 },
+
+    #[error("output is {ratio:.4} times the amount, above the {max} allowed")]
+    OutputTooHigh { ratio: f64, max: f64 },
+
+    #[error("liquidity of {liquidity} is above the {max} allowed")]
+    LiquidityTooHigh { liquidity: f64, max: f64 },
+}
+
+// A quote GraphUpdater kept out of the graph, see RefreshReport::rejected
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RejectedQuote {
+    pub adapter: String,
+    // "<src_chain>-><dst_chain>"
+    pub pair: String,
+    pub violation: SanityViolation,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct QuoteSanityChecker {
+    config: SanityConfig,
+}
+
+impl QuoteSanityChecker {
+    pub fn new(config: SanityConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn bounds(&self, bridge: &str) -> SanityBounds {
+        self.config.bounds(bridge)
+    }
+
+    // The first of `bridge`'s bounds `quote` breaks, for a quote of `amount` in human units of
+    // the source token. Fees are its cost in the source token, `cost_in_source` once the cost is
+    // in a quote currency, and as fixed as the graph takes them, so they're judged at `amount`
+    // or the fee_reference_amount, whichever is more. A quote without an estimated output isn't
+    // held to max_output_ratio.
+    pub fn check(&self, bridge: &str, amount: f64, quote: &BridgeEdge) -> Result<(), SanityViolation> {
+        let bounds = self.bounds(bridge);
+        let judged_at = amount.max(bounds.fee_reference_amount.unwrap_or(DEFAULT_FEE_REFERENCE_AMOUNT));
+        let fees = quote.cost_in_source.unwrap_or(quote.cost);
+        if let Some(max) = bounds.max_fee_fraction
+            && judged_at > 0.0
+            && fees / judged_at > max
+        {
+            return Err(SanityViolation::FeeTooHigh { fraction: fees / judged_at, max });
+        }
+        if let Some(min) = bounds.min_duration
+            && quote.speed < min.as_secs_f64()
+        {
+            return Err(SanityViolation::TooFast { secs: quote.speed, min: min.as_secs_f64() });
+        }
+        if let Some(max) = bounds.max_duration
+            && quote.speed > max.as_secs_f64()
+        {
+            return Err(SanityViolation::TooSlow { secs: quote.speed, max: max.as_secs_f64() });
+        }
+        if let Some(max) = bounds.max_output_ratio
+            && amount > 0.0
+            && quote.estimated_output > 0.0
+            && quote.estimated_output / amount > max
+        {
+            return Err(SanityViolation::OutputTooHigh { ratio: quote.estimated_output / amount, max });
+        }
+        if let Some(max) = bounds.max_liquidity
+            && quote.liquidity > max
+        {
+            return Err(SanityViolation::LiquidityTooHigh { liquidity: quote.liquidity, max });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::HashMap, time::Duration};
+
+    fn quote() -> BridgeEdge {
+        BridgeEdge {
+            cost: 2.0,
+            speed: 120.0,
+            liquidity: 1_000_000.0,
+            risk: 0.1,
+            estimated_output: 98.0,
+            ..BridgeEdge::default()
+        }
+    }
+
+    #[test]
+    fn each_bound_rejects_what_it_covers() {
+        let defaults = SanityBounds {
+            max_fee_fraction: Some(0.05),
+            min_duration: Some(Duration::from_secs(10)),
+            max_duration: Some(Duration::from_secs(3_600)),
+            max_output_ratio: Some(1.0),
+            max_liquidity: Some(1e9),
+            fee_reference_amount: Some(100.0),
+        };
+        let checker = QuoteSanityChecker::new(SanityConfig { defaults, bridges: HashMap::new() });
+        assert_eq!(checker.check("stargate", 100.0, &quote()), Ok(()));
+
+        let cases = [
+            (BridgeEdge { cost: 10.0, ..quote() }, SanityViolation::FeeTooHigh { fraction: 0.1, max: 0.05 }),
+            (BridgeEdge { speed: 1.0, ..quote() }, SanityViolation::TooFast { secs: 1.0, min: 10.0 }),
+            (BridgeEdge { speed: 7_200.0, ..quote() }, SanityViolation::TooSlow { secs: 7_200.0, max: 3_600.0 }),
+            (BridgeEdge { estimated_output: 150.0, ..quote() }, SanityViolation::OutputTooHigh { ratio: 1.5, max: 1.0 }),
+            (BridgeEdge { liquidity: 1e15, ..quote() }, SanityViolation::LiquidityTooHigh { liquidity: 1e15, max: 1e9 }),
+        ];
+        for (quote, violation) in cases {
+            assert_eq!(checker.check("stargate", 100.0, &quote), Err(violation));
+        }
+        // No estimated output to hold against the amount
+        assert_eq!(checker.check("stargate", 100.0, &BridgeEdge { estimated_output: 0.0, ..quote() }), Ok(()));
+    }
+
+    #[test]
+    fn fees_are_judged_in_the_source_token_at_the_reference_amount() {
+        let defaults = SanityBounds { max_fee_fraction: Some(0.05), ..SanityBounds::default() };
+        let checker = QuoteSanityChecker::new(SanityConfig { defaults, bridges: HashMap::new() });
+
+        // A fixed fee of 2 is most of a one-token probe, but 0.2% of the 1000 fees are judged at
+        assert_eq!(checker.check("stargate", 1.0, &quote()), Ok(()));
+        assert_eq!(
+            checker.check("stargate", 1.0, &BridgeEdge { cost: 60.0, ..quote() }),
+            Err(SanityViolation::FeeTooHigh { fraction: 0.06, max: 0.05 })
+        );
+        // A cost of 60 in the quote currency that's 20 of the source token is within bounds
+        assert_eq!(checker.check("stargate", 1.0, &BridgeEdge { cost: 60.0, cost_in_source: Some(20.0), ..quote() }), Ok(()));
+    }
+
+    #[test]
+    fn bridges_override_the_defaults_they_set() {
+        let defaults = SanityBounds {
+            max_duration: Some(Duration::from_secs(3_600)),
+            max_fee_fraction: Some(0.05),
+            fee_reference_amount: Some(100.0),
+            ..SanityBounds::default()
+        };
+        let slow = SanityBounds { max_duration: Some(Duration::from_secs(86_400)), ..SanityBounds::default() };
+        let checker = QuoteSanityChecker::new(SanityConfig { defaults, bridges: HashMap::from([("wormhole".to_string(), slow)]) });
+
+        let day_long = BridgeEdge { speed: 20_000.0, ..quote() };
+        assert!(matches!(checker.check("stargate", 100.0, &day_long), Err(SanityViolation::TooSlow { .. })));
+        assert_eq!(checker.check("wormhole", 100.0, &day_long), Ok(()));
+        // The fee bound still comes from the defaults
+        assert!(matches!(checker.check("wormhole", 100.0, &BridgeEdge { cost: 10.0, ..quote() }), Err(SanityViolation::FeeTooHigh { .. })));
+        // Nothing is checked without bounds
+        assert_eq!(QuoteSanityChecker::default().check("stargate", 100.0, &BridgeEdge { cost: 1e9, ..quote() }), Ok(()));
+    }
+}
@@ -0,0 +1,734 @@
+// Runs GraphUpdater refreshes every global.update_interval, each quoting the pairs whose cadence
+// is due
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use polypath_graph::CompactionOptions;
+use serde::Serialize;
+use tokio::{
+    sync::{broadcast, mpsc},
+    task::JoinHandle,
+    time::{Instant, MissedTickBehavior},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    adapters::{SupportedPair, unix_now},
+    digest::{DigestNotifier, RefreshDigest},
+    quarantine::QuarantinedPair,
+    updater::{GraphUpdater, RefreshReport},
+};
+
+// Reports kept for subscribers that fall behind
+const REPORT_BUFFER: usize = 16;
+
+// New pair list for a configured bridge, applied before the next tick. Sent by whatever
+// watches the config; an empty list stops refreshing the bridge.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PairsChange {
+    pub bridge: String,
+    pub pairs: Vec<SupportedPair>,
+}
+
+// What a scheduler did until it was shut down
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct SchedulerStats {
+    pub ticks: u64,
+    pub refreshes: u64,
+    // Ticks that found the previous refresh still running
+    pub skipped: u64,
+    // Refreshes dropped at shutdown before they completed
+    pub interrupted: u64,
+    pub compactions: u64,
+}
+
+// Refreshes the graph on a fixed interval. The first refresh starts after a random delay of up
+// to `max_jitter`, so replicas started together don't all hit the bridges at once.
+// Every tick expires edges and checks coverage, but a pair is only quoted once its cadence has
+// passed since it last was: hot pairs every tick, normal and cold ones every [refresh] multiple
+// of ticks, pairs with a refresh_interval of their own on that. Pair changes that reclassify a
+// pair take effect from the next tick. A cadence shorter than the interval, as for pairs whose
+// quotes expire sooner, see GraphUpdater::cadence, has the ticks come that much more often.
+#[derive(Debug)]
+pub struct RefreshScheduler {
+    updater: Arc<GraphUpdater>,
+    interval: Duration,
+    max_jitter: Duration,
+    reports: broadcast::Sender<RefreshReport>,
+    changes: mpsc::UnboundedSender<PairsChange>,
+    pending_changes: mpsc::UnboundedReceiver<PairsChange>,
+    // How often to compact the graph and what to drop, see `with_compaction`
+    compaction: Option<(Duration, CompactionOptions)>,
+    // Where refresh digests go, see `with_digests`
+    digests: Option<DigestNotifier>,
+}
+
+impl RefreshScheduler {
+    // Ticks every global.update_interval of the updater's config, with up to a tenth of that
+    // as start jitter, sending digests as [digest] says
+    pub fn new(updater: Arc<GraphUpdater>) -> Self {
+        let interval = updater.dal().config().global.update_interval;
+        let digests = updater.dal().digest_notifier();
+        let (reports, _) = broadcast::channel(REPORT_BUFFER);
+        let (changes, pending_changes) = mpsc::unbounded_channel();
+        Self {
+            updater,
+            interval,
+            max_jitter: interval / 10,
+            reports,
+            changes,
+            pending_changes,
+            compaction: None,
+            digests,
+        }
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn with_max_jitter(mut self, max_jitter: Duration) -> Self {
+        self.max_jitter = max_jitter;
+        self
+    }
+
+    // Compacts the graph every `every`, between refreshes. The configured pairs' tokens are
+    // pinned on top of `options`' own, so intents keep resolving to them.
+    pub fn with_compaction(mut self, every: Duration, options: CompactionOptions) -> Self {
+        self.compaction = Some((every, options));
+        self
+    }
+
+    // Sends a digest of each refresh the notifier's cadence picks. Digests are queued for a
+    // task of their own, so a slow webhook doesn't hold up the refreshes.
+    pub fn with_digests(mut self, digests: DigestNotifier) -> Self {
+        self.digests = Some(digests);
+        self
+    }
+
+    // Receives the report of every refresh that completes after this call
+    pub fn subscribe(&self) -> broadcast::Receiver<RefreshReport> {
+        self.reports.subscribe()
+    }
+
+    // Sender for pair list changes; it keeps working after the scheduler is spawned
+    pub fn changes(&self) -> mpsc::UnboundedSender<PairsChange> {
+        self.changes.clone()
+    }
+
+    // Pairs the refreshes only re-probe with backoff since they kept failing
+    pub fn quarantined(&self) -> Vec<QuarantinedPair> {
+        self.updater.quarantined()
+    }
+
+    pub fn spawn(self, shutdown: CancellationToken) -> JoinHandle<SchedulerStats> {
+        tokio::spawn(self.run(shutdown))
+    }
+
+    pub fn spawn_draining(self, stop: CancellationToken, abort: CancellationToken) -> JoinHandle<SchedulerStats> {
+        tokio::spawn(self.run_draining(stop, abort))
+    }
+
+    // Ticks until `shutdown` is cancelled. A refresh still running then is dropped, which
+    // leaves the graph as the refresh's last applied quote left it.
+    pub async fn run(self, shutdown: CancellationToken) -> SchedulerStats {
+        self.run_draining(shutdown.clone(), shutdown).await
+    }
+
+    // Time between ticks: the update interval, or the shortest cadence when that's shorter
+    fn period(&self) -> Duration {
+        let shortest = self.updater.cadences(self.interval).first().copied().unwrap_or(self.interval);
+        self.interval.min(shortest).max(Duration::from_millis(1))
+    }
+
+    // As `run`, ticking until `stop` is cancelled, but a refresh still running then may finish
+    // until `abort` is cancelled too
+    pub async fn run_draining(mut self, stop: CancellationToken, abort: CancellationToken) -> SchedulerStats {
+        let mut stats = SchedulerStats::default();
+        let jitter = self.max_jitter.mul_f64(fastrand::f64());
+        let mut period = self.period();
+        let mut ticks = tokio::time::interval_at(Instant::now() + jitter, period);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut last_tick = Instant::now();
+        let mut running: Option<JoinHandle<()>> = None;
+        // Whether the running refresh was seen to finish
+        let mut settled = true;
+        // When the pairs of each cadence were last quoted
+        let mut last_quoted: HashMap<Duration, Instant> = HashMap::new();
+        let mut last_compacted = Instant::now();
+        let digests = self.digests.take().map(|digests| Arc::new(digests.start(self.updater.dal().logger().clone())));
+
+        loop {
+            let now = tokio::select! {
+                _ = stop.cancelled() => break,
+                now = ticks.tick() => now,
+                // The quotes a refresh applied may have shortened a cadence
+                _ = async { running.as_mut().expect("a refresh is running").await }, if !settled => {
+                    settled = true;
+                    if self.period() != period {
+                        period = self.period();
+                        ticks = tokio::time::interval_at(last_tick + period, period);
+                        ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+                    }
+                    continue;
+                }
+            };
+            last_tick = now;
+            stats.ticks += 1;
+            if running.as_ref().is_some_and(|refresh| !refresh.is_finished()) {
+                stats.skipped += 1;
+                self.updater.dal().logger().warn("previous graph refresh is still running, skipping this tick");
+                continue;
+            }
+
+            while let Ok(change) = self.pending_changes.try_recv() {
+                self.updater.set_pairs(&change.bridge, change.pairs);
+            }
+            if let Some((every, options)) = &self.compaction
+                && now - last_compacted >= *every
+            {
+                last_compacted = now;
+                stats.compactions += 1;
+                let report = self.updater.graph().compact(&options.clone().with_pinned(self.updater.pair_nodes()));
+                self.updater.dal().logger().info_with("graph compacted", &[
+                    ("edges", &report.edges),
+                    ("nodes", &report.nodes),
+                    ("entries", &report.entries),
+                    ("bytes", &report.bytes),
+                ]);
+            }
+            let due: Vec<Duration> = self
+                .updater
+                .cadences(self.interval)
+                .into_iter()
+                .filter(|cadence| last_quoted.get(cadence).is_none_or(|last| now - *last >= *cadence))
+                .collect();
+            for cadence in &due {
+                last_quoted.insert(*cadence, now);
+            }
+            stats.refreshes += 1;
+            let (updater, interval, cycle) = (Arc::clone(&self.updater), self.interval, stats.refreshes);
+            let (reports, digests) = (self.reports.clone(), digests.clone());
+            running = Some(tokio::spawn(async move {
+                let report = updater.refresh_due(interval, &due).await;
+                if let Some(digests) = digests {
+                    digests.offer(RefreshDigest::new(cycle, &report, updater.graph().coverage().as_ref(), unix_now()));
+                }
+                // No subscribers is fine
+                let _ = reports.send(report);
+            }));
+            settled = false;
+        }
+
+        if let Some(mut refresh) = running.filter(|refresh| !refresh.is_finished()) {
+            tokio::select! {
+                biased;
+                _ = abort.cancelled() => {
+                    refresh.abort();
+                    // A refresh that got to complete anyway isn't counted
+                    if refresh.await.is_err() {
+                        stats.interrupted += 1;
+                    }
+                }
+                _ = &mut refresh => {}
+            }
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        QUARANTINE_BACKOFF,
+        adapters::{self, BridgeEdge, DEFAULT_QUOTE_VALIDITY, mock::MockAdapter},
+        alerts::{Alert, Notifier, tests::RecordingNotifier},
+        digest::DigestCadence,
+        updater::tests::{USDC_ARBITRUM, USDC_BASE, USDC_ETHEREUM, USDC_POLYGON, configured_updater, updater},
+    };
+    use async_trait::async_trait;
+    use polypathroute_core::{ConfigFormat, ConfigManager, RefreshPriority};
+    use std::collections::BTreeMap;
+
+    // An updater for `bridge` whose adapter quotes all three configured pairs, and the adapter
+    fn quoting_everything(bridge: &'static str) -> (Arc<MockAdapter>, Arc<GraphUpdater>) {
+        let quote = |from: &str, to: &str| BridgeEdge {
+            from: from.to_string(),
+            to: to.to_string(),
+            cost: 1.0,
+            speed: 60.0,
+            liquidity: 1_000_000.0,
+            risk: 0.1,
+            valid_until: Some(unix_now() + 86_400),
+            ..BridgeEdge::default()
+        };
+        let mock = Arc::new(
+            MockAdapter::named(bridge)
+                .with_quote("ethereum", "polygon", quote("ethereum", "polygon"))
+                .with_quote("polygon", "arbitrum", quote("polygon", "arbitrum"))
+                .with_quote("ethereum", "base", quote("ethereum", "base")),
+        );
+        let registered = Arc::clone(&mock);
+        adapters::register(bridge, move |_| Ok(Box::new(Arc::clone(&registered))));
+        (mock, Arc::new(configured_updater(bridge)))
+    }
+
+    // Never answers
+    #[derive(Debug)]
+    struct HangingNotifier;
+
+    #[async_trait]
+    impl Notifier for HangingNotifier {
+        fn name(&self) -> &str {
+            "hanging"
+        }
+
+        async fn notify(&self, _alert: &Alert) -> anyhow::Result<()> {
+            std::future::pending().await
+        }
+
+        async fn notify_digest(&self, _digest: &RefreshDigest) -> anyhow::Result<()> {
+            std::future::pending().await
+        }
+    }
+
+    fn requests(mock: &MockAdapter, dst_chain: &str) -> usize {
+        mock.requests().iter().filter(|request| request.dst_chain == dst_chain).count()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn refreshes_follow_the_update_interval() {
+        let scheduler = RefreshScheduler::new(Arc::new(updater("ticker", Duration::ZERO))).with_max_jitter(Duration::ZERO);
+        assert_eq!(scheduler.interval, Duration::from_secs(60));
+        let mut reports = scheduler.subscribe();
+        let shutdown = CancellationToken::new();
+        let started = Instant::now();
+        let handle = scheduler.spawn(shutdown.clone());
+
+        let mut arrivals = Vec::new();
+        for _ in 0..3 {
+            reports.recv().await.unwrap();
+            arrivals.push(started.elapsed().as_secs());
+        }
+        assert_eq!(arrivals, [0, 60, 120]);
+
+        shutdown.cancel();
+        assert_eq!(handle.await.unwrap(), SchedulerStats { ticks: 3, refreshes: 3, skipped: 0, interrupted: 0, compactions: 0 });
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn slow_refreshes_skip_ticks_and_pair_changes_apply_between_them() {
+        // Quotes run in parallel, so a refresh takes one quote's latency
+        let updater = Arc::new(updater("laggard", Duration::from_secs(150)));
+        let kept: Vec<SupportedPair> = updater.dal()
+            .supported_pairs_for("laggard")
+            .into_iter()
+            .filter(|pair| pair.dst_chain == "polygon")
+            .collect();
+        let scheduler = RefreshScheduler::new(Arc::clone(&updater)).with_max_jitter(Duration::ZERO);
+        let mut reports = scheduler.subscribe();
+        let changes = scheduler.changes();
+        let shutdown = CancellationToken::new();
+        let started = Instant::now();
+        let handle = scheduler.spawn(shutdown.clone());
+
+        let first = reports.recv().await.unwrap();
+        assert_eq!(started.elapsed().as_secs(), 150);
+        assert_eq!((first.added, first.failed), (2, 1));
+
+        changes.send(PairsChange { bridge: "laggard".to_string(), pairs: kept }).unwrap();
+        let second = reports.recv().await.unwrap();
+        // Ticks at 60 and 120 found the first refresh running; the second ran from 180, so
+        // 240 and 300 were skipped too
+        assert_eq!(started.elapsed().as_secs(), 330);
+        assert_eq!((second.updated, second.failed), (1, 0));
+
+        shutdown.cancel();
+        assert_eq!(handle.await.unwrap(), SchedulerStats { ticks: 6, refreshes: 2, skipped: 4, interrupted: 0, compactions: 0 });
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn compaction_runs_on_its_own_cadence_and_keeps_pair_tokens() {
+        let updater = Arc::new(updater("compactor", Duration::ZERO));
+        let graph = Arc::clone(updater.graph());
+        // The configured base token, which the bridge never quotes, and a token since removed
+        let base = graph.get_or_create_asset_node("base", USDC_BASE, "USDC");
+        assert_eq!(base, updater.asset_node_id("base", USDC_BASE));
+        let eth = graph.get_or_create_asset_node("ethereum", &USDC_ETHEREUM.to_lowercase(), "USDC");
+        let retired = graph.get_or_create_asset_node("ethereum", "0xretired", "OLD");
+        let metrics = polypath_graph::EdgeMetrics { cost: 1.0, speed: 60.0, liquidity: 1000.0, risk: 0.1 };
+        graph.add_edge(eth, retired, "compactor", metrics, None, None).unwrap();
+        graph.set_edge_active(eth, retired, "compactor", false);
+
+        let options = CompactionOptions::default().with_inactive_for(Duration::ZERO);
+        let scheduler = RefreshScheduler::new(Arc::clone(&updater))
+            .with_max_jitter(Duration::ZERO)
+            .with_compaction(Duration::from_secs(600), options);
+        let mut reports = scheduler.subscribe();
+        let shutdown = CancellationToken::new();
+        let handle = scheduler.spawn(shutdown.clone());
+
+        for _ in 0..10 {
+            reports.recv().await.unwrap();
+        }
+        assert!(graph.get_node(retired).is_some());
+        // The tick at 600s compacts before it refreshes
+        reports.recv().await.unwrap();
+        assert!(graph.get_node(retired).is_none());
+        assert!(graph.get_node(base).is_some());
+        assert_eq!(graph.edge_count(), 2);
+
+        shutdown.cancel();
+        assert_eq!(handle.await.unwrap(), SchedulerStats { ticks: 11, refreshes: 11, skipped: 0, interrupted: 0, compactions: 1 });
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shutdown_drops_a_refresh_in_progress() {
+        let updater = Arc::new(updater("stopper", Duration::from_secs(150)));
+        let scheduler = RefreshScheduler::new(Arc::clone(&updater)).with_max_jitter(Duration::ZERO);
+        let mut reports = scheduler.subscribe();
+        let shutdown = CancellationToken::new();
+        let handle = scheduler.spawn(shutdown.clone());
+
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        shutdown.cancel();
+        assert_eq!(handle.await.unwrap(), SchedulerStats { ticks: 1, refreshes: 1, skipped: 0, interrupted: 1, compactions: 0 });
+        assert!(matches!(reports.try_recv(), Err(broadcast::error::TryRecvError::Closed)));
+        assert_eq!(updater.graph().edge_count(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failing_pairs_drop_to_the_probe_schedule() {
+        // Quotes ethereum -> polygon -> arbitrum; ethereum -> base is configured but never served
+        let quote = |from: &str, to: &str| BridgeEdge {
+            from: from.to_string(),
+            to: to.to_string(),
+            cost: 1.0,
+            speed: 60.0,
+            liquidity: 1_000_000.0,
+            risk: 0.1,
+            valid_until: Some(unix_now() + 86_400),
+            ..BridgeEdge::default()
+        };
+        let mock = Arc::new(
+            MockAdapter::named("flaky")
+                .with_quote("ethereum", "polygon", quote("ethereum", "polygon"))
+                .with_quote("polygon", "arbitrum", quote("polygon", "arbitrum")),
+        );
+        let registered = Arc::clone(&mock);
+        adapters::register("flaky", move |_| Ok(Box::new(Arc::clone(&registered))));
+        let updater = Arc::new(configured_updater("flaky"));
+        // Every pair quoted on every tick
+        let hot = updater.dal().supported_pairs_for("flaky").into_iter().map(|pair| SupportedPair { priority: RefreshPriority::Hot, ..pair });
+        updater.set_pairs("flaky", hot.collect());
+        let scheduler = RefreshScheduler::new(Arc::clone(&updater)).with_max_jitter(Duration::ZERO);
+        let mut reports = scheduler.subscribe();
+        let shutdown = CancellationToken::new();
+        let started = Instant::now();
+        let handle = scheduler.spawn(shutdown.clone());
+
+        // Refreshes at 0, 60, ... 3000s. The base pair fails the first five, is quarantined at
+        // 240s and re-probed 10m later, at 840s, then 30m after that, at 2640s.
+        let mut skipped = Vec::new();
+        for _ in 0..51 {
+            skipped.push(reports.recv().await.unwrap().quarantined);
+        }
+        assert_eq!(started.elapsed().as_secs(), 3000);
+        shutdown.cancel();
+        handle.await.unwrap();
+
+        let requests = |dst_chain: &str| mock.requests().iter().filter(|request| request.dst_chain == dst_chain).count();
+        assert_eq!((requests("polygon"), requests("arbitrum")), (51, 51));
+        assert_eq!(requests("base"), 7);
+        assert_eq!(skipped.iter().filter(|skipped| **skipped == 1).count(), 51 - 7);
+        assert_eq!(updater.graph().active_edge_count(), 2);
+
+        let quarantined = RefreshScheduler::new(Arc::clone(&updater)).quarantined();
+        assert_eq!(quarantined.len(), 1);
+        assert_eq!((quarantined[0].bridge.as_str(), quarantined[0].pair.dst_chain.as_str()), ("flaky", "base"));
+        assert_eq!(quarantined[0].failures, 7);
+        assert!(quarantined[0].last_error.contains("base"), "{}", quarantined[0].last_error);
+        // The third probe waits the longest backoff, from the second at 2640s
+        let next_probe_in = quarantined[0].next_probe - unix_now();
+        assert!(next_probe_in.abs_diff(QUARANTINE_BACKOFF[2].as_secs() - 360) <= 1, "{}", next_probe_in);
+
+        // Reconfiguring the bridge probes the pair again at once
+        updater.set_pairs("flaky", updater.dal().supported_pairs_for("flaky"));
+        assert!(updater.quarantined().is_empty());
+        assert_eq!(updater.refresh_once().await.quarantined, 0);
+        assert_eq!(requests("base"), 8);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pairs_are_quoted_on_their_priority_cadence() {
+        let (mock, updater) = quoting_everything("cadenced");
+        let classed = updater.dal().supported_pairs_for("cadenced").into_iter().map(|pair| {
+            let priority = match pair.dst_chain.as_str() {
+                "polygon" => RefreshPriority::Hot,
+                "arbitrum" => RefreshPriority::Normal,
+                _ => RefreshPriority::Cold,
+            };
+            SupportedPair { priority, ..pair }
+        });
+        updater.set_pairs("cadenced", classed.collect());
+        let scheduler = RefreshScheduler::new(Arc::clone(&updater)).with_max_jitter(Duration::ZERO);
+        let mut reports = scheduler.subscribe();
+        let shutdown = CancellationToken::new();
+        let started = Instant::now();
+        let handle = scheduler.spawn(shutdown.clone());
+
+        // Three hours of minute ticks
+        let mut fetched = Vec::new();
+        for _ in 0..180 {
+            fetched.push(reports.recv().await.unwrap().fetched);
+        }
+        assert_eq!(started.elapsed().as_secs(), 179 * 60);
+        shutdown.cancel();
+        assert_eq!(handle.await.unwrap().refreshes, 180);
+
+        assert_eq!((requests(&mock, "polygon"), requests(&mock, "arbitrum"), requests(&mock, "base")), (180, 60, 18));
+        let total = |priority: RefreshPriority| fetched.iter().map(|counts| counts.get(priority)).sum::<usize>();
+        assert_eq!((total(RefreshPriority::Hot), total(RefreshPriority::Normal), total(RefreshPriority::Cold)), (180, 60, 18));
+        // Every pair is quoted on the first tick, the cold one again ten ticks later
+        assert_eq!(fetched[0].total(), 3);
+        assert_eq!((fetched[1].total(), fetched[3].hot + fetched[3].normal, fetched[10].cold), (1, 2, 1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn normal_pairs_are_quoted_again_before_default_quotes_expire() {
+        // As adapters stamp quotes whose API gives no expiry
+        let quote = |from: &str, to: &str| BridgeEdge {
+            from: from.to_string(),
+            to: to.to_string(),
+            cost: 1.0,
+            speed: 60.0,
+            liquidity: 1_000_000.0,
+            risk: 0.1,
+            quoted_at: unix_now(),
+            ..BridgeEdge::default()
+        }.with_default_validity(DEFAULT_QUOTE_VALIDITY);
+        let mock = Arc::new(
+            MockAdapter::named("expiring")
+                .with_quote("ethereum", "polygon", quote("ethereum", "polygon"))
+                .with_quote("polygon", "arbitrum", quote("polygon", "arbitrum"))
+                .with_quote("ethereum", "base", quote("ethereum", "base")),
+        );
+        let registered = Arc::clone(&mock);
+        adapters::register("expiring", move |_| Ok(Box::new(Arc::clone(&registered))));
+        let updater = Arc::new(configured_updater("expiring"));
+        let interval = Duration::from_secs(60);
+        // Every pair is normal, quoted every third minute by the [refresh] defaults
+        assert_eq!(updater.cadences(interval), [Duration::from_secs(180)]);
+        assert_eq!(updater.bridge_cadences(interval), std::collections::BTreeMap::from([("expiring".to_string(), Duration::from_secs(180))]));
+        let scheduler = RefreshScheduler::new(Arc::clone(&updater)).with_max_jitter(Duration::ZERO);
+        let mut reports = scheduler.subscribe();
+        let shutdown = CancellationToken::new();
+        let started = Instant::now();
+        let handle = scheduler.spawn(shutdown.clone());
+
+        let mut refreshes = Vec::new();
+        for _ in 0..5 {
+            let report = reports.recv().await.unwrap();
+            refreshes.push((started.elapsed().as_secs(), report.fetched.normal));
+        }
+        shutdown.cancel();
+        handle.await.unwrap();
+        // The first quotes hold for a minute, so from then on every pair is quoted within it
+        assert_eq!(updater.cadences(interval), [Duration::from_secs(45)]);
+        assert_eq!(updater.bridge_cadences(interval)["expiring"], Duration::from_secs(45));
+        assert_eq!(refreshes, [(0, 3), (45, 3), (90, 3), (135, 3), (180, 3)]);
+        assert_eq!(requests(&mock, "polygon"), 5);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reclassified_pairs_change_cadence_without_a_restart() {
+        let (mock, updater) = quoting_everything("reclassified");
+        let scheduler = RefreshScheduler::new(Arc::clone(&updater)).with_max_jitter(Duration::ZERO);
+        let mut reports = scheduler.subscribe();
+        let changes = scheduler.changes();
+        let shutdown = CancellationToken::new();
+        let handle = scheduler.spawn(shutdown.clone());
+
+        // Configured without priorities, every pair is normal: quoted at 0 and 180s
+        for _ in 0..6 {
+            reports.recv().await.unwrap();
+        }
+        assert_eq!((requests(&mock, "polygon"), requests(&mock, "arbitrum"), requests(&mock, "base")), (2, 2, 2));
+
+        let reloaded = ConfigManager::from_str(&format!(r#"
+            [bridges.reclassified]
+            base_url = "https://reclassified.test"
+            chains = ["ethereum", "polygon", "arbitrum", "base"]
+            [[bridges.reclassified.pairs]]
+            source_chain = "ethereum"
+            source_address = "{0}"
+            source_token_name = "USDC"
+            destination_chain = "polygon"
+            destination_address = "{1}"
+            destination_token_name = "USDC"
+            [[bridges.reclassified.pairs]]
+            source_chain = "polygon"
+            source_address = "{1}"
+            source_token_name = "USDC"
+            destination_chain = "arbitrum"
+            destination_address = "{2}"
+            destination_token_name = "USDC"
+            priority = "cold"
+            [[bridges.reclassified.pairs]]
+            source_chain = "ethereum"
+            source_address = "{0}"
+            source_token_name = "USDC"
+            destination_chain = "base"
+            destination_address = "{3}"
+            destination_token_name = "USDC"
+            priority = "hot"
+        "#, USDC_ETHEREUM, USDC_POLYGON, USDC_ARBITRUM, USDC_BASE), ConfigFormat::Toml).unwrap();
+        let pairs = adapters::pairs_from_config(&reloaded.bridges["reclassified"]);
+        changes.send(PairsChange { bridge: "reclassified".to_string(), pairs }).unwrap();
+
+        // Ticks at 360 to 660s: normal pairs are due at 360 and 540, the newly cold one at 360
+        // only, the newly hot one on every tick
+        for _ in 0..6 {
+            reports.recv().await.unwrap();
+        }
+        assert_eq!((requests(&mock, "polygon"), requests(&mock, "arbitrum"), requests(&mock, "base")), (4, 3, 8));
+        shutdown.cancel();
+        handle.await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn digests_sum_up_every_nth_refresh() {
+        let recording = Arc::new(RecordingNotifier::default());
+        let digests = DigestNotifier::new(DigestCadence::Every(2)).with_notifier(Arc::clone(&recording) as Arc<dyn Notifier>);
+        let scheduler = RefreshScheduler::new(Arc::new(updater("summarized", Duration::ZERO)))
+            .with_max_jitter(Duration::ZERO)
+            .with_digests(digests);
+        let mut reports = scheduler.subscribe();
+        let shutdown = CancellationToken::new();
+        let handle = scheduler.spawn(shutdown.clone());
+
+        for _ in 0..5 {
+            reports.recv().await.unwrap();
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        shutdown.cancel();
+        handle.await.unwrap();
+
+        let sent = recording.digests.lock().unwrap().clone();
+        assert_eq!(sent.iter().map(|digest| digest.cycle).collect::<Vec<_>>(), [1, 3, 5]);
+        // Two of the three configured pairs are quoted; base never is
+        assert_eq!((sent[0].pairs_refreshed, sent[0].failed, sent[0].edges_deactivated), (2, 1, 0));
+        assert_eq!(sent[0].failures_by_adapter, BTreeMap::from([("summarized".to_string(), 1)]));
+        assert!(sent[0].coverage_pct.is_some_and(|pct| (pct - 200.0 / 3.0).abs() < 1e-9), "{:?}", sent[0].coverage_pct);
+        assert_eq!(sent[0].slowest_adapter.as_ref().map(|slowest| slowest.adapter.as_str()), Some("summarized"));
+        assert!(recording.alerts.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failure_digests_only_go_out_above_the_threshold() {
+        let updater = Arc::new(updater("threshold", Duration::ZERO));
+        let kept: Vec<SupportedPair> = updater.dal()
+            .supported_pairs_for("threshold")
+            .into_iter()
+            .filter(|pair| pair.dst_chain != "base")
+            .collect();
+        let recording = Arc::new(RecordingNotifier::default());
+        let digests = DigestNotifier::new(DigestCadence::FailuresAbove(0)).with_notifier(Arc::clone(&recording) as Arc<dyn Notifier>);
+        let scheduler = RefreshScheduler::new(Arc::clone(&updater)).with_max_jitter(Duration::ZERO).with_digests(digests);
+        let mut reports = scheduler.subscribe();
+        let changes = scheduler.changes();
+        let shutdown = CancellationToken::new();
+        let handle = scheduler.spawn(shutdown.clone());
+
+        assert_eq!(reports.recv().await.unwrap().failed, 1);
+        // Without the pair the bridge doesn't serve, later refreshes don't fail
+        changes.send(PairsChange { bridge: "threshold".to_string(), pairs: kept }).unwrap();
+        for _ in 0..3 {
+            assert_eq!(reports.recv().await.unwrap().failed, 0);
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        shutdown.cancel();
+        handle.await.unwrap();
+
+        let sent = recording.digests.lock().unwrap().clone();
+        assert_eq!(sent.iter().map(|digest| (digest.cycle, digest.failed)).collect::<Vec<_>>(), [(1, 1)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_hanging_webhook_does_not_delay_refreshes() {
+        let digests = DigestNotifier::new(DigestCadence::Every(1))
+            .with_notifier(Arc::new(HangingNotifier))
+            .with_queue_capacity(1);
+        let scheduler = RefreshScheduler::new(Arc::new(updater("stalled", Duration::ZERO)))
+            .with_max_jitter(Duration::ZERO)
+            .with_digests(digests);
+        let mut reports = scheduler.subscribe();
+        let shutdown = CancellationToken::new();
+        let started = Instant::now();
+        let handle = scheduler.spawn(shutdown.clone());
+
+        // The first digest never gets delivered and the second fills the queue; the rest are dropped
+        let mut arrivals = Vec::new();
+        for _ in 0..4 {
+            reports.recv().await.unwrap();
+            arrivals.push(started.elapsed().as_secs());
+        }
+        assert_eq!(arrivals, [0, 60, 120, 180]);
+
+        shutdown.cancel();
+        assert_eq!(handle.await.unwrap(), SchedulerStats { ticks: 4, refreshes: 4, skipped: 0, interrupted: 0, compactions: 0 });
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_deliveries_are_retried_with_backoff() {
+        // Fails its first two digests
+        #[derive(Debug, Default)]
+        struct Flaky {
+            attempts: std::sync::Mutex<Vec<Instant>>,
+            recording: RecordingNotifier,
+        }
+
+        #[async_trait]
+        impl Notifier for Flaky {
+            fn name(&self) -> &str {
+                "flaky"
+            }
+
+            async fn notify(&self, alert: &Alert) -> anyhow::Result<()> {
+                self.recording.notify(alert).await
+            }
+
+            async fn notify_digest(&self, digest: &RefreshDigest) -> anyhow::Result<()> {
+                let mut attempts = self.attempts.lock().unwrap();
+                attempts.push(Instant::now());
+                if attempts.len() <= 2 {
+                    anyhow::bail!("webhook returned 503");
+                }
+                self.recording.digests.lock().unwrap().push(digest.clone());
+                Ok(())
+            }
+        }
+
+        let flaky = Arc::new(Flaky::default());
+        let digests = DigestNotifier::new(DigestCadence::Every(1))
+            .with_notifier(Arc::clone(&flaky) as Arc<dyn Notifier>)
+            .with_backoff(Duration::from_secs(2));
+        let scheduler = RefreshScheduler::new(Arc::new(updater("retried", Duration::ZERO)))
+            .with_max_jitter(Duration::ZERO)
+            .with_digests(digests);
+        let mut reports = scheduler.subscribe();
+        let shutdown = CancellationToken::new();
+        let started = Instant::now();
+        let handle = scheduler.spawn(shutdown.clone());
+
+        reports.recv().await.unwrap();
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        shutdown.cancel();
+        handle.await.unwrap();
+
+        let attempts: Vec<u64> = flaky.attempts.lock().unwrap().iter().map(|at| (*at - started).as_secs()).collect();
+        assert_eq!(attempts, [0, 2, 6]);
+        assert_eq!(flaky.recording.digests.lock().unwrap().len(), 1);
+    }
+}
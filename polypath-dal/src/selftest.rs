@@ -0,0 +1,321 @@
+// A startup check of the whole pipeline that never leaves the machine: the config, the contexts,
+// every adapter parsing its bundled responses, a graph built from them and checked for broken
+// invariants, a route search over it, and the cache and persistence round-trips
+
+use std::{
+    sync::{Arc, atomic::{AtomicUsize, Ordering}},
+    time::Duration,
+};
+use polypath_graph::{EdgeMetrics, Graph, RouteIntent, RouteOptions, Router, RoutingEngine, RoutingParams, ScoringEngine};
+use polypathroute_core::{CacheManager, ConfigManager, CoreContext, LoggingManager, PersistenceManager};
+use serde::Serialize;
+
+use crate::{
+    DalContext,
+    adapters::{BridgeEdge, DynBridgeAdapter, QuoteRequest},
+};
+
+// Distinguishes the scratch directories of self-tests running at once in one process
+static SCRATCH_DIRS: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StepStatus {
+    Pass,
+    Fail,
+    // A step it needs failed
+    Skip,
+}
+
+impl StepStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StepStatus::Pass => "pass",
+            StepStatus::Fail => "fail",
+            StepStatus::Skip => "skip",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SelfTestStep {
+    pub name: String,
+    pub status: StepStatus,
+    pub details: String,
+}
+
+// Steps in the order they ran
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SelfTestReport {
+    pub steps: Vec<SelfTestStep>,
+}
+
+impl SelfTestReport {
+    // Skipped steps don't count; they only follow a failed one
+    pub fn passed(&self) -> bool {
+        self.steps.iter().all(|step| step.status != StepStatus::Fail)
+    }
+
+    pub fn step(&self, name: &str) -> Option<&SelfTestStep> {
+        self.steps.iter().find(|step| step.name == name)
+    }
+
+    // Records a step and hands on what it produced. None for `outcome` means a step it needs failed.
+    fn record<T>(&mut self, name: &str, outcome: Option<Result<(T, String), String>>) -> Option<T> {
+        let (status, details, produced) = match outcome {
+            Some(Ok((produced, details))) => (StepStatus::Pass, details, Some(produced)),
+            Some(Err(reason)) => (StepStatus::Fail, reason, None),
+            None => (StepStatus::Skip, "an earlier step failed".to_string(), None),
+        };
+        self.steps.push(SelfTestStep { name: name.to_string(), status, details });
+        produced
+    }
+}
+
+// A fixture's quote, with the bridge and request it came from
+struct ReplayedQuote {
+    bridge: String,
+    request: QuoteRequest,
+    edge: BridgeEdge,
+}
+
+// Runs every step against the config at `config_path`. Adapters only parse their bundled
+// responses, and the contexts get an in-memory store and cache, so nothing configured is
+// contacted or written to.
+pub async fn selftest(config_path: &str) -> SelfTestReport {
+    let mut report = SelfTestReport::default();
+    let config = report.record("config", Some(load_config(config_path)));
+    let dal = report.record("context", config.map(build_context));
+    let adapters = report.record("adapters", dal.as_ref().map(build_adapters));
+    let quotes = report.record("fixtures", adapters.map(replay_fixtures));
+    let graph = report.record("graph", quotes.as_ref().map(|quotes| build_graph(quotes)));
+    report.record("invariants", graph.as_ref().map(check_graph));
+    let intent = quotes.as_ref().and_then(|quotes| quotes.first()).map(|quote| intent_for(&quote.request));
+    let searchable = graph.zip(intent);
+    report.record("route", searchable.as_ref().map(|(graph, intent)| route(graph, intent)));
+    report.record("scoring", searchable.as_ref().map(|(graph, intent)| score(graph, intent)));
+    report.record("cache", Some(round_trip(&scratch_dir())));
+    report
+}
+
+fn load_config(config_path: &str) -> Result<(ConfigManager, String), String> {
+    let config = ConfigManager::load(config_path).map_err(|err| err.to_string())?;
+    let details = format!("{} bridges configured", config.bridges.len());
+    Ok((config, details))
+}
+
+fn build_context(config: ConfigManager) -> Result<(DalContext, String), String> {
+    let core = CoreContext::builder()
+        .config(config)
+        .logging(LoggingManager)
+        .persistence(PersistenceManager::new())
+        .cache(CacheManager::new())
+        .build()
+        .map_err(|err| err.to_string())?;
+    Ok((DalContext::from_core(core), "in-memory store and cache".to_string()))
+}
+
+// Sections without an implementation are noted rather than failed, as create_all_adapters skips them
+fn build_adapters(dal: &DalContext) -> Result<(Vec<(String, DynBridgeAdapter)>, String), String> {
+    let implemented = dal.adapter_names();
+    let mut bridges: Vec<&String> = dal.config().bridges.keys().collect();
+    bridges.sort();
+    let (mut built, mut failed, mut unimplemented) = (Vec::new(), Vec::new(), Vec::new());
+    for bridge in bridges {
+        if !implemented.contains(bridge) {
+            unimplemented.push(bridge.as_str());
+            continue;
+        }
+        match dal.create_adapter(bridge) {
+            Ok(adapter) => built.push((bridge.clone(), adapter)),
+            Err(err) => failed.push(format!("{}: {}", bridge, err)),
+        }
+    }
+    if !failed.is_empty() {
+        return Err(failed.join("; "));
+    }
+    if built.is_empty() {
+        return Err("no configured bridge has an adapter".to_string());
+    }
+    let mut details = format!("{} built", built.len());
+    if !unimplemented.is_empty() {
+        details.push_str(&format!(", no implementation for {}", unimplemented.join(", ")));
+    }
+    Ok((built, details))
+}
+
+fn replay_fixtures(adapters: Vec<(String, DynBridgeAdapter)>) -> Result<(Vec<ReplayedQuote>, String), String> {
+    let (mut quotes, mut failed, mut unbundled) = (Vec::new(), Vec::new(), Vec::new());
+    for (bridge, adapter) in adapters {
+        match adapter.replay_fixture() {
+            Some(fixture) => match fixture.result {
+                Ok(edge) => quotes.push(ReplayedQuote { bridge, request: fixture.request, edge }),
+                Err(err) => failed.push(format!("{}: {}", bridge, err)),
+            },
+            None => unbundled.push(bridge),
+        }
+    }
+    if !failed.is_empty() {
+        return Err(failed.join("; "));
+    }
+    if quotes.is_empty() {
+        return Err("no adapter bundles a response".to_string());
+    }
+    let mut details = format!("{} parsed", quotes.len());
+    if !unbundled.is_empty() {
+        details.push_str(&format!(", none bundled for {}", unbundled.join(", ")));
+    }
+    Ok((quotes, details))
+}
+
+fn build_graph(quotes: &[ReplayedQuote]) -> Result<(Arc<Graph>, String), String> {
+    let graph = Graph::new(16);
+    for quote in quotes {
+        let request = &quote.request;
+        let from = graph.get_or_create_asset_node(&request.src_chain, &request.src_token.to_lowercase(), "");
+        let to = graph.get_or_create_asset_node(&request.dst_chain, &request.dst_token.to_lowercase(), "");
+        let edge = &quote.edge;
+        let metrics = EdgeMetrics { cost: edge.cost, speed: edge.speed, liquidity: edge.liquidity, risk: edge.risk };
+        graph
+            .add_edge(from, to, &edge.label(&quote.bridge), metrics, edge.min_amount, edge.max_amount)
+            .map_err(|err| format!("{}: {}", quote.bridge, err))?;
+    }
+    let details = format!("{} nodes, {} edges", graph.node_count(), graph.edge_count());
+    Ok((Arc::new(graph), details))
+}
+
+fn check_graph(graph: &Arc<Graph>) -> Result<((), String), String> {
+    let violations = graph.check_invariants();
+    match violations.first() {
+        None => Ok(((), format!("{} edges checked", graph.edge_count()))),
+        Some(first) => Err(format!("{} broken, first: {}", violations.len(), first)),
+    }
+}
+
+// The first fixture's transfer
+fn intent_for(request: &QuoteRequest) -> RouteIntent {
+    RouteIntent {
+        from_chain: request.src_chain.clone(),
+        from_token: request.src_token.clone(),
+        to_chain: request.dst_chain.clone(),
+        to_token: request.dst_token.clone(),
+        amount: request.src_amount.parse().unwrap_or(1.0),
+        preference: None,
+        src_address: None,
+    }
+}
+
+fn route(graph: &Arc<Graph>, intent: &RouteIntent) -> Result<((), String), String> {
+    let outcome = Router::new(Arc::clone(graph))
+        .rank_routes(intent, &RouteOptions::default())
+        .map_err(|err| err.to_string())?;
+    let Some(best) = outcome.ranked.first() else {
+        return Err(format!("no route from {} to {}", intent.from_chain, intent.to_chain));
+    };
+    Ok(((), format!("{} to {}: {} found, best {}", intent.from_chain, intent.to_chain, outcome.ranked.len(), best.summary)))
+}
+
+fn score(graph: &Arc<Graph>, intent: &RouteIntent) -> Result<((), String), String> {
+    let router = Router::new(Arc::clone(graph));
+    let start = router.resolve(&intent.from_chain, &intent.from_token).map_err(|err| err.to_string())?;
+    let end = router.resolve(&intent.to_chain, &intent.to_token).map_err(|err| err.to_string())?;
+    let params = RoutingParams::balanced();
+    let paths = RoutingEngine::new(Arc::clone(graph), 4).find_candidate_paths(start, end, &params, 5);
+    let outcome = ScoringEngine::new().score_and_rank(paths, &params, 5).map_err(|err| err.to_string())?;
+    match outcome.ranked.first() {
+        Some(best) => Ok(((), format!("{} paths scored, best {:.4}", outcome.ranked.len(), best.score_breakdown.final_score))),
+        None => Err("no path was scored".to_string()),
+    }
+}
+
+fn scratch_dir() -> std::path::PathBuf {
+    let run = SCRATCH_DIRS.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("polypath-selftest-{}-{}", std::process::id(), run))
+}
+
+// A value through a file store, then an entry written through a cache and restored into another
+fn round_trip(dir: &std::path::Path) -> Result<((), String), String> {
+    let checked = (|| {
+        let store = PersistenceManager::open(dir).map_err(|err| err.to_string())?;
+        store.store("selftest".to_string(), "stored".to_string()).map_err(|err| err.to_string())?;
+        match store.get("selftest".to_string()).map_err(|err| err.to_string())? {
+            Some(value) if value == "stored" => {}
+            other => return Err(format!("the store returned {:?}", other)),
+        }
+        store.delete("selftest".to_string()).map_err(|err| err.to_string())?;
+
+        let cache = CacheManager::new().with_write_through(store.clone(), Duration::from_secs(3600));
+        cache.set("selftest".to_string(), "cached".to_string(), Some(60)).map_err(|err| err.to_string())?;
+        cache.flush().map_err(|err| err.to_string())?;
+        let restored = CacheManager::new();
+        restored.restore_from(&store).map_err(|err| err.to_string())?;
+        match restored.get("selftest".to_string()).map_err(|err| err.to_string())? {
+            Some(value) if value == "cached" => Ok(((), format!("round-tripped through {}", dir.display()))),
+            other => Err(format!("the restored cache returned {:?}", other)),
+        }
+    })();
+    let _ = std::fs::remove_dir_all(dir);
+    checked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn statuses(report: &SelfTestReport) -> Vec<(&str, StepStatus)> {
+        report.steps.iter().map(|step| (step.name.as_str(), step.status)).collect()
+    }
+
+    #[tokio::test]
+    async fn the_sample_config_passes_every_step() {
+        let report = selftest(concat!(env!("CARGO_MANIFEST_DIR"), "/src/config/config.toml")).await;
+
+        assert!(report.passed(), "{:#?}", report);
+        assert_eq!(
+            statuses(&report),
+            [
+                ("config", StepStatus::Pass),
+                ("context", StepStatus::Pass),
+                ("adapters", StepStatus::Pass),
+                ("fixtures", StepStatus::Pass),
+                ("graph", StepStatus::Pass),
+                ("invariants", StepStatus::Pass),
+                ("route", StepStatus::Pass),
+                ("scoring", StepStatus::Pass),
+                ("cache", StepStatus::Pass),
+            ]
+        );
+        let adapters = &report.step("adapters").unwrap().details;
+        assert_eq!(adapters, "7 built, no implementation for routerprotocol, symbiosis");
+        assert_eq!(report.step("fixtures").unwrap().details, "7 parsed");
+        // The first fixture, across's, is the transfer searched for
+        assert!(report.step("route").unwrap().details.starts_with("arbitrum to base: "), "{:#?}", report);
+    }
+
+    #[tokio::test]
+    async fn a_broken_config_fails_its_step_and_skips_what_needs_it() {
+        let path = std::env::temp_dir().join(format!("polypath-selftest-broken-{}.toml", std::process::id()));
+        std::fs::write(&path, "[global]\nlog_level = \"loud\"\n[bridges.stargate]\nbase_url = \"https://stargate.example\"\nchains = [\"ethereum\"]\n").unwrap();
+
+        let report = selftest(path.to_str().unwrap()).await;
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(!report.passed());
+        assert_eq!(
+            statuses(&report),
+            [
+                ("config", StepStatus::Fail),
+                ("context", StepStatus::Skip),
+                ("adapters", StepStatus::Skip),
+                ("fixtures", StepStatus::Skip),
+                ("graph", StepStatus::Skip),
+                ("invariants", StepStatus::Skip),
+                ("route", StepStatus::Skip),
+                ("scoring", StepStatus::Skip),
+                // Needs nothing from the config
+                ("cache", StepStatus::Pass),
+            ]
+        );
+        assert!(report.step("config").unwrap().details.contains("global.log_level"), "{:#?}", report);
+    }
+}
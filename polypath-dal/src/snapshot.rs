@@ -0,0 +1,423 @@
+// Graph snapshots in the persistence store, so a restart can route before the first refresh, and
+// the node directory saved with each so stored routes can be read without the graph
+
+use std::{collections::{HashMap, HashSet}, time::Duration};
+use polypath_graph::{EdgeSnapshot, Graph, GraphSnapshot, NodeDirectory, NodeId};
+use polypathroute_core::{CoreError, LoggingManager, PersistenceError, PersistenceManager, Versioned};
+use serde::{Deserialize, Serialize};
+
+use crate::{adapters::unix_now, error::DalError};
+
+// Used when the config has no global.snapshot_max_age_secs
+pub const DEFAULT_SNAPSHOT_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+// Earlier snapshots kept per name for replays, see load_graph_snapshot_at; older ones are dropped
+pub const SNAPSHOT_ARCHIVE_LIMIT: usize = 48;
+// Every this many archived snapshots one is archived whole; the others only hold what changed
+// since the one before them
+const ARCHIVE_WHOLE_EVERY: usize = 12;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotMetadata {
+    // Unix seconds
+    pub saved_at: u64,
+    pub node_count: usize,
+    pub edge_count: usize,
+    pub graph_version: u64,
+}
+
+// Archived snapshots that aren't whole name the one they follow, and their graph only holds the
+// nodes and edges that are new or changed since it
+#[derive(Serialize, Deserialize)]
+struct StoredSnapshot {
+    metadata: SnapshotMetadata,
+    graph: GraphSnapshot,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    after: Option<u64>,
+    #[serde(default, skip_serializing_if = "Removed::is_empty")]
+    removed: Removed,
+}
+
+// Nodes and edges gone since the archived snapshot before
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Removed {
+    nodes: Vec<NodeId>,
+    edges: Vec<(NodeId, NodeId, String)>,
+}
+
+impl Removed {
+    fn is_empty(&self) -> bool {
+        self.nodes.is_empty() && self.edges.is_empty()
+    }
+}
+
+fn edge_key(edge: &EdgeSnapshot) -> (NodeId, NodeId, String) {
+    (edge.from, edge.to, edge.bridge_name.clone())
+}
+
+// What `graph` holds beyond `before`, and what of `before` it no longer does
+fn changes_since(before: &GraphSnapshot, graph: GraphSnapshot) -> (GraphSnapshot, Removed) {
+    let before_nodes: HashSet<NodeId> = before.nodes.iter().map(|node| node.id).collect();
+    let before_edges: HashMap<_, &EdgeSnapshot> = before.edges.iter().map(|edge| (edge_key(edge), edge)).collect();
+    let nodes: HashSet<NodeId> = graph.nodes.iter().map(|node| node.id).collect();
+    let edges: HashSet<_> = graph.edges.iter().map(edge_key).collect();
+    let removed = Removed {
+        nodes: before.nodes.iter().map(|node| node.id).filter(|id| !nodes.contains(id)).collect(),
+        edges: before.edges.iter().map(edge_key).filter(|key| !edges.contains(key)).collect(),
+    };
+    let changed = GraphSnapshot {
+        nodes: graph.nodes.into_iter().filter(|node| !before_nodes.contains(&node.id)).collect(),
+        edges: graph.edges.into_iter().filter(|edge| before_edges.get(&edge_key(edge)).is_none_or(|before| *before != edge)).collect(),
+        ..graph
+    };
+    (changed, removed)
+}
+
+// `graph` with `stored`'s changes applied, see changes_since
+fn apply_changes(mut graph: GraphSnapshot, stored: StoredSnapshot) -> GraphSnapshot {
+    let removed_nodes: HashSet<NodeId> = stored.removed.nodes.into_iter().collect();
+    let removed_edges: HashSet<_> = stored.removed.edges.into_iter().collect();
+    let changed: HashSet<_> = stored.graph.edges.iter().map(edge_key).collect();
+    graph.nodes.retain(|node| !removed_nodes.contains(&node.id));
+    graph.nodes.extend(stored.graph.nodes);
+    graph.nodes.sort_by_key(|node| node.id);
+    graph.edges.retain(|edge| {
+        let key = edge_key(edge);
+        !removed_edges.contains(&key) && !changed.contains(&key)
+    });
+    graph.edges.extend(stored.graph.edges);
+    graph.edges.sort_by(|a, b| (a.from, a.to, &a.bridge_name).cmp(&(b.from, b.to, &b.bridge_name)));
+    GraphSnapshot { version: stored.graph.version, clock: stored.graph.clock, ..graph }
+}
+
+impl Versioned for StoredSnapshot {
+    const SCHEMA: &'static str = "graph_snapshot";
+    const VERSION: u32 = 1;
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(transparent)]
+struct StoredDirectory(NodeDirectory);
+
+impl Versioned for StoredDirectory {
+    const SCHEMA: &'static str = "node_directory";
+    const VERSION: u32 = 1;
+}
+
+// Payloads that don't encode or decode are the snapshot's own error; the rest are the store's
+fn snapshot_error(name: &str, err: PersistenceError) -> DalError {
+    match err {
+        PersistenceError::Encode { source, .. } | PersistenceError::Decode { source, .. } => {
+            DalError::Snapshot { name: name.to_string(), source }
+        }
+        err => CoreError::from(err).into(),
+    }
+}
+
+fn snapshot_key(name: &str) -> String {
+    format!("graph_snapshot:{}", name)
+}
+
+fn directory_key(name: &str) -> String {
+    format!("node_directory:{}", name)
+}
+
+fn archive_prefix(name: &str) -> String {
+    format!("graph_snapshot_archive:{}:", name)
+}
+
+// Zero-padded so keys sort by time
+fn archive_key(name: &str, saved_at: u64) -> String {
+    format!("{}{:012}", archive_prefix(name), saved_at)
+}
+
+// Archived snapshots of `name` as (saved_at, key), oldest first
+fn archived(persistence: &PersistenceManager, name: &str) -> Result<Vec<(u64, String)>, DalError> {
+    let prefix = archive_prefix(name);
+    let mut archived: Vec<(u64, String)> = persistence
+        .keys_with_prefix(&prefix)
+        .map_err(CoreError::from)?
+        .into_iter()
+        .filter_map(|key| Some((key[prefix.len()..].parse().ok()?, key)))
+        .collect();
+    archived.sort();
+    Ok(archived)
+}
+
+fn load(persistence: &PersistenceManager, name: &str, key: &str) -> Result<Option<StoredSnapshot>, DalError> {
+    persistence.get_typed(key).map_err(|err| snapshot_error(name, err))
+}
+
+pub fn save_graph_snapshot(persistence: &PersistenceManager, graph: &Graph, name: &str) -> Result<SnapshotMetadata, DalError> {
+    save_graph_snapshot_at(persistence, graph, name, unix_now())
+}
+
+// Also archives the snapshot under its time, keeping the last SNAPSHOT_ARCHIVE_LIMIT
+pub(crate) fn save_graph_snapshot_at(persistence: &PersistenceManager, graph: &Graph, name: &str, saved_at: u64) -> Result<SnapshotMetadata, DalError> {
+    let metadata = SnapshotMetadata {
+        saved_at,
+        node_count: graph.node_count(),
+        edge_count: graph.edge_count(),
+        graph_version: graph.version(),
+    };
+    let snapshot = graph.snapshot();
+    let archived = archived(persistence, name)?;
+    let stored = StoredSnapshot { metadata: metadata.clone(), graph: snapshot.clone(), after: None, removed: Removed::default() };
+    let encoded = persistence.encode_typed(&snapshot_key(name), &stored).map_err(|err| snapshot_error(name, err))?;
+
+    // Whole after ARCHIVE_WHOLE_EVERY - 1 that aren't, or when the one before is unreadable
+    let mut archive = stored;
+    if let Some((before_at, _)) = archived.last().filter(|(before_at, _)| *before_at < saved_at)
+        && let Ok(Some((before, chain))) = materialize(persistence, name, &archived, *before_at)
+        && chain < ARCHIVE_WHOLE_EVERY
+    {
+        let (changed, removed) = changes_since(&before, snapshot);
+        archive = StoredSnapshot { metadata: metadata.clone(), graph: changed, after: Some(*before_at), removed };
+    }
+    persistence.put_typed(&archive_key(name, saved_at), &archive).map_err(|err| snapshot_error(name, err))?;
+    persistence.store(snapshot_key(name), encoded).map_err(CoreError::from)?;
+    let directory = StoredDirectory(graph.export_node_directory().into());
+    persistence.put_typed(&directory_key(name), &directory).map_err(|err| snapshot_error(name, err))?;
+
+    // The oldest kept is written whole before those it follows go
+    let archived = self::archived(persistence, name)?;
+    let excess = archived.len().saturating_sub(SNAPSHOT_ARCHIVE_LIMIT);
+    if excess > 0 {
+        let (oldest_at, oldest_key) = &archived[excess];
+        if let Some(oldest) = load(persistence, name, oldest_key)?
+            && oldest.after.is_some()
+            && let Some((graph, _)) = materialize(persistence, name, &archived, *oldest_at)?
+        {
+            let whole = StoredSnapshot { metadata: oldest.metadata, graph, after: None, removed: Removed::default() };
+            persistence.put_typed(oldest_key, &whole).map_err(|err| snapshot_error(name, err))?;
+        }
+        for (_, key) in &archived[..excess] {
+            persistence.delete(key.clone()).map_err(CoreError::from)?;
+        }
+    }
+    Ok(metadata)
+}
+
+// The whole graph archived at `saved_at`, rebuilt from the last whole one before it, and how many
+// archived snapshots that took. None when one of them has gone.
+fn materialize(persistence: &PersistenceManager, name: &str, archived: &[(u64, String)], saved_at: u64) -> Result<Option<(GraphSnapshot, usize)>, DalError> {
+    let mut chain = Vec::new();
+    let mut next = Some(saved_at);
+    while let Some(at) = next {
+        let Some((_, key)) = archived.iter().find(|(archived_at, _)| *archived_at == at) else {
+            return Ok(None);
+        };
+        let Some(stored) = load(persistence, name, key)? else {
+            return Ok(None);
+        };
+        next = stored.after;
+        chain.push(stored);
+    }
+    let length = chain.len();
+    let mut stored = chain.into_iter().rev();
+    let whole = stored.next().map(|stored| stored.graph);
+    Ok(whole.map(|whole| (stored.fold(whole, apply_changes), length)))
+}
+
+// A graph restored as it stood when `snapshot` was taken, its clock pinned to the one recorded
+// with it, or to when it was saved for snapshots from before clocks were recorded
+fn restore_at(snapshot: GraphSnapshot, metadata: &SnapshotMetadata, shard_count: usize) -> Result<Graph, DalError> {
+    let clock = if snapshot.clock > 0 { snapshot.clock } else { metadata.saved_at };
+    let graph = Graph::from_snapshot(snapshot, shard_count)?;
+    graph.pin_clock(clock);
+    Ok(graph)
+}
+
+// The newest snapshot saved under `name` at or before unix time `at`, whatever its age, with
+// every edge marked stale and the clock pinned to when it was taken. None when there's none that
+// old.
+pub fn load_graph_snapshot_at(persistence: &PersistenceManager, name: &str, shard_count: usize, at: u64) -> Result<Option<(SnapshotMetadata, Graph)>, DalError> {
+    let archived = archived(persistence, name)?;
+    let newest = archived.iter().rfind(|(saved_at, _)| *saved_at <= at);
+    // Snapshots saved before there was an archive only live under the plain key
+    if let Some(stored) = load(persistence, name, &snapshot_key(name))?
+        && stored.metadata.saved_at <= at
+        && newest.is_none_or(|(saved_at, _)| stored.metadata.saved_at > *saved_at)
+    {
+        let graph = restore_at(stored.graph, &stored.metadata, shard_count)?;
+        return Ok(Some((stored.metadata, graph)));
+    }
+    let Some((saved_at, key)) = newest else {
+        return Ok(None);
+    };
+    let Some(metadata) = load(persistence, name, key)?.map(|stored| stored.metadata) else {
+        return Ok(None);
+    };
+    match materialize(persistence, name, &archived, *saved_at)? {
+        Some((graph, _)) => {
+            let graph = restore_at(graph, &metadata, shard_count)?;
+            Ok(Some((metadata, graph)))
+        }
+        None => Ok(None),
+    }
+}
+
+// The node directory saved with the snapshot under `name`, whatever the snapshot's age; None when
+// there's none
+pub fn load_node_directory(persistence: &PersistenceManager, name: &str) -> Result<Option<NodeDirectory>, DalError> {
+    let directory: Option<StoredDirectory> = persistence.get_typed(&directory_key(name)).map_err(|err| snapshot_error(name, err))?;
+    Ok(directory.map(|StoredDirectory(directory)| directory))
+}
+
+// The graph saved under `name`, with every edge marked stale until it's refreshed. A missing
+// snapshot, or one older than `max_age`, gives an empty graph; an unreadable one is an error.
+pub fn load_graph_snapshot(persistence: &PersistenceManager, name: &str, shard_count: usize, max_age: Duration) -> Result<Graph, DalError> {
+    let Some(stored) = load(persistence, name, &snapshot_key(name))? else {
+        return Ok(Graph::try_new(shard_count)?);
+    };
+
+    let age = unix_now().saturating_sub(stored.metadata.saved_at);
+    if age > max_age.as_secs() {
+        LoggingManager.warn_with(
+            "graph snapshot is too old, starting from an empty graph",
+            &[("snapshot", &name), ("age_secs", &age), ("max_age_secs", &max_age.as_secs())],
+        );
+        return Ok(Graph::try_new(shard_count)?);
+    }
+
+    Ok(Graph::from_snapshot(stored.graph, shard_count)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, sync::Arc};
+    use polypath_graph::{EdgeMetrics, RoutingEngine, RoutingParams};
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("polypath-snapshot-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn populated_graph() -> Graph {
+        let graph = Graph::new(16);
+        let chains = ["ethereum", "arbitrum", "polygon"];
+        for (from, to) in [(0, 1), (1, 2), (0, 2)] {
+            let src = graph.get_or_create_asset_node(chains[from], "usdc", "USDC");
+            let dst = graph.get_or_create_asset_node(chains[to], "usdc", "USDC");
+            let cost = if (from, to) == (0, 2) { 40.0 } else { 5.0 };
+            graph.add_edge(src, dst, "stargate", EdgeMetrics { cost, speed: 60.0, liquidity: 1_000_000.0, risk: 0.1 }, None, None).unwrap();
+        }
+        graph
+    }
+
+    #[test]
+    fn restored_graphs_route_immediately() {
+        let dir = temp_dir("restart");
+        let graph = populated_graph();
+        let metadata = save_graph_snapshot(&PersistenceManager::open(&dir).unwrap(), &graph, "main").unwrap();
+        assert_eq!((metadata.node_count, metadata.edge_count, metadata.graph_version), (3, 3, 3));
+
+        let persistence = PersistenceManager::open(&dir).unwrap();
+        let restored = Arc::new(load_graph_snapshot(&persistence, "main", 8, DEFAULT_SNAPSHOT_MAX_AGE).unwrap());
+        let start = restored.get_or_create_asset_node("ethereum", "usdc", "USDC");
+        let end = restored.get_or_create_asset_node("polygon", "usdc", "USDC");
+
+        let path = RoutingEngine::new(Arc::clone(&restored), 3).find_path(start, end, &RoutingParams::cheapest()).unwrap();
+        assert_eq!(path.hops.len(), 2);
+        assert!(restored.get_outgoing_edges(start).iter().all(|edge| edge.is_stale()));
+
+        let missing = load_graph_snapshot(&persistence, "other", 8, DEFAULT_SNAPSHOT_MAX_AGE).unwrap();
+        assert_eq!(missing.node_count(), 0);
+
+        let directory = load_node_directory(&persistence, "main").unwrap().unwrap();
+        assert_eq!(directory.len(), 3);
+        assert_eq!(directory.lookup(end).unwrap().chain, "polygon");
+        assert!(load_node_directory(&persistence, "other").unwrap().is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn snapshots_past_the_max_age_are_ignored() {
+        let persistence = PersistenceManager::new();
+        save_graph_snapshot_at(&persistence, &populated_graph(), "main", unix_now() - 7200).unwrap();
+
+        let stale = load_graph_snapshot(&persistence, "main", 8, DEFAULT_SNAPSHOT_MAX_AGE).unwrap();
+        assert_eq!((stale.node_count(), stale.edge_count()), (0, 0));
+
+        let allowed = load_graph_snapshot(&persistence, "main", 8, Duration::from_secs(3 * 60 * 60)).unwrap();
+        assert_eq!(allowed.edge_count(), 3);
+
+        persistence.store(snapshot_key("main"), "{\"metadata\":".to_string()).unwrap();
+        persistence.store(archive_key("main", 1), "{\"metadata\":".to_string()).unwrap();
+        assert!(matches!(load_graph_snapshot(&persistence, "main", 8, DEFAULT_SNAPSHOT_MAX_AGE), Err(DalError::Snapshot { .. })));
+        assert!(matches!(load_graph_snapshot(&persistence, "other", 6, DEFAULT_SNAPSHOT_MAX_AGE), Err(DalError::Graph(_))));
+
+        // One written by a newer build isn't mistaken for a corrupt one
+        let newer = r#"{"schema":"graph_snapshot","version":2,"payload":{}}"#;
+        persistence.store(snapshot_key("main"), newer.to_string()).unwrap();
+        let result = load_graph_snapshot(&persistence, "main", 8, DEFAULT_SNAPSHOT_MAX_AGE);
+        assert!(
+            matches!(result, Err(DalError::Core(CoreError::Persistence(PersistenceError::UnsupportedVersion { found: 2, .. })))),
+            "{:?}",
+            result.map(|graph| graph.edge_count())
+        );
+    }
+
+    #[test]
+    fn snapshots_from_before_envelopes_still_load() {
+        let persistence = PersistenceManager::new();
+        let graph = populated_graph();
+        save_graph_snapshot(&persistence, &graph, "main").unwrap();
+        let stored: StoredSnapshot = persistence.get_typed(&snapshot_key("main")).unwrap().unwrap();
+        persistence.store(snapshot_key("main"), serde_json::to_string(&stored).unwrap()).unwrap();
+        persistence.store(directory_key("main"), serde_json::to_string(&graph.export_node_directory()).unwrap()).unwrap();
+
+        let restored = load_graph_snapshot(&persistence, "main", 8, DEFAULT_SNAPSHOT_MAX_AGE).unwrap();
+        assert_eq!(restored.edge_count(), 3);
+        assert_eq!(load_node_directory(&persistence, "main").unwrap().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn earlier_snapshots_are_archived_for_replays() {
+        let persistence = PersistenceManager::new();
+        let graph = populated_graph();
+        for saved_at in [1_000, 2_000, 3_000] {
+            save_graph_snapshot_at(&persistence, &graph, "main", saved_at).unwrap();
+            let eth = graph.get_or_create_asset_node("ethereum", "usdc", "USDC");
+            let node = graph.get_or_create_asset_node(&format!("chain-{}", saved_at), "usdc", "USDC");
+            graph.add_edge(eth, node, "hop", EdgeMetrics { cost: 1.0, speed: 60.0, liquidity: 1_000.0, risk: 0.1 }, None, None).unwrap();
+        }
+
+        let (metadata, older) = load_graph_snapshot_at(&persistence, "main", 8, 2_500).unwrap().unwrap();
+        assert_eq!((metadata.saved_at, older.edge_count()), (2_000, 4));
+        assert_eq!(older.clock(), graph.snapshot().clock);
+        let eth = older.get_or_create_asset_node("ethereum", "usdc", "USDC");
+        assert!(older.get_outgoing_edges(eth).iter().all(|edge| edge.is_stale()));
+        let (metadata, latest) = load_graph_snapshot_at(&persistence, "main", 8, u64::MAX).unwrap().unwrap();
+        assert_eq!((metadata.saved_at, latest.edge_count()), (3_000, 5));
+        assert!(load_graph_snapshot_at(&persistence, "main", 8, 999).unwrap().is_none());
+        assert!(load_graph_snapshot_at(&persistence, "other", 8, u64::MAX).unwrap().is_none());
+
+        // Later ones only hold what changed since the one before
+        let stored = |saved_at| load(&persistence, "main", &archive_key("main", saved_at)).unwrap().unwrap();
+        assert!(stored(1_000).after.is_none());
+        let third = stored(3_000);
+        assert_eq!(third.after, Some(2_000));
+        assert_eq!((third.graph.nodes.len(), third.graph.edges.len()), (1, 1));
+
+        // Only the newest SNAPSHOT_ARCHIVE_LIMIT are kept, the oldest of them whole
+        for saved_at in 0..SNAPSHOT_ARCHIVE_LIMIT as u64 {
+            save_graph_snapshot_at(&persistence, &graph, "main", 10_000 + saved_at).unwrap();
+        }
+        let archived = archived(&persistence, "main").unwrap();
+        assert_eq!(archived.len(), SNAPSHOT_ARCHIVE_LIMIT);
+        assert_eq!(archived[0].0, 10_000);
+        assert!(stored(10_000).after.is_none());
+        assert!(stored(10_001).graph.edges.is_empty());
+        let whole = archived.iter().filter(|(saved_at, _)| stored(*saved_at).after.is_none()).count();
+        assert!((SNAPSHOT_ARCHIVE_LIMIT / ARCHIVE_WHOLE_EVERY..=SNAPSHOT_ARCHIVE_LIMIT / ARCHIVE_WHOLE_EVERY + 1).contains(&whole), "{}", whole);
+        assert!(load_graph_snapshot_at(&persistence, "main", 8, 9_999).unwrap().is_none());
+        for saved_at in [10_000, 10_020, 10_000 + SNAPSHOT_ARCHIVE_LIMIT as u64 - 1] {
+            // Not the plain key's
+            persistence.delete(snapshot_key("main")).unwrap();
+            let (_, replayed) = load_graph_snapshot_at(&persistence, "main", 8, saved_at).unwrap().unwrap();
+            assert_eq!((replayed.node_count(), replayed.edge_count()), (graph.node_count(), graph.edge_count()));
+        }
+    }
+}
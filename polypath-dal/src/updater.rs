@@ -0,0 +1,2451 @@
+// Turns adapter quotes into graph nodes and edges
+
+use std::{collections::{BTreeMap, BTreeSet, HashMap}, sync::{Arc, Mutex, atomic::{AtomicU64, Ordering}}, time::Duration};
+use futures::StreamExt;
+use polypath_graph::{Coverage, Edge, EdgeMetrics, EdgeQuote, EdgeUpdate, Graph, GraphError, NodeId, NodeType, QuoteFee, SourceQuality, SpeedBreakdown};
+use polypathroute_core::{GraphConfig, RefreshPriority, RequestContext};
+use serde::Serialize;
+use tokio::time::Instant;
+use tokio_util::task::AbortOnDropHandle;
+use tracing::{Instrument, instrument::WithSubscriber};
+
+use crate::{
+    DalContext,
+    adapters::{AdapterError, BridgeEdge, DEFAULT_QUOTE_VALIDITY, DexAdapter, Disposition, DynBridgeAdapter, FeeComponent, SupportedPair, SwapPair, SwapQuote, merge_pair, unix_now},
+    batch::{FetchOutcome, probe_amount, probe_request},
+    depth::DepthLadder,
+    alerts::{Alert, AlertEngine, EdgeEvent, EdgeIdentity},
+    fx::{self, CurrencyId, FxConverter, FxError, Money},
+    gas::{GasAction, GasEstimate, GasEstimator},
+    history::{self, History, MetricsSample},
+    quarantine::{Quarantine, QuarantinedPair},
+    sanity::{QuoteSanityChecker, RejectedQuote, SanityViolation},
+    updates::{self, EdgeUpdateMsg, UpdateReceiver, UpdateSender},
+};
+
+// Quotes in flight at once during a refresh, unless set with `with_concurrency`
+pub const DEFAULT_REFRESH_CONCURRENCY: usize = 8;
+// Least time between two compactions of the metrics history
+const HISTORY_COMPACTION_INTERVAL: u64 = 60 * 60;
+// What a pair whose edge was restored from a snapshot, and not quoted since, counts for in a
+// graph's coverage
+const STALE_COVERAGE_WEIGHT: f64 = 0.5;
+// Pairs without an edge listed in a Coverage
+const MISSING_PAIRS_SAMPLE: usize = 5;
+// What swap edges are quoted for, in human units of the token swapped
+const SWAP_PROBE_AMOUNT: f64 = 1.0;
+// A swap settles in about a block, and only carries the venue's contract risk
+const SWAP_SECS: f64 = 15.0;
+const SWAP_RISK: f64 = 0.01;
+// Name a refresh's graph updates are timed under, see SlowOpsConfig
+const GRAPH_UPSERT: &str = "graph_upsert";
+// How long an adapter whose quote failed a sanity check is trusted less, see SourceQuality
+const FLAGGED_FOR: u64 = 15 * 60;
+// What the depth of routes whose API doesn't report it is learned with: 1 to 1M of the source
+// token, up to the last amount within 0.5% of the 1-unit rate, see `learn_liquidity`
+const LIQUIDITY_LADDER: DepthLadder = DepthLadder { factor: 10.0, steps: 7, max_slippage: 0.005 };
+// How long a learned depth is used before the ladder is walked again
+const LIQUIDITY_RELEARN: u64 = 60 * 60;
+// Share of its quotes' lifetime a pair is refreshed within at the latest, so the next quote
+// is in before the last one expires, see `cadence`
+const REFRESH_WITHIN_VALIDITY: f64 = 0.75;
+
+// What one refresh did to the graph
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RefreshReport {
+    // Existing edges given fresh metrics
+    pub updated: usize,
+    pub added: usize,
+    // Pairs whose fetch failed or whose quote the graph rejected
+    pub failed: usize,
+    // Edges switched off because their bridge no longer serves the pair
+    pub deactivated: usize,
+    // Edges switched off because their last quote expired without being refreshed
+    pub expired: usize,
+    // Quarantined pairs left out because no probe of them was due
+    pub quarantined: usize,
+    // Pairs quoted, by their priority
+    pub fetched: FetchCounts,
+    // `failed` by the bridge or swap venue that was asked
+    pub failed_by_adapter: BTreeMap<String, usize>,
+    // Slowest quote request of each bridge, in milliseconds
+    pub slowest_quote_ms: BTreeMap<String, u64>,
+    // Quotes kept out of the graph for breaking a [sanity] bound, also counted in `failed`
+    pub rejected: Vec<RejectedQuote>,
+    // Quotes left out for being older than one their adapter already gave for the same edge
+    pub superseded: usize,
+}
+
+impl RefreshReport {
+    fn fail(&mut self, adapter: &str) {
+        self.failed += 1;
+        *self.failed_by_adapter.entry(adapter.to_string()).or_default() += 1;
+    }
+
+    fn timed(&mut self, adapter: &str, latency: Duration) {
+        let slowest = self.slowest_quote_ms.entry(adapter.to_string()).or_default();
+        *slowest = (*slowest).max(latency.as_millis() as u64);
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct FetchCounts {
+    pub hot: usize,
+    pub normal: usize,
+    pub cold: usize,
+}
+
+impl FetchCounts {
+    pub fn get(&self, priority: RefreshPriority) -> usize {
+        match priority {
+            RefreshPriority::Hot => self.hot,
+            RefreshPriority::Normal => self.normal,
+            RefreshPriority::Cold => self.cold,
+        }
+    }
+
+    fn add(&mut self, priority: RefreshPriority) {
+        match priority {
+            RefreshPriority::Hot => self.hot += 1,
+            RefreshPriority::Normal => self.normal += 1,
+            RefreshPriority::Cold => self.cold += 1,
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.hot + self.normal + self.cold
+    }
+}
+
+// Keeps a graph in line with what the context's adapters quote. Asset nodes are keyed by
+// registry chain key and lowercased token address, as pairs are compared case-insensitively; edges are
+// labelled with BridgeEdge::label so aggregated routes don't collide with direct ones, unless
+// [edge_identity] maps them onto one canonical edge, see `edge_label`.
+#[derive(Debug)]
+pub struct GraphUpdater {
+    graph: Arc<Graph>,
+    dal: Arc<DalContext>,
+    concurrency: usize,
+    // The bridges and pairs this graph takes, see `with_scope`; all of them when None
+    scope: Option<GraphConfig>,
+    // valid_until of the latest quote behind each edge, keyed by (from, to, label)
+    expiries: Mutex<HashMap<EdgeKey, u64>>,
+    // The latest quote for an edge of every bridge it was quoted for since it was last switched
+    // off, by bridge, keyed as `expiries`
+    corroborations: Mutex<HashMap<EdgeKey, BTreeMap<String, Corroboration>>>,
+    // Set by `set_pairs`, replacing the pairs of the bridges they're keyed by
+    pair_overrides: Mutex<HashMap<String, Vec<SupportedPair>>>,
+    // Set by `discover_pairs`: what the auto_discover bridges advertise that [discovery] admits
+    discovered: Mutex<HashMap<String, Vec<SupportedPair>>>,
+    // When the auto_discover bridges' advertised pairs were last looked at, None before
+    last_discovery: Mutex<Option<Instant>>,
+    // Unix time the last refresh finished, 0 before the first
+    last_refreshed: AtomicU64,
+    // Where every quote's metrics are recorded, see DalContext::history
+    history: Option<History>,
+    // Unix time of the last History::compact, 0 before the first
+    last_compacted: AtomicU64,
+    // Checks every edge update against the [alerts] rules, see DalContext::alert_engine
+    alerts: Option<AlertEngine>,
+    // Fired during a refresh, sent once it's applied
+    fired: Mutex<Vec<Alert>>,
+    // Prices the source-chain gas added to each quote's cost, see DalContext::gas_estimator
+    gas: Option<Arc<dyn GasEstimator>>,
+    // Puts each quote's cost in the [fx] quote currency, see DalContext::fx_converter
+    fx: Option<Arc<dyn FxConverter>>,
+    // Pairs failing every refresh, only re-probed with backoff
+    quarantine: Quarantine,
+    // Keeps implausible quotes out of the graph, see DalContext::sanity_checker
+    sanity: Option<QuoteSanityChecker>,
+    // Adapter that last quoted each pair of a bridge with a source policy, by quarantine key
+    sources_in_use: Mutex<HashMap<String, String>>,
+    // Venues whose same-chain swaps become swap edges, see `with_dex`
+    dexes: Vec<Arc<dyn DexAdapter>>,
+    // Unix time each adapter last gave a quote that failed a sanity check
+    flagged: Mutex<HashMap<String, u64>>,
+    // Depth learned for the pairs of adapters that don't report it, and the unix time it was
+    // learned at, by the adapter's quarantine key
+    learned_liquidity: Mutex<HashMap<String, (f64, u64)>>,
+    // How long the last quote applied for each pair was valid for, by quarantine key
+    quote_lifetimes: Mutex<HashMap<String, Duration>>,
+}
+
+// An edge as (from, to, label)
+type EdgeKey = (NodeId, NodeId, String);
+
+// A bridge's latest quote for an edge's route, the adapter that gave it and the pair it was
+// quoted for
+#[derive(Debug, Clone)]
+struct Corroboration {
+    source: String,
+    pair: SupportedPair,
+    quote: BridgeEdge,
+}
+
+// The adapters a refresh asks for a bridge's quotes, in order
+struct Sources {
+    bridge: String,
+    adapters: Vec<Arc<DynBridgeAdapter>>,
+    // Whether they come from the bridge's source_policy
+    policy: bool,
+}
+
+impl Sources {
+    // Puts an outcome under the bridge's name and notes the source that gave it. Aggregator
+    // quotes routed through another bridge don't count as quotes for this one.
+    fn attribute(&self, mut outcome: FetchOutcome) -> FetchOutcome {
+        if !self.policy {
+            return outcome;
+        }
+        let source = std::mem::replace(&mut outcome.adapter, self.bridge.clone());
+        if let Ok(quote) = &mut outcome.result {
+            match quote.via.take() {
+                Some(via) if !via.eq_ignore_ascii_case(&self.bridge) => {
+                    let pair = &outcome.pair;
+                    outcome.result = Err(AdapterError::UnsupportedPair {
+                        bridge: format!("{} via {}", source, self.bridge),
+                        src_chain: pair.src_chain.clone(),
+                        dst_chain: pair.dst_chain.clone(),
+                        src_token: pair.src_token.clone(),
+                        dst_token: pair.dst_token.clone(),
+                    });
+                }
+                _ => {}
+            }
+        }
+        outcome.source = Some(source);
+        outcome
+    }
+}
+
+impl GraphUpdater {
+    pub fn new(graph: Arc<Graph>, dal: DalContext) -> Self {
+        Self::shared(graph, Arc::new(dal))
+    }
+
+    // For one of several graphs quoted through the same context, see GraphRegistry
+    pub fn shared(graph: Arc<Graph>, dal: Arc<DalContext>) -> Self {
+        Self {
+            history: dal.history(),
+            alerts: dal.alert_engine(),
+            gas: dal.gas_estimator(),
+            fx: dal.fx_converter(),
+            sanity: dal.sanity_checker(),
+            quarantine: Quarantine::new(dal.config().global.quarantine_after),
+            graph,
+            dal,
+            concurrency: DEFAULT_REFRESH_CONCURRENCY,
+            scope: None,
+            expiries: Mutex::default(),
+            corroborations: Mutex::default(),
+            pair_overrides: Mutex::default(),
+            discovered: Mutex::default(),
+            last_discovery: Mutex::default(),
+            last_refreshed: AtomicU64::new(0),
+            last_compacted: AtomicU64::new(0),
+            fired: Mutex::default(),
+            sources_in_use: Mutex::default(),
+            dexes: Vec::new(),
+            flagged: Mutex::default(),
+            learned_liquidity: Mutex::default(),
+            quote_lifetimes: Mutex::default(),
+        }
+    }
+
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    // Only quotes the bridges and pairs `scope` selects; its other settings are left to whoever
+    // schedules and routes over the graph
+    pub fn with_scope(mut self, scope: GraphConfig) -> Self {
+        self.scope = Some(scope);
+        self
+    }
+
+    // Replaces the engine built from the config's [alerts] section
+    pub fn with_alert_engine(mut self, engine: AlertEngine) -> Self {
+        self.alerts = Some(engine);
+        self
+    }
+
+    // Replaces the estimator built from the config's [gas] section
+    pub fn with_gas_estimator(mut self, estimator: Arc<dyn GasEstimator>) -> Self {
+        self.gas = Some(estimator);
+        self
+    }
+
+    // Replaces the converter built from the config's [fx] section
+    pub fn with_fx_converter(mut self, converter: Arc<dyn FxConverter>) -> Self {
+        self.fx = Some(converter);
+        self
+    }
+
+    // Replaces the checker built from the config's [sanity] section
+    pub fn with_sanity_checker(mut self, checker: QuoteSanityChecker) -> Self {
+        self.sanity = Some(checker);
+        self
+    }
+
+    // Consecutive failures that quarantine a pair, instead of global.quarantine_after
+    pub fn with_quarantine_after(mut self, failures: u32) -> Self {
+        self.quarantine.set_after(failures);
+        self
+    }
+
+    // Quotes `dex`'s swaps on every refresh, all of them whatever is due, as swap edges
+    // labelled with its name. Swaps leave the bridge pairs' fetch counts, quarantine, history
+    // and alerts alone.
+    pub fn with_dex(mut self, dex: Arc<dyn DexAdapter>) -> Self {
+        self.dexes.push(dex);
+        self
+    }
+
+    pub fn graph(&self) -> &Arc<Graph> {
+        &self.graph
+    }
+
+    pub fn dal(&self) -> &DalContext {
+        &self.dal
+    }
+
+    // Whether `bridge`'s `pair` belongs in this graph, see `with_scope`
+    pub fn covers(&self, bridge: &str, pair: &SupportedPair) -> bool {
+        let Some(scope) = &self.scope else {
+            return true;
+        };
+        self.takes_bridge(bridge) && self.admits(&scope.pairs_filter.chains, &scope.pairs_filter.tokens, pair)
+    }
+
+    fn takes_bridge(&self, bridge: &str) -> bool {
+        self.scope.as_ref().is_none_or(|scope| scope.bridges.is_empty() || scope.bridges.iter().any(|name| name == bridge))
+    }
+
+    // Whether both ends of `pair` are on one of `chains` and among `tokens`, by symbol or
+    // address. Empty lists admit anything.
+    fn admits(&self, chains: &[String], tokens: &[String], pair: &SupportedPair) -> bool {
+        let ends = [(&pair.src_chain, &pair.src_token), (&pair.dst_chain, &pair.dst_token)];
+        ends.iter().all(|(chain, token)| {
+            let (chain, address) = self.asset_node(chain, token);
+            let symbol = self.symbol(&chain, &address, pair.token_symbol.as_deref().unwrap_or_default());
+            (chains.is_empty() || chains.iter().any(|wanted| self.dal.chain_key(wanted) == chain))
+                && (tokens.is_empty() || tokens.iter().any(|wanted| wanted.eq_ignore_ascii_case(&symbol) || wanted.eq_ignore_ascii_case(&address)))
+        })
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    // Id the history records an edge of `bridge` under; `bridge` is the edge's label
+    pub fn edge_id(&self, bridge: &str, src: (&str, &str), dst: (&str, &str)) -> String {
+        let (src_chain, src_token) = self.asset_node(src.0, src.1);
+        let (dst_chain, dst_token) = self.asset_node(dst.0, dst.1);
+        history::edge_id(bridge, (&src_chain, &src_token), (&dst_chain, &dst_token))
+    }
+
+    // Id of the asset node the updater uses for `token` on `chain`, see `asset_node`
+    pub fn asset_node_id(&self, chain: &str, token: &str) -> NodeId {
+        let (chain, address) = self.asset_node(chain, token);
+        NodeId::from_parts(&chain, &address)
+    }
+
+    // The asset nodes of every configured pair's tokens, which intents resolve to whether or
+    // not an edge touches them right now
+    pub fn pair_nodes(&self) -> Vec<NodeId> {
+        let mut nodes: Vec<NodeId> = self
+            .configured_pairs()
+            .iter()
+            .flat_map(|(_, pairs)| pairs.iter())
+            .flat_map(|pair| [self.asset_node_id(&pair.src_chain, &pair.src_token), self.asset_node_id(&pair.dst_chain, &pair.dst_token)])
+            .collect();
+        nodes.sort();
+        nodes.dedup();
+        nodes
+    }
+
+    // Asset nodes are keyed by the registry's chain key and the lowercased token address, so
+    // aliases and address casing in config all land on the same node
+    fn asset_node(&self, chain: &str, token: &str) -> (String, String) {
+        (self.dal.chain_key(chain), token.trim().to_lowercase())
+    }
+
+    // The registry's symbol for the token, else the one the pair was configured with
+    fn symbol(&self, chain: &str, token: &str, configured: &str) -> String {
+        match self.dal.registry().resolve_token(chain, token) {
+            Ok(found) => found.symbol,
+            Err(_) => configured.to_string(),
+        }
+    }
+
+    // Pairs only quoted on their probe schedule since they kept failing, see QUARANTINE_BACKOFF
+    pub fn quarantined(&self) -> Vec<QuarantinedPair> {
+        self.quarantine.list()
+    }
+
+    // Key of a bridge's pair in the quarantine, its edge id under the bridge's name
+    fn quarantine_key(&self, bridge: &str, pair: &SupportedPair) -> String {
+        self.edge_id(bridge, (&pair.src_chain, &pair.src_token), (&pair.dst_chain, &pair.dst_token))
+    }
+
+    // Unix time the latest refresh finished at, None until one has
+    pub fn last_refreshed(&self) -> Option<u64> {
+        Some(self.last_refreshed.load(Ordering::Acquire)).filter(|at| *at > 0)
+    }
+
+    // Replaces the pairs quoted for `bridge` from the next refresh on, e.g. after its config
+    // section changed. Pairs discovered for it are still quoted besides them, unless the list is
+    // empty, which stops quoting the bridge; bridges that aren't configured are never quoted.
+    // The bridge's quarantined pairs are probed again right away.
+    pub fn set_pairs(&self, bridge: &str, pairs: Vec<SupportedPair>) {
+        self.quarantine.forget_bridge(bridge);
+        self.pair_overrides.lock().unwrap().insert(bridge.to_string(), pairs);
+    }
+
+    // Quotes every pair of every configured bridge and applies the results to the graph.
+    // Bridges whose config lists no pairs are quoted on the pairs their adapter reports; those
+    // set to auto_discover have their pairs discovered first when it's due, see `discover_pairs`.
+    // Quarantined pairs are only quoted when a probe of them is due.
+    // Quotes are applied on a task of their own, in whole batches, so a refresh dropped part
+    // way leaves the graph consistent; what was already fetched is still applied.
+    pub async fn refresh_once(self: &Arc<Self>) -> RefreshReport {
+        self.refresh_where(|_, _| true).await
+    }
+
+    // As `refresh_once`, only quoting the pairs whose cadence at the `base` update interval is
+    // among `due`, see `cadence`. Expiry, coverage and alerts still cover every pair.
+    pub async fn refresh_due(self: &Arc<Self>, base: Duration, due: &[Duration]) -> RefreshReport {
+        self.refresh_where(|bridge, pair| due.contains(&self.cadence(bridge, pair, base))).await
+    }
+
+    // How often `bridge`'s `pair` is quoted when refreshes run every `base`: its
+    // refresh_interval, else its priority's [refresh] multiple of `base`. Either way it's
+    // quoted again within REFRESH_WITHIN_VALIDITY of the lifetime of its last quote, so its
+    // edge doesn't expire between refreshes.
+    pub fn cadence(&self, bridge: &str, pair: &SupportedPair, base: Duration) -> Duration {
+        let configured = pair.refresh_interval.unwrap_or_else(|| base * self.dal.config().refresh.multiplier(pair.priority));
+        match self.quote_lifetimes.lock().unwrap().get(&self.quarantine_key(bridge, pair)) {
+            Some(lifetime) => {
+                let within = Duration::from_secs(lifetime.mul_f64(REFRESH_WITHIN_VALIDITY).as_secs().max(1));
+                configured.min(within)
+            }
+            None => configured,
+        }
+    }
+
+    // The distinct cadences of the configured pairs, shortest first
+    pub fn cadences(&self, base: Duration) -> Vec<Duration> {
+        let mut cadences: Vec<Duration> = self
+            .configured_pairs()
+            .iter()
+            .flat_map(|(sources, pairs)| pairs.iter().map(|pair| self.cadence(&sources.bridge, pair, base)))
+            .collect();
+        cadences.sort();
+        cadences.dedup();
+        cadences
+    }
+
+    // The longest cadence of each bridge's configured pairs, see `cadence`
+    pub fn bridge_cadences(&self, base: Duration) -> BTreeMap<String, Duration> {
+        let mut cadences = BTreeMap::new();
+        for (sources, pairs) in self.configured_pairs() {
+            for pair in &pairs {
+                let cadence = self.cadence(&sources.bridge, pair, base);
+                let longest = cadences.entry(sources.bridge.clone()).or_insert(cadence);
+                *longest = cadence.max(*longest);
+            }
+        }
+        cadences
+    }
+
+    async fn refresh_where(self: &Arc<Self>, wanted: impl Fn(&str, &SupportedPair) -> bool) -> RefreshReport {
+        self.discover_if_due();
+        let configured = self.configured_pairs();
+        let mut by_bridge = Vec::new();
+        let mut report = RefreshReport::default();
+        for (sources, pairs) in &configured {
+            let bridge = &sources.bridge;
+            let (due, quarantined): (Vec<&SupportedPair>, Vec<&SupportedPair>) = pairs
+                .iter()
+                .filter(|pair| wanted(bridge, pair))
+                .partition(|pair| self.quarantine.is_due(&self.quarantine_key(bridge, pair)));
+            report.quarantined += quarantined.len();
+            by_bridge.push(due.into_iter().map(|pair| {
+                report.fetched.add(pair.priority);
+                let context = self.dal.quote_request(bridge, pair);
+                (Arc::clone(sources), pair.clone(), context)
+            }).collect::<Vec<_>>());
+        }
+        // Bridges take turns, so one whose rate limiter holds its requests back doesn't keep
+        // the others waiting behind them
+        let jobs = round_robin(by_bridge);
+
+        // Quotes are applied while the rest are still being fetched, see `apply_updates`, on a
+        // task of their own so fetches aren't held up while a batch goes into the graph. A
+        // refresh dropped midway, as on shutdown, takes the applier with it: the batch going in
+        // then is finished, the quotes queued behind it aren't applied.
+        let report = Arc::new(Mutex::new(report));
+        let (updates, queued) = updates::channel(self.dal.config().global.update_queue_capacity, self.dal.metrics().clone());
+        let applier = AbortOnDropHandle::new(tokio::spawn(Arc::clone(self).apply_updates(queued, Arc::clone(&report)).with_current_subscriber()));
+        self.fetch_from_sources(jobs, updates, &report).await;
+        if let Err(err) = applier.await
+            && err.is_panic()
+        {
+            std::panic::resume_unwind(err.into_panic());
+        }
+        let mut report = Arc::into_inner(report).expect("the applier is done").into_inner().unwrap();
+        self.refresh_swaps(&mut report).await;
+        let now = unix_now();
+        report.expired = self.expire_at(now);
+        self.last_refreshed.store(now.max(1), Ordering::Release);
+        self.compact_history(now);
+        self.dispatch_alerts().await;
+        let coverage = self.publish_coverage(&configured);
+
+        self.dal.metrics().set_graph_size(self.graph.node_count(), self.graph.active_edge_count());
+        self.dal.logger().info_with("graph refreshed", &[
+            ("added", &report.added),
+            ("updated", &report.updated),
+            ("failed", &report.failed),
+            ("rejected", &report.rejected.len()),
+            ("superseded", &report.superseded),
+            ("deactivated", &report.deactivated),
+            ("expired", &report.expired),
+            ("quarantined", &report.quarantined),
+            ("fetched_hot", &report.fetched.hot),
+            ("fetched_normal", &report.fetched.normal),
+            ("fetched_cold", &report.fetched.cold),
+            ("coverage", &coverage.fraction),
+        ]);
+        report
+    }
+
+    // Every pair this graph is configured to carry, quarantined or not, with the sources of its
+    // bridge: those set or configured for it, then those discovered that aren't already listed,
+    // so a pair keeps its configured priority and limits. Bridges that list no pairs and don't
+    // discover any carry the pairs their adapter reports.
+    fn configured_pairs(&self) -> Vec<(Arc<Sources>, Vec<SupportedPair>)> {
+        let mut configured = Vec::new();
+        for bridge in self.dal.adapter_names() {
+            // Left out before its adapter is even built
+            if !self.takes_bridge(&bridge) {
+                continue;
+            }
+            let Some(sources) = self.sources(&bridge) else {
+                continue;
+            };
+            let overridden = self.pair_overrides.lock().unwrap().get(&bridge).cloned();
+            let discovered = self.discovered.lock().unwrap().get(&bridge).cloned();
+            let pairs = match (overridden, discovered) {
+                (Some(pairs), _) if pairs.is_empty() => pairs,
+                (overridden, Some(discovered)) => {
+                    let mut pairs = overridden.unwrap_or_else(|| self.dal.supported_pairs_for(&bridge));
+                    for pair in discovered {
+                        merge_pair(&mut pairs, pair);
+                    }
+                    pairs
+                }
+                (Some(pairs), None) => pairs,
+                (None, None) => match self.dal.supported_pairs_for(&bridge) {
+                    pairs if pairs.is_empty() => sources.adapters[0].supported_pairs(),
+                    pairs => pairs,
+                },
+            };
+            let pairs = pairs.into_iter().filter(|pair| self.covers(&bridge, pair)).collect();
+            configured.push((sources, pairs));
+        }
+        configured
+    }
+
+    // Measures the graph's coverage of its configured pairs and records it on the graph, e.g.
+    // right after a warm start, before the first refresh
+    pub fn measure_coverage(&self) -> Coverage {
+        self.discover_if_due();
+        self.publish_coverage(&self.configured_pairs())
+    }
+
+    // Discovers pairs unless it was done less than discovery.interval ago
+    fn discover_if_due(&self) {
+        let interval = self.dal.config().discovery.interval;
+        {
+            let mut last_discovery = self.last_discovery.lock().unwrap();
+            if last_discovery.is_some_and(|at| at.elapsed() < interval) {
+                return;
+            }
+            *last_discovery = Some(Instant::now());
+        }
+        self.discover_pairs();
+    }
+
+    // Looks up the pairs every bridge configured with auto_discover advertises that the
+    // [discovery] chains and tokens admit, to be quoted besides its configured ones. Returns
+    // the bridges whose discovered pairs changed, whose quarantined pairs are probed again.
+    pub fn discover_pairs(&self) -> Vec<String> {
+        let config = self.dal.config();
+        let mut bridges: Vec<&String> = config.bridges.iter().filter(|(_, bridge)| bridge.auto_discover).map(|(name, _)| name).collect();
+        bridges.sort();
+        let mut changes = Vec::new();
+        for bridge in bridges.into_iter().filter(|bridge| self.takes_bridge(bridge)) {
+            let Some(sources) = self.sources(bridge) else {
+                continue;
+            };
+            let mut pairs = Vec::new();
+            for pair in sources.adapters[0].supported_pairs() {
+                if self.admits(&config.discovery.chains, &config.discovery.tokens, &pair) {
+                    merge_pair(&mut pairs, pair);
+                }
+            }
+            let previous = self.discovered.lock().unwrap().get(bridge).cloned();
+            if previous.as_ref() == Some(&pairs) {
+                continue;
+            }
+            let previous = previous.unwrap_or_default();
+            let missing_from = |listed: &[SupportedPair], pair: &SupportedPair| {
+                !listed.iter().any(|other| other.matches(&pair.src_chain, &pair.dst_chain, &pair.src_token, &pair.dst_token))
+            };
+            let added = pairs.iter().filter(|pair| missing_from(&previous, pair)).count();
+            let removed = previous.iter().filter(|pair| missing_from(&pairs, pair)).count();
+            self.dal.logger().info_with("discovered pairs changed", &[("bridge", bridge), ("pairs", &pairs.len()), ("added", &added), ("removed", &removed)]);
+            self.quarantine.forget_bridge(bridge);
+            self.discovered.lock().unwrap().insert(bridge.clone(), pairs);
+            changes.push(bridge.clone());
+        }
+        changes
+    }
+
+    // Pairs with an active edge under their bridge's name count fully, or at STALE_COVERAGE_WEIGHT
+    // when the edge was restored from a snapshot and not quoted since. A graph configured with no
+    // pairs is fully covered.
+    fn publish_coverage(&self, configured: &[(Arc<Sources>, Vec<SupportedPair>)]) -> Coverage {
+        let mut coverage = Coverage::default();
+        for (sources, pairs) in configured {
+            let aggregated = format!("{}:", sources.bridge);
+            for pair in pairs {
+                coverage.configured += 1;
+                let from = self.asset_node_id(&pair.src_chain, &pair.src_token);
+                let to = self.asset_node_id(&pair.dst_chain, &pair.dst_token);
+                let edges: Vec<_> = self.graph
+                    .get_outgoing_edges(from)
+                    .into_iter()
+                    .filter(|edge| edge.to == to && (*edge.bridge_name == *sources.bridge || edge.bridge_name.starts_with(&aggregated) || quoted_by(edge, &sources.bridge)))
+                    .collect();
+                if edges.iter().any(|edge| !edge.is_stale()) {
+                    coverage.fresh += 1;
+                } else if !edges.is_empty() {
+                    coverage.stale += 1;
+                } else if coverage.missing_sample.len() < MISSING_PAIRS_SAMPLE {
+                    coverage.missing_sample.push(self.quarantine_key(&sources.bridge, pair));
+                }
+            }
+        }
+        coverage.fraction = match coverage.configured {
+            0 => 1.0,
+            configured => (coverage.fresh as f64 + coverage.stale as f64 * STALE_COVERAGE_WEIGHT) / configured as f64,
+        };
+
+        let min_coverage = self.dal.config().global.min_coverage;
+        let warming_up = self.graph.coverage().is_none_or(|previous| !previous.warmed_up);
+        coverage.warmed_up = !warming_up || coverage.fraction >= min_coverage;
+        if warming_up && coverage.warmed_up {
+            self.dal.logger().info_with("graph warmed up, serving routes", &[("coverage", &coverage.fraction), ("pairs", &coverage.configured)]);
+        }
+        self.graph.set_coverage(coverage.clone());
+        coverage
+    }
+
+    // Puts the quote's cost in the [fx] quote currency when a converter is configured, then adds
+    // the gas the transfer costs on its source chain. Fails when one of its fees can't be
+    // converted, leaving the quote for the caller to skip.
+    pub(crate) async fn price(&self, outcome: &mut FetchOutcome) -> Result<(), FxError> {
+        self.normalize_cost(outcome).await?;
+        self.add_source_gas(outcome).await?;
+        self.cost_in_source(outcome).await
+    }
+
+    // Notes the quote-currency cost in the source token too, at the converter's price for it, as
+    // that's what comes out of an amount sent. Quotes whose source token has no price are skipped
+    // like those with an unconvertible fee.
+    async fn cost_in_source(&self, outcome: &mut FetchOutcome) -> Result<(), FxError> {
+        let (Some(fx), Ok(quote)) = (&self.fx, &mut outcome.result) else {
+            return Ok(());
+        };
+        let pair = &outcome.pair;
+        let unit = Money::new(1.0, CurrencyId::token(&pair.src_chain, &pair.src_token));
+        let rate = self.in_quote_currency(fx.as_ref(), &unit, pair).await?;
+        quote.cost_in_source = Some(quote.cost / rate);
+        Ok(())
+    }
+
+    // Rebuilds the quote's cost from its fees, each converted into the quote currency. A quote
+    // without fees is taken to cost its `cost` in the source token.
+    async fn normalize_cost(&self, outcome: &mut FetchOutcome) -> Result<(), FxError> {
+        let (Some(fx), Ok(quote)) = (&self.fx, &mut outcome.result) else {
+            return Ok(());
+        };
+        let pair = &outcome.pair;
+        let fees: Vec<Money> = match quote.fee_components.is_empty() {
+            true => vec![Money::new(quote.cost, CurrencyId::token(&pair.src_chain, &pair.src_token))],
+            false => quote.fee_components.iter().map(|fee| fee.amount.clone()).collect(),
+        };
+        let mut cost = 0.0;
+        for fee in &fees {
+            cost += self.in_quote_currency(fx.as_ref(), fee, pair).await?;
+        }
+        quote.cost = cost;
+        Ok(())
+    }
+
+    // `money` converted under its symbol: a chain's native token under the registry's native
+    // symbol, other tokens under the registry's symbol or, for the pair's own tokens, the one
+    // the pair was configured with
+    async fn in_quote_currency(&self, fx: &dyn FxConverter, money: &Money, pair: &SupportedPair) -> Result<f64, FxError> {
+        let symbol = match &money.currency {
+            CurrencyId::Fiat(code) => code.clone(),
+            CurrencyId::Token { chain, address } => {
+                let (chain, token) = self.asset_node(chain, address);
+                let native = match money.currency.is_native() {
+                    true => self.dal.registry().resolve_chain(&chain).ok().map(|found| found.native_token),
+                    false => None,
+                };
+                let ours = [&pair.src_token, &pair.dst_token].into_iter().any(|own| own.eq_ignore_ascii_case(&token));
+                let configured = pair.token_symbol.as_deref().filter(|_| ours).unwrap_or_default();
+                match native.unwrap_or_else(|| self.symbol(&chain, &token, configured)) {
+                    symbol if symbol.is_empty() => return Err(FxError::UnknownCurrency { chain, currency: address.clone() }),
+                    symbol => symbol,
+                }
+            }
+        };
+        fx.convert(money.amount, &symbol).await
+    }
+
+    // Adds the gas the transfer costs on its source chain to the quote's cost and lists it as a
+    // "source_gas" fee: in the chain's native token at the converter's price when there's one,
+    // else in the quote's token. Quotes whose gas can't be estimated, or without a converter
+    // whose token can't be priced, are left as they are.
+    async fn add_source_gas(&self, outcome: &mut FetchOutcome) -> Result<(), FxError> {
+        let (Some(gas), Ok(quote)) = (&self.gas, &mut outcome.result) else {
+            return Ok(());
+        };
+        let pair = &outcome.pair;
+        let (chain, token) = self.asset_node(&pair.src_chain, &pair.src_token);
+        let estimate = match gas.estimate(&chain, &GasAction::Bridge { bridge: outcome.adapter.clone() }).await {
+            Ok(estimate) => estimate,
+            Err(err) => {
+                self.dal.logger().debug_with("no gas estimate, cost leaves gas out", &[("chain", &chain), ("error", &err)]);
+                return Ok(());
+            }
+        };
+        let fee = match &self.fx {
+            Some(fx) => {
+                let fee = Money::new(estimate.native_cost(), CurrencyId::native(&chain));
+                quote.cost += self.in_quote_currency(fx.as_ref(), &fee, pair).await?;
+                fee
+            }
+            None => {
+                let symbol = self.symbol(&chain, &token, pair.token_symbol.as_deref().unwrap_or_default());
+                let Some(amount) = self.gas_in(&chain, &symbol, &estimate) else {
+                    self.dal.logger().debug_with("no USD price for the quote's token, cost leaves gas out", &[("token", &symbol)]);
+                    return Ok(());
+                };
+                quote.cost += amount;
+                Money::new(amount, CurrencyId::token(&pair.src_chain, &pair.src_token))
+            }
+        };
+        quote.fee_components.push(FeeComponent { name: "source_gas".to_string(), amount: fee });
+        Ok(())
+    }
+
+    // The gas in units of the token `symbol`: the chain's native token (or its wrapped form) is
+    // charged the native cost as is, other tokens go through their [gas] token_usd price
+    fn gas_in(&self, chain: &str, symbol: &str, estimate: &GasEstimate) -> Option<f64> {
+        let unwrapped = fx::unwrapped(symbol).unwrap_or(symbol);
+        let native = self.dal.registry().resolve_chain(chain).map(|found| found.native_token);
+        if native.is_ok_and(|native| native.eq_ignore_ascii_case(symbol) || native.eq_ignore_ascii_case(unwrapped)) {
+            return Some(estimate.native_cost());
+        }
+        let (_, usd) = self.dal.config().gas.token_usd.iter().find(|(priced, _)| priced.eq_ignore_ascii_case(symbol))?;
+        Some(estimate.usd_cost() / usd)
+    }
+
+    // The adapters quoting `bridge`, in the order they're tried: those of its source_policy, else
+    // its own. None when none of them can be built.
+    fn sources(&self, bridge: &str) -> Option<Arc<Sources>> {
+        let policy = self.dal.config().bridges.get(bridge).and_then(|config| config.source_policy.as_ref());
+        let names = match policy {
+            Some(policy) => policy.ordered(),
+            None => vec![bridge],
+        };
+        let adapters: Vec<_> = names
+            .into_iter()
+            .filter_map(|name| match self.dal.adapter(name) {
+                Ok(adapter) => Some(adapter),
+                Err(err) => {
+                    self.dal.logger().warn_with("skipping bridge", &[("bridge", &name), ("error", &err)]);
+                    None
+                }
+            })
+            .collect();
+        (!adapters.is_empty()).then(|| Arc::new(Sources { bridge: bridge.to_string(), adapters, policy: policy.is_some() }))
+    }
+
+    // Quotes every pair on its bridge's first source. Pairs of bridges with a source policy that
+    // got no quote, or one an aggregator routed through another bridge, are asked of the next
+    // source, and so on until one quotes them. A pair no source quotes keeps the first source's
+    // error. Each pair's outcome, under the bridge's name whichever source quoted it, is queued
+    // on `updates` as soon as it's final; fetching waits while the queue is full.
+    async fn fetch_from_sources(&self, jobs: Vec<(Arc<Sources>, SupportedPair, RequestContext)>, updates: UpdateSender, report: &Mutex<RefreshReport>) {
+        let mut first_errors: Vec<Option<FetchOutcome>> = jobs.iter().map(|_| None).collect();
+        let mut pending: Vec<usize> = (0..jobs.len()).collect();
+        for attempt in 0.. {
+            if pending.is_empty() {
+                break;
+            }
+            let batch = pending
+                .iter()
+                .map(|&index| {
+                    let (sources, pair, context) = &jobs[index];
+                    (Arc::clone(&sources.adapters[attempt]), pair.clone(), context.clone())
+                })
+                .collect();
+            let mut retry = Vec::new();
+            let mut fetched = std::pin::pin!(self.dal.fetch_jobs_streamed(batch, self.concurrency));
+            while let Some((position, outcome)) = fetched.next().await {
+                let index = pending[position];
+                let (sources, pair, context) = &jobs[index];
+                let outcome = sources.attribute(outcome);
+                if !outcome.is_ok() && attempt + 1 < sources.adapters.len() {
+                    first_errors[index].get_or_insert(outcome);
+                    retry.push(index);
+                    continue;
+                }
+                let mut outcome = match outcome.is_ok() {
+                    true => outcome,
+                    false => first_errors[index].take().unwrap_or(outcome),
+                };
+                self.learn_liquidity(&sources.adapters[attempt], &mut outcome).instrument(context.span.clone()).await;
+                self.track_source(sources, pair, &outcome);
+                self.queue_update(outcome, context.clone(), &updates, report).await;
+            }
+            pending = retry;
+        }
+    }
+
+    // A quote for a probe amount says nothing of how much more the route can carry when the API
+    // doesn't report its depth, so the depth is learned by quoting the route along
+    // LIQUIDITY_LADDER, once per LIQUIDITY_RELEARN. A quote whose depth can't be learned keeps
+    // the probe's output as its liquidity.
+    async fn learn_liquidity(&self, adapter: &Arc<DynBridgeAdapter>, outcome: &mut FetchOutcome) {
+        let Ok(quote) = &mut outcome.result else {
+            return;
+        };
+        if !quote.liquidity_unknown {
+            return;
+        }
+        let key = self.quarantine_key(&adapter.name(), &outcome.pair);
+        let now = unix_now();
+        let learned = self.learned_liquidity.lock().unwrap().get(&key).copied();
+        let depth = match learned {
+            Some((depth, learned_at)) if now < learned_at + LIQUIDITY_RELEARN => Some(depth),
+            _ => {
+                let probed = match probe_request(&outcome.pair) {
+                    Ok(request) => self.dal.probe_depth(adapter.as_ref().as_ref(), &request, &LIQUIDITY_LADDER).await.map_err(|err| err.to_string()),
+                    Err(err) => Err(err.to_string()),
+                };
+                match probed.map(|profile| profile.max_amount) {
+                    Ok(Some(depth)) => {
+                        self.learned_liquidity.lock().unwrap().insert(key, (depth, now));
+                        Some(depth)
+                    }
+                    Ok(None) => None,
+                    Err(err) => {
+                        self.dal.logger().warn_with("route depth not learned", &[("adapter", &adapter.name()), ("pair", &key), ("error", &err)]);
+                        None
+                    }
+                }
+            }
+        };
+        if let Some(depth) = depth {
+            quote.liquidity = depth;
+            quote.liquidity_unknown = false;
+        }
+    }
+
+    // Prices an outcome and queues it for the graph. Quotes whose fees can't be priced are
+    // skipped.
+    async fn queue_update(&self, mut outcome: FetchOutcome, context: RequestContext, updates: &UpdateSender, report: &Mutex<RefreshReport>) {
+        if let Some(latency) = outcome.latency {
+            report.lock().unwrap().timed(&outcome.adapter, latency);
+        }
+        if let Err(err) = self.price(&mut outcome).instrument(context.span.clone()).await {
+            report.lock().unwrap().fail(&outcome.adapter);
+            let pair = format!("{}->{}", outcome.pair.src_chain, outcome.pair.dst_chain);
+            context.span.in_scope(|| self.dal.logger().warn_with("quote skipped, its fees can't be converted", &[("adapter", &outcome.adapter), ("pair", &pair), ("error", &err)]));
+            return;
+        }
+        updates.send(EdgeUpdateMsg { outcome, context }).await;
+    }
+
+    // Applies queued updates until every fetch is done: whatever is waiting at once, up to
+    // global.update_batch_size, goes into the graph as one change. Being the only writer of
+    // quotes, it orders them, see `upsert`. Batches are applied on the blocking pool, as they
+    // wait for the graph's batch lock.
+    async fn apply_updates(self: Arc<Self>, mut queued: UpdateReceiver, report: Arc<Mutex<RefreshReport>>) {
+        let batch_size = self.dal.config().global.update_batch_size;
+        let dispatch = tracing::dispatcher::get_default(Clone::clone);
+        while let Some(batch) = queued.next_batch(batch_size).await {
+            let (updater, report, dispatch) = (Arc::clone(&self), Arc::clone(&report), dispatch.clone());
+            let applied = tokio::task::spawn_blocking(move || tracing::dispatcher::with_default(&dispatch, || {
+                let _upserts = updater.dal.time_scope(GRAPH_UPSERT, &[("outcomes", &batch.len())]);
+                let mut report = report.lock().unwrap();
+                updater.graph.batch(|| {
+                    for EdgeUpdateMsg { outcome, context } in batch {
+                        context.span.in_scope(|| updater.apply(outcome, &mut report));
+                    }
+                });
+            }));
+            if let Err(err) = applied.await
+                && err.is_panic()
+            {
+                std::panic::resume_unwind(err.into_panic());
+            }
+        }
+    }
+
+    // Logs when a bridge's pair moves off its preferred source and when it's back on it
+    fn track_source(&self, sources: &Sources, pair: &SupportedPair, outcome: &FetchOutcome) {
+        let (Some(source), true) = (&outcome.source, outcome.is_ok()) else {
+            return;
+        };
+        let preferred = sources.adapters[0].name();
+        let previous = self.sources_in_use.lock().unwrap().insert(self.quarantine_key(&sources.bridge, pair), source.clone());
+        let pair = format!("{}->{}", pair.src_chain, pair.dst_chain);
+        if *source != preferred && previous.as_ref() != Some(source) {
+            self.dal.logger().warn_with("bridge quoted through a fallback source", &[("bridge", &sources.bridge), ("pair", &pair), ("source", source)]);
+        } else if *source == preferred && previous.is_some_and(|previous| previous != preferred) {
+            self.dal.logger().info_with("bridge back on its preferred source", &[("bridge", &sources.bridge), ("pair", &pair), ("source", source)]);
+        }
+    }
+
+    pub(crate) fn apply(&self, outcome: FetchOutcome, report: &mut RefreshReport) {
+        let pair = format!("{}->{}", outcome.pair.src_chain, outcome.pair.dst_chain);
+        // A quote out of bounds is a failure of the adapter's in the report, but neither clears
+        // the pair's failures towards quarantine nor adds to them
+        if let (Ok(quote), Some(checker)) = (&outcome.result, &self.sanity)
+            && let Err(violation) = checker.check(&outcome.adapter, probe_amount(), quote)
+        {
+            self.flagged.lock().unwrap().insert(outcome.source.clone().unwrap_or_else(|| outcome.adapter.clone()), unix_now());
+            self.reject(&outcome.adapter, &outcome.pair, quote, violation, report);
+            return;
+        }
+        self.track_failures(&outcome);
+        match outcome.result {
+            Ok(quote) => match self.upsert(&outcome.adapter, outcome.source.as_deref(), &outcome.pair, &quote) {
+                Ok(None) => {
+                    report.superseded += 1;
+                    self.dal.logger().debug_with("quote older than the edge's, left out", &[("adapter", &outcome.adapter), ("pair", &pair), ("quoted_at", &quote.quoted_at)]);
+                }
+                Ok(Some(added)) => {
+                    if let Some(valid_until) = quote.valid_until {
+                        let lifetime = Duration::from_secs(valid_until.saturating_sub(quote.quoted_at));
+                        self.quote_lifetimes.lock().unwrap().insert(self.quarantine_key(&outcome.adapter, &outcome.pair), lifetime);
+                    }
+                    match added {
+                        true => report.added += 1,
+                        false => report.updated += 1,
+                    }
+                    self.dal.logger().debug_with("edge updated", &[("added", &added), ("cost", &quote.cost)]);
+                }
+                Err(err) => {
+                    report.fail(&outcome.adapter);
+                    self.dal.logger().warn_with("quote rejected by the graph", &[("adapter", &outcome.adapter), ("pair", &pair), ("error", &err)]);
+                }
+            },
+            Err(err) => {
+                report.fail(&outcome.adapter);
+                if matches!(err, AdapterError::UnsupportedPair { .. }) {
+                    report.deactivated += self.deactivate(&outcome.adapter, &outcome.pair);
+                }
+                self.dal.logger().debug_with("quote failed", &[("adapter", &outcome.adapter), ("pair", &pair), ("error", &err)]);
+            }
+        }
+    }
+
+    // Leaves the pair's edge with the metrics of its last good quote, flagged stale until a
+    // plausible quote comes in: still searched at those metrics, but no longer pinned to, see
+    // Graph::set_edge_stale
+    fn reject(&self, adapter: &str, pair: &SupportedPair, quote: &BridgeEdge, violation: SanityViolation, report: &mut RefreshReport) {
+        let from = self.asset_node_id(&pair.src_chain, &pair.src_token);
+        let to = self.asset_node_id(&pair.dst_chain, &pair.dst_token);
+        self.graph.set_edge_stale(from, to, &self.edge_label(adapter, quote), true);
+        let pair = format!("{}->{}", pair.src_chain, pair.dst_chain);
+        self.dal.logger().warn_with("quote failed a sanity check", &[("adapter", &adapter), ("pair", &pair), ("violation", &violation)]);
+        report.fail(adapter);
+        report.rejected.push(RejectedQuote { adapter: adapter.to_string(), pair, violation });
+    }
+
+    // Counts failures that are down to the pair rather than the bridge being unavailable, which
+    // quarantine the pair once there are enough in a row; any quote clears them
+    fn track_failures(&self, outcome: &FetchOutcome) {
+        let key = self.quarantine_key(&outcome.adapter, &outcome.pair);
+        let pair = format!("{}->{}", outcome.pair.src_chain, outcome.pair.dst_chain);
+        match &outcome.result {
+            Ok(_) => {
+                if self.quarantine.succeeded(&key) {
+                    self.dal.logger().info_with("quarantined pair quoted again", &[("adapter", &outcome.adapter), ("pair", &pair)]);
+                }
+            }
+            Err(err) if matches!(err.disposition(), Disposition::Drop | Disposition::Fail) => {
+                if let Some(quarantined) = self.quarantine.failed(&key, &outcome.adapter, &outcome.pair, err.to_string()) {
+                    self.dal.logger().warn_with("pair quarantined after repeated failures", &[
+                        ("adapter", &outcome.adapter),
+                        ("pair", &pair),
+                        ("failures", &quarantined.failures),
+                        ("next_probe", &quarantined.next_probe),
+                        ("error", &quarantined.last_error),
+                    ]);
+                }
+            }
+            Err(_) => {}
+        }
+    }
+
+    async fn refresh_swaps(&self, report: &mut RefreshReport) {
+        let swaps: Vec<(&Arc<dyn DexAdapter>, SwapPair)> = self
+            .dexes
+            .iter()
+            .flat_map(|dex| dex.swap_pairs().into_iter().map(move |pair| (dex, pair)))
+            .collect();
+        let quotes = futures::future::join_all(swaps.iter().map(|(dex, pair)| dex.quote_in(pair, SWAP_PROBE_AMOUNT))).await;
+        let _upserts = self.dal.time_scope(GRAPH_UPSERT, &[("swaps", &swaps.len())]);
+        for ((dex, pair), quote) in swaps.iter().zip(quotes) {
+            let venue = dex.name();
+            let swap = format!("{}:{}->{}", pair.chain, pair.token_in, pair.token_out);
+            let applied = quote.map_err(|err| err.to_string()).and_then(|quote| self.upsert_swap(&venue, pair, &quote));
+            match applied {
+                Ok(true) => report.added += 1,
+                Ok(false) => report.updated += 1,
+                Err(err) => {
+                    report.fail(&venue);
+                    self.dal.logger().warn_with("swap not quoted", &[("venue", &venue), ("swap", &swap), ("error", &err)]);
+                }
+            }
+        }
+    }
+
+    // Whether the swap edge was added rather than updated. The swap's cost is what the probe
+    // loses to it, SWAP_PROBE_AMOUNT in less amount_out at the quote's rate, plus its gas: the
+    // venue's fee is already out of amount_out. Swap edges join tokens of like value, so the
+    // two are taken at par. Its quote expires like a bridge quote without an expiry would.
+    fn upsert_swap(&self, venue: &str, pair: &SwapPair, quote: &SwapQuote) -> Result<bool, String> {
+        let rate = quote.amount_out / SWAP_PROBE_AMOUNT;
+        if !rate.is_finite() || rate <= 0.0 {
+            return Err(format!("swapping {} gives {}", SWAP_PROBE_AMOUNT, quote.amount_out));
+        }
+        let swap_cost = (SWAP_PROBE_AMOUNT * (1.0 - rate)).max(0.0);
+        let (chain, token_in) = self.asset_node(&pair.chain, &pair.token_in);
+        let (_, token_out) = self.asset_node(&pair.chain, &pair.token_out);
+        let from = self.graph.get_or_create_asset_node(&chain, &token_in, &self.symbol(&chain, &token_in, &pair.symbol_in));
+        let to = self.graph.get_or_create_asset_node(&chain, &token_out, &self.symbol(&chain, &token_out, &pair.symbol_out));
+        self.graph.get_or_create_exchange_node(venue, &chain);
+
+        let metrics = EdgeMetrics { cost: swap_cost + quote.gas, speed: SWAP_SECS, liquidity: quote.liquidity, risk: SWAP_RISK };
+        let added = match self.graph.update_edge_metrics(from, to, venue, metrics.clone()).map_err(|err| err.to_string())? {
+            true => {
+                self.graph.set_edge_active(from, to, venue, true);
+                false
+            }
+            false => self.graph.add_swap_edge(from, to, venue, metrics, None, None).map_err(|err| err.to_string())?,
+        };
+        let quoted_at = unix_now();
+        let valid_until = quoted_at + DEFAULT_QUOTE_VALIDITY.as_secs();
+        self.graph.set_edge_quote(from, to, venue, Some(EdgeQuote {
+            reference: format!("{}:{}:{}->{}:{}", venue, chain, token_in, token_out, quoted_at),
+            quoted_at,
+            valid_until: Some(valid_until),
+            fees: vec![
+                QuoteFee { name: "swap".to_string(), amount: swap_cost, token: Some(token_in.clone()) },
+                QuoteFee { name: "gas".to_string(), amount: quote.gas, token: None },
+            ],
+            speed_breakdown: None,
+            source: Some(venue.to_string()),
+            sources: Vec::new(),
+            cost_in_source: None,
+        }));
+        self.expiries.lock().unwrap().insert((from, to, venue.to_string()), valid_until);
+        Ok(added)
+    }
+
+    // What `adapter`'s edges for `quote` are labelled with: the bridge [edge_identity] maps the
+    // adapter and the bridge it routed through to, else BridgeEdge::label
+    fn edge_label(&self, adapter: &str, quote: &BridgeEdge) -> String {
+        match self.dal.config().edge_identity.canonical(adapter, quote.underlying_bridge(adapter)) {
+            Some(bridge) => bridge.to_string(),
+            None => quote.label(adapter),
+        }
+    }
+
+    // Whether the edge was added rather than updated, None when `adapter` already gave a newer
+    // quote for it, which can arrive first. `source` is the adapter that quoted for
+    // `adapter`, if another one did. The latest quote of every bridge quoting the same edge is
+    // kept, and the edge takes the one whose source the edge's source_policy weighs highest, the
+    // freshest of those when several are, preferring quotes that haven't expired.
+    fn upsert(&self, adapter: &str, source: Option<&str>, pair: &SupportedPair, quote: &BridgeEdge) -> Result<Option<bool>, GraphError> {
+        let configured = pair.token_symbol.as_deref().unwrap_or_default();
+        let (src_chain, src_token) = self.asset_node(&pair.src_chain, &pair.src_token);
+        let (dst_chain, dst_token) = self.asset_node(&pair.dst_chain, &pair.dst_token);
+        let from = self.graph.get_or_create_asset_node(&src_chain, &src_token, &self.symbol(&src_chain, &src_token, configured));
+        let to = self.graph.get_or_create_asset_node(&dst_chain, &dst_token, &self.symbol(&dst_chain, &dst_token, configured));
+        let label = self.edge_label(adapter, quote);
+        for chain in [&src_chain, &dst_chain] {
+            self.graph.get_or_create_exchange_node(&label, chain);
+        }
+
+        let corroboration = Corroboration { source: source.unwrap_or(adapter).to_string(), pair: pair.clone(), quote: quote.clone() };
+        let quotes = {
+            let mut corroborations = self.corroborations.lock().unwrap();
+            let quotes = corroborations.entry((from, to, label.clone())).or_default();
+            if !update_if_newer(quotes, adapter, corroboration) {
+                return Ok(None);
+            }
+            quotes.clone()
+        };
+        let (preferred, _) = self.preferred(&label, &quotes);
+        if preferred != adapter && self.note_sources(from, to, &label, &quotes) {
+            return Ok(Some(false));
+        }
+        self.write_edge(from, to, &label, &quotes).map(Some)
+    }
+
+    // The quote an edge shows of those `quotes` its bridges last gave, with the bridge it's of
+    fn preferred<'a>(&self, bridge: &str, quotes: &'a BTreeMap<String, Corroboration>) -> (&'a str, &'a Corroboration) {
+        let now = unix_now();
+        let policy = self.dal.config().bridges.get(bridge).and_then(|config| config.source_policy.clone());
+        let order = policy.as_ref().map(|policy| policy.ordered()).unwrap_or_default();
+        let rank = |source: &str| order.iter().position(|name| *name == source).unwrap_or(order.len());
+        quotes
+            .iter()
+            .min_by_key(|(_, corroboration)| (!corroboration.quote.is_valid_at(now), rank(&corroboration.source), std::cmp::Reverse(corroboration.quote.quoted_at)))
+            .map(|(bridge, corroboration)| (bridge.as_str(), corroboration))
+            .expect("an edge is only written with a quote")
+    }
+
+    // Lists the adapters quoting an edge on its quote, and extends its expiry to their latest,
+    // leaving its metrics alone. False when there's no such edge with a quote.
+    fn note_sources(&self, from: NodeId, to: NodeId, label: &str, quotes: &BTreeMap<String, Corroboration>) -> bool {
+        let Some(edge) = self.graph.get_outgoing_edges(from).into_iter().find(|edge| edge.to == to && &*edge.bridge_name == label) else {
+            return false;
+        };
+        let Some(mut current) = edge.quote.read().unwrap().clone() else {
+            return false;
+        };
+        current.sources = sources(quotes);
+        self.graph.set_edge_quote(from, to, label, Some(current));
+        self.set_expiry(from, to, label, quotes);
+        true
+    }
+
+    // Writes the preferred of `quotes` to the edge, adding it when there's none. Limits are only
+    // taken from the quote that adds an edge; the graph can't change them on an existing one.
+    fn write_edge(&self, from: NodeId, to: NodeId, label: &str, quotes: &BTreeMap<String, Corroboration>) -> Result<bool, GraphError> {
+        let (_, Corroboration { source, pair, quote }) = self.preferred(label, quotes);
+        let (src_chain, src_token) = self.asset_node(&pair.src_chain, &pair.src_token);
+        let (dst_chain, dst_token) = self.asset_node(&pair.dst_chain, &pair.dst_token);
+
+        // Bridges report their own duration; the wait for finality at either end comes on top
+        let finality_secs = self.dal.finality().settlement_time(&src_chain, &dst_chain).as_secs_f64();
+        let metrics = EdgeMetrics { cost: quote.cost, speed: quote.speed + finality_secs, liquidity: quote.liquidity, risk: quote.risk };
+        // A known edge takes the quote's limits in place, keeping its identity and history
+        let min_amount = quote.min_amount.or(pair.min_amount);
+        let max_amount = quote.max_amount.or(pair.max_amount);
+        let update = EdgeUpdate {
+            metrics: Some(metrics.clone()),
+            min_amount: Some(min_amount),
+            max_amount: Some(max_amount),
+            active: Some(true),
+            ..EdgeUpdate::default()
+        };
+        let added = match self.graph.update_edge(from, to, label, update)? {
+            Some(_) => false,
+            None => self.graph.add_edge(from, to, label, metrics.clone(), min_amount, max_amount)?,
+        };
+
+        self.graph.set_edge_quote(from, to, label, Some(EdgeQuote {
+            reference: format!("{}:{}:{}:{}", label, src_chain, dst_chain, quote.quoted_at),
+            quoted_at: quote.quoted_at,
+            valid_until: quote.valid_until,
+            fees: quote
+                .fee_components
+                .iter()
+                .map(|fee| QuoteFee { name: fee.name.clone(), amount: fee.amount.amount, token: Some(fee.amount.currency.to_string()) })
+                .collect(),
+            speed_breakdown: Some(SpeedBreakdown { bridge_secs: quote.speed, finality_secs }),
+            source: Some(source.clone()),
+            sources: sources(quotes),
+            cost_in_source: quote.cost_in_source,
+        }));
+        let edge_id = history::edge_id(label, (&src_chain, &src_token), (&dst_chain, &dst_token));
+        self.record_history(edge_id.clone(), &metrics);
+        self.check_alerts(EdgeEvent::Metrics {
+            edge: EdgeIdentity { edge_id, bridge: label.to_string(), src_chain, src_token, dst_chain, dst_token },
+            metrics,
+            at: unix_now(),
+        });
+        self.set_expiry(from, to, label, quotes);
+        Ok(added)
+    }
+
+    // An edge expires once none of the quotes behind it is valid, never when one has no expiry
+    fn set_expiry(&self, from: NodeId, to: NodeId, label: &str, quotes: &BTreeMap<String, Corroboration>) {
+        let until = quotes.values().map(|corroboration| corroboration.quote.valid_until).try_fold(0, |latest, until| until.map(|until| latest.max(until)));
+        let mut expiries = self.expiries.lock().unwrap();
+        let key = (from, to, label.to_string());
+        match until {
+            Some(until) => expiries.insert(key, until),
+            None => expiries.remove(&key),
+        };
+    }
+
+    // A sample that can't be stored is logged; the graph still takes the quote
+    fn record_history(&self, edge_id: String, metrics: &EdgeMetrics) {
+        let Some(history) = &self.history else {
+            return;
+        };
+        let sample = MetricsSample {
+            timestamp: unix_now(),
+            cost: metrics.cost,
+            speed: metrics.speed,
+            liquidity: metrics.liquidity,
+            risk: metrics.risk,
+            samples: 1,
+        };
+        if let Err(err) = history.record(&edge_id, &sample) {
+            self.dal.logger().warn_with("cannot record edge metrics", &[("edge", &edge_id), ("error", &err)]);
+        }
+    }
+
+    // Alerts fired by `event` are held until the refresh is done
+    fn check_alerts(&self, event: EdgeEvent) {
+        if let Some(engine) = &self.alerts {
+            self.fired.lock().unwrap().extend(engine.evaluate(&event));
+        }
+    }
+
+    // Checks the alert rules against an edge the updater just switched off
+    fn deactivated(&self, from: NodeId, to: NodeId, label: &str) {
+        if self.alerts.is_none() {
+            return;
+        }
+        let asset = |id| match self.graph.get_node(id).map(|node| node.node_type.clone()) {
+            Some(NodeType::Asset { chain, token_address, .. }) => Some((chain, token_address)),
+            _ => None,
+        };
+        let (Some((src_chain, src_token)), Some((dst_chain, dst_token))) = (asset(from), asset(to)) else {
+            return;
+        };
+        let edge_id = history::edge_id(label, (&src_chain, &src_token), (&dst_chain, &dst_token));
+        self.check_alerts(EdgeEvent::Deactivated {
+            edge: EdgeIdentity { edge_id, bridge: label.to_string(), src_chain, src_token, dst_chain, dst_token },
+            at: unix_now(),
+        });
+    }
+
+    // Sends the alerts fired since the last dispatch; notifiers that fail are logged
+    async fn dispatch_alerts(&self) {
+        let Some(engine) = &self.alerts else {
+            return;
+        };
+        let fired = std::mem::take(&mut *self.fired.lock().unwrap());
+        for err in engine.dispatch(&fired).await {
+            self.dal.logger().warn_with("cannot send alert", &[("error", &format!("{:#}", err))]);
+        }
+    }
+
+    // Compacts the history at most once per HISTORY_COMPACTION_INTERVAL
+    fn compact_history(&self, now: u64) {
+        let Some(history) = &self.history else {
+            return;
+        };
+        if now < self.last_compacted.load(Ordering::Acquire) + HISTORY_COMPACTION_INTERVAL {
+            return;
+        }
+        self.last_compacted.store(now, Ordering::Release);
+        match history.compact(now) {
+            Ok(report) => self.dal.logger().debug_with("edge history compacted", &[("downsampled", &report.downsampled), ("pruned", &report.pruned)]),
+            Err(err) => self.dal.logger().warn_with("cannot compact edge history", &[("error", &err)]),
+        }
+    }
+
+    // Drops the adapter's quotes for `pair` from the edges they were behind and switches off the
+    // edges left without any, aggregated ones included. Edges still quoted for another bridge go
+    // over to its quote. Returns how many edges were switched off.
+    fn deactivate(&self, adapter: &str, pair: &SupportedPair) -> usize {
+        let from = self.asset_node_id(&pair.src_chain, &pair.src_token);
+        let to = self.asset_node_id(&pair.dst_chain, &pair.dst_token);
+        let aggregated = format!("{}:", adapter);
+        let mut deactivated = 0;
+        for edge in self.graph.get_outgoing_edges(from).iter().filter(|edge| edge.to == to) {
+            let key = (from, to, edge.bridge_name.to_string());
+            let remaining = {
+                let mut corroborations = self.corroborations.lock().unwrap();
+                match corroborations.get_mut(&key) {
+                    Some(quotes) if quotes.contains_key(adapter) => {
+                        quotes.remove(adapter);
+                        let remaining = quotes.clone();
+                        if remaining.is_empty() {
+                            corroborations.remove(&key);
+                        }
+                        Some(remaining)
+                    }
+                    Some(_) => continue,
+                    // Edges quoted before this updater was, e.g. restored from a snapshot: their
+                    // quote still lists who quoted them, so one merged from several sources stays
+                    // up on the others', with the metrics it has until one of them quotes again
+                    None => {
+                        let quote = edge.get_quote();
+                        let listed = quote.as_ref().is_some_and(|quote| quote.sources.iter().any(|source| source == adapter));
+                        if !(listed || &*edge.bridge_name == adapter || edge.bridge_name.starts_with(&aggregated)) {
+                            continue;
+                        }
+                        if let Some(mut quote) = quote.filter(|quote| quote.sources.iter().any(|source| source != adapter)) {
+                            quote.sources.retain(|source| source != adapter);
+                            if quote.source.as_deref() == Some(adapter) {
+                                quote.source = quote.sources.first().cloned();
+                            }
+                            self.graph.set_edge_quote(from, to, &edge.bridge_name, Some(quote));
+                            continue;
+                        }
+                        None
+                    }
+                }
+            };
+            match remaining {
+                Some(quotes) if !quotes.is_empty() => {
+                    if let Err(err) = self.write_edge(from, to, &edge.bridge_name, &quotes) {
+                        self.dal.logger().warn_with("cannot switch edge to its other sources", &[("edge", &edge.bridge_name), ("error", &err)]);
+                    }
+                }
+                _ => {
+                    if self.graph.set_edge_active(from, to, &edge.bridge_name, false) {
+                        self.deactivated(from, to, &edge.bridge_name);
+                        deactivated += 1;
+                    }
+                }
+            }
+        }
+        deactivated
+    }
+
+    // Switches off edges whose latest quote expired by unix time `now` and returns how many
+    fn expire_at(&self, now: u64) -> usize {
+        let mut expiries = self.expiries.lock().unwrap();
+        let mut expired = 0;
+        expiries.retain(|(from, to, label), until| {
+            if *until > now {
+                return true;
+            }
+            if self.graph.set_edge_active(*from, *to, label, false) {
+                self.deactivated(*from, *to, label);
+                expired += 1;
+            }
+            self.corroborations.lock().unwrap().remove(&(*from, *to, label.clone()));
+            false
+        });
+        expired
+    }
+}
+
+// What routes over the graph make of the adapters its quotes come from, see ConfidenceModel
+impl SourceQuality for GraphUpdater {
+    fn error_rate(&self, source: &str) -> Option<f64> {
+        self.dal.adapter_metrics(source)?.recent_error_rate
+    }
+
+    fn flagged(&self, source: &str) -> bool {
+        self.flagged.lock().unwrap().get(source).is_some_and(|at| unix_now().saturating_sub(*at) < FLAGGED_FOR)
+    }
+}
+
+// Keeps `corroboration` as `bridge`'s latest quote unless it already has one quoted later.
+// Whether it was kept.
+fn update_if_newer(quotes: &mut BTreeMap<String, Corroboration>, bridge: &str, corroboration: Corroboration) -> bool {
+    if quotes.get(bridge).is_some_and(|latest| latest.quote.quoted_at > corroboration.quote.quoted_at) {
+        return false;
+    }
+    quotes.insert(bridge.to_string(), corroboration);
+    true
+}
+
+// The adapters that gave `quotes`, in order
+fn sources(quotes: &BTreeMap<String, Corroboration>) -> Vec<String> {
+    let sources: BTreeSet<&String> = quotes.values().map(|corroboration| &corroboration.source).collect();
+    sources.into_iter().cloned().collect()
+}
+
+// Whether `adapter` is among those whose quotes are merged into `edge`
+fn quoted_by(edge: &Edge, adapter: &str) -> bool {
+    edge.quote.read().unwrap().as_ref().is_some_and(|quote| quote.sources.iter().any(|source| source == adapter))
+}
+
+// The first item of each list, then the second of each, and so on
+fn round_robin<T>(lists: Vec<Vec<T>>) -> Vec<T> {
+    let mut iters: Vec<_> = lists.into_iter().map(Vec::into_iter).collect();
+    let mut merged = Vec::new();
+    loop {
+        let before = merged.len();
+        merged.extend(iters.iter_mut().filter_map(Iterator::next));
+        if merged.len() == before {
+            return merged;
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::adapters::{self, mock::{MockAdapter, MockDex}};
+    use polypath_graph::{AmountLimits, EdgeKind, RouteIntent, RouteOptions, Router, RoutingEngine, RoutingParams};
+    use polypathroute_core::{AlertCondition, AlertRule, AlertsConfig};
+    use std::time::Duration;
+
+    pub(crate) const USDC_ETHEREUM: &str = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48";
+    pub(crate) const USDC_POLYGON: &str = "0x3c499c542cef5e3811e1192ce70d8cc03d5c3359";
+    pub(crate) const USDC_ARBITRUM: &str = "0xaf88d065e77c8cc2239327c5edb3a432268e5831";
+    pub(crate) const USDC_BASE: &str = "0x833589fcd6edb6e08f4c7c32d4f71b54bda02913";
+
+    fn pair(bridge: &str, src_chain: &str, src_token: &str, dst_chain: &str, dst_token: &str) -> String {
+        format!(r#"
+            [[bridges.{}.pairs]]
+            source_chain = "{}"
+            source_address = "{}"
+            source_token_name = "USDC"
+            destination_chain = "{}"
+            destination_address = "{}"
+            destination_token_name = "USDC"
+        "#, bridge, src_chain, src_token, dst_chain, dst_token)
+    }
+
+    // A bridge named `bridge` quoting ethereum -> polygon -> arbitrum, and configured for
+    // ethereum -> base too but not quoting it. Every quote takes `latency`.
+    pub(crate) fn updater(bridge: &'static str, latency: Duration) -> GraphUpdater {
+        adapters::register(bridge, move |context| {
+            let mut mock = MockAdapter::named(bridge).with_latency(latency);
+            for pair in adapters::pairs_from_config(&context.config).iter().filter(|pair| pair.dst_chain != "base") {
+                mock = mock.with_quote(&pair.src_chain, &pair.dst_chain, BridgeEdge {
+                    from: pair.src_chain.clone(),
+                    to: pair.dst_chain.clone(),
+                    cost: 1.0,
+                    speed: 60.0,
+                    liquidity: 1_000_000.0,
+                    risk: 0.1,
+                    valid_until: Some(unix_now() + 600),
+                    min_amount: Some(1.0),
+                    max_amount: Some(50_000.0),
+                    ..BridgeEdge::default()
+                });
+            }
+            Ok(Box::new(mock))
+        });
+        configured_updater(bridge)
+    }
+
+    fn normal(pairs: usize) -> FetchCounts {
+        FetchCounts { normal: pairs, ..FetchCounts::default() }
+    }
+
+    // An updater for `bridge` configured as above, whatever adapter is registered under its name
+    pub(crate) fn configured_updater(bridge: &str) -> GraphUpdater {
+        configured_updater_with(bridge, "")
+    }
+
+    // As `configured_updater`, with `global` added to the [global] table
+    fn configured_updater_with(bridge: &str, global: &str) -> GraphUpdater {
+        let config_path = std::env::temp_dir().join(format!("polypath-dal-updater-{}-{}.toml", bridge, std::process::id()));
+        std::fs::write(&config_path, format!(
+            "[global]\nupdate_interval = 60\ncache_ttl = 1\nlog_level = \"info\"\n{4}\n[bridges.{0}]\nbase_url = \"https://{0}.test\"\nchains = [\"ethereum\", \"polygon\", \"arbitrum\", \"base\"]\n{1}{2}{3}",
+            bridge,
+            pair(bridge, "ethereum", USDC_ETHEREUM, "polygon", USDC_POLYGON),
+            pair(bridge, "polygon", USDC_POLYGON, "arbitrum", USDC_ARBITRUM),
+            pair(bridge, "ethereum", USDC_ETHEREUM, "base", USDC_BASE),
+            global,
+        )).unwrap();
+        let dal = DalContext::new(config_path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&config_path).unwrap();
+        GraphUpdater::new(Arc::new(Graph::new(16)), dal)
+    }
+
+    #[tokio::test]
+    async fn configured_pairs_become_a_routable_graph() {
+        let updater = Arc::new(updater("relay", Duration::ZERO));
+        let graph = Arc::clone(updater.graph());
+        let eth = updater.asset_node_id("ethereum", USDC_ETHEREUM);
+        let arb = updater.asset_node_id("arbitrum", USDC_ARBITRUM);
+        let base = graph.get_or_create_asset_node("base", USDC_BASE, "USDC");
+        graph.get_or_create_asset_node("ethereum", &USDC_ETHEREUM.to_lowercase(), "USDC");
+        let metrics = EdgeMetrics { cost: 1.0, speed: 60.0, liquidity: 1000.0, risk: 0.1 };
+        // Left over from when relay still served base
+        graph.add_edge(eth, base, "relay", metrics, None, None).unwrap();
+
+        assert_eq!(updater.last_refreshed(), None);
+        let report = updater.refresh_once().await;
+        assert!(updater.last_refreshed().is_some_and(|at| at + 5 > unix_now()));
+        assert_eq!(report, RefreshReport {
+            added: 2, failed: 1, deactivated: 1, fetched: normal(3),
+            failed_by_adapter: BTreeMap::from([("relay".to_string(), 1)]),
+            // Quote latencies vary from run to run
+            slowest_quote_ms: report.slowest_quote_ms.clone(),
+            ..RefreshReport::default()
+        });
+        assert_eq!(graph.active_edge_count(), 2);
+        let edge = &graph.get_outgoing_edges(eth)[0];
+        assert_eq!((edge.min_amount(), edge.max_amount()), (Some(1.0), Some(50_000.0)));
+
+        let engine = RoutingEngine::new(Arc::clone(&graph), 4);
+        let path = updater.dal().find_path(&engine, eth, arb, &RoutingParams::cheapest()).unwrap();
+        assert_eq!(path.hops.len(), 2);
+        assert!(path.hops.iter().all(|hop| &*hop.bridge_name == "relay"));
+        assert_eq!(updater.asset_node_id("ETH", &USDC_ETHEREUM.to_lowercase()), eth);
+        let intent = RouteIntent {
+            from_chain: "eth".to_string(),
+            from_token: "usdc".to_string(),
+            to_chain: "arb1".to_string(),
+            to_token: "USDC".to_string(),
+            amount: 100.0,
+            preference: None,
+            src_address: None,
+        };
+        let canonical = updater.dal().canonical_intent(&intent).unwrap();
+        assert_eq!((canonical.from_chain.as_str(), canonical.to_chain.as_str()), ("ethereum", "arbitrum"));
+        assert_eq!(canonical.to_token, "0xaf88d065e77c8cC2239327C5EDb3A432268e5831");
+        assert_eq!(Router::new(Arc::clone(&graph)).best_routes(&canonical, &RouteOptions::default()).unwrap().len(), 1);
+        let quote = path.hops[0].quote.as_ref().unwrap();
+        assert!(quote.reference.starts_with("relay:ethereum:polygon:") && quote.valid_until.is_some());
+
+        let report = updater.refresh_once().await;
+        assert_eq!(report, RefreshReport {
+            updated: 2, failed: 1, fetched: normal(3),
+            failed_by_adapter: BTreeMap::from([("relay".to_string(), 1)]),
+            slowest_quote_ms: report.slowest_quote_ms.clone(),
+            ..RefreshReport::default()
+        });
+        assert_eq!(graph.edge_count(), 3);
+
+        // Both refreshes were recorded in the history under the edge's canonical id
+        let history = updater.history().unwrap();
+        let edge_id = updater.edge_id("relay", ("ETH", USDC_ETHEREUM), ("matic", USDC_POLYGON));
+        assert_eq!(edge_id, format!("relay:ethereum:{}->polygon:{}", USDC_ETHEREUM.to_lowercase(), USDC_POLYGON));
+        let samples = history.query(&edge_id, 0, u64::MAX, crate::Resolution::Raw).unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(history.latest(&edge_id).unwrap().map(|sample| sample.cost), Some(1.0));
+
+        // Quotes are valid for ten minutes; edges nobody refreshes by then go dark
+        assert_eq!(updater.expire_at(unix_now() + 601), 2);
+        assert!(updater.dal().find_path(&engine, eth, arb, &RoutingParams::cheapest()).is_none());
+    }
+
+    #[tokio::test]
+    async fn refreshed_limits_apply_to_the_edge_in_place() {
+        let quote = |min_amount: f64, max_amount: f64| BridgeEdge {
+            from: "ethereum".to_string(),
+            to: "polygon".to_string(),
+            cost: 1.0,
+            speed: 60.0,
+            liquidity: 1_000_000.0,
+            risk: 0.1,
+            min_amount: Some(min_amount),
+            max_amount: Some(max_amount),
+            ..BridgeEdge::default()
+        };
+        adapters::register("gauge", move |_| {
+            Ok(Box::new(
+                MockAdapter::named("gauge")
+                    .with_quote("ethereum", "polygon", quote(1.0, 50_000.0))
+                    .then_quote("ethereum", "polygon", quote(5.0, 20_000.0))
+                    .then_quote("ethereum", "polygon", quote(30_000.0, 20_000.0)),
+            ))
+        });
+        let updater = Arc::new(configured_updater("gauge"));
+        let graph = Arc::clone(updater.graph());
+        let eth = updater.asset_node_id("ethereum", USDC_ETHEREUM);
+        updater.refresh_once().await;
+        let edge = Arc::clone(&graph.get_outgoing_edges(eth)[0]);
+        graph.set_edge_active(eth, edge.to, "gauge", false);
+
+        // The pool shrank: same edge, new limits, and back on
+        assert_eq!(updater.refresh_once().await.updated, 1);
+        assert!(Arc::ptr_eq(&edge, &graph.get_outgoing_edges(eth)[0]));
+        assert_eq!(edge.amount_limits(), AmountLimits { min: Some(5.0), max: Some(20_000.0) });
+
+        // Limits that contradict each other are refused whole
+        let report = updater.refresh_once().await;
+        assert_eq!((report.updated, report.failed), (0, 3));
+        assert_eq!(edge.amount_limits(), AmountLimits { min: Some(5.0), max: Some(20_000.0) });
+    }
+
+    #[tokio::test]
+    async fn the_depth_of_routes_quoted_without_it_is_learned() {
+        // A quote that only knows what it delivers
+        let quote = |output: f64| BridgeEdge {
+            from: "ethereum".to_string(),
+            to: "polygon".to_string(),
+            cost: 0.001,
+            speed: 60.0,
+            liquidity: output,
+            liquidity_unknown: true,
+            risk: 0.1,
+            estimated_output: output,
+            ..BridgeEdge::default()
+        };
+        adapters::register("shallow", move |_| {
+            // The refresh's probe, then the ladder from 1 to 1M, which slips past 0.5% at 100k
+            let mut mock = MockAdapter::named("shallow").with_quote("ethereum", "polygon", quote(0.999));
+            for output in [0.999, 9.99, 99.9, 999.0, 9_990.0, 99_000.0] {
+                mock = mock.then_quote("ethereum", "polygon", quote(output));
+            }
+            Ok(Box::new(mock))
+        });
+        let updater = Arc::new(configured_updater("shallow"));
+        let eth = updater.asset_node_id("ethereum", USDC_ETHEREUM);
+        let liquidity = || updater.graph().get_outgoing_edges(eth)[0].get_metrics().liquidity;
+
+        updater.refresh_once().await;
+        assert_eq!(liquidity(), 10_000.0);
+        // Walking the ladder again would learn 1 from the last quote repeating
+        updater.refresh_once().await;
+        assert_eq!(liquidity(), 10_000.0);
+    }
+
+    #[tokio::test]
+    async fn quotes_out_of_bounds_leave_the_last_good_edge_stale() {
+        let quote = |cost: f64| BridgeEdge {
+            from: "ethereum".to_string(),
+            to: "polygon".to_string(),
+            cost,
+            speed: 60.0,
+            liquidity: 1_000_000.0,
+            risk: 0.1,
+            estimated_output: 1.0 - cost,
+            ..BridgeEdge::default()
+        };
+        adapters::register("sane", move |_| {
+            Ok(Box::new(
+                MockAdapter::named("sane")
+                    .with_quote("ethereum", "polygon", quote(0.01))
+                    .then_quote("ethereum", "polygon", quote(0.6))
+                    .then_quote("ethereum", "polygon", quote(0.02)),
+            ))
+        });
+        // Fees judged at the one-token probe itself
+        let bounds = polypathroute_core::SanityBounds { max_fee_fraction: Some(0.05), fee_reference_amount: Some(1.0), ..Default::default() };
+        let updater = Arc::new(configured_updater("sane").with_sanity_checker(QuoteSanityChecker::new(polypathroute_core::SanityConfig {
+            defaults: bounds,
+            bridges: HashMap::new(),
+        })));
+        let graph = Arc::clone(updater.graph());
+        let eth = updater.asset_node_id("ethereum", USDC_ETHEREUM);
+        assert_eq!(updater.refresh_once().await.added, 1);
+        let edge = Arc::clone(&graph.get_outgoing_edges(eth)[0]);
+
+        // A 60% fee is kept out of the graph and the edge left stale at its last good metrics
+        let report = updater.refresh_once().await;
+        assert_eq!((report.updated, report.failed), (0, 3));
+        assert_eq!(report.rejected, vec![RejectedQuote {
+            adapter: "sane".to_string(),
+            pair: "ethereum->polygon".to_string(),
+            violation: SanityViolation::FeeTooHigh { fraction: 0.6, max: 0.05 },
+        }]);
+        assert_eq!(edge.get_metrics().cost, 0.01);
+        assert!(edge.is_stale());
+        // and routes trust the adapter's quotes less for a while. The pairs it doesn't serve
+        // aren't failures of its.
+        assert!(updater.flagged("sane"));
+        assert_eq!(updater.error_rate("sane"), Some(0.0));
+        assert!(!updater.flagged("conduit"));
+        assert_eq!(updater.error_rate("conduit"), None);
+
+        // A plausible quote takes it back
+        assert_eq!(updater.refresh_once().await.updated, 1);
+        assert_eq!(edge.get_metrics().cost, 0.02);
+        assert!(!edge.is_stale());
+    }
+
+    #[tokio::test]
+    async fn dex_swaps_become_swap_edges_either_side_of_bridges() {
+        const USDT_ETHEREUM: &str = "0xdac17f958d2ee523a2206206994597c13d831ec7";
+        const USDT_POLYGON: &str = "0xc2132d05d31c914a87c6611c10748aeb04b58e8f";
+        let dex = MockDex::named("uniswap")
+            .with_swap(SwapPair::new("ethereum", (USDT_ETHEREUM, "USDT"), (USDC_ETHEREUM, "USDC")), 0.999, 0.3, 2.0)
+            .with_swap(SwapPair::new("polygon", (USDC_POLYGON, "USDC"), (USDT_POLYGON, "USDT")), 0.999, 0.3, 0.01);
+        let updater = Arc::new(updater("conduit", Duration::ZERO).with_dex(Arc::new(dex)));
+        let graph = Arc::clone(updater.graph());
+
+        let report = updater.refresh_once().await;
+        assert_eq!(report, RefreshReport {
+            added: 4, failed: 1, fetched: normal(3),
+            failed_by_adapter: BTreeMap::from([("conduit".to_string(), 1)]),
+            // Quote latencies vary from run to run
+            slowest_quote_ms: report.slowest_quote_ms.clone(),
+            ..RefreshReport::default()
+        });
+        let usdt = updater.asset_node_id("ethereum", USDT_ETHEREUM);
+        let swap = &graph.get_outgoing_edges(usdt)[0];
+        assert_eq!((swap.kind, &*swap.bridge_name), (EdgeKind::Swap, "uniswap"));
+        // 1 USDT gives 0.999 USDC less the 0.3 fee, so 0.301 is lost to the swap besides 2 of gas
+        assert!((swap.get_metrics().cost - 2.301).abs() < 1e-9, "{}", swap.get_metrics().cost);
+        let quote = swap.get_quote().unwrap();
+        assert_eq!(quote.valid_until, Some(quote.quoted_at + DEFAULT_QUOTE_VALIDITY.as_secs()));
+        assert!(matches!(&graph.get_node(usdt).unwrap().node_type, NodeType::Asset { token_symbol, .. } if token_symbol == "USDT"));
+
+        let router = Router::new(Arc::clone(&graph));
+        let intent = RouteIntent {
+            from_chain: "ethereum".to_string(),
+            from_token: USDT_ETHEREUM.to_string(),
+            to_chain: "polygon".to_string(),
+            to_token: USDT_POLYGON.to_string(),
+            amount: 100.0,
+            preference: Some("cheapest".to_string()),
+            src_address: None,
+        };
+        let routes = router.best_routes(&intent, &RouteOptions::default()).unwrap();
+        let hops = &routes[0].ranked.path.hops;
+        assert_eq!(hops.iter().map(|hop| (hop.kind, &*hop.bridge_name)).collect::<Vec<_>>(), [
+            (EdgeKind::Swap, "uniswap"),
+            (EdgeKind::Bridge, "conduit"),
+            (EdgeKind::Swap, "uniswap"),
+        ]);
+        assert!(router.best_routes(&intent, &RouteOptions { max_swaps: Some(0), ..RouteOptions::default() }).unwrap().is_empty());
+
+        let report = updater.refresh_once().await;
+        assert_eq!((report.added, report.updated), (0, 4));
+
+        // Swap quotes expire like bridge quotes
+        updater.expire_at(unix_now() + DEFAULT_QUOTE_VALIDITY.as_secs() + 1);
+        assert!(!swap.is_active());
+        assert!(graph.get_outgoing_edges(usdt).is_empty());
+    }
+
+    #[tokio::test]
+    async fn bridges_fall_back_to_their_next_source_and_return_to_the_preferred_one() {
+        let quote = |cost: f64, via: Option<&str>| BridgeEdge {
+            cost,
+            speed: 60.0,
+            liquidity: 1_000_000.0,
+            risk: 0.1,
+            via: via.map(str::to_string),
+            valid_until: Some(unix_now() + 600),
+            ..BridgeEdge::default()
+        };
+        // The direct adapter is down on the second refresh and its circuit open on the third
+        let direct = MockAdapter::named("ferry")
+            .with_quote("ethereum", "polygon", quote(1.0, None))
+            .with_failing_calls("ethereum", "polygon", 1..2, AdapterError::Upstream { status: 503, body_snippet: String::new() })
+            .with_failing_calls("ethereum", "polygon", 2..3, AdapterError::CircuitOpen { retry_at: std::time::Instant::now() })
+            .with_failure("ethereum", "arbitrum", AdapterError::Network("reset".to_string()));
+        let direct = Arc::new(std::sync::Mutex::new(Some(direct)));
+        adapters::register("ferry", move |_| Ok(Box::new(direct.lock().unwrap().take().unwrap())));
+        // The aggregator routes ethereum -> arbitrum over some other bridge
+        adapters::register("harbor", move |_| {
+            Ok(Box::new(
+                MockAdapter::named("harbor")
+                    .with_quote("ethereum", "polygon", quote(2.0, Some("Ferry")))
+                    .with_quote("ethereum", "arbitrum", quote(2.0, Some("canal"))),
+            ))
+        });
+        let config_path = std::env::temp_dir().join(format!("polypath-dal-updater-sources-{}.toml", std::process::id()));
+        std::fs::write(&config_path, format!(
+            "[global]\nupdate_interval = 60\ncache_ttl = 1\n[bridges.harbor]\nbase_url = \"https://harbor.test\"\nchains = [\"ethereum\"]\n[bridges.ferry]\nbase_url = \"https://ferry.test\"\nchains = [\"ethereum\", \"polygon\", \"arbitrum\"]\n[bridges.ferry.source_policy]\nsources = [{{ adapter = \"harbor\" }}, {{ adapter = \"ferry\", weight = 10 }}]\n{}{}",
+            pair("ferry", "ethereum", USDC_ETHEREUM, "polygon", USDC_POLYGON),
+            pair("ferry", "ethereum", USDC_ETHEREUM, "arbitrum", USDC_ARBITRUM),
+        )).unwrap();
+        let dal = DalContext::new(config_path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&config_path).unwrap();
+        let updater = Arc::new(GraphUpdater::new(Arc::new(Graph::new(16)), dal));
+        // Only ever quoted on ferry's behalf
+        updater.set_pairs("harbor", Vec::new());
+        let eth = updater.asset_node_id("ethereum", USDC_ETHEREUM);
+
+        let mut sources = Vec::new();
+        for refresh in 0..4 {
+            let report = updater.refresh_once().await;
+            // ethereum -> arbitrum gets no quote for ferry from either source
+            assert_eq!((report.added + report.updated, report.failed), (1, 1), "refresh {}", refresh);
+            let edges = updater.graph().get_outgoing_edges(eth);
+            assert_eq!(edges.len(), 1);
+            assert_eq!(&*edges[0].bridge_name, "ferry");
+            assert!(edges[0].is_active.load(Ordering::Acquire));
+            sources.push((edges[0].get_quote().unwrap().source.unwrap(), edges[0].get_metrics().cost));
+        }
+        let harbor = ("harbor".to_string(), 2.0);
+        assert_eq!(sources, [("ferry".to_string(), 1.0), harbor.clone(), harbor, ("ferry".to_string(), 1.0)]);
+        assert_eq!(updater.sources_in_use.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn routes_quoted_through_an_aggregator_merge_into_the_bridges_edge() {
+        let now = unix_now();
+        let quote = move |cost: f64, via: Option<&str>, quoted_at: u64| BridgeEdge {
+            cost,
+            speed: 60.0,
+            liquidity: 1_000_000.0,
+            risk: 0.1,
+            via: via.map(str::to_string),
+            quoted_at,
+            valid_until: Some(now + 600),
+            ..BridgeEdge::default()
+        };
+        let unsupported = || AdapterError::UnsupportedPair {
+            bridge: "weir".to_string(),
+            src_chain: "ethereum".to_string(),
+            dst_chain: "polygon".to_string(),
+            src_token: USDC_ETHEREUM.to_string(),
+            dst_token: USDC_POLYGON.to_string(),
+        };
+        // weir's own API stops serving the pair on the second refresh, the aggregator on the third
+        adapters::register("weir", move |_| {
+            Ok(Box::new(MockAdapter::named("weir").with_quote("ethereum", "polygon", quote(1.0, None, now)).with_failing_calls("ethereum", "polygon", 1..3, unsupported())))
+        });
+        adapters::register("pooler", move |_| {
+            Ok(Box::new(MockAdapter::named("pooler").with_quote("ethereum", "polygon", quote(2.0, Some("Weir"), now - 30)).with_failing_calls("ethereum", "polygon", 2..3, unsupported())))
+        });
+        let config_path = std::env::temp_dir().join(format!("polypath-dal-updater-identity-{}.toml", std::process::id()));
+        std::fs::write(&config_path, format!(
+            "[global]\nupdate_interval = 60\ncache_ttl = 1\n[bridges.weir]\nbase_url = \"https://weir.test\"\nchains = [\"ethereum\", \"polygon\"]\n[bridges.pooler]\nbase_url = \"https://pooler.test\"\nchains = [\"ethereum\", \"polygon\"]\n[[edge_identity.canonical]]\nadapter = \"pooler\"\nvia = \"weir\"\nbridge = \"weir\"\n{}{}",
+            pair("weir", "ethereum", USDC_ETHEREUM, "polygon", USDC_POLYGON),
+            pair("pooler", "ethereum", USDC_ETHEREUM, "polygon", USDC_POLYGON),
+        )).unwrap();
+        let dal = DalContext::new(config_path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&config_path).unwrap();
+        let updater = Arc::new(GraphUpdater::new(Arc::new(Graph::new(16)), dal));
+        let graph = Arc::clone(updater.graph());
+        let eth = updater.asset_node_id("ethereum", USDC_ETHEREUM);
+        let pol = updater.asset_node_id("polygon", USDC_POLYGON);
+
+        let report = updater.refresh_once().await;
+        assert_eq!((report.added, report.updated, report.failed), (1, 1, 0));
+        let edges = graph.get_outgoing_edges(eth);
+        assert_eq!(edges.len(), 1);
+        assert_eq!(&*edges[0].bridge_name, "weir");
+        // Both sources rank alike without a source_policy, so the fresher quote shows
+        let quote = edges[0].get_quote().unwrap();
+        assert_eq!((quote.source.as_deref(), edges[0].get_metrics().cost), (Some("weir"), 1.0));
+        assert_eq!(quote.sources, ["pooler", "weir"]);
+        assert_eq!(updater.measure_coverage().fraction, 1.0);
+        // The one route has no alternative for the search to diversify over
+        let engine = RoutingEngine::new(Arc::clone(&graph), 2);
+        let path = updater.dal().find_path(&engine, eth, pol, &RoutingParams::cheapest()).unwrap();
+        assert_eq!((path.hops.len(), path.hops[0].alternatives), (1, Some(0)));
+
+        // Without weir's own quote the edge carries on on the aggregator's
+        let report = updater.refresh_once().await;
+        assert_eq!((report.deactivated, report.failed), (0, 1));
+        let edge = &graph.get_outgoing_edges(eth)[0];
+        assert!(edge.is_active.load(Ordering::Acquire));
+        let quote = edge.get_quote().unwrap();
+        assert_eq!((quote.source.as_deref(), edge.get_metrics().cost), (Some("pooler"), 2.0));
+        assert_eq!(quote.sources, ["pooler"]);
+
+        // And is only switched off once no source quotes it
+        let report = updater.refresh_once().await;
+        assert_eq!((report.deactivated, report.failed), (1, 2));
+        assert_eq!((graph.edge_count(), graph.active_edge_count()), (1, 0));
+    }
+
+    #[tokio::test]
+    async fn restored_merged_edges_stay_up_on_their_other_sources() {
+        let unsupported = AdapterError::UnsupportedPair {
+            bridge: "sluice".to_string(),
+            src_chain: "ethereum".to_string(),
+            dst_chain: "polygon".to_string(),
+            src_token: USDC_ETHEREUM.to_string(),
+            dst_token: USDC_POLYGON.to_string(),
+        };
+        // sluice's own API no longer serves the pair and the aggregator is down for now
+        adapters::register("sluice", move |_| Ok(Box::new(MockAdapter::named("sluice").with_failing_calls("ethereum", "polygon", 0..usize::MAX, unsupported.clone()))));
+        adapters::register("gatherer", move |_| {
+            Ok(Box::new(MockAdapter::named("gatherer").with_failing_calls("ethereum", "polygon", 0..usize::MAX, AdapterError::Network("down".to_string()))))
+        });
+        let config_path = std::env::temp_dir().join(format!("polypath-dal-updater-restored-identity-{}.toml", std::process::id()));
+        std::fs::write(&config_path, format!(
+            "[global]\nupdate_interval = 60\ncache_ttl = 1\n[bridges.sluice]\nbase_url = \"https://sluice.test\"\nchains = [\"ethereum\", \"polygon\"]\n[bridges.gatherer]\nbase_url = \"https://gatherer.test\"\nchains = [\"ethereum\", \"polygon\"]\n[[edge_identity.canonical]]\nadapter = \"gatherer\"\nvia = \"sluice\"\nbridge = \"sluice\"\n{}{}",
+            pair("sluice", "ethereum", USDC_ETHEREUM, "polygon", USDC_POLYGON),
+            pair("gatherer", "ethereum", USDC_ETHEREUM, "polygon", USDC_POLYGON),
+        )).unwrap();
+        let dal = DalContext::new(config_path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&config_path).unwrap();
+        let updater = Arc::new(GraphUpdater::new(Arc::new(Graph::new(16)), dal));
+        let graph = Arc::clone(updater.graph());
+        // As a snapshot leaves it: the merged edge and its quote, but none of the quotes behind it
+        let eth = graph.get_or_create_asset_node("ethereum", &USDC_ETHEREUM.to_lowercase(), "USDC");
+        let pol = graph.get_or_create_asset_node("polygon", &USDC_POLYGON.to_lowercase(), "USDC");
+        graph.add_edge(eth, pol, "sluice", EdgeMetrics { cost: 1.0, speed: 60.0, liquidity: 1_000_000.0, risk: 0.1 }, None, None).unwrap();
+        graph.set_edge_quote(eth, pol, "sluice", Some(EdgeQuote {
+            reference: "sluice:ethereum:polygon:1".to_string(),
+            quoted_at: unix_now(),
+            valid_until: None,
+            fees: Vec::new(),
+            speed_breakdown: None,
+            source: Some("sluice".to_string()),
+            sources: vec!["gatherer".to_string(), "sluice".to_string()],
+            cost_in_source: None,
+        }));
+
+        let report = updater.refresh_once().await;
+        assert_eq!((report.deactivated, report.failed), (0, 2));
+        let edge = &graph.get_outgoing_edges(eth)[0];
+        assert!(edge.is_active.load(Ordering::Acquire));
+        let quote = edge.get_quote().unwrap();
+        assert_eq!((quote.source.as_deref(), quote.sources), (Some("gatherer"), vec!["gatherer".to_string()]));
+    }
+
+    #[tokio::test]
+    async fn refreshes_alert_on_edges_they_switch_off() {
+        let recorder = Arc::new(crate::alerts::tests::RecordingNotifier::default());
+        let config = AlertsConfig {
+            rules: vec![AlertRule {
+                name: "gone".to_string(),
+                bridge: Some("beacon".to_string()),
+                source_chain: None,
+                destination_chain: Some("base".to_string()),
+                condition: AlertCondition::EdgeDeactivated,
+            }],
+            ..AlertsConfig::default()
+        };
+        let updater = Arc::new(updater("beacon", Duration::ZERO).with_alert_engine(AlertEngine::new(&config).with_notifier(recorder.clone())));
+        let eth = updater.graph().get_or_create_asset_node("ethereum", &USDC_ETHEREUM.to_lowercase(), "USDC");
+        let base = updater.graph().get_or_create_asset_node("base", USDC_BASE, "USDC");
+        let metrics = EdgeMetrics { cost: 1.0, speed: 60.0, liquidity: 1000.0, risk: 0.1 };
+        updater.graph().add_edge(eth, base, "beacon", metrics, None, None).unwrap();
+
+        assert_eq!(updater.refresh_once().await.deactivated, 1);
+        let alerts = recorder.alerts.lock().unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].edge.edge_id, updater.edge_id("beacon", ("ethereum", USDC_ETHEREUM), ("base", USDC_BASE)));
+        assert_eq!((alerts[0].rule.as_str(), alerts[0].metric), ("gone", "active"));
+    }
+
+    // Prices gas on ethereum only, at 200k units and 20 gwei with ETH at $2500: $10 a transfer
+    #[derive(Debug)]
+    struct EthereumGas;
+
+    #[async_trait::async_trait]
+    impl GasEstimator for EthereumGas {
+        async fn estimate(&self, chain: &str, action: &GasAction) -> Result<GasEstimate, crate::GasError> {
+            assert_eq!(action, &GasAction::Bridge { bridge: "fueled".to_string() });
+            match chain {
+                "ethereum" => Ok(GasEstimate { gas_units: 200_000, gas_price_wei: 20_000_000_000, native_usd: 2500.0 }),
+                _ => Err(crate::GasError::UnknownChain(chain.to_string())),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn source_gas_is_added_to_edge_costs() {
+        let updater = Arc::new(updater("fueled", Duration::ZERO).with_gas_estimator(Arc::new(EthereumGas)));
+        updater.refresh_once().await;
+
+        let cost_and_fees = |chain: &str, token: &str| {
+            let edge = updater.graph().get_outgoing_edges(updater.asset_node_id(chain, token))[0].clone();
+            (edge.get_metrics().cost, edge.get_quote().unwrap().fees)
+        };
+        let (cost, fees) = cost_and_fees("ethereum", USDC_ETHEREUM);
+        assert!((cost - 11.0).abs() < 1e-9, "{}", cost);
+        assert_eq!(fees.len(), 1);
+        assert_eq!((fees[0].name.as_str(), fees[0].token.as_deref()), ("source_gas", Some(USDC_ETHEREUM)));
+        assert!((fees[0].amount - 10.0).abs() < 1e-9);
+        // No gas source for polygon: the quote's cost as it came
+        assert_eq!(cost_and_fees("polygon", USDC_POLYGON), (1.0, Vec::new()));
+    }
+
+    // A bridge named `bridge` charging `fees` for ethereum -> polygon and `polygon_fees` for
+    // polygon -> arbitrum, with the static [fx] rates `rates`
+    fn charging(bridge: &'static str, fees: Vec<FeeComponent>, polygon_fees: Vec<FeeComponent>, rates: &[(&str, f64)]) -> GraphUpdater {
+        let quote = |fees: Vec<FeeComponent>| BridgeEdge {
+            cost: fees.iter().map(|fee| fee.amount.amount).sum(),
+            speed: 60.0,
+            liquidity: 1_000_000.0,
+            risk: 0.1,
+            fee_components: fees,
+            ..BridgeEdge::default()
+        };
+        let (quoted, polygon_quoted) = (quote(fees), quote(polygon_fees));
+        adapters::register(bridge, move |_| {
+            Ok(Box::new(
+                MockAdapter::named(bridge)
+                    .with_quote("ethereum", "polygon", quoted.clone())
+                    .with_quote("polygon", "arbitrum", polygon_quoted.clone()),
+            ))
+        });
+        let config = polypathroute_core::FxConfig {
+            rates: rates.iter().map(|(symbol, rate)| (symbol.to_string(), *rate)).collect(),
+            ..polypathroute_core::FxConfig::default()
+        };
+        configured_updater(bridge).with_fx_converter(Arc::new(crate::StaticFxTable::from_config(&config)))
+    }
+
+    fn fee(name: &str, amount: f64, currency: CurrencyId) -> FeeComponent {
+        FeeComponent { name: name.to_string(), amount: Money::new(amount, currency) }
+    }
+
+    #[tokio::test]
+    async fn fees_in_mixed_currencies_are_summed_in_the_quote_currency() {
+        let updater = Arc::new(charging(
+            "mixed",
+            vec![
+                fee("relay", 2.0, CurrencyId::token("ethereum", USDC_ETHEREUM)),
+                fee("message", 0.001, CurrencyId::native("ethereum")),
+                fee("protocol", 0.5, CurrencyId::fiat("usd")),
+            ],
+            vec![fee("bridge", 3.0, CurrencyId::fiat("USD"))],
+            &[("USDC", 1.0), ("ETH", 2500.0)],
+        ));
+        assert_eq!(updater.refresh_once().await.added, 2);
+
+        // 2 USDC, 0.001 ETH at $2500 and $0.50
+        let edge = updater.graph().get_outgoing_edges(updater.asset_node_id("ethereum", USDC_ETHEREUM))[0].clone();
+        assert!((edge.get_metrics().cost - 5.0).abs() < 1e-9, "{}", edge.get_metrics().cost);
+        let fees = edge.get_quote().unwrap().fees;
+        assert_eq!((fees[1].name.as_str(), fees[1].amount), ("message", 0.001));
+        assert_eq!(fees[2].token.as_deref(), Some("USD"));
+        assert_eq!(updater.graph().get_outgoing_edges(updater.asset_node_id("polygon", USDC_POLYGON))[0].get_metrics().cost, 3.0);
+    }
+
+    #[tokio::test]
+    async fn costs_are_also_kept_in_the_source_token() {
+        // USDC priced at half the quote currency
+        let updater = Arc::new(charging(
+            "halved",
+            vec![fee("relay", 2.0, CurrencyId::token("ethereum", USDC_ETHEREUM)), fee("protocol", 0.5, CurrencyId::fiat("usd"))],
+            vec![fee("bridge", 3.0, CurrencyId::fiat("USD"))],
+            &[("USDC", 0.5)],
+        ));
+        assert_eq!(updater.refresh_once().await.added, 2);
+
+        // 1.5 in the quote currency is 3 USDC taken out of what's sent
+        let edge = updater.graph().get_outgoing_edges(updater.asset_node_id("ethereum", USDC_ETHEREUM))[0].clone();
+        assert_eq!(edge.get_metrics().cost, 1.5);
+        assert_eq!(edge.get_quote().unwrap().cost_in_source, Some(3.0));
+        let polygon = updater.graph().get_outgoing_edges(updater.asset_node_id("polygon", USDC_POLYGON))[0].clone();
+        assert_eq!(polygon.get_quote().unwrap().cost_in_source, Some(6.0));
+    }
+
+    #[tokio::test]
+    async fn quotes_with_a_fee_that_cant_be_converted_are_skipped() {
+        let updater = Arc::new(charging(
+            "unpriced",
+            vec![fee("relay", 2.0, CurrencyId::token("ethereum", USDC_ETHEREUM))],
+            // No rate for polygon's native token
+            vec![fee("relay", 2.0, CurrencyId::token("polygon", USDC_POLYGON)), fee("message", 3.0, CurrencyId::native("polygon"))],
+            &[("USDC", 1.0)],
+        ));
+        let report = updater.refresh_once().await;
+
+        // polygon -> arbitrum can't be priced and base isn't quoted at all
+        assert_eq!(report, RefreshReport {
+            added: 1, failed: 2, fetched: normal(3),
+            failed_by_adapter: BTreeMap::from([("unpriced".to_string(), 2)]),
+            // Quote latencies vary from run to run
+            slowest_quote_ms: report.slowest_quote_ms.clone(),
+            ..RefreshReport::default()
+        });
+        assert!(updater.graph().get_outgoing_edges(updater.asset_node_id("polygon", USDC_POLYGON)).is_empty());
+        assert_eq!(updater.graph().get_outgoing_edges(updater.asset_node_id("ethereum", USDC_ETHEREUM))[0].get_metrics().cost, 2.0);
+    }
+
+    #[tokio::test]
+    async fn finality_is_added_to_edge_speed() {
+        let updater = Arc::new(updater("settling", Duration::ZERO));
+        updater.refresh_once().await;
+
+        // 60s reported by the bridge, then 64 blocks of 12s on ethereum and 128 of 2s on polygon
+        let edge = updater.graph().get_outgoing_edges(updater.asset_node_id("ethereum", USDC_ETHEREUM))[0].clone();
+        assert_eq!(edge.get_metrics().speed, 60.0 + 768.0 + 256.0);
+        assert_eq!(edge.get_quote().unwrap().speed_breakdown, Some(SpeedBreakdown { bridge_secs: 60.0, finality_secs: 1024.0 }));
+        let edge_id = updater.edge_id("settling", ("ethereum", USDC_ETHEREUM), ("polygon", USDC_POLYGON));
+        assert_eq!(updater.history().unwrap().latest(&edge_id).unwrap().map(|sample| sample.speed), Some(1084.0));
+    }
+
+    #[tokio::test]
+    async fn unreachable_gas_oracles_fall_back_to_the_last_price() {
+        use wiremock::{Mock, MockServer, ResponseTemplate, matchers::method};
+
+        let oracle = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "gas_price_wei": 20_000_000_000u64 })))
+            .up_to_n_times(1)
+            .mount(&oracle)
+            .await;
+        let gas = polypathroute_core::GasConfig {
+            cache_ttl: Duration::ZERO,
+            chains: HashMap::from([
+                ("ethereum".to_string(), polypathroute_core::GasChainConfig {
+                    rpc_url: None,
+                    oracle_url: Some(oracle.uri()),
+                    native_usd: 2500.0,
+                    default_gas_price_gwei: None,
+                }),
+                ("polygon".to_string(), polypathroute_core::GasChainConfig {
+                    rpc_url: None,
+                    oracle_url: Some("http://127.0.0.1:9".to_string()),
+                    native_usd: 0.5,
+                    default_gas_price_gwei: Some(100.0),
+                }),
+            ]),
+            ..polypathroute_core::GasConfig::default()
+        };
+        let updater = updater("oracled", Duration::ZERO);
+        let estimator = crate::OracleGasEstimator::from_config(&gas, &updater.dal().config().bridges, updater.dal().logger().clone()).unwrap();
+        let updater = Arc::new(updater.with_gas_estimator(Arc::new(estimator)));
+        let eth = updater.asset_node_id("ethereum", USDC_ETHEREUM);
+        let polygon = updater.asset_node_id("polygon", USDC_POLYGON);
+        let cost = |node| updater.graph().get_outgoing_edges(node)[0].get_metrics().cost;
+
+        updater.refresh_once().await;
+        assert!((cost(eth) - 11.0).abs() < 1e-9, "{}", cost(eth));
+        // 200k units at the default 100 gwei, with POL at $0.50
+        assert!((cost(polygon) - 1.01).abs() < 1e-9, "{}", cost(polygon));
+
+        // The oracle stopped answering; the last price still applies
+        updater.refresh_once().await;
+        assert!((cost(eth) - 11.0).abs() < 1e-9, "{}", cost(eth));
+    }
+
+    // Collects what a subscriber writes
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn each_pair_is_traced_from_fetch_to_graph_update() {
+        let config_path = std::env::temp_dir().join(format!("polypath-dal-updater-traced-{}.toml", std::process::id()));
+        std::fs::write(
+            &config_path,
+            "[global]\nupdate_interval = 60\ncache_ttl = 1\nlog_level = \"info\"\n[history]\nenabled = false\n[bridges.tracer]\nbase_url = \"https://tracer.test\"\nchains = [\"ethereum\", \"polygon\", \"arbitrum\"]\n",
+        ).unwrap();
+        let dal = DalContext::new(config_path.to_str().unwrap()).unwrap().with_simulation(1);
+        std::fs::remove_file(&config_path).unwrap();
+        let updater = Arc::new(GraphUpdater::new(Arc::new(Graph::new(16)), dal));
+
+        let capture = Capture::default();
+        let writer = capture.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .json()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_writer(move || writer.clone())
+            .finish();
+        let report = {
+            let _default = tracing::subscriber::set_default(subscriber);
+            updater.refresh_once().await
+        };
+        assert_eq!(report.added, 6);
+
+        // trace ids by the pair their span is for, then the messages logged under each
+        let mut traces: HashMap<String, Vec<(String, String)>> = HashMap::new();
+        for line in String::from_utf8(capture.0.lock().unwrap().clone()).unwrap().lines() {
+            let event: serde_json::Value = serde_json::from_str(line).unwrap();
+            let span = &event["span"];
+            let (Some(trace_id), Some(fields)) = (span["trace_id"].as_str(), span["fields"].as_str()) else {
+                continue;
+            };
+            traces
+                .entry(fields.to_string())
+                .or_default()
+                .push((trace_id.to_string(), event["fields"]["message"].as_str().unwrap().to_string()));
+        }
+
+        assert_eq!(traces.len(), 6);
+        let events = &traces["adapter=tracer src_chain=ethereum dst_chain=polygon"];
+        let messages: Vec<&str> = events.iter().map(|(_, message)| message.as_str()).collect();
+        // The fetch is timed within the pair's trace too
+        assert_eq!(messages, ["fetching quote", "operation timed", "edge updated"]);
+        let mut trace_ids: Vec<&str> = Vec::new();
+        for events in traces.values() {
+            assert!(events.iter().all(|(trace_id, _)| *trace_id == events[0].0), "{:?}", events);
+            trace_ids.push(&events[0].0);
+        }
+        trace_ids.sort();
+        trace_ids.dedup();
+        assert_eq!(trace_ids.len(), 6);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn advertised_pairs_the_allow_list_admits_are_scheduled() {
+        let advertised = |src_chain: &str, src_token: &str, dst_chain: &str, dst_token: &str, symbol: &str| SupportedPair {
+            src_chain: src_chain.to_string(),
+            dst_chain: dst_chain.to_string(),
+            src_token: src_token.to_string(),
+            dst_token: dst_token.to_string(),
+            min_amount: None,
+            max_amount: None,
+            token_symbol: Some(symbol.to_string()),
+            priority: RefreshPriority::default(),
+            refresh_interval: None,
+        };
+        let token = |n: u8| format!("0x{:040x}", n);
+        let (usdt, dai, weth) = (token(1), token(2), token(3));
+        let admitted = vec![
+            advertised("ethereum", USDC_ETHEREUM, "polygon", USDC_POLYGON, "USDC"),
+            advertised("polygon", USDC_POLYGON, "arbitrum", USDC_ARBITRUM, "USDC"),
+            advertised("ethereum", &usdt, "arbitrum", &usdt, "USDT"),
+            advertised("arbitrum", &usdt, "ethereum", &usdt, "USDT"),
+        ];
+        let refused = vec![
+            advertised("ethereum", USDC_ETHEREUM, "base", USDC_BASE, "USDC"),
+            advertised("base", USDC_BASE, "optimism", &token(4), "USDC"),
+            advertised("ethereum", &dai, "polygon", &dai, "DAI"),
+            advertised("polygon", &dai, "ethereum", &dai, "DAI"),
+            advertised("optimism", &usdt, "arbitrum", &usdt, "USDT"),
+            advertised("ethereum", &weth, "optimism", &weth, "WETH"),
+        ];
+        let quote = BridgeEdge { cost: 1.0, speed: 60.0, liquidity: 1_000_000.0, risk: 0.1, ..BridgeEdge::default() };
+        let mut mock = MockAdapter::named("scout").with_advertised_pairs([admitted.clone(), refused].concat());
+        for (src_chain, dst_chain) in [("ethereum", "polygon"), ("polygon", "arbitrum"), ("ethereum", "arbitrum"), ("arbitrum", "ethereum")] {
+            mock = mock.with_quote(src_chain, dst_chain, BridgeEdge { from: src_chain.to_string(), to: dst_chain.to_string(), ..quote.clone() });
+        }
+        let mock = Arc::new(mock);
+        let registered = Arc::clone(&mock);
+        adapters::register("scout", move |_| Ok(Box::new(Arc::clone(&registered))));
+
+        // The configured pair is advertised too, so it's only quoted once
+        let config_path = std::env::temp_dir().join(format!("polypath-dal-updater-scout-{}.toml", std::process::id()));
+        std::fs::write(&config_path, format!(
+            "[discovery]\nchains = [\"ethereum\", \"Polygon\", \"arbitrum\"]\ntokens = [\"USDC\", \"usdt\"]\ninterval = \"30m\"\n[bridges.scout]\nbase_url = \"https://scout.test\"\nchains = [\"ethereum\", \"polygon\", \"arbitrum\"]\nauto_discover = true\n{}",
+            pair("scout", "ethereum", USDC_ETHEREUM, "polygon", USDC_POLYGON),
+        )).unwrap();
+        let dal = DalContext::new(config_path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&config_path).unwrap();
+        let updater = Arc::new(GraphUpdater::new(Arc::new(Graph::new(16)), dal));
+
+        let quoted = |since: usize| -> Vec<(String, String, String)> {
+            let mut quoted: Vec<_> = mock.requests()[since..]
+                .iter()
+                .map(|request| (request.src_chain.clone(), request.dst_chain.clone(), request.src_token.to_lowercase()))
+                .collect();
+            quoted.sort();
+            quoted
+        };
+        let expected = |pairs: &[SupportedPair]| -> Vec<(String, String, String)> {
+            let mut expected: Vec<_> = pairs.iter().map(|pair| (pair.src_chain.clone(), pair.dst_chain.clone(), pair.src_token.to_lowercase())).collect();
+            expected.sort();
+            expected
+        };
+
+        let report = updater.refresh_once().await;
+        assert_eq!((report.added, report.failed), (4, 0));
+        assert_eq!(quoted(0), expected(&admitted));
+        assert_eq!(updater.graph().coverage().unwrap().configured, 4);
+
+        // A reloaded config doesn't drop the discovered pairs, and its own pair wins over the
+        // advertised one
+        let mut reloaded = updater.dal().supported_pairs_for("scout");
+        reloaded[0].priority = RefreshPriority::Hot;
+        updater.set_pairs("scout", reloaded);
+        let calls = mock.call_count();
+        updater.refresh_once().await;
+        assert_eq!(quoted(calls), expected(&admitted));
+        let hot = |updater: &GraphUpdater| -> Vec<String> {
+            let (_, pairs) = &updater.configured_pairs()[0];
+            pairs.iter().filter(|pair| pair.priority == RefreshPriority::Hot).map(|pair| pair.dst_chain.clone()).collect()
+        };
+        assert_eq!(hot(&updater), ["polygon"]);
+
+        // polygon -> arbitrum is withdrawn and ethereum -> polygon USDT offered, which only
+        // counts once the interval is up
+        let mut readvertised: Vec<SupportedPair> = admitted.iter().filter(|pair| pair.src_chain != "polygon").cloned().collect();
+        readvertised.push(advertised("ethereum", &usdt, "polygon", &usdt, "USDT"));
+        readvertised.push(advertised("ethereum", &dai, "arbitrum", &dai, "DAI"));
+        mock.advertise(readvertised.clone());
+        let calls = mock.call_count();
+        updater.refresh_once().await;
+        assert_eq!(quoted(calls), expected(&admitted));
+
+        tokio::time::advance(Duration::from_secs(30 * 60)).await;
+        let calls = mock.call_count();
+        let report = updater.refresh_once().await;
+        assert_eq!(quoted(calls), expected(&readvertised[..4]));
+        assert_eq!((report.added, report.updated), (1, 3));
+        assert!(updater.discover_pairs().is_empty());
+        // Nor does discovering them again drop the reloaded config
+        assert_eq!(hot(&updater), ["polygon"]);
+    }
+
+    #[tokio::test]
+    async fn fetched_quotes_reach_the_graph_in_batches() {
+        let updater = Arc::new(updater("batched", Duration::ZERO));
+        let pairs: Vec<SupportedPair> = (1..=40)
+            .map(|index| SupportedPair {
+                src_chain: "ethereum".to_string(),
+                src_token: format!("0x{:040x}", index),
+                dst_chain: "polygon".to_string(),
+                dst_token: USDC_POLYGON.to_string(),
+                min_amount: None,
+                max_amount: None,
+                token_symbol: Some("USDC".to_string()),
+                priority: RefreshPriority::default(),
+                refresh_interval: None,
+            })
+            .collect();
+        updater.set_pairs("batched", pairs);
+        let mut changes = updater.graph().subscribe();
+        let version = updater.graph().version();
+
+        let report = updater.refresh_once().await;
+        assert_eq!(report.added, 40);
+        assert_eq!(updater.graph().active_edge_count(), 40);
+        let bumps = updater.graph().version() - version;
+        assert!((1..=40 / 4).contains(&bumps), "{} versions for 40 quotes", bumps);
+        assert_eq!(*changes.borrow_and_update(), updater.graph().version());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn fetches_run_on_while_the_graph_is_busy_until_the_queue_is_full() {
+        let quote = BridgeEdge { cost: 1.0, speed: 60.0, liquidity: 1_000_000.0, risk: 0.1, ..BridgeEdge::default() };
+        let mock = Arc::new(MockAdapter::named("backlogged").with_quote("ethereum", "polygon", quote));
+        let registered = Arc::clone(&mock);
+        adapters::register("backlogged", move |_| Ok(Box::new(Arc::clone(&registered))));
+        let updater = Arc::new(configured_updater_with("backlogged", "update_queue_capacity = 4").with_concurrency(2));
+        let pairs: Vec<SupportedPair> = (1..=40)
+            .map(|index| SupportedPair {
+                src_chain: "ethereum".to_string(),
+                src_token: format!("0x{:040x}", index),
+                dst_chain: "polygon".to_string(),
+                dst_token: USDC_POLYGON.to_string(),
+                min_amount: None,
+                max_amount: None,
+                token_symbol: Some("USDC".to_string()),
+                priority: RefreshPriority::default(),
+                refresh_interval: None,
+            })
+            .collect();
+        updater.set_pairs("backlogged", pairs);
+
+        // Another batch holds the graph, so the applier can't get a quote in
+        let (held, release) = std::sync::mpsc::channel::<()>();
+        let (holding, busy) = std::sync::mpsc::channel();
+        let graph = Arc::clone(updater.graph());
+        let holder = std::thread::spawn(move || graph.batch(|| {
+            holding.send(()).unwrap();
+            release.recv().ok();
+        }));
+        busy.recv().unwrap();
+
+        let refresh = tokio::spawn({
+            let updater = Arc::clone(&updater);
+            async move { updater.refresh_once().await }
+        });
+        // Fetches go on until the queue is full, then wait for room rather than pile quotes up
+        let mut fetched = 0;
+        loop {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            if mock.call_count() == fetched && fetched > 0 {
+                break;
+            }
+            fetched = mock.call_count();
+        }
+        assert!((4..40).contains(&fetched), "{} fetched", fetched);
+        assert!(!refresh.is_finished());
+        assert_eq!(updater.graph().edge_count(), 0);
+
+        drop(held);
+        holder.join().unwrap();
+        let report = refresh.await.unwrap();
+        assert_eq!(report.added, 40);
+        assert_eq!(updater.graph().active_edge_count(), 40);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn a_dropped_refresh_applies_no_more_than_the_batch_going_in() {
+        let quote = BridgeEdge { cost: 1.0, speed: 60.0, liquidity: 1_000_000.0, risk: 0.1, ..BridgeEdge::default() };
+        let mock = Arc::new(MockAdapter::named("abandoned").with_quote("ethereum", "polygon", quote));
+        let registered = Arc::clone(&mock);
+        adapters::register("abandoned", move |_| Ok(Box::new(Arc::clone(&registered))));
+        let updater = Arc::new(configured_updater_with("abandoned", "update_queue_capacity = 4\nupdate_batch_size = 1").with_concurrency(2));
+        let pairs: Vec<SupportedPair> = (1..=40)
+            .map(|index| SupportedPair {
+                src_chain: "ethereum".to_string(),
+                src_token: format!("0x{:040x}", index),
+                dst_chain: "polygon".to_string(),
+                dst_token: USDC_POLYGON.to_string(),
+                min_amount: None,
+                max_amount: None,
+                token_symbol: Some("USDC".to_string()),
+                priority: RefreshPriority::default(),
+                refresh_interval: None,
+            })
+            .collect();
+        updater.set_pairs("abandoned", pairs);
+
+        let (held, release) = std::sync::mpsc::channel::<()>();
+        let (holding, busy) = std::sync::mpsc::channel();
+        let graph = Arc::clone(updater.graph());
+        let holder = std::thread::spawn(move || graph.batch(|| {
+            holding.send(()).unwrap();
+            release.recv().ok();
+        }));
+        busy.recv().unwrap();
+
+        let refresh = tokio::spawn({
+            let updater = Arc::clone(&updater);
+            async move { updater.refresh_once().await }
+        });
+        let mut fetched = 0;
+        loop {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            if mock.call_count() == fetched && fetched > 0 {
+                break;
+            }
+            fetched = mock.call_count();
+        }
+        assert!(fetched >= 4, "{} fetched", fetched);
+
+        refresh.abort();
+        assert!(refresh.await.unwrap_err().is_cancelled());
+        drop(held);
+        holder.join().unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(updater.graph().edge_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn quotes_arriving_after_a_newer_one_are_left_out() {
+        let updater = Arc::new(updater("reordered", Duration::ZERO));
+        updater.refresh_once().await;
+        let (_, pairs) = &updater.configured_pairs()[0];
+        let pair = pairs.iter().find(|pair| pair.dst_chain == "polygon").unwrap().clone();
+        let now = unix_now();
+        let quoted = |quoted_at: u64, cost: f64| EdgeUpdateMsg {
+            outcome: FetchOutcome {
+                adapter: "reordered".to_string(),
+                pair: pair.clone(),
+                source: None,
+                result: Ok(BridgeEdge { cost, speed: 60.0, liquidity: 1_000_000.0, risk: 0.1, quoted_at, ..BridgeEdge::default() }),
+                latency: None,
+            },
+            context: updater.dal().quote_request("reordered", &pair),
+        };
+
+        // The newer quote overtakes the older one on its way to the graph
+        let (updates, queued) = updates::channel(4, updater.dal().metrics().clone());
+        assert!(updates.send(quoted(now + 10, 2.0)).await);
+        assert!(updates.send(quoted(now + 5, 3.0)).await);
+        drop(updates);
+        let report = Arc::new(Mutex::new(RefreshReport::default()));
+        Arc::clone(&updater).apply_updates(queued, Arc::clone(&report)).await;
+        let report = report.lock().unwrap().clone();
+        assert_eq!((report.updated, report.superseded), (1, 1));
+
+        let from = updater.asset_node_id(&pair.src_chain, &pair.src_token);
+        let edge = updater.graph().get_outgoing_edges(from).into_iter().find(|edge| &*edge.bridge_name == "reordered").unwrap();
+        assert_eq!(edge.metrics.read().cost, 2.0);
+        assert_eq!(edge.get_quote().unwrap().quoted_at, now + 10);
+    }
+}
@@ -0,0 +1,112 @@
+// Quotes on their way from a refresh's fetches to the graph. The queue is bounded: fetches wait
+// for room when the graph falls behind rather than drop quotes, and its depth is kept in the
+// graph_update_queue_depth gauge.
+
+use polypathroute_core::{MetricsManager, RequestContext};
+use tokio::sync::mpsc;
+
+use crate::batch::FetchOutcome;
+
+// One pair's fetched quote, or why there's none, with the request it's logged under
+#[derive(Debug)]
+pub(crate) struct EdgeUpdateMsg {
+    pub outcome: FetchOutcome,
+    pub context: RequestContext,
+}
+
+// A queue of at most `capacity` updates
+pub(crate) fn channel(capacity: usize, metrics: MetricsManager) -> (UpdateSender, UpdateReceiver) {
+    let (sender, receiver) = mpsc::channel(capacity.max(1));
+    (UpdateSender { sender, metrics: metrics.clone() }, UpdateReceiver { receiver, metrics })
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct UpdateSender {
+    sender: mpsc::Sender<EdgeUpdateMsg>,
+    metrics: MetricsManager,
+}
+
+impl UpdateSender {
+    // Waits for room in the queue. False once nothing receives the updates anymore.
+    pub async fn send(&self, update: EdgeUpdateMsg) -> bool {
+        let sent = self.sender.send(update).await.is_ok();
+        self.metrics.set_update_queue_depth(self.depth());
+        sent
+    }
+
+    pub fn depth(&self) -> usize {
+        self.sender.max_capacity() - self.sender.capacity()
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct UpdateReceiver {
+    receiver: mpsc::Receiver<EdgeUpdateMsg>,
+    metrics: MetricsManager,
+}
+
+impl UpdateReceiver {
+    // Waits for an update, then takes it with whatever else is queued, `max` at most. None once
+    // every sender is gone and the queue is empty.
+    pub async fn next_batch(&mut self, max: usize) -> Option<Vec<EdgeUpdateMsg>> {
+        let mut batch = Vec::new();
+        self.receiver.recv_many(&mut batch, max.max(1)).await;
+        self.metrics.set_update_queue_depth(self.receiver.len());
+        (!batch.is_empty()).then_some(batch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::{AdapterError, SupportedPair};
+    use polypathroute_core::RefreshPriority;
+    use std::time::Duration;
+    use tracing::Span;
+
+    fn update(index: usize) -> EdgeUpdateMsg {
+        let pair = SupportedPair {
+            src_chain: "base".to_string(),
+            dst_chain: "polygon".to_string(),
+            src_token: format!("0x{:040x}", index),
+            dst_token: "usdc".to_string(),
+            min_amount: None,
+            max_amount: None,
+            token_symbol: None,
+            priority: RefreshPriority::default(),
+            refresh_interval: None,
+        };
+        let outcome = FetchOutcome { adapter: "mock".to_string(), pair, source: None, result: Err(AdapterError::missing("quote")), latency: None };
+        EdgeUpdateMsg { outcome, context: RequestContext { trace_id: format!("trace-{}", index), span: Span::none() } }
+    }
+
+    #[tokio::test]
+    async fn a_slow_applier_holds_fetches_back_without_losing_updates() {
+        let metrics = MetricsManager::new();
+        let (updates, mut queued) = channel(4, metrics.clone());
+        let fetches = tokio::spawn(async move {
+            let mut deepest = 0;
+            for index in 0..20 {
+                assert!(updates.send(update(index)).await);
+                deepest = deepest.max(updates.depth());
+            }
+            deepest
+        });
+
+        // Nothing's applied yet, so fetches stop once the queue is full
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!fetches.is_finished());
+        assert!(metrics.encode_prometheus().contains("polypath_graph_update_queue_depth 4"));
+
+        let mut applied = Vec::new();
+        while let Some(batch) = queued.next_batch(3).await {
+            assert!(batch.len() <= 3);
+            applied.extend(batch.into_iter().map(|update| update.outcome.pair.src_token));
+            tokio::time::sleep(Duration::from_millis(2)).await;
+        }
+        assert!(fetches.await.unwrap() <= 4);
+        let expected: Vec<String> = (0..20).map(|index| format!("0x{:040x}", index)).collect();
+        assert_eq!(applied, expected);
+        assert!(metrics.encode_prometheus().contains("polypath_graph_update_queue_depth 0"));
+    }
+}
@@ -0,0 +1,139 @@
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use polypath_graph::{
+    EdgeMetrics, Graph, NodeId, RoutingEngine, RoutingParams, ScoringEngine,
+    testutil::{layered_graph, random_paths},
+};
+use std::{
+    hint::black_box,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+const SEED: u64 = 42;
+const WRITES: usize = 3_200;
+
+fn metrics(i: usize) -> EdgeMetrics {
+    EdgeMetrics { cost: 1.0 + (i % 7) as f64, speed: 60.0, liquidity: 1_000_000.0, risk: 0.1 }
+}
+
+fn nodes(graph: &Graph, count: usize) -> Vec<NodeId> {
+    (0..count).map(|i| graph.get_or_create_asset_node("chain", &format!("0x{:040x}", i), "TKN")).collect()
+}
+
+// Runs `write(graph, nodes, i)` for WRITES values of i split across `threads`, timing only the writes
+fn timed_writes(graph: &Graph, nodes: &[NodeId], threads: usize, write: impl Fn(&Graph, &[NodeId], usize) + Sync) -> Duration {
+    let per_thread = WRITES / threads;
+    let started = Instant::now();
+    thread::scope(|scope| {
+        for t in 0..threads {
+            let write = &write;
+            scope.spawn(move || {
+                for i in t * per_thread..(t + 1) * per_thread {
+                    write(graph, nodes, i);
+                }
+            });
+        }
+    });
+    started.elapsed()
+}
+
+fn add_edge(graph: &Graph, nodes: &[NodeId], i: usize) {
+    let from = nodes[i % nodes.len()];
+    let to = nodes[(i * 7 + 1) % nodes.len()];
+    graph.add_edge(from, to, &format!("bridge{}", i), metrics(i), None, None).unwrap();
+}
+
+fn writes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("writes");
+    group.throughput(Throughput::Elements(WRITES as u64));
+    for threads in [1, 8, 32] {
+        group.bench_with_input(BenchmarkId::new("add_edge", threads), &threads, |b, &threads| {
+            b.iter_custom(|iters| {
+                (0..iters)
+                    .map(|_| {
+                        let graph = Graph::new(64);
+                        let nodes = nodes(&graph, 1_000);
+                        timed_writes(&graph, &nodes, threads, add_edge)
+                    })
+                    .sum()
+            });
+        });
+
+        let graph = Graph::new(64);
+        let nodes = nodes(&graph, 1_000);
+        for i in 0..WRITES {
+            add_edge(&graph, &nodes, i);
+        }
+        group.bench_with_input(BenchmarkId::new("upsert", threads), &threads, |b, &threads| {
+            b.iter_custom(|iters| {
+                (0..iters)
+                    .map(|_| {
+                        timed_writes(&graph, &nodes, threads, |graph, nodes, i| {
+                            let from = nodes[i % nodes.len()];
+                            let to = nodes[(i * 7 + 1) % nodes.len()];
+                            assert!(graph.update_edge_metrics(from, to, &format!("bridge{}", i), metrics(i + 1)).unwrap());
+                        })
+                    })
+                    .sum()
+            });
+        });
+    }
+    group.finish();
+}
+
+fn neighbours(c: &mut Criterion) {
+    let mut group = c.benchmark_group("neighbours");
+    let params = RoutingParams::balanced();
+    for fan_out in [10, 100, 1_000] {
+        let graph = Graph::new(64);
+        let nodes = nodes(&graph, fan_out + 1);
+        for (i, to) in nodes[1..].iter().enumerate() {
+            graph.add_edge(nodes[0], *to, "bridge", metrics(i), None, None).unwrap();
+        }
+        group.throughput(Throughput::Elements(fan_out as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(fan_out), &nodes[0], |b, hub| {
+            b.iter(|| black_box(graph.neighbours(*hub, &params)));
+        });
+    }
+    group.finish();
+}
+
+fn routing(c: &mut Criterion) {
+    let params = RoutingParams::balanced();
+    let mut group = c.benchmark_group("find_path");
+    group.sample_size(20);
+    for edges in [1_000, 10_000, 100_000] {
+        // Six layers, a quarter of the edges spent on keeping them connected
+        let layered = layered_graph(SEED, 6, edges / 20, edges);
+        let (source, sink) = (layered.source(), layered.sink());
+        let engine = RoutingEngine::new(Arc::new(layered.graph), 6);
+        group.bench_with_input(BenchmarkId::from_parameter(edges), &edges, |b, _| {
+            b.iter(|| black_box(engine.find_path(source, sink, &params)));
+        });
+    }
+    group.finish();
+
+    let layered = layered_graph(SEED, 6, 500, 10_000);
+    let (source, sink) = (layered.source(), layered.sink());
+    let engine = RoutingEngine::new(Arc::new(layered.graph), 6);
+    c.bench_function("find_candidate_paths/10000/k5", |b| {
+        b.iter(|| black_box(engine.find_candidate_paths(source, sink, &params, 5)));
+    });
+}
+
+fn scoring(c: &mut Criterion) {
+    let scoring = ScoringEngine::new();
+    let params = RoutingParams::balanced();
+    let paths = random_paths(SEED, 100);
+    c.bench_function("score_and_rank/100", |b| {
+        b.iter_batched(
+            || paths.clone(),
+            |paths| black_box(scoring.score_and_rank(paths, &params, 10).unwrap()),
+            criterion::BatchSize::SmallInput,
+        );
+    });
+}
+
+criterion_group!(benches, writes, neighbours, routing, scoring);
+criterion_main!(benches);
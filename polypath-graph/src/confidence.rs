@@ -0,0 +1,236 @@
+// How far a route's quotes can be trusted, 0-1 for each hop and path. A hop's confidence falls
+// as its quote ages through its validity window, with its source's error rate and when sanity
+// checks lately flagged its source's quotes; a path's follows from its hops' by a combinator.
+
+use std::{collections::HashMap, fmt, sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::{Edge, Hop};
+
+// How long a quote that doesn't say when it expires is good for, unless set
+pub const DEFAULT_VALIDITY_WINDOW: Duration = Duration::from_secs(60);
+// What a flagged source's confidence is multiplied by
+const FLAGGED_FACTOR: f64 = 0.5;
+
+// What the quoting side knows of each adapter quotes come from
+pub trait SourceQuality: Send + Sync {
+    // Share of `source`'s recent requests that failed, 0-1; None when nothing is known of it
+    fn error_rate(&self, source: &str) -> Option<f64>;
+
+    // Whether sanity checks lately flagged one of `source`'s quotes
+    fn flagged(&self, source: &str) -> bool;
+}
+
+// How a path's confidence follows from its hops'
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfidenceCombinator {
+    // The least confident hop's
+    #[default]
+    Min,
+    Product,
+    Mean,
+}
+
+impl ConfidenceCombinator {
+    // 1 for a path without hops
+    pub fn combine(&self, confidences: impl IntoIterator<Item = f64>) -> f64 {
+        let confidences: Vec<f64> = confidences.into_iter().collect();
+        if confidences.is_empty() {
+            return 1.0;
+        }
+        match self {
+            ConfidenceCombinator::Min => confidences.iter().copied().fold(1.0, f64::min),
+            ConfidenceCombinator::Product => confidences.iter().product(),
+            ConfidenceCombinator::Mean => confidences.iter().sum::<f64>() / confidences.len() as f64,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ConfidenceModel {
+    validity: Duration,
+    // `validity` of single bridges, by edge label
+    bridges: HashMap<String, Duration>,
+    combinator: ConfidenceCombinator,
+    quality: Option<Arc<dyn SourceQuality>>,
+}
+
+impl Default for ConfidenceModel {
+    fn default() -> Self {
+        Self { validity: DEFAULT_VALIDITY_WINDOW, bridges: HashMap::new(), combinator: ConfidenceCombinator::default(), quality: None }
+    }
+}
+
+impl fmt::Debug for ConfidenceModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConfidenceModel")
+            .field("validity", &self.validity)
+            .field("bridges", &self.bridges)
+            .field("combinator", &self.combinator)
+            .field("quality", &self.quality.is_some())
+            .finish()
+    }
+}
+
+impl ConfidenceModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // How long quotes without a valid_until, and edges without a quote, are good for
+    pub fn with_validity(mut self, validity: Duration) -> Self {
+        self.validity = validity;
+        self
+    }
+
+    // `with_validity` for the edges labelled `bridge`
+    pub fn with_bridge_validity(mut self, bridge: &str, validity: Duration) -> Self {
+        self.bridges.insert(bridge.to_string(), validity);
+        self
+    }
+
+    pub fn with_combinator(mut self, combinator: ConfidenceCombinator) -> Self {
+        self.combinator = combinator;
+        self
+    }
+
+    // Without one, only how fresh quotes are counts
+    pub fn with_source_quality(mut self, quality: Arc<dyn SourceQuality>) -> Self {
+        self.quality = Some(quality);
+        self
+    }
+
+    pub fn combinator(&self) -> ConfidenceCombinator {
+        self.combinator
+    }
+
+    // Confidence in taking `edge` at unix time `now`. Its quote's age counts against the quote's
+    // own validity when it gives one, else against the bridge's; an edge without a quote ages
+    // from its metrics' last write.
+    pub fn edge_confidence(&self, edge: &Edge, now: u64) -> f64 {
+        let quote = edge.get_quote();
+        let configured = self.bridges.get(&*edge.bridge_name).copied().unwrap_or(self.validity).as_secs();
+        let (written, window) = match &quote {
+            Some(quote) => {
+                let own = quote.valid_until.filter(|until| *until > quote.quoted_at).map(|until| until - quote.quoted_at);
+                (quote.quoted_at, own.unwrap_or(configured))
+            }
+            None => (edge.metrics.last_updated(), configured),
+        };
+        let age = now.saturating_sub(written);
+        let freshness = match window {
+            0 if age == 0 => 1.0,
+            0 => 0.0,
+            window => (1.0 - age as f64 / window as f64).clamp(0.0, 1.0),
+        };
+        let source = quote.as_ref().and_then(|quote| quote.source.as_deref()).unwrap_or(&edge.bridge_name);
+        freshness * self.source_confidence(source)
+    }
+
+    // 1 less the source's error rate, halved when it was flagged
+    fn source_confidence(&self, source: &str) -> f64 {
+        let Some(quality) = &self.quality else {
+            return 1.0;
+        };
+        let reliability = 1.0 - quality.error_rate(source).unwrap_or(0.0).clamp(0.0, 1.0);
+        match quality.flagged(source) {
+            true => reliability * FLAGGED_FACTOR,
+            false => reliability,
+        }
+    }
+
+    pub fn path_confidence(&self, hops: &[Hop]) -> f64 {
+        self.combinator.combine(hops.iter().map(|hop| hop.confidence))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{EdgeMetrics, EdgeQuote, NodeId};
+    use std::sync::RwLock;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn quoted(bridge: &str, source: &str, age: u64, valid_for: Option<u64>) -> Edge {
+        let edge = Edge::new(NodeId(1), NodeId(2), bridge.to_string(), EdgeMetrics { cost: 1.0, speed: 60.0, liquidity: 1_000.0, risk: 0.1 }, None, None);
+        let quoted_at = NOW - age;
+        *edge.quote.write().unwrap() = Some(EdgeQuote {
+            reference: format!("{}:{}", bridge, quoted_at),
+            quoted_at,
+            valid_until: valid_for.map(|secs| quoted_at + secs),
+            fees: Vec::new(),
+            speed_breakdown: None,
+            source: Some(source.to_string()),
+            sources: Vec::new(),
+            cost_in_source: None,
+        });
+        edge
+    }
+
+    #[derive(Default)]
+    struct Sources {
+        error_rates: HashMap<String, f64>,
+        flagged: RwLock<Vec<String>>,
+    }
+
+    impl SourceQuality for Sources {
+        fn error_rate(&self, source: &str) -> Option<f64> {
+            self.error_rates.get(source).copied()
+        }
+
+        fn flagged(&self, source: &str) -> bool {
+            self.flagged.read().unwrap().iter().any(|flagged| flagged == source)
+        }
+    }
+
+    #[test]
+    fn fresher_quotes_from_reliable_sources_are_trusted_more() {
+        let sources = Arc::new(Sources {
+            error_rates: HashMap::from([("stargate".to_string(), 0.0), ("lifi".to_string(), 0.2)]),
+            ..Sources::default()
+        });
+        let model = ConfidenceModel::new()
+            .with_validity(Duration::from_secs(300))
+            .with_bridge_validity("wormhole", Duration::from_secs(600))
+            .with_source_quality(Arc::clone(&sources) as _);
+
+        let direct = model.edge_confidence(&quoted("stargate", "stargate", 3, None), NOW);
+        let aggregated = model.edge_confidence(&quoted("stargate", "lifi", 3, None), NOW);
+        let stale = model.edge_confidence(&quoted("stargate", "stargate", 240, None), NOW);
+        assert!((direct - 0.99).abs() < 1e-9, "{}", direct);
+        assert!((aggregated - 0.99 * 0.8).abs() < 1e-9, "{}", aggregated);
+        assert!((stale - 0.2).abs() < 1e-9, "{}", stale);
+        assert!(direct > aggregated && aggregated > stale);
+
+        // Windows come from the quote, then the bridge, then the model
+        assert!((model.edge_confidence(&quoted("stargate", "stargate", 30, Some(60)), NOW) - 0.5).abs() < 1e-9);
+        assert!((model.edge_confidence(&quoted("wormhole", "wormhole", 240, None), NOW) - 0.6).abs() < 1e-9);
+        // Expired quotes and quotes from the future
+        assert_eq!(model.edge_confidence(&quoted("stargate", "stargate", 900, None), NOW), 0.0);
+        assert_eq!(model.edge_confidence(&quoted("stargate", "stargate", 0, None), NOW - 10), 1.0);
+
+        sources.flagged.write().unwrap().push("stargate".to_string());
+        assert!((model.edge_confidence(&quoted("stargate", "stargate", 3, None), NOW) - 0.99 * 0.5).abs() < 1e-9);
+
+        // Unknown sources count as reliable, and without any quality only freshness counts
+        assert!((model.edge_confidence(&quoted("across", "across", 3, None), NOW) - 0.99).abs() < 1e-9);
+        let freshness_only = ConfidenceModel::new().with_validity(Duration::from_secs(300));
+        assert!((freshness_only.edge_confidence(&quoted("stargate", "lifi", 3, None), NOW) - 0.99).abs() < 1e-9);
+    }
+
+    #[test]
+    fn paths_combine_their_hops() {
+        let hops = [0.9, 0.5, 0.8];
+        assert_eq!(ConfidenceCombinator::Min.combine(hops), 0.5);
+        assert!((ConfidenceCombinator::Product.combine(hops) - 0.36).abs() < 1e-9);
+        assert!((ConfidenceCombinator::Mean.combine(hops) - 2.2 / 3.0).abs() < 1e-9);
+        for combinator in [ConfidenceCombinator::Min, ConfidenceCombinator::Product, ConfidenceCombinator::Mean] {
+            assert_eq!(combinator.combine([]), 1.0);
+            assert_eq!(combinator.combine([0.7]), 0.7);
+        }
+        assert_eq!(serde_json::to_value(ConfidenceCombinator::Product).unwrap(), "product");
+    }
+}
@@ -0,0 +1,265 @@
+// What changed between two versions of a route, so a watch update can say why it was sent
+
+use crate::types::*;
+use serde::Serialize;
+
+// Relative change in any compared metric beyond which a diff is a MetricsShift rather than
+// Cosmetic, unless set with `compare_routes_with`
+pub const DEFAULT_SHIFT_THRESHOLD: f64 = 0.01;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeSeverity {
+    // Same hops, every metric within the threshold
+    Cosmetic,
+    // Same hops, some metric moved beyond the threshold
+    MetricsShift,
+    // Hops were added, removed or moved to another bridge
+    TopologyChange,
+}
+
+impl ChangeSeverity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeSeverity::Cosmetic => "cosmetic",
+            ChangeSeverity::MetricsShift => "metrics_shift",
+            ChangeSeverity::TopologyChange => "topology_change",
+        }
+    }
+}
+
+// Hops are matched by the nodes they join; hops matched to one on the same bridge aren't listed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+// A handful per diff, so Replaced isn't worth boxing
+#[allow(clippy::large_enum_variant)]
+pub enum HopChange {
+    Added { hop: Hop },
+    Removed { hop: Hop },
+    // Between the same nodes over another bridge
+    Replaced { old: Hop, new: Hop },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct MetricDelta {
+    pub old: f64,
+    pub new: f64,
+    // new - old
+    pub delta: f64,
+    // delta over |old|; None when old is 0 and new isn't
+    pub relative: Option<f64>,
+}
+
+impl MetricDelta {
+    pub fn new(old: f64, new: f64) -> Self {
+        let delta = new - old;
+        let relative = match (old == 0.0, delta == 0.0) {
+            (_, true) => Some(0.0),
+            (true, false) => None,
+            (false, false) => Some(delta / old.abs()),
+        };
+        Self { old, new, delta, relative }
+    }
+
+    fn exceeds(&self, threshold: f64) -> bool {
+        self.relative.is_none_or(|relative| relative.abs() > threshold)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RouteDiff {
+    pub hop_changes: Vec<HopChange>,
+    pub total_cost: MetricDelta,
+    pub total_time: MetricDelta,
+    pub total_risk: MetricDelta,
+    pub min_liquidity: MetricDelta,
+    // None unless both routes have one
+    pub estimated_output: Option<MetricDelta>,
+    pub score: MetricDelta,
+    pub severity: ChangeSeverity,
+}
+
+impl RouteDiff {
+    // One line for logs, e.g. "topology_change: 1 replaced; cost 2 -> 1.5 (-25.0%), time 120s -> 90s (-25.0%), score 0.4 -> 0.6 (+50.0%)"
+    pub fn summary(&self) -> String {
+        let count = |wanted: fn(&HopChange) -> bool| self.hop_changes.iter().filter(|change| wanted(change)).count();
+        let hops: Vec<String> = [
+            (count(|change| matches!(change, HopChange::Added { .. })), "added"),
+            (count(|change| matches!(change, HopChange::Removed { .. })), "removed"),
+            (count(|change| matches!(change, HopChange::Replaced { .. })), "replaced"),
+        ]
+        .into_iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, what)| format!("{} {}", count, what))
+        .collect();
+        let hops = match hops.is_empty() {
+            true => "same hops".to_string(),
+            false => hops.join(", "),
+        };
+
+        let mut metrics = vec![describe("cost", "", &self.total_cost), describe("time", "s", &self.total_time)];
+        if let Some(output) = &self.estimated_output {
+            metrics.push(describe("output", "", output));
+        }
+        metrics.push(describe("score", "", &self.score));
+        format!("{}: {}; {}", self.severity.as_str(), hops, metrics.join(", "))
+    }
+}
+
+fn describe(name: &str, unit: &str, delta: &MetricDelta) -> String {
+    let change = match delta.relative {
+        Some(relative) => format!("{:+.1}%", relative * 100.0),
+        None => "new".to_string(),
+    };
+    format!("{} {}{} -> {}{} ({})", name, delta.old, unit, delta.new, unit, change)
+}
+
+// See `compare_routes_with`; uses DEFAULT_SHIFT_THRESHOLD
+pub fn compare_routes(old: &RankedPath, new: &RankedPath) -> RouteDiff {
+    compare_routes_with(old, new, DEFAULT_SHIFT_THRESHOLD)
+}
+
+// How `new` differs from `old`. Removed and replaced hops are listed in `old`'s order, then
+// added ones in `new`'s. Metrics beyond `threshold` make a MetricsShift; the path's totals,
+// estimated output and final score are compared.
+pub fn compare_routes_with(old: &RankedPath, new: &RankedPath, threshold: f64) -> RouteDiff {
+    let mut unmatched: Vec<&Hop> = new.path.hops.iter().collect();
+    let mut hop_changes = Vec::new();
+    for hop in &old.path.hops {
+        match unmatched.iter().position(|other| other.from == hop.from && other.to == hop.to) {
+            Some(at) => {
+                let other = unmatched.remove(at);
+                if other.bridge_name != hop.bridge_name {
+                    hop_changes.push(HopChange::Replaced { old: hop.clone(), new: other.clone() });
+                }
+            }
+            None => hop_changes.push(HopChange::Removed { hop: hop.clone() }),
+        }
+    }
+    hop_changes.extend(unmatched.into_iter().map(|hop| HopChange::Added { hop: hop.clone() }));
+
+    let (before, after) = (&old.path, &new.path);
+    let total_cost = MetricDelta::new(before.total_cost, after.total_cost);
+    let total_time = MetricDelta::new(before.total_time, after.total_time);
+    let total_risk = MetricDelta::new(before.total_risk, after.total_risk);
+    let min_liquidity = MetricDelta::new(before.min_liquidity, after.min_liquidity);
+    let estimated_output = before.estimated_output.zip(after.estimated_output).map(|(old, new)| MetricDelta::new(old, new));
+    let score = MetricDelta::new(old.score_breakdown.final_score, new.score_breakdown.final_score);
+
+    let shifted = [total_cost, total_time, total_risk, min_liquidity, score]
+        .iter()
+        .chain(estimated_output.as_ref())
+        .any(|delta| delta.exceeds(threshold));
+    let severity = match (hop_changes.is_empty(), shifted) {
+        (false, _) => ChangeSeverity::TopologyChange,
+        (true, true) => ChangeSeverity::MetricsShift,
+        (true, false) => ChangeSeverity::Cosmetic,
+    };
+
+    RouteDiff { hop_changes, total_cost, total_time, total_risk, min_liquidity, estimated_output, score, severity }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hop(from: u64, to: u64, bridge: &str, cost: f64) -> Hop {
+        Hop {
+            from: NodeId(from),
+            to: NodeId(to),
+            bridge_name: bridge.into(),
+            kind: EdgeKind::Bridge,
+            metrics: EdgeMetrics { cost, speed: 60.0, liquidity: 1_000_000.0, risk: 0.1 },
+            quote: None,
+            slippage_pct: None,
+            alternatives: None,
+            confidence: 1.0,
+        }
+    }
+
+    fn ranked(hops: Vec<Hop>, final_score: f64) -> RankedPath {
+        let path = Path {
+            total_cost: hops.iter().map(|hop| hop.metrics.cost).sum(),
+            total_time: hops.iter().map(|hop| hop.metrics.speed).sum(),
+            total_risk: hops.iter().map(|hop| hop.metrics.risk).sum(),
+            min_liquidity: hops.iter().map(|hop| hop.metrics.liquidity).reduce(f64::min).unwrap_or(0.0),
+            aggregate_score: 0.0,
+            estimated_output: Some(1000.0 - hops.iter().map(|hop| hop.metrics.cost).sum::<f64>()),
+            graph_version: 0,
+            confidence: 1.0,
+            hops,
+        };
+        RankedPath {
+            rank: 1,
+            score_breakdown: ScoreBreakDown {
+                cost_score: path.total_cost,
+                speed_score: path.total_time,
+                liquidity_score: path.min_liquidity,
+                risk_score: path.total_risk,
+                final_score,
+                estimated_output: path.estimated_output,
+            },
+            path,
+            affordability: None,
+        }
+    }
+
+    #[test]
+    fn same_hops_with_new_metrics() {
+        let old = ranked(vec![hop(1, 2, "stargate", 2.0), hop(2, 3, "across", 1.0)], 0.5);
+        let cheaper = ranked(vec![hop(1, 2, "stargate", 1.5), hop(2, 3, "across", 1.0)], 0.6);
+
+        let diff = compare_routes(&old, &cheaper);
+        assert!(diff.hop_changes.is_empty());
+        assert_eq!(diff.severity, ChangeSeverity::MetricsShift);
+        assert_eq!((diff.total_cost.delta, diff.total_cost.relative), (-0.5, Some(-0.5 / 3.0)));
+        assert_eq!(diff.estimated_output.unwrap().delta, 0.5);
+        assert_eq!(diff.total_time.relative, Some(0.0));
+        assert_eq!(
+            diff.summary(),
+            "metrics_shift: same hops; cost 3 -> 2.5 (-16.7%), time 120s -> 120s (+0.0%), output 997 -> 997.5 (+0.1%), score 0.5 -> 0.6 (+20.0%)"
+        );
+
+        let nudged = ranked(vec![hop(1, 2, "stargate", 2.001), hop(2, 3, "across", 1.0)], 0.5);
+        assert_eq!(compare_routes(&old, &nudged).severity, ChangeSeverity::Cosmetic);
+        assert_eq!(compare_routes_with(&old, &nudged, 0.0).severity, ChangeSeverity::MetricsShift);
+        assert_eq!(compare_routes(&old, &old).severity, ChangeSeverity::Cosmetic);
+    }
+
+    #[test]
+    fn hop_on_another_bridge_is_a_replacement() {
+        let old = ranked(vec![hop(1, 2, "stargate", 2.0), hop(2, 3, "across", 1.0)], 0.5);
+        let swapped = ranked(vec![hop(1, 2, "stargate", 2.0), hop(2, 3, "hop", 1.0)], 0.5);
+
+        let diff = compare_routes(&old, &swapped);
+        assert_eq!(diff.hop_changes, [HopChange::Replaced { old: hop(2, 3, "across", 1.0), new: hop(2, 3, "hop", 1.0) }]);
+        // Topology wins over metrics that didn't move at all
+        assert_eq!(diff.severity, ChangeSeverity::TopologyChange);
+        assert!(diff.summary().starts_with("topology_change: 1 replaced; cost 3 -> 3 (+0.0%)"), "{}", diff.summary());
+
+        let json = serde_json::to_value(&diff).unwrap();
+        assert_eq!(json["severity"], "topology_change");
+        assert_eq!(json["hop_changes"][0]["change"], "replaced");
+        assert_eq!(json["hop_changes"][0]["new"]["bridge_name"], "hop");
+    }
+
+    #[test]
+    fn different_paths_list_every_hop() {
+        let old = ranked(vec![hop(1, 2, "stargate", 2.0), hop(2, 3, "across", 1.0)], 0.5);
+        let direct = ranked(vec![hop(1, 3, "wormhole", 4.0)], 0.3);
+
+        let diff = compare_routes(&old, &direct);
+        assert_eq!(diff.hop_changes, [
+            HopChange::Removed { hop: hop(1, 2, "stargate", 2.0) },
+            HopChange::Removed { hop: hop(2, 3, "across", 1.0) },
+            HopChange::Added { hop: hop(1, 3, "wormhole", 4.0) },
+        ]);
+        assert_eq!(diff.severity, ChangeSeverity::TopologyChange);
+        assert!(diff.summary().starts_with("topology_change: 1 added, 2 removed;"), "{}", diff.summary());
+
+        let empty = ranked(Vec::new(), 0.0);
+        let diff = compare_routes(&empty, &direct);
+        assert_eq!(diff.score.relative, None);
+        assert!(diff.summary().contains("score 0 -> 0.3 (new)"), "{}", diff.summary());
+    }
+}
@@ -0,0 +1,176 @@
+// What the graph's hashed NodeIds stand for, so the paths it produced can be read without it
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::{NodeId, NodeType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeKind {
+    Asset,
+    Exchange,
+}
+
+// One node's identity. `identifier` is what its id was hashed from: with its chain, the token
+// address of an asset; under "exchange", "<name>:<chain>" for an exchange.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeDirectoryEntry {
+    pub node_id: NodeId,
+    pub node_type: NodeKind,
+    pub chain: String,
+    pub identifier: String,
+    // The token symbol of an asset, the name of an exchange
+    pub symbol_or_name: String,
+}
+
+impl NodeDirectoryEntry {
+    pub fn of(node_id: NodeId, node_type: &NodeType) -> Self {
+        match node_type {
+            NodeType::Asset { chain, token_address, token_symbol } => Self {
+                node_id,
+                node_type: NodeKind::Asset,
+                chain: chain.clone(),
+                identifier: token_address.clone(),
+                symbol_or_name: token_symbol.clone(),
+            },
+            NodeType::Exchange { name, chain } => Self {
+                node_id,
+                node_type: NodeKind::Exchange,
+                chain: chain.clone(),
+                identifier: format!("{}:{}", name, chain),
+                symbol_or_name: name.clone(),
+            },
+        }
+    }
+
+    pub fn node_type(&self) -> NodeType {
+        match self.node_type {
+            NodeKind::Asset => NodeType::Asset {
+                chain: self.chain.clone(),
+                token_address: self.identifier.clone(),
+                token_symbol: self.symbol_or_name.clone(),
+            },
+            NodeKind::Exchange => NodeType::Exchange { name: self.symbol_or_name.clone(), chain: self.chain.clone() },
+        }
+    }
+
+    // The id the graph gives the node node_type() describes, which is `node_id` unless the entry
+    // was altered or hashed differently
+    pub fn expected_id(&self) -> NodeId {
+        self.node_type().id()
+    }
+}
+
+// Entries by node id, for resolving stored routes offline. Serialized as the list of entries,
+// the form Graph::export_node_directory gives.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(from = "Vec<NodeDirectoryEntry>", into = "Vec<NodeDirectoryEntry>")]
+pub struct NodeDirectory {
+    entries: BTreeMap<NodeId, NodeDirectoryEntry>,
+}
+
+impl NodeDirectory {
+    pub fn lookup(&self, node_id: NodeId) -> Option<&NodeDirectoryEntry> {
+        self.entries.get(&node_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Sorted by node id
+    pub fn entries(&self) -> impl Iterator<Item = &NodeDirectoryEntry> {
+        self.entries.values()
+    }
+}
+
+impl From<Vec<NodeDirectoryEntry>> for NodeDirectory {
+    fn from(entries: Vec<NodeDirectoryEntry>) -> Self {
+        Self { entries: entries.into_iter().map(|entry| (entry.node_id, entry)).collect() }
+    }
+}
+
+impl From<NodeDirectory> for Vec<NodeDirectoryEntry> {
+    fn from(directory: NodeDirectory) -> Self {
+        directory.entries.into_values().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::GraphError;
+    use crate::graph::Graph;
+    use crate::routing::RoutingEngine;
+    use crate::scoring::ScoringEngine;
+    use crate::types::{EdgeMetrics, RankedPath, RoutingParams};
+    use std::sync::Arc;
+
+    #[test]
+    fn stored_paths_resolve_without_the_graph() {
+        let graph = Graph::new(4);
+        let eth = graph.get_or_create_asset_node("ethereum", "0xa0b8", "USDC");
+        let arb = graph.get_or_create_asset_node("arbitrum", "0xaf88", "USDC");
+        let pol = graph.get_or_create_asset_node("polygon", "0x3c49", "USDC.e");
+        graph.get_or_create_exchange_node("stargate", "ethereum");
+        let metrics = EdgeMetrics { cost: 1.0, speed: 60.0, liquidity: 1_000_000.0, risk: 0.1 };
+        graph.add_edge(eth, arb, "stargate", metrics.clone(), None, None).unwrap();
+        graph.add_edge(arb, pol, "across", metrics, None, None).unwrap();
+        let graph = Arc::new(graph);
+        let path = RoutingEngine::new(Arc::clone(&graph), 3).find_path(eth, pol, &RoutingParams::cheapest()).unwrap();
+        let ranked = ScoringEngine::new().score_and_rank(vec![path], &RoutingParams::cheapest(), 1).unwrap().ranked;
+        let stored = serde_json::to_string(&ranked[0]).unwrap();
+        let exported = serde_json::to_string(&graph.export_node_directory()).unwrap();
+        drop(graph);
+
+        let directory: NodeDirectory = serde_json::from_str(&exported).unwrap();
+        assert_eq!(directory.len(), 4);
+        assert!(directory.entries().all(|entry| entry.expected_id() == entry.node_id));
+        let ranked: RankedPath = serde_json::from_str(&stored).unwrap();
+        let ends: Vec<(String, String, String)> = ranked.path.hops
+            .iter()
+            .flat_map(|hop| [hop.from, hop.to])
+            .map(|id| {
+                let entry = directory.lookup(id).unwrap();
+                assert_eq!(entry.node_type, NodeKind::Asset);
+                (entry.chain.clone(), entry.identifier.clone(), entry.symbol_or_name.clone())
+            })
+            .collect();
+        let asset = |chain: &str, address: &str, symbol: &str| (chain.to_string(), address.to_string(), symbol.to_string());
+        assert_eq!(ends, [
+            asset("ethereum", "0xa0b8", "USDC"),
+            asset("arbitrum", "0xaf88", "USDC"),
+            asset("arbitrum", "0xaf88", "USDC"),
+            asset("polygon", "0x3c49", "USDC.e"),
+        ]);
+        assert!(directory.lookup(NodeId(7)).is_none());
+
+        let exchange = directory.lookup(NodeId::from_parts("exchange", "stargate:ethereum")).unwrap();
+        assert_eq!(exchange.node_type(), NodeType::Exchange { name: "stargate".to_string(), chain: "ethereum".to_string() });
+    }
+
+    #[test]
+    fn imported_directories_pre_register_nodes() {
+        let source = Graph::new(4);
+        let eth = source.get_or_create_asset_node("ethereum", "0xa0b8", "USDC");
+        source.get_or_create_exchange_node("across", "base");
+        let entries = source.export_node_directory();
+
+        let fresh = Graph::new(8);
+        assert_eq!(fresh.import_node_directory(&entries).unwrap(), 2);
+        assert_eq!(fresh.import_node_directory(&entries).unwrap(), 0);
+        assert_eq!(fresh.get_node(eth).unwrap().node_type, source.get_node(eth).unwrap().node_type);
+        assert_eq!(fresh.export_node_directory(), entries);
+
+        let mut tampered = entries[0].clone();
+        tampered.chain = "optimism".to_string();
+        let err = Graph::new(4).import_node_directory(&[tampered]).unwrap_err();
+        assert!(matches!(err, GraphError::DirectoryMismatch { .. }), "{}", err);
+    }
+}
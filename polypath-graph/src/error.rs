@@ -0,0 +1,129 @@
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::types::{NodeId, ParamError};
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum GraphError {
+    #[error("shard count must be a non-zero power of two, got {0}")]
+    InvalidShardCount(usize),
+
+    #[error("node {0:?} is not in the graph")]
+    UnknownNode(NodeId),
+
+    // Graph::add_swap_edge between nodes that aren't two assets on one chain
+    #[error("a swap edge must join two assets on one chain, not {from:?} and {to:?}")]
+    InvalidSwap { from: NodeId, to: NodeId },
+
+    // Graph::try_create_node for a node that's already there
+    #[error("node {0:?} is already in the graph")]
+    AlreadyExists(NodeId),
+
+    // Metrics are stored as unsigned fixed-point values, so these can't be represented
+    #[error("edge metric `{name}` for {bridge} must be a finite, non-negative number, got {value}")]
+    InvalidMetric { bridge: String, name: &'static str, value: f64 },
+
+    // Graph::update_edge asked to leave an edge's min amount above its max
+    #[error("edge limits for {bridge} must have min at most max, got min {min} and max {max}")]
+    InvalidLimits { bridge: String, min: f64, max: f64 },
+
+    // A node directory entry whose id isn't the one its chain and identifier hash to
+    #[error("node directory entry {node_id:?} for `{identifier}` on `{chain}` should have id {expected:?}")]
+    DirectoryMismatch { node_id: NodeId, expected: NodeId, chain: String, identifier: String },
+
+    #[error(transparent)]
+    Params(#[from] ParamError),
+}
+
+// Why Router::best_routes could not look for routes. Finding none is not an error.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum RouteError {
+    #[error("no asset `{token}` on chain `{chain}` in the graph")]
+    UnknownToken { chain: String, token: String },
+
+    #[error("`{token}` matches {matches} assets on chain `{chain}`, give its address instead")]
+    AmbiguousToken { chain: String, token: String, matches: usize },
+
+    #[error("unknown routing preference `{0}`")]
+    UnknownPreference(String),
+
+    #[error("amount must be a positive number, got {0}")]
+    InvalidAmount(f64),
+
+    #[error("invalid pruning rule: {0}")]
+    InvalidPruning(String),
+
+    // Too few of the graph's pairs are quoted yet for its routes to mean much, see
+    // Router::with_min_coverage
+    #[error("the graph is still warming up, {:.0}% of its pairs are quoted", coverage * 100.0)]
+    Warmup { coverage: f64, missing_pairs_sample: Vec<String> },
+
+    #[error(transparent)]
+    Scoring(#[from] ScoringError),
+
+    #[error(transparent)]
+    Params(#[from] ParamError),
+}
+
+// Why ScoringEngine couldn't rank a set of candidates. `index` is the path's position in the set.
+#[derive(Debug, Clone, PartialEq, Error, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ScoringError {
+    #[error("candidate {index} has a NaN {factor} score")]
+    NanScore { index: usize, factor: &'static str },
+}
+
+// Why ObjectiveRegistry::register turned an objective away
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum ObjectiveError {
+    // Another objective's name, or one of RoutingParams' weight fields
+    #[error("objective `{0}` is already registered")]
+    Duplicate(String),
+
+    #[error("default weight of objective `{name}` must be a finite, non-negative number, got {weight}")]
+    InvalidWeight { name: String, weight: f64 },
+
+    #[error("objective `{name}` scores paths without a value like `{like}`, which isn't registered before it")]
+    UnknownFallback { name: String, like: String },
+}
+
+// Why an amount can't be sent along a path, see SlippageModel::propagate
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum SlippageError {
+    #[error("hop {hop} over {bridge} would use {:.0}% of its liquidity, more than the {:.0}% allowed", utilization * 100.0, max * 100.0)]
+    UtilizationExceeded { hop: usize, bridge: String, utilization: f64, max: f64 },
+
+    #[error("the fees of hop {hop} over {bridge} ({cost}) use up the {amount_in} going into it")]
+    FeesExceedAmount { hop: usize, bridge: String, amount_in: f64, cost: f64 },
+}
+
+// Why ExecutionPlan::from_path refused a route
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum PlanError {
+    #[error("the route has no hops")]
+    EmptyPath,
+
+    #[error("amount must be a positive number, got {0}")]
+    InvalidAmount(f64),
+
+    #[error("node {0:?} is not an asset in the graph")]
+    NotAnAsset(NodeId),
+
+    #[error("the route goes from {from} to {to}, not from {intent_from} to {intent_to}")]
+    IntentMismatch { from: String, to: String, intent_from: String, intent_to: String },
+
+    #[error("the route was found at graph version {path_version}, {lag} versions behind the current {graph_version}")]
+    StalePath { path_version: u64, graph_version: u64, lag: u64 },
+
+    #[error("step {step} over {bridge} is no longer an active edge of the graph")]
+    EdgeGone { step: usize, bridge: String },
+
+    #[error("the {bridge} quote for step {step} expired at {expired_at}")]
+    QuoteExpired { step: usize, bridge: String, expired_at: u64 },
+
+    #[error("step {step} over {bridge} takes {amount_in}, outside the limits it accepts")]
+    AmountOutOfRange { step: usize, bridge: String, amount_in: f64 },
+
+    #[error("the fees of step {step} over {bridge} ({cost}) use up the {amount_in} going into it")]
+    FeesExceedAmount { step: usize, bridge: String, amount_in: f64, cost: f64 },
+}
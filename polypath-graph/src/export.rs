@@ -0,0 +1,240 @@
+// Ranked routes written out in bulk for analysis: CSV with one flat row per route, or JSON lines
+// with every hop
+
+use crate::types::{RankedPath, RouteIntent};
+use serde::{Deserialize, Serialize};
+use std::{io::Write, path::Path};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    JsonLines,
+}
+
+impl ExportFormat {
+    // By the file's extension: .csv, or .jsonl / .ndjson
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "csv" => Some(ExportFormat::Csv),
+            "jsonl" | "ndjson" => Some(ExportFormat::JsonLines),
+            _ => None,
+        }
+    }
+}
+
+// A JSON line: one of an intent's routes, or None for an intent without any
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedRoute {
+    pub intent: RouteIntent,
+    #[serde(default)]
+    pub route: Option<RankedPath>,
+}
+
+// A CSV row. Field order is the column order; add columns at the end.
+#[derive(Debug, Serialize)]
+struct CsvRow<'a> {
+    from_chain: &'a str,
+    from_token: &'a str,
+    to_chain: &'a str,
+    to_token: &'a str,
+    amount: f64,
+    preference: Option<&'a str>,
+    // Empty on an intent's marker row when it has no routes
+    rank: Option<usize>,
+    hops: usize,
+    // Bridge of each hop, joined by '|'
+    bridges: String,
+    total_cost: Option<f64>,
+    total_time: Option<f64>,
+    total_risk: Option<f64>,
+    min_liquidity: Option<f64>,
+    estimated_output: Option<f64>,
+    cost_score: Option<f64>,
+    speed_score: Option<f64>,
+    liquidity_score: Option<f64>,
+    risk_score: Option<f64>,
+    final_score: Option<f64>,
+    graph_version: Option<u64>,
+}
+
+impl<'a> CsvRow<'a> {
+    fn new(intent: &'a RouteIntent, route: Option<&RankedPath>) -> Self {
+        let path = route.map(|route| &route.path);
+        let breakdown = route.map(|route| &route.score_breakdown);
+        Self {
+            from_chain: &intent.from_chain,
+            from_token: &intent.from_token,
+            to_chain: &intent.to_chain,
+            to_token: &intent.to_token,
+            amount: intent.amount,
+            preference: intent.preference.as_deref(),
+            rank: route.map(|route| route.rank),
+            hops: path.map_or(0, |path| path.hops.len()),
+            bridges: path.map(|path| path.hops.iter().map(|hop| &*hop.bridge_name).collect::<Vec<_>>().join("|")).unwrap_or_default(),
+            total_cost: path.map(|path| path.total_cost),
+            total_time: path.map(|path| path.total_time),
+            total_risk: path.map(|path| path.total_risk),
+            min_liquidity: path.map(|path| path.min_liquidity),
+            estimated_output: path.and_then(|path| path.estimated_output),
+            cost_score: breakdown.map(|breakdown| breakdown.cost_score),
+            speed_score: breakdown.map(|breakdown| breakdown.speed_score),
+            liquidity_score: breakdown.map(|breakdown| breakdown.liquidity_score),
+            risk_score: breakdown.map(|breakdown| breakdown.risk_score),
+            final_score: breakdown.map(|breakdown| breakdown.final_score),
+            graph_version: path.map(|path| path.graph_version),
+        }
+    }
+}
+
+// Writes a row per ranked route of each intent, and one marker row for an intent without routes.
+// Returns how many rows were written, header aside.
+pub fn write_ranked_paths<W: Write>(writer: W, format: ExportFormat, rows: &[(RouteIntent, Vec<RankedPath>)]) -> Result<usize, ExportError> {
+    let routes = rows.iter().flat_map(|(intent, ranked)| {
+        let marker = ranked.is_empty().then_some((intent, None));
+        ranked.iter().map(move |route| (intent, Some(route))).chain(marker)
+    });
+    let mut written = 0;
+    match format {
+        ExportFormat::Csv => {
+            let mut csv = csv::Writer::from_writer(writer);
+            for (intent, route) in routes {
+                csv.serialize(CsvRow::new(intent, route))?;
+                written += 1;
+            }
+            csv.flush()?;
+        }
+        ExportFormat::JsonLines => {
+            let mut writer = writer;
+            for (intent, route) in routes {
+                serde_json::to_writer(&mut writer, &ExportedRoute { intent: intent.clone(), route: route.cloned() })?;
+                writer.write_all(b"\n")?;
+                written += 1;
+            }
+            writer.flush()?;
+        }
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{EdgeKind, EdgeMetrics, Hop, NodeId, Path as RoutePath, ScoreBreakDown};
+
+    fn intent(from_token: &str, preference: Option<&str>) -> RouteIntent {
+        RouteIntent {
+            from_chain: "ethereum".to_string(),
+            from_token: from_token.to_string(),
+            to_chain: "polygon".to_string(),
+            to_token: "USDC".to_string(),
+            amount: 1000.0,
+            preference: preference.map(str::to_string),
+            src_address: None,
+        }
+    }
+
+    fn ranked(rank: usize, bridges: &[&str]) -> RankedPath {
+        let hops: Vec<Hop> = bridges
+            .iter()
+            .enumerate()
+            .map(|(i, bridge)| Hop {
+                from: NodeId(i as u64),
+                to: NodeId(i as u64 + 1),
+                bridge_name: (*bridge).into(),
+                kind: EdgeKind::Bridge,
+                metrics: EdgeMetrics { cost: 1.0, speed: 60.0, liquidity: 1_000_000.0, risk: 0.1 },
+                quote: None,
+                slippage_pct: Some(0.05),
+                alternatives: None,
+                confidence: 1.0,
+            })
+            .collect();
+        RankedPath {
+            path: RoutePath {
+                total_cost: hops.len() as f64,
+                total_time: 60.0 * hops.len() as f64,
+                total_risk: 0.1,
+                min_liquidity: 1_000_000.0,
+                aggregate_score: 0.5,
+                estimated_output: Some(998.0),
+                graph_version: 7,
+                confidence: 1.0,
+                hops,
+            },
+            rank,
+            score_breakdown: ScoreBreakDown {
+                cost_score: 0.25,
+                speed_score: 120.0,
+                liquidity_score: 0.5,
+                risk_score: 0.1,
+                final_score: 0.75,
+                estimated_output: Some(998.0),
+            },
+            affordability: None,
+        }
+    }
+
+    fn rows() -> Vec<(RouteIntent, Vec<RankedPath>)> {
+        vec![
+            (intent("USDC", Some("cheapest")), vec![ranked(1, &["across", "hop"]), ranked(2, &["stargate"])]),
+            // A symbol needing quotes, and no routes
+            (intent("USD,\"C\"", None), Vec::new()),
+        ]
+    }
+
+    #[test]
+    fn json_lines_round_trip() {
+        let mut out = Vec::new();
+        assert_eq!(write_ranked_paths(&mut out, ExportFormat::JsonLines, &rows()).unwrap(), 3);
+        let lines: Vec<ExportedRoute> = String::from_utf8(out).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        let (first, empty) = (&rows()[0], &rows()[1]);
+        assert_eq!(lines[0], ExportedRoute { intent: first.0.clone(), route: Some(first.1[0].clone()) });
+        assert_eq!(&*lines[1].route.as_ref().unwrap().path.hops[0].bridge_name, "stargate");
+        assert_eq!(lines[2], ExportedRoute { intent: empty.0.clone(), route: None });
+    }
+
+    #[test]
+    fn csv_has_stable_columns_and_quotes_what_needs_it() {
+        let mut out = Vec::new();
+        assert_eq!(write_ranked_paths(&mut out, ExportFormat::Csv, &rows()).unwrap(), 3);
+        let mut reader = csv::Reader::from_reader(out.as_slice());
+        let headers: Vec<String> = reader.headers().unwrap().iter().map(str::to_string).collect();
+        assert_eq!(headers[..9], ["from_chain", "from_token", "to_chain", "to_token", "amount", "preference", "rank", "hops", "bridges"]);
+        assert_eq!(headers.last().unwrap(), "graph_version");
+
+        let records: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+        assert_eq!(records.len(), 3);
+        let column = |record: &csv::StringRecord, name: &str| record.get(headers.iter().position(|header| header == name).unwrap()).unwrap().to_string();
+        assert_eq!(column(&records[0], "rank"), "1");
+        assert_eq!(column(&records[0], "bridges"), "across|hop");
+        assert_eq!(column(&records[0], "total_cost"), "2.0");
+        assert_eq!(column(&records[0], "final_score"), "0.75");
+        assert_eq!(column(&records[1], "bridges"), "stargate");
+        // The marker row of the intent without routes
+        assert_eq!(column(&records[2], "from_token"), "USD,\"C\"");
+        assert_eq!(column(&records[2], "rank"), "");
+        assert_eq!(column(&records[2], "hops"), "0");
+        assert_eq!(column(&records[2], "final_score"), "");
+    }
+
+    #[test]
+    fn format_follows_the_extension() {
+        assert_eq!(ExportFormat::from_path(Path::new("routes.CSV")), Some(ExportFormat::Csv));
+        assert_eq!(ExportFormat::from_path(Path::new("out/routes.jsonl")), Some(ExportFormat::JsonLines));
+        assert_eq!(ExportFormat::from_path(Path::new("routes.json")), None);
+        assert_eq!(ExportFormat::from_path(Path::new("routes")), None);
+    }
+}
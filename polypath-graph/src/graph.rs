@@ -1,57 +1,170 @@
+use crate::directory::NodeDirectoryEntry;
 use crate::types::*;
-use dashmap::DashMap;
+use dashmap::{DashMap, mapref::entry::Entry as NodeEntry};
+use serde::Serialize;
 use std::{
-    collections::HashMap, hash::Hash, sync::{
-        Arc, atomic::{
-            AtomicU64, Ordering
+    collections::{HashMap, HashSet, VecDeque, hash_map::Entry}, sync::{
+        Arc, Mutex, RwLock, RwLockReadGuard, atomic::{
+            AtomicBool, AtomicU64, AtomicU8, Ordering
         }
-    }, time::SystemTime
+    }, time::{Duration, SystemTime, UNIX_EPOCH}
 };
-use anyhow::Result;
+use tokio::sync::watch;
+
+use crate::error::GraphError;
+use crate::invariants::{self, InvariantViolation};
+use crate::view::{GraphStats, GraphView};
+
+// Copies `read_view` takes when the graph keeps changing under it, the last with batches held off
+const READ_VIEW_ATTEMPTS: usize = 3;
+
+// Bits of Graph::batch_state: a batch is being applied, and something changed since it began
+const BATCH_ACTIVE: u8 = 1;
+const BATCH_CHANGED: u8 = 2;
+
+// Reachable nodes with their hop counts, by (node, max_hops, forward)
+type ReachableCache = HashMap<(NodeId, usize, bool), Vec<(NodeId, usize)>>;
+
+pub(crate) type Nodes = DashMap<NodeId, Arc<Node>>;
+
+// One shard of an edge index: the edges of each node that maps to it
+pub(crate) type EdgeShard = DashMap<NodeId, Vec<Arc<Edge>>>;
+
+// What Graph::compact removes
+#[derive(Debug, Clone)]
+pub struct CompactionOptions {
+    // Inactive edges whose metrics weren't written for this long
+    pub inactive_for: Duration,
+    // Nodes kept without edges, e.g. the tokens intents are resolved to
+    pub pinned: HashSet<NodeId>,
+}
+
+impl Default for CompactionOptions {
+    fn default() -> Self {
+        Self { inactive_for: Duration::from_secs(3600), pinned: HashSet::new() }
+    }
+}
+
+impl CompactionOptions {
+    pub fn with_inactive_for(mut self, inactive_for: Duration) -> Self {
+        self.inactive_for = inactive_for;
+        self
+    }
+
+    pub fn with_pinned(mut self, pinned: impl IntoIterator<Item = NodeId>) -> Self {
+        self.pinned.extend(pinned);
+        self
+    }
+}
+
+// What a compaction removed. Bytes are estimated from the sizes of what was held.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CompactionReport {
+    pub edges: usize,
+    pub nodes: usize,
+    // Shard map entries left without edges
+    pub entries: usize,
+    pub bytes: usize,
+}
 
 // Main graph implementation
 #[derive(Debug)]
 pub struct Graph {
-    nodes: Arc<DashMap<NodeId, Arc<Node>>>,
+    nodes: Arc<Nodes>,
 
     // Sharded edge storage for outgoing edges (key is source node)
-    outgoing_edges: Vec<Arc<DashMap<NodeId, Vec<Arc<Edge>>>>>,
+    outgoing_edges: Vec<Arc<EdgeShard>>,
 
     // Sharded edge storage for incoming edges (key is destination node)
-    incoming_edges: Vec<Arc<DashMap<NodeId, Vec<Arc<Edge>>>>>,
+    incoming_edges: Vec<Arc<EdgeShard>>,
 
     // Shard count (power of 2, for efficient hashing)
     shard_count: usize,
 
     version: Arc<AtomicU64>,
 
+    // Publishes each new version to `subscribe` receivers
+    changes: watch::Sender<u64>,
+
+    // Unix time the latest version was published at, see `clock`
+    published_at: AtomicU64,
+    // Set by `pin_clock`, after which publishing leaves published_at alone
+    clock_pinned: AtomicBool,
+
+    // Held while a batch is applied, see `batch`
+    batch: Mutex<()>,
+    batch_state: AtomicU8,
+
+    // Shared by writes that add edges or change what `compact` would drop, held exclusively by
+    // `compact` so it never races them, see `write_guard`
+    writes: RwLock<()>,
+
+    // Set by whoever keeps the graph up to date, None on graphs nobody measures
+    coverage: RwLock<Option<Coverage>>,
+
+    // Answers of `reachable` and the version they're kept for
+    reachable: Mutex<(u64, ReachableCache)>,
+
+    // One copy of each bridge name, shared by its edges, see `intern`
+    bridge_names: Mutex<HashSet<Arc<str>>>,
+
     // Node ID Generator
+    #[allow(dead_code)]
     next_node_id: Arc<AtomicU64>,
 }
 
+// Ends a batch, even one that panics part way, publishing it if anything changed
+struct BatchEnd<'a>(&'a Graph);
+
+impl Drop for BatchEnd<'_> {
+    fn drop(&mut self) {
+        if self.0.batch_state.swap(0, Ordering::AcqRel) & BATCH_CHANGED != 0 {
+            self.0.bump_version();
+        }
+    }
+}
 
 impl Graph {
 
     // Create a graph with specific number of shards
     pub fn new(shard_count: usize) -> Self {
-        assert!(shard_count > 0 && shard_count.is_power_of_two(), "shard count must be a power of 2");
-        
-        let mut outgoing:Vec<Arc<DashMap<NodeId, Vec<Arc<Edge>>>>> = Vec::with_capacity(shard_count);
-        let mut incoming: Vec<Arc<DashMap<NodeId, Vec<Arc<Edge>>>>> = Vec::with_capacity(shard_count);
+        match Self::try_new(shard_count) {
+            Ok(graph) => graph,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    // Like `new`, for shard counts that come from configuration
+    pub fn try_new(shard_count: usize) -> Result<Self, GraphError> {
+        if !shard_count.is_power_of_two() {
+            return Err(GraphError::InvalidShardCount(shard_count));
+        }
+
+        let mut outgoing: Vec<Arc<EdgeShard>> = Vec::with_capacity(shard_count);
+        let mut incoming: Vec<Arc<EdgeShard>> = Vec::with_capacity(shard_count);
 
         for _ in 0..shard_count {
             outgoing.push(Arc::new(DashMap::new()));
             incoming.push(Arc::new(DashMap::new()));
         }
 
-        Self {
+        Ok(Self {
             nodes: Arc::new(DashMap::new()),
             outgoing_edges: outgoing,
             incoming_edges: incoming,
-            shard_count: shard_count,
+            shard_count,
             version: Arc::new(AtomicU64::new(0)),
+            changes: watch::Sender::new(0),
+            published_at: AtomicU64::new(SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()),
+            clock_pinned: AtomicBool::new(false),
+            batch: Mutex::default(),
+            batch_state: AtomicU8::new(0),
+            writes: RwLock::default(),
+            coverage: RwLock::new(None),
+            reachable: Mutex::default(),
+            bridge_names: Mutex::default(),
             next_node_id: Arc::new(AtomicU64::new(1))
-        }
+        })
     }
 
     #[inline]
@@ -65,26 +178,7 @@ impl Graph {
         token_address: &str,
         token_symbol: &str
     ) -> NodeId {
-        let node_id = NodeId::from_parts(chain, token_address);
-        
-        // Check if node exists and return it. Otherwise, get and return it.
-        if self.nodes.contains_key(&node_id) {
-            return node_id;
-        }
-
-        let node = Arc::new( Node {
-            id: node_id,
-            node_type: NodeType::Asset { 
-                chain: chain.to_string(), 
-                token_address: token_address.to_string(), 
-                token_symbol: token_symbol.to_string()
-            },
-            metadata: HashMap::new(),
-            created_at: SystemTime::now()   
-        });
-
-        self.nodes.insert(node_id, node);
-        node_id
+        self.get_or_create_node(NodeSpec::asset(chain, token_address, token_symbol)).0
     }
 
     pub fn get_or_create_exchange_node(
@@ -92,31 +186,81 @@ impl Graph {
         name: &str, 
         chain: &str
     ) -> NodeId {
-        let identifier = format!("{}:{}", name, chain);
-        let node_id = NodeId::from_parts("exchange", &identifier);
+        self.get_or_create_node(NodeSpec::exchange(name, chain)).0
+    }
 
-        if self.nodes.contains_key(&node_id) {
-            return node_id;
+    // The node `spec` describes, created unless it's there. Threads racing to create one node
+    // all get its id, and only one of them builds it; an existing node keeps its metadata.
+    pub fn get_or_create_node(&self, spec: NodeSpec) -> (NodeId, NodeCreation) {
+        let node_id = spec.id();
+        match self.nodes.entry(node_id) {
+            NodeEntry::Occupied(_) => (node_id, NodeCreation::Existing),
+            NodeEntry::Vacant(entry) => {
+                entry.insert(Arc::new(spec.into_node()));
+                (node_id, NodeCreation::Created)
+            }
         }
+    }
 
-        let node = Arc::new(Node {
-            id: node_id,
-            node_type: NodeType::Exchange { 
-                name: name.to_string(), 
-                chain: chain.to_string()
-            },
-            metadata: HashMap::new(),
-            created_at: SystemTime::now()
-        });
+    // Creates the node `spec` describes, or fails with GraphError::AlreadyExists if it's there
+    pub fn try_create_node(&self, spec: NodeSpec) -> Result<NodeId, GraphError> {
+        match self.get_or_create_node(spec) {
+            (node_id, NodeCreation::Created) => Ok(node_id),
+            (node_id, NodeCreation::Existing) => Err(GraphError::AlreadyExists(node_id)),
+        }
+    }
 
-        self.nodes.insert(node_id, node);
-        node_id
+    // Asset nodes on `chain` whose token address or symbol is `token`, all compared
+    // case-insensitively, sorted by id
+    pub fn find_asset_nodes(&self, chain: &str, token: &str) -> Vec<Arc<Node>> {
+        let mut found: Vec<Arc<Node>> = self.nodes
+            .iter()
+            .filter(|entry| match &entry.value().node_type {
+                NodeType::Asset { chain: node_chain, token_address, token_symbol } => {
+                    node_chain.eq_ignore_ascii_case(chain)
+                        && (token_address.eq_ignore_ascii_case(token) || token_symbol.eq_ignore_ascii_case(token))
+                }
+                NodeType::Exchange { .. } => false,
+            })
+            .map(|entry| Arc::clone(entry.value()))
+            .collect();
+        found.sort_by_key(|node| node.id);
+        found
     }
 
     pub fn get_node(&self, node_id: NodeId) -> Option<Arc<Node>> {
         self.nodes.get(&node_id).map(|entry| Arc::clone(entry.value()))
     }
 
+    // What every node id stands for, sorted by id, see NodeDirectory
+    pub fn export_node_directory(&self) -> Vec<NodeDirectoryEntry> {
+        let mut entries: Vec<NodeDirectoryEntry> = self.nodes
+            .iter()
+            .map(|entry| NodeDirectoryEntry::of(*entry.key(), &entry.value().node_type))
+            .collect();
+        entries.sort_by_key(|entry| entry.node_id);
+        entries
+    }
+
+    // Creates the nodes of `entries` the graph doesn't have yet, e.g. to replay stored paths
+    // against a fresh graph, and returns how many. Entries are checked against the id their
+    // chain and identifier hash to before any node is created.
+    pub fn import_node_directory(&self, entries: &[NodeDirectoryEntry]) -> Result<usize, GraphError> {
+        if let Some(entry) = entries.iter().find(|entry| entry.expected_id() != entry.node_id) {
+            return Err(GraphError::DirectoryMismatch {
+                node_id: entry.node_id,
+                expected: entry.expected_id(),
+                chain: entry.chain.clone(),
+                identifier: entry.identifier.clone(),
+            });
+        }
+        // Counts only what this import created, should another thread create some of them too
+        Ok(entries
+            .iter()
+            .filter(|entry| self.try_create_node(NodeSpec::of(entry.node_type())).is_ok())
+            .count())
+    }
+
     pub fn add_edge(
         &self,
         from: NodeId,
@@ -125,20 +269,72 @@ impl Graph {
         metrics: EdgeMetrics,
         min_amount: Option<f64>,
         max_amount: Option<f64>
-    ) -> Result<bool> {
+    ) -> Result<bool, GraphError> {
+        validate_metrics(bridge_name, &metrics)?;
+        self.insert_edge(Edge::new(from, to, self.intern(bridge_name), metrics, min_amount, max_amount))
+    }
 
-        let edge = Arc::new(Edge::new(from, to, bridge_name.to_string(), metrics, min_amount, max_amount));
+    // A same-chain swap from one asset to another on `venue_name`, e.g. a DEX. Updated,
+    // switched off and quoted like a bridge edge, under the venue's name.
+    pub fn add_swap_edge(
+        &self,
+        from_asset: NodeId,
+        to_asset: NodeId,
+        venue_name: &str,
+        metrics: EdgeMetrics,
+        min_amount: Option<f64>,
+        max_amount: Option<f64>
+    ) -> Result<bool, GraphError> {
+        let chain = |node_id: NodeId| match self.get_node(node_id) {
+            Some(node) => match &node.node_type {
+                NodeType::Asset { chain, .. } => Ok(Some(chain.to_lowercase())),
+                NodeType::Exchange { .. } => Ok(None),
+            },
+            None => Err(GraphError::UnknownNode(node_id)),
+        };
+        match (chain(from_asset)?, chain(to_asset)?) {
+            (Some(from_chain), Some(to_chain)) if from_chain == to_chain && from_asset != to_asset => {}
+            _ => return Err(GraphError::InvalidSwap { from: from_asset, to: to_asset }),
+        }
+        validate_metrics(venue_name, &metrics)?;
+        let edge = Edge::new(from_asset, to_asset, self.intern(venue_name), metrics, min_amount, max_amount);
+        self.insert_edge(edge.with_kind(EdgeKind::Swap))
+    }
+
+    // The graph's copy of `name`
+    fn intern(&self, name: &str) -> Arc<str> {
+        let mut names = self.bridge_names.lock().unwrap();
+        match names.get(name) {
+            Some(interned) => Arc::clone(interned),
+            None => {
+                let interned: Arc<str> = Arc::from(name);
+                names.insert(Arc::clone(&interned));
+                interned
+            }
+        }
+    }
+
+    // Metrics are checked before the edge is built, which would clamp them
+    fn insert_edge(&self, edge: Edge) -> Result<bool, GraphError> {
+        let _writes = self.write_guard();
+        let (from, to) = (edge.from, edge.to);
+        for node in [from, to] {
+            if !self.nodes.contains_key(&node) {
+                return Err(GraphError::UnknownNode(node));
+            }
+        }
+        let edge = Arc::new(edge);
 
         // Adding outgoing edges (shard by source)
         let from_shard = &self.outgoing_edges[self.shard_index(from)];
-        from_shard.entry(from).or_insert(Vec::new()).push(Arc::clone(&edge));
+        from_shard.entry(from).or_default().push(Arc::clone(&edge));
 
         // Adding incoming edges (shard by destination)
 
         let to_shard = &self.incoming_edges[self.shard_index(to)];
-        to_shard.entry(to).or_insert(Vec::new()).push(Arc::clone(&edge));
+        to_shard.entry(to).or_default().push(Arc::clone(&edge));
 
-        self.version.fetch_add(1, Ordering::Relaxed);
+        self.bump_version();
 
         Ok(true)
     }
@@ -149,14 +345,31 @@ impl Graph {
         to: NodeId,
         bridge_name: &str,
         metrics: EdgeMetrics,
-    ) -> Result<bool> {
+    ) -> Result<bool, GraphError> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        self.update_edge_metrics_at(from, to, bridge_name, metrics, now)
+    }
+
+    // As update_edge_metrics, with the metrics stamped as of unix time `updated_at`, e.g. when
+    // replaying recorded ones
+    pub fn update_edge_metrics_at(
+        &self,
+        from: NodeId,
+        to: NodeId,
+        bridge_name: &str,
+        metrics: EdgeMetrics,
+        updated_at: u64,
+    ) -> Result<bool, GraphError> {
+        validate_metrics(bridge_name, &metrics)?;
+        let _writes = self.write_guard();
         let shard = &self.outgoing_edges[self.shard_index(from)];
 
         if let Some(edges) = shard.get(&from) {
             for edge in edges.value() {
-                if edge.to == to && edge.bridge_name == bridge_name {
-                    edge.metrics.update(metrics);
-                    self.version.fetch_add(1, Ordering::Release);
+                if edge.to == to && &*edge.bridge_name == bridge_name {
+                    edge.metrics.update_at(metrics, updated_at);
+                    edge.is_stale.store(false, Ordering::Release);
+                    self.bump_version();
                     return Ok(true);
                 }
             }
@@ -165,18 +378,151 @@ impl Graph {
         Ok(false)
     } 
 
+    // Replaces the parts of an edge `update` gives, all or none of them: metrics that can't be
+    // stored or limits that would leave the min above the max are refused before anything is
+    // written. None when there is no such edge. The version moves when anything changed.
+    pub fn update_edge(
+        &self,
+        from: NodeId,
+        to: NodeId,
+        bridge_name: &str,
+        update: EdgeUpdate,
+    ) -> Result<Option<EdgeChanges>, GraphError> {
+        if let Some(metrics) = &update.metrics {
+            validate_metrics(bridge_name, metrics)?;
+        }
+        let _writes = self.write_guard();
+        let shard = &self.outgoing_edges[self.shard_index(from)];
+        let Some(edges) = shard.get(&from) else {
+            return Ok(None);
+        };
+        let Some(edge) = edges.value().iter().find(|edge| edge.to == to && &*edge.bridge_name == bridge_name) else {
+            return Ok(None);
+        };
+
+        let mut changes = EdgeChanges::default();
+        // Held until everything is written, so the limits checked are the ones replaced
+        let mut limits = edge.limits.write().unwrap();
+        let updated = AmountLimits {
+            min: update.min_amount.unwrap_or(limits.min),
+            max: update.max_amount.unwrap_or(limits.max),
+        };
+        if let (Some(min), Some(max)) = (updated.min, updated.max) {
+            if min > max {
+                return Err(GraphError::InvalidLimits { bridge: bridge_name.to_string(), min, max });
+            }
+        }
+        if updated != *limits {
+            *limits = updated;
+            changes.limits = true;
+        }
+        if let Some(metrics) = update.metrics {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+            edge.metrics.update_at(metrics, now);
+            edge.is_stale.store(false, Ordering::Release);
+            changes.metrics = true;
+        }
+        if let Some(active) = update.active {
+            changes.active = edge.is_active.swap(active, Ordering::AcqRel) != active;
+        }
+        if let Some(valid_until) = update.valid_until {
+            if let Some(quote) = edge.quote.write().unwrap().as_mut() {
+                changes.valid_until = quote.valid_until != valid_until;
+                quote.valid_until = valid_until;
+            }
+        }
+        drop(limits);
+
+        if changes.any() {
+            self.bump_version();
+        }
+        Ok(Some(changes))
+    }
+
+    // Switches an edge on or off without touching its metrics. Whether the flag changed, so
+    // false also when there is no such edge.
+    pub fn set_edge_active(
+        &self,
+        from: NodeId,
+        to: NodeId,
+        bridge_name: &str,
+        active: bool,
+    ) -> bool {
+        let _writes = self.write_guard();
+        let shard = &self.outgoing_edges[self.shard_index(from)];
+        let Some(edges) = shard.get(&from) else {
+            return false;
+        };
+        let Some(edge) = edges.value().iter().find(|edge| edge.to == to && &*edge.bridge_name == bridge_name) else {
+            return false;
+        };
+        if edge.is_active.swap(active, Ordering::AcqRel) == active {
+            return false;
+        }
+        self.bump_version();
+        true
+    }
+
+    // Flags an edge's metrics as no longer current, or clears the flag, without touching them.
+    // Stale edges are still searched at the metrics they have, so a graph restored from a
+    // snapshot can route before it's refreshed, but reachability listings and re-checks of
+    // pinned routes (RoutingEngine::path_along) leave them out until fresh metrics come in.
+    // Whether the flag changed, so false also when there is no such edge.
+    pub fn set_edge_stale(
+        &self,
+        from: NodeId,
+        to: NodeId,
+        bridge_name: &str,
+        stale: bool,
+    ) -> bool {
+        let shard = &self.outgoing_edges[self.shard_index(from)];
+        let Some(edges) = shard.get(&from) else {
+            return false;
+        };
+        let Some(edge) = edges.value().iter().find(|edge| edge.to == to && &*edge.bridge_name == bridge_name) else {
+            return false;
+        };
+        if edge.is_stale.swap(stale, Ordering::AcqRel) == stale {
+            return false;
+        }
+        self.bump_version();
+        true
+    }
+
+    // Records the quote behind an edge's current metrics. Whether there is such an edge. The
+    // version isn't bumped, the metrics update that comes with a quote already did.
+    pub fn set_edge_quote(
+        &self,
+        from: NodeId,
+        to: NodeId,
+        bridge_name: &str,
+        quote: Option<EdgeQuote>,
+    ) -> bool {
+        let shard = &self.outgoing_edges[self.shard_index(from)];
+        let Some(edges) = shard.get(&from) else {
+            return false;
+        };
+        let Some(edge) = edges.value().iter().find(|edge| edge.to == to && &*edge.bridge_name == bridge_name) else {
+            return false;
+        };
+        *edge.quote.write().unwrap() = quote;
+        true
+    }
+
     // Get all the outgoing edges from a given Node.
     pub fn get_outgoing_edges(&self, from: NodeId) -> Vec<Arc<Edge>> {
+        let mut edges = Vec::new();
+        self.for_each_outgoing_edge(from, |edge| edges.push(Arc::clone(edge)));
+        edges
+    }
+
+    // Visits the active edges out of `from` under the shard's read lock, so `f` mustn't write
+    // to the graph
+    pub(crate) fn for_each_outgoing_edge(&self, from: NodeId, f: impl FnMut(&Arc<Edge>)) {
         let shard = &self.outgoing_edges[self.shard_index(from)];
-        
-        let res = shard.get(&from)
-                                                        .map(|entry| entry
-                                                                                                        .value().iter()
-                                                                                                        .filter(|edge| edge.is_active())
-                                                                                                        .map(|edge| Arc::clone(edge))
-                                                                                                        .collect()
-                                                        ).unwrap_or_default();
-        res
+        if let Some(entry) = shard.get(&from) {
+            entry.value().iter().filter(|edge| edge.is_active()).for_each(f);
+        }
     }
 
     pub fn get_incoming_edges(&self, to: NodeId) -> Vec<Arc<Edge>> {
@@ -184,7 +530,7 @@ impl Graph {
 
         let res = shard.get(&to).map(|entry| entry.value().iter()
                                                                                                     .filter(|edge| edge.is_active())
-                                                                                                    .map(|edge| Arc::clone(edge))
+                                                                                                    .map(Arc::clone)
                                                                                                     .collect()
                                                                                                         ).unwrap_or_default();
         res
@@ -196,34 +542,824 @@ impl Graph {
         node_id: NodeId, 
         params: &RoutingParams
     ) -> Vec<(NodeId, f64)> {
-        self.get_outgoing_edges(node_id)
-            .into_iter()
-            .map(|edge| {
-                let metrics = edge.get_metrics();
-                let weight = compute_edge_weight(&metrics, params);
-                (edge.to, weight)
-            })
-            .collect()
+        let params = params.normalized();
+        let mut neighbours = Vec::new();
+        self.for_each_outgoing_edge(node_id, |edge| neighbours.push((edge.to, compute_edge_weight(&edge.get_metrics(), &params, None))));
+        neighbours
+    }
+
+    // Nodes `from` reaches over active, fresh edges in at most `max_hops` hops, each with the
+    // fewest hops it takes, nearest first. `from` itself isn't listed.
+    pub fn reachable_destinations(&self, from: NodeId, max_hops: usize) -> Vec<(NodeId, usize)> {
+        self.reachable(from, max_hops, true)
+    }
+
+    // Nodes that reach `to` over active, fresh edges in at most `max_hops` hops, as
+    // reachable_destinations
+    pub fn reachable_sources(&self, to: NodeId, max_hops: usize) -> Vec<(NodeId, usize)> {
+        self.reachable(to, max_hops, false)
+    }
+
+    // Breadth-first along outgoing edges when `forward`, incoming ones otherwise. Answers are
+    // kept until the version moves, as every edge write moves it.
+    fn reachable(&self, start: NodeId, max_hops: usize, forward: bool) -> Vec<(NodeId, usize)> {
+        let version = self.version();
+        let key = (start, max_hops, forward);
+        {
+            let mut cached = self.reachable.lock().unwrap();
+            if cached.0 != version {
+                *cached = (version, HashMap::new());
+            }
+            if let Some(found) = cached.1.get(&key) {
+                return found.clone();
+            }
+        }
+
+        let mut hops = HashMap::from([(start, 0)]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(node) = queue.pop_front() {
+            let depth = hops[&node];
+            if depth == max_hops {
+                continue;
+            }
+            let edges = match forward {
+                true => self.get_outgoing_edges(node),
+                false => self.get_incoming_edges(node),
+            };
+            for edge in edges.iter().filter(|edge| !edge.is_stale()) {
+                let next = if forward { edge.to } else { edge.from };
+                if let Entry::Vacant(entry) = hops.entry(next) {
+                    entry.insert(depth + 1);
+                    queue.push_back(next);
+                }
+            }
+        }
+        hops.remove(&start);
+        let mut found: Vec<(NodeId, usize)> = hops.into_iter().collect();
+        found.sort_by_key(|&(node, hops)| (hops, node));
+
+        let mut cached = self.reachable.lock().unwrap();
+        // Left out if the graph moved on while searching
+        if cached.0 == version {
+            cached.1.insert(key, found.clone());
+        }
+        found
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version.load(Ordering::Acquire)
+    }
+
+    // Unix time the graph's quotes are aged to: when its latest version was published, or it
+    // was built before any. Routes found at one version are rated alike however late they're
+    // searched for; a graph kept up to date is published at least every refresh.
+    pub fn clock(&self) -> u64 {
+        self.published_at.load(Ordering::Acquire)
+    }
+
+    // Ages the graph's quotes to unix time `at` from now on, whatever is published later, e.g. to
+    // replay a graph as it stood at some past time
+    pub fn pin_clock(&self, at: u64) {
+        self.clock_pinned.store(true, Ordering::Release);
+        self.published_at.store(at, Ordering::Release);
+    }
+
+    // Runs `apply`, publishing what it writes as one change: the version moves once, after the
+    // last write, rather than with each, and not at all when nothing changed. Writes others make
+    // meanwhile are published with it. Batches run one at a time.
+    pub fn batch<R>(&self, apply: impl FnOnce() -> R) -> R {
+        let _batch = self.batch.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        self.batch_state.store(BATCH_ACTIVE, Ordering::Release);
+        let _published = BatchEnd(self);
+        apply()
+    }
+
+    // Writers run alongside each other, only `compact` waits for them
+    fn write_guard(&self) -> RwLockReadGuard<'_, ()> {
+        self.writes.read().unwrap_or_else(|poisoned| poisoned.into_inner())
     }
 
+    fn is_batching(&self) -> bool {
+        self.batch_state.load(Ordering::Acquire) & BATCH_ACTIVE != 0
+    }
+
+    fn bump_version(&self) {
+        // A batch that ends before the change is noted leaves it to be published here
+        if self.is_batching() && self.batch_state.fetch_or(BATCH_CHANGED, Ordering::AcqRel) & BATCH_ACTIVE != 0 {
+            return;
+        }
+        if !self.clock_pinned.load(Ordering::Acquire) {
+            self.published_at.fetch_max(SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(), Ordering::AcqRel);
+        }
+        let version = self.version.fetch_add(1, Ordering::AcqRel) + 1;
+        // Concurrent writers may get here out of order; receivers only ever see it move forward
+        self.changes.send_if_modified(|latest| {
+            let newer = version > *latest;
+            if newer {
+                *latest = version;
+            }
+            newer
+        });
+        #[cfg(all(debug_assertions, feature = "check-invariants"))]
+        self.assert_invariants();
+    }
+
+    // What's broken in the graph's storage, nothing for a graph only changed through its
+    // methods. One pass over every edge; a graph being written to meanwhile may show a write
+    // half done.
+    pub fn check_invariants(&self) -> Vec<InvariantViolation> {
+        invariants::check(&self.nodes, &self.outgoing_edges, &self.incoming_edges)
+    }
+
+    // Run after every change with the check-invariants feature, in debug builds
+    #[cfg(all(debug_assertions, feature = "check-invariants"))]
+    fn assert_invariants(&self) {
+        let violations = self.check_invariants();
+        assert!(violations.is_empty(), "graph invariants broken: {:#?}", violations);
+    }
+
+    // The storage itself, for tests that break it on purpose
+    #[cfg(test)]
+    pub(crate) fn storage(&self) -> (&Nodes, &[Arc<EdgeShard>], &[Arc<EdgeShard>]) {
+        (&self.nodes, &self.outgoing_edges, &self.incoming_edges)
+    }
+
+    // Wakes on every change to the graph, with the version after it. Bursts of changes may
+    // be seen as one.
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.changes.subscribe()
+    }
+
+    // Records how much of its configured pairs the graph has edges for, which Router gates
+    // queries on. The version isn't bumped, the edges didn't change.
+    pub fn set_coverage(&self, coverage: Coverage) {
+        *self.coverage.write().unwrap() = Some(coverage);
+    }
+
+    pub fn coverage(&self) -> Option<Coverage> {
+        self.coverage.read().unwrap().clone()
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    // Every edge, active or not
+    pub fn edge_count(&self) -> usize {
+        self.outgoing_edges
+            .iter()
+            .flat_map(|shard| shard.iter().map(|entry| entry.value().len()).collect::<Vec<_>>())
+            .sum()
+    }
+
+    // Edges the router can use
+    pub fn active_edge_count(&self) -> usize {
+        self.outgoing_edges
+            .iter()
+            .flat_map(|shard| shard.iter().map(|entry| entry.value().iter().filter(|edge| edge.is_active()).count()).collect::<Vec<_>>())
+            .sum()
+    }
+
+    pub fn stats(&self) -> GraphStats {
+        GraphStats {
+            version: self.version(),
+            nodes: self.node_count(),
+            edges: self.edge_count(),
+            active_edges: self.active_edge_count(),
+        }
+    }
+
+    // The graph as it is now, frozen, for running several queries against one version. Edges
+    // written while it's copied make it try again, a few times, before it waits out the batch
+    // being applied, if any, and copies with the next held off. Writes made outside a batch
+    // meanwhile may still show, each of them whole.
+    pub fn read_view(&self) -> GraphView {
+        for _ in 1..READ_VIEW_ATTEMPTS {
+            let version = self.version();
+            let view = self.copy_view(version);
+            if self.version() == version && !self.is_batching() {
+                return view;
+            }
+        }
+        let _batch = self.batch.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        self.copy_view(self.version())
+    }
+
+    fn copy_view(&self, version: u64) -> GraphView {
+        let nodes = self.nodes.iter().map(|entry| (*entry.key(), Arc::clone(entry.value()))).collect();
+        let outgoing = self.outgoing_edges
+            .iter()
+            .flat_map(|shard| shard.iter().map(|entry| {
+                (*entry.key(), entry.value().iter().map(|edge| Arc::new(edge.frozen())).collect())
+            }).collect::<Vec<_>>())
+            .collect();
+        GraphView::new(version, self.clock(), nodes, outgoing)
+    }
+
+    // Drops inactive edges that are long dead, the nodes no edge touches anymore unless pinned,
+    // and the shard entries they leave empty, then shrinks the edge lists. Only what no route
+    // search takes goes, so the version isn't bumped; concurrent readers see the graph before
+    // or after each removal. Writes that add edges or switch them on wait for it to finish.
+    pub fn compact(&self, options: &CompactionOptions) -> CompactionReport {
+        let _writes = self.writes.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut report = CompactionReport::default();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let dead = |edge: &Arc<Edge>| !edge.is_active() && edge.metrics.last_updated().saturating_add(options.inactive_for.as_secs()) <= now;
+
+        // Each edge is judged once, from its outgoing list, and exactly those dropped there are
+        // dropped from the incoming index, keyed by their destination
+        let mut removed: HashMap<NodeId, Vec<Arc<Edge>>> = HashMap::new();
+        self.prune_shards(&self.outgoing_edges, &mut report, |_, edge| {
+            if !dead(edge) {
+                return true;
+            }
+            removed.entry(edge.to).or_default().push(Arc::clone(edge));
+            false
+        });
+        report.edges = removed.values().map(Vec::len).sum();
+        self.prune_shards(&self.incoming_edges, &mut report, |to, edge| {
+            !removed.get(&to).is_some_and(|dropped| dropped.iter().any(|dropped| Arc::ptr_eq(dropped, edge)))
+        });
+        // Let go of the dropped edges, and with them their hold on the bridge names below
+        drop(removed);
+        report.bytes += report.edges * (size_of::<Edge>() + size_of::<EdgeMetricsAtomic>() + size_of::<RwLock<Option<EdgeQuote>>>());
+        report.bytes += report.entries * size_of::<(NodeId, Vec<Arc<Edge>>)>();
+
+        let orphans: Vec<NodeId> = self.nodes
+            .iter()
+            .map(|entry| *entry.key())
+            .filter(|node| !options.pinned.contains(node) && !self.has_edges(*node))
+            .collect();
+        for node_id in orphans {
+            let Some((_, node)) = self.nodes.remove(&node_id) else {
+                continue;
+            };
+            report.nodes += 1;
+            report.bytes += size_of::<Node>() + match &node.node_type {
+                NodeType::Asset { chain, token_address, token_symbol } => chain.len() + token_address.len() + token_symbol.len(),
+                NodeType::Exchange { name, chain } => name.len() + chain.len(),
+            };
+        }
+        // Names no edge is labelled with anymore; one being interned for a new edge is held
+        // outside the set too, so it stays
+        self.bridge_names.lock().unwrap().retain(|name| Arc::strong_count(name) > 1);
+        #[cfg(all(debug_assertions, feature = "check-invariants"))]
+        self.assert_invariants();
+        report
+    }
+
+    // Keeps the edges `keep` is true of, given the node they're listed under, then drops the
+    // entries left empty and shrinks what stays
+    fn prune_shards(&self, shards: &[Arc<EdgeShard>], report: &mut CompactionReport, mut keep: impl FnMut(NodeId, &Arc<Edge>) -> bool) {
+        for shard in shards {
+            for mut entry in shard.iter_mut() {
+                let node = *entry.key();
+                let edges = entry.value_mut();
+                let before = edges.len();
+                edges.retain(|edge| keep(node, edge));
+                report.bytes += (before - edges.len()) * size_of::<Arc<Edge>>();
+                report.bytes += (edges.capacity() - edges.len()) * size_of::<Arc<Edge>>();
+                edges.shrink_to_fit();
+            }
+            let before = shard.len();
+            shard.retain(|_, edges| !edges.is_empty());
+            report.entries += before - shard.len();
+            shard.shrink_to_fit();
+        }
+    }
+
+    // Whether any edge, active or not, starts or ends at the node
+    fn has_edges(&self, node_id: NodeId) -> bool {
+        let shard = self.shard_index(node_id);
+        [&self.outgoing_edges[shard], &self.incoming_edges[shard]]
+            .iter()
+            .any(|edges| edges.get(&node_id).is_some_and(|entry| !entry.value().is_empty()))
+    }
+
+    // Plain copy of the nodes and edges, for persisting the graph
+    pub fn snapshot(&self) -> GraphSnapshot {
+        let mut nodes: Vec<Node> = self.nodes.iter().map(|entry| Node::clone(entry.value())).collect();
+        nodes.sort_by_key(|node| node.id);
+
+        let mut edges = Vec::new();
+        for shard in &self.outgoing_edges {
+            for entry in shard.iter() {
+                edges.extend(entry.value().iter().map(|edge| EdgeSnapshot {
+                    from: edge.from,
+                    to: edge.to,
+                    bridge_name: edge.bridge_name.to_string(),
+                    kind: edge.kind,
+                    metrics: edge.get_metrics(),
+                    min_amount: edge.min_amount(),
+                    max_amount: edge.max_amount(),
+                    is_active: edge.is_active(),
+                }));
+            }
+        }
+        edges.sort_by(|a, b| (a.from, a.to, &a.bridge_name).cmp(&(b.from, b.to, &b.bridge_name)));
+
+        GraphSnapshot {
+            version: self.version(),
+            clock: self.clock(),
+            nodes,
+            edges,
+        }
+    }
+
+    // Rebuilds a graph from a snapshot. Restored edges keep their active flag but are marked
+    // stale until `update_edge_metrics` refreshes them.
+    pub fn from_snapshot(snapshot: GraphSnapshot, shard_count: usize) -> Result<Self, GraphError> {
+        let graph = Self::try_new(shard_count)?;
+        for node in snapshot.nodes {
+            graph.nodes.insert(node.id, Arc::new(node));
+        }
+
+        for edge in snapshot.edges {
+            let restored = Arc::new(Edge::new(edge.from, edge.to, graph.intern(&edge.bridge_name), edge.metrics, edge.min_amount, edge.max_amount).with_kind(edge.kind));
+            restored.is_active.store(edge.is_active, Ordering::Release);
+            restored.is_stale.store(true, Ordering::Release);
+
+            graph.outgoing_edges[graph.shard_index(edge.from)].entry(edge.from).or_default().push(Arc::clone(&restored));
+            graph.incoming_edges[graph.shard_index(edge.to)].entry(edge.to).or_default().push(restored);
+        }
+
+        graph.version.store(snapshot.version, Ordering::Release);
+        graph.changes.send_replace(snapshot.version);
+        Ok(graph)
+    }
+
+}
+
+fn validate_metrics(bridge: &str, metrics: &EdgeMetrics) -> Result<(), GraphError> {
+    let values = [("cost", metrics.cost), ("speed", metrics.speed), ("liquidity", metrics.liquidity), ("risk", metrics.risk)];
+    match values.into_iter().find(|(_, value)| !value.is_finite() || *value < 0.0) {
+        Some((name, value)) => Err(GraphError::InvalidMetric { bridge: bridge.to_string(), name, value }),
+        None => Ok(()),
+    }
 }
 
-fn compute_edge_weight(
+// `output_loss` is what the edge loses of the amount a search sends, see SentAmount::loss.
+// Without an amount only the fees are known to be lost, so output falls back to cost.
+pub(crate) fn compute_edge_weight(
     metrics: &EdgeMetrics,
-    params: &RoutingParams
+    params: &RoutingParams,
+    output_loss: Option<f64>,
 ) -> f64 {
     let cost_component = params.alpha * metrics.cost;
     let speed_component = params.beta * metrics.speed;
     let liquidity_component = params.gamma * metrics.liquidity;
     let risk_component = params.delta * metrics.risk;
+    let output_component = params.omega * output_loss.unwrap_or(metrics.cost);
 
-    cost_component + speed_component + liquidity_component + risk_component
+    cost_component + speed_component + liquidity_component + risk_component + output_component
 }
 
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use crate::routing::RoutingEngine;
+    use crate::view::GraphRead;
+
+    #[test]
+    fn snapshots_restore_nodes_and_edges_as_stale() {
+        let graph = Graph::new(16);
+        let eth = graph.get_or_create_asset_node("ethereum", "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", "USDC");
+        let pol = graph.get_or_create_asset_node("polygon", "0x3c499c542cef5e3811e1192ce70d8cc03d5c3359", "USDC");
+        let metrics = EdgeMetrics { cost: 2.5, speed: 60.0, liquidity: 1_000_000.0, risk: 0.1 };
+        graph.add_edge(eth, pol, "stargate", metrics.clone(), None, Some(50_000.0)).unwrap();
+        graph.add_edge(pol, eth, "stargate", metrics.clone(), None, None).unwrap();
+
+        let json = serde_json::to_string(&graph.snapshot()).unwrap();
+        let restored = Graph::from_snapshot(serde_json::from_str(&json).unwrap(), 4).unwrap();
+
+        assert_eq!(restored.node_count(), 2);
+        assert_eq!(restored.edge_count(), 2);
+        assert_eq!(restored.version(), graph.version());
+        assert!(matches!(&restored.get_node(pol).unwrap().node_type, NodeType::Asset { token_symbol, .. } if token_symbol == "USDC"));
+
+        let edge = &restored.get_outgoing_edges(eth)[0];
+        assert_eq!(edge.get_metrics(), metrics);
+        assert_eq!(edge.max_amount(), Some(50_000.0));
+        assert!(edge.is_stale());
+        assert_eq!(restored.get_incoming_edges(pol).len(), 1);
+
+        restored.update_edge_metrics(eth, pol, "stargate", metrics).unwrap();
+        assert!(!restored.get_outgoing_edges(eth)[0].is_stale());
+        assert!(restored.get_outgoing_edges(pol)[0].is_stale());
+    }
+
+    #[test]
+    fn edge_updates_apply_only_what_they_give() {
+        let graph = Graph::new(4);
+        let eth = graph.get_or_create_asset_node("ethereum", "0xa0b8", "USDC");
+        let pol = graph.get_or_create_asset_node("polygon", "0x3c49", "USDC");
+        let metrics = EdgeMetrics { cost: 2.5, speed: 60.0, liquidity: 1_000_000.0, risk: 0.1 };
+        graph.add_edge(eth, pol, "stargate", metrics.clone(), Some(1.0), Some(50_000.0)).unwrap();
+        let edge = Arc::clone(&graph.get_outgoing_edges(eth)[0]);
+        graph.set_edge_quote(eth, pol, "stargate", Some(EdgeQuote {
+            reference: "q1".to_string(),
+            quoted_at: 100,
+            valid_until: Some(160),
+            fees: Vec::new(),
+            speed_breakdown: None,
+            source: None,
+            sources: Vec::new(),
+            cost_in_source: None,
+        }));
+
+        let version = graph.version();
+        let update = EdgeUpdate { max_amount: Some(Some(20_000.0)), active: Some(false), ..EdgeUpdate::default() };
+        let changes = graph.update_edge(eth, pol, "stargate", update).unwrap().unwrap();
+        assert_eq!(changes, EdgeChanges { limits: true, active: true, ..EdgeChanges::default() });
+        assert_eq!(edge.amount_limits(), AmountLimits { min: Some(1.0), max: Some(20_000.0) });
+        assert!(!edge.is_active());
+        assert_eq!(edge.get_metrics(), metrics);
+        assert!(graph.version() > version);
+
+        // Nothing new, nothing changed and the version stays
+        let version = graph.version();
+        let update = EdgeUpdate { min_amount: Some(Some(1.0)), active: Some(false), ..EdgeUpdate::default() };
+        assert!(!graph.update_edge(eth, pol, "stargate", update).unwrap().unwrap().any());
+        assert_eq!(graph.version(), version);
+
+        let cheaper = EdgeMetrics { cost: 1.0, ..metrics };
+        let update = EdgeUpdate { metrics: Some(cheaper.clone()), min_amount: Some(None), valid_until: Some(Some(220)), ..EdgeUpdate::default() };
+        let changes = graph.update_edge(eth, pol, "stargate", update).unwrap().unwrap();
+        assert_eq!(changes, EdgeChanges { metrics: true, limits: true, valid_until: true, ..EdgeChanges::default() });
+        assert_eq!((edge.min_amount(), edge.get_metrics(), edge.get_quote().unwrap().valid_until), (None, cheaper, Some(220)));
+
+        assert_eq!(graph.update_edge(eth, pol, "across", EdgeUpdate::default()), Ok(None));
+    }
+
+    #[test]
+    fn edge_updates_keep_the_min_at_most_the_max() {
+        let graph = Graph::new(4);
+        let eth = graph.get_or_create_asset_node("ethereum", "0xa0b8", "USDC");
+        let pol = graph.get_or_create_asset_node("polygon", "0x3c49", "USDC");
+        let metrics = EdgeMetrics { cost: 2.5, speed: 60.0, liquidity: 1_000_000.0, risk: 0.1 };
+        graph.add_edge(eth, pol, "stargate", metrics.clone(), Some(10.0), Some(100.0)).unwrap();
+        let edge = Arc::clone(&graph.get_outgoing_edges(eth)[0]);
+
+        // A min past the current max is refused, and so is the rest of the update with it
+        let update = EdgeUpdate { metrics: Some(EdgeMetrics { cost: 9.0, ..metrics.clone() }), min_amount: Some(Some(200.0)), ..EdgeUpdate::default() };
+        let err = graph.update_edge(eth, pol, "stargate", update).unwrap_err();
+        assert_eq!(err, GraphError::InvalidLimits { bridge: "stargate".to_string(), min: 200.0, max: 100.0 });
+        assert_eq!((edge.amount_limits(), edge.get_metrics()), (AmountLimits { min: Some(10.0), max: Some(100.0) }, metrics));
+
+        // Moving both at once is fine
+        let update = EdgeUpdate { min_amount: Some(Some(200.0)), max_amount: Some(Some(1_000.0)), ..EdgeUpdate::default() };
+        assert!(graph.update_edge(eth, pol, "stargate", update).unwrap().unwrap().limits);
+    }
+
+    #[test]
+    fn readers_see_old_or_new_limits_but_never_a_mix() {
+        let graph = Arc::new(Graph::new(4));
+        let eth = graph.get_or_create_asset_node("ethereum", "0xa0b8", "USDC");
+        let pol = graph.get_or_create_asset_node("polygon", "0x3c49", "USDC");
+        let metrics = EdgeMetrics { cost: 2.5, speed: 60.0, liquidity: 1_000_000.0, risk: 0.1 };
+        graph.add_edge(eth, pol, "stargate", metrics, Some(1.0), Some(10.0)).unwrap();
+        let (low, high) = (AmountLimits { min: Some(1.0), max: Some(10.0) }, AmountLimits { min: Some(20.0), max: Some(100.0) });
+        let edge = Arc::clone(&graph.get_outgoing_edges(eth)[0]);
+        let done = Arc::new(std::sync::atomic::AtomicBool::new(false));
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let (edge, done) = (Arc::clone(&edge), Arc::clone(&done));
+                std::thread::spawn(move || loop {
+                    let limits = edge.amount_limits();
+                    assert!(limits == low || limits == high, "{:?}", limits);
+                    if done.load(Ordering::Acquire) {
+                        break;
+                    }
+                })
+            })
+            .collect();
+        for round in 0..2_000 {
+            let limits = if round % 2 == 0 { high } else { low };
+            let update = EdgeUpdate { min_amount: Some(limits.min), max_amount: Some(limits.max), ..EdgeUpdate::default() };
+            graph.update_edge(eth, pol, "stargate", update).unwrap();
+        }
+        done.store(true, Ordering::Release);
+        for reader in readers {
+            reader.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn compaction_drops_dead_weight_without_changing_routes() {
+        let layered = crate::testutil::layered_graph(11, 5, 8, 160);
+        let (source, layers, graph) = (layered.source(), layered.layers, Arc::new(layered.graph));
+        let metrics = EdgeMetrics { cost: 0.01, speed: 1.0, liquidity: 1e9, risk: 0.0 };
+        // Tokens of pairs since removed from config, their edges long switched off
+        for i in 0..50 {
+            let retired = graph.get_or_create_asset_node("retired", &format!("0x{:040x}", i), "OLD");
+            let live = layers[1 + i % 3][i % 8];
+            for (from, to) in [(live, retired), (retired, live)] {
+                graph.add_edge(from, to, "retired", metrics.clone(), None, None).unwrap();
+                graph.set_edge_active(from, to, "retired", false);
+            }
+        }
+        // A live edge switched off, and tokens no pair ever used, one of them an intent's target
+        let dropped = graph.get_outgoing_edges(layers[2][0])[0].clone();
+        graph.set_edge_active(dropped.from, dropped.to, &dropped.bridge_name, false);
+        let unused: Vec<NodeId> = (0..20).map(|i| graph.get_or_create_asset_node("unused", &format!("0x{:040x}", i), "NEW")).collect();
+        let (nodes, edges, active) = (graph.node_count(), graph.edge_count(), graph.active_edge_count());
+
+        let engine = RoutingEngine::new(Arc::clone(&graph), 4);
+        let routes = || -> Vec<Option<Path>> {
+            let params = [RoutingParams::cheapest(), RoutingParams::fastest(), RoutingParams::safest()];
+            layers.iter().flatten().flat_map(|&to| params.iter().map(move |params| (to, params)))
+                .map(|(to, params)| engine.find_path(source, to, params))
+                .collect()
+        };
+        let before = routes();
+        assert!(before.iter().filter(|route| route.is_some()).count() > 60);
+
+        // Recently switched off edges stay
+        let pinned = CompactionOptions::default().with_pinned([unused[0]]);
+        assert_eq!(graph.compact(&pinned).edges, 0);
+        assert_eq!((graph.node_count(), graph.edge_count()), (nodes - 19, edges));
+
+        let compacted = std::thread::scope(|scope| {
+            let readers: Vec<_> = (0..2).map(|_| scope.spawn(|| (0..5).all(|_| routes() == before))).collect();
+            let report = graph.compact(&pinned.clone().with_inactive_for(Duration::ZERO));
+            assert!(readers.into_iter().all(|reader| reader.join().unwrap()));
+            report
+        });
+        assert_eq!((compacted.edges, compacted.nodes), (101, 50));
+        assert!(compacted.entries >= 100 && compacted.bytes > 101 * size_of::<Edge>(), "{:?}", compacted);
+        assert_eq!((graph.node_count(), graph.edge_count(), graph.active_edge_count()), (nodes - 69, edges - 101, active));
+        assert!(graph.get_node(unused[0]).is_some() && graph.get_node(unused[1]).is_none());
+        // The retired bridge's name went with its last edge, the others' stay interned
+        let names = graph.bridge_names.lock().unwrap().iter().map(|name| name.to_string()).collect::<HashSet<_>>();
+        assert!(!names.contains("retired") && names.contains(&*dropped.bridge_name), "{:?}", names);
+        assert_eq!(routes(), before);
+        assert_eq!(graph.compact(&pinned.with_inactive_for(Duration::ZERO)), CompactionReport::default());
+    }
+
+    #[test]
+    fn compaction_racing_writers_keeps_the_indices_whole() {
+        let graph = Graph::new(4);
+        let metrics = EdgeMetrics { cost: 0.01, speed: 1.0, liquidity: 1e9, risk: 0.0 };
+        let hub = graph.get_or_create_asset_node("ethereum", "0xhub", "HUB");
+        // Each token keeps an active edge from the hub, so compaction never drops it
+        let tokens: Vec<NodeId> = (0..8)
+            .map(|i| {
+                let token = graph.get_or_create_asset_node("polygon", &format!("0x{:040x}", i), "TKN");
+                graph.add_edge(hub, token, "anchor", metrics.clone(), None, None).unwrap();
+                token
+            })
+            .collect();
+        let done = AtomicBool::new(false);
+
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                while !done.load(Ordering::Acquire) {
+                    graph.compact(&CompactionOptions::default().with_inactive_for(Duration::ZERO));
+                }
+            });
+            let togglers: Vec<_> = (0..2)
+                .map(|offset| {
+                    let (graph, tokens) = (&graph, &tokens);
+                    scope.spawn(move || {
+                        for round in 0..500 {
+                            let (from, to) = (tokens[(round + offset) % 8], tokens[(round + offset + 1) % 8]);
+                            graph.set_edge_active(from, to, "flip", round % 3 == 0);
+                        }
+                    })
+                })
+                .collect();
+            for round in 0..500 {
+                let (from, to) = (tokens[round % 8], tokens[(round + 1) % 8]);
+                // Only this thread adds, so an edge missing here is still missing when added.
+                // Never UnknownNode: the tokens are never without edges.
+                if graph.update_edge(from, to, "flip", EdgeUpdate::default()).unwrap().is_none() {
+                    graph.add_edge(from, to, "flip", metrics.clone(), None, None).unwrap();
+                }
+                graph.set_edge_active(from, to, "flip", round % 2 == 0);
+            }
+            for toggler in togglers {
+                toggler.join().unwrap();
+            }
+            done.store(true, Ordering::Release);
+        });
+
+        assert_eq!(graph.check_invariants(), Vec::new());
+        assert_eq!(graph.node_count(), 9);
+    }
+
+    #[test]
+    fn racing_creators_build_one_node_and_keep_the_winners_metadata() {
+        let graph = Graph::new(4);
+        let barrier = std::sync::Barrier::new(32);
+        let spec = |writer: usize| NodeSpec::asset("ethereum", "0xusdc", "USDC").with_metadata("writer", &writer.to_string());
+        // Odd threads insist on creating the node, even ones take it either way
+        let created: Vec<Option<usize>> = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..32)
+                .map(|writer| {
+                    let (graph, barrier) = (&graph, &barrier);
+                    scope.spawn(move || {
+                        barrier.wait();
+                        if writer % 2 == 1 {
+                            match graph.try_create_node(spec(writer)) {
+                                Ok(id) => Some((id, writer)),
+                                Err(GraphError::AlreadyExists(id)) => {
+                                    assert_eq!(id, spec(0).id());
+                                    None
+                                }
+                                Err(err) => panic!("{}", err),
+                            }
+                        } else {
+                            let (id, creation) = graph.get_or_create_node(spec(writer));
+                            assert_eq!(id, spec(0).id());
+                            (creation == NodeCreation::Created).then_some((id, writer))
+                        }
+                    })
+                })
+                .collect();
+            threads.into_iter().map(|thread| thread.join().unwrap().map(|(_, writer)| writer)).collect()
+        });
+
+        let winners: Vec<usize> = created.into_iter().flatten().collect();
+        assert_eq!(winners.len(), 1);
+        assert_eq!(graph.node_count(), 1);
+        let node = graph.get_node(spec(0).id()).unwrap();
+        assert_eq!(node.metadata, spec(winners[0]).metadata);
+        assert_eq!(graph.get_or_create_asset_node("ethereum", "0xusdc", "USDC"), node.id);
+        assert!(Arc::ptr_eq(&graph.get_node(node.id).unwrap(), &node));
+        assert_eq!(graph.try_create_node(spec(99)), Err(GraphError::AlreadyExists(node.id)));
+        assert_eq!(graph.get_or_create_node(NodeSpec::exchange("uniswap", "ethereum")).1, NodeCreation::Created);
+    }
+
+    #[test]
+    fn invalid_edges_and_shard_counts_are_rejected() {
+        assert_eq!(Graph::try_new(12).err(), Some(GraphError::InvalidShardCount(12)));
+        assert_eq!(Graph::try_new(0).err(), Some(GraphError::InvalidShardCount(0)));
+
+        let graph = Graph::new(4);
+        let eth = graph.get_or_create_asset_node("ethereum", "usdc", "USDC");
+        let pol = graph.get_or_create_asset_node("polygon", "usdc", "USDC");
+        let missing = NodeId::from_parts("base", "usdc");
+        let metrics = EdgeMetrics { cost: 1.0, speed: 60.0, liquidity: 1000.0, risk: 0.1 };
+
+        assert_eq!(graph.add_edge(eth, missing, "stargate", metrics.clone(), None, None), Err(GraphError::UnknownNode(missing)));
+        let negative = EdgeMetrics { cost: -1.0, ..metrics.clone() };
+        assert!(matches!(graph.add_edge(eth, pol, "stargate", negative, None, None), Err(GraphError::InvalidMetric { name: "cost", .. })));
+        graph.add_edge(eth, pol, "stargate", metrics.clone(), None, None).unwrap();
+        let nan = EdgeMetrics { risk: f64::NAN, ..metrics };
+        assert!(matches!(graph.update_edge_metrics(eth, pol, "stargate", nan), Err(GraphError::InvalidMetric { name: "risk", .. })));
+        assert_eq!(graph.edge_count(), 1);
+    }
+
+    #[test]
+    fn inactive_edges_are_hidden_until_switched_back_on() {
+        let graph = Graph::new(4);
+        let eth = graph.get_or_create_asset_node("ethereum", "usdc", "USDC");
+        let pol = graph.get_or_create_asset_node("polygon", "usdc", "USDC");
+        let metrics = EdgeMetrics { cost: 1.0, speed: 60.0, liquidity: 1000.0, risk: 0.1 };
+        graph.add_edge(eth, pol, "stargate", metrics, None, None).unwrap();
+        let version = graph.version();
+        let mut changes = graph.subscribe();
+        assert_eq!(*changes.borrow_and_update(), version);
+
+        assert!(graph.set_edge_active(eth, pol, "stargate", false));
+        assert!(!graph.set_edge_active(eth, pol, "stargate", false));
+        assert!(!graph.set_edge_active(eth, pol, "wormhole", false));
+        assert!(graph.get_outgoing_edges(eth).is_empty());
+        assert_eq!((graph.edge_count(), graph.active_edge_count()), (1, 0));
+        assert_eq!(graph.version(), version + 1);
+        assert!(changes.has_changed().unwrap());
+        assert_eq!(*changes.borrow_and_update(), version + 1);
+
+        assert!(graph.set_edge_active(eth, pol, "stargate", true));
+        assert_eq!(graph.get_incoming_edges(pol).len(), 1);
+    }
+
+    #[test]
+    fn batched_writes_are_published_as_one_version() {
+        let graph = Graph::new(4);
+        let eth = graph.get_or_create_asset_node("ethereum", "usdc", "USDC");
+        let pol = graph.get_or_create_asset_node("polygon", "usdc", "USDC");
+        let metrics = EdgeMetrics { cost: 1.0, speed: 60.0, liquidity: 1000.0, risk: 0.1 };
+        let version = graph.version();
+        let mut changes = graph.subscribe();
+        changes.borrow_and_update();
+
+        graph.batch(|| {
+            graph.add_edge(eth, pol, "stargate", metrics.clone(), None, None).unwrap();
+            graph.add_edge(pol, eth, "stargate", metrics.clone(), None, None).unwrap();
+            graph.update_edge_metrics(eth, pol, "stargate", EdgeMetrics { cost: 2.0, ..metrics.clone() }).unwrap();
+            graph.set_edge_active(pol, eth, "stargate", false);
+            assert_eq!(graph.version(), version);
+            assert!(!changes.has_changed().unwrap());
+        });
+        assert_eq!(graph.version(), version + 1);
+        assert_eq!(*changes.borrow_and_update(), version + 1);
+        assert_eq!((graph.edge_count(), graph.active_edge_count()), (2, 1));
+
+        // Nothing changed, nothing published
+        graph.batch(|| graph.set_edge_active(pol, eth, "stargate", false));
+        assert_eq!(graph.version(), version + 1);
+
+        // Unbatched writes move the version again, as does a batch that panics part way
+        graph.set_edge_active(pol, eth, "stargate", true);
+        assert_eq!(graph.version(), version + 2);
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            graph.batch(|| {
+                graph.set_edge_active(pol, eth, "stargate", false);
+                panic!("applier failed");
+            })
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(graph.version(), version + 3);
+        graph.set_edge_active(pol, eth, "stargate", true);
+        assert_eq!(graph.version(), version + 4);
+    }
+
+    #[test]
+    fn views_wait_out_a_batch_rather_than_show_half_of_it() {
+        let graph = Arc::new(Graph::new(4));
+        let eth = graph.get_or_create_asset_node("ethereum", "usdc", "USDC");
+        let pol = graph.get_or_create_asset_node("polygon", "usdc", "USDC");
+        let metrics = EdgeMetrics { cost: 1.0, speed: 60.0, liquidity: 1000.0, risk: 0.1 };
+        let (started, halfway) = std::sync::mpsc::channel();
+
+        let writer = {
+            let graph = Arc::clone(&graph);
+            std::thread::spawn(move || graph.batch(|| {
+                graph.add_edge(eth, pol, "stargate", metrics.clone(), None, None).unwrap();
+                started.send(()).unwrap();
+                std::thread::sleep(Duration::from_millis(50));
+                graph.add_edge(pol, eth, "stargate", metrics, None, None).unwrap();
+            }))
+        };
+        halfway.recv().unwrap();
+        let view = graph.read_view();
+        writer.join().unwrap();
+
+        assert_eq!((view.get_outgoing_edges(eth).len(), view.get_outgoing_edges(pol).len()), (1, 1));
+        assert_eq!(view.version(), graph.version());
+    }
+
+    #[test]
+    fn found_hops_count_the_alternatives_between_their_nodes() {
+        let graph = Arc::new(Graph::new(4));
+        let eth = graph.get_or_create_asset_node("ethereum", "usdc", "USDC");
+        let pol = graph.get_or_create_asset_node("polygon", "usdc", "USDC");
+        let arb = graph.get_or_create_asset_node("arbitrum", "usdc", "USDC");
+        let metrics = |cost| EdgeMetrics { cost, speed: 60.0, liquidity: 1000.0, risk: 0.1 };
+        graph.add_edge(eth, pol, "stargate", metrics(1.0), None, None).unwrap();
+        graph.add_edge(eth, pol, "across", metrics(2.0), None, None).unwrap();
+        graph.add_edge(eth, pol, "hop", metrics(3.0), None, None).unwrap();
+        graph.add_edge(pol, arb, "wormhole", metrics(1.0), None, None).unwrap();
+        graph.set_edge_active(eth, pol, "hop", false);
+
+        let alternatives = |engine: RoutingEngine| {
+            let path = engine.find_path(eth, arb, &RoutingParams::cheapest()).unwrap();
+            path.hops.iter().map(|hop| hop.alternatives).collect::<Vec<_>>()
+        };
+        assert_eq!(alternatives(RoutingEngine::new(Arc::clone(&graph), 3)), [Some(1), Some(0)]);
+        // Excluded bridges aren't an alternative
+        assert_eq!(alternatives(RoutingEngine::new(Arc::clone(&graph), 3).with_excluded_bridges(["across"])), [Some(0), Some(0)]);
+    }
+
+    #[test]
+    fn reachability_follows_active_fresh_edges_both_ways() {
+        // ethereum USDC -> arbitrum -> polygon -> base, and ethereum USDT -> polygon
+        let graph = Graph::new(4);
+        let eth = graph.get_or_create_asset_node("ethereum", "usdc", "USDC");
+        let usdt = graph.get_or_create_asset_node("ethereum", "usdt", "USDT");
+        let arb = graph.get_or_create_asset_node("arbitrum", "usdc", "USDC");
+        let pol = graph.get_or_create_asset_node("polygon", "usdc", "USDC");
+        let base = graph.get_or_create_asset_node("base", "usdc", "USDC");
+        let metrics = EdgeMetrics { cost: 1.0, speed: 60.0, liquidity: 1000.0, risk: 0.1 };
+        for (from, to, bridge) in [(eth, arb, "stargate"), (arb, pol, "across"), (pol, base, "hop"), (usdt, pol, "wormhole")] {
+            graph.add_edge(from, to, bridge, metrics.clone(), None, None).unwrap();
+        }
+        let sorted = |mut nodes: Vec<(NodeId, usize)>| {
+            nodes.sort_by_key(|&(node, hops)| (hops, node));
+            nodes
+        };
+
+        assert_eq!(graph.reachable_destinations(eth, 4), [(arb, 1), (pol, 2), (base, 3)]);
+        assert_eq!(graph.reachable_destinations(eth, 2), [(arb, 1), (pol, 2)]);
+        assert!(graph.reachable_destinations(base, 4).is_empty());
+        assert_eq!(graph.reachable_sources(base, 4), sorted(vec![(pol, 1), (arb, 2), (usdt, 2), (eth, 3)]));
+        assert_eq!(graph.reachable_sources(base, 4), graph.reachable_sources(base, 4));
+
+        // hop is the only way into base
+        graph.set_edge_active(pol, base, "hop", false);
+        assert_eq!(graph.reachable_destinations(eth, 4), [(arb, 1), (pol, 2)]);
+        assert!(graph.reachable_sources(base, 4).is_empty());
+        graph.set_edge_active(pol, base, "hop", true);
+
+        // Restored edges count once they're quoted again
+        let restored = Graph::from_snapshot(graph.snapshot(), 4).unwrap();
+        assert!(restored.reachable_destinations(eth, 4).is_empty());
+        restored.update_edge_metrics(eth, arb, "stargate", metrics).unwrap();
+        assert_eq!(restored.reachable_destinations(eth, 4), [(arb, 1)]);
+    }
 
     #[test]
     fn graph_creation() {
@@ -232,24 +1368,24 @@ mod tests {
 
         let stargate_eth_node_id = graph.get_or_create_exchange_node("stargate", "ethereum");
         let stargate_pol_node_id = graph.get_or_create_exchange_node("stargate", "polygon");
-        let stargate_arb_node_id = graph.get_or_create_exchange_node("stargate", "arbitrum");
-        let stargate_base_node_id = graph.get_or_create_exchange_node("stargate", "base");
+        let _stargate_arb_node_id = graph.get_or_create_exchange_node("stargate", "arbitrum");
+        let _stargate_base_node_id = graph.get_or_create_exchange_node("stargate", "base");
 
         let eth_usdc_node_id = graph.get_or_create_asset_node("ethereum", "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", "USDC");
-        let pol_usdc_node_id = graph.get_or_create_asset_node("polygon", "0x3c499c542cef5e3811e1192ce70d8cc03d5c3359", "USDC");
-        let base_usdc_node_id = graph.get_or_create_asset_node("base", "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913", "USDC");
+        let _pol_usdc_node_id = graph.get_or_create_asset_node("polygon", "0x3c499c542cef5e3811e1192ce70d8cc03d5c3359", "USDC");
+        let _base_usdc_node_id = graph.get_or_create_asset_node("base", "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913", "USDC");
         let mut edge_metrics = EdgeMetrics {
             cost: 1000.0,
             speed: 192.9,
             liquidity: 100.00,
             risk: 1.2
         };
-        graph.add_edge(stargate_eth_node_id, eth_usdc_node_id, "stargate", edge_metrics.clone(), Some(100.0), Some(1000.0));
-        graph.add_edge(stargate_pol_node_id, eth_usdc_node_id, "stargate", edge_metrics.clone(), Some(100.0), Some(1000.0));
+        graph.add_edge(stargate_eth_node_id, eth_usdc_node_id, "stargate", edge_metrics.clone(), Some(100.0), Some(1000.0)).unwrap();
+        graph.add_edge(stargate_pol_node_id, eth_usdc_node_id, "stargate", edge_metrics.clone(), Some(100.0), Some(1000.0)).unwrap();
 
         edge_metrics.cost = 1500.0;
         edge_metrics.risk = 2.2;
-        let update_res = graph.update_edge_metrics(
+        let _update_res = graph.update_edge_metrics(
             stargate_pol_node_id,
             eth_usdc_node_id,
             "stargate",
@@ -0,0 +1,221 @@
+// What must hold of a graph's storage whatever was done to it: every edge listed once from its
+// source and once into its destination, in the shards their nodes map to, between nodes the
+// graph has, with no two edges over one bridge between the same nodes and nothing stored that
+// isn't a number. See Graph::check_invariants.
+
+use serde::Serialize;
+use std::{collections::HashMap, fmt, sync::Arc};
+use thiserror::Error;
+
+use crate::graph::{EdgeShard, Nodes};
+use crate::types::NodeId;
+
+// The two places an edge is listed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeIndex {
+    // By source node
+    Outgoing,
+    // By destination node
+    Incoming,
+}
+
+impl fmt::Display for EdgeIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            EdgeIndex::Outgoing => "outgoing",
+            EdgeIndex::Incoming => "incoming",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Error, Serialize)]
+#[serde(tag = "invariant", rename_all = "snake_case")]
+pub enum InvariantViolation {
+    // Listed in one index, where it's found in `shard`, but not the other
+    #[error("edge {from:?} -> {to:?} over {bridge} is in {index} shard {shard} but missing from the other index")]
+    Unmirrored { index: EdgeIndex, shard: usize, from: NodeId, to: NodeId, bridge: String },
+
+    #[error("edge {from:?} -> {to:?} over {bridge} joins {node:?}, which is not in the graph")]
+    UnknownEndpoint { from: NodeId, to: NodeId, bridge: String, node: NodeId },
+
+    #[error("edge {from:?} -> {to:?} over {bridge} is in the graph {copies} times")]
+    DuplicateEdge { from: NodeId, to: NodeId, bridge: String, copies: usize },
+
+    // A node's edge list kept in a shard other than the one the node maps to, where lookups
+    // never find it
+    #[error("{index} edges of {node:?} are in shard {shard} rather than shard {expected}")]
+    MisplacedEntry { index: EdgeIndex, node: NodeId, shard: usize, expected: usize },
+
+    // An edge listed under a node it doesn't start at (outgoing) or end at (incoming)
+    #[error("edge {from:?} -> {to:?} over {bridge} is among the {index} edges of {node:?}")]
+    MisfiledEdge { index: EdgeIndex, node: NodeId, from: NodeId, to: NodeId, bridge: String },
+
+    #[error("edge {from:?} -> {to:?} over {bridge} has {name} {value}")]
+    NonFiniteValue { from: NodeId, to: NodeId, bridge: String, name: &'static str, value: f64 },
+}
+
+// One pass over both indexes, then one over every edge
+pub(crate) fn check(nodes: &Nodes, outgoing: &[Arc<EdgeShard>], incoming: &[Arc<EdgeShard>]) -> Vec<InvariantViolation> {
+    let mut violations = Vec::new();
+    let shard_of = |node: NodeId| (node.0 as usize) & (outgoing.len() - 1);
+    let mut copies: HashMap<(NodeId, NodeId, String), usize> = HashMap::new();
+
+    for (index, shards, other) in [(EdgeIndex::Outgoing, outgoing, incoming), (EdgeIndex::Incoming, incoming, outgoing)] {
+        for (shard, entries) in shards.iter().enumerate() {
+            for entry in entries.iter() {
+                let node = *entry.key();
+                if shard_of(node) != shard {
+                    violations.push(InvariantViolation::MisplacedEntry { index, node, shard, expected: shard_of(node) });
+                }
+                for edge in entry.value() {
+                    let (filed_under, counterpart) = match index {
+                        EdgeIndex::Outgoing => (edge.from, edge.to),
+                        EdgeIndex::Incoming => (edge.to, edge.from),
+                    };
+                    if filed_under != node {
+                        violations.push(InvariantViolation::MisfiledEdge { index, node, from: edge.from, to: edge.to, bridge: edge.bridge_name.to_string() });
+                    }
+                    // The same edge, not just one with the same ends, so updates reach both
+                    let mirrored = other[shard_of(counterpart)]
+                        .get(&counterpart)
+                        .is_some_and(|listed| listed.value().iter().any(|other| Arc::ptr_eq(other, edge)));
+                    if !mirrored {
+                        violations.push(InvariantViolation::Unmirrored { index, shard, from: edge.from, to: edge.to, bridge: edge.bridge_name.to_string() });
+                    }
+                    if index == EdgeIndex::Incoming {
+                        continue;
+                    }
+
+                    for endpoint in [edge.from, edge.to] {
+                        if !nodes.contains_key(&endpoint) {
+                            violations.push(InvariantViolation::UnknownEndpoint { from: edge.from, to: edge.to, bridge: edge.bridge_name.to_string(), node: endpoint });
+                        }
+                    }
+                    *copies.entry((edge.from, edge.to, edge.bridge_name.to_string())).or_default() += 1;
+                    let metrics = edge.get_metrics();
+                    let values = [
+                        ("cost", Some(metrics.cost)),
+                        ("speed", Some(metrics.speed)),
+                        ("liquidity", Some(metrics.liquidity)),
+                        ("risk", Some(metrics.risk)),
+                        ("min_amount", edge.min_amount()),
+                        ("max_amount", edge.max_amount()),
+                    ];
+                    for (name, value) in values {
+                        if let Some(value) = value.filter(|value| !value.is_finite()) {
+                            violations.push(InvariantViolation::NonFiniteValue { from: edge.from, to: edge.to, bridge: edge.bridge_name.to_string(), name, value });
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let mut duplicates: Vec<_> = copies.into_iter().filter(|(_, copies)| *copies > 1).collect();
+    duplicates.sort();
+    violations.extend(duplicates.into_iter().map(|((from, to, bridge), copies)| InvariantViolation::DuplicateEdge { from, to, bridge, copies }));
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Graph;
+    use crate::testutil::layered_graph;
+    use crate::types::{AmountLimits, EdgeMetrics};
+
+    fn metrics() -> EdgeMetrics {
+        EdgeMetrics { cost: 1.0, speed: 60.0, liquidity: 1_000_000.0, risk: 0.1 }
+    }
+
+    // One edge from ethereum to polygon USDC
+    fn graph() -> (Graph, NodeId, NodeId) {
+        let graph = Graph::new(4);
+        let eth = graph.get_or_create_asset_node("ethereum", "usdc", "USDC");
+        let pol = graph.get_or_create_asset_node("polygon", "usdc", "USDC");
+        graph.add_edge(eth, pol, "stargate", metrics(), None, Some(50_000.0)).unwrap();
+        (graph, eth, pol)
+    }
+
+    fn shard_of(graph: &Graph, node: NodeId) -> usize {
+        (node.0 as usize) & (graph.storage().1.len() - 1)
+    }
+
+    #[test]
+    fn graphs_built_through_their_methods_hold_up() {
+        let layered = layered_graph(7, 5, 8, 120);
+        assert_eq!(layered.graph.check_invariants(), []);
+
+        let (graph, eth, pol) = graph();
+        graph.add_edge(pol, eth, "stargate", metrics(), None, None).unwrap();
+        graph.set_edge_active(eth, pol, "stargate", false);
+        graph.update_edge_metrics(pol, eth, "stargate", metrics()).unwrap();
+        let restored = Graph::from_snapshot(graph.snapshot(), 16).unwrap();
+        assert_eq!(graph.check_invariants(), []);
+        assert_eq!(restored.check_invariants(), []);
+    }
+
+    #[test]
+    fn edges_missing_from_one_index_or_joining_unknown_nodes_are_reported() {
+        let (graph, eth, pol) = graph();
+        let (nodes, _, incoming) = graph.storage();
+        incoming[shard_of(&graph, pol)].get_mut(&pol).unwrap().clear();
+        nodes.remove(&pol);
+
+        let violations = graph.check_invariants();
+        let bridge = "stargate".to_string();
+        assert!(violations.contains(&InvariantViolation::Unmirrored { index: EdgeIndex::Outgoing, shard: shard_of(&graph, eth), from: eth, to: pol, bridge: bridge.clone() }), "{:?}", violations);
+        assert!(violations.contains(&InvariantViolation::UnknownEndpoint { from: eth, to: pol, bridge, node: pol }), "{:?}", violations);
+        assert_eq!(violations.len(), 2);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "check-invariants", ignore = "add_edge panics on the duplicates it makes")]
+    fn duplicate_triples_are_reported_once_with_their_count() {
+        let (graph, eth, pol) = graph();
+        // A merge that adds rather than updates
+        graph.add_edge(eth, pol, "stargate", metrics(), None, None).unwrap();
+        graph.add_edge(eth, pol, "stargate", metrics(), None, None).unwrap();
+        graph.add_edge(eth, pol, "across", metrics(), None, None).unwrap();
+
+        assert_eq!(
+            graph.check_invariants(),
+            [InvariantViolation::DuplicateEdge { from: eth, to: pol, bridge: "stargate".to_string(), copies: 3 }]
+        );
+    }
+
+    #[test]
+    fn edge_lists_in_the_wrong_shard_or_under_the_wrong_node_are_reported() {
+        let (graph, eth, _) = graph();
+        let (_, outgoing, _) = graph.storage();
+        let expected = shard_of(&graph, eth);
+        let wrong = (expected + 1) % outgoing.len();
+        let (_, edges) = outgoing[expected].remove(&eth).unwrap();
+        outgoing[wrong].insert(eth, edges);
+        let violations = graph.check_invariants();
+        assert!(violations.contains(&InvariantViolation::MisplacedEntry { index: EdgeIndex::Outgoing, node: eth, shard: wrong, expected }), "{:?}", violations);
+        // Lookups from the destination's side no longer find it
+        assert!(violations.iter().any(|violation| matches!(violation, InvariantViolation::Unmirrored { index: EdgeIndex::Incoming, .. })), "{:?}", violations);
+
+        let (graph, eth, pol) = self::graph();
+        let (_, outgoing, _) = graph.storage();
+        let edge = Arc::clone(&outgoing[shard_of(&graph, eth)].get(&eth).unwrap()[0]);
+        outgoing[shard_of(&graph, pol)].entry(pol).or_default().push(edge);
+        let violations = graph.check_invariants();
+        assert!(violations.contains(&InvariantViolation::MisfiledEdge { index: EdgeIndex::Outgoing, node: pol, from: eth, to: pol, bridge: "stargate".to_string() }), "{:?}", violations);
+    }
+
+    #[test]
+    fn non_finite_values_are_reported_by_name() {
+        let (graph, eth, pol) = graph();
+        *graph.get_outgoing_edges(eth)[0].limits.write().unwrap() = AmountLimits { min: Some(f64::NAN), max: Some(f64::INFINITY) };
+
+        let violations = graph.check_invariants();
+        assert!(matches!(&violations[..], [
+            InvariantViolation::NonFiniteValue { name: "min_amount", value: min, .. },
+            InvariantViolation::NonFiniteValue { from, to, name: "max_amount", value: f64::INFINITY, .. },
+        ] if min.is_nan() && (*from, *to) == (eth, pol)), "{:?}", violations);
+        assert!(violations[1].to_string().ends_with("over stargate has max_amount inf"), "{}", violations[1]);
+    }
+}
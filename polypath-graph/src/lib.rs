@@ -1,7 +1,47 @@
 mod types;
+mod confidence;
+mod diff;
+mod directory;
+pub mod export;
+mod error;
 mod graph;
+mod invariants;
+mod objectives;
+mod pinning;
+mod plan;
+mod router;
 mod routing;
 mod scoring;
+mod slippage;
+mod view;
+#[cfg(any(test, feature = "testutil"))]
+pub mod testutil;
+#[cfg(test)]
+mod proptests;
+
+pub use crate::types::*;
+pub use crate::confidence::{ConfidenceCombinator, ConfidenceModel, DEFAULT_VALIDITY_WINDOW, SourceQuality};
+pub use crate::diff::{ChangeSeverity, DEFAULT_SHIFT_THRESHOLD, HopChange, MetricDelta, RouteDiff, compare_routes, compare_routes_with};
+pub use crate::directory::{NodeDirectory, NodeDirectoryEntry, NodeKind};
+pub use crate::export::{ExportError, ExportFormat, ExportedRoute};
+pub use crate::error::{GraphError, ObjectiveError, PlanError, RouteError, ScoringError, SlippageError};
+pub use crate::graph::{CompactionOptions, CompactionReport, Graph};
+pub use crate::invariants::{EdgeIndex, InvariantViolation};
+pub use crate::objectives::{Direction, Objective, ObjectiveRegistry, Unscored};
+pub use crate::pinning::{MemoryPinStore, PinStore, PinnedEdge, PinnedRoute, PinnedScore};
+pub use crate::plan::{BridgeStep, ExecutionPlan, ExecutionStep, PlanOptions};
+pub use crate::router::{
+    BalanceChecker, ROUTE_SEARCH_STAGE, ReachableToken, RouteConstraints, RouteObserver, RouteOptions, RoutePriority, RouteUpdate, Router, SCORING_STAGE, StageTimer,
+    UpdateReason, WatchSettings,
+};
+pub use crate::routing::{CandidatePaths, MAX_SEARCH_LABELS, PruningRule, RoutingEngine, SearchStats};
+pub use crate::slippage::{DEFAULT_MAX_UTILIZATION, SlippageModel};
+pub use crate::view::{GraphRead, GraphStats, GraphView};
+pub use crate::scoring::{
+    BatchRanking, DropReason, ExplainedPath, Explainer, Explanation, MinMax, NormalizationStats,
+    NormalizedMetrics, NormalizedPath, Optimizer, RankingDiagnostics, RankingOutcome, Ranker,
+    ScoreNormalizer, ScoredPath, ScoringEngine, ScoringStrategy, TieBreaker, DEFAULT_TIE_EPSILON,
+};
 
 pub fn add(left: u64, right: u64) -> u64 {
     left + right
@@ -0,0 +1,213 @@
+// What routes are scored on. Each objective takes one number from a path and says which way is
+// better; ScoreNormalizer scales it to 0-1 (1 best) across a candidate set, and the optimizers
+// and the explainer go over the objectives of a registry in the order they were registered.
+// Downstream crates add their own with ScoringEngine::with_objectives.
+
+use serde::Serialize;
+
+use crate::error::ObjectiveError;
+use crate::scoring::MinMax;
+use crate::types::{ParamError, Path, RoutingParams, WEIGHT_ALIASES};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Minimize,
+    Maximize,
+}
+
+// How a path an objective has no value for scores on it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unscored {
+    // As well as a path can
+    Best,
+    // What it scored on another objective, registered before this one
+    Like(&'static str),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Objective {
+    pub name: &'static str,
+    pub extract: fn(&Path) -> f64,
+    pub direction: Direction,
+    // Its weight when RoutingParams don't give one
+    pub default_weight: f64,
+    // Whether a path has a value for it, see `only_when`
+    pub known: fn(&Path) -> bool,
+    pub unscored: Unscored,
+}
+
+impl Objective {
+    pub fn new(name: &'static str, extract: fn(&Path) -> f64, direction: Direction, default_weight: f64) -> Self {
+        Self { name, extract, direction, default_weight, known: |_| true, unscored: Unscored::Best }
+    }
+
+    // Only paths `known` holds for are scaled on their value; the others score as `unscored` says
+    pub fn only_when(mut self, known: fn(&Path) -> bool, unscored: Unscored) -> Self {
+        self.known = known;
+        self.unscored = unscored;
+        self
+    }
+
+    // The path's value, None when it has none
+    pub fn value(&self, path: &Path) -> Option<f64> {
+        (self.known)(path).then(|| (self.extract)(path))
+    }
+
+    // 0-1 score of `value` within `range`, 1 for the best
+    pub fn score(&self, value: f64, range: MinMax) -> f64 {
+        match self.direction {
+            Direction::Minimize => 1.0 - range.position(value),
+            Direction::Maximize => range.position(value),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ObjectiveRegistry {
+    objectives: Vec<Objective>,
+}
+
+impl Default for ObjectiveRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl ObjectiveRegistry {
+    // Cost, speed, liquidity, risk, output and redundancy, weighed by RoutingParams' alpha to
+    // epsilon, with its defaults as their default weights, then confidence. Routes used to be
+    // scored up for a higher total_risk and down for more liquidity; both now go the way gamma
+    // and delta say, see default_weights_rank_fixed_paths in scoring.
+    pub fn builtin() -> Self {
+        let defaults = RoutingParams::default();
+        let default = |name| defaults.weight(name).unwrap_or(0.0);
+        Self {
+            objectives: vec![
+                Objective::new("cost", |path| path.total_cost, Direction::Minimize, default("cost")),
+                Objective::new("speed", |path| path.total_time, Direction::Minimize, default("speed")),
+                Objective::new("liquidity", Path::effective_min_liquidity, Direction::Maximize, default("liquidity")),
+                Objective::new("risk", |path| path.total_risk, Direction::Minimize, default("risk")),
+                // Paths without an estimated output are scored on cost instead
+                Objective::new("output", |path| path.estimated_output.unwrap_or(0.0), Direction::Maximize, default("output"))
+                    .only_when(|path| path.estimated_output.is_some(), Unscored::Like("cost")),
+                Objective::new("redundancy", |path| path.redundancy_score().unwrap_or(0.0), Direction::Maximize, default("redundancy"))
+                    .only_when(|path| path.redundancy_score().is_some(), Unscored::Best),
+                // Unweighed unless asked for, see ConfidenceModel
+                Objective::new("confidence", |path| path.confidence, Direction::Maximize, default("confidence")),
+            ],
+        }
+    }
+
+    pub fn with_objective(mut self, objective: Objective) -> Result<Self, ObjectiveError> {
+        self.register(objective)?;
+        Ok(self)
+    }
+
+    pub fn register(&mut self, objective: Objective) -> Result<(), ObjectiveError> {
+        let name = objective.name;
+        if self.get(name).is_some() || WEIGHT_ALIASES.iter().any(|(_, field)| *field == name) {
+            return Err(ObjectiveError::Duplicate(name.to_string()));
+        }
+        if !objective.default_weight.is_finite() || objective.default_weight < 0.0 {
+            return Err(ObjectiveError::InvalidWeight { name: name.to_string(), weight: objective.default_weight });
+        }
+        if let Unscored::Like(like) = objective.unscored {
+            if self.get(like).is_none() {
+                return Err(ObjectiveError::UnknownFallback { name: name.to_string(), like: like.to_string() });
+            }
+        }
+        self.objectives.push(objective);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Objective> {
+        self.objectives.iter().find(|objective| objective.name == name)
+    }
+
+    pub(crate) fn index_of(&self, name: &str) -> Option<usize> {
+        self.objectives.iter().position(|objective| objective.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Objective> {
+        self.objectives.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.objectives.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objectives.is_empty()
+    }
+
+    // Rejects params weighing an objective the registry doesn't have
+    pub fn check(&self, params: &RoutingParams) -> Result<(), ParamError> {
+        match params.weights.keys().find(|name| self.get(name).is_none()) {
+            Some(name) => Err(ParamError::UnknownObjective(name.clone())),
+            None => Ok(()),
+        }
+    }
+
+    // Each objective's weight from `params`, in order, scaled to sum to 1. Like
+    // RoutingParams::normalized, weights that can't be scaled fall back to the balanced preset.
+    pub fn weights(&self, params: &RoutingParams) -> Vec<f64> {
+        let raw = |params: &RoutingParams| -> Vec<f64> {
+            self.objectives.iter().map(|objective| params.weight(objective.name).unwrap_or(objective.default_weight)).collect()
+        };
+        let mut weights = raw(params);
+        let sum: f64 = weights.iter().sum();
+        if params.validate().is_err() || weights.iter().any(|weight| !weight.is_finite() || *weight < 0.0) || sum <= 0.0 {
+            weights = raw(&RoutingParams::balanced());
+        }
+        let sum: f64 = weights.iter().sum();
+        weights.iter().map(|weight| weight / sum).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hop_count() -> Objective {
+        Objective::new("hop_count", |path| path.hops.len() as f64, Direction::Minimize, 0.0)
+    }
+
+    #[test]
+    fn objectives_register_once_under_unreserved_names() {
+        let mut registry = ObjectiveRegistry::builtin();
+        let names: Vec<&str> = registry.iter().map(|objective| objective.name).collect();
+        assert_eq!(names, ["cost", "speed", "liquidity", "risk", "output", "redundancy", "confidence"]);
+
+        registry.register(hop_count()).unwrap();
+        assert_eq!(registry.len(), 8);
+        assert_eq!(registry.register(hop_count()), Err(ObjectiveError::Duplicate("hop_count".to_string())));
+        assert_eq!(registry.register(Objective { name: "gamma", ..hop_count() }), Err(ObjectiveError::Duplicate("gamma".to_string())));
+        assert!(matches!(registry.register(Objective { name: "gas", default_weight: -1.0, ..hop_count() }), Err(ObjectiveError::InvalidWeight { .. })));
+        let fallback = Objective { name: "gas", ..hop_count() }.only_when(|_| false, Unscored::Like("fees"));
+        assert_eq!(registry.register(fallback), Err(ObjectiveError::UnknownFallback { name: "gas".to_string(), like: "fees".to_string() }));
+    }
+
+    #[test]
+    fn weights_come_from_params_then_defaults() {
+        let registry = ObjectiveRegistry::builtin().with_objective(Objective { default_weight: 1.0, ..hop_count() }).unwrap();
+
+        // cheapest's cost weight and hop_count's default share the sum
+        assert_eq!(registry.weights(&RoutingParams::cheapest()), [0.5, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.5]);
+        assert_eq!(registry.weights(&RoutingParams::cheapest().with_weight("hop_count", 3.0)), [0.25, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.75]);
+        // Unusable weights fall back to the balanced preset, with hop_count at its default
+        let fallback = registry.weights(&RoutingParams::cheapest().with_weight("alpha", -1.0));
+        assert_eq!(fallback, registry.weights(&RoutingParams::balanced()));
+        assert!((fallback.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+
+        // Without custom objectives the weights are the normalized params'
+        let balanced = RoutingParams::balanced().normalized();
+        assert_eq!(ObjectiveRegistry::builtin().weights(&RoutingParams::balanced()), [balanced.alpha, balanced.beta, balanced.gamma, balanced.delta, balanced.omega, balanced.epsilon, 0.0]);
+
+        assert_eq!(registry.check(&RoutingParams::cheapest().with_weight("hop_count", 1.0)), Ok(()));
+        assert_eq!(
+            ObjectiveRegistry::builtin().check(&RoutingParams::cheapest().with_weight("hop_count", 1.0)),
+            Err(ParamError::UnknownObjective("hop_count".to_string()))
+        );
+    }
+}
@@ -0,0 +1,134 @@
+// Route stickiness: the route a caller went with for an intent is pinned, and later queries for
+// the same intent favour it, so a repeat transfer keeps its bridges until a new route is better
+// by more than the configured bonus. See Router::with_stickiness.
+
+use crate::scoring::{summarize, with_notes, ExplainedPath};
+use crate::types::*;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::RwLock};
+
+// One edge of a pinned route, by its nodes and bridge label
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PinnedEdge {
+    pub from: NodeId,
+    pub to: NodeId,
+    pub bridge: String,
+}
+
+impl From<&Hop> for PinnedEdge {
+    fn from(hop: &Hop) -> Self {
+        Self { from: hop.from, to: hop.to, bridge: hop.bridge_name.to_string() }
+    }
+}
+
+// The edges of the route last confirmed for an intent, see Router::confirm_selection
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PinnedRoute {
+    pub edges: Vec<PinnedEdge>,
+    // Unix seconds
+    pub confirmed_at: u64,
+}
+
+impl PinnedRoute {
+    pub fn new(path: &Path, confirmed_at: u64) -> Self {
+        Self { edges: path.hops.iter().map(PinnedEdge::from).collect(), confirmed_at }
+    }
+
+    // Hops of `path` on one of the pinned edges
+    pub fn shared_hops(&self, path: &Path) -> usize {
+        path.hops.iter().filter(|hop| self.edges.contains(&PinnedEdge::from(*hop))).count()
+    }
+}
+
+// Where the pinned routes are kept, by intent hash (RouteIntent::intent_hash). Called on the
+// querying thread.
+pub trait PinStore: Send + Sync {
+    fn load(&self, intent_hash: &str) -> Option<PinnedRoute>;
+
+    fn save(&self, intent_hash: &str, route: &PinnedRoute);
+}
+
+// Pins for as long as the process runs
+#[derive(Debug, Default)]
+pub struct MemoryPinStore {
+    routes: RwLock<HashMap<String, PinnedRoute>>,
+}
+
+impl MemoryPinStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl PinStore for MemoryPinStore {
+    fn load(&self, intent_hash: &str) -> Option<PinnedRoute> {
+        self.routes.read().unwrap().get(intent_hash).cloned()
+    }
+
+    fn save(&self, intent_hash: &str, route: &PinnedRoute) {
+        self.routes.write().unwrap().insert(intent_hash.to_string(), route.clone());
+    }
+}
+
+// How a ranked path stands against the intent's pinned route
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PinnedScore {
+    // Hops on the pinned route's edges, out of the pinned route's hops
+    pub shared_hops: usize,
+    pub pinned_hops: usize,
+    // Added to the path's final score for the hops it shares
+    pub bonus: f64,
+    // How far the path's own final score is below that of the best scoring path
+    pub gap_to_best: f64,
+}
+
+// Gives the paths sharing edges with `pinned` their share of `bonus` and puts the one ahead with
+// it first, when that isn't the best scoring path already. Ranks are renumbered and the
+// summaries of the pinned choice and the best scoring path say how far apart they are.
+pub(crate) fn apply_stickiness(routes: &mut Vec<ExplainedPath>, pinned: &PinnedRoute, bonus: f64) {
+    let Some(best_score) = routes.first().map(|route| route.ranked.score_breakdown.final_score) else {
+        return;
+    };
+    let pinned_hops = pinned.edges.len().max(1);
+    for route in routes.iter_mut() {
+        let shared_hops = pinned.shared_hops(&route.ranked.path);
+        if shared_hops > 0 {
+            route.pinned = Some(PinnedScore {
+                shared_hops,
+                pinned_hops: pinned.edges.len(),
+                bonus: bonus * shared_hops as f64 / pinned_hops as f64,
+                gap_to_best: best_score - route.ranked.score_breakdown.final_score,
+            });
+        }
+    }
+
+    let with_bonus = |route: &ExplainedPath| route.ranked.score_breakdown.final_score + route.pinned.as_ref().map_or(0.0, |pinned| pinned.bonus);
+    let mut sticky = 0;
+    for (index, route) in routes.iter().enumerate().skip(1) {
+        if with_bonus(route) > with_bonus(&routes[sticky]) {
+            sticky = index;
+        }
+    }
+    if sticky == 0 {
+        return;
+    }
+
+    let chosen = routes.remove(sticky);
+    routes.insert(0, chosen);
+    for (index, route) in routes.iter_mut().enumerate() {
+        route.ranked.rank = index + 1;
+    }
+    let gap = routes[0].pinned.as_ref().map_or(0.0, |pinned| pinned.gap_to_best);
+    let (shared, of) = routes[0].pinned.as_ref().map_or((0, 0), |pinned| (pinned.shared_hops, pinned.pinned_hops));
+    let kept = format!(
+        "kept on the last confirmed route ({} of {} hops), {:.4} below the best score; {}",
+        shared,
+        of,
+        gap,
+        summarize(&routes[0].ranked.path, &routes[1].ranked.path)
+    );
+    routes[0].summary = with_notes(kept, &routes[0].ranked.path);
+    // What was the best scoring path is now second
+    let best = format!("best for the selected weights, {:.4} above the pinned route", gap);
+    routes[1].summary = with_notes(best, &routes[1].ranked.path);
+}
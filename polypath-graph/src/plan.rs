@@ -0,0 +1,416 @@
+// Turns a chosen route into the ordered transfers an execution service carries out
+
+use crate::error::PlanError;
+use crate::graph::Graph;
+use crate::types::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlanOptions {
+    // How far the graph may have moved on since the route was found. The version moves
+    // with every node or edge write, so a refresh of a large graph moves it by hundreds.
+    pub max_version_lag: u64,
+    // Fraction of a step's expected output it may fall short by
+    pub slippage: f64,
+}
+
+impl Default for PlanOptions {
+    fn default() -> Self {
+        Self {
+            max_version_lag: 1_000,
+            slippage: 0.005,
+        }
+    }
+}
+
+// One thing to do for the plan, in human units of its tokens
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ExecutionStep {
+    // Letting `spender` move `amount` of `token` on `chain`, ahead of the bridge step after it.
+    // assumed_required when the allowance couldn't be checked.
+    Approve {
+        chain: String,
+        token: String,
+        spender: String,
+        amount: f64,
+        assumed_required: bool,
+    },
+    Bridge(BridgeStep),
+}
+
+impl ExecutionStep {
+    pub fn as_bridge(&self) -> Option<&BridgeStep> {
+        match self {
+            ExecutionStep::Bridge(step) => Some(step),
+            ExecutionStep::Approve { .. } => None,
+        }
+    }
+}
+
+// One transfer of the plan
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BridgeStep {
+    // Position in the plan's steps
+    pub step_index: usize,
+    pub bridge: String,
+    pub src_chain: String,
+    pub dst_chain: String,
+    pub src_token_address: String,
+    pub dst_token_address: String,
+    pub amount_in: f64,
+    pub min_amount_out: f64,
+    pub quote_reference: Option<String>,
+    // Unix seconds
+    pub expires_at: Option<u64>,
+    // Steps to carry out first: the one whose output this one spends and its approval
+    pub depends_on: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExecutionPlan {
+    // Graph version the route was found at
+    pub graph_version: u64,
+    // Unix seconds
+    pub created_at: u64,
+    pub amount_in: f64,
+    // min_amount_out of the last step
+    pub min_amount_out: f64,
+    // Earliest expiry of the steps' quotes
+    pub expires_at: Option<u64>,
+    pub steps: Vec<ExecutionStep>,
+}
+
+impl ExecutionPlan {
+    // See from_path_with; uses the default options and the current time
+    pub fn from_path(ranked: &RankedPath, graph: &Graph, intent: &RouteIntent) -> Result<Self, PlanError> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        Self::from_path_with(ranked, graph, intent, &PlanOptions::default(), now)
+    }
+
+    // Steps for sending intent.amount along the route at unix time `now`. Each step takes
+    // what the previous one is expected to deliver, less its fees (the hop's cost) and the
+    // hop's slippage_pct when the router set one. Fails
+    // when the route doesn't match the intent, was found too many graph versions ago, or a
+    // hop's edge is gone or its quote expired.
+    pub fn from_path_with(
+        ranked: &RankedPath,
+        graph: &Graph,
+        intent: &RouteIntent,
+        opts: &PlanOptions,
+        now: u64,
+    ) -> Result<Self, PlanError> {
+        let path = &ranked.path;
+        let (Some(first), Some(last)) = (path.hops.first(), path.hops.last()) else {
+            return Err(PlanError::EmptyPath);
+        };
+        if !intent.amount.is_finite() || intent.amount <= 0.0 {
+            return Err(PlanError::InvalidAmount(intent.amount));
+        }
+
+        let (from_chain, from_address, from_symbol) = asset(graph, first.from)?;
+        let (to_chain, to_address, to_symbol) = asset(graph, last.to)?;
+        let matches = |chain: &str, address: &str, symbol: &str, want_chain: &str, want_token: &str| {
+            chain.eq_ignore_ascii_case(want_chain)
+                && (address.eq_ignore_ascii_case(want_token) || symbol.eq_ignore_ascii_case(want_token))
+        };
+        if !matches(&from_chain, &from_address, &from_symbol, &intent.from_chain, &intent.from_token)
+            || !matches(&to_chain, &to_address, &to_symbol, &intent.to_chain, &intent.to_token)
+        {
+            return Err(PlanError::IntentMismatch {
+                from: format!("{} {}", from_chain, from_symbol),
+                to: format!("{} {}", to_chain, to_symbol),
+                intent_from: format!("{} {}", intent.from_chain, intent.from_token),
+                intent_to: format!("{} {}", intent.to_chain, intent.to_token),
+            });
+        }
+
+        let graph_version = graph.version();
+        let lag = graph_version.saturating_sub(path.graph_version);
+        if lag > opts.max_version_lag {
+            return Err(PlanError::StalePath { path_version: path.graph_version, graph_version, lag });
+        }
+
+        let mut steps = Vec::with_capacity(path.hops.len());
+        let mut amount_in = intent.amount;
+        for (step_index, hop) in path.hops.iter().enumerate() {
+            let bridge = hop.bridge_name.to_string();
+            let edge = graph
+                .get_outgoing_edges(hop.from)
+                .into_iter()
+                .find(|edge| edge.to == hop.to && edge.bridge_name == hop.bridge_name)
+                .ok_or_else(|| PlanError::EdgeGone { step: step_index, bridge: bridge.clone() })?;
+            if let Some(quote) = hop.quote.as_ref().filter(|quote| quote.is_expired_at(now)) {
+                return Err(PlanError::QuoteExpired { step: step_index, bridge, expired_at: quote.valid_until.unwrap_or_default() });
+            }
+            if !edge.amount_limits().allows(amount_in) {
+                return Err(PlanError::AmountOutOfRange { step: step_index, bridge, amount_in });
+            }
+            let fees = hop.fees_in_source();
+            let amount_out = (amount_in - fees) * (1.0 - hop.slippage_pct.unwrap_or(0.0) / 100.0);
+            if amount_out <= 0.0 {
+                return Err(PlanError::FeesExceedAmount { step: step_index, bridge, amount_in, cost: fees });
+            }
+
+            let (src_chain, src_token_address, _) = asset(graph, hop.from)?;
+            let (dst_chain, dst_token_address, _) = asset(graph, hop.to)?;
+            steps.push(ExecutionStep::Bridge(BridgeStep {
+                step_index,
+                bridge,
+                src_chain,
+                dst_chain,
+                src_token_address,
+                dst_token_address,
+                amount_in,
+                min_amount_out: amount_out * (1.0 - opts.slippage),
+                quote_reference: hop.quote.as_ref().map(|quote| quote.reference.clone()),
+                expires_at: hop.quote.as_ref().and_then(|quote| quote.valid_until),
+                depends_on: step_index.checked_sub(1).into_iter().collect(),
+            }));
+            amount_in = amount_out;
+        }
+
+        let mut plan = Self {
+            graph_version: path.graph_version,
+            created_at: now,
+            amount_in: intent.amount,
+            min_amount_out: 0.0,
+            expires_at: None,
+            steps,
+        };
+        plan.min_amount_out = plan.bridge_steps().last().map_or(0.0, |step| step.min_amount_out);
+        plan.expires_at = plan.bridge_steps().filter_map(|step| step.expires_at).min();
+        Ok(plan)
+    }
+
+    pub fn bridge_steps(&self) -> impl Iterator<Item = &BridgeStep> {
+        self.steps.iter().filter_map(ExecutionStep::as_bridge)
+    }
+
+    // Puts each approval right before the bridge step whose step_index it's given with, then
+    // renumbers the steps; bridge steps come to depend on their approvals too
+    pub fn insert_approvals(&mut self, approvals: Vec<(usize, ExecutionStep)>) {
+        let mut approvals = approvals.into_iter().fold(HashMap::<usize, Vec<ExecutionStep>>::new(), |mut by_step, (index, step)| {
+            by_step.entry(index).or_default().push(step);
+            by_step
+        });
+        // Old position -> new one
+        let mut moved = HashMap::new();
+        let mut steps = Vec::with_capacity(self.steps.len() + approvals.len());
+        for (old_index, step) in std::mem::take(&mut self.steps).into_iter().enumerate() {
+            let ExecutionStep::Bridge(mut bridge) = step else {
+                moved.insert(old_index, steps.len());
+                steps.push(step);
+                continue;
+            };
+            let approved_from = steps.len();
+            steps.extend(approvals.remove(&old_index).unwrap_or_default());
+            moved.insert(old_index, steps.len());
+            bridge.step_index = steps.len();
+            bridge.depends_on = bridge
+                .depends_on
+                .iter()
+                .filter_map(|index| moved.get(index).copied())
+                .chain(approved_from..bridge.step_index)
+                .collect();
+            steps.push(ExecutionStep::Bridge(bridge));
+        }
+        self.steps = steps;
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+// (chain, token address, symbol) of an asset node
+fn asset(graph: &Graph, id: NodeId) -> Result<(String, String, String), PlanError> {
+    match graph.get_node(id).as_deref().map(|node| &node.node_type) {
+        Some(NodeType::Asset { chain, token_address, token_symbol }) => {
+            Ok((chain.clone(), token_address.clone(), token_symbol.clone()))
+        }
+        _ => Err(PlanError::NotAnAsset(id)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::{RouteOptions, Router};
+    use crate::slippage::SlippageModel;
+    use std::sync::Arc;
+
+    const NOW: u64 = 1_750_000_000;
+
+    // USDC on four chains, bridged in a line for fees of 1, 2 and 3 and taking at least 10;
+    // the quotes lapse at NOW + 60, 61 and 62
+    fn graph() -> Arc<Graph> {
+        let graph = Graph::new(16);
+        let chains = ["ethereum", "arbitrum", "base", "polygon"];
+        let nodes: Vec<NodeId> = chains
+            .iter()
+            .enumerate()
+            .map(|(i, chain)| graph.get_or_create_asset_node(chain, &format!("0x{:040x}", i + 1), "USDC"))
+            .collect();
+        for (i, pair) in nodes.windows(2).enumerate() {
+            let metrics = EdgeMetrics { cost: 1.0 + i as f64, speed: 60.0, liquidity: 1_000_000.0, risk: 0.1 };
+            graph.add_edge(pair[0], pair[1], "stargate", metrics, Some(10.0), None).unwrap();
+            let quote = EdgeQuote { reference: format!("q{}", i), quoted_at: NOW - 10, valid_until: Some(NOW + 60 + i as u64), fees: Vec::new(), speed_breakdown: None, source: None, sources: Vec::new(), cost_in_source: None };
+            assert!(graph.set_edge_quote(pair[0], pair[1], "stargate", Some(quote)));
+        }
+        Arc::new(graph)
+    }
+
+    fn intent(from_chain: &str, to_chain: &str, amount: f64) -> RouteIntent {
+        RouteIntent {
+            from_chain: from_chain.to_string(),
+            from_token: "USDC".to_string(),
+            to_chain: to_chain.to_string(),
+            to_token: "USDC".to_string(),
+            amount,
+            preference: Some("cheapest".to_string()),
+            src_address: None,
+        }
+    }
+
+    // Without slippage, so the amounts only lose the fees
+    fn best(graph: &Arc<Graph>, intent: &RouteIntent) -> RankedPath {
+        let router = Router::new(Arc::clone(graph)).with_slippage(SlippageModel::Linear { impact_per_utilization: 0.0 }, 1.0);
+        let routes = router.best_routes(intent, &RouteOptions::default()).unwrap();
+        routes.into_iter().next().unwrap().ranked
+    }
+
+    fn plan(graph: &Graph, ranked: &RankedPath, intent: &RouteIntent, now: u64) -> Result<ExecutionPlan, PlanError> {
+        ExecutionPlan::from_path_with(ranked, graph, intent, &PlanOptions::default(), now)
+    }
+
+    #[test]
+    fn one_hop_plan() {
+        let graph = graph();
+        let intent = intent("ethereum", "arbitrum", 100.0);
+        let plan = plan(&graph, &best(&graph, &intent), &intent, NOW).unwrap();
+
+        assert_eq!(plan.steps, vec![ExecutionStep::Bridge(BridgeStep {
+            step_index: 0,
+            bridge: "stargate".to_string(),
+            src_chain: "ethereum".to_string(),
+            dst_chain: "arbitrum".to_string(),
+            src_token_address: format!("0x{:040x}", 1),
+            dst_token_address: format!("0x{:040x}", 2),
+            amount_in: 100.0,
+            min_amount_out: 99.0 * 0.995,
+            quote_reference: Some("q0".to_string()),
+            expires_at: Some(NOW + 60),
+            depends_on: vec![],
+        })]);
+        assert_eq!((plan.amount_in, plan.min_amount_out, plan.expires_at, plan.created_at), (100.0, 99.0 * 0.995, Some(NOW + 60), NOW));
+
+        let json: serde_json::Value = serde_json::from_str(&plan.to_json().unwrap()).unwrap();
+        assert_eq!((&json["steps"][0]["kind"], &json["steps"][0]["quote_reference"]), (&"bridge".into(), &"q0".into()));
+        assert_eq!(serde_json::from_value::<ExecutionPlan>(json).unwrap(), plan);
+
+        // Slippage the router worked out for each hop comes off the amounts too
+        let slipped = Router::new(Arc::clone(&graph)).best_routes(&intent, &RouteOptions::default()).unwrap().remove(0).ranked;
+        let plan = super::tests::plan(&graph, &slipped, &intent, NOW).unwrap();
+        assert!((plan.min_amount_out - slipped.path.estimated_output.unwrap() * 0.995).abs() < 1e-9);
+        assert!(plan.min_amount_out < 99.0 * 0.995);
+    }
+
+    #[test]
+    fn three_hop_plan_chains_its_steps() {
+        let graph = graph();
+        let intent = intent("ethereum", "polygon", 100.0);
+        let ranked = best(&graph, &intent);
+        let plan = plan(&graph, &ranked, &intent, NOW).unwrap();
+
+        let summary: Vec<(&str, &str, f64, Vec<usize>)> = plan
+            .bridge_steps()
+            .map(|step| (step.src_chain.as_str(), step.dst_chain.as_str(), step.amount_in, step.depends_on.clone()))
+            .collect();
+        assert_eq!(summary, vec![
+            ("ethereum", "arbitrum", 100.0, vec![]),
+            ("arbitrum", "base", 99.0, vec![0]),
+            ("base", "polygon", 97.0, vec![1]),
+        ]);
+        assert_eq!(plan.min_amount_out, 94.0 * 0.995);
+        assert_eq!(plan.expires_at, Some(NOW + 60));
+        assert_eq!(plan.graph_version, ranked.path.graph_version);
+
+        // The first step's quote lapses first
+        assert_eq!(
+            plan_err(&graph, &ranked, &intent, NOW + 60),
+            PlanError::QuoteExpired { step: 0, bridge: "stargate".to_string(), expired_at: NOW + 60 }
+        );
+        assert!(ExecutionPlan::from_path(&ranked, &graph, &intent).is_err());
+    }
+
+    #[test]
+    fn approvals_go_before_their_bridge_steps() {
+        let graph = graph();
+        let intent = intent("ethereum", "polygon", 100.0);
+        let mut plan = plan(&graph, &best(&graph, &intent), &intent, NOW).unwrap();
+        let approve = |chain: &str, amount: f64| ExecutionStep::Approve {
+            chain: chain.to_string(),
+            token: "USDC".to_string(),
+            spender: "0xspender".to_string(),
+            amount,
+            assumed_required: false,
+        };
+        plan.insert_approvals(vec![(2, approve("base", 97.0)), (0, approve("ethereum", 100.0))]);
+
+        let kinds: Vec<(&str, Option<usize>, Vec<usize>)> = plan
+            .steps
+            .iter()
+            .map(|step| match step {
+                ExecutionStep::Approve { chain, .. } => (chain.as_str(), None, vec![]),
+                ExecutionStep::Bridge(bridge) => (bridge.src_chain.as_str(), Some(bridge.step_index), bridge.depends_on.clone()),
+            })
+            .collect();
+        assert_eq!(kinds, vec![
+            ("ethereum", None, vec![]),
+            ("ethereum", Some(1), vec![0]),
+            ("arbitrum", Some(2), vec![1]),
+            ("base", None, vec![]),
+            ("base", Some(4), vec![2, 3]),
+        ]);
+        assert_eq!(plan.bridge_steps().count(), 3);
+
+        let json: serde_json::Value = serde_json::from_str(&plan.to_json().unwrap()).unwrap();
+        assert_eq!((&json["steps"][0]["kind"], &json["steps"][0]["spender"]), (&"approve".into(), &"0xspender".into()));
+        assert_eq!(serde_json::from_value::<ExecutionPlan>(json).unwrap(), plan);
+    }
+
+    fn plan_err(graph: &Graph, ranked: &RankedPath, intent: &RouteIntent, now: u64) -> PlanError {
+        plan(graph, ranked, intent, now).unwrap_err()
+    }
+
+    #[test]
+    fn stale_or_mismatched_routes_are_refused() {
+        let graph = graph();
+        let intent = intent("ethereum", "base", 100.0);
+        let ranked = best(&graph, &intent);
+
+        assert!(matches!(plan_err(&graph, &ranked, &self::intent("ethereum", "polygon", 100.0), NOW), PlanError::IntentMismatch { .. }));
+        assert_eq!(plan_err(&graph, &ranked, &self::intent("ethereum", "base", 5.0), NOW), PlanError::AmountOutOfRange {
+            step: 0,
+            bridge: "stargate".to_string(),
+            amount_in: 5.0,
+        });
+        assert!(matches!(plan_err(&graph, &ranked, &self::intent("ethereum", "base", 10.5), NOW), PlanError::AmountOutOfRange { step: 1, .. }));
+
+        let opts = PlanOptions { max_version_lag: 2, ..PlanOptions::default() };
+        let (from, to) = (ranked.path.hops[1].from, ranked.path.hops[1].to);
+        for _ in 0..3 {
+            graph.update_edge_metrics(from, to, "stargate", ranked.path.hops[1].metrics.clone()).unwrap();
+        }
+        assert!(matches!(
+            ExecutionPlan::from_path_with(&ranked, &graph, &intent, &opts, NOW),
+            Err(PlanError::StalePath { lag: 3, .. })
+        ));
+
+        graph.set_edge_active(from, to, "stargate", false);
+        assert_eq!(plan_err(&graph, &ranked, &intent, NOW), PlanError::EdgeGone { step: 1, bridge: "stargate".to_string() });
+    }
+}
@@ -0,0 +1,212 @@
+// Routing invariants over the seeded graphs of `testutil`. Proptest picks the seed and the
+// sizes, so a failure shrinks towards fewer layers, nodes and edges.
+
+use crate::graph::{Graph, compute_edge_weight};
+use crate::routing::RoutingEngine;
+use crate::scoring::ScoringEngine;
+use crate::testutil::{LayeredGraph, layered_graph, random_paths};
+use crate::types::*;
+use proptest::prelude::*;
+use std::{collections::HashSet, sync::Arc};
+
+#[derive(Debug, Clone)]
+struct GraphSpec {
+    seed: u64,
+    layers: usize,
+    width: usize,
+    extra_edges: usize,
+    // Indices into the snapshot's edges, taken modulo their count, to switch off
+    inactive: Vec<usize>,
+    // Edges between any two nodes, skipping or going back across layers, as node indices
+    // taken modulo the node count and a cost
+    shortcuts: Vec<(usize, usize, f64)>,
+}
+
+impl GraphSpec {
+    fn build(&self) -> LayeredGraph {
+        let edges = (self.layers - 1) * self.width + self.extra_edges;
+        let layered = layered_graph(self.seed, self.layers, self.width, edges);
+        let snapshot = layered.graph.snapshot();
+        for index in &self.inactive {
+            let edge = &snapshot.edges[index % snapshot.edges.len()];
+            layered.graph.set_edge_active(edge.from, edge.to, &edge.bridge_name, false);
+        }
+        let nodes: Vec<NodeId> = layered.layers.iter().flatten().copied().collect();
+        // Each over its own bridge, as two between the same nodes would be duplicates
+        for (i, (from, to, cost)) in self.shortcuts.iter().enumerate() {
+            let metrics = EdgeMetrics { cost: *cost, speed: 60.0, liquidity: 1_000_000.0, risk: 0.1 };
+            layered.graph.add_edge(nodes[from % nodes.len()], nodes[to % nodes.len()], &format!("shortcut{}", i), metrics, None, None).unwrap();
+        }
+        layered
+    }
+}
+
+// At most `max_nodes` nodes over two to four layers
+fn graph_spec(max_nodes: usize) -> impl Strategy<Value = GraphSpec> {
+    (2usize..=4)
+        .prop_flat_map(move |layers| (Just(layers), 1..=(max_nodes / layers).max(1)))
+        .prop_flat_map(|(layers, width)| {
+            (
+                any::<u64>(),
+                Just(layers),
+                Just(width),
+                0usize..=12,
+                prop::collection::vec(any::<usize>(), 0..4),
+                prop::collection::vec((any::<usize>(), any::<usize>(), 0.0..20.0f64), 0..6),
+            )
+        })
+        .prop_map(|(seed, layers, width, extra_edges, inactive, shortcuts)| GraphSpec { seed, layers, width, extra_edges, inactive, shortcuts })
+}
+
+fn routing_params() -> impl Strategy<Value = RoutingParams> {
+    prop_oneof![
+        Just(RoutingParams::cheapest()),
+        Just(RoutingParams::fastest()),
+        Just(RoutingParams::balanced()),
+        Just(RoutingParams::safest()),
+        Just(RoutingParams::max_liquidity()),
+        (0.0..1.0f64, 0.0..1.0f64, 0.0..1.0f64, 0.0..1.0f64).prop_map(|(alpha, beta, gamma, delta)| RoutingParams {
+            alpha: alpha + 0.01,
+            beta,
+            gamma,
+            delta,
+            omega: 0.0,
+            epsilon: 0.0,
+            ..RoutingParams::default()
+        }),
+    ]
+}
+
+fn path_weight(path: &Path, params: &RoutingParams) -> f64 {
+    let params = params.normalized();
+    path.hops.iter().map(|hop| compute_edge_weight(&hop.metrics, &params, None)).sum()
+}
+
+// The lightest walk of at most `max_hops` active edges, by trying all of them
+fn brute_force_weight(graph: &Graph, start: NodeId, end: NodeId, params: &RoutingParams, max_hops: usize) -> Option<f64> {
+    let params = params.normalized();
+    let mut best = (start == end).then_some(0.0);
+    let mut frontier = vec![(start, 0.0)];
+    for _ in 0..max_hops {
+        let mut next = Vec::new();
+        for (node, weight) in frontier {
+            for edge in graph.get_outgoing_edges(node) {
+                let weight = weight + compute_edge_weight(&edge.get_metrics(), &params, None);
+                if edge.to == end {
+                    best = Some(best.map_or(weight, |best: f64| best.min(weight)));
+                }
+                next.push((edge.to, weight));
+            }
+        }
+        frontier = next;
+    }
+    best
+}
+
+fn assert_valid_path(graph: &Graph, path: &Path, start: NodeId, end: NodeId, max_hops: usize) -> Result<(), TestCaseError> {
+    prop_assert!(path.hops.len() <= max_hops, "{} hops over a limit of {}", path.hops.len(), max_hops);
+    prop_assert_eq!(path.hops.first().map_or(start, |hop| hop.from), start);
+    prop_assert_eq!(path.hops.last().map_or(start, |hop| hop.to), end);
+    for pair in path.hops.windows(2) {
+        prop_assert_eq!(pair[0].to, pair[1].from);
+    }
+    for hop in &path.hops {
+        let matches = graph
+            .get_outgoing_edges(hop.from)
+            .iter()
+            .any(|edge| edge.to == hop.to && edge.bridge_name == hop.bridge_name && edge.get_metrics() == hop.metrics);
+        prop_assert!(matches, "no active {} edge {:?} -> {:?} with {:?}", hop.bridge_name, hop.from, hop.to, hop.metrics);
+    }
+
+    let total_cost: f64 = path.hops.iter().map(|hop| hop.metrics.cost).sum();
+    prop_assert!((path.total_cost - total_cost).abs() <= 1e-9 * total_cost.max(1.0));
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn found_paths_follow_active_edges_within_the_hop_limit(
+        spec in graph_spec(40),
+        params in routing_params(),
+        max_hops in 1usize..=5,
+        end_index in any::<prop::sample::Index>(),
+    ) {
+        let layered = spec.build();
+        let start = layered.source();
+        let nodes: Vec<NodeId> = layered.layers.iter().flatten().copied().collect();
+        let end = nodes[end_index.index(nodes.len())];
+        let graph = Arc::new(layered.graph);
+        let engine = RoutingEngine::new(Arc::clone(&graph), max_hops);
+
+        if let Some(path) = engine.find_path(start, end, &params) {
+            assert_valid_path(&graph, &path, start, end, max_hops)?;
+        }
+    }
+
+    #[test]
+    fn found_paths_are_the_lightest_within_the_hop_limit(
+        spec in graph_spec(8),
+        params in routing_params(),
+        max_hops in 1usize..=4,
+        end_index in any::<prop::sample::Index>(),
+    ) {
+        let layered = spec.build();
+        let start = layered.source();
+        let nodes: Vec<NodeId> = layered.layers.iter().flatten().copied().collect();
+        prop_assert!(nodes.len() <= 8);
+        let end = nodes[end_index.index(nodes.len())];
+        let engine = RoutingEngine::new(Arc::new(layered.graph), max_hops);
+
+        let found = engine.find_path(start, end, &params).map(|path| path_weight(&path, &params));
+        let expected = brute_force_weight(engine.graph(), start, end, &params, max_hops);
+        match (found, expected) {
+            (Some(found), Some(expected)) => prop_assert!((found - expected).abs() <= 1e-9 * expected.max(1.0), "found {} expected {}", found, expected),
+            (found, expected) => prop_assert_eq!(found, expected),
+        }
+    }
+
+    #[test]
+    fn candidate_paths_are_distinct(
+        spec in graph_spec(40),
+        params in routing_params(),
+        max_paths in 1usize..=6,
+    ) {
+        let layered = spec.build();
+        let (start, end) = (layered.source(), layered.sink());
+        let max_hops = layered.layers.len();
+        let graph = Arc::new(layered.graph);
+        let engine = RoutingEngine::new(Arc::clone(&graph), max_hops);
+
+        let paths = engine.find_candidate_paths(start, end, &params, max_paths);
+        prop_assert!(paths.len() <= max_paths);
+        let mut signatures = HashSet::new();
+        for path in &paths {
+            assert_valid_path(&graph, path, start, end, max_hops)?;
+            let signature: Vec<NodeId> = path.hops.iter().map(|hop| hop.from).chain(path.hops.last().map(|hop| hop.to)).collect();
+            prop_assert!(signatures.insert(signature), "duplicate candidate {:?}", path);
+        }
+    }
+
+    #[test]
+    fn scores_are_never_nan(
+        seed in any::<u64>(),
+        count in 1usize..=30,
+        duplicates in 0usize..=3,
+        params in routing_params(),
+        max_results in 1usize..=10,
+    ) {
+        let mut paths = random_paths(seed, count);
+        let copies: Vec<Path> = paths.iter().take(duplicates).cloned().collect();
+        paths.extend(copies);
+
+        let ranked = ScoringEngine::new().score_and_rank(paths, &params, max_results).unwrap().ranked;
+        prop_assert!(!ranked.is_empty() && ranked.len() <= max_results);
+        for (i, route) in ranked.iter().enumerate() {
+            prop_assert_eq!(route.rank, i + 1);
+            let breakdown = &route.score_breakdown;
+            for value in [breakdown.cost_score, breakdown.speed_score, breakdown.liquidity_score, breakdown.risk_score, breakdown.final_score] {
+                prop_assert!(!value.is_nan(), "NaN in {:?}", breakdown);
+            }
+        }
+    }
+}
@@ -0,0 +1,1097 @@
+use crate::diff::{RouteDiff, compare_routes};
+use crate::error::{RouteError, SlippageError};
+use crate::graph::Graph;
+use crate::confidence::ConfidenceModel;
+use crate::pinning::{PinStore, PinnedRoute, apply_stickiness};
+use crate::routing::{PruningRule, RoutingEngine};
+use crate::scoring::{DropReason, ExplainedPath, RankingDiagnostics, RankingOutcome, ScoringEngine};
+use crate::slippage::{DEFAULT_MAX_UTILIZATION, SlippageModel};
+use crate::types::*;
+use async_trait::async_trait;
+use futures::{Stream, stream};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, sync::Arc, time::{Duration, SystemTime, UNIX_EPOCH}};
+use tokio::{sync::watch, time::Instant};
+
+// Limits on a whole path; candidates over any of them are dropped before ranking
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RouteConstraints {
+    pub max_cost: Option<f64>,
+    // seconds
+    pub max_time: Option<f64>,
+    pub max_risk: Option<f64>,
+    pub min_liquidity: Option<f64>,
+}
+
+impl RouteConstraints {
+    pub fn allows(&self, path: &Path) -> bool {
+        self.max_cost.is_none_or(|max| path.total_cost <= max)
+            && self.max_time.is_none_or(|max| path.total_time <= max)
+            && self.max_risk.is_none_or(|max| path.total_risk <= max)
+            && self.min_liquidity.is_none_or(|min| path.effective_min_liquidity() >= min)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RouteOptions {
+    pub max_results: usize,
+    pub max_hops: usize,
+    // Most same-chain swap hops a route may take, within max_hops; no limit when None
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_swaps: Option<usize>,
+    pub constraints: RouteConstraints,
+    // See RoutingEngine::with_excluded_bridges
+    pub excluded_bridges: Vec<String>,
+    // Weights for intents without a preference, instead of the balanced preset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub routing_params: Option<RoutingParams>,
+    // Stored preference profile to apply under these options; resolved by whoever stores them,
+    // the router itself ignores it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    // Who the query is answered for, set by the API from the caller's key; the router itself
+    // ignores it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    // Answer even while the graph is warming up, see Router::with_min_coverage
+    pub allow_partial: bool,
+    // Leave out routes the intent's src_address can't pay the gas of, see Router::check_affordability
+    pub affordable_only: bool,
+    // Where the search queues behind others when it runs on a route executor; the router
+    // itself ignores it
+    pub priority: RoutePriority,
+    // Ends the search before max_results candidates when the rest couldn't rank, see PruningRule
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pruning: Option<PruningRule>,
+}
+
+// Queued searches run highest first: interactive queries ahead of normal ones, batch jobs like a
+// rebalancer's last
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoutePriority {
+    Batch,
+    #[default]
+    Normal,
+    Interactive,
+}
+
+impl RoutePriority {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RoutePriority::Batch => "batch",
+            RoutePriority::Normal => "normal",
+            RoutePriority::Interactive => "interactive",
+        }
+    }
+}
+
+impl Default for RouteOptions {
+    fn default() -> Self {
+        Self {
+            max_results: 3,
+            max_hops: 4,
+            max_swaps: None,
+            constraints: RouteConstraints::default(),
+            excluded_bridges: Vec::new(),
+            routing_params: None,
+            profile: None,
+            tenant: None,
+            allow_partial: false,
+            affordable_only: false,
+            priority: RoutePriority::default(),
+            pruning: None,
+        }
+    }
+}
+
+// How Router::watch paces and filters its updates
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WatchSettings {
+    // At most one update per interval; changes in between are folded into the next one
+    pub min_interval: Duration,
+    // Relative rise in the best route's cost, time or risk, or fall in its liquidity, that
+    // counts as degraded
+    pub tolerance: f64,
+}
+
+impl Default for WatchSettings {
+    fn default() -> Self {
+        Self {
+            min_interval: Duration::from_secs(1),
+            tolerance: 0.05,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateReason {
+    // The routes when the watch started
+    Initial,
+    // Another route ranks first, or one appeared where there was none
+    BestChanged,
+    // The best route is the same but worse than when last sent, beyond the tolerance
+    Degraded,
+    // An edge of the last sent best route is gone, or no route is left
+    Broken,
+}
+
+impl UpdateReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UpdateReason::Initial => "initial",
+            UpdateReason::BestChanged => "best_changed",
+            UpdateReason::Degraded => "degraded",
+            UpdateReason::Broken => "broken",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RouteUpdate {
+    pub reason: UpdateReason,
+    pub graph_version: u64,
+    pub new_ranked: Vec<RankedPath>,
+    // The new best route against the one last sent, when there are both
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<RouteDiff>,
+}
+
+// A token a picker can offer, see Router::reachable_destinations
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReachableToken {
+    pub address: String,
+    pub symbol: String,
+    pub min_hops: usize,
+}
+
+// Told of every query best_routes answers, e.g. to keep an audit trail of them. Called on the
+// querying thread, so implementations should hand the work off rather than do it there.
+pub trait RouteObserver: Send + Sync {
+    fn routes_served(&self, intent: &RouteIntent, opts: &RouteOptions, routes: &[ExplainedPath], graph_version: u64);
+}
+
+// Stages of a query told to a StageTimer
+pub const ROUTE_SEARCH_STAGE: &str = "route_search";
+pub const SCORING_STAGE: &str = "scoring";
+
+// Told how long each stage of a query took: ROUTE_SEARCH_STAGE for finding the candidate
+// paths, SCORING_STAGE for ranking them. Called on the querying thread.
+pub trait StageTimer: Send + Sync {
+    fn stage_timed(&self, stage: &'static str, elapsed: Duration, intent: &RouteIntent);
+}
+
+// Whether `address` holds enough of `chain`'s native token for the gas of a transfer over
+// `bridge` leaving from there
+#[async_trait]
+pub trait BalanceChecker: Send + Sync {
+    async fn affordability(&self, chain: &str, bridge: &str, address: &str) -> Affordability;
+}
+
+// Answers route intents against a shared graph: resolves the intent's assets, searches,
+// filters and ranks the candidates with explanations
+#[derive(Clone)]
+pub struct Router {
+    graph: Arc<Graph>,
+    scoring: Arc<ScoringEngine>,
+    watch: WatchSettings,
+    slippage: SlippageModel,
+    max_utilization: f64,
+    min_coverage: f64,
+    observer: Option<Arc<dyn RouteObserver>>,
+    balance: Option<Arc<dyn BalanceChecker>>,
+    // Where confirmed routes are pinned and the score bonus for keeping to them, see
+    // `with_stickiness`
+    stickiness: Option<(Arc<dyn PinStore>, f64)>,
+    timer: Option<Arc<dyn StageTimer>>,
+    confidence: ConfidenceModel,
+}
+
+impl Router {
+    pub fn new(graph: Arc<Graph>) -> Self {
+        Self {
+            graph,
+            scoring: Arc::new(ScoringEngine::new()),
+            watch: WatchSettings::default(),
+            slippage: SlippageModel::default(),
+            max_utilization: DEFAULT_MAX_UTILIZATION,
+            min_coverage: 0.0,
+            observer: None,
+            balance: None,
+            stickiness: None,
+            timer: None,
+            confidence: ConfidenceModel::default(),
+        }
+    }
+
+    // How intent amounts slip on each hop; paths with a hop asked for more than
+    // `max_utilization` of its liquidity are left out
+    pub fn with_slippage(mut self, slippage: SlippageModel, max_utilization: f64) -> Self {
+        self.slippage = slippage;
+        self.max_utilization = max_utilization;
+        self
+    }
+
+    // Refuses queries while the graph's coverage is below `min_coverage` and it never warmed up,
+    // unless they allow
+    // partial answers. Graphs without a measured coverage are never refused.
+    pub fn with_min_coverage(mut self, min_coverage: f64) -> Self {
+        self.min_coverage = min_coverage;
+        self
+    }
+
+    pub fn with_observer(mut self, observer: Arc<dyn RouteObserver>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    pub fn with_stage_timer(mut self, timer: Arc<dyn StageTimer>) -> Self {
+        self.timer = Some(timer);
+        self
+    }
+
+    pub fn with_balance_checker(mut self, balance: Arc<dyn BalanceChecker>) -> Self {
+        self.balance = Some(balance);
+        self
+    }
+
+    pub fn with_scoring(mut self, scoring: ScoringEngine) -> Self {
+        self.scoring = Arc::new(scoring);
+        self
+    }
+
+    // Favours the route last confirmed for an intent, see `confirm_selection`: it stays a
+    // candidate while all its edges are active and fresh, and paths sharing its edges get up to
+    // `bonus` added to their final score, so a new route only displaces it by scoring more than
+    // `bonus` higher. Constraints and exclusions still apply to it as to any other candidate.
+    pub fn with_stickiness(mut self, store: Arc<dyn PinStore>, bonus: f64) -> Self {
+        self.stickiness = Some((store, bonus));
+        self
+    }
+
+    // How the hops of the paths found are rated, see Hop::confidence
+    pub fn with_confidence(mut self, confidence: ConfidenceModel) -> Self {
+        self.confidence = confidence;
+        self
+    }
+
+    pub fn with_watch_settings(mut self, watch: WatchSettings) -> Self {
+        self.watch = watch;
+        self
+    }
+
+    pub fn graph(&self) -> &Arc<Graph> {
+        &self.graph
+    }
+
+    // Pins `route` as the one the caller went with for the intent with `intent_hash`
+    // (RouteIntent::intent_hash). Does nothing without stickiness.
+    pub fn confirm_selection(&self, intent_hash: &str, route: &RankedPath) {
+        if let Some((store, _)) = &self.stickiness {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+            store.save(intent_hash, &PinnedRoute::new(&route.path, now));
+        }
+    }
+
+    // The asset node for `token` on `chain`. `token` is an address or a symbol; a symbol
+    // shared by several assets on the chain is ambiguous.
+    pub fn resolve(&self, chain: &str, token: &str) -> Result<NodeId, RouteError> {
+        let found = self.graph.find_asset_nodes(chain, token);
+        let by_address: Vec<NodeId> = found
+            .iter()
+            .filter(|node| matches!(&node.node_type, NodeType::Asset { token_address, .. } if token_address.eq_ignore_ascii_case(token)))
+            .map(|node| node.id)
+            .collect();
+        match (by_address.as_slice(), found.as_slice()) {
+            ([id], _) => Ok(*id),
+            ([], [node]) => Ok(node.id),
+            (_, []) => Err(RouteError::UnknownToken { chain: chain.to_string(), token: token.to_string() }),
+            (_, found) => Err(RouteError::AmbiguousToken {
+                chain: chain.to_string(),
+                token: token.to_string(),
+                matches: found.len(),
+            }),
+        }
+    }
+
+    // Ranked routes for `intent`, best first. The preference picks the RoutingParams preset;
+    // without one the options' routing_params are used, else balanced. Each candidate carries
+    // the intent's amount through the slippage model before the constraints are checked. An
+    // empty list means no route satisfies the options. Answered queries, including those without
+    // a route, are passed on to the observer; those refused while the graph warms up aren't.
+    pub fn best_routes(&self, intent: &RouteIntent, opts: &RouteOptions) -> Result<Vec<ExplainedPath>, RouteError> {
+        self.rank_routes(intent, opts).map(|outcome| outcome.ranked)
+    }
+
+    // As best_routes, with what became of the candidates the search found, e.g. to say why
+    // none of them is left
+    pub fn rank_routes(&self, intent: &RouteIntent, opts: &RouteOptions) -> Result<RankingOutcome<ExplainedPath>, RouteError> {
+        let warming_up = self.graph.coverage().filter(|coverage| !opts.allow_partial && !coverage.warmed_up && coverage.fraction < self.min_coverage);
+        if let Some(coverage) = warming_up {
+            return Err(RouteError::Warmup { coverage: coverage.fraction, missing_pairs_sample: coverage.missing_sample });
+        }
+        let graph_version = self.graph.version();
+        let outcome = self.search(intent, opts)?;
+        if let Some(observer) = &self.observer {
+            observer.routes_served(intent, opts, &outcome.ranked, graph_version);
+        }
+        Ok(outcome)
+    }
+
+    // The chains and tokens `token` on `chain` can be sent to in at most `max_hops` hops, by
+    // chain, nearest tokens first. Exchange nodes along the way aren't listed.
+    pub fn reachable_destinations(&self, chain: &str, token: &str, max_hops: usize) -> Result<BTreeMap<String, Vec<ReachableToken>>, RouteError> {
+        let from = self.resolve(chain, token)?;
+        Ok(self.by_chain(self.graph.reachable_destinations(from, max_hops)))
+    }
+
+    // The chains and tokens that can be sent to `token` on `chain`, as reachable_destinations
+    pub fn reachable_sources(&self, chain: &str, token: &str, max_hops: usize) -> Result<BTreeMap<String, Vec<ReachableToken>>, RouteError> {
+        let to = self.resolve(chain, token)?;
+        Ok(self.by_chain(self.graph.reachable_sources(to, max_hops)))
+    }
+
+    fn by_chain(&self, reachable: Vec<(NodeId, usize)>) -> BTreeMap<String, Vec<ReachableToken>> {
+        let mut chains: BTreeMap<String, Vec<ReachableToken>> = BTreeMap::new();
+        for (node, min_hops) in reachable {
+            let Some(node) = self.graph.get_node(node) else {
+                continue;
+            };
+            if let NodeType::Asset { chain, token_address, token_symbol } = &node.node_type {
+                chains.entry(chain.clone()).or_default().push(ReachableToken {
+                    address: token_address.clone(),
+                    symbol: token_symbol.clone(),
+                    min_hops,
+                });
+            }
+        }
+        chains
+    }
+
+    // Sets the affordability of `routes`, as best_routes gave them for `intent`: whether the
+    // intent's src_address can pay the gas of the first step on its source chain. The ranking
+    // stays as it is; with affordable_only, routes it can't pay for are left out. Without a
+    // balance checker or a src_address the routes are returned untouched.
+    pub async fn check_affordability(&self, intent: &RouteIntent, opts: &RouteOptions, mut routes: Vec<ExplainedPath>) -> Vec<ExplainedPath> {
+        let (Some(balance), Some(address)) = (&self.balance, intent.src_address.as_deref()) else {
+            return routes;
+        };
+        for route in &mut routes {
+            let first = route.ranked.path.hops.first();
+            let chain = first.and_then(|hop| self.graph.get_node(hop.from)).map(|node| match &node.node_type {
+                NodeType::Asset { chain, .. } | NodeType::Exchange { chain, .. } => chain.clone(),
+            });
+            let affordability = match (chain, first) {
+                (Some(chain), Some(hop)) => balance.affordability(&chain, &hop.bridge_name, address).await,
+                _ => Affordability::Unknown,
+            };
+            route.ranked.affordability = Some(affordability);
+        }
+        if opts.affordable_only {
+            routes.retain(|route| !matches!(route.ranked.affordability, Some(Affordability::InsufficientGas { .. })));
+        }
+        routes
+    }
+
+    fn search(&self, intent: &RouteIntent, opts: &RouteOptions) -> Result<RankingOutcome<ExplainedPath>, RouteError> {
+        if !intent.amount.is_finite() || intent.amount <= 0.0 {
+            return Err(RouteError::InvalidAmount(intent.amount));
+        }
+        let params = match intent.preference.as_deref() {
+            Some(preference) => RoutingParams::preset(preference)
+                .ok_or_else(|| RouteError::UnknownPreference(preference.to_string()))?,
+            None => match &opts.routing_params {
+                Some(params) => {
+                    params.validate()?;
+                    self.scoring.objectives().check(params)?;
+                    params.clone()
+                }
+                None => RoutingParams::balanced(),
+            },
+        };
+        if let Some(pruning) = &opts.pruning {
+            pruning.validate().map_err(RouteError::InvalidPruning)?;
+        }
+        let start = self.resolve(&intent.from_chain, &intent.from_token)?;
+        let end = self.resolve(&intent.to_chain, &intent.to_token)?;
+        if start == end {
+            return Ok(RankingOutcome { ranked: Vec::new(), diagnostics: RankingDiagnostics::new(self.scoring.strategy(), 0) });
+        }
+
+        let engine = RoutingEngine::new(Arc::clone(&self.graph), opts.max_hops)
+            .with_max_swaps(opts.max_swaps)
+            .with_excluded_bridges(opts.excluded_bridges.iter().cloned())
+            .with_confidence(self.confidence.clone())
+            .with_sent_amount(intent.amount, self.slippage);
+        let started = std::time::Instant::now();
+        let mut search = engine.candidate_paths(start, end, &params, opts.max_results).with_pruning(opts.pruning);
+        let mut found = Vec::new();
+        let mut best = self.scoring.best_scoring(&params);
+        // Candidates in a row the best scoring one stayed the same over
+        let mut stable = 0;
+        while let Some(path) = search.next() {
+            found.push(path);
+            if let Some(PruningRule::StopAfterStable { n }) = opts.pruning {
+                stable = if best.push(&found) { stable + 1 } else { 0 };
+                if stable >= n {
+                    search.stop();
+                }
+            }
+        }
+        let search_stats = search.stats();
+        let pinned = self
+            .stickiness
+            .as_ref()
+            .and_then(|(store, bonus)| store.load(&intent.intent_hash()).map(|route| (route, *bonus)));
+        // The pinned route is a candidate of its own as long as every edge of it is usable
+        if let Some(path) = pinned.as_ref().and_then(|(route, _)| engine.path_along(&route.edges)) {
+            if !found.iter().any(|other| same_hops(other, &path)) {
+                found.push(path);
+            }
+        }
+        self.timed(ROUTE_SEARCH_STAGE, started, intent);
+        let found_count = found.len();
+        let (mut over_utilized, mut over_fees) = (0, 0);
+        let mut sendable = Vec::with_capacity(found_count);
+        for mut path in found {
+            match self.slippage.propagate(&mut path, intent.amount, self.max_utilization) {
+                Ok(_) => sendable.push(path),
+                Err(SlippageError::UtilizationExceeded { .. }) => over_utilized += 1,
+                Err(SlippageError::FeesExceedAmount { .. }) => over_fees += 1,
+            }
+        }
+        let sendable_count = sendable.len();
+        let candidates: Vec<Path> = sendable.into_iter().filter(|path| opts.constraints.allows(path)).collect();
+        let constrained = sendable_count - candidates.len();
+
+        // Counted from what the search found rather than what reached the scoring engine
+        // With a pinned route everything is ranked, so a path its bonus lifts isn't cut first
+        let ranked_count = match pinned {
+            Some(_) => candidates.len().max(opts.max_results),
+            None => opts.max_results,
+        };
+        let started = std::time::Instant::now();
+        let mut outcome = self.scoring.score_and_rank_explained(candidates, &params, ranked_count)?;
+        self.timed(SCORING_STAGE, started, intent);
+        if let Some((route, bonus)) = &pinned {
+            apply_stickiness(&mut outcome.ranked, route, *bonus);
+            outcome.diagnostics.record(DropReason::Truncated, outcome.ranked.len().saturating_sub(opts.max_results));
+            outcome.ranked.truncate(opts.max_results);
+        }
+        outcome.diagnostics.candidates = found_count;
+        outcome.diagnostics.search = search_stats;
+        outcome.diagnostics.record(DropReason::Utilization, over_utilized);
+        outcome.diagnostics.record(DropReason::Fees, over_fees);
+        outcome.diagnostics.record(DropReason::Constraints, constrained);
+        Ok(outcome)
+    }
+
+    fn timed(&self, stage: &'static str, started: std::time::Instant, intent: &RouteIntent) {
+        if let Some(timer) = &self.timer {
+            timer.stage_timed(stage, started.elapsed(), intent);
+        }
+    }
+
+    // Streams the routes for `intent` as the graph changes: first the current ones, then an
+    // update whenever the best route changes, degrades or breaks. Intents the graph can't
+    // answer yet, e.g. for tokens no bridge has quoted, watch as having no route. The
+    // stream ends when dropped.
+    pub fn watch(&self, intent: RouteIntent, opts: RouteOptions) -> impl Stream<Item = RouteUpdate> + Send + 'static {
+        struct Watch {
+            router: Router,
+            intent: RouteIntent,
+            opts: RouteOptions,
+            changes: watch::Receiver<u64>,
+            // Best route of the last update, None before the first
+            sent: Option<Option<RankedPath>>,
+            sent_at: Option<Instant>,
+        }
+
+        let state = Watch {
+            router: self.clone(),
+            intent,
+            opts,
+            changes: self.graph.subscribe(),
+            sent: None,
+            sent_at: None,
+        };
+        stream::unfold(state, |mut state| async move {
+            loop {
+                let graph_version = *state.changes.borrow_and_update();
+                // Re-searches on every graph change, which the observer isn't told about
+                let ranked: Vec<RankedPath> = state.router
+                    .search(&state.intent, &state.opts)
+                    .map(|outcome| outcome.ranked.into_iter().map(|route| route.ranked).collect())
+                    .unwrap_or_default();
+                let reason = match &state.sent {
+                    None => Some(UpdateReason::Initial),
+                    Some(sent) => state.router.update_reason(sent.as_ref().map(|sent| &sent.path), &ranked),
+                };
+
+                if let Some(reason) = reason {
+                    let ready_at = state.sent_at.map(|at| at + state.router.watch.min_interval);
+                    if let Some(ready_at) = ready_at.filter(|ready_at| *ready_at > Instant::now()) {
+                        // Looked at again once the interval is over, with whatever changed meanwhile
+                        tokio::time::sleep_until(ready_at).await;
+                        continue;
+                    }
+                    let best = ranked.first().cloned();
+                    let diff = match (state.sent.as_ref().and_then(Option::as_ref), &best) {
+                        (Some(sent), Some(best)) => Some(compare_routes(sent, best)),
+                        _ => None,
+                    };
+                    state.sent = Some(best);
+                    state.sent_at = Some(Instant::now());
+                    return Some((RouteUpdate { reason, graph_version, new_ranked: ranked, diff }, state));
+                }
+
+                if state.changes.changed().await.is_err() {
+                    return None;
+                }
+            }
+        })
+    }
+
+    // Why `ranked` is worth sending to a watcher that last got `sent` as the best route
+    fn update_reason(&self, sent: Option<&Path>, ranked: &[RankedPath]) -> Option<UpdateReason> {
+        match (sent, ranked.first().map(|best| &best.path)) {
+            (None, None) => None,
+            (None, Some(_)) => Some(UpdateReason::BestChanged),
+            (Some(_), None) => Some(UpdateReason::Broken),
+            (Some(sent), Some(best)) if same_hops(sent, best) => {
+                self.is_degraded(sent, best).then_some(UpdateReason::Degraded)
+            }
+            (Some(sent), Some(_)) if !self.is_routable(sent) => Some(UpdateReason::Broken),
+            (Some(_), Some(_)) => Some(UpdateReason::BestChanged),
+        }
+    }
+
+    fn is_degraded(&self, sent: &Path, now: &Path) -> bool {
+        let worse = 1.0 + self.watch.tolerance;
+        now.total_cost > sent.total_cost * worse
+            || now.total_time > sent.total_time * worse
+            || now.total_risk > sent.total_risk * worse
+            || now.effective_min_liquidity() < sent.effective_min_liquidity() * (1.0 - self.watch.tolerance)
+    }
+
+    // Whether every hop of `path` still has its active edge
+    fn is_routable(&self, path: &Path) -> bool {
+        path.hops.iter().all(|hop| {
+            self.graph
+                .get_outgoing_edges(hop.from)
+                .iter()
+                .any(|edge| edge.to == hop.to && edge.bridge_name == hop.bridge_name)
+        })
+    }
+}
+
+fn same_hops(a: &Path, b: &Path) -> bool {
+    a.hops.len() == b.hops.len()
+        && a.hops.iter().zip(&b.hops).all(|(a, b)| a.from == b.from && a.to == b.to && a.bridge_name == b.bridge_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::ChangeSeverity;
+    use crate::error::GraphError;
+    use crate::pinning::MemoryPinStore;
+
+    // ethereum -> polygon USDC directly over stargate, or for less via wormhole and arbitrum
+    fn router() -> Router {
+        let graph = Graph::new(16);
+        let eth = graph.get_or_create_asset_node("ethereum", "0xa0b8", "USDC");
+        let arb = graph.get_or_create_asset_node("arbitrum", "0xaf88", "USDC");
+        let pol = graph.get_or_create_asset_node("polygon", "0x3c49", "USDC");
+        graph.get_or_create_asset_node("polygon", "0x2791", "USDC.e");
+        let metrics = |cost: f64, speed: f64| EdgeMetrics { cost, speed, liquidity: 1_000_000.0, risk: 0.1 };
+        graph.add_edge(eth, pol, "stargate", metrics(5.0, 60.0), None, None).unwrap();
+        graph.add_edge(eth, arb, "wormhole", metrics(1.0, 600.0), None, None).unwrap();
+        graph.add_edge(arb, pol, "across", metrics(1.0, 120.0), None, None).unwrap();
+        Router::new(Arc::new(graph))
+    }
+
+    fn intent(to_token: &str, preference: Option<&str>) -> RouteIntent {
+        RouteIntent {
+            from_chain: "ethereum".to_string(),
+            from_token: "usdc".to_string(),
+            to_chain: "Polygon".to_string(),
+            to_token: to_token.to_string(),
+            amount: 1000.0,
+            preference: preference.map(String::from),
+            src_address: None,
+        }
+    }
+
+    fn bridges(route: &ExplainedPath) -> Vec<&str> {
+        route.ranked.path.hops.iter().map(|hop| &*hop.bridge_name).collect()
+    }
+
+    #[test]
+    fn intents_resolve_to_ranked_explained_routes() {
+        let router = router();
+        let opts = RouteOptions::default();
+
+        let cheapest = router.best_routes(&intent("0x3C49", Some("cheapest")), &opts).unwrap();
+        assert_eq!(cheapest.len(), 2);
+        assert_eq!(bridges(&cheapest[0]), ["wormhole", "across"]);
+        assert_eq!(bridges(&cheapest[1]), ["stargate"]);
+        assert_eq!(cheapest[0].ranked.rank, 1);
+        assert_eq!(cheapest[0].ranked.path.total_cost, 2.0);
+        assert_eq!(cheapest[0].summary, "ranked first for the selected weights; no alternative to its wormhole and across hops");
+        assert!(cheapest[0].explanations.iter().any(|explanation| explanation.factor == "cost" && explanation.weight == 1.0));
+
+        let fastest = router.best_routes(&intent("0x3c49", Some("fastest")), &opts).unwrap();
+        assert_eq!(bridges(&fastest[0]), ["stargate"]);
+
+        let without_wormhole = RouteOptions { excluded_bridges: vec!["Wormhole".to_string()], ..RouteOptions::default() };
+        assert_eq!(bridges(&router.best_routes(&intent("0x3c49", Some("cheapest")), &without_wormhole).unwrap()[0]), ["stargate"]);
+
+        let one_hop = RouteOptions { max_hops: 1, ..RouteOptions::default() };
+        assert_eq!(bridges(&router.best_routes(&intent("0x3c49", Some("cheapest")), &one_hop).unwrap()[0]), ["stargate"]);
+
+        let quick = RouteOptions { constraints: RouteConstraints { max_time: Some(30.0), ..RouteConstraints::default() }, ..RouteOptions::default() };
+        let filtered = router.rank_routes(&intent("0x3c49", Some("cheapest")), &quick).unwrap();
+        assert!(filtered.ranked.is_empty());
+        assert_eq!((filtered.diagnostics.candidates, filtered.diagnostics.dropped_for(DropReason::Constraints)), (2, 2));
+        assert_eq!(filtered.diagnostics.stats, None);
+    }
+
+    #[test]
+    fn amounts_slip_more_as_they_grow_until_the_cap() {
+        let router = router();
+        let opts = RouteOptions::default();
+        let send = |amount: f64| {
+            let intent = RouteIntent { amount, ..intent("0x3c49", Some("fastest")) };
+            router.best_routes(&intent, &opts).unwrap()
+        };
+
+        let small = &send(1_000.0)[0].ranked;
+        let large = &send(500_000.0)[0].ranked;
+        assert_eq!(bridges(&send(1_000.0)[0]), ["stargate"]);
+        let (small_pct, large_pct) = (small.path.hops[0].slippage_pct.unwrap(), large.path.hops[0].slippage_pct.unwrap());
+        assert!((small_pct - 0.01).abs() < 1e-6, "{}", small_pct);
+        assert!((large_pct - 5.0).abs() < 1e-6, "{}", large_pct);
+        assert!((small.path.slippage_pct().unwrap() - small_pct).abs() < 1e-9);
+        assert!((small.path.estimated_output.unwrap() - 995.0 * 0.9999).abs() < 1e-9);
+        assert_eq!(large.score_breakdown.estimated_output, large.path.estimated_output);
+
+        // 900k is 90% of every hop's liquidity
+        assert!(send(900_000.0).is_empty());
+        let capped = router.rank_routes(&RouteIntent { amount: 900_000.0, ..intent("0x3c49", Some("fastest")) }, &opts).unwrap();
+        assert_eq!(capped.diagnostics.dropped_for(DropReason::Utilization), 2);
+        let dust = router.rank_routes(&RouteIntent { amount: 0.01, ..intent("0x3c49", Some("fastest")) }, &opts).unwrap();
+        assert!(dust.ranked.is_empty());
+        assert_eq!(dust.diagnostics.dropped_for(DropReason::Fees), dust.diagnostics.candidates);
+        let lenient = router.clone().with_slippage(SlippageModel::Sqrt, 0.95);
+        let routes = lenient.best_routes(&RouteIntent { amount: 900_000.0, ..intent("0x3c49", Some("fastest")) }, &opts).unwrap();
+        assert!((routes[0].ranked.path.hops[0].slippage_pct.unwrap() - 10.0 * 0.9f64.sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn routing_params_apply_only_without_a_preference() {
+        let router = router();
+        let speed_only = RoutingParams { alpha: 0.0, beta: 1.0, gamma: 0.0, delta: 0.0, omega: 0.0, epsilon: 0.0, ..RoutingParams::default() };
+        let opts = RouteOptions { routing_params: Some(speed_only.clone()), ..RouteOptions::default() };
+
+        assert_eq!(bridges(&router.best_routes(&intent("0x3c49", None), &opts).unwrap()[0]), ["stargate"]);
+        assert_eq!(bridges(&router.best_routes(&intent("0x3c49", Some("cheapest")), &opts).unwrap()[0]), ["wormhole", "across"]);
+
+        let zero = RouteOptions { routing_params: Some(RoutingParams { beta: 0.0, ..speed_only }), ..RouteOptions::default() };
+        assert_eq!(router.best_routes(&intent("0x3c49", None), &zero).unwrap_err(), RouteError::Params(ParamError::ZeroSum));
+    }
+
+    #[test]
+    fn swaps_either_side_of_a_bridge_are_routed_and_capped() {
+        // USDT moves between chains only as USDC, swapped on a DEX at both ends
+        let graph = Graph::new(16);
+        let usdt_eth = graph.get_or_create_asset_node("ethereum", "0xdac1", "USDT");
+        let usdc_eth = graph.get_or_create_asset_node("ethereum", "0xa0b8", "USDC");
+        let usdc_pol = graph.get_or_create_asset_node("polygon", "0x3c49", "USDC");
+        let usdt_pol = graph.get_or_create_asset_node("polygon", "0xc213", "USDT");
+        let metrics = |cost: f64, speed: f64| EdgeMetrics { cost, speed, liquidity: 1_000_000.0, risk: 0.01 };
+        graph.add_swap_edge(usdt_eth, usdc_eth, "uniswap", metrics(0.3, 12.0), None, None).unwrap();
+        graph.add_edge(usdc_eth, usdc_pol, "stargate", metrics(1.0, 60.0), None, None).unwrap();
+        graph.add_swap_edge(usdc_pol, usdt_pol, "quickswap", metrics(0.2, 2.0), None, None).unwrap();
+        // Swaps stay on one chain and join assets only
+        let exchange = graph.get_or_create_exchange_node("uniswap", "ethereum");
+        for (from, to) in [(usdt_eth, usdt_pol), (usdt_eth, usdt_eth), (exchange, usdc_eth)] {
+            assert_eq!(graph.add_swap_edge(from, to, "uniswap", metrics(0.3, 12.0), None, None), Err(GraphError::InvalidSwap { from, to }));
+        }
+        let router = Router::new(Arc::new(graph));
+        let intent = RouteIntent { from_token: "USDT".to_string(), to_token: "USDT".to_string(), ..intent("", Some("cheapest")) };
+
+        let routes = router.best_routes(&intent, &RouteOptions::default()).unwrap();
+        let hops = &routes[0].ranked.path.hops;
+        assert_eq!(bridges(&routes[0]), ["uniswap", "stargate", "quickswap"]);
+        assert_eq!(hops.iter().map(|hop| hop.kind).collect::<Vec<_>>(), [EdgeKind::Swap, EdgeKind::Bridge, EdgeKind::Swap]);
+        assert_eq!(serde_json::to_value(&hops[0]).unwrap()["kind"], "swap");
+
+        let capped = |max_swaps: usize| router.best_routes(&intent, &RouteOptions { max_swaps: Some(max_swaps), ..RouteOptions::default() }).unwrap();
+        assert_eq!(capped(2).len(), 1);
+        assert!(capped(1).is_empty());
+        assert!(capped(0).is_empty());
+        // Only bridge hops are left when swaps are ruled out
+        let bridged = RouteIntent { to_token: "USDC".to_string(), from_token: "0xa0b8".to_string(), ..intent.clone() };
+        assert_eq!(bridges(&router.best_routes(&bridged, &RouteOptions { max_swaps: Some(0), ..RouteOptions::default() }).unwrap()[0]), ["stargate"]);
+
+        // Snapshots keep swap edges swaps
+        let restored = Graph::from_snapshot(router.graph().snapshot(), 4).unwrap();
+        assert_eq!(restored.get_outgoing_edges(usdt_eth)[0].kind, EdgeKind::Swap);
+    }
+
+    #[test]
+    fn bad_intents_are_errors() {
+        let router = router();
+        let opts = RouteOptions::default();
+
+        assert_eq!(
+            router.best_routes(&intent("DAI", None), &opts).unwrap_err(),
+            RouteError::UnknownToken { chain: "Polygon".to_string(), token: "DAI".to_string() }
+        );
+        assert_eq!(
+            router.best_routes(&intent("0x3c49", Some("scenic")), &opts).unwrap_err(),
+            RouteError::UnknownPreference("scenic".to_string())
+        );
+        let free = RouteIntent { amount: 0.0, ..intent("0x3c49", None) };
+        assert_eq!(router.best_routes(&free, &opts).unwrap_err(), RouteError::InvalidAmount(0.0));
+
+        // USDC and USDC.e are distinct symbols, so the symbol alone is enough here
+        assert!(router.resolve("polygon", "usdc").is_ok());
+        router.graph().get_or_create_asset_node("polygon", "0x9999", "usdc");
+        assert!(matches!(router.resolve("polygon", "USDC"), Err(RouteError::AmbiguousToken { matches: 2, .. })));
+    }
+
+    #[test]
+    fn answered_queries_reach_the_observer() {
+        #[derive(Default)]
+        struct Served(std::sync::Mutex<Vec<(String, usize, u64)>>);
+        impl RouteObserver for Served {
+            fn routes_served(&self, intent: &RouteIntent, _: &RouteOptions, routes: &[ExplainedPath], graph_version: u64) {
+                self.0.lock().unwrap().push((intent.to_token.clone(), routes.len(), graph_version));
+            }
+        }
+
+        let served = Arc::new(Served::default());
+        let router = router().with_observer(Arc::clone(&served) as Arc<dyn RouteObserver>);
+        let version = router.graph().version();
+        let quick = RouteOptions { constraints: RouteConstraints { max_time: Some(300.0), ..RouteConstraints::default() }, ..RouteOptions::default() };
+        router.best_routes(&intent("0x3c49", Some("cheapest")), &RouteOptions::default()).unwrap();
+        router.best_routes(&intent("0x3c49", Some("cheapest")), &quick).unwrap();
+        // Errors aren't answers
+        router.best_routes(&intent("DAI", None), &RouteOptions::default()).unwrap_err();
+        assert_eq!(*served.0.lock().unwrap(), [("0x3c49".to_string(), 2, version), ("0x3c49".to_string(), 1, version)]);
+    }
+
+    #[test]
+    fn pruning_stops_the_search_once_the_rest_cant_rank() {
+        // One cheap bridge straight across and eight dear detours
+        let graph = Graph::new(16);
+        let eth = graph.get_or_create_asset_node("ethereum", "0xa0b8", "USDC");
+        let pol = graph.get_or_create_asset_node("polygon", "0x3c49", "USDC");
+        let metrics = |cost: f64| EdgeMetrics { cost, speed: 60.0, liquidity: 1_000_000.0, risk: 0.1 };
+        graph.add_edge(eth, pol, "stargate", metrics(1.0), None, None).unwrap();
+        for index in 0..8 {
+            let via = graph.get_or_create_asset_node(&format!("chain{}", index), "0xdead", "USDC");
+            graph.add_edge(eth, via, "wormhole", metrics(40.0 + index as f64), None, None).unwrap();
+            graph.add_edge(via, pol, "across", metrics(40.0), None, None).unwrap();
+        }
+        let router = Router::new(Arc::new(graph));
+        let query = intent("0x3c49", Some("cheapest"));
+        let rank = |pruning: Option<PruningRule>| router.rank_routes(&query, &RouteOptions { max_results: 6, pruning, ..RouteOptions::default() }).unwrap();
+
+        let unpruned = rank(None);
+        assert_eq!(unpruned.diagnostics.search.found, 6);
+        assert_eq!(unpruned.diagnostics.search.pruned, 0);
+
+        let worse_by = rank(Some(PruningRule::StopWhenWorseBy { factor: 3.0 }));
+        assert_eq!((worse_by.diagnostics.search.found, worse_by.diagnostics.search.pruned), (1, 5));
+        assert!(worse_by.diagnostics.search.expanded < unpruned.diagnostics.search.expanded);
+        assert_eq!(worse_by.diagnostics.candidates, 1);
+
+        let stable = rank(Some(PruningRule::StopAfterStable { n: 2 }));
+        assert_eq!((stable.diagnostics.search.found, stable.diagnostics.search.pruned), (3, 3));
+
+        for pruned in [&worse_by, &stable] {
+            let paths = |outcome: &RankingOutcome<ExplainedPath>| outcome.ranked.iter().map(|route| bridges(route).join(",")).collect::<Vec<_>>();
+            assert_eq!(paths(pruned)[0], "stargate");
+            assert_eq!(paths(pruned), paths(&unpruned)[..pruned.ranked.len()]);
+        }
+
+        let invalid = router.rank_routes(&query, &RouteOptions { pruning: Some(PruningRule::StopWhenWorseBy { factor: 0.0 }), ..RouteOptions::default() });
+        assert!(matches!(invalid, Err(RouteError::InvalidPruning(_))));
+    }
+
+    #[test]
+    fn query_stages_are_timed() {
+        #[derive(Default)]
+        struct Stages(std::sync::Mutex<Vec<(&'static str, String)>>);
+        impl StageTimer for Stages {
+            fn stage_timed(&self, stage: &'static str, _: Duration, intent: &RouteIntent) {
+                self.0.lock().unwrap().push((stage, intent.to_token.clone()));
+            }
+        }
+
+        let stages = Arc::new(Stages::default());
+        let router = router().with_stage_timer(Arc::clone(&stages) as Arc<dyn StageTimer>);
+        router.best_routes(&intent("0x3c49", Some("cheapest")), &RouteOptions::default()).unwrap();
+        // Nothing is searched for an asset the graph doesn't know
+        router.best_routes(&intent("DAI", None), &RouteOptions::default()).unwrap_err();
+        assert_eq!(*stages.0.lock().unwrap(), [(ROUTE_SEARCH_STAGE, "0x3c49".to_string()), (SCORING_STAGE, "0x3c49".to_string())]);
+    }
+
+    #[test]
+    fn queries_wait_until_the_graph_covers_enough_pairs() {
+        let router = router().with_min_coverage(0.6);
+        let query = intent("0x3c49", Some("cheapest"));
+        // Nobody measured this graph
+        assert!(router.best_routes(&query, &RouteOptions::default()).is_ok());
+
+        let coverage = |fraction: f64| Coverage { fraction, configured: 5, fresh: 3, stale: 0, missing_sample: vec!["wormhole:polygon".to_string()], warmed_up: false };
+        router.graph().set_coverage(coverage(0.4));
+        let err = router.best_routes(&query, &RouteOptions::default()).unwrap_err();
+        assert_eq!(err, RouteError::Warmup { coverage: 0.4, missing_pairs_sample: vec!["wormhole:polygon".to_string()] });
+        assert_eq!(err.to_string(), "the graph is still warming up, 40% of its pairs are quoted");
+        let partial = RouteOptions { allow_partial: true, ..RouteOptions::default() };
+        assert_eq!(router.best_routes(&query, &partial).unwrap().len(), 2);
+
+        router.graph().set_coverage(coverage(0.6));
+        assert_eq!(router.best_routes(&query, &RouteOptions::default()).unwrap().len(), 2);
+        // A graph that warmed up once keeps answering through a dip
+        router.graph().set_coverage(Coverage { warmed_up: true, ..coverage(0.4) });
+        assert_eq!(router.best_routes(&query, &RouteOptions::default()).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn pickers_list_what_is_reachable_by_chain() {
+        let router = router();
+        let to_polygon = |hops: usize| ReachableToken { address: "0x3c49".to_string(), symbol: "USDC".to_string(), min_hops: hops };
+        let reachable = router.reachable_destinations("Ethereum", "usdc", 4).unwrap();
+        assert_eq!(reachable.keys().collect::<Vec<_>>(), ["arbitrum", "polygon"]);
+        assert_eq!(reachable["polygon"], [to_polygon(1)]);
+        assert_eq!(reachable["arbitrum"][0].min_hops, 1);
+        let json = serde_json::to_value(&reachable).unwrap();
+        assert_eq!(json["polygon"][0]["symbol"], "USDC");
+
+        let sources = router.reachable_sources("polygon", "0x3c49", 1).unwrap();
+        assert_eq!(sources.keys().collect::<Vec<_>>(), ["arbitrum", "ethereum"]);
+        // USDC.e on polygon is reachable from nowhere and reaches nothing
+        assert!(router.reachable_sources("polygon", "USDC.e", 4).unwrap().is_empty());
+        assert!(matches!(router.reachable_destinations("polygon", "DAI", 4), Err(RouteError::UnknownToken { .. })));
+    }
+
+    #[tokio::test]
+    async fn routes_are_annotated_with_whether_the_sender_can_pay_the_gas() {
+        // Stargate's gas is covered, wormhole's isn't and hop's balance can't be read
+        struct Balances;
+        #[async_trait]
+        impl BalanceChecker for Balances {
+            async fn affordability(&self, chain: &str, bridge: &str, address: &str) -> Affordability {
+                assert_eq!((chain, address), ("ethereum", "0xa11ce"));
+                match bridge {
+                    "stargate" => Affordability::Affordable,
+                    "wormhole" => Affordability::InsufficientGas { required: 0.004, available: 0.001 },
+                    _ => Affordability::Unknown,
+                }
+            }
+        }
+
+        let router = router();
+        let (eth, pol) = (router.resolve("ethereum", "usdc").unwrap(), router.resolve("polygon", "0x3c49").unwrap());
+        let metrics = EdgeMetrics { cost: 3.0, speed: 300.0, liquidity: 1_000_000.0, risk: 0.1 };
+        router.graph().add_edge(eth, pol, "hop", metrics, None, None).unwrap();
+        let opts = RouteOptions::default();
+        let query = RouteIntent { src_address: Some("0xa11ce".to_string()), ..intent("0x3c49", None) };
+        // One route per first bridge, ranked in that order
+        let routes: Vec<ExplainedPath> = [vec!["hop", "wormhole"], vec!["stargate", "hop"], vec!["stargate", "wormhole"]]
+            .into_iter()
+            .enumerate()
+            .map(|(index, excluded)| {
+                let excluded = RouteOptions { excluded_bridges: excluded.into_iter().map(String::from).collect(), ..RouteOptions::default() };
+                let mut route = router.best_routes(&query, &excluded).unwrap().remove(0);
+                route.ranked.rank = index + 1;
+                route
+            })
+            .collect();
+        let order: Vec<Vec<&str>> = routes.iter().map(bridges).collect();
+        assert_eq!(order, [vec!["stargate"], vec!["wormhole", "across"], vec!["hop"]]);
+
+        // Nothing to check without a checker or an address
+        let unchecked = router.check_affordability(&query, &opts, routes.clone()).await;
+        assert!(unchecked.iter().all(|route| route.ranked.affordability.is_none()));
+        let checked_router = router.with_balance_checker(Arc::new(Balances));
+        let anonymous = checked_router.check_affordability(&intent("0x3c49", None), &opts, routes.clone()).await;
+        assert!(anonymous.iter().all(|route| route.ranked.affordability.is_none()));
+
+        let checked = checked_router.check_affordability(&query, &opts, routes.clone()).await;
+        assert_eq!(checked.iter().map(bridges).collect::<Vec<_>>(), order);
+        let annotations: Vec<Option<Affordability>> = checked.iter().map(|route| route.ranked.affordability.clone()).collect();
+        assert_eq!(annotations, [
+            Some(Affordability::Affordable),
+            Some(Affordability::InsufficientGas { required: 0.004, available: 0.001 }),
+            Some(Affordability::Unknown),
+        ]);
+        let json = serde_json::to_value(&checked[0].ranked).unwrap();
+        assert!(json["affordability"]["status"].is_string(), "{}", json);
+
+        // Filtering drops only what's known to be unaffordable, and keeps the ranks
+        let affordable_only = RouteOptions { affordable_only: true, ..RouteOptions::default() };
+        let kept = checked_router.check_affordability(&query, &affordable_only, routes.clone()).await;
+        let kept: Vec<(Vec<&str>, usize)> = kept.iter().map(|route| (bridges(route), route.ranked.rank)).collect();
+        assert_eq!(kept, [(vec!["stargate"], 1), (vec!["hop"], 3)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn watches_emit_only_on_meaningful_changes() {
+        use futures::StreamExt;
+
+        let router = router().with_watch_settings(WatchSettings { min_interval: Duration::from_secs(1), tolerance: 0.1 });
+        let graph = Arc::clone(router.graph());
+        let node = |chain: &str, token: &str| NodeId::from_parts(chain, token);
+        let (eth, arb, pol) = (node("ethereum", "0xa0b8"), node("arbitrum", "0xaf88"), node("polygon", "0x3c49"));
+        let metrics = |cost: f64, speed: f64| EdgeMetrics { cost, speed, liquidity: 1_000_000.0, risk: 0.1 };
+        let mut updates = Box::pin(router.watch(intent("0x3c49", Some("cheapest")), RouteOptions::default()));
+        let quiet = Duration::from_secs(5);
+        let bridges = |update: &RouteUpdate| -> Vec<String> {
+            update.new_ranked[0].path.hops.iter().map(|hop| hop.bridge_name.to_string()).collect()
+        };
+
+        let initial = updates.next().await.unwrap();
+        assert_eq!(initial.reason, UpdateReason::Initial);
+        assert_eq!(bridges(&initial), ["wormhole", "across"]);
+        assert_eq!(initial.diff, None);
+
+        // Cheaper best routes and changes to the others are not worth a push
+        graph.update_edge_metrics(eth, pol, "stargate", metrics(4.0, 60.0)).unwrap();
+        graph.update_edge_metrics(eth, arb, "wormhole", metrics(0.9, 600.0)).unwrap();
+        assert!(tokio::time::timeout(quiet, updates.next()).await.is_err());
+
+        // Within the tolerance of what was sent, then beyond it
+        graph.update_edge_metrics(arb, pol, "across", metrics(1.05, 120.0)).unwrap();
+        assert!(tokio::time::timeout(quiet, updates.next()).await.is_err());
+        graph.update_edge_metrics(arb, pol, "across", metrics(1.5, 120.0)).unwrap();
+        let degraded = updates.next().await.unwrap();
+        assert_eq!(degraded.reason, UpdateReason::Degraded);
+        assert_eq!(degraded.graph_version, graph.version());
+        // Against the route as first sent, not the cheaper one in between
+        let diff = degraded.diff.as_ref().unwrap();
+        assert_eq!((diff.severity, diff.total_cost.old, diff.total_cost.new), (ChangeSeverity::MetricsShift, 2.0, 2.4));
+
+        graph.update_edge_metrics(eth, pol, "stargate", metrics(1.0, 60.0)).unwrap();
+        let changed = updates.next().await.unwrap();
+        assert_eq!(changed.reason, UpdateReason::BestChanged);
+        assert_eq!(bridges(&changed), ["stargate"]);
+        assert_eq!(changed.diff.as_ref().unwrap().severity, ChangeSeverity::TopologyChange);
+
+        // Updates right after another wait out the interval, and a burst makes one update
+        let sent_at = Instant::now();
+        graph.set_edge_active(eth, pol, "stargate", false);
+        let broken = updates.next().await.unwrap();
+        assert_eq!(broken.reason, UpdateReason::Broken);
+        assert_eq!(bridges(&broken), ["wormhole", "across"]);
+        assert_eq!(sent_at.elapsed(), Duration::from_secs(1));
+        let sent_at = Instant::now();
+        graph.set_edge_active(eth, arb, "wormhole", false);
+        graph.set_edge_active(arb, pol, "across", false);
+        let gone = updates.next().await.unwrap();
+        assert_eq!(gone.reason, UpdateReason::Broken);
+        assert!(gone.new_ranked.is_empty() && gone.diff.is_none());
+        assert_eq!(sent_at.elapsed(), Duration::from_secs(1));
+        assert!(tokio::time::timeout(quiet, updates.next()).await.is_err());
+
+        graph.set_edge_active(eth, pol, "stargate", true);
+        assert_eq!(updates.next().await.unwrap().reason, UpdateReason::BestChanged);
+    }
+
+    // ethereum -> polygon USDC over two parallel bridges, routed by mostly cost and some speed
+    fn parallel_bridges() -> (Arc<Graph>, NodeId, NodeId, RouteOptions) {
+        let graph = Graph::new(16);
+        let eth = graph.get_or_create_asset_node("ethereum", "0xa0b8", "USDC");
+        let pol = graph.get_or_create_asset_node("polygon", "0x3c49", "USDC");
+        let metrics = |cost: f64, speed: f64| EdgeMetrics { cost, speed, liquidity: 1_000_000.0, risk: 0.1 };
+        graph.add_edge(eth, pol, "stargate", metrics(2.0, 10.0), None, None).unwrap();
+        graph.add_edge(eth, pol, "across", metrics(3.0, 11.0), None, None).unwrap();
+        let params = RoutingParams { alpha: 0.5, beta: 0.45, gamma: 0.0, delta: 0.0, omega: 0.0, epsilon: 0.0, ..RoutingParams::default() };
+        (Arc::new(graph), eth, pol, RouteOptions { routing_params: Some(params), ..RouteOptions::default() })
+    }
+
+    #[test]
+    fn confirmed_routes_hold_until_a_new_one_beats_the_bonus() {
+        let (graph, eth, pol, opts) = parallel_bridges();
+        let router = Router::new(Arc::clone(&graph)).with_stickiness(Arc::new(MemoryPinStore::new()), 0.1);
+        let intent = intent("0x3c49", None);
+        let first = router.best_routes(&intent, &opts).unwrap();
+        assert_eq!(bridges(&first[0]), ["stargate"]);
+        router.confirm_selection(&intent.intent_hash(), &first[0].ranked);
+
+        // across is now a little better: cheaper, if slower
+        let metrics = |cost: f64, speed: f64| EdgeMetrics { cost, speed, liquidity: 1_000_000.0, risk: 0.1 };
+        graph.update_edge_metrics(eth, pol, "across", metrics(1.0, 11.0)).unwrap();
+        let unpinned = Router::new(Arc::clone(&graph)).best_routes(&intent, &opts).unwrap();
+        assert_eq!(unpinned.iter().map(bridges).collect::<Vec<_>>(), [["across"]]);
+        let sticky = router.best_routes(&intent, &opts).unwrap();
+        assert_eq!(sticky.iter().map(bridges).collect::<Vec<_>>(), [["stargate"], ["across"]]);
+        assert_eq!((sticky[0].ranked.rank, sticky[1].ranked.rank), (1, 2));
+        let pinned = sticky[0].pinned.as_ref().unwrap();
+        assert_eq!((pinned.shared_hops, pinned.pinned_hops, pinned.bonus), (1, 1, 0.1));
+        // Cost outweighs speed by 0.05 of the 0.95 the weights add up to
+        assert!((pinned.gap_to_best - 0.05 / 0.95).abs() < 1e-9, "{}", pinned.gap_to_best);
+        assert!(sticky[0].summary.starts_with("kept on the last confirmed route (1 of 1 hops), 0.0526 below the best score; "), "{}", sticky[0].summary);
+        assert!(sticky[1].summary.starts_with("best for the selected weights, 0.0526 above the pinned route"), "{}", sticky[1].summary);
+        assert!(sticky[1].pinned.is_none());
+
+        // Faster as well, across is ahead by more than the bonus
+        graph.update_edge_metrics(eth, pol, "across", metrics(1.0, 9.0)).unwrap();
+        let moved = router.best_routes(&intent, &opts).unwrap();
+        assert_eq!(moved.iter().map(bridges).collect::<Vec<_>>(), [["across"], ["stargate"]]);
+        assert!(moved[0].summary.starts_with("ranked first for the selected weights"), "{}", moved[0].summary);
+        assert!((moved[1].pinned.as_ref().unwrap().gap_to_best - 1.0).abs() < 1e-9);
+
+        // With one result asked for, the pinned route is still the one kept while it's ahead
+        graph.update_edge_metrics(eth, pol, "across", metrics(1.0, 11.0)).unwrap();
+        let one = RouteOptions { max_results: 1, ..opts.clone() };
+        let outcome = router.rank_routes(&intent, &one).unwrap();
+        assert_eq!(outcome.ranked.iter().map(bridges).collect::<Vec<_>>(), [["stargate"]]);
+        assert_eq!(outcome.diagnostics.dropped_for(DropReason::Truncated), 1);
+    }
+
+    #[test]
+    fn unusable_pinned_routes_fall_back_to_the_best_one() {
+        let (graph, eth, pol, opts) = parallel_bridges();
+        let router = Router::new(Arc::clone(&graph)).with_stickiness(Arc::new(MemoryPinStore::new()), 0.1);
+        let intent = intent("0x3c49", None);
+        let confirmed = router.best_routes(&intent, &opts).unwrap().remove(0).ranked;
+        router.confirm_selection(&intent.intent_hash(), &confirmed);
+        let metrics = |cost: f64, speed: f64| EdgeMetrics { cost, speed, liquidity: 1_000_000.0, risk: 0.1 };
+        graph.update_edge_metrics(eth, pol, "across", metrics(1.0, 11.0)).unwrap();
+        assert_eq!(bridges(&router.best_routes(&intent, &opts).unwrap()[0]), ["stargate"]);
+
+        // Constraints the pinned route breaks rule it out like any other
+        let quick = RouteOptions { constraints: RouteConstraints { max_cost: Some(1.5), ..RouteConstraints::default() }, ..opts.clone() };
+        let routes = router.best_routes(&intent, &quick).unwrap();
+        assert_eq!(routes.iter().map(bridges).collect::<Vec<_>>(), [["across"]]);
+        let excluded = RouteOptions { excluded_bridges: vec!["stargate".to_string()], ..opts.clone() };
+        assert_eq!(router.best_routes(&intent, &excluded).unwrap().iter().map(bridges).collect::<Vec<_>>(), [["across"]]);
+
+        // As does its edge going inactive
+        graph.set_edge_active(eth, pol, "stargate", false);
+        let routes = router.best_routes(&intent, &opts).unwrap();
+        assert_eq!(routes.iter().map(bridges).collect::<Vec<_>>(), [["across"]]);
+        assert!(routes[0].pinned.is_none());
+        assert!(routes[0].summary.starts_with("ranked first for the selected weights"), "{}", routes[0].summary);
+
+        // Or stale, as restored from a snapshot and not quoted since
+        graph.set_edge_active(eth, pol, "stargate", true);
+        let restored = Arc::new(Graph::from_snapshot(graph.snapshot(), 16).unwrap());
+        restored.update_edge_metrics(eth, pol, "across", metrics(1.0, 11.0)).unwrap();
+        let router = Router::new(Arc::clone(&restored)).with_stickiness(Arc::new(MemoryPinStore::new()), 0.1);
+        router.confirm_selection(&intent.intent_hash(), &confirmed);
+        let routes = router.best_routes(&intent, &opts).unwrap();
+        assert_eq!(routes.iter().map(bridges).collect::<Vec<_>>(), [["across"]]);
+        restored.update_edge_metrics(eth, pol, "stargate", metrics(2.0, 10.0)).unwrap();
+        assert_eq!(bridges(&router.best_routes(&intent, &opts).unwrap()[0]), ["stargate"]);
+    }
+}
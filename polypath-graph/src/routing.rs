@@ -1,27 +1,46 @@
-use crate::graph::Graph;
+use crate::confidence::ConfidenceModel;
+use crate::graph::{Graph, compute_edge_weight};
+use crate::view::GraphRead;
+use crate::pinning::PinnedEdge;
+use crate::slippage::{SentAmount, SlippageModel};
 use crate::types::*;
 use core::f64;
+use serde::{Deserialize, Serialize};
 use std::{
-    sync::Arc,
+    sync::{Arc, Mutex},
     cmp::Ordering,
     collections::{
         BinaryHeap, HashMap, HashSet
     }
 };
 
-#[derive(Clone, PartialEq)]
+// A node reached after `hops` hops, `swaps` of them swaps. The search keys everything by
+// (node, hops, swaps), so a cheap route that used up the hop or swap budget can't hide a dearer
+// one that still fits under it.
+type SearchKey = (NodeId, usize, usize);
+
+// Most finished searches' buffers an engine keeps for the next ones
+const SCRATCH_POOL: usize = 8;
+// Entries a pooled search's maps keep room for; one through a huge graph gives the rest back
+const SCRATCH_KEPT: usize = 16_384;
+
+#[derive(Debug, Clone)]
 struct State {
     node: NodeId,
     g_score: f64, // Cost from start
     f_score: f64, // Estimated total cost
-    hops: usize
+    hops: usize,
+    swaps: usize,
 }
 
-impl Eq for State {}
-
+// Min-heap on f_score, then fewer hops, then node id, so equal scores pop in a fixed order
 impl Ord for State {
     fn cmp(&self, other: &Self) -> Ordering {
-        other.f_score.partial_cmp(&self.f_score).unwrap_or(Ordering::Equal)
+        other.f_score.total_cmp(&self.f_score)
+            .then_with(|| other.hops.cmp(&self.hops))
+            .then_with(|| other.swaps.cmp(&self.swaps))
+            .then_with(|| other.node.cmp(&self.node))
+            .then_with(|| other.g_score.total_cmp(&self.g_score))
     }
 }
 
@@ -31,21 +50,350 @@ impl PartialOrd for State {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct RoutingEngine {
-    graph: Arc<Graph>,
+impl PartialEq for State {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for State {}
+
+// What find_path fills as it searches. Kept between searches, emptied but with their capacity,
+// so a search on a large graph doesn't grow its maps from nothing each time.
+#[derive(Debug, Default)]
+struct SearchScratch {
+    open_set: BinaryHeap<State>,
+    // The edge into each search key, with the metrics its weight was computed from
+    came_from: HashMap<SearchKey, (SearchKey, Arc<Edge>, EdgeMetrics)>,
+    g_score: HashMap<SearchKey, f64>,
+    visited: HashSet<SearchKey>,
+}
+
+impl SearchScratch {
+    fn clear(&mut self) {
+        self.open_set.clear();
+        self.came_from.clear();
+        self.g_score.clear();
+        self.visited.clear();
+        self.open_set.shrink_to(SCRATCH_KEPT);
+        self.came_from.shrink_to(SCRATCH_KEPT);
+        self.g_score.shrink_to(SCRATCH_KEPT);
+        self.visited.shrink_to(SCRATCH_KEPT);
+    }
+}
+
+// Shared by an engine's clones, which may search at once from several threads
+#[derive(Debug, Default)]
+struct ScratchPool(Mutex<Vec<SearchScratch>>);
+
+impl ScratchPool {
+    fn take(&self) -> SearchScratch {
+        self.0.lock().unwrap().pop().unwrap_or_default()
+    }
+
+    fn put(&self, mut scratch: SearchScratch) {
+        scratch.clear();
+        let mut pool = self.0.lock().unwrap();
+        if pool.len() < SCRATCH_POOL {
+            pool.push(scratch);
+        }
+    }
+}
+
+// When the candidate search may stop before it has all the paths it was asked for, e.g. when
+// the rest couldn't rank anyway
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PruningRule {
+    // Once the next path's raw weight would be over the best one's times `factor`, at least 1.
+    // Applied by CandidatePaths itself, which stops as soon as nothing left to search can be
+    // under it; a best path weighing nothing is no measure, so then it isn't applied.
+    StopWhenWorseBy { factor: f64 },
+    // Once the best scoring candidate has stayed the same over `n` further ones. Applied by
+    // whoever scores the candidates, see Router and CandidatePaths::stop.
+    StopAfterStable { n: usize },
+}
+
+impl PruningRule {
+    // Why the rule can't be searched with, if it can't
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            PruningRule::StopWhenWorseBy { factor } if !(factor.is_finite() && factor >= 1.0) => {
+                Err(format!("stop_when_worse_by factor must be a finite number of at least 1, got {}", factor))
+            }
+            PruningRule::StopAfterStable { n: 0 } => Err("stop_after_stable needs at least 1 candidate".to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Partial paths one candidate search keeps at most, see CandidatePaths::with_max_labels
+pub const MAX_SEARCH_LABELS: usize = 250_000;
+
+// What one candidate search did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct SearchStats {
+    // Partial paths extended by another hop
+    pub expanded: usize,
+    // Complete paths handed out
+    pub found: usize,
+    // Paths the search was asked for but never looked for, as a pruning rule ended it
+    pub pruned: usize,
+    // Whether partial paths went unextended as the search had as many as it keeps
+    pub capped: bool,
+}
+
+// A partial path of the candidate search: its last hop onto `node` and the label before it
+struct Label {
+    parent: Option<usize>,
+    node: NodeId,
+    step: Option<(Arc<Edge>, EdgeMetrics)>,
+    weight: f64,
+    hops: usize,
+    swaps: usize,
+}
+
+// Min-heap on weight, then fewer hops, then the label found first
+struct Frontier {
+    weight: f64,
+    hops: usize,
+    label: usize,
+}
+
+impl Ord for Frontier {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.weight.total_cmp(&self.weight)
+            .then_with(|| other.hops.cmp(&self.hops))
+            .then_with(|| other.label.cmp(&self.label))
+    }
+}
+
+impl PartialOrd for Frontier {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Frontier {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Frontier {}
+
+// Paths from a start node to an end node by raw weight, cheapest first, no node twice in one
+// path and no two paths through the same nodes. Partial paths are extended best first, so
+// asking for one more path only searches as far as it takes to find it. See
+// RoutingEngine::candidate_paths.
+pub struct CandidatePaths<'a, G> {
+    engine: &'a RoutingEngine<G>,
+    end: NodeId,
+    params: RoutingParams,
+    graph_version: u64,
+    max_paths: usize,
+    labels: Vec<Label>,
+    frontier: BinaryHeap<Frontier>,
+    // Node sequences already extended or handed out; the first label through them was the cheapest
+    settled: HashSet<Vec<NodeId>>,
+    best_weight: Option<f64>,
+    pruning: Option<PruningRule>,
+    max_labels: usize,
+    stats: SearchStats,
+    stopped: bool,
+}
+
+impl<G: GraphRead> CandidatePaths<'_, G> {
+    pub fn with_pruning(mut self, pruning: Option<PruningRule>) -> Self {
+        self.pruning = pruning;
+        self
+    }
+
+    // Keeps at most `max_labels` partial paths, MAX_SEARCH_LABELS unless set; once it has them,
+    // only the paths they already lead to are found
+    pub fn with_max_labels(mut self, max_labels: usize) -> Self {
+        self.max_labels = max_labels;
+        self
+    }
+
+    pub fn stats(&self) -> SearchStats {
+        self.stats
+    }
+
+    // Ends the search, counting the paths not yet found as pruned
+    pub fn stop(&mut self) {
+        if !self.stopped {
+            self.stopped = true;
+            self.stats.pruned = self.max_paths.saturating_sub(self.stats.found);
+        }
+    }
+
+    fn nodes(&self, mut label: usize) -> Vec<NodeId> {
+        let mut nodes = vec![self.labels[label].node];
+        while let Some(parent) = self.labels[label].parent {
+            nodes.push(self.labels[parent].node);
+            label = parent;
+        }
+        nodes.reverse();
+        nodes
+    }
+
+    fn path(&self, mut label: usize) -> Path {
+        let mut steps = Vec::new();
+        while let (Some(step), Some(parent)) = (&self.labels[label].step, self.labels[label].parent) {
+            steps.push((&step.0, &step.1));
+            label = parent;
+        }
+        steps.reverse();
+        self.engine.build_path(steps, self.graph_version)
+    }
+
+    fn extend(&mut self, label: usize, nodes: &[NodeId]) {
+        let (node, weight, hops, swaps) = {
+            let label = &self.labels[label];
+            (label.node, label.weight, label.hops, label.swaps)
+        };
+        self.stats.expanded += 1;
+        let engine = self.engine;
+        engine.graph.for_each_outgoing_edge(node, |edge| {
+            if engine.is_excluded(edge) || nodes.contains(&edge.to) {
+                return;
+            }
+            let swaps = swaps + usize::from(edge.kind == EdgeKind::Swap);
+            if engine.max_swaps.is_some_and(|max| swaps > max) {
+                return;
+            }
+            if self.labels.len() >= self.max_labels {
+                self.stats.capped = true;
+                return;
+            }
+            let metrics = edge.get_metrics();
+            let weight = weight + engine.edge_weight(edge, &metrics, &self.params);
+            self.frontier.push(Frontier { weight, hops: hops + 1, label: self.labels.len() });
+            self.labels.push(Label { parent: Some(label), node: edge.to, step: Some((Arc::clone(edge), metrics)), weight, hops: hops + 1, swaps });
+        });
+    }
+}
+
+impl<G: GraphRead> Iterator for CandidatePaths<'_, G> {
+    type Item = Path;
+
+    fn next(&mut self) -> Option<Path> {
+        if self.stopped || self.stats.found >= self.max_paths {
+            return None;
+        }
+        while let Some(next) = self.frontier.pop() {
+            // Nothing left can weigh less than the cheapest partial path
+            if let (Some(PruningRule::StopWhenWorseBy { factor }), Some(best)) = (self.pruning, self.best_weight) {
+                if best > 0.0 && next.weight > best * factor {
+                    self.stop();
+                    return None;
+                }
+            }
+            let nodes = self.nodes(next.label);
+            if !self.settled.insert(nodes.clone()) {
+                continue;
+            }
+            if self.labels[next.label].node == self.end {
+                self.stats.found += 1;
+                self.best_weight.get_or_insert(next.weight);
+                return Some(self.path(next.label));
+            }
+            if next.hops < self.engine.max_hops {
+                self.extend(next.label, &nodes);
+            }
+        }
+        None
+    }
+}
+
+// Searches the live Graph, or a GraphView to run several searches against one version of it
+#[derive(Debug)]
+pub struct RoutingEngine<G = Graph> {
+    graph: Arc<G>,
     max_hops: usize,
+    // Most swap hops a path may have, on top of max_hops capping all of them; no limit when None
+    max_swaps: Option<usize>,
+    // Bridges whose edges are never taken
+    excluded_bridges: HashSet<String>,
+    // Rates the hops of the paths found
+    confidence: ConfidenceModel,
+    // What the output objective weighs edges at, see `with_sent_amount`
+    sent: Option<SentAmount>,
+    scratch: Arc<ScratchPool>,
 }
 
 
-impl RoutingEngine {
-    pub fn new(graph: Arc<Graph>, max_hops: usize) -> Self {
+// Not derived, which would need G: Clone
+impl<G> Clone for RoutingEngine<G> {
+    fn clone(&self) -> Self {
+        Self {
+            graph: Arc::clone(&self.graph),
+            max_hops: self.max_hops,
+            max_swaps: self.max_swaps,
+            excluded_bridges: self.excluded_bridges.clone(),
+            confidence: self.confidence.clone(),
+            sent: self.sent,
+            scratch: Arc::clone(&self.scratch),
+        }
+    }
+}
+
+impl<G: GraphRead> RoutingEngine<G> {
+    pub fn new(graph: Arc<G>, max_hops: usize) -> Self {
         Self {
             graph,
-            max_hops
+            max_hops,
+            max_swaps: None,
+            excluded_bridges: HashSet::new(),
+            confidence: ConfidenceModel::default(),
+            sent: None,
+            scratch: Arc::default(),
         }
     }
 
+    pub fn with_max_swaps(mut self, max_swaps: Option<usize>) -> Self {
+        self.max_swaps = max_swaps;
+        self
+    }
+
+    // Skips edges of these bridges. An aggregated edge such as "lifi:stargate" is skipped
+    // when either part is excluded.
+    pub fn with_excluded_bridges(mut self, bridges: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.excluded_bridges = bridges.into_iter().map(|bridge| bridge.into().to_lowercase()).collect();
+        self
+    }
+
+    pub fn with_confidence(mut self, confidence: ConfidenceModel) -> Self {
+        self.confidence = confidence;
+        self
+    }
+
+    // Weighs edges for the output objective by what they'd lose of `amount` to fees and
+    // slippage under `model`, so thin or slippy edges lose out to ones delivering more.
+    // Without it output is searched for like cost.
+    pub fn with_sent_amount(mut self, amount: f64, model: SlippageModel) -> Self {
+        self.sent = Some(SentAmount { amount, model });
+        self
+    }
+
+    fn edge_weight(&self, edge: &Edge, metrics: &EdgeMetrics, params: &RoutingParams) -> f64 {
+        let output_loss = match self.sent {
+            Some(sent) if params.omega > 0.0 => Some(sent.loss(edge.cost_in_source().unwrap_or(metrics.cost), metrics.liquidity)),
+            _ => None,
+        };
+        compute_edge_weight(metrics, params, output_loss)
+    }
+
+    fn is_excluded(&self, edge: &Edge) -> bool {
+        !self.excluded_bridges.is_empty()
+            && edge.bridge_name.split(':').any(|part| self.excluded_bridges.iter().any(|excluded| excluded.eq_ignore_ascii_case(part)))
+    }
+
+    pub fn graph(&self) -> &Arc<G> {
+        &self.graph
+    }
+
     // Using A* algorithm
     pub fn find_path(
         &self, 
@@ -54,63 +402,76 @@ impl RoutingEngine {
         params: &RoutingParams
     ) -> Option<Path> {
 
-        let mut open_set = BinaryHeap::new();
-        let mut came_from: HashMap<NodeId, (NodeId, Arc<Edge>)> = HashMap::new();
-        let mut g_score: HashMap<NodeId, f64> = HashMap::new();
-        let mut visited = HashSet::new();
+        let params = params.normalized();
+        let mut scratch = self.scratch.take();
+        let found = self.search(&mut scratch, start, end, &params);
+        self.scratch.put(scratch);
+        found
+    }
 
-        g_score.insert(start, 0.0);
+    // find_path in `scratch`. Edges are visited where the graph keeps them, and only those
+    // improving on a key's best so far are held on to.
+    fn search(&self, scratch: &mut SearchScratch, start: NodeId, end: NodeId, params: &RoutingParams) -> Option<Path> {
+        let graph_version = self.graph.version();
+        let SearchScratch { open_set, came_from, g_score, visited } = scratch;
+
+        g_score.insert((start, 0, 0), 0.0);
         open_set.push(State {
             node: start,
             g_score: 0.0,
             f_score: 0.0,
             hops: 0,
+            swaps: 0,
         });
 
         while let Some(current) = open_set.pop() {
+            let key = (current.node, current.hops, current.swaps);
             if current.node == end {
-                return Some(self.reconstruct_path(start, end, &came_from));
+                return Some(self.reconstruct_path(key, came_from, graph_version));
             }
 
-            if visited.contains(&current.node) || current.hops >= self.max_hops {
+            if current.hops >= self.max_hops || !visited.insert(key) {
                 continue;
             }
 
-            visited.insert(current.node);
-
-            let neighbours = self.graph.neighbours(current.node, params);
-
-            for (neighbor, edge_weight) in neighbours {
-                if visited.contains(&neighbor) {
-                    continue;
+            self.graph.for_each_outgoing_edge(current.node, |edge| {
+                if self.is_excluded(edge) {
+                    return;
+                }
+                let swaps = current.swaps + usize::from(edge.kind == EdgeKind::Swap);
+                if self.max_swaps.is_some_and(|max| swaps > max) {
+                    return;
+                }
+                let next = (edge.to, current.hops + 1, swaps);
+                if visited.contains(&next) {
+                    return;
                 }
 
-                let tentative_g = current.g_score + edge_weight;
-
-                if tentative_g < *g_score.get(&neighbor).unwrap_or(&f64::INFINITY) {
-                    // get actual edge for reconstruction
-                    let edges = self.graph.get_outgoing_edges(current.node);
-                    let edge = edges.iter().find(|e| e.to == neighbor)?;
+                let metrics = edge.get_metrics();
+                let tentative_g = current.g_score + self.edge_weight(edge, &metrics, params);
 
-                    came_from.insert(neighbor, (current.node, Arc::clone(edge)));
-                    g_score.insert(neighbor, tentative_g);
+                if tentative_g < *g_score.get(&next).unwrap_or(&f64::INFINITY) {
+                    g_score.insert(next, tentative_g);
 
-                    let h_score = self.heuristic(neighbor, end);
+                    let h_score = self.heuristic(edge.to, end);
                     let f_score = tentative_g + h_score;
 
                     open_set.push(State {
-                        node: neighbor,
+                        node: edge.to,
                         g_score: tentative_g,
                         f_score,
-                        hops: current.hops + 1
+                        hops: next.1,
+                        swaps,
                     });
+                    came_from.insert(next, (key, Arc::clone(edge), metrics));
                 }
-            }
+            });
         }
 
         None
     }
 
+    // The `max_paths` cheapest paths by raw weight with distinct nodes, cheapest first
     pub fn find_candidate_paths(
         &self,
         start: NodeId,
@@ -118,85 +479,137 @@ impl RoutingEngine {
         params: &RoutingParams,
         max_paths: usize,
     ) -> Vec<Path> {
-        let mut paths = Vec::new();
-        let mut visited_paths = HashSet::new();
-
-        // Try running A* multiple times and exclude previous paths/runs
+        self.candidate_paths(start, end, params, max_paths).collect()
+    }
 
-        for _ in 0..max_paths {
-            if let Some(path) = self.find_path_with_exclusions(start, end, params, None) {
-                let path_signature = self.path_signature(&path);
-                if !visited_paths.contains(&path_signature) {
-                    visited_paths.insert(path_signature);
-                    paths.push(path);
-                }
-            }
+    // find_candidate_paths as an iterator: each path is only searched for once it's asked for
+    pub fn candidate_paths(&self, start: NodeId, end: NodeId, params: &RoutingParams, max_paths: usize) -> CandidatePaths<'_, G> {
+        let mut frontier = BinaryHeap::new();
+        frontier.push(Frontier { weight: 0.0, hops: 0, label: 0 });
+        CandidatePaths {
+            engine: self,
+            end,
+            params: params.normalized(),
+            graph_version: self.graph.version(),
+            max_paths,
+            labels: vec![Label { parent: None, node: start, step: None, weight: 0.0, hops: 0, swaps: 0 }],
+            frontier,
+            settled: HashSet::new(),
+            best_weight: None,
+            pruning: None,
+            max_labels: MAX_SEARCH_LABELS,
+            stats: SearchStats::default(),
+            stopped: false,
         }
-
-        paths
     }
 
-    fn find_path_with_exclusions(
-        &self,
-        start: NodeId,
-        end: NodeId,
-        params: &RoutingParams,
-        exclude: Option<&HashSet<Vec<NodeId>>>
-    ) -> Option<Path> {
-        self.find_path(start, end, params)
+    // Other edges the search could have taken instead of `edge`, between the same two nodes
+    fn alternatives(&self, edge: &Edge) -> usize {
+        let mut alternatives = 0;
+        self.graph.for_each_outgoing_edge(edge.from, |other| {
+            if other.to == edge.to && other.bridge_name != edge.bridge_name && !self.is_excluded(other) {
+                alternatives += 1;
+            }
+        });
+        alternatives
     }
 
-    fn path_signature(&self, path: &Path) -> Vec<NodeId> {
-        path.hops.iter().map(|hop| hop.from).chain(
-            path.hops.last().map(|hop| hop.to)
-        ).collect()
+    // The path along `edges` as the graph has them now, e.g. to re-check a route found earlier.
+    // None when the edges don't join up, or one of them is gone, inactive, stale, excluded or
+    // over the hop or swap budget.
+    pub fn path_along(&self, edges: &[PinnedEdge]) -> Option<Path> {
+        if edges.is_empty() || edges.len() > self.max_hops {
+            return None;
+        }
+        let graph_version = self.graph.version();
+        let mut steps = Vec::with_capacity(edges.len());
+        for (index, pinned) in edges.iter().enumerate() {
+            if index > 0 && edges[index - 1].to != pinned.from {
+                return None;
+            }
+            let edge = self
+                .graph
+                .get_outgoing_edges(pinned.from)
+                .into_iter()
+                .find(|edge| edge.to == pinned.to && *edge.bridge_name == *pinned.bridge)?;
+            if edge.is_stale() || self.is_excluded(&edge) {
+                return None;
+            }
+            let metrics = edge.get_metrics();
+            steps.push((edge, metrics));
+        }
+        let swaps = steps.iter().filter(|(edge, _)| edge.kind == EdgeKind::Swap).count();
+        if self.max_swaps.is_some_and(|max| swaps > max) {
+            return None;
+        }
+        Some(self.build_path(steps.iter().map(|(edge, metrics)| (edge, metrics)), graph_version))
     }
 
-    pub fn reconstruct_path(
-        &self, 
-        start: NodeId,
-        end: NodeId,
-        came_from: &HashMap<NodeId, (NodeId, Arc<Edge>)>
+    // Walks back from `end` to the start (hop 0). Hops carry the metrics the search weighed,
+    // not whatever the edge holds by now, and the edge's quote.
+    fn reconstruct_path(
+        &self,
+        end: SearchKey,
+        came_from: &HashMap<SearchKey, (SearchKey, Arc<Edge>, EdgeMetrics)>,
+        graph_version: u64,
     ) -> Path {
-        let mut hops = Vec::new();
+        let mut steps = Vec::new();
         let mut current = end;
+        while let Some((previous, edge, metrics)) = came_from.get(&current) {
+            steps.push((edge, metrics));
+            current = *previous;
+        }
+        steps.reverse();
+        self.build_path(steps, graph_version)
+    }
+
+    // The path taking `steps` in order, each edge with the metrics it's taken at. Only here are
+    // the metrics copied, into the hops.
+    fn build_path<'a>(&self, steps: impl IntoIterator<Item = (&'a Arc<Edge>, &'a EdgeMetrics)>, graph_version: u64) -> Path {
+        let mut hops = Vec::new();
         let mut total_cost = 0.0;
         let mut total_time = 0.0;
         let mut total_risk = 0.0;
         let mut min_liquidity = f64::INFINITY;
+        let now = self.graph.clock();
+
+        for (edge, metrics) in steps {
+            total_cost += metrics.cost;
+            total_time += metrics.speed;
+            total_risk += metrics.risk;
+            min_liquidity = min_liquidity.min(metrics.liquidity);
+            hops.push(Hop {
+                from: edge.from,
+                to: edge.to,
+                bridge_name: Arc::clone(&edge.bridge_name),
+                kind: edge.kind,
+                metrics: metrics.clone(),
+                quote: edge.get_quote(),
+                slippage_pct: None,
+                alternatives: Some(self.alternatives(edge)),
+                confidence: self.confidence.edge_confidence(edge, now),
+            });
+        }
 
-        while current != start {
-            if let Some((from, edge)) = came_from.get(&current) {
-                let metrics = edge.get_metrics();
-                hops.push(Hop {
-                    from: *from,
-                    to: current,
-                    bridge_name: edge.bridge_name.clone(),
-                    metrics: metrics.clone()
-                });
-                total_cost += metrics.cost;
-                total_time += metrics.speed;
-                total_risk += metrics.risk;
-                min_liquidity = min_liquidity.min(metrics.liquidity);
-                current = *from;
-            }
-            else {
-                break;
-            }
-        } 
-        hops.reverse();
+        if hops.is_empty() {
+            min_liquidity = 0.0;
+        }
 
+        let confidence = self.confidence.path_confidence(&hops);
         Path {
             hops, 
             total_cost,
             total_time,
             total_risk,
             min_liquidity,
-            aggregate_score: 0.0 // Will be computed later by scoring algorithm
+            aggregate_score: 0.0, // Will be computed later by scoring algorithm
+            estimated_output: None,
+            graph_version,
+            confidence,
         }
     }
 
-    fn heuristic(&self, from: NodeId, to: NodeId) -> f64 {
+    fn heuristic(&self, _from: NodeId, _to: NodeId) -> f64 {
         // 0.0 for now. Can enable chain-based heuristic. 
         // Learn about chain-based heuristics
         // this algorithm with 0.0 will behave like Dijisktra
@@ -204,3 +617,116 @@ impl RoutingEngine {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::layered_graph;
+
+    // Nodes as layer.index, e.g. "0.0 -bridge4-> 1.26 -bridge0-> 2.18"
+    fn describe(layers: &[Vec<NodeId>], path: &Path) -> String {
+        let position = |node: NodeId| {
+            let (layer, index) = layers.iter().enumerate().find_map(|(layer, nodes)| nodes.iter().position(|n| *n == node).map(|index| (layer, index))).unwrap();
+            format!("{}.{}", layer, index)
+        };
+        path.hops.iter().fold(position(path.hops[0].from), |described, hop| format!("{} -{}-> {}", described, hop.bridge_name, position(hop.to)))
+    }
+
+    #[test]
+    fn searches_find_the_paths_they_always_have() {
+        // Found before searches stopped collecting edges and copying metrics as they go
+        let layered = layered_graph(7, 5, 40, 600);
+        let layers = layered.layers.clone();
+        let (source, sink) = (layered.source(), layered.sink());
+        let graph = Arc::new(layered.graph);
+        let engine = RoutingEngine::new(Arc::clone(&graph), 5);
+        let expected = [
+            (RoutingParams::cheapest(), "0.0 -bridge4-> 1.26 -bridge0-> 2.18 -bridge6-> 3.39 -bridge7-> 4.0"),
+            (RoutingParams::fastest(), "0.0 -bridge0-> 1.13 -bridge5-> 2.34 -bridge2-> 3.39 -bridge7-> 4.0"),
+            (RoutingParams::safest(), "0.0 -bridge0-> 1.12 -bridge3-> 2.27 -bridge1-> 3.5 -bridge0-> 4.0"),
+            // Weighed on the fees that come out of what's sent, the same as cheapest
+            (RoutingParams::max_output(), "0.0 -bridge4-> 1.26 -bridge0-> 2.18 -bridge6-> 3.39 -bridge7-> 4.0"),
+        ];
+        // The second round searches in the buffers the first left behind
+        for _ in 0..2 {
+            for (params, path) in &expected {
+                assert_eq!(describe(&layers, &engine.find_path(source, sink, params).unwrap()), *path);
+            }
+        }
+        assert_eq!(engine.scratch.0.lock().unwrap().len(), 1);
+        let candidates: Vec<String> =
+            engine.find_candidate_paths(source, sink, &RoutingParams::balanced(), 3).iter().map(|path| describe(&layers, path)).collect();
+        assert_eq!(candidates, [
+            "0.0 -bridge0-> 1.12 -bridge3-> 2.27 -bridge1-> 3.5 -bridge0-> 4.0",
+            "0.0 -bridge0-> 1.32 -bridge3-> 2.4 -bridge7-> 3.5 -bridge0-> 4.0",
+            "0.0 -bridge0-> 1.33 -bridge1-> 2.27 -bridge1-> 3.5 -bridge0-> 4.0",
+        ]);
+
+        // Edges visited in a view are those of the live graph, and hops share their edge's name
+        let view = RoutingEngine::new(Arc::new(graph.read_view()), 5);
+        let (live, viewed) = (engine.find_path(source, sink, &expected[0].0).unwrap(), view.find_path(source, sink, &expected[0].0).unwrap());
+        assert_eq!(describe(&layers, &viewed), expected[0].1);
+        assert_eq!((live.total_cost, live.hops.iter().map(|hop| hop.alternatives).collect::<Vec<_>>()), (viewed.total_cost, viewed.hops.iter().map(|hop| hop.alternatives).collect()));
+        let first = &live.hops[0];
+        let edge = graph.get_outgoing_edges(first.from).into_iter().find(|edge| edge.to == first.to && edge.bridge_name == first.bridge_name).unwrap();
+        assert!(Arc::ptr_eq(&edge.bridge_name, &first.bridge_name));
+    }
+
+    #[test]
+    fn output_searches_prefer_the_edge_that_delivers_more() {
+        let graph = Graph::new(16);
+        let (from, to) = (graph.get_or_create_asset_node("ethereum", "0xa0b8", "USDC"), graph.get_or_create_asset_node("polygon", "0x3c49", "USDC"));
+        // Same fee, but 5,000 is half of the thin edge's liquidity and a rounding error on the deep one's
+        let metrics = |liquidity| EdgeMetrics { cost: 1.0, speed: 60.0, liquidity, risk: 0.1 };
+        graph.add_edge(from, to, "thin", metrics(10_000.0), None, None).unwrap();
+        graph.add_edge(from, to, "deep", metrics(10_000_000.0), None, None).unwrap();
+        let engine = RoutingEngine::new(Arc::new(graph), 2);
+        let bridge = |engine: &RoutingEngine| engine.find_path(from, to, &RoutingParams::max_output()).unwrap().hops[0].bridge_name.to_string();
+
+        // Weighed on fees alone the two tie, and the first edge is taken
+        assert_eq!(bridge(&engine), "thin");
+        assert_eq!(bridge(&engine.with_sent_amount(5_000.0, SlippageModel::default())), "deep");
+    }
+
+    #[test]
+    fn searches_keep_at_most_their_label_cap() {
+        let layered = layered_graph(7, 5, 40, 600);
+        let (source, sink) = (layered.source(), layered.sink());
+        let engine = RoutingEngine::new(Arc::new(layered.graph), 5);
+        let params = RoutingParams::balanced();
+
+        let mut unlimited = engine.candidate_paths(source, sink, &params, 3);
+        assert_eq!(unlimited.by_ref().count(), 3);
+        assert!(!unlimited.stats().capped);
+
+        // Too few labels to get past the first layer finds nothing, but says why
+        let mut capped = engine.candidate_paths(source, sink, &params, 3).with_max_labels(10);
+        assert_eq!(capped.by_ref().count(), 0);
+        assert!(capped.stats().capped);
+    }
+
+    #[test]
+    fn a_weightless_best_path_prunes_nothing() {
+        let graph = Graph::new(16);
+        let (from, via, to) = (graph.get_or_create_asset_node("ethereum", "0xa0b8", "USDC"), graph.get_or_create_asset_node("base", "0x8335", "USDC"), graph.get_or_create_asset_node("polygon", "0x3c49", "USDC"));
+        let free = EdgeMetrics { cost: 0.0, speed: 0.0, liquidity: 0.0, risk: 0.0 };
+        graph.add_edge(from, to, "stargate", free.clone(), None, None).unwrap();
+        graph.add_edge(from, via, "across", EdgeMetrics { cost: 1.0, ..free.clone() }, None, None).unwrap();
+        graph.add_edge(via, to, "across", EdgeMetrics { cost: 1.0, ..free }, None, None).unwrap();
+        let engine = RoutingEngine::new(Arc::new(graph), 5);
+
+        let mut search = engine.candidate_paths(from, to, &RoutingParams::cheapest(), 2).with_pruning(Some(PruningRule::StopWhenWorseBy { factor: 3.0 }));
+        assert_eq!(search.by_ref().count(), 2);
+        assert_eq!(search.stats().pruned, 0);
+    }
+
+    #[test]
+    fn degenerate_pruning_rules_are_rejected() {
+        for rule in [PruningRule::StopWhenWorseBy { factor: 0.0 }, PruningRule::StopWhenWorseBy { factor: 0.5 }, PruningRule::StopWhenWorseBy { factor: f64::NAN }, PruningRule::StopAfterStable { n: 0 }] {
+            assert!(rule.validate().is_err(), "{:?}", rule);
+        }
+        for rule in [PruningRule::StopWhenWorseBy { factor: 1.0 }, PruningRule::StopAfterStable { n: 1 }] {
+            assert_eq!(rule.validate(), Ok(()));
+        }
+    }
+}
@@ -1,4 +1,14 @@
+use crate::error::ScoringError;
+use crate::objectives::{ObjectiveRegistry, Unscored};
+use crate::pinning::PinnedScore;
+use crate::routing::SearchStats;
 use crate::types::*;
+use crate::view::GraphRead;
+use serde::{Serialize, Serializer, ser::SerializeMap};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap}
+};
 
 #[derive(Debug, Clone)]
 pub struct NormalizedPath {
@@ -6,12 +16,125 @@ pub struct NormalizedPath {
     normalized: NormalizedMetrics
 }
 
+// 0-1 scores of a path, 1 best, one per objective of the registry it was normalized with, in
+// the registry's order
 #[derive(Debug, Clone)]
 pub struct NormalizedMetrics {
-    cost: f64,
-    speed: f64,
-    risk: f64,
-    liquidity: f64
+    values: Vec<f64>,
+}
+
+impl NormalizedMetrics {
+    // Per-objective (name, weight, weighted term) as summed by Optimizer::weighed_sum
+    fn weighted_terms(&self, objectives: &ObjectiveRegistry, weights: &[f64]) -> Vec<(&'static str, f64, f64)> {
+        objectives
+            .iter()
+            .zip(weights)
+            .zip(&self.values)
+            .map(|((objective, weight), value)| (objective.name, *weight, weight * value))
+            .collect()
+    }
+
+    fn weighted_sum(&self, weights: &[f64]) -> f64 {
+        weights.iter().zip(&self.values).map(|(weight, value)| weight * value).sum()
+    }
+
+    // The first objective that couldn't be scaled, e.g. from a NaN or infinite metric
+    fn nan_factor(&self, objectives: &ObjectiveRegistry) -> Option<&'static str> {
+        objectives
+            .iter()
+            .zip(&self.values)
+            .find(|(_, value)| value.is_nan())
+            .map(|(objective, _)| objective.name)
+    }
+
+    // At least as good on every objective and better on one
+    fn dominates(&self, other: &NormalizedMetrics) -> bool {
+        let pairs = || self.values.iter().zip(&other.values);
+        pairs().all(|(a, b)| a >= b) && pairs().any(|(a, b)| a > b)
+    }
+}
+
+// Observed range of one objective across a candidate set
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct MinMax {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl MinMax {
+    fn over(values: impl Iterator<Item = f64>) -> Option<Self> {
+        values.fold(None, |acc: Option<MinMax>, v| match acc {
+            Some(range) => Some(MinMax { min: range.min.min(v), max: range.max.max(v) }),
+            None => Some(MinMax { min: v, max: v }),
+        })
+    }
+
+    fn merge(self, other: MinMax) -> Self {
+        MinMax { min: self.min.min(other.min), max: self.max.max(other.max) }
+    }
+
+    // 0-1 position of value within the range, 1.0 when the range is degenerate
+    pub(crate) fn position(&self, value: f64) -> f64 {
+        if self.max > self.min {
+            (value - self.min) / (self.max - self.min)
+        } else {
+            1.0
+        }
+    }
+}
+
+// Min/max per objective used to scale a set of paths, serialized as a map by objective name
+#[derive(Debug, Clone, PartialEq)]
+pub struct NormalizationStats {
+    // In registry order; None when no path had a value for the objective
+    ranges: Vec<(&'static str, Option<MinMax>)>,
+}
+
+impl NormalizationStats {
+    // None without paths
+    pub fn from_paths(objectives: &ObjectiveRegistry, paths: &[Path]) -> Option<Self> {
+        if paths.is_empty() {
+            return None;
+        }
+        let ranges = objectives
+            .iter()
+            .map(|objective| (objective.name, MinMax::over(paths.iter().filter_map(|path| objective.value(path)))))
+            .collect();
+        Some(Self { ranges })
+    }
+
+    pub fn range(&self, objective: &str) -> Option<MinMax> {
+        self.ranges.iter().find(|(name, _)| *name == objective).and_then(|(_, range)| *range)
+    }
+
+    // Both taken with the same registry
+    pub fn merge(&self, other: &NormalizationStats) -> Self {
+        let ranges = self.ranges
+            .iter()
+            .zip(&other.ranges)
+            .map(|((name, a), (_, b))| (*name, merge_optional(*a, *b)))
+            .collect();
+        Self { ranges }
+    }
+}
+
+impl Serialize for NormalizationStats {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.ranges.len()))?;
+        for (name, range) in &self.ranges {
+            // Speed's range has always gone out as time
+            let key = if *name == "speed" { "time" } else { name };
+            map.serialize_entry(key, range)?;
+        }
+        map.end()
+    }
+}
+
+fn merge_optional(a: Option<MinMax>, b: Option<MinMax>) -> Option<MinMax> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.merge(b)),
+        (a, b) => a.or(b),
+    }
 }
 
 // Score normalizer for 0-1 scaling
@@ -22,70 +145,39 @@ pub struct ScoreNormalizer;
 impl ScoreNormalizer {
     pub fn normalize_path(
         &self,
+        objectives: &ObjectiveRegistry,
         paths: &[Path]
     ) -> Vec<NormalizedPath> {
-        if paths.is_empty() {
-            return Vec::new();
+        match NormalizationStats::from_paths(objectives, paths) {
+            Some(stats) => self.normalize_with(objectives, paths, &stats),
+            None => Vec::new(),
         }
+    }
 
-        // Find min/max for each dimension
-        let (min_cost, max_cost) = paths.iter()
-                                    .map(|p| p.total_cost)
-                                    .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
-                                        (min.min(v), max.max(v))
-                                    });
-        
-        let (min_time, max_time) = paths.iter()
-                                    .map(|p| p.total_time)
-                                    .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
-                                        (min.min(v), max.max(v))
-                                    });
-
-        let (min_risk, max_risk) = paths.iter()
-                                    .map(|p| p.total_risk)
-                                    .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
-                                        (min.min(v), max.max(v))
-                                    });
-        
-        let (min_liq, max_liq) = paths.iter()
-                                    .map(|p| p.min_liquidity)
-                                    .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
-                                        (min.min(v), max.max(v))
-                                    });
-        
+    // Scale paths against externally supplied stats, e.g. shared across several candidate sets
+    pub fn normalize_with(
+        &self,
+        objectives: &ObjectiveRegistry,
+        paths: &[Path],
+        stats: &NormalizationStats
+    ) -> Vec<NormalizedPath> {
         paths.iter().map(|path| {
-            let cost_norm = if max_cost > min_cost {
-                1.0 - (path.total_cost - min_cost) / (max_cost - min_cost)
-            } else{
-                1.0
-            };
-
-            let time_norm = if max_time > min_time {
-                1.0 - (path.total_time - min_time) / (max_time - min_time)
-            } else {
-                1.0
-            };
-
-            let risk_norm = if max_risk > min_risk {
-                1.0 - (path.total_risk - min_risk) / (max_risk - min_risk)
-            } else {
-                1.0
-            };
-
-            let liq_norm = if max_liq > min_liq {
-                1.0 - (path.min_liquidity - min_liq) / (max_liq - min_liq)
-            } else {
-                1.0
-            };
+            let mut values: Vec<f64> = Vec::with_capacity(objectives.len());
+            for (objective, (_, range)) in objectives.iter().zip(&stats.ranges) {
+                let value = match (objective.value(path), range) {
+                    (Some(value), Some(range)) => objective.score(value, *range),
+                    (Some(_), None) => 1.0,
+                    (None, _) => match objective.unscored {
+                        Unscored::Best => 1.0,
+                        Unscored::Like(other) => objectives.index_of(other).and_then(|index| values.get(index)).copied().unwrap_or(1.0),
+                    },
+                };
+                values.push(value);
+            }
 
             NormalizedPath {
                 path: path.clone(),
-                normalized: NormalizedMetrics { 
-                    cost: cost_norm, 
-                    speed: time_norm, 
-                    risk: risk_norm, 
-                    liquidity: liq_norm 
-                }
+                normalized: NormalizedMetrics { values }
             }
         }).collect()
                         
@@ -96,11 +188,13 @@ impl ScoreNormalizer {
 #[derive(Debug, Clone)]
 pub struct ScoredPath {
     path: Path,
-    score: f64
+    score: f64,
+    normalized: NormalizedMetrics
 }
 
 
-// Multi-objective optimizer. Needs update.
+// Multi-objective optimizer over the objectives the paths were normalized with. `weights` are
+// theirs in the same order, see ObjectiveRegistry::weights.
 #[derive(Debug)]
 pub struct Optimizer;
 
@@ -109,65 +203,121 @@ impl Optimizer {
     pub fn weighed_sum(
         &self, 
         normalized: &[NormalizedPath],
-        params: &RoutingParams,
+        weights: &[f64],
     ) -> Vec<ScoredPath> {
         normalized.iter().map(|np| {
-            let score = params.alpha * np.normalized.cost
-                                + params.beta * np.normalized.speed
-                                + params.gamma * np.normalized.liquidity
-                                + params.delta * (1.0 - np.normalized.risk);
-
             ScoredPath {
                 path: np.path.clone(),
-                score,
+                score: np.normalized.weighted_sum(weights),
+                normalized: np.normalized.clone(),
             }
         }).collect()
     }
 
-    // Aproximate calculations. need further updates
+    // The paths no other path dominates on every objective. They're ordered, and scored, by the
+    // plain sum of their scores on the objectives `weights` weigh at all, each counted once, so
+    // how much each weighs doesn't reorder the front.
     pub fn pareto_front(
         &self,
         normalized: &[NormalizedPath],
+        weights: &[f64],
         max_results: usize
     ) -> Vec<ScoredPath> {
-        let mut candidates: Vec<&NormalizedPath> = normalized.iter().collect();
-
-        candidates.retain(|candidate| {
-            !normalized.iter().any(|other| {
-                other.normalized.cost <= candidate.normalized.cost 
-                    && other.normalized.speed <= candidate.normalized.speed
-                    && other.normalized.risk <= candidate.normalized.risk
-                    && other.normalized.liquidity >= candidate.normalized.liquidity 
-                    && (other.normalized.cost < candidate.normalized.cost
-                        || other.normalized.speed < candidate.normalized.speed
-                        || other.normalized.risk < candidate.normalized.risk
-                        || other.normalized.liquidity > candidate.normalized.liquidity)
-            })
-        });
-
-        let mut scored: Vec<ScoredPath> = candidates.iter().map(|np| {
-            ScoredPath {
-                path: np.path.clone(),
-                score: np.normalized.cost + np.normalized.speed + np.normalized.liquidity - np.normalized.risk,
-            }
-        }).collect();
+        let front: Vec<NormalizedPath> = normalized
+            .iter()
+            .filter(|candidate| !normalized.iter().any(|other| other.normalized.dominates(&candidate.normalized)))
+            .cloned()
+            .collect();
 
-        scored.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+        let counted: Vec<f64> = weights.iter().map(|weight| if *weight > 0.0 { 1.0 } else { 0.0 }).collect();
+        let mut scored = self.weighed_sum(&front, &counted);
+        scored.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
         scored.truncate(max_results);
         scored
 
     }
 }
 
-#[derive(Debug)]
-pub struct Ranker;
+// Secondary objectives consulted, in order, when final scores are within the ranker's epsilon
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TieBreaker {
+    Cost,
+    Speed,
+    Risk,
+    Liquidity,
+    Output,
+    HopCount,
+}
+
+impl TieBreaker {
+    // Ordering::Less means `a` ranks ahead of `b`
+    fn compare(&self, a: &Path, b: &Path) -> Ordering {
+        match self {
+            TieBreaker::Cost => a.total_cost.total_cmp(&b.total_cost),
+            TieBreaker::Speed => a.total_time.total_cmp(&b.total_time),
+            TieBreaker::Risk => a.total_risk.total_cmp(&b.total_risk),
+            TieBreaker::Liquidity => b.effective_min_liquidity().total_cmp(&a.effective_min_liquidity()),
+            TieBreaker::Output => b.estimated_output.unwrap_or(0.0).total_cmp(&a.estimated_output.unwrap_or(0.0)),
+            TieBreaker::HopCount => a.hops.len().cmp(&b.hops.len()),
+        }
+    }
+}
+
+pub const DEFAULT_TIE_EPSILON: f64 = 1e-9;
+
+#[derive(Debug, Clone)]
+pub struct Ranker {
+    tie_breakers: Vec<TieBreaker>,
+    epsilon: f64,
+}
+
+impl Default for Ranker {
+    fn default() -> Self {
+        Self {
+            tie_breakers: vec![TieBreaker::Risk, TieBreaker::Cost, TieBreaker::HopCount],
+            epsilon: DEFAULT_TIE_EPSILON,
+        }
+    }
+}
 
 impl Ranker {
+    pub fn new(tie_breakers: Vec<TieBreaker>, epsilon: f64) -> Self {
+        Self {
+            tie_breakers,
+            epsilon: epsilon.abs(),
+        }
+    }
+
+    // Highest score first. Runs of scores within epsilon of the run's leader are re-ordered by the
+    // tie breakers, then by hop sequence, so equal-score inputs rank identically whatever their input order.
+    fn sort(&self, scored: &mut [ScoredPath]) {
+        scored.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+
+        let mut start = 0;
+        while start < scored.len() {
+            let leader = scored[start].score;
+            let mut end = start + 1;
+            while end < scored.len() && (leader - scored[end].score).abs() <= self.epsilon {
+                end += 1;
+            }
+            scored[start..end].sort_by(|a, b| self.break_tie(&a.path, &b.path));
+            start = end;
+        }
+    }
+
+    fn break_tie(&self, a: &Path, b: &Path) -> Ordering {
+        self.tie_breakers.iter()
+            .map(|tb| tb.compare(a, b))
+            .find(|ord| *ord != Ordering::Equal)
+            .unwrap_or_else(|| hop_sequence(a).cmp(&hop_sequence(b)))
+    }
+
     pub fn rank(
         &self,
-        scored: Vec<ScoredPath>,
+        mut scored: Vec<ScoredPath>,
         max_results: usize,
     ) -> Vec<RankedPath> {
+        self.sort(&mut scored);
         let mut ranked: Vec<RankedPath> = scored.into_iter().enumerate().map(|(idx, sp)| {
             let metrics = &sp.path;
             RankedPath {
@@ -176,59 +326,989 @@ impl Ranker {
                 score_breakdown: ScoreBreakDown { 
                     cost_score: metrics.total_cost, 
                     speed_score: metrics.total_time, 
-                    liquidity_score: metrics.min_liquidity, 
+                    liquidity_score: metrics.effective_min_liquidity(), 
                     risk_score: metrics.total_risk, 
-                    final_score: sp.score
-                }
+                    final_score: sp.score,
+                    estimated_output: metrics.estimated_output
+                },
+                affordability: None,
             }
         }).collect();
 
         ranked.truncate(max_results);
         ranked
     }
+
+    // Like rank, but candidates scoring below min_score are dropped instead of padding max_results
+    pub fn rank_with_threshold(
+        &self,
+        mut scored: Vec<ScoredPath>,
+        max_results: usize,
+        min_score: f64,
+    ) -> Vec<RankedPath> {
+        scored.retain(|sp| sp.score >= min_score);
+        self.rank(scored, max_results)
+    }
+}
+
+fn hop_sequence(path: &Path) -> Vec<(NodeId, NodeId, &str)> {
+    path.hops.iter().map(|hop| (hop.from, hop.to, &*hop.bridge_name)).collect()
+}
+
+
+// One factor's contribution to the gap between a path and the top ranked path
+#[derive(Debug, Clone, Serialize)]
+pub struct Explanation {
+    pub factor: String,
+    pub this_path: f64,
+    pub best_path: f64,
+    pub weight: f64,
+    // Weighted normalized score of this path minus that of the best path for this factor
+    pub contribution: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExplainedPath {
+    pub ranked: RankedPath,
+    pub explanations: Vec<Explanation>,
+    pub summary: String,
+    // How the path stands against the intent's pinned route, when it shares edges with one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pinned: Option<PinnedScore>,
+}
+
+// Builds explanations for ranked paths relative to the first (best) entry
+#[derive(Debug)]
+pub struct Explainer;
+
+const EXPLAIN_EPSILON: f64 = 1e-9;
+// Percentage points of slippage too small to mention
+const SLIPPAGE_EPSILON: f64 = 0.005;
+// Hop confidence below which the least confident hop is called out
+const LOW_CONFIDENCE: f64 = 0.9;
+
+impl Explainer {
+    // `weights` are the objectives' in registry order, see ObjectiveRegistry::weights
+    pub fn explain(
+        &self,
+        ranked: Vec<(RankedPath, NormalizedMetrics)>,
+        objectives: &ObjectiveRegistry,
+        weights: &[f64],
+    ) -> Vec<ExplainedPath> {
+        let Some((best_path, best_norm)) = ranked.first().cloned() else {
+            return Vec::new();
+        };
+        let best_terms = best_norm.weighted_terms(objectives, weights);
+
+        ranked.into_iter().map(|(ranked_path, normalized)| {
+            let explanations = normalized.weighted_terms(objectives, weights)
+                .iter()
+                .zip(best_terms.iter())
+                .zip(objectives.iter())
+                .map(|(((factor, weight, term), (_, _, best_term)), objective)| Explanation {
+                    factor: factor.to_string(),
+                    this_path: (objective.extract)(&ranked_path.path),
+                    best_path: (objective.extract)(&best_path.path),
+                    weight: *weight,
+                    contribution: term - best_term,
+                })
+                .collect();
+
+            let lead = if ranked_path.rank == best_path.rank {
+                "ranked first for the selected weights".to_string()
+            } else {
+                summarize(&ranked_path.path, &best_path.path)
+            };
+            let summary = with_notes(lead, &ranked_path.path);
+
+            ExplainedPath {
+                ranked: ranked_path,
+                explanations,
+                summary,
+                pinned: None,
+            }
+        }).collect()
+    }
+}
+
+// One-line comparison against the best path, e.g.
+// "cheaper by 1.20 but ~8 minutes slower and uses a lower-liquidity wormhole hop"
+// `lead` followed by what the path has no alternative to and its least confident hop, if low
+pub(crate) fn with_notes(lead: String, path: &Path) -> String {
+    let mut summary = lead;
+    if let Some(hops) = single_points_of_failure(path) {
+        summary = format!("{}; no alternative to its {}", summary, hops);
+    }
+    if let Some(hop) = least_confident_hop(path) {
+        summary = format!("{}; least confident hop: {} ({:.2})", summary, hop.bridge_name, hop.confidence);
+    }
+    summary
+}
+
+pub(crate) fn summarize(path: &Path, best: &Path) -> String {
+    let mut better = Vec::new();
+    let mut worse = Vec::new();
+
+    let cost_delta = path.total_cost - best.total_cost;
+    if cost_delta < -EXPLAIN_EPSILON {
+        better.push(format!("cheaper by {:.2}", -cost_delta));
+    } else if cost_delta > EXPLAIN_EPSILON {
+        worse.push(format!("{:.2} more expensive", cost_delta));
+    }
+
+    let time_delta = path.total_time - best.total_time;
+    if time_delta < -EXPLAIN_EPSILON {
+        better.push(format!("{} faster", approx_duration(-time_delta)));
+    } else if time_delta > EXPLAIN_EPSILON {
+        worse.push(format!("{} slower", approx_duration(time_delta)));
+    }
+
+    if let (Some(output), Some(best_output)) = (path.estimated_output, best.estimated_output) {
+        let output_delta = output - best_output;
+        if output_delta > EXPLAIN_EPSILON {
+            better.push(format!("delivers {:.2} more", output_delta));
+        } else if output_delta < -EXPLAIN_EPSILON {
+            worse.push(format!("delivers {:.2} less", -output_delta));
+        }
+    }
+
+    if let (Some(slippage), Some(best_slippage)) = (path.slippage_pct(), best.slippage_pct()) {
+        let slippage_delta = slippage - best_slippage;
+        if slippage_delta < -SLIPPAGE_EPSILON {
+            better.push(format!("{:.2}% less price impact", -slippage_delta));
+        } else if slippage_delta > SLIPPAGE_EPSILON {
+            worse.push(format!("{:.2}% more price impact", slippage_delta));
+        }
+    }
+
+    let risk_delta = path.total_risk - best.total_risk;
+    if risk_delta < -EXPLAIN_EPSILON {
+        better.push("lower risk".to_string());
+    } else if risk_delta > EXPLAIN_EPSILON {
+        worse.push("higher risk".to_string());
+    }
+
+    let liquidity_delta = path.effective_min_liquidity() - best.effective_min_liquidity();
+    if liquidity_delta > EXPLAIN_EPSILON {
+        better.push("deeper liquidity".to_string());
+    } else if liquidity_delta < -EXPLAIN_EPSILON {
+        let bottleneck = path.hops.iter()
+            .min_by(|a, b| a.metrics.liquidity.partial_cmp(&b.metrics.liquidity).unwrap_or(Ordering::Equal))
+            .map(|hop| format!("uses a lower-liquidity {} hop", hop.bridge_name))
+            .unwrap_or_else(|| "lower liquidity".to_string());
+        worse.push(bottleneck);
+    }
+
+    match (better.is_empty(), worse.is_empty()) {
+        (true, true) => "equivalent to the top route".to_string(),
+        (false, true) => join_phrases(&better),
+        (true, false) => join_phrases(&worse),
+        (false, false) => format!("{} but {}", join_phrases(&better), join_phrases(&worse)),
+    }
+}
+
+// e.g. "wormhole hop" or "wormhole and hop hops"; None when every hop has an alternative
+fn single_points_of_failure(path: &Path) -> Option<String> {
+    let mut bridges: Vec<String> = Vec::new();
+    for hop in path.hops.iter().filter(|hop| hop.is_single_point_of_failure()) {
+        if !bridges.iter().any(|bridge| **bridge == *hop.bridge_name) {
+            bridges.push(hop.bridge_name.to_string());
+        }
+    }
+    match bridges.len() {
+        0 => None,
+        1 => Some(format!("{} hop", bridges[0])),
+        _ => Some(format!("{} hops", join_phrases(&bridges))),
+    }
+}
+
+// The first of the hops trusted least, when it's trusted less than LOW_CONFIDENCE
+fn least_confident_hop(path: &Path) -> Option<&Hop> {
+    path.hops
+        .iter()
+        .filter(|hop| hop.confidence < LOW_CONFIDENCE)
+        .reduce(|least, hop| if hop.confidence < least.confidence { hop } else { least })
 }
 
+// "a", "a and b", "a, b and c"
+fn join_phrases(phrases: &[String]) -> String {
+    match phrases.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} and {}", rest.join(", "), last),
+        None => String::new(),
+    }
+}
+
+fn approx_duration(seconds: f64) -> String {
+    if seconds < 90.0 {
+        format!("~{} seconds", seconds.round())
+    } else if seconds < 90.0 * 60.0 {
+        format!("~{} minutes", (seconds / 60.0).round())
+    } else {
+        format!("~{} hours", (seconds / 3600.0).round())
+    }
+}
+
+// Which optimizer ScoringEngine runs. Chosen explicitly rather than inferred from the weights.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScoringStrategy {
+    #[default]
+    WeightedSum,
+    ParetoFront,
+}
+
+// Why a candidate didn't make it into a RankingOutcome
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DropReason {
+    // A hop would have to use more of its liquidity than allowed to carry the amount, see
+    // SlippageModel::propagate
+    Utilization,
+    // The fees of a hop use up what's left of the amount by then
+    Fees,
+    // Over one of the caller's RouteConstraints
+    Constraints,
+    // Another candidate is at least as good on every objective, under ParetoFront
+    Dominated,
+    // Ranked past max_results
+    Truncated,
+    // The sender can't pay the gas of its first step, see Router::check_affordability
+    Unaffordable,
+    // Found in another version of the graph than the one ranked against, see
+    // ScoringEngine::score_and_rank_in
+    Outdated,
+}
+
+impl DropReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DropReason::Utilization => "utilization",
+            DropReason::Fees => "fees",
+            DropReason::Constraints => "constraints",
+            DropReason::Dominated => "dominated",
+            DropReason::Truncated => "truncated",
+            DropReason::Unaffordable => "unaffordable",
+            DropReason::Outdated => "outdated",
+        }
+    }
+}
+
+// What became of the candidates of one ranking
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RankingDiagnostics {
+    pub candidates: usize,
+    // How many of them were dropped, per reason; reasons that dropped none are left out
+    pub dropped: BTreeMap<DropReason, usize>,
+    // What the survivors of the filters were scaled with, None when none were left to scale
+    pub stats: Option<NormalizationStats>,
+    pub strategy: ScoringStrategy,
+    // How the candidates were searched for; left at its default by a ScoringEngine
+    pub search: SearchStats,
+}
+
+impl RankingDiagnostics {
+    pub fn new(strategy: ScoringStrategy, candidates: usize) -> Self {
+        Self { candidates, dropped: BTreeMap::new(), stats: None, strategy, search: SearchStats::default() }
+    }
+
+    pub fn record(&mut self, reason: DropReason, count: usize) {
+        if count > 0 {
+            *self.dropped.entry(reason).or_default() += count;
+        }
+    }
+
+    pub fn dropped_for(&self, reason: DropReason) -> usize {
+        self.dropped.get(&reason).copied().unwrap_or(0)
+    }
+}
+
+// The ranked candidates, RankedPaths or ExplainedPaths, and how they were arrived at
+#[derive(Debug, Clone, Serialize)]
+pub struct RankingOutcome<T = RankedPath> {
+    pub ranked: Vec<T>,
+    pub diagnostics: RankingDiagnostics,
+}
 
 // Complete scoring Engine
 pub struct ScoringEngine {
     normalizer: ScoreNormalizer,
     optimizer: Optimizer,
-    ranker: Ranker
+    ranker: Ranker,
+    explainer: Explainer,
+    strategy: ScoringStrategy,
+    objectives: ObjectiveRegistry
 }
 
+impl Default for ScoringEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl ScoringEngine {
     pub fn new() -> Self {
         Self {
             normalizer: ScoreNormalizer,
             optimizer: Optimizer,
-            ranker: Ranker
+            ranker: Ranker::default(),
+            explainer: Explainer,
+            strategy: ScoringStrategy::default(),
+            objectives: ObjectiveRegistry::builtin()
         }
     }
 
+    // What paths are scored on, the built-in objectives unless replaced
+    pub fn with_objectives(mut self, objectives: ObjectiveRegistry) -> Self {
+        self.objectives = objectives;
+        self
+    }
+
+    pub fn objectives(&self) -> &ObjectiveRegistry {
+        &self.objectives
+    }
+
+    pub fn with_ranker(mut self, ranker: Ranker) -> Self {
+        self.ranker = ranker;
+        self
+    }
+
+    pub fn with_strategy(mut self, strategy: ScoringStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    pub fn strategy(&self) -> ScoringStrategy {
+        self.strategy
+    }
+
+    // Normalized and optimized; errs on the first path, by index into `paths`, that can't be scored
+    fn score_with_stats(
+        &self,
+        paths: &[Path],
+        weights: &[f64],
+        stats: &NormalizationStats,
+        diagnostics: &mut RankingDiagnostics,
+    ) -> Result<Vec<ScoredPath>, ScoringError> {
+        // Normalize
+        let normalized = self.normalizer.normalize_with(&self.objectives, paths, stats);
+        if let Some((index, factor)) = normalized.iter().enumerate().find_map(|(index, np)| np.normalized.nan_factor(&self.objectives).map(|factor| (index, factor))) {
+            return Err(ScoringError::NanScore { index, factor });
+        }
+
+        // Optimize
+        Ok(match self.strategy {
+            ScoringStrategy::WeightedSum => self.optimizer.weighed_sum(&normalized, weights),
+            ScoringStrategy::ParetoFront => {
+                let front = self.optimizer.pareto_front(&normalized, weights, normalized.len());
+                diagnostics.record(DropReason::Dominated, normalized.len() - front.len());
+                front
+            }
+        })
+    }
+
+    // The best max_results scored paths, sorted, with how they were chosen
+    fn score_sorted(
+        &self,
+        paths: &[Path],
+        weights: &[f64],
+        max_results: usize,
+    ) -> Result<(Vec<ScoredPath>, RankingDiagnostics), ScoringError> {
+        let mut diagnostics = RankingDiagnostics::new(self.strategy, paths.len());
+        let Some(stats) = NormalizationStats::from_paths(&self.objectives, paths) else {
+            return Ok((Vec::new(), diagnostics));
+        };
+        let mut scored = self.score_with_stats(paths, weights, &stats, &mut diagnostics)?;
+        diagnostics.stats = Some(stats);
+
+        self.ranker.sort(&mut scored);
+        diagnostics.record(DropReason::Truncated, scored.len().saturating_sub(max_results));
+        scored.truncate(max_results);
+        Ok((scored, diagnostics))
+    }
+
     pub fn score_and_rank(
         &self,
         paths: Vec<Path>,
         params: &RoutingParams,
         max_results: usize,
-    ) -> Vec<RankedPath> {
-        if paths.is_empty() {
-            return Vec::new();
+    ) -> Result<RankingOutcome, ScoringError> {
+        let weights = self.objectives.weights(params);
+        let (scored, diagnostics) = self.score_sorted(&paths, &weights, max_results)?;
+
+        Ok(RankingOutcome {
+            ranked: self.ranker.rank(scored, max_results),
+            diagnostics,
+        })
+    }
+
+    // As score_and_rank, only ranking the paths found in `graph`'s version, so a computation
+    // run against one GraphView isn't mixed with paths from before or after it
+    pub fn score_and_rank_in(
+        &self,
+        graph: &impl GraphRead,
+        paths: Vec<Path>,
+        params: &RoutingParams,
+        max_results: usize,
+    ) -> Result<RankingOutcome, ScoringError> {
+        let candidates = paths.len();
+        let current: Vec<Path> = paths.into_iter().filter(|path| path.graph_version == graph.version()).collect();
+        let outdated = candidates - current.len();
+        let mut outcome = self.score_and_rank(current, params, max_results)?;
+        outcome.diagnostics.candidates = candidates;
+        outcome.diagnostics.record(DropReason::Outdated, outdated);
+        Ok(outcome)
+    }
+
+    // Same ranking as score_and_rank, with per-factor explanations relative to the top path
+    pub fn score_and_rank_explained(
+        &self,
+        paths: Vec<Path>,
+        params: &RoutingParams,
+        max_results: usize,
+    ) -> Result<RankingOutcome<ExplainedPath>, ScoringError> {
+        let weights = self.objectives.weights(params);
+        let (scored, diagnostics) = self.score_sorted(&paths, &weights, max_results)?;
+
+        let normalized: Vec<NormalizedMetrics> = scored.iter().map(|sp| sp.normalized.clone()).collect();
+        let ranked = self.ranker.rank(scored, max_results);
+
+        Ok(RankingOutcome {
+            ranked: self.explainer.explain(ranked.into_iter().zip(normalized).collect(), &self.objectives, &weights),
+            diagnostics,
+        })
+    }
+
+    // Keeps track of the best scoring of a growing set of candidates, see BestScoring
+    pub(crate) fn best_scoring(&self, params: &RoutingParams) -> BestScoring<'_> {
+        BestScoring { engine: self, weights: self.objectives.weights(params), stats: None, best: None }
+    }
+
+    // Scores several intents' candidate sets in one call.
+    // With shared_normalization the min/max stats are taken over the union of all groups,
+    // making final scores comparable across intents.
+    pub fn score_and_rank_batch(
+        &self,
+        groups: Vec<(IntentId, Vec<Path>)>,
+        params: &RoutingParams,
+        max_results: usize,
+        shared_normalization: bool,
+    ) -> BatchRanking {
+        let weights = self.objectives.weights(params);
+
+        let shared_stats = if shared_normalization {
+            groups.iter()
+                .filter_map(|(_, paths)| NormalizationStats::from_paths(&self.objectives, paths))
+                .reduce(|acc, stats| acc.merge(&stats))
+        } else {
+            None
+        };
+
+        let mut ranked = HashMap::new();
+        let mut stats = HashMap::new();
+        let mut errors = HashMap::new();
+
+        for (intent_id, paths) in groups {
+            let group_stats = match &shared_stats {
+                Some(shared) => Some(shared.clone()),
+                None => NormalizationStats::from_paths(&self.objectives, &paths),
+            };
+
+            let mut diagnostics = RankingDiagnostics::new(self.strategy, paths.len());
+            let results = match &group_stats {
+                Some(group_stats) => match self.score_with_stats(&paths, &weights, group_stats, &mut diagnostics) {
+                    Ok(scored) => self.ranker.rank(scored, max_results),
+                    Err(err) => {
+                        errors.insert(intent_id, err);
+                        continue;
+                    }
+                },
+                None => Vec::new(),
+            };
+
+            if let Some(group_stats) = group_stats {
+                stats.insert(intent_id.clone(), group_stats);
+            }
+            ranked.insert(intent_id, results);
         }
 
-        // Normalize
-        let normalized = self.normalizer.normalize_path(&paths);
+        BatchRanking {
+            ranked,
+            stats,
+            errors,
+            shared_normalization,
+        }
+    }
+}
 
-        // Optimize
-        let score = if params.alpha + params.beta + params.gamma + params.delta == 1.0 {
-            // Weighted Sum
-            self.optimizer.weighed_sum(&normalized, params)
-        } else{
-            // Pareto front
-            self.optimizer.pareto_front(&normalized, max_results)
+// A hop as its ends and bridge, to tell paths apart once they're gone
+type OwnedHop = (NodeId, NodeId, String);
+
+// The best scoring of a set of paths as it grows, see ScoringEngine::best_scoring. A path that
+// widens no objective's range leaves the others' scores as they were, so it is scored on its
+// own unless it comes within the ranker's epsilon of the top score; otherwise, or with the
+// pareto front, the whole set is scored again.
+pub(crate) struct BestScoring<'a> {
+    engine: &'a ScoringEngine,
+    weights: Vec<f64>,
+    stats: Option<NormalizationStats>,
+    // The best path's hops and the top score, None until a path could be scored
+    best: Option<(Vec<OwnedHop>, f64)>,
+}
+
+impl BestScoring<'_> {
+    // Takes in the last of `paths`, the others having been taken in already, and says whether
+    // the best scoring path is still the one it was before
+    pub(crate) fn push(&mut self, paths: &[Path]) -> bool {
+        let Some(path) = paths.last() else {
+            return false;
+        };
+        let engine = self.engine;
+        if let (ScoringStrategy::WeightedSum, Some(stats), Some((_, top))) = (engine.strategy, &self.stats, &self.best) {
+            let own = NormalizationStats::from_paths(&engine.objectives, std::slice::from_ref(path));
+            if own.is_some_and(|own| stats.merge(&own) == *stats) {
+                let normalized = engine.normalizer.normalize_with(&engine.objectives, std::slice::from_ref(path), stats);
+                if let Some(normalized) = normalized.first().filter(|np| np.normalized.nan_factor(&engine.objectives).is_none()) {
+                    let score = normalized.normalized.weighted_sum(&self.weights);
+                    if score < top - engine.ranker.epsilon {
+                        return true;
+                    }
+                    if score > top + engine.ranker.epsilon {
+                        self.best = Some((owned_hops(path), score));
+                        return false;
+                    }
+                }
+            }
+        }
+
+        let before = self.best.take().map(|(hops, _)| hops);
+        self.stats = NormalizationStats::from_paths(&engine.objectives, paths);
+        let mut diagnostics = RankingDiagnostics::new(engine.strategy, paths.len());
+        let scored = self.stats.as_ref().and_then(|stats| engine.score_with_stats(paths, &self.weights, stats, &mut diagnostics).ok());
+        if let Some(mut scored) = scored.filter(|scored| !scored.is_empty()) {
+            let top = scored.iter().map(|sp| sp.score).fold(f64::NEG_INFINITY, f64::max);
+            engine.ranker.sort(&mut scored);
+            self.best = Some((owned_hops(&scored[0].path), top));
+        }
+        before.is_some() && before == self.best.as_ref().map(|(hops, _)| hops.clone())
+    }
+}
+
+fn owned_hops(path: &Path) -> Vec<OwnedHop> {
+    path.hops.iter().map(|hop| (hop.from, hop.to, hop.bridge_name.to_string())).collect()
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchRanking {
+    pub ranked: HashMap<IntentId, Vec<RankedPath>>,
+    // Stats each group was normalized with; identical for every group in shared mode
+    pub stats: HashMap<IntentId, NormalizationStats>,
+    // Groups that couldn't be scored, which are left out of `ranked` and `stats`
+    pub errors: HashMap<IntentId, ScoringError>,
+    pub shared_normalization: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objectives::{Direction, Objective};
+
+    fn path(hops: &[(&str, f64, f64, f64, f64)]) -> Path {
+        let hops: Vec<Hop> = hops.iter().enumerate().map(|(idx, (bridge, cost, speed, liquidity, risk))| Hop {
+            from: NodeId(idx as u64),
+            to: NodeId(idx as u64 + 1),
+            bridge_name: (*bridge).into(),
+            kind: EdgeKind::Bridge,
+            metrics: EdgeMetrics { cost: *cost, speed: *speed, liquidity: *liquidity, risk: *risk },
+            quote: None,
+            slippage_pct: None,
+            alternatives: None,
+            confidence: 1.0,
+        }).collect();
+
+        Path {
+            total_cost: hops.iter().map(|h| h.metrics.cost).sum(),
+            total_time: hops.iter().map(|h| h.metrics.speed).sum(),
+            total_risk: hops.iter().map(|h| h.metrics.risk).sum(),
+            min_liquidity: hops.iter().map(|h| h.metrics.liquidity).fold(f64::INFINITY, f64::min),
+            aggregate_score: 0.0,
+            estimated_output: None,
+            graph_version: 0,
+            confidence: 1.0,
+            hops,
+        }
+    }
+
+    #[test]
+    fn explained_three_path_snapshot() {
+        let paths = vec![
+            path(&[("stargate", 2.0, 200.0, 5_000.0, 0.2)]),
+            path(&[("stargate", 0.3, 60.0, 9_000.0, 0.2), ("wormhole", 0.5, 660.0, 1_000.0, 0.5)]),
+            path(&[("across", 3.0, 90.0, 5_000.0, 0.2)]),
+        ];
+
+        let engine = ScoringEngine::new();
+        let explained = engine.score_and_rank_explained(paths, &RoutingParams::fastest(), 3).unwrap().ranked;
+
+        let summaries: Vec<(usize, &str)> = explained.iter()
+            .map(|e| (e.ranked.rank, e.summary.as_str()))
+            .collect();
+        assert_eq!(summaries, vec![
+            (1, "ranked first for the selected weights"),
+            (2, "cheaper by 1.00 but ~2 minutes slower"),
+            (3, "cheaper by 2.20 but ~11 minutes slower, higher risk and uses a lower-liquidity wormhole hop"),
+        ]);
+
+        let speed = explained[2].explanations.iter().find(|e| e.factor == "speed").unwrap();
+        assert_eq!(speed.this_path, 720.0);
+        assert_eq!(speed.best_path, 90.0);
+        assert_eq!(speed.weight, 1.0);
+        assert!((speed.contribution - -1.0).abs() < 1e-9);
+
+        // Top path has zero contribution on every factor
+        assert!(explained[0].explanations.iter().all(|e| e.contribution == 0.0));
+
+        let json = serde_json::to_value(&explained).unwrap();
+        assert_eq!(json[1]["explanations"][0]["factor"], "cost");
+    }
+
+    fn scored(path: Path, score: f64) -> ScoredPath {
+        ScoredPath {
+            path,
+            score,
+            normalized: NormalizedMetrics { values: vec![0.0; 6] },
+        }
+    }
+
+    #[test]
+    fn equal_scores_rank_deterministically() {
+        let risky = path(&[("wormhole", 1.0, 60.0, 5_000.0, 0.9)]);
+        let safe_two_hop = path(&[("stargate", 0.5, 30.0, 5_000.0, 0.1), ("across", 0.5, 30.0, 5_000.0, 0.1)]);
+        let safe_one_hop = path(&[("stargate", 1.0, 60.0, 5_000.0, 0.2)]);
+
+        let ranker = Ranker::default();
+        let forward = ranker.rank(vec![
+            scored(risky.clone(), 0.5),
+            scored(safe_two_hop.clone(), 0.5),
+            scored(safe_one_hop.clone(), 0.5 + 1e-12),
+        ], 3);
+        let reversed = ranker.rank(vec![
+            scored(safe_one_hop, 0.5),
+            scored(safe_two_hop, 0.5),
+            scored(risky, 0.5),
+        ], 3);
+
+        let bridges = |ranked: &[RankedPath]| -> Vec<String> {
+            ranked.iter().map(|r| r.path.hops[0].bridge_name.to_string() + &r.path.hops.len().to_string()).collect()
+        };
+        // Risk first: 0.2 (two hops of 0.1) ties on risk, then cost ties, then fewer hops wins
+        assert_eq!(bridges(&forward), vec!["stargate1", "stargate2", "wormhole1"]);
+        assert_eq!(bridges(&forward), bridges(&reversed));
+
+        // A custom order puts hop count first
+        let by_hops = Ranker::new(vec![TieBreaker::HopCount, TieBreaker::Risk], 1e-6)
+            .rank(forward.iter().map(|r| scored(r.path.clone(), 0.5)).collect(), 3);
+        assert_eq!(by_hops[2].path.hops.len(), 2);
+    }
+
+    #[test]
+    fn threshold_drops_low_scores() {
+        let ranker = Ranker::default();
+        let ranked = ranker.rank_with_threshold(vec![
+            scored(path(&[("stargate", 1.0, 60.0, 5_000.0, 0.2)]), 0.9),
+            scored(path(&[("across", 1.0, 60.0, 5_000.0, 0.2)]), 0.6),
+            scored(path(&[("hop", 1.0, 60.0, 5_000.0, 0.2)]), 0.2),
+            scored(path(&[("celer", 1.0, 60.0, 5_000.0, 0.2)]), 0.1),
+        ], 5, 0.5);
+
+        assert_eq!(ranked.len(), 2);
+        assert_eq!(ranked[1].rank, 2);
+        assert!(ranked.iter().all(|r| r.score_breakdown.final_score >= 0.5));
+    }
+
+    #[test]
+    fn batch_shared_normalization_spans_groups() {
+        let groups = vec![
+            (IntentId::from("eth-poly"), vec![
+                path(&[("stargate", 1.0, 60.0, 5_000.0, 0.2)]),
+                path(&[("across", 2.0, 60.0, 5_000.0, 0.2)]),
+            ]),
+            (IntentId::from("base-arb"), vec![
+                path(&[("wormhole", 100.0, 60.0, 5_000.0, 0.2)]),
+                path(&[("hop", 50.0, 60.0, 5_000.0, 0.2)]),
+            ]),
+        ];
+        let engine = ScoringEngine::new();
+        let params = RoutingParams::cheapest();
+
+        let score_of = |batch: &BatchRanking, intent: &str, cost: f64| -> f64 {
+            batch.ranked[&IntentId::from(intent)].iter()
+                .find(|r| r.path.total_cost == cost)
+                .map(|r| r.score_breakdown.final_score)
+                .unwrap()
         };
 
-        self.ranker.rank(score, max_results)
+        let per_group = engine.score_and_rank_batch(groups.clone(), &params, 5, false);
+        let shared = engine.score_and_rank_batch(groups, &params, 5, true);
+
+        // Per group the 2.0 path is the worst of its set; against the outlier it is nearly the best
+        assert_eq!(score_of(&per_group, "eth-poly", 2.0), 0.0);
+        assert!((score_of(&shared, "eth-poly", 2.0) - (1.0 - 1.0 / 99.0)).abs() < 1e-9);
+
+        assert_eq!(shared.stats[&IntentId::from("eth-poly")], shared.stats[&IntentId::from("base-arb")]);
+        assert_eq!(shared.stats[&IntentId::from("eth-poly")].range("cost"), Some(MinMax { min: 1.0, max: 100.0 }));
+        assert_eq!(per_group.stats[&IntentId::from("eth-poly")].range("cost"), Some(MinMax { min: 1.0, max: 2.0 }));
+    }
+
+    #[test]
+    fn max_output_prefers_higher_output_over_lower_fees() {
+        // Cheaper by fees, but slippage leaves less at the destination
+        let mut low_fee = path(&[("wormhole", 1.0, 120.0, 50_000.0, 0.3)]);
+        low_fee.estimated_output = Some(990.0);
+        let mut high_output = path(&[("stargate", 2.0, 120.0, 500_000.0, 0.3)]);
+        high_output.estimated_output = Some(995.0);
+
+        let engine = ScoringEngine::new();
+        let paths = vec![low_fee, high_output];
+
+        let by_output = engine.score_and_rank(paths.clone(), &RoutingParams::max_output(), 2).unwrap().ranked;
+        assert_eq!(by_output[0].path.estimated_output, Some(995.0));
+        assert_eq!(by_output[0].score_breakdown.estimated_output, Some(995.0));
+
+        let by_fees = engine.score_and_rank(paths, &RoutingParams::cheapest(), 2).unwrap().ranked;
+        assert_eq!(by_fees[0].path.estimated_output, Some(990.0));
+    }
+
+    #[test]
+    fn max_output_falls_back_to_cost_without_estimates() {
+        let paths = vec![
+            path(&[("stargate", 2.0, 120.0, 5_000.0, 0.3)]),
+            path(&[("wormhole", 1.0, 120.0, 5_000.0, 0.3)]),
+        ];
+
+        let ranked = ScoringEngine::new().score_and_rank(paths, &RoutingParams::max_output(), 2).unwrap().ranked;
+        assert_eq!(ranked[0].path.total_cost, 1.0);
+        assert!(ranked.iter().all(|r| r.score_breakdown.final_score.is_finite()));
+    }
+
+    #[test]
+    fn redundancy_weight_prefers_hops_with_alternatives() {
+        // Equal on every metric, but nothing else connects the across hop's two nodes
+        let mut fragile = path(&[("stargate", 1.0, 60.0, 1_000_000.0, 0.2), ("across", 1.0, 60.0, 1_000_000.0, 0.2)]);
+        fragile.hops[0].alternatives = Some(2);
+        fragile.hops[1].alternatives = Some(0);
+        let mut redundant = path(&[("stargate", 1.0, 60.0, 1_000_000.0, 0.2), ("wormhole", 1.0, 60.0, 1_000_000.0, 0.2)]);
+        redundant.hops[0].alternatives = Some(2);
+        redundant.hops[1].alternatives = Some(1);
+        assert_eq!(fragile.redundancy_score(), Some(0.0));
+        assert_eq!(redundant.redundancy_score(), Some(0.5));
+
+        let engine = ScoringEngine::new();
+        let paths = vec![redundant, fragile];
+        // Without a redundancy weight they tie, and the hop sequence puts across first
+        let unweighted = engine.score_and_rank(paths.clone(), &RoutingParams::balanced(), 2).unwrap().ranked;
+        assert_eq!(&*unweighted[0].path.hops[1].bridge_name, "across");
+        assert_eq!(unweighted[0].score_breakdown.final_score, unweighted[1].score_breakdown.final_score);
 
+        let params = RoutingParams { epsilon: 0.2, ..RoutingParams::balanced() };
+        let explained = engine.score_and_rank_explained(paths, &params, 2).unwrap().ranked;
+        assert_eq!(&*explained[0].ranked.path.hops[1].bridge_name, "wormhole");
+        assert!(explained[0].ranked.score_breakdown.final_score > explained[1].ranked.score_breakdown.final_score);
+        assert_eq!(explained[0].summary, "ranked first for the selected weights");
+        assert_eq!(explained[1].summary, "equivalent to the top route; no alternative to its across hop");
+        let redundancy = explained[1].explanations.iter().find(|e| e.factor == "redundancy").unwrap();
+        assert_eq!((redundancy.this_path, redundancy.best_path), (0.0, 0.5));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn the_least_confident_hop_is_called_out() {
+        let mut doubtful = path(&[("stargate", 1.0, 60.0, 1_000_000.0, 0.2), ("across", 1.0, 60.0, 1_000_000.0, 0.2), ("hop", 1.0, 60.0, 1_000_000.0, 0.2)]);
+        for (hop, confidence) in doubtful.hops.iter_mut().zip([0.95, 0.4, 0.7]) {
+            hop.confidence = confidence;
+        }
+        doubtful.confidence = 0.4;
+        let mut trusted = path(&[("stargate", 1.0, 60.0, 1_000_000.0, 0.2), ("wormhole", 1.0, 60.0, 1_000_000.0, 0.2), ("hop", 1.0, 60.0, 1_000_000.0, 0.2)]);
+        trusted.hops[1].confidence = 0.92;
+        trusted.confidence = 0.92;
+
+        let engine = ScoringEngine::new();
+        let paths = vec![doubtful, trusted];
+        // Confidence goes unweighed by default, so the hop sequence puts across first
+        let unweighted = engine.score_and_rank_explained(paths.clone(), &RoutingParams::balanced(), 2).unwrap().ranked;
+        assert_eq!(&*unweighted[0].ranked.path.hops[1].bridge_name, "across");
+        assert_eq!(unweighted[0].summary, "ranked first for the selected weights; least confident hop: across (0.40)");
+        assert_eq!(unweighted[1].summary, "equivalent to the top route");
+
+        let params = RoutingParams::balanced().with_weight("confidence", 0.2);
+        let explained = engine.score_and_rank_explained(paths, &params, 2).unwrap().ranked;
+        assert_eq!(&*explained[0].ranked.path.hops[1].bridge_name, "wormhole");
+        assert!(explained[0].ranked.score_breakdown.final_score > explained[1].ranked.score_breakdown.final_score);
+        assert_eq!(explained[1].summary, "equivalent to the top route; least confident hop: across (0.40)");
+        let confidence = explained[1].explanations.iter().find(|e| e.factor == "confidence").unwrap();
+        assert_eq!((confidence.this_path, confidence.best_path), (0.4, 0.92));
+        assert!(confidence.contribution < 0.0);
+    }
+
+    #[test]
+    fn nan_metrics_are_rejected_by_path_index() {
+        let mut broken = path(&[("wormhole", 1.0, 120.0, 5_000.0, 0.3)]);
+        broken.total_time = f64::NAN;
+        let paths = vec![path(&[("stargate", 2.0, 120.0, 5_000.0, 0.3)]), broken, path(&[("across", 3.0, 60.0, 5_000.0, 0.3)])];
+
+        for strategy in [ScoringStrategy::WeightedSum, ScoringStrategy::ParetoFront] {
+            let engine = ScoringEngine::new().with_strategy(strategy);
+            let err = engine.score_and_rank(paths.clone(), &RoutingParams::balanced(), 3).unwrap_err();
+            assert_eq!(err, ScoringError::NanScore { index: 1, factor: "speed" });
+            assert_eq!(err.to_string(), "candidate 1 has a NaN speed score");
+            assert!(engine.score_and_rank_explained(paths.clone(), &RoutingParams::balanced(), 3).is_err());
+        }
+
+        let batch = ScoringEngine::new().score_and_rank_batch(vec![
+            (IntentId::from("broken"), paths.clone()),
+            (IntentId::from("fine"), vec![paths[0].clone()]),
+        ], &RoutingParams::balanced(), 3, false);
+        assert_eq!(batch.errors[&IntentId::from("broken")], ScoringError::NanScore { index: 1, factor: "speed" });
+        assert!(!batch.ranked.contains_key(&IntentId::from("broken")));
+        assert_eq!(batch.ranked[&IntentId::from("fine")].len(), 1);
+    }
+
+    #[test]
+    fn outcomes_say_what_became_of_the_candidates() {
+        let paths = vec![
+            path(&[("stargate", 1.0, 60.0, 5_000.0, 0.2)]),
+            path(&[("wormhole", 2.0, 120.0, 5_000.0, 0.4)]),
+            path(&[("across", 0.5, 300.0, 5_000.0, 0.2)]),
+            path(&[("hop", 3.0, 30.0, 5_000.0, 0.2)]),
+        ];
+
+        let pareto = ScoringEngine::new().with_strategy(ScoringStrategy::ParetoFront)
+            .score_and_rank(paths.clone(), &RoutingParams::balanced(), 2)
+            .unwrap();
+        assert_eq!(pareto.ranked.len(), 2);
+        let diagnostics = &pareto.diagnostics;
+        assert_eq!((diagnostics.candidates, diagnostics.strategy), (4, ScoringStrategy::ParetoFront));
+        assert_eq!((diagnostics.dropped_for(DropReason::Dominated), diagnostics.dropped_for(DropReason::Truncated)), (1, 1));
+        assert_eq!(diagnostics.stats.as_ref().unwrap().range("cost"), Some(MinMax { min: 0.5, max: 3.0 }));
+
+        let weighted = ScoringEngine::new().score_and_rank(paths, &RoutingParams::balanced(), 5).unwrap();
+        assert_eq!(weighted.ranked.len(), 4);
+        assert!(weighted.diagnostics.dropped.is_empty());
+
+        let none = ScoringEngine::new().score_and_rank(Vec::new(), &RoutingParams::balanced(), 5).unwrap();
+        assert!(none.ranked.is_empty());
+        assert_eq!(none.diagnostics, RankingDiagnostics::new(ScoringStrategy::WeightedSum, 0));
+
+        let json = serde_json::to_value(&pareto.diagnostics).unwrap();
+        assert_eq!(json["dropped"], serde_json::json!({ "dominated": 1, "truncated": 1 }));
+        assert_eq!(json["strategy"], "pareto_front");
+        assert_eq!(json["stats"]["time"], serde_json::json!({ "min": 30.0, "max": 300.0 }));
+        assert!(json["stats"].get("speed").is_none());
+    }
+
+    // Candidates with a bit of everything: multi-hop, with and without outputs and alternatives
+    fn assorted_paths() -> Vec<Path> {
+        let mut paths = vec![
+            path(&[("stargate", 1.2, 90.0, 400_000.0, 0.2)]),
+            path(&[("across", 0.8, 300.0, 50_000.0, 0.3)]),
+            path(&[("stargate", 0.4, 60.0, 900_000.0, 0.1), ("wormhole", 0.9, 900.0, 20_000.0, 0.6)]),
+            path(&[("hop", 2.5, 45.0, 120_000.0, 0.25)]),
+            path(&[("celer", 0.3, 120.0, 75_000.0, 0.15), ("synapse", 0.6, 240.0, 300_000.0, 0.2)]),
+            path(&[("lifi", 1.9, 75.0, 1_500_000.0, 0.05)]),
+        ];
+        let outputs = [Some(998.1), Some(998.9), None, Some(996.7), Some(998.4), None];
+        let alternatives = [Some(2), Some(0), None, Some(1), Some(3), Some(0)];
+        for ((path, output), alternatives) in paths.iter_mut().zip(outputs).zip(alternatives) {
+            path.estimated_output = output;
+            path.hops.iter_mut().for_each(|hop| hop.alternatives = alternatives);
+        }
+        paths
+    }
+
+    #[test]
+    fn default_weights_rank_fixed_paths() {
+        // (total_cost, final_score) in rank order. Before objectives were registered more liquidity
+        // and less risk scored lower; max_output weighs neither and still ranks as it did then.
+        let expected: [(RoutingParams, [(f64, f64); 6]); 5] = [
+            (RoutingParams::balanced(), [(1.9, 0.731340405014465), (1.2, 0.7194026828549491), (0.8, 0.6819859582154665), (0.9, 0.6344704859892905), (2.5, 0.38274428274428285), (1.3, 0.28235294117647064)]),
+            (RoutingParams::safest(), [(1.9, 0.9320154291224687), (1.2, 0.6844423683382217), (0.8, 0.5454159708258068), (2.5, 0.5288981288981289), (0.9, 0.49020077305998233), (1.3, 0.07058823529411765)]),
+            (RoutingParams::max_liquidity(), [(1.9, 0.9320154291224687), (1.2, 0.42820536210121557), (0.8, 0.24785879826863436), (0.9, 0.23955108491029423), (2.5, 0.21652806652806655), (1.3, 0.07058823529411766)]),
+            (RoutingParams::max_output(), [(0.8, 1.0), (0.9, 0.7727272727272657), (1.3, 0.7058823529411764), (1.2, 0.6363636363636458), (1.9, 0.3529411764705883), (2.5, 0.0)]),
+            (
+                RoutingParams { alpha: 0.3, beta: 0.2, gamma: 0.1, delta: 0.2, omega: 0.1, epsilon: 0.1, ..RoutingParams::default() },
+                [(1.2, 0.751622781179195), (0.9, 0.7021817333413276), (0.8, 0.6693662451859173), (1.9, 0.6346190935390551), (2.5, 0.4118849618849619), (1.3, 0.38235294117647056)],
+            ),
+        ];
+
+        for (params, expected) in expected {
+            let ranked = ScoringEngine::new().score_and_rank(assorted_paths(), &params, 6).unwrap().ranked;
+            assert_eq!(ranked.len(), expected.len());
+            for (ranked, (cost, score)) in ranked.iter().zip(expected) {
+                assert!((ranked.path.total_cost - cost).abs() < 1e-9, "{:?}: {} ranked where {} should", params, ranked.path.total_cost, cost);
+                assert!((ranked.score_breakdown.final_score - score).abs() < 1e-12, "{:?}: {} scored {}, not {}", params, cost, ranked.score_breakdown.final_score, score);
+            }
+        }
+
+        // The cheapest route has the least liquidity and the most risk of the set
+        let balanced = ScoringEngine::new().score_and_rank(assorted_paths(), &RoutingParams::balanced(), 6).unwrap().ranked;
+        let lifi = balanced.iter().find(|ranked| ranked.path.total_cost == 1.9).unwrap();
+        let (cost, speed) = (1.0 - (1.9 - 0.8) / 1.7, 1.0 - (75.0 - 45.0) / 915.0);
+        assert!((lifi.score_breakdown.final_score - (0.4 * cost + 0.3 * speed + 0.2 + 0.1)).abs() < 1e-12);
+    }
+
+    fn hop_count() -> Objective {
+        Objective::new("hop_count", |path| path.hops.len() as f64, Direction::Minimize, 0.0)
+    }
+
+    #[test]
+    fn custom_objectives_are_weighed_by_name() {
+        let direct = path(&[("stargate", 2.0, 60.0, 5_000.0, 0.2)]);
+        let relayed = path(&[("across", 0.5, 30.0, 5_000.0, 0.1), ("hop", 0.5, 30.0, 5_000.0, 0.1)]);
+        let engine = ScoringEngine::new().with_objectives(ObjectiveRegistry::builtin().with_objective(hop_count()).unwrap());
+
+        // Unweighted, hop_count leaves the cheaper route on top
+        let cheapest = engine.score_and_rank(vec![direct.clone(), relayed.clone()], &RoutingParams::cheapest(), 2).unwrap();
+        assert_eq!(cheapest.ranked[0].path.total_cost, 1.0);
+
+        let params = RoutingParams::cheapest().with_weight("hop_count", 2.0);
+        let outcome = engine.score_and_rank_explained(vec![direct, relayed], &params, 2).unwrap();
+        assert_eq!(outcome.ranked[0].ranked.path.hops.len(), 1);
+        assert!((outcome.ranked[0].ranked.score_breakdown.final_score - 2.0 / 3.0).abs() < 1e-9);
+        let hops = outcome.ranked[1].explanations.iter().find(|explanation| explanation.factor == "hop_count").unwrap();
+        assert_eq!((hops.this_path, hops.best_path), (2.0, 1.0));
+        assert!((hops.weight - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(outcome.diagnostics.stats.as_ref().unwrap().range("hop_count"), Some(MinMax { min: 1.0, max: 2.0 }));
+
+        // Only registered objectives can be weighed
+        assert_eq!(engine.objectives().check(&params), Ok(()));
+        assert!(ScoringEngine::new().objectives().check(&params).is_err());
+    }
+
+    #[test]
+    fn custom_objectives_keep_routes_on_the_pareto_front() {
+        // The relayed route is cheaper at the same speed, liquidity and risk
+        let direct = path(&[("stargate", 2.0, 60.0, 5_000.0, 0.2)]);
+        let relayed = path(&[("across", 0.5, 30.0, 5_000.0, 0.1), ("hop", 0.5, 30.0, 5_000.0, 0.1)]);
+        let paths = vec![direct, relayed];
+        let params = RoutingParams::balanced();
+
+        let builtin = ScoringEngine::new().with_strategy(ScoringStrategy::ParetoFront).score_and_rank(paths.clone(), &params, 2).unwrap();
+        assert_eq!(builtin.ranked.len(), 1);
+        assert_eq!(builtin.ranked[0].path.hops.len(), 2);
+        assert_eq!(builtin.diagnostics.dropped_for(DropReason::Dominated), 1);
+
+        // But takes one more hop, so it no longer dominates the direct one
+        let with_hops = ScoringEngine::new()
+            .with_strategy(ScoringStrategy::ParetoFront)
+            .with_objectives(ObjectiveRegistry::builtin().with_objective(hop_count()).unwrap());
+        let front = with_hops.score_and_rank(paths.clone(), &params, 2).unwrap();
+        assert_eq!(front.ranked.len(), 2);
+        assert_eq!(front.diagnostics.dropped_for(DropReason::Dominated), 0);
+
+        // However much fewer hops weigh, the front is ordered on each weighed objective counted
+        // once: one route does better on cost, the other on hops, so the tie goes to the cheaper
+        let hop_heavy = with_hops.score_and_rank(paths, &params.with_weight("hop_count", 10.0), 2).unwrap();
+        let scores: Vec<f64> = hop_heavy.ranked.iter().map(|ranked| ranked.score_breakdown.final_score).collect();
+        assert_eq!(scores, [2.0, 2.0]);
+        assert_eq!(hop_heavy.ranked[0].path.hops.len(), 2);
+    }
+}
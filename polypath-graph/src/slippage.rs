@@ -0,0 +1,162 @@
+// Price impact of sending an amount through a hop, from how much of the hop's liquidity it uses
+
+use crate::error::SlippageError;
+use crate::types::Path;
+use serde::{Deserialize, Serialize};
+
+// Hops asked to move more than this share of their liquidity are refused
+pub const DEFAULT_MAX_UTILIZATION: f64 = 0.8;
+
+// Slippage at full utilization under SlippageModel::Sqrt
+const SQRT_IMPACT: f64 = 0.1;
+
+// Share of a hop's output lost at a given utilization, amount / liquidity
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "model", rename_all = "snake_case")]
+pub enum SlippageModel {
+    // impact_per_utilization × utilization
+    Linear { impact_per_utilization: f64 },
+    // The square-root law: 10% × √utilization, rising steeply at first and flattening after
+    Sqrt,
+}
+
+impl Default for SlippageModel {
+    fn default() -> Self {
+        SlippageModel::Linear { impact_per_utilization: 0.1 }
+    }
+}
+
+impl SlippageModel {
+    // 0-1 share of the output lost at `utilization`
+    pub fn slippage(&self, utilization: f64) -> f64 {
+        let utilization = utilization.max(0.0);
+        let slippage = match self {
+            SlippageModel::Linear { impact_per_utilization } => impact_per_utilization * utilization,
+            SlippageModel::Sqrt => SQRT_IMPACT * utilization.sqrt(),
+        };
+        slippage.clamp(0.0, 1.0)
+    }
+
+    // Sends `amount` along the path: each hop takes its fees (Hop::fees_in_source) and then loses
+    // its slippage at amount in / liquidity. Sets every hop's slippage_pct and the path's
+    // estimated_output, which it returns.
+    pub fn propagate(&self, path: &mut Path, amount: f64, max_utilization: f64) -> Result<f64, SlippageError> {
+        let mut amount_in = amount;
+        for (hop_index, hop) in path.hops.iter_mut().enumerate() {
+            // Hops without liquidity can't fill anything
+            let utilization = match hop.metrics.liquidity > 0.0 {
+                true => amount_in / hop.metrics.liquidity,
+                false => f64::INFINITY,
+            };
+            if utilization > max_utilization {
+                return Err(SlippageError::UtilizationExceeded {
+                    hop: hop_index,
+                    bridge: hop.bridge_name.to_string(),
+                    utilization,
+                    max: max_utilization,
+                });
+            }
+            let fees = hop.fees_in_source();
+            let after_fees = amount_in - fees;
+            if after_fees <= 0.0 {
+                return Err(SlippageError::FeesExceedAmount { hop: hop_index, bridge: hop.bridge_name.to_string(), amount_in, cost: fees });
+            }
+            let slippage = self.slippage(utilization);
+            hop.slippage_pct = Some(slippage * 100.0);
+            amount_in = after_fees * (1.0 - slippage);
+        }
+        path.estimated_output = Some(amount_in);
+        Ok(amount_in)
+    }
+}
+
+// An amount a search weighs edges at, so the output objective follows what each edge would
+// deliver of it rather than its nominal fees
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct SentAmount {
+    pub amount: f64,
+    pub model: SlippageModel,
+}
+
+impl SentAmount {
+    // What a hop with `fees`, in its source token, and `liquidity` loses of the amount: its fees,
+    // then its slippage on the rest, as `propagate` has it. All of it without liquidity.
+    pub(crate) fn loss(&self, fees: f64, liquidity: f64) -> f64 {
+        if liquidity <= 0.0 {
+            return self.amount;
+        }
+        let output = (self.amount - fees).max(0.0) * (1.0 - self.model.slippage(self.amount / liquidity));
+        self.amount - output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::path_with_hops;
+    use crate::types::EdgeQuote;
+
+    #[test]
+    fn larger_amounts_slip_more() {
+        // Two hops with 600k and 1M of liquidity
+        let path = path_with_hops(&[(1.0, 600_000.0), (2.0, 1_000_000.0)]);
+
+        for model in [SlippageModel::default(), SlippageModel::Sqrt] {
+            let mut last = (0.0, 0.0);
+            for amount in [1_000.0, 50_000.0, 200_000.0, 450_000.0] {
+                let mut sent = path.clone();
+                let output = model.propagate(&mut sent, amount, DEFAULT_MAX_UTILIZATION).unwrap();
+                let slippage = sent.hops[0].slippage_pct.unwrap();
+                assert!(slippage > last.0, "{:?} at {}", model, amount);
+                // What's lost beyond the fees grows faster than the amount
+                let lost = (amount - 3.0 - output) / amount;
+                assert!(lost > last.1, "{:?} at {}", model, amount);
+                assert_eq!(sent.estimated_output, Some(output));
+                last = (slippage, lost);
+            }
+        }
+
+        let mut sent = path.clone();
+        let linear = SlippageModel::Linear { impact_per_utilization: 0.1 };
+        let output = linear.propagate(&mut sent, 300_000.0, DEFAULT_MAX_UTILIZATION).unwrap();
+        // 5% on the first hop, 299,999 × 0.95 = 284,999.05 into the second at 2.85% utilization
+        assert!((sent.hops[0].slippage_pct.unwrap() - 5.0).abs() < 1e-9);
+        let second = 284_999.05 / 1_000_000.0 * 0.1;
+        assert!((output - (284_999.05 - 2.0) * (1.0 - second)).abs() < 1e-6, "{}", output);
+    }
+
+    #[test]
+    fn hops_past_the_utilization_cap_are_refused() {
+        let path = path_with_hops(&[(1.0, 600_000.0), (2.0, 1_000_000.0)]);
+
+        let err = SlippageModel::Sqrt.propagate(&mut path.clone(), 500_000.0, DEFAULT_MAX_UTILIZATION).unwrap_err();
+        assert!(matches!(err, SlippageError::UtilizationExceeded { hop: 0, .. }), "{}", err);
+        assert!(err.to_string().contains("83%"), "{}", err);
+        assert!(SlippageModel::Sqrt.propagate(&mut path.clone(), 500_000.0, 0.9).is_ok());
+
+        let dry = path_with_hops(&[(1.0, 0.0)]);
+        assert!(SlippageModel::Sqrt.propagate(&mut dry.clone(), 1.0, DEFAULT_MAX_UTILIZATION).is_err());
+        let costly = path_with_hops(&[(5.0, 1_000.0)]);
+        assert!(matches!(SlippageModel::Sqrt.propagate(&mut costly.clone(), 5.0, DEFAULT_MAX_UTILIZATION), Err(SlippageError::FeesExceedAmount { .. })));
+    }
+
+    #[test]
+    fn fees_come_out_in_the_source_token() {
+        // A cost of 1 in the quote currency that's 4 of the hop's token
+        let mut path = path_with_hops(&[(1.0, 1_000_000.0)]);
+        path.hops[0].quote = Some(EdgeQuote {
+            reference: "q".to_string(),
+            quoted_at: 0,
+            valid_until: None,
+            fees: Vec::new(),
+            speed_breakdown: None,
+            source: None,
+            sources: Vec::new(),
+            cost_in_source: Some(4.0),
+        });
+        let linear = SlippageModel::Linear { impact_per_utilization: 0.0 };
+        assert_eq!(linear.propagate(&mut path.clone(), 100.0, DEFAULT_MAX_UTILIZATION), Ok(96.0));
+        let err = linear.propagate(&mut path, 3.0, DEFAULT_MAX_UTILIZATION).unwrap_err();
+        assert!(matches!(err, SlippageError::FeesExceedAmount { cost, .. } if cost == 4.0), "{}", err);
+    }
+}
@@ -0,0 +1,168 @@
+// Seeded synthetic graphs and paths for benches and tests. The same seed and sizes always
+// give the same graph.
+
+use crate::graph::Graph;
+use crate::types::*;
+
+// Numbers of the bridges `layered_graph` adds its random edges over
+const RANDOM_BRIDGES: std::ops::Range<usize> = 1..8;
+
+// A graph whose asset nodes sit in `layers` layers, with edges only from one layer to the next
+#[derive(Debug)]
+pub struct LayeredGraph {
+    pub graph: Graph,
+    pub layers: Vec<Vec<NodeId>>,
+}
+
+impl LayeredGraph {
+    // First node of the first layer, which reaches every layer
+    pub fn source(&self) -> NodeId {
+        self.layers[0][0]
+    }
+
+    // First node of the last layer
+    pub fn sink(&self) -> NodeId {
+        self.layers[self.layers.len() - 1][0]
+    }
+}
+
+fn metrics(rng: &mut fastrand::Rng) -> EdgeMetrics {
+    EdgeMetrics {
+        cost: 0.1 + rng.f64() * 10.0,
+        speed: 10.0 + rng.f64() * 900.0,
+        liquidity: 1_000.0 + rng.f64() * 10_000_000.0,
+        risk: rng.f64(),
+    }
+}
+
+// `edges` edges between `layers` layers of `width` nodes. The source gets an edge to every
+// node of the second layer, and every node after that one edge into the next layer, each
+// receiving one, so the whole graph is reachable from the source. The remaining edges join
+// random nodes of neighbouring layers over bridge1 to bridge7, never two over one bridge
+// between the same nodes; `edges` below (layers - 1) * width still gets the first
+// ones, and past what the layers have room for gets that many.
+pub fn layered_graph(seed: u64, layers: usize, width: usize, edges: usize) -> LayeredGraph {
+    assert!(layers >= 2 && width >= 1, "a layered graph needs two layers of at least one node");
+    let mut rng = fastrand::Rng::with_seed(seed);
+    let graph = Graph::new(64);
+    let layers: Vec<Vec<NodeId>> = (0..layers)
+        .map(|layer| {
+            (0..width)
+                .map(|i| graph.get_or_create_asset_node(&format!("chain{}", layer), &format!("0x{:040x}", i), "TKN"))
+                .collect()
+        })
+        .collect();
+
+    let mut added = 0;
+    for (layer, pair) in layers.windows(2).enumerate() {
+        let mut targets = pair[1].clone();
+        rng.shuffle(&mut targets);
+        for (i, to) in targets.iter().enumerate() {
+            let from = if layer == 0 { pair[0][0] } else { pair[0][i] };
+            graph.add_edge(from, *to, "bridge0", metrics(&mut rng), None, None).unwrap();
+            added += 1;
+        }
+    }
+    let edges = edges.min(added + (layers.len() - 1) * width * width * (RANDOM_BRIDGES.end - RANDOM_BRIDGES.start));
+    while added < edges {
+        let layer = rng.usize(..layers.len() - 1);
+        let from = layers[layer][rng.usize(..width)];
+        let to = layers[layer + 1][rng.usize(..width)];
+        let bridge = format!("bridge{}", rng.usize(RANDOM_BRIDGES));
+        if graph.get_outgoing_edges(from).iter().any(|edge| edge.to == to && *edge.bridge_name == *bridge) {
+            continue;
+        }
+        graph.add_edge(from, to, &bridge, metrics(&mut rng), None, None).unwrap();
+        added += 1;
+    }
+    LayeredGraph { graph, layers }
+}
+
+// `count` paths of one to four hops with random metrics and consistent totals
+pub fn random_paths(seed: u64, count: usize) -> Vec<Path> {
+    let mut rng = fastrand::Rng::with_seed(seed);
+    (0..count)
+        .map(|_| {
+            let hops: Vec<Hop> = (0..rng.usize(1..=4))
+                .map(|i| Hop {
+                    from: NodeId(i as u64),
+                    to: NodeId(i as u64 + 1),
+                    bridge_name: format!("bridge{}", rng.usize(0..8)).into(),
+                    kind: EdgeKind::Bridge,
+                    metrics: metrics(&mut rng),
+                    quote: None,
+                    slippage_pct: None,
+                    alternatives: None,
+                    confidence: 1.0,
+                })
+                .collect();
+            Path {
+                total_cost: hops.iter().map(|hop| hop.metrics.cost).sum(),
+                total_time: hops.iter().map(|hop| hop.metrics.speed).sum(),
+                total_risk: hops.iter().map(|hop| hop.metrics.risk).sum(),
+                min_liquidity: hops.iter().map(|hop| hop.metrics.liquidity).fold(f64::INFINITY, f64::min),
+                aggregate_score: 0.0,
+                estimated_output: None,
+                graph_version: 0,
+                confidence: 1.0,
+                hops,
+            }
+        })
+        .collect()
+}
+
+// A path with one hop per (cost, liquidity), over bridges named after their position
+pub fn path_with_hops(hops: &[(f64, f64)]) -> Path {
+    let hops: Vec<Hop> = hops
+        .iter()
+        .enumerate()
+        .map(|(i, (cost, liquidity))| Hop {
+            from: NodeId(i as u64),
+            to: NodeId(i as u64 + 1),
+            bridge_name: format!("bridge{}", i).into(),
+            kind: EdgeKind::Bridge,
+            metrics: EdgeMetrics { cost: *cost, speed: 60.0, liquidity: *liquidity, risk: 0.1 },
+            quote: None,
+            slippage_pct: None,
+            alternatives: None,
+            confidence: 1.0,
+        })
+        .collect();
+    Path {
+        total_cost: hops.iter().map(|hop| hop.metrics.cost).sum(),
+        total_time: hops.iter().map(|hop| hop.metrics.speed).sum(),
+        total_risk: hops.iter().map(|hop| hop.metrics.risk).sum(),
+        min_liquidity: hops.iter().map(|hop| hop.metrics.liquidity).reduce(f64::min).unwrap_or(0.0),
+        aggregate_score: 0.0,
+        estimated_output: None,
+        graph_version: 0,
+        confidence: 1.0,
+        hops,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routing::RoutingEngine;
+    use std::sync::Arc;
+
+    #[test]
+    fn generation_is_deterministic_and_connected() {
+        let snapshot = |seed| serde_json::to_string(&layered_graph(seed, 5, 20, 400).graph.snapshot().edges).unwrap();
+        assert_eq!(snapshot(7), snapshot(7));
+        assert_ne!(snapshot(7), snapshot(8));
+
+        let layered = layered_graph(7, 5, 20, 400);
+        assert_eq!((layered.graph.node_count(), layered.graph.edge_count()), (100, 400));
+        assert_eq!(layered.graph.check_invariants(), []);
+        let source = layered.source();
+        let engine = RoutingEngine::new(Arc::new(layered.graph), 4);
+        for sink in &layered.layers[4] {
+            assert_eq!(engine.find_path(source, *sink, &RoutingParams::balanced()).unwrap().hops.len(), 4);
+        }
+
+        assert_eq!(random_paths(3, 10), random_paths(3, 10));
+        assert!(random_paths(3, 10).iter().all(|path| !path.hops.is_empty() && path.total_cost > 0.0));
+    }
+}
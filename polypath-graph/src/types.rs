@@ -1,6 +1,6 @@
 use std::{
     sync::{
-        Arc, 
+        Arc,
         RwLock,
         atomic::{
             AtomicU64,
@@ -9,6 +9,7 @@ use std::{
         }
     },
     collections::{
+        BTreeMap,
         HashMap,
         hash_map::DefaultHasher
     },
@@ -19,6 +20,7 @@ use std::{
     }
 };
 use serde::{Serialize, Deserialize};
+use thiserror::Error;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct NodeId(pub u64);
@@ -32,7 +34,7 @@ impl NodeId {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum NodeType {
     Asset {
         chain: String, 
@@ -45,7 +47,17 @@ pub enum NodeType {
     }
 }
 
-#[derive(Debug, Clone)]
+impl NodeType {
+    // The id the graph gives a node of this type
+    pub fn id(&self) -> NodeId {
+        match self {
+            NodeType::Asset { chain, token_address, .. } => NodeId::from_parts(chain, token_address),
+            NodeType::Exchange { name, chain } => NodeId::from_parts("exchange", &format!("{}:{}", name, chain)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Node {
     pub id: NodeId,
     pub node_type: NodeType,
@@ -53,7 +65,52 @@ pub struct Node {
     pub created_at: SystemTime
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// A node to create, see Graph::get_or_create_node and Graph::try_create_node
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeSpec {
+    pub node_type: NodeType,
+    pub metadata: HashMap<String, String>,
+}
+
+impl NodeSpec {
+    pub fn asset(chain: &str, token_address: &str, token_symbol: &str) -> Self {
+        Self::of(NodeType::Asset {
+            chain: chain.to_string(),
+            token_address: token_address.to_string(),
+            token_symbol: token_symbol.to_string(),
+        })
+    }
+
+    pub fn exchange(name: &str, chain: &str) -> Self {
+        Self::of(NodeType::Exchange { name: name.to_string(), chain: chain.to_string() })
+    }
+
+    pub fn of(node_type: NodeType) -> Self {
+        Self { node_type, metadata: HashMap::new() }
+    }
+
+    pub fn with_metadata(mut self, key: &str, value: &str) -> Self {
+        self.metadata.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    pub fn id(&self) -> NodeId {
+        self.node_type.id()
+    }
+
+    pub(crate) fn into_node(self) -> Node {
+        Node { id: self.id(), node_type: self.node_type, metadata: self.metadata, created_at: SystemTime::now() }
+    }
+}
+
+// Whether Graph::get_or_create_node made the node or found it there
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeCreation {
+    Created,
+    Existing,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct EdgeMetrics {
     pub cost: f64,
     pub speed: f64,
@@ -61,6 +118,56 @@ pub struct EdgeMetrics {
     pub risk: f64
 }
 
+// The bridge quote an edge's metrics came from, for executing a route over it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EdgeQuote {
+    // Identifies the quote to the bridge or to whoever executes the route
+    pub reference: String,
+    // Unix seconds
+    pub quoted_at: u64,
+    // Unix seconds; None when the bridge gave no expiry
+    pub valid_until: Option<u64>,
+    // What the edge's cost is made of, as far as the quote and the updater break it down
+    #[serde(default)]
+    pub fees: Vec<QuoteFee>,
+    // What the edge's speed is made of, when the updater added settlement to the bridge's own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speed_breakdown: Option<SpeedBreakdown>,
+    // Adapter that gave the quote, which for a bridge with several sources needn't be the
+    // bridge the edge is labelled with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    // Every adapter currently quoting the edge's route, `source` among them, when several
+    // adapters' quotes were merged into the one edge
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<String>,
+    // The edge's cost in its source token when its metrics put the cost in a quote currency
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_in_source: Option<f64>,
+}
+
+// One line of an EdgeQuote's fee breakdown, in human units of `token`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuoteFee {
+    pub name: String,
+    pub amount: f64,
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+// An edge's speed in seconds: the bridge's reported duration and the chains' finality
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SpeedBreakdown {
+    pub bridge_secs: f64,
+    pub finality_secs: f64,
+}
+
+impl EdgeQuote {
+    pub fn is_expired_at(&self, now: u64) -> bool {
+        self.valid_until.is_some_and(|until| now >= until)
+    }
+}
+
 // Designed for lock free reads
 #[derive(Debug)]
 pub struct EdgeMetricsAtomic {
@@ -99,114 +206,430 @@ impl EdgeMetricsAtomic {
     }
 
     pub fn update(&self, metrics: EdgeMetrics) -> bool {
+        self.update_at(metrics, SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs())
+    }
+
+    // As update, with the write stamped at unix time `updated_at` rather than now
+    pub fn update_at(&self, metrics: EdgeMetrics, updated_at: u64) -> bool {
         self.cost.store((metrics.cost * 1_000_000.0) as u64, Ordering::Release);
         self.speed.store((metrics.speed * 1_000.0) as u64, Ordering::Release);
         self.liquidity.store((metrics.liquidity) as u64, Ordering::Release);
         self.risk.store((metrics.risk * 1_000_000.0) as u64, Ordering::Release);
-        self.last_updated.store(
-            SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
-            Ordering::Release,
-        );
+        self.last_updated.store(updated_at, Ordering::Release);
         true
     }
+
+    // Unix seconds of the last write
+    pub fn last_updated(&self) -> u64 {
+        self.last_updated.load(Ordering::Acquire)
+    }
+
+    // A separate copy of the values as they are now
+    fn copy(&self) -> Self {
+        Self {
+            cost: AtomicU64::new(self.cost.load(Ordering::Acquire)),
+            speed: AtomicU64::new(self.speed.load(Ordering::Acquire)),
+            liquidity: AtomicU64::new(self.liquidity.load(Ordering::Acquire)),
+            risk: AtomicU64::new(self.risk.load(Ordering::Acquire)),
+            last_updated: AtomicU64::new(self.last_updated.load(Ordering::Acquire)),
+        }
+    }
+}
+
+// What an edge moves a token with: a bridge to another chain, or a swap on the same chain
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeKind {
+    #[default]
+    Bridge,
+    Swap,
+}
+
+impl EdgeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EdgeKind::Bridge => "bridge",
+            EdgeKind::Swap => "swap",
+        }
+    }
+}
+
+// Bounds on the source amount an edge's bridge accepts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct AmountLimits {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl AmountLimits {
+    pub fn allows(&self, amount: f64) -> bool {
+        self.min.is_none_or(|min| amount >= min) && self.max.is_none_or(|max| amount <= max)
+    }
+}
+
+// Parts of an edge for Graph::update_edge to replace; None leaves a part as it is
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EdgeUpdate {
+    pub metrics: Option<EdgeMetrics>,
+    // Some(None) drops a limit
+    pub min_amount: Option<Option<f64>>,
+    pub max_amount: Option<Option<f64>>,
+    pub active: Option<bool>,
+    // Of the edge's quote; edges without a quote have nothing for it to change
+    pub valid_until: Option<Option<u64>>,
+}
+
+// What Graph::update_edge changed. Given metrics always count, as they restamp the edge and
+// clear its stale flag.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EdgeChanges {
+    pub metrics: bool,
+    pub limits: bool,
+    pub active: bool,
+    pub valid_until: bool,
+}
+
+impl EdgeChanges {
+    pub fn any(&self) -> bool {
+        self.metrics || self.limits || self.active || self.valid_until
+    }
 }
 
 #[derive(Debug)]
 pub struct Edge {
     pub from: NodeId,
     pub to: NodeId,
-    pub bridge_name: String,
+    // The bridge, or for a swap edge the venue; shared with the graph's other edges of the bridge
+    // and the hops taking them
+    pub bridge_name: Arc<str>,
+    pub kind: EdgeKind,
     pub metrics: Arc<EdgeMetricsAtomic>,
     pub is_active: Arc<AtomicBool>,
-    pub min_amount: Option<f64>,
-    pub max_amount: Option<f64>,
+    // Set on edges restored from a snapshot until fresh metrics arrive
+    pub is_stale: Arc<AtomicBool>,
+    // Source amounts the bridge accepts, both behind one lock so readers never see a min from
+    // one update with the max from another. See Graph::update_edge.
+    pub limits: Arc<RwLock<AmountLimits>>,
+    // Latest quote behind the metrics, see Graph::set_edge_quote
+    pub quote: Arc<RwLock<Option<EdgeQuote>>>,
 }
 
 impl Edge {
     pub fn new(
         from: NodeId,
         to: NodeId,
-        bridge_name: String,
+        bridge_name: impl Into<Arc<str>>,
         metrics: EdgeMetrics,
         min_amount: Option<f64>,
         max_amount: Option<f64>
     ) -> Self {
         Self {
-            from: from,
-            to: to,
-            bridge_name: bridge_name,
+            from,
+            to,
+            bridge_name: bridge_name.into(),
+            kind: EdgeKind::Bridge,
             metrics: Arc::new(EdgeMetricsAtomic::new(metrics)),
             is_active: Arc::new(AtomicBool::new(true)),
-            min_amount: min_amount,
-            max_amount: max_amount
+            is_stale: Arc::new(AtomicBool::new(false)),
+            limits: Arc::new(RwLock::new(AmountLimits { min: min_amount, max: max_amount })),
+            quote: Arc::new(RwLock::new(None)),
         }
     }
 
+    pub fn with_kind(mut self, kind: EdgeKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
     pub fn is_active(&self) -> bool {
         self.is_active.load(Ordering::Acquire)
     }
 
+    pub fn is_stale(&self) -> bool {
+        self.is_stale.load(Ordering::Acquire)
+    }
+
     pub fn get_metrics(&self) -> EdgeMetrics {
         self.metrics.read()
     }
+
+    pub fn get_quote(&self) -> Option<EdgeQuote> {
+        self.quote.read().unwrap().clone()
+    }
+
+    // The quote's cost in the source token, without copying the rest of it
+    pub fn cost_in_source(&self) -> Option<f64> {
+        self.quote.read().unwrap().as_ref().and_then(|quote| quote.cost_in_source)
+    }
+
+    pub fn amount_limits(&self) -> AmountLimits {
+        *self.limits.read().unwrap()
+    }
+
+    pub fn min_amount(&self) -> Option<f64> {
+        self.amount_limits().min
+    }
+
+    pub fn max_amount(&self) -> Option<f64> {
+        self.amount_limits().max
+    }
+
+    // The edge as it is now, with metrics, flags and quote of its own that later writes to
+    // this one don't reach
+    pub(crate) fn frozen(&self) -> Edge {
+        Edge {
+            from: self.from,
+            to: self.to,
+            bridge_name: self.bridge_name.clone(),
+            kind: self.kind,
+            metrics: Arc::new(self.metrics.copy()),
+            is_active: Arc::new(AtomicBool::new(self.is_active())),
+            is_stale: Arc::new(AtomicBool::new(self.is_stale())),
+            limits: Arc::new(RwLock::new(self.amount_limits())),
+            quote: Arc::new(RwLock::new(self.get_quote())),
+        }
+    }
+}
+
+// How much of what a graph is configured to carry it has edges for, see Graph::set_coverage
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Coverage {
+    // 0-1: configured pairs with a fresh active edge, those whose edge was only restored from a
+    // snapshot counting for less
+    pub fraction: f64,
+    pub configured: usize,
+    pub fresh: usize,
+    // Pairs whose active edge was restored from a snapshot and hasn't been quoted since
+    pub stale: usize,
+    // A few of the pairs without an active edge
+    pub missing_sample: Vec<String>,
+    // Whether a measure ever reached the minimum coverage; once it has, a later dip, like a
+    // bridge down for a while, doesn't put the graph back into warm-up
+    #[serde(default)]
+    pub warmed_up: bool,
+}
+
+// Serializable form of a Graph, see Graph::snapshot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphSnapshot {
+    pub version: u64,
+    // The graph's clock when it was taken; absent from snapshots taken before it was recorded
+    #[serde(default)]
+    pub clock: u64,
+    pub nodes: Vec<Node>,
+    pub edges: Vec<EdgeSnapshot>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EdgeSnapshot {
+    pub from: NodeId,
+    pub to: NodeId,
+    pub bridge_name: String,
+    // Absent from snapshots taken before swap edges
+    #[serde(default)]
+    pub kind: EdgeKind,
+    pub metrics: EdgeMetrics,
+    pub min_amount: Option<f64>,
+    pub max_amount: Option<f64>,
+    pub is_active: bool,
 }
 
 // A single hop in a path
-#[derive(Debug, Clone, Serialize)]
+// Field names are part of the persisted/API schema; rename with #[serde(rename)] rather than changing them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Hop {
     pub from: NodeId,
     pub to: NodeId,
-    pub bridge_name: String,
-    pub metrics: EdgeMetrics
+    pub bridge_name: Arc<str>,
+    #[serde(default)]
+    pub kind: EdgeKind,
+    pub metrics: EdgeMetrics,
+    // Quote behind `metrics` when the path was found
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quote: Option<EdgeQuote>,
+    // Price impact in percent of the hop's output, when an amount was propagated through it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slippage_pct: Option<f64>,
+    // Other active edges between the same two nodes when the path was found; None for hops
+    // not found in a graph
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alternatives: Option<usize>,
+    // 0-1, how far the hop's metrics could be trusted when the path was found, see ConfidenceModel
+    #[serde(default = "full_confidence")]
+    pub confidence: f64,
+}
+
+impl Hop {
+    // What the hop's fees take out of the amount sent through it, in its source token
+    pub fn fees_in_source(&self) -> f64 {
+        self.quote.as_ref().and_then(|quote| quote.cost_in_source).unwrap_or(self.metrics.cost)
+    }
+
+    // 0 for a hop with no alternative, approaching 1 as alternatives are added
+    pub fn redundancy_score(&self) -> Option<f64> {
+        self.alternatives.map(|alternatives| 1.0 - 1.0 / (alternatives as f64 + 1.0))
+    }
+
+    pub fn is_single_point_of_failure(&self) -> bool {
+        self.alternatives == Some(0)
+    }
 }
 
 // complete path from source to destination
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Path {
     pub hops: Vec<Hop>,
     pub total_cost: f64, 
+    // seconds, summed across hops
     pub total_time: f64,
     pub total_risk: f64,
+    // smallest hop liquidity; 0.0 for a path without hops
     pub min_liquidity: f64,
     pub aggregate_score: f64,
+    // amount received at the destination, when the input amount was propagated through the hops
+    #[serde(default)]
+    pub estimated_output: Option<f64>,
+    // Graph::version when the path was found; 0 for paths not found in a graph
+    #[serde(default)]
+    pub graph_version: u64,
+    // The hops' confidence combined, see ConfidenceModel::path_confidence
+    #[serde(default = "full_confidence")]
+    pub confidence: f64,
+}
+
+// Paths and hops serialized before confidence was kept
+fn full_confidence() -> f64 {
+    1.0
 }
 
-#[derive(Debug, Clone, Serialize)]
+impl Path {
+    pub fn is_empty(&self) -> bool {
+        self.hops.is_empty()
+    }
+
+    // Price impact of the hops compounded, in percent; None when no amount was propagated
+    pub fn slippage_pct(&self) -> Option<f64> {
+        let kept = self.hops.iter().map(|hop| hop.slippage_pct.map(|pct| 1.0 - pct / 100.0)).product::<Option<f64>>()?;
+        Some((1.0 - kept) * 100.0)
+    }
+
+    // Redundancy of the least redundant hop; None for a path without hops or with a hop whose
+    // alternatives aren't known
+    pub fn redundancy_score(&self) -> Option<f64> {
+        let scores = self.hops.iter().map(Hop::redundancy_score).collect::<Option<Vec<f64>>>()?;
+        scores.into_iter().reduce(f64::min)
+    }
+
+    // min_liquidity guarded against empty or hand-built paths carrying a non-finite value
+    pub fn effective_min_liquidity(&self) -> f64 {
+        if self.is_empty() || !self.min_liquidity.is_finite() {
+            0.0
+        } else {
+            self.min_liquidity
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ScoreBreakDown {
     pub cost_score: f64,
+    // total_time of the path, in seconds
     pub speed_score: f64,
     pub liquidity_score: f64,
     pub risk_score: f64,
-    pub final_score: f64
+    pub final_score: f64,
+    #[serde(default)]
+    pub estimated_output: Option<f64>
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RankedPath {
     pub path: Path, 
     pub rank: usize, 
-    pub score_breakdown: ScoreBreakDown
+    pub score_breakdown: ScoreBreakDown,
+    // Whether the intent's src_address can pay the first step's gas, see Router::check_affordability
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub affordability: Option<Affordability>,
 }
 
+// What a RankedPath's sender holds of the source chain's native token against the gas its first
+// step needs, both in human units of that token
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Affordability {
+    Affordable,
+    InsufficientGas { required: f64, available: f64 },
+    // The balance or the gas couldn't be found out
+    Unknown,
+}
+
+// Caller-chosen identifier grouping the candidate paths of one intent in batch scoring
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct IntentId(pub String);
+
+impl From<&str> for IntentId {
+    fn from(value: &str) -> Self {
+        IntentId(value.to_string())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RouteIntent {
     pub from_chain: String,
     pub from_token: String,
     pub to_chain: String,
     pub to_token: String,
     pub amount: f64,
-    pub preference: Option<String> // "cheapest" , "fastest", "balanced"
+    pub preference: Option<String>, // "cheapest" , "fastest", "balanced", "safest", "max-liquidity", "max-output"
+    // Wallet the route would be sent from, for checking it can pay the gas
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub src_address: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+impl RouteIntent {
+    // Stable id of the intent, the same for every query asking for the same transfer: FNV-1a
+    // over its fields, tokens compared case-insensitively, as 16 hex digits
+    pub fn intent_hash(&self) -> String {
+        let canonical = format!(
+            "{}:{}->{}:{}:{}:{}",
+            self.from_chain.to_lowercase(),
+            self.from_token.to_lowercase(),
+            self.to_chain.to_lowercase(),
+            self.to_token.to_lowercase(),
+            self.amount,
+            self.preference.as_deref().unwrap_or(""),
+        );
+        let hash = canonical.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3));
+        format!("{:016x}", hash)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RoutingParams {
     pub alpha: f64, // Cost weight
     pub beta: f64, // Speed weight
     pub gamma: f64, // Liquidity Weight (inversely connected!)
     pub delta: f64, // Risk weight
+    pub omega: f64, // Estimated output weight
+    #[serde(default)]
+    pub epsilon: f64, // Redundancy weight
+    // Weights of objectives registered beyond the built-in ones, by name, see ObjectiveRegistry.
+    // The built-in objectives are weighed by the fields above. Literals that listed every field
+    // before this one need `..RoutingParams::default()` now, as any new weight would ask for.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub weights: BTreeMap<String, f64>,
 }
 
+// The built-in objectives and the fields weighing them, which are also accepted as their names
+pub const WEIGHT_ALIASES: [(&str, &str); 6] = [
+    ("cost", "alpha"),
+    ("speed", "beta"),
+    ("liquidity", "gamma"),
+    ("risk", "delta"),
+    ("output", "omega"),
+    ("redundancy", "epsilon"),
+];
+
 impl Default for RoutingParams {
     fn default() -> Self {
         Self {
@@ -214,6 +637,9 @@ impl Default for RoutingParams {
             beta: 0.3,
             gamma: 0.2,
             delta: 0.1,
+            omega: 0.0,
+            epsilon: 0.0,
+            weights: BTreeMap::new(),
         }
     }
 }
@@ -224,7 +650,10 @@ impl RoutingParams {
             alpha: 1.0,
             beta: 0.0,
             gamma: 0.0,
-            delta: 0.0
+            delta: 0.0,
+            omega: 0.0,
+            epsilon: 0.0,
+            weights: BTreeMap::new(),
         }
     }
 
@@ -233,7 +662,10 @@ impl RoutingParams {
             alpha: 0.0,
             beta: 1.0,
             gamma: 0.0,
-            delta: 0.0
+            delta: 0.0,
+            omega: 0.0,
+            epsilon: 0.0,
+            weights: BTreeMap::new(),
         }
     }
 
@@ -241,20 +673,321 @@ impl RoutingParams {
         Self::default()
     }
 
+    pub fn safest() -> Self {
+        Self {
+            alpha: 0.1,
+            beta: 0.1,
+            gamma: 0.2,
+            delta: 0.6,
+            omega: 0.0,
+            epsilon: 0.0,
+            weights: BTreeMap::new(),
+        }
+    }
+
+    pub fn max_liquidity() -> Self {
+        Self {
+            alpha: 0.1,
+            beta: 0.1,
+            gamma: 0.7,
+            delta: 0.1,
+            omega: 0.0,
+            epsilon: 0.0,
+            weights: BTreeMap::new(),
+        }
+    }
+
+    pub fn max_output() -> Self {
+        Self {
+            alpha: 0.0,
+            beta: 0.0,
+            gamma: 0.0,
+            delta: 0.0,
+            omega: 1.0,
+            epsilon: 0.0,
+            weights: BTreeMap::new(),
+        }
+    }
+
+    // Unknown preferences fall back to the balanced preset; use `preset` to detect them
     pub fn from_preferences(preference: &str) -> Self {
+        Self::preset(preference).unwrap_or_else(Self::balanced)
+    }
+
+    pub fn preset(preference: &str) -> Option<Self> {
         match preference {
-            "cheapest" => {
-                Self::cheapest()
-            }
-            "fastest" => {
-                Self::fastest()
+            "cheapest" => Some(Self::cheapest()),
+            "fastest" => Some(Self::fastest()),
+            "balanced" => Some(Self::balanced()),
+            "safest" => Some(Self::safest()),
+            "max-liquidity" => Some(Self::max_liquidity()),
+            "max-output" => Some(Self::max_output()),
+            _ => None,
+        }
+    }
+
+    // The weight of the objective named `objective`, or of the built-in one whose field it names;
+    // None when the params don't weigh it
+    pub fn weight(&self, objective: &str) -> Option<f64> {
+        match builtin(objective) {
+            Some(name) => Some(*self.field(name)),
+            None => self.weights.get(objective).copied(),
+        }
+    }
+
+    // Sets the weight of `objective` by name, its field for a built-in one
+    pub fn with_weight(mut self, objective: &str, weight: f64) -> Self {
+        match builtin(objective) {
+            Some(name) => *self.field_mut(name) = weight,
+            None => {
+                self.weights.insert(objective.to_string(), weight);
             }
-            "balanced" => {
-                Self::balanced()
+        }
+        self
+    }
+
+    fn field(&self, objective: &str) -> &f64 {
+        match objective {
+            "cost" => &self.alpha,
+            "speed" => &self.beta,
+            "liquidity" => &self.gamma,
+            "risk" => &self.delta,
+            "output" => &self.omega,
+            _ => &self.epsilon,
+        }
+    }
+
+    fn field_mut(&mut self, objective: &str) -> &mut f64 {
+        match objective {
+            "cost" => &mut self.alpha,
+            "speed" => &mut self.beta,
+            "liquidity" => &mut self.gamma,
+            "risk" => &mut self.delta,
+            "output" => &mut self.omega,
+            _ => &mut self.epsilon,
+        }
+    }
+
+    // Every weight set, the built-in ones under their field names
+    fn all_weights(&self) -> impl Iterator<Item = (&str, f64)> {
+        WEIGHT_ALIASES
+            .iter()
+            .map(|(objective, field)| (*field, *self.field(objective)))
+            .chain(self.weights.iter().map(|(name, weight)| (name.as_str(), *weight)))
+    }
+
+    fn weight_sum(&self) -> f64 {
+        self.all_weights().map(|(_, weight)| weight).sum()
+    }
+
+    // Rejects NaN/infinite and negative weights, an all-zero weight set, and entries in `weights`
+    // for built-in objectives, which are weighed by their fields.
+    pub fn validate(&self) -> Result<(), ParamError> {
+        for (name, value) in self.all_weights() {
+            if !value.is_finite() {
+                return Err(ParamError::NotFinite { name: name.to_string(), value });
             }
-            _ => {
-                Self::balanced()
+            if value < 0.0 {
+                return Err(ParamError::Negative { name: name.to_string(), value });
             }
         }
+        if let Some(name) = self.weights.keys().find(|name| builtin(name).is_some()) {
+            let field = WEIGHT_ALIASES.iter().find(|(objective, field)| name == objective || name == field).map_or("", |(_, field)| field);
+            return Err(ParamError::Shadowed { name: name.clone(), field });
+        }
+
+        if self.weight_sum() <= 0.0 {
+            return Err(ParamError::ZeroSum);
+        }
+
+        Ok(())
+    }
+
+    // Scales the weights so they sum to 1.0.
+    // Invalid params fall back to the balanced preset; call validate() first to surface the error instead.
+    pub fn normalized(&self) -> Self {
+        if self.validate().is_err() {
+            return Self::balanced();
+        }
+
+        let sum = self.weight_sum();
+        Self {
+            alpha: self.alpha / sum,
+            beta: self.beta / sum,
+            gamma: self.gamma / sum,
+            delta: self.delta / sum,
+            omega: self.omega / sum,
+            epsilon: self.epsilon / sum,
+            weights: self.weights.iter().map(|(name, weight)| (name.clone(), weight / sum)).collect(),
+        }
+    }
+}
+
+// The built-in objective `name` is, or names by its field
+fn builtin(name: &str) -> Option<&'static str> {
+    WEIGHT_ALIASES
+        .iter()
+        .find(|(objective, field)| name == *objective || name == *field)
+        .map(|(objective, _)| *objective)
+}
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum ParamError {
+    #[error("routing weight `{name}` must be a finite number, got {value}")]
+    NotFinite { name: String, value: f64 },
+    #[error("routing weight `{name}` must not be negative, got {value}")]
+    Negative { name: String, value: f64 },
+    #[error("routing weights must not all be zero")]
+    ZeroSum,
+    // A built-in objective in `weights`, whose weight is its field's
+    #[error("routing weight `{name}` is set by `{field}`")]
+    Shadowed { name: String, field: &'static str },
+    // A weight for an objective the scoring engine doesn't have
+    #[error("no objective `{0}` to weigh")]
+    UnknownObjective(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_sums_to_one(params: &RoutingParams) {
+        let sum = params.alpha + params.beta + params.gamma + params.delta + params.omega + params.epsilon;
+        assert!((sum - 1.0).abs() < 1e-9, "weights sum to {}", sum);
+    }
+
+    #[test]
+    fn validate_rejects_invalid_weights() {
+        let negative = RoutingParams { alpha: -3.0, beta: 7.0, gamma: 0.0, delta: 0.0, omega: 0.0, epsilon: 0.0, ..RoutingParams::default() };
+        assert_eq!(negative.validate(), Err(ParamError::Negative { name: "alpha".to_string(), value: -3.0 }));
+
+        let nan = RoutingParams { alpha: 0.5, beta: f64::NAN, gamma: 0.0, delta: 0.0, omega: 0.0, epsilon: 0.0, ..RoutingParams::default() };
+        assert!(matches!(nan.validate(), Err(ParamError::NotFinite { name, .. }) if name == "beta"));
+
+        let zero = RoutingParams { alpha: 0.0, beta: 0.0, gamma: 0.0, delta: 0.0, omega: 0.0, epsilon: 0.0, ..RoutingParams::default() };
+        assert_eq!(zero.validate(), Err(ParamError::ZeroSum));
+        // A custom objective's weight counts towards the sum
+        assert!(zero.clone().with_weight("hop_count", 1.0).validate().is_ok());
+        assert_eq!(zero.clone().with_weight("hop_count", -1.0).validate(), Err(ParamError::Negative { name: "hop_count".to_string(), value: -1.0 }));
+
+        let mut shadowed = RoutingParams::cheapest();
+        shadowed.weights.insert("risk".to_string(), 0.5);
+        assert_eq!(shadowed.validate(), Err(ParamError::Shadowed { name: "risk".to_string(), field: "delta" }));
+
+        assert!(RoutingParams::default().validate().is_ok());
+    }
+
+    #[test]
+    fn normalized_scales_weights_to_one() {
+        let params = RoutingParams { alpha: 2.0, beta: 1.0, gamma: 1.0, delta: 0.0, omega: 0.0, epsilon: 0.0, ..RoutingParams::default() }.normalized();
+        assert_sums_to_one(&params);
+        assert!((params.alpha - 0.5).abs() < 1e-9);
+        assert!((params.beta - 0.25).abs() < 1e-9);
+
+        // Invalid params fall back to the balanced preset
+        let fallback = RoutingParams { alpha: -3.0, beta: 7.0, gamma: 0.0, delta: 0.0, omega: 0.0, epsilon: 0.0, ..RoutingParams::default() }.normalized();
+        assert_eq!(fallback.alpha, RoutingParams::balanced().alpha);
+        assert_sums_to_one(&fallback);
+    }
+
+    #[test]
+    fn weights_are_read_and_set_by_objective_name() {
+        let params = RoutingParams::cheapest().with_weight("speed", 1.0).with_weight("delta", 2.0).with_weight("hop_count", 4.0);
+        assert_eq!((params.beta, params.delta), (1.0, 2.0));
+        assert_eq!(params.weights, BTreeMap::from([("hop_count".to_string(), 4.0)]));
+        assert_eq!((params.weight("alpha"), params.weight("cost"), params.weight("risk")), (Some(1.0), Some(1.0), Some(2.0)));
+        assert_eq!((params.weight("hop_count"), params.weight("gas")), (Some(4.0), None));
+
+        let normalized = params.normalized();
+        assert_eq!((normalized.alpha, normalized.weight("hop_count")), (0.125, Some(0.5)));
+        let json = serde_json::to_value(&normalized).unwrap();
+        assert_eq!(json["weights"], serde_json::json!({ "hop_count": 0.5 }));
+        // Params without custom weights serialize as before
+        assert!(serde_json::to_value(RoutingParams::balanced()).unwrap().get("weights").is_none());
+    }
+
+    #[test]
+    fn presets_after_normalization() {
+        for preset in ["cheapest", "fastest", "balanced", "safest", "max-liquidity", "max-output"] {
+            let params = RoutingParams::from_preferences(preset).normalized();
+            assert!(params.validate().is_ok(), "{} is invalid", preset);
+            assert_sums_to_one(&params);
+        }
+
+        let safest = RoutingParams::from_preferences("safest").normalized();
+        assert!(safest.delta > safest.alpha && safest.delta > safest.beta && safest.delta > safest.gamma);
+
+        let max_liquidity = RoutingParams::from_preferences("max-liquidity").normalized();
+        assert!(max_liquidity.gamma > max_liquidity.alpha && max_liquidity.gamma > max_liquidity.delta);
+
+        let cheapest = RoutingParams::from_preferences("cheapest").normalized();
+        assert_eq!((cheapest.alpha, cheapest.beta, cheapest.gamma, cheapest.delta, cheapest.omega), (1.0, 0.0, 0.0, 0.0, 0.0));
+    }
+
+    fn ranked_path(hop_count: u64) -> RankedPath {
+        let hops: Vec<Hop> = (0..hop_count).map(|idx| Hop {
+            from: NodeId(idx),
+            to: NodeId(idx + 1),
+            bridge_name: if idx % 2 == 0 { "stargate".into() } else { "wormhole".into() },
+            kind: EdgeKind::Bridge,
+            metrics: EdgeMetrics { cost: 0.5 + idx as f64, speed: 60.0, liquidity: 10_000.0 - idx as f64, risk: 0.1 },
+            quote: None,
+            slippage_pct: None,
+            alternatives: None,
+            confidence: 1.0,
+        }).collect();
+
+        let path = Path {
+            total_cost: hops.iter().map(|h| h.metrics.cost).sum(),
+            total_time: hops.iter().map(|h| h.metrics.speed).sum(),
+            total_risk: hops.iter().map(|h| h.metrics.risk).sum(),
+            min_liquidity: hops.iter().map(|h| h.metrics.liquidity).reduce(f64::min).unwrap_or(0.0),
+            aggregate_score: 0.0,
+            estimated_output: None,
+            graph_version: 0,
+            confidence: 1.0,
+            hops,
+        };
+
+        RankedPath {
+            score_breakdown: ScoreBreakDown {
+                cost_score: path.total_cost,
+                speed_score: path.total_time,
+                liquidity_score: path.min_liquidity,
+                risk_score: path.total_risk,
+                final_score: 0.75,
+                estimated_output: None,
+            },
+            path,
+            rank: 1,
+            affordability: None,
+        }
+    }
+
+    #[test]
+    fn ranked_path_serde_round_trip() {
+        for hop_count in [0, 5] {
+            let ranked = ranked_path(hop_count);
+            let json = serde_json::to_string(&ranked).unwrap();
+            let back: RankedPath = serde_json::from_str(&json).unwrap();
+            assert_eq!(back, ranked);
+            assert_eq!(back.path.hops.len(), hop_count as usize);
+        }
+
+        let empty = ranked_path(0).path;
+        assert!(empty.is_empty());
+        assert_eq!(empty.effective_min_liquidity(), 0.0);
+    }
+
+    #[test]
+    fn path_schema_field_names() {
+        let json = serde_json::to_value(ranked_path(1)).unwrap();
+        for field in ["hops", "total_cost", "total_time", "total_risk", "min_liquidity", "aggregate_score"] {
+            assert!(json["path"].get(field).is_some(), "missing {}", field);
+        }
+        for field in ["from", "to", "bridge_name", "metrics"] {
+            assert!(json["path"]["hops"][0].get(field).is_some(), "missing {}", field);
+        }
+        assert!(json["score_breakdown"].get("final_score").is_some());
     }
 }
\ No newline at end of file
@@ -0,0 +1,208 @@
+// Frozen copies of a graph, so several queries can run against one version of it while the
+// live graph keeps changing
+
+use crate::graph::{Graph, compute_edge_weight};
+use crate::types::*;
+use serde::Serialize;
+use std::{collections::HashMap, sync::Arc};
+
+// Sizes of a graph and the version they were counted at
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct GraphStats {
+    pub version: u64,
+    pub nodes: usize,
+    // Every edge, active or not
+    pub edges: usize,
+    pub active_edges: usize,
+}
+
+// How route searches visit a graph's edges, which isn't for outside the crate: see GraphRead
+pub(crate) mod sealed {
+    use crate::types::{Edge, NodeId};
+    use std::sync::Arc;
+
+    pub trait VisitEdges {
+        // get_outgoing_edges without collecting them, for the search's hot path. `f` may run with
+        // part of the graph locked, so it mustn't write to the graph.
+        fn for_each_outgoing_edge(&self, from: NodeId, f: impl FnMut(&Arc<Edge>))
+        where
+            Self: Sized;
+    }
+}
+
+// What route searches and scoring read of a graph: the live Graph, or a GraphView of it. Only
+// implemented in this crate.
+pub trait GraphRead: sealed::VisitEdges {
+    fn version(&self) -> u64;
+
+    // See Graph::clock
+    fn clock(&self) -> u64;
+
+    fn get_node(&self, node_id: NodeId) -> Option<Arc<Node>>;
+
+    // Active edges only
+    fn get_outgoing_edges(&self, from: NodeId) -> Vec<Arc<Edge>>;
+
+    fn stats(&self) -> GraphStats;
+
+    // Get neighbours with weights for pathfinding.
+    fn neighbours(&self, node_id: NodeId, params: &RoutingParams) -> Vec<(NodeId, f64)> {
+        let params = params.normalized();
+        self.get_outgoing_edges(node_id)
+            .into_iter()
+            .map(|edge| (edge.to, compute_edge_weight(&edge.get_metrics(), &params, None)))
+            .collect()
+    }
+}
+
+// A graph as it was at `version`, see Graph::read_view. Nodes are shared with the live graph,
+// which never changes them; edges are copies, so metric updates, deactivations and new edges
+// don't show.
+#[derive(Debug, Clone)]
+pub struct GraphView {
+    version: u64,
+    clock: u64,
+    nodes: HashMap<NodeId, Arc<Node>>,
+    // Every edge, active or not, by source node
+    outgoing: HashMap<NodeId, Vec<Arc<Edge>>>,
+}
+
+impl GraphView {
+    pub(crate) fn new(version: u64, clock: u64, nodes: HashMap<NodeId, Arc<Node>>, outgoing: HashMap<NodeId, Vec<Arc<Edge>>>) -> Self {
+        Self { version, clock, nodes, outgoing }
+    }
+
+    // Edges into `to`, as Graph::get_incoming_edges
+    pub fn get_incoming_edges(&self, to: NodeId) -> Vec<Arc<Edge>> {
+        self.outgoing
+            .values()
+            .flatten()
+            .filter(|edge| edge.to == to && edge.is_active())
+            .map(Arc::clone)
+            .collect()
+    }
+}
+
+impl GraphRead for GraphView {
+    fn version(&self) -> u64 {
+        self.version
+    }
+
+    fn clock(&self) -> u64 {
+        self.clock
+    }
+
+    fn get_node(&self, node_id: NodeId) -> Option<Arc<Node>> {
+        self.nodes.get(&node_id).map(Arc::clone)
+    }
+
+    fn get_outgoing_edges(&self, from: NodeId) -> Vec<Arc<Edge>> {
+        self.outgoing
+            .get(&from)
+            .map(|edges| edges.iter().filter(|edge| edge.is_active()).map(Arc::clone).collect())
+            .unwrap_or_default()
+    }
+
+    fn stats(&self) -> GraphStats {
+        let edges = self.outgoing.values().flatten();
+        GraphStats {
+            version: self.version,
+            nodes: self.nodes.len(),
+            edges: edges.clone().count(),
+            active_edges: edges.filter(|edge| edge.is_active()).count(),
+        }
+    }
+}
+
+impl sealed::VisitEdges for GraphView {
+    fn for_each_outgoing_edge(&self, from: NodeId, f: impl FnMut(&Arc<Edge>)) {
+        if let Some(edges) = self.outgoing.get(&from) {
+            edges.iter().filter(|edge| edge.is_active()).for_each(f);
+        }
+    }
+}
+
+impl GraphRead for Graph {
+    fn version(&self) -> u64 {
+        Graph::version(self)
+    }
+
+    fn clock(&self) -> u64 {
+        Graph::clock(self)
+    }
+
+    fn get_node(&self, node_id: NodeId) -> Option<Arc<Node>> {
+        Graph::get_node(self, node_id)
+    }
+
+    fn get_outgoing_edges(&self, from: NodeId) -> Vec<Arc<Edge>> {
+        Graph::get_outgoing_edges(self, from)
+    }
+
+    fn stats(&self) -> GraphStats {
+        Graph::stats(self)
+    }
+
+    fn neighbours(&self, node_id: NodeId, params: &RoutingParams) -> Vec<(NodeId, f64)> {
+        Graph::neighbours(self, node_id, params)
+    }
+}
+
+impl sealed::VisitEdges for Graph {
+    fn for_each_outgoing_edge(&self, from: NodeId, f: impl FnMut(&Arc<Edge>)) {
+        Graph::for_each_outgoing_edge(self, from, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routing::RoutingEngine;
+    use crate::scoring::{DropReason, ScoringEngine};
+
+    fn metrics(cost: f64) -> EdgeMetrics {
+        EdgeMetrics { cost, speed: 60.0, liquidity: 1_000_000.0, risk: 0.1 }
+    }
+
+    #[test]
+    fn a_view_keeps_answering_as_the_graph_was() {
+        let graph = Arc::new(Graph::new(4));
+        let eth = graph.get_or_create_asset_node("ethereum", "0xusdc", "USDC");
+        let polygon = graph.get_or_create_asset_node("polygon", "0xusdc", "USDC");
+        let arbitrum = graph.get_or_create_asset_node("arbitrum", "0xusdc", "USDC");
+        graph.add_edge(eth, polygon, "stargate", metrics(1.0), None, None).unwrap();
+        graph.add_edge(eth, polygon, "across", metrics(2.0), None, None).unwrap();
+        graph.add_edge(polygon, arbitrum, "hop", metrics(1.0), None, None).unwrap();
+        let view = Arc::new(graph.read_view());
+        let before = view.stats();
+        assert_eq!(before, GraphStats { version: graph.version(), nodes: 3, edges: 3, active_edges: 3 });
+        let params = RoutingParams::cheapest();
+        let weights = view.neighbours(eth, &params);
+
+        // Ingestion moves on: stargate gets dearer, hop goes away and a direct edge appears
+        graph.update_edge_metrics(eth, polygon, "stargate", metrics(5.0)).unwrap();
+        graph.set_edge_active(polygon, arbitrum, "hop", false);
+        graph.add_edge(eth, arbitrum, "relay", metrics(0.5), None, None).unwrap();
+
+        assert_eq!(view.stats(), before);
+        assert_eq!(view.neighbours(eth, &params), weights);
+        assert_ne!(graph.neighbours(eth, &params), weights);
+        assert_eq!(view.get_outgoing_edges(eth)[0].get_metrics().cost, 1.0);
+        assert_eq!(view.get_incoming_edges(arbitrum).len(), 1);
+        assert!(Arc::ptr_eq(&view.get_node(eth).unwrap(), &graph.get_node(eth).unwrap()));
+        assert_eq!(graph.stats(), GraphStats { version: before.version + 3, nodes: 3, edges: 4, active_edges: 3 });
+
+        let in_view = RoutingEngine::new(Arc::clone(&view), 4).find_path(eth, arbitrum, &params).unwrap();
+        let live = RoutingEngine::new(Arc::clone(&graph), 4).find_path(eth, arbitrum, &params).unwrap();
+        let bridges = |path: &Path| path.hops.iter().map(|hop| hop.bridge_name.to_string()).collect::<Vec<_>>();
+        assert_eq!(bridges(&in_view), ["stargate", "hop"]);
+        assert_eq!(in_view.graph_version, before.version);
+        assert_eq!(bridges(&live), ["relay"]);
+
+        // Scored against the view, the live graph's path is from another version
+        let outcome = ScoringEngine::new().score_and_rank_in(view.as_ref(), vec![in_view.clone(), live], &params, 5).unwrap();
+        assert_eq!(outcome.ranked.len(), 1);
+        assert_eq!(outcome.ranked[0].path, in_view);
+        assert_eq!(outcome.diagnostics.dropped_for(DropReason::Outdated), 1);
+    }
+}
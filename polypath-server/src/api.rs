@@ -0,0 +1,1289 @@
+use crate::error::ApiError;
+use crate::idempotency::{self, Begin, ResponseStore};
+use crate::tenants::{ApiKeys, TenantContext, authenticate};
+use axum::{
+    Extension,
+    Json,
+    extract::{Path, Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{
+        IntoResponse,
+        Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+    middleware,
+    routing::{get, post, put},
+};
+use futures::StreamExt;
+use polypath_dal::{GraphEntry, GraphRegistry, GraphUpdater, PreferenceProfile, QuarantinedPair, RouteExecutor, layered_options};
+use polypath_dal::adapters::{TransferReference, TransferStatus};
+use polypath_dal::present::{self, PresentedRoute};
+use polypath_graph::{Coverage, DropReason, ExplainedPath, RankedPath, RankingOutcome, RouteIntent, RouteOptions, RoutePriority, Router};
+use polypathroute_core::{ApiFeature, Fields, RequestContext};
+use serde::{Deserialize, Serialize};
+use std::{collections::{BTreeMap, HashMap}, sync::Arc, time::SystemTime};
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
+
+// What the handlers share: the graphs, whose updaters own them and the DAL context, a router
+// over each, the pool their searches run on, the route responses kept for replays and the API
+// keys requests are made with
+#[derive(Clone)]
+pub struct AppState {
+    graphs: Arc<GraphRegistry>,
+    routers: Arc<HashMap<String, Arc<Router>>>,
+    executor: RouteExecutor,
+    responses: ResponseStore,
+    api_keys: Arc<ApiKeys>,
+    // Cancelled when the server starts shutting down, which ends the watch streams
+    closing: CancellationToken,
+}
+
+impl AppState {
+    pub fn new(graphs: Arc<GraphRegistry>) -> Self {
+        let routers = graphs.iter().map(|entry| (entry.name().to_string(), Arc::new(entry.router()))).collect();
+        let dal = graphs.dal();
+        let executor = dal.route_executor();
+        let server = &dal.config().server;
+        let responses = ResponseStore::new(dal.cache(), server.idempotency_ttl, server.idempotency_max_responses);
+        // Keys that can't be read from the store don't keep the configured ones from working
+        let stored = dal.list_api_keys().unwrap_or_else(|err| {
+            dal.logger().warn_with("stored API keys unreadable, only configured ones apply", &[("error", &err)]);
+            Vec::new()
+        });
+        let api_keys = Arc::new(ApiKeys::new(&dal.config().api_keys, stored, dal.logger()));
+        Self { graphs, routers: Arc::new(routers), executor, responses, api_keys, closing: CancellationToken::new() }
+    }
+
+    pub fn graphs(&self) -> &Arc<GraphRegistry> {
+        &self.graphs
+    }
+
+    // The default graph's updater
+    pub fn updater(&self) -> &Arc<GraphUpdater> {
+        self.graphs.default_graph().updater()
+    }
+
+    pub fn api_keys(&self) -> &Arc<ApiKeys> {
+        &self.api_keys
+    }
+
+    // Ends every watch stream, open or opened later, so a graceful shutdown isn't held up by them
+    pub fn close(&self) {
+        self.closing.cancel();
+    }
+
+    // The graph a request names, the default one when it names none, and its router, if the
+    // tenant may query it
+    fn graph(&self, name: Option<&str>, tenant: Option<&TenantContext>) -> Result<(&GraphEntry, &Arc<Router>), ApiError> {
+        let entry = self.graphs.get(name).map_err(ApiError::UnknownGraph)?;
+        if let Some(tenant) = tenant {
+            tenant.require_graph(entry.name())?;
+        }
+        Ok((entry, &self.routers[entry.name()]))
+    }
+
+    // The options a request routes with: the graph's defaults without any, else the ones sent,
+    // layered over the graph's defaults and the stored profile when they name one. The tenant's
+    // max_results caps them, and they're marked as the tenant's for the audit log.
+    fn route_options(&self, entry: &GraphEntry, request: &RouteRequest, tenant: Option<&TenantContext>) -> Result<RouteOptions, ApiError> {
+        let mut options = self.requested_options(entry, request, tenant)?;
+        // Without API keys anyone could jump the queue, so nobody does
+        options.priority = match tenant {
+            Some(tenant) => tenant.priority(options.priority),
+            None => options.priority.min(RoutePriority::Normal),
+        };
+        if let Some(tenant) = tenant {
+            options.max_results = tenant.max_results(options.max_results);
+        }
+        options.tenant = tenant.map(|tenant| tenant.tenant.clone());
+        Ok(options)
+    }
+
+    fn requested_options(&self, entry: &GraphEntry, request: &RouteRequest, tenant: Option<&TenantContext>) -> Result<RouteOptions, ApiError> {
+        let Some(explicit) = &request.options else {
+            return Ok(entry.route_options());
+        };
+        let options: RouteOptions = serde_json::from_value(explicit.clone()).map_err(ApiError::InvalidOptions)?;
+        let Some(name) = options.profile else {
+            return Ok(options);
+        };
+        if let Some(tenant) = tenant {
+            tenant.require_feature(ApiFeature::Profiles)?;
+        }
+        let profile = self.graphs.dal().load_profile(tenant.map(|tenant| tenant.tenant.as_str()), &name)?.ok_or(ApiError::UnknownProfile(name))?;
+        layered_options(&entry.route_options(), &profile, explicit).map_err(ApiError::InvalidOptions)
+    }
+}
+
+// The tenant `authenticate` found for the request; None while the API is open
+type Tenant = Option<Extension<TenantContext>>;
+
+fn tenant_of(tenant: &Tenant) -> Option<&TenantContext> {
+    tenant.as_ref().map(|Extension(tenant)| tenant)
+}
+
+fn tenant_name(tenant: &Tenant) -> Option<&str> {
+    tenant_of(tenant).map(|tenant| tenant.tenant.as_str())
+}
+
+fn require_feature(tenant: &Tenant, feature: ApiFeature) -> Result<(), ApiError> {
+    tenant_of(tenant).map_or(Ok(()), |tenant| tenant.require_feature(feature))
+}
+
+// A graph nothing was ever loaded into can't answer anything
+fn is_ready(entry: &GraphEntry) -> bool {
+    entry.graph().edge_count() > 0
+}
+
+// Health checks and metrics stay open to load balancers and scrapers; everything else needs an
+// API key once any are set, see `authenticate`
+pub fn app(state: AppState) -> axum::Router {
+    axum::Router::new()
+        .route("/v1/routes", post(routes))
+        .route("/v1/routes/watch", post(watch_routes))
+        .route("/v1/routes/{request_id}", get(stored_routes))
+        .route("/v1/routes/{request_id}/select", post(select_route))
+        .route("/v1/graph/stats", get(graph_stats))
+        .route("/v1/profiles", get(list_profiles))
+        .route("/v1/profiles/{name}", put(save_profile).get(load_profile).delete(delete_profile))
+        .route("/v1/transfers/{adapter}/{reference}", get(track_transfer))
+        .route_layer(middleware::from_fn_with_state(state.clone(), authenticate))
+        .route("/v1/health", get(health))
+        .route("/metrics", get(metrics))
+        .with_state(state)
+}
+
+// The RouteIntent fields at the top level, with the RouteOptions under `options` and the
+// graph to search under `graph`. Without options the graph's [graphs.<name>] defaults apply;
+// options naming a `profile` only override what they set themselves.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteRequest {
+    #[serde(flatten)]
+    pub intent: RouteIntent,
+    // Kept as sent, so a profile's values are only overridden by the fields actually given
+    #[serde(default)]
+    pub options: Option<serde_json::Value>,
+    #[serde(default)]
+    pub graph: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RouteResponse {
+    // What GET /v1/routes/{request_id} finds this response under; the x-request-id of the
+    // request that computed it
+    pub request_id: String,
+    // The graph searched and the intent's hash, for POST /v1/routes/{request_id}/select
+    pub graph: String,
+    pub intent_hash: String,
+    pub graph_version: u64,
+    pub routes: Vec<ExplainedPath>,
+    // The routes in words, in the same order, with ?present=true
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presented: Option<Vec<PresentedRoute>>,
+}
+
+// A stored response as a request with `present` asks for it: the routes in words are kept with
+// every response, and left out unless asked for
+fn as_asked(mut response: serde_json::Value, present: bool) -> serde_json::Value {
+    if !present && let Some(fields) = response.as_object_mut() {
+        fields.remove("presented");
+    }
+    response
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct PresentQuery {
+    #[serde(default)]
+    present: bool,
+}
+
+// Response header echoing the trace id a route query was logged under
+const REQUEST_ID: &str = "x-request-id";
+// Request header naming a route query, so a retry with the same body gets the first answer
+const IDEMPOTENCY_KEY: &str = "idempotency-key";
+
+// A route query's request context, continuing the trace of an inbound `traceparent` or
+// `x-request-id` header when there is a usable one
+fn request_context(state: &AppState, headers: &HeaderMap, intent: &RouteIntent) -> RequestContext {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    let inbound = header("traceparent")
+        .and_then(RequestContext::trace_id_from_traceparent)
+        .or_else(|| header(REQUEST_ID).map(str::trim).filter(|id| !id.is_empty() && id.len() <= 128).map(str::to_string));
+    let logger = state.graphs.dal().logger();
+    let fields: Fields = &[("from_chain", &intent.from_chain), ("to_chain", &intent.to_chain)];
+    match inbound {
+        Some(trace_id) => logger.request_with("route_query", &trace_id, fields),
+        None => logger.request("route_query", fields),
+    }
+}
+
+fn with_request_id(context: &RequestContext, response: impl IntoResponse) -> Response {
+    let mut response = response.into_response();
+    if let Ok(value) = HeaderValue::from_str(&context.trace_id) {
+        response.headers_mut().insert(REQUEST_ID, value);
+    }
+    response
+}
+
+// Searching is CPU-bound, so it queues for the route executor's pool rather than running on the
+// async workers, and never waits on a refresh. The affordability check after it waits on the
+// source chain's RPC instead.
+async fn search(state: &AppState, request: &RouteRequest, context: &RequestContext, tenant: Option<&TenantContext>) -> Result<RankingOutcome<ExplainedPath>, ApiError> {
+    let (entry, router) = state.graph(request.graph.as_deref(), tenant)?;
+    let intent = state.graphs.dal().canonical_intent(&request.intent)?;
+    let options = state.route_options(entry, request, tenant)?;
+    let mut outcome = state.executor
+        .run(Arc::clone(router), intent.clone(), options.clone())
+        .instrument(context.span.clone())
+        .await?;
+    state.graphs.dal().logger().debug_with("routes computed", &[("routes", &outcome.ranked.len()), ("graph_version", &router.graph().version())]);
+    let ranked = outcome.ranked.len();
+    outcome.ranked = router.check_affordability(&intent, &options, outcome.ranked).await;
+    outcome.diagnostics.record(DropReason::Unaffordable, ranked - outcome.ranked.len());
+    Ok(outcome)
+}
+
+// With an Idempotency-Key, a request repeating the body of an earlier one with the key gets its
+// response again rather than a new search; another body is a 422, and a repeat while the first
+// is still being searched a 409. Keys are the tenant's own. Every response found is kept for
+// GET /v1/routes/{request_id} too, for server.idempotency_ttl, so a request id already used is a
+// 409 as well. ?present=true adds the routes in words, to a replay too whether or not
+// the first request asked for them.
+async fn routes(State(state): State<AppState>, tenant: Tenant, headers: HeaderMap, Query(query): Query<PresentQuery>, Json(request): Json<RouteRequest>) -> Response {
+    let context = request_context(&state, &headers, &request.intent);
+    let tenant = tenant_of(&tenant);
+    let key = headers.get(IDEMPOTENCY_KEY).and_then(|value| value.to_str().ok()).map(str::trim).filter(|key| !key.is_empty());
+    let tenant_name = tenant.map(|tenant| tenant.tenant.as_str());
+    let result = async {
+        let body_hash = idempotency::body_hash(&request);
+        let key_name = || key.unwrap_or_default().to_string();
+        let reservation = match state.responses.begin(tenant_name, key, &context.trace_id, &body_hash).map_err(|err| ApiError::Internal(err.to_string()))? {
+            Begin::Fresh(reservation) => reservation,
+            Begin::Stored(response) => return Ok(Json(as_asked(response, query.present))),
+            Begin::Conflict => return Err(ApiError::IdempotencyConflict(key_name())),
+            Begin::InFlight => return Err(ApiError::IdempotencyInFlight(key_name())),
+            Begin::DuplicateRequest => return Err(ApiError::DuplicateRequest(context.trace_id.clone())),
+        };
+
+        let (entry, _) = state.graph(request.graph.as_deref(), tenant)?;
+        if !is_ready(entry) {
+            return Err(ApiError::GraphNotReady);
+        }
+        let graph_version = entry.graph().version();
+        let outcome = search(&state, &request, &context, tenant).await?;
+        if outcome.ranked.is_empty() {
+            return Err(ApiError::NoRoute(Box::new(outcome.diagnostics)));
+        }
+        let opts = state.graphs.dal().present_options(entry.graph());
+        let presented = outcome.ranked.iter().map(|route| present::humanize(&route.ranked, &opts)).collect();
+        let response = RouteResponse {
+            request_id: context.trace_id.clone(),
+            graph: entry.name().to_string(),
+            intent_hash: state.graphs.dal().canonical_intent(&request.intent)?.intent_hash(),
+            graph_version,
+            routes: outcome.ranked,
+            presented: Some(presented),
+        };
+        let response = serde_json::to_value(&response).map_err(|err| ApiError::Internal(err.to_string()))?;
+        reservation.store(&response).map_err(|err| ApiError::Internal(err.to_string()))?;
+        Ok(Json(as_asked(response, query.present)))
+    }
+    .instrument(context.span.clone())
+    .await;
+    with_request_id(&context, result)
+}
+
+// Only the tenant that asked gets the response, in words with ?present=true
+async fn stored_routes(State(state): State<AppState>, tenant: Tenant, Path(request_id): Path<String>, Query(query): Query<PresentQuery>) -> Result<Json<serde_json::Value>, ApiError> {
+    match state.responses.response(tenant_name(&tenant), &request_id).map_err(|err| ApiError::Internal(err.to_string()))? {
+        Some(response) => Ok(Json(as_asked(response, query.present))),
+        None => Err(ApiError::UnknownRequest(request_id)),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SelectRequest {
+    rank: usize,
+}
+
+// Confirms the route of `rank` in a stored response as the one the tenant went with, so repeats
+// of its intent keep to it with global.route_stickiness
+async fn select_route(State(state): State<AppState>, tenant: Tenant, Path(request_id): Path<String>, Json(select): Json<SelectRequest>) -> Result<StatusCode, ApiError> {
+    let Some(response) = state.responses.response(tenant_name(&tenant), &request_id).map_err(|err| ApiError::Internal(err.to_string()))? else {
+        return Err(ApiError::UnknownRequest(request_id));
+    };
+    let graph = response["graph"].as_str().unwrap_or_default();
+    let intent_hash = response["intent_hash"].as_str().unwrap_or_default();
+    let route = response["routes"].as_array().and_then(|routes| routes.iter().find(|route| route["ranked"]["rank"] == select.rank));
+    let Some(route) = route else {
+        return Err(ApiError::UnknownRank(request_id, select.rank));
+    };
+    let ranked: RankedPath = serde_json::from_value(route["ranked"].clone()).map_err(|err| ApiError::Internal(err.to_string()))?;
+    let (_, router) = state.graph(Some(graph), tenant_of(&tenant))?;
+    router.confirm_selection(intent_hash, &ranked);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Server-sent events, one RouteUpdate each, named after its reason; see Router::watch. Once the
+// graph is populated a bad intent is a 400 rather than a stream with no routes. Streams end when
+// the server shuts down.
+async fn watch_routes(State(state): State<AppState>, tenant: Tenant, headers: HeaderMap, Json(request): Json<RouteRequest>) -> Response {
+    let context = request_context(&state, &headers, &request.intent);
+    let result = async {
+        require_feature(&tenant, ApiFeature::Watch)?;
+        let tenant = tenant_of(&tenant);
+        let (entry, router) = state.graph(request.graph.as_deref(), tenant)?;
+        if is_ready(entry) {
+            search(&state, &request, &context, tenant).await?;
+        }
+        let intent = state.graphs.dal().canonical_intent(&request.intent)?;
+        let options = state.route_options(entry, &request, tenant)?;
+        let updates = router
+            .watch(intent, options)
+            .take_until(state.closing.clone().cancelled_owned())
+            .map(|update| Event::default().event(update.reason.as_str()).json_data(&update));
+        Ok::<_, ApiError>(Sse::new(updates).keep_alive(KeepAlive::default()))
+    }
+    .instrument(context.span.clone())
+    .await;
+    with_request_id(&context, result)
+}
+
+async fn list_profiles(State(state): State<AppState>, tenant: Tenant) -> Result<Json<Vec<PreferenceProfile>>, ApiError> {
+    require_feature(&tenant, ApiFeature::Profiles)?;
+    Ok(Json(state.graphs.dal().list_profiles(tenant_name(&tenant))?))
+}
+
+// Stores the body under the name in the path, replacing any profile already there
+async fn save_profile(State(state): State<AppState>, tenant: Tenant, Path(name): Path<String>, Json(profile): Json<PreferenceProfile>) -> Result<Json<PreferenceProfile>, ApiError> {
+    require_feature(&tenant, ApiFeature::Profiles)?;
+    let profile = PreferenceProfile { name, ..profile };
+    state.graphs.dal().save_profile(tenant_name(&tenant), &profile)?;
+    Ok(Json(profile))
+}
+
+async fn load_profile(State(state): State<AppState>, tenant: Tenant, Path(name): Path<String>) -> Result<Json<PreferenceProfile>, ApiError> {
+    require_feature(&tenant, ApiFeature::Profiles)?;
+    match state.graphs.dal().load_profile(tenant_name(&tenant), &name)? {
+        Some(profile) => Ok(Json(profile)),
+        None => Err(ApiError::UnknownProfile(name)),
+    }
+}
+
+async fn delete_profile(State(state): State<AppState>, tenant: Tenant, Path(name): Path<String>) -> Result<StatusCode, ApiError> {
+    require_feature(&tenant, ApiFeature::Profiles)?;
+    match state.graphs.dal().delete_profile(tenant_name(&tenant), &name)? {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Err(ApiError::UnknownProfile(name)),
+    }
+}
+
+// What the reference in a transfer's path is
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ReferenceKind {
+    #[default]
+    SourceTx,
+    MessageId,
+}
+
+#[derive(Debug, Deserialize)]
+struct TransferQuery {
+    #[serde(default)]
+    kind: ReferenceKind,
+}
+
+// ?kind=message_id when the reference is the bridge's message id rather than the source tx hash
+async fn track_transfer(State(state): State<AppState>, Path((adapter, reference)): Path<(String, String)>, Query(query): Query<TransferQuery>) -> Result<Json<TransferStatus>, ApiError> {
+    let reference = match query.kind {
+        ReferenceKind::SourceTx => TransferReference::SourceTx(reference),
+        ReferenceKind::MessageId => TransferReference::MessageId(reference),
+    };
+    let status = state.graphs.dal().track(&adapter, &reference).await.map_err(ApiError::Transfer)?;
+    Ok(Json(status))
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+#[derive(Debug, Serialize)]
+struct GraphStats {
+    version: u64,
+    nodes: usize,
+    edges: usize,
+    active_edges: usize,
+    // Unix time of the last completed refresh
+    last_refreshed: Option<u64>,
+    // Share of the configured pairs quoted, which routes are held back on until it reaches
+    // global.min_coverage
+    coverage: Option<Coverage>,
+}
+
+impl GraphStats {
+    fn of(entry: &GraphEntry) -> Self {
+        let graph = entry.graph();
+        Self {
+            version: graph.version(),
+            nodes: graph.node_count(),
+            edges: graph.edge_count(),
+            active_edges: graph.active_edge_count(),
+            last_refreshed: entry.updater().last_refreshed(),
+            coverage: graph.coverage(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphQuery {
+    graph: Option<String>,
+}
+
+// ?graph=<name> for another graph than the default one
+async fn graph_stats(State(state): State<AppState>, tenant: Tenant, Query(query): Query<GraphQuery>) -> Result<Json<GraphStats>, ApiError> {
+    let (entry, _) = state.graph(query.graph.as_deref(), tenant_of(&tenant))?;
+    Ok(Json(GraphStats::of(entry)))
+}
+
+#[derive(Debug, Serialize)]
+struct AdapterStatus {
+    bridge: String,
+    healthy: bool,
+    latency_ms: Option<u128>,
+    details: String,
+}
+
+#[derive(Debug, Serialize)]
+struct GraphHealth {
+    #[serde(flatten)]
+    stats: GraphStats,
+    // Seconds since the last refresh
+    age_secs: Option<u64>,
+    // Pairs refreshes only re-probe with backoff since they kept failing
+    quarantined: Vec<QuarantinedPair>,
+}
+
+impl GraphHealth {
+    fn of(entry: &GraphEntry) -> Self {
+        let stats = GraphStats::of(entry);
+        Self {
+            age_secs: stats.last_refreshed.map(|at| unix_now().saturating_sub(at)),
+            stats,
+            quarantined: entry.updater().quarantined(),
+        }
+    }
+
+    // Below global.min_coverage and never above it, so its router refuses queries
+    fn is_warming_up(&self, entry: &GraphEntry) -> bool {
+        let min_coverage = entry.updater().dal().config().global.min_coverage;
+        self.stats.coverage.as_ref().is_some_and(|coverage| !coverage.warmed_up && coverage.fraction < min_coverage)
+    }
+
+    // Missed two of the graph's refreshes
+    fn is_stale(&self, entry: &GraphEntry) -> bool {
+        self.age_secs.is_some_and(|age| age > entry.update_interval().as_secs() * 2)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Health {
+    // "ok", "degraded" (a bridge is down or a graph missed two refreshes), "warming_up" (too few
+    // of the default graph's pairs are quoted yet) or "cold"
+    status: &'static str,
+    // The default graph's stats, age and quarantined pairs
+    graph: GraphStats,
+    graph_age_secs: Option<u64>,
+    adapters: Vec<AdapterStatus>,
+    quarantined: Vec<QuarantinedPair>,
+    // Every graph by name, the default one included
+    graphs: BTreeMap<String, GraphHealth>,
+}
+
+// 503 while the graph is cold or warming up, so load balancers hold traffic back until enough of
+// it is quoted
+async fn health(State(state): State<AppState>) -> impl IntoResponse {
+    let mut adapters: Vec<AdapterStatus> = state.graphs.dal()
+        .health_check_all()
+        .await
+        .into_iter()
+        .map(|(bridge, health)| match health {
+            Ok(health) => AdapterStatus {
+                bridge,
+                healthy: health.reachable,
+                latency_ms: Some(health.latency.as_millis()),
+                details: health.details,
+            },
+            Err(err) => AdapterStatus { bridge, healthy: false, latency_ms: None, details: err.to_string() },
+        })
+        .collect();
+    adapters.sort_by(|a, b| a.bridge.cmp(&b.bridge));
+
+    let default = state.graphs.default_graph();
+    let graphs: BTreeMap<String, GraphHealth> = state.graphs.iter().map(|entry| (entry.name().to_string(), GraphHealth::of(entry))).collect();
+    let stale = state.graphs.iter().any(|entry| graphs[entry.name()].is_stale(entry));
+    let (code, status) = if !is_ready(default) {
+        (StatusCode::SERVICE_UNAVAILABLE, "cold")
+    } else if graphs[default.name()].is_warming_up(default) {
+        (StatusCode::SERVICE_UNAVAILABLE, "warming_up")
+    } else if adapters.iter().any(|adapter| !adapter.healthy) || stale {
+        (StatusCode::OK, "degraded")
+    } else {
+        (StatusCode::OK, "ok")
+    };
+    let GraphHealth { stats: graph, age_secs: graph_age_secs, quarantined } = GraphHealth::of(default);
+    (code, Json(Health { status, graph, graph_age_secs, adapters, quarantined, graphs }))
+}
+
+async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.graphs.dal().metrics().encode_prometheus(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{API_KEY, Server};
+    use axum::{body::Body, http::Request};
+    use http_body_util::BodyExt;
+    use polypath_dal::{DalContext, StoredApiKey};
+    use polypathroute_core::ApiKeyConfig;
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    const USDC_BASE: &str = "0x833589fcd6edb6e08f4c7c32d4f71b54bda02913";
+    const USDC_ARBITRUM: &str = "0xaf88d065e77c8cc2239327c5edb3a432268e5831";
+    const USDC_POLYGON: &str = "0x3c499c542cef5e3811e1192ce70d8cc03d5c3359";
+
+    const USDT_POLYGON: &str = "0xc2132d05d31c914a87c6611c10748aeb04b58e8f";
+
+    fn pair(src_chain: &str, src_token: &str, dst_chain: &str, dst_token: &str) -> String {
+        format!(r#"
+            [[bridges.mock.pairs]]
+            source_chain = "{}"
+            source_address = "{}"
+            source_token_name = "USDC"
+            destination_chain = "{}"
+            destination_address = "{}"
+            destination_token_name = "USDC"
+        "#, src_chain, src_token, dst_chain, dst_token)
+    }
+
+    // The mock bridge quoting base -> arbitrum -> polygon USDC
+    fn server(name: &str) -> Server {
+        server_with(name, "")
+    }
+
+    // As `server`, with `extra` appended to the config
+    fn server_with(name: &str, extra: &str) -> Server {
+        Server::new(dal_with(name, extra))
+    }
+
+    fn dal_with(name: &str, extra: &str) -> DalContext {
+        dal_with_global(name, "", extra)
+    }
+
+    // As `dal_with`, with `global` added to the [global] section
+    fn dal_with_global(name: &str, global: &str, extra: &str) -> DalContext {
+        let config_path = std::env::temp_dir().join(format!("polypath-server-{}-{}.toml", name, std::process::id()));
+        std::fs::write(&config_path, format!(
+            "[global]\nupdate_interval = 60\ncache_ttl = 60\nlog_level = \"info\"\n{}\n[bridges.mock]\nbase_url = \"http://mock.test\"\nchains = [\"base\", \"arbitrum\", \"polygon\"]\n{}{}{}",
+            global,
+            pair("base", USDC_BASE, "arbitrum", USDC_ARBITRUM),
+            pair("arbitrum", USDC_ARBITRUM, "polygon", USDC_POLYGON),
+            extra,
+        )).unwrap();
+        let dal = DalContext::new(config_path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&config_path).unwrap();
+        dal
+    }
+
+    async fn call(app: &axum::Router, request: Request<Body>) -> (StatusCode, serde_json::Value) {
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null))
+    }
+
+    fn route_request(body: serde_json::Value) -> Request<Body> {
+        Request::post("/v1/routes")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    fn intent(from_chain: &str, from_token: &str, to_chain: &str) -> serde_json::Value {
+        serde_json::json!({
+            "from_chain": from_chain,
+            "from_token": from_token,
+            "to_chain": to_chain,
+            "to_token": "USDC",
+            "amount": 1000.0,
+            "preference": "cheapest",
+            "options": { "max_results": 2 },
+        })
+    }
+
+    #[tokio::test]
+    async fn routes_are_ranked_and_explained() {
+        let server = server("routes");
+        server.state().updater().refresh_once().await;
+        let app = server.app();
+
+        let (status, body) = call(&app, route_request(intent("base", "usdc", "polygon"))).await;
+        assert_eq!(status, StatusCode::OK);
+        let best = &body["routes"][0];
+        assert_eq!(best["ranked"]["rank"], 1);
+        assert_eq!(best["ranked"]["path"]["hops"].as_array().unwrap().len(), 2);
+        assert!(best["explanations"].as_array().is_some_and(|explanations| !explanations.is_empty()));
+        assert_eq!(body["graph_version"], server.state().updater().graph().version());
+
+        let (status, body) = call(&app, route_request(intent("polygon", "usdc", "base"))).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"], "no route satisfies the request");
+        assert_eq!(body["diagnostics"]["candidates"], 0);
+        assert_eq!(body["diagnostics"]["strategy"], "weighted_sum");
+
+        let (status, stats) = call(&app, Request::get("/v1/graph/stats").body(Body::empty()).unwrap()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(stats["active_edges"], 2);
+        assert!(stats["last_refreshed"].is_u64());
+
+        let (status, health) = call(&app, Request::get("/v1/health").body(Body::empty()).unwrap()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(health["status"], "ok");
+        assert_eq!(health["adapters"][0]["bridge"], "mock");
+        assert_eq!(health["quarantined"], serde_json::json!([]));
+        assert_eq!(health["graph"]["coverage"]["fraction"], 1.0);
+
+        let response = app.clone().oneshot(Request::get("/metrics").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let text = response.into_body().collect().await.unwrap().to_bytes();
+        assert!(String::from_utf8_lossy(&text).contains("polypath_routes_computed_total 2"));
+    }
+
+    #[tokio::test]
+    async fn each_graph_only_routes_over_its_own_pairs() {
+        // A direct base USDC -> polygon USDT pair only the "direct" graph takes
+        let graphs = r#"
+            [graphs.stables]
+            pairs_filter = { tokens = ["USDC"] }
+            update_interval = "5m"
+
+            [graphs.direct]
+            pairs_filter = { chains = ["base", "polygon"] }
+            max_results = 1
+        "#;
+        let server = server_with("graphs", &(pair("base", USDC_BASE, "polygon", USDT_POLYGON) + graphs));
+        for entry in server.state().graphs().iter() {
+            entry.updater().refresh_once().await;
+        }
+        let app = server.app();
+        let edges = |name: &str| server.state().graphs().get(Some(name)).unwrap().graph().active_edge_count();
+        assert_eq!((edges("default"), edges("stables"), edges("direct")), (3, 2, 1));
+
+        let mut stables = intent("base", "usdc", "polygon");
+        stables["graph"] = serde_json::json!("stables");
+        stables["to_token"] = serde_json::json!("USDT");
+        let (status, _) = call(&app, route_request(stables.clone())).await;
+        assert_ne!(status, StatusCode::OK);
+
+        // Every hop of a stables route is one of its own edges, never the direct one
+        stables["to_token"] = serde_json::json!("USDC");
+        let (status, body) = call(&app, route_request(stables)).await;
+        assert_eq!(status, StatusCode::OK);
+        let stables_graph = server.state().graphs().get(Some("stables")).unwrap().graph();
+        let direct = server.state().graphs().get(Some("direct")).unwrap();
+        let direct_from = direct.updater().asset_node_id("base", USDC_BASE).0;
+        let direct_to = direct.updater().asset_node_id("polygon", USDT_POLYGON).0;
+        for route in body["routes"].as_array().unwrap() {
+            let hops = route["ranked"]["path"]["hops"].as_array().unwrap();
+            assert_eq!(hops.len(), 2);
+            for hop in hops {
+                let (from, to) = (hop["from"].as_u64().unwrap(), hop["to"].as_u64().unwrap());
+                assert_ne!((from, to), (direct_from, direct_to));
+                assert!(stables_graph.get_outgoing_edges(polypath_graph::NodeId(from)).iter().any(|edge| edge.to.0 == to), "{}", hop);
+            }
+        }
+
+        // Without options a graph's own defaults apply
+        let mut direct_intent = intent("base", "usdc", "polygon");
+        direct_intent["graph"] = serde_json::json!("direct");
+        direct_intent["to_token"] = serde_json::json!("USDT");
+        direct_intent.as_object_mut().unwrap().remove("options");
+        let (status, body) = call(&app, route_request(direct_intent)).await;
+        assert_eq!(status, StatusCode::OK);
+        let routes = body["routes"].as_array().unwrap();
+        assert_eq!(routes.len(), 1);
+        assert_eq!(routes[0]["ranked"]["path"]["hops"][0]["from"], direct_from);
+
+        let mut unknown = intent("base", "usdc", "polygon");
+        unknown["graph"] = serde_json::json!("volatile");
+        let (status, body) = call(&app, route_request(unknown)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"], "unknown graph `volatile`, known graphs: default, direct, stables");
+
+        let (status, stats) = call(&app, Request::get("/v1/graph/stats?graph=stables").body(Body::empty()).unwrap()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(stats["active_edges"], 2);
+        let (_, health) = call(&app, Request::get("/v1/health").body(Body::empty()).unwrap()).await;
+        assert_eq!(health["graphs"]["direct"]["active_edges"], 1);
+        assert_eq!(health["graph"]["active_edges"], 3);
+        assert_eq!(server.state().graphs().get(Some("stables")).unwrap().update_interval().as_secs(), 300);
+    }
+
+    #[tokio::test]
+    async fn bad_requests_are_client_errors() {
+        let server = server("invalid");
+        server.state().updater().refresh_once().await;
+        let app = server.app();
+
+        let (status, body) = call(&app, route_request(intent("base", "DAI", "polygon"))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"].as_str().unwrap().contains("DAI"), "{}", body);
+
+        let mut zero = intent("base", "usdc", "polygon");
+        zero["amount"] = serde_json::json!(0.0);
+        assert_eq!(call(&app, route_request(zero)).await.0, StatusCode::BAD_REQUEST);
+
+        // More than any hop's liquidity can carry
+        let mut whale = intent("base", "usdc", "polygon");
+        whale["amount"] = serde_json::json!(1e12);
+        let (status, body) = call(&app, route_request(whale)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["reason"], "amount_exceeds_liquidity");
+        assert!(body["diagnostics"]["dropped"]["utilization"].as_u64().unwrap() > 0, "{}", body);
+
+        // Bodies that aren't a RouteRequest are rejected by the extractor
+        let (status, _) = call(&app, route_request(serde_json::json!({ "from_chain": "base" }))).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn watched_routes_stream_their_updates() {
+        let server = server("watch");
+        let updater = Arc::clone(server.state().updater());
+        updater.refresh_once().await;
+        let app = server.app();
+
+        let request = |intent: serde_json::Value| Request::post("/v1/routes/watch")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(intent.to_string()))
+            .unwrap();
+        let (status, body) = call(&app, request(intent("base", "DAI", "polygon"))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"].as_str().unwrap().contains("DAI"), "{}", body);
+
+        let response = app.clone().oneshot(request(intent("base", "usdc", "polygon"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/event-stream");
+        let mut body = response.into_body();
+        let mut next_event = async || {
+            let frame = body.frame().await.unwrap().unwrap();
+            String::from_utf8(frame.into_data().unwrap().to_vec()).unwrap()
+        };
+
+        let initial = next_event().await;
+        assert!(initial.starts_with("event: initial\ndata: {"), "{}", initial);
+        assert!(initial.contains("\"new_ranked\":[{"));
+
+        let base = updater.asset_node_id("base", USDC_BASE);
+        let arbitrum = updater.asset_node_id("arbitrum", USDC_ARBITRUM);
+        assert!(updater.graph().set_edge_active(base, arbitrum, "mock", false));
+        let broken = next_event().await;
+        assert!(broken.starts_with("event: broken\ndata: {"), "{}", broken);
+        assert!(broken.contains("\"new_ranked\":[]"));
+
+        // Shutting down ends the stream
+        server.state().close();
+        assert!(body.frame().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn route_queries_continue_inbound_traces() {
+        let server = server("traced");
+        server.state().updater().refresh_once().await;
+        let app = server.app();
+        let request_id = |request: Request<Body>| {
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                response.headers().get(REQUEST_ID).map(|value| value.to_str().unwrap().to_string())
+            }
+        };
+
+        let mut request = route_request(intent("base", "usdc", "polygon"));
+        request.headers_mut().insert("traceparent", HeaderValue::from_static("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"));
+        assert_eq!(request_id(request).await.as_deref(), Some("4bf92f3577b34da6a3ce929d0e0e4736"));
+
+        // Errors are tagged too
+        let mut request = route_request(intent("polygon", "usdc", "base"));
+        request.headers_mut().insert(REQUEST_ID, HeaderValue::from_static("checkout-42"));
+        assert_eq!(request_id(request).await.as_deref(), Some("checkout-42"));
+
+        let mut request = route_request(intent("base", "usdc", "polygon"));
+        request.headers_mut().insert("traceparent", HeaderValue::from_static("garbage"));
+        let generated = request_id(request).await.unwrap();
+        assert_eq!(generated.len(), 32);
+        assert!(generated.bytes().all(|byte| byte.is_ascii_hexdigit()));
+    }
+
+    #[tokio::test]
+    async fn a_cold_graph_is_unavailable() {
+        let server = server("cold");
+        let app = server.app();
+
+        let (status, body) = call(&app, route_request(intent("base", "usdc", "polygon"))).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["error"], "the route graph has not been populated yet");
+
+        let (status, health) = call(&app, Request::get("/v1/health").body(Body::empty()).unwrap()).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(health["status"], "cold");
+        assert_eq!(health["graph"]["last_refreshed"], serde_json::Value::Null);
+    }
+
+    #[tokio::test]
+    async fn a_warming_up_graph_holds_traffic_back() {
+        let server = server("warmup");
+        server.state().updater().refresh_once().await;
+        // As if one of four configured pairs were quoted so far
+        let coverage = Coverage { fraction: 0.25, configured: 4, fresh: 1, stale: 0, missing_sample: vec!["mock:base".to_string()], warmed_up: false };
+        server.state().updater().graph().set_coverage(coverage);
+        let app = server.app();
+
+        let (status, body) = call(&app, route_request(intent("base", "usdc", "polygon"))).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["error"], "the graph is still warming up, 25% of its pairs are quoted");
+        let mut partial = intent("base", "usdc", "polygon");
+        partial["options"]["allow_partial"] = serde_json::json!(true);
+        assert_eq!(call(&app, route_request(partial)).await.0, StatusCode::OK);
+
+        let (status, health) = call(&app, Request::get("/v1/health").body(Body::empty()).unwrap()).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(health["status"], "warming_up");
+        assert_eq!(health["graph"]["coverage"]["missing_sample"], serde_json::json!(["mock:base"]));
+    }
+
+    fn profile_request(method: &str, name: &str, body: serde_json::Value) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .uri(format!("/v1/profiles/{}", name))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn stored_profiles_shape_the_routes_of_queries_naming_them() {
+        let server = server("profiles");
+        let updater = server.state().updater();
+        updater.refresh_once().await;
+        // A cheap direct hop only wormhole quotes
+        let (base, polygon) = (updater.asset_node_id("base", USDC_BASE), updater.asset_node_id("polygon", USDC_POLYGON));
+        let metrics = polypath_graph::EdgeMetrics { cost: 0.01, speed: 30.0, liquidity: 1_000_000.0, risk: 0.9 };
+        updater.graph().add_edge(base, polygon, "wormhole", metrics, None, None).unwrap();
+        let app = server.app();
+        let bridges = |body: &serde_json::Value| -> Vec<Vec<String>> {
+            body["routes"].as_array().unwrap().iter().map(|route| {
+                route["ranked"]["path"]["hops"].as_array().unwrap().iter().map(|hop| hop["bridge_name"].as_str().unwrap().to_string()).collect()
+            }).collect()
+        };
+
+        let safest = serde_json::json!({ "excluded_bridges": ["wormhole"], "max_hops": 3 });
+        let (status, stored) = call(&app, profile_request("PUT", "safest", safest)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(stored["name"], "safest");
+        let (_, listed) = call(&app, Request::get("/v1/profiles").body(Body::empty()).unwrap()).await;
+        assert_eq!(listed, serde_json::json!([stored]));
+        assert_eq!(call(&app, profile_request("GET", "safest", serde_json::Value::Null)).await.1, stored);
+
+        let (_, body) = call(&app, route_request(intent("base", "usdc", "polygon"))).await;
+        assert_eq!(bridges(&body)[0], ["wormhole"]);
+
+        let mut profiled = intent("base", "usdc", "polygon");
+        profiled["options"] = serde_json::json!({ "profile": "safest", "max_results": 2 });
+        let (status, body) = call(&app, route_request(profiled.clone())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(bridges(&body), [["mock", "mock"]]);
+
+        // What the query sets itself wins over the profile
+        profiled["options"]["excluded_bridges"] = serde_json::json!([]);
+        let (_, body) = call(&app, route_request(profiled.clone())).await;
+        assert_eq!(bridges(&body)[0], ["wormhole"]);
+        profiled["options"]["excluded_bridges"] = serde_json::json!(["mock"]);
+        let (_, body) = call(&app, route_request(profiled.clone())).await;
+        assert_eq!(bridges(&body), [["wormhole"]]);
+
+        let (status, _) = call(&app, profile_request("DELETE", "safest", serde_json::Value::Null)).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, body) = call(&app, route_request(profiled)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"], "unknown preference profile `safest`");
+        assert_eq!(call(&app, profile_request("GET", "safest", serde_json::Value::Null)).await.0, StatusCode::NOT_FOUND);
+
+        assert_eq!(call(&app, profile_request("PUT", "no.dots", serde_json::json!({}))).await.0, StatusCode::BAD_REQUEST);
+        let mut bad_options = intent("base", "usdc", "polygon");
+        bad_options["options"] = serde_json::json!({ "max_hops": "many" });
+        assert_eq!(call(&app, route_request(bad_options)).await.0, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    fn keyed_request(key: &str, body: &str) -> Request<Body> {
+        Request::post("/v1/routes")
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(IDEMPOTENCY_KEY, key)
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    // Searches run so far, from /metrics
+    async fn routes_computed(app: &axum::Router) -> u64 {
+        let response = app.clone().oneshot(Request::get("/metrics").body(Body::empty()).unwrap()).await.unwrap();
+        let text = response.into_body().collect().await.unwrap().to_bytes();
+        String::from_utf8_lossy(&text)
+            .lines()
+            .find_map(|line| line.strip_prefix("polypath_routes_computed_total "))
+            .and_then(|count| count.parse().ok())
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn only_tenants_granted_it_search_at_interactive_priority() {
+        let server = server("priority");
+        let state = server.state();
+        let entry = state.graphs.get(None).unwrap();
+        let mut body = intent("base", "usdc", "polygon");
+        body["options"] = serde_json::json!({ "priority": "interactive" });
+        let request: RouteRequest = serde_json::from_value(body).unwrap();
+        let tenant = |features: Vec<ApiFeature>| TenantContext {
+            tenant: "partner-a".to_string(),
+            policy: Arc::new(ApiKeyConfig { features, ..ApiKeyConfig::new(PARTNER_KEY) }),
+        };
+        let priority = |tenant: Option<&TenantContext>| state.route_options(entry, &request, tenant).unwrap().priority;
+
+        assert_eq!(priority(None), RoutePriority::Normal);
+        assert_eq!(priority(Some(&tenant(Vec::new()))), RoutePriority::Normal);
+        assert_eq!(priority(Some(&tenant(vec![ApiFeature::Interactive]))), RoutePriority::Interactive);
+        // Asking for less is always fine
+        let mut body = intent("base", "usdc", "polygon");
+        body["options"] = serde_json::json!({ "priority": "batch" });
+        let request: RouteRequest = serde_json::from_value(body).unwrap();
+        assert_eq!(state.route_options(entry, &request, None).unwrap().priority, RoutePriority::Batch);
+    }
+
+    #[tokio::test]
+    async fn replays_of_an_idempotency_key_are_answered_from_the_first_response() {
+        let server = server("idempotent");
+        server.state().updater().refresh_once().await;
+        let app = server.app();
+        let body = intent("base", "usdc", "polygon").to_string();
+
+        let (status, first) = call(&app, keyed_request("transfer-1", &body)).await;
+        assert_eq!(status, StatusCode::OK);
+        // The same request with its fields in another order
+        let reordered = r#"{"options": {"max_results": 2}, "preference": "cheapest", "amount": 1000.0, "to_token": "USDC", "to_chain": "polygon", "from_token": "usdc", "from_chain": "base"}"#;
+        for replay in [body.as_str(), reordered] {
+            let (status, again) = call(&app, keyed_request("transfer-1", replay)).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(again, first);
+        }
+        assert_eq!(routes_computed(&app).await, 1);
+
+        let mut other = intent("base", "usdc", "polygon");
+        other["amount"] = serde_json::json!(2000.0);
+        let (status, conflict) = call(&app, keyed_request("transfer-1", &other.to_string())).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(conflict["error"], "idempotency key `transfer-1` was already used for a different request");
+
+        // Without the key, or with another one, the request is searched again
+        call(&app, route_request(intent("base", "usdc", "polygon"))).await;
+        call(&app, keyed_request("transfer-2", &body)).await;
+        assert_eq!(routes_computed(&app).await, 3);
+    }
+
+    #[tokio::test]
+    async fn routes_are_presented_in_words_when_asked_even_on_replays() {
+        let server = server("presented");
+        server.state().updater().refresh_once().await;
+        let app = server.app();
+        let body = intent("base", "usdc", "polygon").to_string();
+        let presenting = |key: &str| {
+            Request::post("/v1/routes?present=true")
+                .header(header::CONTENT_TYPE, "application/json")
+                .header(IDEMPOTENCY_KEY, key)
+                .body(Body::from(body.clone()))
+                .unwrap()
+        };
+
+        let (status, first) = call(&app, keyed_request("transfer-1", &body)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(first.get("presented").is_none());
+        // The replay asks for words the first request didn't
+        let (status, replayed) = call(&app, presenting("transfer-1")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(replayed["routes"], first["routes"]);
+        let presented = &replayed["presented"][0];
+        assert_eq!(presented["rank"], 1);
+        assert_eq!(presented["hops"].as_array().unwrap().len(), 2);
+        // Without [fx] costs are in the tokens sent, not dollars
+        assert!(presented["fee"].as_str().is_some_and(|fee| fee.ends_with(" USDC")), "{}", presented["fee"]);
+
+        let (_, asked) = call(&app, presenting("transfer-2")).await;
+        let (_, replayed) = call(&app, keyed_request("transfer-2", &body)).await;
+        assert!(asked.get("presented").is_some());
+        assert!(replayed.get("presented").is_none());
+        assert_eq!(routes_computed(&app).await, 2);
+    }
+
+    #[tokio::test]
+    async fn responses_are_retrievable_by_request_id_until_they_expire() {
+        let server = server_with("retrieval", "[server]\nidempotency_ttl = 1\n");
+        server.state().updater().refresh_once().await;
+        let app = server.app();
+
+        let response = app.clone().oneshot(route_request(intent("base", "usdc", "polygon"))).await.unwrap();
+        let request_id = response.headers()[REQUEST_ID].to_str().unwrap().to_string();
+        let body: serde_json::Value = serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes()).unwrap();
+        assert_eq!(body["request_id"], request_id);
+        call(&app, keyed_request("transfer-1", &intent("base", "usdc", "polygon").to_string())).await;
+
+        let stored = |id: &str| Request::get(format!("/v1/routes/{}", id)).body(Body::empty()).unwrap();
+        assert_eq!(call(&app, stored(&request_id)).await, (StatusCode::OK, body.clone()));
+        let (status, missing) = call(&app, stored("never-sent")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(missing["error"], "no stored response for request `never-sent`");
+
+        // A request id names one response only
+        let mut reused = route_request(intent("base", "usdc", "arbitrum"));
+        reused.headers_mut().insert(REQUEST_ID, HeaderValue::from_str(&request_id).unwrap());
+        let (status, duplicate) = call(&app, reused).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(duplicate["error"], format!("request id `{}` was already used", request_id));
+        assert_eq!(call(&app, stored(&request_id)).await.1, body);
+
+        tokio::time::sleep(Duration::from_millis(1_100)).await;
+        assert_eq!(call(&app, stored(&request_id)).await.0, StatusCode::NOT_FOUND);
+        // An expired key is free to be used again
+        let (status, _) = call(&app, keyed_request("transfer-1", &intent("base", "usdc", "arbitrum").to_string())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(routes_computed(&app).await, 3);
+    }
+
+    #[tokio::test]
+    async fn a_selected_route_is_kept_for_repeats_of_its_intent() {
+        let direct = pair("base", USDC_BASE, "polygon", USDC_POLYGON);
+        let server = Server::new(dal_with_global("select", "route_stickiness = 10.0", &direct));
+        server.state().updater().refresh_once().await;
+        let app = server.app();
+
+        let response = app.clone().oneshot(route_request(intent("base", "usdc", "polygon"))).await.unwrap();
+        let request_id = response.headers()[REQUEST_ID].to_str().unwrap().to_string();
+        let body: serde_json::Value = serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes()).unwrap();
+        assert_eq!(body["graph"], "default");
+        let second = body["routes"][1]["ranked"]["path"]["hops"].clone();
+        assert!(second.is_array());
+
+        let select = |id: &str, rank: usize| {
+            Request::post(format!("/v1/routes/{}/select", id))
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(serde_json::json!({ "rank": rank }).to_string()))
+                .unwrap()
+        };
+        assert_eq!(call(&app, select(&request_id, 2)).await.0, StatusCode::NO_CONTENT);
+        let (status, missing) = call(&app, select(&request_id, 3)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(missing["error"], format!("response `{}` has no route ranked 3", request_id));
+        assert_eq!(call(&app, select("never-sent", 1)).await.0, StatusCode::NOT_FOUND);
+
+        let (status, body) = call(&app, route_request(intent("base", "usdc", "polygon"))).await;
+        assert_eq!(status, StatusCode::OK);
+        let kept = &body["routes"][0];
+        assert_eq!(kept["ranked"]["path"]["hops"], second);
+        assert!(kept["summary"].as_str().unwrap().starts_with("kept on the last confirmed route"), "{}", kept["summary"]);
+        assert!(body["routes"][1]["summary"].as_str().unwrap().starts_with("best for the selected weights"), "{}", body["routes"][1]["summary"]);
+    }
+
+    const PARTNER_KEY: &str = "partner-a-0123456789abcdef";
+
+    // partner-a may make `requests_per_minute` route queries of at most one route each
+    fn keyed_server(name: &str, requests_per_minute: u32) -> Server {
+        server_with(name, &format!(
+            "[audit]\nenabled = true\n[api_keys.partner-a]\nkey = \"{}\"\nrequests_per_minute = {}\nmax_results = 1\n",
+            PARTNER_KEY, requests_per_minute,
+        ))
+    }
+
+    fn with_key(key: &str, path: &str, body: serde_json::Value) -> Request<Body> {
+        Request::post(path)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(API_KEY, key)
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn route_queries_are_made_for_the_tenant_of_their_key() {
+        let server = keyed_server("tenant", 10);
+        server.state().updater().refresh_once().await;
+        let app = server.app();
+
+        let (status, body) = call(&app, with_key(PARTNER_KEY, "/v1/routes", intent("base", "usdc", "polygon"))).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        // Bearer tokens carry the key too
+        let bearer = Request::get("/v1/graph/stats").header(header::AUTHORIZATION, format!("Bearer {}", PARTNER_KEY)).body(Body::empty()).unwrap();
+        assert_eq!(call(&app, bearer).await.0, StatusCode::OK);
+
+        let audit = server.state().graphs().audit().unwrap();
+        audit.flush();
+        let entries = audit.by_time_range(0, u64::MAX).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].tenant.as_deref(), Some("partner-a"));
+        // The intent asked for two
+        assert_eq!(entries[0].options.max_results, 1);
+
+        let (status, body) = call(&app, route_request(intent("base", "usdc", "polygon"))).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["reason"], "missing_api_key");
+        let (status, body) = call(&app, with_key("not-a-key-of-anyone", "/v1/routes", intent("base", "usdc", "polygon"))).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["reason"], "unknown_api_key");
+
+        // Health checks and metrics need no key
+        assert_eq!(call(&app, Request::get("/v1/health").body(Body::empty()).unwrap()).await.0, StatusCode::OK);
+        let response = app.clone().oneshot(Request::get("/metrics").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let text = response.into_body().collect().await.unwrap().to_bytes();
+        assert!(String::from_utf8_lossy(&text).contains(r#"polypath_api_requests_total{tenant="partner-a",outcome="admitted"} 2"#));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_tenant_over_its_quota_is_told_when_to_retry() {
+        let server = keyed_server("quota", 2);
+        server.state().updater().refresh_once().await;
+        let app = server.app();
+
+        for _ in 0..2 {
+            assert_eq!(call(&app, with_key(PARTNER_KEY, "/v1/routes", intent("base", "usdc", "polygon"))).await.0, StatusCode::OK);
+        }
+        let response = app.clone().oneshot(with_key(PARTNER_KEY, "/v1/routes", intent("base", "usdc", "polygon"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "60");
+        let body: serde_json::Value = serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes()).unwrap();
+        assert_eq!(body["reason"], "quota_exceeded");
+
+        tokio::time::advance(Duration::from_secs(60)).await;
+        assert_eq!(call(&app, with_key(PARTNER_KEY, "/v1/routes", intent("base", "usdc", "polygon"))).await.0, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn tenants_only_reach_the_graphs_and_features_they_are_granted() {
+        let dal = dal_with("granted", &format!(
+            "[graphs.stables]\npairs_filter = {{ tokens = [\"USDC\"] }}\n[api_keys.partner-a]\nkey = \"{}\"\ngraphs = [\"default\"]\n",
+            PARTNER_KEY,
+        ));
+        let policy = ApiKeyConfig { features: vec![ApiFeature::Watch], ..ApiKeyConfig::new("watcher-0123456789abcdef") };
+        dal.save_api_key(&StoredApiKey::new("watcher", policy).unwrap()).unwrap();
+        let server = Server::new(dal);
+        for entry in server.state().graphs().iter() {
+            entry.updater().refresh_once().await;
+        }
+        let app = server.app();
+
+        let (status, body) = call(&app, with_key(PARTNER_KEY, "/v1/routes/watch", intent("base", "usdc", "polygon"))).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["reason"], "feature_not_allowed");
+        let response = app.clone().oneshot(with_key("watcher-0123456789abcdef", "/v1/routes/watch", intent("base", "usdc", "polygon"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let mut stables = intent("base", "usdc", "polygon");
+        stables["graph"] = serde_json::json!("stables");
+        let (status, body) = call(&app, with_key(PARTNER_KEY, "/v1/routes", stables.clone())).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["reason"], "graph_not_allowed");
+        assert_eq!(call(&app, with_key("watcher-0123456789abcdef", "/v1/routes", stables)).await.0, StatusCode::OK);
+
+        let profiles = Request::get("/v1/profiles").header(API_KEY, PARTNER_KEY).body(Body::empty()).unwrap();
+        assert_eq!(call(&app, profiles).await.1["reason"], "feature_not_allowed");
+    }
+
+    #[tokio::test]
+    async fn tenants_only_see_their_own_responses_and_profiles() {
+        const OTHER_KEY: &str = "partner-b-0123456789abcdef";
+        let server = server_with("tenant_stores", &format!(
+            "[api_keys.partner-a]\nkey = \"{}\"\nfeatures = [\"profiles\"]\n[api_keys.partner-b]\nkey = \"{}\"\nfeatures = [\"profiles\"]\n",
+            PARTNER_KEY, OTHER_KEY,
+        ));
+        server.state().updater().refresh_once().await;
+        let app = server.app();
+        let keyed = |key: &str, idempotency_key: &str, body: serde_json::Value| {
+            let mut request = with_key(key, "/v1/routes", body);
+            request.headers_mut().insert(IDEMPOTENCY_KEY, idempotency_key.parse().unwrap());
+            request
+        };
+        let stored = |key: &str, id: &str| Request::get(format!("/v1/routes/{}", id)).header(API_KEY, key).body(Body::empty()).unwrap();
+
+        let (status, first) = call(&app, keyed(PARTNER_KEY, "retry-1", intent("base", "usdc", "polygon"))).await;
+        assert_eq!(status, StatusCode::OK);
+        let request_id = first["request_id"].as_str().unwrap();
+        assert_eq!(call(&app, stored(PARTNER_KEY, request_id)).await.1, first);
+        assert_eq!(call(&app, stored(OTHER_KEY, request_id)).await.0, StatusCode::NOT_FOUND);
+        // The same Idempotency-Key is another tenant's own, neither a replay nor a conflict
+        let mut other_body = intent("base", "usdc", "polygon");
+        other_body["options"] = serde_json::json!({ "max_hops": 2 });
+        let (status, other) = call(&app, keyed(OTHER_KEY, "retry-1", other_body)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_ne!(other["request_id"], first["request_id"]);
+
+        let profile = |key: &str, method: &str, body: serde_json::Value| {
+            let mut request = profile_request(method, "safest", body);
+            request.headers_mut().insert(API_KEY, key.parse().unwrap());
+            request
+        };
+        assert_eq!(call(&app, profile(PARTNER_KEY, "PUT", serde_json::json!({ "max_hops": 2 }))).await.0, StatusCode::OK);
+        assert_eq!(call(&app, profile(PARTNER_KEY, "GET", serde_json::Value::Null)).await.1["max_hops"], 2);
+        assert_eq!(call(&app, profile(OTHER_KEY, "GET", serde_json::Value::Null)).await.0, StatusCode::NOT_FOUND);
+        assert_eq!(call(&app, profile(OTHER_KEY, "DELETE", serde_json::Value::Null)).await.0, StatusCode::NOT_FOUND);
+        let listed = Request::get("/v1/profiles").header(API_KEY, OTHER_KEY).body(Body::empty()).unwrap();
+        assert_eq!(call(&app, listed).await.1, serde_json::json!([]));
+        let mut profiled = intent("base", "usdc", "polygon");
+        profiled["options"] = serde_json::json!({ "profile": "safest" });
+        assert_eq!(call(&app, with_key(OTHER_KEY, "/v1/routes", profiled)).await.0, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn transfers_are_tracked_on_the_bridge_that_carries_them() {
+        let server = server_with("transfers", r#"
+            [bridges.mock.extra.transfers]
+            "0x8f2c" = { status = "in_flight", eta = 1718000600 }
+            "0x1d3f" = { status = "stuck", since = 1718000000, reason = "paused" }
+
+            [bridges.across]
+            base_url = "http://across.test"
+            chains = ["base", "arbitrum"]
+        "#);
+        let app = server.app();
+        let get = |path: &str| Request::get(path).body(Body::empty()).unwrap();
+
+        let (status, body) = call(&app, get("/v1/transfers/mock/0x8f2c")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, serde_json::json!({ "status": "in_flight", "eta": 1718000600 }));
+        let (_, body) = call(&app, get("/v1/transfers/mock/0x1d3f?kind=source_tx")).await;
+        assert_eq!(body["status"], "stuck");
+
+        // Only source tx hashes are configured for the mock bridge
+        let (status, body) = call(&app, get("/v1/transfers/mock/0x8f2c?kind=message_id")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["reason"], "unknown_transfer");
+        let (status, body) = call(&app, get("/v1/transfers/nope/0x8f2c")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["reason"], "unknown_adapter");
+        let (status, body) = call(&app, get("/v1/transfers/across/0x8f2c")).await;
+        assert_eq!(status, StatusCode::NOT_IMPLEMENTED);
+        assert_eq!(body["reason"], "tracking_unsupported");
+    }
+}
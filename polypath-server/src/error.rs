@@ -0,0 +1,163 @@
+use axum::{
+    Json,
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use polypath_dal::{DalError, ExecutorError, adapters::AdapterError};
+use polypath_graph::{DropReason, RankingDiagnostics, RouteError};
+use polypathroute_core::{ApiFeature, RegistryError};
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ApiError {
+    // Nothing has been loaded into the graph yet, so no answer would be meaningful
+    #[error("the route graph has not been populated yet")]
+    GraphNotReady,
+
+    #[error(transparent)]
+    Route(#[from] RouteError),
+
+    // Too many searches are queued already
+    #[error(transparent)]
+    Overloaded(ExecutorError),
+
+    // The intent names a token symbol the registry has on several addresses
+    #[error(transparent)]
+    Registry(#[from] RegistryError),
+
+    // With what became of the candidates, which the response carries
+    #[error("no route satisfies the request")]
+    NoRoute(Box<RankingDiagnostics>),
+
+    // The request names a graph the server doesn't serve
+    #[error(transparent)]
+    UnknownGraph(DalError),
+
+    // Options that don't make a RouteOptions, like a body the extractor rejects
+    #[error("invalid route options: {0}")]
+    InvalidOptions(serde_json::Error),
+
+    #[error("unknown preference profile `{0}`")]
+    UnknownProfile(String),
+
+    // The Idempotency-Key was already sent with another request body
+    #[error("idempotency key `{0}` was already used for a different request")]
+    IdempotencyConflict(String),
+
+    // The first request with the Idempotency-Key hasn't been answered yet
+    #[error("a request with idempotency key `{0}` is still being searched")]
+    IdempotencyInFlight(String),
+
+    // The x-request-id already names another response
+    #[error("request id `{0}` was already used")]
+    DuplicateRequest(String),
+
+    // Never returned, or kept for less than server.idempotency_ttl ago
+    #[error("no stored response for request `{0}`")]
+    UnknownRequest(String),
+
+    // The stored response has no route of the rank selected
+    #[error("response `{0}` has no route ranked {1}")]
+    UnknownRank(String, usize),
+
+    // Storing or reading preference profiles
+    #[error(transparent)]
+    Profile(#[from] DalError),
+
+    // Once any API keys are set, every request but health checks and metrics needs one
+    #[error("an API key is required, in the x-api-key header")]
+    MissingApiKey,
+
+    #[error("the API key is not recognised")]
+    UnknownApiKey,
+
+    #[error("tenant `{tenant}` is over its quota of {limit} requests per minute")]
+    QuotaExceeded { tenant: String, limit: u32, retry_after: Duration },
+
+    #[error("tenant `{tenant}` may not query graph `{graph}`")]
+    GraphNotAllowed { tenant: String, graph: String },
+
+    #[error("tenant `{tenant}` may not use the `{feature}` feature")]
+    FeatureNotAllowed { tenant: String, feature: ApiFeature },
+
+    // Looking up a transfer on the bridge that carries it
+    #[error(transparent)]
+    Transfer(DalError),
+
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl From<ExecutorError> for ApiError {
+    fn from(err: ExecutorError) -> Self {
+        match err {
+            ExecutorError::Route(err) => ApiError::Route(err),
+            ExecutorError::Abandoned => ApiError::Internal(err.to_string()),
+            ExecutorError::Overloaded { .. } => ApiError::Overloaded(err),
+        }
+    }
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::GraphNotReady | ApiError::Route(RouteError::Warmup { .. }) | ApiError::Overloaded(_) => StatusCode::SERVICE_UNAVAILABLE,
+            // Metrics in the graph that can't be scored, nothing the request can change
+            ApiError::Route(RouteError::Scoring(_)) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::Route(_) | ApiError::Registry(_) => StatusCode::BAD_REQUEST,
+            ApiError::InvalidOptions(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Profile(DalError::InvalidProfile { .. }) => StatusCode::BAD_REQUEST,
+            ApiError::NoRoute(_) | ApiError::UnknownGraph(_) | ApiError::UnknownProfile(_) | ApiError::UnknownRequest(_) | ApiError::UnknownRank(..) => StatusCode::NOT_FOUND,
+            ApiError::IdempotencyConflict(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::IdempotencyInFlight(_) | ApiError::DuplicateRequest(_) => StatusCode::CONFLICT,
+            ApiError::MissingApiKey | ApiError::UnknownApiKey => StatusCode::UNAUTHORIZED,
+            ApiError::QuotaExceeded { .. } => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::GraphNotAllowed { .. } | ApiError::FeatureNotAllowed { .. } => StatusCode::FORBIDDEN,
+            ApiError::Profile(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::Transfer(DalError::UnknownAdapter { .. } | DalError::Adapter(AdapterError::UnknownTransfer { .. })) => StatusCode::NOT_FOUND,
+            ApiError::Transfer(DalError::Adapter(AdapterError::Unsupported { .. })) => StatusCode::NOT_IMPLEMENTED,
+            // The bridge's API failed us
+            ApiError::Transfer(_) => StatusCode::BAD_GATEWAY,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    // For clients to act on without parsing the message
+    pub fn reason(&self) -> Option<&'static str> {
+        match self {
+            ApiError::MissingApiKey => Some("missing_api_key"),
+            ApiError::UnknownApiKey => Some("unknown_api_key"),
+            ApiError::QuotaExceeded { .. } => Some("quota_exceeded"),
+            ApiError::GraphNotAllowed { .. } => Some("graph_not_allowed"),
+            ApiError::FeatureNotAllowed { .. } => Some("feature_not_allowed"),
+            ApiError::Transfer(DalError::UnknownAdapter { .. }) => Some("unknown_adapter"),
+            ApiError::Transfer(DalError::Adapter(AdapterError::UnknownTransfer { .. })) => Some("unknown_transfer"),
+            ApiError::Transfer(DalError::Adapter(AdapterError::Unsupported { .. })) => Some("tracking_unsupported"),
+            // A smaller amount might get through
+            ApiError::NoRoute(diagnostics) if diagnostics.dropped_for(DropReason::Utilization) > 0 => Some("amount_exceeds_liquidity"),
+            _ => None,
+        }
+    }
+}
+
+// {"error": "<message>"} with the matching status, the "reason" of an error that has one, and
+// the "diagnostics" of a search that found no route. A tenant over its quota is told when to
+// retry in a Retry-After header, in whole seconds.
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let mut body = serde_json::json!({ "error": self.to_string() });
+        if let Some(reason) = self.reason() {
+            body["reason"] = serde_json::json!(reason);
+        }
+        if let ApiError::NoRoute(diagnostics) = &self {
+            body["diagnostics"] = serde_json::json!(diagnostics);
+        }
+        let mut response = (self.status(), Json(body)).into_response();
+        if let ApiError::QuotaExceeded { retry_after, .. } = &self {
+            let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            response.headers_mut().insert(header::RETRY_AFTER, secs.max(1).into());
+        }
+        response
+    }
+}
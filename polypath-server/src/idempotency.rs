@@ -0,0 +1,238 @@
+// Route responses kept for a while, so a retried request is answered without searching again and
+// an earlier answer can be fetched by its request id
+
+use polypathroute_core::{CacheError, CacheManager, CacheNamespace, sha256_hex};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use crate::api::RouteRequest;
+
+// What an Idempotency-Key was first sent with
+#[derive(Debug, Serialize, Deserialize)]
+struct KeyUse {
+    body_hash: String,
+    request_id: String,
+}
+
+// What a route request gets before it is searched
+#[derive(Debug)]
+pub enum Begin {
+    // Nothing is stored or running under its key or request id; the reservation holds both
+    // until the response is stored or the search is given up
+    Fresh(Box<Reservation>),
+    // The response to the first request with the key and this body
+    Stored(Value),
+    // The key was used with another body
+    Conflict,
+    // The first request with the key and this body is still being searched
+    InFlight,
+    // Its request id already names another response, stored or on its way
+    DuplicateRequest,
+}
+
+// A stored response's scoped request id, and the scoped key it was sent with with the request id
+// that key was used for
+#[derive(Debug)]
+struct Stored {
+    scoped_id: String,
+    key: Option<(String, String)>,
+}
+
+// Keys and request ids of searches still running, scoped like the stored ones
+#[derive(Debug, Default)]
+struct Pending {
+    keys: HashMap<String, String>,
+    request_ids: HashSet<String>,
+}
+
+// Entries live in the core's cache under their keys' hashes, so keys of any length and content
+// can be persisted with it. Keys and responses expire together after `ttl`, and only the last
+// `max_responses` stored are kept. Both are the tenant's own: another tenant sending the same
+// key or request id finds nothing.
+#[derive(Debug, Clone)]
+pub struct ResponseStore {
+    keys: CacheNamespace,
+    responses: CacheNamespace,
+    ttl: u64,
+    max_responses: usize,
+    // Responses stored since startup, oldest first
+    stored: Arc<Mutex<VecDeque<Stored>>>,
+    pending: Arc<Mutex<Pending>>,
+}
+
+impl ResponseStore {
+    pub fn new(cache: &CacheManager, ttl: Duration, max_responses: usize) -> Self {
+        Self {
+            keys: cache.namespace("idempotency_keys"),
+            responses: cache.namespace("route_responses"),
+            ttl: ttl.as_secs(),
+            max_responses,
+            stored: Arc::default(),
+            pending: Arc::default(),
+        }
+    }
+
+    // The response returned to the tenant under `request_id`, None once it has expired
+    pub fn response(&self, tenant: Option<&str>, request_id: &str) -> Result<Option<Value>, CacheError> {
+        self.responses.get_json(&scoped(tenant, request_id))
+    }
+
+    // Reserves `key`, if any, and `request_id` for a search of a body hashing to `body_hash`,
+    // unless an earlier request already has them
+    pub fn begin(&self, tenant: Option<&str>, key: Option<&str>, request_id: &str, body_hash: &str) -> Result<Begin, CacheError> {
+        let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+        let key = key.map(|key| scoped(tenant, key));
+        if let Some(key) = &key {
+            if let Some(running) = pending.keys.get(key) {
+                return Ok(if running == body_hash { Begin::InFlight } else { Begin::Conflict });
+            }
+            if let Some(used) = self.keys.get_json::<KeyUse>(key)? {
+                if used.body_hash != body_hash {
+                    return Ok(Begin::Conflict);
+                }
+                // Otherwise its response has gone and the key is free again
+                if let Some(response) = self.response(tenant, &used.request_id)? {
+                    return Ok(Begin::Stored(response));
+                }
+            }
+        }
+
+        let scoped_id = scoped(tenant, request_id);
+        if pending.request_ids.contains(&scoped_id) || self.responses.get(&scoped_id)?.is_some() {
+            return Ok(Begin::DuplicateRequest);
+        }
+        pending.request_ids.insert(scoped_id.clone());
+        if let Some(key) = &key {
+            pending.keys.insert(key.clone(), body_hash.to_string());
+        }
+        let used = KeyUse { body_hash: body_hash.to_string(), request_id: request_id.to_string() };
+        Ok(Begin::Fresh(Box::new(Reservation { store: self.clone(), key, scoped_id, used })))
+    }
+
+    fn release(&self, key: Option<&str>, request_id: &str) {
+        let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+        pending.request_ids.remove(request_id);
+        if let Some(key) = key {
+            pending.keys.remove(key);
+        }
+    }
+
+    // Drops the oldest responses past `max_responses`, and their keys unless since reused
+    fn evict(&self) -> Result<(), CacheError> {
+        let evicted: Vec<_> = {
+            let mut stored = self.stored.lock().unwrap_or_else(PoisonError::into_inner);
+            let excess = stored.len().saturating_sub(self.max_responses);
+            stored.drain(..excess).collect()
+        };
+        for Stored { scoped_id, key } in evicted {
+            self.responses.remove(&scoped_id)?;
+            if let Some((key, request_id)) = key
+                && self.keys.get_json::<KeyUse>(&key)?.is_some_and(|used| used.request_id == request_id)
+            {
+                self.keys.remove(&key)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// A search's claim on its key and request id, given up when it is dropped
+#[derive(Debug)]
+pub struct Reservation {
+    store: ResponseStore,
+    key: Option<String>,
+    scoped_id: String,
+    used: KeyUse,
+}
+
+impl Reservation {
+    // Keeps `response` under the request id, and under the key for replays of the same body
+    pub fn store(self, response: &Value) -> Result<(), CacheError> {
+        let store = &self.store;
+        store.responses.set_json(&self.scoped_id, response, Some(store.ttl))?;
+        if let Some(key) = &self.key {
+            store.keys.set_json(key, &self.used, Some(store.ttl))?;
+        }
+        let key = self.key.clone().map(|key| (key, self.used.request_id.clone()));
+        store.stored.lock().unwrap_or_else(PoisonError::into_inner).push_back(Stored { scoped_id: self.scoped_id.clone(), key });
+        store.evict()
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.store.release(self.key.as_deref(), &self.scoped_id);
+    }
+}
+
+// Tenant names have no `/`, so no tenant's ids run into another's, nor into the open API's
+fn scoped(tenant: Option<&str>, id: &str) -> String {
+    sha256_hex(format!("{}/{}", tenant.unwrap_or_default(), id).as_bytes())
+}
+
+// The same for requests that only differ in the order or spacing of their JSON fields
+pub fn body_hash(request: &RouteRequest) -> String {
+    sha256_hex(serde_json::to_string(request).expect("route requests always encode").as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(max_responses: usize) -> ResponseStore {
+        ResponseStore::new(&CacheManager::new(), Duration::from_secs(60), max_responses)
+    }
+
+    fn fresh(begin: Begin) -> Box<Reservation> {
+        match begin {
+            Begin::Fresh(reservation) => reservation,
+            other => panic!("expected a fresh request, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn a_key_is_reserved_while_its_first_request_is_searched() {
+        let store = store(10);
+        let first = fresh(store.begin(None, Some("transfer-1"), "request-1", "body").unwrap());
+        assert!(matches!(store.begin(None, Some("transfer-1"), "request-2", "body").unwrap(), Begin::InFlight));
+        assert!(matches!(store.begin(None, Some("transfer-1"), "request-2", "other body").unwrap(), Begin::Conflict));
+        // Another tenant's key of the same name is its own
+        drop(fresh(store.begin(Some("partner-a"), Some("transfer-1"), "request-2", "other body").unwrap()));
+
+        first.store(&serde_json::json!({ "routes": [] })).unwrap();
+        match store.begin(None, Some("transfer-1"), "request-3", "body").unwrap() {
+            Begin::Stored(response) => assert_eq!(response, serde_json::json!({ "routes": [] })),
+            other => panic!("expected the stored response, got {:?}", other),
+        }
+
+        // A search given up frees its key for a retry
+        drop(fresh(store.begin(None, Some("transfer-2"), "request-4", "body").unwrap()));
+        fresh(store.begin(None, Some("transfer-2"), "request-5", "body").unwrap());
+    }
+
+    #[test]
+    fn request_ids_name_a_single_response() {
+        let store = store(10);
+        let first = fresh(store.begin(None, None, "checkout-42", "body").unwrap());
+        assert!(matches!(store.begin(None, None, "checkout-42", "other body").unwrap(), Begin::DuplicateRequest));
+        first.store(&serde_json::json!(1)).unwrap();
+        assert!(matches!(store.begin(None, None, "checkout-42", "other body").unwrap(), Begin::DuplicateRequest));
+        assert_eq!(store.response(None, "checkout-42").unwrap(), Some(serde_json::json!(1)));
+    }
+
+    #[test]
+    fn only_the_latest_responses_are_kept() {
+        let store = store(2);
+        for (n, key) in ["transfer-1", "transfer-2", "transfer-3"].into_iter().enumerate() {
+            let id = format!("request-{}", n);
+            fresh(store.begin(None, Some(key), &id, "body").unwrap()).store(&serde_json::json!(n)).unwrap();
+        }
+        assert_eq!(store.response(None, "request-0").unwrap(), None);
+        assert_eq!(store.response(None, "request-2").unwrap(), Some(serde_json::json!(2)));
+        // The evicted response's key goes with it
+        fresh(store.begin(None, Some("transfer-1"), "request-3", "other body").unwrap());
+    }
+}
@@ -0,0 +1,10 @@
+mod api;
+mod error;
+mod idempotency;
+mod server;
+mod tenants;
+
+pub use crate::api::{AppState, RouteRequest, RouteResponse, app};
+pub use crate::error::ApiError;
+pub use crate::server::{DEFAULT_SHUTDOWN_TIMEOUT, Server};
+pub use crate::tenants::{API_KEY, ApiKeys, TenantContext};
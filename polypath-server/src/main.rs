@@ -0,0 +1,91 @@
+use clap::Parser;
+use polypath_dal::DalContext;
+use polypath_server::Server;
+use std::{process::ExitCode, time::Duration};
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+
+#[derive(Debug, Parser)]
+#[command(name = "polypath-server", version, about = "Serve PolyPath route queries over HTTP")]
+struct Args {
+    /// Path to the TOML, YAML or JSON config
+    #[arg(long, default_value = "config.toml")]
+    config: String,
+
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    listen: String,
+
+    /// Quote every configured bridge with the offline simulator instead of its API
+    #[arg(long)]
+    simulate: bool,
+
+    /// Seed of the simulator's random walks
+    #[arg(long, default_value_t = 0, requires = "simulate")]
+    seed: u64,
+
+    /// Seconds a shutdown waits for graph refreshes in flight before dropping them
+    #[arg(long, default_value_t = 10)]
+    shutdown_timeout: u64,
+}
+
+// Resolves on SIGINT, or SIGTERM where there is one
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+            return;
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+    let dal = match DalContext::new(&args.config) {
+        Ok(dal) if args.simulate => dal.with_simulation(args.seed),
+        Ok(dal) => dal,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let listener = match TcpListener::bind(&args.listen).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("error: cannot listen on {}: {}", args.listen, err);
+            return ExitCode::FAILURE;
+        }
+    };
+    dal.logger().info_with("listening", &[("address", &args.listen)]);
+    if let Some(seed) = dal.simulation_seed() {
+        dal.logger().info_with("simulating every bridge", &[("seed", &seed)]);
+    }
+
+    let shutdown = CancellationToken::new();
+    let on_signal = shutdown.clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        on_signal.cancel();
+    });
+    let server = Server::new(dal).with_shutdown_timeout(Duration::from_secs(args.shutdown_timeout));
+    match server.serve(listener, shutdown).await {
+        Ok(report) if report.errors.is_empty() => ExitCode::SUCCESS,
+        Ok(report) => {
+            for error in &report.errors {
+                eprintln!("error: shutdown: {}", error);
+            }
+            ExitCode::FAILURE
+        }
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use crate::api::{AppState, app};
+use polypath_dal::{DalContext, GraphRegistry, RefreshScheduler, Runtime, ShutdownReport};
+use polypath_graph::Graph;
+use std::{io, sync::Arc, time::Duration};
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+
+const GRAPH_SHARDS: usize = 16;
+
+// How long a shutdown waits for refreshes in flight, unless set with `with_shutdown_timeout`
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Owns everything a running service needs: the DAL context (and through it the core), the
+// graphs, their refresh schedulers and the routers
+pub struct Server {
+    state: AppState,
+    schedulers: Vec<RefreshScheduler>,
+    shutdown_timeout: Duration,
+}
+
+impl Server {
+    // Starts cold; routes are served once the first refresh has filled the graph
+    pub fn new(dal: DalContext) -> Self {
+        Self::from_graphs(GraphRegistry::new(dal, GRAPH_SHARDS))
+    }
+
+    // Warm start of the default graph from an already populated one, e.g. a loaded snapshot
+    pub fn with_graph(graph: Graph, dal: DalContext) -> Self {
+        Self::from_graphs(GraphRegistry::with_default_graph(dal, graph, GRAPH_SHARDS))
+    }
+
+    fn from_graphs(graphs: GraphRegistry) -> Self {
+        Self {
+            schedulers: graphs.schedulers(),
+            state: AppState::new(Arc::new(graphs)),
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+        }
+    }
+
+    pub fn with_shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    pub fn state(&self) -> &AppState {
+        &self.state
+    }
+
+    pub fn app(&self) -> axum::Router {
+        app(self.state.clone())
+    }
+
+    // Serves on `listener` with every graph refreshing in the background until `shutdown` is
+    // cancelled, then ends the watch streams, waits for other requests in flight and shuts the
+    // refreshes down; see Runtime::shutdown
+    pub async fn serve(self, listener: TcpListener, shutdown: CancellationToken) -> io::Result<ShutdownReport> {
+        let app = self.app();
+        let runtime = Runtime::start(Arc::clone(self.state.graphs()), self.schedulers);
+        let state = self.state.clone();
+        let served = axum::serve(listener, app)
+            .with_graceful_shutdown(async move {
+                shutdown.cancelled().await;
+                state.close();
+            })
+            .await;
+        let report = runtime.shutdown(self.shutdown_timeout).await;
+        served.map(|_| report)
+    }
+}
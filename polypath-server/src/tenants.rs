@@ -0,0 +1,159 @@
+// API keys and what each one's tenant may do: the graphs it may query, how many routes a query
+// gets back, the endpoints beyond route queries it's granted and its requests per minute. See
+// `authenticate`, which puts a TenantContext on every request it lets through.
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use polypath_dal::StoredApiKey;
+use polypath_graph::RoutePriority;
+use polypathroute_core::{ApiFeature, ApiKeyConfig, HashedSecret, LoggingManager, MetricsManager};
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::Duration};
+use tokio::time::Instant;
+
+use crate::{api::AppState, error::ApiError};
+
+// Request header carrying the key; an `Authorization: Bearer <key>` header works too
+pub const API_KEY: &str = "x-api-key";
+// What requests_per_minute counts over
+const QUOTA_WINDOW: Duration = Duration::from_secs(60);
+
+// The tenant a request is made for, in the request's extensions
+#[derive(Debug, Clone)]
+pub struct TenantContext {
+    pub tenant: String,
+    pub policy: Arc<ApiKeyConfig>,
+}
+
+impl TenantContext {
+    pub fn require_feature(&self, feature: ApiFeature) -> Result<(), ApiError> {
+        match self.policy.has_feature(feature) {
+            true => Ok(()),
+            false => Err(ApiError::FeatureNotAllowed { tenant: self.tenant.clone(), feature }),
+        }
+    }
+
+    pub fn require_graph(&self, graph: &str) -> Result<(), ApiError> {
+        match self.policy.allows_graph(graph) {
+            true => Ok(()),
+            false => Err(ApiError::GraphNotAllowed { tenant: self.tenant.clone(), graph: graph.to_string() }),
+        }
+    }
+
+    // Interactive priority is for tenants granted it, as a full queue still takes such searches
+    pub fn priority(&self, priority: RoutePriority) -> RoutePriority {
+        match self.policy.has_feature(ApiFeature::Interactive) {
+            true => priority,
+            false => priority.min(RoutePriority::Normal),
+        }
+    }
+
+    // `max_results` within the tenant's cap
+    pub fn max_results(&self, max_results: usize) -> usize {
+        self.policy.max_results.map_or(max_results, |cap| max_results.min(cap))
+    }
+}
+
+// Requests counted in the current minute of a tenant's quota
+#[derive(Debug)]
+struct Window {
+    started: Instant,
+    requests: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct ApiKeys {
+    // By the hash of their key; none of them keeps the key itself
+    tenants: Vec<(HashedSecret, TenantContext)>,
+    // By tenant
+    windows: Mutex<HashMap<String, Window>>,
+}
+
+impl ApiKeys {
+    // The config's keys, then the stored ones of tenants the config doesn't have. A stored key
+    // one of the config's tenants already has is left out with a warning.
+    pub fn new(configured: &HashMap<String, ApiKeyConfig>, stored: Vec<StoredApiKey>, logger: &LoggingManager) -> Self {
+        let mut configured: Vec<(&String, &ApiKeyConfig)> = configured.iter().collect();
+        configured.sort_by_key(|(tenant, _)| *tenant);
+        let mut tenants: Vec<(HashedSecret, TenantContext)> = Vec::new();
+        for (tenant, policy) in &configured {
+            let mut policy = (*policy).clone();
+            let key = std::mem::take(&mut policy.key);
+            tenants.push((HashedSecret::new(&key), TenantContext { tenant: tenant.to_string(), policy: Arc::new(policy) }));
+        }
+        for stored in stored {
+            if configured.iter().any(|(tenant, _)| **tenant == stored.tenant) {
+                continue;
+            }
+            if let Some((other, _)) = configured.iter().find(|(_, policy)| stored.verify(&policy.key)) {
+                logger.warn_with("stored API key left out, another tenant has it", &[("tenant", &stored.tenant), ("other", other)]);
+                continue;
+            }
+            tenants.push((stored.key_hash, TenantContext { tenant: stored.tenant, policy: Arc::new(stored.policy) }));
+        }
+        Self { tenants, windows: Mutex::default() }
+    }
+
+    // Without keys the API is open
+    pub fn is_empty(&self) -> bool {
+        self.tenants.is_empty()
+    }
+
+    // The tenant whose key the request carries, if its quota has room for the request
+    pub fn check(&self, headers: &HeaderMap, metrics: &MetricsManager) -> Result<TenantContext, ApiError> {
+        let key = api_key(headers).ok_or(ApiError::MissingApiKey)?;
+        let (_, context) = self.tenants.iter().find(|(hash, _)| hash.verify(key)).ok_or(ApiError::UnknownApiKey)?;
+        let admitted = self.admit(context);
+        metrics.record_api_request(&context.tenant, admitted.is_ok());
+        admitted.map(|()| context.clone())
+    }
+
+    // Counts a request against the tenant's quota, refusing it with the time until the window
+    // resets when the quota is used up
+    fn admit(&self, context: &TenantContext) -> Result<(), ApiError> {
+        let Some(limit) = context.policy.requests_per_minute else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+        let window = windows.entry(context.tenant.clone()).or_insert(Window { started: now, requests: 0 });
+        if now.duration_since(window.started) >= QUOTA_WINDOW {
+            *window = Window { started: now, requests: 0 };
+        }
+        if window.requests >= limit {
+            return Err(ApiError::QuotaExceeded {
+                tenant: context.tenant.clone(),
+                limit,
+                retry_after: QUOTA_WINDOW.saturating_sub(now.duration_since(window.started)),
+            });
+        }
+        window.requests += 1;
+        Ok(())
+    }
+}
+
+fn api_key(headers: &HeaderMap) -> Option<&str> {
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    header(API_KEY)
+        .or_else(|| header(header::AUTHORIZATION.as_str()).and_then(|value| value.strip_prefix("Bearer ")))
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+}
+
+// Middleware in front of every endpoint but health checks and metrics
+pub async fn authenticate(State(state): State<AppState>, mut request: Request, next: Next) -> Response {
+    let api_keys = state.api_keys();
+    if api_keys.is_empty() {
+        return next.run(request).await;
+    }
+    match api_keys.check(request.headers(), state.graphs().dal().metrics()) {
+        Ok(context) => {
+            request.extensions_mut().insert(context);
+            next.run(request).await
+        }
+        Err(err) => err.into_response(),
+    }
+}
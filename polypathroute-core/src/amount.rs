@@ -0,0 +1,298 @@
+// Token amounts as integer base units, so 18-decimal amounts past 2^53 units survive intact.
+// Only scoring and normalization, where relative precision is enough, take them as f64.
+// Not everything carries them yet: a QuoteRequest's source amount is a human decimal string
+// until an adapter resolves it with the token's decimals (QuoteRequest::src_amount_in), and the
+// graph's edge limits and path amounts are f64, its nodes not knowing their token's decimals.
+
+use std::{cmp::Ordering, fmt, hash::{Hash, Hasher}, str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::errors::AmountError;
+
+// u128 holds 38 full decimal digits
+pub const MAX_DECIMALS: u8 = 38;
+
+// `raw` base units of a token with `decimals` decimals: 1.5 USDC is 1_500_000 with 6.
+// Displayed in human units ("1.5"); serialized as a decimal string with every decimal place
+// ("1.500000"), which is how its decimals come back. Amounts compare by value whatever their
+// decimals, 1.5 with 6 being 1.5 with 18.
+#[derive(Debug, Clone, Copy)]
+pub struct Amount {
+    raw: u128,
+    decimals: u8,
+}
+
+impl Amount {
+    pub fn new(raw: u128, decimals: u8) -> Result<Self, AmountError> {
+        if decimals > MAX_DECIMALS {
+            return Err(AmountError::TooManyDecimals(decimals));
+        }
+        Ok(Self { raw, decimals })
+    }
+
+    pub fn zero(decimals: u8) -> Result<Self, AmountError> {
+        Self::new(0, decimals)
+    }
+
+    // From human units, "1.5" or "1000". Digits past `decimals` are dropped, since the token
+    // can't carry them.
+    pub fn from_human(text: &str, decimals: u8) -> Result<Self, AmountError> {
+        let text = text.trim();
+        let (int, frac) = text.split_once('.').unwrap_or((text, ""));
+        let digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+        if (int.is_empty() && frac.is_empty()) || !digits(int) || !digits(frac) {
+            return Err(AmountError::Invalid(text.to_string()));
+        }
+        let mut raw = format!("{}{}", int, frac.get(..decimals as usize).unwrap_or(frac));
+        for _ in frac.len()..decimals as usize {
+            raw.push('0');
+        }
+        Self::from_raw(&raw, decimals)
+    }
+
+    // From base units as an API gives them, "1500000"
+    pub fn from_raw(raw: &str, decimals: u8) -> Result<Self, AmountError> {
+        let raw = raw.trim();
+        if raw.is_empty() || !raw.chars().all(|c| c.is_ascii_digit()) {
+            return Err(AmountError::Invalid(raw.to_string()));
+        }
+        let digits = raw.trim_start_matches('0');
+        let raw = match digits {
+            "" => 0,
+            digits => digits.parse().map_err(|_| AmountError::Overflow(raw.to_string()))?,
+        };
+        Self::new(raw, decimals)
+    }
+
+    // From a human amount held as f64, by its shortest decimal form
+    pub fn from_f64(value: f64, decimals: u8) -> Result<Self, AmountError> {
+        if !value.is_finite() || value < 0.0 {
+            return Err(AmountError::Invalid(value.to_string()));
+        }
+        Self::from_human(&value.to_string(), decimals)
+    }
+
+    pub fn raw(&self) -> u128 {
+        self.raw
+    }
+
+    pub fn decimals(&self) -> u8 {
+        self.decimals
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.raw == 0
+    }
+
+    // Base units as an integer string, for API requests
+    pub fn to_raw_string(&self) -> String {
+        self.raw.to_string()
+    }
+
+    // Human units, for scoring and normalization
+    pub fn to_f64(&self) -> f64 {
+        self.raw as f64 / 10f64.powi(self.decimals as i32)
+    }
+
+    pub fn checked_add(self, other: Amount) -> Result<Amount, AmountError> {
+        self.same_decimals(&other)?;
+        let raw = self.raw.checked_add(other.raw).ok_or_else(|| AmountError::Overflow(format!("{} + {}", self, other)))?;
+        Ok(Amount { raw, ..self })
+    }
+
+    // Errs when `other` is the larger
+    pub fn checked_sub(self, other: Amount) -> Result<Amount, AmountError> {
+        self.same_decimals(&other)?;
+        let raw = self.raw.checked_sub(other.raw).ok_or_else(|| AmountError::Overflow(format!("{} - {}", self, other)))?;
+        Ok(Amount { raw, ..self })
+    }
+
+    pub fn checked_mul(self, factor: u128) -> Result<Amount, AmountError> {
+        let raw = self.raw.checked_mul(factor).ok_or_else(|| AmountError::Overflow(format!("{} * {}", self, factor)))?;
+        Ok(Amount { raw, ..self })
+    }
+
+    // The same amount with `decimals` decimals, e.g. USDC moving from a 6 to an 18 decimal
+    // chain. Digits past fewer decimals are dropped.
+    pub fn with_decimals(self, decimals: u8) -> Result<Amount, AmountError> {
+        let scale = |places: u8| 10u128.checked_pow(places as u32);
+        let raw = if decimals >= self.decimals {
+            scale(decimals - self.decimals).and_then(|scale| self.raw.checked_mul(scale))
+        } else {
+            scale(self.decimals - decimals).map(|scale| self.raw / scale)
+        };
+        let raw = raw.ok_or_else(|| AmountError::Overflow(format!("{} at {} decimals", self, decimals)))?;
+        Amount::new(raw, decimals)
+    }
+
+    fn same_decimals(&self, other: &Amount) -> Result<(), AmountError> {
+        if self.decimals == other.decimals {
+            Ok(())
+        } else {
+            Err(AmountError::DecimalsMismatch { left: self.decimals, right: other.decimals })
+        }
+    }
+
+    // `raw` at `decimals` decimals, at least self's; None past u128, which no amount reaches
+    fn raw_at(&self, decimals: u8) -> Option<u128> {
+        self.raw.checked_mul(10u128.pow((decimals - self.decimals) as u32))
+    }
+
+    // Human units with every decimal place
+    fn padded(&self) -> String {
+        let digits = format!("{:0>width$}", self.raw, width = self.decimals as usize + 1);
+        let (int, frac) = digits.split_at(digits.len() - self.decimals as usize);
+        match frac {
+            "" => int.to_string(),
+            frac => format!("{}.{}", int, frac),
+        }
+    }
+}
+
+impl Ord for Amount {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let decimals = self.decimals.max(other.decimals);
+        match (self.raw_at(decimals), other.raw_at(decimals)) {
+            (Some(left), Some(right)) => left.cmp(&right),
+            (None, _) => Ordering::Greater,
+            (_, None) => Ordering::Less,
+        }
+    }
+}
+
+impl PartialOrd for Amount {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Amount {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Amount {}
+
+// By the value with trailing zero decimals dropped, so equal amounts hash alike
+impl Hash for Amount {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let (mut raw, mut decimals) = (self.raw, self.decimals);
+        while decimals > 0 && raw % 10 == 0 {
+            raw /= 10;
+            decimals -= 1;
+        }
+        (raw, decimals).hash(state);
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let padded = self.padded();
+        match padded.split_once('.') {
+            Some((int, frac)) if !frac.trim_end_matches('0').is_empty() => write!(f, "{}.{}", int, frac.trim_end_matches('0')),
+            Some((int, _)) => f.write_str(int),
+            None => f.write_str(&padded),
+        }
+    }
+}
+
+// Takes its decimals from the places given, "1.500000" is 1.5 with 6
+impl FromStr for Amount {
+    type Err = AmountError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        let places = text.split_once('.').map_or(0, |(_, frac)| frac.len());
+        let decimals = u8::try_from(places).map_err(|_| AmountError::TooManyDecimals(u8::MAX))?;
+        Amount::from_human(text, decimals)
+    }
+}
+
+impl Serialize for Amount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.padded())
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eighteen_decimal_amounts_round_trip_exactly() {
+        // Past 2^53 wei, which f64 can't hold
+        let amount = Amount::from_human("9.007199254740993", 18).unwrap();
+        assert_eq!(amount.raw(), 9_007_199_254_740_993_000);
+        assert_ne!(amount.raw() as f64 as u128, amount.raw());
+        assert_eq!(amount.to_raw_string(), "9007199254740993000");
+        assert_eq!(amount.to_string(), "9.007199254740993");
+
+        let json = serde_json::to_string(&amount).unwrap();
+        assert_eq!(json, "\"9.007199254740993000\"");
+        assert_eq!(serde_json::from_str::<Amount>(&json).unwrap(), amount);
+        assert_eq!(Amount::from_raw(&amount.to_raw_string(), 18).unwrap(), amount);
+
+        assert_eq!(Amount::from_human("1.5", 6).unwrap().to_raw_string(), "1500000");
+        assert_eq!(Amount::from_human(".25", 2).unwrap().to_raw_string(), "25");
+        assert_eq!(Amount::from_human("0.0000001", 6).unwrap(), Amount::zero(6).unwrap());
+        assert_eq!(Amount::from_human("1000", 0).unwrap().to_string(), "1000");
+        assert_eq!(Amount::from_f64(1500.25, 6).unwrap().to_raw_string(), "1500250000");
+    }
+
+    #[test]
+    fn overflow_and_bad_input_are_rejected() {
+        let max = Amount::new(u128::MAX, 18).unwrap();
+        let one = Amount::from_human("1", 18).unwrap();
+        assert!(matches!(max.checked_add(one), Err(AmountError::Overflow(_))));
+        assert!(matches!(one.checked_sub(max), Err(AmountError::Overflow(_))));
+        assert!(matches!(max.checked_mul(2), Err(AmountError::Overflow(_))));
+        assert!(matches!(Amount::from_human("1000", 6).unwrap().with_decimals(38), Err(AmountError::Overflow(_))));
+        assert!(matches!(Amount::from_raw("340282366920938463463374607431768211456", 0), Err(AmountError::Overflow(_))));
+        assert!(Amount::from_human("1000000000000000000", 18).is_ok());
+        assert!(matches!(Amount::from_human("1000000000000000000", 36), Err(AmountError::Overflow(_))));
+
+        assert!(matches!(Amount::from_human("1e6", 6), Err(AmountError::Invalid(_))));
+        assert!(matches!(Amount::from_human("-1", 6), Err(AmountError::Invalid(_))));
+        assert!(matches!(Amount::new(1, 39), Err(AmountError::TooManyDecimals(39))));
+        assert_eq!(
+            Amount::from_human("1", 6).unwrap().checked_add(one),
+            Err(AmountError::DecimalsMismatch { left: 6, right: 18 })
+        );
+    }
+
+    #[test]
+    fn arithmetic_and_rescaling_stay_in_base_units() {
+        let a = Amount::from_human("0.1", 18).unwrap();
+        let b = Amount::from_human("0.2", 18).unwrap();
+        assert_eq!(a.checked_add(b).unwrap().to_string(), "0.3");
+        assert_eq!(b.checked_sub(a).unwrap(), a);
+
+        let usdc = Amount::from_human("1.234567", 6).unwrap();
+        assert_eq!(usdc.with_decimals(18).unwrap().to_raw_string(), "1234567000000000000");
+        assert_eq!(usdc.with_decimals(2).unwrap().to_string(), "1.23");
+        assert!((usdc.to_f64() - 1.234567).abs() < 1e-12);
+    }
+
+    #[test]
+    fn amounts_compare_by_value_whatever_their_decimals() {
+        let usdc = Amount::from_human("1.5", 6).unwrap();
+        let wei = Amount::from_human("1.5", 18).unwrap();
+        assert_eq!(usdc, wei);
+        assert_eq!(std::collections::HashSet::from([usdc, wei]).len(), 1);
+        // Fewer base units can still be the larger amount
+        let two = Amount::from_human("2", 0).unwrap();
+        assert!(two.raw() < wei.raw() && two > wei);
+        assert!(Amount::from_human("1.499999", 6).unwrap() < wei);
+        assert_eq!([two, usdc, Amount::zero(18).unwrap()].iter().max(), Some(&two));
+        // An amount past u128 at the other's decimals is the larger
+        assert!(Amount::new(u128::MAX, 0).unwrap() > Amount::new(u128::MAX, 38).unwrap());
+    }
+}
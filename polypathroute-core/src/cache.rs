@@ -1,39 +1,977 @@
 // Provides async TTL cache API
 
-use std::{collections::HashMap, hash::Hash};
-use anyhow::Result;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak, atomic::{AtomicU64, Ordering}},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 
+use crate::{
+    errors::{CacheError, PersistenceError},
+    logging::LoggingManager,
+    metrics::MetricsManager,
+    persistence::{PersistenceManager, Versioned, WriteOp},
+};
+
+// Seconds an entry lives when neither the caller nor config gives a ttl
+const DEFAULT_TTL: u64 = 3600;
+// Prefix of persisted entries in the persistence store
+const PERSISTED_PREFIX: &str = "cache/";
+// Pending persistence writes that trigger a flush from the writing thread
+const FLUSH_AFTER_WRITES: usize = 64;
+// Shortest write-through flush interval, so the flush thread never spins
+const MIN_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+struct Entry {
+    value: String,
+    expires_at: Instant,
+    // Tick of the last read or write, for LRU eviction. Atomic so reads only need the read lock.
+    last_used: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    // Source of `last_used` ticks
+    tick: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    expired_purges: AtomicU64,
+    stampedes_prevented: AtomicU64,
+}
+
+impl Counters {
+    fn next_tick(&self) -> u64 {
+        self.tick.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn bump(counter: &AtomicU64, by: usize) {
+        counter.fetch_add(by as u64, Ordering::Relaxed);
+    }
+}
+
+// Snapshot of a cache's counters since it was created
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+pub struct CacheStats {
+    // Live entries
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+    // Live entries dropped to stay within max_entries
+    pub evictions: u64,
+    // Entries dropped because their ttl ran out
+    pub expired_purges: u64,
+    // get_or_insert_with callers served a value another caller was already computing, instead
+    // of computing it too
+    pub stampedes_prevented: u64,
+}
+
+// Persisted form of an entry. Instants don't survive a restart, so the expiry is wall-clock.
+#[derive(Serialize, Deserialize)]
+struct PersistedEntry {
+    value: String,
+    // Unix milliseconds
+    expires_at: u64,
+}
+
+impl Versioned for PersistedEntry {
+    const SCHEMA: &'static str = "cache_entry";
+    const VERSION: u32 = 1;
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+// Cache writes waiting to reach the persistence store, latest write per key
+#[derive(Debug)]
+struct WriteThrough {
+    store: PersistenceManager,
+    // None deletes the key
+    pending: Mutex<HashMap<String, Option<String>>>,
+}
+
+impl WriteThrough {
+    // An entry that can't be encoded is logged and deleted from the store, so the value it
+    // replaced isn't restored in its place
+    fn record(&self, key: &str, entry: Option<PersistedEntry>) {
+        let encoded = entry.and_then(|entry| match self.store.encode_typed(key, &entry) {
+            Ok(encoded) => Some(encoded),
+            Err(err) => {
+                LoggingManager.warn_with("cannot persist cache entry", &[("key", &key), ("error", &err)]);
+                None
+            }
+        });
+        let full = {
+            let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+            pending.insert(key.to_string(), encoded);
+            pending.len() >= FLUSH_AFTER_WRITES
+        };
+        if full {
+            self.flush_logged();
+        }
+    }
+
+    fn flush(&self) -> Result<(), PersistenceError> {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap_or_else(PoisonError::into_inner));
+        let batch: Vec<WriteOp> = pending
+            .into_iter()
+            .map(|(key, value)| {
+                let key = format!("{}{}", PERSISTED_PREFIX, key);
+                match value {
+                    Some(value) => WriteOp::Put { key, value },
+                    None => WriteOp::Delete { key },
+                }
+            })
+            .collect();
+        self.store.storage().apply(&batch)
+    }
+
+    // For flushes nobody waits on; a failed one loses those writes, not the cached values
+    fn flush_logged(&self) {
+        if let Err(err) = self.flush() {
+            LoggingManager.warn_with("cannot persist cache entries", &[("error", &err)]);
+        }
+    }
+}
+
+impl Drop for WriteThrough {
+    fn drop(&mut self) {
+        self.flush_logged();
+    }
+}
+
+// Expired entries read as absent and are dropped lazily, on access or by purge_expired.
+// With a max_entries capacity, inserting past it first drops expired entries and then the
+// least recently used live ones.
+// Clones share one store, so every holder of a CoreContext reads and writes the same cache.
 #[derive(Debug, Clone)]
 pub struct CacheManager {
-    dict: HashMap<String, String>
+    dict: Arc<RwLock<HashMap<String, Entry>>>,
+    counters: Arc<Counters>,
+    default_ttl: Duration,
+    max_entries: Option<usize>,
+    metrics: MetricsManager,
+    write_through: Option<Arc<WriteThrough>>,
+    // Per-key locks held while get_or_insert_with computes a missing value
+    loading: Arc<Mutex<HashMap<String, Arc<Mutex<()>>>>>,
+    // Share of an entry's ttl, 0-1, it is randomly lengthened or shortened by, see `with_ttl_jitter`
+    ttl_jitter: f64,
+    // Window before expiry in which get_or_insert_with refreshes an entry early
+    soft_ttl: Option<Duration>,
+    // Keys get_or_insert_with is refreshing early
+    refreshing: Arc<Mutex<HashSet<String>>>,
 }
 
-const DEFAULT_TTL: u64 = 3600;
+impl Default for CacheManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl CacheManager {
 
     pub fn new() -> Self {
+        Self::with_default_ttl(DEFAULT_TTL)
+    }
+
+    // `ttl` seconds apply to entries set without one, e.g. config's `cache_ttl`
+    pub fn with_default_ttl(ttl: u64) -> Self {
         Self {
-            dict: HashMap::new()
+            dict: Arc::default(),
+            counters: Arc::default(),
+            default_ttl: Duration::from_secs(ttl),
+            max_entries: None,
+            metrics: MetricsManager::disabled(),
+            write_through: None,
+            loading: Arc::default(),
+            ttl_jitter: 0.0,
+            soft_ttl: None,
+            refreshing: Arc::default(),
+        }
+    }
+
+    // Caps the number of entries; unlimited by default
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    // Lengthens or shortens each entry's ttl by a random share of up to `jitter` (0.1 is ±10%)
+    // when it is set, so entries written in the same refresh don't all expire at once
+    pub fn with_ttl_jitter(mut self, jitter: f64) -> Self {
+        self.ttl_jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    // In the last `window` of an entry's ttl, get_or_insert_with has one caller recompute it in
+    // the background while every caller is still served the current value, so a hot key never
+    // goes missing. Entries whose ttl isn't longer than `window` are only recomputed once missing.
+    pub fn with_soft_ttl(mut self, window: Duration) -> Self {
+        self.soft_ttl = Some(window);
+        self
+    }
+
+    // Also counts hits and misses into `metrics`
+    pub fn with_metrics(mut self, metrics: MetricsManager) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    // Also writes entries to `store` under `cache/`, so `restore_from` can bring them back after
+    // a restart. Writes are buffered and flushed every `flush_interval` (at least
+    // MIN_FLUSH_INTERVAL), after FLUSH_AFTER_WRITES pending writes, on `flush` and when the last
+    // clone is dropped.
+    pub fn with_write_through(mut self, store: PersistenceManager, flush_interval: Duration) -> Self {
+        let flush_interval = flush_interval.max(MIN_FLUSH_INTERVAL);
+        let write_through = Arc::new(WriteThrough { store, pending: Mutex::default() });
+        let weak: Weak<WriteThrough> = Arc::downgrade(&write_through);
+        let _ = std::thread::Builder::new().name("polypath-cache-flush".to_string()).spawn(move || {
+            loop {
+                std::thread::sleep(flush_interval);
+                match weak.upgrade() {
+                    Some(write_through) => write_through.flush_logged(),
+                    None => break,
+                }
+            }
+        });
+        self.write_through = Some(write_through);
+        self
+    }
+
+    // Loads the still-live entries persisted under `cache/` in `store` and returns how many.
+    // Expired or unreadable ones are deleted from the store instead. Restored entries aren't
+    // written back.
+    pub fn restore_from(&self, store: &PersistenceManager) -> Result<usize, PersistenceError> {
+        self.restore_at(store, Instant::now(), SystemTime::now())
+    }
+
+    // Writes buffered write-through entries to the store now
+    pub fn flush(&self) -> Result<(), PersistenceError> {
+        match &self.write_through {
+            Some(write_through) => write_through.flush(),
+            None => Ok(()),
         }
     }
 
-    pub fn set(&mut self, key: String, value: String, ttl: Option<u64>) -> Result<bool> {
-        self.dict.insert(key, value);
+    pub fn set(&self, key: String, value: String, ttl: Option<u64>) -> Result<bool, CacheError> {
+        self.set_at(key, value, ttl, Instant::now());
+        Ok(true)
+    }
+
+    pub fn get(&self, key: String) -> Result<Option<String>, CacheError> {
+        Ok(self.get_at(key, Instant::now()))
+    }
+
+    // Entries are (key, value, ttl), inserted under one lock
+    pub fn set_many(&self, entries: Vec<(String, String, Option<u64>)>) -> Result<bool, CacheError> {
+        self.set_many_at(entries, Instant::now());
         Ok(true)
     }
 
-    pub fn get(&self, key: String) -> Result<&String> {
-        Ok(self.dict.get(&key).unwrap())
+    // One result per key, in order
+    pub fn get_many(&self, keys: &[String]) -> Result<Vec<Option<String>>, CacheError> {
+        Ok(self.get_many_at(keys, Instant::now()))
+    }
+
+    // The cached value of `key`, or the one `f` returns, which is cached with `ttl`. Concurrent
+    // callers missing the same key wait for a single call of `f` rather than each making one.
+    // An error from `f` is returned as is and nothing is cached.
+    // Within the soft ttl of an entry, the first caller to get there calls `f` on a thread of its
+    // own and every caller gets the current value; if `f` fails the current value stays and the
+    // next caller tries again.
+    pub fn get_or_insert_with<E>(
+        &self,
+        key: String,
+        ttl: Option<u64>,
+        f: impl FnOnce() -> Result<String, E> + Send + 'static,
+    ) -> Result<String, E> {
+        if let Some((value, claim)) = self.get_for_refresh(key.clone(), ttl) {
+            if let Some(claim) = claim {
+                let cache = self.clone();
+                let _ = std::thread::Builder::new().name("polypath-cache-refresh".to_string()).spawn(move || {
+                    if let Ok(fresh) = f() {
+                        cache.set_at(key, fresh, ttl, Instant::now());
+                    }
+                    drop(claim);
+                });
+            }
+            return Ok(value);
+        }
+
+        let lock = self.loading().entry(key.clone()).or_default().clone();
+        let result = {
+            let _loading = lock.lock().unwrap_or_else(PoisonError::into_inner);
+            // Set by whoever held the lock before us
+            match self.peek_at(&key, Instant::now()) {
+                Some(value) => {
+                    Counters::bump(&self.counters.stampedes_prevented, 1);
+                    Ok(value)
+                }
+                None => f().inspect(|value| self.set_at(key.clone(), value.clone(), ttl, Instant::now())),
+            }
+        };
+
+        // Clones of the lock are only taken under `loading`, so nobody else can be about to wait on it
+        let mut loading = self.loading();
+        if Arc::strong_count(&lock) == 2 {
+            loading.remove(&key);
+        }
+        result
+    }
+
+    // The live value of `key` and, within its soft ttl, the claim to refresh it unless another
+    // caller has it already. `ttl` is what the key is set with; see `with_soft_ttl`.
+    pub fn get_for_refresh(&self, key: String, ttl: Option<u64>) -> Option<(String, Option<RefreshClaim>)> {
+        let now = Instant::now();
+        let (value, expires_at) = self.lookup_at(&key, now)?;
+        let claim = self.claim_refresh_at(key, expires_at, ttl, now);
+        Some((value, claim))
+    }
+
+    // Whether there was a live entry to remove
+    pub fn remove(&self, key: String) -> Result<bool, CacheError> {
+        Ok(self.remove_many_at(std::slice::from_ref(&key), Instant::now()) == 1)
+    }
+
+    // Removes the keys under one lock and returns how many had live entries
+    pub fn remove_many(&self, keys: &[String]) -> Result<usize, CacheError> {
+        Ok(self.remove_many_at(keys, Instant::now()))
     }
 
-    pub fn remove(&mut self, key: String) -> Result<bool> {
-        self.dict.remove_entry(&key);
+    pub fn clear(&self) -> Result<bool, CacheError> {
+        let keys: Vec<String> = self.write().drain().map(|(key, _)| key).collect();
+        for key in &keys {
+            self.persist(key, None);
+        }
         Ok(true)
     }
 
-    pub fn clear(&mut self) -> Result<bool> {
-        self.dict.clear();
+    // Drops every expired entry and returns how many there were
+    pub fn purge_expired(&self) -> usize {
+        self.purge_expired_at(Instant::now())
+    }
+
+    // Live entries only
+    pub fn len(&self) -> usize {
+        self.len_at(Instant::now())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Stores `value` as JSON
+    pub fn set_json<T: Serialize>(&self, key: String, value: &T, ttl: Option<u64>) -> Result<bool, CacheError> {
+        let encoded = serde_json::to_string(value).map_err(|source| CacheError::Encode { key: key.clone(), source })?;
+        self.set_at(key, encoded, ttl, Instant::now());
         Ok(true)
     }
-}
\ No newline at end of file
+
+    // Ok(None) on a miss; an entry stored as some other type is an error rather than a miss
+    pub fn get_json<T: DeserializeOwned>(&self, key: String) -> Result<Option<T>, CacheError> {
+        let Some(encoded) = self.get_at(key.clone(), Instant::now()) else {
+            return Ok(None);
+        };
+        serde_json::from_str(&encoded)
+            .map(Some)
+            .map_err(|source| CacheError::Decode { key, source })
+    }
+
+    // A view of this cache whose keys are prefixed with `name`, so users picking the same
+    // keys in different namespaces don't overwrite each other. Shares the store and capacity.
+    // The prefix carries the name's length, since names may contain the `:` that ends it.
+    pub fn namespace(&self, name: &str) -> CacheNamespace {
+        CacheNamespace {
+            cache: self.clone(),
+            prefix: format!("{}:{}:", name.len(), name),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let counters = &self.counters;
+        CacheStats {
+            entries: self.len(),
+            hits: counters.hits.load(Ordering::Relaxed),
+            misses: counters.misses.load(Ordering::Relaxed),
+            evictions: counters.evictions.load(Ordering::Relaxed),
+            expired_purges: counters.expired_purges.load(Ordering::Relaxed),
+            stampedes_prevented: counters.stampedes_prevented.load(Ordering::Relaxed),
+        }
+    }
+
+    // A writer that panicked can't leave an entry half-written, so a poisoned lock is still usable
+    fn read(&self) -> RwLockReadGuard<'_, HashMap<String, Entry>> {
+        self.dict.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, HashMap<String, Entry>> {
+        self.dict.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn loading(&self) -> MutexGuard<'_, HashMap<String, Arc<Mutex<()>>>> {
+        self.loading.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn refreshing(&self) -> MutexGuard<'_, HashSet<String>> {
+        self.refreshing.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn claim_refresh_at(&self, key: String, expires_at: Instant, ttl: Option<u64>, now: Instant) -> Option<RefreshClaim> {
+        let window = self.soft_ttl?;
+        let ttl = ttl.map_or(self.default_ttl, Duration::from_secs);
+        if ttl <= window || expires_at > now + window {
+            return None;
+        }
+        if !self.refreshing().insert(key.clone()) {
+            Counters::bump(&self.counters.stampedes_prevented, 1);
+            return None;
+        }
+        Some(RefreshClaim { refreshing: Arc::clone(&self.refreshing), key })
+    }
+
+    fn jittered(&self, ttl: Duration) -> Duration {
+        if self.ttl_jitter == 0.0 {
+            return ttl;
+        }
+        ttl.mul_f64(1.0 + self.ttl_jitter * (2.0 * fastrand::f64() - 1.0))
+    }
+
+    // The `_at` variants take the current time so tests can move it without sleeping
+
+    fn set_at(&self, key: String, value: String, ttl: Option<u64>, now: Instant) {
+        self.set_many_at(vec![(key, value, ttl)], now);
+    }
+
+    fn set_many_at(&self, entries: Vec<(String, String, Option<u64>)>, now: Instant) {
+        let entries = entries
+            .into_iter()
+            .map(|(key, value, ttl)| {
+                let ttl = self.jittered(ttl.map(Duration::from_secs).unwrap_or(self.default_ttl));
+                if self.write_through.is_some() {
+                    let expires_at = unix_millis(SystemTime::now() + ttl);
+                    self.persist(&key, Some(PersistedEntry { value: value.clone(), expires_at }));
+                }
+                (key, value, now + ttl)
+            })
+            .collect();
+        self.insert(entries, now);
+    }
+
+    // Entries are (key, value, expires_at)
+    fn insert(&self, entries: Vec<(String, String, Instant)>, now: Instant) {
+        let mut dict = self.write();
+        for (key, value, expires_at) in entries {
+            let last_used = AtomicU64::new(self.counters.next_tick());
+            dict.insert(key, Entry { value, expires_at, last_used });
+        }
+        if let Some(max_entries) = self.max_entries
+            && dict.len() > max_entries
+        {
+            self.evict(&mut dict, max_entries, now);
+        }
+    }
+
+    // Linear in the number of entries, paid only by inserts that overflow the capacity
+    fn evict(&self, dict: &mut HashMap<String, Entry>, max_entries: usize, now: Instant) {
+        let before = dict.len();
+        dict.retain(|_, entry| entry.expires_at > now);
+        Counters::bump(&self.counters.expired_purges, before - dict.len());
+
+        while dict.len() > max_entries {
+            let Some(oldest) = dict
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used.load(Ordering::Relaxed))
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            dict.remove(&oldest);
+            self.persist(&oldest, None);
+            Counters::bump(&self.counters.evictions, 1);
+        }
+    }
+
+    fn get_at(&self, key: String, now: Instant) -> Option<String> {
+        self.lookup_at(&key, now).map(|(value, _)| value)
+    }
+
+    // A live value and when it expires, counted as a hit or miss
+    fn lookup_at(&self, key: &str, now: Instant) -> Option<(String, Instant)> {
+        match self.read().get(key) {
+            Some(entry) if entry.expires_at > now => {
+                entry.last_used.store(self.counters.next_tick(), Ordering::Relaxed);
+                self.record(true);
+                return Some((entry.value.clone(), entry.expires_at));
+            }
+            Some(_) => {}
+            None => {
+                self.record(false);
+                return None;
+            }
+        }
+        // Expired; another writer may have refreshed it since the read lock was released
+        let mut dict = self.write();
+        if dict.get(key).is_some_and(|entry| entry.expires_at <= now) {
+            dict.remove(key);
+            Counters::bump(&self.counters.expired_purges, 1);
+        }
+        let value = dict.get(key).map(|entry| {
+            entry.last_used.store(self.counters.next_tick(), Ordering::Relaxed);
+            (entry.value.clone(), entry.expires_at)
+        });
+        self.record(value.is_some());
+        value
+    }
+
+    fn get_many_at(&self, keys: &[String], now: Instant) -> Vec<Option<String>> {
+        // Expired entries are left for purge_expired rather than taking the write lock here
+        let dict = self.read();
+        keys.iter()
+            .map(|key| {
+                let value = dict.get(key).filter(|entry| entry.expires_at > now).map(|entry| {
+                    entry.last_used.store(self.counters.next_tick(), Ordering::Relaxed);
+                    entry.value.clone()
+                });
+                self.record(value.is_some());
+                value
+            })
+            .collect()
+    }
+
+    // A live value without counting a hit or miss or touching its LRU position
+    fn peek_at(&self, key: &str, now: Instant) -> Option<String> {
+        self.read().get(key).filter(|entry| entry.expires_at > now).map(|entry| entry.value.clone())
+    }
+
+    fn remove_many_at(&self, keys: &[String], now: Instant) -> usize {
+        let mut dict = self.write();
+        let mut removed = 0;
+        for key in keys {
+            if dict.remove(key).is_some_and(|entry| entry.expires_at > now) {
+                removed += 1;
+            }
+            self.persist(key, None);
+        }
+        removed
+    }
+
+    fn record(&self, hit: bool) {
+        match hit {
+            true => {
+                Counters::bump(&self.counters.hits, 1);
+                self.metrics.record_cache_hit();
+            }
+            false => {
+                Counters::bump(&self.counters.misses, 1);
+                self.metrics.record_cache_miss();
+            }
+        }
+    }
+
+    fn persist(&self, key: &str, entry: Option<PersistedEntry>) {
+        if let Some(write_through) = &self.write_through {
+            write_through.record(key, entry);
+        }
+    }
+
+    fn restore_at(&self, store: &PersistenceManager, now: Instant, wall_now: SystemTime) -> Result<usize, PersistenceError> {
+        let wall_now = unix_millis(wall_now);
+        let mut restored = Vec::new();
+        let mut dropped = Vec::new();
+        for (stored_key, encoded) in store.scan_prefix(PERSISTED_PREFIX)? {
+            let key = stored_key[PERSISTED_PREFIX.len()..].to_string();
+            match store.decode_typed::<PersistedEntry>(&stored_key, &encoded) {
+                Ok(entry) if entry.expires_at > wall_now => {
+                    restored.push((key, entry.value, now + Duration::from_millis(entry.expires_at - wall_now)));
+                }
+                // Left for the newer build that wrote it
+                Err(PersistenceError::UnsupportedVersion { .. }) => {}
+                _ => dropped.push(WriteOp::Delete { key: stored_key }),
+            }
+        }
+        store.storage().apply(&dropped)?;
+        let count = restored.len();
+        self.insert(restored, now);
+        Ok(count)
+    }
+
+    fn purge_expired_at(&self, now: Instant) -> usize {
+        let mut dict = self.write();
+        let before = dict.len();
+        dict.retain(|_, entry| entry.expires_at > now);
+        let purged = before - dict.len();
+        Counters::bump(&self.counters.expired_purges, purged);
+        purged
+    }
+
+    fn len_at(&self, now: Instant) -> usize {
+        self.read().values().filter(|entry| entry.expires_at > now).count()
+    }
+}
+
+// A caller's turn to refresh a key early, see CacheManager::get_for_refresh. Others are served
+// the current value until it is dropped, refreshed or not.
+#[derive(Debug)]
+pub struct RefreshClaim {
+    refreshing: Arc<Mutex<HashSet<String>>>,
+    key: String,
+}
+
+impl Drop for RefreshClaim {
+    fn drop(&mut self) {
+        self.refreshing.lock().unwrap_or_else(PoisonError::into_inner).remove(&self.key);
+    }
+}
+
+// Handle returned by CacheManager::namespace
+#[derive(Debug, Clone)]
+pub struct CacheNamespace {
+    cache: CacheManager,
+    prefix: String,
+}
+
+impl CacheNamespace {
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    pub fn set(&self, key: &str, value: String, ttl: Option<u64>) -> Result<bool, CacheError> {
+        self.cache.set(self.key(key), value, ttl)
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<String>, CacheError> {
+        self.cache.get(self.key(key))
+    }
+
+    pub fn set_json<T: Serialize>(&self, key: &str, value: &T, ttl: Option<u64>) -> Result<bool, CacheError> {
+        self.cache.set_json(self.key(key), value, ttl)
+    }
+
+    pub fn get_json<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, CacheError> {
+        self.cache.get_json(self.key(key))
+    }
+
+    // As CacheManager::get_for_refresh, decoding the value
+    pub fn get_json_for_refresh<T: DeserializeOwned>(&self, key: &str, ttl: Option<u64>) -> Result<Option<(T, Option<RefreshClaim>)>, CacheError> {
+        let key = self.key(key);
+        let Some((encoded, claim)) = self.cache.get_for_refresh(key.clone(), ttl) else {
+            return Ok(None);
+        };
+        serde_json::from_str(&encoded)
+            .map(|value| Some((value, claim)))
+            .map_err(|source| CacheError::Decode { key, source })
+    }
+
+    pub fn remove(&self, key: &str) -> Result<bool, CacheError> {
+        self.cache.remove(self.key(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_expire_after_their_ttl() {
+        let cache = CacheManager::new();
+        let now = Instant::now();
+        cache.set_at("quote".to_string(), "1.0".to_string(), Some(10), now);
+
+        assert_eq!(cache.get_at("quote".to_string(), now + Duration::from_secs(9)), Some("1.0".to_string()));
+        assert_eq!(cache.len_at(now + Duration::from_secs(9)), 1);
+
+        assert_eq!(cache.get_at("quote".to_string(), now + Duration::from_secs(10)), None);
+        // Removed on access
+        assert_eq!(cache.purge_expired_at(now), 0);
+        assert_eq!(cache.get("missing".to_string()).unwrap(), None);
+    }
+
+    #[test]
+    fn default_ttl_applies_when_none_is_given() {
+        let cache = CacheManager::with_default_ttl(60);
+        let now = Instant::now();
+        cache.set_at("a".to_string(), "1".to_string(), None, now);
+        cache.set_at("b".to_string(), "2".to_string(), Some(120), now);
+
+        let later = now + Duration::from_secs(60);
+        assert_eq!(cache.len_at(later), 1);
+        assert_eq!(cache.purge_expired_at(later), 1);
+        assert_eq!(cache.get_at("b".to_string(), later), Some("2".to_string()));
+
+        assert_eq!(CacheManager::new().default_ttl, Duration::from_secs(DEFAULT_TTL));
+    }
+
+    #[test]
+    fn concurrent_writers_and_readers_share_one_store() {
+        let cache = CacheManager::new();
+        let threads: Vec<_> = (0..8)
+            .map(|thread| {
+                let cache = cache.clone();
+                std::thread::spawn(move || {
+                    for i in 0..500 {
+                        let key = format!("key-{}", i % 50);
+                        cache.set(key.clone(), format!("{}-{}", thread, i), Some(60)).unwrap();
+                        let _ = cache.get(key.clone()).unwrap();
+                        if i % 7 == 0 {
+                            cache.remove(key).unwrap();
+                        }
+                        cache.purge_expired();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert!(cache.len() <= 50);
+    }
+
+    #[test]
+    fn least_recently_used_entries_are_evicted_past_capacity() {
+        let cache = CacheManager::new().with_max_entries(3);
+        for key in ["a", "b", "c"] {
+            cache.set(key.to_string(), key.to_uppercase(), None).unwrap();
+        }
+        // "a" is the oldest insert but was just read, so "b" goes first
+        assert_eq!(cache.get("a".to_string()).unwrap(), Some("A".to_string()));
+        cache.set("d".to_string(), "D".to_string(), None).unwrap();
+
+        assert_eq!(cache.get("b".to_string()).unwrap(), None);
+        assert_eq!(cache.get("a".to_string()).unwrap(), Some("A".to_string()));
+        assert_eq!(cache.len(), 3);
+
+        // Expired entries make room before any live one is evicted
+        let now = Instant::now();
+        cache.set_at("short".to_string(), "S".to_string(), Some(1), now);
+        cache.set_at("e".to_string(), "E".to_string(), None, now + Duration::from_secs(2));
+
+        assert_eq!(cache.stats(), CacheStats {
+            entries: 3,
+            hits: 2,
+            misses: 1,
+            evictions: 2,
+            expired_purges: 1,
+            stampedes_prevented: 0,
+        });
+    }
+
+    #[test]
+    fn write_through_entries_survive_a_rebuild() {
+        let store = PersistenceManager::new();
+        let cache = CacheManager::with_default_ttl(60).with_write_through(store.clone(), Duration::from_secs(3600));
+        cache.set("live".to_string(), "1".to_string(), None).unwrap();
+        cache.set_json("route".to_string(), &vec!["ethereum", "polygon"], Some(600)).unwrap();
+        cache.set("stale".to_string(), "2".to_string(), Some(0)).unwrap();
+        cache.set("removed".to_string(), "3".to_string(), None).unwrap();
+        cache.remove("removed".to_string()).unwrap();
+        // Nothing hits the store until a flush
+        assert!(store.keys_with_prefix(PERSISTED_PREFIX).unwrap().is_empty());
+        drop(cache);
+        assert_eq!(store.keys_with_prefix(PERSISTED_PREFIX).unwrap(), ["cache/live", "cache/route", "cache/stale"]);
+
+        let rebuilt = CacheManager::with_default_ttl(60);
+        assert_eq!(rebuilt.restore_from(&store).unwrap(), 2);
+        assert_eq!(rebuilt.get("live".to_string()).unwrap(), Some("1".to_string()));
+        assert_eq!(rebuilt.get_json::<Vec<String>>("route".to_string()).unwrap().unwrap(), ["ethereum", "polygon"]);
+        assert_eq!(rebuilt.get("stale".to_string()).unwrap(), None);
+        // Expired entries are cleaned out of the store
+        assert_eq!(store.keys_with_prefix(PERSISTED_PREFIX).unwrap(), ["cache/live", "cache/route"]);
+
+        // An entry from a newer build is skipped but kept
+        let newer = r#"{"schema":"cache_entry","version":2,"payload":{"value":"4","expires_at":99999999999999}}"#;
+        store.store("cache/newer".to_string(), newer.to_string()).unwrap();
+        assert_eq!(CacheManager::new().restore_from(&store).unwrap(), 2);
+        assert_eq!(store.keys_with_prefix(PERSISTED_PREFIX).unwrap(), ["cache/live", "cache/newer", "cache/route"]);
+        store.delete("cache/newer".to_string()).unwrap();
+
+        // Restored entries keep their remaining ttl
+        let now = Instant::now();
+        let later = CacheManager::new();
+        later.restore_at(&store, now, SystemTime::now() + Duration::from_secs(120)).unwrap();
+        assert_eq!(later.len_at(now), 1);
+    }
+
+    #[test]
+    fn full_write_buffers_flush_without_waiting() {
+        let store = PersistenceManager::new();
+        let cache = CacheManager::new().with_write_through(store.clone(), Duration::from_secs(3600));
+        for i in 0..FLUSH_AFTER_WRITES {
+            cache.set(format!("key-{}", i), i.to_string(), None).unwrap();
+        }
+        assert_eq!(store.keys_with_prefix(PERSISTED_PREFIX).unwrap().len(), FLUSH_AFTER_WRITES);
+
+        cache.clear().unwrap();
+        cache.flush().unwrap();
+        assert!(store.keys_with_prefix(PERSISTED_PREFIX).unwrap().is_empty());
+
+        let plain = CacheManager::new();
+        plain.set("key".to_string(), "value".to_string(), None).unwrap();
+        plain.flush().unwrap();
+        assert!(store.keys_with_prefix("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn batch_operations_round_trip() {
+        let cache = CacheManager::new();
+        let now = Instant::now();
+        cache.set_many_at(vec![
+            ("a".to_string(), "1".to_string(), None),
+            ("b".to_string(), "2".to_string(), Some(10)),
+            ("c".to_string(), "3".to_string(), None),
+        ], now);
+
+        let keys = ["a", "b", "missing", "c"].map(String::from);
+        assert_eq!(cache.get_many_at(&keys, now), [Some("1".to_string()), Some("2".to_string()), None, Some("3".to_string())]);
+        assert_eq!(cache.get_many_at(&keys, now + Duration::from_secs(10))[1], None);
+
+        // Expired and missing keys don't count as removed
+        assert_eq!(cache.remove_many_at(&keys, now + Duration::from_secs(10)), 2);
+        assert!(cache.is_empty());
+
+        cache.set("d".to_string(), "4".to_string(), None).unwrap();
+        assert!(cache.remove("d".to_string()).unwrap());
+        assert!(!cache.remove("d".to_string()).unwrap());
+        assert_eq!((cache.stats().hits, cache.stats().misses), (5, 3));
+    }
+
+    #[test]
+    fn concurrent_get_or_insert_with_computes_once() {
+        let cache = CacheManager::new();
+        let calls = Arc::new(AtomicU64::new(0));
+        let start = Arc::new(std::sync::Barrier::new(16));
+        let threads: Vec<_> = (0..16)
+            .map(|_| {
+                let (cache, calls, start) = (cache.clone(), calls.clone(), start.clone());
+                std::thread::spawn(move || {
+                    start.wait();
+                    cache.get_or_insert_with("route".to_string(), None, move || {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                        std::thread::sleep(Duration::from_millis(50));
+                        Ok::<_, CacheError>("computed".to_string())
+                    })
+                })
+            })
+            .collect();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap().unwrap(), "computed");
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(cache.loading().is_empty());
+
+        // Errors pass through and leave nothing behind, so the next caller computes again
+        let failed = cache.get_or_insert_with("other".to_string(), None, || Err("upstream down"));
+        assert_eq!(failed, Err("upstream down"));
+        assert_eq!(cache.get_or_insert_with("other".to_string(), None, || Ok::<_, &str>("2".to_string())), Ok("2".to_string()));
+    }
+
+    #[test]
+    fn hot_keys_are_refreshed_early_by_one_caller_without_a_miss() {
+        let cache = CacheManager::new().with_soft_ttl(Duration::from_secs(1));
+        // Half a second left of its ttl, inside the soft ttl
+        cache.set_at("route".to_string(), "stale".to_string(), Some(2), Instant::now() - Duration::from_millis(1500));
+        let calls = Arc::new(AtomicU64::new(0));
+        let start = Arc::new(std::sync::Barrier::new(50));
+        let threads: Vec<_> = (0..50)
+            .map(|_| {
+                let (cache, calls, start) = (cache.clone(), calls.clone(), start.clone());
+                std::thread::spawn(move || {
+                    start.wait();
+                    cache.get_or_insert_with("route".to_string(), Some(60), move || {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                        std::thread::sleep(Duration::from_millis(100));
+                        Ok::<_, CacheError>("fresh".to_string())
+                    })
+                })
+            })
+            .collect();
+        let values: Vec<String> = threads.into_iter().map(|thread| thread.join().unwrap().unwrap()).collect();
+        wait_for_refreshes(&cache);
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(values.iter().all(|value| value == "stale" || value == "fresh"));
+        // Everyone but the caller that started the refresh found it running, or already done
+        assert_eq!(values.iter().filter(|value| *value == "stale").count(), cache.stats().stampedes_prevented as usize + 1);
+        assert!(cache.stats().stampedes_prevented > 0);
+        assert_eq!(cache.stats().misses, 0);
+        assert_eq!(cache.get("route".to_string()).unwrap(), Some("fresh".to_string()));
+
+        // A failed early refresh keeps serving the current value, and the next caller tries again
+        cache.set_at("quote".to_string(), "1.0".to_string(), Some(2), Instant::now() - Duration::from_millis(1500));
+        assert_eq!(cache.get_or_insert_with("quote".to_string(), None, || Err("upstream down")), Ok("1.0".to_string()));
+        wait_for_refreshes(&cache);
+        assert_eq!(cache.get_or_insert_with("quote".to_string(), None, || Ok::<_, &str>("1.1".to_string())), Ok("1.0".to_string()));
+        wait_for_refreshes(&cache);
+        assert_eq!(cache.get("quote".to_string()).unwrap(), Some("1.1".to_string()));
+        // Entries outside the soft ttl aren't recomputed
+        assert_eq!(cache.get_or_insert_with("route".to_string(), None, || Err("upstream down")), Ok("fresh".to_string()));
+        assert!(cache.get_for_refresh("route".to_string(), None).unwrap().1.is_none());
+    }
+
+    #[test]
+    fn early_refreshes_give_their_key_up_however_they_end() {
+        let cache = CacheManager::new().with_soft_ttl(Duration::from_secs(1));
+        cache.set_at("quote".to_string(), "1.0".to_string(), Some(2), Instant::now() - Duration::from_millis(1500));
+        assert_eq!(cache.get_or_insert_with("quote".to_string(), None, || -> Result<String, &str> { panic!("adapter bug") }), Ok("1.0".to_string()));
+        wait_for_refreshes(&cache);
+        let (_, claim) = cache.get_for_refresh("quote".to_string(), None).unwrap();
+        assert!(claim.is_some());
+        assert!(cache.get_for_refresh("quote".to_string(), None).unwrap().1.is_none());
+        drop(claim);
+        assert!(cache.refreshing().is_empty());
+
+        // Entries set to live no longer than the soft ttl would be refreshed on every read
+        cache.set_at("short".to_string(), "1.0".to_string(), Some(1), Instant::now() - Duration::from_millis(500));
+        assert!(cache.get_for_refresh("short".to_string(), Some(1)).unwrap().1.is_none());
+    }
+
+    fn wait_for_refreshes(cache: &CacheManager) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !cache.refreshing().is_empty() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(cache.refreshing().is_empty());
+    }
+
+    #[test]
+    fn jitter_spreads_expiry_around_the_ttl() {
+        let cache = CacheManager::new().with_ttl_jitter(0.2);
+        let now = Instant::now();
+        cache.set_many_at((0..200).map(|i| (format!("key-{}", i), i.to_string(), Some(100))).collect(), now);
+
+        assert_eq!(cache.len_at(now + Duration::from_secs(79)), 200);
+        assert_eq!(cache.len_at(now + Duration::from_secs(121)), 0);
+        let at_ttl = cache.len_at(now + Duration::from_secs(100));
+        assert!(at_ttl > 0 && at_ttl < 200, "{}", at_ttl);
+
+        assert_eq!(CacheManager::new().with_ttl_jitter(3.0).ttl_jitter, 1.0);
+    }
+
+    #[test]
+    fn namespaces_do_not_clash() {
+        let cache = CacheManager::new();
+        let stargate = cache.namespace("stargate_quotes");
+        let wormhole = cache.namespace("wormhole_quotes");
+
+        stargate.set_json("ethereum:polygon", &1.5_f64, None).unwrap();
+        wormhole.set_json("ethereum:polygon", &vec!["a", "b"], None).unwrap();
+
+        assert_eq!(stargate.get_json::<f64>("ethereum:polygon").unwrap(), Some(1.5));
+        assert_eq!(wormhole.get_json::<Vec<String>>("ethereum:polygon").unwrap(), Some(vec!["a".to_string(), "b".to_string()]));
+        assert_eq!(cache.get_json::<f64>("ethereum:polygon".to_string()).unwrap(), None);
+
+        // Reading back as the wrong type is an error, not a silent miss
+        assert!(matches!(wormhole.get_json::<f64>("ethereum:polygon"), Err(CacheError::Decode { .. })));
+
+        stargate.remove("ethereum:polygon").unwrap();
+        assert_eq!(stargate.get("ethereum:polygon").unwrap(), None);
+        assert!(wormhole.get("ethereum:polygon").unwrap().is_some());
+
+        // A `:` in the name can't be mistaken for one in the key
+        cache.namespace("a").set("b:x", "short name".to_string(), None).unwrap();
+        cache.namespace("a:b").set("x", "long name".to_string(), None).unwrap();
+        assert_eq!(cache.namespace("a").get("b:x").unwrap(), Some("short name".to_string()));
+    }
+}
@@ -1,20 +1,931 @@
-// Loads config.yaml    
-use toml;
+// Loads config.toml, config.yaml or config.json
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
     fs,
+    path::{Path, PathBuf},
+    time::Duration,
 };
-use serde::Deserialize;
-use anyhow::Result;
+use serde::{Deserialize, Deserializer, Serialize};
 
-#[derive(Deserialize, Debug, Clone)]
-struct GlobalConfig {
-    update_interval: u8,
-    cache_ttl: u8,
-    log_level: String
+use crate::{
+    errors::ConfigError,
+    registry::{ChainRef, Registry, TokenRef},
+    secret::{REDACTED, is_secret_key, matches_pattern},
+};
+
+const LOG_LEVELS: [&str; 5] = ["trace", "debug", "info", "warn", "error"];
+const ENV_OVERRIDE_PREFIX: &str = "POLYPATH__";
+const SECRET_REFERENCE: &str = "${secret:";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl ConfigFormat {
+    pub const EXTENSIONS: [&str; 4] = ["toml", "yaml", "yml", "json"];
+
+    // Picked from the file extension, case-insensitively
+    pub fn from_path(path: &Path) -> Result<Self, ConfigError> {
+        let extension = path.extension().and_then(|ext| ext.to_str()).map(str::to_lowercase);
+        match extension.as_deref() {
+            Some("toml") => Ok(ConfigFormat::Toml),
+            Some("yaml" | "yml") => Ok(ConfigFormat::Yaml),
+            Some("json") => Ok(ConfigFormat::Json),
+            _ => Err(ConfigError::UnsupportedFormat {
+                path: path.to_path_buf(),
+                supported: Self::EXTENSIONS.iter().map(|ext| format!(".{}", ext)).collect(),
+            }),
+        }
+    }
+}
+
+// Every field has a default, so the whole [global] section may be left out
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct GlobalConfig {
+    // "30s", "15m", "1h" or a bare number of seconds; 60s by default
+    #[serde(default = "default_update_interval", deserialize_with = "deserialize_duration")]
+    pub update_interval: Duration,
+    // Same format as update_interval; 5m by default
+    #[serde(default = "default_cache_ttl", deserialize_with = "deserialize_duration")]
+    pub cache_ttl: Duration,
+    // One of LOG_LEVELS; "info" by default
+    #[serde(default = "default_log_level")]
+    pub log_level: String,
+    // Cache capacity, least recently used entries are evicted beyond it. Unlimited when unset.
+    #[serde(default)]
+    pub max_entries: Option<usize>,
+    // Persist cache entries so they outlive a restart; off by default
+    #[serde(default)]
+    pub cache_write_through: bool,
+    // Share of an entry's ttl, 0-1, it is randomly lengthened or shortened by so entries set
+    // together don't expire together; 0 by default
+    #[serde(default)]
+    pub cache_ttl_jitter: f64,
+    // Window before an entry expires in which one reader refreshes it while the others keep
+    // getting it, see CacheManager::with_soft_ttl. Off when unset.
+    #[serde(default, deserialize_with = "deserialize_optional_duration")]
+    pub cache_soft_ttl: Option<Duration>,
+    // How often write-through entries are flushed to the store; 5s by default
+    #[serde(default = "default_cache_flush_interval", deserialize_with = "deserialize_duration")]
+    pub cache_flush_interval: Duration,
+    // Where the persistence store keeps its data: a directory for the files backend, a database
+    // file for sqlite. Nothing is persisted when unset.
+    #[serde(default)]
+    pub persistence_path: Option<PathBuf>,
+    // "files", "sqlite" or "memory". Defaults to files with a persistence_path, memory without.
+    #[serde(default)]
+    pub persistence_backend: Option<PersistenceBackend>,
+    // Persisted graph snapshots older than this are ignored on startup
+    #[serde(default)]
+    pub snapshot_max_age_secs: Option<u64>,
+    // Extra key patterns redacted from Debug output on top of DEFAULT_SECRET_PATTERNS,
+    // e.g. "*_password"
+    #[serde(default)]
+    pub secret_patterns: Vec<String>,
+    // Consecutive failures after which a refresh stops quoting a pair on every tick and only
+    // re-probes it with backoff; 5 by default
+    #[serde(default = "default_quarantine_after")]
+    pub quarantine_after: u32,
+    // Share of its configured pairs, 0-1, a graph needs edges for before its routes are first
+    // served, see Router::with_min_coverage; 0.5 by default, 0 serves from the first edge
+    #[serde(default = "default_min_coverage")]
+    pub min_coverage: f64,
+    // Score bonus keeping a repeat transfer on the route last confirmed for its intent, see
+    // Router::with_stickiness; off when unset
+    #[serde(default)]
+    pub route_stickiness: Option<f64>,
+    // Quotes a refresh has fetched that can wait for the graph at once; fetches wait for room
+    // beyond it. 256 by default.
+    #[serde(default = "default_update_queue_capacity")]
+    pub update_queue_capacity: usize,
+    // Most quotes applied to the graph as one change, under one version; 64 by default
+    #[serde(default = "default_update_batch_size")]
+    pub update_batch_size: usize,
+}
+
+impl Default for GlobalConfig {
+    fn default() -> Self {
+        Self {
+            update_interval: default_update_interval(),
+            cache_ttl: default_cache_ttl(),
+            log_level: default_log_level(),
+            max_entries: None,
+            cache_write_through: false,
+            cache_ttl_jitter: 0.0,
+            cache_soft_ttl: None,
+            cache_flush_interval: default_cache_flush_interval(),
+            persistence_path: None,
+            persistence_backend: None,
+            snapshot_max_age_secs: None,
+            secret_patterns: Vec::new(),
+            quarantine_after: default_quarantine_after(),
+            min_coverage: default_min_coverage(),
+            route_stickiness: None,
+            update_queue_capacity: default_update_queue_capacity(),
+            update_batch_size: default_update_batch_size(),
+        }
+    }
+}
+
+fn default_update_interval() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_cache_ttl() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+fn default_cache_flush_interval() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_quarantine_after() -> u32 {
+    5
+}
+
+fn default_min_coverage() -> f64 {
+    0.5
+}
+
+fn default_update_queue_capacity() -> usize {
+    256
+}
+
+fn default_update_batch_size() -> usize {
+    64
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PersistenceBackend {
+    Files,
+    Sqlite,
+    Memory,
+}
+
+impl fmt::Display for PersistenceBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PersistenceBackend::Files => "files",
+            PersistenceBackend::Sqlite => "sqlite",
+            PersistenceBackend::Memory => "memory",
+        })
+    }
+}
+
+// Optional [logging] section, read by LoggingManager::init
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct LoggingConfig {
+    // Mirrors global.log_level; filled in when the config is loaded
+    #[serde(skip, default = "default_log_level")]
+    pub level: String,
+    // Per-target levels on top of `level`, e.g. "polypath_dal=debug,polypath_graph=info"
+    #[serde(default)]
+    pub filter: Option<String>,
+    #[serde(default)]
+    pub format: LogFormat,
+    // Log to rotating files instead of stdout
+    #[serde(default)]
+    pub file: Option<LogFileConfig>,
+    #[serde(default)]
+    pub slow_ops: SlowOpsConfig,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: default_log_level(),
+            filter: None,
+            format: LogFormat::default(),
+            file: None,
+            slow_ops: SlowOpsConfig::default(),
+        }
+    }
+}
+
+// [logging.slow_ops]: how long an operation timed with LoggingManager::time_scope may take before
+// it's logged as a warning, e.g. `default = "1s"` and `fetch_metrics = "5s"`
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct SlowOpsConfig {
+    // For operations without their own entry
+    #[serde(default = "default_slow_op_threshold", deserialize_with = "deserialize_duration")]
+    pub default: Duration,
+    // By operation name: fetch_metrics, graph_upsert, route_search, scoring
+    #[serde(flatten, deserialize_with = "deserialize_duration_map")]
+    pub operations: BTreeMap<String, Duration>,
+}
+
+impl SlowOpsConfig {
+    pub fn threshold(&self, operation: &str) -> Duration {
+        self.operations.get(operation).copied().unwrap_or(self.default)
+    }
+}
+
+impl Default for SlowOpsConfig {
+    fn default() -> Self {
+        Self { default: default_slow_op_threshold(), operations: BTreeMap::new() }
+    }
+}
+
+fn default_slow_op_threshold() -> Duration {
+    Duration::from_secs(1)
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Pretty,
+    Json,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct LogFileConfig {
+    pub directory: PathBuf,
+    // Files are named `<prefix>.<date>.log`
+    #[serde(default = "default_log_file_prefix")]
+    pub prefix: String,
+    #[serde(default)]
+    pub rotation: LogRotation,
+}
+
+fn default_log_file_prefix() -> String {
+    "polypath".to_string()
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Hourly,
+    #[default]
+    Daily,
+    Never,
+}
+
+// Optional [metrics] section; disabled metrics are a no-op
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct MetricsConfig {
+    #[serde(default = "default_metrics_enabled")]
+    pub enabled: bool,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self { enabled: default_metrics_enabled() }
+    }
+}
+
+fn default_metrics_enabled() -> bool {
+    true
+}
+
+// Optional [history] section: edge metrics recorded on every graph update, in the persistence store
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct HistoryConfig {
+    #[serde(default = "default_history_enabled")]
+    pub enabled: bool,
+    // Samples older than this are dropped; 7d by default
+    #[serde(default = "default_history_retention", deserialize_with = "deserialize_duration")]
+    pub retention: Duration,
+    // Raw samples older than this are averaged into one sample per hour; 1d by default
+    #[serde(default = "default_history_downsample_after", deserialize_with = "deserialize_duration")]
+    pub downsample_after: Duration,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_history_enabled(),
+            retention: default_history_retention(),
+            downsample_after: default_history_downsample_after(),
+        }
+    }
+}
+
+fn default_history_enabled() -> bool {
+    true
+}
+
+fn default_history_retention() -> Duration {
+    Duration::from_secs(7 * 24 * 60 * 60)
+}
+
+fn default_history_downsample_after() -> Duration {
+    Duration::from_secs(24 * 60 * 60)
+}
+
+// Optional [alerts] section: rules checked against every edge update. An alert is logged and,
+// with a webhook_url, posted there as JSON.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct AlertsConfig {
+    // A rule fires at most once per edge within this; 15m by default
+    #[serde(default = "default_alert_cooldown", deserialize_with = "deserialize_duration")]
+    pub cooldown: Duration,
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+    #[serde(default)]
+    pub rules: Vec<AlertRule>,
+}
+
+impl Default for AlertsConfig {
+    fn default() -> Self {
+        Self { cooldown: default_alert_cooldown(), webhook_url: None, rules: Vec::new() }
+    }
+}
+
+fn default_alert_cooldown() -> Duration {
+    Duration::from_secs(15 * 60)
+}
+
+// One [[alerts.rules]] entry. Unset bridge and chains match any edge; `bridge` matches either
+// side of an aggregated route, so "lifi" and "stargate" both match lifi's routes via stargate.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct AlertRule {
+    pub name: String,
+    #[serde(default)]
+    pub bridge: Option<String>,
+    #[serde(default)]
+    pub source_chain: Option<String>,
+    #[serde(default)]
+    pub destination_chain: Option<String>,
+    #[serde(flatten)]
+    pub condition: AlertCondition,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AlertCondition {
+    // Cost rose more than `pct` percent over the lowest cost seen within `within`
+    CostIncrease {
+        pct: f64,
+        #[serde(deserialize_with = "deserialize_duration")]
+        within: Duration,
+    },
+    LiquidityBelow { threshold: f64 },
+    // The edge was switched off: its bridge stopped serving the pair or its quote expired
+    EdgeDeactivated,
+}
+
+// Optional [digest] section: a summary of each refresh, logged and, with a webhook_url, posted
+// there as JSON. Off by default.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct DigestConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+    // Sent for the first refresh and every this many after it; 1 by default, every refresh
+    #[serde(default = "default_digest_every")]
+    pub every: u32,
+    // When set, sent instead for every refresh with more failed quotes than this
+    #[serde(default)]
+    pub failures_above: Option<usize>,
+    // Digests waiting to be delivered; past this they're dropped rather than held. 16 by default
+    #[serde(default = "default_digest_queue_capacity")]
+    pub queue_capacity: usize,
+}
+
+impl Default for DigestConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            webhook_url: None,
+            every: default_digest_every(),
+            failures_above: None,
+            queue_capacity: default_digest_queue_capacity(),
+        }
+    }
+}
+
+fn default_digest_every() -> u32 {
+    1
+}
+
+fn default_digest_queue_capacity() -> usize {
+    16
+}
+
+// Optional [gas] section: source-chain gas folded into edge costs, for the chains listed here
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct GasConfig {
+    // How long a fetched gas price is reused; 1m by default
+    #[serde(default = "default_gas_cache_ttl", deserialize_with = "deserialize_duration")]
+    pub cache_ttl: Duration,
+    #[serde(default)]
+    pub chains: HashMap<String, GasChainConfig>,
+    // USD prices of the tokens quotes are in, by symbol, for converting gas into them. A chain's
+    // native token and its wrapped form are priced at the chain's native_usd; USDC, USDT and
+    // DAI at 1 by default.
+    #[serde(default = "default_gas_token_usd")]
+    pub token_usd: HashMap<String, f64>,
+}
+
+impl Default for GasConfig {
+    fn default() -> Self {
+        Self { cache_ttl: default_gas_cache_ttl(), chains: HashMap::new(), token_usd: default_gas_token_usd() }
+    }
+}
+
+fn default_gas_cache_ttl() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_gas_token_usd() -> HashMap<String, f64> {
+    ["USDC", "USDT", "DAI"].into_iter().map(|symbol| (symbol.to_string(), 1.0)).collect()
+}
+
+// Optional [fx] section: the quote currency edge costs are normalized into, and where the prices
+// of the currencies their fees come in are found. Without rates or a price_url costs are kept in
+// the units adapters quote them in.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct FxConfig {
+    // "USD" by default
+    #[serde(default = "default_quote_currency")]
+    pub quote_currency: String,
+    #[serde(default)]
+    pub source: FxSource,
+    // For the static source: what one unit of each symbol is worth in the quote currency
+    #[serde(default)]
+    pub rates: HashMap<String, f64>,
+    // For the http source: a GET with `symbol` and `quote` query parameters answering {"price": ...}
+    #[serde(default)]
+    pub price_url: Option<String>,
+    // How long a fetched price is reused; 5m by default
+    #[serde(default = "default_fx_cache_ttl", deserialize_with = "deserialize_duration")]
+    pub cache_ttl: Duration,
+}
+
+impl Default for FxConfig {
+    fn default() -> Self {
+        Self {
+            quote_currency: default_quote_currency(),
+            source: FxSource::default(),
+            rates: HashMap::new(),
+            price_url: None,
+            cache_ttl: default_fx_cache_ttl(),
+        }
+    }
+}
+
+impl FxConfig {
+    // Whether costs are normalized at all
+    pub fn enabled(&self) -> bool {
+        match self.source {
+            FxSource::Static => !self.rates.is_empty(),
+            FxSource::Http => self.price_url.is_some(),
+        }
+    }
+}
+
+fn default_quote_currency() -> String {
+    "USD".to_string()
+}
+
+fn default_fx_cache_ttl() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FxSource {
+    // The [fx.rates] table
+    #[default]
+    Static,
+    // The price_url endpoint
+    Http,
+}
+
+// One [gas.chains.<chain>] entry. The price comes from rpc_url (eth_gasPrice) or oracle_url (a
+// GET returning {"gas_price_wei": ...}); default_gas_price_gwei is used while neither answers.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct GasChainConfig {
+    #[serde(default)]
+    pub rpc_url: Option<String>,
+    #[serde(default)]
+    pub oracle_url: Option<String>,
+    // USD price of the chain's native token
+    pub native_usd: f64,
+    #[serde(default)]
+    pub default_gas_price_gwei: Option<f64>,
+}
+
+// Optional [finality] section: overrides of FinalityModel's built-in table
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct FinalityConfig {
+    // Assumed for either end of a transfer on a chain neither the table nor `chains` has; 15m
+    // by default
+    #[serde(default = "default_unknown_chain_finality", deserialize_with = "deserialize_duration")]
+    pub unknown_chain: Duration,
+    // By chain key or alias
+    #[serde(default)]
+    pub chains: HashMap<String, ChainFinality>,
+}
+
+impl Default for FinalityConfig {
+    fn default() -> Self {
+        Self { unknown_chain: default_unknown_chain_finality(), chains: HashMap::new() }
+    }
+}
+
+fn default_unknown_chain_finality() -> Duration {
+    Duration::from_secs(15 * 60)
+}
+
+// One [finality.chains.<chain>] entry
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ChainFinality {
+    // e.g. "12s" or "250ms"
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub block_time: Duration,
+    // Blocks to wait for before a transfer on the chain is taken as final
+    pub confirmations: u32,
+}
+
+impl ChainFinality {
+    pub fn confirmation_time(&self) -> Duration {
+        self.block_time * self.confirmations
+    }
+}
+
+// Optional [slippage] section: the price impact routers apply to intent amounts on each hop
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct SlippageConfig {
+    #[serde(default)]
+    pub model: SlippageKind,
+    // Slippage at full utilization under the linear model; 0.1 by default
+    #[serde(default = "default_impact_per_utilization")]
+    pub impact_per_utilization: f64,
+    // Share of a hop's liquidity one transfer may use; 0.8 by default
+    #[serde(default = "default_max_utilization")]
+    pub max_utilization: f64,
+}
+
+impl Default for SlippageConfig {
+    fn default() -> Self {
+        Self {
+            model: SlippageKind::default(),
+            impact_per_utilization: default_impact_per_utilization(),
+            max_utilization: default_max_utilization(),
+        }
+    }
+}
+
+fn default_impact_per_utilization() -> f64 {
+    0.1
+}
+
+fn default_max_utilization() -> f64 {
+    0.8
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SlippageKind {
+    #[default]
+    Linear,
+    Sqrt,
+}
+
+// Optional [confidence] section: how routers rate how far each route's quotes can be trusted,
+// see ConfidenceModel
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ConfidenceConfig {
+    #[serde(default)]
+    pub combinator: ConfidenceCombinatorKind,
+    // How long a quote that doesn't say when it expires stays fully valid for; when unset,
+    // until its bridge's pairs are all quoted again, at the longest of their refresh cadences
+    #[serde(default, deserialize_with = "deserialize_optional_duration")]
+    pub validity: Option<Duration>,
+    // `validity` of single bridges, by bridge name, e.g. `wormhole = "10m"`
+    #[serde(default, deserialize_with = "deserialize_duration_map")]
+    pub bridges: BTreeMap<String, Duration>,
+}
+
+// How a path's confidence follows from its hops'
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfidenceCombinatorKind {
+    // The least confident hop's
+    #[default]
+    Min,
+    Product,
+    Mean,
+}
+
+// Optional [sanity] section: bounds a quote has to stay within to reach the graph, see
+// QuoteSanityChecker. [sanity.bridges.<bridge>] overrides any of them for one bridge. Every
+// bound is off unless set.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SanityConfig {
+    #[serde(flatten)]
+    pub defaults: SanityBounds,
+    // By bridge name
+    #[serde(default)]
+    pub bridges: HashMap<String, SanityBounds>,
+}
+
+impl SanityConfig {
+    // The bounds for `bridge`: its own where it sets them, the section's otherwise
+    pub fn bounds(&self, bridge: &str) -> SanityBounds {
+        let defaults = self.defaults;
+        let Some(own) = self.bridges.get(bridge) else {
+            return defaults;
+        };
+        SanityBounds {
+            max_fee_fraction: own.max_fee_fraction.or(defaults.max_fee_fraction),
+            min_duration: own.min_duration.or(defaults.min_duration),
+            max_duration: own.max_duration.or(defaults.max_duration),
+            max_output_ratio: own.max_output_ratio.or(defaults.max_output_ratio),
+            max_liquidity: own.max_liquidity.or(defaults.max_liquidity),
+            fee_reference_amount: own.fee_reference_amount.or(defaults.fee_reference_amount),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.defaults == SanityBounds::default() && self.bridges.values().all(|bounds| *bounds == SanityBounds::default())
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct SanityBounds {
+    // Fees as a share of the amount quoted, e.g. 0.05 for 5%
+    #[serde(default)]
+    pub max_fee_fraction: Option<f64>,
+    // Transfer time the bridge reports, before finality
+    #[serde(default, deserialize_with = "deserialize_optional_duration")]
+    pub min_duration: Option<Duration>,
+    #[serde(default, deserialize_with = "deserialize_optional_duration")]
+    pub max_duration: Option<Duration>,
+    // Estimated output over the amount quoted; 1.0 catches a bridge promising more than it's
+    // given. Leave it unset for bridges that swap on the way.
+    #[serde(default)]
+    pub max_output_ratio: Option<f64>,
+    // Liquidity above this is taken as a unit mixup rather than a deep pool
+    #[serde(default)]
+    pub max_liquidity: Option<f64>,
+    // Smallest amount, in human units of the source token, max_fee_fraction is judged at; 1000
+    // when unset. Pairs are quoted for one token, of which a bridge's fixed fee can be most.
+    #[serde(default)]
+    pub fee_reference_amount: Option<f64>,
+}
+
+// Optional [audit] section: a record of every route query answered, in the persistence store.
+// Off by default.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct AuditConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    // A segment takes entries until the next one would grow it past this; 1 MiB by default
+    #[serde(default = "default_audit_segment_bytes")]
+    pub segment_bytes: usize,
+    // Entries waiting to be written; past this they're dropped rather than slowing queries
+    // down. 1024 by default
+    #[serde(default = "default_audit_queue_capacity")]
+    pub queue_capacity: usize,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            segment_bytes: default_audit_segment_bytes(),
+            queue_capacity: default_audit_queue_capacity(),
+        }
+    }
+}
+
+fn default_audit_segment_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_audit_queue_capacity() -> usize {
+    1024
+}
+
+// Optional [discovery] section: which of the pairs advertised by bridges set to auto_discover
+// get quoted, and how often the advertisements are looked at again
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct DiscoveryConfig {
+    // Both ends on one of these chains; any chain when empty
+    #[serde(default)]
+    pub chains: Vec<String>,
+    // Both tokens among these, as symbols or addresses; any token when empty
+    #[serde(default)]
+    pub tokens: Vec<String>,
+    // Same format as global.update_interval; 1h by default
+    #[serde(default = "default_discovery_interval", deserialize_with = "deserialize_duration")]
+    pub interval: Duration,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            chains: Vec::new(),
+            tokens: Vec::new(),
+            interval: default_discovery_interval(),
+        }
+    }
+}
+
+fn default_discovery_interval() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+
+// Optional [server] section for the HTTP API
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ServerConfig {
+    // How long a route response is kept for replays of its Idempotency-Key and for
+    // GET /v1/routes/{request_id}. Same format as global.update_interval; 24h by default
+    #[serde(default = "default_idempotency_ttl", deserialize_with = "deserialize_duration")]
+    pub idempotency_ttl: Duration,
+    // How many of those responses are kept at most; the oldest go first. 10000 by default
+    #[serde(default = "default_idempotency_max_responses")]
+    pub idempotency_max_responses: usize,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self { idempotency_ttl: default_idempotency_ttl(), idempotency_max_responses: default_idempotency_max_responses() }
+    }
+}
+
+fn default_idempotency_ttl() -> Duration {
+    Duration::from_secs(24 * 60 * 60)
+}
+
+fn default_idempotency_max_responses() -> usize {
+    10_000
+}
+
+// One [api_keys.<tenant>] entry: a partner's key to the HTTP API and what it may do with it.
+// With none here or in the persistence store the API is open. Debug redacts the key.
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct ApiKeyConfig {
+    // Sent in the x-api-key header; best read through `${secret:VAR}`. Empty on stored keys,
+    // which keep only its hash.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub key: String,
+    // Unlimited when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requests_per_minute: Option<u32>,
+    // Most routes one query gets back, whatever it asks for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_results: Option<usize>,
+    // Graphs it may query; all of them when empty
+    #[serde(default)]
+    pub graphs: Vec<String>,
+    // Endpoints beyond route queries it may use
+    #[serde(default)]
+    pub features: Vec<ApiFeature>,
+}
+
+impl ApiKeyConfig {
+    pub fn new(key: &str) -> Self {
+        Self { key: key.to_string(), requests_per_minute: None, max_results: None, graphs: Vec::new(), features: Vec::new() }
+    }
+
+    pub fn has_feature(&self, feature: ApiFeature) -> bool {
+        self.features.contains(&feature)
+    }
+
+    pub fn allows_graph(&self, graph: &str) -> bool {
+        self.graphs.is_empty() || self.graphs.iter().any(|allowed| allowed == graph)
+    }
+
+    // The field at fault and why. Tenant names end up in persistence keys and metric labels, so
+    // they're kept to letters, digits, `-` and `_`.
+    pub fn validate(&self, tenant: &str) -> Result<(), (&'static str, String)> {
+        if tenant.is_empty() || tenant.len() > 64 || !tenant.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(("tenant", format!("names are 1 to 64 letters, digits, `-` and `_`, got `{}`", tenant)));
+        }
+        if self.key.trim().len() < 16 {
+            return Err(("key", "must be at least 16 characters".to_string()));
+        }
+        if self.requests_per_minute == Some(0) {
+            return Err(("requests_per_minute", "must be at least 1".to_string()));
+        }
+        if self.max_results == Some(0) {
+            return Err(("max_results", "must be at least 1".to_string()));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for ApiKeyConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApiKeyConfig")
+            .field("key", &REDACTED)
+            .field("requests_per_minute", &self.requests_per_minute)
+            .field("max_results", &self.max_results)
+            .field("graphs", &self.graphs)
+            .field("features", &self.features)
+            .finish()
+    }
+}
+
+// Endpoints an API key has to be granted explicitly
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiFeature {
+    // POST /v1/routes/watch
+    Watch,
+    // The /v1/profiles endpoints and queries naming a stored profile
+    Profiles,
+    // Searches at interactive priority, which a full executor queue still takes
+    Interactive,
+}
+
+impl ApiFeature {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiFeature::Watch => "watch",
+            ApiFeature::Profiles => "profiles",
+            ApiFeature::Interactive => "interactive",
+        }
+    }
+}
+
+impl fmt::Display for ApiFeature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// Optional [executor] section: how many route searches run at once and how many wait for a turn
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExecutorConfig {
+    // Searches running at once; the number of CPUs by default
+    #[serde(default = "default_executor_workers")]
+    pub workers: usize,
+    // Searches waiting beyond which only interactive ones are still queued; 64 by default
+    #[serde(default = "default_executor_queue_depth")]
+    pub queue_depth: usize,
+}
+
+impl Default for ExecutorConfig {
+    fn default() -> Self {
+        Self {
+            workers: default_executor_workers(),
+            queue_depth: default_executor_queue_depth(),
+        }
+    }
+}
+
+fn default_executor_workers() -> usize {
+    std::thread::available_parallelism().map(usize::from).unwrap_or(4)
+}
+
+fn default_executor_queue_depth() -> usize {
+    64
+}
+
+// One [graphs.<name>] section: a graph served alongside the others, refreshed on its own from
+// the bridges and pairs it selects. Empty lists select everything.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct GraphConfig {
+    #[serde(default)]
+    pub bridges: Vec<String>,
+    #[serde(default)]
+    pub pairs_filter: PairsFilter,
+    // global.update_interval when unset
+    #[serde(default, deserialize_with = "deserialize_optional_duration")]
+    pub update_interval: Option<Duration>,
+    // Route options for queries that don't give any; RouteOptions' defaults when unset
+    #[serde(default)]
+    pub max_hops: Option<usize>,
+    #[serde(default)]
+    pub max_results: Option<usize>,
+}
+
+// The pairs a graph takes: both ends on one of `chains` and both tokens among `tokens`, given
+// as symbols or addresses
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct PairsFilter {
+    #[serde(default)]
+    pub chains: Vec<String>,
+    #[serde(default)]
+    pub tokens: Vec<String>,
+}
+
+// Optional [registry] section: chains and tokens on top of the built-in ones. Chains with a
+// built-in key replace it; tokens may name their chain by alias.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RegistryConfig {
+    #[serde(default)]
+    pub chains: Vec<ChainRef>,
+    #[serde(default)]
+    pub tokens: Vec<TokenRef>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 pub struct Pair {
     pub source_chain: String,
     pub destination_chain: String,
@@ -22,27 +933,1601 @@ pub struct Pair {
     pub source_address: String,
     pub destination_address: String,
     pub destination_token_name: String,
+    // How often the pair is quoted; its priority's multiple of the update interval when unset
+    #[serde(default, deserialize_with = "deserialize_optional_duration")]
+    pub refresh_interval: Option<Duration>,
+    #[serde(default)]
+    pub priority: RefreshPriority,
+}
+
+// How often a pair is quoted, as a multiple of the update interval set in [refresh]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum RefreshPriority {
+    Hot,
+    #[default]
+    Normal,
+    Cold,
+}
+
+impl RefreshPriority {
+    pub const ALL: [RefreshPriority; 3] = [RefreshPriority::Hot, RefreshPriority::Normal, RefreshPriority::Cold];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RefreshPriority::Hot => "hot",
+            RefreshPriority::Normal => "normal",
+            RefreshPriority::Cold => "cold",
+        }
+    }
+}
+
+// Optional [refresh] section: how many update intervals apart the pairs of each priority are quoted
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RefreshConfig {
+    // 1 by default, every update
+    #[serde(default = "default_hot_multiplier")]
+    pub hot: u32,
+    // 3 by default
+    #[serde(default = "default_normal_multiplier")]
+    pub normal: u32,
+    // 10 by default
+    #[serde(default = "default_cold_multiplier")]
+    pub cold: u32,
+}
+
+impl RefreshConfig {
+    pub fn multiplier(&self, priority: RefreshPriority) -> u32 {
+        match priority {
+            RefreshPriority::Hot => self.hot,
+            RefreshPriority::Normal => self.normal,
+            RefreshPriority::Cold => self.cold,
+        }
+    }
+}
+
+impl Default for RefreshConfig {
+    fn default() -> Self {
+        Self {
+            hot: default_hot_multiplier(),
+            normal: default_normal_multiplier(),
+            cold: default_cold_multiplier(),
+        }
+    }
+}
+
+fn default_hot_multiplier() -> u32 {
+    1
+}
+
+fn default_normal_multiplier() -> u32 {
+    3
+}
+
+fn default_cold_multiplier() -> u32 {
+    10
 }
 
-#[derive(Deserialize, Debug, Clone)]
+// A bridge's [bridges.<name>.source_policy]: the adapters that can quote its routes, e.g. its own
+// API and an aggregator routing through it. Each pair is quoted by the first of them that gives a
+// quote, trying them by weight, highest first.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct SourcePolicy {
+    pub sources: Vec<WeightedSource>,
+}
+
+impl SourcePolicy {
+    // Adapter names in the order they're tried; equal weights keep their listed order
+    pub fn ordered(&self) -> Vec<&str> {
+        let mut sources: Vec<&WeightedSource> = self.sources.iter().collect();
+        sources.sort_by_key(|source| std::cmp::Reverse(source.weight));
+        sources.into_iter().map(|source| source.adapter.as_str()).collect()
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct WeightedSource {
+    // A configured bridge whose adapter quotes on this one's behalf
+    pub adapter: String,
+    // 1 by default
+    #[serde(default = "default_source_weight")]
+    pub weight: u32,
+}
+
+fn default_source_weight() -> u32 {
+    1
+}
+
+// Optional [edge_identity] section: which edges quoted by different adapters are the same route,
+// e.g. LiFi routing through stargate and stargate's own API. Such quotes all update one edge,
+// labelled with the canonical bridge.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct EdgeIdentityConfig {
+    #[serde(default)]
+    pub canonical: Vec<CanonicalEdge>,
+}
+
+impl EdgeIdentityConfig {
+    // The bridge `adapter`'s quotes routed through `via` are labelled with, None when unmapped
+    pub fn canonical(&self, adapter: &str, via: &str) -> Option<&str> {
+        self.canonical
+            .iter()
+            .find(|entry| entry.adapter == adapter && entry.via.eq_ignore_ascii_case(via))
+            .map(|entry| entry.bridge.as_str())
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct CanonicalEdge {
+    // A configured bridge whose quotes are mapped
+    pub adapter: String,
+    // The bridge its quotes are attributed to, e.g. LiFi's underlying tool; the adapter itself
+    // for its direct quotes
+    pub via: String,
+    // What their edges are labelled with
+    pub bridge: String,
+}
+
+// Debug redacts `extra` values that hold secrets
+#[derive(Deserialize, Clone, PartialEq)]
 pub struct BridgeConfig {
     pub base_url: String,
     pub chains: Vec<String>,
     pub pairs: Option<Vec<Pair>>,
-    pub extra: Option<HashMap<String, toml::Value>>
+    pub extra: Option<HashMap<String, toml::Value>>,
+    #[serde(default)]
+    pub source_policy: Option<SourcePolicy>,
+    // Also quote the pairs the adapter advertises that the [discovery] allow-list admits, on
+    // top of `pairs`
+    #[serde(default)]
+    pub auto_discover: bool,
+    // Dotted paths under `extra` that are secrets without their key matching
+    // DEFAULT_SECRET_PATTERNS: keys matching global.secret_patterns and values read through
+    // `${secret:VAR}`. Filled in when the config is loaded.
+    #[serde(skip)]
+    secret_keys: BTreeSet<String>,
+}
+
+impl BridgeConfig {
+    // Whether the value at `path` under `extra`, e.g. "headers.x-api-key", is a secret
+    pub fn is_secret(&self, path: &str) -> bool {
+        let key = path.rsplit('.').next().unwrap_or(path);
+        is_secret_key(key) || self.secret_keys.contains(path)
+    }
+
+    fn redact(&self, value: &toml::Value, path: &str) -> toml::Value {
+        match value {
+            _ if self.is_secret(path) => toml::Value::String(REDACTED.to_string()),
+            toml::Value::Table(table) => toml::Value::Table(
+                table.iter().map(|(key, value)| (key.clone(), self.redact(value, &format!("{}.{}", path, key)))).collect(),
+            ),
+            toml::Value::Array(items) => toml::Value::Array(
+                items.iter().enumerate().map(|(index, item)| self.redact(item, &format!("{}[{}]", path, index))).collect(),
+            ),
+            value => value.clone(),
+        }
+    }
+}
+
+impl fmt::Debug for BridgeConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let extra = self.extra.as_ref().map(|extra| {
+            extra
+                .iter()
+                .map(|(key, value)| (key.clone(), self.redact(value, key)))
+                .collect::<HashMap<_, _>>()
+        });
+        f.debug_struct("BridgeConfig")
+            .field("base_url", &self.base_url)
+            .field("chains", &self.chains)
+            .field("pairs", &self.pairs)
+            .field("extra", &extra)
+            .field("source_policy", &self.source_policy)
+            .field("auto_discover", &self.auto_discover)
+            .finish()
+    }
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 pub struct ConfigManager {
+    #[serde(default)]
     pub global: GlobalConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    #[serde(default)]
+    pub registry: RegistryConfig,
+    #[serde(default)]
+    pub history: HistoryConfig,
+    #[serde(default)]
+    pub alerts: AlertsConfig,
+    #[serde(default)]
+    pub digest: DigestConfig,
+    #[serde(default)]
+    pub gas: GasConfig,
+    #[serde(default)]
+    pub fx: FxConfig,
+    #[serde(default)]
+    pub finality: FinalityConfig,
+    #[serde(default)]
+    pub slippage: SlippageConfig,
+    #[serde(default)]
+    pub sanity: SanityConfig,
+    #[serde(default)]
+    pub confidence: ConfidenceConfig,
+    #[serde(default)]
+    pub audit: AuditConfig,
+    #[serde(default)]
+    pub discovery: DiscoveryConfig,
+    #[serde(default)]
+    pub edge_identity: EdgeIdentityConfig,
+    #[serde(default)]
+    pub server: ServerConfig,
+    // API keys by tenant name
+    #[serde(default)]
+    pub api_keys: HashMap<String, ApiKeyConfig>,
+    #[serde(default)]
+    pub executor: ExecutorConfig,
+    #[serde(default)]
+    pub refresh: RefreshConfig,
+    // Named graphs to serve; one "default" graph over every bridge and pair without any
+    #[serde(default)]
+    pub graphs: HashMap<String, GraphConfig>,
     pub bridges: HashMap<String, BridgeConfig>
 }
 
 impl ConfigManager {
-    pub fn new(config_path: &str) -> Self {
-        let path = config_path; 
-        let s = fs::read_to_string(path).unwrap();
-        let cfg = toml::from_str::<ConfigManager>(&s).unwrap();
-        cfg
+    // Format follows the extension: .toml, .yaml/.yml or .json
+    pub fn load(config_path: &str) -> Result<Self, ConfigError> {
+        let path = PathBuf::from(config_path);
+        let format = ConfigFormat::from_path(&path)?;
+        let source = fs::read_to_string(&path)
+            .map_err(|source| ConfigError::Read { path: path.clone(), source })?;
+        Self::parse(&source, format, &path)
+    }
+
+    // Config embedded as a string, with the same interpolation, overrides and validation as `load`
+    pub fn from_str(contents: &str, format: ConfigFormat) -> Result<Self, ConfigError> {
+        Self::parse(contents, format, Path::new("<string>"))
+    }
+
+    fn parse(source: &str, format: ConfigFormat, path: &Path) -> Result<Self, ConfigError> {
+        let mut document = match format {
+            ConfigFormat::Toml => toml::from_str::<toml::Table>(source).map_err(|err| toml_parse_error(path, source, &err)),
+            ConfigFormat::Yaml => serde_yaml::from_str::<toml::Table>(source).map_err(|err| yaml_parse_error(path, &err)),
+            ConfigFormat::Json => serde_json::from_str::<toml::Table>(source).map_err(|err| json_parse_error(path, &err)),
+        }?;
+        let mut secrets = Vec::new();
+        interpolate_env(&mut document, "", &mut secrets)
+            .map_err(|(key, message)| ConfigError::Invalid { path: path.to_path_buf(), key, message })?;
+        let overridden = apply_env_overrides(&mut document, std::env::vars());
+
+        let mut config = toml::Value::Table(document).try_into::<ConfigManager>().map_err(|err| {
+            // A file that's broken by itself can be pointed at precisely; otherwise an override broke it
+            let typed = match format {
+                ConfigFormat::Toml => toml::from_str::<ConfigManager>(source).err().map(|err| toml_parse_error(path, source, &err)),
+                ConfigFormat::Yaml => serde_yaml::from_str::<ConfigManager>(source).err().map(|err| yaml_parse_error(path, &err)),
+                ConfigFormat::Json => serde_json::from_str::<ConfigManager>(source).err().map(|err| json_parse_error(path, &err)),
+            };
+            typed.unwrap_or_else(|| ConfigError::Invalid {
+                path: path.to_path_buf(),
+                key: overridden.join(", "),
+                message: format!("set from the environment is invalid: {}", err.message().trim()),
+            })
+        })?;
+        config.logging.level = config.global.log_level.clone();
+        config.mark_secrets(&secrets);
+        config.validate()
+            .map_err(|(key, message)| ConfigError::Invalid { path: path.to_path_buf(), key, message })?;
+        Ok(config)
+    }
+
+    // Records which bridge `extra` values are secrets beyond the built-in patterns, given the
+    // document paths that were interpolated from `${secret:VAR}`
+    fn mark_secrets(&mut self, interpolated: &[String]) {
+        fn matching(value: &toml::Value, path: &str, patterns: &[String], found: &mut BTreeSet<String>) {
+            let key = path.rsplit('.').next().unwrap_or(path);
+            if patterns.iter().any(|pattern| matches_pattern(pattern, key)) {
+                found.insert(path.to_string());
+            }
+            if let toml::Value::Table(table) = value {
+                for (key, value) in table {
+                    matching(value, &format!("{}.{}", path, key), patterns, found);
+                }
+            }
+        }
+
+        let patterns = &self.global.secret_patterns;
+        for (name, bridge) in self.bridges.iter_mut() {
+            let mut found = BTreeSet::new();
+            for (key, value) in bridge.extra.iter().flatten() {
+                matching(value, key, patterns, &mut found);
+            }
+            let prefix = format!("bridges.{}.extra.", name);
+            found.extend(interpolated.iter().filter_map(|path| path.strip_prefix(&prefix)).map(str::to_string));
+            bridge.secret_keys = found;
+        }
+    }
+
+    // Values serde accepts but nothing downstream can use, as (key path, what was expected)
+    fn validate(&self) -> Result<(), (String, String)> {
+        // The cache works in whole seconds, so shorter intervals would mean no caching at all
+        for (key, value) in [
+            ("global.update_interval", self.global.update_interval),
+            ("global.cache_ttl", self.global.cache_ttl),
+            ("global.cache_flush_interval", self.global.cache_flush_interval),
+            ("discovery.interval", self.discovery.interval),
+            ("server.idempotency_ttl", self.server.idempotency_ttl),
+        ] {
+            if value < Duration::from_secs(1) {
+                return Err((key.to_string(), format!("must be at least 1s, got {:?}", value)));
+            }
+        }
+        if !LOG_LEVELS.contains(&self.global.log_level.to_lowercase().as_str()) {
+            return Err((
+                "global.log_level".to_string(),
+                format!("must be one of {}, got `{}`", LOG_LEVELS.join(", "), self.global.log_level),
+            ));
+        }
+
+        for (key, value) in [
+            ("global.quarantine_after", self.global.quarantine_after as usize),
+            ("global.update_queue_capacity", self.global.update_queue_capacity),
+            ("global.update_batch_size", self.global.update_batch_size),
+            ("executor.workers", self.executor.workers),
+            ("executor.queue_depth", self.executor.queue_depth),
+            ("refresh.hot", self.refresh.hot as usize),
+            ("refresh.normal", self.refresh.normal as usize),
+            ("refresh.cold", self.refresh.cold as usize),
+            ("digest.every", self.digest.every as usize),
+            ("digest.queue_capacity", self.digest.queue_capacity),
+            ("server.idempotency_max_responses", self.server.idempotency_max_responses),
+        ] {
+            if value == 0 {
+                return Err((key.to_string(), "must be at least 1".to_string()));
+            }
+        }
+        if !(0.0..=1.0).contains(&self.global.min_coverage) {
+            return Err(("global.min_coverage".to_string(), format!("must be between 0 and 1, got {}", self.global.min_coverage)));
+        }
+        if let Some(bonus) = self.global.route_stickiness
+            && !(bonus.is_finite() && bonus >= 0.0)
+        {
+            return Err(("global.route_stickiness".to_string(), format!("must be 0 or more, got {}", bonus)));
+        }
+        if !(0.0..=1.0).contains(&self.global.cache_ttl_jitter) {
+            return Err(("global.cache_ttl_jitter".to_string(), format!("must be between 0 and 1, got {}", self.global.cache_ttl_jitter)));
+        }
+        // Jitter can shorten an entry's ttl, and an entry living no longer than the soft ttl would
+        // never be refreshed early
+        let shortest_ttl = self.global.cache_ttl.mul_f64(1.0 - self.global.cache_ttl_jitter);
+        if let Some(soft_ttl) = self.global.cache_soft_ttl
+            && soft_ttl >= shortest_ttl
+        {
+            return Err((
+                "global.cache_soft_ttl".to_string(),
+                format!("must be shorter than global.cache_ttl less its jitter ({:?}), got {:?}", shortest_ttl, soft_ttl),
+            ));
+        }
+
+        if let Some(backend @ (PersistenceBackend::Files | PersistenceBackend::Sqlite)) = self.global.persistence_backend
+            && self.global.persistence_path.is_none()
+        {
+            return Err((
+                "global.persistence_path".to_string(),
+                format!("must be set for the `{}` persistence backend", backend),
+            ));
+        }
+
+        if let Some(filter) = &self.logging.filter
+            && let Err(err) = tracing_subscriber::EnvFilter::try_new(filter)
+        {
+            return Err(("logging.filter".to_string(), format!("is not a valid filter: {}", err)));
+        }
+
+        if self.history.downsample_after < Duration::from_secs(60 * 60) {
+            return Err((
+                "history.downsample_after".to_string(),
+                format!("must be at least 1h, got {:?}", self.history.downsample_after),
+            ));
+        }
+        if self.history.retention < self.history.downsample_after {
+            return Err((
+                "history.retention".to_string(),
+                format!("must be at least history.downsample_after ({:?}), got {:?}", self.history.downsample_after, self.history.retention),
+            ));
+        }
+
+        if let Some(url) = &self.alerts.webhook_url
+            && !(url.starts_with("http://") || url.starts_with("https://"))
+        {
+            return Err(("alerts.webhook_url".to_string(), format!("must be an http(s) URL, got `{}`", url)));
+        }
+        if let Some(url) = &self.digest.webhook_url
+            && !(url.starts_with("http://") || url.starts_with("https://"))
+        {
+            return Err(("digest.webhook_url".to_string(), format!("must be an http(s) URL, got `{}`", url)));
+        }
+        let mut rule_names = BTreeSet::new();
+        for (index, rule) in self.alerts.rules.iter().enumerate() {
+            let key = format!("alerts.rules[{}]", index);
+            if !rule_names.insert(&rule.name) {
+                return Err((key, format!("reuses the rule name `{}`", rule.name)));
+            }
+            match rule.condition {
+                AlertCondition::CostIncrease { pct, within } if pct.is_nan() || pct <= 0.0 || within < Duration::from_secs(1) => {
+                    return Err((key, format!("needs a pct above 0 and a window of at least 1s, got {} within {:?}", pct, within)));
+                }
+                AlertCondition::LiquidityBelow { threshold } if threshold.is_nan() || threshold <= 0.0 => {
+                    return Err((key, format!("needs a threshold above 0, got {}", threshold)));
+                }
+                _ => {}
+            }
+        }
+
+        let mut gas_chains: Vec<&String> = self.gas.chains.keys().collect();
+        gas_chains.sort();
+        for chain in gas_chains {
+            let gas = &self.gas.chains[chain];
+            let key = |field: &str| format!("gas.chains.{}.{}", chain, field);
+            match (&gas.rpc_url, &gas.oracle_url) {
+                (None, None) if gas.default_gas_price_gwei.is_none() => {
+                    return Err((key("rpc_url"), "must be set, or oracle_url or default_gas_price_gwei".to_string()));
+                }
+                (Some(_), Some(_)) => return Err((key("oracle_url"), "can't be set together with rpc_url".to_string())),
+                _ => {}
+            }
+            for (field, url) in [("rpc_url", &gas.rpc_url), ("oracle_url", &gas.oracle_url)] {
+                if let Some(url) = url
+                    && !(url.starts_with("http://") || url.starts_with("https://"))
+                {
+                    return Err((key(field), format!("must be an http(s) URL, got `{}`", url)));
+                }
+            }
+            if gas.native_usd.is_nan() || gas.native_usd <= 0.0 {
+                return Err((key("native_usd"), format!("must be above 0, got {}", gas.native_usd)));
+            }
+            if let Some(price) = gas.default_gas_price_gwei
+                && (price.is_nan() || price < 0.0)
+            {
+                return Err((key("default_gas_price_gwei"), format!("must not be negative, got {}", price)));
+            }
+        }
+        for (symbol, price) in &self.gas.token_usd {
+            if price.is_nan() || *price <= 0.0 {
+                return Err((format!("gas.token_usd.{}", symbol), format!("must be above 0, got {}", price)));
+            }
+        }
+
+        let fx = &self.fx;
+        if fx.quote_currency.trim().is_empty() {
+            return Err(("fx.quote_currency".to_string(), "must not be empty".to_string()));
+        }
+        let mut fx_symbols: Vec<&String> = fx.rates.keys().collect();
+        fx_symbols.sort();
+        for symbol in fx_symbols {
+            let rate = fx.rates[symbol];
+            if rate.is_nan() || rate <= 0.0 {
+                return Err((format!("fx.rates.{}", symbol), format!("must be above 0, got {}", rate)));
+            }
+        }
+        match (&fx.source, &fx.price_url) {
+            (FxSource::Http, None) => return Err(("fx.price_url".to_string(), "must be set for the http source".to_string())),
+            (_, Some(url)) if !(url.starts_with("http://") || url.starts_with("https://")) => {
+                return Err(("fx.price_url".to_string(), format!("must be an http(s) URL, got `{}`", url)));
+            }
+            _ => {}
+        }
+
+        if self.finality.unknown_chain.is_zero() {
+            return Err(("finality.unknown_chain".to_string(), "must be above 0".to_string()));
+        }
+        let mut finality_chains: Vec<&String> = self.finality.chains.keys().collect();
+        finality_chains.sort();
+        for chain in finality_chains {
+            let finality = &self.finality.chains[chain];
+            if finality.block_time.is_zero() {
+                return Err((format!("finality.chains.{}.block_time", chain), "must be above 0".to_string()));
+            }
+            if finality.confirmations == 0 {
+                return Err((format!("finality.chains.{}.confirmations", chain), "must be at least 1".to_string()));
+            }
+        }
+
+        let slippage = &self.slippage;
+        if !slippage.impact_per_utilization.is_finite() || slippage.impact_per_utilization < 0.0 {
+            return Err(("slippage.impact_per_utilization".to_string(), format!("must be 0 or above, got {}", slippage.impact_per_utilization)));
+        }
+        if !(slippage.max_utilization > 0.0 && slippage.max_utilization <= 1.0) {
+            return Err(("slippage.max_utilization".to_string(), format!("must be above 0 and at most 1, got {}", slippage.max_utilization)));
+        }
+
+        let mut sanity_bridges: Vec<&String> = self.sanity.bridges.keys().collect();
+        sanity_bridges.sort();
+        let sanity_sections = std::iter::once(("sanity".to_string(), self.sanity.defaults))
+            .chain(sanity_bridges.into_iter().map(|bridge| (format!("sanity.bridges.{}", bridge), self.sanity.bounds(bridge))));
+        for (section, bounds) in sanity_sections {
+            for (field, value) in [
+                ("max_fee_fraction", bounds.max_fee_fraction),
+                ("max_output_ratio", bounds.max_output_ratio),
+                ("max_liquidity", bounds.max_liquidity),
+                ("fee_reference_amount", bounds.fee_reference_amount),
+            ] {
+                if let Some(value) = value
+                    && !(value.is_finite() && value > 0.0)
+                {
+                    return Err((format!("{}.{}", section, field), format!("must be above 0, got {}", value)));
+                }
+            }
+            if let (Some(min), Some(max)) = (bounds.min_duration, bounds.max_duration)
+                && min > max
+            {
+                return Err((format!("{}.min_duration", section), format!("must be at most max_duration ({:?}), got {:?}", max, min)));
+            }
+        }
+
+        if self.audit.segment_bytes < 1024 {
+            return Err(("audit.segment_bytes".to_string(), format!("must be at least 1024, got {}", self.audit.segment_bytes)));
+        }
+        if self.audit.queue_capacity == 0 {
+            return Err(("audit.queue_capacity".to_string(), "must be at least 1".to_string()));
+        }
+
+        let mut tenants: Vec<&String> = self.api_keys.keys().collect();
+        tenants.sort();
+        let mut keys = HashMap::new();
+        for tenant in tenants {
+            let api_key = &self.api_keys[tenant];
+            let key = |field: &str| format!("api_keys.{}.{}", tenant, field);
+            api_key.validate(tenant).map_err(|(field, message)| match field {
+                "tenant" => (format!("api_keys.{}", tenant), message),
+                field => (key(field), message),
+            })?;
+            if let Some(other) = keys.insert(api_key.key.as_str(), tenant) {
+                return Err((key("key"), format!("is the same as api_keys.{}.key", other)));
+            }
+            if let Some(graph) = api_key.graphs.iter().find(|graph| *graph != "default" && !self.graphs.contains_key(*graph)) {
+                return Err((key("graphs"), format!("must name configured graphs, got `{}`", graph)));
+            }
+        }
+
+        let mut graph_names: Vec<&String> = self.graphs.keys().collect();
+        graph_names.sort();
+        for name in graph_names {
+            let graph = &self.graphs[name];
+            let key = |field: &str| format!("graphs.{}.{}", name, field);
+            if let Some(bridge) = graph.bridges.iter().find(|bridge| !self.bridges.contains_key(*bridge)) {
+                return Err((key("bridges"), format!("must name configured bridges, got `{}`", bridge)));
+            }
+            if let Some(interval) = graph.update_interval
+                && interval < Duration::from_secs(1)
+            {
+                return Err((key("update_interval"), format!("must be at least 1s, got {:?}", interval)));
+            }
+            for (field, value) in [("max_hops", graph.max_hops), ("max_results", graph.max_results)] {
+                if value == Some(0) {
+                    return Err((key(field), "must be at least 1".to_string()));
+                }
+            }
+        }
+
+        let mut registry = Registry::builtin();
+        for (index, chain) in self.registry.chains.iter().enumerate() {
+            registry.chains.insert(chain.clone()).map_err(|err| (format!("registry.chains[{}]", index), err.to_string()))?;
+        }
+        for (index, token) in self.registry.tokens.iter().enumerate() {
+            registry.insert_token(token.clone()).map_err(|err| (format!("registry.tokens[{}]", index), err.to_string()))?;
+        }
+
+        let mut names: Vec<&String> = self.bridges.keys().collect();
+        names.sort();
+        for name in names {
+            let bridge = &self.bridges[name];
+            for (index, pair) in bridge.pairs.iter().flatten().enumerate() {
+                let key = |field: &str| format!("bridges.{}.pairs[{}].{}", name, index, field);
+                for (field, chain) in [("source_chain", &pair.source_chain), ("destination_chain", &pair.destination_chain)] {
+                    if !bridge.chains.contains(chain) {
+                        return Err((
+                            key(field),
+                            format!("must be one of the bridge's chains ({}), got `{}`", bridge.chains.join(", "), chain),
+                        ));
+                    }
+                }
+                for (field, address) in [("source_address", &pair.source_address), ("destination_address", &pair.destination_address)] {
+                    if !is_address(address) {
+                        return Err((key(field), format!("must be a 0x-prefixed address of 40 hex digits, got `{}`", address)));
+                    }
+                }
+                if let Some(interval) = pair.refresh_interval
+                    && interval < Duration::from_secs(1)
+                {
+                    return Err((key("refresh_interval"), format!("must be at least 1s, got {:?}", interval)));
+                }
+            }
+            if let Some(policy) = &bridge.source_policy {
+                let key = format!("bridges.{}.source_policy.sources", name);
+                if policy.sources.is_empty() {
+                    return Err((key, "must list at least one source".to_string()));
+                }
+                let mut seen = BTreeSet::new();
+                for (index, source) in policy.sources.iter().enumerate() {
+                    let key = format!("{}[{}]", key, index);
+                    if !self.bridges.contains_key(&source.adapter) {
+                        return Err((key, format!("must name a configured bridge, got `{}`", source.adapter)));
+                    }
+                    if !seen.insert(&source.adapter) {
+                        return Err((key, format!("lists `{}` twice", source.adapter)));
+                    }
+                    if source.weight == 0 {
+                        return Err((key, "needs a weight of at least 1".to_string()));
+                    }
+                }
+            }
+        }
+        let mut mapped = BTreeSet::new();
+        for (index, entry) in self.edge_identity.canonical.iter().enumerate() {
+            let key = format!("edge_identity.canonical[{}]", index);
+            if !self.bridges.contains_key(&entry.adapter) {
+                return Err((key, format!("must name a configured bridge, got `{}`", entry.adapter)));
+            }
+            if entry.bridge.trim().is_empty() || entry.via.trim().is_empty() {
+                return Err((key, "needs a via and a bridge".to_string()));
+            }
+            if !mapped.insert((&entry.adapter, entry.via.to_lowercase())) {
+                return Err((key, format!("maps `{}` via `{}` twice", entry.adapter, entry.via)));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Environment variables named POLYPATH__<SECTION>__<KEY>... override the value at that path,
+// e.g. POLYPATH__BRIDGES__STARGATE__BASE_URL. Bridges must already be in the file; values replace
+// strings as-is and are otherwise read as TOML literals (`30`, `true`, `["a"]`). Returns the
+// key paths that were overridden.
+fn apply_env_overrides(document: &mut toml::Table, vars: impl IntoIterator<Item = (String, String)>) -> Vec<String> {
+    let mut overridden = Vec::new();
+    for (name, raw) in vars {
+        let Some(path) = name.strip_prefix(ENV_OVERRIDE_PREFIX) else {
+            continue;
+        };
+        let segments: Vec<String> = path.split("__").map(str::to_lowercase).collect();
+        if segments.len() < 2 || segments.iter().any(String::is_empty) {
+            continue;
+        }
+        if segments[0] == "bridges" && !document.get("bridges").and_then(|b| b.get(&segments[1])).is_some_and(toml::Value::is_table) {
+            continue;
+        }
+
+        let Some((field, parents)) = segments.split_last() else {
+            continue;
+        };
+        let Some(table) = table_at(document, parents) else {
+            continue;
+        };
+
+        let value = match table.get(field) {
+            Some(toml::Value::String(_)) => toml::Value::String(raw),
+            _ => toml::from_str::<toml::Table>(&format!("value = {}", raw))
+                .ok()
+                .and_then(|mut parsed| parsed.remove("value"))
+                .unwrap_or(toml::Value::String(raw)),
+        };
+        table.insert(field.clone(), value);
+        overridden.push(segments.join("."));
+    }
+    overridden.sort();
+    overridden
+}
+
+// The table at `path`, creating missing ones. None when a value along the way isn't a table.
+fn table_at<'a>(table: &'a mut toml::Table, path: &[String]) -> Option<&'a mut toml::Table> {
+    match path.split_first() {
+        None => Some(table),
+        Some((first, rest)) => {
+            let next = table.entry(first.clone()).or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            table_at(next.as_table_mut()?, rest)
+        }
+    }
+}
+
+// Expands `${VAR}` and `${VAR:-default}` in every string of the document. Paths of strings
+// that reference `${secret:VAR}` are added to `secrets`.
+fn interpolate_env(table: &mut toml::Table, prefix: &str, secrets: &mut Vec<String>) -> Result<(), (String, String)> {
+    for (key, value) in table.iter_mut() {
+        let path = match prefix.is_empty() {
+            true => key.clone(),
+            false => format!("{}.{}", prefix, key),
+        };
+        interpolate_value(value, &path, secrets)?;
+    }
+    Ok(())
+}
+
+fn interpolate_value(value: &mut toml::Value, path: &str, secrets: &mut Vec<String>) -> Result<(), (String, String)> {
+    match value {
+        toml::Value::String(text) => {
+            if text.contains(SECRET_REFERENCE) {
+                secrets.push(path.to_string());
+            }
+            *text = expand_env(text).map_err(|message| (path.to_string(), message))?;
+        }
+        toml::Value::Array(items) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                interpolate_value(item, &format!("{}[{}]", path, index), secrets)?;
+            }
+        }
+        toml::Value::Table(table) => interpolate_env(table, path, secrets)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+// Replaces every `${VAR}` with the value of the environment variable VAR, or with `default` for
+// `${VAR:-default}` when VAR is unset. Unset variables without a default are an error.
+// `${secret:VAR}` expands like `${VAR}` and marks the value as a secret when loading config.
+pub fn expand_env(value: &str) -> Result<String, String> {
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(start) = rest.find("${") {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find('}')
+            .ok_or_else(|| format!("unterminated `${{` in `{}`", value))?;
+        let reference = &after[..end];
+        let reference = reference.strip_prefix("secret:").unwrap_or(reference);
+        let (name, default) = match reference.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (reference, None),
+        };
+        match (std::env::var(name), default) {
+            (Ok(var), _) => expanded.push_str(&var),
+            (Err(_), Some(default)) => expanded.push_str(default),
+            (Err(_), None) => return Err(format!("environment variable `{}` referenced in config is not set", name)),
+        }
+        rest = &after[end + 1..];
+    }
+
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+fn deserialize_duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Seconds(u64),
+        Text(String),
+    }
+
+    match Raw::deserialize(deserializer) {
+        Ok(Raw::Seconds(secs)) => Ok(Duration::from_secs(secs)),
+        Ok(Raw::Text(text)) => parse_duration(&text).map_err(serde::de::Error::custom),
+        Err(_) => Err(serde::de::Error::custom("expected a duration such as \"30s\", \"15m\" or \"1h\", or a number of seconds")),
+    }
+}
+
+fn deserialize_duration_map<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BTreeMap<String, Duration>, D::Error> {
+    #[derive(Deserialize)]
+    struct Entry(#[serde(deserialize_with = "deserialize_duration")] Duration);
+
+    let entries = BTreeMap::<String, Entry>::deserialize(deserializer)?;
+    Ok(entries.into_iter().map(|(name, Entry(duration))| (name, duration)).collect())
+}
+
+fn deserialize_optional_duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    deserialize_duration(deserializer).map(Some)
+}
+
+// Humantime-style durations: one or more `<number><unit>` parts, e.g. "90s", "1h30m", "250ms".
+// Units are ms, s, m, h and d; a bare number is seconds.
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let invalid = || format!("`{}` is not a duration such as \"30s\", \"15m\" or \"1h\"", text);
+    let trimmed = text.trim();
+    if let Ok(secs) = trimmed.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+
+    if trimmed.is_empty() {
+        return Err(invalid());
+    }
+
+    let mut total = Duration::ZERO;
+    let mut rest = trimmed;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let amount: u64 = rest[..digits].parse().map_err(|_| invalid())?;
+        rest = rest[digits..].trim_start();
+        let unit_len = rest.find(|c: char| c.is_ascii_digit() || c.is_whitespace()).unwrap_or(rest.len());
+        let unit = match &rest[..unit_len] {
+            "ms" => Duration::from_millis(1),
+            "s" | "sec" | "secs" => Duration::from_secs(1),
+            "m" | "min" | "mins" => Duration::from_secs(60),
+            "h" | "hr" | "hrs" => Duration::from_secs(60 * 60),
+            "d" | "day" | "days" => Duration::from_secs(24 * 60 * 60),
+            _ => return Err(invalid()),
+        };
+        total += unit.checked_mul(u32::try_from(amount).map_err(|_| invalid())?).ok_or_else(invalid)?;
+        rest = rest[unit_len..].trim_start();
+    }
+    Ok(total)
+}
+
+fn is_address(value: &str) -> bool {
+    value.strip_prefix("0x")
+        .is_some_and(|hex| hex.len() == 40 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+fn toml_parse_error(path: &Path, source: &str, err: &toml::de::Error) -> ConfigError {
+    let span = err.span().unwrap_or(0..0);
+    let offset = span.start.min(source.len());
+    let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+
+    // Missing fields are reported against their table's header, or an empty span for top-level ones
+    let mut key = match span.is_empty() && offset == 0 {
+        true => String::new(),
+        false => key_path_at(source, offset),
+    };
+    if let Some(field) = err.message().strip_prefix("missing field `").and_then(|rest| rest.split('`').next()) {
+        key = match key.is_empty() {
+            true => field.to_string(),
+            false => format!("{}.{}", key, field),
+        };
+    }
+
+    ConfigError::Parse {
+        path: path.to_path_buf(),
+        key,
+        line: source[..offset].matches('\n').count() + 1,
+        column: source[line_start..offset].chars().count() + 1,
+        message: err.message().trim().to_string(),
+    }
+}
+
+// serde_yaml prefixes messages about nested values with their path, `global.cache_ttl: invalid type..`
+fn yaml_parse_error(path: &Path, err: &serde_yaml::Error) -> ConfigError {
+    let message = err.to_string();
+    let (key, message) = match message.split_once(": ") {
+        Some((key, rest)) if !key.contains(' ') => (key.to_string(), rest.to_string()),
+        _ => (String::new(), message),
+    };
+    let location = err.location();
+    ConfigError::Parse {
+        path: path.to_path_buf(),
+        key,
+        line: location.as_ref().map_or(0, |location| location.line()),
+        column: location.as_ref().map_or(0, |location| location.column()),
+        message: strip_position(&message),
+    }
+}
+
+fn json_parse_error(path: &Path, err: &serde_json::Error) -> ConfigError {
+    ConfigError::Parse {
+        path: path.to_path_buf(),
+        key: String::new(),
+        line: err.line(),
+        column: err.column(),
+        message: strip_position(&err.to_string()),
+    }
+}
+
+// serde_yaml and serde_json end messages with the position, which ConfigError::Parse has already
+fn strip_position(message: &str) -> String {
+    match message.rfind(" at line ") {
+        Some(index) => message[..index].to_string(),
+        None => message.to_string(),
+    }
+}
+
+// TOML path of whatever sits at `offset`: the enclosing table, plus the key when the offset
+// is on a `key = value` line. Arrays of tables are indexed, `bridges.stargate.pairs[1]`.
+fn key_path_at(source: &str, offset: usize) -> String {
+    let mut table = String::new();
+    let mut array_lengths: HashMap<String, usize> = HashMap::new();
+    let mut line_start = 0;
+
+    for line in source.split_inclusive('\n') {
+        let trimmed = line.trim();
+        let is_offset_line = offset < line_start + line.len();
+        if let Some(name) = trimmed.strip_prefix("[[").and_then(|rest| rest.split("]]").next()) {
+            let length = array_lengths.entry(name.trim().to_string()).or_insert(0);
+            table = format!("{}[{}]", name.trim(), length);
+            *length += 1;
+        } else if let Some(name) = trimmed.strip_prefix('[').and_then(|rest| rest.split(']').next()) {
+            table = name.trim().to_string();
+        } else if is_offset_line && let Some((key, _)) = trimmed.split_once('=') {
+            return match table.is_empty() {
+                true => key.trim().to_string(),
+                false => format!("{}.{}", table, key.trim()),
+            };
+        }
+        if is_offset_line {
+            break;
+        }
+        line_start += line.len();
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GLOBAL: &str = "[global]\nupdate_interval = 60\ncache_ttl = 120\nlog_level = \"info\"\n";
+
+    fn load(name: &str, contents: &str) -> Result<ConfigManager, ConfigError> {
+        let path = std::env::temp_dir().join(format!("polypath-core-config-{}-{}.toml", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        let result = ConfigManager::load(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        result
+    }
+
+    fn bridge_with_pair(source_chain: &str, source_address: &str) -> String {
+        format!(r#"
+            [bridges.stargate]
+            base_url = "https://stargate.test"
+            chains = ["ethereum", "polygon"]
+
+            [[bridges.stargate.pairs]]
+            source_chain = "ethereum"
+            destination_chain = "polygon"
+            source_token_name = "USDC"
+            destination_token_name = "USDC"
+            source_address = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"
+            destination_address = "0x3c499c542cef5e3811e1192ce70d8cc03d5c3359"
+
+            [[bridges.stargate.pairs]]
+            source_chain = "{}"
+            destination_chain = "polygon"
+            source_token_name = "USDC"
+            destination_token_name = "USDC"
+            source_address = "{}"
+            destination_address = "0x3c499c542cef5e3811e1192ce70d8cc03d5c3359"
+        "#, source_chain, source_address)
+    }
+
+    #[test]
+    fn bundled_config_is_valid() {
+        ConfigManager::load("./src/config/config.toml").unwrap();
+    }
+
+    #[test]
+    fn toml_yaml_and_json_load_the_same_config() {
+        let toml = r#"
+            [global]
+            update_interval = 60
+            cache_ttl = 120
+            log_level = "info"
+            max_entries = 500
+
+            [bridges.stargate]
+            base_url = "https://stargate.test"
+            chains = ["ethereum", "polygon"]
+            extra = { api_key = "key", timeout_ms = 2500 }
+
+            [[bridges.stargate.pairs]]
+            source_chain = "ethereum"
+            destination_chain = "polygon"
+            source_token_name = "USDC"
+            destination_token_name = "USDC"
+            source_address = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"
+            destination_address = "0x3c499c542cef5e3811e1192ce70d8cc03d5c3359"
+        "#;
+        let yaml = r#"
+global:
+  update_interval: 60
+  cache_ttl: 120
+  log_level: info
+  max_entries: 500
+bridges:
+  stargate:
+    base_url: https://stargate.test
+    chains: [ethereum, polygon]
+    extra:
+      api_key: key
+      timeout_ms: 2500
+    pairs:
+      - source_chain: ethereum
+        destination_chain: polygon
+        source_token_name: USDC
+        destination_token_name: USDC
+        source_address: "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"
+        destination_address: "0x3c499c542cef5e3811e1192ce70d8cc03d5c3359"
+"#;
+        let json = r#"{
+            "global": { "update_interval": 60, "cache_ttl": 120, "log_level": "info", "max_entries": 500 },
+            "bridges": {
+                "stargate": {
+                    "base_url": "https://stargate.test",
+                    "chains": ["ethereum", "polygon"],
+                    "extra": { "api_key": "key", "timeout_ms": 2500 },
+                    "pairs": [{
+                        "source_chain": "ethereum",
+                        "destination_chain": "polygon",
+                        "source_token_name": "USDC",
+                        "destination_token_name": "USDC",
+                        "source_address": "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48",
+                        "destination_address": "0x3c499c542cef5e3811e1192ce70d8cc03d5c3359"
+                    }]
+                }
+            }
+        }"#;
+
+        let from_toml = ConfigManager::from_str(toml, ConfigFormat::Toml).unwrap();
+        assert_eq!(ConfigManager::from_str(yaml, ConfigFormat::Yaml).unwrap(), from_toml);
+        assert_eq!(ConfigManager::from_str(json, ConfigFormat::Json).unwrap(), from_toml);
+        assert_eq!(from_toml.bridges["stargate"].pairs.as_ref().unwrap().len(), 1);
+
+        let path = std::env::temp_dir().join(format!("polypath-core-config-yaml-{}.YML", std::process::id()));
+        fs::write(&path, yaml).unwrap();
+        let loaded = ConfigManager::load(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), from_toml);
+
+        // Validation runs whatever the format
+        let err = ConfigManager::from_str(&json.replace("\"ethereum\", \"polygon\"", "\"ethereum\""), ConfigFormat::Json).unwrap_err();
+        assert!(err.to_string().contains("`bridges.stargate.pairs[0].destination_chain`"), "{}", err);
+        let err = ConfigManager::from_str(&yaml.replace("max_entries: 500", "max_entries: lots"), ConfigFormat::Yaml).unwrap_err();
+        assert!(matches!(&err, ConfigError::Parse { key, line: 6, .. } if key == "global.max_entries"), "{}", err);
+    }
+
+    #[test]
+    fn unknown_extensions_list_the_supported_formats() {
+        for name in ["config.ini", "config"] {
+            let err = ConfigManager::load(name).unwrap_err();
+            assert!(matches!(err, ConfigError::UnsupportedFormat { .. }));
+            assert!(err.to_string().contains("expected one of .toml, .yaml, .yml, .json"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn missing_file_names_the_path() {
+        let err = ConfigManager::load("./src/config/missing.toml").unwrap_err();
+        assert!(matches!(err, ConfigError::Read { .. }));
+        assert!(err.to_string().contains("./src/config/missing.toml"));
+    }
+
+    #[test]
+    fn parse_errors_name_the_key_and_position() {
+        let err = load("type", "[global]\nupdate_interval = \"often\"\ncache_ttl = 1\nlog_level = \"info\"\n[bridges]\n").unwrap_err();
+        match &err {
+            ConfigError::Parse { key, line, message, .. } => {
+                assert_eq!(key, "global.update_interval");
+                assert_eq!(*line, 2);
+                assert!(message.contains("`often` is not a duration"), "{}", message);
+            }
+            other => panic!("unexpected error: {}", other),
+        }
+
+        let err = load("missing", "[bridges.stargate]\nbase_url = \"https://stargate.test\"\n").unwrap_err();
+        assert!(matches!(&err, ConfigError::Parse { key, .. } if key == "bridges.stargate.chains"), "{}", err);
+        let err = load("no-bridges", GLOBAL).unwrap_err();
+        assert!(matches!(&err, ConfigError::Parse { key, .. } if key == "bridges"), "{}", err);
+
+        let err = load("syntax", &format!("{}[bridges.stargate]\nbase_url = \"https://stargate.test\nchains = []\n", GLOBAL)).unwrap_err();
+        assert!(matches!(&err, ConfigError::Parse { key, line: 6, .. } if key == "bridges.stargate.base_url"), "{}", err);
+        assert!(err.to_string().contains("polypath-core-config-syntax"));
+    }
+
+    #[test]
+    fn pairs_must_use_the_bridge_chains() {
+        let err = load("chain", &(GLOBAL.to_string() + &bridge_with_pair("made-up-chain", "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"))).unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid { .. }));
+        assert!(err.to_string().contains("`bridges.stargate.pairs[1].source_chain`"), "{}", err);
+        assert!(err.to_string().contains("got `made-up-chain`"));
+    }
+
+    #[test]
+    fn pair_addresses_must_be_hex() {
+        let err = load("address", &(GLOBAL.to_string() + &bridge_with_pair("ethereum", "0xa0b8"))).unwrap_err();
+        assert!(err.to_string().contains("`bridges.stargate.pairs[1].source_address` must be a 0x-prefixed address"), "{}", err);
+    }
+
+    #[test]
+    fn env_references_and_overrides_apply() {
+        // SAFETY: the variable names are unique to this test
+        unsafe {
+            std::env::set_var("POLYPATH_TEST_CONFIG_CHAIN", "polygon");
+            std::env::set_var("POLYPATH__BRIDGES__ENVTEST__BASE_URL", "https://override.test");
+            std::env::set_var("POLYPATH__BRIDGES__ENVTEST__EXTRA__TIMEOUT_MS", "2500");
+        }
+        let config = load("env", &format!(r#"{}
+            [bridges.envtest]
+            base_url = "https://file.test"
+            chains = ["ethereum", "${{POLYPATH_TEST_CONFIG_CHAIN}}", "${{POLYPATH_TEST_CONFIG_UNSET:-base}}"]
+        "#, GLOBAL)).unwrap();
+
+        let bridge = &config.bridges["envtest"];
+        assert_eq!(bridge.base_url, "https://override.test");
+        assert_eq!(bridge.chains, ["ethereum", "polygon", "base"]);
+        assert_eq!(bridge.extra.as_ref().unwrap()["timeout_ms"].as_integer(), Some(2500));
+        // Overrides never invent bridges the file doesn't configure
+        assert!(!config.bridges.contains_key("stargate"));
+    }
+
+    #[test]
+    fn unset_env_references_name_the_key() {
+        let err = load("unset", &format!("{}[bridges.stargate]\nbase_url = \"${{POLYPATH_TEST_CONFIG_MISSING}}/v1\"\nchains = []\n", GLOBAL)).unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid { .. }));
+        assert!(err.to_string().contains("`bridges.stargate.base_url` environment variable `POLYPATH_TEST_CONFIG_MISSING`"), "{}", err);
+    }
+
+    #[test]
+    fn global_overrides_are_typed_from_the_existing_value() {
+        let mut document: toml::Table = toml::from_str(GLOBAL).unwrap();
+        let vars = [
+            ("POLYPATH__GLOBAL__LOG_LEVEL", "debug"),
+            ("POLYPATH__GLOBAL__CACHE_TTL", "30"),
+            ("POLYPATH__BRIDGES__UNKNOWN__BASE_URL", "https://nowhere.test"),
+            ("OTHER__GLOBAL__LOG_LEVEL", "error"),
+        ];
+        let overridden = apply_env_overrides(&mut document, vars.map(|(k, v)| (k.to_string(), v.to_string())));
+
+        assert_eq!(overridden, ["global.cache_ttl", "global.log_level"]);
+        assert_eq!(document["global"]["log_level"].as_str(), Some("debug"));
+        assert_eq!(document["global"]["cache_ttl"].as_integer(), Some(30));
+        assert!(!document.contains_key("bridges"));
+    }
+
+    #[test]
+    fn durations_accept_units_and_bare_seconds() {
+        let config = load("durations", "[global]\nupdate_interval = \"15m\"\ncache_ttl = 3600\n[bridges]\n").unwrap();
+        assert_eq!(config.global.update_interval, Duration::from_secs(15 * 60));
+        assert_eq!(config.global.cache_ttl, Duration::from_secs(3600));
+
+        assert_eq!(parse_duration("1h30m").unwrap(), Duration::from_secs(90 * 60));
+        assert_eq!(parse_duration("250ms").unwrap(), Duration::from_millis(250));
+        assert_eq!(parse_duration("2 d").unwrap(), Duration::from_secs(2 * 24 * 60 * 60));
+        assert_eq!(parse_duration("45").unwrap(), Duration::from_secs(45));
+        for invalid in ["", "soon", "10 fortnights", "m5", "-5s"] {
+            assert!(parse_duration(invalid).is_err(), "{}", invalid);
+        }
+
+        let err = load("bad-duration", "[global]\ncache_ttl = \"forever\"\n[bridges]\n").unwrap_err();
+        assert!(matches!(&err, ConfigError::Parse { key, message, .. } if key == "global.cache_ttl" && message.contains("`forever`")), "{}", err);
+    }
+
+    #[test]
+    fn global_section_is_optional() {
+        let config = ConfigManager::from_str("[bridges.stargate]\nbase_url = \"https://stargate.test\"\nchains = []\n", ConfigFormat::Toml).unwrap();
+        assert_eq!(config.global, GlobalConfig::default());
+        assert_eq!(config.global.update_interval, Duration::from_secs(60));
+        assert_eq!(config.global.cache_ttl, Duration::from_secs(300));
+        assert_eq!(config.global.log_level, "info");
+        assert_eq!(config.global.quarantine_after, 5);
+        assert_eq!(config.global.min_coverage, 0.5);
+        assert_eq!(config.global.route_stickiness, None);
+        assert_eq!(config.global.update_queue_capacity, 256);
+        assert_eq!(config.global.update_batch_size, 64);
+    }
+
+    #[test]
+    fn alert_rules_are_read_and_checked() {
+        let config = ConfigManager::from_str(r#"
+            [alerts]
+            webhook_url = "https://hooks.test/polypath"
+
+            [[alerts.rules]]
+            name = "stargate-cost"
+            bridge = "stargate"
+            kind = "cost_increase"
+            pct = 100
+            within = "10m"
+
+            [[alerts.rules]]
+            name = "thin"
+            source_chain = "ethereum"
+            kind = "liquidity_below"
+            threshold = 50000
+
+            [[alerts.rules]]
+            name = "gone"
+            kind = "edge_deactivated"
+
+            [bridges]
+        "#, ConfigFormat::Toml).unwrap();
+        let alerts = &config.alerts;
+        assert_eq!(alerts.cooldown, Duration::from_secs(15 * 60));
+        assert_eq!(alerts.rules.len(), 3);
+        assert_eq!(alerts.rules[0].bridge.as_deref(), Some("stargate"));
+        assert_eq!(alerts.rules[0].condition, AlertCondition::CostIncrease { pct: 100.0, within: Duration::from_secs(600) });
+        assert_eq!(alerts.rules[1].condition, AlertCondition::LiquidityBelow { threshold: 50_000.0 });
+        assert_eq!(alerts.rules[2].condition, AlertCondition::EdgeDeactivated);
+
+        let rule = |body: &str| format!("[[alerts.rules]]\nname = \"r\"\n{}\n[bridges]\n", body);
+        let err = ConfigManager::from_str(&rule("kind = \"cost_increase\"\npct = 0\nwithin = 60"), ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("`alerts.rules[0]` needs a pct above 0"), "{}", err);
+        let err = ConfigManager::from_str(&rule("kind = \"sunspots\""), ConfigFormat::Toml).unwrap_err();
+        assert!(matches!(&err, ConfigError::Parse { key, .. } if key.starts_with("alerts.rules")), "{}", err);
+        let err = ConfigManager::from_str("[alerts]\nwebhook_url = \"hooks.test\"\n[bridges]\n", ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("`alerts.webhook_url` must be an http(s) URL"), "{}", err);
+    }
+
+    #[test]
+    fn digest_settings_are_read_and_checked() {
+        let config = ConfigManager::from_str("[bridges]\n", ConfigFormat::Toml).unwrap();
+        assert_eq!(config.digest, DigestConfig::default());
+        assert!(!config.digest.enabled);
+
+        let config = ConfigManager::from_str(r#"
+            [digest]
+            enabled = true
+            webhook_url = "https://hooks.test/digest"
+            failures_above = 3
+            [bridges]
+        "#, ConfigFormat::Toml).unwrap();
+        assert_eq!((config.digest.every, config.digest.failures_above, config.digest.queue_capacity), (1, Some(3), 16));
+
+        let err = ConfigManager::from_str("[digest]\nevery = 0\n[bridges]\n", ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("`digest.every` must be at least 1"), "{}", err);
+        let err = ConfigManager::from_str("[digest]\nwebhook_url = \"hooks.test\"\n[bridges]\n", ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("`digest.webhook_url` must be an http(s) URL"), "{}", err);
+    }
+
+    #[test]
+    fn graphs_are_checked() {
+        let bridges = "[bridges.stargate]\nbase_url = \"https://stargate.test\"\nchains = []\n";
+        let config = ConfigManager::from_str(
+            &format!("[graphs.stables]\nbridges = [\"stargate\"]\nupdate_interval = \"5m\"\nmax_hops = 2\npairs_filter = {{ tokens = [\"USDC\"] }}\n{}", bridges),
+            ConfigFormat::Toml,
+        )
+        .unwrap();
+        let stables = &config.graphs["stables"];
+        assert_eq!(stables.update_interval, Some(Duration::from_secs(300)));
+        assert_eq!((stables.max_hops, stables.max_results), (Some(2), None));
+        assert_eq!(stables.pairs_filter, PairsFilter { chains: Vec::new(), tokens: vec!["USDC".to_string()] });
+
+        let err = ConfigManager::from_str(&format!("[graphs.stables]\nbridges = [\"hop\"]\n{}", bridges), ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("`graphs.stables.bridges` must name configured bridges, got `hop`"), "{}", err);
+        let err = ConfigManager::from_str(&format!("[graphs.all]\nmax_results = 0\n{}", bridges), ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("`graphs.all.max_results` must be at least 1"), "{}", err);
+    }
+
+    #[test]
+    fn gas_chains_are_checked() {
+        let config = ConfigManager::from_str(
+            "[gas.chains.ethereum]\nrpc_url = \"https://eth.example\"\nnative_usd = 3000.0\n[bridges]\n",
+            ConfigFormat::Toml,
+        )
+        .unwrap();
+        assert_eq!(config.gas.cache_ttl, Duration::from_secs(60));
+        assert_eq!(config.gas.chains["ethereum"].rpc_url.as_deref(), Some("https://eth.example"));
+
+        let err = ConfigManager::from_str("[gas.chains.ethereum]\nnative_usd = 3000.0\n[bridges]\n", ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("`gas.chains.ethereum.rpc_url` must be set"), "{}", err);
+        let err = ConfigManager::from_str(
+            "[gas.chains.ethereum]\noracle_url = \"ftp://gas\"\nnative_usd = 3000.0\n[bridges]\n",
+            ConfigFormat::Toml,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("`gas.chains.ethereum.oracle_url` must be an http(s) URL"), "{}", err);
+        let err = ConfigManager::from_str(
+            "[gas.chains.ethereum]\ndefault_gas_price_gwei = 20.0\nnative_usd = 0.0\n[bridges]\n",
+            ConfigFormat::Toml,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("`gas.chains.ethereum.native_usd` must be above 0"), "{}", err);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn fx_sections_are_checked() {
+        let config = ConfigManager::from_str("[bridges]\n", ConfigFormat::Toml).unwrap();
+        assert_eq!(config.fx.quote_currency, "USD");
+        assert!(!config.fx.enabled());
+        let config = ConfigManager::from_str("[fx]\nquote_currency = \"EUR\"\n[fx.rates]\nETH = 2300.0\n[bridges]\n", ConfigFormat::Toml).unwrap();
+        assert!(config.fx.enabled());
+        assert_eq!((config.fx.source, config.fx.rates["ETH"]), (FxSource::Static, 2300.0));
+
+        let err = ConfigManager::from_str("[fx.rates]\nETH = 0\n[bridges]\n", ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("`fx.rates.ETH` must be above 0"), "{}", err);
+        let err = ConfigManager::from_str("[fx]\nsource = \"http\"\n[bridges]\n", ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("`fx.price_url` must be set for the http source"), "{}", err);
+        let err = ConfigManager::from_str("[fx]\nsource = \"http\"\nprice_url = \"prices\"\n[bridges]\n", ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("`fx.price_url` must be an http(s) URL"), "{}", err);
+    }
+
+    #[test]
+    fn pair_cadences_are_checked() {
+        let pair = |extra: &str| format!(
+            "[bridges.stargate]\nbase_url = \"https://stargate.example\"\nchains = [\"ethereum\", \"polygon\"]\n[[bridges.stargate.pairs]]\nsource_chain = \"ethereum\"\ndestination_chain = \"polygon\"\nsource_token_name = \"USDC\"\nsource_address = \"0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48\"\ndestination_address = \"0x3c499c542cef5e3811e1192ce70d8cc03d5c3359\"\ndestination_token_name = \"USDC\"\n{}",
+            extra
+        );
+        let config = ConfigManager::from_str(&pair(""), ConfigFormat::Toml).unwrap();
+        let configured = &config.bridges["stargate"].pairs.as_ref().unwrap()[0];
+        assert_eq!((configured.priority, configured.refresh_interval), (RefreshPriority::Normal, None));
+        assert_eq!(config.refresh, RefreshConfig { hot: 1, normal: 3, cold: 10 });
+
+        let config = ConfigManager::from_str(&format!("[refresh]\ncold = 20\n{}", pair("priority = \"cold\"\nrefresh_interval = \"10m\"\n")), ConfigFormat::Toml).unwrap();
+        let configured = &config.bridges["stargate"].pairs.as_ref().unwrap()[0];
+        assert_eq!((configured.priority, configured.refresh_interval), (RefreshPriority::Cold, Some(Duration::from_secs(600))));
+        assert_eq!(config.refresh.multiplier(RefreshPriority::Cold), 20);
+
+        let err = ConfigManager::from_str(&pair("priority = \"lukewarm\"\n"), ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("lukewarm"), "{}", err);
+        let err = ConfigManager::from_str(&pair("refresh_interval = 0\n"), ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("`bridges.stargate.pairs[0].refresh_interval` must be at least 1s"), "{}", err);
+        let err = ConfigManager::from_str(&format!("[refresh]\nhot = 0\n{}", pair("")), ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("`refresh.hot` must be at least 1"), "{}", err);
+    }
+
+    #[test]
+    fn finality_entries_are_checked() {
+        let config = ConfigManager::from_str("[bridges]\n", ConfigFormat::Toml).unwrap();
+        assert_eq!(config.finality, FinalityConfig::default());
+
+        let err = ConfigManager::from_str("[finality.chains.ethereum]\nblock_time = \"12s\"\nconfirmations = 0\n[bridges]\n", ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("`finality.chains.ethereum.confirmations` must be at least 1"), "{}", err);
+        let err = ConfigManager::from_str("[finality.chains.ethereum]\nblock_time = 0\nconfirmations = 64\n[bridges]\n", ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("`finality.chains.ethereum.block_time` must be above 0"), "{}", err);
+        let err = ConfigManager::from_str("[finality]\nunknown_chain = 0\n[bridges]\n", ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("`finality.unknown_chain` must be above 0"), "{}", err);
+    }
+
+    #[test]
+    fn api_keys_are_checked_and_kept_out_of_debug_output() {
+        let toml = "[api_keys.partner-a]\nkey = \"pa-0123456789abcdef\"\nrequests_per_minute = 60\nmax_results = 2\nfeatures = [\"watch\"]\n[bridges]\n";
+        let config = ConfigManager::from_str(toml, ConfigFormat::Toml).unwrap();
+        let partner = &config.api_keys["partner-a"];
+        assert_eq!((partner.requests_per_minute, partner.max_results), (Some(60), Some(2)));
+        assert!(partner.has_feature(ApiFeature::Watch) && !partner.has_feature(ApiFeature::Profiles));
+        assert!(partner.allows_graph("default"));
+        assert!(!format!("{:?}", config).contains("pa-0123456789abcdef"));
+
+        let err = ConfigManager::from_str("[api_keys.partner-a]\nkey = \"short\"\n[bridges]\n", ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("`api_keys.partner-a.key` must be at least 16 characters"), "{}", err);
+        let err = ConfigManager::from_str("[api_keys.\"partner a\"]\nkey = \"pa-0123456789abcdef\"\n[bridges]\n", ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("`api_keys.partner a` names are 1 to 64 letters"), "{}", err);
+        let shared = "[api_keys.a]\nkey = \"pa-0123456789abcdef\"\n[api_keys.b]\nkey = \"pa-0123456789abcdef\"\n[bridges]\n";
+        let err = ConfigManager::from_str(shared, ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("`api_keys.b.key` is the same as api_keys.a.key"), "{}", err);
+        let err = ConfigManager::from_str(&toml.replace("features = [\"watch\"]", "graphs = [\"volatile\"]"), ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("`api_keys.partner-a.graphs` must name configured graphs, got `volatile`"), "{}", err);
+        let err = ConfigManager::from_str(&toml.replace("\"watch\"", "\"teleport\""), ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("teleport"), "{}", err);
+    }
+
+    #[test]
+    fn slippage_settings_are_checked() {
+        let config = ConfigManager::from_str("[bridges]\n", ConfigFormat::Toml).unwrap();
+        assert_eq!(config.slippage, SlippageConfig::default());
+        let config = ConfigManager::from_str("[slippage]\nmodel = \"sqrt\"\nmax_utilization = 0.5\n[bridges]\n", ConfigFormat::Toml).unwrap();
+        assert_eq!((config.slippage.model, config.slippage.max_utilization), (SlippageKind::Sqrt, 0.5));
+
+        let err = ConfigManager::from_str("[slippage]\nmax_utilization = 1.5\n[bridges]\n", ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("`slippage.max_utilization` must be above 0 and at most 1, got 1.5"), "{}", err);
+        let err = ConfigManager::from_str("[slippage]\nimpact_per_utilization = -0.1\n[bridges]\n", ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("`slippage.impact_per_utilization` must be 0 or above"), "{}", err);
+    }
+
+    #[test]
+    fn confidence_windows_are_read_per_bridge() {
+        let config = ConfigManager::from_str("[bridges]\n", ConfigFormat::Toml).unwrap();
+        assert_eq!(config.confidence, ConfidenceConfig::default());
+
+        let toml = "[confidence]\ncombinator = \"product\"\nvalidity = \"2m\"\n[confidence.bridges]\nwormhole = \"10m\"\nacross = 30\n[bridges]\n";
+        let config = ConfigManager::from_str(toml, ConfigFormat::Toml).unwrap();
+        assert_eq!(config.confidence.combinator, ConfidenceCombinatorKind::Product);
+        assert_eq!(config.confidence.validity, Some(Duration::from_secs(120)));
+        assert_eq!(config.confidence.bridges["wormhole"], Duration::from_secs(600));
+        assert_eq!(config.confidence.bridges["across"], Duration::from_secs(30));
+    }
+
+    #[test]
+    fn sanity_bounds_merge_per_bridge_and_are_checked() {
+        let config = ConfigManager::from_str("[bridges]\n", ConfigFormat::Toml).unwrap();
+        assert!(config.sanity.is_empty());
+
+        let toml = "[sanity]\nmax_fee_fraction = 0.05\nmax_duration = \"2h\"\n[sanity.bridges.wormhole]\nmax_duration = \"1d\"\nmin_duration = \"30s\"\n[bridges]\n";
+        let config = ConfigManager::from_str(toml, ConfigFormat::Toml).unwrap();
+        let wormhole = config.sanity.bounds("wormhole");
+        assert_eq!(wormhole.max_fee_fraction, Some(0.05));
+        assert_eq!((wormhole.min_duration, wormhole.max_duration), (Some(Duration::from_secs(30)), Some(Duration::from_secs(86_400))));
+        assert_eq!(config.sanity.bounds("stargate"), config.sanity.defaults);
+        assert_eq!(config.sanity.bounds("stargate").max_duration, Some(Duration::from_secs(7_200)));
+
+        let err = ConfigManager::from_str("[sanity]\nmax_fee_fraction = 0\n[bridges]\n", ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("`sanity.max_fee_fraction` must be above 0, got 0"), "{}", err);
+        // A bridge's minimum is checked against the maximum it inherits
+        let err = ConfigManager::from_str("[sanity]\nmax_duration = \"1m\"\n[sanity.bridges.across]\nmin_duration = \"5m\"\n[bridges]\n", ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("`sanity.bridges.across.min_duration` must be at most max_duration"), "{}", err);
+    }
+
+    #[test]
+    fn source_policies_name_configured_bridges() {
+        let bridges = "[bridges.lifi]\nbase_url = \"https://li.quest\"\nchains = [\"ethereum\"]\n[bridges.stargate]\nbase_url = \"https://stargate.finance\"\nchains = [\"ethereum\"]\n";
+        let config = ConfigManager::from_str(
+            &format!("{}[bridges.stargate.source_policy]\nsources = [{{ adapter = \"lifi\" }}, {{ adapter = \"stargate\", weight = 3 }}]\n", bridges),
+            ConfigFormat::Toml,
+        )
+        .unwrap();
+        assert_eq!(config.bridges["stargate"].source_policy.as_ref().unwrap().ordered(), ["stargate", "lifi"]);
+        assert_eq!(config.bridges["lifi"].source_policy, None);
+
+        for (sources, message) in [
+            ("[]", "`bridges.stargate.source_policy.sources` must list at least one source"),
+            ("[{ adapter = \"socket\" }]", "`bridges.stargate.source_policy.sources[0]` must name a configured bridge, got `socket`"),
+            ("[{ adapter = \"lifi\" }, { adapter = \"lifi\" }]", "`bridges.stargate.source_policy.sources[1]` lists `lifi` twice"),
+            ("[{ adapter = \"lifi\", weight = 0 }]", "`bridges.stargate.source_policy.sources[0]` needs a weight of at least 1"),
+        ] {
+            let err = ConfigManager::from_str(&format!("{}[bridges.stargate.source_policy]\nsources = {}\n", bridges, sources), ConfigFormat::Toml).unwrap_err();
+            assert!(err.to_string().contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn edge_identities_map_configured_adapters() {
+        let bridges = "[bridges.lifi]\nbase_url = \"https://li.quest\"\nchains = [\"ethereum\"]\n[bridges.stargate]\nbase_url = \"https://stargate.finance\"\nchains = [\"ethereum\"]\n";
+        let config = ConfigManager::from_str(
+            &format!("{}[[edge_identity.canonical]]\nadapter = \"lifi\"\nvia = \"stargate\"\nbridge = \"stargate\"\n", bridges),
+            ConfigFormat::Toml,
+        )
+        .unwrap();
+        assert_eq!(config.edge_identity.canonical("lifi", "Stargate"), Some("stargate"));
+        assert_eq!(config.edge_identity.canonical("lifi", "across"), None);
+        assert_eq!(config.edge_identity.canonical("stargate", "stargate"), None);
+        assert_eq!(ConfigManager::from_str(bridges, ConfigFormat::Toml).unwrap().edge_identity, EdgeIdentityConfig::default());
+
+        for (entries, message) in [
+            ("{ adapter = \"socket\", via = \"stargate\", bridge = \"stargate\" }", "`edge_identity.canonical[0]` must name a configured bridge, got `socket`"),
+            ("{ adapter = \"lifi\", via = \"stargate\", bridge = \"\" }", "`edge_identity.canonical[0]` needs a via and a bridge"),
+            (
+                "{ adapter = \"lifi\", via = \"stargate\", bridge = \"stargate\" }, { adapter = \"lifi\", via = \"STARGATE\", bridge = \"stg\" }",
+                "`edge_identity.canonical[1]` maps `lifi` via `STARGATE` twice",
+            ),
+        ] {
+            let err = ConfigManager::from_str(&format!("{}[edge_identity]\ncanonical = [{}]\n", bridges, entries), ConfigFormat::Toml).unwrap_err();
+            assert!(err.to_string().contains(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn audit_is_off_unless_enabled() {
+        let config = ConfigManager::from_str("[bridges]\n", ConfigFormat::Toml).unwrap();
+        assert_eq!(config.audit, AuditConfig::default());
+        assert!(!config.audit.enabled);
+        let config = ConfigManager::from_str("[audit]\nenabled = true\nsegment_bytes = 4096\n[bridges]\n", ConfigFormat::Toml).unwrap();
+        assert_eq!((config.audit.enabled, config.audit.segment_bytes, config.audit.queue_capacity), (true, 4096, 1024));
+
+        let err = ConfigManager::from_str("[audit]\nsegment_bytes = 100\n[bridges]\n", ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("`audit.segment_bytes` must be at least 1024, got 100"), "{}", err);
+        let err = ConfigManager::from_str("[audit]\nqueue_capacity = 0\n[bridges]\n", ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("`audit.queue_capacity` must be at least 1"), "{}", err);
+    }
+
+    #[test]
+    fn history_durations_are_checked() {
+        let config = ConfigManager::from_str("[history]\nretention = \"30d\"\n[bridges]\n", ConfigFormat::Toml).unwrap();
+        assert!(config.history.enabled);
+        assert_eq!(config.history.retention, Duration::from_secs(30 * 24 * 60 * 60));
+        assert_eq!(config.history.downsample_after, Duration::from_secs(24 * 60 * 60));
+
+        let err = ConfigManager::from_str("[history]\nretention = \"12h\"\n[bridges]\n", ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("`history.retention` must be at least history.downsample_after"), "{}", err);
+        let err = ConfigManager::from_str("[history]\ndownsample_after = \"10m\"\n[bridges]\n", ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("`history.downsample_after` must be at least 1h"), "{}", err);
+    }
+
+    #[test]
+    fn debug_output_redacts_secrets() {
+        // SAFETY: the variable name is unique to this test
+        unsafe {
+            std::env::set_var("POLYPATH_TEST_SECRET_PASSPHRASE", "hunter2-passphrase");
+        }
+        let config = ConfigManager::from_str(r#"
+            [global]
+            secret_patterns = ["*_password"]
+
+            [bridges.stargate]
+            base_url = "https://stargate.test"
+            chains = ["ethereum"]
+
+            [bridges.stargate.extra]
+            api_key = "sg-live-api-key"
+            private_key = "0xdeadbeefprivate"
+            db_password = "pg-password-value"
+            passphrase = "${secret:POLYPATH_TEST_SECRET_PASSPHRASE}"
+            slippage_tolerance = 3000
+
+            [bridges.stargate.extra.headers]
+            authorization = "Bearer header-token-value"
+            x-request-source = "polypath"
+        "#, ConfigFormat::Toml).unwrap();
+
+        let bridge = &config.bridges["stargate"];
+        assert_eq!(bridge.extra.as_ref().unwrap()["passphrase"].as_str(), Some("hunter2-passphrase"));
+        assert!(bridge.is_secret("passphrase") && bridge.is_secret("headers.authorization"));
+
+        let debug = format!("{:?} {:#?}", config, config);
+        for secret in ["sg-live-api-key", "0xdeadbeefprivate", "pg-password-value", "hunter2-passphrase", "header-token-value"] {
+            assert!(!debug.contains(secret), "`{}` leaked into {}", secret, debug);
+        }
+        for visible in ["https://stargate.test", "3000", "x-request-source", "polypath", REDACTED] {
+            assert!(debug.contains(visible), "`{}` missing from {}", visible, debug);
+        }
+    }
+
+    #[test]
+    fn logging_section_is_optional_and_follows_the_global_level() {
+        let config = ConfigManager::from_str("[global]\nlog_level = \"warn\"\n[bridges]\n", ConfigFormat::Toml).unwrap();
+        assert_eq!(config.logging, LoggingConfig { level: "warn".to_string(), ..LoggingConfig::default() });
+
+        let config = ConfigManager::from_str(r#"
+            [logging]
+            filter = "polypath_dal=debug,polypath_graph=info"
+            format = "json"
+            file = { directory = "/var/log/polypath", rotation = "hourly" }
+            [bridges]
+        "#, ConfigFormat::Toml).unwrap();
+        assert_eq!(config.logging.format, LogFormat::Json);
+        let file = config.logging.file.unwrap();
+        assert_eq!((file.prefix.as_str(), file.rotation), ("polypath", LogRotation::Hourly));
+
+        let err = ConfigManager::from_str("[logging]\nfilter = \"polypath_dal=loud\"\n[bridges]\n", ConfigFormat::Toml).unwrap_err();
+        assert!(matches!(&err, ConfigError::Invalid { key, .. } if key == "logging.filter"), "{}", err);
+    }
+
+    #[test]
+    fn slow_op_thresholds_fall_back_to_the_default() {
+        let config = ConfigManager::from_str("[bridges]\n", ConfigFormat::Toml).unwrap();
+        assert_eq!(config.logging.slow_ops.threshold("fetch_metrics"), Duration::from_secs(1));
+
+        let config = ConfigManager::from_str(r#"
+            [logging.slow_ops]
+            default = "2s"
+            fetch_metrics = "5s"
+            scoring = "250ms"
+            [bridges]
+        "#, ConfigFormat::Toml).unwrap();
+        let slow_ops = &config.logging.slow_ops;
+        assert_eq!(slow_ops.threshold("fetch_metrics"), Duration::from_secs(5));
+        assert_eq!(slow_ops.threshold("scoring"), Duration::from_millis(250));
+        assert_eq!(slow_ops.threshold("route_search"), Duration::from_secs(2));
+
+        let err = ConfigManager::from_str("[logging.slow_ops]\nscoring = \"soon\"\n[bridges]\n", ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("soon"), "{}", err);
+    }
+
+    #[test]
+    fn global_settings_are_checked() {
+        let err = load("ttl", &(GLOBAL.replace("cache_ttl = 120", "cache_ttl = \"0s\"") + "[bridges]\n")).unwrap_err();
+        assert!(err.to_string().contains("`global.cache_ttl` must be at least 1s"), "{}", err);
+        let err = load("interval", &(GLOBAL.replace("update_interval = 60", "update_interval = 0") + "[bridges]\n")).unwrap_err();
+        assert!(err.to_string().contains("`global.update_interval` must be at least 1s"), "{}", err);
+
+        let err = load("level", &(GLOBAL.replace("\"info\"", "\"loud\"") + "[bridges]\n")).unwrap_err();
+        assert!(err.to_string().contains("`global.log_level` must be one of trace, debug, info, warn, error"), "{}", err);
+
+        let err = load("backend", "[global]\npersistence_backend = \"sqlite\"\n[bridges]\n").unwrap_err();
+        assert!(err.to_string().contains("`global.persistence_path` must be set for the `sqlite` persistence backend"), "{}", err);
+
+        let err = load("quarantine", "[global]\nquarantine_after = 0\n[bridges]\n").unwrap_err();
+        assert!(err.to_string().contains("`global.quarantine_after` must be at least 1"), "{}", err);
+        let err = load("update_queue", "[global]\nupdate_queue_capacity = 0\n[bridges]\n").unwrap_err();
+        assert!(err.to_string().contains("`global.update_queue_capacity` must be at least 1"), "{}", err);
+
+        let err = load("coverage", "[global]\nmin_coverage = 1.5\n[bridges]\n").unwrap_err();
+        assert!(err.to_string().contains("`global.min_coverage` must be between 0 and 1, got 1.5"), "{}", err);
+        let err = load("stickiness", "[global]\nroute_stickiness = -0.05\n[bridges]\n").unwrap_err();
+        assert!(err.to_string().contains("`global.route_stickiness` must be 0 or more, got -0.05"), "{}", err);
+        let err = load("jitter", "[global]\ncache_ttl_jitter = -0.1\n[bridges]\n").unwrap_err();
+        assert!(err.to_string().contains("`global.cache_ttl_jitter` must be between 0 and 1, got -0.1"), "{}", err);
+        let err = load("soft-ttl", "[global]\ncache_ttl = \"1m\"\ncache_soft_ttl = \"2m\"\n[bridges]\n").unwrap_err();
+        assert!(err.to_string().contains("`global.cache_soft_ttl` must be shorter than global.cache_ttl less its jitter (60s), got 120s"), "{}", err);
+        let err = load("soft-ttl-jitter", "[global]\ncache_ttl = \"1m\"\ncache_soft_ttl = \"50s\"\ncache_ttl_jitter = 0.2\n[bridges]\n").unwrap_err();
+        assert!(err.to_string().contains("must be shorter than global.cache_ttl less its jitter (48s), got 50s"), "{}", err);
+        let config = load("soft-ttl-ok", "[global]\ncache_soft_ttl = \"30s\"\ncache_ttl_jitter = 0.1\n[bridges]\n").unwrap();
+        assert_eq!(config.global.cache_soft_ttl, Some(Duration::from_secs(30)));
+        assert_eq!(config.global.cache_ttl_jitter, 0.1);
+
+        let err = load("discovery", "[discovery]\ninterval = 0\n[bridges]\n").unwrap_err();
+        assert!(err.to_string().contains("`discovery.interval` must be at least 1s"), "{}", err);
+        let err = load("idempotency", "[server]\nidempotency_ttl = \"500ms\"\n[bridges]\n").unwrap_err();
+        assert!(err.to_string().contains("`server.idempotency_ttl` must be at least 1s"), "{}", err);
+        let err = load("executor", "[executor]\nworkers = 0\n[bridges]\n").unwrap_err();
+        assert!(err.to_string().contains("`executor.workers` must be at least 1"), "{}", err);
+    }
+}
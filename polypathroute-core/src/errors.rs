@@ -1,18 +1,144 @@
-// Unified error definitions 
+// Unified error definitions
 
+use std::{io, path::{Path, PathBuf}};
+use thiserror::Error;
 
+// Config files that can't be read, parsed or validated. `key` is the TOML path of the
+// offending value, e.g. `bridges.stargate.pairs[0].source_chain`.
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("cannot read config file `{}`: {source}", path.display())]
+    Read { path: PathBuf, source: io::Error },
 
-pub struct ConfigError;
-pub struct CacheError;
-pub struct NetworkError;
-pub struct DataError;
-pub struct GraphError;
+    #[error("unsupported config file `{}`, expected one of {}", path.display(), supported.join(", "))]
+    UnsupportedFormat { path: PathBuf, supported: Vec<String> },
 
+    // `key` is empty when the format doesn't say which value failed
+    #[error("cannot parse config file `{}` {}: {message}", path.display(), parse_location(key, *line, *column))]
+    Parse { path: PathBuf, key: String, line: usize, column: usize, message: String },
 
-pub enum Errors {
-    ConfigError,
-    CacheError,
-    NetworkError,
-    DataError,
-    GraphError
-}
\ No newline at end of file
+    #[error("invalid config file `{}`: `{key}` {message}", path.display())]
+    Invalid { path: PathBuf, key: String, message: String },
+
+    // CoreContextBuilder::build without config_path or config
+    #[error("no config given, set a config path or a loaded config")]
+    Missing,
+}
+
+// Typed cache values that don't survive the trip through their JSON encoding
+#[derive(Debug, Error)]
+pub enum CacheError {
+    #[error("cannot encode cache entry `{key}`: {source}")]
+    Encode { key: String, source: serde_json::Error },
+
+    #[error("cache entry `{key}` does not decode as the requested type: {source}")]
+    Decode { key: String, source: serde_json::Error },
+}
+
+#[derive(Debug, Error)]
+pub enum PersistenceError {
+    #[error("persistence I/O failed on `{}`: {source}", path.display())]
+    Io { path: PathBuf, source: io::Error },
+
+    // A stored file that doesn't hold what the store wrote
+    #[error("corrupt persistence file `{}`: {reason}", path.display())]
+    Corrupt { path: PathBuf, reason: String },
+
+    #[error("persistence database `{}` failed: {source}", path.display())]
+    Sqlite { path: PathBuf, source: rusqlite::Error },
+
+    #[error("cannot encode `{key}` for storage: {source}")]
+    Encode { key: String, source: serde_json::Error },
+
+    #[error("stored value `{key}` does not decode: {source}")]
+    Decode { key: String, source: serde_json::Error },
+
+    #[error("stored value `{key}` is a `{found}`, expected a `{expected}`")]
+    SchemaMismatch { key: String, expected: String, found: String },
+
+    // Written by a newer build than this one
+    #[error("`{schema}` version {found} is newer than this build reads (up to {max_supported})")]
+    UnsupportedVersion { schema: String, found: u32, max_supported: u32 },
+
+    #[error("cannot upgrade `{schema}` from version {from}: {reason}")]
+    Migration { schema: String, from: u32, reason: String },
+}
+
+fn parse_location(key: &str, line: usize, column: usize) -> String {
+    match key.is_empty() {
+        true => format!("(line {}, column {})", line, column),
+        false => format!("at `{}` (line {}, column {})", key, line, column),
+    }
+}
+
+impl PersistenceError {
+    pub(crate) fn io(path: &Path, source: io::Error) -> Self {
+        PersistenceError::Io { path: path.to_path_buf(), source }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum LoggingError {
+    #[error("invalid log filter `{filter}`: {reason}")]
+    Filter { filter: String, reason: String },
+
+    #[error("cannot log to `{}`: {reason}", directory.display())]
+    File { directory: PathBuf, reason: String },
+}
+
+// Chains and tokens the registry can't resolve or won't accept
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum RegistryError {
+    #[error("unknown chain `{0}`")]
+    UnknownChain(String),
+
+    #[error("no token `{token}` on chain `{chain}`")]
+    UnknownToken { chain: String, token: String },
+
+    #[error("`{token}` matches {} tokens on chain `{chain}` ({}), give its address instead", matches.len(), matches.join(", "))]
+    AmbiguousToken { chain: String, token: String, matches: Vec<String> },
+
+    #[error("`{address}` is not a valid address: {reason}")]
+    InvalidAddress { address: String, reason: String },
+
+    #[error("chain name `{name}` is used by both `{first}` and `{second}`")]
+    DuplicateChainName { name: String, first: String, second: String },
+
+    #[error(transparent)]
+    Amount(#[from] AmountError),
+}
+
+// Token amounts that can't be read or don't fit in u128 base units, see Amount
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum AmountError {
+    #[error("`{0}` is not a decimal amount")]
+    Invalid(String),
+
+    #[error("amount overflows: {0}")]
+    Overflow(String),
+
+    #[error("{0} decimals is more than an amount can have")]
+    TooManyDecimals(u8),
+
+    #[error("amounts with {left} and {right} decimals don't add up")]
+    DecimalsMismatch { left: u8, right: u8 },
+}
+
+// Everything the core crate can fail with
+#[derive(Debug, Error)]
+pub enum CoreError {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+
+    #[error(transparent)]
+    Cache(#[from] CacheError),
+
+    #[error(transparent)]
+    Persistence(#[from] PersistenceError),
+
+    #[error(transparent)]
+    Logging(#[from] LoggingError),
+
+    #[error(transparent)]
+    Registry(#[from] RegistryError),
+}
@@ -0,0 +1,130 @@
+// How long a transfer waits on the chains at either end before it's settled, on top of what
+// the bridge reports for itself
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+
+use crate::{
+    config::{ChainFinality, FinalityConfig},
+    logging::LoggingManager,
+    registry::Registry,
+};
+
+// (chain, block time in ms, confirmations)
+const BUILTIN_FINALITY: &[(&str, u64, u32)] = &[
+    ("ethereum", 12_000, 64),
+    ("optimism", 2_000, 10),
+    ("bsc", 3_000, 15),
+    ("polygon", 2_000, 128),
+    ("zksync", 1_000, 10),
+    ("base", 2_000, 10),
+    ("arbitrum", 250, 20),
+    ("avalanche", 2_000, 1),
+    ("linea", 2_000, 10),
+    ("blast", 2_000, 10),
+    ("scroll", 3_000, 10),
+    ("solana", 400, 32),
+];
+
+// Block times and confirmation counts per chain key: the built-in table under the config's
+// [finality] section. Chains are looked up by key or alias.
+#[derive(Debug)]
+pub struct FinalityModel {
+    chains: HashMap<String, ChainFinality>,
+    unknown_chain: Duration,
+    registry: Registry,
+    // Unknown chains already warned about
+    warned: Mutex<HashSet<String>>,
+}
+
+impl FinalityModel {
+    pub fn builtin() -> Self {
+        Self::from_config(&FinalityConfig::default(), &Registry::builtin())
+    }
+
+    pub fn from_config(config: &FinalityConfig, registry: &Registry) -> Self {
+        let mut model = Self {
+            chains: HashMap::new(),
+            unknown_chain: config.unknown_chain,
+            registry: registry.clone(),
+            warned: Mutex::default(),
+        };
+        for (chain, block_time_ms, confirmations) in BUILTIN_FINALITY {
+            let finality = ChainFinality { block_time: Duration::from_millis(*block_time_ms), confirmations: *confirmations };
+            model.chains.insert(chain.to_string(), finality);
+        }
+        for (chain, finality) in &config.chains {
+            model.chains.insert(model.key(chain), *finality);
+        }
+        model
+    }
+
+    // Registry key for a chain name or alias; chains the registry doesn't know are lowercased
+    fn key(&self, chain: &str) -> String {
+        match self.registry.resolve_chain(chain) {
+            Ok(found) => found.key,
+            Err(_) => chain.trim().to_lowercase(),
+        }
+    }
+
+    pub fn chain(&self, chain: &str) -> Option<ChainFinality> {
+        self.chains.get(&self.key(chain)).copied()
+    }
+
+    // How long a transfer waits for its confirmations on `chain`. Chains without an entry get
+    // [finality] unknown_chain, with a warning the first time each is asked about.
+    pub fn confirmation_time(&self, chain: &str) -> Duration {
+        if let Some(finality) = self.chain(chain) {
+            return finality.confirmation_time();
+        }
+        if self.warned.lock().unwrap_or_else(PoisonError::into_inner).insert(self.key(chain)) {
+            LoggingManager.warn_with("no finality known for chain, assuming a conservative default", &[
+                ("chain", &chain),
+                ("assumed_secs", &self.unknown_chain.as_secs()),
+            ]);
+        }
+        self.unknown_chain
+    }
+
+    // Source confirmations plus destination finality
+    pub fn settlement_time(&self, src_chain: &str, dst_chain: &str) -> Duration {
+        self.confirmation_time(src_chain) + self.confirmation_time(dst_chain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ConfigFormat, ConfigManager};
+
+    #[test]
+    fn settlement_adds_both_ends() {
+        let model = FinalityModel::builtin();
+        assert_eq!(model.confirmation_time("ethereum"), Duration::from_secs(12 * 64));
+        assert_eq!(model.confirmation_time("arb1"), Duration::from_secs(5));
+        assert_eq!(model.settlement_time("ethereum", "arbitrum"), Duration::from_secs(12 * 64 + 5));
+        assert_eq!(model.settlement_time("arbitrum", "ethereum"), model.settlement_time("ethereum", "arbitrum"));
+    }
+
+    #[test]
+    fn config_overrides_the_table_and_unknown_chains_are_conservative() {
+        let config = ConfigManager::from_str(
+            "[finality]\nunknown_chain = \"20m\"\n[finality.chains.matic]\nblock_time = \"2s\"\nconfirmations = 16\n[finality.chains.atlantis]\nblock_time = \"500ms\"\nconfirmations = 4\n[bridges]\n",
+            ConfigFormat::Toml,
+        )
+        .unwrap();
+        let model = FinalityModel::from_config(&config.finality, &Registry::builtin());
+        assert_eq!(model.confirmation_time("polygon"), Duration::from_secs(32));
+        assert_eq!(model.confirmation_time("Atlantis"), Duration::from_secs(2));
+        assert_eq!(model.confirmation_time("ethereum"), Duration::from_secs(12 * 64));
+
+        // Never 0: a chain nobody describes gets the configured default, both times it's asked
+        assert_eq!(model.settlement_time("lemuria", "polygon"), Duration::from_secs(20 * 60 + 32));
+        assert_eq!(model.confirmation_time("lemuria"), Duration::from_secs(20 * 60));
+        assert_eq!(FinalityModel::builtin().confirmation_time("lemuria"), Duration::from_secs(15 * 60));
+        assert!(model.chain("lemuria").is_none());
+    }
+}
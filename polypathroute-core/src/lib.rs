@@ -1,31 +1,162 @@
+mod amount;
 mod cache;
 mod config;
+mod finality;
 mod logging;
+mod metrics;
 mod persistence;
+mod registry;
+mod secret;
 mod errors;
 
-use crate::cache::CacheManager;
-use crate::config::ConfigManager;
-pub use crate::logging::LoggingManager;
-use crate::persistence::PersistenceManager;
-use crate::errors::Errors;
+pub use crate::amount::{Amount, MAX_DECIMALS};
+pub use crate::cache::{CacheManager, CacheNamespace, CacheStats, RefreshClaim};
+pub use crate::config::{
+    AlertCondition, AlertRule, ApiFeature, ApiKeyConfig, AlertsConfig, AuditConfig, BridgeConfig, CanonicalEdge, ChainFinality, ConfidenceCombinatorKind, ConfidenceConfig, ConfigFormat, ConfigManager, DigestConfig, DiscoveryConfig, EdgeIdentityConfig, ExecutorConfig, FinalityConfig, FxConfig, FxSource, GasChainConfig, GasConfig, GlobalConfig, GraphConfig, HistoryConfig, LogFileConfig, LogFormat, LogRotation, LoggingConfig, MetricsConfig,
+    Pair, PairsFilter, PersistenceBackend, RefreshConfig, RefreshPriority, RegistryConfig, SanityBounds, SanityConfig, ServerConfig, SlippageConfig, SlippageKind, SlowOpsConfig, SourcePolicy, WeightedSource, expand_env, parse_duration,
+};
+pub use crate::finality::FinalityModel;
+pub use crate::logging::{Fields, LoggingGuard, LoggingManager, RequestContext, SlowOpGuard};
+pub use crate::metrics::MetricsManager;
+pub use crate::persistence::{
+    FileStorage, MemoryStorage, Migration, Migrator, PersistenceManager, SqliteStorage, Storage, Transaction, Versioned,
+    WriteOp,
+};
+pub use crate::registry::{ChainRef, ChainRegistry, Registry, TokenRef, TokenRegistry, checksum_address};
+pub use crate::secret::{DEFAULT_SECRET_PATTERNS, HashedSecret, REDACTED, Redacted, is_secret_key, sha256_hex};
+pub use crate::errors::{AmountError, CacheError, ConfigError, CoreError, LoggingError, PersistenceError, RegistryError};
+
+use std::sync::Arc;
 
 #[derive(Debug, Clone)]
 pub struct CoreContext {
     pub cache_manager: CacheManager,
     pub config_manager: ConfigManager,
     pub logging_manager: LoggingManager,
-    pub persisence_manager: PersistenceManager
+    pub metrics_manager: MetricsManager,
+    pub persisence_manager: PersistenceManager,
+    // Built-in chains and tokens plus the config's [registry] section
+    pub registry: Registry,
+    // Shared by clones so log files are flushed once the last one is dropped
+    logging_guard: Arc<LoggingGuard>,
 }
 
 impl CoreContext {
-    pub fn new(config_path: &str) -> Self {
-        Self {
-            cache_manager: CacheManager::new(),
-            config_manager: ConfigManager::new(config_path),
-            logging_manager: LoggingManager {  },
-            persisence_manager: PersistenceManager::new()
-        }
+    // Everything built from the config at `config_path`; see `builder` to supply components
+    pub fn new(config_path: &str) -> Result<Self, CoreError> {
+        Self::builder().config_path(config_path).build()
+    }
+
+    pub fn builder() -> CoreContextBuilder {
+        CoreContextBuilder::default()
+    }
+
+    // Flushes the log file writer at shutdown, for every clone; see LoggingGuard::flush
+    pub fn flush_logs(&self) -> bool {
+        self.logging_guard.flush()
+    }
+}
+
+// Components that aren't set are built from the config
+#[derive(Debug, Default)]
+pub struct CoreContextBuilder {
+    config_path: Option<String>,
+    config: Option<ConfigManager>,
+    cache: Option<CacheManager>,
+    persistence: Option<PersistenceManager>,
+    logging: Option<LoggingManager>,
+    metrics: Option<MetricsManager>,
+    registry: Option<Registry>,
+}
+
+impl CoreContextBuilder {
+    pub fn config_path(mut self, path: impl Into<String>) -> Self {
+        self.config_path = Some(path.into());
+        self
+    }
+
+    // Takes precedence over `config_path`
+    pub fn config(mut self, config: ConfigManager) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    pub fn cache(mut self, cache: CacheManager) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn persistence(mut self, persistence: PersistenceManager) -> Self {
+        self.persistence = Some(persistence);
+        self
+    }
+
+    // The caller takes care of the subscriber, so LoggingManager::init is skipped
+    pub fn logging(mut self, logging: LoggingManager) -> Self {
+        self.logging = Some(logging);
+        self
+    }
+
+    pub fn metrics(mut self, metrics: MetricsManager) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    // Replaces the registry built from the config
+    pub fn registry(mut self, registry: Registry) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    pub fn build(self) -> Result<CoreContext, CoreError> {
+        let config_manager = match (self.config, self.config_path) {
+            (Some(config), _) => config,
+            (None, Some(path)) => ConfigManager::load(&path)?,
+            (None, None) => return Err(ConfigError::Missing.into()),
+        };
+        let (logging_manager, logging_guard) = match self.logging {
+            Some(logging) => (logging, LoggingGuard::default()),
+            None => (LoggingManager, LoggingManager::init(&config_manager.logging)?),
+        };
+        let metrics_manager = self.metrics.unwrap_or_else(|| MetricsManager::from_config(&config_manager.metrics));
+        let persisence_manager = match self.persistence {
+            Some(persistence) => persistence,
+            None => PersistenceManager::from_config(&config_manager.global)?,
+        };
+        let cache_manager = match self.cache {
+            Some(cache) => cache,
+            None => {
+                let global = &config_manager.global;
+                let mut cache = CacheManager::with_default_ttl(global.cache_ttl.as_secs())
+                    .with_ttl_jitter(global.cache_ttl_jitter)
+                    .with_metrics(metrics_manager.clone());
+                if let Some(soft_ttl) = global.cache_soft_ttl {
+                    cache = cache.with_soft_ttl(soft_ttl);
+                }
+                if let Some(max_entries) = global.max_entries {
+                    cache = cache.with_max_entries(max_entries);
+                }
+                if global.cache_write_through {
+                    // Restored before write-through is on, so restored entries aren't written back
+                    cache.restore_from(&persisence_manager)?;
+                    cache = cache.with_write_through(persisence_manager.clone(), global.cache_flush_interval);
+                }
+                cache
+            }
+        };
+        let registry = match self.registry {
+            Some(registry) => registry,
+            None => Registry::from_config(&config_manager.registry)?,
+        };
+        Ok(CoreContext {
+            cache_manager,
+            config_manager,
+            logging_manager,
+            metrics_manager,
+            persisence_manager,
+            registry,
+            logging_guard: Arc::new(logging_guard),
+        })
     }
 }
 
@@ -36,7 +167,99 @@ mod tests {
 
     #[test]
     fn test_get_core_context() {
-        let coreVal: CoreContext = CoreContext::new("./src/config/config.toml");
-        println!("coreValue: {:?}", &coreVal);
+        let core_val: CoreContext = CoreContext::new("./src/config/config.toml").unwrap();
+        println!("coreValue: {:?}", &core_val);
+    }
+
+    #[test]
+    fn load_failures_keep_their_error_type() {
+        let missing = CoreContext::new("./src/config/missing.toml");
+        assert!(matches!(missing, Err(CoreError::Config(ConfigError::Read { .. }))));
+
+        // A persistence path that's a file can't hold the store's directory
+        let file = std::env::temp_dir().join(format!("polypath-core-persistence-file-{}", std::process::id()));
+        let config = std::env::temp_dir().join(format!("polypath-core-persistence-{}.toml", std::process::id()));
+        std::fs::write(&file, "").unwrap();
+        std::fs::write(&config, format!("[global]\npersistence_path = {:?}\n[bridges]\n", file)).unwrap();
+        let result = CoreContext::new(config.to_str().unwrap());
+        std::fs::remove_file(&file).unwrap();
+        std::fs::remove_file(&config).unwrap();
+        assert!(matches!(result, Err(CoreError::Persistence(PersistenceError::Io { .. }))));
+    }
+
+    #[test]
+    fn builder_uses_injected_components() {
+        let config = ConfigManager::from_str("[global]\nmax_entries = 5\n[bridges]\n", ConfigFormat::Toml).unwrap();
+        let cache = CacheManager::with_default_ttl(1);
+        let persistence = PersistenceManager::new();
+        persistence.store("seed".to_string(), "1".to_string()).unwrap();
+
+        let core = CoreContext::builder()
+            .config_path("./src/config/missing.toml")
+            .config(config.clone())
+            .cache(cache.clone())
+            .persistence(persistence)
+            .logging(LoggingManager)
+            .build()
+            .unwrap();
+
+        assert_eq!(core.config_manager, config);
+        core.cache_manager.set("route".to_string(), "x".to_string(), None).unwrap();
+        assert_eq!(cache.get("route".to_string()).unwrap(), Some("x".to_string()));
+        assert_eq!(core.persisence_manager.get("seed".to_string()).unwrap(), Some("1".to_string()));
+    }
+
+    #[test]
+    fn builder_builds_the_rest_from_a_config_path() {
+        let core = CoreContext::builder().config_path("./src/config/config.toml").build().unwrap();
+        assert_eq!(core.config_manager, ConfigManager::load("./src/config/config.toml").unwrap());
+        assert!(core.cache_manager.is_empty());
+        assert!(core.persisence_manager.keys_with_prefix("").unwrap().is_empty());
+
+        let missing = CoreContext::builder().config_path("./src/config/missing.toml").build();
+        assert!(matches!(missing, Err(CoreError::Config(ConfigError::Read { .. }))));
+        assert!(matches!(CoreContext::builder().build(), Err(CoreError::Config(ConfigError::Missing))));
+    }
+
+    #[test]
+    fn write_through_cache_survives_a_restart() {
+        let dir = std::env::temp_dir().join(format!("polypath-core-write-through-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let config = |write_through: bool| {
+            let source = format!(
+                "[global]\npersistence_backend = \"sqlite\"\npersistence_path = {:?}\ncache_write_through = {}\n[bridges]\n",
+                dir.join("store.db"),
+                write_through,
+            );
+            ConfigManager::from_str(&source, ConfigFormat::Toml).unwrap()
+        };
+
+        let core = CoreContext::builder().config(config(true)).build().unwrap();
+        core.cache_manager.set("route".to_string(), "ethereum->polygon".to_string(), None).unwrap();
+        drop(core);
+        let restarted = CoreContext::builder().config(config(true)).build().unwrap();
+        assert_eq!(restarted.cache_manager.get("route".to_string()).unwrap(), Some("ethereum->polygon".to_string()));
+        restarted.cache_manager.clear().unwrap();
+        drop(restarted);
+
+        let disabled = CoreContext::builder().config(config(false)).build().unwrap();
+        assert!(disabled.cache_manager.is_empty());
+        disabled.cache_manager.set("route".to_string(), "arbitrum->base".to_string(), None).unwrap();
+        disabled.cache_manager.flush().unwrap();
+        assert!(disabled.persisence_manager.keys_with_prefix("").unwrap().is_empty());
+        drop(disabled);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn clones_share_the_cache() {
+        let core = CoreContext::new("./src/config/config.toml").unwrap();
+        let clone = core.clone();
+
+        core.cache_manager.set("route".to_string(), "ethereum->polygon".to_string(), None).unwrap();
+        assert_eq!(clone.cache_manager.get("route".to_string()).unwrap(), Some("ethereum->polygon".to_string()));
+
+        clone.cache_manager.remove("route".to_string()).unwrap();
+        assert!(core.cache_manager.is_empty());
     }
 }
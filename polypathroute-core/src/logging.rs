@@ -1,31 +1,469 @@
 // Provides tracing::span log context
 
-use tracing::{event, Level};
-use anyhow::Result;
+use std::{
+    fmt::Display,
+    sync::{Mutex, PoisonError, atomic::{AtomicBool, Ordering}},
+    time::{Duration, Instant},
+};
+use tracing::{Level, Span, Subscriber, event, span::EnteredSpan};
+use tracing_appender::{non_blocking::WorkerGuard, rolling::{RollingFileAppender, Rotation}};
+use tracing_subscriber::{EnvFilter, fmt::MakeWriter};
+
+use crate::{
+    config::{LogFormat, LogRotation, LoggingConfig, SlowOpsConfig},
+    errors::LoggingError,
+};
+
+// Only the first `init` in a process installs a subscriber
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+// Ad-hoc context for an event or span, e.g. `&[("bridge", &"stargate"), ("pair", &pair)]`
+pub type Fields<'a> = &'a [(&'a str, &'a dyn Display)];
 
 #[derive(Debug, Clone)]
 pub struct LoggingManager;
 
+// One unit of work followed through the logs, e.g. quoting one pair during a refresh or
+// answering one route query. Events logged inside `span`, or in futures instrumented with it,
+// carry the trace id and the span's fields.
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    pub trace_id: String,
+    pub span: Span,
+}
+
+impl RequestContext {
+    // The trace id of a W3C `traceparent` header, "00-<32 hex trace id>-<16 hex parent id>-<2 hex flags>".
+    // None when the header is malformed or the trace id is all zeros.
+    pub fn trace_id_from_traceparent(header: &str) -> Option<String> {
+        let parts: Vec<&str> = header.trim().split('-').collect();
+        let hex = |part: &str, len: usize| part.len() == len && part.bytes().all(|byte| byte.is_ascii_hexdigit());
+        match parts.as_slice() {
+            [version, trace_id, parent_id, flags]
+                if hex(version, 2) && *version != "ff" && hex(trace_id, 32) && hex(parent_id, 16) && hex(flags, 2)
+                    && trace_id.bytes().any(|byte| byte != b'0') =>
+            {
+                Some(trace_id.to_lowercase())
+            }
+            _ => None,
+        }
+    }
+}
+
+// Keeps the file writer running; dropping it or calling `flush` flushes buffered lines
+#[derive(Debug, Default)]
+pub struct LoggingGuard {
+    writer: Mutex<Option<WorkerGuard>>,
+}
+
+impl LoggingGuard {
+    // Writes out what's buffered and stops the file writer, so lines logged after this are
+    // lost. Whether there was a file writer left to flush.
+    pub fn flush(&self) -> bool {
+        self.writer.lock().unwrap_or_else(PoisonError::into_inner).take().is_some()
+    }
+}
+
+// Times an operation from `LoggingManager::time_scope` until it's dropped, then logs how long it
+// took: a warning past the threshold, otherwise at debug
+#[derive(Debug)]
+#[must_use = "the operation is timed until the guard is dropped"]
+pub struct SlowOpGuard {
+    operation: String,
+    threshold: Duration,
+    fields: String,
+    started: Instant,
+}
+
+impl SlowOpGuard {
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
+
+impl Drop for SlowOpGuard {
+    fn drop(&mut self) {
+        emit_timed(&self.operation, self.started.elapsed(), self.threshold, &self.fields);
+    }
+}
+
 impl LoggingManager {
 
-    pub fn info(&self, value: &str) -> Result<()> {
-        event!(Level::INFO, value);
-        Ok(())
+    // Installs the process-wide subscriber. Later calls keep the first subscriber and only warn.
+    pub fn init(config: &LoggingConfig) -> Result<LoggingGuard, LoggingError> {
+        if INSTALLED.swap(true, Ordering::SeqCst) {
+            LoggingManager.warn("logging is already initialized, keeping the existing subscriber");
+            return Ok(LoggingGuard::default());
+        }
+        let installed = match &config.file {
+            Some(file) => {
+                let appender = RollingFileAppender::builder()
+                    .rotation(match file.rotation {
+                        LogRotation::Hourly => Rotation::HOURLY,
+                        LogRotation::Daily => Rotation::DAILY,
+                        LogRotation::Never => Rotation::NEVER,
+                    })
+                    .filename_prefix(&file.prefix)
+                    .filename_suffix("log")
+                    .build(&file.directory)
+                    .map_err(|err| LoggingError::File { directory: file.directory.clone(), reason: err.to_string() });
+                match appender {
+                    Ok(appender) => {
+                        let (writer, guard) = tracing_appender::non_blocking(appender);
+                        build_subscriber(config, writer, false).map(|subscriber| (subscriber, Some(guard)))
+                    }
+                    Err(err) => Err(err),
+                }
+            }
+            None => build_subscriber(config, std::io::stdout, true).map(|subscriber| (subscriber, None)),
+        };
+        let (subscriber, writer) = installed.inspect_err(|_| INSTALLED.store(false, Ordering::SeqCst))?;
+
+        if tracing::subscriber::set_global_default(subscriber).is_err() {
+            LoggingManager.warn("a tracing subscriber was installed elsewhere, keeping it");
+        }
+        Ok(LoggingGuard { writer: Mutex::new(writer) })
+    }
+
+    // Whether events at `level` pass the active filter
+    pub fn is_enabled(&self, level: Level) -> bool {
+        match level {
+            Level::TRACE => tracing::enabled!(Level::TRACE),
+            Level::DEBUG => tracing::enabled!(Level::DEBUG),
+            Level::INFO => tracing::enabled!(Level::INFO),
+            Level::WARN => tracing::enabled!(Level::WARN),
+            Level::ERROR => tracing::enabled!(Level::ERROR),
+        }
+    }
+
+    pub fn info(&self, message: &str) {
+        emit(Level::INFO, message, &[]);
     }
 
     // debug, warn, error
-    pub fn debug(&self, value: &str) -> Result<()> {
-        event!(Level::DEBUG, value);
-        Ok(())
+    pub fn debug(&self, message: &str) {
+        emit(Level::DEBUG, message, &[]);
     }
 
-    pub fn warn(&self, value: &str) -> Result<()> {
-        event!(Level::WARN, value);
-        Ok(())
+    pub fn warn(&self, message: &str) {
+        emit(Level::WARN, message, &[]);
+    }
+
+    pub fn error(&self, message: &str) {
+        emit(Level::ERROR, message, &[]);
+    }
+
+    pub fn info_with(&self, message: &str, fields: Fields) {
+        emit(Level::INFO, message, fields);
+    }
+
+    pub fn debug_with(&self, message: &str, fields: Fields) {
+        emit(Level::DEBUG, message, fields);
+    }
+
+    pub fn warn_with(&self, message: &str, fields: Fields) {
+        emit(Level::WARN, message, fields);
+    }
+
+    pub fn error_with(&self, message: &str, fields: Fields) {
+        emit(Level::ERROR, message, fields);
+    }
+
+    // Events logged while the returned guard is alive are tagged with the span
+    pub fn span(&self, name: &str) -> EnteredSpan {
+        self.span_with(name, &[])
+    }
+
+    // For async code, call `.exit()` on the guard and instrument the future with the span instead
+    pub fn span_with(&self, name: &str, fields: Fields) -> EnteredSpan {
+        match fields.is_empty() {
+            true => tracing::info_span!("polypath", span = %name).entered(),
+            false => tracing::info_span!("polypath", span = %name, fields = %render(fields)).entered(),
+        }
+    }
+
+    // A request under a fresh random trace id, see `request_with`
+    pub fn request(&self, name: &str, fields: Fields) -> RequestContext {
+        self.request_with(name, &format!("{:032x}", fastrand::u128(1..)), fields)
+    }
+
+    // A request continuing `trace_id`, e.g. one an inbound HTTP request came with. The span
+    // isn't entered: instrument futures with it, or run sync code in `span.in_scope`.
+    pub fn request_with(&self, name: &str, trace_id: &str, fields: Fields) -> RequestContext {
+        let span = match fields.is_empty() {
+            true => tracing::info_span!("polypath", span = %name, trace_id = %trace_id),
+            false => tracing::info_span!("polypath", span = %name, trace_id = %trace_id, fields = %render(fields)),
+        };
+        RequestContext { trace_id: trace_id.to_string(), span }
+    }
+
+    // Times `operation` until the guard is dropped; thresholds usually come from
+    // `SlowOpsConfig::threshold`
+    pub fn time_scope(&self, operation: &str, threshold: Duration) -> SlowOpGuard {
+        self.time_scope_with(operation, threshold, &[])
+    }
+
+    pub fn time_scope_with(&self, operation: &str, threshold: Duration, fields: Fields) -> SlowOpGuard {
+        SlowOpGuard { operation: operation.to_string(), threshold, fields: render(fields), started: Instant::now() }
+    }
+
+    // For an operation timed elsewhere, e.g. in a crate without tracing. Logs like a dropped
+    // SlowOpGuard.
+    pub fn record_duration(&self, operation: &str, elapsed: Duration, threshold: Duration, fields: Fields) {
+        emit_timed(operation, elapsed, threshold, &render(fields));
+    }
+
+    // `record_duration` with the threshold configured for `operation`
+    pub fn record_slow_op(&self, slow_ops: &SlowOpsConfig, operation: &str, elapsed: Duration, fields: Fields) {
+        self.record_duration(operation, elapsed, slow_ops.threshold(operation), fields);
+    }
+}
+
+fn emit_timed(operation: &str, elapsed: Duration, threshold: Duration, fields: &str) {
+    let (elapsed_ms, threshold_ms) = (elapsed.as_millis() as u64, threshold.as_millis() as u64);
+    macro_rules! emit_at {
+        ($level:expr, $message:expr) => {
+            match fields.is_empty() {
+                true => event!($level, operation = %operation, elapsed_ms, threshold_ms, $message),
+                false => event!($level, operation = %operation, elapsed_ms, threshold_ms, fields = %fields, $message),
+            }
+        };
+    }
+
+    match elapsed > threshold {
+        true => emit_at!(Level::WARN, "slow operation"),
+        false => emit_at!(Level::DEBUG, "operation timed"),
+    }
+}
+
+// tracing needs field names at compile time, so ad-hoc fields travel as one `fields` value
+// in `key=value` form
+fn emit(level: Level, message: &str, fields: Fields) {
+    macro_rules! emit_at {
+        ($level:expr) => {
+            match fields.is_empty() {
+                true => event!($level, "{}", message),
+                false => event!($level, fields = %render(fields), "{}", message),
+            }
+        };
+    }
+
+    match level {
+        Level::TRACE => emit_at!(Level::TRACE),
+        Level::DEBUG => emit_at!(Level::DEBUG),
+        Level::INFO => emit_at!(Level::INFO),
+        Level::WARN => emit_at!(Level::WARN),
+        Level::ERROR => emit_at!(Level::ERROR),
+    }
+}
+
+// `config.level` is the default directive and `config.filter` adds per-target ones after it
+fn build_subscriber<W>(config: &LoggingConfig, writer: W, ansi: bool) -> Result<Box<dyn Subscriber + Send + Sync>, LoggingError>
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let directives = match &config.filter {
+        Some(filter) => format!("{},{}", config.level, filter),
+        None => config.level.clone(),
+    };
+    let filter = EnvFilter::try_new(&directives)
+        .map_err(|err| LoggingError::Filter { filter: directives.clone(), reason: err.to_string() })?;
+    let builder = tracing_subscriber::fmt().with_env_filter(filter).with_writer(writer).with_ansi(ansi);
+    Ok(match config.format {
+        LogFormat::Pretty => Box::new(builder.pretty().finish()),
+        LogFormat::Json => Box::new(builder.json().finish()),
+    })
+}
+
+fn render(fields: Fields) -> String {
+    fields
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, sync::{Arc, Mutex}};
+    use serde_json::Value;
+    use super::*;
+
+    // Collects everything the subscriber writes
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Capture {
+        fn lines(&self) -> Vec<Value> {
+            String::from_utf8(self.0.lock().unwrap().clone())
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect()
+        }
+    }
+
+    fn json_config(level: &str, filter: Option<&str>) -> LoggingConfig {
+        LoggingConfig {
+            level: level.to_string(),
+            filter: filter.map(str::to_string),
+            format: LogFormat::Json,
+            file: None,
+            slow_ops: SlowOpsConfig::default(),
+        }
+    }
+
+    // Runs `f` with a subscriber built from `config` and returns the JSON lines it wrote
+    fn captured(config: &LoggingConfig, f: impl FnOnce()) -> Vec<Value> {
+        let capture = Capture::default();
+        let writer = capture.clone();
+        let subscriber = build_subscriber(config, move || writer.clone(), false).unwrap();
+        tracing::subscriber::with_default(subscriber, f);
+        capture.lines()
+    }
+
+    #[test]
+    fn json_events_carry_the_message_and_fields() {
+        let lines = captured(&json_config("info", None), || {
+            let logger = LoggingManager;
+            logger.info("cache warmed");
+            logger.warn_with("quote fetched", &[("bridge", &"stargate"), ("pair", &"ethereum->polygon"), ("attempts", &2)]);
+        });
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!((lines[0]["level"].as_str(), lines[0]["fields"]["message"].as_str()), (Some("INFO"), Some("cache warmed")));
+        assert!(lines[0]["fields"].get("fields").is_none());
+        assert_eq!(lines[1]["level"], "WARN");
+        assert_eq!(lines[1]["fields"]["message"], "quote fetched");
+        assert_eq!(lines[1]["fields"]["fields"], "bridge=stargate pair=ethereum->polygon attempts=2");
+    }
+
+    #[test]
+    fn spans_tag_the_events_inside_them() {
+        let lines = captured(&json_config("debug", None), || {
+            let logger = LoggingManager;
+            {
+                let _span = logger.span_with("fetch_quote", &[("adapter", &"across"), ("pair", &"arbitrum->base")]);
+                logger.debug("request sent");
+            }
+            logger.info("outside");
+        });
+
+        assert_eq!(lines[0]["fields"]["message"], "request sent");
+        assert_eq!(lines[0]["span"]["span"], "fetch_quote");
+        assert_eq!(lines[0]["span"]["fields"], "adapter=across pair=arbitrum->base");
+        assert_eq!(lines[1]["fields"]["message"], "outside");
+        assert!(lines[1].get("span").is_none());
+    }
+
+    #[test]
+    fn requests_tag_their_events_with_the_trace_id() {
+        let logger = LoggingManager;
+        let (first, second) = (logger.request("quote", &[("adapter", &"across")]), logger.request("quote", &[]));
+        assert_eq!(first.trace_id.len(), 32);
+        assert_ne!(first.trace_id, second.trace_id);
+
+        let lines = captured(&json_config("info", None), || {
+            let inbound = logger.request_with("route_query", "4bf92f3577b34da6a3ce929d0e0e4736", &[("from", &"base")]);
+            inbound.span.in_scope(|| logger.info("routes computed"));
+        });
+        assert_eq!(lines[0]["span"]["trace_id"], "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(lines[0]["span"]["fields"], "from=base");
+    }
+
+    #[test]
+    fn traceparent_headers_are_parsed() {
+        assert_eq!(
+            RequestContext::trace_id_from_traceparent("00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01").as_deref(),
+            Some("4bf92f3577b34da6a3ce929d0e0e4736")
+        );
+        for header in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e47zz-00f067aa0ba902b7-01",
+        ] {
+            assert_eq!(RequestContext::trace_id_from_traceparent(header), None, "{}", header);
+        }
+    }
+
+    #[test]
+    fn the_level_filter_suppresses_lower_levels() {
+        let logger = LoggingManager;
+        let mut enabled = (true, false);
+        let lines = captured(&json_config("info", None), || {
+            logger.debug("hidden");
+            logger.info("shown");
+            enabled = (logger.is_enabled(Level::DEBUG), logger.is_enabled(Level::INFO));
+        });
+        let messages: Vec<_> = lines.iter().map(|line| line["fields"]["message"].clone()).collect();
+        assert_eq!(messages, ["shown"]);
+        assert_eq!(enabled, (false, true));
+
+        // A per-target directive overrides the default level for that target
+        let lines = captured(&json_config("info", Some("polypathroute_core=debug")), || logger.debug("shown"));
+        assert_eq!(lines.len(), 1);
+
+        assert!(matches!(build_subscriber(&json_config("info", Some("polypath_dal=loud")), io::sink, false), Err(LoggingError::Filter { .. })));
+    }
+
+    #[test]
+    fn operations_past_their_threshold_warn() {
+        let logger = LoggingManager;
+        let lines = captured(&json_config("debug", None), || {
+            let _guard = logger.time_scope_with("fetch_metrics", Duration::from_millis(5), &[("adapter", &"across"), ("pair", &"ethereum->base")]);
+            std::thread::sleep(Duration::from_millis(20));
+        });
+
+        assert_eq!(lines.len(), 1);
+        assert_eq!((lines[0]["level"].as_str(), lines[0]["fields"]["message"].as_str()), (Some("WARN"), Some("slow operation")));
+        assert_eq!(lines[0]["fields"]["operation"], "fetch_metrics");
+        assert!(lines[0]["fields"]["elapsed_ms"].as_u64().unwrap() >= 20);
+        assert_eq!(lines[0]["fields"]["threshold_ms"], 5);
+        assert_eq!(lines[0]["fields"]["fields"], "adapter=across pair=ethereum->base");
+    }
+
+    #[test]
+    fn operations_within_their_threshold_log_at_debug_only() {
+        let logger = LoggingManager;
+        let slow_ops = SlowOpsConfig { default: Duration::from_secs(10), ..SlowOpsConfig::default() };
+        let timed = || {
+            let _guard = logger.time_scope("scoring", slow_ops.threshold("scoring"));
+            std::thread::sleep(Duration::from_millis(5));
+        };
+
+        let lines = captured(&json_config("debug", None), timed);
+        assert_eq!(lines.len(), 1);
+        assert_eq!((lines[0]["level"].as_str(), lines[0]["fields"]["message"].as_str()), (Some("DEBUG"), Some("operation timed")));
+        assert_eq!(lines[0]["fields"]["operation"], "scoring");
+        assert!(lines[0]["fields"].get("fields").is_none());
+        assert!(captured(&json_config("info", None), timed).is_empty());
+
+        // Durations measured elsewhere are judged the same way
+        let lines = captured(&json_config("info", None), || {
+            logger.record_slow_op(&slow_ops, "route_search", Duration::from_secs(12), &[("graph", &"default")]);
+            logger.record_slow_op(&slow_ops, "route_search", Duration::from_secs(2), &[]);
+        });
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["fields"]["elapsed_ms"], 12_000);
     }
 
-    pub fn error(&self, value: &str) -> Result<()> {
-        event!(Level::ERROR, value);
-        Ok(())
+    #[test]
+    fn init_more_than_once_only_warns() {
+        let _first = LoggingManager::init(&LoggingConfig::default()).unwrap();
+        let second = LoggingManager::init(&LoggingConfig::default()).unwrap();
+        assert!(!second.flush());
     }
-}
\ No newline at end of file
+}
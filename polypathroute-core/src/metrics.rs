@@ -0,0 +1,260 @@
+// Process metrics in the Prometheus text format, for a /metrics endpoint
+
+use std::{fmt, sync::Arc, time::{Duration, Instant}};
+use prometheus_client::{
+    encoding::{EncodeLabelSet, text::encode},
+    metrics::{counter::Counter, family::Family, gauge::Gauge, histogram::{Histogram, exponential_buckets}},
+    registry::Registry,
+};
+
+use crate::config::MetricsConfig;
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct AdapterRequestLabels {
+    adapter: String,
+    // "ok" or "error"
+    outcome: String,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct AdapterLabels {
+    adapter: String,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct ApiRequestLabels {
+    tenant: String,
+    // "admitted" or "throttled"
+    outcome: String,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct PriorityLabels {
+    priority: String,
+}
+
+type HistogramFamily<L> = Family<L, Histogram, fn() -> Histogram>;
+
+struct Metrics {
+    registry: Registry,
+    cache_hits: Counter,
+    cache_misses: Counter,
+    adapter_requests: Family<AdapterRequestLabels, Counter>,
+    routes_computed: Counter,
+    adapter_latency: HistogramFamily<AdapterLabels>,
+    route_search_duration: Histogram,
+    route_job_queued: HistogramFamily<PriorityLabels>,
+    route_job_duration: HistogramFamily<PriorityLabels>,
+    route_jobs_rejected: Family<PriorityLabels, Counter>,
+    api_requests: Family<ApiRequestLabels, Counter>,
+    graph_nodes: Gauge,
+    graph_edges_active: Gauge,
+    graph_update_queue_depth: Gauge,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let mut metrics = Self {
+            registry: Registry::with_prefix("polypath"),
+            cache_hits: Counter::default(),
+            cache_misses: Counter::default(),
+            adapter_requests: Family::default(),
+            routes_computed: Counter::default(),
+            // 10ms to ~20s
+            adapter_latency: Family::new_with_constructor(|| Histogram::new(exponential_buckets(0.01, 2.0, 12))),
+            // 100µs to ~1.6s
+            route_search_duration: Histogram::new(exponential_buckets(0.0001, 2.0, 15)),
+            // 100µs to ~6.5s
+            route_job_queued: Family::new_with_constructor(|| Histogram::new(exponential_buckets(0.0001, 2.0, 17))),
+            route_job_duration: Family::new_with_constructor(|| Histogram::new(exponential_buckets(0.0001, 2.0, 17))),
+            route_jobs_rejected: Family::default(),
+            api_requests: Family::default(),
+            graph_nodes: Gauge::default(),
+            graph_edges_active: Gauge::default(),
+            graph_update_queue_depth: Gauge::default(),
+        };
+        let registry = &mut metrics.registry;
+        registry.register("cache_hits", "Cache reads that found a live entry", metrics.cache_hits.clone());
+        registry.register("cache_misses", "Cache reads that found nothing", metrics.cache_misses.clone());
+        registry.register("adapter_requests", "Upstream quote requests by adapter and outcome", metrics.adapter_requests.clone());
+        registry.register("routes_computed", "Route searches run", metrics.routes_computed.clone());
+        registry.register("adapter_latency_seconds", "Upstream quote latency by adapter", metrics.adapter_latency.clone());
+        registry.register("route_search_duration_seconds", "Time spent in route searches", metrics.route_search_duration.clone());
+        registry.register("route_job_queued_seconds", "Time route jobs waited for a worker by priority", metrics.route_job_queued.clone());
+        registry.register("route_job_duration_seconds", "Time route jobs ran for by priority", metrics.route_job_duration.clone());
+        registry.register("route_jobs_rejected", "Route jobs turned away by a full queue by priority", metrics.route_jobs_rejected.clone());
+        registry.register("api_requests", "HTTP API requests by tenant and whether its quota let them through", metrics.api_requests.clone());
+        registry.register("graph_nodes", "Nodes in the routing graph", metrics.graph_nodes.clone());
+        registry.register("graph_edges_active", "Active edges in the routing graph", metrics.graph_edges_active.clone());
+        registry.register("graph_update_queue_depth", "Fetched quotes waiting to be applied to the routing graph", metrics.graph_update_queue_depth.clone());
+        metrics
+    }
+}
+
+// Clones record into the same metrics. A disabled manager records nothing and encodes to
+// an empty string, so instrumented code costs a branch when metrics are off.
+#[derive(Clone, Default)]
+pub struct MetricsManager {
+    inner: Option<Arc<Metrics>>,
+}
+
+impl fmt::Debug for MetricsManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MetricsManager").field("enabled", &self.is_enabled()).finish()
+    }
+}
+
+impl MetricsManager {
+    pub fn new() -> Self {
+        Self { inner: Some(Arc::new(Metrics::new())) }
+    }
+
+    pub fn disabled() -> Self {
+        Self { inner: None }
+    }
+
+    pub fn from_config(config: &MetricsConfig) -> Self {
+        match config.enabled {
+            true => Self::new(),
+            false => Self::disabled(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    pub fn record_cache_hit(&self) {
+        if let Some(metrics) = &self.inner {
+            metrics.cache_hits.inc();
+        }
+    }
+
+    pub fn record_cache_miss(&self) {
+        if let Some(metrics) = &self.inner {
+            metrics.cache_misses.inc();
+        }
+    }
+
+    // One upstream request to `adapter`, successful or not
+    pub fn record_adapter_request(&self, adapter: &str, ok: bool, latency: Duration) {
+        if let Some(metrics) = &self.inner {
+            let outcome = if ok { "ok" } else { "error" };
+            metrics.adapter_requests
+                .get_or_create(&AdapterRequestLabels { adapter: adapter.to_string(), outcome: outcome.to_string() })
+                .inc();
+            metrics.adapter_latency
+                .get_or_create(&AdapterLabels { adapter: adapter.to_string() })
+                .observe(latency.as_secs_f64());
+        }
+    }
+
+    // Runs `search`, counting it and its duration whether or not it finds a route
+    pub fn time_route_search<T>(&self, search: impl FnOnce() -> T) -> T {
+        let Some(metrics) = &self.inner else {
+            return search();
+        };
+        let started = Instant::now();
+        let result = search();
+        metrics.route_search_duration.observe(started.elapsed().as_secs_f64());
+        metrics.routes_computed.inc();
+        result
+    }
+
+    // One route job a route executor ran, after waiting `queued` for a worker
+    pub fn record_route_job(&self, priority: &str, queued: Duration, ran: Duration) {
+        if let Some(metrics) = &self.inner {
+            let labels = PriorityLabels { priority: priority.to_string() };
+            metrics.route_job_queued.get_or_create(&labels).observe(queued.as_secs_f64());
+            metrics.route_job_duration.get_or_create(&labels).observe(ran.as_secs_f64());
+        }
+    }
+
+    pub fn record_route_job_rejected(&self, priority: &str) {
+        if let Some(metrics) = &self.inner {
+            metrics.route_jobs_rejected.get_or_create(&PriorityLabels { priority: priority.to_string() }).inc();
+        }
+    }
+
+    // One HTTP API request made with `tenant`'s key, `admitted` or turned away by its quota
+    pub fn record_api_request(&self, tenant: &str, admitted: bool) {
+        if let Some(metrics) = &self.inner {
+            let outcome = if admitted { "admitted" } else { "throttled" };
+            metrics.api_requests.get_or_create(&ApiRequestLabels { tenant: tenant.to_string(), outcome: outcome.to_string() }).inc();
+        }
+    }
+
+    pub fn set_graph_size(&self, nodes: usize, active_edges: usize) {
+        if let Some(metrics) = &self.inner {
+            metrics.graph_nodes.set(nodes as i64);
+            metrics.graph_edges_active.set(active_edges as i64);
+        }
+    }
+
+    pub fn set_update_queue_depth(&self, depth: usize) {
+        if let Some(metrics) = &self.inner {
+            metrics.graph_update_queue_depth.set(depth as i64);
+        }
+    }
+
+    // Every metric in the Prometheus text exposition format
+    pub fn encode_prometheus(&self) -> String {
+        let mut encoded = String::new();
+        if let Some(metrics) = &self.inner {
+            encode(&mut encoded, &metrics.registry).expect("writing to a String cannot fail");
+        }
+        encoded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recorded_values_show_up_in_the_encoding() {
+        let metrics = MetricsManager::new();
+        let clone = metrics.clone();
+        metrics.record_cache_hit();
+        clone.record_cache_miss();
+        metrics.record_adapter_request("stargate", true, Duration::from_millis(120));
+        metrics.record_adapter_request("stargate", false, Duration::from_millis(30));
+        assert_eq!(metrics.time_route_search(|| 7), 7);
+        metrics.set_graph_size(12, 30);
+        metrics.set_update_queue_depth(3);
+        metrics.record_route_job("batch", Duration::from_millis(40), Duration::from_millis(2));
+        metrics.record_route_job_rejected("batch");
+        metrics.record_api_request("partner-a", true);
+        metrics.record_api_request("partner-a", false);
+
+        let encoded = metrics.encode_prometheus();
+        for line in [
+            "polypath_cache_hits_total 1",
+            "polypath_cache_misses_total 1",
+            "polypath_adapter_requests_total{adapter=\"stargate\",outcome=\"ok\"} 1",
+            "polypath_adapter_requests_total{adapter=\"stargate\",outcome=\"error\"} 1",
+            "polypath_adapter_latency_seconds_count{adapter=\"stargate\"} 2",
+            "polypath_routes_computed_total 1",
+            "polypath_route_search_duration_seconds_count 1",
+            "polypath_graph_nodes 12",
+            "polypath_graph_edges_active 30",
+            "polypath_graph_update_queue_depth 3",
+            "polypath_route_job_queued_seconds_count{priority=\"batch\"} 1",
+            "polypath_route_job_duration_seconds_count{priority=\"batch\"} 1",
+            "polypath_route_jobs_rejected_total{priority=\"batch\"} 1",
+            "polypath_api_requests_total{tenant=\"partner-a\",outcome=\"admitted\"} 1",
+            "polypath_api_requests_total{tenant=\"partner-a\",outcome=\"throttled\"} 1",
+        ] {
+            assert!(encoded.lines().any(|encoded| encoded == line), "missing `{}` in\n{}", line, encoded);
+        }
+    }
+
+    #[test]
+    fn disabled_metrics_record_nothing() {
+        let metrics = MetricsManager::from_config(&MetricsConfig { enabled: false });
+        metrics.record_cache_hit();
+        assert_eq!(metrics.time_route_search(|| "route"), "route");
+        assert!(!metrics.is_enabled());
+        assert_eq!(metrics.encode_prometheus(), "");
+    }
+}
@@ -0,0 +1,209 @@
+// One JSON file per key in a directory
+
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+use serde::{Deserialize, Serialize};
+
+use super::{Storage, WriteOp};
+use crate::{errors::PersistenceError, secret::sha256_hex};
+
+// Distinguishes temp files of concurrent writers within one process
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+// Keys up to this many bytes are named by their hex; longer ones by a hash, keeping file and
+// temp names well under NAME_MAX
+const MAX_HEX_KEY_LEN: usize = 64;
+
+// On-disk form of one entry. The key is kept alongside the value so a file can be checked
+// against the name it was found under, and so hashed names can be listed.
+#[derive(Serialize, Deserialize)]
+struct Record {
+    key: String,
+    value: String,
+}
+
+// Each entry is `<hex of key>.json`, or `_<sha256 of key>.json` for long keys. Single writes are atomic; a batch is applied one write at
+// a time, so a crash part way through leaves the writes before it in place.
+#[derive(Debug)]
+pub struct FileStorage {
+    dir: PathBuf,
+}
+
+impl FileStorage {
+    // Store backed by `dir`, created if missing
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self, PersistenceError> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).map_err(|source| PersistenceError::io(&dir, source))?;
+        Ok(Self { dir })
+    }
+}
+
+impl Storage for FileStorage {
+    fn get(&self, key: &str) -> Result<Option<String>, PersistenceError> {
+        let path = entry_path(&self.dir, key);
+        match fs::read(&path) {
+            Ok(bytes) => read_record(&path, &bytes, key).map(Some),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(source) => Err(PersistenceError::io(&path, source)),
+        }
+    }
+
+    // The value is written to a temp file and renamed into place, so readers see either the
+    // old or the new value, never a partial one
+    fn put(&self, key: &str, value: &str) -> Result<(), PersistenceError> {
+        let path = entry_path(&self.dir, key);
+        let temp = self.dir.join(format!(
+            ".{}.{}-{}.tmp",
+            file_stem(key),
+            std::process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let encoded = serde_json::to_vec(&Record { key: key.to_string(), value: value.to_string() })
+            .map_err(|err| PersistenceError::Corrupt { path: path.clone(), reason: err.to_string() })?;
+
+        let write = || -> std::io::Result<()> {
+            let mut file = fs::File::create(&temp)?;
+            file.write_all(&encoded)?;
+            file.sync_all()?;
+            fs::rename(&temp, &path)
+        };
+        if let Err(source) = write() {
+            let _ = fs::remove_file(&temp);
+            return Err(PersistenceError::io(&path, source));
+        }
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<bool, PersistenceError> {
+        let path = entry_path(&self.dir, key);
+        match fs::remove_file(&path) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(source) => Err(PersistenceError::io(&path, source)),
+        }
+    }
+
+    fn scan_prefix(&self, prefix: &str) -> Result<Vec<(String, String)>, PersistenceError> {
+        let mut entries: Vec<(String, String)> = read_entries(&self.dir)?
+            .into_iter()
+            .filter(|(key, _)| key.starts_with(prefix))
+            .collect();
+        entries.sort();
+        Ok(entries)
+    }
+
+    fn apply(&self, batch: &[WriteOp]) -> Result<(), PersistenceError> {
+        for op in batch {
+            match op {
+                WriteOp::Put { key, value } => self.put(key, value)?,
+                WriteOp::Delete { key } => {
+                    self.delete(key)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn entry_path(dir: &Path, key: &str) -> PathBuf {
+    dir.join(format!("{}.json", file_stem(key)))
+}
+
+// Keys may contain anything, including path separators, so names carry them hex-encoded. The
+// `_` keeps hashed names apart from the hex of a 32-byte key.
+fn file_stem(key: &str) -> String {
+    match key.len() <= MAX_HEX_KEY_LEN {
+        true => hex_encode(key),
+        false => format!("_{}", sha256_hex(key.as_bytes())),
+    }
+}
+
+// Key and value of every entry file in `dir`, taken from the record since hashed names can't
+// be decoded; other files, such as temp files, are ignored
+fn read_entries(dir: &Path) -> Result<Vec<(String, String)>, PersistenceError> {
+    let entries = fs::read_dir(dir).map_err(|source| PersistenceError::io(dir, source))?;
+    let mut records = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|source| PersistenceError::io(dir, source))?;
+        let path = entry.path();
+        if entry.file_name().to_str().is_none_or(|name| name.starts_with('.') || !name.ends_with(".json")) {
+            continue;
+        }
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            // Deleted since the directory was listed
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(source) => return Err(PersistenceError::io(&path, source)),
+        };
+        let record = decode_record(&path, &bytes)?;
+        if path != entry_path(dir, &record.key) {
+            return Err(PersistenceError::Corrupt {
+                path,
+                reason: format!("holds key `{}`, which belongs in another file", record.key),
+            });
+        }
+        records.push((record.key, record.value));
+    }
+    Ok(records)
+}
+
+fn decode_record(path: &Path, bytes: &[u8]) -> Result<Record, PersistenceError> {
+    serde_json::from_slice(bytes)
+        .map_err(|err| PersistenceError::Corrupt { path: path.to_path_buf(), reason: err.to_string() })
+}
+
+fn read_record(path: &Path, bytes: &[u8], key: &str) -> Result<String, PersistenceError> {
+    let record = decode_record(path, bytes)?;
+    if record.key != key {
+        return Err(PersistenceError::Corrupt {
+            path: path.to_path_buf(),
+            reason: format!("holds key `{}`, expected `{}`", record.key, key),
+        });
+    }
+    Ok(record.value)
+}
+
+fn hex_encode(key: &str) -> String {
+    key.bytes().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistence::tests::temp_dir;
+
+    #[test]
+    fn corrupt_files_are_reported() {
+        let dir = temp_dir("corrupt");
+        let store = FileStorage::open(&dir).unwrap();
+        store.put("graph", "ok").unwrap();
+        fs::write(entry_path(&dir, "graph"), b"{\"key\": \"graph\", \"val").unwrap();
+        fs::write(entry_path(&dir, "moved"), serde_json::to_vec(&Record { key: "other".to_string(), value: String::new() }).unwrap()).unwrap();
+
+        assert!(matches!(store.get("graph"), Err(PersistenceError::Corrupt { .. })));
+        assert!(matches!(store.get("moved"), Err(PersistenceError::Corrupt { .. })));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn long_keys_are_stored_under_hashed_names() {
+        let dir = temp_dir("long-keys");
+        let store = FileStorage::open(&dir).unwrap();
+        let key = format!("edge_history/{}/raw/000000000042", "stargate:ethereum:0xa0b8->arbitrum:0xaf88/".repeat(6));
+        assert!(key.len() > 200);
+        store.put(&key, "[1]").unwrap();
+        store.put("edge_history/short", "[2]").unwrap();
+
+        let reopened = FileStorage::open(&dir).unwrap();
+        assert_eq!(reopened.get(&key).unwrap(), Some("[1]".to_string()));
+        assert_eq!(reopened.keys_with_prefix("edge_history/").unwrap(), ["edge_history/short".to_string(), key.clone()]);
+        assert_eq!(reopened.scan_prefix("edge_history/stargate").unwrap(), [(key.clone(), "[1]".to_string())]);
+        assert!(reopened.delete(&key).unwrap());
+        assert_eq!(reopened.get(&key).unwrap(), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,66 @@
+// Nothing survives the process, for setups without a persistence_path
+
+use std::{
+    collections::BTreeMap,
+    sync::{PoisonError, RwLock, RwLockWriteGuard},
+};
+
+use super::{Storage, WriteOp};
+use crate::errors::PersistenceError;
+
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    entries: RwLock<BTreeMap<String, String>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // A writer that panicked can't leave an entry half-written, so a poisoned lock is still usable
+    fn write(&self) -> RwLockWriteGuard<'_, BTreeMap<String, String>> {
+        self.entries.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn get(&self, key: &str) -> Result<Option<String>, PersistenceError> {
+        Ok(self.entries.read().unwrap_or_else(PoisonError::into_inner).get(key).cloned())
+    }
+
+    fn put(&self, key: &str, value: &str) -> Result<(), PersistenceError> {
+        self.write().insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<bool, PersistenceError> {
+        Ok(self.write().remove(key).is_some())
+    }
+
+    fn scan_prefix(&self, prefix: &str) -> Result<Vec<(String, String)>, PersistenceError> {
+        Ok(self.entries
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .range(prefix.to_string()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect())
+    }
+
+    // Under one write lock, so readers see all of the batch or none of it
+    fn apply(&self, batch: &[WriteOp]) -> Result<(), PersistenceError> {
+        let mut entries = self.write();
+        for op in batch {
+            match op {
+                WriteOp::Put { key, value } => {
+                    entries.insert(key.clone(), value.clone());
+                }
+                WriteOp::Delete { key } => {
+                    entries.remove(key);
+                }
+            }
+        }
+        Ok(())
+    }
+}
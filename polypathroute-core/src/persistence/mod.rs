@@ -0,0 +1,391 @@
+// Simple K/V store for data snapshots, over a pluggable storage backend
+
+mod files;
+mod memory;
+mod schema;
+mod sqlite;
+
+use std::{fmt, path::PathBuf, sync::Arc};
+
+use crate::{
+    config::{GlobalConfig, PersistenceBackend},
+    errors::{ConfigError, CoreError, PersistenceError},
+};
+
+pub use files::FileStorage;
+pub use memory::MemoryStorage;
+pub use schema::{Migration, Migrator, Versioned};
+pub use sqlite::SqliteStorage;
+
+// One write of a batch passed to Storage::apply
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WriteOp {
+    Put { key: String, value: String },
+    Delete { key: String },
+}
+
+// A backend of the persistence store. Keys and values are plain strings; callers encode
+// structured values themselves, usually as JSON.
+pub trait Storage: Send + Sync + fmt::Debug {
+    fn get(&self, key: &str) -> Result<Option<String>, PersistenceError>;
+
+    // Replaces any existing value
+    fn put(&self, key: &str, value: &str) -> Result<(), PersistenceError>;
+
+    // Whether there was a value to delete
+    fn delete(&self, key: &str) -> Result<bool, PersistenceError>;
+
+    // Entries whose key starts with `prefix`, sorted by key
+    fn scan_prefix(&self, prefix: &str) -> Result<Vec<(String, String)>, PersistenceError>;
+
+    // Keys starting with `prefix`, sorted. Backends that can list keys without reading values
+    // should override this.
+    fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>, PersistenceError> {
+        Ok(self.scan_prefix(prefix)?.into_iter().map(|(key, _)| key).collect())
+    }
+
+    // Applies the writes in order. How atomic a batch is depends on the backend.
+    fn apply(&self, batch: &[WriteOp]) -> Result<(), PersistenceError>;
+}
+
+impl dyn Storage + '_ {
+    // Runs `f` with a Transaction that collects its writes, then applies them as one batch if
+    // `f` returns Ok. Nothing is written when it returns an error.
+    pub fn transaction<T>(
+        &self,
+        f: impl FnOnce(&mut Transaction<'_>) -> Result<T, PersistenceError>,
+    ) -> Result<T, PersistenceError> {
+        let mut transaction = Transaction { storage: self, writes: Vec::new() };
+        let result = f(&mut transaction)?;
+        if !transaction.writes.is_empty() {
+            self.apply(&transaction.writes)?;
+        }
+        Ok(result)
+    }
+}
+
+// Reads see the transaction's own writes on top of the store. Other writers aren't locked out,
+// so a value read here may have changed by the time the batch is applied.
+pub struct Transaction<'a> {
+    storage: &'a dyn Storage,
+    writes: Vec<WriteOp>,
+}
+
+impl Transaction<'_> {
+    pub fn get(&self, key: &str) -> Result<Option<String>, PersistenceError> {
+        let pending = self.writes.iter().rev().find_map(|op| match op {
+            WriteOp::Put { key: written, value } if written == key => Some(Some(value.clone())),
+            WriteOp::Delete { key: deleted } if deleted == key => Some(None),
+            _ => None,
+        });
+        match pending {
+            Some(value) => Ok(value),
+            None => self.storage.get(key),
+        }
+    }
+
+    pub fn put(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.writes.push(WriteOp::Put { key: key.into(), value: value.into() });
+    }
+
+    pub fn delete(&mut self, key: impl Into<String>) {
+        self.writes.push(WriteOp::Delete { key: key.into() });
+    }
+}
+
+// Clones share one backend and one set of migrations
+#[derive(Debug, Clone)]
+pub struct PersistenceManager {
+    storage: Arc<dyn Storage>,
+    migrator: Migrator,
+}
+
+impl Default for PersistenceManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PersistenceManager {
+
+    // In-memory store; use `open` or `sqlite` for one that survives restarts
+    pub fn new() -> Self {
+        Self::from_storage(MemoryStorage::new())
+    }
+
+    // One file per key in `dir`, created if missing
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self, PersistenceError> {
+        Ok(Self::from_storage(FileStorage::open(dir)?))
+    }
+
+    // SQLite database at `path`, created if missing
+    pub fn sqlite(path: impl Into<PathBuf>) -> Result<Self, PersistenceError> {
+        Ok(Self::from_storage(SqliteStorage::open(path)?))
+    }
+
+    pub fn from_storage(storage: impl Storage + 'static) -> Self {
+        Self { storage: Arc::new(storage), migrator: Migrator::new() }
+    }
+
+    // The backend picked by global.persistence_backend. Without one, a persistence_path means
+    // files and no path means memory.
+    pub fn from_config(config: &GlobalConfig) -> Result<Self, CoreError> {
+        let path = config.persistence_path.as_ref();
+        let backend = config.persistence_backend.unwrap_or(match path {
+            Some(_) => PersistenceBackend::Files,
+            None => PersistenceBackend::Memory,
+        });
+        match (backend, path) {
+            (PersistenceBackend::Memory, _) => Ok(Self::new()),
+            (PersistenceBackend::Files, Some(path)) => Ok(Self::open(path)?),
+            (PersistenceBackend::Sqlite, Some(path)) => Ok(Self::sqlite(path)?),
+            (backend, None) => Err(ConfigError::Invalid {
+                path: PathBuf::new(),
+                key: "global.persistence_path".to_string(),
+                message: format!("must be set for the `{}` persistence backend", backend),
+            }
+            .into()),
+        }
+    }
+
+    pub fn storage(&self) -> &dyn Storage {
+        self.storage.as_ref()
+    }
+
+    // Where each schema registers its upgrades; see `get_typed`
+    pub fn migrator(&self) -> &Migrator {
+        &self.migrator
+    }
+
+    // Stores `value` in an envelope with T's schema and version
+    pub fn put_typed<T: Versioned>(&self, key: &str, value: &T) -> Result<(), PersistenceError> {
+        self.storage.put(key, &self.encode_typed(key, value)?)
+    }
+
+    // Reads a value stored by `put_typed`, upgrading it from an older version through the
+    // registered migrations. Versions newer than T::VERSION fail with UnsupportedVersion.
+    pub fn get_typed<T: Versioned>(&self, key: &str) -> Result<Option<T>, PersistenceError> {
+        match self.storage.get(key)? {
+            Some(encoded) => self.decode_typed(key, &encoded).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    // For values written in batches or read by a scan
+    pub fn encode_typed<T: Versioned>(&self, key: &str, value: &T) -> Result<String, PersistenceError> {
+        self.migrator.encode(key, value)
+    }
+
+    pub fn decode_typed<T: Versioned>(&self, key: &str, encoded: &str) -> Result<T, PersistenceError> {
+        self.migrator.decode(key, encoded)
+    }
+
+    pub fn store(&self, key: String, value: String) -> Result<bool, PersistenceError> {
+        self.storage.put(&key, &value)?;
+        Ok(true)
+    }
+
+    pub fn get(&self, key: String) -> Result<Option<String>, PersistenceError> {
+        self.storage.get(&key)
+    }
+
+    // Whether there was a value to delete
+    pub fn delete(&self, key: String) -> Result<bool, PersistenceError> {
+        self.storage.delete(&key)
+    }
+
+    // Stored keys starting with `prefix`, sorted
+    pub fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>, PersistenceError> {
+        self.storage.keys_with_prefix(prefix)
+    }
+
+    // Stored entries whose key starts with `prefix`, sorted by key
+    pub fn scan_prefix(&self, prefix: &str) -> Result<Vec<(String, String)>, PersistenceError> {
+        self.storage.scan_prefix(prefix)
+    }
+
+    // See `Storage::transaction`
+    pub fn transaction<T>(
+        &self,
+        f: impl FnOnce(&mut Transaction<'_>) -> Result<T, PersistenceError>,
+    ) -> Result<T, PersistenceError> {
+        self.storage().transaction(f)
+    }
+
+    // Deletes every entry and returns how many there were
+    pub fn clear_all(&self) -> Result<usize, PersistenceError> {
+        let keys = self.storage.keys_with_prefix("")?;
+        let batch: Vec<WriteOp> = keys.iter().map(|key| WriteOp::Delete { key: key.clone() }).collect();
+        self.storage.apply(&batch)?;
+        Ok(keys.len())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    pub(crate) fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("polypath-persistence-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    // The behaviour every backend shares. `open` gives a store over the same data each time it's
+    // called when the backend is durable.
+    fn check_backend(open: impl Fn() -> PersistenceManager, durable: bool) {
+        let first = open();
+        first.store("snapshot/graph".to_string(), "{\"version\":3}".to_string()).unwrap();
+        first.store("snapshot/quotes".to_string(), "[]".to_string()).unwrap();
+        first.store("snapshot/quotes".to_string(), "[1]".to_string()).unwrap();
+        first.store("meta".to_string(), "1".to_string()).unwrap();
+        first.store("ünïcode/key".to_string(), "ü".to_string()).unwrap();
+        let store = match durable {
+            true => {
+                drop(first);
+                open()
+            }
+            false => first,
+        };
+
+        assert_eq!(store.get("snapshot/graph".to_string()).unwrap(), Some("{\"version\":3}".to_string()));
+        assert_eq!(store.get("missing".to_string()).unwrap(), None);
+        assert_eq!(store.keys_with_prefix("snapshot/").unwrap(), ["snapshot/graph", "snapshot/quotes"]);
+        assert_eq!(
+            store.scan_prefix("snapshot/q").unwrap(),
+            [("snapshot/quotes".to_string(), "[1]".to_string())]
+        );
+        assert_eq!(store.scan_prefix("ünï").unwrap().len(), 1);
+
+        assert!(store.delete("meta".to_string()).unwrap());
+        assert!(!store.delete("meta".to_string()).unwrap());
+
+        let moved = store.transaction(|tx| {
+            let graph = tx.get("snapshot/graph")?.unwrap();
+            tx.put("archive/graph", graph);
+            tx.delete("snapshot/graph");
+            assert_eq!(tx.get("snapshot/graph")?, None);
+            tx.get("archive/graph")
+        });
+        assert_eq!(moved.unwrap(), Some("{\"version\":3}".to_string()));
+        assert_eq!(store.keys_with_prefix("").unwrap(), ["archive/graph", "snapshot/quotes", "ünïcode/key"]);
+
+        let failed: Result<(), _> = store.transaction(|tx| {
+            tx.put("archive/other", "x");
+            Err(PersistenceError::Corrupt { path: PathBuf::from("test"), reason: "abort".to_string() })
+        });
+        assert!(failed.is_err());
+        assert_eq!(store.get("archive/other".to_string()).unwrap(), None);
+
+        assert_eq!(store.clear_all().unwrap(), 3);
+        assert!(store.keys_with_prefix("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn file_backend() {
+        let dir = temp_dir("files");
+        check_backend(|| PersistenceManager::open(&dir).unwrap(), true);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn sqlite_backend() {
+        let dir = temp_dir("sqlite");
+        check_backend(|| PersistenceManager::sqlite(dir.join("store.db")).unwrap(), true);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn memory_backend() {
+        let store = PersistenceManager::new();
+        check_backend(|| store.clone(), false);
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Quote {
+        bridge: String,
+        fee_usd: f64,
+    }
+
+    impl Versioned for Quote {
+        const SCHEMA: &'static str = "quote";
+        const VERSION: u32 = 2;
+    }
+
+    fn rename_fee(mut payload: serde_json::Value) -> Result<serde_json::Value, String> {
+        let object = payload.as_object_mut().ok_or("not an object")?;
+        let fee = object.remove("fee").ok_or("no `fee` field")?;
+        object.insert("fee_usd".to_string(), fee);
+        Ok(payload)
+    }
+
+    #[test]
+    fn older_versions_are_migrated_on_read() {
+        let store = PersistenceManager::new();
+        store.store("v1".to_string(), r#"{"schema":"quote","version":1,"payload":{"bridge":"stargate","fee":1.5}}"#.to_string()).unwrap();
+        // Written before values had an envelope
+        store.store("legacy".to_string(), r#"{"bridge":"across","fee":0.5}"#.to_string()).unwrap();
+
+        let missing = store.get_typed::<Quote>("v1");
+        assert!(matches!(missing, Err(PersistenceError::Migration { from: 1, .. })), "{:?}", missing);
+
+        store.migrator().register("quote", 1, rename_fee);
+        let quote = Quote { bridge: "stargate".to_string(), fee_usd: 1.5 };
+        assert_eq!(store.get_typed::<Quote>("v1").unwrap(), Some(quote));
+        assert_eq!(store.get_typed::<Quote>("legacy").unwrap().unwrap().fee_usd, 0.5);
+        assert_eq!(store.get_typed::<Quote>("missing").unwrap(), None);
+
+        let current = Quote { bridge: "hop".to_string(), fee_usd: 2.0 };
+        store.put_typed("v2", &current).unwrap();
+        assert_eq!(store.get("v2".to_string()).unwrap().unwrap(), r#"{"schema":"quote","version":2,"payload":{"bridge":"hop","fee_usd":2.0}}"#);
+        assert_eq!(store.get_typed::<Quote>("v2").unwrap(), Some(current));
+
+        store.migrator().register("quote", 1, |_| Err("lost the fee".to_string()));
+        let failed = store.get_typed::<Quote>("v1");
+        assert!(matches!(failed, Err(PersistenceError::Migration { reason, .. }) if reason == "lost the fee"));
+    }
+
+    #[test]
+    fn newer_versions_and_other_schemas_are_rejected() {
+        let store = PersistenceManager::new();
+        store.store("v3".to_string(), r#"{"schema":"quote","version":3,"payload":{"bridge":"hop","fee_usd":2.0,"eta":60}}"#.to_string()).unwrap();
+        store.store("profile".to_string(), r#"{"schema":"profile","version":1,"payload":{}}"#.to_string()).unwrap();
+        store.store("garbage".to_string(), "not json".to_string()).unwrap();
+
+        let newer = store.get_typed::<Quote>("v3");
+        assert!(
+            matches!(&newer, Err(PersistenceError::UnsupportedVersion { found: 3, max_supported: 2, .. })),
+            "{:?}",
+            newer
+        );
+        assert!(matches!(store.get_typed::<Quote>("profile"), Err(PersistenceError::SchemaMismatch { .. })));
+        assert!(matches!(store.get_typed::<Quote>("garbage"), Err(PersistenceError::Decode { .. })));
+    }
+
+    #[test]
+    fn backend_follows_config() {
+        let dir = temp_dir("config");
+        let config = |backend: Option<PersistenceBackend>, path: Option<PathBuf>| GlobalConfig {
+            persistence_backend: backend,
+            persistence_path: path,
+            ..GlobalConfig::default()
+        };
+
+        let sqlite = PersistenceManager::from_config(&config(Some(PersistenceBackend::Sqlite), Some(dir.join("store.db")))).unwrap();
+        sqlite.store("key".to_string(), "value".to_string()).unwrap();
+        assert!(dir.join("store.db").is_file());
+
+        let files = PersistenceManager::from_config(&config(None, Some(dir.join("files")))).unwrap();
+        files.store("key".to_string(), "value".to_string()).unwrap();
+        assert!(dir.join("files").is_dir());
+
+        let memory = PersistenceManager::from_config(&config(Some(PersistenceBackend::Memory), Some(dir.join("unused")))).unwrap();
+        memory.store("key".to_string(), "value".to_string()).unwrap();
+        assert!(!dir.join("unused").exists());
+
+        let missing = PersistenceManager::from_config(&config(Some(PersistenceBackend::Sqlite), None));
+        assert!(matches!(missing, Err(CoreError::Config(ConfigError::Invalid { .. }))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,98 @@
+// Stored values are wrapped in an envelope naming their schema and version, so a value written
+// by an older build can be upgraded on read instead of failing to decode
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
+
+use crate::errors::PersistenceError;
+
+// A type stored through PersistenceManager::put_typed. Bump VERSION when its encoding changes
+// and register a migration from the previous version.
+pub trait Versioned: Serialize + DeserializeOwned {
+    const SCHEMA: &'static str;
+    const VERSION: u32;
+}
+
+// Upgrades a payload from one version to the next
+pub type Migration = Arc<dyn Fn(Value) -> Result<Value, String> + Send + Sync>;
+
+#[derive(Serialize)]
+struct Envelope<'a, T> {
+    schema: &'a str,
+    version: u32,
+    payload: &'a T,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StoredEnvelope {
+    schema: String,
+    version: u32,
+    payload: Value,
+}
+
+// Upgrade functions by schema and the version they upgrade from. Clones share them.
+#[derive(Clone, Default)]
+pub struct Migrator {
+    migrations: Arc<RwLock<HashMap<(String, u32), Migration>>>,
+}
+
+impl fmt::Debug for Migrator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut registered: Vec<_> = self.migrations.read().unwrap_or_else(PoisonError::into_inner).keys().cloned().collect();
+        registered.sort();
+        f.debug_struct("Migrator").field("migrations", &registered).finish()
+    }
+}
+
+impl Migrator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Registers the upgrade of `schema` payloads from `from` to `from + 1`, replacing any earlier one
+    pub fn register(
+        &self,
+        schema: &str,
+        from: u32,
+        upgrade: impl Fn(Value) -> Result<Value, String> + Send + Sync + 'static,
+    ) {
+        self.migrations.write().unwrap_or_else(PoisonError::into_inner).insert((schema.to_string(), from), Arc::new(upgrade));
+    }
+
+    pub fn encode<T: Versioned>(&self, key: &str, value: &T) -> Result<String, PersistenceError> {
+        let envelope = Envelope { schema: T::SCHEMA, version: T::VERSION, payload: value };
+        serde_json::to_string(&envelope).map_err(|source| PersistenceError::Encode { key: key.to_string(), source })
+    }
+
+    // Reads an envelope written by this or an older build, upgrading its payload to T::VERSION.
+    // Values stored before envelopes existed are read as version 1.
+    pub fn decode<T: Versioned>(&self, key: &str, encoded: &str) -> Result<T, PersistenceError> {
+        let decode_error = |source| PersistenceError::Decode { key: key.to_string(), source };
+        let value: Value = serde_json::from_str(encoded).map_err(decode_error)?;
+        let (schema, version, mut payload) = match serde_json::from_value::<StoredEnvelope>(value.clone()) {
+            Ok(envelope) => (envelope.schema, envelope.version, envelope.payload),
+            Err(_) => (T::SCHEMA.to_string(), 1, value),
+        };
+        if schema != T::SCHEMA {
+            return Err(PersistenceError::SchemaMismatch { key: key.to_string(), expected: T::SCHEMA.to_string(), found: schema });
+        }
+        if version > T::VERSION {
+            return Err(PersistenceError::UnsupportedVersion { schema, found: version, max_supported: T::VERSION });
+        }
+        for from in version..T::VERSION {
+            let migration = self.migrations.read().unwrap_or_else(PoisonError::into_inner).get(&(schema.clone(), from)).cloned();
+            let Some(migration) = migration else {
+                return Err(PersistenceError::Migration { schema, from, reason: "no migration is registered".to_string() });
+            };
+            payload = migration(payload).map_err(|reason| PersistenceError::Migration { schema: schema.clone(), from, reason })?;
+        }
+        serde_json::from_value(payload).map_err(decode_error)
+    }
+}
@@ -0,0 +1,136 @@
+// A single SQLite database file, for data that needs ordered scans or atomic batches
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+use rusqlite::{Connection, OptionalExtension, params};
+
+use super::{Storage, WriteOp};
+use crate::errors::PersistenceError;
+
+// Applied in order to databases whose user_version is below their position; never edit one that
+// has shipped, append a new one instead
+const MIGRATIONS: [&str; 1] = [
+    "CREATE TABLE entries (key TEXT PRIMARY KEY NOT NULL, value TEXT NOT NULL) WITHOUT ROWID;",
+];
+
+#[derive(Debug)]
+pub struct SqliteStorage {
+    path: PathBuf,
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    // Opens or creates the database at `path` in WAL mode and brings its schema up to date
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, PersistenceError> {
+        let path = path.into();
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).map_err(|source| PersistenceError::io(parent, source))?;
+        }
+        let error = |source| PersistenceError::Sqlite { path: path.clone(), source };
+
+        let mut connection = Connection::open(&path).map_err(error)?;
+        connection.query_row("PRAGMA journal_mode = WAL", [], |row| row.get::<_, String>(0)).map_err(error)?;
+        migrate(&mut connection).map_err(error)?;
+        Ok(Self { path, connection: Mutex::new(connection) })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // A statement that panicked was rolled back by SQLite, so a poisoned lock is still usable
+    fn connection(&self) -> MutexGuard<'_, Connection> {
+        self.connection.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn error(&self, source: rusqlite::Error) -> PersistenceError {
+        PersistenceError::Sqlite { path: self.path.clone(), source }
+    }
+}
+
+fn migrate(connection: &mut Connection) -> rusqlite::Result<()> {
+    let version: i64 = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    let transaction = connection.transaction()?;
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", index as i64 + 1)?;
+    }
+    transaction.commit()
+}
+
+impl Storage for SqliteStorage {
+    fn get(&self, key: &str) -> Result<Option<String>, PersistenceError> {
+        self.connection()
+            .query_row("SELECT value FROM entries WHERE key = ?1", params![key], |row| row.get(0))
+            .optional()
+            .map_err(|err| self.error(err))
+    }
+
+    fn put(&self, key: &str, value: &str) -> Result<(), PersistenceError> {
+        self.connection()
+            .execute("INSERT OR REPLACE INTO entries (key, value) VALUES (?1, ?2)", params![key, value])
+            .map(|_| ())
+            .map_err(|err| self.error(err))
+    }
+
+    fn delete(&self, key: &str) -> Result<bool, PersistenceError> {
+        self.connection()
+            .execute("DELETE FROM entries WHERE key = ?1", params![key])
+            .map(|deleted| deleted > 0)
+            .map_err(|err| self.error(err))
+    }
+
+    fn scan_prefix(&self, prefix: &str) -> Result<Vec<(String, String)>, PersistenceError> {
+        let connection = self.connection();
+        let scan = || -> rusqlite::Result<Vec<(String, String)>> {
+            let mut statement = connection.prepare_cached(
+                "SELECT key, value FROM entries WHERE substr(key, 1, length(?1)) = ?1 ORDER BY key",
+            )?;
+            let rows = statement.query_map(params![prefix], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect()
+        };
+        scan().map_err(|err| self.error(err))
+    }
+
+    fn apply(&self, batch: &[WriteOp]) -> Result<(), PersistenceError> {
+        let mut connection = self.connection();
+        let mut apply = || -> rusqlite::Result<()> {
+            let transaction = connection.transaction()?;
+            for op in batch {
+                match op {
+                    WriteOp::Put { key, value } => {
+                        transaction.execute("INSERT OR REPLACE INTO entries (key, value) VALUES (?1, ?2)", params![key, value])?;
+                    }
+                    WriteOp::Delete { key } => {
+                        transaction.execute("DELETE FROM entries WHERE key = ?1", params![key])?;
+                    }
+                }
+            }
+            transaction.commit()
+        };
+        apply().map_err(|err| self.error(err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistence::tests::temp_dir;
+
+    #[test]
+    fn reopening_keeps_the_schema_version() {
+        let dir = temp_dir("sqlite-schema");
+        let path = dir.join("store.db");
+        drop(SqliteStorage::open(&path).unwrap());
+
+        let store = SqliteStorage::open(&path).unwrap();
+        let connection = store.connection();
+        let version: i64 = connection.pragma_query_value(None, "user_version", |row| row.get(0)).unwrap();
+        let mode: String = connection.pragma_query_value(None, "journal_mode", |row| row.get(0)).unwrap();
+        assert_eq!((version, mode.as_str()), (MIGRATIONS.len() as i64, "wal"));
+        drop(connection);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
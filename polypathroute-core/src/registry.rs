@@ -0,0 +1,407 @@
+// Canonical chain keys and token addresses. Chains are found by key, alias or EVM chain id and
+// tokens by symbol or address, all case-insensitively; addresses come back EIP-55 checksummed.
+
+use serde::{Deserialize, Serialize};
+use tiny_keccak::{Hasher, Keccak};
+
+use crate::{amount::Amount, config::RegistryConfig, errors::{AmountError, RegistryError}};
+
+// (key, EVM chain id, aliases, native token)
+const BUILTIN_CHAINS: &[(&str, Option<u64>, &[&str], &str)] = &[
+    ("ethereum", Some(1), &["eth", "mainnet", "ethereum-mainnet"], "ETH"),
+    ("optimism", Some(10), &["op", "op-mainnet"], "ETH"),
+    ("bsc", Some(56), &["bnb", "binance", "bnb-smart-chain"], "BNB"),
+    ("polygon", Some(137), &["matic", "pol", "polygon-pos"], "POL"),
+    ("zksync", Some(324), &["zksync-era", "era"], "ETH"),
+    ("base", Some(8453), &["base-mainnet"], "ETH"),
+    ("arbitrum", Some(42161), &["arb", "arb1", "arbitrum-one"], "ETH"),
+    ("avalanche", Some(43114), &["avax", "avalanche-c", "c-chain"], "AVAX"),
+    ("linea", Some(59144), &[], "ETH"),
+    ("blast", Some(81457), &[], "ETH"),
+    ("scroll", Some(534352), &[], "ETH"),
+    ("solana", None, &["sol"], "SOL"),
+];
+
+// (chain, address, symbol, decimals)
+const BUILTIN_TOKENS: &[(&str, &str, &str, u8)] = &[
+    ("ethereum", "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48", "USDC", 6),
+    ("ethereum", "0xdAC17F958D2ee523a2206206994597C13D831ec7", "USDT", 6),
+    ("ethereum", "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2", "WETH", 18),
+    ("optimism", "0x0b2C639c533813f4Aa9D7837CAf62653d097Ff85", "USDC", 6),
+    ("optimism", "0x94b008aA00579c1307B0EF2c499aD98a8ce58e58", "USDT", 6),
+    ("bsc", "0x8AC76a51cc950d9822D68b83fE1Ad97B32Cd580d", "USDC", 18),
+    ("bsc", "0x55d398326f99059fF775485246999027B3197955", "USDT", 18),
+    ("polygon", "0x3c499c542cEF5E3811e1192ce70d8cC03d5c3359", "USDC", 6),
+    ("polygon", "0x2791Bca1f2de4661ED88A30C99A7a9449Aa84174", "USDC.e", 6),
+    ("polygon", "0xc2132D05D31c914a87C6611C10748AEb04B58e8F", "USDT", 6),
+    ("base", "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913", "USDC", 6),
+    ("arbitrum", "0xaf88d065e77c8cC2239327C5EDb3A432268e5831", "USDC", 6),
+    ("arbitrum", "0xFd086bC7CD5C481DCC9C85ebE478A1C0b69FCbb9", "USDT", 6),
+    ("avalanche", "0xB97EF9Ef8734C71904D8002F8b6Bc66Dd9c48a6E", "USDC", 6),
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChainRef {
+    // Lowercase key used everywhere else, e.g. in bridge config and graph nodes
+    pub key: String,
+    // EVM chain id; None for non-EVM chains
+    #[serde(default)]
+    pub chain_id: Option<u64>,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    // Symbol of the gas token
+    pub native_token: String,
+}
+
+impl ChainRef {
+    fn is_named(&self, name: &str) -> bool {
+        self.key.eq_ignore_ascii_case(name)
+            || self.aliases.iter().any(|alias| alias.eq_ignore_ascii_case(name))
+            || self.chain_id.is_some_and(|id| id.to_string() == name)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TokenRef {
+    // Canonical chain key
+    pub chain: String,
+    // Checksummed for EVM addresses, as given otherwise
+    pub address: String,
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+impl TokenRef {
+    // `human` units of the token, "1.5", see Amount::from_human
+    pub fn amount(&self, human: &str) -> Result<Amount, AmountError> {
+        Amount::from_human(human, self.decimals)
+    }
+
+    // Base units of the token as an API gives them
+    pub fn amount_from_raw(&self, raw: &str) -> Result<Amount, AmountError> {
+        Amount::from_raw(raw, self.decimals)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChainRegistry {
+    chains: Vec<ChainRef>,
+}
+
+impl ChainRegistry {
+    pub fn builtin() -> Self {
+        let mut registry = Self::default();
+        for (key, chain_id, aliases, native_token) in BUILTIN_CHAINS {
+            registry
+                .insert(ChainRef {
+                    key: key.to_string(),
+                    chain_id: *chain_id,
+                    aliases: aliases.iter().map(|alias| alias.to_string()).collect(),
+                    native_token: native_token.to_string(),
+                })
+                .expect("built-in chains have distinct names");
+        }
+        registry
+    }
+
+    // Adds the chain, replacing one with the same key. Its key, aliases and chain id must not
+    // name another chain.
+    pub fn insert(&mut self, mut chain: ChainRef) -> Result<(), RegistryError> {
+        chain.key = chain.key.trim().to_lowercase();
+        let names = std::iter::once(chain.key.clone())
+            .chain(chain.aliases.iter().cloned())
+            .chain(chain.chain_id.map(|id| id.to_string()));
+        for name in names {
+            if let Some(other) = self.chains.iter().find(|other| other.key != chain.key && other.is_named(&name)) {
+                return Err(RegistryError::DuplicateChainName { name, first: other.key.clone(), second: chain.key });
+            }
+        }
+        match self.chains.iter_mut().find(|other| other.key == chain.key) {
+            Some(existing) => *existing = chain,
+            None => self.chains.push(chain),
+        }
+        Ok(())
+    }
+
+    // By key, alias or EVM chain id ("8453")
+    pub fn resolve(&self, name: &str) -> Result<&ChainRef, RegistryError> {
+        let name = name.trim();
+        self.chains
+            .iter()
+            .find(|chain| chain.is_named(name))
+            .ok_or_else(|| RegistryError::UnknownChain(name.to_string()))
+    }
+
+    pub fn by_chain_id(&self, chain_id: u64) -> Option<&ChainRef> {
+        self.chains.iter().find(|chain| chain.chain_id == Some(chain_id))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ChainRef> {
+        self.chains.iter()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TokenRegistry {
+    tokens: Vec<TokenRef>,
+}
+
+impl TokenRegistry {
+    // Adds the token, replacing one at the same address on the same chain. `token.chain` is
+    // taken to be a canonical key already, see Registry::insert_token.
+    pub fn insert(&mut self, mut token: TokenRef) -> Result<(), RegistryError> {
+        token.address = canonical_address(&token.address)?;
+        match self.tokens.iter_mut().find(|other| other.chain == token.chain && other.address == token.address) {
+            Some(existing) => *existing = token,
+            None => self.tokens.push(token),
+        }
+        Ok(())
+    }
+
+    // Tokens on the chain with canonical key `chain` whose address or symbol is `token`
+    pub fn find(&self, chain: &str, token: &str) -> Vec<&TokenRef> {
+        let token = token.trim();
+        let by_address = token.starts_with("0x") || token.starts_with("0X");
+        self.tokens
+            .iter()
+            .filter(|candidate| candidate.chain == chain)
+            .filter(|candidate| match by_address {
+                true => candidate.address.eq_ignore_ascii_case(token),
+                false => candidate.symbol.eq_ignore_ascii_case(token) || candidate.address == token,
+            })
+            .collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &TokenRef> {
+        self.tokens.iter()
+    }
+}
+
+// The built-in chains and tokens plus those from the [registry] config section
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Registry {
+    pub chains: ChainRegistry,
+    pub tokens: TokenRegistry,
+}
+
+impl Registry {
+    pub fn builtin() -> Self {
+        let mut registry = Self { chains: ChainRegistry::builtin(), tokens: TokenRegistry::default() };
+        for (chain, address, symbol, decimals) in BUILTIN_TOKENS {
+            let token = TokenRef { chain: chain.to_string(), address: address.to_string(), symbol: symbol.to_string(), decimals: *decimals };
+            registry.insert_token(token).expect("built-in tokens are valid");
+        }
+        registry
+    }
+
+    // Config chains go in before config tokens, so tokens may be on the new chains
+    pub fn from_config(config: &RegistryConfig) -> Result<Self, RegistryError> {
+        let mut registry = Self::builtin();
+        for chain in &config.chains {
+            registry.chains.insert(chain.clone())?;
+        }
+        for token in &config.tokens {
+            registry.insert_token(token.clone())?;
+        }
+        Ok(registry)
+    }
+
+    // Adds a token whose chain may be given by alias
+    pub fn insert_token(&mut self, mut token: TokenRef) -> Result<(), RegistryError> {
+        token.chain = self.chains.resolve(&token.chain)?.key.clone();
+        self.tokens.insert(token)
+    }
+
+    pub fn resolve_chain(&self, name: &str) -> Result<ChainRef, RegistryError> {
+        self.chains.resolve(name).cloned()
+    }
+
+    // `token` is a symbol or an address on `chain`, itself a key or an alias. A symbol several
+    // tokens on the chain share is ambiguous.
+    pub fn resolve_token(&self, chain: &str, token: &str) -> Result<TokenRef, RegistryError> {
+        let chain = &self.chains.resolve(chain)?.key;
+        match self.tokens.find(chain, token).as_slice() {
+            [found] => Ok((*found).clone()),
+            [] => Err(RegistryError::UnknownToken { chain: chain.clone(), token: token.trim().to_string() }),
+            found => Err(RegistryError::AmbiguousToken {
+                chain: chain.clone(),
+                token: token.trim().to_string(),
+                matches: found.iter().map(|token| token.address.clone()).collect(),
+            }),
+        }
+    }
+
+    // `human` units of `token` on `chain`, both as resolve_token takes them
+    pub fn parse_amount(&self, chain: &str, token: &str, human: &str) -> Result<Amount, RegistryError> {
+        Ok(self.resolve_token(chain, token)?.amount(human)?)
+    }
+}
+
+// EIP-55 checksummed form of a 0x-prefixed EVM address. All-lowercase and all-uppercase
+// addresses are accepted as they carry no checksum; mixed case must already be correct.
+pub fn checksum_address(address: &str) -> Result<String, RegistryError> {
+    let invalid = |reason: &str| RegistryError::InvalidAddress { address: address.to_string(), reason: reason.to_string() };
+    let hex = address
+        .strip_prefix("0x")
+        .or_else(|| address.strip_prefix("0X"))
+        .ok_or_else(|| invalid("missing the 0x prefix"))?;
+    if hex.len() != 40 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid("expected 40 hex digits"));
+    }
+
+    let lower = hex.to_ascii_lowercase();
+    let mut hash = [0u8; 32];
+    let mut keccak = Keccak::v256();
+    keccak.update(lower.as_bytes());
+    keccak.finalize(&mut hash);
+
+    let checksummed: String = lower
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let nibble = (hash[i / 2] >> if i % 2 == 0 { 4 } else { 0 }) & 0x0f;
+            if c.is_ascii_alphabetic() && nibble >= 8 { c.to_ascii_uppercase() } else { c }
+        })
+        .collect();
+
+    let mixed = hex.chars().any(|c| c.is_ascii_lowercase()) && hex.chars().any(|c| c.is_ascii_uppercase());
+    if mixed && hex != checksummed {
+        return Err(invalid("the mixed-case checksum is wrong"));
+    }
+    Ok(format!("0x{}", checksummed))
+}
+
+// EVM addresses checksummed, anything else (e.g. Solana mints) kept as given
+fn canonical_address(address: &str) -> Result<String, RegistryError> {
+    let address = address.trim();
+    if address.is_empty() {
+        return Err(RegistryError::InvalidAddress { address: String::new(), reason: "it is empty".to_string() });
+    }
+    match address.starts_with("0x") || address.starts_with("0X") {
+        true => checksum_address(address),
+        false => Ok(address.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ConfigFormat, ConfigManager};
+
+    #[test]
+    fn chains_resolve_by_key_alias_and_chain_id() {
+        let registry = Registry::builtin();
+        for name in ["arbitrum", "ARB", " arbitrum-one ", "42161"] {
+            assert_eq!(registry.resolve_chain(name).unwrap().key, "arbitrum");
+        }
+        assert_eq!(registry.resolve_chain("Eth").unwrap().chain_id, Some(1));
+        assert_eq!(registry.resolve_chain("sol").unwrap().native_token, "SOL");
+        assert_eq!(registry.chains.by_chain_id(8453).unwrap().key, "base");
+        assert_eq!(registry.resolve_chain("fantom"), Err(RegistryError::UnknownChain("fantom".to_string())));
+
+        let mut chains = ChainRegistry::builtin();
+        let clash = ChainRef { key: "arbitrum-nova".to_string(), chain_id: Some(42170), aliases: vec!["arb".to_string()], native_token: "ETH".to_string() };
+        assert!(matches!(chains.insert(clash), Err(RegistryError::DuplicateChainName { first, .. }) if first == "arbitrum"));
+    }
+
+    #[test]
+    fn addresses_are_checksummed() {
+        for (_, address, _, _) in BUILTIN_TOKENS {
+            assert_eq!(checksum_address(&address.to_lowercase()).unwrap(), *address);
+            assert_eq!(checksum_address(&address.to_uppercase().replacen("0X", "0x", 1)).unwrap(), *address);
+        }
+        assert!(checksum_address("0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913").is_ok());
+        // One letter's case flipped
+        assert!(checksum_address("0x833589fcD6eDb6E08f4c7C32D4f71b54bdA02913").is_err());
+        assert!(checksum_address("833589fcd6edb6e08f4c7c32d4f71b54bda02913").is_err());
+        assert!(checksum_address("0x833589fcd6edb6e08f4c7c32d4f71b54bda0291").is_err());
+
+        let registry = Registry::builtin();
+        let usdc = registry.resolve_token("base", "0x833589fcd6edb6e08f4c7c32d4f71b54bda02913").unwrap();
+        assert_eq!(usdc, TokenRef {
+            chain: "base".to_string(),
+            address: "0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913".to_string(),
+            symbol: "USDC".to_string(),
+            decimals: 6,
+        });
+        assert_eq!(registry.resolve_token("base-mainnet", "usdc").unwrap(), usdc);
+    }
+
+    #[test]
+    fn shared_symbols_are_ambiguous() {
+        let mut registry = Registry::builtin();
+        assert_eq!(registry.resolve_token("matic", "usdc.e").unwrap().address, "0x2791Bca1f2de4661ED88A30C99A7a9449Aa84174");
+        assert_eq!(registry.resolve_token("polygon", "USDC").unwrap().address, "0x3c499c542cEF5E3811e1192ce70d8cC03d5c3359");
+
+        let bridged = TokenRef { chain: "base".to_string(), address: format!("0x{}", "d9".repeat(20)), symbol: "usdc".to_string(), decimals: 6 };
+        registry.insert_token(bridged).unwrap();
+        match registry.resolve_token("base", "USDC") {
+            Err(RegistryError::AmbiguousToken { chain, matches, .. }) => {
+                assert_eq!(chain, "base");
+                assert_eq!(matches.len(), 2);
+            }
+            other => panic!("expected an ambiguous token, got {:?}", other),
+        }
+        assert_eq!(
+            registry.resolve_token("base", "DAI"),
+            Err(RegistryError::UnknownToken { chain: "base".to_string(), token: "DAI".to_string() })
+        );
+        assert_eq!(registry.resolve_token("atlantis", "USDC"), Err(RegistryError::UnknownChain("atlantis".to_string())));
+    }
+
+    #[test]
+    fn amounts_take_the_decimals_of_their_token() {
+        let registry = Registry::builtin();
+        assert_eq!(registry.parse_amount("eth", "USDC", "1.5").unwrap().to_raw_string(), "1500000");
+        assert_eq!(registry.parse_amount("bsc", "USDC", "1.5").unwrap().to_raw_string(), "1500000000000000000");
+        let weth = registry.resolve_token("ethereum", "WETH").unwrap();
+        assert_eq!(weth.amount_from_raw("9007199254740993").unwrap().to_string(), "0.009007199254740993");
+        assert_eq!(
+            registry.parse_amount("eth", "USDC", "1,5"),
+            Err(RegistryError::Amount(AmountError::Invalid("1,5".to_string())))
+        );
+    }
+
+    #[test]
+    fn config_adds_chains_and_tokens() {
+        let config = ConfigManager::from_str(
+            r#"
+            [bridges]
+            [[registry.chains]]
+            key = "Sonic"
+            chain_id = 146
+            aliases = ["s"]
+            native_token = "S"
+
+            [[registry.tokens]]
+            chain = "s"
+            address = "0x29219dd400f2bf60e5a23d13be72b486d4038894"
+            symbol = "USDC"
+            decimals = 6
+
+            [[registry.tokens]]
+            chain = "solana"
+            address = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"
+            symbol = "USDC"
+            decimals = 6
+            "#,
+            ConfigFormat::Toml,
+        )
+        .unwrap();
+        let registry = Registry::from_config(&config.registry).unwrap();
+
+        assert_eq!(registry.resolve_chain("146").unwrap().key, "sonic");
+        let usdc = registry.resolve_token("S", "usdc").unwrap();
+        assert_eq!((usdc.chain.as_str(), usdc.address.to_lowercase()), ("sonic", "0x29219dd400f2bf60e5a23d13be72b486d4038894".to_string()));
+        assert_eq!(checksum_address(&usdc.address).unwrap(), usdc.address);
+        assert_eq!(registry.resolve_token("solana", "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v").unwrap().symbol, "USDC");
+        // Built-ins are still there
+        assert_eq!(registry.resolve_token("ethereum", "usdt").unwrap().decimals, 6);
+
+        let invalid = ConfigManager::from_str(
+            "[bridges]\n[[registry.tokens]]\nchain = \"atlantis\"\naddress = \"0x1\"\nsymbol = \"X\"\ndecimals = 6\n",
+            ConfigFormat::Toml,
+        );
+        assert!(matches!(invalid, Err(crate::ConfigError::Invalid { key, .. }) if key == "registry.tokens[0]"));
+    }
+}
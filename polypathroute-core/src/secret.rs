@@ -0,0 +1,160 @@
+// Keeps API keys and other credentials out of Debug output, logs and persisted data
+
+use std::fmt;
+use ring::{digest, rand::{SecureRandom, SystemRandom}};
+use serde::{Deserialize, Serialize, Serializer};
+use subtle::ConstantTimeEq;
+
+// What secrets render as
+pub const REDACTED: &str = "***REDACTED***";
+
+// Config keys whose values are always treated as secrets; global.secret_patterns adds more.
+// `*` matches any run of characters and matching ignores case. Proxy URLs may carry credentials.
+pub const DEFAULT_SECRET_PATTERNS: [&str; 6] = ["*_key", "*-key", "*_secret", "*token*", "authorization", "proxy"];
+
+// A value that only shows itself through `expose`. Debug and Display print REDACTED and so
+// does serialization, so a Redacted field never reaches a log line or a persisted file.
+#[derive(Clone, PartialEq, Eq, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Redacted<T>(T);
+
+impl<T> Redacted<T> {
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> From<T> for Redacted<T> {
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T> fmt::Debug for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", REDACTED)
+    }
+}
+
+impl<T> fmt::Display for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T> Serialize for Redacted<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(REDACTED)
+    }
+}
+
+// A secret kept only as a salted SHA-256, e.g. a stored API key, so whoever reads the store
+// can't use it. Both are hex.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HashedSecret {
+    salt: String,
+    hash: String,
+}
+
+impl HashedSecret {
+    pub fn new(secret: &str) -> Self {
+        let mut salt = [0u8; 16];
+        SystemRandom::new().fill(&mut salt).expect("the system has a source of randomness");
+        let salt = hex(&salt);
+        Self { hash: salted_hash(&salt, secret), salt }
+    }
+
+    // Compares in constant time, so how long it takes says nothing of how close `secret` came
+    pub fn verify(&self, secret: &str) -> bool {
+        salted_hash(&self.salt, secret).as_bytes().ct_eq(self.hash.as_bytes()).into()
+    }
+}
+
+fn salted_hash(salt: &str, secret: &str) -> String {
+    sha256_hex(format!("{}{}", salt, secret).as_bytes())
+}
+
+// SHA-256 as 64 hex digits, the same on every build
+pub fn sha256_hex(data: &[u8]) -> String {
+    hex(digest::digest(&digest::SHA256, data).as_ref())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Whether `key`, the last segment of a config key path, matches one of DEFAULT_SECRET_PATTERNS
+pub fn is_secret_key(key: &str) -> bool {
+    DEFAULT_SECRET_PATTERNS.iter().any(|pattern| matches_pattern(pattern, key))
+}
+
+pub(crate) fn matches_pattern(pattern: &str, key: &str) -> bool {
+    let (pattern, key) = (pattern.to_lowercase(), key.to_lowercase());
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = key.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No `*` at all
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacted_values_never_show() {
+        let secret = Redacted::new("sk-live-123".to_string());
+        assert_eq!(format!("{:?}", secret), "\"***REDACTED***\"");
+        assert_eq!(secret.to_string(), REDACTED);
+        assert_eq!(serde_json::to_string(&secret).unwrap(), "\"***REDACTED***\"");
+        assert_eq!(secret.expose(), "sk-live-123");
+
+        let parsed: Redacted<String> = serde_json::from_str("\"sk-live-123\"").unwrap();
+        assert_eq!(parsed, secret);
+    }
+
+    #[test]
+    fn hashed_secrets_verify_only_their_secret() {
+        let hashed = HashedSecret::new("pk-0123456789abcdef");
+        assert!(hashed.verify("pk-0123456789abcdef"));
+        assert!(!hashed.verify("pk-0123456789abcdeF"));
+        assert!(!hashed.verify(""));
+        // Salted, so the same secret hashes differently each time
+        assert_ne!(HashedSecret::new("pk-0123456789abcdef"), hashed);
+        assert!(!serde_json::to_string(&hashed).unwrap().contains("0123456789abcdef"));
+        assert_eq!(sha256_hex(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    }
+
+    #[test]
+    fn patterns_match_whole_keys() {
+        for key in ["api_key", "PRIVATE_KEY", "x-api-key", "client_secret", "access_token_v2", "Authorization"] {
+            assert!(is_secret_key(key), "{}", key);
+        }
+        for key in ["keys", "key_id", "slippage_tolerance", "secretive"] {
+            assert!(!is_secret_key(key), "{}", key);
+        }
+        assert!(matches_pattern("db_*_password", "db_main_password"));
+        assert!(!matches_pattern("db_*_password", "db_password"));
+        assert!(matches_pattern("exact", "EXACT"));
+    }
+}